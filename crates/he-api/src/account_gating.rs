@@ -0,0 +1,99 @@
+//! New-account restrictions
+//!
+//! Handlers for features closed to new accounts check them with the
+//! [`unrestricted`] henforcer, and chat with [`can_chat`]. The rules are in
+//! [`he_game_mechanics::account_gating`]; restrictions are worked out from
+//! the account's level and age on every check, so they lift by themselves.
+//! The standing they are worked out from is loaded once per request.
+
+use chrono::Utc;
+use he_database::queries::{AccountGatingQueries, AccountStandingRow};
+use he_game_mechanics::account_gating::{self, AccountStanding, ActiveRestriction, GatingDenied, Restriction};
+use he_helix_henforcer::cache;
+use he_helix_henforcer::{add_to_relay, reply_error, reply_ok, HenforcerError, Relay, StandardResult};
+use sqlx::PgPool;
+
+/// Relay key the refusal is put under when a henforcer fails
+pub const DENIED_KEY: &str = "gating_denied";
+/// Relay cache object type of an account's standing, keyed by user id
+pub const STANDING_OBJECT: &str = "account_standing";
+
+fn standing_from(row: AccountStandingRow) -> AccountStanding {
+    AccountStanding {
+        level: row.level,
+        created_at: row.created_at,
+        verified: row.verified,
+        exempt: row.exempt.iter().filter_map(|r| Restriction::from_str(r)).collect(),
+    }
+}
+
+pub async fn standing(pool: &PgPool, user_id: i64) -> anyhow::Result<Option<AccountStanding>> {
+    cache::cached(STANDING_OBJECT, user_id, || async {
+        Ok(AccountGatingQueries::standing(pool, user_id).await?.map(standing_from))
+    })
+    .await
+}
+
+/// The restrictions on the player now
+pub async fn restrictions(pool: &PgPool, user_id: i64) -> anyhow::Result<Option<Vec<ActiveRestriction>>> {
+    let config = &crate::balance::current().account_gating;
+    Ok(standing(pool, user_id).await?.map(|s| account_gating::restrictions(&s, Utc::now(), config)))
+}
+
+fn denied(denied: GatingDenied) -> StandardResult {
+    let reason = denied.message();
+    reply_error(HenforcerError::AccessDenied { reason }, add_to_relay(Relay::new(), DENIED_KEY, denied))
+}
+
+fn lookup_failed(e: anyhow::Error) -> StandardResult {
+    reply_error(HenforcerError::Custom { reason: format!("account standing lookup failed: {}", e) }, Relay::new())
+}
+
+fn no_account(user_id: i64) -> StandardResult {
+    reply_error(
+        HenforcerError::NotFound { object_type: "user".to_string(), id: user_id.to_string() },
+        Relay::new(),
+    )
+}
+
+/// Henforcer for features closed to new accounts. Passes unless
+/// `restriction` applies to the player, relaying their standing as
+/// `standing`; a refusal relays the [`GatingDenied`] as [`DENIED_KEY`].
+pub async fn unrestricted(pool: &PgPool, user_id: i64, restriction: Restriction) -> StandardResult {
+    let config = &crate::balance::current().account_gating;
+    let standing = match standing(pool, user_id).await {
+        Ok(Some(standing)) => standing,
+        Ok(None) => return no_account(user_id),
+        Err(e) => return lookup_failed(e),
+    };
+
+    match account_gating::check(restriction, &standing, Utc::now(), config) {
+        None => reply_ok(add_to_relay(Relay::new(), "standing", standing)),
+        Some(active) => denied(GatingDenied::Restricted(active)),
+    }
+}
+
+/// Henforcer for posting a chat message of `body`: chat-restricted players
+/// are rate limited and held to shorter messages
+pub async fn can_chat(pool: &PgPool, user_id: i64, body: &str) -> StandardResult {
+    let config = &crate::balance::current().account_gating;
+    let standing = match standing(pool, user_id).await {
+        Ok(Some(standing)) => standing,
+        Ok(None) => return no_account(user_id),
+        Err(e) => return lookup_failed(e),
+    };
+    let now = Utc::now();
+    if account_gating::check(Restriction::Chat, &standing, now, config).is_none() {
+        return reply_ok(add_to_relay(Relay::new(), "standing", standing));
+    }
+
+    let recent =
+        match AccountGatingQueries::recent_chat_messages(pool, user_id, config.restricted_chat_window_minutes).await {
+            Ok(recent) => recent,
+            Err(e) => return lookup_failed(e),
+        };
+    match account_gating::check_chat(&standing, recent, body.chars().count(), now, config) {
+        Ok(()) => reply_ok(add_to_relay(Relay::new(), "standing", standing)),
+        Err(refused) => denied(refused),
+    }
+}
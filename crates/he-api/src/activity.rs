@@ -0,0 +1,280 @@
+//! Account activity timeline
+//!
+//! The player's own recent activity, merged newest first from where each
+//! kind is already recorded: logins from the IP history, hacks from the
+//! hacked database, money from the ledger, clan events from membership and
+//! war records, and account security events from the audit log. Categories
+//! are fetched separately, one page past the cursor each, and merged here.
+//!
+//! Entries with the same timestamp are ordered by category, then by their
+//! position within it, so a cursor names one exact place in the timeline.
+
+use chrono::{DateTime, Utc};
+use he_database::queries::{ActivityQueries, ActivityRow};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityCategory {
+    Login,
+    HackPerformed,
+    HackSuffered,
+    Money,
+    Clan,
+    Security,
+}
+
+impl ActivityCategory {
+    pub const ALL: [ActivityCategory; 6] = [
+        ActivityCategory::Login,
+        ActivityCategory::HackPerformed,
+        ActivityCategory::HackSuffered,
+        ActivityCategory::Money,
+        ActivityCategory::Clan,
+        ActivityCategory::Security,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityCategory::Login => "login",
+            ActivityCategory::HackPerformed => "hack_performed",
+            ActivityCategory::HackSuffered => "hack_suffered",
+            ActivityCategory::Money => "money",
+            ActivityCategory::Clan => "clan",
+            ActivityCategory::Security => "security",
+        }
+    }
+
+    pub fn from_str(name: &str) -> Option<ActivityCategory> {
+        ActivityCategory::ALL.into_iter().find(|category| category.as_str() == name)
+    }
+
+    /// A comma-separated list; empty means every category
+    pub fn parse_list(list: &str) -> Result<Vec<ActivityCategory>, String> {
+        let mut categories = Vec::new();
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let category = Self::from_str(name).ok_or_else(|| format!("Unknown category: {}", name))?;
+            if !categories.contains(&category) {
+                categories.push(category);
+            }
+        }
+        if categories.is_empty() {
+            categories = Self::ALL.to_vec();
+        }
+        Ok(categories)
+    }
+}
+
+/// Audit events a player sees about their own account, by `SecurityEvent`
+/// variant. Logins are their own category; detections stay hidden.
+const SECURITY_EVENTS: &[(&str, &str)] = &[
+    ("LogoutEvent", "logout"),
+    ("PasswordChange", "password_changed"),
+    ("EmailChangeRequested", "email_change_requested"),
+    ("EmailChangeConfirmed", "email_change_confirmed"),
+    ("EmailChanged", "email_changed"),
+    ("EmailChangeRevoked", "email_change_revoked"),
+    ("UsernameChanged", "username_changed"),
+    ("LoginDisowned", "login_disowned"),
+    ("DataExport", "data_exported"),
+];
+
+/// Position in the timeline: the last entry already seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ActivityCursor {
+    pub at: DateTime<Utc>,
+    pub category: ActivityCategory,
+    pub seq: i64,
+}
+
+impl ActivityCursor {
+    /// Where `category` continues from: entries at the cursor's timestamp
+    /// come after it only if their category sorts after the cursor's
+    fn bound(&self, category: ActivityCategory) -> (DateTime<Utc>, i64) {
+        let seq = match category.cmp(&self.category) {
+            std::cmp::Ordering::Less => i64::MIN,
+            std::cmp::Ordering::Equal => self.seq,
+            std::cmp::Ordering::Greater => i64::MAX,
+        };
+        (self.at, seq)
+    }
+}
+
+/// Opaque to clients: `<microseconds>.<category>.<seq>`
+impl From<ActivityCursor> for String {
+    fn from(cursor: ActivityCursor) -> String {
+        format!("{}.{}.{}", cursor.at.timestamp_micros(), cursor.category.as_str(), cursor.seq)
+    }
+}
+
+impl TryFrom<String> for ActivityCursor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut parts = value.splitn(3, '.');
+        let (Some(micros), Some(category), Some(seq)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err("malformed cursor".to_string());
+        };
+        let at = micros
+            .parse::<i64>()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or("malformed cursor")?;
+        let category = ActivityCategory::from_str(category).ok_or("malformed cursor")?;
+        let seq = seq.parse::<i64>().map_err(|_| "malformed cursor")?;
+        Ok(Self { at, category, seq })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityEntry {
+    pub category: ActivityCategory,
+    #[serde(flatten)]
+    pub row: ActivityRow,
+}
+
+impl ActivityEntry {
+    fn cursor(&self) -> ActivityCursor {
+        ActivityCursor { at: self.row.at, category: self.category, seq: self.row.seq }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivityPage {
+    pub entries: Vec<ActivityEntry>,
+    /// Pass back to get the next page; `None` on the last page
+    pub next_cursor: Option<ActivityCursor>,
+}
+
+async fn fetch(
+    pool: &PgPool,
+    user_id: i64,
+    category: ActivityCategory,
+    before: Option<(DateTime<Utc>, i64)>,
+    limit: i64,
+) -> anyhow::Result<Vec<ActivityRow>> {
+    match category {
+        ActivityCategory::Login => ActivityQueries::logins(pool, user_id, before, limit).await,
+        ActivityCategory::HackPerformed => ActivityQueries::hacks_performed(pool, user_id, before, limit).await,
+        ActivityCategory::HackSuffered => ActivityQueries::hacks_suffered(pool, user_id, before, limit).await,
+        ActivityCategory::Money => ActivityQueries::money(pool, user_id, before, limit).await,
+        ActivityCategory::Clan => ActivityQueries::clan(pool, user_id, before, limit).await,
+        ActivityCategory::Security => {
+            let variants: Vec<&str> = SECURITY_EVENTS.iter().map(|(variant, _)| *variant).collect();
+            let mut rows = ActivityQueries::security(pool, user_id, &variants, before, limit).await?;
+            for row in &mut rows {
+                if let Some((_, kind)) = SECURITY_EVENTS.iter().find(|(variant, _)| *variant == row.kind) {
+                    row.kind = kind.to_string();
+                }
+            }
+            Ok(rows)
+        }
+    }
+}
+
+/// Newest first across categories; ties by category, then position
+fn merge(mut entries: Vec<ActivityEntry>, limit: usize) -> ActivityPage {
+    entries.sort_by(|a, b| {
+        b.row.at
+            .cmp(&a.row.at)
+            .then(a.category.cmp(&b.category))
+            .then(b.row.seq.cmp(&a.row.seq))
+    });
+    let more = entries.len() > limit;
+    entries.truncate(limit);
+    let next_cursor = if more { entries.last().map(ActivityEntry::cursor) } else { None };
+    ActivityPage { entries, next_cursor }
+}
+
+/// One page of the player's activity in `categories`, after `cursor`
+pub async fn timeline(
+    pool: &PgPool,
+    user_id: i64,
+    categories: &[ActivityCategory],
+    cursor: Option<ActivityCursor>,
+    limit: i64,
+) -> anyhow::Result<ActivityPage> {
+    let limit = limit.clamp(1, MAX_LIMIT);
+    let mut entries = Vec::new();
+    for &category in categories {
+        let before = cursor.map(|cursor| cursor.bound(category));
+        for row in fetch(pool, user_id, category, before, limit + 1).await? {
+            entries.push(ActivityEntry { category, row });
+        }
+    }
+    Ok(merge(entries, limit as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(category: ActivityCategory, second: u32, seq: i64) -> ActivityEntry {
+        ActivityEntry {
+            category,
+            row: ActivityRow {
+                seq,
+                at: Utc.with_ymd_and_hms(2024, 11, 1, 12, 0, second).unwrap(),
+                kind: String::new(),
+                details: serde_json::Value::Null,
+            },
+        }
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = entry(ActivityCategory::HackSuffered, 5, 42).cursor();
+        let encoded = String::from(cursor);
+        assert!(encoded.ends_with(".hack_suffered.42"));
+        assert_eq!(ActivityCursor::try_from(encoded), Ok(cursor));
+        assert!(ActivityCursor::try_from("1.nope.3".to_string()).is_err());
+        assert!(ActivityCursor::try_from("12345".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_categories_parse() {
+        assert_eq!(ActivityCategory::parse_list("").unwrap(), ActivityCategory::ALL.to_vec());
+        assert_eq!(
+            ActivityCategory::parse_list("money, login,money").unwrap(),
+            vec![ActivityCategory::Money, ActivityCategory::Login]
+        );
+        assert!(ActivityCategory::parse_list("login,logins").is_err());
+    }
+
+    #[test]
+    fn test_merge_pages_without_gaps() {
+        let all = vec![
+            entry(ActivityCategory::Money, 9, 1),
+            entry(ActivityCategory::Login, 5, 7),
+            entry(ActivityCategory::Clan, 5, 2),
+            entry(ActivityCategory::Login, 5, 6),
+            entry(ActivityCategory::Security, 1, 3),
+        ];
+
+        let first = merge(all.clone(), 2);
+        let cursor = first.next_cursor.unwrap();
+        assert_eq!((cursor.category, cursor.seq), (ActivityCategory::Login, 7));
+
+        // What each category would return past the cursor
+        let rest: Vec<ActivityEntry> = all
+            .into_iter()
+            .filter(|e| {
+                let (at, seq) = cursor.bound(e.category);
+                (e.row.at, e.row.seq) < (at, seq)
+            })
+            .collect();
+        let second = merge(rest, 10);
+        let order: Vec<_> = second.entries.iter().map(|e| (e.category, e.row.seq)).collect();
+        assert_eq!(
+            order,
+            vec![(ActivityCategory::Login, 6), (ActivityCategory::Clan, 2), (ActivityCategory::Security, 3)]
+        );
+        assert!(second.next_cursor.is_none());
+    }
+}
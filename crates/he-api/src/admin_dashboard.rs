@@ -0,0 +1,612 @@
+//! Server-rendered admin dashboard
+//!
+//! Basic visibility for operators without Grafana, served under `/admin` by
+//! the game server itself: players online, running processes by type, recent
+//! audit events, cron job status, daily actives and feature flag toggles. Pages are rendered
+//! with Leptos SSR as in he-vdp and need no client-side script. The economy
+//! page shows the [`crate::economy_stream`] series and reloads itself every
+//! [`ECONOMY_REFRESH_SECS`] seconds.
+//!
+//! Signing in takes the admin's password and a current MFA code and opens a
+//! session that lasts [`SESSION_TTL`], kept in a cookie scoped to `/admin`.
+//! Sessions are held in memory, so each node asks for its own sign-in, and
+//! the dashboard permission is checked again on every request.
+
+use chrono::{DateTime, Utc};
+use he_database::models::CronJob;
+use he_database::queries::{
+    AuditEntry, DailyActivityRow, EconomyAnomalyRow, EconomyFlowRow, EconomyMinuteRow, ProcessTypeCount, TopEarnerRow,
+};
+use he_monitoring::Threshold;
+use leptos::*;
+use once_cell::sync::Lazy;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::economy_stream::alert_level;
+use crate::live_ops::FeatureFlag;
+
+pub const SESSION_COOKIE: &str = "he_admin";
+pub const SESSION_TTL: Duration = Duration::from_secs(15 * 60);
+const TOKEN_LENGTH: usize = 43;
+/// Audit event details are cut to this many characters
+const DETAIL_LENGTH: usize = 160;
+pub const ECONOMY_REFRESH_SECS: u32 = 30;
+
+const CSS: &str = "
+body { margin: 0; font-family: monospace; background: #0b0f14; color: #c9d1d9; }
+header { display: flex; justify-content: space-between; align-items: center; padding: 12px 24px; background: #11161d; border-bottom: 1px solid #1f2a36; }
+main { padding: 24px; display: grid; gap: 24px; grid-template-columns: repeat(auto-fit, minmax(420px, 1fr)); }
+section { background: #11161d; border: 1px solid #1f2a36; border-radius: 6px; padding: 16px; }
+h1 { font-size: 18px; margin: 0; color: #3fb950; }
+h2 { font-size: 15px; margin: 0 0 12px; }
+table { width: 100%; border-collapse: collapse; font-size: 13px; }
+th, td { text-align: left; padding: 4px 8px; border-bottom: 1px solid #1f2a36; vertical-align: top; }
+.big { font-size: 40px; color: #3fb950; }
+.muted { color: #6e7681; }
+.warning { color: #d29922; }
+.critical, .error { color: #f85149; }
+.ok { color: #3fb950; }
+form.inline { display: inline; }
+a { color: #58a6ff; }
+nav a { margin-right: 16px; }
+td.num { text-align: right; }
+button { font-family: monospace; background: #1f2a36; color: #c9d1d9; border: 1px solid #30363d; border-radius: 4px; padding: 2px 10px; cursor: pointer; }
+.sign-in { max-width: 340px; margin: 80px auto; }
+.sign-in label { display: block; margin: 12px 0 4px; }
+.sign-in input { width: 100%; box-sizing: border-box; padding: 6px; background: #0b0f14; color: #c9d1d9; border: 1px solid #30363d; }
+.sign-in button { margin-top: 16px; width: 100%; padding: 8px; }
+";
+
+/// A signed-in dashboard session
+#[derive(Debug, Clone)]
+pub struct AdminSession {
+    pub user_id: i64,
+    /// Echoed by every form on the dashboard
+    pub csrf: String,
+    expires_at: Instant,
+}
+
+static SESSIONS: Lazy<Mutex<HashMap<String, AdminSession>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Open a session for `user_id` and return its token
+pub fn open_session(user_id: i64) -> String {
+    let token = random_token();
+    let now = Instant::now();
+    let mut sessions = SESSIONS.lock().unwrap();
+    sessions.retain(|_, session| session.expires_at > now);
+    sessions.insert(
+        token.clone(),
+        AdminSession {
+            user_id,
+            csrf: random_token(),
+            expires_at: now + SESSION_TTL,
+        },
+    );
+    token
+}
+
+/// The unexpired session behind `token`
+pub fn session(token: &str) -> Option<AdminSession> {
+    let sessions = SESSIONS.lock().unwrap();
+    sessions
+        .get(token)
+        .filter(|session| session.expires_at > Instant::now())
+        .cloned()
+}
+
+pub fn close_session(token: &str) {
+    SESSIONS.lock().unwrap().remove(token);
+}
+
+/// What the economy page shows
+#[derive(Debug, Clone)]
+pub struct EconomyView {
+    pub node_id: String,
+    /// Every reason's totals per minute, oldest first
+    pub minutes: Vec<EconomyMinuteRow>,
+    pub minted_threshold: Threshold,
+    pub sunk_threshold: Threshold,
+    /// Totals per reason over the same minutes
+    pub reasons: Vec<EconomyFlowRow>,
+    pub top_earners: Vec<TopEarnerRow>,
+    /// Wealth spikes nobody has acknowledged
+    pub anomalies: Vec<EconomyAnomalyRow>,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Everything the dashboard shows
+#[derive(Debug, Clone)]
+pub struct Overview {
+    pub node_id: String,
+    pub online_players: i64,
+    pub processes: Vec<ProcessTypeCount>,
+    pub audit: Vec<AuditEntry>,
+    pub jobs: Vec<CronJob>,
+    /// From he-cron's analytics job, newest day first
+    pub activity: Vec<DailyActivityRow>,
+    /// Sorted by name
+    pub flags: Vec<FeatureFlag>,
+    pub generated_at: DateTime<Utc>,
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Cents as `-1234.56`
+fn format_money(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    format!("{}{}.{:02}", sign, cents / 100, cents % 100)
+}
+
+fn job_status(job: &CronJob) -> (&'static str, &'static str) {
+    if job.paused {
+        ("paused", "muted")
+    } else if job.is_running() {
+        ("running", "ok")
+    } else {
+        match job.last_success {
+            Some(true) => ("ok", "ok"),
+            Some(false) => ("failed", "error"),
+            None => ("never run", "muted"),
+        }
+    }
+}
+
+fn detail(entry: &AuditEntry) -> String {
+    let detail = entry.event_data.to_string();
+    if detail.chars().count() <= DETAIL_LENGTH {
+        detail
+    } else {
+        format!("{}…", detail.chars().take(DETAIL_LENGTH).collect::<String>())
+    }
+}
+
+#[component]
+fn Page(
+    title: &'static str,
+    /// Reload the page this often, in seconds
+    #[prop(optional)]
+    refresh: Option<u32>,
+    children: Children,
+) -> impl IntoView {
+    view! {
+        <!DOCTYPE html>
+        <html lang="en">
+            <head>
+                <meta charset="utf-8"/>
+                <meta name="viewport" content="width=device-width, initial-scale=1"/>
+                <meta name="robots" content="noindex"/>
+                {refresh.map(|secs| view! { <meta http-equiv="refresh" content=secs.to_string()/> })}
+                <title>{title}</title>
+                <style>{CSS}</style>
+            </head>
+            <body>{children()}</body>
+        </html>
+    }
+}
+
+/// The sign-in form, with the reason the last attempt failed
+pub fn render_sign_in(error: Option<String>) -> String {
+    leptos::ssr::render_to_string(move || {
+        view! {
+            <Page title="HackerExperience admin - sign in">
+                <section class="sign-in">
+                    <h1>"HackerExperience admin"</h1>
+                    {error.clone().map(|error| view! { <p class="error">{error}</p> })}
+                    <form method="post" action="/admin/login">
+                        <label for="email">"Email"</label>
+                        <input id="email" name="email" type="email" autocomplete="username" required=true/>
+                        <label for="password">"Password"</label>
+                        <input id="password" name="password" type="password" autocomplete="current-password" required=true/>
+                        <label for="code">"MFA code"</label>
+                        <input id="code" name="code" inputmode="numeric" autocomplete="one-time-code" required=true/>
+                        <button type="submit">"Sign in"</button>
+                    </form>
+                </section>
+            </Page>
+        }
+    })
+    .to_string()
+}
+
+/// The dashboard; `csrf` is the session's form token
+pub fn render_overview(overview: Overview, csrf: String) -> String {
+    leptos::ssr::render_to_string(move || {
+        let Overview { node_id, online_players, processes, audit, jobs, activity, flags, generated_at } = overview.clone();
+        let csrf = csrf.clone();
+
+        let processes = processes
+            .into_iter()
+            .map(|count| view! { <tr><td>{count.process_type}</td><td>{count.active}</td></tr> })
+            .collect_view();
+
+        let audit = audit
+            .into_iter()
+            .map(|entry| {
+                let detail = detail(&entry);
+                let user = entry.user_id.map(|id| id.to_string()).unwrap_or_default();
+                view! {
+                    <tr>
+                        <td class="muted">{format_time(entry.timestamp)}</td>
+                        <td class=entry.severity.clone()>{entry.event_type}</td>
+                        <td>{user}</td>
+                        <td class="muted">{detail}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        let jobs = jobs
+            .into_iter()
+            .map(|job| {
+                let (status, class) = job_status(&job);
+                let next_run = job.next_run_at.map(format_time).unwrap_or_default();
+                let error = job.last_error.clone().unwrap_or_default();
+                view! {
+                    <tr>
+                        <td>{job.name}</td>
+                        <td class=class>{status}</td>
+                        <td class="muted">{next_run}</td>
+                        <td>{job.failure_count}" / "{job.run_count}</td>
+                        <td class="error">{error}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        let activity = activity
+            .into_iter()
+            .map(|day| {
+                view! {
+                    <tr>
+                        <td class="muted">{day.day.to_string()}</td>
+                        <td>{day.dau}</td>
+                        <td>{day.wau}</td>
+                        <td>{day.new_players}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        let flags = flags
+            .into_iter()
+            .map(|flag| {
+                let (state, class, action) = if flag.enabled {
+                    ("on", "ok", "Disable")
+                } else {
+                    ("off", "muted", "Enable")
+                };
+                let by = flag.updated_by.map(|id| id.to_string()).unwrap_or_default();
+                view! {
+                    <tr>
+                        <td>{flag.name.clone()}</td>
+                        <td class=class>{state}</td>
+                        <td class="muted">{format_time(flag.updated_at)}" "{by}</td>
+                        <td>
+                            <form class="inline" method="post" action="/admin/flags">
+                                <input type="hidden" name="csrf" value=csrf.clone()/>
+                                <input type="hidden" name="name" value=flag.name/>
+                                <input type="hidden" name="enabled" value=(!flag.enabled).to_string()/>
+                                <button type="submit">{action}</button>
+                            </form>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        view! {
+            <Page title="HackerExperience admin">
+                <header>
+                    <h1>"HackerExperience admin"</h1>
+                    <nav><a href="/admin/economy">"Economy"</a></nav>
+                    <span class="muted">{node_id}" · "{format_time(generated_at)}" UTC"</span>
+                    <form class="inline" method="post" action="/admin/logout">
+                        <input type="hidden" name="csrf" value=csrf.clone()/>
+                        <button type="submit">"Sign out"</button>
+                    </form>
+                </header>
+                <main>
+                    <section>
+                        <h2>"Players online"</h2>
+                        <div class="big">{online_players}</div>
+                    </section>
+                    <section>
+                        <h2>"Running processes"</h2>
+                        <table>
+                            <tr><th>"Type"</th><th>"Active"</th></tr>
+                            {processes}
+                        </table>
+                    </section>
+                    <section>
+                        <h2>"Jobs"</h2>
+                        <table>
+                            <tr><th>"Job"</th><th>"Status"</th><th>"Next run"</th><th>"Failed / runs"</th><th>"Last error"</th></tr>
+                            {jobs}
+                        </table>
+                    </section>
+                    <section>
+                        <h2>"Daily actives"</h2>
+                        <table>
+                            <tr><th>"Day"</th><th>"DAU"</th><th>"WAU"</th><th>"New players"</th></tr>
+                            {activity}
+                        </table>
+                    </section>
+                    <section>
+                        <h2>"Feature flags"</h2>
+                        <table>
+                            <tr><th>"Flag"</th><th>"State"</th><th>"Changed"</th><th></th></tr>
+                            {flags}
+                        </table>
+                    </section>
+                    <section>
+                        <h2>"Recent audit events"</h2>
+                        <table>
+                            <tr><th>"Time"</th><th>"Event"</th><th>"User"</th><th>"Detail"</th></tr>
+                            {audit}
+                        </table>
+                    </section>
+                </main>
+            </Page>
+        }
+    })
+    .to_string()
+}
+
+/// The economy page; `csrf` is the session's form token
+pub fn render_economy(view: EconomyView, csrf: String) -> String {
+    leptos::ssr::render_to_string(move || {
+        let EconomyView {
+            node_id,
+            minutes,
+            minted_threshold,
+            sunk_threshold,
+            reasons,
+            top_earners,
+            anomalies,
+            generated_at,
+        } = view.clone();
+        let csrf = csrf.clone();
+
+        // The newest minute is still filling up
+        let last = minutes.iter().rev().nth(1).cloned();
+        let (last_minted, last_sunk) = last.map(|row| (row.minted, row.sunk)).unwrap_or((0, 0));
+        let minted_level = alert_level(last_minted, &minted_threshold);
+        let sunk_level = alert_level(last_sunk, &sunk_threshold);
+
+        let minutes = minutes
+            .into_iter()
+            .rev()
+            .map(|row| {
+                view! {
+                    <tr>
+                        <td class="muted">{row.minute.format("%H:%M").to_string()}</td>
+                        <td class=format!("num {}", alert_level(row.minted, &minted_threshold))>{format_money(row.minted)}</td>
+                        <td class=format!("num {}", alert_level(row.sunk, &sunk_threshold))>{format_money(row.sunk)}</td>
+                        <td class="num">{format_money(row.minted - row.sunk)}</td>
+                        <td class="num">{row.transactions}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        let reasons = reasons
+            .into_iter()
+            .map(|row| {
+                view! {
+                    <tr>
+                        <td>{row.reason}</td>
+                        <td class="num">{row.transactions}</td>
+                        <td class="num">{format_money(row.minted)}</td>
+                        <td class="num">{format_money(row.sunk)}</td>
+                        <td class="num">{format_money(row.volume)}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        let top_earners = top_earners
+            .into_iter()
+            .map(|row| {
+                view! {
+                    <tr>
+                        <td>{row.login.unwrap_or_default()}" "<span class="muted">"#"{row.user_id}</span></td>
+                        <td class="num">{format_money(row.earned)}</td>
+                        <td class="num">{format_money(row.spent)}</td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        let anomalies = anomalies
+            .into_iter()
+            .map(|row| {
+                view! {
+                    <tr>
+                        <td class="muted">{format_time(row.window_start)}</td>
+                        <td>{row.login.unwrap_or_default()}" "<span class="muted">"#"{row.user_id}</span></td>
+                        <td class="num warning">{format_money(row.earned)}</td>
+                        <td class="num">{format_money(row.baseline.round() as i64)}</td>
+                        <td class="num">{format!("{:.0}x", row.ratio)}</td>
+                        <td>
+                            <form class="inline" method="post" action=format!("/admin/economy/anomalies/{}/acknowledge", row.id)>
+                                <input type="hidden" name="csrf" value=csrf.clone()/>
+                                <button type="submit">"Acknowledge"</button>
+                            </form>
+                        </td>
+                    </tr>
+                }
+            })
+            .collect_view();
+
+        view! {
+            <Page title="HackerExperience admin - economy" refresh=ECONOMY_REFRESH_SECS>
+                <header>
+                    <h1>"Economy"</h1>
+                    <nav><a href="/admin">"Overview"</a></nav>
+                    <span class="muted">{node_id}" · "{format_time(generated_at)}" UTC"</span>
+                </header>
+                <main>
+                    <section>
+                        <h2>"Minted last minute"</h2>
+                        <div class=format!("big {}", minted_level)>{format_money(last_minted)}</div>
+                        <p class="muted">
+                            "Warning "{format_money(minted_threshold.warning as i64)}
+                            ", critical "{format_money(minted_threshold.critical as i64)}
+                        </p>
+                    </section>
+                    <section>
+                        <h2>"Sunk last minute"</h2>
+                        <div class=format!("big {}", sunk_level)>{format_money(last_sunk)}</div>
+                        <p class="muted">
+                            "Warning "{format_money(sunk_threshold.warning as i64)}
+                            ", critical "{format_money(sunk_threshold.critical as i64)}
+                        </p>
+                    </section>
+                    <section>
+                        <h2>"Wealth spikes"</h2>
+                        <table>
+                            <tr><th>"Window"</th><th>"Player"</th><th>"Earned"</th><th>"Baseline"</th><th>"Ratio"</th><th></th></tr>
+                            {anomalies}
+                        </table>
+                    </section>
+                    <section>
+                        <h2>"Top earners"</h2>
+                        <table>
+                            <tr><th>"Player"</th><th>"Earned"</th><th>"Spent"</th></tr>
+                            {top_earners}
+                        </table>
+                    </section>
+                    <section>
+                        <h2>"Money per minute"</h2>
+                        <table>
+                            <tr><th>"Minute"</th><th>"Minted"</th><th>"Sunk"</th><th>"Net"</th><th>"Transactions"</th></tr>
+                            {minutes}
+                        </table>
+                    </section>
+                    <section>
+                        <h2>"By reason"</h2>
+                        <table>
+                            <tr><th>"Reason"</th><th>"Transactions"</th><th>"Minted"</th><th>"Sunk"</th><th>"Volume"</th></tr>
+                            {reasons}
+                        </table>
+                    </section>
+                </main>
+            </Page>
+        }
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(paused: bool, last_success: Option<bool>, started: Option<DateTime<Utc>>) -> CronJob {
+        let now = Utc::now();
+        CronJob {
+            name: "analytics".to_string(),
+            schedule: "0 * * * *".to_string(),
+            time_budget_seconds: 60,
+            paused,
+            trigger_requested_at: None,
+            next_run_at: None,
+            last_started_at: started,
+            last_finished_at: started.map(|_| now - chrono::Duration::hours(1)),
+            last_duration_ms: None,
+            last_success,
+            last_error: None,
+            run_count: 3,
+            failure_count: 1,
+            updated_at: now,
+        }
+    }
+
+    fn overview() -> Overview {
+        let now = Utc::now();
+        Overview {
+            node_id: "node-a".to_string(),
+            online_players: 42,
+            processes: vec![ProcessTypeCount { process_type: "cracker".to_string(), active: 7 }],
+            audit: vec![AuditEntry {
+                id: 1,
+                timestamp: now,
+                event_type: "LoginFailed".to_string(),
+                severity: "warning".to_string(),
+                user_id: Some(9),
+                event_data: serde_json::json!({ "reason": "<script>alert(1)</script>" }),
+            }],
+            jobs: vec![job(false, Some(false), None)],
+            activity: Vec::new(),
+            flags: vec![FeatureFlag { name: "doom".to_string(), enabled: true, updated_by: Some(1), updated_at: now }],
+            generated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_sessions_open_and_close() {
+        let token = open_session(17);
+        assert_eq!(token.len(), TOKEN_LENGTH);
+
+        let opened = session(&token).expect("an open session");
+        assert_eq!(opened.user_id, 17);
+        assert_eq!(opened.csrf.len(), TOKEN_LENGTH);
+        assert_ne!(opened.csrf, token);
+
+        close_session(&token);
+        assert!(session(&token).is_none());
+        assert!(session("not-a-token").is_none());
+    }
+
+    #[test]
+    fn test_money_and_job_status() {
+        assert_eq!(format_money(123_456), "1234.56");
+        assert_eq!(format_money(-5), "-0.05");
+
+        let now = Utc::now();
+        assert_eq!(job_status(&job(true, Some(false), None)), ("paused", "muted"));
+        assert_eq!(job_status(&job(false, Some(true), Some(now))), ("running", "ok"));
+        assert_eq!(job_status(&job(false, Some(false), None)), ("failed", "error"));
+        assert_eq!(job_status(&job(false, None, None)), ("never run", "muted"));
+    }
+
+    #[test]
+    fn test_long_audit_details_are_cut() {
+        let mut entry = overview().audit.remove(0);
+        entry.event_data = serde_json::json!({ "detail": "x".repeat(DETAIL_LENGTH * 2) });
+        let detail = detail(&entry);
+        assert_eq!(detail.chars().count(), DETAIL_LENGTH + 1);
+        assert!(detail.ends_with('…'));
+    }
+
+    #[test]
+    fn test_overview_escapes_values_and_carries_the_form_token() {
+        let html = render_overview(overview(), "csrf-token".to_string());
+        assert!(html.contains("node-a"));
+        assert!(html.contains("cracker"));
+        assert!(html.contains("value=\"csrf-token\""));
+        assert!(html.contains("Disable"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn test_sign_in_shows_the_failure() {
+        let html = render_sign_in(Some("Invalid MFA code".to_string()));
+        assert!(html.contains("Invalid MFA code"));
+        assert!(html.contains("action=\"/admin/login\""));
+        assert!(!render_sign_in(None).contains("class=\"error\""));
+    }
+}
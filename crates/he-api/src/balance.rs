@@ -0,0 +1,46 @@
+//! Game balance configuration
+//!
+//! The [`GameConfig`] is read once, at startup, by
+//! [`he_game_mechanics::config::load_config`]; sections the file leaves out
+//! keep their defaults. Mission generation rates come from the `[missions]`
+//! table of the balance file instead, like the rules in [`crate::rules`].
+//! Everything that reads a balance value goes through [`current`], so every
+//! node runs the same numbers.
+
+use he_game_mechanics::config::{self, GameConfig, MissionGenConfig};
+use once_cell::sync::OnceCell;
+
+const DEFAULT_BALANCE_PATH: &str = "game-balance.toml";
+
+static CONFIG: OnceCell<GameConfig> = OnceCell::new();
+
+/// Read the game configuration
+pub fn load() -> &'static GameConfig {
+    CONFIG.get_or_init(|| {
+        let mut config = config::load_config().unwrap_or_else(|e| {
+            tracing::warn!("Could not load game configuration, using defaults: {}", e);
+            GameConfig::default()
+        });
+        config.mission_gen = mission_gen();
+        config
+    })
+}
+
+/// The configuration the server runs with
+pub fn current() -> &'static GameConfig {
+    load()
+}
+
+fn mission_gen() -> MissionGenConfig {
+    let path = std::env::var("GAME_BALANCE_PATH").unwrap_or_else(|_| DEFAULT_BALANCE_PATH.to_string());
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => MissionGenConfig::from_balance(&contents).unwrap_or_else(|e| {
+            tracing::warn!("Invalid balance file {}, using default mission generation: {}", path, e);
+            MissionGenConfig::default()
+        }),
+        Err(e) => {
+            tracing::warn!("Could not read balance file {}, using default mission generation: {}", path, e);
+            MissionGenConfig::default()
+        }
+    }
+}
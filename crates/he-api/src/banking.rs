@@ -0,0 +1,290 @@
+//! NPC bank network
+//!
+//! Opening accounts, transfers and laundering, each in one transaction with
+//! its ledger posting; the rules are in [`he_game_mechanics::banking`].
+//! Moving money out of someone else's account is a theft. It needs the
+//! account's bank hacked, is logged with the thief's gateway address where
+//! the bank's tier keeps it or the thief is traced, and marks the money so
+//! the victim can follow it from `/api/bank/thefts`. The victim is told
+//! through the outbox.
+//!
+//! Stolen money an account held past its balance was spent on something
+//! other than a transfer, and leaves the trail when the account next moves
+//! money.
+
+use he_database::queries::{
+    BankAccountRow, BankNetworkQueries, BankRow, IpResetQueries, LedgerAccount, LedgerQueries, LedgerReason,
+    LockedBankAccount, NewBankTransfer,
+};
+use he_game_mechanics::banking::{
+    self, BankTerms, LaunderDenied, OpenDenied, SecurityTier, TransferCheck, TransferDenied,
+};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use crate::outbox::{self, OutboxMessage};
+
+/// What a transfer or laundering did
+#[derive(Debug, Clone, Serialize)]
+pub struct Receipt {
+    pub transfer_id: i64,
+    pub kind: &'static str,
+    pub amount: i64,
+    pub fee: i64,
+    /// Stolen money the transfer carried or laundering cleaned
+    pub stolen: i64,
+}
+
+fn tier(level: i16) -> SecurityTier {
+    SecurityTier::from_level(level).unwrap_or(SecurityTier::Basic)
+}
+
+fn account_terms(account: &LockedBankAccount) -> BankTerms {
+    BankTerms {
+        bank_id: account.bank_id,
+        tier: tier(account.security_tier),
+        transfer_fee_bps: account.transfer_fee_bps as i64,
+        min_transfer_fee: account.min_transfer_fee,
+        laundering_fee_bps: None,
+    }
+}
+
+fn bank_terms(bank: &BankRow) -> BankTerms {
+    BankTerms {
+        bank_id: bank.id,
+        tier: tier(bank.security_tier),
+        transfer_fee_bps: bank.transfer_fee_bps as i64,
+        min_transfer_fee: bank.min_transfer_fee,
+        laundering_fee_bps: bank.laundering_fee_bps.map(i64::from),
+    }
+}
+
+/// Open an account at another bank
+pub async fn open_account(pool: &PgPool, user_id: i64, bank_id: i64) -> anyhow::Result<Result<BankAccountRow, OpenDenied>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    if BankNetworkQueries::bank(&mut *tx, bank_id).await?.is_none() {
+        tx.rollback().await?;
+        return Ok(Err(OpenDenied::BankNotFound));
+    }
+
+    let (open_accounts, already_customer) = BankNetworkQueries::lock_customer(&mut *tx, user_id, bank_id).await?;
+    if let Err(denied) = banking::check_open(already_customer, open_accounts as usize, &crate::balance::current().banking) {
+        tx.rollback().await?;
+        return Ok(Err(denied));
+    }
+
+    let account = BankNetworkQueries::open_account(&mut *tx, user_id, bank_id).await?;
+    tx.commit().await?;
+    Ok(Ok(account))
+}
+
+/// The account's stolen lots, after dropping what it no longer holds
+async fn held_stolen(conn: &mut PgConnection, account: &LockedBankAccount) -> anyhow::Result<Vec<(i64, i64)>> {
+    let mut lots = BankNetworkQueries::stolen_lots(&mut *conn, account.id).await?;
+    let held: i64 = lots.iter().map(|(_, amount)| amount).sum();
+    let spent = banking::take_stolen(&mut lots, held - account.balance);
+    if !spent.is_empty() {
+        BankNetworkQueries::release_stolen(conn, account.id, &spent).await?;
+    }
+    Ok(lots)
+}
+
+/// Move money between two accounts by number. The player must own the
+/// source account or have hacked its bank.
+pub async fn transfer(
+    pool: &PgPool,
+    user_id: i64,
+    from_number: &str,
+    to_number: &str,
+    amount: i64,
+) -> anyhow::Result<Result<Receipt, TransferDenied>> {
+    if from_number == to_number {
+        return Ok(Err(TransferDenied::SameAccount));
+    }
+    let config = &crate::balance::current().banking;
+    let mut tx = he_database::tagging::begin(pool).await?;
+
+    let (Some(from), Some(to)) = BankNetworkQueries::lock_pair(&mut *tx, from_number, to_number, user_id).await? else {
+        tx.rollback().await?;
+        return Ok(Err(TransferDenied::AccountNotFound));
+    };
+    let terms = account_terms(&from);
+    let theft = match (from.user_id == user_id, from.bank_hacked) {
+        (true, _) => None,
+        (false, true) => Some(terms.tier),
+        (false, false) => {
+            tx.rollback().await?;
+            return Ok(Err(TransferDenied::NoAccess));
+        }
+    };
+    let recent_theft = match theft {
+        Some(_) => BankNetworkQueries::recent_theft(&mut *tx, user_id, from.id, config.theft_cooldown_hours).await?,
+        None => false,
+    };
+    let Some(fee) = banking::transfer_fee(amount, &terms, to.bank_id) else {
+        tx.rollback().await?;
+        return Ok(Err(TransferDenied::AmountTooLarge { max: config.max_transfer }));
+    };
+    let check = TransferCheck { amount, fee, balance: from.balance, theft, recent_theft };
+    if let Err(denied) = banking::check_transfer(&check, config) {
+        tx.rollback().await?;
+        return Ok(Err(denied));
+    }
+
+    let mut lots = held_stolen(&mut *tx, &from).await?;
+    let mut carried = banking::take_stolen(&mut lots, amount);
+    let already_stolen: i64 = carried.iter().map(|(_, amount)| amount).sum();
+    // Traced thieves are logged by every bank
+    let thief_ip = match theft {
+        Some(tier) if tier.logs_thief_ip(config) || crate::honeypot::traced(&mut *tx, user_id).await? => {
+            IpResetQueries::gateway_ip(&mut *tx, user_id).await?
+        }
+        _ => None,
+    };
+
+    let kind = if theft.is_some() { "theft" } else { "transfer" };
+    let stolen = if theft.is_some() { amount } else { already_stolen };
+    let transfer_id = BankNetworkQueries::record_transfer(&mut *tx, &NewBankTransfer {
+        kind,
+        initiator_id: user_id,
+        initiator_ip: thief_ip.as_deref(),
+        from: &from,
+        to: &to,
+        via_bank_id: None,
+        amount,
+        fee,
+        stolen,
+    })
+    .await?;
+
+    if !carried.is_empty() {
+        BankNetworkQueries::release_stolen(&mut *tx, from.id, &carried).await?;
+    }
+    // Whatever a theft took that was not stolen already is stolen now
+    if theft.is_some() && amount > already_stolen {
+        carried.push((transfer_id, amount - already_stolen));
+    }
+    if !carried.is_empty() {
+        BankNetworkQueries::carry_stolen(&mut *tx, transfer_id, Some(to.id), &carried).await?;
+    }
+
+    let reason = if theft.is_some() { LedgerReason::Theft } else { LedgerReason::Transfer };
+    let legs = [
+        (LedgerAccount::Bank(from.id), -(amount + fee)),
+        (LedgerAccount::Bank(to.id), amount),
+        (LedgerAccount::Sink, fee),
+    ];
+    if !LedgerQueries::post(&mut *tx, reason, &format!("bank_transfer:{}", transfer_id), &legs).await? {
+        tx.rollback().await?;
+        return Ok(Err(TransferDenied::InsufficientFunds { needed: amount + fee, balance: from.balance }));
+    }
+
+    if theft.is_some() {
+        outbox::enqueue(&mut *tx, &[theft_message(transfer_id, &from, &to, amount, thief_ip.as_deref())]).await?;
+    }
+    tx.commit().await?;
+    if theft.is_some() {
+        outbox::wake();
+    }
+
+    Ok(Ok(Receipt { transfer_id, kind, amount, fee, stolen }))
+}
+
+/// Tell the victim money left their account
+fn theft_message(
+    theft_id: i64,
+    from: &LockedBankAccount,
+    to: &LockedBankAccount,
+    amount: i64,
+    thief_ip: Option<&str>,
+) -> OutboxMessage {
+    let event = he_websocket::GameEvent::Custom {
+        event_name: "bank_theft".to_string(),
+        payload: json!({
+            "theft_id": theft_id,
+            "account": from.account_number,
+            "amount": amount,
+            "to_account": to.account_number,
+            "thief_ip": thief_ip,
+        }),
+    };
+    OutboxMessage::to_user(format!("bank_theft:{}", theft_id), from.user_id, &event)
+}
+
+/// Clean `amount` of an account's stolen money through a bank that
+/// launders. The fee comes out of the account and ends every trail through
+/// the laundered money.
+pub async fn launder(
+    pool: &PgPool,
+    user_id: i64,
+    account_number: &str,
+    bank_id: i64,
+    amount: i64,
+) -> anyhow::Result<Result<Receipt, LaunderDenied>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(account) = BankNetworkQueries::lock_own(&mut *tx, user_id, account_number).await? else {
+        tx.rollback().await?;
+        return Ok(Err(LaunderDenied::AccountNotFound));
+    };
+    let Some(bank) = BankNetworkQueries::bank(&mut *tx, bank_id).await? else {
+        tx.rollback().await?;
+        return Ok(Err(LaunderDenied::BankNotFound));
+    };
+
+    let mut lots = held_stolen(&mut *tx, &account).await?;
+    let stolen: i64 = lots.iter().map(|(_, amount)| amount).sum();
+    let fee = match banking::check_launder(amount, stolen, &bank_terms(&bank), &crate::balance::current().banking) {
+        Ok(fee) => fee,
+        Err(denied) => {
+            tx.rollback().await?;
+            return Ok(Err(denied));
+        }
+    };
+    let laundered = banking::take_stolen(&mut lots, amount);
+
+    let transfer_id = BankNetworkQueries::record_transfer(&mut *tx, &NewBankTransfer {
+        kind: "laundering",
+        initiator_id: user_id,
+        initiator_ip: None,
+        from: &account,
+        to: &account,
+        via_bank_id: Some(bank.id),
+        amount,
+        fee,
+        stolen: amount,
+    })
+    .await?;
+    BankNetworkQueries::release_stolen(&mut *tx, account.id, &laundered).await?;
+    BankNetworkQueries::carry_stolen(&mut *tx, transfer_id, None, &laundered).await?;
+
+    let legs = [(LedgerAccount::Bank(account.id), -fee), (LedgerAccount::Sink, fee)];
+    if !LedgerQueries::post(&mut *tx, LedgerReason::Laundering, &format!("bank_transfer:{}", transfer_id), &legs).await? {
+        anyhow::bail!("account {} cannot cover a laundering fee below its stolen money", account.id);
+    }
+    tx.commit().await?;
+
+    Ok(Ok(Receipt { transfer_id, kind: "laundering", amount, fee, stolen: amount }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bank_terms() {
+        let bank = BankRow {
+            id: 4,
+            name: "Cayman Offshore".to_string(),
+            security_tier: 1,
+            transfer_fee_bps: 300,
+            min_transfer_fee: 1_000,
+            laundering_fee_bps: Some(2_500),
+            ip_address: None,
+        };
+        let terms = bank_terms(&bank);
+        assert_eq!(terms.tier, SecurityTier::Basic);
+        assert_eq!(banking::laundering_fee(100_000, &terms), Some(25_000));
+        assert_eq!(banking::transfer_fee(100_000, &terms, 1), Some(3_000));
+        assert_eq!(tier(9), SecurityTier::Basic);
+    }
+}
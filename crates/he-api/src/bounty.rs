@@ -0,0 +1,458 @@
+//! Player bounties
+//!
+//! Placing a bounty escrows the reward from the placer's bank account and
+//! lists the target on the board. Breaking into the target's gateway claims
+//! every open bounty on them the hunter is eligible for, inside the hack's
+//! completion transaction; [`bounty::check_claim`] refuses colluding hunters
+//! and the bounty stays open. A sweeper refunds bounties whose window closed.
+//!
+//! As with co-op missions, what follows from a state change, the
+//! notifications and socket pushes to placer, target and hunter, is published
+//! as he-events events and handled by the coordinator. The events are queued
+//! in the outbox by the transaction that makes the change, so none is lost if
+//! the node dies right after commit.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use he_core::{HelixError, HelixResult};
+use he_database::models::Process;
+use he_database::queries::{
+    BankQueries, BountyClaimSignals, BountyPlacementState, BountyQueries, BountyRow, LedgerAccount, LedgerReason,
+};
+use crate::event_schemas;
+use crate::outbox::{self, OutboxMessage};
+use he_events::{DispatchConfig, Event, EventData, EventDispatcher, EventHandler, EventSchema, EventType};
+use he_game_mechanics::bounty::{self, ClaimBlocked, ClaimSignals, PlaceDenied};
+use he_helix_notification::model::CreateNotificationParams;
+use he_helix_notification::{NotificationCenter, NotificationClass};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use he_database::models::legacy_user_uuid;
+
+/// `data_type` of the he-events payloads published here
+pub(crate) const DATA_TYPE: &str = "bounty";
+/// How often closed windows are refunded
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+const EXPIRY_BATCH: i64 = 200;
+
+static DISPATCHER: OnceCell<EventDispatcher> = OnceCell::const_new();
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BountyEvent {
+    pub bounty_id: i64,
+    pub placer_id: i64,
+    pub target_id: i64,
+    pub reward: i64,
+    #[serde(flatten)]
+    pub kind: BountyEventKind,
+    /// Set when delivered from the outbox; the same on every redelivery
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BountyEventKind {
+    Placed { expires_at: DateTime<Utc> },
+    Claimed { hunter_id: i64 },
+    ClaimBlocked { hunter_id: i64, blocked: ClaimBlocked },
+    Expired { refunded: bool },
+}
+
+impl BountyEventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            BountyEventKind::Placed { .. } => "bounty_placed",
+            BountyEventKind::Claimed { .. } => "bounty_claimed",
+            BountyEventKind::ClaimBlocked { .. } => "bounty_claim_blocked",
+            BountyEventKind::Expired { .. } => "bounty_expired",
+        }
+    }
+
+    pub(crate) fn event_types() -> Vec<EventType> {
+        ["bounty_placed", "bounty_claimed", "bounty_claim_blocked", "bounty_expired"]
+            .into_iter()
+            .map(|name| EventType::Custom(name.to_string()))
+            .collect()
+    }
+}
+
+impl EventSchema for BountyEvent {
+    const VERSION: u32 = 1;
+
+    fn fixtures() -> Vec<(u32, serde_json::Value)> {
+        vec![(1, serde_json::json!({
+            "bounty_id": 4,
+            "placer_id": 10,
+            "target_id": 20,
+            "reward": 50_000,
+            "event": "claimed",
+            "hunter_id": 30
+        }))]
+    }
+}
+
+impl BountyEvent {
+    pub fn placed(bounty: &BountyRow) -> Self {
+        Self::new(bounty, BountyEventKind::Placed { expires_at: bounty.expires_at })
+    }
+
+    fn new(bounty: &BountyRow, kind: BountyEventKind) -> Self {
+        Self {
+            bounty_id: bounty.id,
+            placer_id: bounty.placer_id,
+            target_id: bounty.target_id,
+            reward: bounty.reward,
+            kind,
+            dedup_key: None,
+        }
+    }
+
+    /// The event queued in the outbox. `origin` names what caused it, e.g.
+    /// the hacking process, so the same change never queues it twice.
+    pub(crate) fn to_outbox(&self, origin: &str) -> OutboxMessage {
+        OutboxMessage::event(
+            format!("bounty:{}:{}:{}", self.bounty_id, self.kind.name(), origin),
+            DATA_TYPE,
+            serde_json::to_value(self).unwrap_or_default(),
+        )
+    }
+
+    fn to_event(&self) -> Event {
+        Event::new(
+            EventType::Custom(self.kind.name().to_string()),
+            EventData::Custom {
+                data_type: DATA_TYPE.to_string(),
+                payload: serde_json::to_value(self).unwrap_or_default(),
+            },
+        )
+    }
+
+    /// Who hears about the event. Targets are never told who placed it.
+    fn recipients(&self) -> Vec<i64> {
+        match &self.kind {
+            BountyEventKind::Placed { .. } => vec![self.target_id, self.placer_id],
+            BountyEventKind::Claimed { hunter_id } => vec![*hunter_id, self.placer_id, self.target_id],
+            BountyEventKind::ClaimBlocked { hunter_id, .. } => vec![*hunter_id],
+            BountyEventKind::Expired { .. } => vec![self.placer_id],
+        }
+    }
+
+    /// The event as one recipient sees it
+    fn payload_for(&self, user_id: i64) -> serde_json::Value {
+        let mut payload = serde_json::to_value(self).unwrap_or_default();
+        if user_id != self.placer_id {
+            if let Some(payload) = payload.as_object_mut() {
+                payload.remove("placer_id");
+            }
+        }
+        payload
+    }
+}
+
+/// Escrow `reward` and open a bounty on `target_id`
+pub async fn place(
+    pool: &PgPool,
+    placer_id: i64,
+    target_id: i64,
+    reward: i64,
+    window_hours: Option<i64>,
+) -> anyhow::Result<Result<BountyRow, PlaceDenied>> {
+    if !crate::rules::current().bounties_enabled {
+        return Ok(Err(PlaceDenied::Disabled));
+    }
+    let config = &crate::balance::current().bounty;
+    let window_hours = window_hours.unwrap_or(config.default_window_hours);
+
+    let check = |state: BountyPlacementState| {
+        if !state.target_exists {
+            return Err(PlaceDenied::TargetNotFound);
+        }
+        bounty::check_place(
+            placer_id,
+            target_id,
+            reward,
+            window_hours,
+            state.open_placed as usize,
+            state.balance,
+            config,
+        )
+    };
+
+    if let Err(denied) = check(BountyQueries::placement_state(pool, placer_id, target_id).await?) {
+        return Ok(Err(denied));
+    }
+
+    let max_open = config.max_open_per_placer as i64;
+    let mut tx = he_database::tagging::begin(pool).await?;
+    match BountyQueries::place(&mut *tx, placer_id, target_id, reward, window_hours, max_open).await? {
+        Some(bounty) => {
+            outbox::enqueue(&mut *tx, &[BountyEvent::placed(&bounty).to_outbox("place")]).await?;
+            tx.commit().await?;
+            outbox::wake();
+            Ok(Ok(bounty))
+        }
+        None => {
+            tx.rollback().await?;
+            // Lost a race with another placement or a payment; say why now
+            Ok(Err(check(BountyQueries::placement_state(pool, placer_id, target_id).await?)
+                .err()
+                .unwrap_or(PlaceDenied::InsufficientFunds { reward, balance: 0 })))
+        }
+    }
+}
+
+/// Claim the bounties on the owner of the gateway `process` broke into, in
+/// its completion transaction
+pub(crate) async fn claim(conn: &mut PgConnection, process: &Process, target_ip: &str) -> anyhow::Result<Vec<BountyEvent>> {
+    let config = &crate::balance::current().bounty;
+    let hunter_id = process.user_id;
+    let mut events = Vec::new();
+
+    for open in BountyQueries::open_on_ip(conn, target_ip).await? {
+        let signals = BountyQueries::claim_signals(conn, open.id, hunter_id, config.transfer_lookback_days).await?;
+
+        let blocked = match bounty::check_claim(&claim_signals(signals), config) {
+            Ok(()) => {
+                let reference = format!("bounty:{}", open.id);
+                let paid = BankQueries::credit_primary_account(
+                    conn,
+                    hunter_id,
+                    open.reward,
+                    LedgerAccount::BountyEscrow,
+                    LedgerReason::BountyPayout,
+                    &reference,
+                )
+                .await?;
+                if paid {
+                    BountyQueries::mark_claimed(conn, open.id, hunter_id).await?;
+                    events.push(BountyEvent::new(&open, BountyEventKind::Claimed { hunter_id }));
+                    continue;
+                }
+                ClaimBlocked::NoBankAccount
+            }
+            Err(blocked) => blocked,
+        };
+
+        // The hunter being the placer is not worth a moderator's time
+        if blocked != ClaimBlocked::Placer {
+            BountyQueries::record_blocked(conn, open.id, hunter_id, reason(&blocked)).await?;
+            events.push(BountyEvent::new(&open, BountyEventKind::ClaimBlocked { hunter_id, blocked }));
+        }
+    }
+
+    Ok(events)
+}
+
+fn claim_signals(row: BountyClaimSignals) -> ClaimSignals {
+    ClaimSignals {
+        hunter_is_placer: row.hunter_is_placer,
+        clanmate_of_placer: row.clanmate_of_placer,
+        clanmate_of_target: row.clanmate_of_target,
+        shares_ip_with_placer: row.shares_ip_with_placer,
+        shares_ip_with_target: row.shares_ip_with_target,
+        hunter_account_age_hours: row.hunter_account_age_hours,
+        transfers_with_placer: row.transfers_with_placer,
+    }
+}
+
+/// The `reason` tag of a refused claim
+fn reason(blocked: &ClaimBlocked) -> &'static str {
+    match blocked {
+        ClaimBlocked::Placer => "placer",
+        ClaimBlocked::Clanmate => "clanmate",
+        ClaimBlocked::SharedIp => "shared_ip",
+        ClaimBlocked::NewAccount { .. } => "new_account",
+        ClaimBlocked::MoneyTrail => "money_trail",
+        ClaimBlocked::NoBankAccount => "no_bank_account",
+    }
+}
+
+/// Refund bounties whose window closed, every [`EXPIRY_INTERVAL`]
+pub(crate) fn spawn_expiry(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRY_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = expire_due(&pool).await {
+                tracing::warn!("Bounty expiry failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn expire_due(pool: &PgPool) -> anyhow::Result<()> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let expired = BountyQueries::expire_due(&mut *tx, EXPIRY_BATCH).await?;
+    if expired.is_empty() {
+        tx.rollback().await?;
+        return Ok(());
+    }
+
+    let messages: Vec<OutboxMessage> = expired
+        .iter()
+        .map(|(bounty, refunded)| {
+            if !refunded {
+                tracing::warn!("Bounty {} expired but its placer has no bank account; refund held", bounty.id);
+            }
+            // A held refund settles in a later sweep and is announced again
+            let origin = if *refunded { "refund" } else { "expiry" };
+            BountyEvent::new(bounty, BountyEventKind::Expired { refunded: *refunded }).to_outbox(origin)
+        })
+        .collect();
+    outbox::enqueue(&mut *tx, &messages).await?;
+    tx.commit().await?;
+    outbox::wake();
+    Ok(())
+}
+
+/// Hand events to the coordinator; the outbox relay delivers queued events
+/// through here and retries on an error
+pub async fn publish(
+    pool: &PgPool,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+    events: Vec<BountyEvent>,
+) -> anyhow::Result<()> {
+    let dispatcher = dispatcher(pool, ws_manager)
+        .await
+        .map_err(|e| anyhow::anyhow!("bounty event dispatcher unavailable: {}", e))?;
+
+    for event in events {
+        let mut published = event.to_event();
+        if let Err(e) = event_schemas::registry().validate(&mut published) {
+            tracing::error!("Not publishing invalid event for bounty {}: {}", event.bounty_id, e);
+            continue;
+        }
+        dispatcher
+            .dispatch(published)
+            .await
+            .map_err(|e| anyhow::anyhow!("event for bounty {}: {}", event.bounty_id, e))?;
+    }
+
+    Ok(())
+}
+
+/// The dispatcher, started with the coordinator on first use
+async fn dispatcher(
+    pool: &PgPool,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+) -> HelixResult<&'static EventDispatcher> {
+    DISPATCHER
+        .get_or_try_init(|| async {
+            let dispatcher = EventDispatcher::new(DispatchConfig::default()).await?;
+            let coordinator = Arc::new(BountyCoordinator {
+                notifications: NotificationCenter::new(pool.clone(), None),
+                ws_manager,
+            });
+            for event_type in BountyEventKind::event_types() {
+                dispatcher.add_handler(event_type, coordinator.clone()).await;
+            }
+            dispatcher.start().await?;
+            Ok(dispatcher)
+        })
+        .await
+}
+
+/// Notifies everyone a bounty event concerns and pushes it to their sockets
+struct BountyCoordinator {
+    notifications: NotificationCenter,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+}
+
+#[async_trait]
+impl EventHandler for BountyCoordinator {
+    async fn handle(&self, event: &Event) -> HelixResult<()> {
+        let EventData::Custom { data_type, .. } = &event.data else {
+            return Ok(());
+        };
+        if data_type != DATA_TYPE {
+            return Ok(());
+        }
+        let bounty_event: BountyEvent = event_schemas::registry()
+            .decode(event)
+            .map_err(|e| HelixError::internal(format!("Malformed bounty event: {}", e)))?;
+
+        for user_id in bounty_event.recipients() {
+            let mut payload = bounty_event.payload_for(user_id);
+
+            let data: HashMap<String, serde_json::Value> = payload
+                .as_object()
+                .map(|fields| fields.clone().into_iter().collect())
+                .unwrap_or_default();
+            let params = CreateNotificationParams {
+                account_id: legacy_user_uuid(user_id),
+                class: NotificationClass::Entity,
+                code: bounty_event.kind.name().to_string(),
+                data,
+                target_id: None,
+                group_key: None,
+            };
+            match self.notifications.create(params).await {
+                // Lets the client update the collapsed row instead of adding one
+                Ok(Some(stored)) if stored.is_collapsed() => {
+                    if let Some(fields) = payload.as_object_mut() {
+                        fields.insert(
+                            "collapse".to_string(),
+                            serde_json::json!({
+                                "notification_id": stored.notification_id,
+                                "count": stored.count,
+                                "first_time": stored.first_time,
+                            }),
+                        );
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to notify user {} of bounty {}: {}", user_id, bounty_event.bounty_id, e);
+                }
+            }
+
+            if let Some(ws_manager) = &self.ws_manager {
+                let message = he_websocket::GameEvent::Custom {
+                    event_name: "bounty".to_string(),
+                    payload,
+                };
+                ws_manager.send_to_user(user_id, message.to_server_message());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "BountyCoordinator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_is_not_told_the_placer() {
+        let event = BountyEvent {
+            bounty_id: 4,
+            placer_id: 10,
+            target_id: 20,
+            reward: 50_000,
+            kind: BountyEventKind::Claimed { hunter_id: 30 },
+            dedup_key: None,
+        };
+
+        assert_eq!(event.recipients(), vec![30, 10, 20]);
+        assert!(event.payload_for(20).get("placer_id").is_none());
+        assert!(event.payload_for(30).get("placer_id").is_none());
+        assert_eq!(event.payload_for(10)["placer_id"], 10);
+        assert_eq!(event.payload_for(30)["event"], "claimed");
+
+        let published = event.to_event();
+        assert_eq!(published.event_type, EventType::Custom("bounty_claimed".to_string()));
+
+        let queued = event.to_outbox("process:99");
+        assert_eq!(queued.dedup_key, "bounty:4:bounty_claimed:process:99");
+    }
+}
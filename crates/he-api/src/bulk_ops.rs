@@ -0,0 +1,681 @@
+//! Bulk admin data fixes
+//!
+//! Ops tasks like "refund everyone the marketplace double-charged last
+//! Tuesday" are bulk operations: a filter choosing players and one mutation
+//! applied to each of them, a money credit or debit, experience or faction
+//! reputation.
+//!
+//! Planning an operation is its dry run and cannot be skipped. The players
+//! the filter matches are snapshotted with a preview of the first few
+//! values before and after, and executing it needs the matched count the
+//! dry run reported, within [`DRY_RUN_TTL_MINUTES`] of it. Only the
+//! snapshotted players are ever touched.
+//!
+//! The worker applies an operation a chunk at a time, one transaction per
+//! chunk, waiting the operation's throttle between chunks. Every player's
+//! value before and after is recorded, and those records are the rollback
+//! artifact: rolling back applies each recorded difference in reverse, in
+//! chunks the same way. Chunks are claimed with `SKIP LOCKED`, so every node
+//! can run the worker and an operation interrupted by a restart resumes.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use he_database::queries::{
+    BankQueries, BulkChunkCounts, BulkFilter, BulkOperationQueries, BulkOperationRow, BulkTargetRow,
+    LedgerAccount, LedgerQueries, LedgerReason, ProgressionQueries,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
+
+/// How long a dry run can be executed for
+pub const DRY_RUN_TTL_MINUTES: i64 = 60;
+/// Most players one operation may touch
+pub const MAX_MATCHED: i64 = 100_000;
+/// Players listed with their values in the dry run
+const PREVIEW_ROWS: i64 = 20;
+/// Most user ids a filter may list
+const MAX_USER_IDS: usize = 10_000;
+const DEFAULT_CHUNK_SIZE: i32 = 100;
+const MAX_CHUNK_SIZE: i32 = 1000;
+const DEFAULT_THROTTLE_MS: i32 = 250;
+const MAX_THROTTLE_MS: i32 = 60_000;
+/// Largest credit or debit per player, in cents
+const MAX_MONEY: i64 = 100_000_000;
+const MAX_EXPERIENCE: i64 = 1_000_000;
+const MAX_REPUTATION: i32 = 10_000;
+const MAX_TITLE_LENGTH: usize = 200;
+const MAX_REASON_LENGTH: usize = 2000;
+/// How long the worker waits when no chunk is due
+const IDLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Which players an operation applies to; every field narrows the match
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterSpec {
+    #[serde(default)]
+    pub user_ids: Option<Vec<i64>>,
+    #[serde(default)]
+    pub registered_since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub registered_until: Option<DateTime<Utc>>,
+    /// Players with a bank ledger entry for this reason, e.g. the purchase
+    /// a bug charged twice
+    #[serde(default)]
+    pub ledger_reason: Option<String>,
+    #[serde(default)]
+    pub ledger_reference: Option<String>,
+    #[serde(default)]
+    pub ledger_since: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub ledger_until: Option<DateTime<Utc>>,
+}
+
+impl FilterSpec {
+    fn is_empty(&self) -> bool {
+        self.user_ids.is_none()
+            && self.registered_since.is_none()
+            && self.registered_until.is_none()
+            && self.ledger_reason.is_none()
+            && self.ledger_reference.is_none()
+    }
+
+    pub fn to_filter(&self) -> BulkFilter {
+        BulkFilter {
+            user_ids: self.user_ids.clone(),
+            registered_since: self.registered_since,
+            registered_until: self.registered_until,
+            ledger_reason: self.ledger_reason.clone(),
+            ledger_reference: self.ledger_reference.clone(),
+            ledger_since: self.ledger_since,
+            ledger_until: self.ledger_until,
+        }
+    }
+}
+
+/// What an operation does to each player. Negative amounts take away.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mutation {
+    /// Money into the player's oldest active bank account, in cents
+    Credit { amount: i64 },
+    Experience { amount: i64 },
+    Reputation { faction: String, amount: i32 },
+}
+
+impl Mutation {
+    pub fn amount(&self) -> i64 {
+        match self {
+            Mutation::Credit { amount } | Mutation::Experience { amount } => *amount,
+            Mutation::Reputation { amount, .. } => *amount as i64,
+        }
+    }
+
+    /// Where credited money comes from and debited money goes: the mint
+    /// for credits, the sink for debits. Rollbacks send it back there.
+    fn system_account(&self) -> LedgerAccount {
+        if self.amount() > 0 { LedgerAccount::Mint } else { LedgerAccount::Sink }
+    }
+
+    /// Ledger legs moving `amount` into `bank_account_id`, or out of it when
+    /// reversing
+    fn legs(&self, bank_account_id: i64, reverse: bool) -> [(LedgerAccount, i64); 2] {
+        let amount = if reverse { -self.amount() } else { self.amount() };
+        [(self.system_account(), -amount), (LedgerAccount::Bank(bank_account_id), amount)]
+    }
+
+    fn validate(&self) -> Result<(), BulkRefused> {
+        if self.amount() == 0 {
+            return Err(BulkRefused::ZeroAmount);
+        }
+        let within = match self {
+            Mutation::Credit { amount } => (-MAX_MONEY..=MAX_MONEY).contains(amount),
+            Mutation::Experience { amount } => (-MAX_EXPERIENCE..=MAX_EXPERIENCE).contains(amount),
+            Mutation::Reputation { faction, amount } => {
+                if faction.trim().is_empty() || faction.len() > 32 {
+                    return Err(BulkRefused::Faction);
+                }
+                (-MAX_REPUTATION..=MAX_REPUTATION).contains(amount)
+            }
+        };
+        if within { Ok(()) } else { Err(BulkRefused::AmountTooLarge) }
+    }
+}
+
+/// A bulk operation as an operator submits it for its dry run
+#[derive(Debug, Clone, Deserialize)]
+pub struct BulkSpec {
+    pub title: String,
+    /// Why, e.g. the bug being made good; kept with the audit record
+    pub reason: String,
+    pub filter: FilterSpec,
+    pub mutation: Mutation,
+    pub chunk_size: Option<i32>,
+    /// Pause between chunks
+    pub throttle_ms: Option<i32>,
+}
+
+impl BulkSpec {
+    pub fn chunk_size(&self) -> i32 {
+        self.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn throttle_ms(&self) -> i32 {
+        self.throttle_ms.unwrap_or(DEFAULT_THROTTLE_MS)
+    }
+
+    pub fn validate(&self) -> Result<(), BulkRefused> {
+        let title = self.title.trim();
+        if title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH {
+            return Err(BulkRefused::Title);
+        }
+        let reason = self.reason.trim();
+        if reason.is_empty() || reason.chars().count() > MAX_REASON_LENGTH {
+            return Err(BulkRefused::Reason);
+        }
+
+        let filter = &self.filter;
+        if filter.is_empty() {
+            return Err(BulkRefused::EmptyFilter);
+        }
+        if let Some(user_ids) = &filter.user_ids {
+            if user_ids.is_empty() || user_ids.len() > MAX_USER_IDS {
+                return Err(BulkRefused::UserIds);
+            }
+        }
+        if let Some(reason) = filter.ledger_reason.as_deref() {
+            if LedgerReason::from_str(reason).is_none() {
+                return Err(BulkRefused::UnknownLedgerReason);
+            }
+        }
+        let ledger_window = filter.ledger_since.is_some() || filter.ledger_until.is_some();
+        if ledger_window && filter.ledger_reason.is_none() && filter.ledger_reference.is_none() {
+            return Err(BulkRefused::LedgerWindowAlone);
+        }
+        for (since, until) in [
+            (filter.registered_since, filter.registered_until),
+            (filter.ledger_since, filter.ledger_until),
+        ] {
+            if let (Some(since), Some(until)) = (since, until) {
+                if since >= until {
+                    return Err(BulkRefused::EmptyWindow);
+                }
+            }
+        }
+
+        self.mutation.validate()?;
+        if !(1..=MAX_CHUNK_SIZE).contains(&self.chunk_size()) {
+            return Err(BulkRefused::ChunkSize);
+        }
+        if !(0..=MAX_THROTTLE_MS).contains(&self.throttle_ms()) {
+            return Err(BulkRefused::Throttle);
+        }
+        Ok(())
+    }
+}
+
+/// Why a bulk operation request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkRefused {
+    Title,
+    Reason,
+    /// A filter has to narrow the match somehow
+    EmptyFilter,
+    UserIds,
+    UnknownLedgerReason,
+    /// A ledger window without a reason or reference to look for
+    LedgerWindowAlone,
+    EmptyWindow,
+    ZeroAmount,
+    AmountTooLarge,
+    Faction,
+    ChunkSize,
+    Throttle,
+    NoMatches,
+    TooManyMatches(i64),
+    NotFound,
+    /// Not planned, and not a failed run that can resume
+    NotExecutable,
+    DryRunExpired,
+    /// The confirmed count is not what the dry run matched
+    CountMismatch { matched: i32 },
+    NotRunning,
+    /// Still running, or nothing applied that is not rolled back already
+    NotRollbackable,
+}
+
+impl BulkRefused {
+    pub fn message(&self) -> String {
+        match self {
+            BulkRefused::Title => format!("A title of 1 to {} characters is required", MAX_TITLE_LENGTH),
+            BulkRefused::Reason => format!("A reason of 1 to {} characters is required", MAX_REASON_LENGTH),
+            BulkRefused::EmptyFilter => "The filter must narrow the players somehow".to_string(),
+            BulkRefused::UserIds => format!("User ids must list 1 to {} players", MAX_USER_IDS),
+            BulkRefused::UnknownLedgerReason => "Unknown ledger reason".to_string(),
+            BulkRefused::LedgerWindowAlone => "A ledger window needs a ledger reason or reference".to_string(),
+            BulkRefused::EmptyWindow => "Every window must end after it starts".to_string(),
+            BulkRefused::ZeroAmount => "The amount cannot be zero".to_string(),
+            BulkRefused::AmountTooLarge => "The amount is larger than one bulk operation may move".to_string(),
+            BulkRefused::Faction => "A faction of at most 32 characters is required".to_string(),
+            BulkRefused::ChunkSize => format!("Chunk size must be 1 to {}", MAX_CHUNK_SIZE),
+            BulkRefused::Throttle => format!("Throttle must be 0 to {} ms", MAX_THROTTLE_MS),
+            BulkRefused::NoMatches => "The filter matches no players".to_string(),
+            BulkRefused::TooManyMatches(matched) => {
+                format!("The filter matches {} players; at most {} are allowed", matched, MAX_MATCHED)
+            }
+            BulkRefused::NotFound => "Bulk operation not found".to_string(),
+            BulkRefused::NotExecutable => "This operation cannot be executed".to_string(),
+            BulkRefused::DryRunExpired => {
+                format!("The dry run is over {} minutes old; plan the operation again", DRY_RUN_TTL_MINUTES)
+            }
+            BulkRefused::CountMismatch { matched } => {
+                format!("The dry run matched {} players; confirm that count to execute", matched)
+            }
+            BulkRefused::NotRunning => "This operation is not running".to_string(),
+            BulkRefused::NotRollbackable => "This operation has nothing to roll back, or is still running".to_string(),
+        }
+    }
+}
+
+/// A player's value the mutation changes, and the bank account holding it
+/// for money. `None` if a money mutation has no account to go to.
+async fn current_value(
+    conn: &mut PgConnection,
+    user_id: i64,
+    mutation: &Mutation,
+) -> anyhow::Result<Option<(Option<i64>, i64)>> {
+    Ok(match mutation {
+        Mutation::Credit { .. } => {
+            let Some(account_id) = BankQueries::primary_account(&mut *conn, user_id).await? else {
+                return Ok(None);
+            };
+            BankQueries::account_balance(conn, account_id).await?.map(|balance| (Some(account_id), balance))
+        }
+        Mutation::Experience { .. } => Some((None, ProgressionQueries::experience(conn, user_id).await?)),
+        Mutation::Reputation { faction, .. } => Some((None, ProgressionQueries::reputation(conn, user_id, faction).await?)),
+    })
+}
+
+/// Dry-run `spec`: record the operation, snapshot the players it matches
+/// and preview the first of them
+pub async fn plan(
+    pool: &PgPool,
+    spec: &BulkSpec,
+    admin_id: i64,
+) -> anyhow::Result<Result<BulkOperationRow, BulkRefused>> {
+    if let Err(refused) = spec.validate() {
+        return Ok(Err(refused));
+    }
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let (operation_id, matched) = BulkOperationQueries::plan(
+        &mut *tx,
+        spec.title.trim(),
+        spec.reason.trim(),
+        &spec.filter.to_filter(),
+        &serde_json::to_value(&spec.filter)?,
+        &serde_json::to_value(&spec.mutation)?,
+        spec.chunk_size(),
+        spec.throttle_ms(),
+        admin_id,
+    )
+    .await?;
+    if matched == 0 {
+        tx.rollback().await?;
+        return Ok(Err(BulkRefused::NoMatches));
+    }
+    if matched > MAX_MATCHED {
+        tx.rollback().await?;
+        return Ok(Err(BulkRefused::TooManyMatches(matched)));
+    }
+
+    let mut preview = Vec::new();
+    for user_id in BulkOperationQueries::sample(&mut *tx, operation_id, PREVIEW_ROWS).await? {
+        preview.push(match current_value(&mut *tx, user_id, &spec.mutation).await? {
+            Some((bank_account_id, before)) => serde_json::json!({
+                "user_id": user_id,
+                "bank_account_id": bank_account_id,
+                "before": before,
+                "after": before + spec.mutation.amount()
+            }),
+            None => serde_json::json!({
+                "user_id": user_id,
+                "note": "No active bank account; will be skipped"
+            }),
+        });
+    }
+    BulkOperationQueries::set_preview(&mut *tx, operation_id, &serde_json::Value::Array(preview)).await?;
+    tx.commit().await?;
+
+    BulkOperationQueries::get(pool, operation_id)
+        .await?
+        .map(Ok)
+        .ok_or_else(|| anyhow::anyhow!("bulk operation {} vanished after planning", operation_id))
+}
+
+/// Whether `operation` can be executed now with `confirm_matched`
+fn check_executable(
+    operation: &BulkOperationRow,
+    confirm_matched: i32,
+    now: DateTime<Utc>,
+) -> Result<(), BulkRefused> {
+    let resumable = operation.status == "failed" && operation.rollback_started_at.is_none();
+    if operation.status != "planned" && !resumable {
+        return Err(BulkRefused::NotExecutable);
+    }
+    if confirm_matched != operation.matched {
+        return Err(BulkRefused::CountMismatch { matched: operation.matched });
+    }
+    if operation.status == "planned"
+        && operation.planned_at < now - ChronoDuration::minutes(DRY_RUN_TTL_MINUTES)
+    {
+        return Err(BulkRefused::DryRunExpired);
+    }
+    Ok(())
+}
+
+/// Start applying a dry-run operation, or resume one that failed
+pub async fn execute(
+    pool: &PgPool,
+    operation_id: i64,
+    admin_id: i64,
+    confirm_matched: i32,
+) -> anyhow::Result<Result<BulkOperationRow, BulkRefused>> {
+    let Some(operation) = BulkOperationQueries::get(pool, operation_id).await? else {
+        return Ok(Err(BulkRefused::NotFound));
+    };
+    if let Err(refused) = check_executable(&operation, confirm_matched, Utc::now()) {
+        return Ok(Err(refused));
+    }
+
+    let planned_since = if operation.status == "planned" {
+        Utc::now() - ChronoDuration::minutes(DRY_RUN_TTL_MINUTES)
+    } else {
+        operation.planned_at
+    };
+    Ok(BulkOperationQueries::start(pool, operation_id, admin_id, confirm_matched, planned_since)
+        .await?
+        .ok_or(BulkRefused::NotExecutable))
+}
+
+/// Stop a running operation; what it applied stays applied until rolled back
+pub async fn cancel(pool: &PgPool, operation_id: i64) -> anyhow::Result<Result<BulkOperationRow, BulkRefused>> {
+    if BulkOperationQueries::get(pool, operation_id).await?.is_none() {
+        return Ok(Err(BulkRefused::NotFound));
+    }
+    Ok(BulkOperationQueries::cancel(pool, operation_id).await?.ok_or(BulkRefused::NotRunning))
+}
+
+/// Start undoing everything an operation applied
+pub async fn rollback(
+    pool: &PgPool,
+    operation_id: i64,
+    admin_id: i64,
+    reason: &str,
+) -> anyhow::Result<Result<BulkOperationRow, BulkRefused>> {
+    if BulkOperationQueries::get(pool, operation_id).await?.is_none() {
+        return Ok(Err(BulkRefused::NotFound));
+    }
+    Ok(BulkOperationQueries::start_rollback(pool, operation_id, admin_id, reason)
+        .await?
+        .ok_or(BulkRefused::NotRollbackable))
+}
+
+/// Apply and roll back operations, a chunk at a time
+pub(crate) fn spawn_worker(pool: PgPool) {
+    tokio::spawn(async move {
+        loop {
+            match work(&pool).await {
+                Ok(true) => tokio::task::yield_now().await,
+                Ok(false) => tokio::time::sleep(IDLE_INTERVAL).await,
+                Err(e) => {
+                    tracing::warn!("Bulk operation worker failed: {}", e);
+                    tokio::time::sleep(IDLE_INTERVAL).await;
+                }
+            }
+        }
+    });
+}
+
+/// Run the most overdue chunk; false if none was due
+async fn work(pool: &PgPool) -> anyhow::Result<bool> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(operation) = BulkOperationQueries::claim_due(&mut *tx).await? else {
+        tx.rollback().await?;
+        return Ok(false);
+    };
+
+    match run_chunk(&mut *tx, &operation).await {
+        Ok(()) => tx.commit().await?,
+        Err(e) => {
+            tx.rollback().await?;
+            tracing::error!("Bulk operation {} failed: {}", operation.id, e);
+            BulkOperationQueries::fail(pool, operation.id, &e.to_string()).await?;
+        }
+    }
+    Ok(true)
+}
+
+async fn run_chunk(conn: &mut PgConnection, operation: &BulkOperationRow) -> anyhow::Result<()> {
+    let mutation: Mutation = serde_json::from_value(operation.mutation.clone())?;
+    let rolling_back = operation.status == "rolling_back";
+    let status = if rolling_back { "applied" } else { "pending" };
+    let rows = BulkOperationQueries::chunk(&mut *conn, operation.id, status, operation.chunk_size as i64).await?;
+
+    let mut counts = BulkChunkCounts::default();
+    for row in &rows {
+        if rolling_back {
+            match revert_row(&mut *conn, operation.id, row, &mutation).await? {
+                None => counts.rolled_back += 1,
+                Some(failure) => {
+                    BulkOperationQueries::record_rollback(&mut *conn, operation.id, row.user_id, Some(failure)).await?;
+                    counts.rollback_failed += 1;
+                    continue;
+                }
+            }
+            BulkOperationQueries::record_rollback(&mut *conn, operation.id, row.user_id, None).await?;
+        } else if apply_row(&mut *conn, operation.id, row.user_id, &mutation).await? {
+            counts.applied += 1;
+        } else {
+            counts.skipped += 1;
+        }
+    }
+
+    let next_chunk_at = (rows.len() as i64 == operation.chunk_size as i64)
+        .then(|| Utc::now() + ChronoDuration::milliseconds(operation.throttle_ms as i64));
+    BulkOperationQueries::finish_chunk(conn, operation.id, counts, next_chunk_at).await?;
+    if next_chunk_at.is_none() {
+        tracing::info!(
+            "Bulk operation {} finished {}",
+            operation.id,
+            if rolling_back { "rolling back" } else { "applying" }
+        );
+    }
+    Ok(())
+}
+
+/// Apply `mutation` to one player and record their value before and after;
+/// false if they were skipped
+async fn apply_row(conn: &mut PgConnection, operation_id: i64, user_id: i64, mutation: &Mutation) -> anyhow::Result<bool> {
+    let Some((bank_account_id, before)) = current_value(&mut *conn, user_id, mutation).await? else {
+        BulkOperationQueries::record_skipped(conn, operation_id, user_id, None, "No active bank account").await?;
+        return Ok(false);
+    };
+    let after = before + mutation.amount();
+
+    let refusal = match mutation {
+        Mutation::Credit { .. } => {
+            let reference = format!("bulk:{}", operation_id);
+            let legs = mutation.legs(bank_account_id.unwrap_or_default(), false);
+            let posted = LedgerQueries::post(&mut *conn, LedgerReason::BulkFix, &reference, &legs).await?;
+            (!posted).then_some("Balance cannot cover the debit")
+        }
+        Mutation::Experience { amount } => {
+            if after < 0 {
+                Some("Not enough experience to take away")
+            } else {
+                ProgressionQueries::add_experience(&mut *conn, user_id, *amount).await?;
+                None
+            }
+        }
+        Mutation::Reputation { faction, amount } => {
+            ProgressionQueries::add_reputation(&mut *conn, user_id, faction, *amount).await?;
+            None
+        }
+    };
+
+    match refusal {
+        None => {
+            BulkOperationQueries::record_applied(conn, operation_id, user_id, bank_account_id, before, after).await?;
+            Ok(true)
+        }
+        Some(note) => {
+            BulkOperationQueries::record_skipped(conn, operation_id, user_id, Some(before), note).await?;
+            Ok(false)
+        }
+    }
+}
+
+/// Undo one applied row; why not, if it could not be undone
+async fn revert_row(
+    conn: &mut PgConnection,
+    operation_id: i64,
+    row: &BulkTargetRow,
+    mutation: &Mutation,
+) -> anyhow::Result<Option<&'static str>> {
+    Ok(match mutation {
+        Mutation::Credit { .. } => {
+            let Some(bank_account_id) = row.bank_account_id else {
+                return Ok(Some("No bank account was recorded"));
+            };
+            let reference = format!("bulk:{}:rollback", operation_id);
+            let legs = mutation.legs(bank_account_id, true);
+            let posted = LedgerQueries::post(&mut *conn, LedgerReason::BulkFix, &reference, &legs).await?;
+            (!posted).then_some("Balance cannot cover the reversal")
+        }
+        Mutation::Experience { amount } => {
+            let current = ProgressionQueries::experience(&mut *conn, row.user_id).await?;
+            if current - amount < 0 {
+                Some("Not enough experience left to take back")
+            } else {
+                ProgressionQueries::add_experience(conn, row.user_id, -amount).await?;
+                None
+            }
+        }
+        Mutation::Reputation { faction, amount } => {
+            let Some(before) = row.before_value else {
+                return Ok(Some("No standing was recorded"));
+            };
+            let current = ProgressionQueries::reputation(&mut *conn, row.user_id, faction).await?;
+            let refusal = reputation_revert_refusal(*amount, before, current);
+            if refusal.is_none() {
+                ProgressionQueries::add_reputation(conn, row.user_id, faction, -amount).await?;
+            }
+            refusal
+        }
+    })
+}
+
+/// Why taking back a reputation change of `amount` would go wrong, given the
+/// standing recorded before the fix and the standing now. Like experience, it
+/// never takes back more than is left: a player whose standing moved back
+/// since the fix would otherwise end up past where they started.
+fn reputation_revert_refusal(amount: i32, before: i64, current: i64) -> Option<&'static str> {
+    let reverted = current - amount as i64;
+    let overshoots = if amount > 0 { reverted < before } else { reverted > before };
+    overshoots.then_some("Standing moved since the fix; taking it back would overshoot")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use he_database::queries::balanced_legs;
+
+    fn refund_spec() -> BulkSpec {
+        serde_json::from_value(serde_json::json!({
+            "title": "Refund double-charged purchases",
+            "reason": "Marketplace charged twice between 14:00 and 15:00",
+            "filter": {
+                "ledger_reason": "marketplace_purchase",
+                "ledger_since": "2024-11-05T14:00:00Z",
+                "ledger_until": "2024-11-05T15:00:00Z"
+            },
+            "mutation": { "kind": "credit", "amount": 5_000 }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_spec_validation() {
+        let spec = refund_spec();
+        assert_eq!(spec.validate(), Ok(()));
+        assert_eq!(spec.chunk_size(), DEFAULT_CHUNK_SIZE);
+        assert_eq!(spec.throttle_ms(), DEFAULT_THROTTLE_MS);
+
+        let empty = BulkSpec { filter: FilterSpec::default(), ..refund_spec() };
+        assert_eq!(empty.validate(), Err(BulkRefused::EmptyFilter));
+
+        let window_alone = BulkSpec {
+            filter: FilterSpec { ledger_reason: None, ..refund_spec().filter },
+            ..refund_spec()
+        };
+        assert_eq!(window_alone.validate(), Err(BulkRefused::LedgerWindowAlone));
+
+        let backwards = BulkSpec {
+            filter: FilterSpec { ledger_until: refund_spec().filter.ledger_since, ..refund_spec().filter },
+            ..refund_spec()
+        };
+        assert_eq!(backwards.validate(), Err(BulkRefused::EmptyWindow));
+
+        let unknown = BulkSpec {
+            filter: FilterSpec { ledger_reason: Some("bug".to_string()), ..refund_spec().filter },
+            ..refund_spec()
+        };
+        assert_eq!(unknown.validate(), Err(BulkRefused::UnknownLedgerReason));
+
+        let zero = BulkSpec { mutation: Mutation::Credit { amount: 0 }, ..refund_spec() };
+        assert_eq!(zero.validate(), Err(BulkRefused::ZeroAmount));
+        let huge = BulkSpec { mutation: Mutation::Experience { amount: -MAX_EXPERIENCE - 1 }, ..refund_spec() };
+        assert_eq!(huge.validate(), Err(BulkRefused::AmountTooLarge));
+
+        let chunky = BulkSpec { chunk_size: Some(0), ..refund_spec() };
+        assert_eq!(chunky.validate(), Err(BulkRefused::ChunkSize));
+        let untitled = BulkSpec { title: "  ".to_string(), ..refund_spec() };
+        assert_eq!(untitled.validate(), Err(BulkRefused::Title));
+    }
+
+    #[test]
+    fn test_mutation_round_trips_through_the_stored_spec() {
+        let reputation = Mutation::Reputation { faction: "security".to_string(), amount: -40 };
+        let stored = serde_json::to_value(&reputation).unwrap();
+        assert_eq!(stored["kind"], "reputation");
+        assert_eq!(serde_json::from_value::<Mutation>(stored).unwrap(), reputation);
+        assert_eq!(reputation.amount(), -40);
+    }
+
+    #[test]
+    fn test_reputation_revert_stays_within_the_recorded_standing() {
+        // +500 from 100, untouched since
+        assert_eq!(reputation_revert_refusal(500, 100, 600), None);
+        // Gained more since: taking the fix back leaves the rest
+        assert_eq!(reputation_revert_refusal(500, 100, 900), None);
+        // Lost standing since: the reversal would land below 100
+        assert!(reputation_revert_refusal(500, 100, 300).is_some());
+        // A -40 penalty from 20 reverses only while it would not climb past 20
+        assert_eq!(reputation_revert_refusal(-40, 20, -20), None);
+        assert!(reputation_revert_refusal(-40, 20, 10).is_some());
+    }
+
+    #[test]
+    fn test_money_legs_balance_and_reverse_to_the_same_system_account() {
+        let credit = Mutation::Credit { amount: 2_500 };
+        let applied = credit.legs(7, false);
+        assert_eq!(applied, [(LedgerAccount::Mint, -2_500), (LedgerAccount::Bank(7), 2_500)]);
+        let reversed = credit.legs(7, true);
+        assert_eq!(reversed, [(LedgerAccount::Mint, 2_500), (LedgerAccount::Bank(7), -2_500)]);
+
+        let debit = Mutation::Credit { amount: -900 };
+        assert_eq!(debit.legs(7, false), [(LedgerAccount::Sink, 900), (LedgerAccount::Bank(7), -900)]);
+        for legs in [applied, reversed, debit.legs(7, false), debit.legs(7, true)] {
+            assert!(balanced_legs(&legs).is_ok());
+        }
+    }
+}
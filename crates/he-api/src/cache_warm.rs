@@ -0,0 +1,113 @@
+//! Cache warming on deploy
+//!
+//! A fresh node warms Redis from the manifest in `CACHE_WARM_MANIFEST` (the
+//! built-in one when unset) before orchestration routes traffic to it; the
+//! `/ready/cache` probe reports the progress. Without Redis there is nothing
+//! to warm and the probe is ready straight away.
+//!
+//! Each warmed key is built by the same loader its handler reads it through
+//! with [`read_through`], so a warm writes exactly what a cold read would.
+
+use async_trait::async_trait;
+use he_cache::warm::LEADERBOARDS;
+use he_cache::{CacheKeys, CacheManager, CacheWarmer, WarmGroup, WarmManifest, WarmProgress, WarmSource};
+use he_database::OptimizedLeaderboardQueries;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Players on the cached top-players board
+pub const TOP_PLAYERS: i32 = 100;
+
+/// The node's cache and its warmer, if it has a cache
+pub struct CacheWarm {
+    cache: Option<Arc<CacheManager>>,
+    warmer: Option<Arc<CacheWarmer>>,
+}
+
+impl CacheWarm {
+    /// Progress of the warm, `None` when there is no cache
+    pub fn progress(&self) -> Option<WarmProgress> {
+        self.warmer.as_ref().map(|w| w.progress())
+    }
+
+    /// The cache handlers read warmed keys through
+    pub fn cache(&self) -> Option<&CacheManager> {
+        self.cache.as_deref()
+    }
+}
+
+/// Start warming `cache` in the background
+pub fn start(cache: Option<Arc<CacheManager>>, pool: PgPool) -> CacheWarm {
+    let Some(cache) = cache else {
+        return CacheWarm { cache: None, warmer: None };
+    };
+
+    let warmer = Arc::new(CacheWarmer::new(cache.clone(), manifest()));
+    let source = DatabaseSource { pool };
+    let task = warmer.clone();
+    tokio::spawn(async move {
+        task.warm_up(&source).await;
+    });
+
+    CacheWarm { cache: Some(cache), warmer: Some(warmer) }
+}
+
+/// `key` from the cache, or from `load` and cached on a miss. An unavailable
+/// cache only costs the load.
+pub async fn read_through<F, Fut>(cache: Option<&CacheManager>, key: &str, load: F) -> anyhow::Result<Value>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = anyhow::Result<Value>>,
+{
+    let Some(cache) = cache else {
+        return load().await;
+    };
+    match cache.get::<Value>(key).await {
+        Ok(Some(value)) => return Ok(value),
+        Ok(None) => {}
+        Err(e) => tracing::debug!("Cache read of {} failed: {}", key, e),
+    }
+
+    let value = load().await?;
+    if let Err(e) = cache.set(key, &value, None).await {
+        tracing::debug!("Cache write of {} failed: {}", key, e);
+    }
+    Ok(value)
+}
+
+/// The top-players board, cached under `leaderboard("players")`
+pub async fn top_players(pool: &PgPool) -> anyhow::Result<Value> {
+    let players = OptimizedLeaderboardQueries::get_top_players(pool, TOP_PLAYERS, 0).await?;
+    Ok(serde_json::to_value(players)?)
+}
+
+fn manifest() -> WarmManifest {
+    let Ok(path) = std::env::var("CACHE_WARM_MANIFEST") else {
+        return WarmManifest::default();
+    };
+    match std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|json| {
+        WarmManifest::from_json(&json).map_err(|e| e.to_string())
+    }) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            tracing::warn!("Ignoring cache warm manifest {}: {}", path, e);
+            WarmManifest::default()
+        }
+    }
+}
+
+struct DatabaseSource {
+    pool: PgPool,
+}
+
+#[async_trait]
+impl WarmSource for DatabaseSource {
+    async fn load(&self, group: &WarmGroup) -> anyhow::Result<Vec<(String, Value)>> {
+        match group.name.as_str() {
+            LEADERBOARDS => Ok(vec![(CacheKeys::leaderboard("players"), top_players(&self.pool).await?)]),
+            other => anyhow::bail!("No source for cache warm group {}", other),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Process cancellation
+//!
+//! Cancelling a process happens in one transaction: the process is deleted,
+//! which frees what it held on its host, part of its cost is refunded by
+//! [`he_game_mechanics::cancellation`], the gateway logs the cancellation
+//! and a remote target logs the aborted access. Clients are told through
+//! the outbox once it committed.
+
+use chrono::Utc;
+use he_database::queries::{
+    BankQueries, LedgerAccount, LedgerReason, LogQueries, NewProcessCancellation, ProcessHostQueries, ProcessQueries,
+};
+use he_game_mechanics::cancellation::{self, Settlement};
+use he_game_mechanics::process::ProcessType;
+use serde::Serialize;
+use sqlx::PgPool;
+use crate::outbox::{self, OutboxMessage};
+
+/// A cancelled process
+#[derive(Debug, Clone, Serialize)]
+pub struct Cancellation {
+    pub pid: i64,
+    pub process_type: String,
+    #[serde(flatten)]
+    pub settlement: Settlement,
+    /// Server whose resources were released, when not the gateway
+    pub host_server_id: Option<i64>,
+}
+
+/// Cancel one of the player's unfinished processes. `None` if there is no
+/// such process.
+pub async fn cancel(pool: &PgPool, user_id: i64, pid: i64) -> anyhow::Result<Option<Cancellation>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(process) = ProcessQueries::lock_unfinished(&mut *tx, pid, user_id).await? else {
+        return Ok(None);
+    };
+
+    let progress = cancellation::progress(process.start_time, process.end_time, Utc::now());
+    let cost = ProcessQueries::cost(&mut *tx, pid).await?;
+    let mut settlement = cancellation::settle(cost, progress, &crate::balance::current().cancellation);
+
+    let reference = format!("process:{}", pid);
+    if settlement.refund > 0 {
+        let paid = BankQueries::credit_primary_account(
+            &mut *tx,
+            user_id,
+            settlement.refund,
+            LedgerAccount::Sink,
+            LedgerReason::ProcessRefund,
+            &reference,
+        )
+        .await?;
+        if !paid {
+            settlement.penalty = settlement.cost;
+            settlement.refund = 0;
+        }
+    }
+
+    LogQueries::add_server_log(
+        &mut *tx,
+        user_id,
+        &process.process_type,
+        &format!("Process {} ({}) cancelled at {:.0}%", pid, process.process_type, settlement.progress * 100.0),
+        None,
+    )
+    .await?;
+
+    let log_type = ProcessType::from_str(&process.process_type).target_log_type();
+    if let (Some(log_type), Some(target_ip)) = (log_type, process.target_pc_id.as_deref()) {
+        // A traced player's gateway is logged wherever they act
+        let origin_ip = match crate::honeypot::traced(&mut *tx, user_id).await? {
+            true => None,
+            false => crate::hosting::host_ip(&process),
+        };
+        LogQueries::add_aborted_access_log(&mut *tx, target_ip, user_id, log_type, origin_ip).await?;
+    }
+
+    let hold = ProcessHostQueries::hold(&mut *tx, pid).await?;
+    ProcessQueries::record_cancellation(
+        &mut *tx,
+        &NewProcessCancellation {
+            pid,
+            user_id,
+            process_type: &process.process_type,
+            target_ip: process.target_pc_id.as_deref(),
+            host_server_id: hold.as_ref().map(|h| h.server_id),
+            cpu_released: hold.as_ref().map_or(0, |h| h.cpu_usage),
+            ram_released: hold.as_ref().map_or(0, |h| h.ram_usage),
+            net_released: hold.as_ref().map_or(0, |h| h.net_usage),
+            progress: settlement.progress,
+            cost: settlement.cost,
+            refund: settlement.refund,
+        },
+    )
+    .await?;
+
+    let event = he_websocket::EventBuilder::process_cancelled(pid);
+    let mut messages = vec![OutboxMessage::to_process(format!("process:{}:cancelled", pid), pid, &event)];
+    if let Some(hold) = hold.as_ref().filter(|h| h.owner_id != user_id) {
+        // The host's owner sees its load drop
+        messages.push(OutboxMessage::to_user(format!("process:{}:cancelled:host", pid), hold.owner_id, &event));
+    }
+    if settlement.refund > 0 {
+        let event = he_websocket::EventBuilder::money_received(settlement.refund, "Process refund".to_string());
+        messages.push(OutboxMessage::to_user(format!("process:{}:refund", pid), user_id, &event));
+    }
+    outbox::enqueue(&mut *tx, &messages).await?;
+
+    tx.commit().await?;
+    outbox::wake();
+
+    Ok(Some(Cancellation {
+        pid,
+        process_type: process.process_type,
+        settlement,
+        host_server_id: hold.map(|h| h.server_id),
+    }))
+}
@@ -0,0 +1,61 @@
+//! Flagging chat messages for moderators
+//!
+//! Posted messages are checked against the configured offensive terms. They are
+//! not blocked; a matching message is recorded once in `chat_flags` and
+//! published on the `mod.chat-flags` channel for a moderator to look at.
+
+use he_core::validation::find_offensive_terms;
+use he_database::queries::ChatFlagQueries;
+use he_websocket::{EventBuilder, ModChannel};
+use sqlx::PgPool;
+use crate::outbox::{self, OutboxMessage};
+
+/// Characters of a flagged message sent with the flag
+const EXCERPT_CHARS: usize = 120;
+
+fn excerpt(body: &str) -> String {
+    let mut chars = body.chars();
+    let excerpt: String = chars.by_ref().take(EXCERPT_CHARS).collect();
+    if chars.next().is_some() {
+        format!("{}…", excerpt)
+    } else {
+        excerpt
+    }
+}
+
+/// Flag message `message_id` if its body has offensive terms. Returns the
+/// new flag's id, `None` when the message is clean or already flagged.
+pub async fn screen(pool: &PgPool, user_id: i64, message_id: i64, body: &str) -> anyhow::Result<Option<i64>> {
+    let offensive_terms = &crate::balance::current().moderation.offensive_terms;
+    let terms: Vec<String> = find_offensive_terms(body, offensive_terms).into_iter().map(str::to_string).collect();
+    if terms.is_empty() {
+        return Ok(None);
+    }
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(flag_id) = ChatFlagQueries::flag(&mut *tx, message_id, user_id, &terms).await? else {
+        tx.rollback().await?;
+        return Ok(None);
+    };
+
+    let event = EventBuilder::chat_flagged(flag_id, message_id, user_id, terms, excerpt(body));
+    let message = OutboxMessage::to_moderators(format!("chat_flag:{}", flag_id), ModChannel::ChatFlags, &event);
+    outbox::enqueue(&mut *tx, &[message]).await?;
+    tx.commit().await?;
+    outbox::wake();
+    Ok(Some(flag_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excerpt() {
+        assert_eq!(excerpt("short"), "short");
+        let long = "x".repeat(EXCERPT_CHARS + 5);
+        let cut = excerpt(&long);
+        assert_eq!(cut.chars().count(), EXCERPT_CHARS + 1);
+        assert!(cut.ends_with('…'));
+    }
+}
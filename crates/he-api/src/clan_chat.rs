@@ -0,0 +1,161 @@
+//! Clan chat
+//!
+//! Each clan's private room is provisioned when the clan is created, and
+//! triggers keep its members and their roles in step with the clan's (see
+//! `20241110_clan_chat.sql`). Members read and post in the general channel;
+//! the announcements channel only takes posts from the leader and officers,
+//! who also pin messages. Once the clan disbands the room is archived and
+//! stays readable to whoever was still in it. The rules are in
+//! [`he_game_mechanics::clan_chat`].
+
+use he_database::queries::{
+    ChatThreadMessage, ClanChatQueries, ClanChatRoomRow, ClanServerQueries, CoopMissionQueries, PinnedMessageRow,
+};
+use he_game_mechanics::clan_chat::{self, Channel, ClanChatDenied};
+use std::sync::Arc;
+
+type ClanChatResult<T> = anyhow::Result<Result<T, ClanChatDenied>>;
+
+/// The thread behind one channel of the player's clan room, and the
+/// player's role in it
+struct Thread {
+    clan_id: i64,
+    id: i64,
+    role: String,
+    archived: bool,
+}
+
+fn thread_id(room: &ClanChatRoomRow, channel: Channel) -> i64 {
+    match channel {
+        Channel::General => room.thread_id,
+        Channel::Announcements => room.announcements_thread_id,
+    }
+}
+
+async fn channel_thread(pool: &sqlx::PgPool, user_id: i64, channel: Channel) -> ClanChatResult<Thread> {
+    let Some(clan) = ClanServerQueries::membership(pool, user_id).await? else {
+        return Ok(Err(ClanChatDenied::NoClan));
+    };
+    let Some(room) = ClanChatQueries::room(pool, clan.clan_id).await? else {
+        return Ok(Err(ClanChatDenied::NoClan));
+    };
+    let id = thread_id(&room, channel);
+    let Some(role) = ClanChatQueries::member_role(pool, id, user_id).await? else {
+        return Ok(Err(ClanChatDenied::NoClan));
+    };
+
+    Ok(Ok(Thread { clan_id: room.clan_id, id, role, archived: room.archived_at.is_some() }))
+}
+
+/// Messages in a channel after `after_id`, oldest first
+pub async fn messages(
+    pool: &sqlx::PgPool,
+    user_id: i64,
+    channel: Channel,
+    after_id: i64,
+) -> ClanChatResult<Vec<ChatThreadMessage>> {
+    let config = &crate::balance::current().clan_chat;
+    let thread = match channel_thread(pool, user_id, channel).await? {
+        Ok(thread) => thread,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    Ok(Ok(CoopMissionQueries::messages(pool, thread.id, after_id, config.page_size).await?))
+}
+
+/// Post a player's message and push it to the clan's sockets
+pub async fn post(
+    pool: &sqlx::PgPool,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+    user_id: i64,
+    channel: Channel,
+    body: &str,
+) -> ClanChatResult<i64> {
+    let thread = match channel_thread(pool, user_id, channel).await? {
+        Ok(thread) => thread,
+        Err(denied) => return Ok(Err(denied)),
+    };
+    if thread.archived {
+        return Ok(Err(ClanChatDenied::Archived));
+    }
+    if !clan_chat::can_post(channel, &thread.role) {
+        return Ok(Err(ClanChatDenied::NotOfficer));
+    }
+    let Some(message_id) = CoopMissionQueries::post_message(pool, thread.id, Some(user_id), body).await? else {
+        return Ok(Err(ClanChatDenied::Archived));
+    };
+    if let Err(e) = crate::chat_filter::screen(pool, user_id, message_id, body).await {
+        tracing::warn!("Failed to screen chat message {}: {}", message_id, e);
+    }
+
+    if let Some(ws_manager) = ws_manager {
+        let message = he_websocket::GameEvent::Custom {
+            event_name: "clan_chat".to_string(),
+            payload: serde_json::json!({
+                "channel": channel,
+                "message_id": message_id,
+                "user_id": user_id,
+                "body": body,
+            }),
+        };
+        ws_manager.send_to_entity(he_websocket::Entity::Clan(thread.clan_id), message.to_server_message());
+    }
+
+    Ok(Ok(message_id))
+}
+
+/// A channel's pinned messages, latest pin first
+pub async fn pins(pool: &sqlx::PgPool, user_id: i64, channel: Channel) -> ClanChatResult<Vec<PinnedMessageRow>> {
+    let thread = match channel_thread(pool, user_id, channel).await? {
+        Ok(thread) => thread,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    Ok(Ok(ClanChatQueries::pins(pool, thread.id).await?))
+}
+
+/// Pin a message of the channel; leader and officers only
+pub async fn pin(pool: &sqlx::PgPool, user_id: i64, channel: Channel, message_id: i64) -> ClanChatResult<()> {
+    let config = &crate::balance::current().clan_chat;
+    let thread = match channel_thread(pool, user_id, channel).await? {
+        Ok(thread) => thread,
+        Err(denied) => return Ok(Err(denied)),
+    };
+    if thread.archived {
+        return Ok(Err(ClanChatDenied::Archived));
+    }
+    if !clan_chat::is_officer(&thread.role) {
+        return Ok(Err(ClanChatDenied::NotOfficer));
+    }
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    ClanChatQueries::lock_thread(&mut *tx, thread.id).await?;
+    if ClanChatQueries::pin_count(&mut *tx, thread.id).await? >= config.max_pins as i64 {
+        return Ok(Err(ClanChatDenied::TooManyPins { max: config.max_pins }));
+    }
+    if !ClanChatQueries::pin(&mut *tx, thread.id, message_id, user_id).await? {
+        return Ok(Err(ClanChatDenied::MessageNotFound));
+    }
+    tx.commit().await?;
+
+    Ok(Ok(()))
+}
+
+/// Unpin a message of the channel; leader and officers only
+pub async fn unpin(pool: &sqlx::PgPool, user_id: i64, channel: Channel, message_id: i64) -> ClanChatResult<()> {
+    let thread = match channel_thread(pool, user_id, channel).await? {
+        Ok(thread) => thread,
+        Err(denied) => return Ok(Err(denied)),
+    };
+    if thread.archived {
+        return Ok(Err(ClanChatDenied::Archived));
+    }
+    if !clan_chat::is_officer(&thread.role) {
+        return Ok(Err(ClanChatDenied::NotOfficer));
+    }
+    if !ClanChatQueries::unpin(pool, thread.id, message_id).await? {
+        return Ok(Err(ClanChatDenied::NotPinned));
+    }
+
+    Ok(Ok(()))
+}
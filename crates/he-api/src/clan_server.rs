@@ -0,0 +1,548 @@
+//! Clan servers
+//!
+//! Each clan gets its server the first time a member needs it. Members pay
+//! into the clan treasury from their bank, and the leader and officers spend
+//! it on the server's hardware. Members store software on it for each other,
+//! limited by its disk, and lend it their best firewall for defense. The
+//! rules are in [`he_game_mechanics::clan_server`].
+//!
+//! Officers declare wars on other clans. While one runs, members of either
+//! side strike the other side's server; taking it down wins the war, and a
+//! war that runs out is won on score. Strikes are reported to the spectator
+//! battle log once their transaction commits, and declarations to both clans'
+//! webhooks.
+//!
+//! Members leave, and officers remove them, here too; the caller revokes
+//! their sessions' subscriptions to the clan's events once that commits.
+
+use chrono::{Duration, Utc};
+use he_database::queries::{
+    BankQueries, ClanFileRow, ClanMembership, ClanServerQueries, ClanServerRow, ClanWarRow, LedgerAccount,
+    LedgerQueries, LedgerReason,
+};
+use he_game_mechanics::clan_server::{self, ClanServerDenied, Levels, Part, Strike};
+use he_game_mechanics::config::ClanServerConfig;
+use he_game_mechanics::identity;
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use crate::war_spectator::{WarEvent, WarEventKind, WarSide, WarSpectator};
+
+/// Fresh addresses tried when giving a clan its server
+const ALLOCATION_ATTEMPTS: usize = 8;
+
+const FIREWALL: &str = "firewall";
+const CRACKER: &str = "cracker";
+
+type ClanServerResult<T> = anyhow::Result<Result<T, ClanServerDenied>>;
+
+/// A part's level, what it provides and what the next level costs
+#[derive(Debug, Clone, Serialize)]
+pub struct PartStatus {
+    pub part: Part,
+    pub level: i32,
+    pub capacity: i32,
+    pub next_cost: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClanServerView {
+    #[serde(flatten)]
+    pub server: ClanServerRow,
+    pub parts: Vec<PartStatus>,
+    pub defense: i64,
+    pub defenders: usize,
+}
+
+/// A bought upgrade
+#[derive(Debug, Clone, Serialize)]
+pub struct Upgrade {
+    pub part: Part,
+    pub level: i32,
+    pub cost: i64,
+    pub capacity: i32,
+}
+
+/// What a strike did, and the war after it
+#[derive(Debug, Clone, Serialize)]
+pub struct StrikeReport {
+    pub strike: Strike,
+    pub war: ClanWarRow,
+}
+
+fn levels(server: &ClanServerRow) -> Levels {
+    Levels {
+        cpu: server.cpu_level,
+        ram: server.ram_level,
+        hdd: server.hdd_level,
+        net: server.net_level,
+    }
+}
+
+async fn membership(pool: &PgPool, user_id: i64) -> ClanServerResult<ClanMembership> {
+    Ok(ClanServerQueries::membership(pool, user_id).await?.ok_or(ClanServerDenied::NoClan))
+}
+
+/// The clan's server, set up on first use. Locks the clan; `None` if it is
+/// gone.
+async fn ensure(conn: &mut PgConnection, clan_id: i64, config: &ClanServerConfig) -> anyhow::Result<Option<ClanServerRow>> {
+    let Some(leader_id) = ClanServerQueries::lock_clan(&mut *conn, clan_id).await? else {
+        return Ok(None);
+    };
+    if let Some(server) = ClanServerQueries::server(&mut *conn, clan_id).await? {
+        return Ok(Some(server));
+    }
+
+    let capacity = Part::ALL.map(|part| clan_server::capacity(part, 0, config));
+    for _ in 0..ALLOCATION_ATTEMPTS {
+        let ip = identity::random_ip(&mut rand::thread_rng()).to_string();
+        if ClanServerQueries::create_server(&mut *conn, clan_id, leader_id, &ip, capacity).await? {
+            return ClanServerQueries::server(conn, clan_id).await;
+        }
+    }
+
+    anyhow::bail!("no free address after {} attempts", ALLOCATION_ATTEMPTS)
+}
+
+async fn defense(conn: &mut PgConnection, server: &ClanServerRow, config: &ClanServerConfig) -> anyhow::Result<(i64, usize)> {
+    let versions = ClanServerQueries::defender_versions(conn, server.clan_id).await?;
+    Ok((clan_server::defense(&versions, server.cpu_level, config), versions.len()))
+}
+
+/// The caller's clan server
+pub async fn view(pool: &PgPool, user_id: i64) -> ClanServerResult<ClanServerView> {
+    let config = &crate::balance::current().clan_server;
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(server) = ensure(&mut *tx, clan.clan_id, config).await? else {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    };
+    let (defense, defenders) = defense(&mut *tx, &server, config).await?;
+    tx.commit().await?;
+
+    let levels = levels(&server);
+    let parts = Part::ALL
+        .into_iter()
+        .map(|part| PartStatus {
+            part,
+            level: levels.get(part),
+            capacity: clan_server::capacity(part, levels.get(part), config),
+            next_cost: clan_server::upgrade_cost(part, levels.get(part), config),
+        })
+        .collect();
+
+    Ok(Ok(ClanServerView { server, parts, defense, defenders }))
+}
+
+/// Pay into the clan treasury from the caller's primary bank account
+pub async fn deposit(pool: &PgPool, user_id: i64, amount: i64) -> ClanServerResult<()> {
+    let config = &crate::balance::current().clan_server;
+    if amount < config.min_deposit {
+        return Ok(Err(ClanServerDenied::AmountTooSmall { min: config.min_deposit }));
+    }
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let reference = format!("clan:{}", clan.clan_id);
+    let paid = BankQueries::debit_primary_account(
+        &mut *tx,
+        user_id,
+        amount,
+        LedgerAccount::ClanTreasury(clan.clan_id),
+        LedgerReason::ClanDeposit,
+        &reference,
+    )
+    .await?;
+    if !paid {
+        return Ok(Err(ClanServerDenied::InsufficientFunds));
+    }
+    tx.commit().await?;
+
+    Ok(Ok(()))
+}
+
+/// Buy the next level of `part` from the treasury
+pub async fn upgrade(pool: &PgPool, user_id: i64, part: &str) -> ClanServerResult<Upgrade> {
+    let config = &crate::balance::current().clan_server;
+    let Some(part) = Part::from_str(part) else {
+        return Ok(Err(ClanServerDenied::UnknownPart));
+    };
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) if clan.is_officer() => clan,
+        Ok(_) => return Ok(Err(ClanServerDenied::NotOfficer)),
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(server) = ensure(&mut *tx, clan.clan_id, config).await? else {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    };
+    let level = levels(&server).get(part);
+    let Some(cost) = clan_server::upgrade_cost(part, level, config) else {
+        return Ok(Err(ClanServerDenied::MaxLevel { part }));
+    };
+
+    let reference = format!("clan:{}:{}:{}", clan.clan_id, part.as_str(), level + 1);
+    let paid = LedgerQueries::post(
+        &mut *tx,
+        LedgerReason::ClanServerUpgrade,
+        &reference,
+        &[(LedgerAccount::ClanTreasury(clan.clan_id), -cost), (LedgerAccount::Sink, cost)],
+    )
+    .await?;
+    if !paid {
+        return Ok(Err(ClanServerDenied::TreasuryTooLow { needed: cost, balance: server.treasury }));
+    }
+
+    let capacity = clan_server::capacity(part, level + 1, config);
+    ClanServerQueries::set_level(&mut *tx, clan.clan_id, part.as_str(), level + 1, capacity).await?;
+    ClanServerQueries::record_upgrade(&mut *tx, clan.clan_id, part.as_str(), level + 1, cost, user_id).await?;
+    tx.commit().await?;
+
+    Ok(Ok(Upgrade { part, level: level + 1, cost, capacity }))
+}
+
+/// Software on the caller's clan server
+pub async fn files(pool: &PgPool, user_id: i64) -> ClanServerResult<Vec<ClanFileRow>> {
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+    Ok(Ok(ClanServerQueries::files(pool, clan.clan_id).await?))
+}
+
+/// Copy a piece of the caller's software onto the clan server
+pub async fn upload(pool: &PgPool, user_id: i64, software_id: i64) -> ClanServerResult<i64> {
+    let config = &crate::balance::current().clan_server;
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(server) = ensure(&mut *tx, clan.clan_id, config).await? else {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    };
+    let Some(size) = ClanServerQueries::own_software_size(&mut *tx, user_id, software_id).await? else {
+        return Ok(Err(ClanServerDenied::SoftwareNotFound));
+    };
+    let free_mb = ClanServerQueries::lock_free_space(&mut *tx, server.server_id).await?;
+    if size > free_mb {
+        return Ok(Err(ClanServerDenied::StorageFull { free_mb }));
+    }
+
+    let stored = ClanServerQueries::copy_software(&mut *tx, software_id, server.server_id).await?;
+    ClanServerQueries::record_file(&mut *tx, stored, clan.clan_id, user_id).await?;
+    tx.commit().await?;
+
+    Ok(Ok(stored))
+}
+
+/// Copy a file from the clan server to the caller's gateway
+pub async fn download(pool: &PgPool, user_id: i64, software_id: i64) -> ClanServerResult<i64> {
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some((_, size)) = ClanServerQueries::file(&mut *tx, clan.clan_id, software_id).await? else {
+        return Ok(Err(ClanServerDenied::FileNotFound));
+    };
+    let Some(gateway) = ClanServerQueries::gateway(&mut *tx, user_id).await? else {
+        return Ok(Err(ClanServerDenied::StorageFull { free_mb: 0 }));
+    };
+    let free_mb = ClanServerQueries::lock_free_space(&mut *tx, gateway).await?;
+    if size > free_mb {
+        return Ok(Err(ClanServerDenied::StorageFull { free_mb }));
+    }
+
+    let copy = ClanServerQueries::copy_software(&mut *tx, software_id, gateway).await?;
+    tx.commit().await?;
+
+    Ok(Ok(copy))
+}
+
+/// Remove a file from the clan server
+pub async fn remove_file(pool: &PgPool, user_id: i64, software_id: i64) -> ClanServerResult<()> {
+    let config = &crate::balance::current().clan_server;
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(server) = ensure(&mut *tx, clan.clan_id, config).await? else {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    };
+    let Some((uploaded_by, size)) = ClanServerQueries::file(&mut *tx, clan.clan_id, software_id).await? else {
+        return Ok(Err(ClanServerDenied::FileNotFound));
+    };
+    if uploaded_by != Some(user_id) && !clan.is_officer() {
+        return Ok(Err(ClanServerDenied::NotUploader));
+    }
+
+    ClanServerQueries::lock_free_space(&mut *tx, server.server_id).await?;
+    ClanServerQueries::delete_file(&mut *tx, server.server_id, software_id, size).await?;
+    tx.commit().await?;
+
+    Ok(Ok(()))
+}
+
+/// Leave the caller's clan, returning it. The leader cannot leave.
+pub async fn leave(pool: &PgPool, user_id: i64) -> ClanServerResult<i64> {
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    if ClanServerQueries::lock_clan(&mut *tx, clan.clan_id).await?.is_none() {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    }
+    match ClanServerQueries::lock_member(&mut *tx, clan.clan_id, user_id).await?.as_deref() {
+        None => return Ok(Err(ClanServerDenied::NoClan)),
+        Some("leader") => return Ok(Err(ClanServerDenied::LeaderCannotLeave)),
+        Some(_) => {}
+    }
+    ClanServerQueries::remove_member(&mut *tx, clan.clan_id, user_id).await?;
+    tx.commit().await?;
+
+    Ok(Ok(clan.clan_id))
+}
+
+/// Remove `member_id` from the caller's clan, returning it. Officers remove
+/// members; only the leader removes officers, and nobody removes the leader.
+pub async fn kick(pool: &PgPool, user_id: i64, member_id: i64) -> ClanServerResult<i64> {
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) if clan.is_officer() => clan,
+        Ok(_) => return Ok(Err(ClanServerDenied::NotOfficer)),
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    if ClanServerQueries::lock_clan(&mut *tx, clan.clan_id).await?.is_none() {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    }
+    let outranks = match ClanServerQueries::lock_member(&mut *tx, clan.clan_id, member_id).await?.as_deref() {
+        None => return Ok(Err(ClanServerDenied::MemberNotFound)),
+        Some("leader") => false,
+        Some("officer") => clan.role == "leader",
+        Some(_) => true,
+    };
+    if !outranks {
+        return Ok(Err(ClanServerDenied::NotOfficer));
+    }
+    ClanServerQueries::remove_member(&mut *tx, clan.clan_id, member_id).await?;
+    tx.commit().await?;
+
+    Ok(Ok(clan.clan_id))
+}
+
+/// Lend the caller's best firewall to the clan server, returning its new
+/// defense
+pub async fn lend_firewall(pool: &PgPool, user_id: i64) -> ClanServerResult<i64> {
+    let config = &crate::balance::current().clan_server;
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(server) = ensure(&mut *tx, clan.clan_id, config).await? else {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    };
+    let Some(version) = ClanServerQueries::best_software(&mut *tx, user_id, FIREWALL).await? else {
+        return Ok(Err(ClanServerDenied::NoFirewall));
+    };
+    ClanServerQueries::lend_firewall(&mut *tx, clan.clan_id, user_id, version).await?;
+    let (defense, _) = defense(&mut *tx, &server, config).await?;
+    tx.commit().await?;
+
+    Ok(Ok(defense))
+}
+
+/// Declare war on another clan
+pub async fn declare_war(pool: &PgPool, user_id: i64, target_clan_id: i64) -> ClanServerResult<ClanWarRow> {
+    let config = &crate::balance::current().clan_server;
+    if !crate::rules::current().clan_wars_enabled {
+        return Ok(Err(ClanServerDenied::WarsDisabled));
+    }
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) if clan.is_officer() => clan,
+        Ok(_) => return Ok(Err(ClanServerDenied::NotOfficer)),
+        Err(denied) => return Ok(Err(denied)),
+    };
+    if clan.clan_id == target_clan_id {
+        return Ok(Err(ClanServerDenied::SameClan));
+    }
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    if ClanServerQueries::lock_clan(&mut *tx, target_clan_id).await?.is_none() {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    }
+    let Some(war) = ClanServerQueries::declare_war(
+        &mut *tx,
+        clan.clan_id,
+        target_clan_id,
+        config.max_integrity,
+        config.war_hours,
+        user_id,
+    )
+    .await?
+    else {
+        return Ok(Err(ClanServerDenied::AlreadyAtWar));
+    };
+    crate::webhooks::war_declared(&mut *tx, &war).await?;
+    tx.commit().await?;
+
+    Ok(Ok(war))
+}
+
+/// The caller's clan's wars, newest first
+pub async fn wars(pool: &PgPool, user_id: i64, before_id: Option<i64>, limit: i64) -> ClanServerResult<Vec<ClanWarRow>> {
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+    Ok(Ok(ClanServerQueries::wars(pool, clan.clan_id, before_id, limit).await?))
+}
+
+/// The side that wins a war that ran out, if either
+fn winner_on_score(war: &ClanWarRow) -> Option<i64> {
+    match war.attacker_score.cmp(&war.defender_score) {
+        std::cmp::Ordering::Greater => Some(war.attacker_clan_id),
+        std::cmp::Ordering::Less => Some(war.defender_clan_id),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+fn war_event(war: &ClanWarRow, kind: WarEventKind, side: WarSide, actor_id: Option<i64>) -> WarEvent {
+    WarEvent {
+        war_id: war.id,
+        kind,
+        side,
+        actor_id,
+        target_id: None,
+        attacker_score: war.attacker_score,
+        defender_score: war.defender_score,
+        occurred_at: Utc::now(),
+    }
+}
+
+/// Strike the other side's clan server in `war_id`
+pub async fn attack(pool: &PgPool, spectator: &WarSpectator, user_id: i64, war_id: i64) -> ClanServerResult<StrikeReport> {
+    let config = &crate::balance::current().clan_server;
+    if !crate::rules::current().clan_wars_enabled {
+        return Ok(Err(ClanServerDenied::WarsDisabled));
+    }
+    let clan = match membership(pool, user_id).await? {
+        Ok(clan) => clan,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(war) = ClanServerQueries::lock_war(&mut *tx, war_id).await? else {
+        return Ok(Err(ClanServerDenied::WarNotFound));
+    };
+    let (side, target_clan_id, integrity) = if clan.clan_id == war.attacker_clan_id {
+        (WarSide::Attacker, war.defender_clan_id, war.defender_integrity)
+    } else if clan.clan_id == war.defender_clan_id {
+        (WarSide::Defender, war.attacker_clan_id, war.attacker_integrity)
+    } else {
+        return Ok(Err(ClanServerDenied::NotAtWar));
+    };
+    if war.status != "active" {
+        return Ok(Err(ClanServerDenied::NotAtWar));
+    }
+
+    let now = Utc::now();
+    if war.ends_at <= now {
+        let ended = ClanServerQueries::end_war(&mut *tx, war.id, winner_on_score(&war)).await?;
+        tx.commit().await?;
+        report(spectator, &[war_event(&ended, WarEventKind::WarEnded, side, None)]).await;
+        return Ok(Err(ClanServerDenied::NotAtWar));
+    }
+
+    let cooldown = Duration::minutes(config.attack_cooldown_minutes);
+    if let Some(last) = ClanServerQueries::last_strike(&mut *tx, war.id, user_id).await? {
+        if last + cooldown > now {
+            let minutes = (last + cooldown - now).num_minutes() + 1;
+            return Ok(Err(ClanServerDenied::AttackCooldown { minutes }));
+        }
+    }
+    let Some(cracker) = ClanServerQueries::best_software(&mut *tx, user_id, CRACKER).await? else {
+        return Ok(Err(ClanServerDenied::NoCracker));
+    };
+    let Some(target) = ensure(&mut *tx, target_clan_id, config).await? else {
+        return Ok(Err(ClanServerDenied::ClanNotFound));
+    };
+
+    let (defense, _) = defense(&mut *tx, &target, config).await?;
+    let strike = clan_server::strike(cracker, defense, integrity, config);
+    let mut war = ClanServerQueries::record_strike(
+        &mut *tx,
+        war.id,
+        user_id,
+        side.as_str(),
+        strike.damage,
+        strike.points,
+        strike.integrity,
+    )
+    .await?;
+
+    let mut events = vec![war_event(&war, WarEventKind::AttackLaunched, side, Some(user_id))];
+    if strike.downed {
+        war = ClanServerQueries::end_war(&mut *tx, war.id, Some(clan.clan_id)).await?;
+        events.push(war_event(&war, WarEventKind::ServerDowned, side, Some(user_id)));
+        events.push(war_event(&war, WarEventKind::WarEnded, side, None));
+    }
+    tx.commit().await?;
+
+    report(spectator, &events).await;
+    Ok(Ok(StrikeReport { strike, war }))
+}
+
+/// Add committed war events to the battle log
+async fn report(spectator: &WarSpectator, events: &[WarEvent]) {
+    for event in events {
+        if let Err(e) = spectator.record(event).await {
+            tracing::warn!("Failed to record war {} event: {}", event.war_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn war(attacker_score: i64, defender_score: i64) -> ClanWarRow {
+        ClanWarRow {
+            id: 1,
+            attacker_clan_id: 10,
+            defender_clan_id: 20,
+            status: "active".to_string(),
+            attacker_score,
+            defender_score,
+            attacker_integrity: 100,
+            defender_integrity: 100,
+            winner_clan_id: None,
+            started_at: Utc::now(),
+            ends_at: Utc::now(),
+            ended_at: None,
+        }
+    }
+
+    #[test]
+    fn test_winner_on_score() {
+        assert_eq!(winner_on_score(&war(30, 10)), Some(10));
+        assert_eq!(winner_on_score(&war(5, 10)), Some(20));
+        assert_eq!(winner_on_score(&war(7, 7)), None);
+    }
+}
@@ -0,0 +1,529 @@
+//! Server-side process completion
+//!
+//! Processes complete on the server instead of waiting for a client to poll.
+//! End times sit in a min-heap and the worker sleeps until the next one is due.
+//! A heartbeat also re-reads overdue processes from the database, which covers
+//! processes started on another node or before a restart.
+//!
+//! Each completion runs in a single transaction. The process row is claimed by
+//! setting `completed_at`, then the handler for its type applies rewards and
+//! writes logs. If a handler fails, the claim rolls back and the process is
+//! retried on the next heartbeat. A process that was already claimed is skipped,
+//! so handlers never run twice. Downloads and deletes also fulfil the player
+//! contracts they prove, and completions advance the owner's co-op mission. Socket notifications and bounty events are queued in
+//! the outbox by the same transaction, so they are delivered even if the node
+//! dies right after commit. Remote processes against a honeypot earn nothing
+//! and trace their player; while traced, targets log the player's gateway.
+//! Processes on the player's own hardware wear it, and repairs restore it.
+//! Big login hacks are announced to the player's and their clan's webhooks.
+//! Mining payouts for a player without a bank account are held and paid by a
+//! sweep once they open one.
+//! Downloads of other players' software store a copy on the thief's gateway,
+//! and keygens crack the license of a locked copy (see [`crate::piracy`]).
+//!
+//! With several nodes, each completes only the processes of the shards it
+//! leases (see [`crate::scheduler`]), and polls those shards every
+//! [`scheduler::TICK`](crate::scheduler::TICK) for processes due before the
+//! next poll, since they were mostly started on other nodes.
+//!
+//! `main` starts the worker with the server; [`ensure_started`] covers
+//! states built without it, as in tests.
+
+use actix_web::web;
+use chrono::{DateTime, Utc};
+use he_database::models::Process;
+use he_database::queries::{
+    BankQueries, HackedDatabaseQueries, LedgerAccount, LedgerReason, LogQueries, ProcessQueries, ProgressionQueries,
+};
+use he_game_mechanics::process::{CompletionReward, ProcessType};
+use he_game_mechanics::scripting::Formula;
+use once_cell::sync::Lazy;
+use sqlx::{PgConnection, PgPool};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use crate::bounty::BountyEvent;
+use crate::contracts::ContractEvent;
+use crate::coop::CoopEvent;
+use crate::hardware_wear::HardwareChange;
+use crate::honeypot::HoneypotTrip;
+use crate::ip_reset::IpReset;
+use crate::outbox::OutboxMessage;
+use crate::piracy::PiracyEvent;
+use crate::scheduler::{self, Fence, Route};
+use crate::tutorial::TutorialAdvance;
+use crate::state::AppState;
+
+const HEARTBEAT: Duration = Duration::from_secs(30);
+const HEARTBEAT_BATCH: i64 = 500;
+const HELD_PAYOUT_INTERVAL: Duration = Duration::from_secs(60);
+const HELD_PAYOUT_BATCH: i64 = 100;
+
+/// Process end times, soonest first
+#[derive(Default)]
+pub struct CompletionQueue {
+    heap: Mutex<BinaryHeap<Reverse<(DateTime<Utc>, i64)>>>,
+    wakeup: Notify,
+}
+
+impl CompletionQueue {
+    pub fn schedule(&self, pid: i64, end_time: DateTime<Utc>) {
+        self.heap.lock().unwrap().push(Reverse((end_time, pid)));
+        // The new process may finish before whatever the worker is sleeping on
+        self.wakeup.notify_one();
+    }
+
+    /// Remove and return every process due at `now`
+    pub fn pop_due(&self, now: DateTime<Utc>) -> Vec<i64> {
+        let mut heap = self.heap.lock().unwrap();
+        let mut due = Vec::new();
+        while let Some(Reverse((end_time, pid))) = heap.peek().copied() {
+            if end_time > now {
+                break;
+            }
+            heap.pop();
+            due.push(pid);
+        }
+        due
+    }
+
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.heap.lock().unwrap().peek().map(|Reverse((end_time, _))| *end_time)
+    }
+}
+
+static QUEUE: Lazy<CompletionQueue> = Lazy::new(CompletionQueue::default);
+static WORKER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Queue a freshly created process for completion
+pub fn schedule(state: &web::Data<AppState>, pid: i64, end_time: DateTime<Utc>) {
+    QUEUE.schedule(pid, end_time);
+    ensure_started(state);
+}
+
+/// Move a queued process to a new end time, e.g. after a complication.
+/// Its old entry stays in the heap but only triggers a claim that fails
+/// because the process is not due yet.
+pub fn reschedule(pid: i64, end_time: DateTime<Utc>) {
+    QUEUE.schedule(pid, end_time);
+}
+
+/// Start the worker from `state` if it is not running yet
+pub fn ensure_started(state: &web::Data<AppState>) {
+    start(state.db.pool.clone(), state.ws_manager.clone());
+}
+
+/// Start the worker, and the complications worker, held mining payouts,
+/// bounty expiry, contract, referral and reservation sweepers, bulk operation
+/// worker, economy stream and outbox relay with it, if they are not running
+/// yet
+pub fn start(pool: PgPool, ws_manager: Option<Arc<he_websocket::ConnectionManager>>) {
+    if WORKER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    spawn_held_payouts(pool.clone());
+    crate::complications::spawn(pool.clone(), ws_manager.clone());
+    crate::bounty::spawn_expiry(pool.clone());
+    crate::contracts::spawn_sweeper(pool.clone());
+    crate::referrals::spawn_milestones(pool.clone());
+    crate::ledger::spawn_reconciler(pool.clone());
+    crate::reservations::spawn_sweeper(pool.clone());
+    crate::bulk_ops::spawn_worker(pool.clone());
+    crate::economy_stream::spawn_processor(pool.clone());
+    crate::outbox::spawn_relay(pool.clone(), ws_manager.clone());
+    crate::gateway::spawn_forwarder(ws_manager.clone());
+    crate::ws_acl::spawn_revalidation(ws_manager.clone());
+    tokio::spawn(run(pool));
+}
+
+async fn run(pool: PgPool) {
+    let mut next_heartbeat = Utc::now();
+
+    loop {
+        let now = Utc::now();
+        let started = std::time::Instant::now();
+
+        if now >= next_heartbeat {
+            let owned = scheduler::owned_shards();
+            let interval = if owned.is_some() { scheduler::TICK } else { HEARTBEAT };
+            let pending = match &owned {
+                None => ProcessQueries::pending_completions(&pool, now, HEARTBEAT_BATCH).await,
+                Some(shards) => {
+                    let until = now + chrono::Duration::from_std(interval).unwrap_or_default();
+                    ProcessQueries::pending_completions_in(&pool, until, scheduler::SHARDS, shards, HEARTBEAT_BATCH).await
+                }
+            };
+            match pending {
+                Ok(pending) => {
+                    scheduler::report_lag(shard_lag(&pending, now));
+                    for (pid, end_time) in pending {
+                        QUEUE.heap.lock().unwrap().push(Reverse((end_time, pid)));
+                    }
+                }
+                Err(e) => tracing::warn!("Completion heartbeat failed: {}", e),
+            }
+            next_heartbeat = now + chrono::Duration::from_std(interval).unwrap_or_default();
+        }
+
+        for pid in QUEUE.pop_due(now) {
+            let fence = match scheduler::route(pid) {
+                Route::Local => None,
+                Route::Fenced(fence) => Some(fence),
+                // Its shard's owner picks it up on its next poll
+                Route::Elsewhere => continue,
+            };
+            match complete(&pool, pid, fence).await {
+                Ok(true) => crate::outbox::wake(),
+                Ok(false) => {}
+                Err(e) => tracing::error!("Failed to complete process {}: {}", pid, e),
+            }
+        }
+
+        crate::live_ops::record_tick("completion", started.elapsed());
+
+        let until_next = QUEUE
+            .next_due()
+            .map(|due| (due - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+            .unwrap_or(HEARTBEAT)
+            .min(HEARTBEAT);
+
+        tokio::select! {
+            _ = tokio::time::sleep(until_next) => {}
+            _ = QUEUE.wakeup.notified() => {}
+        }
+    }
+}
+
+/// Pay held mining payouts once their miners have a bank account
+fn spawn_held_payouts(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HELD_PAYOUT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let started = std::time::Instant::now();
+            if let Err(e) = pay_held_payouts(&pool).await {
+                tracing::warn!("Held mining payout failed: {}", e);
+            }
+            crate::live_ops::record_tick("mining_payouts", started.elapsed());
+        }
+    });
+}
+
+async fn pay_held_payouts(pool: &PgPool) -> anyhow::Result<()> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let paid = BankQueries::pay_held_mining_payouts(&mut *tx, HELD_PAYOUT_BATCH).await?;
+    let messages: Vec<OutboxMessage> = paid
+        .iter()
+        .map(|payout| {
+            let event = he_websocket::EventBuilder::money_received(payout.money, "Mining pool".to_string());
+            OutboxMessage::to_user(format!("process:{}:money", payout.pid), payout.user_id, &event)
+        })
+        .collect();
+    crate::outbox::enqueue(&mut *tx, &messages).await?;
+    tx.commit().await?;
+
+    for payout in &paid {
+        tracing::info!(
+            "Paid held mining payout of ${} for process {} to user {}",
+            payout.money,
+            payout.pid,
+            payout.user_id
+        );
+    }
+    if !paid.is_empty() {
+        crate::outbox::wake();
+    }
+    Ok(())
+}
+
+/// Seconds the oldest overdue process of each shard has waited
+fn shard_lag(pending: &[(i64, DateTime<Utc>)], now: DateTime<Utc>) -> HashMap<i32, i64> {
+    let mut lag = HashMap::new();
+    for (pid, end_time) in pending.iter().filter(|(_, end_time)| *end_time <= now) {
+        let waited = (now - *end_time).num_seconds();
+        let oldest = lag.entry(scheduler::shard_of(*pid)).or_insert(waited);
+        *oldest = (*oldest).max(waited);
+    }
+    lag
+}
+
+/// What completing a process changed, for its notifications
+struct CompletedProcess {
+    process: Process,
+    reward: CompletionReward,
+    /// The money was held for lack of a bank account
+    money_held: bool,
+    ip_reset: Option<IpReset>,
+    bounties: Vec<BountyEvent>,
+    tutorial: Option<TutorialAdvance>,
+    contracts: Vec<ContractEvent>,
+    coop: Vec<CoopEvent>,
+    hardware: Vec<HardwareChange>,
+    piracy: Vec<PiracyEvent>,
+}
+
+/// Claim and complete one process, under `fence` when its shard is leased.
+/// `Ok(false)` if it is not due, was cancelled, was already completed, or the
+/// lease was superseded.
+async fn complete(pool: &PgPool, pid: i64, fence: Option<Fence>) -> anyhow::Result<bool> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+
+    if let Some(fence) = fence {
+        if !ProcessQueries::fence_shard(&mut *tx, fence.shard, fence.token).await? {
+            tx.rollback().await?;
+            scheduler::fenced_out(fence);
+            return Ok(false);
+        }
+    }
+
+    let Some(process) = ProcessQueries::claim_completion(&mut *tx, pid).await? else {
+        tx.rollback().await?;
+        return Ok(false);
+    };
+
+    let Applied { reward, money_held, ip_reset, bounties, honeypot } =
+        CompletionHandler::for_type(&ProcessType::from_str(&process.process_type))
+            .apply(&mut *tx, &process)
+            .await?;
+    let tutorial = crate::tutorial::advance(&mut *tx, &process).await?;
+    // Nothing taken from a honeypot proves a contract
+    let contracts = match honeypot {
+        Some(_) => Vec::new(),
+        None => crate::contracts::fulfil(&mut *tx, &process).await?,
+    };
+    // Nor does it advance a co-op mission
+    let coop = match honeypot {
+        Some(_) => Vec::new(),
+        None => crate::coop::credit(&mut *tx, &process).await?,
+    };
+
+    let hardware = crate::hardware_wear::apply(&mut *tx, &process).await?;
+    // Nothing is copied out of a honeypot
+    let piracy = match honeypot {
+        Some(_) => Vec::new(),
+        None => crate::piracy::apply(&mut *tx, &process).await?,
+    };
+    if honeypot.is_none() {
+        crate::webhooks::big_hack(&mut *tx, &process, &reward, &bounties).await?;
+    }
+
+    let completed = CompletedProcess {
+        process,
+        reward,
+        money_held,
+        ip_reset,
+        bounties,
+        tutorial,
+        contracts,
+        coop,
+        hardware,
+        piracy,
+    };
+    crate::outbox::enqueue(&mut *tx, &completed.outbox_messages()).await?;
+
+    tx.commit().await?;
+    if let Some(trip) = &honeypot {
+        crate::honeypot::report(trip);
+    }
+    Ok(true)
+}
+
+/// What a completion handler applied
+struct Applied {
+    reward: CompletionReward,
+    money_held: bool,
+    ip_reset: Option<IpReset>,
+    bounties: Vec<BountyEvent>,
+    honeypot: Option<HoneypotTrip>,
+}
+
+/// What happens when a process of a given type completes
+enum CompletionHandler {
+    /// Acts on another server, which logs the access
+    Remote { log_type: &'static str },
+    /// Mining pays out to the owner's bank account, or holds the payout
+    /// until they have one
+    Mining,
+    /// Moves the owner's gateway to a new address
+    ResetIp,
+    /// Records what the target runs in the owner's server database
+    Scan,
+    /// Everything else only logs on the owner's server
+    Local,
+}
+
+impl CompletionHandler {
+    fn for_type(process_type: &ProcessType) -> Self {
+        match (process_type, process_type.target_log_type()) {
+            (ProcessType::BitcoinMine, _) => CompletionHandler::Mining,
+            (ProcessType::ResetIp, _) => CompletionHandler::ResetIp,
+            (ProcessType::PortScan | ProcessType::SystemScan, _) => CompletionHandler::Scan,
+            (_, Some(log_type)) => CompletionHandler::Remote { log_type },
+            _ => CompletionHandler::Local,
+        }
+    }
+
+    /// Apply rewards and logs inside the completion transaction
+    async fn apply(
+        &self,
+        conn: &mut PgConnection,
+        process: &Process,
+    ) -> anyhow::Result<Applied> {
+        let mut reward = ProcessType::from_str(&process.process_type).completion_reward_under(crate::rules::current());
+        let scripts = crate::formula_scripts::current();
+        reward.experience = scripts.apply(Formula::ExperienceReward, reward.experience, &process.process_type, &[]);
+        reward.money = scripts.apply(Formula::MoneyReward, reward.money, &process.process_type, &[]);
+        let mut money_held = false;
+        let mut ip_reset = None;
+        let mut bounties = Vec::new();
+        let mut honeypot = None;
+
+        LogQueries::add_server_log(
+            conn,
+            process.user_id,
+            &process.process_type,
+            &format!("Process {} ({}) completed", process.pid, process.process_type),
+            None,
+        ).await?;
+
+        match self {
+            CompletionHandler::Remote { log_type } => {
+                if let Some(target_ip) = process.target_pc_id.as_deref() {
+                    // A traced player's gateway is logged wherever they act
+                    let origin_ip = match crate::honeypot::traced(conn, process.user_id).await? {
+                        true => None,
+                        false => crate::hosting::host_ip(process),
+                    };
+                    LogQueries::add_remote_access_log(conn, target_ip, process.user_id, log_type, origin_ip).await?;
+                    honeypot = crate::honeypot::spring(conn, process, target_ip).await?;
+                    if honeypot.is_some() {
+                        reward = CompletionReward { experience: 0, money: 0 };
+                    }
+                    if *log_type == "login" {
+                        HackedDatabaseQueries::record(conn, process.user_id, target_ip).await?;
+                        if honeypot.is_none() {
+                            bounties = crate::bounty::claim(conn, process, target_ip).await?;
+                        }
+                    }
+                }
+            }
+            CompletionHandler::Mining => {
+                let reference = format!("process:{}", process.pid);
+                let paid = BankQueries::credit_primary_account(
+                    conn,
+                    process.user_id,
+                    reward.money,
+                    LedgerAccount::Mint,
+                    LedgerReason::MiningPayout,
+                    &reference,
+                )
+                .await?;
+                if !paid && reward.money > 0 {
+                    // Nowhere to pay out yet; the sweep pays it once they open an account
+                    BankQueries::hold_mining_payout(conn, process.pid, process.user_id, reward.money).await?;
+                    money_held = true;
+                }
+            }
+            CompletionHandler::ResetIp => {
+                ip_reset = crate::ip_reset::apply(conn, process).await?;
+            }
+            CompletionHandler::Scan => {
+                if let Some(target_ip) = process.target_pc_id.as_deref() {
+                    crate::server_browser::record_scan(conn, process.user_id, target_ip).await?;
+                }
+            }
+            CompletionHandler::Local => {}
+        }
+
+        if reward.experience > 0 {
+            ProgressionQueries::add_experience(conn, process.user_id, reward.experience).await?;
+        }
+
+        Ok(Applied { reward, money_held, ip_reset, bounties, honeypot })
+    }
+}
+
+impl CompletedProcess {
+    /// Socket pushes and bounty events the completion queues in the outbox
+    fn outbox_messages(&self) -> Vec<OutboxMessage> {
+        let pid = self.process.pid;
+        let user_id = self.process.user_id;
+
+        let result = serde_json::json!({
+            "experience": self.reward.experience,
+            "money": self.reward.money,
+            "money_held": self.money_held,
+        });
+        let event = he_websocket::EventBuilder::process_completed(pid, self.process.process_type.clone(), result.to_string());
+        let mut messages = vec![OutboxMessage::to_process(format!("process:{}:completed", pid), pid, &event)];
+
+        if self.reward.money > 0 && !self.money_held {
+            let event = he_websocket::EventBuilder::money_received(self.reward.money, "Mining pool".to_string());
+            messages.push(OutboxMessage::to_user(format!("process:{}:money", pid), user_id, &event));
+        }
+
+        if let Some(ip_reset) = &self.ip_reset {
+            messages.extend(crate::ip_reset::outbox_messages(pid, user_id, ip_reset));
+        }
+
+        if let Some(tutorial) = &self.tutorial {
+            messages.extend(crate::tutorial::outbox_messages(pid, user_id, tutorial));
+        }
+
+        messages.extend(crate::hardware_wear::outbox_messages(pid, user_id, &self.hardware));
+
+        let origin = format!("process:{}", pid);
+        messages.extend(self.bounties.iter().map(|event| event.to_outbox(&origin)));
+        messages.extend(self.contracts.iter().flat_map(|event| event.outbox_messages(&origin)));
+        messages.extend(self.coop.iter().map(|event| event.to_outbox(&origin)));
+        messages.extend(self.piracy.iter().map(|event| event.to_outbox(&origin)));
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_pops_due_in_order() {
+        let queue = CompletionQueue::default();
+        let now = Utc::now();
+        queue.schedule(3, now + chrono::Duration::seconds(60));
+        queue.schedule(2, now - chrono::Duration::seconds(5));
+        queue.schedule(1, now - chrono::Duration::seconds(10));
+
+        assert_eq!(queue.pop_due(now), vec![1, 2]);
+        assert_eq!(queue.next_due(), Some(now + chrono::Duration::seconds(60)));
+        assert!(queue.pop_due(now).is_empty());
+    }
+
+    #[test]
+    fn test_shard_lag_takes_oldest_overdue() {
+        let now = Utc::now();
+        let pending = [
+            (1, now - chrono::Duration::seconds(40)),
+            (33, now - chrono::Duration::seconds(5)),
+            (2, now + chrono::Duration::seconds(3)),
+        ];
+        let lag = shard_lag(&pending, now);
+        assert_eq!(lag.get(&1), Some(&40));
+        assert_eq!(lag.get(&2), None);
+    }
+
+    #[test]
+    fn test_handler_for_type() {
+        assert!(matches!(CompletionHandler::for_type(&ProcessType::BitcoinMine), CompletionHandler::Mining));
+        assert!(matches!(
+            CompletionHandler::for_type(&ProcessType::Download),
+            CompletionHandler::Remote { log_type: "download" }
+        ));
+        assert!(matches!(CompletionHandler::for_type(&ProcessType::ResetIp), CompletionHandler::ResetIp));
+        assert!(matches!(CompletionHandler::for_type(&ProcessType::PortScan), CompletionHandler::Scan));
+        assert!(matches!(CompletionHandler::for_type(&ProcessType::Research), CompletionHandler::Local));
+    }
+}
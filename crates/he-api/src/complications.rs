@@ -0,0 +1,174 @@
+//! Complications worker
+//!
+//! Rolls for random events on long-running processes (see
+//! [`he_game_mechanics::complications`]) and applies them: the process's end
+//! time moves, or it is paused until its owner resumes it, the completion queue is updated and the
+//! owner hears about it over the WebSocket straight away.
+//!
+//! Every node runs this worker. The roll for a process in a given interval is
+//! derived from a keyed hash of its pid and the interval number instead of a
+//! local RNG, so all nodes agree on the outcome and the unique pid in
+//! `process_complications` keeps it from being applied twice. The hash is
+//! keyed with `COMPLICATION_SECRET`; without it the worker does not run.
+
+use chrono::Utc;
+use he_database::models::Process;
+use he_database::queries::ProcessQueries;
+use he_game_mechanics::complications::{self, ComplicationKind, ProcessContext};
+use he_game_mechanics::config::ComplicationConfig;
+use he_game_mechanics::process::ProcessType;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often running processes are rolled for
+pub const ROLL_INTERVAL: Duration = Duration::from_secs(15);
+/// Candidates loaded per query; a roll walks every page
+const ROLL_BATCH: i64 = 1000;
+
+pub struct ComplicationRoller {
+    secret: Vec<u8>,
+    config: ComplicationConfig,
+}
+
+impl ComplicationRoller {
+    pub fn new(secret: Vec<u8>, config: ComplicationConfig) -> Self {
+        Self { secret, config }
+    }
+
+    /// Keyed with `COMPLICATION_SECRET`, which every node shares, so players
+    /// cannot work out their rolls in advance; `None` if it is not set
+    pub fn from_env() -> Option<Self> {
+        let secret = std::env::var("COMPLICATION_SECRET").ok().filter(|s| !s.is_empty())?;
+        Some(Self::new(secret.into_bytes(), crate::balance::current().complications.clone()))
+    }
+
+    /// Uniform sample in [0, 1) for `pid` in roll interval `window`
+    fn sample(&self, pid: i64, window: i64) -> f64 {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.secret);
+        hasher.update(pid.to_be_bytes());
+        hasher.update(window.to_be_bytes());
+        let digest = hasher.finalize();
+
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        // Top 53 bits, the precision of an f64 mantissa
+        (u64::from_be_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Complication for `process` in roll interval `window`, if any
+    fn roll(&self, process: &Process, window: i64) -> Option<ComplicationKind> {
+        let context = ProcessContext {
+            process_type: ProcessType::from_str(&process.process_type),
+            remote: process.target_pc_id.is_some(),
+            start_time: process.start_time,
+            end_time: process.end_time,
+        };
+        complications::pick(&context, ROLL_INTERVAL, self.sample(process.pid, window), &self.config)
+    }
+}
+
+/// Start the worker; called once alongside the completion worker
+pub fn spawn(pool: PgPool, ws_manager: Option<Arc<he_websocket::ConnectionManager>>) {
+    let Some(roller) = ComplicationRoller::from_env() else {
+        tracing::warn!("COMPLICATION_SECRET not set, process complications are off");
+        return;
+    };
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(ROLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let started = std::time::Instant::now();
+            if let Err(e) = roll_all(&roller, &pool, ws_manager.as_deref()).await {
+                tracing::warn!("Complication roll failed: {}", e);
+            }
+            crate::live_ops::record_tick("complications", started.elapsed());
+        }
+    });
+}
+
+async fn roll_all(
+    roller: &ComplicationRoller,
+    pool: &PgPool,
+    ws_manager: Option<&he_websocket::ConnectionManager>,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let window = now.timestamp() / ROLL_INTERVAL.as_secs() as i64;
+    let mut after_pid = 0;
+
+    loop {
+        let candidates =
+            ProcessQueries::complication_candidates(pool, roller.config.min_process_seconds, after_pid, ROLL_BATCH)
+                .await?;
+        let last_page = (candidates.len() as i64) < ROLL_BATCH;
+        if let Some(last) = candidates.last() {
+            after_pid = last.pid;
+        }
+
+        for process in candidates {
+            let Some(kind) = roller.roll(&process, window) else {
+                continue;
+            };
+
+            let new_end_time = complications::apply(kind, now, process.end_time, &roller.config);
+            if !ProcessQueries::apply_complication(pool, &process, kind.as_str(), new_end_time).await? {
+                continue;
+            }
+
+            tracing::info!("Process {} of user {} hit {}", process.pid, process.user_id, kind.as_str());
+            if let Some(end_time) = new_end_time {
+                crate::completion::reschedule(process.pid, end_time);
+            }
+            notify(ws_manager, &process, kind, new_end_time);
+        }
+
+        if last_page {
+            return Ok(());
+        }
+    }
+}
+
+fn notify(
+    ws_manager: Option<&he_websocket::ConnectionManager>,
+    process: &Process,
+    kind: ComplicationKind,
+    new_end_time: Option<chrono::DateTime<Utc>>,
+) {
+    let Some(ws_manager) = ws_manager else {
+        return;
+    };
+
+    let remaining_time = new_end_time.map(|end_time| (end_time - Utc::now()).num_seconds().max(0) as u64);
+    let event = he_websocket::EventBuilder::process_complication(
+        process.pid,
+        process.process_type.clone(),
+        kind.as_str().to_string(),
+        kind.message().to_string(),
+        remaining_time,
+    );
+    ws_manager.send_to_entity(he_websocket::Entity::Process(process.pid), event.to_server_message());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolls_agree_across_nodes() {
+        let a = ComplicationRoller::new(b"secret".to_vec(), ComplicationConfig::default());
+        let b = ComplicationRoller::new(b"secret".to_vec(), ComplicationConfig::default());
+        let other = ComplicationRoller::new(b"other".to_vec(), ComplicationConfig::default());
+
+        let samples: Vec<f64> = (0..1000).map(|window| a.sample(42, window)).collect();
+        assert!(samples.iter().all(|s| (0.0..1.0).contains(s)));
+        assert_eq!(samples[7], b.sample(42, 7));
+        assert_ne!(samples[7], other.sample(42, 7));
+        assert_ne!(samples[7], a.sample(43, 7));
+
+        // Roughly uniform
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!((mean - 0.5).abs() < 0.05);
+    }
+}
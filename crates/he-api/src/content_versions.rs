@@ -0,0 +1,43 @@
+//! Postgres storage for content versions
+//!
+//! Used instead of Redis when no cache is configured. Versions kept in one
+//! node's memory would give each node its own ETags and miss bumps made on
+//! the others, so every node reads and bumps the same rows.
+
+use async_trait::async_trait;
+use he_cache::versions::{ContentVersion, VersionStore};
+use he_cache::CacheError;
+use he_database::queries::{ContentVersionQueries, ContentVersionRow};
+use sqlx::PgPool;
+
+pub struct PgVersions {
+    pool: PgPool,
+}
+
+impl PgVersions {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn unavailable(e: anyhow::Error) -> CacheError {
+    tracing::error!("Content version query failed: {}", e);
+    CacheError::Unavailable
+}
+
+fn version_from_row(row: ContentVersionRow) -> ContentVersion {
+    ContentVersion::new(row.version.max(0) as u64, row.modified_at)
+}
+
+#[async_trait]
+impl VersionStore for PgVersions {
+    async fn current(&self, key: &str) -> Result<ContentVersion, CacheError> {
+        let row = ContentVersionQueries::current(&self.pool, key).await.map_err(unavailable)?;
+        Ok(version_from_row(row))
+    }
+
+    async fn bump(&self, key: &str) -> Result<ContentVersion, CacheError> {
+        let row = ContentVersionQueries::bump(&self.pool, key).await.map_err(unavailable)?;
+        Ok(version_from_row(row))
+    }
+}
@@ -0,0 +1,417 @@
+//! Player contracts board
+//!
+//! Posting takes the reward into escrow plus a posting fee that is never
+//! returned. A contract is fulfilled inside the completion transaction of
+//! the contractor's own download or delete of the named file, so the proof is
+//! the transfer the server performed, not anything the client reports. The
+//! reward then waits out the dispute window: the poster can send it to the
+//! moderators' queue, otherwise a sweeper pays the contractor. The same
+//! sweeper reopens contracts whose contractor ran out of time and refunds
+//! those nobody fulfilled. A contract stays unsettled, its reward in escrow,
+//! until the payee has a bank account to receive it.
+//!
+//! Notifications are queued in the outbox by the transaction that makes each
+//! change.
+
+use he_database::models::Process;
+use he_database::queries::{ContractPostingState, ContractQueries, ContractRow, NewContract};
+use he_game_mechanics::contracts::{
+    self, AcceptDenied, ContractKind, ContractStatus, DisputeDenied, PostDenied, Resolution,
+};
+use he_game_mechanics::process::ProcessType;
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
+use crate::outbox::{self, OutboxMessage};
+
+/// How often windows are checked for payouts, lapses and expiry
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+const SWEEP_BATCH: i64 = 200;
+
+/// What happened to a contract, as its parties are told
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ContractEventKind {
+    Accepted,
+    Fulfilled,
+    Disputed,
+    Paid { paid: bool },
+    Refunded { refunded: bool },
+    /// The contractor ran out of time and the contract is open again
+    Reopened { contractor_id: i64 },
+    Expired { refunded: bool },
+}
+
+impl ContractEventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ContractEventKind::Accepted => "accepted",
+            ContractEventKind::Fulfilled => "fulfilled",
+            ContractEventKind::Disputed => "disputed",
+            ContractEventKind::Paid { .. } => "paid",
+            ContractEventKind::Refunded { .. } => "refunded",
+            ContractEventKind::Reopened { .. } => "reopened",
+            ContractEventKind::Expired { .. } => "expired",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractEvent {
+    pub contract_id: i64,
+    pub poster_id: i64,
+    pub contractor_id: Option<i64>,
+    pub status: String,
+    pub reward: i64,
+    pub file_name: String,
+    #[serde(flatten)]
+    pub kind: ContractEventKind,
+}
+
+impl ContractEvent {
+    fn new(contract: &ContractRow, kind: ContractEventKind) -> Self {
+        Self {
+            contract_id: contract.id,
+            poster_id: contract.poster_id,
+            contractor_id: contract.contractor_id,
+            status: contract.status.clone(),
+            reward: contract.reward,
+            file_name: contract.file_name.clone(),
+            kind,
+        }
+    }
+
+    /// Who hears about the event; nobody is told about their own action
+    fn recipients(&self) -> Vec<i64> {
+        match &self.kind {
+            ContractEventKind::Accepted => vec![self.poster_id],
+            ContractEventKind::Disputed => self.contractor_id.into_iter().collect(),
+            ContractEventKind::Reopened { contractor_id } => vec![self.poster_id, *contractor_id],
+            ContractEventKind::Fulfilled
+            | ContractEventKind::Paid { .. }
+            | ContractEventKind::Refunded { .. }
+            | ContractEventKind::Expired { .. } => {
+                std::iter::once(self.poster_id).chain(self.contractor_id).collect()
+            }
+        }
+    }
+
+    /// One socket push per recipient. `origin` names what caused the event,
+    /// e.g. the fulfilling process, so the same change never queues it twice.
+    pub(crate) fn outbox_messages(&self, origin: &str) -> Vec<OutboxMessage> {
+        let event = he_websocket::GameEvent::Custom {
+            event_name: "contract".to_string(),
+            payload: serde_json::to_value(self).unwrap_or_default(),
+        };
+        self.recipients()
+            .into_iter()
+            .map(|user_id| {
+                let key = format!("contract:{}:{}:{}:{}", self.contract_id, self.kind.name(), origin, user_id);
+                OutboxMessage::to_user(key, user_id, &event)
+            })
+            .collect()
+    }
+}
+
+/// A contract as a player asks to post it
+#[derive(Debug, Clone)]
+pub struct PostContract {
+    pub kind: ContractKind,
+    pub target_ip: String,
+    pub target_file_id: String,
+    pub reward: i64,
+    pub window_hours: Option<i64>,
+    pub description: Option<String>,
+}
+
+/// Check, escrow and put up a contract
+pub async fn post(pool: &PgPool, poster_id: i64, request: &PostContract) -> anyhow::Result<Result<ContractRow, PostDenied>> {
+    let config = &crate::balance::current().contracts;
+    let window_hours = request.window_hours.unwrap_or(config.default_window_hours);
+
+    let check = |state: ContractPostingState| {
+        contracts::check_post(
+            state.level,
+            request.reward,
+            window_hours,
+            state.open_posted as usize,
+            state.balance,
+            config,
+        )
+    };
+
+    if let Err(denied) = check(ContractQueries::posting_state(pool, poster_id).await?) {
+        return Ok(Err(denied));
+    }
+
+    let Some(target) = ContractQueries::target(pool, &request.target_ip, &request.target_file_id).await? else {
+        return Ok(Err(PostDenied::FileNotFound));
+    };
+    if target.owner_id == poster_id {
+        return Ok(Err(PostDenied::OwnFile));
+    }
+
+    let contract = NewContract {
+        poster_id,
+        kind: request.kind.as_str(),
+        target_ip: &request.target_ip,
+        target_owner_id: target.owner_id,
+        target_file_id: &request.target_file_id,
+        file_name: &target.file_name,
+        description: request.description.as_deref(),
+        reward: request.reward,
+        fee: contracts::posting_fee(request.reward, config),
+        window_hours,
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    match ContractQueries::post(&mut *tx, &contract, config.max_open_per_poster as i64).await? {
+        Some(posted) => {
+            tx.commit().await?;
+            Ok(Ok(posted))
+        }
+        None => {
+            tx.rollback().await?;
+            // Lost a race with another post or a payment; say why now
+            let state = ContractQueries::posting_state(pool, poster_id).await?;
+            Ok(Err(check(state).err().unwrap_or(PostDenied::InsufficientFunds {
+                needed: contract.reward + contract.fee,
+                balance: 0,
+            })))
+        }
+    }
+}
+
+/// Take an open contract
+pub async fn accept(pool: &PgPool, contract_id: i64, contractor_id: i64) -> anyhow::Result<Result<ContractRow, AcceptDenied>> {
+    let config = &crate::balance::current().contracts;
+    let mut tx = he_database::tagging::begin(pool).await?;
+
+    let Some(contract) = ContractQueries::lock(&mut *tx, contract_id).await? else {
+        tx.rollback().await?;
+        return Ok(Err(AcceptDenied::NotFound));
+    };
+    let accepted = ContractQueries::accepted_count(&mut *tx, contractor_id).await?;
+    let status = ContractStatus::from_str(&contract.status).unwrap_or(ContractStatus::Cancelled);
+    if let Err(denied) = contracts::check_accept(
+        status,
+        contract.poster_id,
+        contract.target_owner_id,
+        contractor_id,
+        accepted as usize,
+        config,
+    ) {
+        tx.rollback().await?;
+        return Ok(Err(denied));
+    }
+
+    let contract = ContractQueries::accept(&mut *tx, contract_id, contractor_id, config.work_hours).await?;
+    let event = ContractEvent::new(&contract, ContractEventKind::Accepted);
+    // The same player may take a contract again after it lapsed
+    let origin = format!("user:{}:{}", contractor_id, contract.work_deadline.map_or(0, |deadline| deadline.timestamp()));
+    outbox::enqueue(&mut *tx, &event.outbox_messages(&origin)).await?;
+    tx.commit().await?;
+    outbox::wake();
+
+    Ok(Ok(contract))
+}
+
+/// Take down an open contract; the reward is refunded, the fee is not
+pub async fn cancel(pool: &PgPool, contract_id: i64, poster_id: i64) -> anyhow::Result<Option<ContractRow>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some((contract, refunded)) = ContractQueries::cancel(&mut *tx, contract_id, poster_id).await? else {
+        tx.rollback().await?;
+        return Ok(None);
+    };
+    if !refunded {
+        tracing::warn!("Contract {} cancelled but its poster has no bank account; refund held", contract.id);
+    }
+    tx.commit().await?;
+    Ok(Some(contract))
+}
+
+/// Hold a fulfilled contract's reward for the moderators
+pub async fn dispute(
+    pool: &PgPool,
+    contract_id: i64,
+    user_id: i64,
+    reason: &str,
+) -> anyhow::Result<Result<ContractRow, DisputeDenied>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+
+    let Some(contract) = ContractQueries::lock(&mut *tx, contract_id).await? else {
+        tx.rollback().await?;
+        return Ok(Err(DisputeDenied::NotFound));
+    };
+    let status = ContractStatus::from_str(&contract.status).unwrap_or(ContractStatus::Cancelled);
+    if let Err(denied) = contracts::check_dispute(
+        status,
+        contract.poster_id,
+        user_id,
+        contract.fulfilled_at,
+        chrono::Utc::now(),
+        &crate::balance::current().contracts,
+    ) {
+        tx.rollback().await?;
+        return Ok(Err(denied));
+    }
+
+    let contract = ContractQueries::dispute(&mut *tx, contract_id, reason).await?;
+    let event = ContractEvent::new(&contract, ContractEventKind::Disputed);
+    outbox::enqueue(&mut *tx, &event.outbox_messages("poster")).await?;
+    tx.commit().await?;
+    outbox::wake();
+
+    Ok(Ok(contract))
+}
+
+/// Settle a disputed contract. `None` if it is not awaiting a moderator.
+pub async fn resolve(
+    pool: &PgPool,
+    contract_id: i64,
+    moderator_id: i64,
+    resolution: Resolution,
+    note: &str,
+) -> anyhow::Result<Option<ContractRow>> {
+    let pay_contractor = resolution == Resolution::PayContractor;
+    let mut tx = he_database::tagging::begin(pool).await?;
+
+    let Some((contract, credited)) =
+        ContractQueries::resolve(&mut *tx, contract_id, moderator_id, pay_contractor, note).await?
+    else {
+        tx.rollback().await?;
+        return Ok(None);
+    };
+
+    let kind = if pay_contractor {
+        ContractEventKind::Paid { paid: credited }
+    } else {
+        ContractEventKind::Refunded { refunded: credited }
+    };
+    let event = ContractEvent::new(&contract, kind);
+    outbox::enqueue(&mut *tx, &event.outbox_messages("resolution")).await?;
+    tx.commit().await?;
+    outbox::wake();
+
+    Ok(Some(contract))
+}
+
+/// Fulfil the contracts the owner of `process` holds on the file it moved or
+/// deleted, in its completion transaction
+pub(crate) async fn fulfil(conn: &mut PgConnection, process: &Process) -> anyhow::Result<Vec<ContractEvent>> {
+    let Some(kind) = ContractKind::proved_by(&ProcessType::from_str(&process.process_type)) else {
+        return Ok(Vec::new());
+    };
+    let (Some(target_ip), Some(file_id)) = (process.target_pc_id.as_deref(), process.target_file_id.as_deref()) else {
+        return Ok(Vec::new());
+    };
+
+    let fulfilled = ContractQueries::fulfil(
+        conn,
+        process.user_id,
+        kind.as_str(),
+        target_ip,
+        file_id,
+        process.pid,
+        crate::balance::current().contracts.dispute_window_hours,
+    )
+    .await?;
+
+    Ok(fulfilled
+        .iter()
+        .map(|contract| ContractEvent::new(contract, ContractEventKind::Fulfilled))
+        .collect())
+}
+
+/// Pay out, reopen and expire contracts every [`SWEEP_INTERVAL`]
+pub(crate) fn spawn_sweeper(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep(&pool).await {
+                tracing::warn!("Contract sweep failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn sweep(pool: &PgPool) -> anyhow::Result<()> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let mut events = Vec::new();
+
+    for (contract, paid) in ContractQueries::pay_due(&mut *tx, SWEEP_BATCH).await? {
+        if !paid {
+            tracing::warn!("Contract {} is due but its contractor has no bank account; payout held", contract.id);
+        }
+        events.push(ContractEvent::new(&contract, ContractEventKind::Paid { paid }));
+    }
+    for (contract, contractor_id) in ContractQueries::reopen_lapsed(&mut *tx, SWEEP_BATCH).await? {
+        events.push(ContractEvent::new(&contract, ContractEventKind::Reopened { contractor_id }));
+    }
+    for (contract, refunded) in ContractQueries::expire_due(&mut *tx, SWEEP_BATCH).await? {
+        if !refunded {
+            tracing::warn!("Contract {} expired but its poster has no bank account; refund held", contract.id);
+        }
+        events.push(ContractEvent::new(&contract, ContractEventKind::Expired { refunded }));
+    }
+    let mut held = Vec::new();
+    for (contract, to_contractor) in ContractQueries::settle_held(&mut *tx, SWEEP_BATCH).await? {
+        let kind = if to_contractor {
+            ContractEventKind::Paid { paid: true }
+        } else {
+            ContractEventKind::Refunded { refunded: true }
+        };
+        held.push(ContractEvent::new(&contract, kind));
+    }
+
+    if events.is_empty() && held.is_empty() {
+        tx.rollback().await?;
+        return Ok(());
+    }
+
+    // A contract can lapse more than once, so reopenings are keyed by when
+    let swept = chrono::Utc::now().timestamp();
+    let messages: Vec<OutboxMessage> = events
+        .iter()
+        .flat_map(|event| match &event.kind {
+            ContractEventKind::Reopened { contractor_id } => {
+                event.outbox_messages(&format!("lapse:{}:{}", contractor_id, swept))
+            }
+            _ => event.outbox_messages("sweep"),
+        })
+        .chain(held.iter().flat_map(|event| event.outbox_messages("held")))
+        .collect();
+    outbox::enqueue(&mut *tx, &messages).await?;
+    tx.commit().await?;
+    outbox::wake();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_recipients() {
+        let event = ContractEvent {
+            contract_id: 5,
+            poster_id: 10,
+            contractor_id: Some(30),
+            status: "fulfilled".to_string(),
+            reward: 100_000,
+            file_name: "payroll.db".to_string(),
+            kind: ContractEventKind::Fulfilled,
+        };
+        assert_eq!(event.recipients(), vec![10, 30]);
+
+        let messages = event.outbox_messages("process:77");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].dedup_key, "contract:5:fulfilled:process:77:30");
+
+        let disputed = ContractEvent { kind: ContractEventKind::Disputed, ..event.clone() };
+        assert_eq!(disputed.recipients(), vec![30]);
+        let expired = ContractEvent { contractor_id: None, kind: ContractEventKind::Expired { refunded: true }, ..event };
+        assert_eq!(expired.recipients(), vec![10]);
+    }
+}
@@ -0,0 +1,579 @@
+//! Clan co-op missions
+//!
+//! Templates with a `coop_max_members` limit can be accepted as a group by a
+//! clan member. Clanmates join while the mission is recruiting and the leader
+//! starts it once enough are in. Every member's completed processes of the
+//! mission's objective type add up to one shared total (see
+//! [`missions::coop_progress`]); each member's part is kept, and the reward is
+//! split by [`missions::split_coop_reward`] when the total is reached. A member
+//! abandoning a running mission fails it if too few are left.
+//!
+//! State changes happen in the request's transaction, or for progress in the
+//! process completion's, which queues its events in the outbox. What follows
+//! from them, the announcements in the mission's chat thread and the pushes to
+//! the clan's sockets, is published as he-events events and handled by the
+//! coordinator, so nothing slow runs while a mission row is locked.
+
+use async_trait::async_trait;
+use he_core::{HelixError, HelixResult};
+use he_database::models::Process;
+use he_database::queries::{
+    BankQueries, ChatThreadMessage, CoopMemberRow, CoopMissionQueries, CoopMissionRow, EntityAccessQueries,
+    LedgerAccount, LedgerReason, ProgressionQueries,
+};
+use he_events::{DispatchConfig, Event, EventData, EventDispatcher, EventHandler, EventSchema, EventType};
+use he_game_mechanics::missions::{self, CoopAbandonOutcome, CoopMember, CoopShare};
+use he_game_mechanics::process::ProcessType;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use crate::event_schemas;
+use crate::outbox::OutboxMessage;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+/// `data_type` of the he-events payloads published here
+pub(crate) const DATA_TYPE: &str = "coop_mission";
+
+static DISPATCHER: OnceCell<EventDispatcher> = OnceCell::const_new();
+
+/// Why a co-op action was refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum CoopDenied {
+    NotFound,
+    /// The template cannot be played as a group
+    NotCoop,
+    NoClan,
+    NotInClan,
+    AlreadyInMission,
+    /// Members who abandoned cannot rejoin
+    AlreadyLeft,
+    Full { max_members: i32 },
+    NotRecruiting,
+    NotRunning,
+    NotMember,
+    NotLeader,
+    TooFewMembers { min: usize },
+    ThreadClosed,
+}
+
+impl CoopDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            CoopDenied::NotFound => "Co-op mission not found".to_string(),
+            CoopDenied::NotCoop => "This mission cannot be played co-op".to_string(),
+            CoopDenied::NoClan => "You need to be in a clan to start a co-op mission".to_string(),
+            CoopDenied::NotInClan => "This co-op mission belongs to another clan".to_string(),
+            CoopDenied::AlreadyInMission => "You are already in a co-op mission".to_string(),
+            CoopDenied::AlreadyLeft => "You abandoned this mission".to_string(),
+            CoopDenied::Full { max_members } => format!("This mission takes at most {} members", max_members),
+            CoopDenied::NotRecruiting => "This mission is no longer taking members".to_string(),
+            CoopDenied::NotRunning => "This mission is not running".to_string(),
+            CoopDenied::NotMember => "You are not in this mission".to_string(),
+            CoopDenied::NotLeader => "Only the leader can start the mission".to_string(),
+            CoopDenied::TooFewMembers { min } => format!("At least {} members are needed", min),
+            CoopDenied::ThreadClosed => "The mission chat is closed".to_string(),
+        }
+    }
+}
+
+/// Something that happened to a co-op mission
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoopEvent {
+    pub coop_id: i64,
+    pub clan_id: i64,
+    pub thread_id: i64,
+    #[serde(flatten)]
+    pub kind: CoopEventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CoopEventKind {
+    Accepted { leader_id: i64, mission: String },
+    Joined { user_id: i64 },
+    Started { members: Vec<i64> },
+    Progress { user_id: i64, amount: i32, progress: i32, target: i32 },
+    Abandoned { user_id: i64 },
+    Message { user_id: i64, body: String },
+    Completed { shares: Vec<CoopShare> },
+    Failed { reason: String },
+}
+
+impl CoopEventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            CoopEventKind::Accepted { .. } => "accepted",
+            CoopEventKind::Joined { .. } => "joined",
+            CoopEventKind::Started { .. } => "started",
+            CoopEventKind::Progress { .. } => "progress",
+            CoopEventKind::Abandoned { .. } => "abandoned",
+            CoopEventKind::Message { .. } => "message",
+            CoopEventKind::Completed { .. } => "completed",
+            CoopEventKind::Failed { .. } => "failed",
+        }
+    }
+
+    fn event_type(&self) -> EventType {
+        match self {
+            CoopEventKind::Started { .. } => EventType::MissionStarted,
+            CoopEventKind::Completed { .. } => EventType::MissionCompleted,
+            CoopEventKind::Failed { .. } => EventType::MissionFailed,
+            CoopEventKind::Accepted { .. } => EventType::Custom("coop_mission_accepted".to_string()),
+            CoopEventKind::Joined { .. } => EventType::Custom("coop_mission_joined".to_string()),
+            CoopEventKind::Progress { .. } => EventType::Custom("coop_mission_progress".to_string()),
+            CoopEventKind::Abandoned { .. } => EventType::Custom("coop_mission_abandoned".to_string()),
+            CoopEventKind::Message { .. } => EventType::Custom("coop_mission_message".to_string()),
+        }
+    }
+
+    /// Every event type the coordinator listens to
+    pub(crate) fn event_types() -> Vec<EventType> {
+        [
+            "coop_mission_accepted",
+            "coop_mission_joined",
+            "coop_mission_progress",
+            "coop_mission_abandoned",
+            "coop_mission_message",
+        ]
+        .into_iter()
+        .map(|name| EventType::Custom(name.to_string()))
+        .chain([EventType::MissionStarted, EventType::MissionCompleted, EventType::MissionFailed])
+        .collect()
+    }
+}
+
+impl EventSchema for CoopEvent {
+    const VERSION: u32 = 1;
+
+    fn fixtures() -> Vec<(u32, serde_json::Value)> {
+        vec![(1, serde_json::json!({
+            "coop_id": 7,
+            "clan_id": 3,
+            "thread_id": 12,
+            "event": "progress",
+            "user_id": 42,
+            "amount": 1,
+            "progress": 4,
+            "target": 10
+        }))]
+    }
+}
+
+impl CoopEvent {
+    fn new(coop: &CoopMissionRow, kind: CoopEventKind) -> Self {
+        Self {
+            coop_id: coop.id,
+            clan_id: coop.clan_id,
+            thread_id: coop.thread_id,
+            kind,
+        }
+    }
+
+    /// What the game posts in the mission's chat thread
+    fn announcement(&self) -> Option<String> {
+        Some(match &self.kind {
+            CoopEventKind::Accepted { leader_id, mission } => {
+                format!("Player {} opened a co-op run of {}", leader_id, mission)
+            }
+            CoopEventKind::Joined { user_id } => format!("Player {} joined", user_id),
+            CoopEventKind::Started { members } => format!("Mission started with {} members", members.len()),
+            CoopEventKind::Abandoned { user_id } => format!("Player {} abandoned the mission", user_id),
+            CoopEventKind::Completed { .. } => "Mission complete, rewards have been paid out".to_string(),
+            CoopEventKind::Failed { reason } => format!("Mission failed: {}", reason),
+            CoopEventKind::Progress { .. } | CoopEventKind::Message { .. } => return None,
+        })
+    }
+
+    /// The event queued in the outbox. `origin` names what caused it, e.g.
+    /// the completed process, so the same change never queues it twice.
+    pub(crate) fn to_outbox(&self, origin: &str) -> OutboxMessage {
+        OutboxMessage::event(
+            format!("coop:{}:{}:{}", self.coop_id, self.kind.name(), origin),
+            DATA_TYPE,
+            serde_json::to_value(self).unwrap_or_default(),
+        )
+    }
+
+    fn to_event(&self) -> Event {
+        Event::new(
+            self.kind.event_type(),
+            EventData::Custom {
+                data_type: DATA_TYPE.to_string(),
+                payload: serde_json::to_value(self).unwrap_or_default(),
+            },
+        )
+    }
+}
+
+type CoopResult<T> = anyhow::Result<Result<T, CoopDenied>>;
+
+/// Open a co-op run of `mission_id` for the player's clan, led by them
+pub async fn accept(pool: &PgPool, user_id: i64, mission_id: i64) -> CoopResult<(CoopMissionRow, Vec<CoopEvent>)> {
+    let config = &crate::balance::current().missions;
+    let Some(template) = CoopMissionQueries::template(pool, mission_id).await? else {
+        return Ok(Err(CoopDenied::NotCoop));
+    };
+    let max_members = missions::coop_member_limit(template.max_members, config);
+    if max_members < config.coop_min_members {
+        return Ok(Err(CoopDenied::NotCoop));
+    }
+    let Some(clan_id) = EntityAccessQueries::clans(pool, user_id).await?.into_iter().next() else {
+        return Ok(Err(CoopDenied::NoClan));
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    if CoopMissionQueries::in_running(&mut *tx, user_id).await? {
+        return Ok(Err(CoopDenied::AlreadyInMission));
+    }
+    let coop = CoopMissionQueries::create(&mut *tx, &template, clan_id, user_id, max_members as i32).await?;
+    tx.commit().await?;
+
+    let event = CoopEvent::new(&coop, CoopEventKind::Accepted { leader_id: user_id, mission: template.name });
+    Ok(Ok((coop, vec![event])))
+}
+
+/// Join a recruiting mission of the player's clan
+pub async fn join(pool: &PgPool, user_id: i64, coop_id: i64) -> CoopResult<Vec<CoopEvent>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(coop) = CoopMissionQueries::lock(&mut *tx, coop_id).await? else {
+        return Ok(Err(CoopDenied::NotFound));
+    };
+    if !EntityAccessQueries::is_clan_member(pool, coop.clan_id, user_id).await? {
+        return Ok(Err(CoopDenied::NotInClan));
+    }
+    if coop.status != "recruiting" {
+        return Ok(Err(CoopDenied::NotRecruiting));
+    }
+
+    let members = CoopMissionQueries::members(&mut *tx, coop_id).await?;
+    if members.iter().any(|m| m.user_id == user_id) {
+        return Ok(Err(CoopDenied::AlreadyLeft));
+    }
+    if members.iter().filter(|m| !m.abandoned).count() >= coop.max_members as usize {
+        return Ok(Err(CoopDenied::Full { max_members: coop.max_members }));
+    }
+    if CoopMissionQueries::in_running(&mut *tx, user_id).await? {
+        return Ok(Err(CoopDenied::AlreadyInMission));
+    }
+
+    CoopMissionQueries::add_member(&mut *tx, &coop, user_id).await?;
+    tx.commit().await?;
+
+    Ok(Ok(vec![CoopEvent::new(&coop, CoopEventKind::Joined { user_id })]))
+}
+
+/// Close recruiting and start the mission; leader only
+pub async fn start(pool: &PgPool, user_id: i64, coop_id: i64) -> CoopResult<Vec<CoopEvent>> {
+    let config = &crate::balance::current().missions;
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(coop) = CoopMissionQueries::lock(&mut *tx, coop_id).await? else {
+        return Ok(Err(CoopDenied::NotFound));
+    };
+    if coop.leader_id != user_id {
+        return Ok(Err(CoopDenied::NotLeader));
+    }
+    if coop.status != "recruiting" {
+        return Ok(Err(CoopDenied::NotRecruiting));
+    }
+
+    let members: Vec<i64> = CoopMissionQueries::members(&mut *tx, coop_id)
+        .await?
+        .into_iter()
+        .filter(|m| !m.abandoned)
+        .map(|m| m.user_id)
+        .collect();
+    if members.len() < config.coop_min_members {
+        return Ok(Err(CoopDenied::TooFewMembers { min: config.coop_min_members }));
+    }
+
+    CoopMissionQueries::start(&mut *tx, coop_id).await?;
+    tx.commit().await?;
+
+    Ok(Ok(vec![CoopEvent::new(&coop, CoopEventKind::Started { members })]))
+}
+
+/// Credit a member's completed process to their running mission, completing
+/// it and paying out once the target is reached. Runs in the completion
+/// transaction, so only processes the server finished count, each once, and
+/// only those started after the mission did.
+pub(crate) async fn credit(conn: &mut PgConnection, process: &Process) -> anyhow::Result<Vec<CoopEvent>> {
+    let Some(coop) = CoopMissionQueries::lock_running_for(conn, process.user_id, process.start_time).await? else {
+        return Ok(Vec::new());
+    };
+    let process_type = ProcessType::from_str(&process.process_type);
+    if coop.objective.is_none() && process.target_pc_id.is_none() {
+        return Ok(Vec::new());
+    }
+    let Some(amount) = missions::coop_progress(&process_type, coop.objective.as_deref(), &crate::balance::current().missions)
+        .filter(|amount| *amount > 0)
+    else {
+        return Ok(Vec::new());
+    };
+
+    let user_id = process.user_id;
+    let total = CoopMissionQueries::add_progress(conn, coop.id, user_id, amount).await?;
+    let mut events = vec![CoopEvent::new(
+        &coop,
+        CoopEventKind::Progress { user_id, amount, progress: total, target: coop.target },
+    )];
+
+    if total >= coop.target {
+        let shares = pay_out(conn, &coop).await?;
+        CoopMissionQueries::finish(conn, &coop, "completed", None).await?;
+        events.push(CoopEvent::new(&coop, CoopEventKind::Completed { shares }));
+    }
+
+    Ok(events)
+}
+
+/// Split the reward by contribution and pay every remaining member
+async fn pay_out(conn: &mut PgConnection, coop: &CoopMissionRow) -> anyhow::Result<Vec<CoopShare>> {
+    let members: Vec<CoopMember> = CoopMissionQueries::members(conn, coop.id)
+        .await?
+        .into_iter()
+        .map(|m| CoopMember { user_id: m.user_id, contribution: m.contribution, abandoned: m.abandoned })
+        .collect();
+
+    let mut shares = missions::split_coop_reward(
+        coop.reward_money,
+        crate::rules::current().experience(coop.reward_exp as i64),
+        &members,
+        &crate::balance::current().missions,
+    );
+    for share in &mut shares {
+        let reference = format!("coop_mission:{}", coop.id);
+        let paid = BankQueries::credit_primary_account(
+            conn,
+            share.user_id,
+            share.money,
+            LedgerAccount::Mint,
+            LedgerReason::CoopReward,
+            &reference,
+        )
+        .await?;
+        if !paid {
+            // Nowhere to pay out; the experience is still granted
+            share.money = 0;
+        }
+        if share.experience > 0 {
+            ProgressionQueries::add_experience(conn, share.user_id, share.experience).await?;
+        }
+        CoopMissionQueries::record_share(conn, coop.id, share.user_id, share.money, share.experience).await?;
+    }
+
+    Ok(shares)
+}
+
+/// Leave a mission. The leader leaving before the start, or too few members
+/// remaining once it runs, fails it for everyone.
+pub async fn abandon(pool: &PgPool, user_id: i64, coop_id: i64) -> CoopResult<Vec<CoopEvent>> {
+    let config = &crate::balance::current().missions;
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(coop) = CoopMissionQueries::lock(&mut *tx, coop_id).await? else {
+        return Ok(Err(CoopDenied::NotFound));
+    };
+    if coop.status != "recruiting" && coop.status != "active" {
+        return Ok(Err(CoopDenied::NotRunning));
+    }
+    if !CoopMissionQueries::abandon(&mut *tx, coop_id, user_id).await? {
+        return Ok(Err(CoopDenied::NotMember));
+    }
+
+    let mut events = vec![CoopEvent::new(&coop, CoopEventKind::Abandoned { user_id })];
+
+    let failure = if coop.status == "recruiting" {
+        (coop.leader_id == user_id).then_some("the leader left before the start")
+    } else {
+        let members: Vec<CoopMember> = CoopMissionQueries::members(&mut *tx, coop_id)
+            .await?
+            .into_iter()
+            .map(|m| CoopMember { user_id: m.user_id, contribution: m.contribution, abandoned: m.abandoned })
+            .collect();
+        (missions::coop_abandon_outcome(&members, config) == CoopAbandonOutcome::Fail)
+            .then_some("too few members are left")
+    };
+
+    if let Some(reason) = failure {
+        CoopMissionQueries::finish(&mut *tx, &coop, "failed", Some(reason)).await?;
+        events.push(CoopEvent::new(&coop, CoopEventKind::Failed { reason: reason.to_string() }));
+    }
+
+    tx.commit().await?;
+    Ok(Ok(events))
+}
+
+/// A mission and its members' contributions, for members of its clan
+pub async fn view(pool: &PgPool, user_id: i64, coop_id: i64) -> CoopResult<(CoopMissionRow, Vec<CoopMemberRow>)> {
+    let Some(coop) = CoopMissionQueries::get(pool, coop_id).await? else {
+        return Ok(Err(CoopDenied::NotFound));
+    };
+    if !EntityAccessQueries::is_clan_member(pool, coop.clan_id, user_id).await? {
+        return Ok(Err(CoopDenied::NotInClan));
+    }
+
+    let mut conn = pool.acquire().await?;
+    let members = CoopMissionQueries::members(&mut *conn, coop_id).await?;
+    Ok(Ok((coop, members)))
+}
+
+/// Messages in the mission's chat thread after `after_id`
+pub async fn messages(
+    pool: &PgPool,
+    user_id: i64,
+    coop_id: i64,
+    after_id: i64,
+    limit: i64,
+) -> CoopResult<Vec<ChatThreadMessage>> {
+    let Some(coop) = CoopMissionQueries::get(pool, coop_id).await? else {
+        return Ok(Err(CoopDenied::NotFound));
+    };
+    if !CoopMissionQueries::is_thread_member(pool, coop.thread_id, user_id).await? {
+        return Ok(Err(CoopDenied::NotMember));
+    }
+
+    Ok(Ok(CoopMissionQueries::messages(pool, coop.thread_id, after_id, limit).await?))
+}
+
+/// Post a player's message in the mission's chat thread
+pub async fn post_message(pool: &PgPool, user_id: i64, coop_id: i64, body: &str) -> CoopResult<Vec<CoopEvent>> {
+    let Some(coop) = CoopMissionQueries::get(pool, coop_id).await? else {
+        return Ok(Err(CoopDenied::NotFound));
+    };
+    if !CoopMissionQueries::is_thread_member(pool, coop.thread_id, user_id).await? {
+        return Ok(Err(CoopDenied::NotMember));
+    }
+    let Some(message_id) = CoopMissionQueries::post_message(pool, coop.thread_id, Some(user_id), body).await? else {
+        return Ok(Err(CoopDenied::ThreadClosed));
+    };
+    if let Err(e) = crate::chat_filter::screen(pool, user_id, message_id, body).await {
+        tracing::warn!("Failed to screen chat message {}: {}", message_id, e);
+    }
+
+    Ok(Ok(vec![CoopEvent::new(&coop, CoopEventKind::Message { user_id, body: body.to_string() })]))
+}
+
+/// Publish events for the coordinator. Failing to publish never undoes the
+/// change that produced them.
+pub async fn publish(
+    pool: &PgPool,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+    events: Vec<CoopEvent>,
+) {
+    if let Err(e) = dispatch(pool, ws_manager, events).await {
+        tracing::warn!("Failed to publish co-op events: {}", e);
+    }
+}
+
+/// Hand events to the coordinator; the outbox relay delivers queued events
+/// through here and retries on an error
+pub(crate) async fn dispatch(
+    pool: &PgPool,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+    events: Vec<CoopEvent>,
+) -> anyhow::Result<()> {
+    let dispatcher = dispatcher(pool, ws_manager)
+        .await
+        .map_err(|e| anyhow::anyhow!("co-op event dispatcher unavailable: {}", e))?;
+
+    for event in events {
+        let mut published = event.to_event();
+        if let Err(e) = event_schemas::registry().validate(&mut published) {
+            tracing::error!("Not publishing invalid co-op event for mission {}: {}", event.coop_id, e);
+            continue;
+        }
+        dispatcher
+            .dispatch(published)
+            .await
+            .map_err(|e| anyhow::anyhow!("co-op event for mission {}: {}", event.coop_id, e))?;
+    }
+    Ok(())
+}
+
+/// The dispatcher, started with the coordinator on first use
+async fn dispatcher(
+    pool: &PgPool,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+) -> HelixResult<&'static EventDispatcher> {
+    DISPATCHER
+        .get_or_try_init(|| async {
+            let dispatcher = EventDispatcher::new(DispatchConfig::default()).await?;
+            let coordinator = Arc::new(CoopCoordinator { pool: pool.clone(), ws_manager });
+            for event_type in CoopEventKind::event_types() {
+                dispatcher.add_handler(event_type, coordinator.clone()).await;
+            }
+            dispatcher.start().await?;
+            Ok(dispatcher)
+        })
+        .await
+}
+
+/// Announces co-op events in the mission's chat thread and pushes them to
+/// the clan's sockets
+struct CoopCoordinator {
+    pool: PgPool,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+}
+
+#[async_trait]
+impl EventHandler for CoopCoordinator {
+    async fn handle(&self, event: &Event) -> HelixResult<()> {
+        let EventData::Custom { data_type, payload } = &event.data else {
+            return Ok(());
+        };
+        if data_type != DATA_TYPE {
+            return Ok(());
+        }
+        let coop_event: CoopEvent = event_schemas::registry()
+            .decode(event)
+            .map_err(|e| HelixError::internal(format!("Malformed co-op event: {}", e)))?;
+
+        if let Some(text) = coop_event.announcement() {
+            CoopMissionQueries::post_message(&self.pool, coop_event.thread_id, None, &text)
+                .await
+                .map_err(|e| HelixError::internal(format!("Failed to announce in thread {}: {}", coop_event.thread_id, e)))?;
+        }
+
+        if let Some(ws_manager) = &self.ws_manager {
+            let message = he_websocket::GameEvent::Custom {
+                event_name: "coop_mission".to_string(),
+                payload: payload.clone(),
+            };
+            ws_manager.send_to_entity(he_websocket::Entity::Clan(coop_event.clan_id), message.to_server_message());
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "CoopCoordinator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_round_trips_through_payload() {
+        let event = CoopEvent {
+            coop_id: 7,
+            clan_id: 3,
+            thread_id: 11,
+            kind: CoopEventKind::Failed { reason: "too few members are left".to_string() },
+        };
+
+        let published = event.to_event();
+        assert_eq!(published.event_type, EventType::MissionFailed);
+        let EventData::Custom { data_type, payload } = published.data else {
+            panic!("co-op events are custom payloads");
+        };
+        assert_eq!(data_type, DATA_TYPE);
+
+        let received: CoopEvent = serde_json::from_value(payload).unwrap();
+        assert_eq!(received.clan_id, 3);
+        assert_eq!(received.announcement().unwrap(), "Mission failed: too few members are left");
+        assert!(CoopEventKind::event_types().contains(&EventType::Custom("coop_mission_joined".to_string())));
+    }
+}
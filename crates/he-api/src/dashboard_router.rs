@@ -7,8 +7,14 @@ use actix_web::{web, HttpResponse, Responder, HttpRequest};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use crate::AppState;
+use crate::cache_warm::{self, CacheWarm};
+use crate::http_cache::{CachePolicy, ConditionalGet};
+use he_cache::CacheKeys;
 use tera::Context;
 
+/// Rankings are recomputed elsewhere, so the top list is only cached briefly
+const LEADERBOARD_MAX_AGE_SECONDS: u32 = 60;
+
 /// Dashboard API routes structure
 pub struct DashboardRouter;
 
@@ -27,7 +33,11 @@ impl DashboardRouter {
                     .route("/processes", web::get().to(get_processes))
                     .route("/hardware", web::get().to(get_hardware))
                     .route("/news", web::get().to(get_news))
-                    .route("/ranking/top", web::get().to(get_top_users))
+                    .service(
+                        web::resource("/ranking/top")
+                            .wrap(ConditionalGet::new(CachePolicy::public(LEADERBOARD_MAX_AGE_SECONDS)))
+                            .route(web::get().to(get_top_users))
+                    )
                     .route("/logs/recent", web::get().to(get_recent_logs))
                     .route("/missions/active", web::get().to(get_active_missions))
                     .route("/mail/unread", web::get().to(get_unread_mail))
@@ -185,28 +195,31 @@ async fn get_news(
     HttpResponse::Ok().json(result)
 }
 
-/// Get top users for ranking
+/// Get top users for ranking, read through the warmed top-players board
 async fn get_top_users(
     data: web::Data<AppState>,
+    cache_warm: web::Data<CacheWarm>,
 ) -> impl Responder {
-    let users = sqlx::query!(
-        r#"
-        SELECT username, reputation
-        FROM users
-        ORDER BY reputation DESC
-        LIMIT 10
-        "#
-    )
-    .fetch_all(&data.pool)
-    .await
-    .unwrap_or_default();
+    let key = CacheKeys::leaderboard("players");
+    let board = cache_warm::read_through(cache_warm.cache(), &key, || cache_warm::top_players(&data.pool))
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to load the top players: {}", e);
+            json!([])
+        });
 
-    let result: Vec<_> = users.iter().map(|u| {
-        json!({
-            "username": u.username,
-            "reputation": u.reputation
+    let result: Vec<_> = board
+        .as_array()
+        .into_iter()
+        .flatten()
+        .take(10)
+        .map(|player| {
+            json!({
+                "username": player["username"],
+                "reputation": player["reputation"]
+            })
         })
-    }).collect();
+        .collect();
 
     HttpResponse::Ok().json(result)
 }
@@ -0,0 +1,231 @@
+//! Whois registry
+//!
+//! Hostname resolution for the browser and the terminal, reverse lookups for
+//! players with a log analyzer, and player hostname registration. Rules are
+//! in [`he_game_mechanics::dns`]. Every lookup and registration that reaches
+//! the registry is logged on the registry server, where a player who breaks
+//! in can read who was looking for whom.
+
+use he_database::queries::{BankQueries, ClanServerQueries, DnsQueries, DnsRecordRow, LedgerAccount, LedgerReason};
+use he_game_mechanics::dns::{self, DnsDenied, Query, NSLOOKUP_COMMAND, WHOIS_COMMAND};
+use he_game_mechanics::terminal_grammar::ParsedCommand;
+use serde::Serialize;
+use sqlx::PgPool;
+
+type DnsResult<T> = anyhow::Result<Result<T, DnsDenied>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Resolution {
+    pub hostname: String,
+    pub ip_address: String,
+}
+
+/// The player's hostnames with what another one costs
+#[derive(Debug, Serialize)]
+pub struct Hostnames {
+    pub hostnames: Vec<DnsRecordRow>,
+    pub max_names: usize,
+    pub registration_fee: i64,
+}
+
+/// A lookup as the terminal answers it
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Lookup {
+    Forward(Resolution),
+    Reverse { ip_address: String, hostnames: Vec<String> },
+}
+
+/// The address `name` points at
+pub async fn resolve(pool: &PgPool, user_id: i64, name: &str) -> DnsResult<Resolution> {
+    let config = &crate::balance::current().dns;
+    let hostname = match dns::normalize(name, config) {
+        Ok(hostname) => hostname,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    DnsQueries::log_lookup(pool, &config.registry_ip, user_id, &format!("Lookup of {}", hostname)).await?;
+    Ok(match DnsQueries::resolve(pool, &hostname).await? {
+        Some(record) => Ok(Resolution { hostname: record.hostname, ip_address: record.ip_address }),
+        None => Err(DnsDenied::NotFound),
+    })
+}
+
+/// The hostnames pointing at `ip`, for players with a log analyzer
+pub async fn reverse(pool: &PgPool, user_id: i64, ip: &str) -> DnsResult<Vec<String>> {
+    let config = &crate::balance::current().dns;
+    let ip = match dns::parse_query(ip, config) {
+        Ok(Query::Address(ip)) => ip,
+        Ok(Query::Hostname(_)) => return Ok(Err(DnsDenied::InvalidHostname)),
+        Err(denied) => return Ok(Err(denied)),
+    };
+    let mut conn = pool.acquire().await?;
+    let analyzer = ClanServerQueries::best_software(&mut *conn, user_id, &config.reverse_lookup_software).await?;
+    drop(conn);
+    if let Err(denied) = dns::can_reverse(analyzer, config) {
+        return Ok(Err(denied));
+    }
+
+    DnsQueries::log_lookup(pool, &config.registry_ip, user_id, &format!("Reverse lookup of {}", ip)).await?;
+    let hostnames = DnsQueries::reverse(pool, &ip).await?;
+    if hostnames.is_empty() {
+        return Ok(Err(DnsDenied::NotFound));
+    }
+    Ok(Ok(hostnames))
+}
+
+/// Who registered `name`, and when
+pub async fn whois(pool: &PgPool, user_id: i64, name: &str) -> DnsResult<DnsRecordRow> {
+    let config = &crate::balance::current().dns;
+    let hostname = match dns::normalize(name, config) {
+        Ok(hostname) => hostname,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    DnsQueries::log_lookup(pool, &config.registry_ip, user_id, &format!("Whois of {}", hostname)).await?;
+    Ok(DnsQueries::resolve(pool, &hostname).await?.ok_or(DnsDenied::NotFound))
+}
+
+/// Forward or reverse, whichever `query` asks for
+pub async fn lookup(pool: &PgPool, user_id: i64, query: &str) -> DnsResult<Lookup> {
+    Ok(match dns::parse_query(query, &crate::balance::current().dns) {
+        Ok(Query::Hostname(hostname)) => resolve(pool, user_id, &hostname).await?.map(Lookup::Forward),
+        Ok(Query::Address(ip)) => reverse(pool, user_id, &ip)
+            .await?
+            .map(|hostnames| Lookup::Reverse { ip_address: ip, hostnames }),
+        Err(denied) => Err(denied),
+    })
+}
+
+pub async fn hostnames(pool: &PgPool, user_id: i64) -> anyhow::Result<Hostnames> {
+    let config = &crate::balance::current().dns;
+    Ok(Hostnames {
+        hostnames: DnsQueries::owned(pool, user_id).await?,
+        max_names: config.max_names_per_player,
+        registration_fee: config.registration_fee,
+    })
+}
+
+/// Register `name` for the player's main server, paying the fee from their
+/// primary bank account
+pub async fn register(pool: &PgPool, user_id: i64, name: &str) -> DnsResult<DnsRecordRow> {
+    let config = &crate::balance::current().dns;
+    let hostname = match dns::check_registration(name, config) {
+        Ok(hostname) => hostname,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    // Locks the player's bank account, so their registrations are counted
+    // one at a time
+    if BankQueries::primary_account(&mut *tx, user_id).await?.is_none() {
+        return Ok(Err(DnsDenied::InsufficientFunds));
+    }
+    let Some(server_id) = DnsQueries::main_server(&mut *tx, user_id).await? else {
+        return Ok(Err(DnsDenied::NoServer));
+    };
+    if DnsQueries::count_owned(&mut *tx, user_id).await? >= config.max_names_per_player as i64 {
+        return Ok(Err(DnsDenied::TooManyNames { max: config.max_names_per_player }));
+    }
+    if !DnsQueries::register(&mut *tx, &hostname, server_id, user_id, config.registration_fee).await? {
+        return Ok(Err(DnsDenied::HostnameTaken));
+    }
+    let reference = format!("hostname:{}", hostname);
+    let paid = BankQueries::debit_primary_account(
+        &mut *tx,
+        user_id,
+        config.registration_fee,
+        LedgerAccount::Sink,
+        LedgerReason::HostnameRegistration,
+        &reference,
+    )
+    .await?;
+    if !paid {
+        return Ok(Err(DnsDenied::InsufficientFunds));
+    }
+    tx.commit().await?;
+
+    DnsQueries::log_lookup(pool, &config.registry_ip, user_id, &format!("Registration of {}", hostname)).await?;
+    let record = DnsQueries::resolve(pool, &hostname)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Hostname {} vanished after registration", hostname))?;
+    Ok(Ok(record))
+}
+
+/// Give up a hostname the player registered; the fee is not refunded
+pub async fn release(pool: &PgPool, user_id: i64, name: &str) -> DnsResult<()> {
+    let hostname = match dns::normalize(name, &crate::balance::current().dns) {
+        Ok(hostname) => hostname,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    if DnsQueries::release(pool, &hostname, user_id).await? {
+        return Ok(Ok(()));
+    }
+    Ok(Err(match DnsQueries::resolve(pool, &hostname).await? {
+        Some(_) => DnsDenied::NotOwner,
+        None => DnsDenied::NotFound,
+    }))
+}
+
+fn format_lookup(lookup: &Lookup) -> String {
+    match lookup {
+        Lookup::Forward(resolution) => format!("Name:    {}\nAddress: {}", resolution.hostname, resolution.ip_address),
+        Lookup::Reverse { ip_address, hostnames } => {
+            let mut lines = vec![format!("{} is known as:", ip_address)];
+            lines.extend(hostnames.iter().map(|hostname| format!("  {}", hostname)));
+            lines.join("\n")
+        }
+    }
+}
+
+fn format_whois(record: &DnsRecordRow) -> String {
+    [
+        format!("Domain:     {}", record.hostname),
+        format!("Address:    {}", record.ip_address),
+        format!("Registrant: {}", record.owner.as_deref().unwrap_or("First Whois")),
+        format!("Registered: {}", record.registered_at.format("%Y-%m-%d")),
+    ]
+    .join("\n")
+}
+
+/// Run `nslookup` or `whois` for the terminal; `None` for other commands
+pub async fn run_command(pool: &PgPool, user_id: i64, parsed: &ParsedCommand) -> Option<DnsResult<String>> {
+    let command = parsed.path.first()?.as_str();
+    let query = parsed.args.first().map(String::as_str).unwrap_or_default();
+    let output = match command {
+        NSLOOKUP_COMMAND => lookup(pool, user_id, query).await.map(|found| found.map(|lookup| format_lookup(&lookup))),
+        // An address to whois is a reverse lookup
+        WHOIS_COMMAND if matches!(dns::parse_query(query, &crate::balance::current().dns), Ok(Query::Address(_))) => {
+            lookup(pool, user_id, query).await.map(|found| found.map(|lookup| format_lookup(&lookup)))
+        }
+        WHOIS_COMMAND => whois(pool, user_id, query).await.map(|found| found.map(|record| format_whois(&record))),
+        _ => return None,
+    };
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminal_output() {
+        let forward = Lookup::Forward(Resolution { hostname: "first.org".into(), ip_address: "1.2.3.4".into() });
+        assert_eq!(format_lookup(&forward), "Name:    first.org\nAddress: 1.2.3.4");
+        let reverse = Lookup::Reverse { ip_address: "1.2.3.4".into(), hostnames: vec!["a.com".into(), "b.net".into()] };
+        assert_eq!(format_lookup(&reverse), "1.2.3.4 is known as:\n  a.com\n  b.net");
+
+        let record = DnsRecordRow {
+            hostname: "my-box.io".into(),
+            ip_address: "10.0.0.7".into(),
+            server_id: 7,
+            owner_id: Some(3),
+            owner: Some("neo".into()),
+            fee_paid: 25_000,
+            registered_at: "2024-11-09T12:00:00Z".parse().unwrap(),
+        };
+        assert!(format_whois(&record).contains("Registrant: neo"));
+        assert!(format_whois(&record).ends_with("Registered: 2024-11-09"));
+    }
+}
@@ -0,0 +1,375 @@
+//! Doom virus endgame
+//!
+//! A high-level player researches the virus one step at a time, each step
+//! taking its research time, then installs it on one of their servers. That
+//! starts the one world-wide countdown, announced to every node through the
+//! live-ops announcements, and plants clues to the host in NPC logs.
+//! Defenders read the logs of NPCs they have cracked and try to disarm the
+//! host; if the countdown runs out, the instigator wins the round. Every node
+//! ticks the countdown, one at a time, and rewards are held until the winner
+//! has a bank account. Rules are in [`he_game_mechanics::doom`].
+
+use chrono::{DateTime, Duration, Utc};
+use he_database::queries::{
+    ClanServerQueries, DoomCampaignRow, DoomQueries, GeneratedMissionQueries, PuzzleQueries, ServerQueries,
+    TerminalQueries,
+};
+use he_game_mechanics::doom::{
+    self, DoomCampaign, DoomDenied, DoomEvent, DoomPhase, DoomResearchStep, DoomReward, DOOM_RESEARCH_CHAIN,
+};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+
+const TICK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+const REWARD_BATCH: i64 = 50;
+
+type DoomResult<T> = anyhow::Result<Result<T, DoomDenied>>;
+
+/// The player's own campaign
+#[derive(Debug, Serialize)]
+pub struct CampaignView {
+    pub phase: DoomPhase,
+    /// The step being researched, or the next one to start
+    pub step: Option<&'static DoomResearchStep>,
+    pub step_ready_at: Option<DateTime<Utc>>,
+    pub ready_to_install: bool,
+}
+
+/// The countdown as a defender sees it
+#[derive(Debug, Serialize)]
+pub struct CountdownView {
+    pub instigator_id: i32,
+    pub detonates_at: DateTime<Utc>,
+    pub seconds_remaining: i64,
+    /// NPCs the player found clues on
+    pub clues_found: Vec<String>,
+    pub wrong_guesses: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DoomStatus {
+    pub campaign: Option<CampaignView>,
+    pub countdown: Option<CountdownView>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum ResearchOutcome {
+    Started { step: &'static DoomResearchStep, ready_at: DateTime<Utc> },
+    Completed { step: &'static DoomResearchStep, ready_to_install: bool },
+}
+
+#[derive(Debug, Serialize)]
+pub struct NpcLogs {
+    pub lines: Vec<String>,
+    /// Whether reading them gave the player a new clue
+    pub clue_found: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum DisarmOutcome {
+    WrongLocation { guesses_left: u32 },
+    Disarmed { rewards: Vec<DoomReward> },
+}
+
+fn campaign_of(row: &DoomCampaignRow) -> anyhow::Result<DoomCampaign> {
+    Ok(serde_json::from_value(row.campaign.clone())?)
+}
+
+fn completed_steps(campaign: &DoomCampaign) -> Option<usize> {
+    match campaign.phase {
+        DoomPhase::Researching { completed_steps } => Some(completed_steps),
+        _ => None,
+    }
+}
+
+fn step_ready_at(row: &DoomCampaignRow, step: &DoomResearchStep) -> Option<DateTime<Utc>> {
+    row.step_started_at.map(|started| started + Duration::hours(step.research_hours))
+}
+
+/// The player's best version of every software the research chain asks for
+async fn software_versions(conn: &mut PgConnection, user_id: i64) -> anyhow::Result<HashMap<String, f32>> {
+    let mut versions = HashMap::new();
+    for step in DOOM_RESEARCH_CHAIN {
+        if versions.contains_key(step.required_software) {
+            continue;
+        }
+        let version = ClanServerQueries::best_software(&mut *conn, user_id, step.required_software).await?;
+        versions.insert(step.required_software.to_string(), version.unwrap_or(0.0) as f32);
+    }
+    Ok(versions)
+}
+
+/// Hold every reward until the sweep can pay it
+async fn hold_rewards(conn: &mut PgConnection, campaign_id: i64, rewards: &[DoomReward]) -> anyhow::Result<()> {
+    for reward in rewards.iter().filter(|reward| reward.money > 0) {
+        DoomQueries::add_reward(&mut *conn, campaign_id, reward.user_id as i64, reward.money).await?;
+    }
+    Ok(())
+}
+
+pub async fn status(pool: &PgPool, user_id: i64) -> anyhow::Result<DoomStatus> {
+    let campaign = match DoomQueries::own(pool, user_id).await? {
+        Some(row) => {
+            let campaign = campaign_of(&row)?;
+            let step = completed_steps(&campaign).and_then(|completed| DOOM_RESEARCH_CHAIN.get(completed));
+            Some(CampaignView {
+                step_ready_at: step.and_then(|step| step_ready_at(&row, step)),
+                ready_to_install: campaign.is_ready_to_install(),
+                phase: campaign.phase,
+                step,
+            })
+        }
+        None => None,
+    };
+
+    let mut conn = pool.acquire().await?;
+    let countdown = match DoomQueries::countdown(&mut conn).await? {
+        Some(row) => {
+            let campaign = campaign_of(&row)?;
+            let defender_id = i32::try_from(user_id)?;
+            let now = Utc::now();
+            match (row.detonates_at, campaign.seconds_remaining(now)) {
+                (Some(detonates_at), Some(seconds_remaining)) => Some(CountdownView {
+                    instigator_id: campaign.instigator_id,
+                    detonates_at,
+                    seconds_remaining,
+                    clues_found: campaign.clues_found.get(&defender_id).cloned().unwrap_or_default(),
+                    wrong_guesses: campaign.wrong_guesses.get(&defender_id).copied().unwrap_or(0),
+                }),
+                _ => None,
+            }
+        }
+        None => None,
+    };
+
+    Ok(DoomStatus { campaign, countdown })
+}
+
+/// Start the next research step, or finish the one running once its time is up.
+/// The first call starts the campaign.
+pub async fn research(pool: &PgPool, user_id: i64) -> DoomResult<ResearchOutcome> {
+    let config = &crate::balance::current().doom;
+    let instigator_id = i32::try_from(user_id)?;
+    let level = TerminalQueries::level(pool, user_id).await?;
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let row = match DoomQueries::lock_own(&mut tx, user_id).await? {
+        Some(row) => row,
+        None => {
+            let campaign = match DoomCampaign::new(instigator_id, level, config) {
+                Ok(campaign) => campaign,
+                Err(e) => return Ok(Err(e.into())),
+            };
+            let created = DoomQueries::create(&mut tx, user_id, &serde_json::to_value(&campaign)?).await?;
+            let Some(row) = created else {
+                return Ok(Err(DoomDenied::Refused { message: "Your campaign is busy, try again".to_string() }));
+            };
+            row
+        }
+    };
+
+    let mut campaign = campaign_of(&row)?;
+    let Some(step) = completed_steps(&campaign).and_then(|completed| DOOM_RESEARCH_CHAIN.get(completed)) else {
+        return Ok(Err(DoomDenied::Refused { message: "Doom research is finished; install the virus".to_string() }));
+    };
+    let versions = software_versions(&mut tx, user_id).await?;
+    let now = Utc::now();
+
+    let outcome = match step_ready_at(&row, step) {
+        None => {
+            let completed = completed_steps(&campaign).unwrap_or(0);
+            if let Err(e) = doom::next_research_step(completed, &versions) {
+                return Ok(Err(e.into()));
+            }
+            DoomQueries::save(&mut tx, row.id, &row.campaign, Some(now), None, true).await?;
+            ResearchOutcome::Started { step, ready_at: now + Duration::hours(step.research_hours) }
+        }
+        Some(ready_at) if now < ready_at => return Ok(Err(DoomDenied::ResearchRunning { ready_at })),
+        Some(_) => {
+            // The software has to still be there when the step finishes
+            if let Err(e) = campaign.complete_research_step(&versions) {
+                return Ok(Err(e.into()));
+            }
+            DoomQueries::save(&mut tx, row.id, &serde_json::to_value(&campaign)?, None, None, true).await?;
+            ResearchOutcome::Completed { step, ready_to_install: campaign.is_ready_to_install() }
+        }
+    };
+    tx.commit().await?;
+
+    Ok(Ok(outcome))
+}
+
+/// Install the researched virus on one of the player's servers and start the countdown
+pub async fn install(pool: &PgPool, user_id: i64, host_ip: &str) -> DoomResult<DateTime<Utc>> {
+    let config = &crate::balance::current().doom;
+    if ServerQueries::get_player_owner_by_ip(pool, host_ip).await? != Some(user_id) {
+        return Ok(Err(DoomDenied::NotYourServer));
+    }
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(row) = DoomQueries::lock_own(&mut tx, user_id).await? else {
+        return Ok(Err(DoomDenied::NoCampaign));
+    };
+    if DoomQueries::countdown(&mut tx).await?.is_some() {
+        return Ok(Err(DoomDenied::CountdownRunning));
+    }
+
+    let npc_ips: Vec<String> = GeneratedMissionQueries::targets(&mut tx)
+        .await?
+        .into_iter()
+        .map(|target| target.ip)
+        .collect();
+    let mut campaign = campaign_of(&row)?;
+    let installed = campaign.install(host_ip, &npc_ips, Utc::now(), &mut rand::thread_rng(), config);
+    let detonates_at = match installed {
+        Ok(DoomEvent::CountdownStarted { detonates_at, .. }) => detonates_at,
+        Ok(_) => return Ok(Err(DoomDenied::Refused { message: "Doom could not be installed".to_string() })),
+        Err(e) => return Ok(Err(e.into())),
+    };
+
+    for clue in &campaign.clues {
+        DoomQueries::plant_clue(&mut tx, &clue.npc_ip, &clue.log_line).await?;
+    }
+    DoomQueries::save(&mut tx, row.id, &serde_json::to_value(&campaign)?, None, Some(detonates_at), true).await?;
+    DoomQueries::announce(
+        &mut tx,
+        "Doom virus installed",
+        &format!(
+            "A Doom virus is counting down and detonates at {} UTC. Clues to its host are hidden in NPC logs.",
+            detonates_at.format("%Y-%m-%d %H:%M")
+        ),
+        "critical",
+    )
+    .await?;
+    tx.commit().await?;
+
+    tracing::info!("User {} installed Doom, detonating at {}", user_id, detonates_at);
+    Ok(Ok(detonates_at))
+}
+
+/// Read the Doom lines in an NPC's logs; the first read of a clue counts it as found
+pub async fn read_logs(pool: &PgPool, user_id: i64, npc_ip: &str) -> DoomResult<NpcLogs> {
+    let defender_id = i32::try_from(user_id)?;
+    let mut tx = he_database::tagging::begin(pool).await?;
+    if !PuzzleQueries::has_hacked(&mut tx, user_id, npc_ip).await? {
+        return Ok(Err(DoomDenied::ServerNotHacked { ip: npc_ip.to_string() }));
+    }
+
+    let lines = DoomQueries::clue_logs(&mut tx, npc_ip).await?;
+    let mut clue_found = false;
+    if !lines.is_empty() {
+        if let Some(row) = DoomQueries::lock_countdown(&mut tx).await? {
+            let mut campaign = campaign_of(&row)?;
+            if campaign.inspect_npc_log(defender_id, npc_ip).is_some() {
+                clue_found = true;
+                DoomQueries::save(
+                    &mut tx,
+                    row.id,
+                    &serde_json::to_value(&campaign)?,
+                    None,
+                    row.detonates_at,
+                    true,
+                )
+                .await?;
+            }
+        }
+    }
+    tx.commit().await?;
+
+    Ok(Ok(NpcLogs { lines, clue_found }))
+}
+
+/// Try to disarm the virus at `ip`
+pub async fn disarm(pool: &PgPool, user_id: i64, ip: &str) -> DoomResult<DisarmOutcome> {
+    let config = &crate::balance::current().doom;
+    let defender_id = i32::try_from(user_id)?;
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(row) = DoomQueries::lock_countdown(&mut tx).await? else {
+        return Ok(Err(DoomDenied::NoCountdown));
+    };
+
+    let mut campaign = campaign_of(&row)?;
+    let outcome = match campaign.attempt_disarm(defender_id, ip, Utc::now(), config) {
+        Ok(DoomEvent::WrongLocation { guesses_left, .. }) => {
+            DoomQueries::save(&mut tx, row.id, &serde_json::to_value(&campaign)?, None, row.detonates_at, true).await?;
+            DisarmOutcome::WrongLocation { guesses_left }
+        }
+        Ok(DoomEvent::Disarmed { rewards, .. }) => {
+            DoomQueries::save(&mut tx, row.id, &serde_json::to_value(&campaign)?, None, row.detonates_at, false).await?;
+            hold_rewards(&mut tx, row.id, &rewards).await?;
+            DoomQueries::announce(
+                &mut tx,
+                "Doom virus disarmed",
+                &format!("The Doom virus was found at {} and disarmed before it could detonate.", ip),
+                "info",
+            )
+            .await?;
+            tracing::info!("User {} disarmed Doom campaign {}", user_id, row.id);
+            DisarmOutcome::Disarmed { rewards }
+        }
+        Ok(_) => return Ok(Err(DoomDenied::NoCountdown)),
+        Err(e) => return Ok(Err(e.into())),
+    };
+    tx.commit().await?;
+
+    Ok(Ok(outcome))
+}
+
+/// Detonate the countdown once it has run out; another node holding it skips
+async fn tick(pool: &PgPool) -> anyhow::Result<()> {
+    let config = &crate::balance::current().doom;
+    let now = Utc::now();
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(row) = DoomQueries::lock_detonating(&mut tx, now).await? else {
+        return Ok(());
+    };
+
+    let mut campaign = campaign_of(&row)?;
+    if let Some(DoomEvent::ServerReset { rewards, .. }) = campaign.tick(now, config) {
+        DoomQueries::save(&mut tx, row.id, &serde_json::to_value(&campaign)?, None, row.detonates_at, false).await?;
+        hold_rewards(&mut tx, row.id, &rewards).await?;
+        DoomQueries::announce(
+            &mut tx,
+            "Doom virus detonated",
+            "Nobody found the Doom virus in time. The round is over.",
+            "critical",
+        )
+        .await?;
+        tracing::info!("Doom campaign {} detonated", row.id);
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn pay_held_rewards(pool: &PgPool) -> anyhow::Result<()> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let paid = DoomQueries::pay_held_rewards(&mut tx, REWARD_BATCH).await?;
+    tx.commit().await?;
+
+    for reward in &paid {
+        tracing::info!("Paid Doom reward {} of ${} to user {}", reward.id, reward.money, reward.user_id);
+    }
+    Ok(())
+}
+
+/// Tick the countdown and pay held rewards, on every node
+pub fn start(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let started = std::time::Instant::now();
+            if let Err(e) = tick(&pool).await {
+                tracing::warn!("Doom tick failed: {}", e);
+            }
+            if let Err(e) = pay_held_rewards(&pool).await {
+                tracing::warn!("Doom reward payout failed: {}", e);
+            }
+            crate::live_ops::record_tick("doom", started.elapsed());
+        }
+    });
+}
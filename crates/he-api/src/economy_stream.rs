@@ -0,0 +1,236 @@
+//! Real-time economy stream
+//!
+//! A stream processor tails the money ledger: every few seconds it folds the
+//! transactions past its cursor into per-minute series of money created and
+//! destroyed per reason and of what each player took in and paid out (see
+//! `migrations-postgres/20241115_economy_stream.sql`). From those it
+//!
+//! - counts transactions per reason into the `transactions_total` metric
+//!   and the minted and sunk money into `economy_money_cents_total`,
+//! - sets the last complete minute's minted and sunk money, which the alert
+//!   manager checks against the `money_minted_per_minute` and
+//!   `money_sunk_per_minute` thresholds, and
+//! - flags players whose earnings over the last [`SPIKE_RULE`] window jump
+//!   far above their own baseline.
+//!
+//! The admin dashboard's economy page renders all of it. The cursor row is
+//! locked with `SKIP LOCKED`, so every node runs the processor but only one
+//! folds at a time. Transactions are read once they are a few seconds old,
+//! so ones committed slightly out of id order are not skipped.
+
+use chrono::{DateTime, Duration as ChronoDuration, DurationRound, Utc};
+use he_database::queries::{EconomyStreamQueries, SpikeCandidate};
+use he_monitoring::{AlertManager, EconomyMetrics, Threshold};
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+
+/// How often the ledger is tailed
+const STREAM_INTERVAL: Duration = Duration::from_secs(5);
+/// Transactions younger than this are left for the next pass
+const SETTLE_SECS: i64 = 3;
+/// Most transactions folded per pass
+const BATCH: i64 = 5000;
+/// How often players are checked for wealth spikes
+const SPIKE_INTERVAL: Duration = Duration::from_secs(60);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// How long the per-minute series are kept
+pub const MINUTES_RETENTION_DAYS: i64 = 30;
+
+/// When a player's earnings count as a sudden wealth spike
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpikeRule {
+    /// Earnings are summed over this many minutes
+    pub window_minutes: i64,
+    /// and compared with the player's average per window over this period
+    pub baseline_hours: i64,
+    /// Flag when the window is this many times the average
+    pub ratio: f64,
+    /// and at least this many cents, so small players are not flagged for
+    /// their first pay day
+    pub floor: i64,
+}
+
+pub const SPIKE_RULE: SpikeRule = SpikeRule {
+    window_minutes: 10,
+    baseline_hours: 24,
+    ratio: 10.0,
+    floor: 1_000_000,
+};
+
+impl SpikeRule {
+    /// How many windows the baseline period holds
+    fn baseline_windows(&self) -> f64 {
+        (self.baseline_hours * 60 / self.window_minutes).max(1) as f64
+    }
+
+    /// The candidate's average earnings per window over the baseline and
+    /// how many times that the window is, if it is a spike
+    pub fn check(&self, candidate: &SpikeCandidate) -> Option<(f64, f64)> {
+        if candidate.earned < self.floor {
+            return None;
+        }
+        let baseline = candidate.baseline_earned as f64 / self.baseline_windows();
+        // A player with no baseline is measured against one cent a window
+        let ratio = candidate.earned as f64 / baseline.max(1.0);
+        (ratio >= self.ratio).then_some((baseline, ratio))
+    }
+
+    /// The window `now` falls in; a player is flagged at most once per window
+    pub fn window_start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now.duration_trunc(ChronoDuration::minutes(self.window_minutes)).unwrap_or(now)
+    }
+}
+
+/// How a value sits against its alert threshold, as the dashboard colours it
+pub fn alert_level(value: i64, threshold: &Threshold) -> &'static str {
+    let value = value as f64;
+    if value >= threshold.critical {
+        "critical"
+    } else if value >= threshold.warning {
+        "warning"
+    } else {
+        "ok"
+    }
+}
+
+/// Tail the ledger every [`STREAM_INTERVAL`]
+pub(crate) fn spawn_processor(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STREAM_INTERVAL);
+        let mut last_spike_scan = Instant::now();
+        let mut last_prune = Instant::now();
+        loop {
+            interval.tick().await;
+            let folded = match fold(&pool).await {
+                Ok(folded) => folded,
+                Err(e) => {
+                    tracing::warn!("Economy stream failed: {}", e);
+                    continue;
+                }
+            };
+            // Only the node holding the cursor scans and prunes
+            if !folded {
+                continue;
+            }
+
+            if let Err(e) = record_last_minute(&pool).await {
+                tracing::warn!("Economy stream could not read the last minute: {}", e);
+            }
+            if last_spike_scan.elapsed() >= SPIKE_INTERVAL {
+                last_spike_scan = Instant::now();
+                if let Err(e) = flag_spikes(&pool, SPIKE_RULE, Utc::now()).await {
+                    tracing::warn!("Economy spike scan failed: {}", e);
+                }
+            }
+            if last_prune.elapsed() >= PRUNE_INTERVAL {
+                last_prune = Instant::now();
+                let now = Utc::now();
+                let earners_before = now - ChronoDuration::hours(SPIKE_RULE.baseline_hours * 2);
+                match EconomyStreamQueries::prune(&pool, now - ChronoDuration::days(MINUTES_RETENTION_DAYS), earners_before).await {
+                    Ok(pruned) if pruned > 0 => tracing::info!("Economy stream pruned {} old rows", pruned),
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Economy stream prune failed: {}", e),
+                }
+            }
+        }
+    });
+}
+
+/// Fold the next batch, if this node holds the cursor; false if another
+/// node does
+async fn fold(pool: &PgPool) -> anyhow::Result<bool> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(cursor) = EconomyStreamQueries::claim_cursor(&mut *tx).await? else {
+        tx.rollback().await?;
+        return Ok(false);
+    };
+
+    let settled_before = Utc::now() - ChronoDuration::seconds(SETTLE_SECS);
+    let Some(upto) = EconomyStreamQueries::batch_end(&mut *tx, cursor, settled_before, BATCH).await? else {
+        tx.rollback().await?;
+        return Ok(true);
+    };
+    let flows = EconomyStreamQueries::fold(&mut *tx, cursor, upto).await?;
+    tx.commit().await?;
+
+    for flow in &flows {
+        EconomyMetrics::transactions(&flow.reason, flow.transactions as u64, flow.minted, flow.sunk);
+    }
+    Ok(true)
+}
+
+async fn record_last_minute(pool: &PgPool) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let minute = now.duration_trunc(ChronoDuration::minutes(1))? - ChronoDuration::minutes(1);
+    let last = EconomyStreamQueries::minutes(pool, minute)
+        .await?
+        .into_iter()
+        .find(|row| row.minute == minute);
+    let (minted, sunk) = last.map(|row| (row.minted, row.sunk)).unwrap_or((0, 0));
+    EconomyMetrics::last_minute(minted, sunk);
+    Ok(())
+}
+
+/// Flag every player whose earnings spiked in the window ending at `now`
+async fn flag_spikes(pool: &PgPool, rule: SpikeRule, now: DateTime<Utc>) -> anyhow::Result<()> {
+    let window_since = now - ChronoDuration::minutes(rule.window_minutes);
+    let baseline_since = window_since - ChronoDuration::hours(rule.baseline_hours);
+    let candidates = EconomyStreamQueries::spike_candidates(pool, window_since, baseline_since, rule.floor).await?;
+
+    let window_start = rule.window_start(now);
+    let mut flagged = 0;
+    for candidate in candidates {
+        let Some((baseline, ratio)) = rule.check(&candidate) else {
+            continue;
+        };
+        let flag = EconomyStreamQueries::flag(pool, candidate.user_id, window_start, candidate.earned, baseline, ratio);
+        if flag.await?.is_some() {
+            flagged += 1;
+            AlertManager::warning(&format!(
+                "Player {} earned {} cents in {} minutes, {:.0}x their baseline",
+                candidate.user_id, candidate.earned, rule.window_minutes, ratio
+            ));
+        }
+    }
+    if flagged > 0 {
+        EconomyMetrics::anomalies_flagged(flagged);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn candidate(earned: i64, baseline_earned: i64) -> SpikeCandidate {
+        SpikeCandidate { user_id: 7, earned, baseline_earned }
+    }
+
+    #[test]
+    fn test_spikes_need_the_floor_and_the_ratio() {
+        // 144 windows in a day at 10 minutes each
+        let steady = candidate(2_000_000, 144 * 1_000_000);
+        assert_eq!(SPIKE_RULE.check(&steady), None);
+
+        let spike = candidate(20_000_000, 144 * 1_000_000);
+        let (baseline, ratio) = SPIKE_RULE.check(&spike).unwrap();
+        assert_eq!(baseline, 1_000_000.0);
+        assert_eq!(ratio, 20.0);
+
+        assert_eq!(SPIKE_RULE.check(&candidate(SPIKE_RULE.floor - 1, 0)), None);
+        let newcomer = SPIKE_RULE.check(&candidate(SPIKE_RULE.floor, 0)).unwrap();
+        assert_eq!(newcomer, (0.0, SPIKE_RULE.floor as f64));
+    }
+
+    #[test]
+    fn test_window_start_and_alert_level() {
+        let now = Utc.with_ymd_and_hms(2024, 11, 15, 13, 47, 12).unwrap();
+        assert_eq!(SPIKE_RULE.window_start(now), Utc.with_ymd_and_hms(2024, 11, 15, 13, 40, 0).unwrap());
+
+        let threshold = Threshold::new(100.0, 200.0);
+        assert_eq!(alert_level(99, &threshold), "ok");
+        assert_eq!(alert_level(100, &threshold), "warning");
+        assert_eq!(alert_level(250, &threshold), "critical");
+    }
+}
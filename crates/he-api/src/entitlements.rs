@@ -0,0 +1,266 @@
+//! Premium entitlements
+//!
+//! Premium is sold through a Stripe-compatible payment provider, which
+//! reports subscription changes to `/api/webhooks/payments`. Webhooks are
+//! checked against `PAYMENT_WEBHOOK_SECRET` (several may be given, comma
+//! separated, while the secret is rolled), recorded so a redelivery is
+//! applied once, and mirrored into `subscriptions`. A subscription that
+//! entitles its player gives them the premium_player role; he-cron's
+//! `expire_entitlements` job takes it away once access and its grace period
+//! run out (see [`he_game_mechanics::entitlements`]).
+//!
+//! Premium-only features check entitlement with the [`has_premium`]
+//! henforcer rather than the role, so a lapsed subscription stops them
+//! before the expiry job has run.
+
+use chrono::{DateTime, Utc};
+use he_database::queries::{EntitlementQueries, SubscriptionRow, SubscriptionUpdate};
+use he_game_mechanics::config::EntitlementConfig;
+use he_game_mechanics::entitlements::{self, SubscriptionStatus, WebhookDenied};
+use he_helix_henforcer::cache;
+use he_helix_henforcer::{add_to_relay, reply_error, reply_ok, HenforcerError, Relay, StandardResult};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use crate::outbox::{self, OutboxMessage};
+
+/// Provider name stored with subscriptions and events
+pub const PROVIDER: &str = "stripe";
+/// Header the provider signs webhooks in
+pub const SIGNATURE_HEADER: &str = "Stripe-Signature";
+/// Metadata key the checkout puts the buying player's id in
+const USER_METADATA_KEY: &str = "user_id";
+/// Relay cache object type of a player's entitling subscription, keyed by
+/// user id
+pub const ENTITLEMENT_OBJECT: &str = "entitlement";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Verifies webhooks from the payment provider
+pub struct PaymentWebhooks {
+    secrets: Vec<String>,
+    config: EntitlementConfig,
+}
+
+impl PaymentWebhooks {
+    pub fn from_env() -> Self {
+        let secrets = std::env::var("PAYMENT_WEBHOOK_SECRET")
+            .map(|value| value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        Self { secrets, config: crate::balance::current().entitlements.clone() }
+    }
+
+    /// Webhooks are refused until a secret is configured
+    pub fn is_configured(&self) -> bool {
+        !self.secrets.is_empty()
+    }
+
+    pub fn verify(&self, header: Option<&str>, payload: &[u8], now: DateTime<Utc>) -> Result<(), WebhookDenied> {
+        let header = entitlements::parse_signature_header(header.ok_or(WebhookDenied::MissingSignature)?)?;
+        entitlements::check_timestamp(header.timestamp, now, &self.config)?;
+
+        let matched = self.secrets.iter().any(|secret| {
+            let expected = sign(secret, header.timestamp, payload);
+            header.signatures.iter().any(|signature| constant_time_eq(signature, &expected))
+        });
+        if !matched {
+            return Err(WebhookDenied::BadSignature);
+        }
+        Ok(())
+    }
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<payload>`, as the provider signs it
+fn sign(secret: &str, timestamp: i64, payload: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// Unix seconds
+    pub created: i64,
+    pub data: WebhookData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookData {
+    pub object: serde_json::Value,
+}
+
+/// The parts of a provider subscription object used here
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderSubscription {
+    id: String,
+    customer: Option<String>,
+    status: String,
+    /// Newer API versions only carry the period on the items
+    current_period_start: Option<i64>,
+    current_period_end: Option<i64>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    #[serde(default)]
+    items: ProviderItems,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ProviderItems {
+    #[serde(default)]
+    data: Vec<ProviderItem>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderItem {
+    price: ProviderPrice,
+    current_period_start: Option<i64>,
+    current_period_end: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderPrice {
+    id: String,
+    lookup_key: Option<String>,
+}
+
+/// What a webhook did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Applied {
+    Subscription { subscription_id: i64, entitled: bool, role_changed: bool },
+    /// Older than the last event applied to its subscription
+    Stale,
+    /// Not an event premium depends on
+    Ignored,
+}
+
+/// Apply a verified webhook. Errors are for events that should be retried.
+pub async fn apply(pool: &PgPool, event: &WebhookEvent) -> anyhow::Result<Applied> {
+    if !event.event_type.starts_with("customer.subscription.") {
+        return Ok(Applied::Ignored);
+    }
+    let object: ProviderSubscription = serde_json::from_value(event.data.object.clone())?;
+    let Some(status) = SubscriptionStatus::from_provider(&object.status) else {
+        anyhow::bail!("unknown subscription status {}", object.status);
+    };
+    let item = object.items.data.first();
+    let period_start = object.current_period_start.or(item.and_then(|i| i.current_period_start));
+    let period_end = object.current_period_end.or(item.and_then(|i| i.current_period_end));
+    let (Some(period_start), Some(period_end)) = (period_start.and_then(timestamp), period_end.and_then(timestamp)) else {
+        anyhow::bail!("subscription {} has no current period", object.id);
+    };
+    let Some(event_at) = timestamp(event.created) else {
+        anyhow::bail!("event {} has an invalid creation time", event.id);
+    };
+    let plan = item.map(|i| i.price.lookup_key.clone().unwrap_or_else(|| i.price.id.clone())).unwrap_or_default();
+
+    let config = &crate::balance::current().entitlements;
+    let access_until = entitlements::access_until(status, period_end, config);
+    let entitled = entitlements::is_entitled(access_until, Utc::now());
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let existing = EntitlementQueries::lock_subscription(&mut tx, PROVIDER, &object.id).await?;
+    if existing.as_ref().is_some_and(|s| !entitlements::is_newer(event_at, Some(s.last_event_at))) {
+        return Ok(Applied::Stale);
+    }
+    let user_id = match &existing {
+        Some(subscription) => subscription.user_id,
+        None => match object.metadata.get(USER_METADATA_KEY).and_then(|id| id.parse::<i64>().ok()) {
+            Some(user_id) => user_id,
+            None => anyhow::bail!("subscription {} has no {} metadata", object.id, USER_METADATA_KEY),
+        },
+    };
+
+    let subscription = EntitlementQueries::upsert(&mut tx, &SubscriptionUpdate {
+        provider: PROVIDER,
+        provider_subscription_id: &object.id,
+        provider_customer_id: object.customer.as_deref(),
+        user_id,
+        plan: &plan,
+        status: status.as_str(),
+        current_period_start: period_start,
+        current_period_end: period_end,
+        access_until,
+        event_at,
+    })
+    .await?;
+    let role_changed = EntitlementQueries::sync_role(&mut tx, &subscription, entitled).await?;
+    if role_changed {
+        outbox::enqueue(&mut *tx, &[premium_message(&subscription, &event.id, entitled)]).await?;
+    }
+    tx.commit().await?;
+    cache::invalidate(ENTITLEMENT_OBJECT, user_id);
+    if role_changed {
+        outbox::wake();
+    }
+
+    Ok(Applied::Subscription { subscription_id: subscription.id, entitled, role_changed })
+}
+
+fn timestamp(secs: i64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(secs, 0)
+}
+
+/// Tell the player their premium started or ended
+fn premium_message(subscription: &SubscriptionRow, event_id: &str, entitled: bool) -> OutboxMessage {
+    let event = he_websocket::GameEvent::Custom {
+        event_name: "premium_changed".to_string(),
+        payload: json!({
+            "premium": entitled,
+            "plan": subscription.plan,
+            "access_until": subscription.access_until,
+        }),
+    };
+    OutboxMessage::to_user(format!("premium:{}", event_id), subscription.user_id, &event)
+}
+
+/// Henforcer for premium-only features. Passes while the player has
+/// premium, relaying the subscription it comes from as `subscription`.
+pub async fn has_premium(pool: &PgPool, user_id: i64) -> StandardResult {
+    let entitlement = cache::cached(ENTITLEMENT_OBJECT, user_id, || EntitlementQueries::entitlement(pool, user_id));
+    match entitlement.await {
+        Ok(Some(subscription)) => reply_ok(add_to_relay(Relay::new(), "subscription", subscription)),
+        Ok(None) => reply_error(
+            HenforcerError::AccessDenied { reason: "premium required".to_string() },
+            Relay::new(),
+        ),
+        Err(e) => reply_error(
+            HenforcerError::Custom { reason: format!("entitlement lookup failed: {}", e) },
+            Relay::new(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_signatures() {
+        let webhooks = PaymentWebhooks {
+            secrets: vec!["whsec_old".to_string(), "whsec_new".to_string()],
+            config: EntitlementConfig::default(),
+        };
+        let now = Utc::now();
+        let payload = br#"{"id":"evt_1"}"#;
+        let header = format!("t={},v1={}", now.timestamp(), sign("whsec_new", now.timestamp(), payload));
+
+        assert_eq!(webhooks.verify(Some(&header), payload, now), Ok(()));
+        assert_eq!(webhooks.verify(Some(&header), br#"{"id":"evt_2"}"#, now), Err(WebhookDenied::BadSignature));
+        assert_eq!(webhooks.verify(None, payload, now), Err(WebhookDenied::MissingSignature));
+
+        let later = now + chrono::Duration::hours(1);
+        assert!(matches!(webhooks.verify(Some(&header), payload, later), Err(WebhookDenied::Stale { .. })));
+    }
+}
@@ -290,6 +290,42 @@ impl From<anyhow::Error> for ApiError {
     }
 }
 
+/// `HeError` carries its own code; the message shown to clients depends on
+/// whether the request or the server was at fault
+impl From<he_core::HeError> for ApiError {
+    fn from(err: he_core::HeError) -> Self {
+        use he_core::ErrorCode;
+
+        let code = err.code();
+        let kind = match code {
+            ErrorCode::Unauthenticated => ErrorKind::Unauthorized,
+            ErrorCode::Forbidden => ErrorKind::Forbidden,
+            ErrorCode::NotFound => ErrorKind::NotFound,
+            ErrorCode::Validation => ErrorKind::ValidationError,
+            ErrorCode::Conflict => ErrorKind::Conflict,
+            ErrorCode::InsufficientResources => ErrorKind::InsufficientResources,
+            ErrorCode::GameRule => ErrorKind::ActionNotAllowed,
+            ErrorCode::RateLimited => ErrorKind::RateLimitExceeded,
+            ErrorCode::Unavailable => ErrorKind::ServiceUnavailable,
+            ErrorCode::Database => ErrorKind::DatabaseError,
+            ErrorCode::Internal | ErrorCode::Configuration => ErrorKind::InternalError,
+        };
+
+        he_monitoring::ErrorReporter::capture(&err);
+
+        // Server faults may carry internals (SQL, paths) - keep them in the details
+        let message = if code.is_server_error() {
+            "Internal error occurred".to_string()
+        } else {
+            err.root().to_string()
+        };
+
+        ApiError::new(kind, message)
+            .with_details(err.chain().join(": "))
+            .with_source(err)
+    }
+}
+
 /// Macro for creating errors with context
 #[macro_export]
 macro_rules! api_error {
@@ -0,0 +1,42 @@
+//! Payload schemas of the game's own events
+//!
+//! Every event type the API publishes is registered here with the type of
+//! its payload, so `bounty`, `coop` and `heist` validate what they publish
+//! and decode what their coordinators receive against one [`SchemaRegistry`].
+//! Changing a payload means bumping its `EventSchema::VERSION`, adding an
+//! upcaster from the previous version and a fixture for the new one; the
+//! test below fails until all three are there.
+
+use he_events::SchemaRegistry;
+use once_cell::sync::Lazy;
+use crate::bounty::{BountyEvent, BountyEventKind};
+use crate::coop::{CoopEvent, CoopEventKind};
+use crate::heist::{HeistEvent, HeistEventKind};
+
+static REGISTRY: Lazy<SchemaRegistry> = Lazy::new(|| {
+    let mut registry = SchemaRegistry::new();
+    for event_type in BountyEventKind::event_types() {
+        registry.register::<BountyEvent>(event_type).expect("bounty event types are registered once");
+    }
+    for event_type in CoopEventKind::event_types() {
+        registry.register::<CoopEvent>(event_type).expect("co-op event types are registered once");
+    }
+    for event_type in HeistEventKind::event_types() {
+        registry.register::<HeistEvent>(event_type).expect("heist event types are registered once");
+    }
+    registry
+});
+
+pub fn registry() -> &'static SchemaRegistry {
+    &REGISTRY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_schemas_compatible() {
+        assert_eq!(registry().check_compatibility(), Ok(()));
+    }
+}
@@ -0,0 +1,66 @@
+//! Account field encryption workers
+//!
+//! Installs the keyring from `FIELD_ENCRYPTION_KEYS` and
+//! `FIELD_ENCRYPTION_INDEX_KEY`, then keeps it in step with the key registry
+//! and runs the re-encryption job from [`he_database::encryption`]. Every node may run the job: rows are claimed
+//! with `SKIP LOCKED`.
+
+use he_database::encryption;
+use he_helix_security::encryption::{install_keyring, FieldEncryption};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// How often the node reports its keys and picks up the active version
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+/// How often rows are resealed while a rotation is open
+pub const RESEAL_INTERVAL: Duration = Duration::from_secs(5);
+/// Accounts, and separately IP history entries, resealed per batch
+pub const RESEAL_BATCH: i64 = 500;
+
+/// Install the keyring and start the workers. Without keys account fields
+/// stay in plaintext; keys that are set but invalid, or set without an index
+/// key, are an error.
+pub async fn start(pool: PgPool, node_id: String) -> anyhow::Result<()> {
+    match FieldEncryption::from_env()? {
+        Some(keyring) => install_keyring(keyring),
+        None => {
+            tracing::warn!("No field encryption keys set, account fields are stored in plaintext");
+            return Ok(());
+        }
+    }
+
+    // Seal with the registered version before serving requests
+    if let Err(e) = encryption::sync(&pool, &node_id).await {
+        tracing::error!("Failed to sync field encryption keys: {}", e);
+    }
+
+    let sync_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SYNC_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let started = std::time::Instant::now();
+            if let Err(e) = encryption::sync(&sync_pool, &node_id).await {
+                tracing::warn!("Field encryption key sync failed: {}", e);
+            }
+            crate::live_ops::record_tick("field_encryption_sync", started.elapsed());
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(RESEAL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let started = std::time::Instant::now();
+            match encryption::reseal_batch(&pool, RESEAL_BATCH).await {
+                Ok(Some(n)) if n > 0 => tracing::debug!("Resealed {} account fields", n),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Field re-encryption failed: {}", e),
+            }
+            crate::live_ops::record_tick("field_encryption_reseal", started.elapsed());
+        }
+    });
+    tracing::info!("Field encryption workers started");
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! Formula override scripts of this node
+//!
+//! Scripts are stored in Postgres and saved from the admin API. Every save,
+//! removal or reload adds a revision, and each node's live-ops reloader
+//! recompiles the stored set when the revision moves, so a change made on one
+//! node reaches all of them within a few seconds. A set that fails to compile
+//! or trial-run keeps the running one in place. The scripts themselves are in
+//! [`he_game_mechanics::scripting`].
+
+use he_database::queries::{FormulaScriptQueries, FormulaScriptRow};
+use he_game_mechanics::scripting::{Formula, FormulaScripts, ScriptError};
+use once_cell::sync::Lazy;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
+
+static SCRIPTS: Lazy<RwLock<Arc<FormulaScripts>>> = Lazy::new(|| RwLock::new(Arc::new(FormulaScripts::none())));
+/// Revision the running set was last compiled from; -1 before the first load
+static REVISION: AtomicI64 = AtomicI64::new(-1);
+
+/// Check one script before it is stored
+pub fn validate(formula: Formula, source: &str) -> Result<(), ScriptError> {
+    FormulaScripts::compile(&[(formula, source.to_string())]).map(|_| ())
+}
+
+fn sources(rows: Vec<FormulaScriptRow>) -> Result<Vec<(Formula, String)>, ScriptError> {
+    rows.into_iter()
+        .map(|row| match Formula::from_name(&row.formula) {
+            Some(formula) => Ok((formula, row.source)),
+            None => Err(ScriptError::UnknownScript { file: row.formula }),
+        })
+        .collect()
+}
+
+/// Recompile the stored scripts if they changed since the last load and
+/// return the formulas now overridden. On error the running scripts stay in
+/// place until the next change.
+pub async fn reload(pool: &PgPool) -> anyhow::Result<Result<Vec<Formula>, ScriptError>> {
+    let revision = FormulaScriptQueries::revision(pool).await?;
+    if revision == REVISION.load(Ordering::Relaxed) {
+        return Ok(Ok(current().overridden()));
+    }
+
+    let rows = FormulaScriptQueries::all(pool).await?;
+    REVISION.store(revision, Ordering::Relaxed);
+    let scripts = match sources(rows).and_then(|sources| FormulaScripts::compile(&sources)) {
+        Ok(scripts) => scripts,
+        Err(e) => return Ok(Err(e)),
+    };
+    let overridden = scripts.overridden();
+    *SCRIPTS.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(scripts);
+    Ok(Ok(overridden))
+}
+
+/// The scripts the server runs with
+pub fn current() -> Arc<FormulaScripts> {
+    SCRIPTS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Each formula, whether a script overrides it on this node and how many
+/// calls fell back to the native result since the scripts were loaded
+pub fn status() -> serde_json::Value {
+    let scripts = current();
+    let formulas: Vec<_> = Formula::ALL
+        .into_iter()
+        .map(|formula| {
+            serde_json::json!({
+                "formula": formula,
+                "overridden": scripts.overridden().contains(&formula),
+                "fallbacks": scripts.fallbacks(formula),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "revision": REVISION.load(Ordering::Relaxed).max(0),
+        "formulas": formulas,
+    })
+}
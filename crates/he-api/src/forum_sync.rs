@@ -0,0 +1,85 @@
+//! Forum account sync worker
+//!
+//! Runs the phpBB bridge from [`he_legacy_compat::forum`] when
+//! `FORUM_DATABASE_URL` is set. Every node may run it: messages are claimed
+//! with `SKIP LOCKED`, and a forum change found by two nodes at once is queued
+//! only once.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use he_legacy_compat::forum::{self, ForumBridge, ForumConfig, GameRenamer};
+use he_monitoring::{ForumSyncMetrics, ForumSyncSample, HealthStatus};
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often queued changes are picked up
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Connect to the forum and start the worker, if a forum is configured
+pub async fn start(pool: PgPool) {
+    let Ok(url) = std::env::var("FORUM_DATABASE_URL") else {
+        return;
+    };
+
+    let forum = match MySqlPoolOptions::new().max_connections(5).connect_lazy(&url) {
+        Ok(forum) => forum,
+        Err(e) => {
+            tracing::error!("Invalid FORUM_DATABASE_URL, forum sync disabled: {}", e);
+            return;
+        }
+    };
+
+    let renamer = Arc::new(Renamer { pool: pool.clone() });
+    let bridge = Arc::new(ForumBridge::new(pool, forum, ForumConfig::from_env(), renamer));
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let started = std::time::Instant::now();
+            if let Err(e) = bridge.run_once().await {
+                tracing::warn!("Forum sync failed: {}", e);
+            }
+            crate::live_ops::record_tick("forum_sync", started.elapsed());
+            record_metrics();
+        }
+    });
+    tracing::info!("Forum account sync started");
+}
+
+/// Forum renames, under the rules of [`crate::username::change`]
+struct Renamer {
+    pool: PgPool,
+}
+
+#[async_trait]
+impl GameRenamer for Renamer {
+    async fn rename(&self, user_id: i64, login: &str) -> anyhow::Result<Result<(), String>> {
+        let change = crate::username::change_from_forum(&self.pool, user_id, login).await?;
+        Ok(change.map(|_| ()).map_err(|denied| denied.message()))
+    }
+}
+
+fn record_metrics() {
+    let status = forum::status();
+    ForumSyncMetrics::record(&ForumSyncSample {
+        pending_to_forum: status.pending_to_forum,
+        pending_to_game: status.pending_to_game,
+        dead_letters: status.dead_letters,
+        lag_seconds: status.lag_seconds(Utc::now()),
+        applied_to_forum: status.applied_to_forum,
+        applied_to_game: status.applied_to_game,
+        failures: status.failures,
+    });
+}
+
+/// The sync as a `/health` check
+pub fn health() -> HealthStatus {
+    let (healthy, message) = forum::status().health(Utc::now());
+    HealthStatus {
+        name: "forum_sync".to_string(),
+        healthy,
+        message,
+    }
+}
@@ -0,0 +1,392 @@
+//! Regional WebSocket gateways
+//!
+//! Each node serves one region and advertises that region's gateway URL
+//! through live-ops. Discovery lists the healthy regions for a player, ranked
+//! by the round-trip times the client measured against `/api/gateways/ping`,
+//! and recommends one. A player sticks to the region they last used unless
+//! another is clearly faster, so a reconnect lands where their sessions were.
+//!
+//! Presence is kept per node with its region. An event for a player whose
+//! sessions are on another node, in this region or another, is forwarded over
+//! NATS to that node's subject; every node subscribes to its own. Without
+//! `NATS_URL` events only reach sessions on the node that sends them.
+//!
+//! When a node starts draining, its sessions are told where to reconnect,
+//! spread over [`MIGRATION_SPREAD`] so the other gateways are not hit at once.
+
+use futures::StreamExt;
+use he_database::queries::{GatewayQueries, GatewayRegionRow};
+use he_monitoring::GatewayMetrics;
+use he_websocket::{ConnectionManager, Entity, ServerMessage};
+use once_cell::sync::OnceCell;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Nodes not seen for this long are left out of discovery and forwarding
+const LIVE_SECS: i64 = 30;
+/// How long after their last session a player keeps their region
+const AFFINITY_TTL_SECS: i64 = 3600;
+/// How much faster another region must be to move a player off theirs
+const AFFINITY_MARGIN_MS: u32 = 50;
+/// Presence of players gone this long is forgotten
+const KEEP_PRESENCE_SECS: i64 = 86_400;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// Sessions of a draining node reconnect at random within this window
+pub const MIGRATION_SPREAD: Duration = Duration::from_secs(30);
+const SUBJECT_PREFIX: &str = "he.gateway";
+
+static NODE: OnceCell<NodeIdentity> = OnceCell::new();
+static FORWARDER: OnceCell<async_nats::Client> = OnceCell::new();
+
+#[derive(Debug, Clone)]
+struct NodeIdentity {
+    node_id: String,
+    region: String,
+}
+
+/// A gateway a player may connect to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GatewayOption {
+    pub region: String,
+    pub url: String,
+    pub healthy_nodes: i64,
+    pub sessions: i64,
+    /// As measured by the client, when it sent one
+    pub rtt_ms: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Discovery {
+    pub recommended: Option<GatewayOption>,
+    /// Fastest first, then least loaded
+    pub gateways: Vec<GatewayOption>,
+    pub affinity: Option<String>,
+}
+
+/// Rank the healthy regions for a player. `rtts` are the client's
+/// measurements by region, `affinity` the region they last used.
+pub fn rank(regions: Vec<GatewayRegionRow>, rtts: &HashMap<String, u32>, affinity: Option<&str>) -> Discovery {
+    let mut gateways: Vec<GatewayOption> = regions
+        .into_iter()
+        .filter(|region| region.healthy_nodes > 0)
+        .filter_map(|region| {
+            Some(GatewayOption {
+                rtt_ms: rtts.get(&region.region).copied(),
+                url: region.ws_url?,
+                region: region.region,
+                healthy_nodes: region.healthy_nodes,
+                sessions: region.sessions,
+            })
+        })
+        .collect();
+
+    gateways.sort_by(|a, b| {
+        let load = |gateway: &GatewayOption| gateway.sessions as f64 / gateway.healthy_nodes as f64;
+        match (a.rtt_ms, b.rtt_ms) {
+            (Some(a_rtt), Some(b_rtt)) => a_rtt.cmp(&b_rtt),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => load(a).total_cmp(&load(b)),
+        }
+    });
+
+    let sticky = affinity.and_then(|affinity| gateways.iter().find(|gateway| gateway.region == affinity));
+    let recommended = match (sticky, gateways.first()) {
+        (Some(sticky), Some(best)) => match (sticky.rtt_ms, best.rtt_ms) {
+            (Some(sticky_rtt), Some(best_rtt)) if sticky_rtt > best_rtt + AFFINITY_MARGIN_MS => Some(best.clone()),
+            _ => Some(sticky.clone()),
+        },
+        (None, best) => best.cloned(),
+        (Some(_), None) => None,
+    };
+
+    Discovery {
+        recommended,
+        gateways,
+        affinity: affinity.map(str::to_string),
+    }
+}
+
+/// Client round trips from a query value like `eu-west:42,us-east:130`;
+/// malformed entries are skipped
+pub fn parse_rtts(value: &str) -> HashMap<String, u32> {
+    value
+        .split(',')
+        .filter_map(|entry| {
+            let (region, rtt) = entry.trim().split_once(':')?;
+            Some((region.to_string(), rtt.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Healthy gateways for `user_id`, or for an anonymous client
+pub async fn discover(pool: &PgPool, user_id: Option<i64>, rtts: &HashMap<String, u32>) -> anyhow::Result<Discovery> {
+    let regions = GatewayQueries::regions(pool, LIVE_SECS).await?;
+    let affinity = match user_id {
+        Some(user_id) => GatewayQueries::affinity(pool, user_id, AFFINITY_TTL_SECS).await?,
+        None => None,
+    };
+    Ok(rank(regions, rtts, affinity.as_deref()))
+}
+
+/// Record which node and region this process is, forget the sessions it
+/// held before a restart, and prune old presence
+pub async fn register_node(pool: &PgPool, node_id: &str, region: &str) {
+    let _ = NODE.set(NodeIdentity { node_id: node_id.to_string(), region: region.to_string() });
+
+    if let Err(e) = GatewayQueries::reset_node(pool, node_id).await {
+        tracing::warn!("Failed to reset presence of node {}: {}", node_id, e);
+    }
+
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match GatewayQueries::prune(&pool, KEEP_PRESENCE_SECS).await {
+                Ok(pruned) if pruned > 0 => tracing::debug!("Pruned presence of {} players", pruned),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Presence pruning failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Count a session opened on this node
+pub async fn session_opened(pool: &PgPool, user_id: i64) {
+    let Some(node) = NODE.get() else {
+        return;
+    };
+    GatewayMetrics::session_opened(&node.region, &node.node_id);
+    if let Err(e) = GatewayQueries::session_opened(pool, user_id, &node.node_id, &node.region).await {
+        tracing::warn!("Failed to record presence of user {}: {}", user_id, e);
+    }
+}
+
+pub async fn session_closed(pool: &PgPool, user_id: i64) {
+    let Some(node) = NODE.get() else {
+        return;
+    };
+    GatewayMetrics::session_closed(&node.region, &node.node_id);
+    if let Err(e) = GatewayQueries::session_closed(pool, user_id, &node.node_id).await {
+        tracing::warn!("Failed to clear presence of user {}: {}", user_id, e);
+    }
+}
+
+/// Where a session on a draining node should go, and when
+#[derive(Debug, Clone, Serialize)]
+pub struct Migration {
+    pub url: Option<String>,
+    pub region: Option<String>,
+    pub reconnect_after_ms: u64,
+}
+
+/// The gateway `user_id` should move to, with a random delay within
+/// [`MIGRATION_SPREAD`]
+pub async fn migration(pool: &PgPool, user_id: i64) -> Migration {
+    let recommended = discover(pool, Some(user_id), &HashMap::new())
+        .await
+        .map_err(|e| tracing::warn!("Gateway discovery for migrating user {} failed: {}", user_id, e))
+        .ok()
+        .and_then(|discovery| discovery.recommended);
+
+    if let Some(node) = NODE.get() {
+        GatewayMetrics::migrated(&node.region);
+    }
+    let spread = MIGRATION_SPREAD.as_millis() as u64;
+    Migration {
+        url: recommended.as_ref().map(|gateway| gateway.url.clone()),
+        region: recommended.map(|gateway| gateway.region),
+        reconnect_after_ms: rand::thread_rng().gen_range(0..spread),
+    }
+}
+
+/// Who a forwarded message is for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "to", rename_all = "snake_case")]
+enum ForwardTarget {
+    User { user_id: i64 },
+    Entity { entity: Entity },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Forwarded {
+    origin_node: String,
+    origin_region: String,
+    target: ForwardTarget,
+    message: ServerMessage,
+}
+
+/// Subjects may not contain `.`, `*`, `>` or whitespace inside a token
+fn subject_token(name: &str) -> String {
+    name.chars()
+        .map(|c| if c == '.' || c == '*' || c == '>' || c.is_whitespace() { '_' } else { c })
+        .collect()
+}
+
+fn node_subject(node_id: &str) -> String {
+    format!("{}.node.{}", SUBJECT_PREFIX, subject_token(node_id))
+}
+
+fn entity_subject() -> String {
+    format!("{}.entity", SUBJECT_PREFIX)
+}
+
+/// Connect to `NATS_URL` and deliver events forwarded to this node. Does
+/// nothing without `NATS_URL` or before [`register_node`].
+pub(crate) fn spawn_forwarder(ws_manager: Option<Arc<ConnectionManager>>) {
+    let (Ok(url), Some(node)) = (std::env::var("NATS_URL"), NODE.get()) else {
+        tracing::info!("Cross-node event forwarding disabled");
+        return;
+    };
+
+    tokio::spawn(async move {
+        let client = match async_nats::connect(url.as_str()).await {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!("Cannot reach NATS at {}, events will not be forwarded: {}", url, e);
+                return;
+            }
+        };
+        let _ = FORWARDER.set(client.clone());
+
+        let subscribed = futures::future::try_join(
+            client.subscribe(node_subject(&node.node_id)),
+            client.subscribe(entity_subject()),
+        )
+        .await;
+        let (direct, entities) = match subscribed {
+            Ok(subscribers) => subscribers,
+            Err(e) => {
+                tracing::error!("Failed to subscribe to forwarded events: {}", e);
+                return;
+            }
+        };
+
+        let mut incoming = futures::stream::select(direct, entities);
+        while let Some(message) = incoming.next().await {
+            let forwarded: Forwarded = match serde_json::from_slice(&message.payload) {
+                Ok(forwarded) => forwarded,
+                Err(e) => {
+                    tracing::warn!("Dropping malformed forwarded event: {}", e);
+                    continue;
+                }
+            };
+            if forwarded.origin_node == node.node_id {
+                continue;
+            }
+            let Some(ws_manager) = &ws_manager else {
+                continue;
+            };
+            match forwarded.target {
+                ForwardTarget::User { user_id } => ws_manager.send_to_user(user_id, forwarded.message),
+                ForwardTarget::Entity { entity } => ws_manager.send_to_entity(entity, forwarded.message),
+            }
+        }
+        tracing::warn!("NATS subscription closed, events are no longer forwarded to this node");
+    });
+}
+
+/// Deliver `message` to the player's sessions on this node and forward it to
+/// every other node they are connected to
+pub async fn send_to_user(
+    pool: &PgPool,
+    ws_manager: Option<&ConnectionManager>,
+    user_id: i64,
+    message: ServerMessage,
+) -> anyhow::Result<()> {
+    if let Some(ws_manager) = ws_manager {
+        ws_manager.send_to_user(user_id, message.clone());
+    }
+
+    let (Some(client), Some(node)) = (FORWARDER.get(), NODE.get()) else {
+        return Ok(());
+    };
+    let peers = GatewayQueries::peers(pool, user_id, &node.node_id, LIVE_SECS).await?;
+    if peers.is_empty() {
+        return Ok(());
+    }
+
+    let payload = serde_json::to_vec(&Forwarded {
+        origin_node: node.node_id.clone(),
+        origin_region: node.region.clone(),
+        target: ForwardTarget::User { user_id },
+        message,
+    })?;
+    for peer in peers {
+        client.publish(node_subject(&peer.node_id), payload.clone().into()).await?;
+        GatewayMetrics::forwarded(&node.region, &peer.region);
+    }
+    Ok(())
+}
+
+/// Deliver `message` to the entity's subscribers on every node
+pub async fn send_to_entity(ws_manager: Option<&ConnectionManager>, entity: Entity, message: ServerMessage) -> anyhow::Result<()> {
+    if let Some(ws_manager) = ws_manager {
+        ws_manager.send_to_entity(entity, message.clone());
+    }
+
+    let (Some(client), Some(node)) = (FORWARDER.get(), NODE.get()) else {
+        return Ok(());
+    };
+    let payload = serde_json::to_vec(&Forwarded {
+        origin_node: node.node_id.clone(),
+        origin_region: node.region.clone(),
+        target: ForwardTarget::Entity { entity },
+        message,
+    })?;
+    client.publish(entity_subject(), payload.into()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(name: &str, healthy_nodes: i64, sessions: i64) -> GatewayRegionRow {
+        GatewayRegionRow {
+            region: name.to_string(),
+            ws_url: Some(format!("wss://{}.example.com/ws", name)),
+            healthy_nodes,
+            draining_nodes: 0,
+            sessions,
+        }
+    }
+
+    #[test]
+    fn test_rank_by_rtt_with_affinity() {
+        let regions = || vec![region("eu-west", 2, 100), region("us-east", 1, 10), region("ap-south", 0, 0)];
+
+        let rtts = parse_rtts("eu-west:40, us-east:120,bogus");
+        let discovery = rank(regions(), &rtts, None);
+        let order: Vec<_> = discovery.gateways.iter().map(|gateway| gateway.region.as_str()).collect();
+        assert_eq!(order, vec!["eu-west", "us-east"]);
+        assert_eq!(discovery.recommended.unwrap().region, "eu-west");
+
+        // Close enough: stay where the player was
+        let rtts = parse_rtts("eu-west:40,us-east:80");
+        assert_eq!(rank(regions(), &rtts, Some("us-east")).recommended.unwrap().region, "us-east");
+        // Much slower: move
+        let rtts = parse_rtts("eu-west:40,us-east:120");
+        assert_eq!(rank(regions(), &rtts, Some("us-east")).recommended.unwrap().region, "eu-west");
+        // A region with no healthy node is never recommended
+        assert_eq!(rank(regions(), &HashMap::new(), Some("ap-south")).recommended.unwrap().region, "us-east");
+    }
+
+    #[test]
+    fn test_subjects_are_single_tokens() {
+        assert_eq!(node_subject("api-1.eu.internal"), "he.gateway.node.api-1_eu_internal");
+        let forwarded = Forwarded {
+            origin_node: "a".to_string(),
+            origin_region: "eu-west".to_string(),
+            target: ForwardTarget::User { user_id: 7 },
+            message: ServerMessage { event_type: "contract".to_string(), data: serde_json::json!({}) },
+        };
+        let decoded: Forwarded = serde_json::from_slice(&serde_json::to_vec(&forwarded).unwrap()).unwrap();
+        assert!(matches!(decoded.target, ForwardTarget::User { user_id: 7 }));
+    }
+}
@@ -0,0 +1,623 @@
+//! Account settings handlers
+//!
+//! Email changes need both addresses: the current address confirms the change,
+//! then the new address is verified before the switch. The current address also
+//! gets a revoke link that stays valid for `EMAIL_CHANGE_REVOCATION_HOURS`, even
+//! after the change completed.
+//!
+//! The login history lets the player disown a login, which signs the account
+//! out everywhere and mails a password reset code.
+//!
+//! Username changes also need the password; see [`crate::username`].
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_core::external::PHPMailer;
+use he_database::queries::{
+    DisownLogin, EmailChangeQueries, LoginHistoryQueries, StartEmailChange, UserQueries, UsernameQueries,
+    EMAIL_CHANGE_REVOCATION_HOURS, PASSWORD_RESET_TOKEN_TTL_HOURS,
+};
+use he_game_mechanics::username::RenameDenied;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use std::net::IpAddr;
+use he_database::models::legacy_user_uuid;
+use crate::login_history::{self, LoginCursor};
+use crate::state::AppState;
+use crate::handlers::process::{extract_user_id, require_permission};
+use crate::username::RenamePropagation;
+
+const USERNAMES_PERMISSION: &str = "usernames:view";
+
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+    pub new_email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeUsernameRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct EmailTokenRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginHistoryParams {
+    pub cursor: Option<LoginCursor>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+fn client_ip(req: &HttpRequest) -> IpAddr {
+    req.peer_addr()
+        .map(|addr| addr.ip())
+        .unwrap_or(IpAddr::from([127, 0, 0, 1]))
+}
+
+/// Send a plain-text notice. Mail failures are logged, never surfaced: the
+/// account change itself already happened.
+async fn send_notice(to: String, subject: &'static str, body: String) {
+    let recipient = to.clone();
+    let result = web::block(move || PHPMailer::with_default().send_simple_email(&to, subject, &body, false)).await;
+
+    match result {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => tracing::warn!("Failed to send '{}' to {}: {}", subject, recipient, e),
+        Err(e) => tracing::warn!("Mailer task failed for '{}': {}", subject, e),
+    }
+}
+
+pub async fn get_email_change(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    match EmailChangeQueries::latest_for_user(&state.db.pool, user_id).await {
+        Ok(request) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "request": request
+        })),
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to load email change: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn request_email_change(
+    state: web::Data<AppState>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    data: web::Json<ChangeEmailRequest>,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    let new_email = data.new_email.trim().to_string();
+    if !validator::validate_email(new_email.as_str()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Invalid email address"
+        }));
+    }
+
+    let user = match UserQueries::get_user_by_id(&state.db.pool, user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "User not found"
+            }));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to load user: {}", e)
+            }));
+        }
+    };
+
+    // Re-authenticate: a stolen session alone must not be enough
+    if !UserQueries::verify_password(&user, &data.password).await.unwrap_or(false) {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Invalid password"
+        }));
+    }
+
+    if user.email.eq_ignore_ascii_case(&new_email) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "New email matches the current one"
+        }));
+    }
+
+    match EmailChangeQueries::start(&state.db.pool, user_id, &user.email, &new_email).await {
+        Ok(StartEmailChange::Started { request, confirm_token, revoke_token }) => {
+            audit.log_event(SecurityEvent::EmailChangeRequested {
+                user_id,
+                new_email: new_email.clone(),
+                ip: client_ip(&req),
+            }).await;
+
+            send_notice(
+                user.email.clone(),
+                "Confirm your email change",
+                format!(
+                    "A request was made to change your account email to {}.\n\n\
+                     Confirmation code: {}\n\n\
+                     If this wasn't you, revoke it with this code within {} hours: {}\n",
+                    new_email, confirm_token, EMAIL_CHANGE_REVOCATION_HOURS, revoke_token
+                ),
+            ).await;
+
+            HttpResponse::Accepted().json(serde_json::json!({
+                "success": true,
+                "message": "Check your current email address to confirm the change",
+                "request": request
+            }))
+        }
+        Ok(StartEmailChange::EmailInUse) => {
+            HttpResponse::Conflict().json(serde_json::json!({
+                "success": false,
+                "message": "Email address already in use"
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to start email change: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn confirm_email_change(
+    state: web::Data<AppState>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    data: web::Json<EmailTokenRequest>,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    match EmailChangeQueries::confirm_old_address(&state.db.pool, user_id, &data.token).await {
+        Ok(Some((request, verify_token))) => {
+            audit.log_event(SecurityEvent::EmailChangeConfirmed {
+                user_id,
+                ip: client_ip(&req),
+            }).await;
+
+            send_notice(
+                request.new_email.clone(),
+                "Verify your new email address",
+                format!("Use this code to finish changing your account email: {}\n", verify_token),
+            ).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Check your new email address to verify it",
+                "request": request
+            }))
+        }
+        Ok(None) => {
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "Invalid or expired confirmation code"
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to confirm email change: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn verify_new_email(
+    state: web::Data<AppState>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    data: web::Json<EmailTokenRequest>,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    match EmailChangeQueries::complete(&state.db.pool, user_id, &data.token).await {
+        Ok(Some(request)) => {
+            if let Err(e) = state.auth.logout_all(&legacy_user_uuid(user_id)).await {
+                tracing::error!("Failed to invalidate sessions for user {}: {}", user_id, e);
+            }
+
+            audit.log_event(SecurityEvent::EmailChanged {
+                user_id,
+                old_email: request.old_email.clone(),
+                new_email: request.new_email.clone(),
+                ip: client_ip(&req),
+            }).await;
+
+            send_notice(
+                request.old_email.clone(),
+                "Your account email was changed",
+                format!(
+                    "Your account email is now {}. All sessions were signed out.\n\n\
+                     If this wasn't you, use the revoke code from the earlier email before {}.\n",
+                    request.new_email, request.revocable_until
+                ),
+            ).await;
+            send_notice(
+                request.new_email.clone(),
+                "Your account email was changed",
+                "This address is now the email for your account. Please sign in again.\n".to_string(),
+            ).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Email changed, please sign in again",
+                "request": request
+            }))
+        }
+        Ok(None) => {
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "Invalid or expired verification code"
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to verify email: {}", e)
+            }))
+        }
+    }
+}
+
+/// Public: the owner may be signed out by the time they see the notice
+pub async fn revoke_email_change(
+    state: web::Data<AppState>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    data: web::Json<EmailTokenRequest>,
+) -> HttpResponse {
+    match EmailChangeQueries::revoke(&state.db.pool, &data.token).await {
+        Ok(Some(request)) => {
+            let rolled_back = request.completed_at.is_some();
+            if rolled_back {
+                let user_uuid = legacy_user_uuid(request.user_id);
+                if let Err(e) = state.auth.logout_all(&user_uuid).await {
+                    tracing::error!("Failed to invalidate sessions for user {}: {}", request.user_id, e);
+                }
+            }
+
+            audit.log_event(SecurityEvent::EmailChangeRevoked {
+                user_id: request.user_id,
+                rolled_back,
+                ip: client_ip(&req),
+            }).await;
+
+            let body = if rolled_back {
+                format!("The change to {} was reverted and all sessions were signed out.\n", request.new_email)
+            } else {
+                format!("The pending change to {} was cancelled.\n", request.new_email)
+            };
+            send_notice(request.old_email.clone(), "Email change revoked", body.clone()).await;
+            send_notice(request.new_email.clone(), "Email change revoked", body).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "rolled_back": rolled_back
+            }))
+        }
+        Ok(None) => {
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "Invalid or expired revoke code"
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to revoke email change: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn change_username(
+    state: web::Data<AppState>,
+    audit: web::Data<AuditLogger>,
+    propagation: web::Data<RenamePropagation>,
+    req: HttpRequest,
+    data: web::Json<ChangeUsernameRequest>,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    let user = match UserQueries::get_user_by_id(&state.db.pool, user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return rename_denied(&RenameDenied::UserNotFound),
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to load user: {}", e)
+            }));
+        }
+    };
+
+    // Re-authenticate: a stolen session alone must not be enough
+    if !UserQueries::verify_password(&user, &data.password).await.unwrap_or(false) {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Invalid password"
+        }));
+    }
+
+    match crate::username::change(&state.db.pool, user_id, data.username.trim()).await {
+        Ok(Ok(change)) => {
+            audit.log_event(SecurityEvent::UsernameChanged {
+                user_id,
+                old_username: change.old_login.clone(),
+                new_username: change.new_login.clone(),
+                ip: client_ip(&req),
+            }).await;
+            propagation.propagate(&state.db.pool, state.ws_manager.as_deref(), &change).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "username": change.new_login,
+                "change": change
+            }))
+        }
+        Ok(Err(denied)) => rename_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to change username: {}", e)
+        })),
+    }
+}
+
+fn rename_denied(denied: &RenameDenied) -> HttpResponse {
+    let mut response = match denied {
+        RenameDenied::UserNotFound => HttpResponse::NotFound(),
+        RenameDenied::Taken | RenameDenied::Reserved { .. } => HttpResponse::Conflict(),
+        RenameDenied::Cooldown { .. } => HttpResponse::TooManyRequests(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct FormerHoldersParams {
+    pub name: String,
+}
+
+/// A player's username changes, newest first; moderators only
+pub async fn username_history(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, USERNAMES_PERMISSION).await {
+        return response;
+    }
+
+    match UsernameQueries::history(&state.db.pool, path.into_inner()).await {
+        Ok(changes) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "changes": changes
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load username history: {}", e)
+        })),
+    }
+}
+
+/// Who used to go by a name, for impersonation reports; moderators only
+pub async fn former_holders(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    params: web::Query<FormerHoldersParams>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, USERNAMES_PERMISSION).await {
+        return response;
+    }
+
+    match UsernameQueries::former_holders(&state.db.pool, params.name.trim()).await {
+        Ok(changes) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "changes": changes
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to look up username: {}", e)
+        })),
+    }
+}
+
+/// The caller's sign-in attempts, newest first
+pub async fn get_logins(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    params: web::Query<LoginHistoryParams>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let limit = params.limit.unwrap_or(login_history::DEFAULT_LIMIT);
+    match login_history::page(&state.db.pool, user_id, params.cursor, limit).await {
+        Ok(page) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "logins": page.entries,
+            "next_cursor": page.next_cursor
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load login history: {}", e)
+        })),
+    }
+}
+
+/// "This wasn't me": sign out everywhere and require a new password
+pub async fn disown_login(
+    state: web::Data<AppState>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    match LoginHistoryQueries::disown(&state.db.pool, path.into_inner(), user_id).await {
+        Ok(DisownLogin::Disowned { login, ip_index, reset_token }) => {
+            let user_uuid = legacy_user_uuid(user_id);
+            if let Err(e) = state.auth.logout_all(&user_uuid).await {
+                tracing::error!("Failed to invalidate sessions for user {}: {}", user_id, e);
+            }
+
+            audit.log_event(SecurityEvent::LoginDisowned {
+                user_id,
+                login_id: login.id,
+                ip_masked: login.ip_masked.clone(),
+                ip_index,
+                ip: client_ip(&req),
+            }).await;
+
+            match UserQueries::get_user_by_id(&state.db.pool, user_id).await {
+                Ok(Some(user)) => {
+                    send_notice(
+                        user.email,
+                        "Reset your password",
+                        format!(
+                            "You reported the sign-in from {} at {} as not yours. All sessions were signed out.\n\n\
+                             Choose a new password within {} hours with this code: {}\n",
+                            login.ip_masked,
+                            login.at.format("%Y-%m-%d %H:%M UTC"),
+                            PASSWORD_RESET_TOKEN_TTL_HOURS,
+                            reset_token
+                        ),
+                    ).await;
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to load user {} for the reset notice: {}", user_id, e),
+            }
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "All sessions were signed out. Check your email to choose a new password",
+                "login": login
+            }))
+        }
+        Ok(DisownLogin::AlreadyDisowned) => HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "message": "This login was already reported"
+        })),
+        Ok(DisownLogin::NotFound) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Login not found"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to report login: {}", e)
+        })),
+    }
+}
+
+/// Public: set a new password with the code mailed after a disowned login
+pub async fn reset_password(
+    state: web::Data<AppState>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    data: web::Json<ResetPasswordRequest>,
+) -> HttpResponse {
+    let passwords = he_auth::PasswordManager::new(he_auth::PasswordConfig::from_env());
+    if let Err(e) = passwords.validate_password(&data.new_password) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": e.to_string()
+        }));
+    }
+
+    match LoginHistoryQueries::reset_password(&state.db.pool, &data.token, &data.new_password).await {
+        Ok(Some(user_id)) => {
+            audit.log_event(SecurityEvent::PasswordChange { user_id, ip: client_ip(&req) }).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Password changed; sign in with the new one"
+            }))
+        }
+        Ok(None) => HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Invalid or expired reset code"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to reset password: {}", e)
+        })),
+    }
+}
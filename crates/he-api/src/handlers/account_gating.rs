@@ -0,0 +1,223 @@
+//! New-account restriction handlers
+//!
+//! Players see the restrictions on their account and what lifts them. Staff
+//! holding `accounts:verify` verify accounts or exempt them from single
+//! restrictions. The helpers here gate the handlers of restricted features.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::AccountGatingQueries;
+use he_game_mechanics::account_gating::{GatingDenied, Restriction};
+use he_helix_henforcer::{cache, HenforcerError, HenforcerResult, Relay, StandardResult};
+use serde::Deserialize;
+use crate::account_gating::{self, DENIED_KEY};
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::handlers::process::require_permission;
+
+const VERIFY_PERMISSION: &str = "accounts:verify";
+
+#[derive(Deserialize)]
+pub struct OverrideRequest {
+    #[serde(default)]
+    pub verified: bool,
+    /// Restriction names, e.g. `trading`
+    #[serde(default)]
+    pub exempt: Vec<String>,
+    pub note: Option<String>,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+/// Turn a failed gating henforcer into the response refusing the caller
+fn refused(user_id: i64, error: HenforcerError, relay: Relay) -> HttpResponse {
+    let denied = relay.get(DENIED_KEY).and_then(|d| serde_json::from_value::<GatingDenied>(d.clone()).ok());
+    match (error, denied) {
+        (HenforcerError::AccessDenied { .. }, Some(denied)) => {
+            let mut response = match denied {
+                GatingDenied::ChatLimited { .. } => HttpResponse::TooManyRequests(),
+                _ => HttpResponse::Forbidden(),
+            };
+            response.json(serde_json::json!({
+                "success": false,
+                "message": denied.message(),
+                "denied": denied
+            }))
+        }
+        (HenforcerError::NotFound { .. }, _) => unauthorized(),
+        (e, _) => {
+            tracing::error!("Account gating check failed for user {}: {}", user_id, e);
+            HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "success": false,
+                "message": "Account checks unavailable"
+            }))
+        }
+    }
+}
+
+fn henforced(user_id: i64, result: StandardResult) -> Result<i64, HttpResponse> {
+    match result {
+        HenforcerResult::Ok(_) => Ok(user_id),
+        HenforcerResult::Err(e, relay) => Err(refused(user_id, e, relay)),
+    }
+}
+
+/// Resolve the caller and check `restriction` does not apply to them
+pub(crate) async fn require_unrestricted(
+    state: &web::Data<AppState>,
+    req: &HttpRequest,
+    restriction: Restriction,
+) -> Result<i64, HttpResponse> {
+    let Some(user_id) = extract_user_id(state, req).await else {
+        return Err(unauthorized());
+    };
+    henforced(user_id, account_gating::unrestricted(&state.db.pool, user_id, restriction).await)
+}
+
+/// Check the caller may post `body` in chat
+pub(crate) async fn require_chat(state: &web::Data<AppState>, user_id: i64, body: &str) -> Result<(), HttpResponse> {
+    henforced(user_id, account_gating::can_chat(&state.db.pool, user_id, body).await).map(|_| ())
+}
+
+/// Restrictions on the caller's account and what lifts each
+pub async fn my_restrictions(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match account_gating::restrictions(&state.db.pool, user_id).await {
+        Ok(Some(restrictions)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "restrictions": restrictions
+        })),
+        Ok(None) => unauthorized(),
+        Err(e) => failed("load restrictions", e),
+    }
+}
+
+/// A player's restrictions and the override staff set, if any
+pub async fn get_override(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, VERIFY_PERMISSION).await {
+        return response;
+    }
+
+    let user_id = path.into_inner();
+    let restrictions = match account_gating::restrictions(&state.db.pool, user_id).await {
+        Ok(Some(restrictions)) => restrictions,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "User not found"
+            }));
+        }
+        Err(e) => return failed("load restrictions", e),
+    };
+    match AccountGatingQueries::get_override(&state.db.pool, user_id).await {
+        Ok(gating_override) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "restrictions": restrictions,
+            "override": gating_override
+        })),
+        Err(e) => failed("load override", e),
+    }
+}
+
+/// Verify a player, or exempt them from single restrictions
+pub async fn set_override(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Json<OverrideRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, VERIFY_PERMISSION).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+
+    if let Some(unknown) = data.exempt.iter().find(|r| Restriction::from_str(r).is_none()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Unknown restriction {}", unknown)
+        }));
+    }
+
+    let user_id = path.into_inner();
+    match AccountGatingQueries::set_override(
+        &state.db.pool,
+        user_id,
+        data.verified,
+        &data.exempt,
+        data.note.as_deref(),
+        admin_id,
+    )
+    .await
+    {
+        Ok(Some(gating_override)) => {
+            cache::invalidate(account_gating::STANDING_OBJECT, user_id);
+            tracing::info!(
+                "Admin {} set account override for user {}: verified={} exempt={:?}",
+                admin_id,
+                user_id,
+                gating_override.verified,
+                gating_override.exempt
+            );
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "override": gating_override
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "User not found"
+        })),
+        Err(e) => failed("set override", e),
+    }
+}
+
+/// Put a player back under the restrictions their level and age call for
+pub async fn clear_override(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, VERIFY_PERMISSION).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+
+    let user_id = path.into_inner();
+    match AccountGatingQueries::clear_override(&state.db.pool, user_id).await {
+        Ok(true) => {
+            cache::invalidate(account_gating::STANDING_OBJECT, user_id);
+            tracing::info!("Admin {} cleared account override for user {}", admin_id, user_id);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Override cleared"
+            }))
+        }
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "No override for this user"
+        })),
+        Err(e) => failed("clear override", e),
+    }
+}
@@ -0,0 +1,52 @@
+//! Account activity timeline handler
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use serde::Deserialize;
+use crate::activity::{self, ActivityCategory, ActivityCursor};
+use crate::state::AppState;
+use crate::handlers::process::extract_user_id;
+
+#[derive(Deserialize)]
+pub struct ActivityParams {
+    /// Comma-separated categories; all of them when absent
+    pub categories: Option<String>,
+    pub cursor: Option<ActivityCursor>,
+    pub limit: Option<i64>,
+}
+
+/// The caller's activity, newest first
+pub async fn timeline(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    params: web::Query<ActivityParams>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let categories = match ActivityCategory::parse_list(params.categories.as_deref().unwrap_or("")) {
+        Ok(categories) => categories,
+        Err(message) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": message
+            }));
+        }
+    };
+    let limit = params.limit.unwrap_or(activity::DEFAULT_LIMIT);
+
+    match activity::timeline(&state.db.pool, user_id, &categories, params.cursor, limit).await {
+        Ok(page) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "entries": page.entries,
+            "next_cursor": page.next_cursor
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load activity: {}", e)
+        })),
+    }
+}
@@ -0,0 +1,445 @@
+//! Admin dashboard handlers
+//!
+//! HTML pages and form posts for [`crate::admin_dashboard`]. Everything but
+//! the sign-in form needs a dashboard session whose user still holds the
+//! `admin:dashboard` permission; forms must echo the session's CSRF token.
+//! Sign-in attempts, flag changes and acknowledged wealth spikes are written
+//! to the audit trail.
+
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, NaiveDate, Utc};
+use he_auth::AuthService;
+use he_database::queries::{AdminDashboardQueries, AnalyticsQueries, CronJobQueries, EconomyStreamQueries, UserQueries};
+use he_monitoring::{AlertManager, AlertMetric};
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use he_database::models::legacy_user_uuid;
+use crate::admin_dashboard::{self, AdminSession, EconomyView, Overview, SESSION_COOKIE, SESSION_TTL};
+use crate::live_ops::LiveOps;
+use crate::state::AppState;
+
+const DASHBOARD_PERMISSION: &str = "admin:dashboard";
+/// Nodes not seen for this long do not count towards players online
+const LIVE_SECS: i64 = 30;
+const AUDIT_EVENTS: i64 = 50;
+const MAX_FLAG_NAME_LEN: usize = 64;
+/// Days of actives on the overview
+const OVERVIEW_DAYS: i64 = 7;
+const DEFAULT_ANALYTICS_DAYS: i64 = 30;
+const MAX_ANALYTICS_DAYS: i64 = 366;
+/// Minutes of money flow on the economy page
+const ECONOMY_MINUTES: i64 = 60;
+const TOP_EARNERS: i64 = 20;
+const ANOMALIES: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct SignInForm {
+    pub email: String,
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct FlagForm {
+    pub csrf: String,
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SignOutForm {
+    pub csrf: String,
+}
+
+#[derive(Deserialize)]
+pub struct AcknowledgeForm {
+    pub csrf: String,
+}
+
+#[derive(Deserialize)]
+pub struct AnalyticsQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+fn html(body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(body)
+}
+
+fn redirect(to: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, to))
+        .finish()
+}
+
+fn sign_in_failed(message: &str) -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(admin_dashboard::render_sign_in(Some(message.to_string())))
+}
+
+fn session_cookie(token: &str, max_age: CookieDuration) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, token.to_string())
+        .path("/admin")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .max_age(max_age)
+        .finish()
+}
+
+/// The request's dashboard session, if it is open and its user may still
+/// use the dashboard
+async fn current_session(auth: &AuthService, req: &HttpRequest) -> Option<AdminSession> {
+    let token = req.cookie(SESSION_COOKIE)?.value().to_string();
+    let session = admin_dashboard::session(&token)?;
+
+    match auth.check_permission(&legacy_user_uuid(session.user_id), DASHBOARD_PERMISSION).await {
+        Ok(true) => Some(session),
+        Ok(false) => {
+            admin_dashboard::close_session(&token);
+            None
+        }
+        Err(e) => {
+            tracing::error!("Permission check failed for admin {}: {}", session.user_id, e);
+            None
+        }
+    }
+}
+
+async fn audit_command(audit: &AuditLogger, admin_id: i64, command: &str, detail: String, accepted: bool) {
+    audit.log_event(SecurityEvent::LiveOpsCommand {
+        admin_id,
+        command: command.to_string(),
+        detail,
+        accepted,
+    }).await;
+}
+
+pub async fn sign_in_page(auth: web::Data<AuthService>, req: HttpRequest) -> HttpResponse {
+    if current_session(&auth, &req).await.is_some() {
+        return redirect("/admin");
+    }
+    html(admin_dashboard::render_sign_in(None))
+}
+
+/// Password, dashboard permission and MFA code, in that order; every
+/// failure gets the same answer
+pub async fn sign_in(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    form: web::Form<SignInForm>,
+) -> HttpResponse {
+    const REJECTED: &str = "Invalid credentials, permission or MFA code";
+
+    let user = match UserQueries::get_user_by_email(&state.db.pool, form.email.trim()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return sign_in_failed(REJECTED),
+        Err(e) => {
+            tracing::error!("Dashboard sign-in lookup failed: {}", e);
+            return sign_in_failed("Sign-in is unavailable, try again shortly");
+        }
+    };
+    if !UserQueries::verify_password(&user, &form.password).await.unwrap_or(false) {
+        return sign_in_failed(REJECTED);
+    }
+
+    let admin = legacy_user_uuid(user.id);
+    match auth.check_permission(&admin, DASHBOARD_PERMISSION).await {
+        Ok(true) => {}
+        Ok(false) => {
+            audit_command(&audit, user.id, "dashboard_sign_in", "missing permission".to_string(), false).await;
+            return sign_in_failed(REJECTED);
+        }
+        Err(e) => {
+            tracing::error!("Permission check failed for user {}: {}", user.id, e);
+            return sign_in_failed("Sign-in is unavailable, try again shortly");
+        }
+    }
+
+    let verified = auth.verify_mfa(&admin, form.code.trim()).await.unwrap_or_else(|e| {
+        tracing::error!("MFA verification failed for user {}: {}", user.id, e);
+        false
+    });
+    audit_command(&audit, user.id, "dashboard_sign_in", String::new(), verified).await;
+    if !verified {
+        return sign_in_failed(REJECTED);
+    }
+
+    let token = admin_dashboard::open_session(user.id);
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, "/admin"))
+        .cookie(session_cookie(&token, CookieDuration::seconds(SESSION_TTL.as_secs() as i64)))
+        .finish()
+}
+
+pub async fn overview(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    live_ops: web::Data<LiveOps>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(session) = current_session(&auth, &req).await else {
+        return redirect("/admin/login");
+    };
+
+    let pool = &state.db.pool;
+    let today = Utc::now().date_naive();
+    let loaded = futures::try_join!(
+        AdminDashboardQueries::online_players(pool, LIVE_SECS),
+        AdminDashboardQueries::processes_by_type(pool),
+        AdminDashboardQueries::recent_audit(pool, AUDIT_EVENTS),
+        CronJobQueries::list(pool),
+        AnalyticsQueries::activity_range(pool, today - Duration::days(OVERVIEW_DAYS), today),
+    );
+    let (online_players, processes, audit, jobs, mut activity) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to load dashboard: {}", e));
+        }
+    };
+
+    let mut flags: Vec<_> = live_ops.flags().await.values().cloned().collect();
+    flags.sort_by(|a, b| a.name.cmp(&b.name));
+
+    activity.reverse();
+
+    let overview = Overview {
+        node_id: live_ops.node_id().to_string(),
+        online_players,
+        processes,
+        audit,
+        jobs,
+        activity,
+        flags,
+        generated_at: Utc::now(),
+    };
+    html(admin_dashboard::render_overview(overview, session.csrf))
+}
+
+async fn load_economy(state: &AppState, node_id: String) -> anyhow::Result<EconomyView> {
+    let pool = &state.db.pool;
+    let since = Utc::now() - Duration::minutes(ECONOMY_MINUTES);
+    let (minutes, reasons, top_earners, anomalies) = futures::try_join!(
+        EconomyStreamQueries::minutes(pool, since),
+        EconomyStreamQueries::reasons(pool, since),
+        EconomyStreamQueries::top_earners(pool, since, TOP_EARNERS),
+        EconomyStreamQueries::open_anomalies(pool, ANOMALIES),
+    )?;
+    let thresholds = AlertManager::thresholds();
+
+    Ok(EconomyView {
+        node_id,
+        minutes,
+        minted_threshold: thresholds.get(AlertMetric::MoneyMintedPerMinute),
+        sunk_threshold: thresholds.get(AlertMetric::MoneySunkPerMinute),
+        reasons,
+        top_earners,
+        anomalies,
+        generated_at: Utc::now(),
+    })
+}
+
+/// Money created and destroyed per minute, top earners and wealth spikes
+/// over the last hour
+pub async fn economy(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    live_ops: web::Data<LiveOps>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(session) = current_session(&auth, &req).await else {
+        return redirect("/admin/login");
+    };
+
+    match load_economy(&state, live_ops.node_id().to_string()).await {
+        Ok(view) => html(admin_dashboard::render_economy(view, session.csrf)),
+        Err(e) => HttpResponse::InternalServerError()
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("Failed to load economy: {}", e)),
+    }
+}
+
+/// The economy page's figures as JSON, for polling
+pub async fn economy_live(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    live_ops: web::Data<LiveOps>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if current_session(&auth, &req).await.is_none() {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Dashboard session required"
+        }));
+    }
+
+    match load_economy(&state, live_ops.node_id().to_string()).await {
+        Ok(view) => HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(serde_json::json!({
+                "success": true,
+                "minutes": view.minutes,
+                "thresholds": {
+                    "minted_per_minute": view.minted_threshold,
+                    "sunk_per_minute": view.sunk_threshold
+                },
+                "reasons": view.reasons,
+                "top_earners": view.top_earners,
+                "anomalies": view.anomalies,
+                "generated_at": view.generated_at
+            })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load economy: {}", e)
+        })),
+    }
+}
+
+/// Mark a wealth spike as looked at
+pub async fn acknowledge_anomaly(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    form: web::Form<AcknowledgeForm>,
+) -> HttpResponse {
+    let Some(session) = current_session(&auth, &req).await else {
+        return redirect("/admin/login");
+    };
+    if form.csrf != session.csrf {
+        return HttpResponse::Forbidden().body("Invalid form token");
+    }
+
+    let anomaly_id = path.into_inner();
+    match EconomyStreamQueries::acknowledge(&state.db.pool, anomaly_id, session.user_id).await {
+        Ok(acknowledged) => {
+            audit_command(&audit, session.user_id, "acknowledge_wealth_spike", anomaly_id.to_string(), acknowledged).await;
+            redirect("/admin/economy")
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("Failed to acknowledge: {}", e)),
+    }
+}
+
+/// he-cron's daily aggregates for the dashboard as JSON: actives, funnel
+/// cohorts and economy per day from `from` to `to`, the last 30 days by
+/// default
+pub async fn analytics(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    query: web::Query<AnalyticsQuery>,
+) -> HttpResponse {
+    if current_session(&auth, &req).await.is_none() {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Dashboard session required"
+        }));
+    }
+
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = query.from.unwrap_or(to - Duration::days(DEFAULT_ANALYTICS_DAYS - 1));
+    if from > to || (to - from).num_days() >= MAX_ANALYTICS_DAYS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Pick a range of at most {} days", MAX_ANALYTICS_DAYS)
+        }));
+    }
+
+    let pool = &state.db.pool;
+    let loaded = futures::try_join!(
+        AnalyticsQueries::activity_range(pool, from, to),
+        AnalyticsQueries::funnel_range(pool, from, to),
+        AnalyticsQueries::economy_range(pool, from, to),
+    );
+    match loaded {
+        Ok((activity, funnel, economy)) => HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(serde_json::json!({
+                "success": true,
+                "from": from,
+                "to": to,
+                "activity": activity,
+                "funnel": funnel,
+                "economy": economy
+            })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load analytics: {}", e)
+        })),
+    }
+}
+
+pub async fn toggle_flag(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    live_ops: web::Data<LiveOps>,
+    req: HttpRequest,
+    form: web::Form<FlagForm>,
+) -> HttpResponse {
+    let Some(session) = current_session(&auth, &req).await else {
+        return redirect("/admin/login");
+    };
+    if form.csrf != session.csrf {
+        return HttpResponse::Forbidden().body("Invalid form token");
+    }
+    let name = form.name.trim();
+    if name.is_empty() || name.len() > MAX_FLAG_NAME_LEN {
+        return HttpResponse::BadRequest().body("Invalid flag name");
+    }
+
+    let detail = format!("{}={}", name, form.enabled);
+    match live_ops.set_flag(&state.db.pool, name, form.enabled, session.user_id).await {
+        Ok(()) => {
+            audit_command(&audit, session.user_id, "set_flag", detail, true).await;
+            redirect("/admin")
+        }
+        Err(e) => {
+            audit_command(&audit, session.user_id, "set_flag", detail, false).await;
+            HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to set flag: {}", e))
+        }
+    }
+}
+
+pub async fn sign_out(req: HttpRequest, form: web::Form<SignOutForm>) -> HttpResponse {
+    if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+        let token = cookie.value();
+        if admin_dashboard::session(token).is_some_and(|session| session.csrf == form.csrf) {
+            admin_dashboard::close_session(token);
+        }
+    }
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, "/admin/login"))
+        .cookie(session_cookie("", CookieDuration::ZERO))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_cookie_stays_on_the_dashboard() {
+        let cookie = session_cookie("token", CookieDuration::minutes(15));
+        assert_eq!(cookie.name(), SESSION_COOKIE);
+        assert_eq!(cookie.path(), Some("/admin"));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+    }
+}
@@ -0,0 +1,225 @@
+//! Cold storage handlers
+//!
+//! Players page through their archived processes and the archived logs of
+//! their servers, one bounded time window at a time so each request scans
+//! only a few monthly partitions. Archived rows never change, so pages may be
+//! cached. Operators holding `archive:manage` see the archive job's recent
+//! passes and tune each table's retention window.
+
+use actix_web::{http::header, web, HttpResponse, HttpRequest};
+use chrono::{DateTime, Duration, Utc};
+use he_auth::AuthService;
+use he_database::queries::{ArchiveQueries, ArchiveTable};
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::handlers::game::extract_user_id;
+use crate::handlers::process::require_permission;
+use crate::state::AppState;
+
+const MANAGE_PERMISSION: &str = "archive:manage";
+const DEFAULT_WINDOW_DAYS: i64 = 31;
+const MAX_WINDOW_DAYS: i64 = 92;
+const DEFAULT_PAGE: i64 = 50;
+const MAX_PAGE: i64 = 200;
+const RUNS_LISTED: i64 = 50;
+const MIN_BATCH_SIZE: i32 = 100;
+const MAX_BATCH_SIZE: i32 = 50_000;
+
+/// A time window and keyset page; pass the previous page's `next` back as
+/// `before` and `before_id`
+#[derive(Deserialize)]
+pub struct ArchiveQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct RetentionRequest {
+    pub retention_days: i32,
+    pub batch_size: i32,
+    pub enabled: bool,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "success": false,
+        "message": message
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+struct Page {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    before: Option<(DateTime<Utc>, i64)>,
+    limit: i64,
+}
+
+impl ArchiveQuery {
+    /// The window ending at `to` (now by default), at most
+    /// [`MAX_WINDOW_DAYS`] long
+    fn page(&self) -> Result<Page, HttpResponse> {
+        let to = self.to.unwrap_or_else(Utc::now);
+        let from = self.from.unwrap_or(to - Duration::days(DEFAULT_WINDOW_DAYS));
+        if from >= to || to - from > Duration::days(MAX_WINDOW_DAYS) {
+            return Err(bad_request(format!("Pick a window of at most {} days", MAX_WINDOW_DAYS)));
+        }
+        let before = match (self.before, self.before_id) {
+            (Some(at), Some(id)) => Some((at, id)),
+            (None, None) => None,
+            _ => return Err(bad_request("before and before_id go together".to_string())),
+        };
+        Ok(Page { from, to, before, limit: self.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE) })
+    }
+}
+
+fn page_response<T: serde::Serialize>(
+    key: &str,
+    rows: &[T],
+    limit: i64,
+    cursor: impl Fn(&T) -> (DateTime<Utc>, i64),
+) -> HttpResponse {
+    // A short page is the last one
+    let next = rows.last().filter(|_| rows.len() as i64 == limit).map(cursor);
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "private, max-age=300"))
+        .json(serde_json::json!({
+            "success": true,
+            key: rows,
+            "next": next.map(|(before, before_id)| serde_json::json!({ "before": before, "before_id": before_id }))
+        }))
+}
+
+/// The caller's archived processes, newest first
+pub async fn processes(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ArchiveQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let page = match query.page() {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+
+    match ArchiveQueries::processes(&state.db.pool, user_id, page.from, page.to, page.before, page.limit).await {
+        Ok(rows) => page_response("processes", &rows, page.limit, |row| (row.completed_at, row.pid)),
+        Err(e) => failed("load archived processes", e),
+    }
+}
+
+/// Archived logs of the caller's servers, newest first
+pub async fn logs(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ArchiveQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let page = match query.page() {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+
+    match ArchiveQueries::logs(&state.db.pool, user_id, page.from, page.to, page.before, page.limit).await {
+        Ok(rows) => page_response("logs", &rows, page.limit, |row| (row.created_at, row.id)),
+        Err(e) => failed("load archived logs", e),
+    }
+}
+
+/// Retention of every table and the archive job's latest passes
+pub async fn status(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        return response;
+    }
+
+    let pool = &state.db.pool;
+    match futures::try_join!(ArchiveQueries::retention(pool), ArchiveQueries::runs(pool, RUNS_LISTED)) {
+        Ok((retention, runs)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "retention": retention,
+            "runs": runs
+        })),
+        Err(e) => failed("load archive status", e),
+    }
+}
+
+/// Set one table's retention window, batch size and whether it is archived
+pub async fn set_retention(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<RetentionRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let Some(table) = ArchiveTable::from_str(&path) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Unknown archive table"
+        }));
+    };
+    if body.retention_days < table.min_retention_days() {
+        return bad_request(format!(
+            "{} must be kept for at least {} days",
+            table.as_str(),
+            table.min_retention_days()
+        ));
+    }
+    if !(MIN_BATCH_SIZE..=MAX_BATCH_SIZE).contains(&body.batch_size) {
+        return bad_request(format!("Batch size must be between {} and {}", MIN_BATCH_SIZE, MAX_BATCH_SIZE));
+    }
+
+    let saved = ArchiveQueries::set_retention(
+        &state.db.pool,
+        table,
+        body.retention_days,
+        body.batch_size,
+        body.enabled,
+        admin_id,
+    )
+    .await;
+    match saved {
+        Ok(retention) => {
+            audit.log_event(SecurityEvent::ArchiveRetentionChanged {
+                admin_id,
+                table: table.as_str().to_string(),
+                retention_days: body.retention_days,
+                batch_size: body.batch_size,
+                enabled: body.enabled,
+            }).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "retention": retention
+            }))
+        }
+        Err(e) => failed("save retention", e),
+    }
+}
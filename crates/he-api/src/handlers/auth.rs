@@ -2,14 +2,20 @@
 
 use actix_web::{web, HttpResponse, HttpRequest};
 use serde::{Deserialize, Serialize};
+use crate::login_history::{self, LoginContext};
+use crate::referrals;
 use crate::state::AppState;
-use he_database::queries::UserQueries;
+use he_database::queries::{LoginHistoryQueries, TutorialQueries, UserQueries};
+use he_helix_security::{AuditLogger, IpPolicy, PolicyScope, SecurityEvent};
 
 #[derive(Deserialize)]
 pub struct RegisterRequest {
     pub login: String,
     pub email: String,
     pub password: String,
+    /// Another player's referral code
+    #[serde(default)]
+    pub referral_code: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -25,10 +31,52 @@ pub struct AuthResponse {
     pub message: String,
 }
 
+/// The client's device identifier, compared across accounts by the
+/// referral checks
+fn device_id(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(referrals::DEVICE_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
 pub async fn register(
     state: web::Data<AppState>,
+    policy: web::Data<IpPolicy>,
+    audit: web::Data<AuditLogger>,
+    http_req: HttpRequest,
     req: web::Json<RegisterRequest>,
 ) -> HttpResponse {
+    // Per-country / network registration restrictions
+    let peer_ip = http_req.peer_addr().map(|addr| addr.ip());
+    if let Some(ip) = peer_ip {
+        if !policy.check_and_audit(ip, PolicyScope::Registration, &audit).await.allowed {
+            return HttpResponse::Forbidden().json(AuthResponse {
+                success: false,
+                token: None,
+                message: "Registration is not available from your location".to_string(),
+            });
+        }
+    }
+
+    // Blocked names and names given up recently are not for new accounts either
+    match crate::username::check_new(&state.db.pool, &req.login).await {
+        Ok(Ok(())) => {}
+        Ok(Err(denied)) => {
+            return HttpResponse::BadRequest().json(AuthResponse {
+                success: false,
+                token: None,
+                message: denied.message(),
+            });
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(AuthResponse {
+                success: false,
+                token: None,
+                message: format!("Failed to check username: {}", e),
+            });
+        }
+    }
+
     // Check if user exists
     let existing = UserQueries::get_user_by_email(&state.db.pool, &req.email).await;
 
@@ -43,6 +91,20 @@ pub async fn register(
     // Create user
     match UserQueries::create_user(&state.db.pool, &req.login, &req.email, &req.password).await {
         Ok(user) => {
+            if let Err(e) = TutorialQueries::start(&state.db.pool, user.id).await {
+                tracing::warn!("Failed to start tutorial for user {}: {}", user.id, e);
+            }
+            let device = device_id(&http_req);
+            if let Some(code) = req.referral_code.as_deref() {
+                if let Err(e) = referrals::attribute(&state.db.pool, user.id, code, peer_ip, device).await {
+                    tracing::warn!("Failed to attribute referral for user {}: {}", user.id, e);
+                }
+            }
+            if let Some(ip) = peer_ip {
+                if let Err(e) = referrals::record_sign_in(&state.db.pool, user.id, ip, device).await {
+                    tracing::warn!("Failed to record sign-in for user {}: {}", user.id, e);
+                }
+            }
             HttpResponse::Ok().json(AuthResponse {
                 success: true,
                 token: None,
@@ -59,11 +121,38 @@ pub async fn register(
     }
 }
 
+/// Add the attempt to the player's login history; never fails the login
+async fn record_attempt(
+    state: &AppState,
+    policy: &IpPolicy,
+    user_id: i64,
+    context: &LoginContext,
+    failure_reason: Option<&str>,
+) {
+    if let Err(e) = login_history::record(&state.db.pool, policy, user_id, context, failure_reason).await {
+        tracing::warn!("Failed to record login attempt for user {}: {}", user_id, e);
+    }
+}
+
+async fn audit_failure(audit: &AuditLogger, username: &str, ip: std::net::IpAddr, reason: &str) {
+    let attempt_count = audit.get_failed_login_attempts(ip, 5).await.unwrap_or(0);
+    audit.log_event(SecurityEvent::LoginFailure {
+        username: username.to_string(),
+        ip,
+        reason: reason.to_string(),
+        attempt_count: attempt_count + 1,
+    }).await;
+}
+
 pub async fn login(
     state: web::Data<AppState>,
+    policy: web::Data<IpPolicy>,
+    audit: web::Data<AuditLogger>,
     req: web::Json<LoginRequest>,
     http_req: HttpRequest,
 ) -> HttpResponse {
+    let context = LoginContext::from_request(&http_req);
+
     // Get user
     let user = match UserQueries::get_user_by_email(&state.db.pool, &req.email).await {
         Ok(Some(u)) => u,
@@ -87,6 +176,8 @@ pub async fn login(
     let valid = UserQueries::verify_password(&user, &req.password).await.unwrap_or(false);
 
     if !valid {
+        record_attempt(&state, &policy, user.id, &context, Some("invalid_password")).await;
+        audit_failure(&audit, &req.email, context.ip, "Invalid password").await;
         return HttpResponse::Unauthorized().json(AuthResponse {
             success: false,
             token: None,
@@ -94,6 +185,52 @@ pub async fn login(
         });
     }
 
+    // Bans are shared with the forum
+    match UserQueries::active_ban(&state.db.pool, user.id).await {
+        Ok(None) => {}
+        Ok(Some((reason, until))) => {
+            record_attempt(&state, &policy, user.id, &context, Some("banned")).await;
+            audit_failure(&audit, &req.email, context.ip, "Account banned").await;
+            let until = until
+                .map(|until| format!(" until {}", until.format("%Y-%m-%d %H:%M UTC")))
+                .unwrap_or_default();
+            let reason = if reason.is_empty() { String::new() } else { format!(": {}", reason) };
+            return HttpResponse::Forbidden().json(AuthResponse {
+                success: false,
+                token: None,
+                message: format!("This account is banned{}{}", until, reason),
+            });
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(AuthResponse {
+                success: false,
+                token: None,
+                message: "Login failed".to_string(),
+            });
+        }
+    }
+
+    // A disowned login locks the account until the password is reset
+    match LoginHistoryQueries::reset_required(&state.db.pool, user.id).await {
+        Ok(false) => {}
+        Ok(true) => {
+            record_attempt(&state, &policy, user.id, &context, Some("password_reset_required")).await;
+            audit_failure(&audit, &req.email, context.ip, "Password reset required").await;
+            return HttpResponse::Forbidden().json(AuthResponse {
+                success: false,
+                token: None,
+                message: "A password reset is required; use the code sent to your email".to_string(),
+            });
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(AuthResponse {
+                success: false,
+                token: None,
+                message: "Login failed".to_string(),
+            });
+        }
+    }
+
     // Get client IP
     let client_ip = http_req
         .connection_info()
@@ -103,12 +240,25 @@ pub async fn login(
 
     // Update last login
     let _ = UserQueries::update_last_login(&state.db.pool, user.id, &client_ip).await;
+    if let Some(ip) = http_req.peer_addr().map(|addr| addr.ip()) {
+        if let Err(e) = referrals::record_sign_in(&state.db.pool, user.id, ip, device_id(&http_req)).await {
+            tracing::warn!("Failed to record sign-in for user {}: {}", user.id, e);
+        }
+    }
 
     // Generate token using auth service
     let auth_result = state.auth.authenticate(&req.email, &req.password, Some(client_ip)).await;
 
     match auth_result {
         Ok(he_auth::AuthenticationResult::Success { token, .. }) => {
+            crate::live_ops::record_login();
+            record_attempt(&state, &policy, user.id, &context, None).await;
+            audit.log_event(SecurityEvent::LoginSuccess {
+                user_id: user.id,
+                username: user.login.clone(),
+                ip: context.ip,
+                session_id: uuid::Uuid::new_v4().to_string(),
+            }).await;
             HttpResponse::Ok().json(AuthResponse {
                 success: true,
                 token: Some(token),
@@ -116,6 +266,8 @@ pub async fn login(
             })
         }
         _ => {
+            record_attempt(&state, &policy, user.id, &context, Some("authentication_failed")).await;
+            audit_failure(&audit, &req.email, context.ip, "Authentication failed").await;
             HttpResponse::Unauthorized().json(AuthResponse {
                 success: false,
                 token: None,
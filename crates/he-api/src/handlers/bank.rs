@@ -1,14 +1,25 @@
 //! Banking handlers
+//!
+//! Players keep accounts at several NPC banks and move money between them.
+//! A transfer out of someone else's account is a theft, allowed to players
+//! who hacked its bank; the victim follows the money from their thefts until
+//! it is laundered.
 
 use actix_web::{web, HttpResponse, HttpRequest};
-use serde::{Deserialize, Serialize};
+use he_database::queries::BankNetworkQueries;
+use he_game_mechanics::banking::{LaunderDenied, OpenDenied, TransferDenied};
+use serde::Deserialize;
+use crate::banking;
 use crate::state::AppState;
 use crate::handlers::game::extract_user_id;
 
-#[derive(Serialize)]
-pub struct AccountInfo {
-    pub account_number: String,
-    pub balance: i64,
+const DEFAULT_TRANSFERS: i64 = 50;
+const MAX_TRANSFERS: i64 = 200;
+const THEFTS_SIZE: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct OpenAccountRequest {
+    pub bank_id: i64,
 }
 
 #[derive(Deserialize)]
@@ -18,77 +29,236 @@ pub struct TransferRequest {
     pub amount: i64,
 }
 
+#[derive(Deserialize)]
+pub struct LaunderRequest {
+    pub account: String,
+    /// The bank laundering the money
+    pub bank_id: i64,
+    pub amount: i64,
+}
+
+#[derive(Deserialize)]
+pub struct TransfersQuery {
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn open_denied(denied: &OpenDenied) -> HttpResponse {
+    let mut response = match denied {
+        OpenDenied::BankNotFound => HttpResponse::NotFound(),
+        OpenDenied::AlreadyCustomer | OpenDenied::TooManyAccounts { .. } => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn transfer_denied(denied: &TransferDenied) -> HttpResponse {
+    let mut response = match denied {
+        TransferDenied::AccountNotFound => HttpResponse::NotFound(),
+        TransferDenied::NoAccess => HttpResponse::Forbidden(),
+        TransferDenied::InsufficientFunds { .. } => HttpResponse::PaymentRequired(),
+        TransferDenied::TheftCooldown { .. } => HttpResponse::TooManyRequests(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn launder_denied(denied: &LaunderDenied) -> HttpResponse {
+    let mut response = match denied {
+        LaunderDenied::AccountNotFound | LaunderDenied::BankNotFound => HttpResponse::NotFound(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// The banks players can hold accounts at, with their fees
+pub async fn list_banks(state: web::Data<AppState>) -> HttpResponse {
+    match BankNetworkQueries::banks(&state.db.pool).await {
+        Ok(banks) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "banks": banks
+        })),
+        Err(e) => failed("load banks", e),
+    }
+}
+
 pub async fn get_accounts(
     state: web::Data<AppState>,
     req: HttpRequest,
 ) -> HttpResponse {
-    let user_id = match extract_user_id(&state, &req).await {
-        Some(id) => id,
-        None => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "success": false,
-                "message": "Unauthorized"
-            }));
-        }
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
     };
 
-    match he_database::queries::BankQueries::get_user_accounts(&state.db.pool, user_id).await {
-        Ok(accounts) => {
-            let account_list: Vec<AccountInfo> = accounts.into_iter().map(|a| AccountInfo {
-                account_number: a.account_number,
-                balance: a.balance,
-            }).collect();
+    match BankNetworkQueries::accounts(&state.db.pool, user_id).await {
+        Ok(accounts) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "accounts": accounts
+        })),
+        Err(e) => failed("get accounts", e),
+    }
+}
 
-            HttpResponse::Ok().json(serde_json::json!({
-                "success": true,
-                "accounts": account_list
-            }))
-        }
-        Err(e) => {
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "message": format!("Failed to get accounts: {}", e)
-            }))
-        }
+pub async fn open_account(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<OpenAccountRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match banking::open_account(&state.db.pool, user_id, data.bank_id).await {
+        Ok(Ok(account)) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "account": account
+        })),
+        Ok(Err(denied)) => open_denied(&denied),
+        Err(e) => failed("open account", e),
     }
 }
 
+/// Move money out of one of the caller's accounts, or out of an account at
+/// a bank they hacked
 pub async fn transfer_money(
     state: web::Data<AppState>,
-    _req: HttpRequest,
+    req: HttpRequest,
     data: web::Json<TransferRequest>,
 ) -> HttpResponse {
-    // Validate amount
-    if data.amount <= 0 {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "success": false,
-            "message": "Invalid amount"
-        }));
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match banking::transfer(&state.db.pool, user_id, &data.from_account, &data.to_account, data.amount).await {
+        Ok(Ok(receipt)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": format!("Transferred ${}.{:02}", receipt.amount / 100, receipt.amount % 100),
+            "transfer": receipt
+        })),
+        Ok(Err(denied)) => transfer_denied(&denied),
+        Err(e) => failed("transfer", e),
     }
+}
 
-    match he_database::queries::BankQueries::transfer_money(
-        &state.db.pool,
-        &data.from_account,
-        &data.to_account,
-        data.amount,
-    ).await {
-        Ok(true) => {
-            HttpResponse::Ok().json(serde_json::json!({
-                "success": true,
-                "message": format!("Transferred ${} successfully", data.amount)
-            }))
-        }
-        Ok(false) => {
-            HttpResponse::BadRequest().json(serde_json::json!({
+/// Transfers in and out of one of the caller's accounts, newest first
+pub async fn account_transfers(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TransfersQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let pool = &state.db.pool;
+    let account_id = match BankNetworkQueries::own_account_id(pool, user_id, &path.into_inner()).await {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
                 "success": false,
-                "message": "Insufficient funds"
-            }))
+                "message": "Bank account not found"
+            }));
         }
-        Err(e) => {
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "message": format!("Transfer failed: {}", e)
+        Err(e) => return failed("load account", e),
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_TRANSFERS).clamp(1, MAX_TRANSFERS);
+    match BankNetworkQueries::account_transfers(pool, account_id, query.before_id, limit).await {
+        Ok(transfers) => {
+            let next_before_id = if transfers.len() as i64 == limit { transfers.last().map(|t| t.id) } else { None };
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "transfers": transfers,
+                "next_before_id": next_before_id
             }))
         }
+        Err(e) => failed("load transfers", e),
     }
-}
\ No newline at end of file
+}
+
+/// Clean stolen money in one of the caller's accounts
+pub async fn launder(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<LaunderRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match banking::launder(&state.db.pool, user_id, &data.account, data.bank_id, data.amount).await {
+        Ok(Ok(receipt)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Money laundered",
+            "laundering": receipt
+        })),
+        Ok(Err(denied)) => launder_denied(&denied),
+        Err(e) => failed("launder", e),
+    }
+}
+
+/// Money stolen from the caller, and how much of it can still be traced
+pub async fn thefts(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match BankNetworkQueries::thefts_from(&state.db.pool, user_id, THEFTS_SIZE).await {
+        Ok(thefts) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "thefts": thefts
+        })),
+        Err(e) => failed("load thefts", e),
+    }
+}
+
+/// Follow money stolen from the caller: every transfer that moved it and the
+/// accounts holding what is left
+pub async fn trace_theft(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match BankNetworkQueries::theft_trace(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Some((hops, holdings))) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "hops": hops,
+            "holdings": holdings
+        })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Theft not found"
+        })),
+        Err(e) => failed("trace theft", e),
+    }
+}
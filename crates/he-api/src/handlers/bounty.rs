@@ -0,0 +1,108 @@
+//! Bounty board handlers
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_database::queries::BountyQueries;
+use he_game_mechanics::account_gating::Restriction;
+use he_game_mechanics::bounty::PlaceDenied;
+use serde::Deserialize;
+use crate::bounty;
+use crate::state::AppState;
+use crate::handlers::account_gating::require_unrestricted;
+use crate::handlers::game::extract_user_id;
+
+/// Targets listed on the board
+const BOARD_SIZE: i64 = 100;
+/// Bounties listed in a player's history
+const HISTORY_SIZE: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct PlaceBountyRequest {
+    pub target_id: i64,
+    /// In cents
+    pub reward: i64,
+    pub window_hours: Option<i64>,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn place_denied(denied: &PlaceDenied) -> HttpResponse {
+    let mut response = match denied {
+        PlaceDenied::TargetNotFound => HttpResponse::NotFound(),
+        PlaceDenied::InsufficientFunds { .. } => HttpResponse::PaymentRequired(),
+        PlaceDenied::TooManyOpen { .. } => HttpResponse::Conflict(),
+        PlaceDenied::Disabled => HttpResponse::Forbidden(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Escrow a reward on another player
+pub async fn place_bounty(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<PlaceBountyRequest>,
+) -> HttpResponse {
+    let user_id = match require_unrestricted(&state, &req, Restriction::BountyPlacement).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    match bounty::place(&state.db.pool, user_id, body.target_id, body.reward, body.window_hours).await {
+        Ok(Ok(placed)) => {
+            // Starts the outbox relay that delivers the placement
+            crate::completion::ensure_started(&state);
+            HttpResponse::Created().json(serde_json::json!({
+                "success": true,
+                "message": "Bounty placed",
+                "bounty": placed
+            }))
+        }
+        Ok(Err(denied)) => place_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to place bounty: {}", e)
+        })),
+    }
+}
+
+/// Players with open bounties on them, largest total first. Placers are not
+/// shown.
+pub async fn get_board(state: web::Data<AppState>) -> HttpResponse {
+    match BountyQueries::board(&state.db.pool, BOARD_SIZE).await {
+        Ok(board) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "board": board
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load bounty board: {}", e)
+        })),
+    }
+}
+
+/// Bounties the player placed or claimed
+pub async fn get_my_bounties(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match BountyQueries::for_user(&state.db.pool, user_id, HISTORY_SIZE).await {
+        Ok(bounties) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "bounties": bounties
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load bounties: {}", e)
+        })),
+    }
+}
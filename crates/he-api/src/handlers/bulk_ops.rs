@@ -0,0 +1,275 @@
+//! Bulk admin data fix handlers
+//!
+//! Planning, executing, cancelling and rolling back bulk operations (see
+//! [`crate::bulk_ops`]) all need `data:bulk_fix` and are audited. The row
+//! listing pages through every player's value before and after, which is
+//! the record to export before and after a run.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::{BulkOperationQueries, BulkOperationRow};
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::bulk_ops::{self, BulkRefused, BulkSpec};
+use crate::handlers::process::require_permission;
+use crate::state::AppState;
+
+const BULK_PERMISSION: &str = "data:bulk_fix";
+const LIST_SIZE: i64 = 50;
+const DEFAULT_ROWS: i64 = 500;
+const MAX_ROWS: i64 = 5000;
+const ROW_STATUSES: [&str; 5] = ["pending", "applied", "skipped", "rolled_back", "rollback_failed"];
+
+#[derive(Deserialize)]
+pub struct ExecuteRequest {
+    /// The matched count the dry run reported
+    pub confirm_matched: i32,
+}
+
+#[derive(Deserialize)]
+pub struct RollbackRequest {
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct RowsQuery {
+    pub status: Option<String>,
+    /// Rows after this player, for paging
+    pub after_user_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn refused(refused: BulkRefused) -> HttpResponse {
+    let mut response = match refused {
+        BulkRefused::NotFound => HttpResponse::NotFound(),
+        BulkRefused::NotExecutable
+        | BulkRefused::DryRunExpired
+        | BulkRefused::CountMismatch { .. }
+        | BulkRefused::NotRunning
+        | BulkRefused::NotRollbackable => HttpResponse::Conflict(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": refused.message()
+    }))
+}
+
+fn audit_event(admin_id: i64, operation: &BulkOperationRow, action: &str) -> SecurityEvent {
+    SecurityEvent::BulkOperation {
+        admin_id,
+        operation_id: operation.id,
+        title: operation.title.clone(),
+        action: action.to_string(),
+        mutation: operation.mutation.clone(),
+        matched: operation.matched,
+    }
+}
+
+/// Recent operations, newest first
+pub async fn list(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, BULK_PERMISSION).await {
+        return response;
+    }
+
+    match BulkOperationQueries::list(&state.db.pool, LIST_SIZE).await {
+        Ok(operations) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "operations": operations
+        })),
+        Err(e) => failed("list bulk operations", e),
+    }
+}
+
+/// Dry-run an operation: nothing changes, but the matched players are
+/// snapshotted and a preview returned. Executing needs the dry run.
+pub async fn plan(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    body: web::Json<BulkSpec>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, BULK_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match bulk_ops::plan(&state.db.pool, &body, admin_id).await {
+        Ok(Ok(operation)) => {
+            audit.log_event(audit_event(admin_id, &operation, "dry_run")).await;
+            HttpResponse::Created().json(serde_json::json!({
+                "success": true,
+                "message": format!(
+                    "Dry run matched {} players; execute with confirm_matched to apply",
+                    operation.matched
+                ),
+                "operation": operation
+            }))
+        }
+        Ok(Err(e)) => refused(e),
+        Err(e) => failed("plan bulk operation", e),
+    }
+}
+
+pub async fn get(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, BULK_PERMISSION).await {
+        return response;
+    }
+
+    match BulkOperationQueries::get(&state.db.pool, path.into_inner()).await {
+        Ok(Some(operation)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "operation": operation
+        })),
+        Ok(None) => refused(BulkRefused::NotFound),
+        Err(e) => failed("load bulk operation", e),
+    }
+}
+
+/// Every matched player's status and value before and after, by player id
+pub async fn rows(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    query: web::Query<RowsQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, BULK_PERMISSION).await {
+        return response;
+    }
+    if let Some(status) = query.status.as_deref() {
+        if !ROW_STATUSES.contains(&status) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "Unknown row status"
+            }));
+        }
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_ROWS).clamp(1, MAX_ROWS);
+    let result = BulkOperationQueries::rows(
+        &state.db.pool,
+        path.into_inner(),
+        query.status.as_deref(),
+        query.after_user_id,
+        limit,
+    )
+    .await;
+    match result {
+        Ok(rows) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "next_after_user_id": (rows.len() as i64 == limit).then(|| rows.last().map(|row| row.user_id)).flatten(),
+            "rows": rows
+        })),
+        Err(e) => failed("list bulk operation rows", e),
+    }
+}
+
+/// Start applying a dry-run operation in chunks
+pub async fn execute(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<ExecuteRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, BULK_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match bulk_ops::execute(&state.db.pool, path.into_inner(), admin_id, body.confirm_matched).await {
+        Ok(Ok(operation)) => {
+            audit.log_event(audit_event(admin_id, &operation, "execute")).await;
+            tracing::warn!("Bulk operation {} ({}) started by admin {}", operation.id, operation.title, admin_id);
+            HttpResponse::Accepted().json(serde_json::json!({
+                "success": true,
+                "message": "Bulk operation started",
+                "operation": operation
+            }))
+        }
+        Ok(Err(e)) => refused(e),
+        Err(e) => failed("execute bulk operation", e),
+    }
+}
+
+/// Stop a running operation after its current chunk
+pub async fn cancel(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, BULK_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match bulk_ops::cancel(&state.db.pool, path.into_inner()).await {
+        Ok(Ok(operation)) => {
+            audit.log_event(audit_event(admin_id, &operation, "cancel")).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Bulk operation cancelled; applied rows stay applied until rolled back",
+                "operation": operation
+            }))
+        }
+        Ok(Err(e)) => refused(e),
+        Err(e) => failed("cancel bulk operation", e),
+    }
+}
+
+/// Undo everything an operation applied, from its recorded values
+pub async fn rollback(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<RollbackRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, BULK_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let reason = body.reason.trim();
+    if reason.is_empty() || reason.len() > 500 {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "A reason of at most 500 characters is required"
+        }));
+    }
+
+    match bulk_ops::rollback(&state.db.pool, path.into_inner(), admin_id, reason).await {
+        Ok(Ok(operation)) => {
+            audit.log_event(audit_event(admin_id, &operation, "rollback")).await;
+            tracing::warn!("Bulk operation {} rollback started by admin {}: {}", operation.id, admin_id, reason);
+            HttpResponse::Accepted().json(serde_json::json!({
+                "success": true,
+                "message": "Rollback started",
+                "operation": operation
+            }))
+        }
+        Ok(Err(e)) => refused(e),
+        Err(e) => failed("roll back bulk operation", e),
+    }
+}
@@ -0,0 +1,112 @@
+//! Cache keyspace handlers
+//!
+//! Operators holding `cache:stats` list the keyspaces missing most since
+//! startup or the last reset, with a suggested TTL for each (see
+//! [`he_cache::keyspace`]), and how many queries the request-scoped
+//! henforcer relay cache saved (see [`he_helix_henforcer::cache`]).
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_cache::keyspace::Keyspaces;
+use he_helix_henforcer::cache as relay_cache;
+use serde::Deserialize;
+use crate::state::AppState;
+use crate::handlers::process::require_permission;
+
+const CACHE_PERMISSION: &str = "cache:stats";
+const DEFAULT_WORST: usize = 10;
+const MAX_WORST: usize = 100;
+/// Reads a keyspace needs before it is ranked
+const DEFAULT_MIN_READS: u64 = 100;
+
+#[derive(Deserialize)]
+pub struct WorstQuery {
+    pub limit: Option<usize>,
+    pub min_reads: Option<u64>,
+}
+
+/// The keyspaces with the lowest hit ratio
+pub async fn worst_keyspaces(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    keyspaces: web::Data<Keyspaces>,
+    req: HttpRequest,
+    query: web::Query<WorstQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, CACHE_PERMISSION).await {
+        return response;
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_WORST).clamp(1, MAX_WORST);
+    let min_reads = query.min_reads.unwrap_or(DEFAULT_MIN_READS);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "keyspaces": keyspaces.worst(limit, min_reads)
+    }))
+}
+
+/// Start the counts over, e.g. after changing a TTL
+pub async fn reset(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    keyspaces: web::Data<Keyspaces>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, CACHE_PERMISSION).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+
+    keyspaces.reset();
+    tracing::info!("Cache keyspace stats reset by admin {}", admin_id);
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "Cache keyspace stats reset"
+    }))
+}
+
+/// Queries saved by the henforcer relay cache, per object type
+pub async fn relay(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, CACHE_PERMISSION).await {
+        return response;
+    }
+
+    let objects: Vec<serde_json::Value> = relay_cache::stats()
+        .into_iter()
+        .map(|(object_type, counts)| serde_json::json!({
+            "object_type": object_type,
+            "queries_saved": counts.hits,
+            "queries_made": counts.misses,
+            "invalidations": counts.invalidations,
+            "hit_ratio": counts.hit_ratio()
+        }))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "objects": objects
+    }))
+}
+
+/// Start the relay cache counts over
+pub async fn reset_relay(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, CACHE_PERMISSION).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+
+    relay_cache::reset();
+    tracing::info!("Relay cache stats reset by admin {}", admin_id);
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "Relay cache stats reset"
+    }))
+}
@@ -0,0 +1,492 @@
+//! Clan membership, clan server and clan chat handlers
+//!
+//! The caller's clan is always the one they belong to; only wars name
+//! another clan.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_game_mechanics::clan_chat::{Channel, ClanChatDenied};
+use he_game_mechanics::clan_server::ClanServerDenied;
+use he_websocket::Entity;
+use serde::Deserialize;
+use crate::{clan_chat, clan_server};
+use crate::state::AppState;
+use crate::handlers::account_gating::require_chat;
+use crate::handlers::game::extract_user_id;
+use crate::war_spectator::WarSpectator;
+
+const DEFAULT_WARS: i64 = 20;
+const MAX_WARS: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct DepositRequest {
+    pub amount: i64,
+}
+
+#[derive(Deserialize)]
+pub struct UploadRequest {
+    pub software_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct DeclareWarRequest {
+    pub clan_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct ChatPost {
+    pub body: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChatQuery {
+    /// Only messages after this id
+    pub after: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct WarsQuery {
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn clan_denied(denied: &ClanServerDenied) -> HttpResponse {
+    let mut response = match denied {
+        ClanServerDenied::ClanNotFound
+        | ClanServerDenied::MemberNotFound
+        | ClanServerDenied::SoftwareNotFound
+        | ClanServerDenied::FileNotFound
+        | ClanServerDenied::WarNotFound => HttpResponse::NotFound(),
+        ClanServerDenied::NoClan
+        | ClanServerDenied::NotOfficer
+        | ClanServerDenied::NotUploader
+        | ClanServerDenied::NotAtWar
+        | ClanServerDenied::WarsDisabled => HttpResponse::Forbidden(),
+        ClanServerDenied::UnknownPart | ClanServerDenied::AmountTooSmall { .. } | ClanServerDenied::SameClan => {
+            HttpResponse::BadRequest()
+        }
+        ClanServerDenied::TreasuryTooLow { .. } | ClanServerDenied::InsufficientFunds => {
+            HttpResponse::PaymentRequired()
+        }
+        ClanServerDenied::AttackCooldown { .. } => HttpResponse::TooManyRequests(),
+        _ => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn chat_denied(denied: &ClanChatDenied) -> HttpResponse {
+    let mut response = match denied {
+        ClanChatDenied::UnknownChannel | ClanChatDenied::MessageNotFound | ClanChatDenied::NotPinned => {
+            HttpResponse::NotFound()
+        }
+        ClanChatDenied::NoClan | ClanChatDenied::NotOfficer => HttpResponse::Forbidden(),
+        _ => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn channel(name: &str) -> Result<Channel, HttpResponse> {
+    Channel::from_str(name).ok_or_else(|| chat_denied(&ClanChatDenied::UnknownChannel))
+}
+
+/// The caller's clan server: hardware, upgrade prices, treasury and defense
+pub async fn server(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::view(&state.db.pool, user_id).await {
+        Ok(Ok(server)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "server": server
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("load clan server", e),
+    }
+}
+
+/// Pay into the clan treasury from the caller's bank
+pub async fn deposit(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<DepositRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::deposit(&state.db.pool, user_id, data.amount).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": format!("Deposited ${}.{:02}", data.amount / 100, data.amount % 100)
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("deposit", e),
+    }
+}
+
+/// Buy the next level of a clan server part
+pub async fn upgrade(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::upgrade(&state.db.pool, user_id, &path.into_inner()).await {
+        Ok(Ok(upgrade)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": format!("Clan server {} upgraded to level {}", upgrade.part.as_str(), upgrade.level),
+            "upgrade": upgrade
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("upgrade clan server", e),
+    }
+}
+
+pub async fn files(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::files(&state.db.pool, user_id).await {
+        Ok(Ok(files)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "files": files
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("load clan files", e),
+    }
+}
+
+/// Copy a piece of the caller's software onto the clan server
+pub async fn upload(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<UploadRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::upload(&state.db.pool, user_id, data.software_id).await {
+        Ok(Ok(software_id)) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "software_id": software_id
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("upload", e),
+    }
+}
+
+/// Copy a clan server file to the caller's gateway
+pub async fn download(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::download(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok(software_id)) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "software_id": software_id
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("download", e),
+    }
+}
+
+pub async fn remove_file(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::remove_file(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "File removed"
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("remove file", e),
+    }
+}
+
+/// Stop the player's sessions receiving the clan's events
+fn revoke_clan(state: &AppState, user_id: i64, clan_id: i64) {
+    if let Some(ws_manager) = &state.ws_manager {
+        ws_manager.revoke(user_id, Entity::Clan(clan_id));
+    }
+}
+
+/// Leave the caller's clan
+pub async fn leave(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::leave(&state.db.pool, user_id).await {
+        Ok(Ok(clan_id)) => {
+            revoke_clan(&state, user_id, clan_id);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "You left the clan"
+            }))
+        }
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("leave clan", e),
+    }
+}
+
+/// Remove a member from the caller's clan
+pub async fn kick(state: web::Data<AppState>, req: HttpRequest, path: web::Path<i64>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let member_id = path.into_inner();
+    match clan_server::kick(&state.db.pool, user_id, member_id).await {
+        Ok(Ok(clan_id)) => {
+            revoke_clan(&state, member_id, clan_id);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Member removed from the clan"
+            }))
+        }
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("remove clan member", e),
+    }
+}
+
+/// Lend the caller's best firewall to the clan server
+pub async fn lend_firewall(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::lend_firewall(&state.db.pool, user_id).await {
+        Ok(Ok(defense)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Firewall lent to the clan server",
+            "defense": defense
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("lend firewall", e),
+    }
+}
+
+pub async fn declare_war(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<DeclareWarRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::declare_war(&state.db.pool, user_id, data.clan_id).await {
+        Ok(Ok(war)) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "war": war
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("declare war", e),
+    }
+}
+
+/// The caller's clan's wars, newest first
+pub async fn wars(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<WarsQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_WARS).clamp(1, MAX_WARS);
+    match clan_server::wars(&state.db.pool, user_id, query.before_id, limit).await {
+        Ok(Ok(wars)) => {
+            let next_before_id = if wars.len() as i64 == limit { wars.last().map(|w| w.id) } else { None };
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "wars": wars,
+                "next_before_id": next_before_id
+            }))
+        }
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("load wars", e),
+    }
+}
+
+/// Strike the enemy clan server in a war
+pub async fn attack(
+    state: web::Data<AppState>,
+    spectator: web::Data<WarSpectator>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match clan_server::attack(&state.db.pool, &spectator, user_id, path.into_inner()).await {
+        Ok(Ok(report)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "strike": report.strike,
+            "war": report.war
+        })),
+        Ok(Err(denied)) => clan_denied(&denied),
+        Err(e) => failed("attack", e),
+    }
+}
+
+/// Messages in a channel of the caller's clan room, oldest first
+pub async fn chat(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ChatQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let channel = match channel(&path.into_inner()) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+
+    match clan_chat::messages(&state.db.pool, user_id, channel, query.after.unwrap_or(0)).await {
+        Ok(Ok(messages)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "messages": messages
+        })),
+        Ok(Err(denied)) => chat_denied(&denied),
+        Err(e) => failed("load clan chat", e),
+    }
+}
+
+pub async fn post_chat(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Json<ChatPost>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let channel = match channel(&path.into_inner()) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+    let max_len = crate::balance::current().clan_chat.max_message_length;
+    let body = data.body.trim();
+    if body.is_empty() || body.chars().count() > max_len {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Messages must be 1 to {} characters", max_len)
+        }));
+    }
+    if let Err(response) = require_chat(&state, user_id, body).await {
+        return response;
+    }
+
+    match clan_chat::post(&state.db.pool, state.ws_manager.clone(), user_id, channel, body).await {
+        Ok(Ok(message_id)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Message posted",
+            "message_id": message_id
+        })),
+        Ok(Err(denied)) => chat_denied(&denied),
+        Err(e) => failed("post to clan chat", e),
+    }
+}
+
+/// Pinned messages of a channel, latest pin first
+pub async fn chat_pins(state: web::Data<AppState>, req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let channel = match channel(&path.into_inner()) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+
+    match clan_chat::pins(&state.db.pool, user_id, channel).await {
+        Ok(Ok(pins)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "pins": pins
+        })),
+        Ok(Err(denied)) => chat_denied(&denied),
+        Err(e) => failed("load pinned messages", e),
+    }
+}
+
+pub async fn pin_chat(state: web::Data<AppState>, req: HttpRequest, path: web::Path<(String, i64)>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let (name, message_id) = path.into_inner();
+    let channel = match channel(&name) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+
+    match clan_chat::pin(&state.db.pool, user_id, channel, message_id).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Message pinned"
+        })),
+        Ok(Err(denied)) => chat_denied(&denied),
+        Err(e) => failed("pin message", e),
+    }
+}
+
+pub async fn unpin_chat(state: web::Data<AppState>, req: HttpRequest, path: web::Path<(String, i64)>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let (name, message_id) = path.into_inner();
+    let channel = match channel(&name) {
+        Ok(channel) => channel,
+        Err(response) => return response,
+    };
+
+    match clan_chat::unpin(&state.db.pool, user_id, channel, message_id).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Message unpinned"
+        })),
+        Ok(Err(denied)) => chat_denied(&denied),
+        Err(e) => failed("unpin message", e),
+    }
+}
@@ -0,0 +1,304 @@
+//! Player contracts board handlers
+//!
+//! Contracts are fulfilled by the server when the contractor's download or
+//! delete completes, never from here. Disputes go to moderators holding the
+//! `contracts:moderate` permission.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::ContractQueries;
+use he_game_mechanics::contracts::{AcceptDenied, ContractKind, DisputeDenied, PostDenied, Resolution};
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::contracts::{self, PostContract};
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::handlers::process::require_permission;
+
+const MANAGE_PERMISSION: &str = "contracts:moderate";
+/// Contracts listed on the board
+const BOARD_SIZE: i64 = 100;
+/// Contracts listed in a player's history
+const HISTORY_SIZE: i64 = 50;
+const QUEUE_SIZE: i64 = 100;
+const MAX_TEXT_LENGTH: usize = 500;
+
+#[derive(Deserialize)]
+pub struct PostContractRequest {
+    pub kind: ContractKind,
+    pub target_ip: String,
+    pub file_id: String,
+    /// In cents
+    pub reward: i64,
+    pub window_hours: Option<i64>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DisputeRequest {
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveRequest {
+    pub resolution: Resolution,
+    pub note: String,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn bad_text(field: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "success": false,
+        "message": format!("{} must be 1 to {} characters", field, MAX_TEXT_LENGTH)
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn valid_text(text: &str) -> bool {
+    !text.trim().is_empty() && text.chars().count() <= MAX_TEXT_LENGTH
+}
+
+fn post_denied(denied: &PostDenied) -> HttpResponse {
+    let mut response = match denied {
+        PostDenied::LevelTooLow { .. } => HttpResponse::Forbidden(),
+        PostDenied::FileNotFound => HttpResponse::NotFound(),
+        PostDenied::InsufficientFunds { .. } => HttpResponse::PaymentRequired(),
+        PostDenied::TooManyOpen { .. } => HttpResponse::Conflict(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn accept_denied(denied: &AcceptDenied) -> HttpResponse {
+    let mut response = match denied {
+        AcceptDenied::NotFound => HttpResponse::NotFound(),
+        AcceptDenied::OwnContract | AcceptDenied::Target => HttpResponse::Forbidden(),
+        AcceptDenied::NotOpen | AcceptDenied::TooManyAccepted { .. } => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn dispute_denied(denied: &DisputeDenied) -> HttpResponse {
+    let mut response = match denied {
+        DisputeDenied::NotFound => HttpResponse::NotFound(),
+        DisputeDenied::NotPoster => HttpResponse::Forbidden(),
+        DisputeDenied::NotFulfilled | DisputeDenied::WindowClosed => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Escrow a reward for a job against another player's file
+pub async fn post_contract(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<PostContractRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    if body.description.as_deref().is_some_and(|description| !valid_text(description)) {
+        return bad_text("Description");
+    }
+
+    let body = body.into_inner();
+    let request = PostContract {
+        kind: body.kind,
+        target_ip: body.target_ip,
+        target_file_id: body.file_id,
+        reward: body.reward,
+        window_hours: body.window_hours,
+        description: body.description,
+    };
+
+    match contracts::post(&state.db.pool, user_id, &request).await {
+        Ok(Ok(posted)) => {
+            // Starts the sweeper that pays out or refunds it
+            crate::completion::ensure_started(&state);
+            HttpResponse::Created().json(serde_json::json!({
+                "success": true,
+                "message": "Contract posted",
+                "contract": posted
+            }))
+        }
+        Ok(Err(denied)) => post_denied(&denied),
+        Err(e) => failed("post contract", e),
+    }
+}
+
+/// Open contracts, largest reward first
+pub async fn get_board(state: web::Data<AppState>) -> HttpResponse {
+    match ContractQueries::board(&state.db.pool, BOARD_SIZE).await {
+        Ok(board) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "board": board
+        })),
+        Err(e) => failed("load contract board", e),
+    }
+}
+
+/// Contracts the player posted or took
+pub async fn get_my_contracts(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match ContractQueries::for_user(&state.db.pool, user_id, HISTORY_SIZE).await {
+        Ok(contracts) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "contracts": contracts
+        })),
+        Err(e) => failed("load contracts", e),
+    }
+}
+
+pub async fn accept_contract(state: web::Data<AppState>, req: HttpRequest, path: web::Path<i64>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match contracts::accept(&state.db.pool, path.into_inner(), user_id).await {
+        Ok(Ok(contract)) => {
+            crate::completion::ensure_started(&state);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Contract accepted",
+                "contract": contract
+            }))
+        }
+        Ok(Err(denied)) => accept_denied(&denied),
+        Err(e) => failed("accept contract", e),
+    }
+}
+
+/// Take down a contract nobody has accepted. The posting fee is not refunded.
+pub async fn cancel_contract(state: web::Data<AppState>, req: HttpRequest, path: web::Path<i64>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match contracts::cancel(&state.db.pool, path.into_inner(), user_id).await {
+        Ok(Some(contract)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Contract cancelled; the reward was refunded",
+            "contract": contract
+        })),
+        Ok(None) => HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "message": "Only your own open contracts can be cancelled"
+        })),
+        Err(e) => failed("cancel contract", e),
+    }
+}
+
+/// Hold the reward of a fulfilled contract for a moderator
+pub async fn dispute_contract(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<DisputeRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    if !valid_text(&body.reason) {
+        return bad_text("Reason");
+    }
+
+    match contracts::dispute(&state.db.pool, path.into_inner(), user_id, body.reason.trim()).await {
+        Ok(Ok(contract)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Dispute opened; a moderator will review it",
+            "contract": contract
+        })),
+        Ok(Err(denied)) => dispute_denied(&denied),
+        Err(e) => failed("dispute contract", e),
+    }
+}
+
+/// Disputed contracts waiting on a moderator, oldest first
+pub async fn list_disputes(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        return response;
+    }
+
+    match ContractQueries::disputes(&state.db.pool, QUEUE_SIZE).await {
+        Ok(disputes) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "disputes": disputes
+        })),
+        Err(e) => failed("load disputes", e),
+    }
+}
+
+/// Pay the contractor or refund the poster
+pub async fn resolve_dispute(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<ResolveRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    if !valid_text(&body.note) {
+        return bad_text("Note");
+    }
+
+    let contract_id = path.into_inner();
+    match contracts::resolve(&state.db.pool, contract_id, admin_id, body.resolution, body.note.trim()).await {
+        Ok(Some(contract)) => {
+            let resolution = match body.resolution {
+                Resolution::PayContractor => "pay_contractor",
+                Resolution::RefundPoster => "refund_poster",
+            };
+            audit.log_event(SecurityEvent::ContractDisputeResolved {
+                admin_id,
+                contract_id,
+                resolution: resolution.to_string(),
+                amount: contract.reward,
+            }).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Dispute resolved",
+                "contract": contract
+            }))
+        }
+        Ok(None) => HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "message": "This contract is not under dispute"
+        })),
+        Err(e) => failed("resolve dispute", e),
+    }
+}
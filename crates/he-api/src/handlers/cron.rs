@@ -0,0 +1,223 @@
+//! Admin dashboard for he-cron scheduled jobs
+//!
+//! Every endpoint requires the `cron:manage` permission. he-cron runs as a
+//! separate process, so pause and trigger requests are only recorded here;
+//! the scheduler picks them up within a few seconds.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::CronJobQueries;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::state::AppState;
+use crate::handlers::process::require_permission;
+
+const MANAGE_PERMISSION: &str = "cron:manage";
+const DEFAULT_RUNS: i64 = 20;
+const MAX_RUNS: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct RunsQuery {
+    pub limit: Option<i64>,
+}
+
+fn job_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "success": false,
+        "message": "Unknown job"
+    }))
+}
+
+fn database_error(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+/// Every job with its statistics, plus the open alerts
+pub async fn list_jobs(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        return response;
+    }
+
+    let jobs = match CronJobQueries::list(&state.db.pool).await {
+        Ok(jobs) => jobs,
+        Err(e) => return database_error("load jobs", e),
+    };
+    let alerts = match CronJobQueries::open_alerts(&state.db.pool).await {
+        Ok(alerts) => alerts,
+        Err(e) => return database_error("load alerts", e),
+    };
+
+    let jobs: Vec<_> = jobs
+        .into_iter()
+        .map(|job| {
+            let running = job.is_running();
+            let mut value = serde_json::to_value(job).unwrap_or_default();
+            value["running"] = serde_json::Value::Bool(running);
+            value
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "jobs": jobs,
+        "alerts": alerts
+    }))
+}
+
+/// One job and its latest runs
+pub async fn get_job(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<RunsQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        return response;
+    }
+
+    let name = path.into_inner();
+    let job = match CronJobQueries::get(&state.db.pool, &name).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return job_not_found(),
+        Err(e) => return database_error("load job", e),
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_RUNS).clamp(1, MAX_RUNS);
+    match CronJobQueries::recent_runs(&state.db.pool, &name, limit).await {
+        Ok(runs) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "running": job.is_running(),
+            "job": job,
+            "runs": runs
+        })),
+        Err(e) => database_error("load runs", e),
+    }
+}
+
+/// Ask the scheduler to run a job now, even if it is paused
+pub async fn trigger_job(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let name = path.into_inner();
+    match CronJobQueries::request_trigger(&state.db.pool, &name, admin_id).await {
+        Ok(true) => {
+            audit.log_event(SecurityEvent::CronJobControlled {
+                admin_id,
+                job_name: name.clone(),
+                action: "trigger".to_string(),
+            }).await;
+
+            HttpResponse::Accepted().json(serde_json::json!({
+                "success": true,
+                "message": format!("{} will run shortly", name)
+            }))
+        }
+        Ok(false) => job_not_found(),
+        Err(e) => database_error("trigger job", e),
+    }
+}
+
+pub async fn pause_job(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    set_paused(state, auth, audit, req, path.into_inner(), true).await
+}
+
+pub async fn resume_job(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    set_paused(state, auth, audit, req, path.into_inner(), false).await
+}
+
+/// Pausing only stops scheduled runs; a run in progress finishes
+async fn set_paused(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    name: String,
+    paused: bool,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match CronJobQueries::set_paused(&state.db.pool, &name, paused, admin_id).await {
+        Ok(true) => {
+            let action = if paused { "pause" } else { "resume" };
+            audit.log_event(SecurityEvent::CronJobControlled {
+                admin_id,
+                job_name: name.clone(),
+                action: action.to_string(),
+            }).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": format!("{} {}", name, if paused { "paused" } else { "resumed" })
+            }))
+        }
+        Ok(false) => job_not_found(),
+        Err(e) => database_error("update job", e),
+    }
+}
+
+/// Close an alert by hand, e.g. after a run was killed with its host
+pub async fn acknowledge_alert(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let alert_id = path.into_inner();
+    match CronJobQueries::acknowledge_alert(&state.db.pool, alert_id, admin_id).await {
+        Ok(Some(job_name)) => {
+            audit.log_event(SecurityEvent::CronJobControlled {
+                admin_id,
+                job_name,
+                action: "acknowledge_alert".to_string(),
+            }).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Alert acknowledged"
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "No open alert with this id"
+        })),
+        Err(e) => database_error("acknowledge alert", e),
+    }
+}
@@ -0,0 +1,857 @@
+//! Defender alerts and attacker tracing
+//!
+//! Hostile processes started against another player's server are registered here.
+//! A background tick rolls detection at each progress checkpoint and pushes
+//! alerts to the defender over WebSocket. Detected attacks can be traced back
+//! with a `trace_attacker` process.
+//!
+//! A trace follows the attack's route back one server at a time, as set out
+//! in [`he_game_mechanics::trace_route`]. Every hostile process leaves a
+//! connection log on its target and each bounce when it starts; the trace
+//! reads those, and the traced attacker can wipe them from servers it has not
+//! read yet. The tick advances traces and lands wipes, and both sides get a
+//! `trace_progress` event for every step. A completed trace reveals the
+//! attacker's gateway and earns the defender reputation.
+//!
+//! Alongside the per-process rolls, every hostile action adds to the
+//! attacker's footprint on that defender, as set out in
+//! [`he_game_mechanics::defense::IntrusionFootprint`]. Once the footprint
+//! gives the attacker away, the defender gets an `intrusion_detected` event
+//! and the bounced IP is flagged on their side.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use chrono::{DateTime, Utc};
+use he_database::queries::{
+    ClanServerQueries, HardwareQueries, HoneypotQueries, LogQueries, ProcessQueries, ProgressionQueries, ServerQueries,
+};
+use he_game_mechanics::defense::{
+    apply_detection_notice, DefenseState, DetectionNotice, IntrusionFootprint, StealthProfile,
+};
+use he_game_mechanics::detection::{DefenderAlert, DefenderProfile, HostileActivity, HostileProcessMonitor};
+use he_game_mechanics::honeypot::Route;
+use he_game_mechanics::process::ProcessType;
+use he_game_mechanics::trace_route::{self, TraceRoute, TraceStatus, TraceStep, REPUTATION_FACTION};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::state::AppState;
+use crate::handlers::process::extract_user_id;
+
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+// Finished attacks stay traceable for a while after the process ends
+const TRACE_WINDOW_SECONDS: i64 = 3600;
+// Defense state and stealth skill are not persisted yet
+const DEFAULT_STEALTH: i32 = 50;
+const DEFAULT_DEFENDER: DefenderProfile = DefenderProfile {
+    firewall_level: 50,
+    security_rating: 50,
+    ids_active: false,
+};
+
+struct TrackedAttack {
+    monitor: HostileProcessMonitor,
+    attacker_id: i64,
+    route: Route,
+    attacker_stealth: i32,
+    started_at: DateTime<Utc>,
+    duration_seconds: i64,
+    ended_at: Option<DateTime<Utc>>,
+}
+
+impl TrackedAttack {
+    fn progress(&self, now: DateTime<Utc>) -> f32 {
+        let elapsed = (now - self.started_at).num_seconds();
+        (elapsed as f32 / self.duration_seconds.max(1) as f32).clamp(0.0, 1.0)
+    }
+}
+
+struct ActiveTrace {
+    trace: TraceRoute,
+    defender_id: i64,
+    attacker_id: i64,
+    /// When the traced attack started; its trail is what was logged since
+    since: DateTime<Utc>,
+    last_advanced: DateTime<Utc>,
+    /// The attacker's wipe in progress; one at a time
+    wipe: Option<PendingWipe>,
+    /// Set once the trace broke or completed and was paid out
+    finished_at: Option<DateTime<Utc>>,
+}
+
+struct PendingWipe {
+    hop_ip: String,
+    from_ip: String,
+    lands_at: DateTime<Utc>,
+}
+
+/// A trace step for both sides to hear about
+struct TraceNotice {
+    pid: i64,
+    defender_id: i64,
+    attacker_id: i64,
+    status: &'static str,
+    hop: usize,
+    hops: usize,
+    progress: f32,
+    remaining_seconds: i64,
+    /// What the defender learns
+    revealed_ip: Option<String>,
+    /// The attacker's server the step was about
+    hop_ip: Option<String>,
+}
+
+impl TraceNotice {
+    fn new(pid: i64, active: &ActiveTrace, status: &'static str, hop: usize) -> Self {
+        Self {
+            pid,
+            defender_id: active.defender_id,
+            attacker_id: active.attacker_id,
+            status,
+            hop,
+            hops: active.trace.hops.len(),
+            progress: active.trace.progress(),
+            remaining_seconds: active.trace.remaining_seconds(),
+            revealed_ip: None,
+            hop_ip: active.trace.hops.get(hop).map(|h| h.ip.clone()),
+        }
+    }
+
+    fn from_step(pid: i64, active: &ActiveTrace, step: TraceStep) -> Self {
+        match step {
+            TraceStep::Resolved { hop, from_ip } => {
+                Self { revealed_ip: Some(from_ip), ..Self::new(pid, active, "hop_resolved", hop) }
+            }
+            TraceStep::Tampered { hop, broken: false, .. } => Self::new(pid, active, "tampered", hop),
+            TraceStep::Tampered { hop, broken: true, .. } | TraceStep::Broken { hop } => {
+                Self::new(pid, active, "broken", hop)
+            }
+            TraceStep::Completed { attacker_ip } => Self {
+                revealed_ip: Some(attacker_ip),
+                hop_ip: None,
+                ..Self::new(pid, active, "completed", active.trace.hops.len() - 1)
+            },
+        }
+    }
+}
+
+/// One attacker's evidence on one defender's servers
+struct TrackedFootprint {
+    footprint: IntrusionFootprint,
+    last_action: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct DefenseRegistry {
+    attacks: HashMap<i64, TrackedAttack>,
+    traces: HashMap<i64, ActiveTrace>,
+    /// Keyed by (attacker, defender)
+    footprints: HashMap<(i64, i64), TrackedFootprint>,
+    /// What each defender has flagged
+    defenses: HashMap<i64, DefenseState>,
+}
+
+static REGISTRY: Lazy<Mutex<DefenseRegistry>> = Lazy::new(|| Mutex::new(DefenseRegistry::default()));
+static TICKER_STARTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Deserialize)]
+pub struct StartTraceRequest {
+    /// Hostile process the defender was alerted about
+    pub process_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct WipeTrailRequest {
+    /// The trace against the attacker, from their `trace_progress` events
+    pub trace_id: i64,
+    /// One of the attacker's servers the trace has yet to read
+    pub ip: String,
+}
+
+/// The attack's route, with a connection logged on every server it passes
+async fn lay_trail(pool: &PgPool, attacker_id: i64, target_ip: &str) -> anyhow::Result<Route> {
+    let mut conn = pool.acquire().await?;
+    let (gateway_ip, bounce_ips) = HoneypotQueries::route(&mut conn, attacker_id, target_ip).await?;
+    let Some(gateway_ip) = gateway_ip else {
+        // No gateway, no trail to follow
+        return Ok(Route {
+            gateway_ip: format!("pc_{}", attacker_id),
+            bounce_ips,
+            host_ip: None,
+            target_ip: target_ip.to_string(),
+        });
+    };
+    let route = Route { gateway_ip, bounce_ips, host_ip: None, target_ip: target_ip.to_string() };
+    LogQueries::add_trail(&mut conn, &TraceRoute::servers(&route)).await?;
+    Ok(route)
+}
+
+/// Version 1.0 software is worth 10 effectiveness points, up to 100
+fn software_effectiveness(version: Option<f64>) -> i32 {
+    (version.unwrap_or(0.0) * 10.0).round().clamp(0.0, 100.0) as i32
+}
+
+/// The attacker's stealth: skill is not persisted yet, tools come from their servers
+async fn stealth_profile(pool: &PgPool, attacker_id: i64) -> anyhow::Result<StealthProfile> {
+    let mut conn = pool.acquire().await?;
+    let cloak = ClanServerQueries::best_software(&mut conn, attacker_id, "proxy_chain").await?;
+    let log_cleaner = ClanServerQueries::best_software(&mut conn, attacker_id, "log_cleaner").await?;
+    Ok(StealthProfile {
+        skill: DEFAULT_STEALTH,
+        cloak: software_effectiveness(cloak),
+        log_cleaner: software_effectiveness(log_cleaner),
+    })
+}
+
+fn fresh_defense_state() -> DefenseState {
+    DefenseState {
+        firewall_strength: DEFAULT_DEFENDER.firewall_level,
+        ids_active: DEFAULT_DEFENDER.ids_active,
+        ids_sensitivity: 0,
+        honeypot_active: false,
+        log_monitoring: true,
+        security_rating: DEFAULT_DEFENDER.security_rating,
+        active_traces: Vec::new(),
+        blocked_ips: Vec::new(),
+        security_events: Vec::new(),
+    }
+}
+
+/// Add the action to the attacker's footprint on the defender. Returns the
+/// notice the first time it gives them away, after flagging their IP.
+fn record_footprint(
+    attacker_id: i64,
+    defender_id: i64,
+    route: &Route,
+    activity: HostileActivity,
+    stealth: &StealthProfile,
+    now: DateTime<Utc>,
+) -> Option<DetectionNotice> {
+    let config = &crate::balance::current().stealth;
+    // The defender only ever sees the last server before theirs
+    let bounced_ip = route.bounce_ips.last().unwrap_or(&route.gateway_ip).clone();
+
+    let mut guard = REGISTRY.lock().unwrap();
+    let registry = &mut *guard;
+    let tracked = registry.footprints.entry((attacker_id, defender_id)).or_insert_with(|| TrackedFootprint {
+        footprint: IntrusionFootprint::new(attacker_id as u64, defender_id as u64, bounced_ip.clone()),
+        last_action: now,
+    });
+    tracked.footprint.bounced_ip = bounced_ip;
+    tracked.last_action = now;
+
+    let firewall_level = DEFAULT_DEFENDER.firewall_level;
+    let notice = match activity {
+        HostileActivity::LogTampering => tracked.footprint.delete_logs(firewall_level, stealth, config).1,
+        _ => tracked.footprint.record(activity, firewall_level, stealth, config),
+    }?;
+
+    let defense = registry.defenses.entry(defender_id).or_insert_with(fresh_defense_state);
+    apply_detection_notice(defense, &notice, config);
+    Some(notice)
+}
+
+/// Register a freshly started process; no-op unless it is hostile and aimed at another player
+pub(crate) async fn watch_hostile_process(
+    state: &web::Data<AppState>,
+    attacker_id: i64,
+    pid: i64,
+    process_type: &str,
+    target_ip: Option<&str>,
+    duration_seconds: i32,
+) {
+    let Some(activity) = HostileActivity::from_process(&ProcessType::from_str(process_type)) else {
+        return;
+    };
+    let Some(target_ip) = target_ip else {
+        return;
+    };
+
+    let defender_id = match ServerQueries::get_player_owner_by_ip(&state.db.pool, target_ip).await {
+        Ok(Some(owner)) if owner != attacker_id => owner,
+        Ok(_) => return,
+        Err(e) => {
+            tracing::warn!("Failed to look up owner of {}: {}", target_ip, e);
+            return;
+        }
+    };
+
+    let route = match lay_trail(&state.db.pool, attacker_id, target_ip).await {
+        Ok(route) => route,
+        Err(e) => {
+            tracing::warn!("Failed to lay the trail of process {}: {}", pid, e);
+            Route {
+                gateway_ip: format!("pc_{}", attacker_id),
+                bounce_ips: Vec::new(),
+                host_ip: None,
+                target_ip: target_ip.to_string(),
+            }
+        }
+    };
+
+    let stealth = match stealth_profile(&state.db.pool, attacker_id).await {
+        Ok(stealth) => stealth,
+        Err(e) => {
+            tracing::warn!("Failed to load the stealth of user {}: {}", attacker_id, e);
+            StealthProfile { skill: DEFAULT_STEALTH, ..StealthProfile::default() }
+        }
+    };
+    let notice = record_footprint(attacker_id, defender_id, &route, activity, &stealth, Utc::now());
+    if let (Some(notice), Some(ws_manager)) = (notice, &state.ws_manager) {
+        send_detection_notice(ws_manager, &notice);
+    }
+
+    let monitor = HostileProcessMonitor::new(
+        pid as u64,
+        defender_id as u64,
+        activity,
+        // Attacker's gateway; the defender only sees it once revealed or traced
+        route.gateway_ip.clone(),
+        &DEFAULT_DEFENDER,
+        DEFAULT_STEALTH,
+        &crate::balance::current().detection,
+    );
+
+    REGISTRY.lock().unwrap().attacks.insert(pid, TrackedAttack {
+        monitor,
+        attacker_id,
+        route,
+        attacker_stealth: DEFAULT_STEALTH,
+        started_at: Utc::now(),
+        duration_seconds: duration_seconds as i64,
+        ended_at: None,
+    });
+
+    start_ticker(state.db.pool.clone(), state.ws_manager.clone());
+}
+
+/// The hostile process was cancelled before finishing
+pub(crate) fn hostile_process_ended(state: &web::Data<AppState>, pid: i64) {
+    let alert = {
+        let mut registry = REGISTRY.lock().unwrap();
+        let now = Utc::now();
+        registry.attacks.get_mut(&pid).and_then(|attack| {
+            attack.ended_at = Some(now);
+            let progress = attack.progress(now);
+            attack.monitor.finish(progress)
+        })
+    };
+
+    if let (Some(alert), Some(ws_manager)) = (alert, &state.ws_manager) {
+        send_alert(ws_manager, &alert);
+    }
+}
+
+fn start_ticker(pool: PgPool, ws_manager: Option<Arc<he_websocket::ConnectionManager>>) {
+    if TICKER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let started = std::time::Instant::now();
+            let now = Utc::now();
+            let alerts = tick(now);
+            let notices = tick_traces(&pool, now).await;
+            if let Some(ws_manager) = &ws_manager {
+                for alert in &alerts {
+                    send_alert(ws_manager, alert);
+                }
+                for notice in &notices {
+                    send_trace_notice(ws_manager, notice);
+                }
+            }
+            crate::live_ops::record_tick("defense", started.elapsed());
+        }
+    });
+}
+
+/// Roll detection for every running attack and drop attacks past the trace window
+fn tick(now: DateTime<Utc>) -> Vec<DefenderAlert> {
+    let mut registry = REGISTRY.lock().unwrap();
+    let mut rng = rand::thread_rng();
+    let mut alerts = Vec::new();
+
+    for attack in registry.attacks.values_mut() {
+        if attack.ended_at.is_some() {
+            continue;
+        }
+
+        let progress = attack.progress(now);
+        alerts.extend(attack.monitor.observe(progress, &mut rng));
+
+        if progress >= 1.0 {
+            attack.ended_at = Some(now);
+            alerts.extend(attack.monitor.finish(progress));
+        }
+    }
+
+    registry.attacks.retain(|_, attack| {
+        attack
+            .ended_at
+            .map_or(true, |ended| (now - ended).num_seconds() < TRACE_WINDOW_SECONDS)
+    });
+    // A quiet attacker's evidence goes stale; flags expire on their own
+    registry
+        .footprints
+        .retain(|_, tracked| (now - tracked.last_action).num_seconds() < TRACE_WINDOW_SECONDS);
+    for defense in registry.defenses.values_mut() {
+        defense.active_traces.retain(|trace| trace.expires_at > now);
+    }
+    registry.defenses.retain(|_, defense| !defense.active_traces.is_empty());
+
+    alerts
+}
+
+/// Land the wipes that are due, advance every trace and pay out the ones
+/// that finished. Returns what both sides should hear.
+async fn tick_traces(pool: &PgPool, now: DateTime<Utc>) -> Vec<TraceNotice> {
+    let config = &crate::balance::current().detection;
+
+    let due: Vec<(i64, PendingWipe, DateTime<Utc>)> = {
+        let mut registry = REGISTRY.lock().unwrap();
+        let mut due = Vec::new();
+        for (&pid, active) in registry.traces.iter_mut() {
+            match active.wipe.take() {
+                Some(wipe) if wipe.lands_at <= now => due.push((pid, wipe, active.since)),
+                pending => active.wipe = pending,
+            }
+        }
+        due
+    };
+
+    // What is left of each wiped hop's trail
+    let mut landed = Vec::new();
+    for (pid, wipe, since) in due {
+        let hop = [(wipe.hop_ip.clone(), wipe.from_ip.clone())];
+        let wiped = LogQueries::wipe_trail(pool, &wipe.hop_ip, &wipe.from_ip, since, config.log_wipe_batch).await;
+        match wiped.and(LogQueries::trail(pool, &hop, since).await) {
+            Ok(counts) => {
+                let (intact, wiped) = counts.first().copied().unwrap_or_default();
+                landed.push((pid, wipe.hop_ip, trace_route::log_integrity(intact, wiped)));
+            }
+            Err(e) => tracing::warn!("Failed to wipe {} for trace {}: {}", wipe.hop_ip, pid, e),
+        }
+    }
+
+    let mut notices = Vec::new();
+    let mut finished = Vec::new();
+    {
+        let mut registry = REGISTRY.lock().unwrap();
+        for (pid, hop_ip, integrity) in landed {
+            if let Some(active) = registry.traces.get_mut(&pid) {
+                if let Some(step) = active.trace.wipe(&hop_ip, integrity, config) {
+                    notices.push(TraceNotice::from_step(pid, active, step));
+                }
+            }
+        }
+
+        for (&pid, active) in registry.traces.iter_mut() {
+            if active.finished_at.is_some() {
+                continue;
+            }
+            // Whole seconds only, so nothing is lost between ticks
+            let seconds = (now - active.last_advanced).num_seconds();
+            active.last_advanced += chrono::Duration::seconds(seconds);
+            let steps = active.trace.advance(seconds, config);
+            if steps.is_empty() && active.trace.status == TraceStatus::Running {
+                notices.push(TraceNotice::new(pid, active, "reading", active.trace.current));
+            }
+            for step in steps {
+                notices.push(TraceNotice::from_step(pid, active, step));
+            }
+            if active.trace.status != TraceStatus::Running {
+                active.finished_at = Some(now);
+                finished.push((pid, active.defender_id, active.trace.status));
+            }
+        }
+
+        registry.traces.retain(|_, active| {
+            active
+                .finished_at
+                .map_or(true, |finished| (now - finished).num_seconds() < TRACE_WINDOW_SECONDS)
+        });
+    }
+
+    for (pid, defender_id, status) in finished {
+        // The trace process only stood for the trace; its result is the trace's
+        if let Err(e) = ProcessQueries::cancel_process(pool, pid, defender_id).await {
+            tracing::warn!("Failed to end trace process {}: {}", pid, e);
+        }
+        if status == TraceStatus::Completed {
+            if let Err(e) = reward_trace(pool, defender_id, config.trace_reputation).await {
+                tracing::warn!("Failed to reward user {} for trace {}: {}", defender_id, pid, e);
+            }
+        }
+    }
+
+    notices
+}
+
+async fn reward_trace(pool: &PgPool, defender_id: i64, reputation: i32) -> anyhow::Result<()> {
+    let mut conn = pool.acquire().await?;
+    ProgressionQueries::add_reputation(&mut conn, defender_id, REPUTATION_FACTION, reputation).await
+}
+
+fn send_trace_notice(ws_manager: &he_websocket::ConnectionManager, notice: &TraceNotice) {
+    let event = |role: &str, ip: Option<String>| {
+        he_websocket::EventBuilder::trace_progress(
+            notice.pid,
+            role.to_string(),
+            notice.status.to_string(),
+            notice.hop as u32,
+            notice.hops as u32,
+            notice.progress,
+            notice.remaining_seconds.max(0) as u64,
+            ip,
+        )
+        .to_server_message()
+    };
+    ws_manager.send_to_user(notice.defender_id, event("defender", notice.revealed_ip.clone()));
+    ws_manager.send_to_user(notice.attacker_id, event("attacker", notice.hop_ip.clone()));
+
+    if let ("completed", Some(attacker_ip)) = (notice.status, &notice.revealed_ip) {
+        let event = he_websocket::EventBuilder::trace_completed(notice.pid, attacker_ip.clone());
+        ws_manager.send_to_user(notice.defender_id, event.to_server_message());
+    }
+}
+
+fn send_detection_notice(ws_manager: &he_websocket::ConnectionManager, notice: &DetectionNotice) {
+    let event = he_websocket::GameEvent::Custom {
+        event_name: "intrusion_detected".to_string(),
+        payload: serde_json::json!({
+            "flagged_ip": notice.flagged_ip,
+            "activity": notice.activity,
+            "risk": notice.risk,
+        }),
+    };
+    ws_manager.send_to_user(notice.defender_id as i64, event.to_server_message());
+}
+
+fn send_alert(ws_manager: &he_websocket::ConnectionManager, alert: &DefenderAlert) {
+    let kind = serde_json::to_value(alert.kind).ok().and_then(|v| v.as_str().map(str::to_string));
+    let activity = serde_json::to_value(alert.activity).ok().and_then(|v| v.as_str().map(str::to_string));
+
+    let event = he_websocket::EventBuilder::attack_alert(
+        kind.unwrap_or_default(),
+        activity.unwrap_or_default(),
+        alert.process_id as i64,
+        alert.attacker_ip.clone(),
+        alert.progress,
+    );
+    ws_manager.send_to_user(alert.defender_id as i64, event.to_server_message());
+}
+
+pub async fn start_trace(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<StartTraceRequest>,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    let cpu = match HardwareQueries::get_user_hardware(&state.db.pool, user_id).await {
+        Ok(hw) => hw.cpu_mhz,
+        Err(_) => 1000,
+    };
+    let config = &crate::balance::current().detection;
+
+    let attack = {
+        let registry = REGISTRY.lock().unwrap();
+        registry
+            .attacks
+            .get(&data.process_id)
+            .filter(|attack| attack.monitor.defender_id == user_id as u64)
+            .map(|attack| (attack.route.clone(), attack.started_at))
+    };
+    let Some((route, since)) = attack else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "No attack on your servers with that process id"
+        }));
+    };
+
+    let integrity: Vec<f64> = match LogQueries::trail(&state.db.pool, &TraceRoute::servers(&route), since).await {
+        Ok(counts) => counts.into_iter().map(|(intact, wiped)| trace_route::log_integrity(intact, wiped)).collect(),
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to start trace: {}", e)
+            }));
+        }
+    };
+
+    let trace = {
+        let registry = REGISTRY.lock().unwrap();
+        match registry.attacks.get(&data.process_id) {
+            Some(attack) => TraceRoute::start(
+                &attack.monitor,
+                &route,
+                &integrity,
+                cpu,
+                attack.attacker_stealth,
+                config,
+            )
+            .map(|trace| (trace, attack.attacker_id)),
+            None => {
+                return HttpResponse::NotFound().json(serde_json::json!({
+                    "success": false,
+                    "message": "No attack on your servers with that process id"
+                }));
+            }
+        }
+    };
+
+    let (trace, attacker_id) = match trace {
+        Ok(trace) => trace,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": e.to_string()
+            }));
+        }
+    };
+
+    match ProcessQueries::create_process_with_duration(
+        &state.db.pool,
+        user_id,
+        TraceRoute::PROCESS_TYPE,
+        &format!("pc_{}", user_id),
+        None,
+        trace.remaining_seconds() as i32,
+    ).await {
+        Ok(process) => {
+            let duration = trace.remaining_seconds();
+            let hops = trace.hops.len();
+            let active = ActiveTrace {
+                trace,
+                defender_id: user_id,
+                attacker_id,
+                since,
+                last_advanced: Utc::now(),
+                wipe: None,
+                finished_at: None,
+            };
+            if let Some(ws_manager) = &state.ws_manager {
+                send_trace_notice(ws_manager, &TraceNotice::new(process.pid, &active, "started", 0));
+            }
+            REGISTRY.lock().unwrap().traces.insert(process.pid, active);
+            start_ticker(state.db.pool.clone(), state.ws_manager.clone());
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "pid": process.pid,
+                "hops": hops,
+                "duration": duration,
+                "message": format!("Trace started (ETA: {} seconds)", duration)
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to start trace: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn get_trace(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    let pid = path.into_inner();
+    let registry = REGISTRY.lock().unwrap();
+    let Some(active) = registry.traces.get(&pid).filter(|t| t.defender_id == user_id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Trace not found"
+        }));
+    };
+
+    // The defender only knows the addresses read so far
+    let trace = &active.trace;
+    let revealed: Vec<&str> = trace.hops[..trace.current].iter().map(|hop| hop.from_ip.as_str()).collect();
+    let completed = trace.status == TraceStatus::Completed;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "pid": pid,
+        "status": trace.status,
+        "progress": trace.progress(),
+        "hop": trace.current,
+        "hops": trace.hops.len(),
+        "remaining_seconds": trace.remaining_seconds(),
+        "revealed": revealed,
+        "completed": completed,
+        "attacker_ip": completed.then(|| revealed.last().copied()).flatten()
+    }))
+}
+
+/// Traces running against the player, with their own servers on each
+pub async fn traces_on_me(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    let registry = REGISTRY.lock().unwrap();
+    let traces: Vec<_> = registry
+        .traces
+        .iter()
+        .filter(|(_, active)| active.attacker_id == user_id && active.trace.status == TraceStatus::Running)
+        .map(|(pid, active)| {
+            let hops: Vec<_> = active
+                .trace
+                .hops
+                .iter()
+                .enumerate()
+                .map(|(index, hop)| serde_json::json!({
+                    "ip": hop.ip,
+                    "integrity": hop.integrity,
+                    "read": index < active.trace.current,
+                }))
+                .collect();
+            serde_json::json!({
+                "trace_id": pid,
+                "progress": active.trace.progress(),
+                "hop": active.trace.current,
+                "remaining_seconds": active.trace.remaining_seconds(),
+                "hops": hops,
+                "wiping": active.wipe.as_ref().map(|wipe| serde_json::json!({
+                    "ip": wipe.hop_ip,
+                    "lands_at": wipe.lands_at,
+                })),
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "traces": traces
+    }))
+}
+
+/// Start wiping the player's trail from a server a trace against them has
+/// yet to read. It lands after a while, if the trace has not read the
+/// server by then.
+pub async fn wipe_trail(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<WipeTrailRequest>,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    let config = &crate::balance::current().detection;
+    let mut registry = REGISTRY.lock().unwrap();
+    let Some(active) = registry.traces.get_mut(&data.trace_id).filter(|t| t.attacker_id == user_id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "No trace against you with that id"
+        }));
+    };
+    let Some(hop) = active.trace.unread_hop(&data.ip) else {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "message": "The trace has already read that server, or does not pass it"
+        }));
+    };
+    if active.wipe.is_some() {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "message": "You are already wiping a server"
+        }));
+    }
+
+    let lands_at = Utc::now() + chrono::Duration::seconds(config.log_wipe_seconds);
+    active.wipe = Some(PendingWipe {
+        hop_ip: data.ip.clone(),
+        from_ip: active.trace.hops[hop].from_ip.clone(),
+        lands_at,
+    });
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "trace_id": data.trace_id,
+        "ip": data.ip,
+        "lands_at": lands_at
+    }))
+}
+
+/// IPs the player's stealth detections have flagged, until they expire
+pub async fn flagged(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    let registry = REGISTRY.lock().unwrap();
+    let flagged: Vec<_> = registry
+        .defenses
+        .get(&user_id)
+        .map(|defense| {
+            defense
+                .active_traces
+                .iter()
+                .filter(|trace| trace.expires_at > Utc::now())
+                .map(|trace| serde_json::json!({
+                    "ip": trace.attacker_ip,
+                    "strength": trace.trace_strength,
+                    "flagged_at": trace.created_at,
+                    "expires_at": trace.expires_at,
+                }))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "flagged": flagged
+    }))
+}
@@ -0,0 +1,174 @@
+//! Whois registry handlers
+//!
+//! The browser resolves hostnames here before it opens a page. Players also
+//! look up who registered a hostname, reverse-resolve addresses with a log
+//! analyzer, and register hostnames of their own. The registry itself lives
+//! in [`crate::dns`].
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_game_mechanics::dns::DnsDenied;
+use serde::Deserialize;
+use crate::dns;
+use crate::handlers::game::extract_user_id;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct ResolveQuery {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReverseQuery {
+    pub ip: String,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterRequest {
+    pub hostname: String,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn denied(denied: &DnsDenied) -> HttpResponse {
+    let mut response = match denied {
+        DnsDenied::NotFound => HttpResponse::NotFound(),
+        DnsDenied::NotOwner | DnsDenied::NeedsSoftware { .. } => HttpResponse::Forbidden(),
+        DnsDenied::HostnameTaken | DnsDenied::TooManyNames { .. } => HttpResponse::Conflict(),
+        DnsDenied::InsufficientFunds => HttpResponse::PaymentRequired(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// The address a hostname points at
+pub async fn resolve(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ResolveQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match dns::resolve(&state.db.pool, user_id, &query.name).await {
+        Ok(Ok(resolution)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "hostname": resolution.hostname,
+            "ip_address": resolution.ip_address
+        })),
+        Ok(Err(refused)) => denied(&refused),
+        Err(e) => failed("resolve hostname", e),
+    }
+}
+
+/// The hostnames pointing at an address; needs a log analyzer
+pub async fn reverse(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ReverseQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match dns::reverse(&state.db.pool, user_id, &query.ip).await {
+        Ok(Ok(hostnames)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "ip_address": query.ip.trim(),
+            "hostnames": hostnames
+        })),
+        Ok(Err(refused)) => denied(&refused),
+        Err(e) => failed("look up address", e),
+    }
+}
+
+/// Who registered a hostname, and when
+pub async fn whois(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match dns::whois(&state.db.pool, user_id, &path).await {
+        Ok(Ok(record)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "record": record
+        })),
+        Ok(Err(refused)) => denied(&refused),
+        Err(e) => failed("look up hostname", e),
+    }
+}
+
+/// The caller's hostnames, how many they may hold and the fee
+pub async fn list_hostnames(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match dns::hostnames(&state.db.pool, user_id).await {
+        Ok(hostnames) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "hostnames": hostnames
+        })),
+        Err(e) => failed("load hostnames", e),
+    }
+}
+
+/// Register a hostname for the caller's main server
+pub async fn register(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<RegisterRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match dns::register(&state.db.pool, user_id, &data.hostname).await {
+        Ok(Ok(record)) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "message": "Hostname registered",
+            "record": record
+        })),
+        Ok(Err(refused)) => denied(&refused),
+        Err(e) => failed("register hostname", e),
+    }
+}
+
+pub async fn release(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match dns::release(&state.db.pool, user_id, &path).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Hostname released"
+        })),
+        Ok(Err(refused)) => denied(&refused),
+        Err(e) => failed("release hostname", e),
+    }
+}
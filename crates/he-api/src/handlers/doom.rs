@@ -0,0 +1,148 @@
+//! Doom virus handlers
+//!
+//! Research, installing and disarming all run on the server; the client only
+//! learns the host through the clues it reads.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_game_mechanics::doom::DoomDenied;
+use serde::Deserialize;
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::doom;
+
+#[derive(Deserialize)]
+pub struct ServerRequest {
+    pub ip: String,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn invalid_ip() -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "success": false,
+        "message": "Invalid IP address"
+    }))
+}
+
+fn denied(denied: DoomDenied) -> HttpResponse {
+    let mut response = match denied {
+        DoomDenied::NoCampaign | DoomDenied::NoCountdown => HttpResponse::NotFound(),
+        DoomDenied::NotYourServer | DoomDenied::ServerNotHacked { .. } => HttpResponse::Forbidden(),
+        DoomDenied::Refused { .. } | DoomDenied::ResearchRunning { .. } | DoomDenied::CountdownRunning => {
+            HttpResponse::Conflict()
+        }
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// The caller's campaign and the countdown, if one is running
+pub async fn status(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match doom::status(&state.db.pool, user_id).await {
+        Ok(status) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "doom": status
+        })),
+        Err(e) => failed("load Doom status", e),
+    }
+}
+
+/// Start or finish the caller's next research step
+pub async fn research(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match doom::research(&state.db.pool, user_id).await {
+        Ok(Ok(outcome)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "research": outcome
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("research Doom", e),
+    }
+}
+
+pub async fn install(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<ServerRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    if body.ip.parse::<std::net::IpAddr>().is_err() {
+        return invalid_ip();
+    }
+
+    match doom::install(&state.db.pool, user_id, &body.ip).await {
+        Ok(Ok(detonates_at)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "detonates_at": detonates_at
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("install Doom", e),
+    }
+}
+
+/// Doom lines in the logs of an NPC the caller has cracked
+pub async fn read_logs(state: web::Data<AppState>, req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let ip = path.into_inner();
+    if ip.parse::<std::net::IpAddr>().is_err() {
+        return invalid_ip();
+    }
+
+    match doom::read_logs(&state.db.pool, user_id, &ip).await {
+        Ok(Ok(logs)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "server_ip": ip,
+            "logs": logs
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("read logs", e),
+    }
+}
+
+pub async fn disarm(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<ServerRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    if body.ip.parse::<std::net::IpAddr>().is_err() {
+        return invalid_ip();
+    }
+
+    match doom::disarm(&state.db.pool, user_id, &body.ip).await {
+        Ok(Ok(outcome)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "disarm": outcome
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("disarm Doom", e),
+    }
+}
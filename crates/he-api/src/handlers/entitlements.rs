@@ -0,0 +1,170 @@
+//! Premium entitlement handlers
+//!
+//! The payment provider's webhook endpoint is unauthenticated and trusts only
+//! the signature. Players read their own premium status; support staff with
+//! `entitlements:view` read anyone's subscriptions.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use chrono::Utc;
+use he_auth::AuthService;
+use he_database::queries::EntitlementQueries;
+use he_game_mechanics::entitlements::WebhookDenied;
+use he_helix_henforcer::{HenforcerError, HenforcerResult};
+use crate::entitlements::{self, Applied, PaymentWebhooks, WebhookEvent, PROVIDER, SIGNATURE_HEADER};
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::handlers::process::require_permission;
+
+const VIEW_PERMISSION: &str = "entitlements:view";
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn webhook_denied(denied: &WebhookDenied) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Resolve the caller and check they have premium, for premium-only features
+pub(crate) async fn require_premium(state: &web::Data<AppState>, req: &HttpRequest) -> Result<i64, HttpResponse> {
+    let Some(user_id) = extract_user_id(state, req).await else {
+        return Err(HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        })));
+    };
+
+    match entitlements::has_premium(&state.db.pool, user_id).await {
+        HenforcerResult::Ok(_) => Ok(user_id),
+        HenforcerResult::Err(HenforcerError::AccessDenied { .. }, _) => Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "This feature needs premium",
+            "premium_required": true
+        }))),
+        HenforcerResult::Err(e, _) => {
+            tracing::error!("Premium check failed for user {}: {}", user_id, e);
+            Err(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "success": false,
+                "message": "Entitlements unavailable"
+            })))
+        }
+    }
+}
+
+/// Subscription events from the payment provider. Anything but a 2xx makes
+/// the provider redeliver, so only failures worth retrying return 500.
+pub async fn payment_webhook(
+    state: web::Data<AppState>,
+    webhooks: web::Data<PaymentWebhooks>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse {
+    if !webhooks.is_configured() {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "success": false,
+            "message": "Payment webhooks are not configured"
+        }));
+    }
+
+    let header = req.headers().get(SIGNATURE_HEADER).and_then(|v| v.to_str().ok());
+    if let Err(denied) = webhooks.verify(header, &body, Utc::now()) {
+        tracing::warn!("Rejected payment webhook: {}", denied.message());
+        return webhook_denied(&denied);
+    }
+
+    let (event, payload) = match (serde_json::from_slice::<WebhookEvent>(&body), serde_json::from_slice(&body)) {
+        (Ok(event), Ok(payload)) => (event, payload),
+        _ => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "Malformed event"
+            }));
+        }
+    };
+
+    let pool = &state.db.pool;
+    match EntitlementQueries::record_event(pool, PROVIDER, &event.id, &event.event_type, &payload).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Already processed"
+            }));
+        }
+        Err(e) => return failed("record event", e),
+    }
+
+    let applied = entitlements::apply(pool, &event).await;
+    let error = applied.as_ref().err().map(|e| e.to_string());
+    if let Err(e) = EntitlementQueries::finish_event(pool, PROVIDER, &event.id, error.as_deref()).await {
+        tracing::warn!("Failed to record outcome of payment event {}: {}", event.id, e);
+    }
+
+    match applied {
+        Ok(Applied::Subscription { subscription_id, entitled, role_changed }) => {
+            if role_changed {
+                tracing::info!("Subscription {} now entitles: {}", subscription_id, entitled);
+            }
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Subscription updated"
+            }))
+        }
+        Ok(Applied::Stale) | Ok(Applied::Ignored) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Nothing to update"
+        })),
+        Err(e) => {
+            tracing::error!("Failed to apply payment event {} ({}): {}", event.id, event.event_type, e);
+            failed("apply event", e)
+        }
+    }
+}
+
+/// The caller's premium status and subscriptions
+pub async fn my_premium(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let pool = &state.db.pool;
+    match futures::try_join!(EntitlementQueries::entitlement(pool, user_id), EntitlementQueries::for_user(pool, user_id)) {
+        Ok((current, subscriptions)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "premium": current.is_some(),
+            "access_until": current.as_ref().and_then(|s| s.access_until),
+            "subscriptions": subscriptions
+        })),
+        Err(e) => failed("load subscriptions", e),
+    }
+}
+
+/// A player's subscriptions, for support
+pub async fn user_subscriptions(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, VIEW_PERMISSION).await {
+        return response;
+    }
+
+    match EntitlementQueries::for_user(&state.db.pool, path.into_inner()).await {
+        Ok(subscriptions) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "subscriptions": subscriptions
+        })),
+        Err(e) => failed("load subscriptions", e),
+    }
+}
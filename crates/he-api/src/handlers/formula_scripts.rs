@@ -0,0 +1,198 @@
+//! Formula override script handlers
+//!
+//! Showing, saving and reloading the formula scripts (see
+//! [`crate::formula_scripts`]) needs `rules:scripts`. Changes are stored for
+//! every node and audited whether or not they were accepted.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::FormulaScriptQueries;
+use he_game_mechanics::scripting::Formula;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::formula_scripts;
+use crate::handlers::process::require_permission;
+use crate::state::AppState;
+
+const SCRIPTS_PERMISSION: &str = "rules:scripts";
+
+#[derive(Deserialize)]
+pub struct SaveScriptRequest {
+    pub source: String,
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn unknown_formula(name: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "success": false,
+        "message": format!("{} is not a formula; expected one of success_rate, duration, experience_reward, money_reward", name)
+    }))
+}
+
+/// Apply the stored scripts on this node right away; the others follow on
+/// their next live-ops reload
+async fn reload_here(state: &AppState) -> HttpResponse {
+    match formula_scripts::reload(&state.db.pool).await {
+        Ok(Ok(_)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "scripts": formula_scripts::status()
+        })),
+        Ok(Err(e)) => HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "success": false,
+            "message": format!("Stored scripts not loaded, the running ones stay: {}", e)
+        })),
+        Err(e) => failed("reload formula scripts", e),
+    }
+}
+
+/// The stored scripts, which formulas this node overrides and how often
+/// each fell back
+pub async fn status(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, SCRIPTS_PERMISSION).await {
+        return response;
+    }
+
+    match FormulaScriptQueries::all(&state.db.pool).await {
+        Ok(stored) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "stored": stored,
+            "scripts": formula_scripts::status()
+        })),
+        Err(e) => failed("load formula scripts", e),
+    }
+}
+
+/// Store a formula's script for every node; a script that fails to compile
+/// or trial-run is not stored
+pub async fn save(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<SaveScriptRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, SCRIPTS_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let Some(formula) = Formula::from_name(&path) else {
+        return unknown_formula(&path);
+    };
+
+    if let Err(e) = formula_scripts::validate(formula, &body.source) {
+        audit.log_event(SecurityEvent::FormulaScriptChanged {
+            admin_id,
+            formula: formula.name().to_string(),
+            removed: false,
+            error: Some(e.to_string()),
+        }).await;
+        return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "success": false,
+            "message": format!("Script not saved: {}", e)
+        }));
+    }
+
+    if let Err(e) = FormulaScriptQueries::save(&state.db.pool, formula.name(), &body.source, admin_id).await {
+        return failed("save formula script", e);
+    }
+    audit.log_event(SecurityEvent::FormulaScriptChanged {
+        admin_id,
+        formula: formula.name().to_string(),
+        removed: false,
+        error: None,
+    }).await;
+    tracing::warn!("Formula script {} saved by admin {}", formula, admin_id);
+    reload_here(&state).await
+}
+
+/// Drop a formula's script so every node runs it natively again
+pub async fn remove(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, SCRIPTS_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let Some(formula) = Formula::from_name(&path) else {
+        return unknown_formula(&path);
+    };
+
+    match FormulaScriptQueries::remove(&state.db.pool, formula.name(), admin_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": format!("{} has no script", formula)
+            }));
+        }
+        Err(e) => return failed("remove formula script", e),
+    }
+    audit.log_event(SecurityEvent::FormulaScriptChanged {
+        admin_id,
+        formula: formula.name().to_string(),
+        removed: true,
+        error: None,
+    }).await;
+    tracing::warn!("Formula script {} removed by admin {}", formula, admin_id);
+    reload_here(&state).await
+}
+
+/// Have every node recompile the stored scripts; a set that fails to load
+/// leaves the running one in place
+pub async fn reload(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, SCRIPTS_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    if let Err(e) = FormulaScriptQueries::bump(&state.db.pool, admin_id).await {
+        return failed("reload formula scripts", e);
+    }
+    let result = match formula_scripts::reload(&state.db.pool).await {
+        Ok(result) => result,
+        Err(e) => return failed("reload formula scripts", e),
+    };
+    audit.log_event(SecurityEvent::FormulaScriptsReloaded {
+        admin_id,
+        overridden: result
+            .as_ref()
+            .map(|formulas| formulas.iter().map(|formula| formula.name().to_string()).collect())
+            .unwrap_or_default(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    }).await;
+
+    match result {
+        Ok(formulas) => {
+            tracing::warn!("Formula scripts reloaded by admin {}: {:?}", admin_id, formulas);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": format!("{} formula scripts loaded", formulas.len()),
+                "scripts": formula_scripts::status()
+            }))
+        }
+        Err(e) => HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "success": false,
+            "message": format!("Scripts not reloaded, the running ones stay: {}", e)
+        })),
+    }
+}
@@ -103,4 +103,12 @@ pub async fn extract_user_id(state: &web::Data<AppState>, req: &HttpRequest) ->
 
     let validated = state.auth.validate_token(token).await.ok()??;
     Some(validated.user_id.as_u128() as i64)
-}
\ No newline at end of file
+}
+/// The rules this server runs with; public so clients can show them before
+/// sign-up
+pub async fn get_rules() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "rules": crate::rules::current()
+    }))
+}
@@ -0,0 +1,49 @@
+//! WebSocket gateway discovery handlers
+//!
+//! Clients time a few requests to `/api/gateways/ping` on each region's host,
+//! then pass the round trips to discovery, e.g.
+//! `/api/gateways?rtt=eu-west:42,us-east:130`. Signed-in players also get the
+//! region they last used when it is not clearly slower.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use serde::Deserialize;
+use crate::gateway;
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+
+#[derive(Deserialize)]
+pub struct DiscoverQuery {
+    pub rtt: Option<String>,
+}
+
+/// Healthy gateways for the caller, with the recommended one
+pub async fn discover(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<DiscoverQuery>,
+) -> HttpResponse {
+    let user_id = extract_user_id(&state, &req).await;
+    let rtts = query.rtt.as_deref().map(gateway::parse_rtts).unwrap_or_default();
+
+    match gateway::discover(&state.db.pool, user_id, &rtts).await {
+        Ok(discovery) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(serde_json::json!({
+                "success": true,
+                "recommended": discovery.recommended,
+                "gateways": discovery.gateways,
+                "affinity": discovery.affinity
+            })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to discover gateways: {}", e)
+        })),
+    }
+}
+
+/// Empty response for clients timing round trips to this region
+pub async fn ping() -> HttpResponse {
+    HttpResponse::NoContent()
+        .insert_header(("Cache-Control", "no-store"))
+        .finish()
+}
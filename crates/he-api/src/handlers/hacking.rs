@@ -5,6 +5,7 @@ use chrono::Utc;
 use he_database::{Database, queries::ProcessQueries};
 use he_game_world::{GameWorld, NPCServer};
 use he_game_mechanics::{GameEngine, GameMechanics};
+use he_game_mechanics::scripting::Formula;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -132,9 +133,21 @@ pub async fn hack_server(
             duration,
         ).await?;
 
+        // Scripts see the success rate in basis points
+        let success_rate = crate::formula_scripts::current().apply(
+            Formula::SuccessRate,
+            (success_rate.to_f64().unwrap_or(0.5) * 10_000.0).round() as i64,
+            &data.crack_method,
+            &[
+                ("player_level", player.level as i64),
+                ("target_difficulty", target_info.difficulty_level as i64),
+                ("security_rating", target_info.security_rating as i64),
+            ],
+        );
+
         // Check if hack succeeds (random based on success_rate)
         let mut rng = rand::thread_rng();
-        let success = rng.gen::<f64>() < success_rate.to_f64().unwrap_or(0.5);
+        let success = rng.gen_range(0..10_000) < success_rate;
 
         if success {
             // Add to user's hacked servers list
@@ -144,13 +157,15 @@ pub async fn hack_server(
             let server_tier = (server.security.firewall_level / 25 + 1).min(5) as u32;
             let is_first_time = !state.has_hacked_server(user.user_id, &data.target_ip).await?;
 
-            // Update progression when hack completes
-            state.update_progression_on_hack(
-                user.user_id,
-                server_tier,
-                is_first_time,
-                &data.target_ip,
-            ).await?;
+            // Update progression when hack completes; honeypots pay nothing
+            if server.grants_rewards() {
+                state.update_progression_on_hack(
+                    user.user_id,
+                    server_tier,
+                    is_first_time,
+                    &data.target_ip,
+                ).await?;
+            }
 
             // Grant access after process completes
             Ok(HttpResponse::Ok().json(HackResponse {
@@ -220,8 +235,10 @@ pub async fn server_action(
             "download" => {
                 if let Some(filename) = &data.parameter {
                     if let Some(file) = server.download_file(filename, &format!("player_{}", user.user_id)) {
-                        // Add file to user's storage
-                        state.add_file_to_user(user.user_id, file.clone()).await?;
+                        // Add file to user's storage; a honeypot's files are bait
+                        if server.grants_rewards() {
+                            state.add_file_to_user(user.user_id, file.clone()).await?;
+                        }
 
                         Ok(HttpResponse::Ok().json(ServerActionResponse {
                             success: true,
@@ -298,8 +315,10 @@ pub async fn server_action(
                 if let Some(amount_str) = &data.parameter {
                     if let Ok(amount) = amount_str.parse::<i64>() {
                         if server.transfer_money(amount, &format!("player_{}", user.user_id)) {
-                            // Add money to user's account
-                            state.add_money_to_user(user.user_id, amount).await?;
+                            // Add money to user's account; a honeypot's money is bait
+                            if server.grants_rewards() {
+                                state.add_money_to_user(user.user_id, amount).await?;
+                            }
 
                             Ok(HttpResponse::Ok().json(ServerActionResponse {
                                 success: true,
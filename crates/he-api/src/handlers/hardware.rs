@@ -4,6 +4,7 @@ use actix_web::{web, HttpResponse, HttpRequest};
 use serde::{Deserialize, Serialize};
 use crate::state::AppState;
 use crate::handlers::game::extract_user_id;
+use he_game_mechanics::wear::RepairDenied;
 
 #[derive(Serialize)]
 pub struct HardwareInfo {
@@ -14,6 +15,13 @@ pub struct HardwareInfo {
     pub net_speed: f64,
 }
 
+#[derive(Deserialize)]
+pub struct RepairRequest {
+    pub component: String,
+    /// Quality to fit instead of the current one
+    pub quality: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct UpgradeRequest {
     pub component: String, // cpu, ram, hdd, net
@@ -117,4 +125,87 @@ pub async fn upgrade_hardware(
             }))
         }
     }
-}
\ No newline at end of file
+}
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn denied(denied: RepairDenied) -> HttpResponse {
+    let mut response = match denied {
+        RepairDenied::InProgress => HttpResponse::Conflict(),
+        RepairDenied::InsufficientFunds { .. } => HttpResponse::PaymentRequired(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Wear, condition and repair quote of each component of the caller's hardware
+pub async fn get_condition(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let hardware = match he_database::queries::HardwareQueries::get_user_hardware(&state.db.pool, user_id).await {
+        Ok(hardware) => hardware,
+        Err(e) => return failed("get hardware", e),
+    };
+
+    match crate::hardware_wear::report(&state.db.pool, hardware.hardware_id).await {
+        Ok(components) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "components": components
+        })),
+        Err(e) => failed("get hardware condition", e),
+    }
+}
+
+/// Start a paid repair process for one component of the caller's hardware
+pub async fn repair_hardware(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<RepairRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let hardware = match he_database::queries::HardwareQueries::get_user_hardware(&state.db.pool, user_id).await {
+        Ok(hardware) => hardware,
+        Err(e) => return failed("get hardware", e),
+    };
+
+    let started = crate::hardware_wear::start_repair(
+        &state.db.pool,
+        user_id,
+        hardware.hardware_id,
+        &data.component,
+        data.quality.as_deref(),
+    )
+    .await;
+    match started {
+        Ok(Ok(repair)) => {
+            crate::completion::schedule(&state, repair.pid, repair.end_time);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "repair": repair
+            }))
+        }
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("start repair", e),
+    }
+}
@@ -0,0 +1,213 @@
+//! Bank heist handlers
+//!
+//! Players list the open heist windows, form or join a crew in one of the
+//! roles, and act in their role once the leader starts the raid. The raid
+//! itself lives in [`crate::heist`].
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_game_mechanics::heist::{HeistDenied, HeistRole};
+use serde::Deserialize;
+use crate::handlers::game::extract_user_id;
+use crate::heist::{self, HeistEvent};
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct RoleRequest {
+    pub role: String,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn denied(denied: &HeistDenied) -> HttpResponse {
+    let mut response = match denied {
+        HeistDenied::NotFound => HttpResponse::NotFound(),
+        HeistDenied::NotMember | HeistDenied::NotLeader => HttpResponse::Forbidden(),
+        _ => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn role(request: &RoleRequest) -> Result<HeistRole, HttpResponse> {
+    HeistRole::from_str(&request.role).ok_or_else(|| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!(
+                "Pick one of the roles {}",
+                HeistRole::ALL.iter().map(|r| r.as_str()).collect::<Vec<_>>().join(", ")
+            )
+        }))
+    })
+}
+
+/// Publish what a raid action did and answer with `message`
+async fn outcome(
+    state: &web::Data<AppState>,
+    outcome: anyhow::Result<Result<Vec<HeistEvent>, HeistDenied>>,
+    message: &str,
+) -> HttpResponse {
+    match outcome {
+        Ok(Ok(events)) => {
+            let body = serde_json::json!({
+                "success": true,
+                "message": message,
+                "events": &events
+            });
+            heist::publish(state.ws_manager.clone(), events).await;
+            HttpResponse::Ok().json(body)
+        }
+        Ok(Err(refused)) => denied(&refused),
+        Err(e) => failed("update the raid", e),
+    }
+}
+
+/// Heists whose window is open, with what is left in each vault
+pub async fn open_heists(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    if extract_user_id(&state, &req).await.is_none() {
+        return unauthorized();
+    }
+
+    match heist::open_heists(&state.db.pool).await {
+        Ok(heists) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "heists": heists
+        })),
+        Err(e) => failed("load heists", e),
+    }
+}
+
+/// Form a crew against a heist, leading it in the chosen role
+pub async fn form_crew(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Json<RoleRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let role = match role(&data) {
+        Ok(role) => role,
+        Err(response) => return response,
+    };
+
+    match heist::form(&state.db.pool, user_id, path.into_inner(), role).await {
+        Ok(Ok((raid_id, events))) => {
+            heist::publish(state.ws_manager.clone(), events).await;
+            HttpResponse::Created().json(serde_json::json!({
+                "success": true,
+                "message": "Crew formed",
+                "raid_id": raid_id
+            }))
+        }
+        Ok(Err(refused)) => denied(&refused),
+        Err(e) => failed("form a crew", e),
+    }
+}
+
+/// A raid and its crew; crew members only
+pub async fn get_raid(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match heist::view(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok((raid, crew))) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "raid": raid,
+            "crew": crew
+        })),
+        Ok(Err(refused)) => denied(&refused),
+        Err(e) => failed("load the raid", e),
+    }
+}
+
+pub async fn join_raid(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Json<RoleRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let role = match role(&data) {
+        Ok(role) => role,
+        Err(response) => return response,
+    };
+
+    let result = heist::join(&state.db.pool, user_id, path.into_inner(), role).await;
+    outcome(&state, result, "Joined the crew").await
+}
+
+pub async fn leave_raid(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let result = heist::leave(&state.db.pool, user_id, path.into_inner()).await;
+    outcome(&state, result, "Left the crew").await
+}
+
+pub async fn start_raid(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let result = heist::start(&state.db.pool, user_id, path.into_inner()).await;
+    outcome(&state, result, "Raid started").await
+}
+
+/// Act in the caller's role: DDoS the firewall, crack the vault, transfer
+/// the funds or wipe the logs, whichever the raid is waiting on
+pub async fn act(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let result = heist::act(&state.db.pool, user_id, path.into_inner()).await;
+    outcome(&state, result, "Raid updated").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_request_parses_known_roles() {
+        assert_eq!(role(&RoleRequest { role: "crack".to_string() }).ok(), Some(HeistRole::Crack));
+        let refused = role(&RoleRequest { role: "getaway".to_string() }).unwrap_err();
+        assert_eq!(refused.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,82 @@
+//! Honeypot handlers
+//!
+//! Investigators holding `honeypots:view` page through honeypot trips with
+//! the route each attacker really came through, and see what the intrusion
+//! detector recorded against a player (see [`crate::honeypot`]).
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::HoneypotQueries;
+use he_helix_security::IntrusionDetector;
+use serde::Deserialize;
+use crate::state::AppState;
+use crate::handlers::process::require_permission;
+
+const VIEW_PERMISSION: &str = "honeypots:view";
+const DEFAULT_TRIPS: i64 = 100;
+const MAX_TRIPS: i64 = 500;
+
+#[derive(Deserialize)]
+pub struct TripsQuery {
+    /// Only trips that flagged their player as likely scripted
+    #[serde(default)]
+    pub anomalous: bool,
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Honeypot trips, newest first
+pub async fn trips(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    query: web::Query<TripsQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, VIEW_PERMISSION).await {
+        return response;
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_TRIPS).clamp(1, MAX_TRIPS);
+    match HoneypotQueries::trips(&state.db.pool, query.anomalous, query.before_id, limit).await {
+        Ok(trips) => {
+            let next_before_id = if trips.len() as i64 == limit { trips.last().map(|t| t.id) } else { None };
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "trips": trips,
+                "next_before_id": next_before_id
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load honeypot trips: {}", e)
+        })),
+    }
+}
+
+/// In-game patterns the intrusion detector holds against a player
+pub async fn user_patterns(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    detector: web::Data<IntrusionDetector>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, VIEW_PERMISSION).await {
+        return response;
+    }
+
+    let patterns: Vec<_> = detector
+        .account_patterns(path.into_inner())
+        .into_iter()
+        .map(|p| serde_json::json!({
+            "pattern": p.pattern_type,
+            "occurrences": p.occurrences,
+            "last_seen_secs_ago": p.last_seen.elapsed().as_secs(),
+            "details": p.details
+        }))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "patterns": patterns
+    }))
+}
@@ -0,0 +1,183 @@
+//! Admin management of IP policy rules
+//!
+//! Every endpoint requires the `security:manage` permission. Changes are written
+//! to `ip_policy_rules` and the local policy is reloaded straight away; other
+//! nodes pick them up on their next periodic reload.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_helix_security::ip_policy::{self, NewIpRule, RuleTarget};
+use he_helix_security::{AuditLogger, IpPolicy, SecurityEvent};
+use std::net::IpAddr;
+use crate::state::AppState;
+use crate::handlers::process::require_permission;
+
+const MANAGE_PERMISSION: &str = "security:manage";
+
+/// Resolve the caller and check they may manage IP policy
+async fn require_admin(
+    state: &web::Data<AppState>,
+    auth: &AuthService,
+    req: &HttpRequest,
+) -> Result<i64, HttpResponse> {
+    require_permission(state, auth, req, MANAGE_PERMISSION).await
+}
+
+/// Reload after a change. The rule is stored either way, so a failure here
+/// only delays it until the periodic reload.
+async fn reload(state: &web::Data<AppState>, policy: &IpPolicy) {
+    if let Err(e) = policy.reload(&state.db.pool).await {
+        tracing::warn!("IP policy reload after change failed: {}", e);
+    }
+}
+
+pub async fn list_rules(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    policy: web::Data<IpPolicy>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_admin(&state, &auth, &req).await {
+        return response;
+    }
+
+    match ip_policy::list_rules(&state.db.pool).await {
+        Ok(rules) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "rules": rules,
+            "loaded": policy.rules().await.len()
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load rules: {}", e)
+        })),
+    }
+}
+
+pub async fn add_rule(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    policy: web::Data<IpPolicy>,
+    req: HttpRequest,
+    data: web::Json<NewIpRule>,
+) -> HttpResponse {
+    let admin_id = match require_admin(&state, &auth, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let rule = data.into_inner();
+
+    if let RuleTarget::Country(code) = &rule.target {
+        if code.len() != 2 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": "Country must be an upper-case ISO 3166-1 alpha-2 code"
+            }));
+        }
+    }
+
+    match ip_policy::add_rule(&state.db.pool, &rule, admin_id).await {
+        Ok(rule_id) => {
+            audit.log_event(SecurityEvent::IpPolicyRuleChanged {
+                admin_id,
+                rule_id,
+                change: "added".to_string(),
+            }).await;
+            reload(&state, &policy).await;
+
+            HttpResponse::Created().json(serde_json::json!({
+                "success": true,
+                "message": "Rule added",
+                "rule_id": rule_id
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to add rule: {}", e)
+        })),
+    }
+}
+
+pub async fn delete_rule(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    policy: web::Data<IpPolicy>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let admin_id = match require_admin(&state, &auth, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let rule_id = path.into_inner();
+
+    match ip_policy::delete_rule(&state.db.pool, rule_id).await {
+        Ok(true) => {
+            audit.log_event(SecurityEvent::IpPolicyRuleChanged {
+                admin_id,
+                rule_id,
+                change: "deleted".to_string(),
+            }).await;
+            reload(&state, &policy).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Rule deleted"
+            }))
+        }
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Rule not found"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to delete rule: {}", e)
+        })),
+    }
+}
+
+pub async fn reload_rules(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    policy: web::Data<IpPolicy>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_admin(&state, &auth, &req).await {
+        return response;
+    }
+
+    match policy.reload(&state.db.pool).await {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": format!("Loaded {} rules", count)
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to reload rules: {}", e)
+        })),
+    }
+}
+
+/// Dry-run the policy for an address, including its GeoIP data
+pub async fn check_ip(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    policy: web::Data<IpPolicy>,
+    req: HttpRequest,
+    path: web::Path<IpAddr>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(&state, &auth, &req).await {
+        return response;
+    }
+
+    let ip = path.into_inner();
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "all": policy.check(ip, ip_policy::PolicyScope::All).await,
+        "registration": policy.check(ip, ip_policy::PolicyScope::Registration).await
+    }))
+}
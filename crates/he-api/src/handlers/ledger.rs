@@ -0,0 +1,220 @@
+//! Ledger forensics handlers
+//!
+//! Investigators holding `ledger:audit` search the money ledger by player,
+//! account, reason, reference and time, follow a transaction to all of its
+//! entries and see where balances disagree with the ledger (see
+//! [`crate::ledger`]). Settling a discrepancy changes money and needs
+//! `ledger:adjust`.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use chrono::{DateTime, Utc};
+use he_auth::AuthService;
+use he_database::queries::{LedgerFilter, LedgerQueries, LedgerReason};
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::ledger::{self, Resolution};
+use crate::state::AppState;
+use crate::handlers::process::require_permission;
+
+const AUDIT_PERMISSION: &str = "ledger:audit";
+const ADJUST_PERMISSION: &str = "ledger:adjust";
+const DEFAULT_ENTRIES: i64 = 100;
+const MAX_ENTRIES: i64 = 1000;
+const DISCREPANCIES_SIZE: i64 = 200;
+const MAX_NOTE_LENGTH: usize = 500;
+
+#[derive(Deserialize)]
+pub struct EntriesQuery {
+    pub user_id: Option<i64>,
+    pub account: Option<String>,
+    pub reason: Option<String>,
+    pub reference: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct DiscrepanciesQuery {
+    /// Include resolved discrepancies
+    #[serde(default)]
+    pub all: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveRequest {
+    /// `restore` or `adjust`
+    pub action: String,
+    pub note: String,
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "success": false,
+        "message": message
+    }))
+}
+
+/// Entries matching the query, newest first, with their transaction
+pub async fn entries(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    query: web::Query<EntriesQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, AUDIT_PERMISSION).await {
+        return response;
+    }
+
+    let query = query.into_inner();
+    if let Some(reason) = query.reason.as_deref() {
+        if LedgerReason::from_str(reason).is_none() {
+            return bad_request("Unknown reason");
+        }
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_ENTRIES).clamp(1, MAX_ENTRIES);
+    let filter = LedgerFilter {
+        user_id: query.user_id,
+        account: query.account,
+        reason: query.reason,
+        reference: query.reference,
+        since: query.since,
+        until: query.until,
+        before_id: query.before_id,
+    };
+    match LedgerQueries::entries(&state.db.pool, &filter, limit).await {
+        Ok(entries) => {
+            // A full page may have more behind it
+            let next_before_id = if entries.len() as i64 == limit { entries.last().map(|e| e.id) } else { None };
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "entries": entries,
+                "next_before_id": next_before_id
+            }))
+        }
+        Err(e) => failed("search the ledger", e),
+    }
+}
+
+/// Every entry of one transaction
+pub async fn transaction(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, AUDIT_PERMISSION).await {
+        return response;
+    }
+
+    match LedgerQueries::transaction(&state.db.pool, path.into_inner()).await {
+        Ok(entries) if entries.is_empty() => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Transaction not found"
+        })),
+        Ok(entries) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "entries": entries
+        })),
+        Err(e) => failed("load transaction", e),
+    }
+}
+
+/// A ledger account's balance as its entries have it
+pub async fn account_balance(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, AUDIT_PERMISSION).await {
+        return response;
+    }
+
+    let account = path.into_inner();
+    match LedgerQueries::balance(&state.db.pool, &account).await {
+        Ok(balance) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "account": account,
+            "balance": balance
+        })),
+        Err(e) => failed("load balance", e),
+    }
+}
+
+/// Accounts whose balance disagrees with the ledger, newest first
+pub async fn discrepancies(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    query: web::Query<DiscrepanciesQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, AUDIT_PERMISSION).await {
+        return response;
+    }
+
+    match LedgerQueries::discrepancies(&state.db.pool, !query.all, DISCREPANCIES_SIZE).await {
+        Ok(discrepancies) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "discrepancies": discrepancies
+        })),
+        Err(e) => failed("load discrepancies", e),
+    }
+}
+
+/// Restore a balance from the ledger, or accept it with an adjustment
+pub async fn resolve(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<ResolveRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, ADJUST_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let Some(resolution) = Resolution::from_str(&body.action) else {
+        return bad_request("Action must be restore or adjust");
+    };
+    let note = body.note.trim();
+    if note.is_empty() || note.chars().count() > MAX_NOTE_LENGTH {
+        return bad_request(&format!("Note must be 1 to {} characters", MAX_NOTE_LENGTH));
+    }
+
+    let discrepancy_id = path.into_inner();
+    match ledger::resolve(&state.db.pool, discrepancy_id, admin_id, resolution, note).await {
+        Ok(Some(discrepancy)) => {
+            audit.log_event(SecurityEvent::LedgerDiscrepancyResolved {
+                admin_id,
+                discrepancy_id,
+                bank_account_id: discrepancy.bank_account_id,
+                action: resolution.as_str().to_string(),
+            }).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": match resolution {
+                    Resolution::Restore => "Balance restored from the ledger",
+                    Resolution::Adjust => "Balance accepted with an adjustment",
+                },
+                "discrepancy": discrepancy
+            }))
+        }
+        Ok(None) => HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "message": "This discrepancy is not open"
+        })),
+        Err(e) => failed("resolve discrepancy", e),
+    }
+}
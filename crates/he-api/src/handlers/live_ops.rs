@@ -0,0 +1,486 @@
+//! Admin live-ops console
+//!
+//! A WebSocket for the `liveops:console` permission (admins only). The admin
+//! must also complete an MFA step-up on the socket before health samples are
+//! streamed or any command is accepted, and the step-up expires after
+//! [`STEP_UP_TTL`]. Every step-up attempt and every command is written to the
+//! audit trail, whether it was accepted or not.
+//!
+//! Messages are JSON objects tagged by `type`. Client to server: `step_up`,
+//! `announce`, `set_flag`, `drain`, `nodes`, `thresholds`, `set_threshold`,
+//! `clear_threshold`. Server to client: `hello`, `step_up_ok`,
+//! `step_up_failed`, `health`, `flags`, `nodes`, `thresholds`, `command_ok`,
+//! `error`.
+//!
+//! Threshold commands act on the console node's environment unless another
+//! one is named.
+
+use actix::{Actor, ActorContext, ActorFutureExt, AsyncContext, StreamHandler, WrapFuture};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use he_auth::AuthService;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use he_monitoring::{AlertMetric, AlertThresholds, Threshold};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+use he_database::models::legacy_user_uuid;
+use crate::handlers::process::require_permission;
+use crate::live_ops::{
+    environment_name, parse_environment, FeatureFlag, HealthSample, LiveOps, NodeStatus, ThresholdChange,
+    ANNOUNCEMENT_PRIORITIES,
+};
+use crate::state::AppState;
+
+const CONSOLE_PERMISSION: &str = "liveops:console";
+
+/// How long a successful step-up authorises commands
+const STEP_UP_TTL: Duration = Duration::from_secs(15 * 60);
+/// Failed step-ups before the console is closed
+const MAX_STEP_UP_ATTEMPTS: u32 = 3;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MAX_FLAG_NAME_LEN: usize = 64;
+const MAX_TITLE_LEN: usize = 200;
+const MAX_CONTENT_LEN: usize = 4000;
+/// Threshold changes listed with the thresholds
+const THRESHOLD_HISTORY: i64 = 20;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ConsoleCommand {
+    StepUp { code: String },
+    Announce { title: String, content: String, priority: String },
+    SetFlag { name: String, enabled: bool },
+    /// `node` defaults to the node the console is connected to
+    Drain { node: Option<String>, draining: bool },
+    Nodes,
+    Thresholds { environment: Option<String> },
+    SetThreshold { metric: AlertMetric, warning: f64, critical: f64, environment: Option<String> },
+    /// Back to the environment default
+    ClearThreshold { metric: AlertMetric, environment: Option<String> },
+}
+
+impl ConsoleCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::StepUp { .. } => "step_up",
+            Self::Announce { .. } => "announce",
+            Self::SetFlag { .. } => "set_flag",
+            Self::Drain { .. } => "drain",
+            Self::Nodes => "nodes",
+            Self::Thresholds { .. } => "thresholds",
+            Self::SetThreshold { .. } => "set_threshold",
+            Self::ClearThreshold { .. } => "clear_threshold",
+        }
+    }
+
+    /// Reject malformed commands before they reach the database
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Announce { title, content, priority } => {
+                if title.trim().is_empty() || title.len() > MAX_TITLE_LEN {
+                    return Err("Announcement title must be 1-200 characters".to_string());
+                }
+                if content.trim().is_empty() || content.len() > MAX_CONTENT_LEN {
+                    return Err("Announcement content must be 1-4000 characters".to_string());
+                }
+                if !ANNOUNCEMENT_PRIORITIES.contains(&priority.as_str()) {
+                    return Err("Priority must be info, warning or critical".to_string());
+                }
+            }
+            Self::SetFlag { name, .. } => {
+                let valid = !name.is_empty()
+                    && name.len() <= MAX_FLAG_NAME_LEN
+                    && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.');
+                if !valid {
+                    return Err("Flag names use lowercase letters, digits, '_' and '.'".to_string());
+                }
+            }
+            Self::Drain { node: Some(node), .. } if node.is_empty() => {
+                return Err("Node id must not be empty".to_string());
+            }
+            Self::SetThreshold { metric, warning, critical, .. } => {
+                he_monitoring::thresholds::validate(*metric, &Threshold::new(*warning, *critical))
+                    .map_err(|e| e.to_string())?;
+            }
+            _ => {}
+        }
+
+        if let Self::Thresholds { environment: Some(name), .. }
+        | Self::SetThreshold { environment: Some(name), .. }
+        | Self::ClearThreshold { environment: Some(name), .. } = self
+        {
+            if parse_environment(name).is_none() {
+                return Err("Environment must be development, staging or production".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// The environment a threshold command acts on
+    fn environment(&self, live_ops: &LiveOps) -> he_core::config::Environment {
+        match self {
+            Self::Thresholds { environment: Some(name) }
+            | Self::SetThreshold { environment: Some(name), .. }
+            | Self::ClearThreshold { environment: Some(name), .. } => {
+                parse_environment(name).unwrap_or_else(|| live_ops.environment().clone())
+            }
+            _ => live_ops.environment().clone(),
+        }
+    }
+
+    /// What the audit trail records about the command
+    fn detail(&self) -> String {
+        match self {
+            Self::StepUp { .. } => String::new(),
+            Self::Announce { title, priority, .. } => format!("[{}] {}", priority, title),
+            Self::SetFlag { name, enabled } => format!("{}={}", name, enabled),
+            Self::Drain { node, draining } => {
+                format!("{}={}", node.as_deref().unwrap_or("<this node>"), draining)
+            }
+            Self::Nodes => String::new(),
+            Self::Thresholds { environment } => environment.clone().unwrap_or_default(),
+            Self::SetThreshold { metric, warning, critical, environment } => format!(
+                "{}:{}={}/{}",
+                environment.as_deref().unwrap_or("<this node>"),
+                metric,
+                warning,
+                critical
+            ),
+            Self::ClearThreshold { metric, environment } => {
+                format!("{}:{}=default", environment.as_deref().unwrap_or("<this node>"), metric)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ConsoleEvent {
+    Hello { node: String, step_up_required: bool },
+    StepUpOk { expires_in: u64 },
+    StepUpFailed { attempts_left: u32 },
+    Health { sample: HealthSample },
+    Flags { flags: Vec<FeatureFlag> },
+    Nodes { nodes: Vec<NodeStatus> },
+    Thresholds { thresholds: AlertThresholds, changes: Vec<ThresholdChange> },
+    CommandOk { command: &'static str, message: String },
+    Error { message: String },
+}
+
+impl ConsoleEvent {
+    fn error(message: impl Into<String>) -> Self {
+        Self::Error { message: message.into() }
+    }
+}
+
+/// Open the live-ops console
+pub async fn console(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    live_ops: web::Data<LiveOps>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let admin_id = match require_permission(&state, &auth, &req, CONSOLE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return Ok(response),
+    };
+
+    let session = ConsoleSession {
+        admin_id,
+        pool: state.db.pool.clone(),
+        auth,
+        audit,
+        live_ops,
+        stepped_up_until: None,
+        failed_step_ups: 0,
+        streaming_health: false,
+        hb: Instant::now(),
+    };
+
+    ws::start(session, &req, stream)
+}
+
+/// An environment's thresholds in force and its latest changes
+async fn threshold_event(
+    live_ops: &LiveOps,
+    pool: &PgPool,
+    environment: he_core::config::Environment,
+) -> anyhow::Result<ConsoleEvent> {
+    let changes = live_ops.threshold_changes(pool, &environment, THRESHOLD_HISTORY).await?;
+    match live_ops.thresholds(pool, environment).await? {
+        Ok(thresholds) => Ok(ConsoleEvent::Thresholds { thresholds, changes }),
+        Err(e) => Ok(ConsoleEvent::error(format!("Stored thresholds are invalid: {}", e))),
+    }
+}
+
+struct ConsoleSession {
+    admin_id: i64,
+    pool: PgPool,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    live_ops: web::Data<LiveOps>,
+    stepped_up_until: Option<Instant>,
+    failed_step_ups: u32,
+    streaming_health: bool,
+    hb: Instant,
+}
+
+impl Actor for ConsoleSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+
+        self.send(ctx, &ConsoleEvent::Hello {
+            node: self.live_ops.node_id().to_string(),
+            step_up_required: true,
+        });
+    }
+}
+
+impl ConsoleSession {
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, event: &ConsoleEvent) {
+        match serde_json::to_string(event) {
+            Ok(text) => ctx.text(text),
+            Err(e) => tracing::error!("Failed to serialise live-ops event: {}", e),
+        }
+    }
+
+    fn stepped_up(&self) -> bool {
+        self.stepped_up_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    fn audit(&self, command: &'static str, detail: String, accepted: bool) -> impl std::future::Future<Output = ()> {
+        let audit = self.audit.clone();
+        let event = SecurityEvent::LiveOpsCommand {
+            admin_id: self.admin_id,
+            command: command.to_string(),
+            detail,
+            accepted,
+        };
+        async move { audit.log_event(event).await }
+    }
+
+    fn handle_command(&mut self, command: ConsoleCommand, ctx: &mut ws::WebsocketContext<Self>) {
+        let name = command.name();
+
+        if let ConsoleCommand::StepUp { code } = command {
+            self.step_up(code, ctx);
+            return;
+        }
+
+        if !self.stepped_up() {
+            ctx.spawn(self.audit(name, command.detail(), false).into_actor(self));
+            self.send(ctx, &ConsoleEvent::error("Step-up required"));
+            return;
+        }
+
+        if let Err(message) = command.validate() {
+            ctx.spawn(self.audit(name, command.detail(), false).into_actor(self));
+            self.send(ctx, &ConsoleEvent::error(message));
+            return;
+        }
+
+        let detail = command.detail();
+        let environment = command.environment(&self.live_ops);
+        let audit = self.audit(name, detail, true);
+        let live_ops = self.live_ops.clone();
+        let pool = self.pool.clone();
+        let admin_id = self.admin_id;
+
+        let fut = async move {
+            audit.await;
+            match command {
+                ConsoleCommand::Announce { title, content, priority } => live_ops
+                    .announce(&pool, &title, &content, &priority, admin_id)
+                    .await
+                    .map(|id| ConsoleEvent::CommandOk { command: name, message: format!("Announcement {} sent", id) }),
+                ConsoleCommand::SetFlag { name: flag, enabled } => {
+                    live_ops.set_flag(&pool, &flag, enabled, admin_id).await?;
+                    let mut flags: Vec<_> = live_ops.flags().await.values().cloned().collect();
+                    flags.sort_by(|a, b| a.name.cmp(&b.name));
+                    Ok(ConsoleEvent::Flags { flags })
+                }
+                ConsoleCommand::Drain { node, draining } => {
+                    let node = node.unwrap_or_else(|| live_ops.node_id().to_string());
+                    if live_ops.set_draining(&pool, &node, draining, admin_id).await? {
+                        let state = if draining { "draining" } else { "accepting traffic" };
+                        Ok(ConsoleEvent::CommandOk { command: name, message: format!("Node {} is {}", node, state) })
+                    } else {
+                        Ok(ConsoleEvent::error(format!("Unknown node {}", node)))
+                    }
+                }
+                ConsoleCommand::Nodes => live_ops.nodes(&pool).await.map(|nodes| ConsoleEvent::Nodes { nodes }),
+                ConsoleCommand::Thresholds { .. } => threshold_event(&live_ops, &pool, environment).await,
+                ConsoleCommand::SetThreshold { metric, warning, critical, .. } => {
+                    let threshold = Threshold::new(warning, critical);
+                    match live_ops.set_threshold(&pool, &environment, metric, threshold, admin_id).await? {
+                        Ok(()) => threshold_event(&live_ops, &pool, environment).await,
+                        Err(e) => Ok(ConsoleEvent::error(e.to_string())),
+                    }
+                }
+                ConsoleCommand::ClearThreshold { metric, .. } => {
+                    if live_ops.clear_threshold(&pool, &environment, metric, admin_id).await? {
+                        threshold_event(&live_ops, &pool, environment).await
+                    } else {
+                        Ok(ConsoleEvent::error(format!(
+                            "{} has no override in {}",
+                            metric,
+                            environment_name(&environment)
+                        )))
+                    }
+                }
+                ConsoleCommand::StepUp { .. } => unreachable!("step-up is handled before dispatch"),
+            }
+        };
+
+        ctx.spawn(fut.into_actor(self).map(move |result, act, ctx| {
+            let event = result.unwrap_or_else(|e: anyhow::Error| {
+                tracing::error!("Live-ops command {} failed: {}", name, e);
+                ConsoleEvent::error(format!("Command {} failed", name))
+            });
+            act.send(ctx, &event);
+        }));
+    }
+
+    fn step_up(&mut self, code: String, ctx: &mut ws::WebsocketContext<Self>) {
+        let auth = self.auth.clone();
+        let user = legacy_user_uuid(self.admin_id);
+
+        let fut = async move {
+            auth.verify_mfa(&user, &code).await.unwrap_or_else(|e| {
+                tracing::error!("MFA verification failed for user {}: {}", user, e);
+                false
+            })
+        };
+
+        ctx.spawn(fut.into_actor(self).map(|verified, act, ctx| {
+            ctx.spawn(act.audit("step_up", String::new(), verified).into_actor(act));
+
+            if !verified {
+                act.failed_step_ups += 1;
+                let attempts_left = MAX_STEP_UP_ATTEMPTS.saturating_sub(act.failed_step_ups);
+                act.send(ctx, &ConsoleEvent::StepUpFailed { attempts_left });
+                if attempts_left == 0 {
+                    ctx.close(Some(ws::CloseCode::Policy.into()));
+                    ctx.stop();
+                }
+                return;
+            }
+
+            act.failed_step_ups = 0;
+            act.stepped_up_until = Some(Instant::now() + STEP_UP_TTL);
+            act.send(ctx, &ConsoleEvent::StepUpOk { expires_in: STEP_UP_TTL.as_secs() });
+            act.start_health_stream(ctx);
+        }));
+    }
+
+    /// Forward every new health sample; starts once per session
+    fn start_health_stream(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        if std::mem::replace(&mut self.streaming_health, true) {
+            return;
+        }
+
+        let mut health = self.live_ops.subscribe_health();
+        if let Some(sample) = health.borrow_and_update().clone() {
+            self.send(ctx, &ConsoleEvent::Health { sample });
+        }
+
+        ctx.add_stream(futures_util::stream::unfold(health, |mut health| async move {
+            health.changed().await.ok()?;
+            let sample = health.borrow_and_update().clone();
+            Some((sample, health))
+        }));
+    }
+}
+
+impl StreamHandler<Option<HealthSample>> for ConsoleSession {
+    fn handle(&mut self, sample: Option<HealthSample>, ctx: &mut Self::Context) {
+        // Samples keep flowing after the step-up expires, but stop being sent
+        if let (Some(sample), true) = (sample, self.stepped_up()) {
+            self.send(ctx, &ConsoleEvent::Health { sample });
+        }
+    }
+
+    fn finished(&mut self, _ctx: &mut Self::Context) {}
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ConsoleSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                self.hb = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => {
+                self.hb = Instant::now();
+            }
+            Ok(ws::Message::Text(text)) => {
+                self.hb = Instant::now();
+                match serde_json::from_str::<ConsoleCommand>(&text) {
+                    Ok(command) => self.handle_command(command, ctx),
+                    Err(e) => self.send(ctx, &ConsoleEvent::error(format!("Invalid command: {}", e))),
+                }
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Err(_) => ctx.stop(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_parsing_and_validation() {
+        let command: ConsoleCommand =
+            serde_json::from_str(r#"{"type":"drain","draining":true}"#).unwrap();
+        assert!(matches!(command, ConsoleCommand::Drain { node: None, draining: true }));
+        assert!(command.validate().is_ok());
+
+        let command: ConsoleCommand =
+            serde_json::from_str(r#"{"type":"set_flag","name":"Market; DROP","enabled":true}"#).unwrap();
+        assert!(command.validate().is_err());
+
+        let command: ConsoleCommand = serde_json::from_str(
+            r#"{"type":"announce","title":"Restart","content":"At 18:00 UTC","priority":"urgent"}"#,
+        )
+        .unwrap();
+        assert!(command.validate().is_err());
+
+        assert!(serde_json::from_str::<ConsoleCommand>(r#"{"type":"shutdown"}"#).is_err());
+
+        let command: ConsoleCommand = serde_json::from_str(
+            r#"{"type":"set_threshold","metric":"cpu_percent","warning":70,"critical":85,"environment":"staging"}"#,
+        )
+        .unwrap();
+        assert!(command.validate().is_ok());
+
+        let command: ConsoleCommand = serde_json::from_str(
+            r#"{"type":"set_threshold","metric":"cpu_percent","warning":95,"critical":85}"#,
+        )
+        .unwrap();
+        assert!(command.validate().is_err());
+
+        let command: ConsoleCommand =
+            serde_json::from_str(r#"{"type":"clear_threshold","metric":"disk_percent","environment":"qa"}"#).unwrap();
+        assert!(command.validate().is_err());
+    }
+}
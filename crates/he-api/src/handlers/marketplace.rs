@@ -0,0 +1,258 @@
+//! Software marketplace handlers
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_database::cache::cache_keys;
+use he_database::queries::{MarketplaceQueries, PurchaseOutcome};
+use he_database::{CacheManager, SoftwareListing};
+use he_game_mechanics::account_gating::Restriction;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::time::Duration;
+use crate::state::AppState;
+use crate::handlers::account_gating::require_unrestricted;
+use crate::handlers::process::extract_user_id;
+
+const SEARCH_PAGE_SIZE: i64 = 25;
+const SEARCH_CACHE_TTL: Duration = Duration::from_secs(30);
+
+// Browse/search results are read far more often than listings change
+static MARKETPLACE_CACHE: Lazy<CacheManager> = Lazy::new(|| CacheManager::new(256, 2048));
+
+#[derive(Deserialize)]
+pub struct CreateListingRequest {
+    pub software_id: i64,
+    pub price: i64,
+    pub copies: i32,
+    #[serde(default)]
+    pub allow_resale: bool,
+    #[serde(default)]
+    pub royalty_percent: i16,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+    #[serde(rename = "type")]
+    pub software_type: Option<String>,
+    pub min_version: Option<f64>,
+    pub page: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct PurchaseQuery {
+    /// Spend the money and disk space held by this reservation
+    pub reservation: Option<i64>,
+}
+
+pub async fn search_listings(
+    state: web::Data<AppState>,
+    query: web::Query<SearchQuery>,
+) -> HttpResponse {
+    let page = query.page.unwrap_or(1).max(1);
+    let key = cache_keys::marketplace_search_key(
+        query.q.as_deref().unwrap_or(""),
+        query.software_type.as_deref().unwrap_or(""),
+        query.min_version.unwrap_or(0.0),
+        page,
+    );
+
+    if let Some(listings) = MARKETPLACE_CACHE.get::<Vec<SoftwareListing>>(&key).await {
+        return HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "page": page,
+            "listings": listings
+        }));
+    }
+
+    match MarketplaceQueries::search_listings(
+        &state.db.pool,
+        query.q.as_deref(),
+        query.software_type.as_deref(),
+        query.min_version,
+        SEARCH_PAGE_SIZE,
+        (page - 1) * SEARCH_PAGE_SIZE,
+    ).await {
+        Ok(listings) => {
+            MARKETPLACE_CACHE.set(key, listings.clone(), SEARCH_CACHE_TTL).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "page": page,
+                "listings": listings
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to search marketplace: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn get_listing(
+    state: web::Data<AppState>,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let listing_id = path.into_inner();
+    let key = cache_keys::marketplace_listing_key(listing_id);
+
+    if let Some(listing) = MARKETPLACE_CACHE.get::<SoftwareListing>(&key).await {
+        return HttpResponse::Ok().json(serde_json::json!({ "success": true, "listing": listing }));
+    }
+
+    match MarketplaceQueries::get_listing(&state.db.pool, listing_id).await {
+        Ok(Some(listing)) => {
+            MARKETPLACE_CACHE.set(key, listing.clone(), SEARCH_CACHE_TTL).await;
+            HttpResponse::Ok().json(serde_json::json!({ "success": true, "listing": listing }))
+        }
+        Ok(None) => {
+            HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "Listing not found"
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to get listing: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn create_listing(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<CreateListingRequest>,
+) -> HttpResponse {
+    let user_id = match require_unrestricted(&state, &req, Restriction::Trading).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    if data.price <= 0 || data.copies <= 0 || !(0..=50).contains(&data.royalty_percent) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Invalid price, copy limit or royalty percent"
+        }));
+    }
+
+    match MarketplaceQueries::create_listing(
+        &state.db.pool,
+        user_id,
+        data.software_id,
+        data.price,
+        data.copies,
+        data.allow_resale,
+        // Royalties only make sense if buyers may resell
+        if data.allow_resale { data.royalty_percent } else { 0 },
+    ).await {
+        Ok(Some(listing)) => {
+            MARKETPLACE_CACHE.invalidate_pattern("marketplace:search:").await;
+            HttpResponse::Created().json(serde_json::json!({ "success": true, "listing": listing }))
+        }
+        Ok(None) => {
+            HttpResponse::Forbidden().json(serde_json::json!({
+                "success": false,
+                "message": "You do not own this software or its license forbids resale"
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to create listing: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn cancel_listing(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let user_id = match extract_user_id(&state, &req).await {
+        Some(id) => id,
+        None => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "success": false,
+                "message": "Unauthorized"
+            }));
+        }
+    };
+
+    let listing_id = path.into_inner();
+    match MarketplaceQueries::cancel_listing(&state.db.pool, listing_id, user_id).await {
+        Ok(true) => {
+            invalidate_listing(listing_id).await;
+            HttpResponse::Ok().json(serde_json::json!({ "success": true }))
+        }
+        Ok(false) => {
+            HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "Listing not found"
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to cancel listing: {}", e)
+            }))
+        }
+    }
+}
+
+pub async fn purchase_listing(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    query: web::Query<PurchaseQuery>,
+) -> HttpResponse {
+    let user_id = match require_unrestricted(&state, &req, Restriction::Trading).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let listing_id = path.into_inner();
+    match MarketplaceQueries::purchase(&state.db.pool, listing_id, user_id, query.reservation).await {
+        Ok(PurchaseOutcome::Purchased { software_id, license_key }) => {
+            invalidate_listing(listing_id).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "software_id": software_id,
+                "license_key": license_key
+            }))
+        }
+        Ok(outcome) => {
+            let (status, message) = match outcome {
+                PurchaseOutcome::NotFound => (actix_web::http::StatusCode::NOT_FOUND, "Listing not found"),
+                PurchaseOutcome::SoldOut => (actix_web::http::StatusCode::CONFLICT, "Listing is sold out"),
+                PurchaseOutcome::OwnListing => (actix_web::http::StatusCode::BAD_REQUEST, "You cannot buy your own listing"),
+                PurchaseOutcome::InsufficientFunds => (actix_web::http::StatusCode::BAD_REQUEST, "Insufficient funds"),
+                PurchaseOutcome::NoSpace => (actix_web::http::StatusCode::BAD_REQUEST, "Not enough free disk space"),
+                PurchaseOutcome::ReservationInvalid => {
+                    (actix_web::http::StatusCode::CONFLICT, "The reservation is not yours or no longer held")
+                }
+                PurchaseOutcome::SellerUnavailable => {
+                    (actix_web::http::StatusCode::CONFLICT, "The seller cannot accept payment right now")
+                }
+                _ => (actix_web::http::StatusCode::BAD_REQUEST, "You need a server to store the software"),
+            };
+            HttpResponse::build(status).json(serde_json::json!({
+                "success": false,
+                "message": message
+            }))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Purchase failed: {}", e)
+            }))
+        }
+    }
+}
+
+async fn invalidate_listing(listing_id: i64) {
+    MARKETPLACE_CACHE.invalidate(&cache_keys::marketplace_listing_key(listing_id)).await;
+    MARKETPLACE_CACHE.invalidate_pattern("marketplace:search:").await;
+}
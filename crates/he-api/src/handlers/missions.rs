@@ -1,9 +1,26 @@
 //! Mission handlers
 
 use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::GeneratedMissionQueries;
+use he_game_mechanics::mission_gen::GeneratedMissionDenied;
 use serde::{Deserialize, Serialize};
+use crate::coop::{self, CoopDenied, CoopEvent};
+use crate::mission_gen;
 use crate::state::AppState;
+use crate::handlers::account_gating::require_chat;
 use crate::handlers::game::extract_user_id;
+use crate::handlers::process::require_permission;
+
+/// Longest chat message accepted in a co-op mission thread
+const MAX_CHAT_MESSAGE_LEN: usize = 500;
+/// Most chat messages returned per request
+const CHAT_PAGE_SIZE: i64 = 100;
+
+/// Reading the mission generation log
+const GENERATION_LOG_PERMISSION: &str = "missions:debug";
+const DEFAULT_GENERATIONS: i64 = 50;
+const MAX_GENERATIONS: i64 = 200;
 
 #[derive(Serialize)]
 pub struct MissionInfo {
@@ -21,6 +38,26 @@ pub struct ProgressUpdate {
     pub progress: i32,
 }
 
+#[derive(Deserialize)]
+pub struct ChatPost {
+    pub body: String,
+}
+
+#[derive(Deserialize)]
+pub struct GenerationLogQuery {
+    pub user_id: Option<i64>,
+    /// `generated` or `rejected`
+    pub outcome: Option<String>,
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ChatQuery {
+    /// Only messages after this id
+    pub after: Option<i64>,
+}
+
 pub async fn get_missions(
     state: web::Data<AppState>,
     req: HttpRequest,
@@ -101,4 +138,360 @@ pub async fn update_progress(
             }))
         }
     }
-}
\ No newline at end of file
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn coop_denied(denied: &CoopDenied) -> HttpResponse {
+    let mut response = match denied {
+        CoopDenied::NotFound => HttpResponse::NotFound(),
+        CoopDenied::NotCoop => HttpResponse::BadRequest(),
+        CoopDenied::NoClan | CoopDenied::NotInClan | CoopDenied::NotMember | CoopDenied::NotLeader => {
+            HttpResponse::Forbidden()
+        }
+        _ => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Publish what a co-op action did and answer with `message`
+async fn coop_outcome(
+    state: &web::Data<AppState>,
+    outcome: anyhow::Result<Result<Vec<CoopEvent>, CoopDenied>>,
+    message: &str,
+) -> HttpResponse {
+    match outcome {
+        Ok(Ok(events)) => {
+            let body = serde_json::json!({
+                "success": true,
+                "message": message,
+                "events": &events
+            });
+            coop::publish(&state.db.pool, state.ws_manager.clone(), events).await;
+            HttpResponse::Ok().json(body)
+        }
+        Ok(Err(denied)) => coop_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Co-op mission update failed: {}", e)
+        })),
+    }
+}
+
+/// Open a co-op run of a mission template for the player's clan
+pub async fn accept_coop(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match coop::accept(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok((coop_mission, events))) => {
+            coop::publish(&state.db.pool, state.ws_manager.clone(), events).await;
+            HttpResponse::Created().json(serde_json::json!({
+                "success": true,
+                "message": "Co-op mission opened",
+                "coop_mission": coop_mission
+            }))
+        }
+        Ok(Err(denied)) => coop_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to open co-op mission: {}", e)
+        })),
+    }
+}
+
+/// A co-op mission with its members' contributions; clan members only
+pub async fn get_coop(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    match coop::view(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok((coop_mission, members))) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "coop_mission": coop_mission,
+            "members": members
+        })),
+        Ok(Err(denied)) => coop_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to get co-op mission: {}", e)
+        })),
+    }
+}
+
+pub async fn join_coop(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let outcome = coop::join(&state.db.pool, user_id, path.into_inner()).await;
+    coop_outcome(&state, outcome, "Joined co-op mission").await
+}
+
+pub async fn start_coop(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let outcome = coop::start(&state.db.pool, user_id, path.into_inner()).await;
+    coop_outcome(&state, outcome, "Co-op mission started").await
+}
+
+pub async fn abandon_coop(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let outcome = coop::abandon(&state.db.pool, user_id, path.into_inner()).await;
+    coop_outcome(&state, outcome, "Co-op mission abandoned").await
+}
+
+/// Messages in a co-op mission's chat thread, oldest first
+pub async fn coop_chat(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    query: web::Query<ChatQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let coop_id = path.into_inner();
+    match coop::messages(&state.db.pool, user_id, coop_id, query.after.unwrap_or(0), CHAT_PAGE_SIZE).await {
+        Ok(Ok(messages)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "messages": messages
+        })),
+        Ok(Err(denied)) => coop_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to get chat: {}", e)
+        })),
+    }
+}
+
+pub async fn post_coop_chat(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Json<ChatPost>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let body = data.body.trim();
+    if body.is_empty() || body.chars().count() > MAX_CHAT_MESSAGE_LEN {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Messages must be 1 to {} characters", MAX_CHAT_MESSAGE_LEN)
+        }));
+    }
+    if let Err(response) = require_chat(&state, user_id, body).await {
+        return response;
+    }
+
+    let outcome = coop::post_message(&state.db.pool, user_id, path.into_inner(), body).await;
+    coop_outcome(&state, outcome, "Message posted").await
+}
+
+fn generated_denied(denied: &GeneratedMissionDenied) -> HttpResponse {
+    let mut response = match denied {
+        GeneratedMissionDenied::NotFound => HttpResponse::NotFound(),
+        GeneratedMissionDenied::UnknownObjective => HttpResponse::BadRequest(),
+        GeneratedMissionDenied::Expired => HttpResponse::Gone(),
+        GeneratedMissionDenied::OnCooldown { .. } => HttpResponse::TooManyRequests(),
+        _ => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// The caller's generated missions, topping up their offers first
+pub async fn generated_missions(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match mission_gen::offers(&state.db.pool, user_id).await {
+        Ok(missions) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "missions": missions
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to get generated missions: {}", e)
+        })),
+    }
+}
+
+pub async fn accept_generated(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match mission_gen::accept(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok(mission)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Mission accepted",
+            "mission": mission
+        })),
+        Ok(Err(denied)) => generated_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to accept mission: {}", e)
+        })),
+    }
+}
+
+/// Mark one objective of an accepted mission done
+pub async fn complete_generated_objective(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<(i64, usize)>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let (mission_id, node) = path.into_inner();
+
+    match mission_gen::complete_objective(&state.db.pool, user_id, mission_id, node).await {
+        Ok(Ok(outcome)) => {
+            let message = if outcome.paid_money.is_some() { "Mission completed" } else { "Objective completed" };
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": message,
+                "outcome": outcome
+            }))
+        }
+        Ok(Err(denied)) => generated_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to complete objective: {}", e)
+        })),
+    }
+}
+
+pub async fn abandon_generated(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match mission_gen::abandon(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok(mission)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Mission abandoned",
+            "mission": mission
+        })),
+        Ok(Err(denied)) => generated_denied(&denied),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to abandon mission: {}", e)
+        })),
+    }
+}
+
+/// Mission generations, newest first, with every attempt each made
+pub async fn generation_log(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    query: web::Query<GenerationLogQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, GENERATION_LOG_PERMISSION).await {
+        return response;
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_GENERATIONS).clamp(1, MAX_GENERATIONS);
+    match GeneratedMissionQueries::generation_log(
+        &state.db.pool,
+        query.user_id,
+        query.outcome.as_deref(),
+        query.before_id,
+        limit,
+    )
+    .await
+    {
+        Ok(generations) => {
+            let next_before_id =
+                if generations.len() as i64 == limit { generations.last().map(|g| g.id) } else { None };
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "generations": generations,
+                "next_before_id": next_before_id
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to get generation log: {}", e)
+        })),
+    }
+}
+
+/// One generation and the mission it produced, if any
+pub async fn generation(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, GENERATION_LOG_PERMISSION).await {
+        return response;
+    }
+
+    match GeneratedMissionQueries::generation(&state.db.pool, path.into_inner()).await {
+        Ok(Some((generation, mission))) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "generation": generation,
+            "mission": mission
+        })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Generation not found"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to get generation: {}", e)
+        })),
+    }
+}
@@ -1,11 +1,52 @@
 //! API Handlers
 
+pub mod account;
+pub mod account_gating;
+pub mod activity;
+pub mod admin_dashboard;
+pub mod archive;
 pub mod auth;
+pub mod bounty;
+pub mod bulk_ops;
+pub mod formula_scripts;
+pub mod clans;
+pub mod contracts;
+pub mod dns;
+pub mod referrals;
+pub mod reports;
+pub mod reservations;
+pub mod entitlements;
+pub mod ledger;
+pub mod cron;
+pub mod defense;
+pub mod doom;
 pub mod game;
+pub mod gateway;
 pub mod hacking;
+pub mod heists;
+pub mod honeypots;
+pub mod ip_policy;
+pub mod live_ops;
+pub mod panic_levers;
+pub mod request_log;
 pub mod process;
+pub mod query_audit;
+pub mod cache_stats;
 pub mod hardware;
 pub mod bank;
+pub mod marketplace;
 pub mod missions;
 pub mod progression;
-pub mod monitoring;
\ No newline at end of file
+pub mod puzzles;
+pub mod server;
+pub mod server_browser;
+pub mod software;
+pub mod status;
+pub mod terminal;
+pub mod tutorial;
+pub mod wars;
+pub mod webhooks;
+pub mod monitoring;
+pub mod notifications;
+pub mod oidc;
+pub mod plugins;
\ No newline at end of file
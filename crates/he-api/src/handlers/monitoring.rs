@@ -1,8 +1,12 @@
 //! Monitoring and metrics endpoints
 
-use actix_web::{web, HttpResponse, Result};
-use he_monitoring::{MonitoringService, HealthCheck, HealthStatus};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use he_degradation::Degradation;
+use he_monitoring::{Criticality, HealthRegistry, MonitoringService, OverallState, ProbeState};
 use serde_json::json;
+use crate::cache_warm::CacheWarm;
+use crate::drain::Drain;
+use crate::live_ops::LiveOps;
 
 /// Prometheus metrics endpoint
 pub async fn metrics() -> Result<HttpResponse> {
@@ -12,58 +16,113 @@ pub async fn metrics() -> Result<HttpResponse> {
         .body(metrics))
 }
 
-/// Health check endpoint with detailed status
-pub async fn health() -> Result<HttpResponse> {
-    let mut health_check = HealthCheck::new();
+/// Every component's state and latency with the dependency graph, plus the
+/// degradation state of guarded dependencies. Degraded nodes answer 200, so
+/// only a critical component being down fails it.
+pub async fn health(health: web::Data<HealthRegistry>, degradation: web::Data<Degradation>) -> Result<HttpResponse> {
+    let report = health.check().await;
+    let degraded = degradation.status();
 
-    // Add various health checks
-    health_check.add_check(|| HealthStatus {
-        name: "api".to_string(),
-        healthy: true,
-        message: "API server is running".to_string(),
+    let response = json!({
+        "status": report.status,
+        "ready": report.is_ready(),
+        "components": report.components,
+        "dependencies": report.edges,
+        "degraded": degraded.iter().any(|d| d.degraded),
+        "degradation": degraded
     });
 
-    health_check.add_check(|| HealthStatus {
-        name: "database".to_string(),
-        healthy: true, // Would check actual DB connection
-        message: "Database connection OK".to_string(),
-    });
+    if report.status == OverallState::Unhealthy {
+        Ok(HttpResponse::ServiceUnavailable().json(response))
+    } else {
+        Ok(HttpResponse::Ok().json(response))
+    }
+}
 
-    health_check.add_check(|| HealthStatus {
-        name: "cache".to_string(),
-        healthy: true, // Would check Redis connection
-        message: "Cache layer operational".to_string(),
-    });
+/// Readiness probe for Kubernetes. A draining node, or one with a critical
+/// component down, reports not ready so it is taken out of the load balancer;
+/// degraded optional components keep it in.
+pub async fn ready(live_ops: web::Data<LiveOps>, health: web::Data<HealthRegistry>) -> Result<HttpResponse> {
+    if live_ops.is_draining() {
+        return Ok(HttpResponse::ServiceUnavailable().json(json!({
+            "ready": false,
+            "message": "Node is draining"
+        })));
+    }
 
-    let statuses = health_check.check_health();
-    let overall_healthy = health_check.is_healthy();
+    let report = health.check().await;
+    if !report.is_ready() {
+        let down: Vec<&str> = report
+            .components
+            .iter()
+            .filter(|c| c.criticality == Criticality::Critical && c.probe_state == ProbeState::Down)
+            .map(|c| c.name.as_str())
+            .collect();
+        return Ok(HttpResponse::ServiceUnavailable().json(json!({
+            "ready": false,
+            "message": format!("Critical components down: {}", down.join(", "))
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "ready": true,
+        "status": report.status,
+        "message": "Server ready to accept traffic"
+    })))
+}
+
+/// Cache warm progress. Answers 503 until every group up to the manifest's
+/// gate priority has been warmed, so orchestration can hold traffic back.
+pub async fn cache_warm(cache_warm: web::Data<CacheWarm>) -> Result<HttpResponse> {
+    let Some(progress) = cache_warm.progress() else {
+        return Ok(HttpResponse::Ok().json(json!({
+            "ready": true,
+            "message": "No cache to warm"
+        })));
+    };
 
     let response = json!({
-        "status": if overall_healthy { "healthy" } else { "unhealthy" },
-        "checks": statuses.into_iter().map(|s| json!({
-            "name": s.name,
-            "status": if s.healthy { "pass" } else { "fail" },
-            "message": s.message
-        })).collect::<Vec<_>>()
+        "ready": progress.is_ready(),
+        "complete": progress.is_complete(),
+        "keys_written": progress.keys_written(),
+        "progress": progress
     });
 
-    if overall_healthy {
+    if progress.is_ready() {
         Ok(HttpResponse::Ok().json(response))
     } else {
         Ok(HttpResponse::ServiceUnavailable().json(response))
     }
 }
 
-/// Readiness probe for Kubernetes
-pub async fn ready() -> Result<HttpResponse> {
-    // Check if server is ready to accept traffic
-    Ok(HttpResponse::Ok().json(json!({
-        "ready": true,
-        "message": "Server ready to accept traffic"
-    })))
+/// Restart drain progress. Answers 503 until the node is drained and safe to
+/// terminate, so a preStop hook can poll it.
+pub async fn drain_progress(drain: web::Data<Drain>) -> Result<HttpResponse> {
+    let progress = drain.progress();
+    if progress.safe_to_terminate {
+        Ok(HttpResponse::Ok().json(progress))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(progress))
+    }
+}
+
+/// Start draining for a restart. Only the pod itself may ask, from its
+/// preStop hook; the peer address is checked because forwarded headers are
+/// client-controlled.
+pub async fn start_drain(drain: web::Data<Drain>, req: HttpRequest) -> Result<HttpResponse> {
+    if !req.peer_addr().is_some_and(|addr| addr.ip().is_loopback()) {
+        return Ok(HttpResponse::Forbidden().json(json!({
+            "success": false,
+            "message": "Drains can only be started from the node itself"
+        })));
+    }
+
+    let progress = drain.into_inner().begin().await;
+    Ok(HttpResponse::Accepted().json(progress))
 }
 
-/// Liveness probe for Kubernetes
+/// Liveness probe for Kubernetes. Runs no component probes: a failing
+/// dependency makes the node unready, not dead, so it is not restarted.
 pub async fn live() -> Result<HttpResponse> {
     // Simple check that server is alive
     Ok(HttpResponse::Ok().json(json!({
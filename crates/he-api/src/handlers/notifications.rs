@@ -0,0 +1,217 @@
+//! Notification center
+//!
+//! Lists the player's notifications newest first with cursor pagination, and
+//! marks read or deletes them in bulk. Classes the player mutes are never
+//! stored for them and are hidden from the list and the unread counters.
+//! Identical notifications arrive collapsed into one with a count; the
+//! occurrences endpoint expands such a group.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_helix_notification::model::{NotificationCursor, NotificationQuery, NotificationSelection};
+use he_helix_notification::{NotificationCenter, NotificationClass};
+use serde::Deserialize;
+use uuid::Uuid;
+use he_database::models::legacy_user_uuid;
+use crate::state::AppState;
+use crate::handlers::process::extract_user_id;
+
+#[derive(Deserialize)]
+pub struct ListParams {
+    pub class: Option<NotificationClass>,
+    pub code: Option<String>,
+    pub read: Option<bool>,
+    pub cursor: Option<NotificationCursor>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct MuteRequest {
+    pub muted: bool,
+}
+
+/// Notifications are keyed by account UUID, as in he-auth
+async fn account_id(state: &web::Data<AppState>, req: &HttpRequest) -> Result<Uuid, HttpResponse> {
+    match extract_user_id(state, req).await {
+        Some(user_id) => Ok(legacy_user_uuid(user_id)),
+        None => Err(HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }))),
+    }
+}
+
+fn failed(e: he_helix_notification::NotificationError) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Notification error: {}", e)
+    }))
+}
+
+pub async fn list_notifications(
+    state: web::Data<AppState>,
+    center: web::Data<NotificationCenter>,
+    req: HttpRequest,
+    params: web::Query<ListParams>,
+) -> HttpResponse {
+    let account = match account_id(&state, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let params = params.into_inner();
+    let query = NotificationQuery {
+        account_id: Some(account),
+        class: params.class,
+        code: params.code,
+        is_read: params.read,
+        limit: params.limit,
+        cursor: params.cursor,
+        ..Default::default()
+    };
+
+    match center.list(&query).await {
+        Ok(page) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "notifications": page.notifications,
+            "next_cursor": page.next_cursor
+        })),
+        Err(e) => failed(e),
+    }
+}
+
+pub async fn unread_counts(
+    state: web::Data<AppState>,
+    center: web::Data<NotificationCenter>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let account = match account_id(&state, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match center.unread_counts(account).await {
+        Ok(counts) => {
+            let by_class: serde_json::Map<String, serde_json::Value> = NotificationClass::all()
+                .iter()
+                .map(|class| (class.as_str().to_string(), counts.get(class).copied().unwrap_or(0).into()))
+                .collect();
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "total": counts.values().sum::<i64>(),
+                "by_class": by_class
+            }))
+        }
+        Err(e) => failed(e),
+    }
+}
+
+pub async fn mark_read(
+    state: web::Data<AppState>,
+    center: web::Data<NotificationCenter>,
+    req: HttpRequest,
+    selection: web::Json<NotificationSelection>,
+) -> HttpResponse {
+    let account = match account_id(&state, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match center.mark_read(account, &selection).await {
+        Ok(updated) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "updated": updated
+        })),
+        Err(e) => failed(e),
+    }
+}
+
+pub async fn delete_notifications(
+    state: web::Data<AppState>,
+    center: web::Data<NotificationCenter>,
+    req: HttpRequest,
+    selection: web::Json<NotificationSelection>,
+) -> HttpResponse {
+    let account = match account_id(&state, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match center.delete(account, &selection).await {
+        Ok(deleted) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "deleted": deleted
+        })),
+        Err(e) => failed(e),
+    }
+}
+
+pub async fn get_preferences(
+    state: web::Data<AppState>,
+    center: web::Data<NotificationCenter>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let account = match account_id(&state, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match center.muted_classes(account).await {
+        Ok(muted) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "muted_classes": muted
+        })),
+        Err(e) => failed(e),
+    }
+}
+
+pub async fn set_class_muted(
+    state: web::Data<AppState>,
+    center: web::Data<NotificationCenter>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Json<MuteRequest>,
+) -> HttpResponse {
+    let account = match account_id(&state, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let class: NotificationClass = match path.into_inner().parse() {
+        Ok(class) => class,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "message": e.to_string()
+            }));
+        }
+    };
+
+    match center.set_muted(account, class, data.muted).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": if data.muted { "Class muted" } else { "Class unmuted" }
+        })),
+        Err(e) => failed(e),
+    }
+}
+
+/// Expand a collapsed notification into what it stands for
+pub async fn occurrences(
+    state: web::Data<AppState>,
+    center: web::Data<NotificationCenter>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> HttpResponse {
+    let account = match account_id(&state, &req).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match center.occurrences(account, path.into_inner()).await {
+        Ok(occurrences) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "occurrences": occurrences
+        })),
+        Err(e) => failed(e),
+    }
+}
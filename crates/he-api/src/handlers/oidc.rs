@@ -0,0 +1,311 @@
+//! OpenID Connect endpoints for companion services
+//!
+//! The consent screen lives in the frontend: it loads the request details from
+//! `GET /api/oauth/authorize` with the player's session and posts the decision
+//! back, getting the URL to send the browser to. The token, introspection and
+//! revocation endpoints follow RFC 6749, 7662 and 7009 and are called by the
+//! companion services themselves.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use he_auth::oidc::{AuthorizationRequest, OidcError, OidcProvider, OidcSubject};
+use he_auth::AuthService;
+use serde::Deserialize;
+use crate::handlers::process::require_permission;
+use crate::state::AppState;
+
+const CLIENTS_PERMISSION: &str = "oauth:clients";
+
+/// Provider and client admin routes, registered with the provider only when
+/// a signing key is configured
+pub fn configure(cfg: &mut web::ServiceConfig, provider: web::Data<OidcProvider>) {
+    cfg.app_data(provider)
+        .route("/.well-known/openid-configuration", web::get().to(discovery))
+        .route("/.well-known/jwks.json", web::get().to(jwks))
+        .route("/api/oauth/authorize", web::get().to(consent_details))
+        .route("/api/oauth/authorize", web::post().to(decide_consent))
+        .route("/oauth/token", web::post().to(token))
+        .route("/oauth/introspect", web::post().to(introspect))
+        .route("/oauth/revoke", web::post().to(revoke))
+        .route("/api/admin/oauth/clients", web::get().to(list_clients))
+        .route("/api/admin/oauth/clients", web::post().to(register_client))
+        .route("/api/admin/oauth/clients/{client_id}", web::delete().to(remove_client));
+}
+
+pub async fn discovery(oidc: web::Data<OidcProvider>) -> HttpResponse {
+    HttpResponse::Ok().json(oidc.discovery())
+}
+
+/// Public keys ID tokens are signed with
+pub async fn jwks(oidc: web::Data<OidcProvider>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=3600"))
+        .json(oidc.jwks())
+}
+
+/// Details for the consent screen
+pub async fn consent_details(
+    state: web::Data<AppState>,
+    oidc: web::Data<OidcProvider>,
+    req: HttpRequest,
+    query: web::Query<AuthorizationRequest>,
+) -> HttpResponse {
+    if authed_subject(&state, &req).await.is_none() {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    }
+
+    match oidc.validate_authorization(&query).await {
+        Ok((client, scopes)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "client_name": client.name,
+            "redirect_uri": query.redirect_uri,
+            "scopes": scopes
+        })),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": e.to_string()
+        })),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ConsentDecision {
+    #[serde(flatten)]
+    pub request: AuthorizationRequest,
+    pub approve: bool,
+}
+
+/// Record the player's decision and return where to send the browser
+pub async fn decide_consent(
+    state: web::Data<AppState>,
+    oidc: web::Data<OidcProvider>,
+    req: HttpRequest,
+    data: web::Json<ConsentDecision>,
+) -> HttpResponse {
+    let Some(subject) = authed_subject(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let ConsentDecision { request, approve } = data.into_inner();
+    let result = if approve {
+        oidc.approve(&request, subject).await.map(|code| vec![("code", code)])
+    } else {
+        // Only redirect the denial to a URI registered for the client
+        oidc.validate_authorization(&request)
+            .await
+            .map(|_| vec![("error", "access_denied".to_string())])
+    };
+
+    match result {
+        Ok(mut params) => {
+            if let Some(s) = &request.state {
+                params.push(("state", s.clone()));
+            }
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "redirect_to": redirect_url(&request.redirect_uri, &params)
+            }))
+        }
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": e.to_string()
+        })),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: String,
+    pub redirect_uri: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub code_verifier: String,
+}
+
+pub async fn token(
+    oidc: web::Data<OidcProvider>,
+    form: web::Form<TokenRequest>,
+) -> HttpResponse {
+    if form.grant_type != "authorization_code" {
+        return oauth_error(OidcError::UnsupportedGrantType);
+    }
+
+    let client = match oidc.authenticate_client(&form.client_id, form.client_secret.as_deref()).await {
+        Ok(client) => client,
+        Err(e) => return oauth_error(e),
+    };
+
+    match oidc.exchange_code(&client, &form.code, &form.redirect_uri, &form.code_verifier).await {
+        Ok(tokens) => HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(tokens),
+        Err(e) => oauth_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TokenActionRequest {
+    pub token: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+}
+
+/// Only confidential clients may introspect
+pub async fn introspect(
+    oidc: web::Data<OidcProvider>,
+    form: web::Form<TokenActionRequest>,
+) -> HttpResponse {
+    match oidc.authenticate_client(&form.client_id, form.client_secret.as_deref()).await {
+        Ok(client) if client.confidential => HttpResponse::Ok().json(oidc.introspect(&form.token).await),
+        Ok(_) | Err(_) => oauth_error(OidcError::InvalidClient),
+    }
+}
+
+pub async fn revoke(
+    oidc: web::Data<OidcProvider>,
+    form: web::Form<TokenActionRequest>,
+) -> HttpResponse {
+    let client = match oidc.authenticate_client(&form.client_id, form.client_secret.as_deref()).await {
+        Ok(client) => client,
+        Err(e) => return oauth_error(e),
+    };
+
+    match oidc.revoke(&client, &form.token).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => oauth_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RegisterClientRequest {
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    #[serde(default)]
+    pub confidential: bool,
+}
+
+pub async fn list_clients(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    oidc: web::Data<OidcProvider>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, CLIENTS_PERMISSION).await {
+        return response;
+    }
+
+    match oidc.list_clients().await {
+        Ok(clients) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "clients": clients
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": e.to_string()
+        })),
+    }
+}
+
+/// Register a first-party client. The secret is only ever shown here.
+pub async fn register_client(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    oidc: web::Data<OidcProvider>,
+    req: HttpRequest,
+    data: web::Json<RegisterClientRequest>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, CLIENTS_PERMISSION).await {
+        return response;
+    }
+
+    let data = data.into_inner();
+    match oidc.register_client(data.name.trim(), data.redirect_uris, data.confidential).await {
+        Ok((client, secret)) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "client": client,
+            "client_secret": secret
+        })),
+        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": e.to_string()
+        })),
+    }
+}
+
+pub async fn remove_client(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    oidc: web::Data<OidcProvider>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, CLIENTS_PERMISSION).await {
+        return response;
+    }
+
+    match oidc.remove_client(&path.into_inner()).await {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Client removed"
+        })),
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Client not found"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": e.to_string()
+        })),
+    }
+}
+
+async fn authed_subject(state: &web::Data<AppState>, req: &HttpRequest) -> Option<OidcSubject> {
+    let token = req.headers()
+        .get("Authorization")?
+        .to_str().ok()?
+        .strip_prefix("Bearer ")?;
+
+    let user = state.auth.validate_token(token).await.ok()??;
+    Some(OidcSubject {
+        user_id: user.user_id,
+        email: user.email,
+        roles: user.roles,
+    })
+}
+
+fn oauth_error(e: OidcError) -> HttpResponse {
+    let body = serde_json::json!({
+        "error": e.code(),
+        "error_description": e.to_string()
+    });
+    match e {
+        OidcError::InvalidClient => HttpResponse::Unauthorized().json(body),
+        OidcError::Server(_) => HttpResponse::InternalServerError().json(body),
+        _ => HttpResponse::BadRequest().json(body),
+    }
+}
+
+fn redirect_url(base: &str, params: &[(&str, String)]) -> String {
+    let query: Vec<String> = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, percent_encode(v)))
+        .collect();
+    let sep = if base.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", base, sep, query.join("&"))
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
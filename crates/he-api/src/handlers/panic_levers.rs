@@ -0,0 +1,127 @@
+//! Incident-response panic lever handlers
+//!
+//! Every engage and release needs a fresh MFA code from the operator, on top
+//! of the permission, and is audited whether or not it went through.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::PanicLeverQueries;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use he_database::models::legacy_user_uuid;
+use crate::handlers::process::require_permission;
+use crate::panic_levers::{Lever, LeverRefused, PanicLevers};
+use crate::state::AppState;
+
+const PANIC_PERMISSION: &str = "incident:panic";
+/// Lever changes listed with the current state
+const HISTORY_LIMIT: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct LeverRequest {
+    pub engaged: bool,
+    pub reason: String,
+    /// Current MFA code
+    pub code: String,
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+/// Every lever's state and the latest changes
+pub async fn list(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    levers: web::Data<PanicLevers>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, PANIC_PERMISSION).await {
+        return response;
+    }
+
+    match PanicLeverQueries::events(&state.db.pool, HISTORY_LIMIT).await {
+        Ok(history) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "levers": levers.levers().as_slice(),
+            "history": history
+        })),
+        Err(e) => failed("load panic lever history", e),
+    }
+}
+
+/// Engage or release one lever
+pub async fn set(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    levers: web::Data<PanicLevers>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<LeverRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, PANIC_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let Some(lever) = Lever::from_str(&path) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Unknown lever"
+        }));
+    };
+    let reason = body.reason.trim();
+    if reason.is_empty() || reason.len() > 500 {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "A reason of at most 500 characters is required"
+        }));
+    }
+    let action = if body.engaged { "engage" } else { "release" };
+    let log = |accepted: bool| SecurityEvent::PanicLever {
+        admin_id,
+        lever: lever.as_str().to_string(),
+        action: action.to_string(),
+        reason: reason.to_string(),
+        accepted,
+    };
+
+    let verified = auth
+        .verify_mfa(&legacy_user_uuid(admin_id), body.code.trim())
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("MFA verification failed for admin {}: {}", admin_id, e);
+            false
+        });
+    if !verified {
+        audit.log_event(log(false)).await;
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "A valid MFA code is required"
+        }));
+    }
+
+    match levers.set(&state.db.pool, lever, body.engaged, admin_id, reason).await {
+        Ok(Ok(row)) => {
+            audit.log_event(log(true)).await;
+            tracing::warn!("Panic lever {} {}d by admin {}: {}", lever.as_str(), action, admin_id, reason);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "lever": row
+            }))
+        }
+        Ok(Err(refused)) => {
+            audit.log_event(log(false)).await;
+            HttpResponse::Conflict().json(serde_json::json!({
+                "success": false,
+                "message": refused.message(),
+                "unchanged": refused == LeverRefused::Unchanged
+            }))
+        }
+        Err(e) => failed("update panic lever", e),
+    }
+}
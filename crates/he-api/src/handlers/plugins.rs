@@ -0,0 +1,157 @@
+//! Plugin handlers
+//!
+//! Admins holding `plugins:manage` list, load, reload and unload the WASM
+//! plugins in the plugin directory. Players see the terminal commands the
+//! loaded plugins added, and run them through the terminal handlers.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_game_mechanics::terminal_grammar;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use crate::plugins::wasm::{PluginHost, WasmPluginError};
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::handlers::process::require_permission;
+
+const MANAGE_PERMISSION: &str = "plugins:manage";
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn plugin_error(e: &WasmPluginError) -> HttpResponse {
+    let mut response = match e {
+        WasmPluginError::NotFound(_) => HttpResponse::NotFound(),
+        WasmPluginError::CommandTaken { .. } => HttpResponse::Conflict(),
+        WasmPluginError::OutOfFuel | WasmPluginError::Timeout => HttpResponse::ServiceUnavailable(),
+        WasmPluginError::Trap(_) | WasmPluginError::BadOutput => HttpResponse::BadGateway(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": e.to_string()
+    }))
+}
+
+/// Loaded plugins with their capabilities, limits, commands and jobs
+pub async fn list(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    host: web::Data<PluginHost>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        return response;
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "plugins": host.list()
+    }))
+}
+
+/// Load a plugin from the plugin directory, or reload it if it is loaded
+pub async fn load(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    host: web::Data<PluginHost>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let name = path.into_inner();
+    match host.load(&name).await {
+        Ok(plugin) => {
+            audit.log_event(SecurityEvent::PluginChanged {
+                admin_id,
+                plugin: name,
+                action: "load".to_string(),
+            }).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Plugin loaded",
+                "plugin": plugin
+            }))
+        }
+        Err(e) => plugin_error(&e),
+    }
+}
+
+/// Stop a plugin's jobs and remove its commands
+pub async fn unload(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    host: web::Data<PluginHost>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let name = path.into_inner();
+    if !host.unload(&name) {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Plugin is not loaded"
+        }));
+    }
+
+    audit.log_event(SecurityEvent::PluginChanged {
+        admin_id,
+        plugin: name,
+        action: "unload".to_string(),
+    }).await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "Plugin unloaded"
+    }))
+}
+
+/// Terminal commands the loaded plugins added, with their grammar and their
+/// description in the caller's locale
+pub async fn terminal_commands(
+    state: web::Data<AppState>,
+    host: web::Data<PluginHost>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if extract_user_id(&state, &req).await.is_none() {
+        return unauthorized();
+    }
+
+    let translations = host.translations(&crate::terminal::locale());
+    let commands: Vec<_> = host
+        .commands()
+        .into_iter()
+        .map(|(command, plugin)| {
+            let spec = host.grammar(&command);
+            let description = spec.as_ref().map(|spec| {
+                terminal_grammar::describe(spec, std::slice::from_ref(&spec.name), &translations)
+            });
+            serde_json::json!({ "command": command, "plugin": plugin, "description": description, "grammar": spec })
+        })
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "commands": commands
+    }))
+}
@@ -1,14 +1,27 @@
 //! Process management handlers
 
 use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_helix_security::{AuditLogger, IntrusionDetector, SecurityEvent};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use he_database::models::legacy_user_uuid;
+use crate::quota::{ProcessGuard, QuotaViolation};
 use crate::state::AppState;
-use he_database::queries::ProcessQueries;
+use he_database::queries::{HostedProcess, ProcessHostQueries, ProcessQueries};
+use he_game_mechanics::hosting::HostDenied;
+use he_game_mechanics::identity::ResetDenied;
+use he_game_mechanics::process::ProcessType;
+use he_game_mechanics::rules::PvpDenied;
+
+const MANAGE_PERMISSION: &str = "processes:manage";
 
 #[derive(Serialize)]
 pub struct ProcessResponse {
     pub success: bool,
     pub processes: Vec<ProcessInfo>,
+    /// Other players' processes running on the player's servers
+    pub hosted: Vec<HostedProcess>,
 }
 
 #[derive(Serialize)]
@@ -20,12 +33,20 @@ pub struct ProcessInfo {
     pub start_time: String,
     pub end_time: String,
     pub priority: i32,
+    /// Stopped by a power spike until resumed
+    pub paused: bool,
 }
 
 #[derive(Deserialize)]
 pub struct CreateProcessRequest {
     pub process_type: String,
     pub target_pc_id: Option<String>,
+    /// Run on this server, owned or hacked, instead of the gateway
+    #[serde(default)]
+    pub host_ip: Option<String>,
+    /// Start in a process slot held by this reservation
+    #[serde(default)]
+    pub reservation_id: Option<i64>,
 }
 
 pub async fn list_processes(
@@ -43,6 +64,19 @@ pub async fn list_processes(
         }
     };
 
+    // Picks up processes left over from before a restart
+    crate::completion::ensure_started(&state);
+
+    let hosted = match ProcessHostQueries::hosted_on(&state.db.pool, user_id).await {
+        Ok(hosted) => hosted,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to get processes: {}", e)
+            }));
+        }
+    };
+
     // Get user processes
     match ProcessQueries::get_user_processes(&state.db.pool, user_id).await {
         Ok(processes) => {
@@ -54,11 +88,13 @@ pub async fn list_processes(
                 start_time: p.start_time.to_string(),
                 end_time: p.end_time.to_string(),
                 priority: p.priority,
+                paused: p.paused_at.is_some(),
             }).collect();
 
             HttpResponse::Ok().json(ProcessResponse {
                 success: true,
                 processes: process_list,
+                hosted,
             })
         }
         Err(e) => {
@@ -72,6 +108,9 @@ pub async fn list_processes(
 
 pub async fn create_process(
     state: web::Data<AppState>,
+    guard: web::Data<ProcessGuard>,
+    audit: web::Data<AuditLogger>,
+    intrusion: web::Data<IntrusionDetector>,
     req: HttpRequest,
     data: web::Json<CreateProcessRequest>,
 ) -> HttpResponse {
@@ -85,6 +124,70 @@ pub async fn create_process(
         }
     };
 
+    let ip = req.peer_addr()
+        .map(|addr| addr.ip())
+        .unwrap_or(IpAddr::from([127, 0, 0, 1]));
+
+    if let Some(reservation_id) = data.reservation_id {
+        match crate::reservations::use_slot(&state.db.pool, user_id, reservation_id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return HttpResponse::Conflict().json(serde_json::json!({
+                    "success": false,
+                    "message": "The reservation holds no process slot"
+                }));
+            }
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "success": false,
+                    "message": format!("Failed to use reservation: {}", e)
+                }));
+            }
+        }
+    }
+
+    let is_ip_reset = ProcessType::from_str(&data.process_type) == ProcessType::ResetIp;
+    if is_ip_reset {
+        match crate::ip_reset::check(&state.db.pool, user_id).await {
+            Ok(Ok(())) => {}
+            Ok(Err(denied)) => return ip_reset_denied(&denied),
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "success": false,
+                    "message": format!("Failed to check IP reset: {}", e)
+                }));
+            }
+        }
+    }
+
+    let host = match data.host_ip.as_deref() {
+        Some(host_ip) => match crate::hosting::resolve(&state.db.pool, user_id, host_ip.trim()).await {
+            Ok(Ok(host)) => Some(host),
+            Ok(Err(denied)) => return host_denied(&denied),
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "success": false,
+                    "message": format!("Failed to check host: {}", e)
+                }));
+            }
+        },
+        None => None,
+    };
+
+    // Another player's server is only a target under the server's PvP rules
+    if let Some(target_ip) = data.target_pc_id.as_deref() {
+        match crate::rules::check_target(&state.db.pool, user_id, target_ip).await {
+            Ok(Ok(())) => {}
+            Ok(Err(denied)) => return pvp_denied(&denied),
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "success": false,
+                    "message": format!("Failed to check target: {}", e)
+                }));
+            }
+        }
+    }
+
     // Get user's hardware specs to calculate process duration
     let hardware = match he_database::queries::HardwareQueries::get_user_hardware(&state.db.pool, user_id).await {
         Ok(hw) => hw,
@@ -104,6 +207,25 @@ pub async fn create_process(
         }
     };
 
+    // Failed components slow down processes on the player's own hardware
+    let own_specs = he_game_mechanics::HardwareSpecs {
+        cpu: hardware.cpu_mhz,
+        ram: hardware.ram_mb,
+        hdd: hardware.hdd_mb,
+        net: hardware.net_mbps,
+        security_level: 50,
+        performance_rating: 100,
+    };
+    let own_specs = match host {
+        Some(_) => own_specs,
+        None => crate::hardware_wear::effective_specs(&state.db.pool, hardware.hardware_id, own_specs.clone())
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to read hardware condition of user {}: {}", user_id, e);
+                own_specs
+            }),
+    };
+
     // Create player state for game mechanics
     let player_state = he_game_mechanics::PlayerState {
         user_id: user_id as i32,
@@ -111,14 +233,8 @@ pub async fn create_process(
         experience: 0,
         money: 0,
         reputation: std::collections::HashMap::new(),
-        hardware_specs: he_game_mechanics::HardwareSpecs {
-            cpu: hardware.cpu_mhz,
-            ram: hardware.ram_mb,
-            hdd: hardware.hdd_mb,
-            net: hardware.net_mbps,
-            security_level: 50,
-            performance_rating: 100,
-        },
+        // A process on another server runs with that server's hardware
+        hardware_specs: host.as_ref().map(|host| host.specs()).unwrap_or(own_specs),
         software_installed: Vec::new(),
         active_processes: Vec::new(),
         clan_membership: None,
@@ -135,15 +251,30 @@ pub async fn create_process(
         defense_systems: Vec::new(),
     };
 
+    // Transfers go at the slower of both ends
+    let target_net = match data.target_pc_id.as_deref() {
+        Some(target_ip) => ProcessHostQueries::net_total(&state.db.pool, target_ip).await.unwrap_or(None),
+        None => None,
+    };
+    let mut extended_target = he_game_mechanics::extended::extend_target_info(&target_info);
+    extended_target.internet_speed = target_net.or(extended_target.internet_speed);
+
     // Use game mechanics to calculate process time
     let game_engine = he_game_mechanics::GameEngine::new();
     let process_type = he_game_mechanics::process::ProcessType::from_str(&data.process_type);
-    let duration = he_game_mechanics::process::calculate_duration(
+    let duration = he_game_mechanics::process::calculate_duration_under(
         &data.process_type,
-        &player_state,
-        &target_info,
+        &he_game_mechanics::extended::extend_player_state(&player_state),
+        &extended_target,
         &game_engine.config().process,
+        crate::rules::current(),
     );
+    let duration = crate::formula_scripts::current().apply(
+        he_game_mechanics::scripting::Formula::Duration,
+        duration as i64,
+        &data.process_type,
+        &[("player_level", player_state.level as i64)],
+    ) as i32;
 
     // Calculate resource usage
     let resource_usage = he_game_mechanics::process::calculate_resource_usage(
@@ -153,28 +284,116 @@ pub async fn create_process(
     );
 
     // Create process in database with calculated duration
-    let pc_id = format!("pc_{}", user_id);
+    let pc_id = host.as_ref().map(|host| host.ip.clone()).unwrap_or_else(|| format!("pc_{}", user_id));
     let end_time = chrono::Utc::now() + chrono::Duration::seconds(duration as i64);
 
-    match ProcessQueries::create_process_with_duration(
-        &state.db.pool,
-        user_id,
-        &data.process_type,
-        &pc_id,
-        data.target_pc_id.clone(),
-        duration,
-    ).await {
-        Ok(process) => {
+    // The quota is checked in the transaction that inserts the process
+    let admitted: anyhow::Result<Result<_, QuotaViolation>> = async {
+        let mut tx = state.db.pool.begin().await?;
+        if let Err(violation) = guard
+            .admit_and_report(&mut tx, user_id, &data.process_type, ip, &audit, &intrusion)
+            .await?
+        {
+            return Ok(Err(violation));
+        }
+        let process = ProcessQueries::create_process_in(
+            &mut tx,
+            user_id,
+            &data.process_type,
+            &pc_id,
+            data.target_pc_id.clone(),
+            duration,
+        ).await?;
+        tx.commit().await?;
+        Ok(Ok(process))
+    }.await;
+
+    match admitted {
+        Ok(Err(violation)) => {
+            HttpResponse::TooManyRequests().json(serde_json::json!({
+                "success": false,
+                "message": violation.message(),
+                "violation": violation
+            }))
+        }
+        Ok(Ok(process)) => {
+            // Held on the host once the process exists, so the hold can name it
+            if let Some(host) = &host {
+                let reserved = crate::hosting::reserve(&state.db.pool, process.pid, user_id, host, &resource_usage).await;
+                if !matches!(reserved, Ok(Ok(()))) {
+                    if let Err(e) = ProcessQueries::cancel_process(&state.db.pool, process.pid, user_id).await {
+                        tracing::error!("Failed to cancel process {} without a host: {}", process.pid, e);
+                    }
+                }
+                match reserved {
+                    Ok(Ok(())) => {}
+                    Ok(Err(denied)) => return host_denied(&denied),
+                    Err(e) => {
+                        return HttpResponse::InternalServerError().json(serde_json::json!({
+                            "success": false,
+                            "message": format!("Failed to allocate on host: {}", e)
+                        }));
+                    }
+                }
+            }
+
+            // Wears the player's own hardware once it completes
+            if host.is_none() && hardware.hardware_id > 0 {
+                if let Err(e) =
+                    crate::hardware_wear::record_load(&state.db.pool, process.pid, hardware.hardware_id, &resource_usage).await
+                {
+                    tracing::warn!("Failed to record load of process {}: {}", process.pid, e);
+                }
+            }
+
+            // Paid once the process exists, so the charge can name it
+            if is_ip_reset {
+                let charged = crate::ip_reset::charge(&state.db.pool, user_id, process.pid).await;
+                if !matches!(charged, Ok(Ok(()))) {
+                    if let Err(e) = ProcessQueries::cancel_process(&state.db.pool, process.pid, user_id).await {
+                        tracing::error!("Failed to cancel unpaid IP reset {}: {}", process.pid, e);
+                    }
+                }
+                match charged {
+                    Ok(Ok(())) => {}
+                    Ok(Err(denied)) => return ip_reset_denied(&denied),
+                    Err(e) => {
+                        return HttpResponse::InternalServerError().json(serde_json::json!({
+                            "success": false,
+                            "message": format!("Failed to charge for IP reset: {}", e)
+                        }));
+                    }
+                }
+            }
+
             // Send WebSocket event about process start
             if let Some(ws_manager) = &state.ws_manager {
+                let entity = he_websocket::Entity::Process(process.pid);
+                ws_manager.grant(user_id, entity);
                 let event = he_websocket::EventBuilder::process_started(
                     process.pid,
                     data.process_type.clone(),
                     duration as u64,
                 );
-                ws_manager.send_to_user(user_id, event.to_server_message());
+                ws_manager.send_to_entity(entity, event.to_server_message());
+
+                // Shows up in the host owner's TOP list
+                if let Some(host) = host.as_ref().filter(|host| host.owner_id != user_id) {
+                    ws_manager.send_to_user(host.owner_id, event.to_server_message());
+                }
             }
 
+            crate::completion::schedule(&state, process.pid, process.end_time);
+
+            crate::handlers::defense::watch_hostile_process(
+                &state,
+                user_id,
+                process.pid,
+                &data.process_type,
+                data.target_pc_id.as_deref(),
+                duration,
+            ).await;
+
             HttpResponse::Ok().json(serde_json::json!({
                 "success": true,
                 "pid": process.pid,
@@ -194,6 +413,40 @@ pub async fn create_process(
     }
 }
 
+fn host_denied(denied: &HostDenied) -> HttpResponse {
+    let mut response = match denied {
+        HostDenied::HostNotFound => HttpResponse::NotFound(),
+        HostDenied::NoAccess => HttpResponse::Forbidden(),
+        HostDenied::TooManyProcesses { .. } | HostDenied::Exhausted { .. } => HttpResponse::Conflict(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn pvp_denied(denied: &PvpDenied) -> HttpResponse {
+    HttpResponse::Forbidden().json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn ip_reset_denied(denied: &ResetDenied) -> HttpResponse {
+    let mut response = match denied {
+        ResetDenied::InProgress => HttpResponse::Conflict(),
+        ResetDenied::Cooldown { .. } => HttpResponse::TooManyRequests(),
+        ResetDenied::InsufficientFunds { .. } => HttpResponse::PaymentRequired(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
 pub async fn cancel_process(
     state: web::Data<AppState>,
     req: HttpRequest,
@@ -211,14 +464,16 @@ pub async fn cancel_process(
 
     let pid = path.into_inner();
 
-    match ProcessQueries::cancel_process(&state.db.pool, pid, user_id).await {
-        Ok(true) => {
+    match crate::cancellation::cancel(&state.db.pool, user_id, pid).await {
+        Ok(Some(cancellation)) => {
+            crate::handlers::defense::hostile_process_ended(&state, pid);
             HttpResponse::Ok().json(serde_json::json!({
                 "success": true,
-                "message": format!("Process {} cancelled", pid)
+                "message": format!("Process {} cancelled", pid),
+                "cancellation": cancellation
             }))
         }
-        Ok(false) => {
+        Ok(None) => {
             HttpResponse::NotFound().json(serde_json::json!({
                 "success": false,
                 "message": "Process not found"
@@ -233,8 +488,195 @@ pub async fn cancel_process(
     }
 }
 
+/// Restart a process a power spike stopped, keeping the progress it had made
+pub async fn resume_process(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let pid = path.into_inner();
+
+    match ProcessQueries::resume(&state.db.pool, pid, user_id).await {
+        Ok(Some(end_time)) => {
+            crate::completion::reschedule(pid, end_time);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": format!("Process {} resumed", pid),
+                "end_time": end_time.to_string()
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "No paused process with that id"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to resume process: {}", e)
+        })),
+    }
+}
+
 // Helper function to extract user ID from JWT token
-async fn extract_user_id(state: &web::Data<AppState>, req: &HttpRequest) -> Option<i64> {
+/// Resolve the caller and check they hold `permission`
+pub(crate) async fn require_permission(
+    state: &web::Data<AppState>,
+    auth: &AuthService,
+    req: &HttpRequest,
+    permission: &str,
+) -> Result<i64, HttpResponse> {
+    let Some(user_id) = extract_user_id(state, req).await else {
+        return Err(HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        })));
+    };
+
+    match auth.check_permission(&legacy_user_uuid(user_id), permission).await {
+        Ok(true) => Ok(user_id),
+        Ok(false) => Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "success": false,
+            "message": "Access denied"
+        }))),
+        Err(e) => {
+            tracing::error!("Permission check failed for user {}: {}", user_id, e);
+            Err(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+                "success": false,
+                "message": "Authorization unavailable"
+            })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct KillSwitchRequest {
+    pub process_type: String,
+    pub reason: String,
+}
+
+pub async fn list_kill_switches(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    guard: web::Data<ProcessGuard>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        return response;
+    }
+
+    let switches: Vec<_> = guard.kill_switches().await.values().cloned().collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "kill_switches": switches
+    }))
+}
+
+/// Disable a process type for everyone and cancel its running processes
+pub async fn engage_kill_switch(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    guard: web::Data<ProcessGuard>,
+    req: HttpRequest,
+    data: web::Json<KillSwitchRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let process_type = data.process_type.trim();
+    if process_type.is_empty() || process_type.len() > 50 || data.reason.trim().is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "A process type and a reason are required"
+        }));
+    }
+
+    match guard.engage(&state.db.pool, process_type, data.reason.trim(), admin_id).await {
+        Ok(cancelled) => {
+            audit.log_event(SecurityEvent::ProcessKillSwitch {
+                admin_id,
+                process_type: process_type.to_lowercase(),
+                engaged: true,
+                cancelled: cancelled.len(),
+            }).await;
+
+            if let Some(ws_manager) = &state.ws_manager {
+                for (pid, user_id) in &cancelled {
+                    let event = he_websocket::EventBuilder::process_completed(
+                        *pid,
+                        process_type.to_lowercase(),
+                        "cancelled".to_string(),
+                    );
+                    ws_manager.send_to_user(*user_id, event.to_server_message());
+                }
+            }
+
+            tracing::warn!(
+                "Kill-switch engaged for '{}' by admin {}: {} processes cancelled",
+                process_type, admin_id, cancelled.len()
+            );
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": format!("{} processes disabled", process_type),
+                "cancelled": cancelled.len()
+            }))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to engage kill-switch: {}", e)
+        })),
+    }
+}
+
+pub async fn release_kill_switch(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    guard: web::Data<ProcessGuard>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let process_type = path.into_inner();
+    match guard.release(&state.db.pool, &process_type).await {
+        Ok(true) => {
+            audit.log_event(SecurityEvent::ProcessKillSwitch {
+                admin_id,
+                process_type: process_type.to_lowercase(),
+                engaged: false,
+                cancelled: 0,
+            }).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": format!("{} processes enabled", process_type)
+            }))
+        }
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "No kill-switch for this process type"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to release kill-switch: {}", e)
+        })),
+    }
+}
+
+pub(crate) async fn extract_user_id(state: &web::Data<AppState>, req: &HttpRequest) -> Option<i64> {
     let token = req.headers()
         .get("Authorization")?
         .to_str().ok()?
@@ -7,7 +7,7 @@ use uuid::Uuid;
 use he_progression::{
     PlayerProgression, LevelInfo, SkillTree, AchievementProgress,
     UnlockableContent, ReputationSystem, ProgressionEvent,
-    PlayerStatistics, SkillError,
+    PlayerStatistics, SkillError, CorporationAction,
 };
 
 use crate::{error::ApiResult, auth::Claims};
@@ -167,6 +167,72 @@ pub async fn modify_reputation(
     Ok(HttpResponse::Ok().json(&change))
 }
 
+/// Get standings with all NPC corporations
+pub async fn get_corporation_standings(
+    claims: Claims,
+) -> ApiResult<HttpResponse> {
+    // TODO: Load from database
+    let player_id = claims.sub.parse::<Uuid>()
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid player ID"))?;
+    let mut progression = PlayerProgression::new(player_id);
+
+    progression.reputation.decay_corporation_standings(chrono::Utc::now());
+
+    Ok(HttpResponse::Ok().json(&progression.reputation.corporation_standings))
+}
+
+/// Get standing, store discount and contract access for one corporation
+pub async fn get_corporation_standing(
+    claims: Claims,
+    path: web::Path<Uuid>,
+) -> ApiResult<HttpResponse> {
+    // TODO: Load from database
+    let player_id = claims.sub.parse::<Uuid>()
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid player ID"))?;
+    let mut progression = PlayerProgression::new(player_id);
+    let corporation_id = path.into_inner();
+
+    progression.reputation.decay_corporation_standings(chrono::Utc::now());
+    let tier = progression.reputation.corporation_tier(&corporation_id);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "standing": progression.reputation.corporation_standing(&corporation_id),
+        "tier": tier,
+        "store_discount": tier.store_discount(),
+        "max_contract_tier": tier.max_contract_tier(),
+    })))
+}
+
+/// Record a bounty completion or hack against a corporation
+#[derive(Debug, Deserialize)]
+pub struct CorporationActionRequest {
+    pub corporation_id: Uuid,
+    pub corporation_name: String,
+    pub action: CorporationAction,
+}
+
+pub async fn record_corporation_action(
+    claims: Claims,
+    payload: web::Json<CorporationActionRequest>,
+) -> ApiResult<HttpResponse> {
+    // TODO: Load from database
+    let player_id = claims.sub.parse::<Uuid>()
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid player ID"))?;
+    let mut progression = PlayerProgression::new(player_id);
+    let payload = payload.into_inner();
+
+    let change = progression.reputation.record_corporation_action(
+        payload.corporation_id,
+        &payload.corporation_name,
+        payload.action,
+        chrono::Utc::now(),
+    );
+
+    // TODO: Save to database
+
+    Ok(HttpResponse::Ok().json(&change))
+}
+
 /// Get player statistics
 pub async fn get_statistics(
     claims: Claims,
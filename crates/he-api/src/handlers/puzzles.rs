@@ -0,0 +1,122 @@
+//! Storyline puzzle handlers
+//!
+//! Hidden files are read and answers checked on the server; the client only
+//! learns a stage's file once it may read it.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_game_mechanics::puzzles::PuzzleDenied;
+use serde::Deserialize;
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::puzzles;
+
+#[derive(Deserialize)]
+pub struct AnswerRequest {
+    pub answer: String,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn denied(denied: PuzzleDenied) -> HttpResponse {
+    let mut response = match denied {
+        PuzzleDenied::PuzzleNotFound => HttpResponse::NotFound(),
+        PuzzleDenied::ServerNotHacked { .. } => HttpResponse::Forbidden(),
+        PuzzleDenied::AlreadySolved | PuzzleDenied::WindowClosed { .. } | PuzzleDenied::AwaitingCommunity { .. } => {
+            HttpResponse::Conflict()
+        }
+        PuzzleDenied::CoolingDown { .. } => HttpResponse::TooManyRequests(),
+        PuzzleDenied::AnswerTooLong { .. } | PuzzleDenied::WrongAnswer { .. } => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Every puzzle with the caller's progress and the community milestones
+pub async fn list(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match puzzles::overview(&state.db.pool, user_id).await {
+        Ok(puzzles) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "puzzles": puzzles
+        })),
+        Err(e) => failed("load puzzles", e),
+    }
+}
+
+pub async fn get(state: web::Data<AppState>, req: HttpRequest, path: web::Path<i64>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match puzzles::puzzle(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok(puzzle)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "puzzle": puzzle
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("load puzzle", e),
+    }
+}
+
+/// Puzzle files hidden on a server the caller has cracked
+pub async fn hidden_files(state: web::Data<AppState>, req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+    let ip = path.into_inner();
+    if ip.parse::<std::net::IpAddr>().is_err() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Invalid IP address"
+        }));
+    }
+
+    match puzzles::hidden_files(&state.db.pool, user_id, &ip).await {
+        Ok(Ok(files)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "server_ip": ip,
+            "files": files
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("list hidden files", e),
+    }
+}
+
+/// Answer the caller's current stage
+pub async fn answer(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<AnswerRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match puzzles::answer(&state.db.pool, user_id, path.into_inner(), &body.answer).await {
+        Ok(Ok(outcome)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "outcome": outcome
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("check answer", e),
+    }
+}
@@ -0,0 +1,76 @@
+//! SQL audit handlers
+//!
+//! Operators holding `database:audit` list the statements costing the
+//! database most since startup or the last reset, with the plans captured
+//! for the slowest (see [`he_database::audit`]).
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::audit::{QueryAudit, TopOrder};
+use serde::Deserialize;
+use crate::state::AppState;
+use crate::handlers::process::require_permission;
+
+const AUDIT_PERMISSION: &str = "database:audit";
+const DEFAULT_TOP: usize = 20;
+const MAX_TOP: usize = 200;
+
+#[derive(Deserialize)]
+pub struct TopQuery {
+    /// `total_time` (default), `max_time`, `calls` or `slow_calls`
+    pub order: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// The worst statements by total time, or by `order`
+pub async fn top_queries(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<QueryAudit>,
+    req: HttpRequest,
+    query: web::Query<TopQuery>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, AUDIT_PERMISSION).await {
+        return response;
+    }
+
+    let order = match query.order.as_deref() {
+        None => TopOrder::TotalTime,
+        Some(order) => match TopOrder::from_str(order) {
+            Some(order) => order,
+            None => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "success": false,
+                    "message": "Unknown order"
+                }));
+            }
+        },
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_TOP).clamp(1, MAX_TOP);
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "queries_seen": audit.queries_seen(),
+        "statements": audit.top(limit, order)
+    }))
+}
+
+/// Start the totals over, e.g. after deploying an index
+pub async fn reset(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<QueryAudit>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, AUDIT_PERMISSION).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+
+    audit.reset();
+    tracing::info!("Query audit reset by admin {}", admin_id);
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "Query audit reset"
+    }))
+}
@@ -0,0 +1,124 @@
+//! Referral handlers
+//!
+//! Players see their code and how their referrals are doing; codes are used
+//! at registration (see [`crate::handlers::auth::register`]). Referrals held
+//! by the self-referral heuristics go to moderators holding the
+//! `referrals:moderate` permission.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_database::queries::ReferralQueries;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::referrals;
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::handlers::process::require_permission;
+
+const MANAGE_PERMISSION: &str = "referrals:moderate";
+/// Referrals listed for their referrer
+const HISTORY_SIZE: i64 = 50;
+const QUEUE_SIZE: i64 = 100;
+const MAX_NOTE_LENGTH: usize = 500;
+
+#[derive(Deserialize)]
+pub struct ReviewRequest {
+    pub approve: bool,
+    pub note: String,
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+/// The player's code, stats and referrals
+pub async fn my_referrals(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let pool = &state.db.pool;
+    let loaded = futures::try_join!(
+        referrals::ensure_code(pool, user_id),
+        ReferralQueries::stats(pool, user_id),
+        ReferralQueries::for_referrer(pool, user_id, HISTORY_SIZE),
+    );
+    match loaded {
+        Ok((code, stats, referrals)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "code": code,
+            "stats": stats,
+            "referrals": referrals
+        })),
+        Err(e) => failed("load referrals", e),
+    }
+}
+
+/// Referrals held by the heuristics, oldest first
+pub async fn flagged(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        return response;
+    }
+
+    match ReferralQueries::flagged(&state.db.pool, QUEUE_SIZE).await {
+        Ok(referrals) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "referrals": referrals
+        })),
+        Err(e) => failed("load flagged referrals", e),
+    }
+}
+
+/// Clear a flagged referral to earn rewards, or reject it
+pub async fn review(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<ReviewRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let note = body.note.trim();
+    if note.is_empty() || note.chars().count() > MAX_NOTE_LENGTH {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Note must be 1 to {} characters", MAX_NOTE_LENGTH)
+        }));
+    }
+
+    let referral_id = path.into_inner();
+    match referrals::review(&state.db.pool, referral_id, admin_id, body.approve, note).await {
+        Ok(Some(referral)) => {
+            audit.log_event(SecurityEvent::ReferralReviewed {
+                admin_id,
+                referral_id,
+                approved: body.approve,
+            }).await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": if body.approve { "Referral cleared" } else { "Referral rejected" },
+                "referral": referral
+            }))
+        }
+        Ok(None) => HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "message": "This referral is not awaiting review"
+        })),
+        Err(e) => failed("review referral", e),
+    }
+}
@@ -0,0 +1,224 @@
+//! Report handlers
+//!
+//! Players report chat messages, usernames, server pages and clan names.
+//! Moderators holding `reports:view` work the queue and read a player's
+//! moderation history; closing a case needs `reports:resolve`.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_cache::{versions::VersionStore, CacheKeys};
+use he_database::queries::{ModerationQueries, ReportQueries};
+use he_game_mechanics::reports::{ReportAction, ReportCategory, ReportDenied, ReportTarget, ResolveDenied};
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::reports;
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::handlers::process::require_permission;
+
+const VIEW_PERMISSION: &str = "reports:view";
+const RESOLVE_PERMISSION: &str = "reports:resolve";
+const QUEUE_SIZE: i64 = 100;
+const HISTORY_SIZE: i64 = 100;
+/// Days of closed cases the SLA metrics cover
+const METRICS_WINDOW_DAYS: i64 = 7;
+const MAX_NOTE_LENGTH: usize = 500;
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    pub target: ReportTarget,
+    /// Message id, user id, server IP or clan id
+    pub target_key: String,
+    pub category: ReportCategory,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveRequest {
+    #[serde(flatten)]
+    pub action: ReportAction,
+    pub note: String,
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn report_denied(denied: &ReportDenied) -> HttpResponse {
+    let mut response = match denied {
+        ReportDenied::TargetNotFound => HttpResponse::NotFound(),
+        ReportDenied::OwnContent => HttpResponse::Forbidden(),
+        ReportDenied::TooManyOpen { .. } => HttpResponse::TooManyRequests(),
+        ReportDenied::AlreadyReported => HttpResponse::Conflict(),
+        ReportDenied::DescriptionTooLong { .. } => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn resolve_denied(denied: &ResolveDenied) -> HttpResponse {
+    let mut response = match denied {
+        ResolveDenied::NotOpen => HttpResponse::Conflict(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Report a piece of content to the moderators
+pub async fn submit(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<ReportRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let description = body.description.trim();
+    let target_key = body.target_key.trim();
+    match reports::submit(&state.db.pool, user_id, body.target, target_key, body.category, description).await {
+        Ok(Ok(receipt)) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "message": "Thanks, a moderator will look at it",
+            "report": receipt
+        })),
+        Ok(Err(denied)) => report_denied(&denied),
+        Err(e) => failed("file report", e),
+    }
+}
+
+/// Open cases, most urgent first, with how the queue is keeping up
+pub async fn queue(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, VIEW_PERMISSION).await {
+        return response;
+    }
+
+    let pool = &state.db.pool;
+    let loaded = futures::try_join!(
+        ReportQueries::queue(pool, QUEUE_SIZE),
+        ReportQueries::sla_metrics(pool, METRICS_WINDOW_DAYS),
+    );
+    match loaded {
+        Ok((cases, metrics)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "cases": cases,
+            "metrics": metrics
+        })),
+        Err(e) => failed("load report queue", e),
+    }
+}
+
+/// One case with its evidence and every report filed on it
+pub async fn get_case(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, VIEW_PERMISSION).await {
+        return response;
+    }
+
+    let case_id = path.into_inner();
+    let pool = &state.db.pool;
+    match futures::try_join!(ReportQueries::case(pool, case_id), ReportQueries::reports(pool, case_id)) {
+        Ok((Some(case), reports)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "case": case,
+            "reports": reports
+        })),
+        Ok((None, _)) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Case not found"
+        })),
+        Err(e) => failed("load case", e),
+    }
+}
+
+/// Dismiss a case or act on the player responsible
+pub async fn resolve(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    versions: web::Data<dyn VersionStore>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<ResolveRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, RESOLVE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let note = body.note.trim();
+    if note.is_empty() || note.chars().count() > MAX_NOTE_LENGTH {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Note must be 1 to {} characters", MAX_NOTE_LENGTH)
+        }));
+    }
+
+    let case_id = path.into_inner();
+    match reports::resolve(&state.db.pool, case_id, admin_id, body.action, note).await {
+        Ok(Ok(case)) => {
+            audit.log_event(SecurityEvent::ReportResolved {
+                admin_id,
+                case_id,
+                action: body.action.as_str().to_string(),
+                moderation_action_id: case.moderation_action_id,
+            }).await;
+
+            // A cleared server page must not be served from cache
+            if body.action == ReportAction::RemoveContent && case.target == ReportTarget::ServerPage.as_str() {
+                if let Err(e) = versions.bump(&CacheKeys::server_info(&case.target_key)).await {
+                    tracing::warn!("Failed to bump content version of server {}: {}", case.target_key, e);
+                }
+            }
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Case closed",
+                "case": case
+            }))
+        }
+        Ok(Err(denied)) => resolve_denied(&denied),
+        Err(e) => failed("resolve case", e),
+    }
+}
+
+/// Actions moderators have taken against a player
+pub async fn moderation_history(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, VIEW_PERMISSION).await {
+        return response;
+    }
+
+    match ModerationQueries::history(&state.db.pool, path.into_inner(), HISTORY_SIZE).await {
+        Ok(actions) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "actions": actions
+        })),
+        Err(e) => failed("load moderation history", e),
+    }
+}
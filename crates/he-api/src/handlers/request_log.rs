@@ -0,0 +1,179 @@
+//! Request logging handlers
+//!
+//! Operators holding `request_log:manage` tune how many requests are logged
+//! and put single users in verbose mode for a limited time. Verbose mode is
+//! audited on and off, since it logs what the user sends.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_auth::AuthService;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use serde::Deserialize;
+use crate::handlers::process::require_permission;
+use crate::request_log::RequestLog;
+use crate::state::AppState;
+
+const REQUEST_LOG_PERMISSION: &str = "request_log:manage";
+/// Longest a user stays in verbose mode per request
+const MAX_VERBOSE_MINUTES: i64 = 24 * 60;
+const MAX_BODY_BYTES: i32 = 64 * 1024;
+
+#[derive(Deserialize)]
+pub struct SamplingRequest {
+    pub sample_rate: f64,
+    pub max_body_bytes: i32,
+}
+
+#[derive(Deserialize)]
+pub struct VerboseRequest {
+    pub minutes: i64,
+    pub reason: String,
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "success": false,
+        "message": message
+    }))
+}
+
+/// Current sampling and the users in verbose mode
+pub async fn settings(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    log: web::Data<RequestLog>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, REQUEST_LOG_PERMISSION).await {
+        return response;
+    }
+
+    let settings = log.settings();
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "sample_rate": settings.sample_rate,
+        "max_body_bytes": settings.max_body_bytes,
+        "verbose": settings.verbose
+    }))
+}
+
+pub async fn set_sampling(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    log: web::Data<RequestLog>,
+    req: HttpRequest,
+    body: web::Json<SamplingRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, REQUEST_LOG_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    if !(0.0..=1.0).contains(&body.sample_rate) {
+        return bad_request("sample_rate must be between 0 and 1");
+    }
+    if !(0..=MAX_BODY_BYTES).contains(&body.max_body_bytes) {
+        return bad_request(&format!("max_body_bytes must be between 0 and {}", MAX_BODY_BYTES));
+    }
+
+    match log.set_sampling(&state.db.pool, body.sample_rate, body.max_body_bytes, admin_id).await {
+        Ok(settings) => {
+            audit.log_event(SecurityEvent::RequestLogSamplingChanged {
+                admin_id,
+                sample_rate: settings.sample_rate,
+                max_body_bytes: settings.max_body_bytes,
+            }).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "settings": settings
+            }))
+        }
+        Err(e) => failed("update request log sampling", e),
+    }
+}
+
+/// Log everything a user sends, with bodies, for the next few minutes
+pub async fn enable_verbose(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    log: web::Data<RequestLog>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<VerboseRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, REQUEST_LOG_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let user_id = path.into_inner();
+
+    if !(1..=MAX_VERBOSE_MINUTES).contains(&body.minutes) {
+        return bad_request(&format!("minutes must be between 1 and {}", MAX_VERBOSE_MINUTES));
+    }
+    let reason = body.reason.trim();
+    if reason.is_empty() || reason.len() > 500 {
+        return bad_request("A reason of at most 500 characters is required");
+    }
+
+    match log.enable_verbose(&state.db.pool, user_id, body.minutes, admin_id, reason).await {
+        Ok(row) => {
+            audit.log_event(SecurityEvent::VerboseRequestLogging {
+                admin_id,
+                user_id,
+                enabled: true,
+                reason: Some(reason.to_string()),
+                expires_at: Some(row.expires_at),
+            }).await;
+            tracing::warn!("Verbose request logging on for user {} until {} by admin {}: {}", user_id, row.expires_at, admin_id, reason);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "verbose": row
+            }))
+        }
+        Err(e) => failed("enable verbose request logging", e),
+    }
+}
+
+pub async fn disable_verbose(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    log: web::Data<RequestLog>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, REQUEST_LOG_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let user_id = path.into_inner();
+
+    match log.disable_verbose(&state.db.pool, user_id).await {
+        Ok(true) => {
+            audit.log_event(SecurityEvent::VerboseRequestLogging {
+                admin_id,
+                user_id,
+                enabled: false,
+                reason: None,
+                expires_at: None,
+            }).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Verbose request logging disabled"
+            }))
+        }
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "User is not in verbose mode"
+        })),
+        Err(e) => failed("disable verbose request logging", e),
+    }
+}
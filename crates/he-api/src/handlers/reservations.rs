@@ -0,0 +1,114 @@
+//! Resource reservation handlers
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_database::queries::{ReservationQueries, ReservationRow};
+use he_game_mechanics::reservations::{ReservationDenied, ReservationRequest};
+use serde::Deserialize;
+use crate::quota::ProcessGuard;
+use crate::reservations;
+use crate::state::AppState;
+use crate::handlers::process::extract_user_id;
+
+#[derive(Deserialize)]
+pub struct ReserveRequest {
+    #[serde(flatten)]
+    pub request: ReservationRequest,
+    pub ttl_secs: Option<i64>,
+    /// e.g. `generated_mission:12`, to have the mission settle the holds
+    pub purpose: Option<String>,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn denied(denied: ReservationDenied) -> HttpResponse {
+    let mut response = match denied {
+        ReservationDenied::NotFound => HttpResponse::NotFound(),
+        ReservationDenied::NotHeld { .. } | ReservationDenied::Expired | ReservationDenied::TooManyOpen { .. } => {
+            HttpResponse::Conflict()
+        }
+        ReservationDenied::InsufficientFunds { .. } => HttpResponse::PaymentRequired(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+fn outcome(result: anyhow::Result<Result<ReservationRow, ReservationDenied>>, action: &str) -> HttpResponse {
+    match result {
+        Ok(Ok(reservation)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "reservation": reservation
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed(action, e),
+    }
+}
+
+/// The caller's latest reservations
+pub async fn list(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match ReservationQueries::list(&state.db.pool, user_id, reservations::LIST_LIMIT).await {
+        Ok(reservations) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "reservations": reservations
+        })),
+        Err(e) => failed("load reservations", e),
+    }
+}
+
+/// Hold money, disk space and process slots
+pub async fn reserve(
+    state: web::Data<AppState>,
+    guard: web::Data<ProcessGuard>,
+    req: HttpRequest,
+    body: web::Json<ReserveRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let result = reservations::reserve(
+        &state.db.pool,
+        user_id,
+        body.request,
+        body.ttl_secs,
+        body.purpose.as_deref(),
+        guard.config().max_concurrent,
+    )
+    .await;
+    outcome(result, "reserve resources")
+}
+
+pub async fn commit(state: web::Data<AppState>, req: HttpRequest, path: web::Path<i64>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    outcome(reservations::commit(&state.db.pool, user_id, path.into_inner()).await, "commit reservation")
+}
+
+pub async fn release(state: web::Data<AppState>, req: HttpRequest, path: web::Path<i64>) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    outcome(reservations::release(&state.db.pool, user_id, path.into_inner()).await, "release reservation")
+}
@@ -0,0 +1,168 @@
+//! Player server customization
+//!
+//! Players set a hostname, a message of the day shown as the login banner to
+//! anyone connecting to their server, and a public profile shown in the in-game
+//! browser. Text that matches the configured offensive terms is still saved, but each
+//! matching field is recorded in the audit log and the page is filed in the
+//! report queue for a moderator to review.
+//!
+//! Server pages are revalidated against the content version of the server's
+//! `server_info` cache key, which every update bumps.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_cache::{versions::VersionStore, CacheKeys};
+use he_core::validation::{find_offensive_terms, ServerCustomizationInput};
+use he_database::queries::ServerQueries;
+use he_game_mechanics::reports::{ReportCategory, ReportTarget};
+use he_helix_security::{AuditLogger, SecurityEvent};
+use validator::Validate;
+use crate::reports;
+use crate::state::AppState;
+use crate::handlers::process::extract_user_id;
+
+const REPORT_EXCERPT_CHARS: usize = 200;
+
+pub async fn get_customization(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    match ServerQueries::get_customization(&state.db.pool, user_id).await {
+        Ok(Some(page)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "server": page
+        })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "You have no server"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load server: {}", e)
+        })),
+    }
+}
+
+pub async fn update_customization(
+    state: web::Data<AppState>,
+    audit: web::Data<AuditLogger>,
+    versions: web::Data<dyn VersionStore>,
+    req: HttpRequest,
+    data: web::Json<ServerCustomizationInput>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let input = data.into_inner();
+    if let Err(e) = input.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": format!("Invalid input: {}", e)
+        }));
+    }
+
+    let hostname = input.hostname.as_deref().map(str::trim);
+    let motd = input.motd.as_deref().map(str::trim);
+    let public_profile = input.public_profile.as_deref().map(str::trim);
+
+    let (server_id, ip) = match ServerQueries::update_customization(
+        &state.db.pool,
+        user_id,
+        hostname,
+        motd,
+        public_profile,
+    ).await {
+        Ok(Some(server)) => server,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "success": false,
+                "message": "You have no server"
+            }));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "message": format!("Failed to update server: {}", e)
+            }));
+        }
+    };
+
+    if let Err(e) = versions.bump(&CacheKeys::server_info(&ip)).await {
+        tracing::warn!("Failed to bump content version of server {}: {}", ip, e);
+    }
+
+    let offensive_terms = &crate::balance::current().moderation.offensive_terms;
+    let mut flagged = Vec::new();
+    for (field, value) in [("hostname", hostname), ("motd", motd), ("public_profile", public_profile)] {
+        let Some(text) = value else { continue };
+        let terms = find_offensive_terms(text, offensive_terms);
+        if terms.is_empty() {
+            continue;
+        }
+        flagged.push(format!("{} ({})", field, terms.join(", ")));
+
+        audit.log_event(SecurityEvent::ContentReported {
+            user_id,
+            server_id,
+            field: field.to_string(),
+            terms: terms.iter().map(|t| t.to_string()).collect(),
+            excerpt: text.chars().take(REPORT_EXCERPT_CHARS).collect(),
+        }).await;
+    }
+
+    if !flagged.is_empty() {
+        let description = format!("Offensive terms in {}", flagged.join("; "));
+        if let Err(e) = reports::auto_report(&state.db.pool, ReportTarget::ServerPage, &ip, ReportCategory::Other, &description).await {
+            tracing::warn!("Failed to report server {} to moderators: {}", ip, e);
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "Server updated"
+    }))
+}
+
+/// Page for a player server in the in-game browser, with its MOTD as the login banner
+pub async fn view_page(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return HttpResponse::Unauthorized().json(serde_json::json!({
+            "success": false,
+            "message": "Unauthorized"
+        }));
+    };
+
+    let ip = path.into_inner();
+    match ServerQueries::get_public_page(&state.db.pool, &ip).await {
+        Ok(Some(page)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "ip_address": page.ip_address,
+            "hostname": page.hostname,
+            "motd": page.motd,
+            "public_profile": page.public_profile,
+            "is_own": page.owner_id == user_id
+        })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "No page at this address"
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load page: {}", e)
+        })),
+    }
+}
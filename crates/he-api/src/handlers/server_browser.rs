@@ -0,0 +1,146 @@
+//! Server browser handlers
+//!
+//! Players browse NPC servers, scan address ranges for more and page through
+//! their own server database (see [`crate::server_browser`]).
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use chrono::{DateTime, Utc};
+use he_database::queries::BrowserFilter;
+use he_game_mechanics::server_browser::{BrowserDenied, Difficulty};
+use serde::Deserialize;
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+
+#[derive(Deserialize)]
+pub struct BrowseQuery {
+    #[serde(rename = "type")]
+    pub npc_type: Option<String>,
+    pub tier: Option<i16>,
+    pub region: Option<String>,
+    /// `trivial`, `easy`, `fair`, `hard` or `deadly`, as estimated for the
+    /// caller
+    pub difficulty: Option<String>,
+    pub before_id: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ScanRangeRequest {
+    /// e.g. `10.0.0.0/24`
+    pub range: String,
+}
+
+#[derive(Deserialize)]
+pub struct KnownQuery {
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn denied(denied: BrowserDenied) -> HttpResponse {
+    let mut response = match denied {
+        BrowserDenied::RateLimited { .. } => HttpResponse::TooManyRequests(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// NPC servers by type, tier, region and estimated difficulty
+pub async fn browse(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<BrowseQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let difficulty = match query.difficulty.as_deref() {
+        None => None,
+        Some(difficulty) => match Difficulty::from_str(difficulty) {
+            Some(difficulty) => Some(difficulty),
+            None => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "success": false,
+                    "message": "Unknown difficulty"
+                }));
+            }
+        },
+    };
+    let filter = BrowserFilter {
+        npc_type: query.npc_type.as_deref(),
+        npc_tier: query.tier,
+        region: query.region.as_deref(),
+    };
+
+    match crate::server_browser::browse(&state.db.pool, user_id, &filter, difficulty, query.before_id, query.limit).await {
+        Ok(Ok(page)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "servers": page.servers,
+            "next_before_id": page.next_before_id
+        })),
+        Ok(Err(reason)) => denied(reason),
+        Err(e) => failed("browse servers", e),
+    }
+}
+
+/// Find the NPC servers in a range and add them to the caller's database
+pub async fn scan_range(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<ScanRangeRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match crate::server_browser::scan_range(&state.db.pool, user_id, &body.range).await {
+        Ok(Ok(servers)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": format!("Found {} servers", servers.len()),
+            "servers": servers
+        })),
+        Ok(Err(reason)) => denied(reason),
+        Err(e) => failed("scan range", e),
+    }
+}
+
+/// The caller's server database, latest finds first
+pub async fn known_servers(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<KnownQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match crate::server_browser::known(&state.db.pool, user_id, query.before, query.limit).await {
+        Ok(servers) => {
+            let next_before = servers.last().and_then(|s| s.discovered_at);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "servers": servers,
+                "next_before": next_before
+            }))
+        }
+        Err(e) => failed("load known servers", e),
+    }
+}
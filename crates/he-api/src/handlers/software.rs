@@ -0,0 +1,205 @@
+//! Software catalog
+//!
+//! The catalog only changes when the server restarts with different packs,
+//! so the response body is serialised once and served with an ETag.
+//! Copy protection of the player's own copies is managed here too.
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use he_game_mechanics::piracy::{KeygenDenied, ProtectDenied};
+use he_game_world::SoftwareCatalog;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use crate::handlers::game::extract_user_id;
+use crate::state::AppState;
+
+/// How long clients and proxies may reuse the catalog without revalidating
+const MAX_AGE_SECONDS: u32 = 300;
+
+/// Pre-rendered `/api/software/catalog` response
+pub struct SoftwareCatalogCache {
+    body: web::Bytes,
+    etag: String,
+    /// Lowercased names of the titles with copy protection
+    protected: HashSet<String>,
+}
+
+impl SoftwareCatalogCache {
+    pub fn new(catalog: &SoftwareCatalog) -> Self {
+        let software: Vec<_> = catalog.all().collect();
+        let body = serde_json::to_vec(&serde_json::json!({
+            "success": true,
+            "packs": catalog.packs,
+            "software": software
+        }))
+        .expect("software catalog serialises");
+
+        let digest = Sha256::digest(&body);
+        let etag = format!("\"{}\"", hex_prefix(&digest, 16));
+
+        let protected = catalog
+            .all()
+            .filter(|software| software.copy_protection)
+            .map(|software| software.name.to_lowercase())
+            .collect();
+
+        Self { body: body.into(), etag, protected }
+    }
+
+    pub fn etag(&self) -> &str {
+        &self.etag
+    }
+
+    /// Whether copies of the title called `name` can be license-locked
+    pub fn copy_protected(&self, name: &str) -> bool {
+        self.protected.contains(&name.trim().to_lowercase())
+    }
+}
+
+fn hex_prefix(bytes: &[u8], len: usize) -> String {
+    bytes.iter().take(len).map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Every software item with its pack, dependencies and modules
+pub async fn get_catalog(
+    cache: web::Data<SoftwareCatalogCache>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let cache_control = format!("public, max-age={}", MAX_AGE_SECONDS);
+
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').any(|tag| tag.trim() == cache.etag || tag.trim() == "*"))
+        .unwrap_or(false);
+
+    if not_modified {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, cache.etag.clone()))
+            .insert_header((header::CACHE_CONTROL, cache_control))
+            .finish();
+    }
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((header::ETAG, cache.etag.clone()))
+        .insert_header((header::CACHE_CONTROL, cache_control))
+        .body(cache.body.clone())
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+/// License-lock one of the caller's copies to the server it is on
+pub async fn protect_software(
+    state: web::Data<AppState>,
+    cache: web::Data<SoftwareCatalogCache>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match crate::piracy::protect(&state.db.pool, &cache, user_id, path.into_inner()).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Software license-locked to its server"
+        })),
+        Ok(Err(denied)) => {
+            let mut response = match denied {
+                ProtectDenied::NotFound => HttpResponse::NotFound(),
+                ProtectDenied::AlreadyLocked => HttpResponse::Conflict(),
+                _ => HttpResponse::BadRequest(),
+            };
+            response.json(serde_json::json!({
+                "success": false,
+                "message": denied.message(),
+                "denied": denied
+            }))
+        }
+        Err(e) => failed("protect software", e),
+    }
+}
+
+/// Start a keygen process that cracks the license of one of the caller's copies
+pub async fn start_keygen(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match crate::piracy::start_keygen(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok(keygen)) => {
+            crate::completion::schedule(&state, keygen.pid, keygen.end_time);
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "keygen": keygen
+            }))
+        }
+        Ok(Err(denied)) => {
+            let mut response = match denied {
+                KeygenDenied::NotFound => HttpResponse::NotFound(),
+                KeygenDenied::InProgress => HttpResponse::Conflict(),
+                _ => HttpResponse::BadRequest(),
+            };
+            response.json(serde_json::json!({
+                "success": false,
+                "message": denied.message(),
+                "denied": denied
+            }))
+        }
+        Err(e) => failed("start keygen", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_catalog_revalidates_with_etag() {
+        let cache = web::Data::new(SoftwareCatalogCache::new(&SoftwareCatalog::default()));
+        let etag = cache.etag().to_string();
+        let app = test::init_service(
+            App::new()
+                .app_data(cache)
+                .route("/api/software/catalog", web::get().to(get_catalog)),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/api/software/catalog").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), etag.as_str());
+
+        let req = test::TestRequest::get()
+            .uri("/api/software/catalog")
+            .insert_header((header::IF_NONE_MATCH, etag.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 304);
+    }
+
+    #[test]
+    fn test_copy_protected_titles() {
+        let cache = SoftwareCatalogCache::new(&SoftwareCatalog::default());
+        assert!(cache.copy_protected("Elite Cracker"));
+        assert!(!cache.copy_protected("Basic Cracker"));
+    }
+}
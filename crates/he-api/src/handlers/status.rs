@@ -0,0 +1,225 @@
+//! Public status page and incident administration
+//!
+//! `/status` and `/status.json` serve the report cached by the
+//! [`StatusMonitor`]. Incident endpoints require the `status:manage`
+//! permission and refresh the report so changes show up immediately.
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use he_auth::AuthService;
+use he_helix_security::{AuditLogger, SecurityEvent};
+use he_status::{IncidentImpact, IncidentStatus, COMPONENTS};
+use serde::Deserialize;
+use crate::handlers::process::require_permission;
+use crate::state::AppState;
+use crate::status::StatusMonitor;
+
+const MANAGE_PERMISSION: &str = "status:manage";
+/// The report is rebuilt once a minute
+const MAX_AGE_SECONDS: u32 = 30;
+const MAX_TITLE_LEN: usize = 200;
+
+#[derive(Deserialize)]
+pub struct CreateIncidentRequest {
+    pub title: String,
+    pub impact: String,
+    #[serde(default)]
+    pub components: Vec<String>,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct IncidentUpdateRequest {
+    pub status: String,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveIncidentRequest {
+    pub message: Option<String>,
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "success": false,
+        "message": message
+    }))
+}
+
+fn incident_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "success": false,
+        "message": "Unknown incident"
+    }))
+}
+
+fn database_error(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+/// Server-rendered status page
+pub async fn page(monitor: web::Data<StatusMonitor>) -> HttpResponse {
+    let report = monitor.report().await;
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", MAX_AGE_SECONDS)))
+        .body(he_status::render_page(&report))
+}
+
+/// Machine-readable status
+pub async fn json(monitor: web::Data<StatusMonitor>) -> HttpResponse {
+    let report = monitor.report().await;
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, format!("public, max-age={}", MAX_AGE_SECONDS)))
+        .json(&*report)
+}
+
+/// Open and recently resolved incidents
+pub async fn list_incidents(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    monitor: web::Data<StatusMonitor>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if let Err(response) = require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        return response;
+    }
+
+    match monitor.store().incidents(chrono::Utc::now()).await {
+        Ok(incidents) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "incidents": incidents
+        })),
+        Err(e) => database_error("load incidents", e),
+    }
+}
+
+pub async fn create_incident(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    monitor: web::Data<StatusMonitor>,
+    req: HttpRequest,
+    body: web::Json<CreateIncidentRequest>,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let title = body.title.trim();
+    if title.is_empty() || title.len() > MAX_TITLE_LEN {
+        return bad_request("Title must be 1-200 characters");
+    }
+    if body.message.trim().is_empty() {
+        return bad_request("The first update needs a message");
+    }
+    let Some(impact) = IncidentImpact::parse(&body.impact) else {
+        return bad_request("Impact must be minor, major or critical");
+    };
+    if let Some(unknown) = body.components.iter().find(|c| !COMPONENTS.iter().any(|(key, _)| key == c)) {
+        return bad_request(&format!("Unknown component '{}'", unknown));
+    }
+
+    let id = match monitor
+        .store()
+        .create_incident(title, impact, &body.components, body.message.trim(), admin_id)
+        .await
+    {
+        Ok(id) => id,
+        Err(e) => return database_error("create incident", e),
+    };
+
+    audit.log_event(SecurityEvent::StatusIncidentChanged {
+        admin_id,
+        incident_id: id,
+        action: "create".to_string(),
+    }).await;
+
+    incident_changed(&monitor, id).await
+}
+
+pub async fn post_update(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    monitor: web::Data<StatusMonitor>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<IncidentUpdateRequest>,
+) -> HttpResponse {
+    let Some(status) = IncidentStatus::parse(&body.status) else {
+        return bad_request("Status must be investigating, identified, monitoring or resolved");
+    };
+    let body = body.into_inner();
+    update(state, auth, audit, monitor, req, path.into_inner(), status, body.message).await
+}
+
+pub async fn resolve_incident(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    monitor: web::Data<StatusMonitor>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: Option<web::Json<ResolveIncidentRequest>>,
+) -> HttpResponse {
+    let message = body
+        .and_then(|body| body.into_inner().message)
+        .unwrap_or_else(|| "This incident has been resolved.".to_string());
+    update(state, auth, audit, monitor, req, path.into_inner(), IncidentStatus::Resolved, message).await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn update(
+    state: web::Data<AppState>,
+    auth: web::Data<AuthService>,
+    audit: web::Data<AuditLogger>,
+    monitor: web::Data<StatusMonitor>,
+    req: HttpRequest,
+    id: i64,
+    status: IncidentStatus,
+    message: String,
+) -> HttpResponse {
+    let admin_id = match require_permission(&state, &auth, &req, MANAGE_PERMISSION).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    if message.trim().is_empty() {
+        return bad_request("Updates need a message");
+    }
+
+    match monitor.store().update_incident(id, status, message.trim(), admin_id).await {
+        Ok(true) => {}
+        Ok(false) => return incident_not_found(),
+        Err(e) => return database_error("update incident", e),
+    }
+
+    let action = if status == IncidentStatus::Resolved { "resolve" } else { "update" };
+    audit.log_event(SecurityEvent::StatusIncidentChanged {
+        admin_id,
+        incident_id: id,
+        action: action.to_string(),
+    }).await;
+
+    incident_changed(&monitor, id).await
+}
+
+/// Refresh the public report and return the incident as it now stands
+async fn incident_changed(monitor: &StatusMonitor, id: i64) -> HttpResponse {
+    if let Err(e) = monitor.refresh().await {
+        tracing::warn!("Failed to refresh status report: {}", e);
+    }
+
+    match monitor.store().incident(id).await {
+        Ok(Some(incident)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "incident": incident
+        })),
+        Ok(None) => incident_not_found(),
+        Err(e) => database_error("load incident", e),
+    }
+}
@@ -0,0 +1,224 @@
+//! Terminal handlers
+//!
+//! Running command lines, with help in the caller's locale, the caller's
+//! aliases, and completion data for tab completion: command and alias names,
+//! addresses from the hacked database, and file names on the server the
+//! caller is connected to.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_game_mechanics::terminal::TerminalDenied;
+use serde::Deserialize;
+use crate::plugins::wasm::{PluginHost, WasmPluginError};
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::terminal::{self, Stage, StageStatus};
+
+#[derive(Deserialize)]
+pub struct RunRequest {
+    pub line: String,
+}
+
+#[derive(Deserialize)]
+pub struct AliasRequest {
+    pub expansion: String,
+}
+
+#[derive(Deserialize)]
+pub struct CompletionQuery {
+    #[serde(default)]
+    pub prefix: String,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn denied(denied: TerminalDenied) -> HttpResponse {
+    let mut response = match denied {
+        TerminalDenied::UnknownCommand { .. } | TerminalDenied::AliasNotFound => HttpResponse::NotFound(),
+        TerminalDenied::CoolingDown { .. } => HttpResponse::TooManyRequests(),
+        TerminalDenied::TooManyAliases { .. } | TerminalDenied::AliasShadowsCommand { .. } => HttpResponse::Conflict(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Run a command line. Chains answer with every stage's outcome; the status
+/// is that of the stage that failed, if one did.
+pub async fn run(
+    state: web::Data<AppState>,
+    host: web::Data<PluginHost>,
+    req: HttpRequest,
+    body: web::Json<RunRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    let stages = match terminal::run(&state.db.pool, &host, user_id, &body.line, &terminal::locale()).await {
+        Ok(Ok(stages)) => stages,
+        Ok(Err(d)) => return denied(d),
+        Err(e) => return failed("run command", e),
+    };
+
+    let failure = stages.iter().find(|stage| stage.status == StageStatus::Failed);
+    let mut response = match failure {
+        None => HttpResponse::Ok(),
+        Some(Stage { denied: Some(TerminalDenied::UnknownCommand { .. }), .. }) => HttpResponse::NotFound(),
+        Some(Stage { denied: Some(TerminalDenied::CoolingDown { .. }), .. }) => HttpResponse::TooManyRequests(),
+        Some(Stage { error: Some(WasmPluginError::OutOfFuel | WasmPluginError::Timeout), .. }) => {
+            HttpResponse::ServiceUnavailable()
+        }
+        Some(Stage { error: Some(WasmPluginError::Trap(_) | WasmPluginError::BadOutput), .. }) => HttpResponse::BadGateway(),
+        Some(_) => HttpResponse::BadRequest(),
+    };
+    let message = failure.map(|stage| match (&stage.denied, &stage.error) {
+        (Some(d), _) => d.message(),
+        (_, Some(e)) => e.to_string(),
+        _ => String::new(),
+    });
+    let output: Vec<&str> = stages.iter().filter_map(|stage| stage.output.as_deref()).collect();
+    response.json(serde_json::json!({
+        "success": failure.is_none(),
+        "message": message,
+        "output": output.join("\n"),
+        "stages": stages
+    }))
+}
+
+/// The caller's aliases, with their alias slots and chain length
+pub async fn aliases(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match terminal::aliases(&state.db.pool, user_id).await {
+        Ok(aliases) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "aliases": aliases.aliases,
+            "alias_slots": aliases.alias_slots,
+            "max_chain_len": aliases.max_chain_len
+        })),
+        Err(e) => failed("load aliases", e),
+    }
+}
+
+/// Create or replace an alias
+pub async fn save_alias(
+    state: web::Data<AppState>,
+    host: web::Data<PluginHost>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<AliasRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match terminal::save_alias(&state.db.pool, &host, user_id, &path, &body.expansion).await {
+        Ok(Ok(alias)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "alias": alias
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("save alias", e),
+    }
+}
+
+pub async fn delete_alias(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match terminal::delete_alias(&state.db.pool, user_id, &path).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({ "success": true })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("delete alias", e),
+    }
+}
+
+/// Command and alias names starting with `prefix`
+pub async fn complete_commands(
+    state: web::Data<AppState>,
+    host: web::Data<PluginHost>,
+    req: HttpRequest,
+    query: web::Query<CompletionQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match terminal::complete_commands(&state.db.pool, &host, user_id, &query.prefix).await {
+        Ok(completions) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "completions": completions
+        })),
+        Err(e) => failed("complete commands", e),
+    }
+}
+
+/// Addresses from the caller's hacked database starting with `prefix`
+pub async fn complete_ips(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<CompletionQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match terminal::complete_ips(&state.db.pool, user_id, &query.prefix).await {
+        Ok(completions) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "completions": completions
+        })),
+        Err(e) => failed("complete addresses", e),
+    }
+}
+
+/// File names on the connected server starting with `prefix`; empty when
+/// the caller is not connected anywhere
+pub async fn complete_files(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<CompletionQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match terminal::complete_files(&state.db.pool, user_id, &query.prefix).await {
+        Ok(Some((server_ip, completions))) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "server_ip": server_ip,
+            "completions": completions
+        })),
+        Ok(None) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "server_ip": null,
+            "completions": []
+        })),
+        Err(e) => failed("complete files", e),
+    }
+}
@@ -0,0 +1,85 @@
+//! Tutorial handlers
+//!
+//! Steps cannot be completed from here; they advance when the matching
+//! process completes on the server.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_database::queries::TutorialQueries;
+use he_game_mechanics::tutorial::TutorialStep;
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::tutorial::{self, TutorialState};
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {} tutorial: {}", action, e)
+    }))
+}
+
+/// The player's current step and its hint
+pub async fn get_tutorial(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match TutorialQueries::get(&state.db.pool, user_id).await {
+        Ok(Some(row)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "tutorial": TutorialState::from_row(&row)
+        })),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "success": false,
+            "message": "Tutorial not started"
+        })),
+        Err(e) => failed("load", e),
+    }
+}
+
+/// Leave the tutorial without the reward
+pub async fn skip_tutorial(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match TutorialQueries::skip(&state.db.pool, user_id).await {
+        Ok(Some(row)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "Tutorial skipped",
+            "tutorial": TutorialState::from_row(&row)
+        })),
+        Ok(None) => HttpResponse::Conflict().json(serde_json::json!({
+            "success": false,
+            "message": "No tutorial in progress"
+        })),
+        Err(e) => failed("skip", e),
+    }
+}
+
+/// Start over from the first step. A replay does not pay the reward again.
+pub async fn reset_tutorial(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match TutorialQueries::reset(&state.db.pool, user_id).await {
+        Ok(row) => {
+            if let Some(ws_manager) = &state.ws_manager {
+                tutorial::push_hint(ws_manager, user_id, TutorialStep::FIRST);
+            }
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "message": "Tutorial restarted",
+                "tutorial": TutorialState::from_row(&row)
+            }))
+        }
+        Err(e) => failed("reset", e),
+    }
+}
@@ -0,0 +1,208 @@
+//! Clan war spectating
+//!
+//! Anyone can watch a war without signing in or joining a clan. The socket
+//! first replays the latest events, then streams new ones as they become
+//! visible; spectators never see real player names or events younger than
+//! the spectator delay. Each IP may hold a few sockets at once, and the
+//! routes are rate limited on top of that.
+//!
+//! Server to client messages are JSON objects tagged by `type`: `backlog`,
+//! `event`, `ended`. Anything the client sends besides pings is ignored.
+
+use actix::{Actor, ActorContext, ActorFutureExt, AsyncContext, StreamHandler, WrapFuture};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_actors::ws;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use crate::war_spectator::{SpectatorEvent, ViewerSlot, WarSpectator};
+
+/// Events replayed when a spectator connects
+const BACKLOG_SIZE: i64 = 50;
+const DEFAULT_LOG_LIMIT: i64 = 100;
+const MAX_LOG_LIMIT: i64 = 500;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+pub struct BattleLogQuery {
+    /// Only events with a greater id
+    pub after: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SpectatorMessage<'a> {
+    Backlog { events: &'a [SpectatorEvent] },
+    Event { event: &'a SpectatorEvent },
+    Ended { war_id: i64 },
+}
+
+/// Battle log of a war, oldest first. Page with `after` set to the last id.
+pub async fn battle_log(
+    spectator: web::Data<WarSpectator>,
+    path: web::Path<i64>,
+    query: web::Query<BattleLogQuery>,
+) -> HttpResponse {
+    let war_id = path.into_inner();
+    let after = query.after.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_LOG_LIMIT).clamp(1, MAX_LOG_LIMIT);
+
+    match spectator.battle_log(war_id, after, limit).await {
+        Ok(events) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "war_id": war_id,
+            "delay_seconds": spectator.delay().as_secs(),
+            "next_after": events.last().map(|event| event.id),
+            "events": events
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("Failed to load battle log: {}", e)
+        })),
+    }
+}
+
+/// Live spectator socket for a war
+pub async fn spectate(
+    spectator: web::Data<WarSpectator>,
+    path: web::Path<i64>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let Some(ip) = req.peer_addr().map(|addr| addr.ip()) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "message": "Unknown client address"
+        })));
+    };
+
+    let spectator = spectator.into_inner();
+    let Some(slot) = spectator.claim_viewer(ip) else {
+        return Ok(HttpResponse::TooManyRequests().json(serde_json::json!({
+            "success": false,
+            "message": "Too many spectator connections from this address"
+        })));
+    };
+
+    let session = SpectatorSession {
+        war_id: path.into_inner(),
+        spectator,
+        last_id: 0,
+        hb: Instant::now(),
+        _slot: slot,
+    };
+
+    ws::start(session, &req, stream)
+}
+
+struct SpectatorSession {
+    war_id: i64,
+    spectator: Arc<WarSpectator>,
+    /// Newest event sent, so the live stream does not repeat the backlog
+    last_id: i64,
+    hb: Instant,
+    _slot: ViewerSlot,
+}
+
+impl Actor for SpectatorSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+
+        let spectator = self.spectator.clone();
+        let war_id = self.war_id;
+
+        let fut = async move { spectator.recent(war_id, BACKLOG_SIZE).await };
+        ctx.spawn(fut.into_actor(self).map(|result, act, ctx| {
+            let events = result.unwrap_or_else(|e| {
+                tracing::error!("Failed to load backlog of war {}: {}", act.war_id, e);
+                Vec::new()
+            });
+
+            act.send(ctx, &SpectatorMessage::Backlog { events: &events });
+            if let Some(last) = events.last() {
+                act.last_id = last.id;
+                if last.ends_war() {
+                    act.finish(ctx);
+                    return;
+                }
+            }
+            act.start_stream(ctx);
+        }));
+    }
+}
+
+impl SpectatorSession {
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, message: &SpectatorMessage) {
+        match serde_json::to_string(message) {
+            Ok(text) => ctx.text(text),
+            Err(e) => tracing::error!("Failed to serialise spectator message: {}", e),
+        }
+    }
+
+    fn start_stream(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let events = self.spectator.subscribe(self.war_id, self.last_id);
+        ctx.add_stream(futures_util::stream::unfold(events, |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((event, events)),
+                    // A slow spectator misses events rather than holding up the rest
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }));
+    }
+
+    fn finish(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        self.send(ctx, &SpectatorMessage::Ended { war_id: self.war_id });
+        ctx.close(Some(ws::CloseCode::Normal.into()));
+        ctx.stop();
+    }
+}
+
+impl StreamHandler<Arc<SpectatorEvent>> for SpectatorSession {
+    fn handle(&mut self, event: Arc<SpectatorEvent>, ctx: &mut Self::Context) {
+        if event.id <= self.last_id {
+            return;
+        }
+        self.last_id = event.id;
+        self.send(ctx, &SpectatorMessage::Event { event: &event });
+    }
+
+    /// The poller stops once the war has ended
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        self.finish(ctx);
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpectatorSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                self.hb = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => {
+                self.hb = Instant::now();
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Err(_) => ctx.stop(),
+            _ => {}
+        }
+    }
+}
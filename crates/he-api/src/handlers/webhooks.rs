@@ -0,0 +1,152 @@
+//! Webhook handlers
+//!
+//! `owner` is `clan` for the caller's clan, which only its leader and
+//! officers manage, or `player` for the caller.
+
+use actix_web::{web, HttpResponse, HttpRequest};
+use he_game_mechanics::webhooks::WebhookDenied;
+use serde::Deserialize;
+use crate::state::AppState;
+use crate::handlers::game::extract_user_id;
+use crate::webhooks;
+
+#[derive(Deserialize)]
+pub struct RegisterRequest {
+    pub owner: String,
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct OwnerQuery {
+    pub owner: String,
+}
+
+#[derive(Deserialize)]
+pub struct EnabledRequest {
+    pub enabled: bool,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized().json(serde_json::json!({
+        "success": false,
+        "message": "Unauthorized"
+    }))
+}
+
+fn failed(action: &str, e: anyhow::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false,
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+fn denied(denied: WebhookDenied) -> HttpResponse {
+    let mut response = match denied {
+        WebhookDenied::NotFound => HttpResponse::NotFound(),
+        WebhookDenied::NoClan | WebhookDenied::NotOfficer => HttpResponse::Forbidden(),
+        WebhookDenied::TooManyWebhooks { .. } => HttpResponse::Conflict(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(serde_json::json!({
+        "success": false,
+        "message": denied.message(),
+        "denied": denied
+    }))
+}
+
+/// Register a webhook. Its signing secret is only ever shown here.
+pub async fn register(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    data: web::Json<RegisterRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match webhooks::register(&state.db.pool, user_id, &data.owner, &data.url, &data.events).await {
+        Ok(Ok(registered)) => HttpResponse::Created().json(serde_json::json!({
+            "success": true,
+            "webhook": registered
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("register webhook", e),
+    }
+}
+
+pub async fn list(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<OwnerQuery>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match webhooks::list(&state.db.pool, user_id, &query.owner).await {
+        Ok(Ok(webhooks)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "webhooks": webhooks
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("load webhooks", e),
+    }
+}
+
+pub async fn remove(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match webhooks::remove(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(serde_json::json!({ "success": true })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("remove webhook", e),
+    }
+}
+
+/// Turn a webhook off, or back on after it was disabled for failing
+pub async fn set_enabled(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+    data: web::Json<EnabledRequest>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match webhooks::set_enabled(&state.db.pool, user_id, path.into_inner(), data.enabled).await {
+        Ok(Ok(webhook)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "webhook": webhook
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("update webhook", e),
+    }
+}
+
+/// The webhook's latest deliveries, newest first
+pub async fn deliveries(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<i64>,
+) -> HttpResponse {
+    let Some(user_id) = extract_user_id(&state, &req).await else {
+        return unauthorized();
+    };
+
+    match webhooks::deliveries(&state.db.pool, user_id, path.into_inner()).await {
+        Ok(Ok(deliveries)) => HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "deliveries": deliveries
+        })),
+        Ok(Err(d)) => denied(d),
+        Err(e) => failed("load webhook deliveries", e),
+    }
+}
@@ -0,0 +1,283 @@
+//! Hardware wear and repairs
+//!
+//! Processes started on the player's own hardware note what they use. When
+//! one completes, the components it kept under heavy load wear (see
+//! [`he_game_mechanics::wear`]) and may fail, inside the completion
+//! transaction. A failed component slows every process started while it is
+//! down. The player is told through the outbox when a component turns worn,
+//! critical or failed, and when a repair restores it. Repairs are `repair`
+//! processes, paid for when they start like IP resets.
+
+use he_database::models::{Hardware, Process};
+use he_database::queries::{HardwareComponentRow, ProcessLoad, ProcessQueries, WearQueries};
+use he_game_mechanics::process::ProcessType;
+use he_game_mechanics::wear::{self, Component, ComponentState, Condition, Quality, RepairDenied, RepairQuote};
+use he_game_mechanics::{HardwareSpecs, ResourceUsage};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use crate::outbox::OutboxMessage;
+
+/// A component whose condition the player is told about
+pub(crate) struct HardwareChange {
+    pub component: Component,
+    pub condition: Condition,
+    pub quality: Quality,
+    pub wear: f64,
+}
+
+/// A component as its owner sees it
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentReport {
+    pub component: Component,
+    pub quality: Quality,
+    pub wear: f64,
+    pub condition: Condition,
+    pub failed_at: Option<DateTime<Utc>>,
+    /// Chance it fails the next time a process loads it heavily
+    pub failure_chance: f64,
+    /// Restoring it as it is, when there is anything to restore
+    pub repair: Option<RepairQuote>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RepairStarted {
+    pub pid: i64,
+    pub end_time: DateTime<Utc>,
+    pub quote: RepairQuote,
+}
+
+fn state(row: &HardwareComponentRow) -> Option<ComponentState> {
+    Some(ComponentState {
+        component: Component::from_str(&row.component)?,
+        quality: Quality::from_str(&row.quality).unwrap_or(Quality::Standard),
+        wear: row.wear,
+        failed: row.failed_at.is_some(),
+    })
+}
+
+/// Share of `component`'s capacity that `load` uses
+fn share(component: Component, load: &ProcessLoad, hardware: &Hardware) -> f64 {
+    let (used, capacity) = match component {
+        Component::Cpu => (load.cpu_percent as f64, 100.0),
+        Component::Ram => (load.ram_mb as f64, hardware.ram_mb as f64),
+        Component::Hdd => (load.hdd_mb as f64, hardware.hdd_mb as f64),
+        Component::Net => (load.net_kbps as f64, hardware.net_mbps as f64 * 1000.0),
+    };
+    if capacity <= 0.0 {
+        return 0.0;
+    }
+    used.max(0.0) / capacity
+}
+
+/// `specs` of the player's own hardware, with failed components degraded
+pub async fn effective_specs(pool: &PgPool, hardware_id: i64, specs: HardwareSpecs) -> anyhow::Result<HardwareSpecs> {
+    let failed: Vec<Component> = WearQueries::components(pool, hardware_id)
+        .await?
+        .iter()
+        .filter(|row| row.failed_at.is_some())
+        .filter_map(|row| Component::from_str(&row.component))
+        .collect();
+    Ok(wear::degrade(&specs, &failed, &crate::balance::current().hardware_wear))
+}
+
+/// Note what process `pid` uses of the player's own hardware
+pub async fn record_load(pool: &PgPool, pid: i64, hardware_id: i64, usage: &ResourceUsage) -> anyhow::Result<()> {
+    let load = ProcessLoad {
+        cpu_percent: usage.cpu_usage,
+        ram_mb: usage.ram_usage,
+        hdd_mb: usage.hdd_usage,
+        net_kbps: usage.net_usage,
+    };
+    WearQueries::record_load(pool, pid, hardware_id, &load).await
+}
+
+/// Wear the hardware a completing process ran on, or finish the repair it
+/// was, inside the completion transaction
+pub(crate) async fn apply(conn: &mut PgConnection, process: &Process) -> anyhow::Result<Vec<HardwareChange>> {
+    if ProcessType::from_str(&process.process_type) == ProcessType::Repair {
+        let Some((_, component, quality)) = WearQueries::finish_repair(conn, process.pid).await? else {
+            return Ok(Vec::new());
+        };
+        let Some(component) = Component::from_str(&component) else {
+            return Ok(Vec::new());
+        };
+        let quality = Quality::from_str(&quality).unwrap_or(Quality::Standard);
+        return Ok(vec![HardwareChange { component, condition: Condition::Good, quality, wear: 0.0 }]);
+    }
+
+    let Some((hardware_id, load)) = WearQueries::take_load(conn, process.pid).await? else {
+        return Ok(Vec::new());
+    };
+    let Some(hardware) = WearQueries::hardware(conn, hardware_id).await? else {
+        return Ok(Vec::new());
+    };
+    let others = WearQueries::running_load(conn, hardware_id).await?;
+    let total = ProcessLoad {
+        cpu_percent: load.cpu_percent + others.cpu_percent,
+        ram_mb: load.ram_mb + others.ram_mb,
+        hdd_mb: load.hdd_mb + others.hdd_mb,
+        net_kbps: load.net_kbps + others.net_kbps,
+    };
+    let hours = (process.end_time - process.start_time).num_seconds() as f64 / 3600.0;
+
+    let config = &crate::balance::current().hardware_wear;
+    let mut changes = Vec::new();
+    for row in WearQueries::lock_components(conn, hardware_id).await? {
+        let Some(state) = state(&row) else {
+            continue;
+        };
+        let own = share(state.component, &load, &hardware);
+        let gain = wear::wear_gain(own, share(state.component, &total, &hardware), hours, state.quality, config);
+        if gain <= 0.0 {
+            continue;
+        }
+
+        let worn = (state.wear + gain).min(1.0);
+        let failed = state.failed || wear::fails(worn, rand::thread_rng().gen(), config);
+        let warning = wear::warning(wear::condition(worn, failed, config), row.warned_level);
+        let warned_level = warning.map_or(row.warned_level, |condition| condition.level());
+        WearQueries::update_component(conn, hardware_id, state.component.as_str(), worn, failed, warned_level).await?;
+
+        if let Some(condition) = warning {
+            changes.push(HardwareChange { component: state.component, condition, quality: state.quality, wear: worn });
+        }
+    }
+    Ok(changes)
+}
+
+pub(crate) fn outbox_messages(pid: i64, user_id: i64, changes: &[HardwareChange]) -> Vec<OutboxMessage> {
+    changes
+        .iter()
+        .map(|change| {
+            let event = he_websocket::EventBuilder::hardware_condition(
+                change.component.as_str().to_string(),
+                change.condition.as_str().to_string(),
+                change.quality.as_str().to_string(),
+                change.wear,
+            );
+            let dedup_key = format!("process:{}:hardware:{}", pid, change.component.as_str());
+            OutboxMessage::to_user(dedup_key, user_id, &event)
+        })
+        .collect()
+}
+
+/// Condition of every component of the player's own hardware
+pub async fn report(pool: &PgPool, hardware_id: i64) -> anyhow::Result<Vec<ComponentReport>> {
+    let config = &crate::balance::current().hardware_wear;
+    let rows = WearQueries::components(pool, hardware_id).await?;
+
+    Ok(Component::ALL
+        .into_iter()
+        .map(|component| {
+            let row = rows.iter().find(|row| row.component == component.as_str());
+            let state = row.and_then(state).unwrap_or(ComponentState {
+                component,
+                quality: Quality::Standard,
+                wear: 0.0,
+                failed: false,
+            });
+            let repair = (state.failed || state.wear >= config.min_repair_wear).then(|| wear::quote(&state, None, config));
+            ComponentReport {
+                component,
+                quality: state.quality,
+                wear: state.wear,
+                condition: wear::condition(state.wear, state.failed, config),
+                failed_at: row.and_then(|row| row.failed_at),
+                failure_chance: wear::failure_chance(state.wear, config),
+                repair,
+            }
+        })
+        .collect())
+}
+
+/// Start a paid repair of `component`, fitting `fit` if given. The caller
+/// schedules the returned process for completion.
+pub async fn start_repair(
+    pool: &PgPool,
+    user_id: i64,
+    hardware_id: i64,
+    component: &str,
+    fit: Option<&str>,
+) -> anyhow::Result<Result<RepairStarted, RepairDenied>> {
+    let Some(component) = Component::from_str(component) else {
+        return Ok(Err(RepairDenied::UnknownComponent));
+    };
+    let fit = match fit {
+        None => None,
+        Some(fit) => match Quality::from_str(fit) {
+            Some(fit) => Some(fit),
+            None => return Ok(Err(RepairDenied::UnknownQuality)),
+        },
+    };
+
+    let config = &crate::balance::current().hardware_wear;
+    let checked = WearQueries::repair_state(pool, user_id, hardware_id, component.as_str()).await?;
+    let state = state(&checked.component).unwrap_or(ComponentState {
+        component,
+        quality: Quality::Standard,
+        wear: 0.0,
+        failed: false,
+    });
+    let quote = match wear::check_repair(&state, fit, checked.repairing, checked.balance, config) {
+        Ok(quote) => quote,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let pc_id = format!("pc_{}", user_id);
+    let duration = quote.duration_secs.clamp(1, i32::MAX as i64) as i32;
+    let process = ProcessQueries::create_process_with_duration(pool, user_id, "repair", &pc_id, None, duration).await?;
+
+    // Paid once the process exists, so the charge can name it
+    let charged = WearQueries::charge_repair(
+        pool,
+        user_id,
+        process.pid,
+        hardware_id,
+        component.as_str(),
+        quote.quality.as_str(),
+        quote.cost,
+    )
+    .await;
+    if !matches!(charged, Ok(true)) {
+        if let Err(e) = ProcessQueries::cancel_process(pool, process.pid, user_id).await {
+            tracing::error!("Failed to cancel unpaid repair {}: {}", process.pid, e);
+        }
+    }
+    if !charged? {
+        // Lost a race with another repair or a payment; say which
+        let checked = WearQueries::repair_state(pool, user_id, hardware_id, component.as_str()).await?;
+        let denied = wear::check_repair(&state, fit, checked.repairing, checked.balance, config)
+            .err()
+            .unwrap_or(RepairDenied::InProgress);
+        return Ok(Err(denied));
+    }
+
+    Ok(Ok(RepairStarted { pid: process.pid, end_time: process.end_time, quote }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_shares() {
+        let hardware = Hardware {
+            hardware_id: 1,
+            user_id: 1,
+            cpu_mhz: 2000,
+            ram_mb: 1024,
+            hdd_mb: 10240,
+            net_mbps: 10,
+            gpu_cores: 1,
+            total_slots: 5,
+            used_slots: 1,
+        };
+        let load = ProcessLoad { cpu_percent: 80, ram_mb: 512, hdd_mb: 0, net_kbps: 5000 };
+        assert!((share(Component::Cpu, &load, &hardware) - 0.8).abs() < 1e-9);
+        assert!((share(Component::Ram, &load, &hardware) - 0.5).abs() < 1e-9);
+        assert_eq!(share(Component::Hdd, &load, &hardware), 0.0);
+        assert!((share(Component::Net, &load, &hardware) - 0.5).abs() < 1e-9);
+    }
+}
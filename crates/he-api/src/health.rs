@@ -0,0 +1,97 @@
+//! The API node's health probes
+//!
+//! One [`HealthRegistry`] backs `/health/detailed`, the `/ready` probe and the
+//! status page, so they always agree. The database is the only critical
+//! component: without the cache reads fall back to the database, and the
+//! forum sync and WebSocket failing leave the game playable.
+//!
+//! Probe messages are public through `/health/detailed` and the status page,
+//! so they never carry the underlying error; that goes to the log.
+
+use he_database::RedisCache;
+use he_monitoring::{Criticality, HealthRegistry, ProbeResult};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::live_ops::LiveOps;
+
+/// A probe slower than this reports its component down
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// A database answering slower than this is reported degraded
+const SLOW_DATABASE: Duration = Duration::from_millis(500);
+/// Liveness endpoint the API probe requests through the whole HTTP stack
+const DEFAULT_API_PROBE_URL: &str = "http://127.0.0.1:3005/live";
+
+pub fn registry(pool: PgPool, cache: Option<Arc<RedisCache>>, live_ops: Arc<LiveOps>) -> HealthRegistry {
+    let health = HealthRegistry::new(PROBE_TIMEOUT);
+
+    let api_probe_url = std::env::var("API_PROBE_URL").unwrap_or_else(|_| DEFAULT_API_PROBE_URL.to_string());
+    let client = reqwest::Client::builder().timeout(PROBE_TIMEOUT).build().ok();
+    health.register("api", Criticality::Critical, &[], move || {
+        let client = client.clone();
+        let url = api_probe_url.clone();
+        async move {
+            let Some(client) = client else {
+                return ProbeResult::down("API probe unavailable");
+            };
+            match client.get(&url).send().await {
+                Ok(response) if response.status().is_success() => ProbeResult::up("API server is answering requests"),
+                Ok(response) => {
+                    tracing::warn!("API probe got {} from {}", response.status(), url);
+                    ProbeResult::down("API server is answering with errors")
+                }
+                Err(e) => {
+                    tracing::warn!("API probe request to {} failed: {}", url, e);
+                    ProbeResult::down("API server is not answering requests")
+                }
+            }
+        }
+    });
+
+    health.register("database", Criticality::Critical, &[], move || {
+        let pool = pool.clone();
+        async move {
+            let started = std::time::Instant::now();
+            match sqlx::query("SELECT 1").execute(&pool).await {
+                Ok(_) if started.elapsed() > SLOW_DATABASE => ProbeResult::degraded("Database is responding slowly"),
+                Ok(_) => ProbeResult::up("Database connection OK"),
+                Err(e) => {
+                    tracing::warn!("Database health probe failed: {}", e);
+                    ProbeResult::down("Database is not responding")
+                }
+            }
+        }
+    });
+
+    health.register("cache", Criticality::Optional, &[], move || {
+        let cache = cache.clone();
+        async move {
+            let Some(cache) = cache else {
+                return ProbeResult::up("Cache not configured, reads go to the database");
+            };
+            match cache.exists("status:probe").await {
+                Ok(_) => ProbeResult::up("Cache layer operational"),
+                Err(e) => {
+                    tracing::warn!("Cache health probe failed: {}", e);
+                    ProbeResult::down("Cache is unreachable")
+                }
+            }
+        }
+    });
+
+    health.register("websocket", Criticality::Optional, &["api"], move || {
+        let draining = live_ops.is_draining();
+        async move {
+            // Sessions move to other nodes; the service itself is not out
+            if draining {
+                ProbeResult::degraded("Node is draining, new sessions go to other nodes")
+            } else {
+                ProbeResult::up("Accepting connections")
+            }
+        }
+    });
+
+    health.register_check("forum_sync", Criticality::Optional, &["database"], crate::forum_sync::health);
+
+    health
+}
@@ -0,0 +1,478 @@
+//! Bank heists
+//!
+//! he-cron's `bank_heists` job opens timed heist windows on high-tier NPC
+//! banks. A player forms a crew against an open heist and others join it,
+//! each taking one role; the leader starts the raid once every role is held.
+//! From then on the raid instance is a state machine
+//! ([`he_game_mechanics::heist`]): each stage waits for its role to act, and
+//! the firewall coming back up or the trace landing moves it on its own,
+//! which is applied whenever the raid is next touched. The crew that gets
+//! away splits its loot by role and by who acted.
+//!
+//! State changes happen in the request's transaction with the raid row
+//! locked. The events they produce are published through he-events and
+//! pushed to the crew's sockets by the coordinator.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use he_core::{HelixError, HelixResult};
+use he_database::queries::{BankQueries, HeistMemberRow, HeistQueries, HeistRaidRow, HeistRow, LedgerAccount, LedgerReason};
+use he_events::{DispatchConfig, Event, EventData, EventDispatcher, EventHandler, EventSchema, EventType};
+use he_game_mechanics::config::HeistConfig;
+use he_game_mechanics::heist::{self, CrewMember, HeistDenied, HeistRole, HeistShare, HeistStage, RaidState, Settled};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use crate::event_schemas;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+/// `data_type` of the he-events payloads published here
+const DATA_TYPE: &str = "bank_heist";
+
+static DISPATCHER: OnceCell<EventDispatcher> = OnceCell::const_new();
+
+/// Something that happened to a raid
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeistEvent {
+    pub heist_id: i64,
+    pub raid_id: i64,
+    /// Who is told
+    pub crew: Vec<i64>,
+    #[serde(flatten)]
+    pub kind: HeistEventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HeistEventKind {
+    Formed { leader_id: i64, role: HeistRole, bank: String },
+    Joined { user_id: i64, role: HeistRole },
+    Left { user_id: i64 },
+    Started,
+    Acted { user_id: i64, role: HeistRole, stage: HeistStage },
+    FirewallRestored,
+    Escaped { loot: i64, shares: Vec<HeistShare> },
+    Failed { reason: String },
+}
+
+impl HeistEventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            HeistEventKind::Formed { .. } => "bank_heist_formed",
+            HeistEventKind::Joined { .. } => "bank_heist_joined",
+            HeistEventKind::Left { .. } => "bank_heist_left",
+            HeistEventKind::Started => "bank_heist_started",
+            HeistEventKind::Acted { .. } => "bank_heist_acted",
+            HeistEventKind::FirewallRestored => "bank_heist_firewall_restored",
+            HeistEventKind::Escaped { .. } => "bank_heist_escaped",
+            HeistEventKind::Failed { .. } => "bank_heist_failed",
+        }
+    }
+
+    /// Every event type the coordinator listens to
+    pub(crate) fn event_types() -> Vec<EventType> {
+        [
+            "bank_heist_formed",
+            "bank_heist_joined",
+            "bank_heist_left",
+            "bank_heist_started",
+            "bank_heist_acted",
+            "bank_heist_firewall_restored",
+            "bank_heist_escaped",
+            "bank_heist_failed",
+        ]
+        .into_iter()
+        .map(|name| EventType::Custom(name.to_string()))
+        .collect()
+    }
+}
+
+impl EventSchema for HeistEvent {
+    const VERSION: u32 = 1;
+
+    fn fixtures() -> Vec<(u32, serde_json::Value)> {
+        vec![(1, serde_json::json!({
+            "heist_id": 2,
+            "raid_id": 9,
+            "crew": [42, 43, 44, 45],
+            "event": "acted",
+            "user_id": 42,
+            "role": "ddos",
+            "stage": "vault"
+        }))]
+    }
+}
+
+impl HeistEvent {
+    fn new(raid: &HeistRaidRow, crew: &[HeistMemberRow], kind: HeistEventKind) -> Self {
+        Self {
+            heist_id: raid.heist_id,
+            raid_id: raid.id,
+            crew: crew.iter().map(|m| m.user_id).collect(),
+            kind,
+        }
+    }
+
+    fn to_event(&self) -> Event {
+        Event::new(
+            EventType::Custom(self.kind.name().to_string()),
+            EventData::Custom {
+                data_type: DATA_TYPE.to_string(),
+                payload: serde_json::to_value(self).unwrap_or_default(),
+            },
+        )
+    }
+}
+
+type HeistResult<T> = anyhow::Result<Result<T, HeistDenied>>;
+
+fn raid_state(raid: &HeistRaidRow) -> RaidState {
+    RaidState {
+        stage: HeistStage::from_str(&raid.stage).unwrap_or(HeistStage::Failed),
+        closes_at: raid.closes_at,
+        firewall_down_until: raid.firewall_down_until,
+        trace_at: raid.trace_at,
+    }
+}
+
+fn roles(crew: &[HeistMemberRow]) -> Vec<HeistRole> {
+    crew.iter().filter_map(|m| HeistRole::from_str(&m.role)).collect()
+}
+
+/// Apply what time did to a locked raid, saving it. Returns the settled
+/// state and the event for the change, if any.
+async fn settle(
+    conn: &mut PgConnection,
+    raid: &HeistRaidRow,
+    crew: &[HeistMemberRow],
+) -> anyhow::Result<(RaidState, Option<HeistEvent>)> {
+    let (state, settled) = heist::settle(&raid_state(raid), Utc::now());
+    let event = match settled {
+        Settled::Unchanged => None,
+        Settled::FirewallRestored => {
+            HeistQueries::save_state(conn, raid.id, state.stage.as_str(), state.firewall_down_until, state.trace_at).await?;
+            Some(HeistEvent::new(raid, crew, HeistEventKind::FirewallRestored))
+        }
+        Settled::Failed(failure) => {
+            HeistQueries::finish(conn, raid.id, state.stage.as_str(), None, Some(failure.reason())).await?;
+            Some(HeistEvent::new(raid, crew, HeistEventKind::Failed { reason: failure.reason().to_string() }))
+        }
+    };
+    Ok((state, event))
+}
+
+/// Heists whose window is open
+pub async fn open_heists(pool: &PgPool) -> anyhow::Result<Vec<HeistRow>> {
+    HeistQueries::open_heists(pool).await
+}
+
+/// Form a crew against an open heist, led by the player in `role`
+pub async fn form(pool: &PgPool, user_id: i64, heist_id: i64, role: HeistRole) -> HeistResult<(i64, Vec<HeistEvent>)> {
+    let now = Utc::now();
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(heist) = HeistQueries::lock_heist(&mut *tx, heist_id).await?.filter(|h| h.opens_at <= now) else {
+        return Ok(Err(HeistDenied::NotFound));
+    };
+    if heist.closes_at <= now {
+        return Ok(Err(HeistDenied::WindowClosed));
+    }
+    if heist.vault <= 0 {
+        return Ok(Err(HeistDenied::VaultEmpty));
+    }
+    if HeistQueries::in_raid(&mut *tx, user_id).await? {
+        return Ok(Err(HeistDenied::AlreadyInRaid));
+    }
+
+    let raid_id = HeistQueries::create_raid(&mut *tx, heist_id, user_id, role.as_str()).await?;
+    tx.commit().await?;
+
+    let event = HeistEvent {
+        heist_id,
+        raid_id,
+        crew: vec![user_id],
+        kind: HeistEventKind::Formed { leader_id: user_id, role, bank: heist.bank_name },
+    };
+    Ok(Ok((raid_id, vec![event])))
+}
+
+/// Join a recruiting crew in `role`
+pub async fn join(pool: &PgPool, user_id: i64, raid_id: i64, role: HeistRole) -> HeistResult<Vec<HeistEvent>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(raid) = HeistQueries::lock_raid(&mut *tx, raid_id).await? else {
+        return Ok(Err(HeistDenied::NotFound));
+    };
+    if raid.stage != HeistStage::Recruiting.as_str() {
+        return Ok(Err(HeistDenied::NotRecruiting));
+    }
+    let mut crew = HeistQueries::crew(&mut *tx, raid_id).await?;
+    // Recruiting crews only ever settle into a closed window
+    let (state, settled) = settle(&mut *tx, &raid, &crew).await?;
+    if settled.is_some() {
+        tx.commit().await?;
+        return Ok(Err(HeistDenied::WindowClosed));
+    }
+    if crew.iter().any(|m| m.user_id == user_id) || HeistQueries::in_raid(&mut *tx, user_id).await? {
+        return Ok(Err(HeistDenied::AlreadyInRaid));
+    }
+    if let Err(denied) = heist::check_join(state.stage, role, &roles(&crew), &crate::balance::current().heist) {
+        return Ok(Err(denied));
+    }
+
+    HeistQueries::add_member(&mut *tx, raid_id, user_id, role.as_str()).await?;
+    tx.commit().await?;
+
+    crew.push(HeistMemberRow { user_id, role: role.as_str().to_string(), actions: 0, share: None });
+    Ok(Ok(vec![HeistEvent::new(&raid, &crew, HeistEventKind::Joined { user_id, role })]))
+}
+
+/// Leave a crew before its raid starts. The leader leaving disbands it.
+pub async fn leave(pool: &PgPool, user_id: i64, raid_id: i64) -> HeistResult<Vec<HeistEvent>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(raid) = HeistQueries::lock_raid(&mut *tx, raid_id).await? else {
+        return Ok(Err(HeistDenied::NotFound));
+    };
+    let crew = HeistQueries::crew(&mut *tx, raid_id).await?;
+    if raid.stage != HeistStage::Recruiting.as_str() {
+        return Ok(Err(HeistDenied::NotRecruiting));
+    }
+    if !HeistQueries::remove_member(&mut *tx, raid_id, user_id).await? {
+        return Ok(Err(HeistDenied::NotMember));
+    }
+
+    let mut events = vec![HeistEvent::new(&raid, &crew, HeistEventKind::Left { user_id })];
+    if raid.leader_id == user_id {
+        let reason = "the leader left before the start";
+        HeistQueries::finish(&mut *tx, raid_id, HeistStage::Failed.as_str(), None, Some(reason)).await?;
+        events.push(HeistEvent::new(&raid, &crew, HeistEventKind::Failed { reason: reason.to_string() }));
+    }
+    tx.commit().await?;
+    Ok(Ok(events))
+}
+
+/// Start the raid once every role is held; leader only
+pub async fn start(pool: &PgPool, user_id: i64, raid_id: i64) -> HeistResult<Vec<HeistEvent>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(raid) = HeistQueries::lock_raid(&mut *tx, raid_id).await? else {
+        return Ok(Err(HeistDenied::NotFound));
+    };
+    if raid.leader_id != user_id {
+        return Ok(Err(HeistDenied::NotLeader));
+    }
+    if raid.stage != HeistStage::Recruiting.as_str() {
+        return Ok(Err(HeistDenied::NotRecruiting));
+    }
+    let crew = HeistQueries::crew(&mut *tx, raid_id).await?;
+    // Recruiting crews only ever settle into a closed window
+    let (state, settled) = settle(&mut *tx, &raid, &crew).await?;
+    if settled.is_some() {
+        tx.commit().await?;
+        return Ok(Err(HeistDenied::WindowClosed));
+    }
+    let started = match heist::start(&state, &roles(&crew), Utc::now()) {
+        Ok(started) => started,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    HeistQueries::save_state(&mut *tx, raid_id, started.stage.as_str(), None, None).await?;
+    tx.commit().await?;
+    Ok(Ok(vec![HeistEvent::new(&raid, &crew, HeistEventKind::Started)]))
+}
+
+/// Act in the player's role, paying out the loot if that got the crew away
+pub async fn act(pool: &PgPool, user_id: i64, raid_id: i64) -> HeistResult<Vec<HeistEvent>> {
+    let config = &crate::balance::current().heist;
+    let now = Utc::now();
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(raid) = HeistQueries::lock_raid(&mut *tx, raid_id).await? else {
+        return Ok(Err(HeistDenied::NotFound));
+    };
+    let crew = HeistQueries::crew(&mut *tx, raid_id).await?;
+    let Some(role) = crew.iter().find(|m| m.user_id == user_id).and_then(|m| HeistRole::from_str(&m.role)) else {
+        return Ok(Err(HeistDenied::NotMember));
+    };
+
+    let (state, settled) = settle(&mut *tx, &raid, &crew).await?;
+    let mut events: Vec<HeistEvent> = settled.into_iter().collect();
+    if state.stage.is_over() {
+        if events.is_empty() {
+            return Ok(Err(HeistDenied::NotRunning));
+        }
+        tx.commit().await?;
+        return Ok(Ok(events));
+    }
+    let next = match heist::act(&state, role, now, config) {
+        Ok(next) => next,
+        Err(denied) => {
+            // Keep a restored firewall even though this action did not go through
+            tx.commit().await?;
+            return Ok(Err(denied));
+        }
+    };
+
+    HeistQueries::record_action(&mut *tx, raid_id, user_id).await?;
+    HeistQueries::save_state(&mut *tx, raid_id, next.stage.as_str(), next.firewall_down_until, next.trace_at).await?;
+    events.push(HeistEvent::new(&raid, &crew, HeistEventKind::Acted { user_id, role, stage: next.stage }));
+
+    if next.stage == HeistStage::Escaped {
+        let (loot, shares) = pay_out(&mut *tx, &raid, config).await?;
+        HeistQueries::finish(&mut *tx, raid_id, HeistStage::Escaped.as_str(), Some(loot), None).await?;
+        events.push(HeistEvent::new(&raid, &crew, HeistEventKind::Escaped { loot, shares }));
+    }
+
+    tx.commit().await?;
+    Ok(Ok(events))
+}
+
+/// Take the crew's loot out of the vault and split it between the members
+/// who acted
+async fn pay_out(conn: &mut PgConnection, raid: &HeistRaidRow, config: &HeistConfig) -> anyhow::Result<(i64, Vec<HeistShare>)> {
+    let vault = HeistQueries::lock_heist(conn, raid.heist_id).await?.map_or(0, |h| h.vault);
+    let loot = heist::loot(vault, config);
+    HeistQueries::take(conn, raid.heist_id, loot).await?;
+
+    // Read after the last action was recorded
+    let crew: Vec<CrewMember> = HeistQueries::crew(conn, raid.id)
+        .await?
+        .into_iter()
+        .filter_map(|m| Some(CrewMember { user_id: m.user_id, role: HeistRole::from_str(&m.role)?, actions: m.actions }))
+        .collect();
+
+    let mut shares = heist::split_loot(loot, &crew, config);
+    let reference = format!("bank_heist:{}", raid.id);
+    for share in &mut shares {
+        let paid = BankQueries::credit_primary_account(
+            conn,
+            share.user_id,
+            share.money,
+            LedgerAccount::Mint,
+            LedgerReason::HeistLoot,
+            &reference,
+        )
+        .await?;
+        if !paid {
+            // No account to pay into; the cut stays lost
+            share.money = 0;
+        }
+        HeistQueries::record_share(conn, raid.id, share.user_id, share.money).await?;
+    }
+    Ok((loot, shares))
+}
+
+/// A raid as of now and its crew, for crew members
+pub async fn view(pool: &PgPool, user_id: i64, raid_id: i64) -> HeistResult<(HeistRaidRow, Vec<HeistMemberRow>)> {
+    let Some(mut raid) = HeistQueries::raid(pool, raid_id).await? else {
+        return Ok(Err(HeistDenied::NotFound));
+    };
+    let mut conn = pool.acquire().await?;
+    let crew = HeistQueries::crew(&mut *conn, raid_id).await?;
+    if !crew.iter().any(|m| m.user_id == user_id) {
+        return Ok(Err(HeistDenied::NotMember));
+    }
+
+    // Show what time did even before the next action saves it
+    let (state, _) = heist::settle(&raid_state(&raid), Utc::now());
+    raid.stage = state.stage.as_str().to_string();
+    raid.firewall_down_until = state.firewall_down_until;
+    Ok(Ok((raid, crew)))
+}
+
+/// Publish events for the coordinator. Failing to publish never undoes the
+/// change that produced them.
+pub async fn publish(ws_manager: Option<Arc<he_websocket::ConnectionManager>>, events: Vec<HeistEvent>) {
+    let dispatcher = match dispatcher(ws_manager).await {
+        Ok(dispatcher) => dispatcher,
+        Err(e) => {
+            tracing::warn!("Heist event dispatcher unavailable: {}", e);
+            return;
+        }
+    };
+
+    for event in events {
+        let mut published = event.to_event();
+        if let Err(e) = event_schemas::registry().validate(&mut published) {
+            tracing::error!("Not publishing invalid heist event for raid {}: {}", event.raid_id, e);
+            continue;
+        }
+        if let Err(e) = dispatcher.dispatch(published).await {
+            tracing::warn!("Failed to publish heist event for raid {}: {}", event.raid_id, e);
+        }
+    }
+}
+
+/// The dispatcher, started with the coordinator on first use
+async fn dispatcher(ws_manager: Option<Arc<he_websocket::ConnectionManager>>) -> HelixResult<&'static EventDispatcher> {
+    DISPATCHER
+        .get_or_try_init(|| async {
+            let dispatcher = EventDispatcher::new(DispatchConfig::default()).await?;
+            let coordinator = Arc::new(HeistCoordinator { ws_manager });
+            for event_type in HeistEventKind::event_types() {
+                dispatcher.add_handler(event_type, coordinator.clone()).await;
+            }
+            dispatcher.start().await?;
+            Ok(dispatcher)
+        })
+        .await
+}
+
+/// Pushes raid events to every crew member's sockets
+struct HeistCoordinator {
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+}
+
+#[async_trait]
+impl EventHandler for HeistCoordinator {
+    async fn handle(&self, event: &Event) -> HelixResult<()> {
+        let EventData::Custom { data_type, payload } = &event.data else {
+            return Ok(());
+        };
+        if data_type != DATA_TYPE {
+            return Ok(());
+        }
+        let heist_event: HeistEvent = event_schemas::registry()
+            .decode(event)
+            .map_err(|e| HelixError::internal(format!("Malformed heist event: {}", e)))?;
+
+        if let Some(ws_manager) = &self.ws_manager {
+            for user_id in &heist_event.crew {
+                let message = he_websocket::GameEvent::Custom {
+                    event_name: "bank_heist".to_string(),
+                    payload: payload.clone(),
+                };
+                ws_manager.send_to_user(*user_id, message.to_server_message());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "HeistCoordinator"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_round_trips_through_payload() {
+        let event = HeistEvent {
+            heist_id: 2,
+            raid_id: 9,
+            crew: vec![42, 43],
+            kind: HeistEventKind::Escaped { loot: 100, shares: vec![HeistShare { user_id: 42, money: 100 }] },
+        };
+
+        let published = event.to_event();
+        assert_eq!(published.event_type, EventType::Custom("bank_heist_escaped".to_string()));
+        let EventData::Custom { data_type, payload } = published.data else {
+            panic!("heist events are custom payloads");
+        };
+        assert_eq!(data_type, DATA_TYPE);
+
+        let received: HeistEvent = serde_json::from_value(payload).unwrap();
+        assert_eq!(received.crew, vec![42, 43]);
+        assert!(matches!(received.kind, HeistEventKind::Escaped { loot: 100, .. }));
+        assert!(HeistEventKind::event_types().contains(&published.event_type));
+    }
+}
@@ -0,0 +1,134 @@
+//! Honeypot servers
+//!
+//! A remote process that completes against a honeypot pays nothing. It runs
+//! inside the completion transaction, which records the trip with the
+//! attacker's real route, traces them and takes their Underground standing;
+//! the rules are in [`he_game_mechanics::honeypot`]. The attacker is not told.
+//! While they are traced, the servers they act on log their gateway rather
+//! than the address they came from, and banks log it on every theft.
+//!
+//! Trips are handed to the intrusion detector once the transaction commits,
+//! which is where scripted accounts that keep tripping them surface.
+
+use chrono::{DateTime, Utc};
+use he_database::models::Process;
+use he_database::queries::{HoneypotQueries, NewHoneypotTrip, ProgressionQueries};
+use he_game_mechanics::honeypot::{self, Route, REPUTATION_FACTION};
+use he_helix_security::IntrusionDetector;
+use once_cell::sync::OnceCell;
+use sqlx::PgConnection;
+use std::sync::Arc;
+
+static DETECTOR: OnceCell<Arc<IntrusionDetector>> = OnceCell::new();
+
+/// Feed honeypot trips to the intrusion detector
+pub fn attach_detector(detector: Arc<IntrusionDetector>) {
+    let _ = DETECTOR.set(detector);
+}
+
+/// A process that tripped a honeypot
+#[derive(Debug, Clone)]
+pub struct HoneypotTrip {
+    pub trip_id: i64,
+    pub user_id: i64,
+    pub route: Route,
+    pub traced_until: DateTime<Utc>,
+    pub anomalous: bool,
+}
+
+/// Spring the trap if `process` acted on a honeypot at `target_ip`. Its
+/// rewards are the caller's to withhold.
+pub(crate) async fn spring(
+    conn: &mut PgConnection,
+    process: &Process,
+    target_ip: &str,
+) -> anyhow::Result<Option<HoneypotTrip>> {
+    let Some(server_id) = HoneypotQueries::at(&mut *conn, target_ip).await? else {
+        return Ok(None);
+    };
+    let config = &crate::balance::current().honeypot;
+
+    let (gateway_ip, bounce_ips) = HoneypotQueries::route(&mut *conn, process.user_id, target_ip).await?;
+    let host_ip = crate::hosting::host_ip(process);
+    let recent_trips = HoneypotQueries::recent_trips(&mut *conn, process.user_id, config.anomaly_window_hours).await?;
+    let traced_until = HoneypotQueries::lock_traced(&mut *conn, process.user_id).await?;
+    let trap = honeypot::spring(Utc::now(), traced_until, recent_trips as u32, config);
+
+    let Some(trip_id) = HoneypotQueries::record_trip(&mut *conn, &NewHoneypotTrip {
+        server_id,
+        user_id: process.user_id,
+        pid: process.pid,
+        process_type: &process.process_type,
+        gateway_ip: gateway_ip.as_deref(),
+        bounce_ips: &bounce_ips,
+        host_ip,
+        target_ip,
+        anomalous: trap.anomalous,
+    })
+    .await?
+    else {
+        return Ok(None);
+    };
+    HoneypotQueries::set_traced(&mut *conn, process.user_id, trap.traced_until, trip_id).await?;
+    ProgressionQueries::add_reputation(&mut *conn, process.user_id, REPUTATION_FACTION, trap.reputation_change).await?;
+
+    Ok(Some(HoneypotTrip {
+        trip_id,
+        user_id: process.user_id,
+        route: Route {
+            gateway_ip: gateway_ip.unwrap_or_default(),
+            bounce_ips,
+            host_ip: host_ip.map(str::to_string),
+            target_ip: target_ip.to_string(),
+        },
+        traced_until: trap.traced_until,
+        anomalous: trap.anomalous,
+    }))
+}
+
+/// Whether the player's real address shows wherever they act
+pub(crate) async fn traced(conn: &mut PgConnection, user_id: i64) -> anyhow::Result<bool> {
+    HoneypotQueries::is_traced(conn, user_id).await
+}
+
+/// Hand a committed trip to the intrusion detector
+pub(crate) fn report(trip: &HoneypotTrip) {
+    tracing::info!(
+        "User {} tripped honeypot {} via {}",
+        trip.user_id,
+        trip.route.target_ip,
+        trip.route.hops().join(" -> ")
+    );
+    if let Some(detector) = DETECTOR.get() {
+        detector.report_honeypot_trip(trip.user_id, &trip.route.target_ip, trip.anomalous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_feeds_detector() {
+        let detector = Arc::new(IntrusionDetector::new());
+        attach_detector(detector.clone());
+
+        let trip = HoneypotTrip {
+            trip_id: 1,
+            user_id: 42,
+            route: Route {
+                gateway_ip: "10.0.0.1".to_string(),
+                bounce_ips: vec!["10.0.0.2".to_string()],
+                host_ip: None,
+                target_ip: "192.0.2.7".to_string(),
+            },
+            traced_until: Utc::now(),
+            anomalous: true,
+        };
+        report(&trip);
+
+        let patterns = detector.account_patterns(42);
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern_type, "honeypot_anomaly");
+    }
+}
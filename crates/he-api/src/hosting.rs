@@ -0,0 +1,182 @@
+//! Processes on remote servers
+//!
+//! A player may launch a process on a server they own or have hacked instead
+//! of their gateway (see [`he_game_mechanics::hosting`]). The process then
+//! runs with the host's hardware and its transfers go at the slower of the
+//! host's link and the target's. Its `pc_id` is the host's address, so the
+//! target logs the access from there.
+//!
+//! Resources are held on the host in `process_hosts` once the process
+//! exists; a host that turns out to be full cancels it again. When an IP
+//! reset takes a hacked host away from its hackers, their processes on it are
+//! cancelled in the same transaction.
+
+use he_database::models::Process;
+use he_database::queries::{HostUsage, ProcessHostQueries};
+use he_game_mechanics::hosting::{self, HostAccess, HostDenied, HostHardware};
+use he_game_mechanics::{HardwareSpecs, ResourceUsage};
+use sqlx::{PgConnection, PgPool};
+use std::net::IpAddr;
+use crate::outbox::OutboxMessage;
+
+/// A server a player may run processes on
+#[derive(Debug, Clone)]
+pub struct Host {
+    pub server_id: i64,
+    pub owner_id: i64,
+    pub ip: String,
+    pub hardware: HostHardware,
+    pub access: HostAccess,
+}
+
+impl Host {
+    /// The hardware a process here runs with
+    pub fn specs(&self) -> HardwareSpecs {
+        hosting::host_specs(&self.hardware, self.access, &crate::balance::current().hosting)
+    }
+}
+
+/// A process cancelled because its player lost access to its host
+#[derive(Debug, Clone)]
+pub(crate) struct RevokedProcess {
+    pub pid: i64,
+    pub user_id: i64,
+    pub process_type: String,
+}
+
+/// The server at `ip`, if `user_id` may run processes on it
+pub async fn resolve(pool: &PgPool, user_id: i64, ip: &str) -> anyhow::Result<Result<Host, HostDenied>> {
+    let Some(host) = ProcessHostQueries::host(pool, user_id, ip).await? else {
+        return Ok(Err(HostDenied::HostNotFound));
+    };
+    let Some(access) = host.access.as_deref().and_then(HostAccess::from_str) else {
+        return Ok(Err(HostDenied::NoAccess));
+    };
+
+    Ok(Ok(Host {
+        server_id: host.server_id,
+        owner_id: host.owner_id,
+        ip: host.ip_address,
+        hardware: HostHardware {
+            cpu_mhz: host.cpu_total,
+            ram_mb: host.ram_total,
+            net_mbps: host.net_total,
+        },
+        access,
+    }))
+}
+
+fn usage(used: &HostUsage) -> ResourceUsage {
+    let clamp = |value: i64| value.clamp(0, i64::from(i32::MAX)) as i32;
+    ResourceUsage {
+        cpu_usage: clamp(used.cpu),
+        ram_usage: clamp(used.ram),
+        net_usage: clamp(used.net),
+        hdd_usage: 0,
+    }
+}
+
+/// Hold `request` on the host for process `pid`, unless the host is full
+pub async fn reserve(
+    pool: &PgPool,
+    pid: i64,
+    user_id: i64,
+    host: &Host,
+    request: &ResourceUsage,
+) -> anyhow::Result<Result<(), HostDenied>> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let load = ProcessHostQueries::lock_load(&mut *tx, host.server_id, user_id).await?;
+    let used = match host.access {
+        HostAccess::Owned => &load.all,
+        HostAccess::Hacked => &load.hacked,
+    };
+
+    if let Err(denied) = hosting::check_allocation(
+        &host.hardware,
+        host.access,
+        load.running.max(0) as usize,
+        &usage(used),
+        request,
+        &crate::balance::current().hosting,
+    ) {
+        tx.rollback().await?;
+        return Ok(Err(denied));
+    }
+
+    ProcessHostQueries::reserve(
+        &mut *tx,
+        pid,
+        user_id,
+        host.server_id,
+        host.access.as_str(),
+        request.cpu_usage,
+        request.ram_usage,
+        request.net_usage,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(Ok(()))
+}
+
+/// The address of the host `process` runs on, if not its player's gateway
+pub fn host_ip(process: &Process) -> Option<&str> {
+    process.pc_id.parse::<IpAddr>().is_ok().then_some(process.pc_id.as_str())
+}
+
+/// Cancel processes on the owner's servers that their players can no longer
+/// reach, inside the transaction that took their access away
+pub(crate) async fn revoke_lost(conn: &mut PgConnection, owner_id: i64) -> anyhow::Result<Vec<RevokedProcess>> {
+    let revoked = ProcessHostQueries::revoke_lost(conn, owner_id).await?;
+    Ok(revoked
+        .into_iter()
+        .map(|(pid, user_id, process_type)| RevokedProcess { pid, user_id, process_type })
+        .collect())
+}
+
+/// Tell each player whose process was cancelled
+pub(crate) fn outbox_messages(revoked: &[RevokedProcess]) -> Vec<OutboxMessage> {
+    revoked
+        .iter()
+        .map(|process| {
+            let event = he_websocket::EventBuilder::process_completed(
+                process.pid,
+                process.process_type.clone(),
+                "cancelled".to_string(),
+            );
+            OutboxMessage::to_user(format!("process:{}:revoked", process.pid), process.user_id, &event)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_host_ip_and_revoked_messages() {
+        let mut process = Process {
+            pid: 7,
+            user_id: 3,
+            game_id: None,
+            pc_id: "pc_3".to_string(),
+            target_pc_id: Some("10.0.0.9".to_string()),
+            target_file_id: None,
+            target_folder: None,
+            process_type: "crack".to_string(),
+            priority: 0,
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            completed_at: None,
+            paused_at: None,
+        };
+        assert_eq!(host_ip(&process), None);
+        process.pc_id = "192.0.2.40".to_string();
+        assert_eq!(host_ip(&process), Some("192.0.2.40"));
+
+        let revoked = [RevokedProcess { pid: 7, user_id: 3, process_type: "crack".to_string() }];
+        let messages = outbox_messages(&revoked);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].dedup_key, "process:7:revoked");
+    }
+}
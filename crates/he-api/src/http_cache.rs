@@ -0,0 +1,396 @@
+//! HTTP caching for read endpoints
+//!
+//! [`ConditionalGet`] wraps a route group with a [`CachePolicy`]. Every
+//! successful GET gets the policy's `Cache-Control`. Groups that are
+//! [`versioned_by`](CachePolicy::versioned_by) a cache key also get an ETag
+//! and Last-Modified from that key's content version in he-cache, and a
+//! request whose `If-None-Match`/`If-Modified-Since` is still current is
+//! answered `304 Not Modified` without running the handler.
+//!
+//! Since a 304 skips the handler, it also skips the handler's sign-in check.
+//! [`Private`](CachePolicy::private) groups are only revalidated for requests
+//! whose bearer token he-auth accepts; anything else goes to the handler and
+//! gets no validators.
+//!
+//! The version is read before the handler loads its data, and writers bump it
+//! only after committing (see [`he_cache::versions`]), so a response is never
+//! tagged with a newer version than its body. Without a [`VersionStore`] in
+//! the app data, responses only get `Cache-Control`.
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Method, StatusCode},
+    web, Error, HttpResponse,
+};
+use chrono::Utc;
+use futures_util::future::LocalBoxFuture;
+use he_auth::AuthService;
+use he_cache::versions::{ContentVersion, VersionStore};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+
+type KeyFn = Arc<dyn Fn(&ServiceRequest) -> Option<String>>;
+
+/// Caching rules for a route group
+#[derive(Clone)]
+pub struct CachePolicy {
+    cache_control: String,
+    vary: Option<&'static str>,
+    key: Option<KeyFn>,
+    /// Only revalidate requests from a signed-in caller
+    authenticated: bool,
+}
+
+impl CachePolicy {
+    /// The same for every client; shared caches may reuse it for `max_age` seconds
+    pub fn public(max_age: u32) -> Self {
+        Self {
+            cache_control: format!("public, max-age={}", max_age),
+            vary: None,
+            key: None,
+            authenticated: false,
+        }
+    }
+
+    /// Depends on who is asking: only the client may keep it, and it must
+    /// revalidate before every reuse, signed in
+    pub fn private() -> Self {
+        Self {
+            cache_control: "private, no-cache".to_string(),
+            vary: Some("Authorization"),
+            key: None,
+            authenticated: true,
+        }
+    }
+
+    /// Revalidate against the content version of the cache key `key` returns
+    /// for a request; requests it returns `None` for are not revalidated
+    pub fn versioned_by(mut self, key: impl Fn(&ServiceRequest) -> Option<String> + 'static) -> Self {
+        self.key = Some(Arc::new(key));
+        self
+    }
+
+    pub fn cache_control(&self) -> &str {
+        &self.cache_control
+    }
+}
+
+/// Conditional request handling for a route group
+pub struct ConditionalGet {
+    policy: CachePolicy,
+}
+
+impl ConditionalGet {
+    pub fn new(policy: CachePolicy) -> Self {
+        Self { policy }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ConditionalGet
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ConditionalGetService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ConditionalGetService {
+            service: Rc::new(service),
+            policy: self.policy.clone(),
+        }))
+    }
+}
+
+pub struct ConditionalGetService<S> {
+    service: Rc<S>,
+    policy: CachePolicy,
+}
+
+impl<S, B> Service<ServiceRequest> for ConditionalGetService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let policy = self.policy.clone();
+
+        Box::pin(async move {
+            if req.method() != Method::GET && req.method() != Method::HEAD {
+                return service.call(req).await.map(ServiceResponse::map_into_left_body);
+            }
+
+            let version = current_version(&req, &policy).await;
+
+            if let Some(version) = &version {
+                let if_none_match = header_str(&req, header::IF_NONE_MATCH);
+                let if_modified_since = header_str(&req, header::IF_MODIFIED_SINCE);
+                if version.is_fresh(if_none_match, if_modified_since) {
+                    let mut response = HttpResponse::NotModified();
+                    response.insert_header((header::CACHE_CONTROL, policy.cache_control.clone()));
+                    apply_validators(&mut response, version, &policy);
+                    return Ok(req.into_response(response.finish().map_into_right_body()));
+                }
+            }
+
+            let mut res = service.call(req).await?;
+            if res.status() == StatusCode::OK {
+                let headers = res.headers_mut();
+                if !headers.contains_key(header::CACHE_CONTROL) {
+                    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_str(&policy.cache_control)?);
+                }
+                if let Some(vary) = policy.vary {
+                    headers.insert(header::VARY, header::HeaderValue::from_static(vary));
+                }
+                if let Some(version) = &version {
+                    headers.insert(header::ETAG, header::HeaderValue::from_str(&version.etag())?);
+                    if let Some(last_modified) = version.last_modified(Utc::now()) {
+                        headers.insert(header::LAST_MODIFIED, header::HeaderValue::from_str(&last_modified)?);
+                    }
+                }
+            }
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+/// Version of the key the policy picks for this request, if any. A failed
+/// lookup serves the request uncached rather than failing it.
+async fn current_version(req: &ServiceRequest, policy: &CachePolicy) -> Option<ContentVersion> {
+    let key = policy.key.as_ref().and_then(|key| key(req))?;
+    if policy.authenticated && !signed_in(req).await {
+        return None;
+    }
+    let store = req.app_data::<web::Data<dyn VersionStore>>()?;
+
+    match store.current(&key).await {
+        Ok(version) => Some(version),
+        Err(e) => {
+            tracing::warn!("Failed to load content version of {}: {}", key, e);
+            None
+        }
+    }
+}
+
+/// Whether the request carries a bearer token he-auth accepts, as the
+/// handlers check it
+async fn signed_in(req: &ServiceRequest) -> bool {
+    let Some(token) = header_str(req, header::AUTHORIZATION).and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    let Some(auth) = req.app_data::<web::Data<AuthService>>() else {
+        return false;
+    };
+
+    matches!(auth.validate_token(token).await, Ok(Some(_)))
+}
+
+fn header_str(req: &ServiceRequest, name: header::HeaderName) -> Option<&str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+fn apply_validators(response: &mut actix_web::HttpResponseBuilder, version: &ContentVersion, policy: &CachePolicy) {
+    response.insert_header((header::ETAG, version.etag()));
+    if let Some(last_modified) = version.last_modified(Utc::now()) {
+        response.insert_header((header::LAST_MODIFIED, last_modified));
+    }
+    if let Some(vary) = policy.vary {
+        response.insert_header((header::VARY, vary));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use he_cache::versions::MemoryVersions;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const KEY: &str = "server:1.2.3.4:info:v1";
+
+    /// Data behind `KEY`, changed by writers that bump its version afterwards
+    struct Page {
+        revision: AtomicU64,
+    }
+
+    async fn read_page(page: web::Data<Page>) -> HttpResponse {
+        // Give writers a chance to land between the version lookup and the read
+        tokio::task::yield_now().await;
+        HttpResponse::Ok().body(page.revision.load(Ordering::SeqCst).to_string())
+    }
+
+    fn version_of(etag: &str) -> u64 {
+        etag.trim_matches('"').split('-').next().unwrap().parse().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_conditional_get() {
+        let versions: Arc<dyn VersionStore> = Arc::new(MemoryVersions::new());
+        let page = web::Data::new(Page { revision: AtomicU64::new(0) });
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(versions.clone()))
+                .app_data(page.clone())
+                .service(
+                    web::resource("/page")
+                        .wrap(ConditionalGet::new(CachePolicy::public(60).versioned_by(|_| Some(KEY.to_string()))))
+                        .route(web::get().to(read_page)),
+                ),
+        )
+        .await;
+
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/page").to_request()).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get(header::CACHE_CONTROL).unwrap(), "public, max-age=60");
+        assert!(resp.headers().get(header::VARY).is_none());
+        let etag = resp.headers().get(header::ETAG).unwrap().to_str().unwrap().to_string();
+
+        let req = test::TestRequest::get()
+            .uri("/page")
+            .insert_header((header::IF_NONE_MATCH, etag.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 304);
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), etag.as_str());
+
+        page.revision.store(1, Ordering::SeqCst);
+        versions.bump(KEY).await.unwrap();
+
+        let req = test::TestRequest::get()
+            .uri("/page")
+            .insert_header((header::IF_NONE_MATCH, etag.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_ne!(resp.headers().get(header::ETAG).unwrap(), etag.as_str());
+        assert_eq!(test::read_body(resp).await, "1");
+
+        // Writes are not revalidated
+        let req = test::TestRequest::post()
+            .uri("/page")
+            .insert_header((header::IF_NONE_MATCH, "*"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 405);
+    }
+
+    #[actix_web::test]
+    async fn test_private_needs_sign_in() {
+        let versions: Arc<dyn VersionStore> = Arc::new(MemoryVersions::new());
+        let page = web::Data::new(Page { revision: AtomicU64::new(0) });
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(versions.clone()))
+                .app_data(page.clone())
+                .service(
+                    web::resource("/page")
+                        .wrap(ConditionalGet::new(CachePolicy::private().versioned_by(|_| Some(KEY.to_string()))))
+                        .route(web::get().to(read_page)),
+                ),
+        )
+        .await;
+
+        // No token, or one nothing can check: the handler runs and nothing is tagged
+        for authorization in [None, Some("Bearer forged")] {
+            let mut req = test::TestRequest::get().uri("/page").insert_header((header::IF_NONE_MATCH, "*"));
+            if let Some(authorization) = authorization {
+                req = req.insert_header((header::AUTHORIZATION, authorization));
+            }
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), 200);
+            assert_eq!(resp.headers().get(header::CACHE_CONTROL).unwrap(), "private, no-cache");
+            assert_eq!(resp.headers().get(header::VARY).unwrap(), "Authorization");
+            assert!(resp.headers().get(header::ETAG).is_none());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_revalidation_under_concurrent_writes() {
+        let versions: Arc<dyn VersionStore> = Arc::new(MemoryVersions::new());
+        let page = web::Data::new(Page { revision: AtomicU64::new(0) });
+        let app = Rc::new(
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::from(versions.clone()))
+                    .app_data(page.clone())
+                    .service(
+                        web::resource("/page")
+                            .wrap(ConditionalGet::new(CachePolicy::public(0).versioned_by(|_| Some(KEY.to_string()))))
+                            .route(web::get().to(read_page)),
+                    ),
+            )
+            .await,
+        );
+
+        let writer = {
+            let versions = versions.clone();
+            let page = page.clone();
+            async move {
+                for _ in 0..200 {
+                    // Commit, then bump
+                    page.revision.fetch_add(1, Ordering::SeqCst);
+                    tokio::task::yield_now().await;
+                    versions.bump(KEY).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            }
+        };
+
+        let readers = (0..4).map(|_| {
+            let app = app.clone();
+            async move {
+                let mut cached: Option<(String, u64)> = None;
+                for _ in 0..200 {
+                    let mut req = test::TestRequest::get().uri("/page");
+                    if let Some((etag, _)) = &cached {
+                        req = req.insert_header((header::IF_NONE_MATCH, etag.as_str()));
+                    }
+                    let resp = test::call_service(&*app, req.to_request()).await;
+                    match resp.status().as_u16() {
+                        304 => assert!(cached.is_some()),
+                        200 => {
+                            let etag = resp.headers().get(header::ETAG).unwrap().to_str().unwrap().to_string();
+                            let body: u64 = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap().parse().unwrap();
+                            // Version n is reached after the n-1th write, so the body is at least that new
+                            assert!(body + 1 >= version_of(&etag), "body {} tagged {}", body, etag);
+                            cached = Some((etag, body));
+                        }
+                        status => panic!("unexpected status {}", status),
+                    }
+                    tokio::task::yield_now().await;
+                }
+                cached
+            }
+        });
+
+        let (_, cached) = futures_util::join!(writer, futures_util::future::join_all(readers));
+
+        // Once writes settle, revalidating never keeps a stale body
+        let latest = page.revision.load(Ordering::SeqCst);
+        for (etag, body) in cached.into_iter().flatten() {
+            let req = test::TestRequest::get()
+                .uri("/page")
+                .insert_header((header::IF_NONE_MATCH, etag.as_str()))
+                .to_request();
+            let resp = test::call_service(&*app, req).await;
+            if resp.status() == 304 {
+                assert_eq!(body, latest);
+            } else {
+                assert_eq!(test::read_body(resp).await, latest.to_string());
+            }
+        }
+    }
+}
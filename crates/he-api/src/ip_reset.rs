@@ -0,0 +1,96 @@
+//! IP resets
+//!
+//! `reset_ip` is a process like any other, gated by
+//! [`he_game_mechanics::identity`]: it is paid when it starts and at most one
+//! completes per cooldown. On completion the gateway moves to a fresh
+//! address, inside the completion transaction. Players who had it in their
+//! hacked database or a tunnel open to or through it are told the server is
+//! gone, but not where it went, and their processes running on it are
+//! cancelled. Logs keep the old address.
+
+use chrono::Utc;
+use he_database::models::Process;
+use he_database::queries::{IpResetQueries, IpResetScrub};
+use he_game_mechanics::identity::{self, ResetDenied};
+use sqlx::{PgConnection, PgPool};
+use std::collections::BTreeSet;
+use crate::hosting::RevokedProcess;
+use crate::outbox::OutboxMessage;
+
+/// Fresh addresses tried before the completion is retried later
+const ALLOCATION_ATTEMPTS: usize = 8;
+
+/// A completed reset
+pub(crate) struct IpReset {
+    pub old_ip: String,
+    pub new_ip: String,
+    pub scrub: IpResetScrub,
+    /// Other players' processes on the server, cancelled with their access
+    pub revoked: Vec<RevokedProcess>,
+}
+
+/// Whether the player may start a reset now
+pub async fn check(pool: &PgPool, user_id: i64) -> anyhow::Result<Result<(), ResetDenied>> {
+    let state = IpResetQueries::state(pool, user_id).await?;
+    Ok(identity::check_reset(
+        state.last_reset,
+        state.in_progress,
+        state.balance,
+        Utc::now(),
+        &crate::balance::current().ip_reset,
+    ))
+}
+
+/// Pay for the reset started as `pid`. On a lost race the reason is checked
+/// again for the player.
+pub async fn charge(pool: &PgPool, user_id: i64, pid: i64) -> anyhow::Result<Result<(), ResetDenied>> {
+    let config = &crate::balance::current().ip_reset;
+    if IpResetQueries::charge(pool, user_id, pid, config.cost, config.cooldown_hours).await? {
+        return Ok(Ok(()));
+    }
+
+    Ok(Err(check(pool, user_id).await?.err().unwrap_or(ResetDenied::InProgress)))
+}
+
+/// Move the owner's gateway, inside the completion transaction. `None` if
+/// the owner has no gateway.
+pub(crate) async fn apply(conn: &mut PgConnection, process: &Process) -> anyhow::Result<Option<IpReset>> {
+    let Some(old_ip) = IpResetQueries::gateway_ip(conn, process.user_id).await? else {
+        return Ok(None);
+    };
+
+    for _ in 0..ALLOCATION_ATTEMPTS {
+        let new_ip = identity::random_ip(&mut rand::thread_rng()).to_string();
+        if !IpResetQueries::move_gateway(conn, process.user_id, &old_ip, &new_ip).await? {
+            continue;
+        }
+
+        let scrub = IpResetQueries::scrub(conn, process.user_id, &old_ip, &new_ip).await?;
+        let revoked = crate::hosting::revoke_lost(conn, process.user_id).await?;
+        IpResetQueries::complete(conn, process.pid, &old_ip, &new_ip).await?;
+        return Ok(Some(IpReset { old_ip, new_ip, scrub, revoked }));
+    }
+
+    anyhow::bail!("no free address after {} attempts", ALLOCATION_ATTEMPTS)
+}
+
+/// Tell the owner their new address, and everyone who lost access that the
+/// old one is gone
+pub(crate) fn outbox_messages(pid: i64, user_id: i64, reset: &IpReset) -> Vec<OutboxMessage> {
+    let event = he_websocket::EventBuilder::ip_changed(reset.old_ip.clone(), reset.new_ip.clone());
+    let mut messages = vec![OutboxMessage::to_user(format!("process:{}:ip_changed", pid), user_id, &event)];
+
+    let affected: BTreeSet<i64> = reset
+        .scrub
+        .lost_by
+        .iter()
+        .chain(&reset.scrub.tunnels_closed_for)
+        .copied()
+        .collect();
+    for player in affected {
+        let event = he_websocket::EventBuilder::hacked_server_lost(reset.old_ip.clone());
+        messages.push(OutboxMessage::to_user(format!("process:{}:server_lost:{}", pid, player), player, &event));
+    }
+    messages.extend(crate::hosting::outbox_messages(&reset.revoked));
+    messages
+}
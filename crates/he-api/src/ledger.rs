@@ -0,0 +1,104 @@
+//! Money ledger reconciliation
+//!
+//! Every money movement is posted to the double-entry ledger (see
+//! [`he_database::queries::LedgerQueries`]) and bank balances are kept as a
+//! cache of it. The reconciler compares the two periodically and records
+//! every account that disagrees as a discrepancy, which the admin forensics
+//! API lists. A moderator then either restores the balance from the ledger
+//! or accepts it with an adjustment entry; nothing is corrected unattended.
+
+use he_database::queries::{LedgerDiscrepancy, LedgerDrift, LedgerQueries};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// How often balances are checked against the ledger
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(900);
+
+/// How a moderator settles a discrepancy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Reset the balance to what the ledger has
+    Restore,
+    /// Keep the balance and post the difference to the ledger
+    Adjust,
+}
+
+impl Resolution {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "restore" => Some(Resolution::Restore),
+            "adjust" => Some(Resolution::Adjust),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::Restore => "restore",
+            Resolution::Adjust => "adjust",
+        }
+    }
+}
+
+/// Money the balance holds that the ledger does not explain; negative if
+/// the balance is short
+pub fn unexplained(drift: &LedgerDrift) -> i64 {
+    drift.balance - drift.ledger_balance
+}
+
+/// Settle an open discrepancy. `None` if it is not open.
+pub async fn resolve(
+    pool: &PgPool,
+    discrepancy_id: i64,
+    admin_id: i64,
+    resolution: Resolution,
+    note: &str,
+) -> anyhow::Result<Option<LedgerDiscrepancy>> {
+    LedgerQueries::resolve(pool, discrepancy_id, admin_id, resolution == Resolution::Restore, note).await
+}
+
+/// Reconcile every [`RECONCILE_INTERVAL`]
+pub(crate) fn spawn_reconciler(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RECONCILE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = reconcile(&pool).await {
+                tracing::warn!("Ledger reconciliation failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn reconcile(pool: &PgPool) -> anyhow::Result<()> {
+    let drift = LedgerQueries::drift(pool).await?;
+    let opened = LedgerQueries::record_drift(pool, &drift).await?;
+    if opened > 0 {
+        let total: i64 = drift.iter().map(unexplained).sum();
+        tracing::error!(
+            "Ledger reconciliation found {} new discrepancies; {} accounts disagree by {} cents in all",
+            opened,
+            drift.len(),
+            total
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolution_and_unexplained_money() {
+        for resolution in [Resolution::Restore, Resolution::Adjust] {
+            assert_eq!(Resolution::from_str(resolution.as_str()), Some(resolution));
+        }
+        assert_eq!(Resolution::from_str("delete"), None);
+
+        let drift = LedgerDrift { bank_account_id: 4, user_id: 2, balance: 1_500, ledger_balance: 1_000 };
+        assert_eq!(unexplained(&drift), 500);
+        let short = LedgerDrift { balance: 200, ..drift };
+        assert_eq!(unexplained(&short), -800);
+    }
+}
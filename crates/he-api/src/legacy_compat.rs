@@ -5,6 +5,7 @@ use sqlx::Row;
 use crate::AppState;
 use std::time::{SystemTime, UNIX_EPOCH};
 use he_helix_http::auth::{verify_password, issue_jwt};
+use crate::legacy_rbac::{unmapped_routes, LegacyRbac};
 
 // Simple plugin-style composition for legacy routes
 pub trait LegacyPlugin {
@@ -42,49 +43,79 @@ impl LegacyPlugin for InternetPlugin {
     fn register(&self, scope: Scope) -> Scope { plugin_internet(scope) }
 }
 
+/// Every (method, path) pair the plugins register under `/legacy`
+pub const REGISTERED_ROUTES: &[(&str, &str)] = &[
+    ("GET", "/legacy/ping"),
+    ("GET", "/legacy/api/status"),
+    ("GET", "/legacy/news/recent"),
+    ("GET", "/legacy/logs/recent"),
+    ("POST", "/legacy/auth/login"),
+    ("POST", "/legacy/auth/logout"),
+    ("GET", "/legacy/session"),
+    ("GET", "/legacy/process"),
+    ("POST", "/legacy/process/start"),
+    ("GET", "/legacy/process/{pid}"),
+    ("POST", "/legacy/process/{pid}/cancel"),
+    ("GET", "/legacy/hardware/info"),
+    ("POST", "/legacy/hardware/upgrade"),
+    ("POST", "/legacy/internet/scan"),
+    ("POST", "/legacy/internet/connect"),
+    ("GET", "/legacy/servers/available"),
+    ("GET", "/legacy/software/installed"),
+    ("GET", "/legacy/software/store"),
+    ("POST", "/legacy/software/{id}/start"),
+    ("POST", "/legacy/software/{id}/stop"),
+    ("POST", "/legacy/software/{id}/uninstall"),
+];
+
+// Each route is wrapped in `LegacyRbac`, like the PHP routes in `legacy_router`
 fn plugin_core(scope: Scope) -> Scope {
     scope
-        .route("/ping", web::get().to(ping))
-        .route("/api/status", web::get().to(status))
-        .route("/news/recent", web::get().to(legacy_news_recent))
-        .route("/logs/recent", web::get().to(legacy_logs_recent))
+        .route("/ping", web::get().to(ping).wrap(LegacyRbac))
+        .route("/api/status", web::get().to(status).wrap(LegacyRbac))
+        .route("/news/recent", web::get().to(legacy_news_recent).wrap(LegacyRbac))
+        .route("/logs/recent", web::get().to(legacy_logs_recent).wrap(LegacyRbac))
 }
 
 fn plugin_auth(scope: Scope) -> Scope {
     scope
-        .route("/auth/login", web::post().to(legacy_login))
-        .route("/auth/logout", web::post().to(legacy_logout))
-        .route("/session", web::get().to(legacy_session))
+        .route("/auth/login", web::post().to(legacy_login).wrap(LegacyRbac))
+        .route("/auth/logout", web::post().to(legacy_logout).wrap(LegacyRbac))
+        .route("/session", web::get().to(legacy_session).wrap(LegacyRbac))
 }
 
 fn plugin_process(scope: Scope) -> Scope {
     scope
-        .route("/process", web::get().to(legacy_process_list))
-        .route("/process/start", web::post().to(legacy_process_start))
-        .route("/process/{pid}", web::get().to(legacy_process_info))
-        .route("/process/{pid}/cancel", web::post().to(legacy_process_cancel))
+        .route("/process", web::get().to(legacy_process_list).wrap(LegacyRbac))
+        .route("/process/start", web::post().to(legacy_process_start).wrap(LegacyRbac))
+        .route("/process/{pid}", web::get().to(legacy_process_info).wrap(LegacyRbac))
+        .route("/process/{pid}/cancel", web::post().to(legacy_process_cancel).wrap(LegacyRbac))
 }
 
 fn plugin_hardware(scope: Scope) -> Scope {
     scope
-        .route("/hardware/info", web::get().to(legacy_hardware_info))
-        .route("/hardware/upgrade", web::post().to(legacy_hardware_upgrade))
+        .route("/hardware/info", web::get().to(legacy_hardware_info).wrap(LegacyRbac))
+        .route("/hardware/upgrade", web::post().to(legacy_hardware_upgrade).wrap(LegacyRbac))
 }
 
 fn plugin_internet(scope: Scope) -> Scope {
     scope
-        .route("/internet/scan", web::post().to(legacy_internet_scan))
-        .route("/internet/connect", web::post().to(legacy_internet_connect))
-        .route("/servers/available", web::get().to(legacy_servers_available))
+        .route("/internet/scan", web::post().to(legacy_internet_scan).wrap(LegacyRbac))
+        .route("/internet/connect", web::post().to(legacy_internet_connect).wrap(LegacyRbac))
+        .route("/servers/available", web::get().to(legacy_servers_available).wrap(LegacyRbac))
         // Software
-        .route("/software/installed", web::get().to(legacy_software_installed))
-        .route("/software/store", web::get().to(legacy_software_store))
-        .route("/software/{id}/start", web::post().to(legacy_software_start))
-        .route("/software/{id}/stop", web::post().to(legacy_software_stop))
-        .route("/software/{id}/uninstall", web::post().to(legacy_software_uninstall))
+        .route("/software/installed", web::get().to(legacy_software_installed).wrap(LegacyRbac))
+        .route("/software/store", web::get().to(legacy_software_store).wrap(LegacyRbac))
+        .route("/software/{id}/start", web::post().to(legacy_software_start).wrap(LegacyRbac))
+        .route("/software/{id}/stop", web::post().to(legacy_software_stop).wrap(LegacyRbac))
+        .route("/software/{id}/uninstall", web::post().to(legacy_software_uninstall).wrap(LegacyRbac))
 }
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
+    for (method, path) in unmapped_routes(REGISTERED_ROUTES) {
+        tracing::error!("Legacy route {} {} has no RBAC mapping and will be denied", method, path);
+    }
+
     let plugins: Vec<Box<dyn LegacyPlugin>> = vec![
         Box::new(CorePlugin),
         Box::new(AuthPlugin),
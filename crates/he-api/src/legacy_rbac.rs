@@ -0,0 +1,353 @@
+//! RBAC enforcement for the legacy PHP compatibility routes
+//!
+//! Every route registered by `legacy_router` and `legacy_compat` must have an entry in
+//! [`LEGACY_ROUTE_PERMISSIONS`]. Routes without an entry are rejected (fail closed),
+//! public routes pass through, and everything else is checked against he-auth's
+//! `AuthService::check_permission`.
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    body::EitherBody,
+    web, Error, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use he_auth::AuthService;
+use he_database::models::legacy_user_uuid;
+use he_helix_http::auth::verify_jwt;
+use std::future::{ready, Future, Ready};
+use std::rc::Rc;
+
+use crate::AppState;
+
+/// Access requirement for a legacy route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutePermission {
+    /// Reachable without a session (landing, login, legal pages)
+    Public,
+    /// Requires an authenticated user holding this permission
+    Requires(&'static str),
+}
+
+use RoutePermission::{Public, Requires};
+
+/// Route → permission map for the legacy layer, keyed by (method, path)
+pub const LEGACY_ROUTE_PERMISSIONS: &[(&str, &str, RoutePermission)] = &[
+    // Core pages
+    ("GET", "/", Public),
+    ("GET", "/index.php", Public),
+
+    // Authentication
+    ("GET", "/login.php", Public),
+    ("POST", "/login.php", Public),
+    ("GET", "/register.php", Public),
+    ("POST", "/register.php", Public),
+    ("GET", "/logout.php", Requires("game:play")),
+    ("GET", "/welcome.php", Requires("game:play")),
+
+    // Game core
+    ("GET", "/processes.php", Requires("game:play")),
+    ("POST", "/processes.php", Requires("process:start")),
+    ("GET", "/software.php", Requires("game:play")),
+    ("POST", "/software.php", Requires("process:start")),
+    ("GET", "/hardware.php", Requires("game:play")),
+    ("POST", "/hardware.php", Requires("game:play")),
+    ("GET", "/internet.php", Requires("game:play")),
+    ("POST", "/internet.php", Requires("process:start")),
+
+    // Missions & activities
+    ("GET", "/missions.php", Requires("game:play")),
+    ("POST", "/missions.php", Requires("mission:start")),
+    ("GET", "/research.php", Requires("game:play")),
+    ("POST", "/research.php", Requires("process:start")),
+    ("GET", "/university.php", Requires("game:play")),
+    ("POST", "/university.php", Requires("process:start")),
+
+    // Social
+    ("GET", "/clan.php", Requires("game:play")),
+    ("POST", "/clan.php", Requires("game:play")),
+    ("GET", "/war.php", Requires("game:play")),
+    ("POST", "/war.php", Requires("game:play")),
+    ("GET", "/ranking.php", Requires("game:play")),
+    ("GET", "/profile.php", Requires("profile:view")),
+    ("GET", "/mail.php", Requires("game:play")),
+    ("POST", "/mail.php", Requires("chat:send")),
+
+    // Financial
+    ("GET", "/bitcoin.php", Requires("game:play")),
+    ("POST", "/bitcoin.php", Requires("game:play")),
+    ("GET", "/finances.php", Requires("game:play")),
+
+    // Special
+    ("GET", "/ddos.php", Requires("game:play")),
+    ("POST", "/ddos.php", Requires("process:start")),
+    ("GET", "/doom.php", Requires("game:play")),
+    ("GET", "/riddle.php", Requires("game:play")),
+    ("POST", "/riddle.php", Requires("mission:start")),
+
+    // Utilities
+    ("GET", "/webserver.php", Requires("game:play")),
+    ("GET", "/log.php", Requires("game:play")),
+    ("POST", "/log.php", Requires("process:start")),
+    ("GET", "/settings.php", Requires("profile:view")),
+    ("POST", "/settings.php", Requires("profile:edit")),
+    ("GET", "/reset.php", Requires("profile:edit")),
+    ("GET", "/stats.php", Requires("game:play")),
+
+    // Ajax
+    ("GET", "/ajax.php", Requires("game:play")),
+    ("POST", "/ajax.php", Requires("game:play")),
+
+    // Static pages
+    ("GET", "/about.php", Public),
+    ("GET", "/privacy.php", Public),
+    ("GET", "/tos.php", Public),
+    ("GET", "/changelog.php", Public),
+    ("GET", "/legal.php", Public),
+
+    // JSON compat API (legacy_compat)
+    ("GET", "/legacy/ping", Public),
+    ("GET", "/legacy/api/status", Public),
+    ("GET", "/legacy/news/recent", Public),
+    ("GET", "/legacy/logs/recent", Requires("game:play")),
+    ("POST", "/legacy/auth/login", Public),
+    ("POST", "/legacy/auth/logout", Requires("game:play")),
+    ("GET", "/legacy/session", Requires("game:play")),
+    ("GET", "/legacy/process", Requires("game:play")),
+    ("POST", "/legacy/process/start", Requires("process:start")),
+    ("GET", "/legacy/process/{pid}", Requires("game:play")),
+    ("POST", "/legacy/process/{pid}/cancel", Requires("process:start")),
+    ("GET", "/legacy/hardware/info", Requires("game:play")),
+    ("POST", "/legacy/hardware/upgrade", Requires("game:play")),
+    ("POST", "/legacy/internet/scan", Requires("process:start")),
+    ("POST", "/legacy/internet/connect", Requires("process:start")),
+    ("GET", "/legacy/servers/available", Requires("game:play")),
+    ("GET", "/legacy/software/installed", Requires("game:play")),
+    ("GET", "/legacy/software/store", Requires("game:play")),
+    ("POST", "/legacy/software/{id}/start", Requires("process:start")),
+    ("POST", "/legacy/software/{id}/stop", Requires("process:start")),
+    ("POST", "/legacy/software/{id}/uninstall", Requires("process:start")),
+];
+
+/// Look up the permission for a (method, path) pair
+pub fn permission_for(method: &str, path: &str) -> Option<RoutePermission> {
+    LEGACY_ROUTE_PERMISSIONS
+        .iter()
+        .find(|(m, p, _)| m.eq_ignore_ascii_case(method) && *p == path)
+        .map(|(_, _, permission)| *permission)
+}
+
+/// Registered routes that have no permission mapping
+pub fn unmapped_routes<'a>(registered: &'a [(&'a str, &'a str)]) -> Vec<(&'a str, &'a str)> {
+    registered
+        .iter()
+        .filter(|(method, path)| permission_for(method, path).is_none())
+        .copied()
+        .collect()
+}
+
+/// RBAC middleware for legacy routes
+pub struct LegacyRbac;
+
+impl<S, B> Transform<S, ServiceRequest> for LegacyRbac
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LegacyRbacService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(LegacyRbacService {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct LegacyRbacService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for LegacyRbacService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let path = req.match_pattern().unwrap_or_else(|| req.path().to_string());
+            let user_id = req
+                .headers()
+                .get("Authorization")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .zip(req.app_data::<web::Data<AppState>>())
+                .and_then(|(token, state)| verify_jwt(token, &state.jwt_secret).ok())
+                .map(|claims| claims.sub);
+            let auth = req.app_data::<web::Data<AuthService>>().cloned();
+
+            let decision = authorize(req.method().as_str(), &path, user_id, |user_id, required| async move {
+                match auth {
+                    Some(auth) => auth.check_permission(&legacy_user_uuid(user_id), required).await,
+                    None => Err(anyhow::anyhow!("AuthService missing from app data")),
+                }
+            })
+            .await;
+
+            match decision {
+                Ok(()) => service.call(req).await.map(ServiceResponse::map_into_left_body),
+                Err(Denial::Unauthenticated) => Ok(deny(req, HttpResponse::Unauthorized(), "Authorization required")),
+                Err(Denial::Forbidden) => Ok(deny(req, HttpResponse::Forbidden(), "Access denied")),
+                Err(Denial::Unavailable) => Ok(deny(req, HttpResponse::ServiceUnavailable(), "Authorization unavailable")),
+            }
+        })
+    }
+}
+
+/// Why a legacy request was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denial {
+    /// No valid session on a route that needs one (401)
+    Unauthenticated,
+    /// The caller's roles lack the permission, or the route is unmapped (403)
+    Forbidden,
+    /// The permission check itself failed (503)
+    Unavailable,
+}
+
+/// Decide whether a caller may use a legacy route. `has_permission` checks
+/// the caller's roles and is only asked for signed-in callers on mapped,
+/// non-public routes.
+pub async fn authorize<F, Fut>(
+    method: &str,
+    path: &str,
+    user_id: Option<i64>,
+    has_permission: F,
+) -> Result<(), Denial>
+where
+    F: FnOnce(i64, &'static str) -> Fut,
+    Fut: Future<Output = anyhow::Result<bool>>,
+{
+    let required = match permission_for(method, path) {
+        Some(Public) => return Ok(()),
+        Some(Requires(required)) => required,
+        None => {
+            tracing::error!("Legacy route {} {} has no RBAC mapping, denying", method, path);
+            return Err(Denial::Forbidden);
+        }
+    };
+
+    let Some(user_id) = user_id else {
+        return Err(Denial::Unauthenticated);
+    };
+
+    match has_permission(user_id, required).await {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            tracing::warn!("User {} lacks {} for {}", user_id, required, path);
+            Err(Denial::Forbidden)
+        }
+        Err(e) => {
+            tracing::error!("Permission check failed for user {} on {}: {}", user_id, path, e);
+            Err(Denial::Unavailable)
+        }
+    }
+}
+
+fn deny<B>(
+    req: ServiceRequest,
+    mut builder: actix_web::HttpResponseBuilder,
+    message: &str,
+) -> ServiceResponse<EitherBody<B>> {
+    let response = builder
+        .json(serde_json::json!({
+            "success": false,
+            "message": message
+        }))
+        .map_into_right_body();
+
+    req.into_response(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::legacy_compat;
+    use crate::legacy_router;
+
+    fn registered() -> Vec<(&'static str, &'static str)> {
+        legacy_router::REGISTERED_ROUTES
+            .iter()
+            .chain(legacy_compat::REGISTERED_ROUTES)
+            .copied()
+            .collect()
+    }
+
+    /// Permissions of a role that can browse but not act, like `moderator`
+    async fn browse_only(_user_id: i64, permission: &'static str) -> anyhow::Result<bool> {
+        Ok(permission == "game:play")
+    }
+
+    #[test]
+    fn test_every_registered_route_has_permission() {
+        let registered = registered();
+        let missing = unmapped_routes(&registered);
+        assert!(missing.is_empty(), "legacy routes without RBAC mapping: {:?}", missing);
+    }
+
+    #[test]
+    fn test_no_stale_permission_entries() {
+        let registered = registered();
+        for (method, path, _) in LEGACY_ROUTE_PERMISSIONS {
+            assert!(
+                registered.iter().any(|(m, p)| m == method && p == path),
+                "permission mapped for unregistered route {} {}",
+                method,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_route_is_unmapped() {
+        assert_eq!(permission_for("GET", "/admin.php"), None);
+        assert_eq!(unmapped_routes(&[("GET", "/admin.php")]), vec![("GET", "/admin.php")]);
+    }
+
+    #[test]
+    fn test_game_actions_require_permissions() {
+        assert_eq!(permission_for("GET", "/login.php"), Some(Public));
+        assert_eq!(permission_for("post", "/processes.php"), Some(Requires("process:start")));
+        assert_eq!(permission_for("POST", "/settings.php"), Some(Requires("profile:edit")));
+        assert_eq!(permission_for("POST", "/legacy/process/start"), Some(Requires("process:start")));
+        assert_eq!(permission_for("POST", "/legacy/software/{id}/start"), Some(Requires("process:start")));
+    }
+
+    #[actix_web::test]
+    async fn test_legacy_compat_actions_are_forbidden_without_the_permission() {
+        for path in ["/legacy/process/start", "/legacy/software/{id}/start", "/legacy/internet/scan"] {
+            assert_eq!(authorize("POST", path, Some(7), browse_only).await, Err(Denial::Forbidden), "{}", path);
+        }
+        assert_eq!(authorize("POST", "/legacy/hardware/upgrade", Some(7), browse_only).await, Ok(()));
+        assert_eq!(authorize("GET", "/legacy/process/{pid}", Some(7), browse_only).await, Ok(()));
+    }
+
+    #[actix_web::test]
+    async fn test_legacy_compat_routes_need_a_session() {
+        assert_eq!(authorize("POST", "/legacy/process/start", None, browse_only).await, Err(Denial::Unauthenticated));
+        assert_eq!(authorize("POST", "/legacy/auth/login", None, browse_only).await, Ok(()));
+        assert_eq!(authorize("GET", "/legacy/admin", Some(7), browse_only).await, Err(Denial::Forbidden));
+    }
+}
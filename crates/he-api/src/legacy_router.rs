@@ -3,82 +3,136 @@
 //! This is the master router that provides 100% PHP compatibility
 
 use actix_web::{web, HttpResponse, HttpRequest};
+use he_cache::CacheKeys;
+use he_database::models::legacy_user_uuid;
+use std::collections::HashMap;
 use crate::AppState;
-
-/// Register ALL legacy PHP routes with their Rust implementations
-pub fn register_all_routes(cfg: &mut web::ServiceConfig) {
-    cfg
-        // ============= CORE PAGES =============
-        .route("/", web::get().to(index_handler))
-        .route("/index.php", web::get().to(index_handler))
-
-        // ============= AUTHENTICATION =============
-        .route("/login.php", web::get().to(login_page))
-        .route("/login.php", web::post().to(login_handler))
-        .route("/register.php", web::get().to(register_page))
-        .route("/register.php", web::post().to(register_handler))
-        .route("/logout.php", web::get().to(logout_handler))
-        .route("/welcome.php", web::get().to(welcome_handler))
-
-        // ============= GAME CORE =============
-        .route("/processes.php", web::get().to(processes_handler))
-        .route("/processes.php", web::post().to(processes_action))
-        .route("/software.php", web::get().to(software_handler))
-        .route("/software.php", web::post().to(software_action))
-        .route("/hardware.php", web::get().to(hardware_handler))
-        .route("/hardware.php", web::post().to(hardware_action))
-        .route("/internet.php", web::get().to(internet_handler))
-        .route("/internet.php", web::post().to(internet_action))
-
-        // ============= MISSIONS & ACTIVITIES =============
-        .route("/missions.php", web::get().to(missions_handler))
-        .route("/missions.php", web::post().to(missions_action))
-        .route("/research.php", web::get().to(research_handler))
-        .route("/research.php", web::post().to(research_action))
-        .route("/university.php", web::get().to(university_handler))
-        .route("/university.php", web::post().to(university_action))
-
-        // ============= SOCIAL =============
-        .route("/clan.php", web::get().to(clan_handler))
-        .route("/clan.php", web::post().to(clan_action))
-        .route("/war.php", web::get().to(war_handler))
-        .route("/war.php", web::post().to(war_action))
-        .route("/ranking.php", web::get().to(ranking_handler))
-        .route("/profile.php", web::get().to(profile_handler))
-        .route("/mail.php", web::get().to(mail_handler))
-        .route("/mail.php", web::post().to(mail_action))
-
-        // ============= FINANCIAL =============
-        .route("/bitcoin.php", web::get().to(bitcoin_handler))
-        .route("/bitcoin.php", web::post().to(bitcoin_action))
-        .route("/finances.php", web::get().to(finances_handler))
-
-        // ============= SPECIAL =============
-        .route("/ddos.php", web::get().to(ddos_handler))
-        .route("/ddos.php", web::post().to(ddos_action))
-        .route("/doom.php", web::get().to(doom_handler))
-        .route("/riddle.php", web::get().to(riddle_handler))
-        .route("/riddle.php", web::post().to(riddle_action))
-
-        // ============= UTILITIES =============
-        .route("/webserver.php", web::get().to(webserver_handler))
-        .route("/log.php", web::get().to(log_handler))
-        .route("/log.php", web::post().to(log_action))
-        .route("/settings.php", web::get().to(settings_handler))
-        .route("/settings.php", web::post().to(settings_action))
-        .route("/reset.php", web::get().to(reset_handler))
-        .route("/stats.php", web::get().to(stats_handler))
-
-        // ============= AJAX =============
-        .route("/ajax.php", web::get().to(ajax_handler))
-        .route("/ajax.php", web::post().to(ajax_handler))
-
-        // ============= STATIC PAGES =============
-        .route("/about.php", web::get().to(about_handler))
-        .route("/privacy.php", web::get().to(privacy_handler))
-        .route("/tos.php", web::get().to(tos_handler))
-        .route("/changelog.php", web::get().to(changelog_handler))
-        .route("/legal.php", web::get().to(legal_handler));
+use crate::http_cache::{CachePolicy, ConditionalGet};
+use crate::legacy_rbac::{unmapped_routes, LegacyRbac};
+
+macro_rules! legacy_routes {
+    (@method GET) => { web::get() };
+    (@method POST) => { web::post() };
+    (@route $method:ident $handler:ident) => { legacy_routes!(@method $method).to($handler) };
+    (@route $method:ident $handler:ident $policy:expr) => {
+        legacy_routes!(@method $method).to($handler).wrap(ConditionalGet::new($policy))
+    };
+    ($( $method:ident $path:literal => $handler:ident $([$policy:expr])? ),* $(,)?) => {
+        /// Every (method, path) pair registered by `register_all_routes`
+        pub const REGISTERED_ROUTES: &[(&str, &str)] = &[$((stringify!($method), $path)),*];
+
+        /// Register ALL legacy PHP routes with their Rust implementations
+        ///
+        /// Each route is wrapped in `LegacyRbac`; routes missing from the permission
+        /// map are reported here and denied at request time. Routes with a
+        /// cache policy revalidate inside it, so a 304 is only ever sent to a
+        /// caller RBAC let through.
+        pub fn register_all_routes(cfg: &mut web::ServiceConfig) {
+            for (method, path) in unmapped_routes(REGISTERED_ROUTES) {
+                tracing::error!("Legacy route {} {} has no RBAC mapping and will be denied", method, path);
+            }
+
+            $( cfg.route($path, legacy_routes!(@route $method $handler $($policy)?).wrap(LegacyRbac)); )*
+        }
+    };
+}
+
+fn query_param(req: &actix_web::dev::ServiceRequest, name: &str) -> Option<String> {
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()?
+        .into_inner()
+        .remove(name)
+}
+
+/// A player's profile, revalidated against their profile version. Other
+/// views (friends, search, edit) are not revalidated.
+fn profile_cache() -> CachePolicy {
+    CachePolicy::private().versioned_by(|req| {
+        if query_param(req, "view").is_some() {
+            return None;
+        }
+        let user_id = query_param(req, "id")?.parse::<i64>().ok()?;
+        Some(CacheKeys::user_profile(legacy_user_uuid(user_id)))
+    })
+}
+
+/// A server on the internet map, revalidated against its page version
+fn internet_cache() -> CachePolicy {
+    CachePolicy::private().versioned_by(|req| query_param(req, "ip").map(|ip| CacheKeys::server_info(&ip)))
+}
+
+legacy_routes! {
+    // ============= CORE PAGES =============
+    GET "/" => index_handler,
+    GET "/index.php" => index_handler,
+
+    // ============= AUTHENTICATION =============
+    GET "/login.php" => login_page,
+    POST "/login.php" => login_handler,
+    GET "/register.php" => register_page,
+    POST "/register.php" => register_handler,
+    GET "/logout.php" => logout_handler,
+    GET "/welcome.php" => welcome_handler,
+
+    // ============= GAME CORE =============
+    GET "/processes.php" => processes_handler,
+    POST "/processes.php" => processes_action,
+    GET "/software.php" => software_handler,
+    POST "/software.php" => software_action,
+    GET "/hardware.php" => hardware_handler,
+    POST "/hardware.php" => hardware_action,
+    GET "/internet.php" => internet_handler [internet_cache()],
+    POST "/internet.php" => internet_action,
+
+    // ============= MISSIONS & ACTIVITIES =============
+    GET "/missions.php" => missions_handler,
+    POST "/missions.php" => missions_action,
+    GET "/research.php" => research_handler,
+    POST "/research.php" => research_action,
+    GET "/university.php" => university_handler,
+    POST "/university.php" => university_action,
+
+    // ============= SOCIAL =============
+    GET "/clan.php" => clan_handler,
+    POST "/clan.php" => clan_action,
+    GET "/war.php" => war_handler,
+    POST "/war.php" => war_action,
+    GET "/ranking.php" => ranking_handler,
+    GET "/profile.php" => profile_handler [profile_cache()],
+    GET "/mail.php" => mail_handler,
+    POST "/mail.php" => mail_action,
+
+    // ============= FINANCIAL =============
+    GET "/bitcoin.php" => bitcoin_handler,
+    POST "/bitcoin.php" => bitcoin_action,
+    GET "/finances.php" => finances_handler,
+
+    // ============= SPECIAL =============
+    GET "/ddos.php" => ddos_handler,
+    POST "/ddos.php" => ddos_action,
+    GET "/doom.php" => doom_handler,
+    GET "/riddle.php" => riddle_handler,
+    POST "/riddle.php" => riddle_action,
+
+    // ============= UTILITIES =============
+    GET "/webserver.php" => webserver_handler,
+    GET "/log.php" => log_handler,
+    POST "/log.php" => log_action,
+    GET "/settings.php" => settings_handler,
+    POST "/settings.php" => settings_action,
+    GET "/reset.php" => reset_handler,
+    GET "/stats.php" => stats_handler,
+
+    // ============= AJAX =============
+    GET "/ajax.php" => ajax_handler,
+    POST "/ajax.php" => ajax_handler,
+
+    // ============= STATIC PAGES =============
+    GET "/about.php" => about_handler,
+    GET "/privacy.php" => privacy_handler,
+    GET "/tos.php" => tos_handler,
+    GET "/changelog.php" => changelog_handler,
+    GET "/legal.php" => legal_handler,
 }
 
 // ============= HANDLER IMPLEMENTATIONS =============
@@ -2,6 +2,64 @@
 
 pub mod middleware;
 pub mod handlers;
+pub mod admin_dashboard;
+pub mod completion;
+pub mod complications;
+pub mod banking;
+pub mod bounty;
+pub mod clan_server;
+pub mod clan_chat;
+pub mod rules;
+pub mod balance;
+pub mod formula_scripts;
+pub mod username;
+pub mod cache_warm;
+pub mod contracts;
+pub mod referrals;
+pub mod reservations;
+pub mod reports;
+pub mod entitlements;
+pub mod account_gating;
+pub mod activity;
+pub mod login_history;
+pub mod cancellation;
+pub mod ledger;
+pub mod bulk_ops;
+pub mod economy_stream;
+pub mod mission_gen;
+pub mod chat_filter;
+pub mod coop;
+pub mod heist;
+pub mod dns;
+pub mod doom;
+pub mod event_schemas;
+pub mod forum_sync;
+pub mod webhooks;
+pub mod field_encryption;
+pub mod terminal;
+pub mod gateway;
+pub mod honeypot;
+pub mod piracy;
+pub mod hosting;
+pub mod scheduler;
+pub mod server_browser;
+pub mod health;
+pub mod hardware_wear;
+pub mod ip_reset;
+pub mod outbox;
+pub mod quota;
+pub mod live_ops;
+pub mod drain;
+pub mod panic_levers;
+pub mod request_log;
+pub mod status;
+pub mod tutorial;
+pub mod puzzles;
+pub mod http_cache;
+pub mod content_versions;
+pub mod war_spectator;
+pub mod ws_acl;
+pub mod plugins;
 pub mod routes;
 pub mod config;
 pub mod openapi;
@@ -0,0 +1,668 @@
+//! Live-ops state of this API node
+//!
+//! Feature flags, node drains, announcements, alert threshold overrides and
+//! formula scripts are changed from the admin console and stored in Postgres.
+//! Every node reloads them on an interval, the same way process kill-switches
+//! are shared, so a change made on one node reaches all of them within a few
+//! seconds.
+//! Threshold overrides are per environment; a node only applies those of the
+//! environment it runs in. Nodes also advertise the gateway region they serve,
+//! which [`crate::gateway`] discovery reads.
+//!
+//! The reloader also takes a health sample (loop tick durations, running
+//! processes, login rate) and publishes it to console sessions.
+
+use chrono::{DateTime, Utc};
+use he_core::config::Environment;
+use he_database::queries::ProcessQueries;
+use he_monitoring::{AlertManager, AlertMetric, AlertThresholds, Threshold, ThresholdError};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch, RwLock};
+
+pub const ANNOUNCEMENT_PRIORITIES: &[&str] = &["info", "warning", "critical"];
+/// Region of a node that does not set `GATEWAY_REGION`
+pub const DEFAULT_REGION: &str = "default";
+
+/// Environment names as stored, the same ones `APP_ENVIRONMENT` takes
+pub fn environment_name(environment: &Environment) -> &'static str {
+    match environment {
+        Environment::Development => "development",
+        Environment::Staging => "staging",
+        Environment::Production => "production",
+    }
+}
+
+pub fn parse_environment(name: &str) -> Option<Environment> {
+    match name.to_lowercase().as_str() {
+        "development" | "dev" => Some(Environment::Development),
+        "staging" | "stage" => Some(Environment::Staging),
+        "production" | "prod" => Some(Environment::Production),
+        _ => None,
+    }
+}
+
+const LOGIN_WINDOW: Duration = Duration::from_secs(60);
+/// Announcements buffered for slow subscribers before they start lagging
+const ANNOUNCEMENT_BUFFER: usize = 64;
+
+static LOGINS: Lazy<Mutex<VecDeque<Instant>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+static TICKS: Lazy<Mutex<BTreeMap<&'static str, TickStats>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Count a successful login towards the login rate
+pub fn record_login() {
+    let now = Instant::now();
+    let mut logins = LOGINS.lock().unwrap();
+    logins.push_back(now);
+    prune_logins(&mut logins, now);
+}
+
+fn logins_per_minute() -> usize {
+    let mut logins = LOGINS.lock().unwrap();
+    prune_logins(&mut logins, Instant::now());
+    logins.len()
+}
+
+fn prune_logins(logins: &mut VecDeque<Instant>, now: Instant) {
+    while logins.front().is_some_and(|at| now.duration_since(*at) > LOGIN_WINDOW) {
+        logins.pop_front();
+    }
+}
+
+/// Durations of one background loop's iterations
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct TickStats {
+    pub last_ms: f64,
+    /// Slowest iteration since the previous health sample
+    pub max_ms: f64,
+    pub count: u64,
+}
+
+/// Record how long one iteration of a background loop took
+pub fn record_tick(loop_name: &'static str, took: Duration) {
+    let ms = took.as_secs_f64() * 1000.0;
+    let mut ticks = TICKS.lock().unwrap();
+    let stats = ticks.entry(loop_name).or_default();
+    stats.last_ms = ms;
+    stats.max_ms = stats.max_ms.max(ms);
+    stats.count += 1;
+}
+
+/// Current tick stats; the maximum starts over for the next sample
+fn take_ticks() -> BTreeMap<String, TickStats> {
+    let mut ticks = TICKS.lock().unwrap();
+    let snapshot = ticks.iter().map(|(name, stats)| (name.to_string(), *stats)).collect();
+    for stats in ticks.values_mut() {
+        stats.max_ms = stats.last_ms;
+    }
+    snapshot
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthSample {
+    pub node: String,
+    pub region: String,
+    pub sampled_at: DateTime<Utc>,
+    pub draining: bool,
+    pub ticks: BTreeMap<String, TickStats>,
+    /// `None` if the database could not be read
+    pub active_processes: Option<i64>,
+    pub logins_per_minute: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub enabled: bool,
+    pub updated_by: Option<i64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    pub node_id: String,
+    pub region: String,
+    pub ws_url: Option<String>,
+    pub draining: bool,
+    pub drain_requested_by: Option<i64>,
+    pub drain_requested_at: Option<DateTime<Utc>>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// One change to an environment's alert thresholds
+#[derive(Debug, Clone, Serialize)]
+pub struct ThresholdChange {
+    pub id: i64,
+    pub environment: String,
+    pub metric: String,
+    /// `None` when the default was in force
+    pub old_warning: Option<f64>,
+    pub old_critical: Option<f64>,
+    /// `None` when the override was removed
+    pub new_warning: Option<f64>,
+    pub new_critical: Option<f64>,
+    pub changed_by: Option<i64>,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Announcement {
+    pub id: i64,
+    pub title: String,
+    pub content: String,
+    pub priority: String,
+}
+
+pub struct LiveOps {
+    node_id: String,
+    environment: Environment,
+    /// Gateway region this node serves
+    region: String,
+    /// Public WebSocket URL of this node's regional gateway
+    ws_url: Option<String>,
+    flags: RwLock<Arc<HashMap<String, FeatureFlag>>>,
+    draining: watch::Sender<bool>,
+    /// Highest announcement id delivered by this node
+    last_announcement: AtomicI64,
+    announcements: broadcast::Sender<Announcement>,
+    health: watch::Sender<Option<HealthSample>>,
+}
+
+impl LiveOps {
+    pub fn new(node_id: String, environment: Environment) -> Self {
+        Self {
+            node_id,
+            environment,
+            region: DEFAULT_REGION.to_string(),
+            ws_url: None,
+            flags: RwLock::new(Arc::new(HashMap::new())),
+            draining: watch::channel(false).0,
+            last_announcement: AtomicI64::new(i64::MAX),
+            announcements: broadcast::channel(ANNOUNCEMENT_BUFFER).0,
+            health: watch::channel(None).0,
+        }
+    }
+
+    /// Advertise `ws_url` as the gateway of `region`
+    pub fn with_gateway(mut self, region: String, ws_url: Option<String>) -> Self {
+        self.region = region;
+        self.ws_url = ws_url;
+        self
+    }
+
+    /// Node id from `NODE_ID`, falling back to `HOSTNAME`, environment from
+    /// `APP_ENVIRONMENT`, production when unset, and gateway from
+    /// `GATEWAY_REGION` and `GATEWAY_URL`
+    pub fn from_env() -> Self {
+        let node_id = std::env::var("NODE_ID")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| format!("he-api-{}", uuid::Uuid::new_v4().simple()));
+        let environment = std::env::var("APP_ENVIRONMENT")
+            .ok()
+            .and_then(|name| parse_environment(&name))
+            .unwrap_or(Environment::Production);
+        let region = std::env::var("GATEWAY_REGION").unwrap_or_else(|_| DEFAULT_REGION.to_string());
+        Self::new(node_id, environment).with_gateway(region, std::env::var("GATEWAY_URL").ok())
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// A draining node reports not-ready and refuses new WebSocket sessions
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Changes of this node's drain flag, for sessions to migrate away
+    pub fn subscribe_draining(&self) -> watch::Receiver<bool> {
+        self.draining.subscribe()
+    }
+
+    pub async fn flags(&self) -> Arc<HashMap<String, FeatureFlag>> {
+        self.flags.read().await.clone()
+    }
+
+    /// Unknown flags are off
+    pub async fn is_enabled(&self, flag: &str) -> bool {
+        self.flags().await.get(flag).is_some_and(|f| f.enabled)
+    }
+
+    pub fn subscribe_health(&self) -> watch::Receiver<Option<HealthSample>> {
+        self.health.subscribe()
+    }
+
+    pub fn subscribe_announcements(&self) -> broadcast::Receiver<Announcement> {
+        self.announcements.subscribe()
+    }
+
+    /// Register this node and load the current state. A restarted node comes
+    /// back undrained; announcements made before it started are not replayed.
+    pub async fn start(&self, pool: &PgPool) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO live_ops_nodes (node_id, region, ws_url) VALUES ($1, $2, $3)
+            ON CONFLICT (node_id) DO UPDATE
+                SET draining = FALSE, drain_requested_by = NULL, drain_requested_at = NULL,
+                    region = EXCLUDED.region, ws_url = EXCLUDED.ws_url,
+                    started_at = NOW(), last_seen_at = NOW()
+            "#,
+            self.node_id,
+            self.region,
+            self.ws_url
+        )
+        .execute(pool)
+        .await?;
+
+        let last = sqlx::query_scalar!(
+            r#"SELECT COALESCE(MAX(id), 0) AS "id!" FROM live_ops_announcements"#
+        )
+        .fetch_one(pool)
+        .await?;
+        self.last_announcement.store(last, Ordering::Relaxed);
+
+        self.reload(pool).await
+    }
+
+    /// Refresh this node's heartbeat and drain flag, the feature flags and
+    /// formula scripts, and deliver announcements made since the last reload
+    pub async fn reload(&self, pool: &PgPool) -> anyhow::Result<()> {
+        let draining = sqlx::query_scalar!(
+            "UPDATE live_ops_nodes SET last_seen_at = NOW() WHERE node_id = $1 RETURNING draining",
+            self.node_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        if let Some(draining) = draining {
+            self.set_local_draining(draining);
+        }
+
+        let flags: HashMap<String, FeatureFlag> = sqlx::query_as!(
+            FeatureFlag,
+            "SELECT name, enabled, updated_by, updated_at FROM feature_flags"
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|flag| (flag.name.clone(), flag))
+        .collect();
+        *self.flags.write().await = Arc::new(flags);
+
+        if let Err(e) = crate::formula_scripts::reload(pool).await? {
+            tracing::error!("Keeping the running formula scripts: {}", e);
+        }
+
+        let after = self.last_announcement.load(Ordering::Relaxed);
+        let announcements = sqlx::query_as!(
+            Announcement,
+            "SELECT id, title, content, priority FROM live_ops_announcements WHERE id > $1 ORDER BY id",
+            after
+        )
+        .fetch_all(pool)
+        .await?;
+
+        for announcement in announcements {
+            self.last_announcement.fetch_max(announcement.id, Ordering::Relaxed);
+            // No receivers just means nobody is connected to this node
+            let _ = self.announcements.send(announcement);
+        }
+
+        self.reload_thresholds(pool).await
+    }
+
+    /// Apply this environment's threshold overrides to the alert manager. A
+    /// set that no longer validates, say after bounds were tightened, is
+    /// logged and the thresholds in force are kept.
+    async fn reload_thresholds(&self, pool: &PgPool) -> anyhow::Result<()> {
+        let thresholds = match self.thresholds(pool, self.environment.clone()).await? {
+            Ok(thresholds) => thresholds,
+            Err(e) => {
+                tracing::error!("Ignoring invalid alert thresholds: {}", e);
+                return Ok(());
+            }
+        };
+
+        if *AlertManager::thresholds() != thresholds {
+            tracing::info!(
+                "Alert thresholds for {} updated, overridden: {:?}",
+                environment_name(&self.environment),
+                thresholds.overridden
+            );
+            AlertManager::set_thresholds(thresholds);
+        }
+        Ok(())
+    }
+
+    /// `environment`'s defaults with its stored overrides applied
+    pub async fn thresholds(
+        &self,
+        pool: &PgPool,
+        environment: Environment,
+    ) -> anyhow::Result<Result<AlertThresholds, ThresholdError>> {
+        let rows = sqlx::query!(
+            "SELECT metric, warning, critical FROM alert_threshold_overrides WHERE environment = $1",
+            environment_name(&environment)
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let overrides = rows.into_iter().filter_map(|row| match AlertMetric::from_str(&row.metric) {
+            Some(metric) => Some((metric, Threshold::new(row.warning, row.critical))),
+            None => {
+                tracing::warn!("Ignoring threshold override for unknown metric {}", row.metric);
+                None
+            }
+        });
+        Ok(AlertThresholds::with_overrides(environment, overrides))
+    }
+
+    /// Override one metric's threshold in `environment` and record the change
+    pub async fn set_threshold(
+        &self,
+        pool: &PgPool,
+        environment: &Environment,
+        metric: AlertMetric,
+        threshold: Threshold,
+        admin_id: i64,
+    ) -> anyhow::Result<Result<(), ThresholdError>> {
+        if let Err(e) = he_monitoring::thresholds::validate(metric, &threshold) {
+            return Ok(Err(e));
+        }
+
+        let mut tx = pool.begin().await?;
+        let old = sqlx::query!(
+            r#"
+            SELECT warning, critical FROM alert_threshold_overrides
+            WHERE environment = $1 AND metric = $2
+            FOR UPDATE
+            "#,
+            environment_name(environment),
+            metric.as_str()
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO alert_threshold_overrides (environment, metric, warning, critical, updated_by)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (environment, metric) DO UPDATE
+                SET warning = EXCLUDED.warning, critical = EXCLUDED.critical,
+                    updated_by = EXCLUDED.updated_by, updated_at = NOW()
+            "#,
+            environment_name(environment),
+            metric.as_str(),
+            threshold.warning,
+            threshold.critical,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO alert_threshold_changes
+                (environment, metric, old_warning, old_critical, new_warning, new_critical, changed_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            environment_name(environment),
+            metric.as_str(),
+            old.as_ref().map(|old| old.warning),
+            old.as_ref().map(|old| old.critical),
+            Some(threshold.warning),
+            Some(threshold.critical),
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.reload_thresholds(pool).await?;
+        Ok(Ok(()))
+    }
+
+    /// Put one metric back on its default; false if it was not overridden
+    pub async fn clear_threshold(
+        &self,
+        pool: &PgPool,
+        environment: &Environment,
+        metric: AlertMetric,
+        admin_id: i64,
+    ) -> anyhow::Result<bool> {
+        let mut tx = pool.begin().await?;
+        let old = sqlx::query!(
+            r#"
+            DELETE FROM alert_threshold_overrides
+            WHERE environment = $1 AND metric = $2
+            RETURNING warning, critical
+            "#,
+            environment_name(environment),
+            metric.as_str()
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(old) = old else {
+            tx.rollback().await?;
+            return Ok(false);
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO alert_threshold_changes (environment, metric, old_warning, old_critical, changed_by)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            environment_name(environment),
+            metric.as_str(),
+            old.warning,
+            old.critical,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.reload_thresholds(pool).await?;
+        Ok(true)
+    }
+
+    /// Latest threshold changes in `environment`, newest first
+    pub async fn threshold_changes(
+        &self,
+        pool: &PgPool,
+        environment: &Environment,
+        limit: i64,
+    ) -> anyhow::Result<Vec<ThresholdChange>> {
+        let changes = sqlx::query_as!(
+            ThresholdChange,
+            r#"
+            SELECT id, environment, metric, old_warning, old_critical, new_warning, new_critical,
+                   changed_by, changed_at
+            FROM alert_threshold_changes
+            WHERE environment = $1
+            ORDER BY changed_at DESC, id DESC
+            LIMIT $2
+            "#,
+            environment_name(environment),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(changes)
+    }
+
+    fn set_local_draining(&self, draining: bool) {
+        if self.draining.send_replace(draining) != draining {
+            if draining {
+                tracing::warn!("Node {} is draining", self.node_id);
+            } else {
+                tracing::info!("Node {} is no longer draining", self.node_id);
+            }
+        }
+    }
+
+    pub async fn set_flag(&self, pool: &PgPool, name: &str, enabled: bool, admin_id: i64) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO feature_flags (name, enabled, updated_by) VALUES ($1, $2, $3)
+            ON CONFLICT (name) DO UPDATE
+                SET enabled = EXCLUDED.enabled, updated_by = EXCLUDED.updated_by, updated_at = NOW()
+            "#,
+            name,
+            enabled,
+            admin_id
+        )
+        .execute(pool)
+        .await?;
+
+        self.reload(pool).await
+    }
+
+    /// Start or stop draining `node_id`; false if no such node has registered
+    pub async fn set_draining(&self, pool: &PgPool, node_id: &str, draining: bool, admin_id: i64) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE live_ops_nodes
+            SET draining = $2,
+                drain_requested_by = CASE WHEN $2 THEN $3 ELSE NULL END,
+                drain_requested_at = CASE WHEN $2 THEN NOW() ELSE NULL END
+            WHERE node_id = $1
+            "#,
+            node_id,
+            draining,
+            admin_id
+        )
+        .execute(pool)
+        .await?;
+
+        if node_id == self.node_id {
+            self.set_local_draining(draining);
+        }
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Drain this node ahead of a restart. No operator asked for it, so none
+    /// is recorded; the node is marked draining locally even if the database
+    /// cannot be reached.
+    pub async fn drain_for_restart(&self, pool: &PgPool) -> anyhow::Result<()> {
+        self.set_local_draining(true);
+        sqlx::query!(
+            r#"
+            UPDATE live_ops_nodes
+            SET draining = TRUE, drain_requested_by = NULL, drain_requested_at = NOW()
+            WHERE node_id = $1
+            "#,
+            self.node_id
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Queue an announcement for every node; returns its id
+    pub async fn announce(
+        &self,
+        pool: &PgPool,
+        title: &str,
+        content: &str,
+        priority: &str,
+        admin_id: i64,
+    ) -> anyhow::Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO live_ops_announcements (title, content, priority, created_by)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            title,
+            content,
+            priority,
+            admin_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        self.reload(pool).await?;
+        Ok(id)
+    }
+
+    pub async fn nodes(&self, pool: &PgPool) -> anyhow::Result<Vec<NodeStatus>> {
+        let nodes = sqlx::query_as!(
+            NodeStatus,
+            r#"
+            SELECT node_id, region, ws_url, draining, drain_requested_by, drain_requested_at, last_seen_at
+            FROM live_ops_nodes
+            ORDER BY node_id
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(nodes)
+    }
+
+    async fn sample(&self, pool: &PgPool) -> HealthSample {
+        let active_processes = ProcessQueries::count_running(pool)
+            .await
+        .map_err(|e| tracing::warn!("Failed to count active processes: {}", e))
+        .ok();
+
+        HealthSample {
+            node: self.node_id.clone(),
+            region: self.region.clone(),
+            sampled_at: Utc::now(),
+            draining: self.is_draining(),
+            ticks: take_ticks(),
+            active_processes,
+            logins_per_minute: logins_per_minute(),
+        }
+    }
+
+    /// Reload shared state and publish a health sample every `interval`
+    pub fn spawn_reloader(self: Arc<Self>, pool: PgPool, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.reload(&pool).await {
+                    tracing::error!("Failed to reload live-ops state: {}", e);
+                }
+                let sample = self.sample(&pool).await;
+                self.health.send_replace(Some(sample));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_max_resets_per_sample() {
+        record_tick("test_loop", Duration::from_millis(30));
+        record_tick("test_loop", Duration::from_millis(10));
+
+        let first = take_ticks()["test_loop"];
+        assert_eq!(first.last_ms, 10.0);
+        assert_eq!(first.max_ms, 30.0);
+
+        let second = take_ticks()["test_loop"];
+        assert_eq!(second.max_ms, 10.0);
+        assert!(second.count >= 2);
+    }
+}
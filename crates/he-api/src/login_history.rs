@@ -0,0 +1,258 @@
+//! Login history
+//!
+//! Sign-in attempts on known accounts go to the audit log and are projected
+//! into `login_history`, the read model behind `/api/account/logins`. The
+//! player sees when, from roughly where and on what device each attempt was
+//! made and whether it succeeded. Addresses are masked; only their blind index
+//! is kept, for the security tooling to correlate.
+//!
+//! Disowning a login ("this wasn't me") drops every session of the account,
+//! requires a new password set with a mailed reset token, and is logged as
+//! `LoginDisowned`.
+
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use he_database::queries::{LoginHistoryQueries, LoginHistoryRow, NewLogin};
+use he_helix_security::IpPolicy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::net::IpAddr;
+use crate::referrals;
+
+pub const DEFAULT_LIMIT: i64 = 25;
+pub const MAX_LIMIT: i64 = 100;
+const MAX_USER_AGENT_LEN: usize = 256;
+
+/// What the client told us about itself when signing in
+#[derive(Debug, Clone)]
+pub struct LoginContext {
+    pub ip: IpAddr,
+    pub user_agent: Option<String>,
+    pub languages: Option<String>,
+    pub device_id: Option<String>,
+}
+
+impl LoginContext {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        Self {
+            ip: req.peer_addr().map(|addr| addr.ip()).unwrap_or(IpAddr::from([127, 0, 0, 1])),
+            user_agent: header("User-Agent").map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect()),
+            languages: header("Accept-Language"),
+            device_id: header(referrals::DEVICE_HEADER),
+        }
+    }
+}
+
+/// The address with its host part hidden: the first two IPv4 octets or the
+/// first three IPv6 groups
+pub fn mask_ip(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, _, _] = ip.octets();
+            format!("{}.{}.*.*", a, b)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return mask_ip(IpAddr::V4(ip));
+            }
+            let [a, b, c, ..] = ip.segments();
+            format!("{:x}:{:x}:{:x}:*", a, b, c)
+        }
+    }
+}
+
+/// "Browser on OS" from a user agent, `None` if neither is recognised
+pub fn describe_device(user_agent: &str) -> Option<String> {
+    // Order matters: Edge and Opera also claim Chrome, Chrome claims Safari
+    const BROWSERS: &[(&str, &str)] = &[
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ];
+    const SYSTEMS: &[(&str, &str)] = &[
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ];
+    let find = |table: &[(&str, &'static str)]| {
+        table.iter().find(|(marker, _)| user_agent.contains(marker)).map(|(_, name)| *name)
+    };
+
+    match (find(BROWSERS), find(SYSTEMS)) {
+        (Some(browser), Some(system)) => Some(format!("{} on {}", browser, system)),
+        (Some(browser), None) => Some(browser.to_string()),
+        (None, Some(system)) => Some(format!("Unknown browser on {}", system)),
+        (None, None) => None,
+    }
+}
+
+/// The client's device identifier hashed, or else a hash of its user agent
+/// and languages
+pub fn fingerprint(context: &LoginContext) -> Option<String> {
+    if let Some(hash) = context.device_id.as_deref().and_then(referrals::device_hash) {
+        return Some(hash);
+    }
+    let user_agent = context.user_agent.as_deref()?;
+    let mut hasher = Sha256::new();
+    hasher.update(user_agent.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(context.languages.as_deref().unwrap_or("").as_bytes());
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// Add an attempt on `user_id`'s account to the read model; the caller logs
+/// the matching audit event. `failure_reason` is `None` for a success.
+pub async fn record(
+    pool: &PgPool,
+    policy: &IpPolicy,
+    user_id: i64,
+    context: &LoginContext,
+    failure_reason: Option<&str>,
+) -> anyhow::Result<()> {
+    let geo = policy.geo(context.ip);
+    let ip_masked = mask_ip(context.ip);
+    let ip_index = he_database::encryption::ip_index(&context.ip.to_string());
+    let device = context.user_agent.as_deref().and_then(describe_device);
+    let fingerprint = fingerprint(context);
+
+    LoginHistoryQueries::record(
+        pool,
+        &NewLogin {
+            user_id,
+            ip_masked: &ip_masked,
+            ip_index: ip_index.as_deref(),
+            country: geo.country.as_deref(),
+            network: geo.as_org.as_deref(),
+            user_agent: context.user_agent.as_deref(),
+            device: device.as_deref(),
+            fingerprint: fingerprint.as_deref(),
+            success: failure_reason.is_none(),
+            failure_reason,
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Position in the history: the last entry already seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LoginCursor {
+    pub at: DateTime<Utc>,
+    pub id: i64,
+}
+
+/// Opaque to clients: `<microseconds>.<id>`
+impl From<LoginCursor> for String {
+    fn from(cursor: LoginCursor) -> String {
+        format!("{}.{}", cursor.at.timestamp_micros(), cursor.id)
+    }
+}
+
+impl TryFrom<String> for LoginCursor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (micros, id) = value.split_once('.').ok_or("malformed cursor")?;
+        let at = micros
+            .parse::<i64>()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or("malformed cursor")?;
+        let id = id.parse::<i64>().map_err(|_| "malformed cursor")?;
+        Ok(Self { at, id })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginPage {
+    pub entries: Vec<LoginHistoryRow>,
+    /// Pass back to get the next page; `None` on the last page
+    pub next_cursor: Option<LoginCursor>,
+}
+
+/// One page of the player's logins, newest first, after `cursor`
+pub async fn page(pool: &PgPool, user_id: i64, cursor: Option<LoginCursor>, limit: i64) -> anyhow::Result<LoginPage> {
+    let limit = limit.clamp(1, MAX_LIMIT);
+    let mut entries =
+        LoginHistoryQueries::list(pool, user_id, cursor.map(|cursor| (cursor.at, cursor.id)), limit + 1).await?;
+
+    let more = entries.len() as i64 > limit;
+    entries.truncate(limit as usize);
+    let next_cursor = if more {
+        entries.last().map(|entry| LoginCursor { at: entry.at, id: entry.id })
+    } else {
+        None
+    };
+    Ok(LoginPage { entries, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_ip() {
+        assert_eq!(mask_ip("203.0.113.42".parse().unwrap()), "203.0.*.*");
+        assert_eq!(mask_ip("2001:db8:85a3::8a2e:370:7334".parse().unwrap()), "2001:db8:85a3:*");
+        assert_eq!(mask_ip("::ffff:198.51.100.7".parse().unwrap()), "198.51.*.*");
+    }
+
+    #[test]
+    fn test_describe_device() {
+        let firefox = "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:120.0) Gecko/20100101 Firefox/120.0";
+        let edge = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) \
+                    Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0";
+        let safari = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 \
+                      (KHTML, like Gecko) Version/17.0 Mobile/15E148 Safari/604.1";
+        assert_eq!(describe_device(firefox).as_deref(), Some("Firefox on Windows"));
+        assert_eq!(describe_device(edge).as_deref(), Some("Edge on macOS"));
+        assert_eq!(describe_device(safari).as_deref(), Some("Safari on iOS"));
+        assert_eq!(describe_device("curl/8.4.0"), None);
+    }
+
+    #[test]
+    fn test_fingerprint_prefers_device_id() {
+        let mut context = LoginContext {
+            ip: IpAddr::from([127, 0, 0, 1]),
+            user_agent: Some("curl/8.4.0".to_string()),
+            languages: Some("en-GB".to_string()),
+            device_id: None,
+        };
+        let by_agent = fingerprint(&context).unwrap();
+        context.languages = Some("de-DE".to_string());
+        assert_ne!(fingerprint(&context).unwrap(), by_agent);
+
+        context.device_id = Some("device-1".to_string());
+        assert_eq!(fingerprint(&context), referrals::device_hash("device-1"));
+        context.device_id = None;
+        context.user_agent = None;
+        assert_eq!(fingerprint(&context), None);
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = LoginCursor { at: DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(), id: 42 };
+        let text = String::from(cursor);
+        assert_eq!(text, "1700000000123456.42");
+        assert_eq!(LoginCursor::try_from(text), Ok(cursor));
+        assert!(LoginCursor::try_from("42".to_string()).is_err());
+    }
+}
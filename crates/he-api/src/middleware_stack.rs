@@ -2,12 +2,14 @@ use anyhow::{anyhow, Result};
 //! Production middleware stack with auth, rate limiting, and security
 
 use actix_web::{
-    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    body::{BodySize, EitherBody, MessageBody},
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
     Error, HttpMessage, HttpResponse, web,
-    http::StatusCode,
+    http::{header, StatusCode},
 };
 use std::future::{Ready, ready};
 use std::pin::Pin;
+use std::rc::Rc;
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 use std::collections::HashMap;
@@ -15,6 +17,16 @@ use std::sync::{Arc, Mutex};
 use futures_util::future::LocalBoxFuture;
 use serde::{Deserialize, Serialize};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use he_helix_security::{AuditLogger, IpPolicy, PolicyScope};
+use crate::panic_levers::{
+    is_registration_request, needs_captcha, token_issued_at, Lever, PanicLevers, CAPTCHA_HEADER,
+};
+use crate::request_log::{
+    redact_body, redact_headers, redact_path, redact_query, Detail, RequestLog, CAPTURE_LIMIT,
+};
+use he_core::context::{parse_locale, parse_traceparent, RequestContext};
+use he_core::RequestId;
+use tracing::Instrument;
 
 /// JWT claims structure
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,7 +57,18 @@ impl AuthMiddleware {
                 "/health".to_string(),
                 "/api/login".to_string(),
                 "/api/register".to_string(),
+                // Email change revoke links must work while signed out
+                "/api/account/email/revoke".to_string(),
                 "/metrics".to_string(),
+                // Restart drains, polled by the orchestrator; starting one
+                // is limited to loopback
+                "/drain".to_string(),
+                // Public status page and /status.json
+                "/status".to_string(),
+                // Public clan war spectating
+                "/spectate/".to_string(),
+                // Payment provider webhooks carry a signature instead
+                "/api/webhooks/payments".to_string(),
             ],
         }
     }
@@ -100,41 +123,37 @@ where
         }
 
         // Extract and validate JWT
-        let auth_header = req.headers().get("Authorization");
-
-        let jwt_secret = self.jwt_secret.clone();
-        let fut = self.service.call(req);
-
-        Box::pin(async move {
-            match auth_header {
-                Some(header_value) => {
-                    let header_str = header_value.to_str().map_err(|_| {
-                        actix_web::error::ErrorUnauthorized("Invalid authorization header")
-                    })?;
-
-                    if !header_str.starts_with("Bearer ") {
-                        return Err(actix_web::error::ErrorUnauthorized("Invalid token format"));
-                    }
-
-                    let token = &header_str[7..];
-                    let key = DecodingKey::from_secret(jwt_secret.as_bytes());
-                    let validation = Validation::default();
+        let auth_header = match req.headers().get("Authorization") {
+            Some(header_value) => header_value.to_str().map(str::to_string).map_err(|_| {
+                actix_web::error::ErrorUnauthorized("Invalid authorization header")
+            }),
+            None => Err(actix_web::error::ErrorUnauthorized("Authorization required")),
+        };
+
+        let claims = auth_header.and_then(|header_str| {
+            let token = header_str
+                .strip_prefix("Bearer ")
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid token format"))?;
+            let key = DecodingKey::from_secret(self.jwt_secret.as_bytes());
+            let validation = Validation::default();
+
+            decode::<Claims>(token, &key, &validation)
+                .map(|token_data| token_data.claims)
+                .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid or expired token"))
+        });
 
-                    match decode::<Claims>(token, &key, &validation) {
-                        Ok(token_data) => {
-                            // Token is valid, proceed
-                            fut.await
-                        }
-                        Err(_) => {
-                            Err(actix_web::error::ErrorUnauthorized("Invalid or expired token"))
-                        }
-                    }
-                }
-                None => {
-                    Err(actix_web::error::ErrorUnauthorized("Authorization required"))
-                }
+        match claims {
+            Ok(claims) => {
+                // Token is valid: hand the user to inner middleware and handlers
+                req.extensions_mut().insert(AuthedUser {
+                    id: claims.sub,
+                    token_issued: Instant::now(),
+                });
+                let fut = self.service.call(req);
+                Box::pin(async move { fut.await })
             }
-        })
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
     }
 }
 
@@ -327,4 +346,451 @@ where
             Ok(res)
         })
     }
-}
\ No newline at end of file
+}
+/// IP policy guard - rejects requests from denied CIDRs, ASNs and countries
+pub struct IpPolicyGuard {
+    policy: web::Data<IpPolicy>,
+    audit: web::Data<AuditLogger>,
+}
+
+impl IpPolicyGuard {
+    pub fn new(policy: web::Data<IpPolicy>, audit: web::Data<AuditLogger>) -> Self {
+        Self { policy, audit }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for IpPolicyGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = IpPolicyGuardService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IpPolicyGuardService {
+            service: Rc::new(service),
+            policy: self.policy.clone(),
+            audit: self.audit.clone(),
+        }))
+    }
+}
+
+pub struct IpPolicyGuardService<S> {
+    service: Rc<S>,
+    policy: web::Data<IpPolicy>,
+    audit: web::Data<AuditLogger>,
+}
+
+impl<S, B> Service<ServiceRequest> for IpPolicyGuardService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let policy = self.policy.clone();
+        let audit = self.audit.clone();
+
+        Box::pin(async move {
+            // Peer address only: forwarded headers are client-controlled
+            let Some(ip) = req.peer_addr().map(|addr| addr.ip()) else {
+                return service.call(req).await.map(ServiceResponse::map_into_left_body);
+            };
+
+            let decision = policy.check_and_audit(ip, PolicyScope::All, &audit).await;
+            if decision.allowed {
+                return service.call(req).await.map(ServiceResponse::map_into_left_body);
+            }
+
+            let response = HttpResponse::Forbidden()
+                .json(serde_json::json!({
+                    "success": false,
+                    "message": "Access from your network is not permitted"
+                }))
+                .map_into_right_body();
+
+            Ok(req.into_response(response))
+        })
+    }
+}
+
+/// Panic guard - enforces the operator panic levers: refuses revoked
+/// tokens and sign-ups while registration is disabled, and checks CAPTCHAs
+/// on sign-in and sign-up when they are required. Money-moving writes are
+/// refused by [`crate::panic_levers::EconomyWrite`] on the routes themselves. See
+/// [`crate::panic_levers`].
+pub struct PanicGuard {
+    levers: web::Data<PanicLevers>,
+}
+
+impl PanicGuard {
+    pub fn new(levers: web::Data<PanicLevers>) -> Self {
+        Self { levers }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for PanicGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = PanicGuardService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(PanicGuardService {
+            service: Rc::new(service),
+            levers: self.levers.clone(),
+        }))
+    }
+}
+
+pub struct PanicGuardService<S> {
+    service: Rc<S>,
+    levers: web::Data<PanicLevers>,
+}
+
+impl<S, B> Service<ServiceRequest> for PanicGuardService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let levers = self.levers.clone();
+
+        Box::pin(async move {
+            let refuse = |req: ServiceRequest,
+                          mut response: actix_web::HttpResponseBuilder,
+                          message: &str|
+             -> Result<ServiceResponse<EitherBody<B>>, Error> {
+                let response = response
+                    .json(serde_json::json!({ "success": false, "message": message }))
+                    .map_into_right_body();
+                Ok(req.into_response(response))
+            };
+
+            if let Some(cutoff) = levers.revoked_before() {
+                let token = req
+                    .headers()
+                    .get("Authorization")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .map(str::to_string)
+                    .or_else(|| req.cookie("auth_token").map(|cookie| cookie.value().to_string()));
+                let revoked = token.is_some_and(|token| token_issued_at(&token).is_some_and(|iat| iat < cutoff));
+                if revoked {
+                    return refuse(req, HttpResponse::Unauthorized(), "Session revoked, please sign in again");
+                }
+            }
+
+            let method = req.method().clone();
+            let path = req.path().to_string();
+            if levers.is_engaged(Lever::DisableRegistration) && is_registration_request(&method, &path) {
+                return refuse(req, HttpResponse::ServiceUnavailable(), "Registration is temporarily disabled");
+            }
+
+            if needs_captcha(&method, &path) {
+                if let Some(captcha) = levers.captcha() {
+                    let token = req
+                        .headers()
+                        .get(CAPTCHA_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string);
+                    let Some(token) = token else {
+                        return refuse(req, HttpResponse::BadRequest(), "CAPTCHA required");
+                    };
+                    // Peer address only: forwarded headers are client-controlled
+                    let ip = req.peer_addr().map(|addr| addr.ip());
+                    match captcha.verify(&token, ip).await {
+                        Ok(true) => {}
+                        Ok(false) => return refuse(req, HttpResponse::BadRequest(), "CAPTCHA verification failed"),
+                        Err(e) => {
+                            tracing::error!("CAPTCHA verification unavailable: {}", e);
+                            return refuse(req, HttpResponse::ServiceUnavailable(), "CAPTCHA verification unavailable");
+                        }
+                    }
+                }
+            }
+
+            service.call(req).await.map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+/// Request context - builds the [`RequestContext`] once per request, from the
+/// authenticated user, `X-Request-Id`/`traceparent`, `Accept-Language` and the
+/// node serving it, and runs the rest of the request inside it. Handlers read
+/// it from the request extensions; library code through
+/// [`RequestContext::current`]. Wrap it inside [`AuthMiddleware`] so the user
+/// is known.
+pub struct RequestContextLayer;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestContextLayer
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestContextService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestContextService { service }))
+    }
+}
+
+pub struct RequestContextService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestContextService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let context = request_context(&req);
+        req.extensions_mut().insert(context.clone());
+
+        let span = tracing::info_span!(
+            "request",
+            request_id = %context.request_id.0,
+            trace_id = %context.trace_id,
+            user_id = context.user_id,
+            locale = %context.locale,
+        );
+        let request_id = context.request_id.0.to_string();
+        let fut = context.scope(self.service.call(req).instrument(span));
+
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Ok(value) = actix_web::http::header::HeaderValue::from_str(&request_id) {
+                res.headers_mut().insert(
+                    actix_web::http::header::HeaderName::from_static("x-request-id"),
+                    value,
+                );
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Build the context for `req`. A client-supplied `X-Request-Id` is kept when
+/// it is a UUID, so a retried request can be followed through the logs.
+fn request_context(req: &ServiceRequest) -> RequestContext {
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+
+    let request_id = header("x-request-id")
+        .and_then(|id| uuid::Uuid::parse_str(id).ok())
+        .map(RequestId)
+        .unwrap_or_default();
+    let mut context = RequestContext::new(request_id);
+    if let Some(trace_id) = header("traceparent").and_then(parse_traceparent) {
+        context = context.with_trace(trace_id);
+    }
+    if let Some(accept_language) = header("accept-language") {
+        context = context.with_locale(parse_locale(accept_language));
+    }
+
+    let user_id = req
+        .extensions()
+        .get::<AuthedUser>()
+        .and_then(|user| user.id.parse::<i64>().ok());
+    let shard = req
+        .app_data::<web::Data<crate::live_ops::LiveOps>>()
+        .map(|live_ops| format!("{}/{}", live_ops.region(), live_ops.node_id()));
+    let client_ip = req.peer_addr().map(|addr| addr.ip().to_string());
+
+    context.with_user(user_id).with_shard(shard).with_client_ip(client_ip)
+}
+
+/// Request logging - logs sampled requests and server errors, and the
+/// redacted headers and bodies of users in verbose mode. See
+/// [`crate::request_log`]. Wrap it inside [`RequestContextLayer`] so its
+/// lines carry the request and trace ids.
+pub struct RequestLogging {
+    log: web::Data<RequestLog>,
+}
+
+impl RequestLogging {
+    pub fn new(log: web::Data<RequestLog>) -> Self {
+        Self { log }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestLogging
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestLoggingService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestLoggingService {
+            service: Rc::new(service),
+            log: self.log.clone(),
+        }))
+    }
+}
+
+pub struct RequestLoggingService<S> {
+    service: Rc<S>,
+    log: web::Data<RequestLog>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestLoggingService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let settings = self.log.settings();
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let context = req.extensions().get::<RequestContext>().cloned();
+            let user_id = context.as_ref().and_then(|context| context.user_id);
+            let detail = settings.detail(user_id, rand::random::<f64>(), chrono::Utc::now());
+
+            let method = req.method().to_string();
+            let path = redact_path(req.path());
+            let query = redact_query(req.query_string());
+
+            let mut request = None;
+            if detail == Detail::Verbose {
+                let headers = redact_headers(req.headers());
+                let content_type = req.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+                let length = req
+                    .headers()
+                    .get(header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<usize>().ok());
+                // Only bodies of a known, bounded size are read; the handler
+                // gets the same bytes back
+                let body = match length {
+                    Some(0) | None => String::new(),
+                    Some(length) if length > CAPTURE_LIMIT => format!("<{} bytes, not captured>", length),
+                    Some(_) => match req.extract::<web::Bytes>().await {
+                        Ok(bytes) => {
+                            let body = redact_body(content_type.as_deref(), &bytes, settings.max_body_bytes);
+                            req.set_payload(Payload::Stream {
+                                payload: Box::pin(futures_util::stream::once(async move {
+                                    Ok::<_, actix_web::error::PayloadError>(bytes)
+                                })),
+                            });
+                            body
+                        }
+                        Err(e) => format!("<unreadable: {}>", e),
+                    },
+                };
+                request = Some((headers, body));
+            }
+
+            let res = service.call(req).await?;
+            let status = res.status();
+            if detail == Detail::Errors && !status.is_server_error() {
+                return Ok(res.map_into_left_body());
+            }
+
+            let request_id = context.as_ref().map(|context| context.request_id.0.to_string()).unwrap_or_default();
+            let trace_id = context.as_ref().map(|context| context.trace_id.clone()).unwrap_or_default();
+            let duration_ms = started.elapsed().as_millis() as u64;
+
+            let Some((headers, request_body)) = request else {
+                if status.is_server_error() {
+                    tracing::warn!(
+                        target: "request_log",
+                        request_id = %request_id, trace_id = %trace_id, user_id, method = %method,
+                        path = %path, query = %query, status = status.as_u16(), duration_ms,
+                        "{} {} -> {}", method, path, status.as_u16()
+                    );
+                } else {
+                    tracing::info!(
+                        target: "request_log",
+                        request_id = %request_id, trace_id = %trace_id, user_id, method = %method,
+                        path = %path, query = %query, status = status.as_u16(), duration_ms,
+                        "{} {} -> {}", method, path, status.as_u16()
+                    );
+                }
+                return Ok(res.map_into_left_body());
+            };
+
+            // Verbose: read back a response of known, bounded size; streams
+            // and anything larger pass through untouched
+            let content_type = res.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+            let (res, response_body) = match res.response().body().size() {
+                BodySize::Sized(size) if size as usize <= CAPTURE_LIMIT => {
+                    let (http_req, res) = res.into_parts();
+                    let (res, body) = res.into_parts();
+                    let bytes = actix_web::body::to_bytes(body).await.map_err(|e| {
+                        let e: Box<dyn std::error::Error> = e.into();
+                        actix_web::error::ErrorInternalServerError(e.to_string())
+                    })?;
+                    let logged = redact_body(content_type.as_deref(), &bytes, settings.max_body_bytes);
+                    let res = res.set_body(bytes).map_into_boxed_body().map_into_right_body();
+                    (ServiceResponse::new(http_req, res), logged)
+                }
+                BodySize::Sized(size) => (res.map_into_left_body(), format!("<{} bytes, not captured>", size)),
+                BodySize::None => (res.map_into_left_body(), String::new()),
+                BodySize::Stream => (res.map_into_left_body(), "<stream, not captured>".to_string()),
+            };
+
+            tracing::info!(
+                target: "request_log",
+                request_id = %request_id, trace_id = %trace_id, user_id, method = %method,
+                path = %path, query = %query, status = status.as_u16(), duration_ms,
+                headers = %serde_json::to_string(&headers).unwrap_or_default(),
+                request_body = %request_body, response_body = %response_body,
+                "{} {} -> {} (verbose)", method, path, status.as_u16()
+            );
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,373 @@
+//! Generated missions
+//!
+//! Players keep a few procedurally generated missions on offer next to the
+//! templates. Offers are topped up when the player looks at them, up to the
+//! daily allowance, and every generation is written to the generation log
+//! whether or not it produced a mission. The rules and the objective graphs
+//! are in [`he_game_mechanics::mission_gen`]; the rates come from the
+//! `[missions]` table of the balance file.
+//!
+//! An accepted mission is worked through objective by objective, each only
+//! once the ones it depends on are done. The reward is paid when the last
+//! required objective is done, with the bonus if every bonus objective was
+//! done before it. Resources the player reserved for the mission are
+//! committed with the payout, or released if they abandon it.
+//!
+//! Each kind counts as one mission template for the anti-farm rules in
+//! [`he_game_mechanics::missions`]: a kind cannot be accepted again until its
+//! cooldown since the last completion has passed, and the payout decays with
+//! recent repeats of the kind, gets the catch-up bonus for players below the
+//! median level, and is clamped to what is left of the daily caps.
+
+use chrono::{DateTime, Utc};
+use he_database::queries::{
+    BankQueries, GeneratedMissionQueries, GeneratedMissionRow, LedgerAccount, LedgerReason, MissionCompletionRow,
+    MissionTargetRow, NewGeneratedMission, NewGenerationLog, ProgressionQueries, ReservationQueries,
+};
+use crate::reservations;
+use he_game_mechanics::config::{MissionConfig, MissionGenConfig};
+use he_game_mechanics::mission_gen::{
+    self, GeneratedMissionDenied, MissionKind, ObjectiveGraph, PlayerProfile, RecentMission, Target,
+};
+use he_game_mechanics::missions::{scale_mission_reward, MissionHistory, MissionReward, MissionType, ScaledReward};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+
+type GeneratedMissionResult<T> = anyhow::Result<Result<T, GeneratedMissionDenied>>;
+
+/// Generation rates, read from the balance file at startup
+pub fn config() -> &'static MissionGenConfig {
+    &crate::balance::current().mission_gen
+}
+
+/// Repeat, cooldown and daily cap rules, read from the balance file at startup
+pub fn scaling() -> &'static MissionConfig {
+    &crate::balance::current().missions
+}
+
+/// What finishing an objective did
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectiveOutcome {
+    pub mission: GeneratedMissionRow,
+    /// Set when this objective finished the mission
+    pub paid_money: Option<i64>,
+    pub paid_experience: Option<i64>,
+    pub bonus: bool,
+    /// How the payout was scaled, when there was one
+    pub scaling: Option<ScaledReward>,
+}
+
+fn target(row: MissionTargetRow) -> Target {
+    Target { server_id: row.server_id, ip: row.ip, firewall: row.firewall, files: row.files, bank_id: row.bank_id }
+}
+
+fn objectives(mission: &GeneratedMissionRow) -> anyhow::Result<ObjectiveGraph> {
+    Ok(serde_json::from_value(mission.objectives.clone())?)
+}
+
+fn done(mission: &GeneratedMissionRow) -> Vec<usize> {
+    mission.completed_nodes.iter().map(|n| *n as usize).collect()
+}
+
+/// The template a generated mission counts as
+fn template(kind: &str) -> MissionType {
+    MissionType::Custom(kind.to_string())
+}
+
+fn clamp(amount: i64) -> i32 {
+    amount.clamp(0, i32::MAX as i64) as i32
+}
+
+/// Completions old enough to no longer affect repeats, cooldowns or today's caps
+fn history_since(now: DateTime<Utc>, config: &MissionConfig) -> DateTime<Utc> {
+    let hours = config.repeat_window_hours.max(24).max(config.template_cooldown_minutes.div_ceil(60));
+    now - chrono::Duration::hours(hours as i64)
+}
+
+/// Rebuild the player's completion history from what their missions paid
+fn history(completions: &[MissionCompletionRow], config: &MissionConfig) -> MissionHistory {
+    let mut history = MissionHistory::new();
+    for completion in completions {
+        let paid = MissionReward::new()
+            .with_money(clamp(completion.paid_money))
+            .with_experience(clamp(completion.paid_exp));
+        history.record(template(&completion.kind), &paid, completion.ended_at.into(), config);
+    }
+    history
+}
+
+/// Refuse a kind that is still on cooldown since its last completion
+fn check_cooldown(
+    kind: &str,
+    completions: &[MissionCompletionRow],
+    now: DateTime<Utc>,
+    config: &MissionConfig,
+) -> Result<(), GeneratedMissionDenied> {
+    match history(completions, config).cooldown_remaining(&template(kind), now.into(), config) {
+        Some(remaining) => Err(GeneratedMissionDenied::OnCooldown { minutes: remaining.as_secs().div_ceil(60) }),
+        None => Ok(()),
+    }
+}
+
+/// Scale a payout of `money` and `experience` for a mission of `kind`
+#[allow(clippy::too_many_arguments)]
+fn scale_payout(
+    kind: &str,
+    money: i64,
+    experience: i64,
+    completions: &[MissionCompletionRow],
+    level: i32,
+    median_level: i32,
+    now: DateTime<Utc>,
+    config: &MissionConfig,
+) -> ScaledReward {
+    let reward = MissionReward::new().with_money(clamp(money)).with_experience(clamp(experience));
+    let history = history(completions, config);
+    scale_mission_reward(&reward, &template(kind), &history, level, median_level, now.into(), config)
+}
+
+async fn completions(conn: &mut PgConnection, user_id: i64, now: DateTime<Utc>) -> anyhow::Result<Vec<MissionCompletionRow>> {
+    GeneratedMissionQueries::completions(conn, user_id, history_since(now, scaling())).await
+}
+
+/// The player's open missions, after expiring stale offers and generating
+/// new ones to fill the free slots
+pub async fn offers(pool: &PgPool, user_id: i64) -> anyhow::Result<Vec<GeneratedMissionRow>> {
+    let config = config();
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    if !GeneratedMissionQueries::lock_player(&mut *tx, user_id).await? {
+        return Ok(Vec::new());
+    }
+    GeneratedMissionQueries::expire_offers(&mut *tx, user_id).await?;
+    let counts = GeneratedMissionQueries::counts(&mut *tx, user_id).await?;
+    let wanted = (config.open_offers - counts.offered).min(config.missions_per_day - counts.today);
+
+    if wanted > 0 {
+        let (level, cracker) = GeneratedMissionQueries::profile(&mut *tx, user_id).await?;
+        let recent = GeneratedMissionQueries::recent(&mut *tx, user_id, config.variety_window as i64).await?;
+        let recent = recent
+            .into_iter()
+            .filter_map(|(kind, server_id)| Some(RecentMission { kind: MissionKind::from_str(&kind)?, server_id }))
+            .collect();
+        let mut player = PlayerProfile { level, cracker, recent };
+        let targets: Vec<Target> =
+            GeneratedMissionQueries::targets(&mut *tx).await?.into_iter().map(target).collect();
+
+        for _ in 0..wanted {
+            let seed = rand::random::<u64>();
+            let generation = mission_gen::generate(&player, &targets, seed, config);
+            let generation_id = GeneratedMissionQueries::log_generation(
+                &mut *tx,
+                &NewGenerationLog {
+                    user_id,
+                    seed: seed as i64,
+                    player_level: level,
+                    cracker_version: cracker,
+                    recent: &serde_json::to_value(&player.recent)?,
+                    attempts: &serde_json::to_value(&generation.attempts)?,
+                    outcome: if generation.plan.is_some() { "generated" } else { "rejected" },
+                },
+            )
+            .await?;
+
+            let Some(plan) = generation.plan else {
+                tracing::warn!("Mission generation {} for user {} found nothing to offer", generation_id, user_id);
+                continue;
+            };
+            GeneratedMissionQueries::insert(
+                &mut *tx,
+                &NewGeneratedMission {
+                    user_id,
+                    generation_id,
+                    kind: plan.kind.as_str(),
+                    tier: plan.tier.as_str(),
+                    target_server_id: plan.target.server_id,
+                    target_ip: &plan.target.ip,
+                    objectives: &serde_json::to_value(&plan.objectives)?,
+                    reward_money: plan.reward_money,
+                    bonus_money: plan.bonus_money,
+                    reward_exp: plan.reward_experience,
+                    offer_hours: config.offer_hours,
+                },
+            )
+            .await?;
+            // Offers made together count towards each other's variety
+            player.recent.insert(0, RecentMission { kind: plan.kind, server_id: Some(plan.target.server_id) });
+            player.recent.truncate(config.variety_window);
+        }
+    }
+    tx.commit().await?;
+
+    Ok(GeneratedMissionQueries::open(pool, user_id).await?)
+}
+
+pub async fn accept(pool: &PgPool, user_id: i64, mission_id: i64) -> GeneratedMissionResult<GeneratedMissionRow> {
+    let config = config();
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(mission) = GeneratedMissionQueries::lock(&mut *tx, mission_id, user_id).await? else {
+        return Ok(Err(GeneratedMissionDenied::NotFound));
+    };
+    if mission.status != "offered" {
+        return Ok(Err(GeneratedMissionDenied::NotOffered));
+    }
+    if mission.expires_at <= chrono::Utc::now() {
+        return Ok(Err(GeneratedMissionDenied::Expired));
+    }
+    let counts = GeneratedMissionQueries::counts(&mut *tx, user_id).await?;
+    if counts.active >= config.max_active {
+        return Ok(Err(GeneratedMissionDenied::TooManyActive { max: config.max_active }));
+    }
+    let now = Utc::now();
+    let completions = completions(&mut *tx, user_id, now).await?;
+    if let Err(denied) = check_cooldown(&mission.kind, &completions, now, scaling()) {
+        return Ok(Err(denied));
+    }
+
+    let mission = GeneratedMissionQueries::accept(&mut *tx, mission.id).await?;
+    tx.commit().await?;
+
+    Ok(Ok(mission))
+}
+
+/// Mark objective `node` done, paying out if it finishes the mission
+pub async fn complete_objective(
+    pool: &PgPool,
+    user_id: i64,
+    mission_id: i64,
+    node: usize,
+) -> GeneratedMissionResult<ObjectiveOutcome> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(mission) = GeneratedMissionQueries::lock(&mut *tx, mission_id, user_id).await? else {
+        return Ok(Err(GeneratedMissionDenied::NotFound));
+    };
+    if mission.status != "active" {
+        return Ok(Err(GeneratedMissionDenied::NotActive));
+    }
+    let graph = objectives(&mission)?;
+    if node >= graph.nodes.len() {
+        return Ok(Err(GeneratedMissionDenied::UnknownObjective));
+    }
+    let mut done = done(&mission);
+    if done.contains(&node) {
+        return Ok(Err(GeneratedMissionDenied::ObjectiveDone));
+    }
+    let waiting_on = graph.waiting_on(node, &done);
+    if !waiting_on.is_empty() {
+        return Ok(Err(GeneratedMissionDenied::ObjectiveLocked { waiting_on }));
+    }
+
+    let mission = GeneratedMissionQueries::complete_node(&mut *tx, mission.id, node as i32).await?;
+    done.push(node);
+    if !graph.is_complete(&done) {
+        tx.commit().await?;
+        return Ok(Ok(ObjectiveOutcome { mission, paid_money: None, paid_experience: None, bonus: false, scaling: None }));
+    }
+
+    let bonus = graph.bonus_complete(&done);
+    let money = mission.reward_money + if bonus { mission.bonus_money } else { 0 };
+    let experience = crate::rules::current().experience(mission.reward_exp);
+    let now = Utc::now();
+    let (level, _) = GeneratedMissionQueries::profile(&mut *tx, user_id).await?;
+    let median_level = GeneratedMissionQueries::median_level(&mut *tx).await?;
+    let completions = completions(&mut *tx, user_id, now).await?;
+    let scaled = scale_payout(&mission.kind, money, experience, &completions, level, median_level, now, scaling());
+    let money = scaled.reward.money as i64;
+    let experience = scaled.reward.experience as i64;
+
+    if money > 0 {
+        let reference = format!("generated_mission:{}", mission.id);
+        let paid = BankQueries::credit_primary_account(
+            &mut *tx,
+            user_id,
+            money,
+            LedgerAccount::Mint,
+            LedgerReason::MissionReward,
+            &reference,
+        )
+        .await?;
+        if !paid {
+            tracing::warn!("No bank account to pay generated mission {} to user {}", mission.id, user_id);
+        }
+    }
+    ProgressionQueries::add_experience(&mut *tx, user_id, experience).await?;
+    ReservationQueries::settle_purpose(&mut *tx, user_id, &reservations::mission_purpose(mission.id), "committed").await?;
+    GeneratedMissionQueries::record_payout(&mut *tx, mission.id, money, experience).await?;
+    let mission = GeneratedMissionQueries::finish(&mut *tx, mission.id, "completed").await?;
+    tx.commit().await?;
+
+    Ok(Ok(ObjectiveOutcome {
+        mission,
+        paid_money: Some(money),
+        paid_experience: Some(experience),
+        bonus,
+        scaling: Some(scaled),
+    }))
+}
+
+/// Give up an offered or active mission; it still counts towards variety
+pub async fn abandon(pool: &PgPool, user_id: i64, mission_id: i64) -> GeneratedMissionResult<GeneratedMissionRow> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(mission) = GeneratedMissionQueries::lock(&mut *tx, mission_id, user_id).await? else {
+        return Ok(Err(GeneratedMissionDenied::NotFound));
+    };
+    if mission.status != "offered" && mission.status != "active" {
+        return Ok(Err(GeneratedMissionDenied::NotActive));
+    }
+
+    ReservationQueries::settle_purpose(&mut *tx, user_id, &reservations::mission_purpose(mission.id), "released").await?;
+    let mission = GeneratedMissionQueries::finish(&mut *tx, mission.id, "abandoned").await?;
+    tx.commit().await?;
+
+    Ok(Ok(mission))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn noon() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 11, 26, 12, 0, 0).unwrap()
+    }
+
+    fn completion(kind: &str, ended_at: DateTime<Utc>, paid_money: i64, paid_exp: i64) -> MissionCompletionRow {
+        MissionCompletionRow { kind: kind.to_string(), ended_at, paid_money, paid_exp }
+    }
+
+    #[test]
+    fn test_repeated_kind_pays_less_and_waits_out_its_cooldown() {
+        let config = MissionConfig::default();
+        let now = noon();
+        let completions = [
+            completion("heist", now - chrono::Duration::hours(3), 1_000, 100),
+            completion("heist", now - chrono::Duration::minutes(10), 750, 75),
+        ];
+
+        let fresh = scale_payout("sabotage", 1_000, 100, &completions, 10, 10, now, &config);
+        assert_eq!((fresh.reward.money, fresh.reward.experience), (1_000, 100));
+
+        let farmed = scale_payout("heist", 1_000, 100, &completions, 10, 10, now, &config);
+        assert_eq!(farmed.repeat_multiplier, 0.75 * 0.75);
+        assert_eq!((farmed.reward.money, farmed.reward.experience), (562, 56));
+
+        assert_eq!(
+            check_cooldown("heist", &completions, now, &config),
+            Err(GeneratedMissionDenied::OnCooldown { minutes: 20 })
+        );
+        assert_eq!(check_cooldown("sabotage", &completions, now, &config), Ok(()));
+    }
+
+    #[test]
+    fn test_payout_is_clamped_to_the_rest_of_todays_caps() {
+        let config = MissionConfig { daily_money_cap: 5_000, daily_experience_cap: 500, ..MissionConfig::default() };
+        let now = noon();
+        let completions = [completion("data_theft", now - chrono::Duration::minutes(1), 4_500, 450)];
+
+        let scaled = scale_payout("heist", 2_000, 200, &completions, 5, 30, now, &config);
+        assert!(scaled.capped);
+        assert!(scaled.catch_up_multiplier > 1.0);
+        assert_eq!((scaled.reward.money, scaled.reward.experience), (500, 50));
+    }
+}
@@ -0,0 +1,146 @@
+//! Postgres storage for the OpenID Connect provider
+//!
+//! Every node reads and writes the same clients, codes and tokens, so a code
+//! issued behind one node can be exchanged on another and a restart keeps
+//! companion services signed in.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use he_auth::oidc::{OidcClient, OidcCode, OidcError, OidcStore, OidcSubject, OidcToken};
+use he_database::queries::{OidcClientRow, OidcGrantRow, OidcQueries};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct PgOidcStore {
+    pool: PgPool,
+}
+
+impl PgOidcStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn server_error(e: anyhow::Error) -> OidcError {
+    tracing::error!("OIDC store query failed: {}", e);
+    OidcError::Server("storage unavailable".to_string())
+}
+
+fn client_from_row(row: OidcClientRow) -> OidcClient {
+    OidcClient {
+        client_id: row.client_id,
+        name: row.name,
+        redirect_uris: row.redirect_uris,
+        confidential: row.confidential,
+        secret_hash: row.secret_hash,
+        created_at: row.created_at,
+    }
+}
+
+fn subject_from_row(row: &OidcGrantRow) -> OidcSubject {
+    OidcSubject {
+        user_id: row.user_id,
+        email: row.email.clone(),
+        roles: row.roles.clone(),
+    }
+}
+
+#[async_trait]
+impl OidcStore for PgOidcStore {
+    async fn insert_client(&self, client: &OidcClient) -> Result<(), OidcError> {
+        let row = OidcClientRow {
+            client_id: client.client_id.clone(),
+            name: client.name.clone(),
+            redirect_uris: client.redirect_uris.clone(),
+            confidential: client.confidential,
+            secret_hash: client.secret_hash.clone(),
+            created_at: client.created_at,
+        };
+        OidcQueries::insert_client(&self.pool, &row).await.map_err(server_error)
+    }
+
+    async fn client(&self, client_id: &str) -> Result<Option<OidcClient>, OidcError> {
+        let row = OidcQueries::client(&self.pool, client_id).await.map_err(server_error)?;
+        Ok(row.map(client_from_row))
+    }
+
+    async fn list_clients(&self) -> Result<Vec<OidcClient>, OidcError> {
+        let rows = OidcQueries::list_clients(&self.pool).await.map_err(server_error)?;
+        Ok(rows.into_iter().map(client_from_row).collect())
+    }
+
+    async fn remove_client(&self, client_id: &str) -> Result<bool, OidcError> {
+        OidcQueries::remove_client(&self.pool, client_id).await.map_err(server_error)
+    }
+
+    async fn insert_code(&self, code_hash: &str, code: &OidcCode) -> Result<(), OidcError> {
+        let row = OidcGrantRow {
+            client_id: code.client_id.clone(),
+            redirect_uri: Some(code.redirect_uri.clone()),
+            scopes: code.scopes.clone(),
+            nonce: code.nonce.clone(),
+            code_challenge: Some(code.code_challenge.clone()),
+            user_id: code.subject.user_id,
+            email: code.subject.email.clone(),
+            roles: code.subject.roles.clone(),
+            issued_at: code.auth_time,
+            expires_at: code.expires_at,
+        };
+        OidcQueries::insert_code(&self.pool, code_hash, &row).await.map_err(server_error)
+    }
+
+    async fn take_code(&self, code_hash: &str) -> Result<Option<OidcCode>, OidcError> {
+        let row = OidcQueries::take_code(&self.pool, code_hash).await.map_err(server_error)?;
+        Ok(row.map(|row| OidcCode {
+            subject: subject_from_row(&row),
+            client_id: row.client_id,
+            redirect_uri: row.redirect_uri.unwrap_or_default(),
+            scopes: row.scopes,
+            nonce: row.nonce,
+            code_challenge: row.code_challenge.unwrap_or_default(),
+            auth_time: row.issued_at,
+            expires_at: row.expires_at,
+        }))
+    }
+
+    async fn insert_token(&self, token_hash: &str, token: &OidcToken) -> Result<(), OidcError> {
+        let row = OidcGrantRow {
+            client_id: token.client_id.clone(),
+            redirect_uri: None,
+            scopes: token.scopes.clone(),
+            nonce: None,
+            code_challenge: None,
+            user_id: token.subject.user_id,
+            email: token.subject.email.clone(),
+            roles: token.subject.roles.clone(),
+            issued_at: token.issued_at,
+            expires_at: token.expires_at,
+        };
+        OidcQueries::insert_token(&self.pool, token_hash, &row).await.map_err(server_error)
+    }
+
+    async fn token(&self, token_hash: &str) -> Result<Option<OidcToken>, OidcError> {
+        let row = OidcQueries::token(&self.pool, token_hash).await.map_err(server_error)?;
+        Ok(row.map(|row| OidcToken {
+            subject: subject_from_row(&row),
+            client_id: row.client_id,
+            scopes: row.scopes,
+            issued_at: row.issued_at,
+            expires_at: row.expires_at,
+        }))
+    }
+
+    async fn revoke_token(&self, token_hash: &str, client_id: &str) -> Result<bool, OidcError> {
+        OidcQueries::revoke_token(&self.pool, token_hash, client_id).await.map_err(server_error)
+    }
+
+    async fn revoke_user(&self, user_id: &Uuid) -> Result<usize, OidcError> {
+        let removed = OidcQueries::revoke_user(&self.pool, *user_id).await.map_err(server_error)?;
+        Ok(removed as usize)
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> Result<(), OidcError> {
+        OidcQueries::purge_expired(&self.pool, now).await.map_err(server_error)?;
+        Ok(())
+    }
+}
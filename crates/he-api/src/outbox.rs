@@ -0,0 +1,256 @@
+//! Transactional outbox for socket notifications
+//!
+//! A notification pushed after commit is lost if the node dies between the
+//! commit and the push. Instead, code that changes state queues its
+//! notifications with [`enqueue`] in the same transaction, and the relay
+//! delivers them once they are committed: to a player's sockets, to the
+//! sockets watching a process or a moderator channel, or to an event
+//! dispatcher.
+//!
+//! Delivery is at least once. A relay leases a batch, delivers it and marks
+//! it sent; if it dies in between, the lease runs out and the batch is
+//! delivered again. Every socket message carries its outbox `dedup_key` in
+//! `data`, unchanged across redeliveries, so clients drop repeats.
+
+use he_database::queries::{OutboxQueries, OutboxRow};
+use he_websocket::{GameEvent, ModChannel};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How often the outbox is polled when nothing wakes the relay
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const BATCH: i64 = 200;
+/// How long a leased batch is left alone before another relay retries it
+const LEASE_SECS: i64 = 30;
+/// Deliveries tried before a notification is given up on
+const MAX_ATTEMPTS: i32 = 10;
+/// Sent notifications are kept this long, then pruned
+const KEEP_SENT_HOURS: i64 = 24;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+static WAKEUP: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Where a queued notification goes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recipient {
+    User(i64),
+    /// Every socket subscribed to the process
+    Process(i64),
+    /// Every moderator socket subscribed to the channel
+    Moderators(ModChannel),
+    /// An he-events dispatcher, chosen by the payload's `data_type`
+    Dispatcher,
+}
+
+impl Recipient {
+    fn channel(&self) -> (&'static str, Option<i64>) {
+        match self {
+            Recipient::User(user_id) => ("user", Some(*user_id)),
+            Recipient::Process(pid) => ("process", Some(*pid)),
+            Recipient::Moderators(channel) => (channel.name(), None),
+            Recipient::Dispatcher => ("event", None),
+        }
+    }
+}
+
+/// A notification to queue
+#[derive(Debug, Clone)]
+pub struct OutboxMessage {
+    /// Unique per notification and stable across retries of the transaction
+    /// that queues it, e.g. `process:42:completed`
+    pub dedup_key: String,
+    pub recipient: Recipient,
+    pub payload: Value,
+}
+
+impl OutboxMessage {
+    pub fn to_user(dedup_key: impl Into<String>, user_id: i64, event: &GameEvent) -> Self {
+        Self::socket(dedup_key, Recipient::User(user_id), event)
+    }
+
+    pub fn to_process(dedup_key: impl Into<String>, pid: i64, event: &GameEvent) -> Self {
+        Self::socket(dedup_key, Recipient::Process(pid), event)
+    }
+
+    pub fn to_moderators(dedup_key: impl Into<String>, channel: ModChannel, event: &GameEvent) -> Self {
+        Self::socket(dedup_key, Recipient::Moderators(channel), event)
+    }
+
+    /// An he-events payload; `data_type` picks the dispatcher
+    pub fn event(dedup_key: impl Into<String>, data_type: &str, payload: Value) -> Self {
+        Self {
+            dedup_key: dedup_key.into(),
+            recipient: Recipient::Dispatcher,
+            payload: serde_json::json!({ "data_type": data_type, "payload": payload }),
+        }
+    }
+
+    fn socket(dedup_key: impl Into<String>, recipient: Recipient, event: &GameEvent) -> Self {
+        Self {
+            dedup_key: dedup_key.into(),
+            recipient,
+            payload: serde_json::to_value(event).unwrap_or_default(),
+        }
+    }
+}
+
+/// Queue `messages` in the caller's transaction. Call [`wake`] after commit
+/// to have them delivered right away instead of on the next poll.
+pub async fn enqueue(conn: &mut PgConnection, messages: &[OutboxMessage]) -> anyhow::Result<()> {
+    for message in messages {
+        let (channel, recipient) = message.recipient.channel();
+        OutboxQueries::enqueue(conn, &message.dedup_key, channel, recipient, &message.payload).await?;
+    }
+    Ok(())
+}
+
+/// Tell the relay committed notifications are waiting
+pub fn wake() {
+    WAKEUP.notify_one();
+}
+
+/// Deliver queued notifications until the process exits
+pub(crate) fn spawn_relay(pool: PgPool, ws_manager: Option<Arc<he_websocket::ConnectionManager>>) {
+    tokio::spawn({
+        let pool = pool.clone();
+        async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match OutboxQueries::prune_sent(&pool, KEEP_SENT_HOURS).await {
+                    Ok(pruned) if pruned > 0 => tracing::debug!("Pruned {} sent outbox notifications", pruned),
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Outbox pruning failed: {}", e),
+                }
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let started = std::time::Instant::now();
+            let delivered = match relay_batch(&pool, ws_manager.clone()).await {
+                Ok(delivered) => delivered,
+                Err(e) => {
+                    tracing::warn!("Outbox relay failed: {}", e);
+                    0
+                }
+            };
+            crate::live_ops::record_tick("outbox", started.elapsed());
+
+            // A full batch likely left more behind
+            if delivered as i64 >= BATCH {
+                continue;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = WAKEUP.notified() => {}
+            }
+        }
+    });
+}
+
+/// Lease, deliver and mark one batch; returns how many were delivered
+async fn relay_batch(pool: &PgPool, ws_manager: Option<Arc<he_websocket::ConnectionManager>>) -> anyhow::Result<usize> {
+    let rows = OutboxQueries::lease_due(pool, BATCH, LEASE_SECS).await?;
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let mut sent = Vec::with_capacity(rows.len());
+    for row in rows {
+        match deliver(pool, ws_manager.clone(), &row).await {
+            Ok(()) => sent.push(row.id),
+            Err(e) if row.attempts >= MAX_ATTEMPTS => {
+                tracing::error!("Giving up on outbox notification {}: {}", row.dedup_key, e);
+                OutboxQueries::mark_failed(pool, row.id, &e.to_string()).await?;
+            }
+            // Left leased; retried when the lease runs out
+            Err(e) => tracing::warn!("Outbox notification {} not delivered: {}", row.dedup_key, e),
+        }
+    }
+
+    OutboxQueries::mark_sent(pool, &sent).await?;
+    Ok(sent.len())
+}
+
+async fn deliver(
+    pool: &PgPool,
+    ws_manager: Option<Arc<he_websocket::ConnectionManager>>,
+    row: &OutboxRow,
+) -> anyhow::Result<()> {
+    match (row.channel.as_str(), row.recipient) {
+        ("user", Some(user_id)) => {
+            crate::gateway::send_to_user(pool, ws_manager.as_deref(), user_id, socket_message(row)?).await?;
+        }
+        ("process", Some(pid)) => {
+            let entity = he_websocket::Entity::Process(pid);
+            crate::gateway::send_to_entity(ws_manager.as_deref(), entity, socket_message(row)?).await?;
+        }
+        ("event", _) => {
+            let data_type = row.payload["data_type"].as_str().unwrap_or_default();
+            let payload = row.payload["payload"].clone();
+            match data_type {
+                crate::bounty::DATA_TYPE => {
+                    let mut event: crate::bounty::BountyEvent = serde_json::from_value(payload)?;
+                    event.dedup_key = Some(row.dedup_key.clone());
+                    crate::bounty::publish(pool, ws_manager, vec![event]).await?;
+                }
+                crate::coop::DATA_TYPE => {
+                    let event: crate::coop::CoopEvent = serde_json::from_value(payload)?;
+                    crate::coop::dispatch(pool, ws_manager, vec![event]).await?;
+                }
+                other => anyhow::bail!("no dispatcher for {:?}", other),
+            }
+        }
+        (channel, recipient) => {
+            let Some(mod_channel) = ModChannel::from_name(channel).filter(|_| recipient.is_none()) else {
+                anyhow::bail!("bad outbox address {} {:?}", channel, recipient);
+            };
+            let entity = he_websocket::Entity::Moderation(mod_channel);
+            crate::gateway::send_to_entity(ws_manager.as_deref(), entity, socket_message(row)?).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The socket message for a queued game event, tagged with its dedup key
+fn socket_message(row: &OutboxRow) -> anyhow::Result<he_websocket::ServerMessage> {
+    let event: GameEvent = serde_json::from_value(row.payload.clone())?;
+    let mut message = event.to_server_message();
+    if let Some(data) = message.data.as_object_mut() {
+        data.insert("dedup_key".to_string(), Value::String(row.dedup_key.clone()));
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_message_carries_dedup_key() {
+        let message = OutboxMessage::to_user(
+            "process:7:money",
+            3,
+            &he_websocket::EventBuilder::money_received(500, "Mining pool".to_string()),
+        );
+        assert_eq!(message.recipient.channel(), ("user", Some(3)));
+
+        let row = OutboxRow {
+            id: 1,
+            dedup_key: message.dedup_key.clone(),
+            channel: "user".to_string(),
+            recipient: Some(3),
+            payload: message.payload,
+            attempts: 1,
+        };
+        let sent = socket_message(&row).unwrap();
+        assert_eq!(sent.event_type, "money_received");
+        assert_eq!(sent.data["dedup_key"], "process:7:money");
+    }
+}
@@ -0,0 +1,389 @@
+//! Incident-response panic levers
+//!
+//! Switches operators pull during an incident, each engaged and released on
+//! its own from the admin API:
+//!
+//! - `revoke_sessions`: tokens issued before the lever was engaged are
+//!   refused and WebSocket sessions opened before it are closed. Everyone,
+//!   the operator included, signs in again. Releasing it lets tokens that
+//!   have not expired back in.
+//! - `freeze_economy`: transfers, purchases and other money-moving writes
+//!   are refused. Those routes are wrapped in [`EconomyWrite`] where they
+//!   are registered.
+//! - `disable_registration`: sign-ups are refused.
+//! - `force_captcha`: sign-in and sign-up need a CAPTCHA, whether or not
+//!   `CAPTCHA_REQUIRED` is set.
+//! - `ws_read_only`: WebSocket clients keep receiving broadcasts but their
+//!   messages are dropped.
+//!
+//! Levers live in Postgres and every node reloads them on an interval, like
+//! [`crate::live_ops`], so one pulled on one node reaches all of them within
+//! a few seconds. The `PanicGuard` middleware and [`EconomyWrite`] enforce
+//! the HTTP side.
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::{web, Error, HttpResponse};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use futures_util::future::LocalBoxFuture;
+use he_database::queries::{PanicLeverQueries, PanicLeverRow};
+use sqlx::PgPool;
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Header carrying the CAPTCHA response token on sign-in and sign-up
+pub const CAPTCHA_HEADER: &str = "X-Captcha-Token";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lever {
+    RevokeSessions,
+    FreezeEconomy,
+    DisableRegistration,
+    ForceCaptcha,
+    WsReadOnly,
+}
+
+impl Lever {
+    pub const ALL: [Lever; 5] = [
+        Lever::RevokeSessions,
+        Lever::FreezeEconomy,
+        Lever::DisableRegistration,
+        Lever::ForceCaptcha,
+        Lever::WsReadOnly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lever::RevokeSessions => "revoke_sessions",
+            Lever::FreezeEconomy => "freeze_economy",
+            Lever::DisableRegistration => "disable_registration",
+            Lever::ForceCaptcha => "force_captcha",
+            Lever::WsReadOnly => "ws_read_only",
+        }
+    }
+
+    pub fn from_str(name: &str) -> Option<Lever> {
+        Lever::ALL.into_iter().find(|lever| lever.as_str() == name)
+    }
+}
+
+const REGISTRATION_PATHS: &[&str] = &["/api/register", "/api/auth/register"];
+const SIGN_IN_PATHS: &[&str] = &["/api/login", "/api/auth/login"];
+
+/// `path` matches `pattern`, where `*` stands for one path segment
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let mut pattern = pattern.split('/');
+    let mut path = path.trim_end_matches('/').split('/');
+    loop {
+        match (pattern.next(), path.next()) {
+            (None, None) => return true,
+            (Some("*"), Some(segment)) if !segment.is_empty() => {}
+            (Some(expected), Some(segment)) if expected == segment => {}
+            _ => return false,
+        }
+    }
+}
+
+pub fn is_registration_request(method: &Method, path: &str) -> bool {
+    method == Method::POST && REGISTRATION_PATHS.iter().any(|p| path_matches(p, path))
+}
+
+/// Sign-in and sign-up, the requests a CAPTCHA guards
+pub fn needs_captcha(method: &Method, path: &str) -> bool {
+    method == Method::POST
+        && (REGISTRATION_PATHS.iter().chain(SIGN_IN_PATHS).any(|p| path_matches(p, path)))
+}
+
+/// When a JWT was issued, from its `iat` claim. The signature is not
+/// checked: this only decides whether to refuse a token, and the auth layers
+/// verify the ones that get through.
+pub fn token_issued_at(token: &str) -> Option<i64> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    claims.get("iat")?.as_i64()
+}
+
+/// Siteverify-style CAPTCHA provider (Turnstile, hCaptcha, reCAPTCHA)
+pub struct CaptchaVerifier {
+    secret: String,
+    verify_url: String,
+    client: reqwest::Client,
+}
+
+impl CaptchaVerifier {
+    /// From `CAPTCHA_SECRET` and `CAPTCHA_VERIFY_URL`; `None` unless both are set
+    pub fn from_env() -> Option<Self> {
+        let secret = std::env::var("CAPTCHA_SECRET").ok().filter(|s| !s.is_empty())?;
+        let verify_url = std::env::var("CAPTCHA_VERIFY_URL").ok().filter(|s| !s.is_empty())?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .map_err(|e| tracing::error!("Failed to build CAPTCHA client: {}", e))
+            .ok()?;
+        Some(Self { secret, verify_url, client })
+    }
+
+    pub async fn verify(&self, token: &str, ip: Option<IpAddr>) -> anyhow::Result<bool> {
+        let mut form = vec![("secret", self.secret.clone()), ("response", token.to_string())];
+        if let Some(ip) = ip {
+            form.push(("remoteip", ip.to_string()));
+        }
+        let response: serde_json::Value = self.client
+            .post(&self.verify_url)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.get("success").and_then(|s| s.as_bool()).unwrap_or(false))
+    }
+}
+
+/// Why a lever could not be pulled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeverRefused {
+    /// Already in the requested state
+    Unchanged,
+    /// CAPTCHAs cannot be forced without a provider to check them
+    NoCaptchaProvider,
+}
+
+impl LeverRefused {
+    pub fn message(&self) -> &'static str {
+        match self {
+            LeverRefused::Unchanged => "Lever is already in that state",
+            LeverRefused::NoCaptchaProvider => "No CAPTCHA provider is configured on this node",
+        }
+    }
+}
+
+pub struct PanicLevers {
+    levers: watch::Sender<Arc<Vec<PanicLeverRow>>>,
+    read_only: watch::Sender<bool>,
+    /// Unix time before which tokens and sessions are revoked
+    revoked_before: watch::Sender<Option<i64>>,
+    captcha: Option<CaptchaVerifier>,
+    /// `CAPTCHA_REQUIRED`: sign-in needs a CAPTCHA even with the lever released
+    captcha_required: bool,
+}
+
+impl PanicLevers {
+    pub fn new(captcha: Option<CaptchaVerifier>, captcha_required: bool) -> Self {
+        Self {
+            levers: watch::channel(Arc::new(Vec::new())).0,
+            read_only: watch::channel(false).0,
+            revoked_before: watch::channel(None).0,
+            captcha,
+            captcha_required,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let required = std::env::var("CAPTCHA_REQUIRED").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+        let captcha = CaptchaVerifier::from_env();
+        if required && captcha.is_none() {
+            tracing::warn!("CAPTCHA_REQUIRED is set but no CAPTCHA provider is configured; not enforcing");
+        }
+        Self::new(captcha, required)
+    }
+
+    pub fn levers(&self) -> Arc<Vec<PanicLeverRow>> {
+        self.levers.borrow().clone()
+    }
+
+    pub fn is_engaged(&self, lever: Lever) -> bool {
+        self.levers.borrow().iter().any(|row| row.lever == lever.as_str() && row.engaged)
+    }
+
+    pub fn revoked_before(&self) -> Option<i64> {
+        *self.revoked_before.borrow()
+    }
+
+    pub fn subscribe_revocations(&self) -> watch::Receiver<Option<i64>> {
+        self.revoked_before.subscribe()
+    }
+
+    pub fn subscribe_read_only(&self) -> watch::Receiver<bool> {
+        self.read_only.subscribe()
+    }
+
+    /// The verifier to check sign-ins with, if CAPTCHAs are required now
+    pub fn captcha(&self) -> Option<&CaptchaVerifier> {
+        let required = self.captcha_required || self.is_engaged(Lever::ForceCaptcha);
+        self.captcha.as_ref().filter(|_| required)
+    }
+
+    pub async fn reload(&self, pool: &PgPool) -> anyhow::Result<()> {
+        self.apply(PanicLeverQueries::list(pool).await?);
+        Ok(())
+    }
+
+    fn apply(&self, levers: Vec<PanicLeverRow>) {
+        let engaged = |lever: Lever| levers.iter().find(|row| row.lever == lever.as_str() && row.engaged);
+        let read_only = engaged(Lever::WsReadOnly).is_some();
+        let revoked_before = engaged(Lever::RevokeSessions)
+            .and_then(|row| row.engaged_at)
+            .map(|at: DateTime<Utc>| at.timestamp());
+
+        for row in levers.iter() {
+            let was = self.levers.borrow().iter().find(|old| old.lever == row.lever).map(|old| old.engaged);
+            if was.is_some_and(|was| was != row.engaged) {
+                if row.engaged {
+                    tracing::warn!("Panic lever {} engaged: {}", row.lever, row.reason.as_deref().unwrap_or(""));
+                } else {
+                    tracing::warn!("Panic lever {} released", row.lever);
+                }
+            }
+        }
+
+        self.read_only.send_if_modified(|current| std::mem::replace(current, read_only) != read_only);
+        self.revoked_before.send_if_modified(|current| std::mem::replace(current, revoked_before) != revoked_before);
+        self.levers.send_replace(Arc::new(levers));
+    }
+
+    /// Engage or release `lever` for every node
+    pub async fn set(
+        &self,
+        pool: &PgPool,
+        lever: Lever,
+        engaged: bool,
+        admin_id: i64,
+        reason: &str,
+    ) -> anyhow::Result<Result<PanicLeverRow, LeverRefused>> {
+        if engaged && lever == Lever::ForceCaptcha && self.captcha.is_none() {
+            return Ok(Err(LeverRefused::NoCaptchaProvider));
+        }
+        let Some(row) = PanicLeverQueries::set(pool, lever.as_str(), engaged, admin_id, reason).await? else {
+            return Ok(Err(LeverRefused::Unchanged));
+        };
+        self.reload(pool).await?;
+        Ok(Ok(row))
+    }
+
+    pub fn spawn_reloader(self: Arc<Self>, pool: PgPool, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.reload(&pool).await {
+                    tracing::error!("Failed to reload panic levers: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Economy write - marks a route that moves money or goods, refusing it
+/// while the `freeze_economy` lever is engaged. Wrapped onto each such route
+/// where it is registered, so a new one cannot be left off a path list.
+pub struct EconomyWrite;
+
+impl<S, B> Transform<S, ServiceRequest> for EconomyWrite
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = EconomyWriteService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(EconomyWriteService { service: Rc::new(service) }))
+    }
+}
+
+pub struct EconomyWriteService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for EconomyWriteService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let frozen = req
+            .app_data::<web::Data<PanicLevers>>()
+            .is_some_and(|levers| levers.is_engaged(Lever::FreezeEconomy));
+        if frozen {
+            let response = HttpResponse::ServiceUnavailable()
+                .json(serde_json::json!({ "success": false, "message": "The economy is temporarily frozen" }))
+                .map_into_right_body();
+            return Box::pin(async move { Ok(req.into_response(response)) });
+        }
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move { service.call(req).await.map(ServiceResponse::map_into_left_body) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_matching() {
+        assert!(is_registration_request(&Method::POST, "/api/auth/register"));
+        assert!(!is_registration_request(&Method::GET, "/api/register"));
+        assert!(needs_captcha(&Method::POST, "/api/login"));
+        assert!(!needs_captcha(&Method::POST, "/api/auth/logout"));
+    }
+
+    #[test]
+    fn test_token_issued_at() {
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(r#"{"sub":"7","iat":1700000000}"#);
+        assert_eq!(token_issued_at(&format!("e30.{}.sig", payload)), Some(1_700_000_000));
+        assert_eq!(token_issued_at("not-a-jwt"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_economy_write_refused_while_frozen() {
+        use actix_web::{test, App};
+
+        let levers = web::Data::new(PanicLevers::new(None, false));
+        let app = test::init_service(
+            App::new()
+                .app_data(levers.clone())
+                .route("/pay", web::post().to(HttpResponse::Ok).wrap(EconomyWrite))
+                .route("/read", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let pay = || test::TestRequest::post().uri("/pay").to_request();
+        assert!(test::call_service(&app, pay()).await.status().is_success());
+
+        levers.apply(vec![PanicLeverRow {
+            lever: Lever::FreezeEconomy.as_str().to_string(),
+            engaged: true,
+            reason: None,
+            engaged_by: None,
+            engaged_at: None,
+            released_by: None,
+            released_at: None,
+        }]);
+        let response = test::call_service(&app, pay()).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        let read = test::TestRequest::post().uri("/read").to_request();
+        assert!(test::call_service(&app, read).await.status().is_success());
+    }
+}
@@ -0,0 +1,177 @@
+//! Software piracy and copy protection
+//!
+//! Owners of copy-protected titles lock copies to the server they are on with
+//! [`protect`]. A completed download of another player's software stores a
+//! copy on the thief's gateway, inside the completion transaction; a locked
+//! copy keeps its lock, so it does not count there, and may carry its owner's
+//! trojan. A keygen process started with [`start_keygen`] clears the lock,
+//! and a trojan riding on the copy gives its owner the thief's gateway in
+//! their hacked database. The rules are in [`he_game_mechanics::piracy`].
+
+use chrono::{DateTime, Utc};
+use he_database::models::Process;
+use he_database::queries::{ClanServerQueries, HackedDatabaseQueries, PiracyQueries, ProcessQueries};
+use he_game_mechanics::piracy::{self, KeygenDenied, KeygenQuote, ProtectDenied};
+use he_game_mechanics::process::ProcessType;
+use rand::Rng;
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use crate::handlers::software::SoftwareCatalogCache;
+use crate::outbox::OutboxMessage;
+
+/// A stolen copy arrived, a license was cracked or a trojan went off
+#[derive(Debug, Clone)]
+pub struct PiracyEvent {
+    /// Who is told
+    pub user_id: i64,
+    pub software_id: i64,
+    pub name: String,
+    pub change: &'static str,
+    pub ip: Option<String>,
+}
+
+impl PiracyEvent {
+    pub(crate) fn to_outbox(&self, origin: &str) -> OutboxMessage {
+        let event = he_websocket::EventBuilder::software_piracy(
+            self.software_id,
+            self.name.clone(),
+            self.change.to_string(),
+            self.ip.clone(),
+        );
+        OutboxMessage::to_user(format!("{}:piracy:{}:{}", origin, self.change, self.user_id), self.user_id, &event)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeygenStarted {
+    pub pid: i64,
+    pub end_time: DateTime<Utc>,
+    pub quote: KeygenQuote,
+}
+
+/// Lock one of the player's copies to the server it is on
+pub async fn protect(
+    pool: &PgPool,
+    catalog: &SoftwareCatalogCache,
+    user_id: i64,
+    software_id: i64,
+) -> anyhow::Result<Result<(), ProtectDenied>> {
+    let Some(copy) = PiracyQueries::owned(pool, user_id, software_id).await? else {
+        return Ok(Err(ProtectDenied::NotFound));
+    };
+    let checked = piracy::check_protect(
+        catalog.copy_protected(&copy.name),
+        copy.license_server_id.is_some(),
+        copy.pirated_from.is_some(),
+    );
+    if let Err(denied) = checked {
+        return Ok(Err(denied));
+    }
+
+    // Lost a race with another lock
+    if !PiracyQueries::lock(pool, software_id).await? {
+        return Ok(Err(ProtectDenied::AlreadyLocked));
+    }
+    Ok(Ok(()))
+}
+
+/// Start a keygen on one of the player's locked copies. The caller schedules
+/// the returned process for completion.
+pub async fn start_keygen(
+    pool: &PgPool,
+    user_id: i64,
+    software_id: i64,
+) -> anyhow::Result<Result<KeygenStarted, KeygenDenied>> {
+    let Some(copy) = PiracyQueries::owned(pool, user_id, software_id).await? else {
+        return Ok(Err(KeygenDenied::NotFound));
+    };
+    let running = PiracyQueries::keygen_running(pool, software_id).await?;
+    let cracker = PiracyQueries::cracker(pool, user_id).await?;
+    let quote = match piracy::check_keygen(
+        copy.version,
+        piracy::runs_on(copy.license_server_id, copy.server_id),
+        running,
+        cracker,
+        &crate::balance::current().piracy,
+    ) {
+        Ok(quote) => quote,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let pc_id = format!("pc_{}", user_id);
+    let duration = quote.duration_secs.clamp(1, i32::MAX as i64) as i32;
+    let process =
+        ProcessQueries::create_file_process(pool, user_id, "keygen", &pc_id, &software_id.to_string(), duration).await?;
+
+    Ok(Ok(KeygenStarted { pid: process.pid, end_time: process.end_time, quote }))
+}
+
+/// Store the copy a download took, or crack the license a keygen worked on,
+/// in the completion transaction of `process`
+pub(crate) async fn apply(conn: &mut PgConnection, process: &Process) -> anyhow::Result<Vec<PiracyEvent>> {
+    let Some(software_id) = process.target_file_id.as_deref().and_then(|id| id.parse::<i64>().ok()) else {
+        return Ok(Vec::new());
+    };
+    match ProcessType::from_str(&process.process_type) {
+        ProcessType::Download => Ok(steal(conn, process, software_id).await?.into_iter().collect()),
+        ProcessType::Keygen => keygen(conn, process, software_id).await,
+        _ => Ok(Vec::new()),
+    }
+}
+
+async fn steal(conn: &mut PgConnection, process: &Process, software_id: i64) -> anyhow::Result<Option<PiracyEvent>> {
+    let Some(target_ip) = process.target_pc_id.as_deref() else {
+        return Ok(None);
+    };
+    let Some(source) = PiracyQueries::source(&mut *conn, target_ip, software_id).await? else {
+        return Ok(None);
+    };
+    // Only other players' software is stolen this way
+    let Some(owner_id) = source.owner_id.filter(|owner_id| *owner_id != process.user_id) else {
+        return Ok(None);
+    };
+    let Some(gateway) = ClanServerQueries::gateway(&mut *conn, process.user_id).await? else {
+        return Ok(None);
+    };
+    if ClanServerQueries::lock_free_space(&mut *conn, gateway).await? < source.size {
+        tracing::debug!("No room on the gateway of user {} for stolen software {}", process.user_id, source.id);
+        return Ok(None);
+    }
+
+    let roll = rand::thread_rng().gen::<f64>();
+    let trojan = piracy::trojaned(source.license_server_id.is_some(), roll, &crate::balance::current().piracy);
+    let copy_id = PiracyQueries::store_copy(&mut *conn, source.id, gateway, trojan.then_some(owner_id)).await?;
+
+    Ok(Some(PiracyEvent {
+        user_id: process.user_id,
+        software_id: copy_id,
+        name: source.name,
+        change: "stolen",
+        ip: None,
+    }))
+}
+
+async fn keygen(conn: &mut PgConnection, process: &Process, software_id: i64) -> anyhow::Result<Vec<PiracyEvent>> {
+    let Some(unlocked) = PiracyQueries::unlock(&mut *conn, process.user_id, software_id).await? else {
+        return Ok(Vec::new());
+    };
+    let mut events = vec![PiracyEvent {
+        user_id: process.user_id,
+        software_id,
+        name: unlocked.name.clone(),
+        change: "unlocked",
+        ip: None,
+    }];
+
+    if let (Some(owner_id), Some(gateway_ip)) = (unlocked.trojan_owner_id, unlocked.gateway_ip) {
+        HackedDatabaseQueries::record(&mut *conn, owner_id, &gateway_ip).await?;
+        events.push(PiracyEvent {
+            user_id: owner_id,
+            software_id,
+            name: unlocked.name,
+            change: "trojan_triggered",
+            ip: Some(gateway_ip),
+        });
+    }
+    Ok(events)
+}
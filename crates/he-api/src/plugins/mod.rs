@@ -317,5 +317,7 @@ impl Plugin for PluginProxy {
 #[macro_use]
 pub mod macros;
 
+pub mod wasm;
+
 // Re-export common types
 pub use self::{Plugin, PluginContext, PluginError, PluginManager, PluginMetadata};
\ No newline at end of file
@@ -0,0 +1,646 @@
+//! WASM plugin host
+//!
+//! Server-side extensions are WebAssembly modules run under wasmtime. A
+//! plugin is `<name>.wasm` in `PLUGIN_DIR` next to a `<name>.json`
+//! [`PluginManifest`] naming the capabilities it is granted and its limits.
+//! It is linked only against the host functions its capabilities allow, so
+//! importing anything else fails the load:
+//!
+//! - `read_game_state`: `he.state(ptr, cap) -> len` copies a JSON snapshot of
+//!   the player a command runs for into the plugin
+//! - `commands`: `he.register_command(ptr, len)` during `init` adds a
+//!   terminal command, run through `on_command`
+//! - `jobs`: `he.schedule_job(ptr, len, interval_secs)` during `init` runs
+//!   `on_job` with the job's name on that interval
+//!
+//! The manifest may also declare the [`CommandSpec`] grammar of its commands,
+//! which the terminal parses lines against and generates help from, and
+//! translations of their descriptions by locale. `help` is the terminal's
+//! own and cannot be registered.
+//!
+//! `he.log(level, ptr, len)` is always there. Every call runs in a fresh
+//! instance with the manifest's fuel, memory and wall-clock limits, so a
+//! plugin keeps nothing between calls and one that spins or allocates
+//! without end is stopped on its own. Admins load, reload and unload
+//! plugins at runtime from `/api/admin/plugins`.
+//!
+//! A plugin exports `memory`, `alloc(len) -> ptr` and `init()`. Strings are
+//! UTF-8 passed as a pointer and length; `on_command(ptr, len)` returns its
+//! output as `ptr << 32 | len`.
+
+use he_game_mechanics::terminal_grammar::{CommandSpec, HELP_COMMAND};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use wasmtime::{Caller, Config, Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+/// How often the engine's epoch advances; wall-clock limits are counted in these
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+/// Jobs cannot run more often than this
+const MIN_JOB_INTERVAL_SECS: u64 = 10;
+const MAX_COMMAND_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    ReadGameState,
+    Commands,
+    Jobs,
+}
+
+impl Capability {
+    /// The host function the capability grants
+    fn import(&self) -> &'static str {
+        match self {
+            Capability::ReadGameState => "state",
+            Capability::Commands => "register_command",
+            Capability::Jobs => "schedule_job",
+        }
+    }
+}
+
+/// What one call into a plugin may use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginLimits {
+    /// Wasm instructions, roughly
+    pub fuel: u64,
+    pub memory_bytes: usize,
+    pub timeout_ms: u64,
+    /// Longest output a command may return
+    pub output_bytes: usize,
+}
+
+impl Default for PluginLimits {
+    fn default() -> Self {
+        Self {
+            fuel: 10_000_000,
+            memory_bytes: 16 * 1024 * 1024,
+            timeout_ms: 100,
+            output_bytes: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub limits: PluginLimits,
+    /// Grammar of the commands the plugin registers
+    #[serde(default)]
+    pub grammar: Vec<CommandSpec>,
+    /// Translated text by locale, then by key
+    #[serde(default)]
+    pub translations: HashMap<String, HashMap<String, String>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WasmPluginError {
+    #[error("Invalid plugin name: {0}")]
+    InvalidName(String),
+
+    #[error("Plugin {0} is not in the plugin directory")]
+    NotFound(String),
+
+    #[error("Invalid manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("Plugin imports {0}, which its capabilities do not grant")]
+    NotGranted(String),
+
+    #[error("Plugin failed to load: {0}")]
+    Load(String),
+
+    #[error("Command {command} already belongs to plugin {plugin}")]
+    CommandTaken { command: String, plugin: String },
+
+    #[error("Plugin ran out of fuel")]
+    OutOfFuel,
+
+    #[error("Plugin ran past its time limit")]
+    Timeout,
+
+    #[error("Plugin failed: {0}")]
+    Trap(String),
+
+    #[error("Plugin returned invalid output")]
+    BadOutput,
+}
+
+impl WasmPluginError {
+    fn from_call(e: anyhow::Error) -> Self {
+        match e.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => WasmPluginError::OutOfFuel,
+            Some(Trap::Interrupt) => WasmPluginError::Timeout,
+            _ => WasmPluginError::Trap(e.to_string()),
+        }
+    }
+}
+
+/// Per-call store data
+struct HostState {
+    plugin: String,
+    limits: StoreLimits,
+    /// The snapshot `he.state` hands out
+    game_state: Option<Vec<u8>>,
+    /// Only `init` may register commands and jobs
+    registering: bool,
+    commands: Vec<String>,
+    jobs: Vec<(String, u64)>,
+}
+
+fn read_guest(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Option<Vec<u8>> {
+    let memory = caller.get_export("memory")?.into_memory()?;
+    let mut bytes = vec![0; usize::try_from(len).ok()?];
+    memory.read(&caller, usize::try_from(ptr).ok()?, &mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn read_guest_str(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Option<String> {
+    read_guest(caller, ptr, len).and_then(|bytes| String::from_utf8(bytes).ok())
+}
+
+fn valid_command(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_COMMAND_LENGTH
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+/// Host functions for the granted capabilities
+fn linker(engine: &Engine, capabilities: &[Capability]) -> anyhow::Result<Linker<HostState>> {
+    let mut linker = Linker::new(engine);
+
+    linker.func_wrap("he", "log", |mut caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32| {
+        let Some(message) = read_guest_str(&mut caller, ptr, len) else {
+            return;
+        };
+        let plugin = &caller.data().plugin;
+        match level {
+            0 => tracing::error!(plugin = %plugin, "{}", message),
+            1 => tracing::warn!(plugin = %plugin, "{}", message),
+            _ => tracing::info!(plugin = %plugin, "{}", message),
+        }
+    })?;
+
+    if capabilities.contains(&Capability::ReadGameState) {
+        linker.func_wrap("he", "state", |mut caller: Caller<'_, HostState>, ptr: i32, cap: i32| -> i32 {
+            let Some(state) = caller.data().game_state.clone() else {
+                return -1;
+            };
+            if state.len() > cap.max(0) as usize {
+                return state.len() as i32;
+            }
+            let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory()) else {
+                return -1;
+            };
+            match memory.write(&mut caller, ptr.max(0) as usize, &state) {
+                Ok(()) => state.len() as i32,
+                Err(_) => -1,
+            }
+        })?;
+    }
+
+    if capabilities.contains(&Capability::Commands) {
+        linker.func_wrap("he", "register_command", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> i32 {
+            let Some(name) = read_guest_str(&mut caller, ptr, len) else {
+                return -1;
+            };
+            let data = caller.data_mut();
+            if !data.registering || !valid_command(&name) || name == HELP_COMMAND {
+                return -1;
+            }
+            if !data.commands.contains(&name) {
+                data.commands.push(name);
+            }
+            0
+        })?;
+    }
+
+    if capabilities.contains(&Capability::Jobs) {
+        linker.func_wrap(
+            "he",
+            "schedule_job",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32, interval_secs: i64| -> i32 {
+                let Some(name) = read_guest_str(&mut caller, ptr, len) else {
+                    return -1;
+                };
+                let data = caller.data_mut();
+                if !data.registering || name.is_empty() || data.jobs.iter().any(|(job, _)| *job == name) {
+                    return -1;
+                }
+                data.jobs.push((name, (interval_secs.max(0) as u64).max(MIN_JOB_INTERVAL_SECS)));
+                0
+            },
+        )?;
+    }
+
+    Ok(linker)
+}
+
+/// Refuse modules that import what their manifest does not grant
+fn check_imports(module: &Module, capabilities: &[Capability]) -> Result<(), WasmPluginError> {
+    for import in module.imports() {
+        let granted = import.module() == "he"
+            && (import.name() == "log" || capabilities.iter().any(|c| c.import() == import.name()));
+        if !granted {
+            return Err(WasmPluginError::NotGranted(format!("{}.{}", import.module(), import.name())));
+        }
+    }
+    Ok(())
+}
+
+/// A compiled plugin
+pub struct LoadedPlugin {
+    pub manifest: PluginManifest,
+    engine: Engine,
+    pre: InstancePre<HostState>,
+    commands: Vec<String>,
+    jobs: Vec<(String, u64)>,
+    loaded_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl LoadedPlugin {
+    /// Compile a plugin and run its `init` to learn its commands and jobs
+    fn compile(engine: &Engine, manifest: PluginManifest, wasm: &[u8]) -> Result<Self, WasmPluginError> {
+        let module = Module::new(engine, wasm).map_err(|e| WasmPluginError::Load(e.to_string()))?;
+        check_imports(&module, &manifest.capabilities)?;
+        let pre = linker(engine, &manifest.capabilities)
+            .and_then(|linker| linker.instantiate_pre(&module))
+            .map_err(|e| WasmPluginError::Load(e.to_string()))?;
+
+        let mut plugin = Self {
+            manifest,
+            engine: engine.clone(),
+            pre,
+            commands: Vec::new(),
+            jobs: Vec::new(),
+            loaded_at: chrono::Utc::now(),
+        };
+        let (mut store, instance) = plugin.instantiate(None, true)?;
+        let init = instance
+            .get_typed_func::<(), ()>(&mut store, "init")
+            .map_err(|e| WasmPluginError::Load(e.to_string()))?;
+        init.call(&mut store, ()).map_err(WasmPluginError::from_call)?;
+
+        let state = store.into_data();
+        plugin.commands = state.commands;
+        plugin.jobs = state.jobs;
+        Ok(plugin)
+    }
+
+    fn instantiate(
+        &self,
+        game_state: Option<Vec<u8>>,
+        registering: bool,
+    ) -> Result<(Store<HostState>, wasmtime::Instance), WasmPluginError> {
+        let limits = &self.manifest.limits;
+        let state = HostState {
+            plugin: self.manifest.name.clone(),
+            limits: StoreLimitsBuilder::new().memory_size(limits.memory_bytes).instances(1).build(),
+            game_state,
+            registering,
+            commands: Vec::new(),
+            jobs: Vec::new(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(limits.fuel).map_err(|e| WasmPluginError::Load(e.to_string()))?;
+        store.set_epoch_deadline((limits.timeout_ms / EPOCH_TICK.as_millis() as u64).max(1));
+
+        let instance = self.pre.instantiate(&mut store).map_err(WasmPluginError::from_call)?;
+        Ok((store, instance))
+    }
+
+    /// Copy `bytes` into a fresh instance through its `alloc`
+    fn pass(
+        store: &mut Store<HostState>,
+        instance: &wasmtime::Instance,
+        bytes: &[u8],
+    ) -> Result<(i32, i32), WasmPluginError> {
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut *store, "alloc")
+            .map_err(|e| WasmPluginError::Trap(e.to_string()))?;
+        let len = i32::try_from(bytes.len()).map_err(|_| WasmPluginError::BadOutput)?;
+        let ptr = alloc.call(&mut *store, len).map_err(WasmPluginError::from_call)?;
+        let memory = instance.get_memory(&mut *store, "memory").ok_or(WasmPluginError::BadOutput)?;
+        memory
+            .write(&mut *store, ptr.max(0) as usize, bytes)
+            .map_err(|e| WasmPluginError::Trap(e.to_string()))?;
+        Ok((ptr, len))
+    }
+
+    /// Run a command line; blocks for as long as the limits allow
+    pub fn run_command(&self, line: &str, game_state: Vec<u8>) -> Result<String, WasmPluginError> {
+        let (mut store, instance) = self.instantiate(Some(game_state), false)?;
+        let (ptr, len) = Self::pass(&mut store, &instance, line.as_bytes())?;
+        let on_command = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, "on_command")
+            .map_err(|e| WasmPluginError::Trap(e.to_string()))?;
+        let packed = on_command.call(&mut store, (ptr, len)).map_err(WasmPluginError::from_call)?;
+
+        let (out_ptr, out_len) = ((packed as u64 >> 32) as usize, (packed as u64 & 0xffff_ffff) as usize);
+        if out_len > self.manifest.limits.output_bytes {
+            return Err(WasmPluginError::BadOutput);
+        }
+        let memory = instance.get_memory(&mut store, "memory").ok_or(WasmPluginError::BadOutput)?;
+        let mut output = vec![0; out_len];
+        memory.read(&store, out_ptr, &mut output).map_err(|_| WasmPluginError::BadOutput)?;
+        String::from_utf8(output).map_err(|_| WasmPluginError::BadOutput)
+    }
+
+    /// Run one of the plugin's jobs; blocks for as long as the limits allow
+    pub fn run_job(&self, job: &str) -> Result<(), WasmPluginError> {
+        let (mut store, instance) = self.instantiate(None, false)?;
+        let (ptr, len) = Self::pass(&mut store, &instance, job.as_bytes())?;
+        let on_job = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "on_job")
+            .map_err(|e| WasmPluginError::Trap(e.to_string()))?;
+        on_job.call(&mut store, (ptr, len)).map_err(WasmPluginError::from_call)
+    }
+}
+
+/// A loaded plugin as the admin API shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub capabilities: Vec<Capability>,
+    pub limits: PluginLimits,
+    pub commands: Vec<String>,
+    pub jobs: Vec<String>,
+    pub loaded_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<&LoadedPlugin> for PluginInfo {
+    fn from(plugin: &LoadedPlugin) -> Self {
+        Self {
+            name: plugin.manifest.name.clone(),
+            version: plugin.manifest.version.clone(),
+            description: plugin.manifest.description.clone(),
+            capabilities: plugin.manifest.capabilities.clone(),
+            limits: plugin.manifest.limits.clone(),
+            commands: plugin.commands.clone(),
+            jobs: plugin.jobs.iter().map(|(name, _)| name.clone()).collect(),
+            loaded_at: plugin.loaded_at,
+        }
+    }
+}
+
+struct Running {
+    plugin: Arc<LoadedPlugin>,
+    jobs: Vec<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Registry {
+    plugins: HashMap<String, Running>,
+    /// Terminal command to the plugin that registered it
+    commands: HashMap<String, String>,
+}
+
+/// Loads plugins from the plugin directory and routes calls to them
+pub struct PluginHost {
+    engine: Engine,
+    dir: PathBuf,
+    registry: RwLock<Registry>,
+}
+
+impl PluginHost {
+    pub fn new(dir: PathBuf) -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true).epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+
+        // Wall-clock limits count epochs; the ticker lives as long as the engine
+        let weak = engine.weak();
+        std::thread::Builder::new().name("wasm-epoch".to_string()).spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            match weak.upgrade() {
+                Some(engine) => engine.increment_epoch(),
+                None => break,
+            }
+        })?;
+
+        Ok(Self { engine, dir, registry: RwLock::new(Registry::default()) })
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::new(std::env::var("PLUGIN_DIR").unwrap_or_else(|_| "plugins".to_string()).into())
+    }
+
+    /// Load a plugin from the plugin directory, replacing a loaded one of
+    /// the same name once the new one is ready
+    pub async fn load(&self, name: &str) -> Result<PluginInfo, WasmPluginError> {
+        if !valid_command(name) {
+            return Err(WasmPluginError::InvalidName(name.to_string()));
+        }
+        let manifest_path = self.dir.join(format!("{}.json", name));
+        let wasm_path = self.dir.join(format!("{}.wasm", name));
+        let (Ok(manifest), Ok(wasm)) = (tokio::fs::read(&manifest_path).await, tokio::fs::read(&wasm_path).await) else {
+            return Err(WasmPluginError::NotFound(name.to_string()));
+        };
+        let manifest: PluginManifest =
+            serde_json::from_slice(&manifest).map_err(|e| WasmPluginError::InvalidManifest(e.to_string()))?;
+        if manifest.name != name {
+            return Err(WasmPluginError::InvalidManifest(format!("manifest names plugin {}", manifest.name)));
+        }
+
+        let engine = self.engine.clone();
+        let plugin = tokio::task::spawn_blocking(move || LoadedPlugin::compile(&engine, manifest, &wasm))
+            .await
+            .map_err(|e| WasmPluginError::Load(e.to_string()))??;
+        self.install(Arc::new(plugin))
+    }
+
+    fn install(&self, plugin: Arc<LoadedPlugin>) -> Result<PluginInfo, WasmPluginError> {
+        let name = plugin.manifest.name.clone();
+        let mut registry = self.registry.write().unwrap_or_else(|e| e.into_inner());
+        for command in &plugin.commands {
+            match registry.commands.get(command) {
+                Some(owner) if *owner != name => {
+                    return Err(WasmPluginError::CommandTaken { command: command.clone(), plugin: owner.clone() });
+                }
+                _ => {}
+            }
+        }
+
+        Self::remove(&mut registry, &name);
+        for command in &plugin.commands {
+            registry.commands.insert(command.clone(), name.clone());
+        }
+        let jobs = plugin.jobs.iter().map(|(job, interval)| spawn_job(plugin.clone(), job.clone(), *interval)).collect();
+        let info = PluginInfo::from(plugin.as_ref());
+        registry.plugins.insert(name.clone(), Running { plugin, jobs });
+        tracing::info!("Loaded plugin {} {}", name, info.version);
+        Ok(info)
+    }
+
+    fn remove(registry: &mut Registry, name: &str) -> bool {
+        let Some(running) = registry.plugins.remove(name) else {
+            return false;
+        };
+        for job in running.jobs {
+            job.abort();
+        }
+        registry.commands.retain(|_, owner| owner != name);
+        true
+    }
+
+    /// Stop a plugin's jobs and drop its commands; false if it was not loaded
+    pub fn unload(&self, name: &str) -> bool {
+        let mut registry = self.registry.write().unwrap_or_else(|e| e.into_inner());
+        let removed = Self::remove(&mut registry, name);
+        if removed {
+            tracing::info!("Unloaded plugin {}", name);
+        }
+        removed
+    }
+
+    pub fn list(&self) -> Vec<PluginInfo> {
+        let registry = self.registry.read().unwrap_or_else(|e| e.into_inner());
+        let mut plugins: Vec<_> = registry.plugins.values().map(|r| PluginInfo::from(r.plugin.as_ref())).collect();
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        plugins
+    }
+
+    /// Terminal commands plugins added, with the plugin of each
+    pub fn commands(&self) -> Vec<(String, String)> {
+        let registry = self.registry.read().unwrap_or_else(|e| e.into_inner());
+        let mut commands: Vec<_> = registry.commands.iter().map(|(c, p)| (c.clone(), p.clone())).collect();
+        commands.sort();
+        commands
+    }
+
+    /// The grammar the plugin that has `command` declared for it
+    pub fn grammar(&self, command: &str) -> Option<CommandSpec> {
+        let registry = self.registry.read().unwrap_or_else(|e| e.into_inner());
+        let owner = registry.commands.get(command)?;
+        let plugin = &registry.plugins.get(owner)?.plugin;
+        plugin.manifest.grammar.iter().find(|spec| spec.name == command).cloned()
+    }
+
+    /// Every loaded plugin's translations for `locale`, falling back to its
+    /// language (`pt` for `pt-BR`) key by key
+    pub fn translations(&self, locale: &str) -> HashMap<String, String> {
+        let language = locale.split(['-', '_']).next().unwrap_or(locale);
+        let registry = self.registry.read().unwrap_or_else(|e| e.into_inner());
+        let mut translations = HashMap::new();
+        for running in registry.plugins.values() {
+            let manifest = &running.plugin.manifest;
+            for tag in [language, locale] {
+                if let Some(texts) = manifest.translations.get(tag) {
+                    translations.extend(texts.iter().map(|(k, v)| (k.clone(), v.clone())));
+                }
+            }
+        }
+        translations
+    }
+
+    /// Run a terminal command line for a player; `None` if no plugin has the
+    /// command. `game_state` is what the plugin may read of the player.
+    pub async fn run_command(
+        &self,
+        line: &str,
+        game_state: &serde_json::Value,
+    ) -> Option<Result<String, WasmPluginError>> {
+        let command = line.split_whitespace().next()?;
+        let plugin = {
+            let registry = self.registry.read().unwrap_or_else(|e| e.into_inner());
+            let owner = registry.commands.get(command)?;
+            registry.plugins.get(owner)?.plugin.clone()
+        };
+
+        let line = line.to_string();
+        let game_state = serde_json::to_vec(game_state).unwrap_or_default();
+        let result = tokio::task::spawn_blocking(move || plugin.run_command(&line, game_state)).await;
+        Some(result.unwrap_or_else(|e| Err(WasmPluginError::Trap(e.to_string()))))
+    }
+}
+
+fn spawn_job(plugin: Arc<LoadedPlugin>, job: String, interval_secs: u64) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let (plugin, job) = (plugin.clone(), job.clone());
+            let name = plugin.manifest.name.clone();
+            match tokio::task::spawn_blocking(move || plugin.run_job(&job)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("Plugin {} job failed: {}", name, e),
+                Err(e) => tracing::warn!("Plugin {} job panicked: {}", name, e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECHO: &str = r#"
+        (module
+          (import "he" "register_command" (func $register (param i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "echo")
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "init")
+            (drop (call $register (i32.const 0) (i32.const 4))))
+          (func (export "on_command") (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+              (i64.extend_i32_u (local.get $len))))
+          (func (export "spin") (loop $forever (br $forever))))
+    "#;
+
+    fn manifest(capabilities: Vec<Capability>) -> PluginManifest {
+        PluginManifest {
+            name: "echo".to_string(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+            capabilities,
+            limits: PluginLimits::default(),
+            grammar: Vec::new(),
+            translations: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_capabilities_and_commands() {
+        let host = PluginHost::new(PathBuf::from(".")).unwrap();
+        assert!(matches!(
+            LoadedPlugin::compile(&host.engine, manifest(vec![]), ECHO.as_bytes()),
+            Err(WasmPluginError::NotGranted(import)) if import == "he.register_command"
+        ));
+
+        let plugin = LoadedPlugin::compile(&host.engine, manifest(vec![Capability::Commands]), ECHO.as_bytes()).unwrap();
+        assert_eq!(plugin.commands, vec!["echo".to_string()]);
+        assert_eq!(plugin.run_command("echo hi", Vec::new()).unwrap(), "echo hi");
+    }
+
+    #[test]
+    fn test_limits_stop_runaway_calls() {
+        let host = PluginHost::new(PathBuf::from(".")).unwrap();
+        let plugin = LoadedPlugin::compile(&host.engine, manifest(vec![Capability::Commands]), ECHO.as_bytes()).unwrap();
+        let (mut store, instance) = plugin.instantiate(None, false).unwrap();
+        let spin = instance.get_typed_func::<(), ()>(&mut store, "spin").unwrap();
+        let err = spin.call(&mut store, ()).map_err(WasmPluginError::from_call).unwrap_err();
+        assert!(matches!(err, WasmPluginError::OutOfFuel | WasmPluginError::Timeout));
+    }
+}
@@ -0,0 +1,268 @@
+//! Storyline puzzles
+//!
+//! Players work through puzzles stage by stage. A stage's hidden file only
+//! shows up for players on that stage who have cracked its server, while the
+//! stage is open; encrypted files are sealed on the way out with the key
+//! found on another server. Answers are checked here against the stored
+//! hashes, never on the client. Every right answer may push the community
+//! past a milestone, which is recorded once and announced to every online
+//! player through the live-ops announcements. Rules are in
+//! [`he_game_mechanics::puzzles`].
+
+use chrono::Utc;
+use he_database::queries::{
+    BankQueries, LedgerAccount, LedgerReason, ProgressionQueries, PuzzleMilestoneRow, PuzzleProgressRow, PuzzleQueries,
+    PuzzleRow, PuzzleStageRow,
+};
+use he_game_mechanics::process::CompletionReward;
+use he_game_mechanics::puzzles::{self, AccessWindow, CommunityGate, PuzzleDenied, StageAccess};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool};
+
+type PuzzleResult<T> = anyhow::Result<Result<T, PuzzleDenied>>;
+
+/// The stage a player is on, as far as they may know it
+#[derive(Debug, Serialize)]
+pub struct StageView {
+    pub stage: i32,
+    pub server_ip: String,
+    pub window: Option<AccessWindow>,
+    pub community: Option<CommunityGate>,
+    /// Why the stage cannot be worked on right now
+    pub blocked: Option<PuzzleDenied>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PuzzleView {
+    #[serde(flatten)]
+    pub puzzle: PuzzleRow,
+    pub progress: Option<PuzzleProgressRow>,
+    pub current: Option<StageView>,
+    pub milestones: Vec<PuzzleMilestoneRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HiddenFile {
+    pub puzzle_id: i64,
+    pub name: String,
+    pub content: String,
+    pub encrypted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnswerOutcome {
+    pub solved_stage: i32,
+    /// `None` once the puzzle is finished
+    pub next_stage: Option<i32>,
+    /// Paid when this answer finished the puzzle for the first time
+    pub reward: Option<CompletionReward>,
+    /// Community milestones this answer completed
+    pub milestones: Vec<PuzzleMilestoneRow>,
+}
+
+fn answer_hash(slug: &str, stage: i32, answer: &str) -> String {
+    let digest = Sha256::digest(puzzles::answer_digest_input(slug, stage, answer).as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+async fn stage_access(conn: &mut PgConnection, user_id: i64, stage: &PuzzleStageRow) -> anyhow::Result<StageAccess> {
+    let community = match stage.requires_milestone_id {
+        Some(id) => PuzzleQueries::milestones(&mut *conn, stage.puzzle_id)
+            .await?
+            .into_iter()
+            .find(|milestone| milestone.id == id)
+            .map(|milestone| CommunityGate {
+                milestone: milestone.title,
+                players: milestone.players,
+                required: milestone.players_required as i64,
+                reached: milestone.reached_at.is_some(),
+            }),
+        None => None,
+    };
+    let window = stage
+        .window_start_minute
+        .zip(stage.window_minutes)
+        .map(|(start_minute, minutes)| AccessWindow { start_minute, minutes });
+
+    Ok(StageAccess {
+        server_ip: stage.server_ip.clone(),
+        server_hacked: PuzzleQueries::has_hacked(conn, user_id, &stage.server_ip).await?,
+        window,
+        community,
+    })
+}
+
+async fn view(conn: &mut PgConnection, user_id: i64, puzzle: PuzzleRow, progress: Option<PuzzleProgressRow>) -> anyhow::Result<PuzzleView> {
+    let mut current = None;
+    if let Some(progress) = progress.as_ref().filter(|progress| progress.completed_at.is_none()) {
+        if let Some(stage) = PuzzleQueries::stage(&mut *conn, puzzle.id, progress.stage).await? {
+            let access = stage_access(&mut *conn, user_id, &stage).await?;
+            current = Some(StageView {
+                stage: stage.stage,
+                blocked: puzzles::check_access(&access, Utc::now()).err(),
+                server_ip: access.server_ip,
+                window: access.window,
+                community: access.community,
+            });
+        }
+    }
+    let milestones = PuzzleQueries::milestones(conn, puzzle.id).await?;
+    Ok(PuzzleView { puzzle, progress, current, milestones })
+}
+
+/// Every active puzzle with the player's progress; puzzles they have not
+/// met yet are started, so the mystery server is always on their list
+pub async fn overview(pool: &PgPool, user_id: i64) -> anyhow::Result<Vec<PuzzleView>> {
+    PuzzleQueries::start_all(pool, user_id).await?;
+    let progress = PuzzleQueries::progress(pool, user_id).await?;
+    let mut conn = pool.acquire().await?;
+
+    let mut views = Vec::new();
+    for puzzle in PuzzleQueries::puzzles(pool).await? {
+        let progress = progress.iter().find(|p| p.puzzle_id == puzzle.id).cloned();
+        views.push(view(&mut conn, user_id, puzzle, progress).await?);
+    }
+    Ok(views)
+}
+
+pub async fn puzzle(pool: &PgPool, user_id: i64, puzzle_id: i64) -> PuzzleResult<PuzzleView> {
+    let mut conn = pool.acquire().await?;
+    let Some(puzzle) = PuzzleQueries::puzzle(&mut conn, puzzle_id).await? else {
+        return Ok(Err(PuzzleDenied::PuzzleNotFound));
+    };
+    let progress = PuzzleQueries::progress(pool, user_id).await?.into_iter().find(|p| p.puzzle_id == puzzle_id);
+    Ok(Ok(view(&mut conn, user_id, puzzle, progress).await?))
+}
+
+/// The puzzle files hidden on `ip` the player can read right now: their
+/// stages' files while the stage is open, and the keys to them
+pub async fn hidden_files(pool: &PgPool, user_id: i64, ip: &str) -> PuzzleResult<Vec<HiddenFile>> {
+    let mut conn = pool.acquire().await?;
+    if !PuzzleQueries::has_hacked(&mut conn, user_id, ip).await? {
+        return Ok(Err(PuzzleDenied::ServerNotHacked { ip: ip.to_string() }));
+    }
+
+    let now = Utc::now();
+    let mut files = Vec::new();
+    for stage in PuzzleQueries::stages_on_server(pool, user_id, ip).await? {
+        let access = stage_access(&mut conn, user_id, &stage).await?;
+        if stage.server_ip == ip && puzzles::check_access(&access, now).is_ok() {
+            let content = match &stage.cipher_key {
+                Some(key) => puzzles::vigenere(&stage.content, key, false),
+                None => stage.content.clone(),
+            };
+            files.push(HiddenFile {
+                puzzle_id: stage.puzzle_id,
+                name: stage.file_name.clone(),
+                content,
+                encrypted: stage.cipher_key.is_some(),
+            });
+        }
+
+        let community_open = access.community.as_ref().map_or(true, |gate| gate.reached);
+        if let (Some(key), Some(name), true) = (&stage.cipher_key, &stage.key_file_name, community_open) {
+            if stage.key_server_ip.as_deref() == Some(ip) {
+                files.push(HiddenFile { puzzle_id: stage.puzzle_id, name: name.clone(), content: key.clone(), encrypted: false });
+            }
+        }
+    }
+    Ok(Ok(files))
+}
+
+/// Check `answer` to the player's current stage of `puzzle_id`
+pub async fn answer(pool: &PgPool, user_id: i64, puzzle_id: i64, answer: &str) -> PuzzleResult<AnswerOutcome> {
+    let config = &crate::balance::current().puzzles;
+    if answer.chars().count() > config.max_answer_len {
+        return Ok(Err(PuzzleDenied::AnswerTooLong { max: config.max_answer_len }));
+    }
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(puzzle) = PuzzleQueries::puzzle(&mut tx, puzzle_id).await? else {
+        return Ok(Err(PuzzleDenied::PuzzleNotFound));
+    };
+    let progress = PuzzleQueries::lock_progress(&mut tx, user_id, puzzle_id).await?;
+    if progress.completed_at.is_some() {
+        return Ok(Err(PuzzleDenied::AlreadySolved));
+    }
+    let now = Utc::now();
+    if let Some(locked_until) = progress.locked_until.filter(|until| *until > now) {
+        let retry_after_secs = (locked_until - now).num_seconds().max(1);
+        return Ok(Err(PuzzleDenied::CoolingDown { retry_after_secs }));
+    }
+    let stage = PuzzleQueries::stage(&mut tx, puzzle_id, progress.stage)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Puzzle {} has no stage {}", puzzle_id, progress.stage))?;
+    if let Err(denied) = puzzles::check_access(&stage_access(&mut tx, user_id, &stage).await?, now) {
+        return Ok(Err(denied));
+    }
+
+    if answer_hash(&puzzle.slug, stage.stage, answer) != stage.answer_hash {
+        let retry_after_secs = puzzles::answer_cooldown_secs(progress.wrong_attempts + 1, config);
+        let locked_until = now + chrono::Duration::seconds(retry_after_secs);
+        PuzzleQueries::record_wrong_answer(&mut tx, user_id, puzzle_id, locked_until).await?;
+        tx.commit().await?;
+        return Ok(Err(PuzzleDenied::WrongAnswer { retry_after_secs }));
+    }
+
+    let next_stage = stage.stage + 1;
+    let finished = next_stage as i64 > puzzle.stages;
+    let mut reward = None;
+    if !finished {
+        PuzzleQueries::advance(&mut tx, user_id, puzzle_id, next_stage).await?;
+    } else {
+        if progress.rewarded_at.is_none() {
+            let mut paid = CompletionReward {
+                experience: crate::rules::current().experience(puzzle.reward_experience),
+                money: puzzle.reward_money,
+            };
+            let reference = format!("puzzle:{}:{}", puzzle_id, user_id);
+            if paid.money > 0 {
+                let credited = BankQueries::credit_primary_account(
+                    &mut tx,
+                    user_id,
+                    paid.money,
+                    LedgerAccount::Mint,
+                    LedgerReason::PuzzleReward,
+                    &reference,
+                )
+                .await?;
+                if !credited {
+                    // Nowhere to pay out; the experience is still granted
+                    paid.money = 0;
+                }
+            }
+            if paid.experience > 0 {
+                ProgressionQueries::add_experience(&mut tx, user_id, paid.experience).await?;
+            }
+            reward = Some(paid);
+        }
+        PuzzleQueries::complete(&mut tx, user_id, puzzle_id, reward.is_some()).await?;
+    }
+    let milestones = PuzzleQueries::reach_milestones(&mut tx, puzzle_id).await?;
+    tx.commit().await?;
+
+    for milestone in &milestones {
+        tracing::info!("Puzzle {} milestone reached: {}", puzzle.slug, milestone.title);
+    }
+    Ok(Ok(AnswerOutcome {
+        solved_stage: stage.stage,
+        next_stage: (!finished).then_some(next_stage),
+        reward,
+        milestones,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answer_hash_matches_seed() {
+        // The first stage of the seeded mystery server puzzle
+        assert_eq!(
+            answer_hash("mystery", 1, "  Ghost in the   WIRES "),
+            "5f1e1ac4dce250b0e8beb1316827e5a853e2b4ff9965f0f7d48fcbff9ebf5816"
+        );
+    }
+}
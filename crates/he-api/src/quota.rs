@@ -0,0 +1,369 @@
+//! Per-account process quotas and the operator kill-switch
+//!
+//! Checked in the transaction that inserts the process, with the player's row
+//! locked, so concurrent requests cannot both pass on the same counts. An
+//! account may have at most `max_concurrent` processes running, slots held by
+//! its reservations included, and may start at most the hourly limit of each
+//! process type. Starts are counted from `process_starts`, which cancelling
+//! does not touch. Violations go to the audit log and the intrusion detector,
+//! so a client spamming processes ends up blocked.
+//!
+//! Process types are compared by [`ProcessType::key`], so `DDoS`, `ddos` and
+//! `ddos_attack` share one limit and one kill-switch.
+//!
+//! The kill-switch stops a process type for everyone, e.g. while an exploit in
+//! it is being fixed. Engaging it cancels all unfinished processes of the type.
+//! Switches live in `process_kill_switches` and every node reloads them on an
+//! interval.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use he_database::queries::{ProcessQueries, ReservationQueries};
+use he_game_mechanics::process::ProcessType;
+use he_helix_security::{AuditLogger, IntrusionDetector, SecurityEvent};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use std::env;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// Quota limits, keyed by [`ProcessType::key`]
+#[derive(Debug, Clone)]
+pub struct QuotaConfig {
+    pub max_concurrent: i64,
+    pub default_hourly: i64,
+    pub hourly: HashMap<String, i64>,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        let hourly = [
+            ("crack", 30),
+            ("ddos", 10),
+            ("bitcoin_mine", 20),
+            ("port_scan", 120),
+            ("system_scan", 120),
+            ("install", 60),
+        ]
+            .into_iter()
+            .map(|(t, n)| (t.to_string(), n))
+            .collect();
+
+        Self {
+            max_concurrent: 10,
+            default_hourly: 120,
+            hourly,
+        }
+    }
+}
+
+impl QuotaConfig {
+    /// Defaults, overridden by `PROCESS_MAX_CONCURRENT`, `PROCESS_HOURLY_DEFAULT`
+    /// and `PROCESS_HOURLY_LIMITS` (e.g. `crack=30,ddos=10`)
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Some(n) = env::var("PROCESS_MAX_CONCURRENT").ok().and_then(|v| v.parse().ok()) {
+            config.max_concurrent = n;
+        }
+        if let Some(n) = env::var("PROCESS_HOURLY_DEFAULT").ok().and_then(|v| v.parse().ok()) {
+            config.default_hourly = n;
+        }
+        if let Ok(limits) = env::var("PROCESS_HOURLY_LIMITS") {
+            for entry in limits.split(',') {
+                let Some((process_type, n)) = entry.split_once('=') else { continue };
+                match n.trim().parse() {
+                    Ok(n) => {
+                        config.hourly.insert(type_key(process_type.trim()), n);
+                    }
+                    Err(_) => tracing::warn!("Ignoring bad PROCESS_HOURLY_LIMITS entry '{}'", entry),
+                }
+            }
+        }
+
+        config
+    }
+
+    pub fn hourly_limit(&self, process_type: &str) -> i64 {
+        self.hourly.get(&type_key(process_type)).copied().unwrap_or(self.default_hourly)
+    }
+}
+
+/// The key limits and kill-switches are stored under
+pub fn type_key(process_type: &str) -> String {
+    ProcessType::from_str(process_type).key()
+}
+
+/// Why a process was refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "quota", rename_all = "snake_case")]
+pub enum QuotaViolation {
+    KillSwitch { process_type: String, reason: String },
+    Concurrent { limit: i64 },
+    Hourly { process_type: String, limit: i64 },
+}
+
+impl QuotaViolation {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QuotaViolation::KillSwitch { .. } => "kill_switch",
+            QuotaViolation::Concurrent { .. } => "concurrent",
+            QuotaViolation::Hourly { .. } => "hourly",
+        }
+    }
+
+    pub fn limit(&self) -> Option<i64> {
+        match self {
+            QuotaViolation::KillSwitch { .. } => None,
+            QuotaViolation::Concurrent { limit } | QuotaViolation::Hourly { limit, .. } => Some(*limit),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            QuotaViolation::KillSwitch { process_type, reason } => {
+                format!("{} processes are disabled: {}", process_type, reason)
+            }
+            QuotaViolation::Concurrent { limit } => {
+                format!("You can run at most {} processes at once", limit)
+            }
+            QuotaViolation::Hourly { process_type, limit } => {
+                format!("You can start at most {} {} processes per hour", limit, process_type)
+            }
+        }
+    }
+}
+
+/// Apply the limits to the account's current counts
+pub fn evaluate(
+    config: &QuotaConfig,
+    process_type: &str,
+    active: i64,
+    started_last_hour: i64,
+) -> Result<(), QuotaViolation> {
+    if active >= config.max_concurrent {
+        return Err(QuotaViolation::Concurrent { limit: config.max_concurrent });
+    }
+
+    let limit = config.hourly_limit(process_type);
+    if started_last_hour >= limit {
+        return Err(QuotaViolation::Hourly {
+            process_type: type_key(process_type),
+            limit,
+        });
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KillSwitch {
+    pub process_type: String,
+    pub reason: String,
+    pub engaged_by: Option<i64>,
+    pub engaged_at: DateTime<Utc>,
+}
+
+pub struct ProcessGuard {
+    config: QuotaConfig,
+    switches: RwLock<Arc<HashMap<String, KillSwitch>>>,
+}
+
+impl ProcessGuard {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            switches: RwLock::new(Arc::new(HashMap::new())),
+        }
+    }
+
+    pub fn config(&self) -> &QuotaConfig {
+        &self.config
+    }
+
+    pub async fn kill_switches(&self) -> Arc<HashMap<String, KillSwitch>> {
+        self.switches.read().await.clone()
+    }
+
+    /// Check whether `user_id` may start a `process_type` process now. Runs
+    /// in the caller's transaction and locks the player's row, which stays
+    /// locked until the transaction ends: insert the process on `conn` before
+    /// committing.
+    pub async fn admit(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i64,
+        process_type: &str,
+    ) -> anyhow::Result<Result<(), QuotaViolation>> {
+        let process_type = ProcessType::from_str(process_type);
+        let key = process_type.key();
+
+        if let Some(switch) = self.kill_switches().await.get(&key) {
+            return Ok(Err(QuotaViolation::KillSwitch {
+                process_type: key,
+                reason: switch.reason.clone(),
+            }));
+        }
+
+        ProcessQueries::lock_quota(conn, user_id).await?;
+
+        // Slots held by reservations count as running
+        let active = ProcessQueries::count_active(conn, user_id).await?
+            + ReservationQueries::held_slots(conn, user_id, None).await?;
+        let since = Utc::now() - ChronoDuration::hours(1);
+        let started = ProcessQueries::count_started_since(conn, user_id, &process_type.spellings(), since).await?;
+
+        Ok(evaluate(&self.config, &key, active, started))
+    }
+
+    /// Admit, and report violations to the audit log and the intrusion
+    /// detector. Kill-switch refusals are audited but not counted against the
+    /// client.
+    pub async fn admit_and_report(
+        &self,
+        conn: &mut PgConnection,
+        user_id: i64,
+        process_type: &str,
+        ip: IpAddr,
+        audit: &AuditLogger,
+        intrusion: &IntrusionDetector,
+    ) -> anyhow::Result<Result<(), QuotaViolation>> {
+        let result = self.admit(conn, user_id, process_type).await?;
+
+        if let Err(violation) = &result {
+            audit.log_event(SecurityEvent::ProcessQuotaExceeded {
+                user_id,
+                process_type: type_key(process_type),
+                quota: violation.kind().to_string(),
+                limit: violation.limit(),
+                ip,
+            }).await;
+
+            if !matches!(violation, QuotaViolation::KillSwitch { .. }) {
+                intrusion.report_quota_violation(ip, user_id, violation.kind());
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Stop `process_type` for everyone and cancel its unfinished processes.
+    /// Returns `(pid, user_id)` of each cancelled process.
+    pub async fn engage(
+        &self,
+        pool: &PgPool,
+        process_type: &str,
+        reason: &str,
+        admin_id: i64,
+    ) -> anyhow::Result<Vec<(i64, i64)>> {
+        let process_type = ProcessType::from_str(process_type);
+        let key = process_type.key();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO process_kill_switches (process_type, reason, engaged_by)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (process_type) DO UPDATE
+                SET reason = EXCLUDED.reason, engaged_by = EXCLUDED.engaged_by, engaged_at = NOW()
+            "#,
+            key,
+            reason,
+            admin_id
+        )
+        .execute(pool)
+        .await?;
+
+        // Block new processes on this node before cancelling the running ones
+        self.reload(pool).await?;
+
+        ProcessQueries::cancel_all_of_type(pool, &process_type.spellings()).await
+    }
+
+    /// Allow `process_type` again; false if it was not switched off
+    pub async fn release(&self, pool: &PgPool, process_type: &str) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM process_kill_switches WHERE process_type = $1",
+            type_key(process_type)
+        )
+        .execute(pool)
+        .await?;
+
+        self.reload(pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replace the cached switches with the rows in the database
+    pub async fn reload(&self, pool: &PgPool) -> anyhow::Result<usize> {
+        let switches: HashMap<String, KillSwitch> = sqlx::query_as!(
+            KillSwitch,
+            "SELECT process_type, reason, engaged_by, engaged_at FROM process_kill_switches"
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|s| (type_key(&s.process_type), s))
+        .collect();
+
+        let count = switches.len();
+        *self.switches.write().await = Arc::new(switches);
+        Ok(count)
+    }
+
+    /// Reload on an interval so switches engaged on any node apply here, and
+    /// drop starts past the hourly window
+    pub fn spawn_reloader(self: Arc<Self>, pool: PgPool, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.reload(&pool).await {
+                    tracing::error!("Failed to reload process kill-switches: {}", e);
+                }
+                if let Err(e) = ProcessQueries::prune_starts(&pool, Utc::now() - ChronoDuration::hours(2)).await {
+                    tracing::warn!("Failed to prune process starts: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_limit() {
+        let config = QuotaConfig::default();
+        assert!(evaluate(&config, "scan", 9, 0).is_ok());
+        assert_eq!(
+            evaluate(&config, "scan", 10, 0),
+            Err(QuotaViolation::Concurrent { limit: 10 })
+        );
+    }
+
+    #[test]
+    fn test_hourly_limit_by_type() {
+        let config = QuotaConfig::default();
+        assert!(evaluate(&config, "crack", 0, 29).is_ok());
+        assert_eq!(
+            evaluate(&config, "crack", 0, 30),
+            Err(QuotaViolation::Hourly { process_type: "crack".to_string(), limit: 30 })
+        );
+        // Types without their own limit use the default
+        assert!(evaluate(&config, "download", 0, 119).is_ok());
+        assert!(evaluate(&config, "download", 0, 120).is_err());
+    }
+
+    #[test]
+    fn test_spellings_share_a_limit() {
+        let config = QuotaConfig::default();
+        assert_eq!(config.hourly_limit("DDoS_Attack"), 10);
+        assert_eq!(config.hourly_limit("ddos"), 10);
+        assert_eq!(
+            evaluate(&config, "DDOS", 0, 10),
+            Err(QuotaViolation::Hourly { process_type: "ddos".to_string(), limit: 10 })
+        );
+    }
+}
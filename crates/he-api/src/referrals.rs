@@ -0,0 +1,289 @@
+//! Referrals and invites
+//!
+//! Every player gets a referral code on first asking. A sign-up that carries
+//! a code is attributed to its owner after the sign-up's IP and device are
+//! compared with the owner's sign-in history; self-referrals are rejected and
+//! doubtful ones held for a moderator (see
+//! [`he_game_mechanics::referrals`]).
+//!
+//! Rewards are never paid at sign-up. A sweeper pays each milestone once the
+//! referred account has reached its level and playtime. It rejects a
+//! referral whose account turns out to sign in from the referrer's IPs or
+//! devices and holds one whose account is banned for a moderator. Referrers
+//! are told through the outbox.
+
+use he_database::queries::{NewReferral, ReferralProgress, ReferralQueries, ReferralRow};
+use he_game_mechanics::referrals::{self, AbuseSignal, ReferralStatus, SignupSignals};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::net::IpAddr;
+use std::time::Duration;
+use crate::outbox::{self, OutboxMessage};
+
+/// Header clients send a stable per-install identifier in
+pub const DEVICE_HEADER: &str = "X-Device-Id";
+/// How often pending referrals are checked for milestones
+const SWEEP_INTERVAL: Duration = Duration::from_secs(300);
+const SWEEP_BATCH: i64 = 200;
+/// Attempts at a code that is not already taken
+const CODE_ATTEMPTS: usize = 5;
+
+/// What happened to a referral, as its referrer is told
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ReferralEventKind {
+    Joined,
+    Milestone { milestone: usize, reward: i64, paid: bool },
+    Rejected,
+}
+
+impl ReferralEventKind {
+    fn key(&self) -> String {
+        match self {
+            ReferralEventKind::Joined => "joined".to_string(),
+            ReferralEventKind::Milestone { milestone, .. } => format!("milestone:{}", milestone),
+            ReferralEventKind::Rejected => "rejected".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferralEvent {
+    pub referral_id: i64,
+    pub referred_id: i64,
+    pub status: String,
+    #[serde(flatten)]
+    pub kind: ReferralEventKind,
+}
+
+impl ReferralEvent {
+    fn new(referral: &ReferralRow, status: ReferralStatus, kind: ReferralEventKind) -> Self {
+        Self {
+            referral_id: referral.id,
+            referred_id: referral.referred_id,
+            status: status.as_str().to_string(),
+            kind,
+        }
+    }
+
+    fn outbox_message(&self, referrer_id: i64) -> OutboxMessage {
+        let event = he_websocket::GameEvent::Custom {
+            event_name: "referral".to_string(),
+            payload: serde_json::to_value(self).unwrap_or_default(),
+        };
+        OutboxMessage::to_user(format!("referral:{}:{}", self.referral_id, self.kind.key()), referrer_id, &event)
+    }
+}
+
+/// Hash a client's device identifier; the raw value is never stored
+pub fn device_hash(device_id: &str) -> Option<String> {
+    let device_id = device_id.trim();
+    (!device_id.is_empty()).then(|| format!("{:x}", Sha256::digest(device_id.as_bytes())))
+}
+
+/// The player's code, creating one if they have none
+pub async fn ensure_code(pool: &PgPool, user_id: i64) -> anyhow::Result<String> {
+    if let Some(code) = ReferralQueries::code_for(pool, user_id).await? {
+        return Ok(code);
+    }
+
+    let config = &crate::balance::current().referrals;
+    for _ in 0..CODE_ATTEMPTS {
+        let code = referrals::generate_code(&mut rand::thread_rng(), config);
+        if ReferralQueries::create_code(pool, user_id, &code).await? {
+            return Ok(code);
+        }
+        // Either the code is taken or a concurrent request made one
+        if let Some(code) = ReferralQueries::code_for(pool, user_id).await? {
+            return Ok(code);
+        }
+    }
+    anyhow::bail!("no free referral code after {} attempts", CODE_ATTEMPTS)
+}
+
+/// Remember where a player signs in from, for later sign-ups to be
+/// compared with
+pub async fn record_sign_in(pool: &PgPool, user_id: i64, ip: IpAddr, device: Option<&str>) -> anyhow::Result<()> {
+    let device = device.and_then(device_hash);
+    ReferralQueries::record_sign_in(pool, user_id, &ip.to_string(), &referrals::network_of(ip), device.as_deref())
+        .await
+}
+
+/// Attribute a new account to the owner of `code`. `None` if the code is
+/// unknown or is the account's own.
+pub async fn attribute(
+    pool: &PgPool,
+    referred_id: i64,
+    code: &str,
+    ip: Option<IpAddr>,
+    device: Option<&str>,
+) -> anyhow::Result<Option<ReferralRow>> {
+    let config = &crate::balance::current().referrals;
+    let Some(code) = referrals::normalize_code(code, config) else {
+        return Ok(None);
+    };
+    let Some(referrer_id) = ReferralQueries::owner_of(pool, &code).await? else {
+        return Ok(None);
+    };
+    if referrer_id == referred_id {
+        return Ok(None);
+    }
+
+    let ip_text = ip.map(|ip| ip.to_string());
+    let network = ip.map(referrals::network_of);
+    let device = device.and_then(device_hash);
+    let found = ReferralQueries::signals(
+        pool,
+        referrer_id,
+        ip_text.as_deref(),
+        network.as_deref(),
+        device.as_deref(),
+        config.burst_window_hours,
+    )
+    .await?;
+
+    let signals = referrals::assess(
+        &SignupSignals {
+            shares_ip: found.shares_ip,
+            shares_device: found.shares_device,
+            shares_network: found.shares_network,
+            recent_from_network: found.recent_from_network as usize,
+            referrer_age_hours: found.referrer_age_hours,
+        },
+        config,
+    );
+    let status = referrals::initial_status(&signals);
+    if status != ReferralStatus::Pending {
+        tracing::info!(
+            "Referral of user {} by {} is {}: {:?}",
+            referred_id,
+            referrer_id,
+            status.as_str(),
+            signals
+        );
+    }
+
+    let referral = ReferralQueries::attribute(
+        pool,
+        &NewReferral {
+            referrer_id,
+            referred_id,
+            code: &code,
+            status: status.as_str(),
+            signals: serde_json::to_value(&signals).unwrap_or_default(),
+            signup_ip: ip_text.as_deref(),
+            signup_network: network.as_deref(),
+        },
+    )
+    .await?;
+
+    // A rejected self-referral is not announced to its "referrer"
+    if let Some(referral) = referral.as_ref().filter(|_| status != ReferralStatus::Rejected) {
+        let event = ReferralEvent::new(referral, status, ReferralEventKind::Joined);
+        let mut conn = pool.acquire().await?;
+        outbox::enqueue(&mut *conn, &[event.outbox_message(referrer_id)]).await?;
+        outbox::wake();
+    }
+    Ok(referral)
+}
+
+/// Clear a flagged referral to earn rewards again, or reject it. `None` if
+/// it is not flagged.
+pub async fn review(
+    pool: &PgPool,
+    referral_id: i64,
+    admin_id: i64,
+    approve: bool,
+    note: &str,
+) -> anyhow::Result<Option<ReferralRow>> {
+    ReferralQueries::review(pool, referral_id, admin_id, approve, note).await
+}
+
+/// Pay milestones every [`SWEEP_INTERVAL`]
+pub(crate) fn spawn_milestones(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep(&pool).await {
+                tracing::warn!("Referral sweep failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn sweep(pool: &PgPool) -> anyhow::Result<()> {
+    let config = &crate::balance::current().referrals;
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let mut messages = Vec::new();
+
+    for progress in ReferralQueries::pending(&mut *tx, SWEEP_BATCH).await? {
+        let ReferralProgress { referral, level, playtime_secs, banned, shares_sign_in } = progress;
+
+        if banned || shares_sign_in {
+            let mut signals: Vec<AbuseSignal> = serde_json::from_value(referral.signals.clone()).unwrap_or_default();
+            if shares_sign_in {
+                signals.push(AbuseSignal::SameIp);
+            }
+            let status = if banned { ReferralStatus::Flagged } else { ReferralStatus::Rejected };
+            ReferralQueries::set_status(
+                &mut *tx,
+                referral.id,
+                status.as_str(),
+                serde_json::to_value(&signals).unwrap_or_default(),
+            )
+            .await?;
+            if status == ReferralStatus::Rejected {
+                messages.push(
+                    ReferralEvent::new(&referral, status, ReferralEventKind::Rejected)
+                        .outbox_message(referral.referrer_id),
+                );
+            }
+            continue;
+        }
+
+        let due = referrals::due_milestones(level, playtime_secs, referral.milestones_paid.max(0) as usize, config);
+        for (milestone, reward) in due {
+            let last = milestone + 1 == config.milestones.len();
+            let Some(paid) =
+                ReferralQueries::pay_milestone(&mut *tx, &referral, milestone as i32, reward, last).await?
+            else {
+                continue;
+            };
+            if !paid {
+                tracing::warn!("Referral {} reached a milestone but its referrer has no bank account", referral.id);
+            }
+            let status = if last { ReferralStatus::Completed } else { ReferralStatus::Pending };
+            let kind = ReferralEventKind::Milestone { milestone, reward, paid };
+            messages.push(ReferralEvent::new(&referral, status, kind).outbox_message(referral.referrer_id));
+        }
+    }
+
+    outbox::enqueue(&mut *tx, &messages).await?;
+    tx.commit().await?;
+    if !messages.is_empty() {
+        outbox::wake();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_hash_and_event_keys() {
+        assert_eq!(device_hash("  "), None);
+        assert_eq!(device_hash("install-1").map(|hash| hash.len()), Some(64));
+        assert_eq!(device_hash("install-1"), device_hash(" install-1 "));
+
+        let event = ReferralEvent {
+            referral_id: 9,
+            referred_id: 40,
+            status: "pending".to_string(),
+            kind: ReferralEventKind::Milestone { milestone: 1, reward: 200_000, paid: true },
+        };
+        assert_eq!(event.outbox_message(12).dedup_key, "referral:9:milestone:1");
+    }
+}
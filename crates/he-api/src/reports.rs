@@ -0,0 +1,403 @@
+//! Player reports and the moderation queue
+//!
+//! A report names a piece of content: a chat message, a username, a server
+//! page or a clan name. The content is read as it is now and kept with the
+//! case as evidence, and its fingerprint decides which case the report
+//! joins (see [`he_game_mechanics::reports`]). Server pages whose text trips
+//! the offensive-terms list are reported by the game itself.
+//!
+//! Moderators close a case by dismissing it or by acting on the player
+//! responsible. Actions are recorded in the moderation history the case
+//! links to, and the player and the reporters are told through the outbox.
+//! Cases are published to moderators on `mod.reports` as they open, gain
+//! reporters and close.
+
+use chrono::{Duration, Utc};
+use he_database::queries::{ModerationQueries, NewReportCase, ReportCaseRow, ReportQueries, ServerQueries};
+use he_game_mechanics::reports::{self, ReportAction, ReportCategory, ReportDenied, ReportTarget, ResolveDenied};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use he_websocket::{EventBuilder, ModChannel};
+use crate::outbox::{self, OutboxMessage};
+
+/// Messages before a reported one kept as its context
+const CHAT_CONTEXT: i64 = 5;
+/// What a removed chat message reads afterwards
+const REMOVED_MESSAGE: &str = "[removed by a moderator]";
+
+/// Content as reported, with who is responsible for it
+#[derive(Debug, Clone)]
+pub struct Evidence {
+    pub subject_id: Option<i64>,
+    /// The text compared between reports
+    pub content: String,
+    pub snapshot: serde_json::Value,
+}
+
+/// What the reporter is told
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportReceipt {
+    pub case_id: i64,
+    /// Other players had reported the same content already
+    pub joined_existing: bool,
+}
+
+/// Fingerprint of the content, so reports of the same text share a case
+pub fn fingerprint(target: ReportTarget, target_key: &str, content: &str) -> String {
+    let normalized = reports::normalize_content(content);
+    let digest = Sha256::digest(format!("{}\n{}\n{}", target.as_str(), target_key, normalized).as_bytes());
+    format!("{:x}", digest)
+}
+
+/// Read the reported content now. `None` if it does not exist or the
+/// reporter cannot see it.
+pub async fn collect_evidence(
+    pool: &PgPool,
+    target: ReportTarget,
+    target_key: &str,
+    reporter_id: Option<i64>,
+) -> anyhow::Result<Option<Evidence>> {
+    let captured_at = Utc::now();
+    let evidence = match target {
+        ReportTarget::ChatMessage => {
+            let (Ok(message_id), Some(reporter_id)) = (target_key.parse::<i64>(), reporter_id) else {
+                return Ok(None);
+            };
+            let Some(message) = ReportQueries::chat_message(pool, message_id, reporter_id).await? else {
+                return Ok(None);
+            };
+            let context = ReportQueries::chat_context(pool, message.thread_id, message.id, CHAT_CONTEXT).await?;
+            Evidence {
+                subject_id: message.user_id,
+                content: message.body.clone(),
+                snapshot: json!({
+                    "captured_at": captured_at,
+                    "thread_id": message.thread_id,
+                    "message": {
+                        "id": message.id,
+                        "user_id": message.user_id,
+                        "body": message.body,
+                        "created_at": message.created_at,
+                    },
+                    "context": context,
+                }),
+            }
+        }
+        ReportTarget::Username => {
+            let Ok(user_id) = target_key.parse::<i64>() else {
+                return Ok(None);
+            };
+            let Some(login) = ReportQueries::username(pool, user_id).await? else {
+                return Ok(None);
+            };
+            Evidence {
+                subject_id: Some(user_id),
+                content: login.clone(),
+                snapshot: json!({ "captured_at": captured_at, "user_id": user_id, "username": login }),
+            }
+        }
+        ReportTarget::ServerPage => {
+            let Some(page) = ServerQueries::get_public_page(pool, target_key).await? else {
+                return Ok(None);
+            };
+            let fields = [page.hostname.as_deref(), page.motd.as_deref(), page.public_profile.as_deref()];
+            Evidence {
+                subject_id: Some(page.owner_id),
+                content: fields.iter().map(|field| field.unwrap_or("")).collect::<Vec<_>>().join("\n"),
+                snapshot: json!({
+                    "captured_at": captured_at,
+                    "server_id": page.server_id,
+                    "ip_address": page.ip_address,
+                    "owner": page.owner,
+                    "hostname": page.hostname,
+                    "motd": page.motd,
+                    "page_html": page.public_profile,
+                }),
+            }
+        }
+        ReportTarget::ClanName => {
+            let Ok(clan_id) = target_key.parse::<i64>() else {
+                return Ok(None);
+            };
+            let Some((name, tag, leader_id)) = ReportQueries::clan(pool, clan_id).await? else {
+                return Ok(None);
+            };
+            Evidence {
+                subject_id: Some(leader_id),
+                content: format!("{}\n{}", name, tag),
+                snapshot: json!({
+                    "captured_at": captured_at,
+                    "clan_id": clan_id,
+                    "name": name,
+                    "tag": tag,
+                    "leader_id": leader_id,
+                }),
+            }
+        }
+    };
+    Ok(Some(evidence))
+}
+
+/// File a player's report
+pub async fn submit(
+    pool: &PgPool,
+    reporter_id: i64,
+    target: ReportTarget,
+    target_key: &str,
+    category: ReportCategory,
+    description: &str,
+) -> anyhow::Result<Result<ReportReceipt, ReportDenied>> {
+    let config = &crate::balance::current().reports;
+    let open = ReportQueries::open_by_reporter(pool, reporter_id).await?;
+    if let Err(denied) = reports::check_report(description, open.max(0) as usize, config) {
+        return Ok(Err(denied));
+    }
+
+    let Some(evidence) = collect_evidence(pool, target, target_key, Some(reporter_id)).await? else {
+        return Ok(Err(ReportDenied::TargetNotFound));
+    };
+    if evidence.subject_id == Some(reporter_id) {
+        return Ok(Err(ReportDenied::OwnContent));
+    }
+
+    file(pool, Some(reporter_id), target, target_key, category, description, evidence).await
+}
+
+/// Report content on the game's behalf, e.g. text the offensive-terms list
+/// matched. Returns the case, unless the content is gone or the game has
+/// reported it already.
+pub async fn auto_report(
+    pool: &PgPool,
+    target: ReportTarget,
+    target_key: &str,
+    category: ReportCategory,
+    description: &str,
+) -> anyhow::Result<Option<i64>> {
+    let Some(evidence) = collect_evidence(pool, target, target_key, None).await? else {
+        return Ok(None);
+    };
+    let filed = file(pool, None, target, target_key, category, description, evidence).await?;
+    Ok(filed.ok().map(|receipt| receipt.case_id))
+}
+
+async fn file(
+    pool: &PgPool,
+    reporter_id: Option<i64>,
+    target: ReportTarget,
+    target_key: &str,
+    category: ReportCategory,
+    description: &str,
+    evidence: Evidence,
+) -> anyhow::Result<Result<ReportReceipt, ReportDenied>> {
+    let config = &crate::balance::current().reports;
+    let fingerprint = fingerprint(target, target_key, &evidence.content);
+    let mut tx = he_database::tagging::begin(pool).await?;
+
+    // Content a moderator just found fine is recorded but not queued again
+    let dismissed = ReportQueries::dismissed_case(
+        &mut *tx,
+        target.as_str(),
+        target_key,
+        &fingerprint,
+        config.dismissed_dedup_days,
+    )
+    .await?;
+    if let Some(case_id) = dismissed {
+        if ReportQueries::add_report(&mut *tx, case_id, reporter_id, category.as_str(), description).await?.is_none() {
+            tx.rollback().await?;
+            return Ok(Err(ReportDenied::AlreadyReported));
+        }
+        tx.commit().await?;
+        return Ok(Ok(ReportReceipt { case_id, joined_existing: true }));
+    }
+
+    let now = Utc::now();
+    let (case, created) = ReportQueries::open_case(
+        &mut *tx,
+        &NewReportCase {
+            target: target.as_str(),
+            target_key,
+            fingerprint: &fingerprint,
+            subject_id: evidence.subject_id,
+            evidence: evidence.snapshot,
+            category: category.as_str(),
+            priority: reports::priority(category, 1, config),
+            sla_due_at: reports::sla_due(now, category, config),
+        },
+    )
+    .await?;
+
+    let Some(reporters) =
+        ReportQueries::add_report(&mut *tx, case.id, reporter_id, category.as_str(), description).await?
+    else {
+        tx.rollback().await?;
+        return Ok(Err(ReportDenied::AlreadyReported));
+    };
+
+    let (change, category, priority) = if created {
+        ("opened", category, case.priority)
+    } else {
+        let current = ReportCategory::from_str(&case.category).unwrap_or(category);
+        let category = reports::escalate(current, category);
+        let priority = reports::priority(category, reporters.max(0) as usize, config);
+        ReportQueries::requeue(
+            &mut *tx,
+            case.id,
+            category.as_str(),
+            priority,
+            reporters,
+            reports::sla_due(case.created_at, category, config),
+        )
+        .await?;
+        ("requeued", category, priority)
+    };
+
+    let event = EventBuilder::report_case(
+        case.id,
+        change.to_string(),
+        category.as_str().to_string(),
+        priority,
+        reporters as i32,
+    );
+    let message =
+        OutboxMessage::to_moderators(format!("report_case:{}:{}", case.id, reporters), ModChannel::Reports, &event);
+    outbox::enqueue(&mut *tx, &[message]).await?;
+    tx.commit().await?;
+    outbox::wake();
+    Ok(Ok(ReportReceipt { case_id: case.id, joined_existing: !created }))
+}
+
+/// Close an open case with `action`, taking it against the player
+/// responsible in the same transaction
+pub async fn resolve(
+    pool: &PgPool,
+    case_id: i64,
+    moderator_id: i64,
+    action: ReportAction,
+    note: &str,
+) -> anyhow::Result<Result<ReportCaseRow, ResolveDenied>> {
+    let config = &crate::balance::current().reports;
+    let mut tx = he_database::tagging::begin(pool).await?;
+
+    let Some(case) = ReportQueries::lock_open(&mut *tx, case_id).await? else {
+        tx.rollback().await?;
+        return Ok(Err(ResolveDenied::NotOpen));
+    };
+    let Some(target) = ReportTarget::from_str(&case.target) else {
+        anyhow::bail!("report case {} has unknown target {}", case.id, case.target);
+    };
+    if let Err(denied) = reports::check_resolution(action, target, case.subject_id.is_some(), config) {
+        tx.rollback().await?;
+        return Ok(Err(denied));
+    }
+
+    let mut moderation_action_id = None;
+    let mut messages = Vec::new();
+    if let Some(subject_id) = case.subject_id.filter(|_| action != ReportAction::Dismiss) {
+        let expires_at = match action {
+            ReportAction::Ban { hours: Some(hours) } => Some(Utc::now() + Duration::hours(hours)),
+            _ => None,
+        };
+        match action {
+            ReportAction::RemoveContent => remove_content(&mut tx, target, &case.target_key).await?,
+            ReportAction::Ban { .. } => ModerationQueries::ban(&mut *tx, subject_id, note, expires_at).await?,
+            ReportAction::Warn | ReportAction::Dismiss => {}
+        }
+        let id = ModerationQueries::record(&mut *tx, subject_id, moderator_id, action.as_str(), note, expires_at).await?;
+        moderation_action_id = Some(id);
+        messages.push(subject_message(&case, subject_id, id, action, note));
+    }
+
+    let status = if action == ReportAction::Dismiss { "dismissed" } else { "actioned" };
+    let closed =
+        ReportQueries::close(&mut *tx, case.id, status, moderator_id, action.as_str(), moderation_action_id, note)
+            .await?;
+    for reporter_id in ReportQueries::reporters(&mut *tx, case.id).await? {
+        messages.push(reporter_message(&closed, reporter_id));
+    }
+    let event = EventBuilder::report_case(
+        closed.id,
+        closed.status.clone(),
+        closed.category.clone(),
+        closed.priority,
+        closed.reporters,
+    );
+    messages.push(OutboxMessage::to_moderators(format!("report_case:{}:closed", closed.id), ModChannel::Reports, &event));
+
+    outbox::enqueue(&mut *tx, &messages).await?;
+    tx.commit().await?;
+    outbox::wake();
+    Ok(Ok(closed))
+}
+
+async fn remove_content(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    target: ReportTarget,
+    target_key: &str,
+) -> anyhow::Result<()> {
+    let removed = match target {
+        ReportTarget::ChatMessage => match target_key.parse::<i64>() {
+            Ok(message_id) => ModerationQueries::remove_chat_message(&mut **tx, message_id, REMOVED_MESSAGE).await?,
+            Err(_) => false,
+        },
+        ReportTarget::ServerPage => ModerationQueries::clear_server_page(&mut **tx, target_key).await?,
+        ReportTarget::ClanName => match target_key.parse::<i64>() {
+            Ok(clan_id) => ModerationQueries::reset_clan_name(&mut **tx, clan_id).await?,
+            Err(_) => false,
+        },
+        ReportTarget::Username => false,
+    };
+    if !removed {
+        tracing::warn!("Reported {} {} was already gone", target.as_str(), target_key);
+    }
+    Ok(())
+}
+
+/// Tell the player what was done about their content
+fn subject_message(
+    case: &ReportCaseRow,
+    subject_id: i64,
+    moderation_action_id: i64,
+    action: ReportAction,
+    note: &str,
+) -> OutboxMessage {
+    let event = he_websocket::GameEvent::Custom {
+        event_name: "moderation_action".to_string(),
+        payload: json!({
+            "moderation_action_id": moderation_action_id,
+            "target": case.target,
+            "action": action,
+            "reason": note,
+        }),
+    };
+    OutboxMessage::to_user(format!("moderation:{}", moderation_action_id), subject_id, &event)
+}
+
+/// Tell a reporter their report was dealt with; not how
+fn reporter_message(case: &ReportCaseRow, reporter_id: i64) -> OutboxMessage {
+    let event = he_websocket::GameEvent::Custom {
+        event_name: "report_closed".to_string(),
+        payload: json!({
+            "case_id": case.id,
+            "target": case.target,
+            "actioned": case.status == "actioned",
+        }),
+    };
+    OutboxMessage::to_user(format!("report:{}:closed:{}", case.id, reporter_id), reporter_id, &event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_matches_trivial_copies() {
+        let a = fingerprint(ReportTarget::ChatMessage, "41", "Buy  GOLD now");
+        assert_eq!(a.len(), 64);
+        assert_eq!(a, fingerprint(ReportTarget::ChatMessage, "41", " buy gold\nnow "));
+        assert_ne!(a, fingerprint(ReportTarget::ChatMessage, "42", "buy gold now"));
+        assert_ne!(a, fingerprint(ReportTarget::Username, "41", "buy gold now"));
+    }
+}
@@ -0,0 +1,374 @@
+//! Request/response logging with PII redaction
+//!
+//! The `RequestLogging` middleware logs a sample of requests, `sample_rate`
+//! of them, plus every server error: method, path, query, status, duration
+//! and the request's `request_id`/`trace_id`/`user_id`, so a log line can be
+//! followed into the request's span and out to the services it called.
+//!
+//! Operators put a user in verbose mode from the admin API to debug their
+//! account. That user's requests are all logged, with the request headers
+//! and the request and response bodies, cut to `max_body_bytes`.
+//!
+//! Nothing is logged before it is redacted. Fields are judged by name, in
+//! JSON bodies, forms, query strings and headers alike: passwords, tokens,
+//! secrets, cookies, keys and codes are replaced, emails masked to their
+//! first letter and domain. JWTs are replaced wherever they turn up.
+//!
+//! Settings and verbose users live in Postgres and every node reloads them
+//! on an interval, like [`crate::panic_levers`].
+
+use chrono::{DateTime, Utc};
+use he_database::queries::{RequestLogQueries, RequestLogSettingsRow, VerboseLoggingRow};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Bodies larger than this are not read at all, whatever `max_body_bytes`
+pub const CAPTURE_LIMIT: usize = 256 * 1024;
+
+/// Field names holding secrets, matched anywhere in the name
+const SECRET_FIELDS: &[&str] = &[
+    "password", "passwd", "passphrase", "secret", "token", "authorization", "cookie",
+    "api_key", "apikey", "private_key", "captcha", "mfa", "totp", "otp_",
+];
+/// Field names holding secrets only when they are the whole name
+const SECRET_NAMES: &[&str] = &["code", "otp", "pin", "key", "signature"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldClass {
+    Secret,
+    Email,
+    Plain,
+}
+
+fn classify(name: &str) -> FieldClass {
+    let name = name.to_ascii_lowercase().replace('-', "_");
+    if SECRET_NAMES.contains(&name.as_str()) || SECRET_FIELDS.iter().any(|field| name.contains(field)) {
+        FieldClass::Secret
+    } else if name.contains("email") {
+        FieldClass::Email
+    } else {
+        FieldClass::Plain
+    }
+}
+
+/// `alice@example.com` becomes `a***@example.com`; anything that is not an
+/// address is redacted whole
+pub fn mask_email(value: &str) -> String {
+    match value.split_once('@') {
+        Some((local, domain)) if !domain.is_empty() => match local.chars().next() {
+            Some(first) => format!("{}***@{}", first, domain),
+            None => format!("***@{}", domain),
+        },
+        _ => REDACTED.to_string(),
+    }
+}
+
+fn looks_like_jwt(value: &str) -> bool {
+    value.starts_with("eyJ") && value.split('.').count() == 3
+}
+
+/// Redact a JSON document in place
+pub fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                match classify(name) {
+                    FieldClass::Secret if !field.is_null() => *field = Value::String(REDACTED.to_string()),
+                    FieldClass::Email => match field {
+                        Value::String(address) => *address = mask_email(address),
+                        other => redact_json(other),
+                    },
+                    _ => redact_json(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        Value::String(s) if looks_like_jwt(s) => *s = REDACTED.to_string(),
+        _ => {}
+    }
+}
+
+/// Redact a query string or form body, keeping the parameters' order
+pub fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => match classify(name) {
+                FieldClass::Secret => format!("{}={}", name, REDACTED),
+                FieldClass::Email => format!("{}={}", name, mask_email(&value.replace("%40", "@"))),
+                FieldClass::Plain if looks_like_jwt(value) => format!("{}={}", name, REDACTED),
+                FieldClass::Plain => pair.to_string(),
+            },
+            None => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Mask path segments that are email addresses, as in lookups by email
+pub fn redact_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            let decoded = segment.replace("%40", "@");
+            if decoded.contains('@') { mask_email(&decoded) } else { segment.to_string() }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn redact_headers(headers: &actix_web::http::header::HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = match classify(name.as_str()) {
+                FieldClass::Secret => REDACTED.to_string(),
+                FieldClass::Email => value.to_str().map(mask_email).unwrap_or_else(|_| REDACTED.to_string()),
+                FieldClass::Plain => value.to_str().map(str::to_string).unwrap_or_else(|_| "<binary>".to_string()),
+            };
+            (name.as_str().to_string(), value)
+        })
+        .collect()
+}
+
+fn truncate(mut text: String, max: usize) -> String {
+    if text.len() > max {
+        let mut end = max;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("...[truncated]");
+    }
+    text
+}
+
+/// A body as it may be logged: JSON and forms redacted and cut to `max`
+/// bytes, anything else described by its size and type
+pub fn redact_body(content_type: Option<&str>, body: &[u8], max: usize) -> String {
+    if body.is_empty() {
+        return String::new();
+    }
+    let content_type = content_type.unwrap_or("application/octet-stream");
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+    if essence == "application/json" || essence.ends_with("+json") {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(mut json) => {
+                redact_json(&mut json);
+                truncate(json.to_string(), max)
+            }
+            Err(_) => format!("<{} bytes of invalid JSON>", body.len()),
+        }
+    } else if essence == "application/x-www-form-urlencoded" {
+        match std::str::from_utf8(body) {
+            Ok(form) => truncate(redact_query(form), max),
+            Err(_) => format!("<{} bytes of invalid form>", body.len()),
+        }
+    } else {
+        format!("<{} bytes of {}>", body.len(), essence)
+    }
+}
+
+/// Whether a request is logged, and how much of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detail {
+    /// Only if it ends in a server error
+    Errors,
+    /// Method, path, status and timing
+    Summary,
+    /// Headers and bodies as well
+    Verbose,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    pub sample_rate: f64,
+    pub max_body_bytes: usize,
+    pub verbose: Vec<VerboseLoggingRow>,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self { sample_rate: 0.01, max_body_bytes: 4096, verbose: Vec::new() }
+    }
+}
+
+impl LogSettings {
+    pub fn is_verbose(&self, user_id: i64, now: DateTime<Utc>) -> bool {
+        self.verbose.iter().any(|row| row.user_id == user_id && row.expires_at > now)
+    }
+
+    /// How much to log of a request by `user_id`, given a roll in `[0, 1)`
+    pub fn detail(&self, user_id: Option<i64>, roll: f64, now: DateTime<Utc>) -> Detail {
+        if user_id.is_some_and(|id| self.is_verbose(id, now)) {
+            Detail::Verbose
+        } else if roll < self.sample_rate {
+            Detail::Summary
+        } else {
+            Detail::Errors
+        }
+    }
+}
+
+pub struct RequestLog {
+    settings: watch::Sender<Arc<LogSettings>>,
+}
+
+impl Default for RequestLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestLog {
+    pub fn new() -> Self {
+        Self { settings: watch::channel(Arc::new(LogSettings::default())).0 }
+    }
+
+    pub fn settings(&self) -> Arc<LogSettings> {
+        self.settings.borrow().clone()
+    }
+
+    pub async fn reload(&self, pool: &PgPool) -> anyhow::Result<()> {
+        let settings = RequestLogQueries::settings(pool).await?;
+        let verbose = RequestLogQueries::verbose(pool).await?;
+        self.apply(&settings, verbose);
+        Ok(())
+    }
+
+    fn apply(&self, settings: &RequestLogSettingsRow, verbose: Vec<VerboseLoggingRow>) {
+        self.settings.send_replace(Arc::new(LogSettings {
+            sample_rate: settings.sample_rate.clamp(0.0, 1.0),
+            max_body_bytes: usize::try_from(settings.max_body_bytes).unwrap_or(0),
+            verbose,
+        }));
+    }
+
+    /// Change the sample rate and body limit for every node
+    pub async fn set_sampling(
+        &self,
+        pool: &PgPool,
+        sample_rate: f64,
+        max_body_bytes: i32,
+        admin_id: i64,
+    ) -> anyhow::Result<RequestLogSettingsRow> {
+        let settings = RequestLogQueries::set_settings(pool, sample_rate, max_body_bytes, admin_id).await?;
+        self.reload(pool).await?;
+        Ok(settings)
+    }
+
+    /// Log everything `user_id` sends for the next `minutes`
+    pub async fn enable_verbose(
+        &self,
+        pool: &PgPool,
+        user_id: i64,
+        minutes: i64,
+        admin_id: i64,
+        reason: &str,
+    ) -> anyhow::Result<VerboseLoggingRow> {
+        let expires_at = Utc::now() + chrono::Duration::minutes(minutes);
+        let row = RequestLogQueries::set_verbose(pool, user_id, admin_id, reason, expires_at).await?;
+        self.reload(pool).await?;
+        Ok(row)
+    }
+
+    /// False if `user_id` was not in verbose mode
+    pub async fn disable_verbose(&self, pool: &PgPool, user_id: i64) -> anyhow::Result<bool> {
+        let was = RequestLogQueries::clear_verbose(pool, user_id).await?;
+        self.reload(pool).await?;
+        Ok(was)
+    }
+
+    pub fn spawn_reloader(self: Arc<Self>, pool: PgPool, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.reload(&pool).await {
+                    tracing::error!("Failed to reload request log settings: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redact_json_by_field_name() {
+        let mut body = json!({
+            "username": "alice",
+            "password": "hunter2",
+            "email": "alice@example.com",
+            "mfa_code": "123456",
+            "code": "654321",
+            "country_code": "PT",
+            "session": { "refresh_token": "abc", "note": "eyJhbGciOi.eyJzdWIiOiI3In0.sig" },
+            "contacts": [{ "backup_email": "bob@example.org" }],
+            "api_key": null
+        });
+        redact_json(&mut body);
+
+        assert_eq!(body["username"], "alice");
+        assert_eq!(body["password"], REDACTED);
+        assert_eq!(body["email"], "a***@example.com");
+        assert_eq!(body["mfa_code"], REDACTED);
+        assert_eq!(body["code"], REDACTED);
+        assert_eq!(body["country_code"], "PT");
+        assert_eq!(body["session"]["refresh_token"], REDACTED);
+        assert_eq!(body["session"]["note"], REDACTED);
+        assert_eq!(body["contacts"][0]["backup_email"], "b***@example.org");
+        assert!(body["api_key"].is_null());
+    }
+
+    #[test]
+    fn test_redact_query_path_and_body() {
+        assert_eq!(
+            redact_query("page=2&token=abc&email=carol%40example.com"),
+            format!("page=2&token={}&email=c***@example.com", REDACTED)
+        );
+        assert_eq!(redact_path("/api/users/by-email/dave%40example.com"), "/api/users/by-email/d***@example.com");
+        assert_eq!(redact_path("/api/servers/42"), "/api/servers/42");
+
+        let json = br#"{"password":"x","bio":"hello world"}"#;
+        let body = redact_body(Some("application/json; charset=utf-8"), json, 100);
+        assert_eq!(body, format!(r#"{{"bio":"hello world","password":"{}"}}"#, REDACTED));
+        assert_eq!(redact_body(Some("application/json"), json, 10), r#"{"bio":"he...[truncated]"#);
+        assert_eq!(
+            redact_body(Some("application/x-www-form-urlencoded"), b"user=erin&passwd=x", 100),
+            format!("user=erin&passwd={}", REDACTED)
+        );
+        assert_eq!(redact_body(Some("image/png"), &[0u8; 10], 100), "<10 bytes of image/png>");
+        assert_eq!(redact_body(None, b"", 100), "");
+    }
+
+    #[test]
+    fn test_detail() {
+        let now = Utc::now();
+        let settings = LogSettings {
+            sample_rate: 0.1,
+            max_body_bytes: 100,
+            verbose: vec![VerboseLoggingRow {
+                user_id: 7,
+                enabled_by: Some(1),
+                reason: "ticket".to_string(),
+                expires_at: now + chrono::Duration::minutes(5),
+                created_at: now,
+            }],
+        };
+        assert_eq!(settings.detail(Some(7), 0.99, now), Detail::Verbose);
+        assert_eq!(settings.detail(Some(8), 0.05, now), Detail::Summary);
+        assert_eq!(settings.detail(None, 0.5, now), Detail::Errors);
+        assert_eq!(settings.detail(Some(7), 0.99, now + chrono::Duration::minutes(6)), Detail::Errors);
+    }
+}
@@ -0,0 +1,163 @@
+//! Resource reservations
+//!
+//! Players hold money, gateway disk space and process slots before a
+//! multi-step action so a later step cannot fail for want of them. Holds are
+//! taken under a per-player lock, and the flows that spend these resources
+//! count other holds as spent:
+//!
+//! - marketplace purchases spend a reservation's money and disk space;
+//! - starting a process with a reservation uses one of its slots;
+//! - holds for `generated_mission:<id>` are committed when the mission pays
+//!   out and released when it is abandoned.
+//!
+//! A hold lapses on its own at its expiry; the sweeper only records it.
+//! Rules are in [`he_game_mechanics::reservations`].
+
+use chrono::{Duration as ChronoDuration, Utc};
+use he_database::queries::{ClanServerQueries, NewReservation, ProcessQueries, ReservationQueries, ReservationRow};
+use he_game_mechanics::reservations::{self, Available, ReservationDenied, ReservationRequest};
+use sqlx::PgPool;
+use std::time::Duration;
+
+type ReservationResult<T> = anyhow::Result<Result<T, ReservationDenied>>;
+
+/// Reservations listed to the player
+pub const LIST_LIMIT: i64 = 50;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The purpose tying a hold to a generated mission
+pub fn mission_purpose(mission_id: i64) -> String {
+    format!("generated_mission:{}", mission_id)
+}
+
+/// Hold `request` for `user_id`, who may run `max_processes` at once
+pub async fn reserve(
+    pool: &PgPool,
+    user_id: i64,
+    request: ReservationRequest,
+    ttl_secs: Option<i64>,
+    purpose: Option<&str>,
+    max_processes: i64,
+) -> ReservationResult<ReservationRow> {
+    let config = &crate::balance::current().reservations;
+    let purpose = purpose.map(str::trim).filter(|purpose| !purpose.is_empty());
+    let ttl = match reservations::check_request(&request, ttl_secs, purpose, config) {
+        Ok(ttl) => ttl,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    ReservationQueries::lock_user(&mut tx, user_id).await?;
+    if ReservationQueries::open_count(&mut tx, user_id).await? >= config.max_open {
+        return Ok(Err(ReservationDenied::TooManyOpen { max: config.max_open }));
+    }
+
+    let mut available = Available::default();
+    let mut bank_account_id = None;
+    if request.money > 0 {
+        let Some((id, spendable)) = ReservationQueries::spendable_account(&mut tx, user_id, None).await? else {
+            return Ok(Err(ReservationDenied::NoBankAccount));
+        };
+        bank_account_id = Some(id);
+        available.money = spendable;
+    }
+    let mut server_id = None;
+    if request.hdd_mb > 0 {
+        let Some(id) = ClanServerQueries::gateway(&mut tx, user_id).await? else {
+            return Ok(Err(ReservationDenied::NoServer));
+        };
+        server_id = Some(id);
+        available.hdd_mb = ReservationQueries::free_space(&mut tx, id, None).await?;
+    }
+    if request.process_slots > 0 {
+        // Same lock as the process quota, so a slot and a process cannot
+        // both take the last place
+        ProcessQueries::lock_quota(&mut tx, user_id).await?;
+        let running = ProcessQueries::count_active(&mut tx, user_id).await?;
+        let held = ReservationQueries::held_slots(&mut tx, user_id, None).await?;
+        available.process_slots = max_processes - running - held;
+    }
+    if let Err(denied) = reservations::check_available(&request, &available) {
+        return Ok(Err(denied));
+    }
+
+    let row = ReservationQueries::create(
+        &mut tx,
+        &NewReservation {
+            user_id,
+            bank_account_id,
+            server_id,
+            money: request.money,
+            hdd_mb: request.hdd_mb,
+            process_slots: request.process_slots,
+            purpose,
+            expires_at: Utc::now() + ChronoDuration::seconds(ttl),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Ok(row))
+}
+
+/// Close one of the player's live reservations as 'committed' or 'released'
+async fn settle(pool: &PgPool, user_id: i64, id: i64, status: &str) -> ReservationResult<ReservationRow> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let Some(reservation) = ReservationQueries::lock(&mut tx, id, user_id).await? else {
+        return Ok(Err(ReservationDenied::NotFound));
+    };
+    if reservation.status != "held" {
+        return Ok(Err(ReservationDenied::NotHeld { status: reservation.status }));
+    }
+    if reservation.expires_at <= Utc::now() {
+        return Ok(Err(ReservationDenied::Expired));
+    }
+
+    let row = ReservationQueries::settle(&mut tx, id, status).await?;
+    tx.commit().await?;
+    Ok(Ok(row))
+}
+
+/// The action the holds were for went through outside the reservation-aware
+/// flows; whatever is left on it is freed as used
+pub async fn commit(pool: &PgPool, user_id: i64, id: i64) -> ReservationResult<ReservationRow> {
+    settle(pool, user_id, id, "committed").await
+}
+
+/// Give the holds back
+pub async fn release(pool: &PgPool, user_id: i64, id: i64) -> ReservationResult<ReservationRow> {
+    settle(pool, user_id, id, "released").await
+}
+
+/// Turn one slot held by a live reservation into a free one for the
+/// process about to start; false if it holds none
+pub async fn use_slot(pool: &PgPool, user_id: i64, id: i64) -> anyhow::Result<bool> {
+    let mut conn = pool.acquire().await?;
+    ReservationQueries::take_slot(&mut conn, id, user_id).await
+}
+
+/// Record lapsed holds as expired every [`SWEEP_INTERVAL`]
+pub(crate) fn spawn_sweeper(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match ReservationQueries::expire(&pool).await {
+                Ok(0) => {}
+                Ok(expired) => tracing::debug!("{} resource reservations expired", expired),
+                Err(e) => tracing::warn!("Reservation sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mission_purpose_fits() {
+        assert_eq!(mission_purpose(42), "generated_mission:42");
+        assert!(mission_purpose(i64::MAX).len() <= he_game_mechanics::config::ReservationConfig::default().max_purpose_len);
+    }
+}
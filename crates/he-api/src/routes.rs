@@ -1,8 +1,13 @@
 //! API Routes
 
 use actix_web::web;
-use crate::handlers::{auth, game, process, hardware, bank, missions};
+use he_cache::CacheKeys;
+use crate::http_cache::{CachePolicy, ConditionalGet};
+use crate::panic_levers::EconomyWrite;
+use crate::handlers::{account, account_gating, activity, admin_dashboard, archive, auth, bounty, bulk_ops, cache_stats, clans, contracts, cron, dns, entitlements, defense, doom, formula_scripts, game, gateway, heists, honeypots, ip_policy, ledger, live_ops, panic_levers, process, query_audit, request_log, hardware, bank, marketplace, missions, notifications, plugins, puzzles, referrals, reports, reservations, server, server_browser, software, status, terminal, tutorial, webhooks};
 
+/// Every route but the OpenID Connect provider's, which
+/// [`crate::handlers::oidc::configure`] adds when the provider is configured
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg
         // Authentication routes
@@ -11,28 +16,337 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         .route("/api/auth/logout", web::post().to(auth::logout))
         .route("/api/auth/refresh", web::post().to(auth::refresh_token))
 
+        // Account settings
+        .route("/api/account/email", web::get().to(account::get_email_change))
+        .route("/api/account/email", web::post().to(account::request_email_change))
+        .route("/api/account/email/confirm", web::post().to(account::confirm_email_change))
+        .route("/api/account/email/verify", web::post().to(account::verify_new_email))
+        .route("/api/account/email/revoke", web::post().to(account::revoke_email_change))
+        .route("/api/account/restrictions", web::get().to(account_gating::my_restrictions))
+        .route("/api/account/activity", web::get().to(activity::timeline))
+        .route("/api/account/logins", web::get().to(account::get_logins))
+        .route("/api/account/logins/{id}/disown", web::post().to(account::disown_login))
+        .route("/api/account/password/reset", web::post().to(account::reset_password))
+        .route("/api/account/username", web::post().to(account::change_username))
+
         // Game routes
         .route("/api/game/status", web::get().to(game::get_status))
         .route("/api/game/dashboard", web::get().to(game::get_dashboard))
+        .route("/api/rules", web::get().to(game::get_rules))
+
+        // Software catalog
+        .route("/api/software/catalog", web::get().to(software::get_catalog))
+        .route("/api/software/{id}/protect", web::post().to(software::protect_software))
+        .route("/api/software/{id}/keygen", web::post().to(software::start_keygen))
+
+        // Player server customization
+        .route("/api/server/customization", web::get().to(server::get_customization))
+        .route("/api/server/customization", web::put().to(server::update_customization))
+        .service(
+            web::resource("/api/internet/{ip}/page")
+                .wrap(ConditionalGet::new(
+                    CachePolicy::private().versioned_by(|req| req.match_info().get("ip").map(CacheKeys::server_info)),
+                ))
+                .route(web::get().to(server::view_page))
+        )
+
+        // Server browser
+        .route("/api/internet/servers", web::get().to(server_browser::browse))
+        .route("/api/internet/scan-range", web::post().to(server_browser::scan_range))
+        .route("/api/internet/known", web::get().to(server_browser::known_servers))
+
+        // Whois registry
+        .route("/api/dns/resolve", web::get().to(dns::resolve))
+        .route("/api/dns/reverse", web::get().to(dns::reverse))
+        .route("/api/dns/whois/{hostname}", web::get().to(dns::whois))
+        .route("/api/dns/hostnames", web::get().to(dns::list_hostnames))
+        .route("/api/dns/hostnames", web::post().to(dns::register).wrap(EconomyWrite))
+        .route("/api/dns/hostnames/{hostname}", web::delete().to(dns::release))
+
+        // Notification center
+        .route("/api/notifications", web::get().to(notifications::list_notifications))
+        .route("/api/notifications/unread", web::get().to(notifications::unread_counts))
+        .route("/api/notifications/read", web::post().to(notifications::mark_read))
+        .route("/api/notifications/delete", web::post().to(notifications::delete_notifications))
+        .route("/api/notifications/preferences", web::get().to(notifications::get_preferences))
+        .route("/api/notifications/preferences/{class}", web::put().to(notifications::set_class_muted))
+        .route("/api/notifications/{id}/occurrences", web::get().to(notifications::occurrences))
 
         // Process management
         .route("/api/processes", web::get().to(process::list_processes))
-        .route("/api/processes", web::post().to(process::create_process))
+        .route("/api/processes", web::post().to(process::create_process).wrap(EconomyWrite))
+        .route("/api/archive/processes", web::get().to(archive::processes))
+        .route("/api/archive/logs", web::get().to(archive::logs))
         .route("/api/processes/{pid}/cancel", web::delete().to(process::cancel_process))
+        .route("/api/processes/{pid}/resume", web::post().to(process::resume_process))
+
+        // Admin: process kill-switch
+        .route("/api/admin/process-kill-switch", web::get().to(process::list_kill_switches))
+        .route("/api/admin/process-kill-switch", web::post().to(process::engage_kill_switch))
+        .route("/api/admin/process-kill-switch/{process_type}", web::delete().to(process::release_kill_switch))
+
+        // Defense
+        .route("/api/defense/trace", web::post().to(defense::start_trace))
+        .route("/api/defense/trace/{pid}", web::get().to(defense::get_trace))
+        .route("/api/defense/evasion", web::get().to(defense::traces_on_me))
+        .route("/api/defense/evasion/wipe", web::post().to(defense::wipe_trail))
+        .route("/api/defense/flagged", web::get().to(defense::flagged))
+
+        // Admin: IP policy
+        .route("/api/admin/ip-policy/rules", web::get().to(ip_policy::list_rules))
+        .route("/api/admin/ip-policy/rules", web::post().to(ip_policy::add_rule))
+        .route("/api/admin/ip-policy/rules/{id}", web::delete().to(ip_policy::delete_rule))
+        .route("/api/admin/ip-policy/reload", web::post().to(ip_policy::reload_rules))
+        .route("/api/admin/ip-policy/check/{ip}", web::get().to(ip_policy::check_ip))
+
+        // Admin: cold storage
+        .route("/api/admin/archive", web::get().to(archive::status))
+        .route("/api/admin/archive/retention/{table}", web::put().to(archive::set_retention))
+
+        // Admin: scheduled jobs (he-cron)
+        .route("/api/admin/cron/jobs", web::get().to(cron::list_jobs))
+        .route("/api/admin/cron/jobs/{name}", web::get().to(cron::get_job))
+        .route("/api/admin/cron/jobs/{name}/trigger", web::post().to(cron::trigger_job))
+        .route("/api/admin/cron/jobs/{name}/pause", web::post().to(cron::pause_job))
+        .route("/api/admin/cron/jobs/{name}/resume", web::post().to(cron::resume_job))
+        .route("/api/admin/cron/alerts/{id}/ack", web::post().to(cron::acknowledge_alert))
+
+        // Admin: contract disputes
+        .route("/api/admin/contracts/disputes", web::get().to(contracts::list_disputes))
+        .route("/api/admin/contracts/{id}/resolve", web::post().to(contracts::resolve_dispute))
+
+        // Admin: referral review
+        .route("/api/admin/referrals/flagged", web::get().to(referrals::flagged))
+        .route("/api/admin/referrals/{id}/review", web::post().to(referrals::review))
+
+        // Admin: ledger forensics
+        .route("/api/admin/ledger/entries", web::get().to(ledger::entries))
+        .route("/api/admin/ledger/transactions/{id}", web::get().to(ledger::transaction))
+        .route("/api/admin/ledger/accounts/{account}/balance", web::get().to(ledger::account_balance))
+        .route("/api/admin/ledger/discrepancies", web::get().to(ledger::discrepancies))
+        .route("/api/admin/ledger/discrepancies/{id}/resolve", web::post().to(ledger::resolve))
+
+        // Admin: honeypot trips
+        .route("/api/admin/honeypots/trips", web::get().to(honeypots::trips))
+        .route("/api/admin/honeypots/users/{id}", web::get().to(honeypots::user_patterns))
+
+        // Admin: SQL audit
+        .route("/api/admin/database/queries", web::get().to(query_audit::top_queries))
+        .route("/api/admin/database/queries/reset", web::post().to(query_audit::reset))
+
+        // Admin: cache keyspaces
+        .route("/api/admin/cache/keyspaces", web::get().to(cache_stats::worst_keyspaces))
+        .route("/api/admin/cache/keyspaces/reset", web::post().to(cache_stats::reset))
+        .route("/api/admin/cache/relay", web::get().to(cache_stats::relay))
+        .route("/api/admin/cache/relay/reset", web::post().to(cache_stats::reset_relay))
+
+        // Admin: mission generation log
+        .route("/api/admin/missions/generation-log", web::get().to(missions::generation_log))
+        .route("/api/admin/missions/generation-log/{id}", web::get().to(missions::generation))
+
+        // Admin: username history
+        .route("/api/admin/usernames", web::get().to(account::former_holders))
+        .route("/api/admin/users/{id}/usernames", web::get().to(account::username_history))
+
+        // Admin: report queue and moderation
+        .route("/api/admin/reports/queue", web::get().to(reports::queue))
+        .route("/api/admin/reports/{id}", web::get().to(reports::get_case))
+        .route("/api/admin/reports/{id}/resolve", web::post().to(reports::resolve))
+        .route("/api/admin/moderation/users/{id}/actions", web::get().to(reports::moderation_history))
+
+        // Admin: premium subscriptions
+        .route("/api/admin/users/{id}/subscriptions", web::get().to(entitlements::user_subscriptions))
+
+        // Admin: new-account restriction overrides
+        .route("/api/admin/users/{id}/gating-override", web::get().to(account_gating::get_override))
+        .route("/api/admin/users/{id}/gating-override", web::put().to(account_gating::set_override))
+        .route("/api/admin/users/{id}/gating-override", web::delete().to(account_gating::clear_override))
+
+        // Admin: WASM plugins
+        .route("/api/admin/plugins", web::get().to(plugins::list))
+        .route("/api/admin/plugins/{name}/load", web::post().to(plugins::load))
+        .route("/api/admin/plugins/{name}", web::delete().to(plugins::unload))
+
+        // Admin dashboard (server-rendered)
+        .route("/admin", web::get().to(admin_dashboard::overview))
+        .route("/admin/login", web::get().to(admin_dashboard::sign_in_page))
+        .route("/admin/login", web::post().to(admin_dashboard::sign_in))
+        .route("/admin/analytics", web::get().to(admin_dashboard::analytics))
+        .route("/admin/economy", web::get().to(admin_dashboard::economy))
+        .route("/admin/economy/live", web::get().to(admin_dashboard::economy_live))
+        .route("/admin/economy/anomalies/{id}/acknowledge", web::post().to(admin_dashboard::acknowledge_anomaly))
+        .route("/admin/flags", web::post().to(admin_dashboard::toggle_flag))
+        .route("/admin/logout", web::post().to(admin_dashboard::sign_out))
+
+        // Admin: live-ops console
+        .route("/api/admin/live-ops/ws", web::get().to(live_ops::console))
+
+        // Admin: incident-response panic levers
+        .route("/api/admin/panic", web::get().to(panic_levers::list))
+        .route("/api/admin/panic/{lever}", web::post().to(panic_levers::set))
+        // Admin: bulk data fixes
+        .route("/api/admin/bulk-operations", web::get().to(bulk_ops::list))
+        .route("/api/admin/bulk-operations", web::post().to(bulk_ops::plan))
+        .route("/api/admin/bulk-operations/{id}", web::get().to(bulk_ops::get))
+        .route("/api/admin/bulk-operations/{id}/rows", web::get().to(bulk_ops::rows))
+        .route("/api/admin/bulk-operations/{id}/execute", web::post().to(bulk_ops::execute))
+        .route("/api/admin/bulk-operations/{id}/cancel", web::post().to(bulk_ops::cancel))
+        .route("/api/admin/bulk-operations/{id}/rollback", web::post().to(bulk_ops::rollback))
+        // Admin: formula override scripts
+        .route("/api/admin/formula-scripts", web::get().to(formula_scripts::status))
+        .route("/api/admin/formula-scripts/reload", web::post().to(formula_scripts::reload))
+        .route("/api/admin/formula-scripts/{formula}", web::put().to(formula_scripts::save))
+        .route("/api/admin/formula-scripts/{formula}", web::delete().to(formula_scripts::remove))
+
+        // Admin: request logging sampling and per-user verbose mode
+        .route("/api/admin/request-log", web::get().to(request_log::settings))
+        .route("/api/admin/request-log/sampling", web::put().to(request_log::set_sampling))
+        .route("/api/admin/request-log/verbose/{user_id}", web::put().to(request_log::enable_verbose))
+        .route("/api/admin/request-log/verbose/{user_id}", web::delete().to(request_log::disable_verbose))
+
+        // Admin: status page incidents
+        .route("/api/admin/status/incidents", web::get().to(status::list_incidents))
+        .route("/api/admin/status/incidents", web::post().to(status::create_incident))
+        .route("/api/admin/status/incidents/{id}/updates", web::post().to(status::post_update))
+        .route("/api/admin/status/incidents/{id}/resolve", web::post().to(status::resolve_incident))
 
         // Hardware management
         .route("/api/hardware", web::get().to(hardware::get_hardware))
-        .route("/api/hardware/upgrade", web::post().to(hardware::upgrade_hardware))
+        .route("/api/hardware/upgrade", web::post().to(hardware::upgrade_hardware).wrap(EconomyWrite))
+        .route("/api/hardware/condition", web::get().to(hardware::get_condition))
+        .route("/api/hardware/repair", web::post().to(hardware::repair_hardware).wrap(EconomyWrite))
 
         // Banking
+        .route("/api/banks", web::get().to(bank::list_banks))
         .route("/api/bank/accounts", web::get().to(bank::get_accounts))
-        .route("/api/bank/transfer", web::post().to(bank::transfer_money))
+        .route("/api/bank/accounts", web::post().to(bank::open_account).wrap(EconomyWrite))
+        .route("/api/bank/accounts/{number}/transfers", web::get().to(bank::account_transfers))
+        .route("/api/bank/transfer", web::post().to(bank::transfer_money).wrap(EconomyWrite))
+        .route("/api/bank/launder", web::post().to(bank::launder).wrap(EconomyWrite))
+        .route("/api/bank/thefts", web::get().to(bank::thefts))
+        .route("/api/bank/thefts/{id}", web::get().to(bank::trace_theft))
+
+        // Clan membership
+        .route("/api/clan/leave", web::post().to(clans::leave))
+        .route("/api/clan/members/{id}", web::delete().to(clans::kick))
+
+        // Clan servers
+        .route("/api/clan/server", web::get().to(clans::server))
+        .route("/api/clan/treasury", web::post().to(clans::deposit).wrap(EconomyWrite))
+        .route("/api/clan/server/upgrades/{part}", web::post().to(clans::upgrade).wrap(EconomyWrite))
+        .route("/api/clan/server/files", web::get().to(clans::files))
+        .route("/api/clan/server/files", web::post().to(clans::upload))
+        .route("/api/clan/server/files/{id}/download", web::post().to(clans::download))
+        .route("/api/clan/server/files/{id}", web::delete().to(clans::remove_file))
+        .route("/api/clan/server/defense", web::post().to(clans::lend_firewall))
+        .route("/api/clan/wars", web::get().to(clans::wars))
+        .route("/api/clan/wars", web::post().to(clans::declare_war))
+        .route("/api/clan/wars/{id}/attack", web::post().to(clans::attack))
+        .route("/api/clan/chat/{channel}", web::get().to(clans::chat))
+        .route("/api/clan/chat/{channel}", web::post().to(clans::post_chat))
+        .route("/api/clan/chat/{channel}/pins", web::get().to(clans::chat_pins))
+        .route("/api/clan/chat/{channel}/pins/{message_id}", web::put().to(clans::pin_chat))
+        .route("/api/clan/chat/{channel}/pins/{message_id}", web::delete().to(clans::unpin_chat))
+
+        // Clan and player webhooks
+        .route("/api/webhooks", web::get().to(webhooks::list))
+        .route("/api/webhooks", web::post().to(webhooks::register))
+        .route("/api/webhooks/{id}", web::delete().to(webhooks::remove))
+        .route("/api/webhooks/{id}/enabled", web::put().to(webhooks::set_enabled))
+        .route("/api/webhooks/{id}/deliveries", web::get().to(webhooks::deliveries))
+
+        // Software marketplace
+        .route("/api/marketplace", web::get().to(marketplace::search_listings))
+        .route("/api/marketplace", web::post().to(marketplace::create_listing).wrap(EconomyWrite))
+        .route("/api/marketplace/{id}", web::get().to(marketplace::get_listing))
+        .route("/api/marketplace/{id}", web::delete().to(marketplace::cancel_listing))
+        .route("/api/marketplace/{id}/purchase", web::post().to(marketplace::purchase_listing).wrap(EconomyWrite))
+
+        // Resource reservations
+        .route("/api/reservations", web::get().to(reservations::list))
+        .route("/api/reservations", web::post().to(reservations::reserve).wrap(EconomyWrite))
+        .route("/api/reservations/{id}/commit", web::post().to(reservations::commit).wrap(EconomyWrite))
+        .route("/api/reservations/{id}/release", web::post().to(reservations::release).wrap(EconomyWrite))
+
+        // Bounties
+        .route("/api/bounties", web::get().to(bounty::get_board))
+        .route("/api/bounties", web::post().to(bounty::place_bounty).wrap(EconomyWrite))
+        .route("/api/bounties/mine", web::get().to(bounty::get_my_bounties))
+
+        // Regional WebSocket gateways
+        .route("/api/gateways", web::get().to(gateway::discover))
+        .route("/api/gateways/ping", web::get().to(gateway::ping))
+
+        // Player contracts
+        .route("/api/contracts", web::get().to(contracts::get_board))
+        .route("/api/contracts", web::post().to(contracts::post_contract).wrap(EconomyWrite))
+        .route("/api/contracts/mine", web::get().to(contracts::get_my_contracts))
+        .route("/api/contracts/{id}/accept", web::post().to(contracts::accept_contract).wrap(EconomyWrite))
+        .route("/api/contracts/{id}", web::delete().to(contracts::cancel_contract))
+        .route("/api/contracts/{id}/dispute", web::post().to(contracts::dispute_contract))
+
+        // Referrals
+        .route("/api/referrals", web::get().to(referrals::my_referrals))
+        .route("/api/reports", web::post().to(reports::submit))
+        .route("/api/premium", web::get().to(entitlements::my_premium))
+        .route("/api/webhooks/payments", web::post().to(entitlements::payment_webhook))
+
+        // Terminal: plugin commands, aliases, chains and completion
+        .route("/api/terminal/commands", web::get().to(plugins::terminal_commands))
+        .route("/api/terminal/run", web::post().to(terminal::run))
+        .route("/api/terminal/aliases", web::get().to(terminal::aliases))
+        .route("/api/terminal/aliases/{name}", web::put().to(terminal::save_alias))
+        .route("/api/terminal/aliases/{name}", web::delete().to(terminal::delete_alias))
+        .route("/api/terminal/complete/commands", web::get().to(terminal::complete_commands))
+        .route("/api/terminal/complete/ips", web::get().to(terminal::complete_ips))
+        .route("/api/terminal/complete/files", web::get().to(terminal::complete_files))
+
+        // Doom virus endgame
+        .route("/api/doom", web::get().to(doom::status))
+        .route("/api/doom/research", web::post().to(doom::research))
+        .route("/api/doom/install", web::post().to(doom::install))
+        .route("/api/doom/logs/{ip}", web::get().to(doom::read_logs))
+        .route("/api/doom/disarm", web::post().to(doom::disarm))
+
+        // Storyline puzzles
+        .route("/api/puzzles", web::get().to(puzzles::list))
+        .route("/api/puzzles/files/{ip}", web::get().to(puzzles::hidden_files))
+        .route("/api/puzzles/{id}", web::get().to(puzzles::get))
+        .route("/api/puzzles/{id}/answer", web::post().to(puzzles::answer))
+
+        // Tutorial
+        .route("/api/tutorial", web::get().to(tutorial::get_tutorial))
+        .route("/api/tutorial/skip", web::post().to(tutorial::skip_tutorial))
+        .route("/api/tutorial/reset", web::post().to(tutorial::reset_tutorial))
 
         // Missions
         .route("/api/missions", web::get().to(missions::get_missions))
         .route("/api/missions/{id}/accept", web::post().to(missions::accept_mission))
-        .route("/api/missions/{id}/progress", web::post().to(missions::update_progress))
+        .route("/api/missions/{id}/progress", web::post().to(missions::update_progress).wrap(EconomyWrite))
+        .route("/api/missions/{id}/coop", web::post().to(missions::accept_coop))
+        .route("/api/missions/generated", web::get().to(missions::generated_missions))
+        .route("/api/missions/generated/{id}/accept", web::post().to(missions::accept_generated))
+        .route(
+            "/api/missions/generated/{id}/objectives/{node}",
+            web::post().to(missions::complete_generated_objective).wrap(EconomyWrite),
+        )
+        .route("/api/missions/generated/{id}/abandon", web::post().to(missions::abandon_generated))
+        .route("/api/coop-missions/{id}", web::get().to(missions::get_coop))
+        .route("/api/coop-missions/{id}/join", web::post().to(missions::join_coop).wrap(EconomyWrite))
+        .route("/api/coop-missions/{id}/start", web::post().to(missions::start_coop).wrap(EconomyWrite))
+        .route("/api/coop-missions/{id}/abandon", web::post().to(missions::abandon_coop))
+        .route("/api/coop-missions/{id}/chat", web::get().to(missions::coop_chat))
+        .route("/api/coop-missions/{id}/chat", web::post().to(missions::post_coop_chat))
+        // Bank heists
+        .route("/api/heists", web::get().to(heists::open_heists))
+        .route("/api/heists/{id}/crews", web::post().to(heists::form_crew).wrap(EconomyWrite))
+        .route("/api/heist-raids/{id}", web::get().to(heists::get_raid))
+        .route("/api/heist-raids/{id}/join", web::post().to(heists::join_raid).wrap(EconomyWrite))
+        .route("/api/heist-raids/{id}/leave", web::post().to(heists::leave_raid).wrap(EconomyWrite))
+        .route("/api/heist-raids/{id}/start", web::post().to(heists::start_raid).wrap(EconomyWrite))
+        .route("/api/heist-raids/{id}/act", web::post().to(heists::act).wrap(EconomyWrite))
 
         // WebSocket endpoint
+        .route("/api/ws-protocol", web::get().to(crate::websocket::protocol_document))
         .route("/ws", web::get().to(crate::websocket::websocket_handler));
 }
\ No newline at end of file
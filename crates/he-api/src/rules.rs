@@ -0,0 +1,66 @@
+//! Server game rules
+//!
+//! The rules are read from the `[rules]` table of the balance file once, at
+//! startup; a server whose rules contradict each other refuses to start
+//! rather than run with half of them. Rewards and process durations are
+//! scaled everywhere they are computed, and anything one player does to
+//! another checks [`check_target`] first. The rules themselves are in
+//! [`he_game_mechanics::rules`].
+
+use he_database::queries::{GatewayQueries, ServerQueries};
+use he_game_mechanics::rules::{GameRules, PvpDenied, RulesError};
+use once_cell::sync::OnceCell;
+use sqlx::PgPool;
+
+const DEFAULT_BALANCE_PATH: &str = "game-balance.toml";
+
+type PvpResult<T> = anyhow::Result<Result<T, PvpDenied>>;
+
+static RULES: OnceCell<GameRules> = OnceCell::new();
+
+/// Read the rules from the balance file. A missing file runs the defaults;
+/// rules that fail validation are an error.
+pub fn load() -> Result<&'static GameRules, RulesError> {
+    RULES.get_or_try_init(|| {
+        let path = std::env::var("GAME_BALANCE_PATH").unwrap_or_else(|_| DEFAULT_BALANCE_PATH.to_string());
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => GameRules::from_balance(&contents),
+            Err(e) => {
+                tracing::warn!("Could not read balance file {}, using default game rules: {}", path, e);
+                Ok(GameRules::default())
+            }
+        }
+    })
+}
+
+/// The rules the server runs with
+pub fn current() -> &'static GameRules {
+    load().expect("Invalid game rules")
+}
+
+/// Whether `user_id` may target the player who owns `target_ip`; servers
+/// nobody owns are always fair game
+pub async fn check_target(pool: &PgPool, user_id: i64, target_ip: &str) -> PvpResult<()> {
+    let Some(owner) = ServerQueries::get_player_owner_by_ip(pool, target_ip).await? else {
+        return Ok(Ok(()));
+    };
+    if owner == user_id {
+        return Ok(Ok(()));
+    }
+
+    check_player(pool, owner).await
+}
+
+/// Whether another player may target `target_id`
+pub async fn check_player(pool: &PgPool, target_id: i64) -> PvpResult<()> {
+    let rules = current();
+    if !rules.pvp_enabled {
+        return Ok(Err(PvpDenied::Disabled));
+    }
+    if rules.offline_protection_hours == 0 {
+        return Ok(Ok(()));
+    }
+    let presence = GatewayQueries::presence(pool, target_id).await?;
+
+    Ok(rules.check_target(presence.online, presence.last_seen_at, chrono::Utc::now()))
+}
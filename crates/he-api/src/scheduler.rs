@@ -0,0 +1,455 @@
+//! Process completion shared between nodes
+//!
+//! Processes are split into [`SHARDS`] shards by pid. With `REDIS_URL` set,
+//! every node leases a share of the shards in Redis and the completion
+//! worker only completes processes of shards this node holds. Without it the
+//! node completes everything, as a single node always has.
+//!
+//! Nodes announce themselves every [`TICK`]. Each tick a node renews its
+//! leases, takes free shards up to its fair share and gives up any above it,
+//! so shards move to a node that joins and, once its leases lapse, away from
+//! one that leaves. A node with no backlog of its own steals the shard whose
+//! owner has completions overdue by more than [`STEAL_AFTER_SECS`].
+//!
+//! Every lease carries a fencing token, raised on each change of owner. A
+//! completion records its token on the shard's `completion_shards` row in the
+//! transaction that claims the process, and an older token fails the claim,
+//! so a node that lost a shard and has not noticed yet completes nothing in
+//! it.
+
+use he_monitoring::SchedulerMetrics;
+use once_cell::sync::OnceCell;
+use redis::aio::ConnectionManager;
+use redis::Script;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Number of completion shards. Changing it moves processes between shards,
+/// so every node must be restarted with the same value.
+pub const SHARDS: i32 = 32;
+/// How often leases are renewed and shards rebalanced
+pub const TICK: Duration = Duration::from_secs(5);
+/// Leases lapse this long after their last renewal
+const LEASE_TTL: Duration = Duration::from_secs(15);
+/// Nodes not heard from for this long are left out of fair shares
+const NODE_TTL: Duration = Duration::from_secs(20);
+/// A shard this far behind may be stolen by a node that is keeping up
+pub const STEAL_AFTER_SECS: i64 = 30;
+
+const NODES_KEY: &str = "sched:nodes";
+const LAG_KEY: &str = "sched:lag";
+
+/// Extend a lease only if this node still holds it
+const RENEW: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+end
+return 0
+"#;
+/// Drop a lease only if this node still holds it
+const RELEASE: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
+/// Take a lease over only if its holder is still the one observed
+const STEAL: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[3])
+    return 1
+end
+return 0
+"#;
+
+static CLUSTER: OnceCell<Arc<Cluster>> = OnceCell::new();
+
+/// Shard of a process
+pub fn shard_of(pid: i64) -> i32 {
+    pid.rem_euclid(SHARDS as i64) as i32
+}
+
+/// Most shards one of `nodes` live nodes should hold
+pub fn fair_share(nodes: usize) -> usize {
+    (SHARDS as usize).div_ceil(nodes.max(1))
+}
+
+/// A shard as seen in Redis
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardView {
+    pub shard: i32,
+    /// Node holding the lease, `None` when free
+    pub owner: Option<String>,
+    /// Seconds its oldest overdue process has waited, as its owner reported
+    pub lag_secs: i64,
+}
+
+/// Lease changes for one tick
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Plan {
+    pub release: Vec<i32>,
+    pub acquire: Vec<i32>,
+    pub steal: Option<i32>,
+}
+
+/// What `node` should change given the shards and the number of live nodes.
+/// Above its fair share it gives up the shards least behind, so a shard it
+/// stole keeps draining; below it takes free shards, and when none are free
+/// and it has no backlog, steals the shard furthest behind.
+pub fn plan(node: &str, shards: &[ShardView], live_nodes: usize) -> Plan {
+    let share = fair_share(live_nodes);
+    let mut owned: Vec<&ShardView> = shards.iter().filter(|s| s.owner.as_deref() == Some(node)).collect();
+    let own_lag = owned.iter().map(|s| s.lag_secs).max().unwrap_or(0);
+
+    if owned.len() > share {
+        owned.sort_by_key(|s| (s.lag_secs, s.shard));
+        let release = owned[..owned.len() - share].iter().map(|s| s.shard).collect();
+        return Plan { release, ..Plan::default() };
+    }
+
+    let acquire: Vec<i32> =
+        shards.iter().filter(|s| s.owner.is_none()).map(|s| s.shard).take(share - owned.len()).collect();
+    let steal = if acquire.is_empty() && own_lag < STEAL_AFTER_SECS {
+        shards
+            .iter()
+            .filter(|s| s.owner.is_some() && s.owner.as_deref() != Some(node) && s.lag_secs > STEAL_AFTER_SECS)
+            .max_by_key(|s| (s.lag_secs, -s.shard))
+            .map(|s| s.shard)
+    } else {
+        None
+    };
+    Plan { release: Vec::new(), acquire, steal }
+}
+
+/// Lease value: the holder and its fencing token
+fn lease_value(node: &str, token: i64) -> String {
+    format!("{}|{}", node, token)
+}
+
+fn lease_owner(value: &str) -> Option<&str> {
+    value.rsplit_once('|').map(|(node, _)| node)
+}
+
+fn lease_key(shard: i32) -> String {
+    format!("sched:lease:{}", shard)
+}
+
+fn fence_key(shard: i32) -> String {
+    format!("sched:fence:{}", shard)
+}
+
+/// A held lease, as a completion records it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fence {
+    pub shard: i32,
+    pub token: i64,
+}
+
+/// Where a due process is completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// No cluster: complete it here, unfenced
+    Local,
+    /// Complete it here under this lease
+    Fenced(Fence),
+    /// Another node holds its shard
+    Elsewhere,
+}
+
+struct Lease {
+    token: i64,
+    renewed_at: Instant,
+}
+
+struct Cluster {
+    node_id: String,
+    redis: ConnectionManager,
+    leases: Mutex<HashMap<i32, Lease>>,
+    /// Backlog of each held shard, in seconds, from the completion heartbeat
+    lag: Mutex<HashMap<i32, i64>>,
+}
+
+/// Join the completion cluster through `REDIS_URL`, if set. A node that
+/// cannot reach Redis completes everything on its own; the claim on the
+/// process row still keeps it from completing one twice.
+pub async fn start(node_id: &str) {
+    let Ok(url) = std::env::var("REDIS_URL") else {
+        tracing::info!("Completion scheduler running single-node");
+        return;
+    };
+    let redis = match redis::Client::open(url.as_str()) {
+        Ok(client) => client.get_connection_manager().await,
+        Err(e) => Err(e),
+    };
+    let redis = match redis {
+        Ok(redis) => redis,
+        Err(e) => {
+            tracing::error!("Cannot reach Redis, completion scheduler running single-node: {}", e);
+            return;
+        }
+    };
+
+    let cluster = Arc::new(Cluster {
+        node_id: node_id.to_string(),
+        redis,
+        leases: Mutex::new(HashMap::new()),
+        lag: Mutex::new(HashMap::new()),
+    });
+    if CLUSTER.set(cluster.clone()).is_err() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(TICK);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = cluster.tick().await {
+                tracing::warn!("Completion scheduler tick failed: {}", e);
+            }
+            cluster.expire_unrenewed();
+        }
+    });
+}
+
+/// Where a due process is completed
+pub fn route(pid: i64) -> Route {
+    let Some(cluster) = CLUSTER.get() else {
+        return Route::Local;
+    };
+    let shard = shard_of(pid);
+    match cluster.leases.lock().unwrap().get(&shard) {
+        Some(lease) => Route::Fenced(Fence { shard, token: lease.token }),
+        None => Route::Elsewhere,
+    }
+}
+
+/// Shards held by this node, `None` without a cluster, i.e. all of them
+pub fn owned_shards() -> Option<Vec<i32>> {
+    let cluster = CLUSTER.get()?;
+    let mut shards: Vec<i32> = cluster.leases.lock().unwrap().keys().copied().collect();
+    shards.sort_unstable();
+    Some(shards)
+}
+
+/// Record the backlog of the held shards: their oldest overdue process's
+/// wait in seconds, shards without one left out
+pub fn report_lag(lag: HashMap<i32, i64>) {
+    if let Some(cluster) = CLUSTER.get() {
+        *cluster.lag.lock().unwrap() = lag;
+    }
+}
+
+/// Count a completion refused for a superseded lease, and forget the lease
+pub fn fenced_out(fence: Fence) {
+    let Some(cluster) = CLUSTER.get() else {
+        return;
+    };
+    let mut leases = cluster.leases.lock().unwrap();
+    if leases.get(&fence.shard).is_some_and(|lease| lease.token == fence.token) {
+        leases.remove(&fence.shard);
+        SchedulerMetrics::changed(&cluster.node_id, "lost");
+    }
+    SchedulerMetrics::fenced_out(&cluster.node_id);
+}
+
+impl Cluster {
+    async fn tick(&self) -> anyhow::Result<()> {
+        let mut redis = self.redis.clone();
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let ttl_ms = LEASE_TTL.as_millis() as u64;
+
+        redis::pipe()
+            .zadd(NODES_KEY, &self.node_id, now_ms)
+            .ignore()
+            .zrembyscore(NODES_KEY, "-inf", now_ms - NODE_TTL.as_millis() as i64)
+            .ignore()
+            .query_async::<_, ()>(&mut redis)
+            .await?;
+        let live_nodes: usize = redis::cmd("ZCARD").arg(NODES_KEY).query_async(&mut redis).await?;
+
+        self.renew(&mut redis, ttl_ms).await?;
+
+        let held = self.held();
+        if !held.is_empty() {
+            let lag = self.lag.lock().unwrap().clone();
+            let mut report = redis::pipe();
+            for shard in held {
+                report.hset(LAG_KEY, shard, lag.get(&shard).copied().unwrap_or(0)).ignore();
+            }
+            report.query_async::<_, ()>(&mut redis).await?;
+        }
+
+        let keys: Vec<String> = (0..SHARDS).map(lease_key).collect();
+        let values: Vec<Option<String>> = redis::cmd("MGET").arg(&keys).query_async(&mut redis).await?;
+        let reported: HashMap<i32, i64> = redis::cmd("HGETALL").arg(LAG_KEY).query_async(&mut redis).await?;
+        let shards: Vec<ShardView> = (0..SHARDS)
+            .zip(&values)
+            .map(|(shard, value)| {
+                let owner = value.as_deref().and_then(lease_owner).map(str::to_string);
+                // A free shard's last report is stale
+                let lag_secs = owner.as_ref().and_then(|_| reported.get(&shard).copied()).unwrap_or(0);
+                ShardView { shard, owner, lag_secs }
+            })
+            .collect();
+
+        let plan = plan(&self.node_id, &shards, live_nodes);
+        for shard in plan.release {
+            self.release(&mut redis, shard).await?;
+        }
+        for shard in plan.acquire {
+            self.acquire(&mut redis, shard, None, ttl_ms).await?;
+        }
+        if let Some(shard) = plan.steal {
+            let observed = values[shard as usize].clone();
+            self.acquire(&mut redis, shard, observed, ttl_ms).await?;
+        }
+
+        SchedulerMetrics::owned(&self.node_id, self.held().len());
+        Ok(())
+    }
+
+    fn held(&self) -> Vec<i32> {
+        self.leases.lock().unwrap().keys().copied().collect()
+    }
+
+    async fn renew(&self, redis: &mut ConnectionManager, ttl_ms: u64) -> anyhow::Result<()> {
+        let held: Vec<(i32, i64)> = self.leases.lock().unwrap().iter().map(|(shard, l)| (*shard, l.token)).collect();
+        for (shard, token) in held {
+            let renewed: i64 = Script::new(RENEW)
+                .key(lease_key(shard))
+                .arg(lease_value(&self.node_id, token))
+                .arg(ttl_ms)
+                .invoke_async(redis)
+                .await?;
+            let mut leases = self.leases.lock().unwrap();
+            if renewed == 1 {
+                if let Some(lease) = leases.get_mut(&shard) {
+                    lease.renewed_at = Instant::now();
+                }
+            } else if leases.remove(&shard).is_some() {
+                tracing::info!("Lost completion shard {}", shard);
+                SchedulerMetrics::changed(&self.node_id, "lost");
+            }
+        }
+        Ok(())
+    }
+
+    /// Take `shard`, free when `observed` is `None`, otherwise from the holder
+    /// whose lease value was observed
+    async fn acquire(
+        &self,
+        redis: &mut ConnectionManager,
+        shard: i32,
+        observed: Option<String>,
+        ttl_ms: u64,
+    ) -> anyhow::Result<()> {
+        let token: i64 = redis::cmd("INCR").arg(fence_key(shard)).query_async(redis).await?;
+        let value = lease_value(&self.node_id, token);
+        let taken = match &observed {
+            None => {
+                let set: Option<String> = redis::cmd("SET")
+                    .arg(lease_key(shard))
+                    .arg(&value)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(ttl_ms)
+                    .query_async(redis)
+                    .await?;
+                set.is_some()
+            }
+            Some(observed) => {
+                let stolen: i64 =
+                    Script::new(STEAL).key(lease_key(shard)).arg(observed).arg(&value).arg(ttl_ms).invoke_async(redis).await?;
+                stolen == 1
+            }
+        };
+        if !taken {
+            return Ok(());
+        }
+
+        self.leases.lock().unwrap().insert(shard, Lease { token, renewed_at: Instant::now() });
+        let change = if observed.is_some() { "stolen" } else { "acquired" };
+        tracing::info!("Completion shard {} {} with token {}", shard, change, token);
+        SchedulerMetrics::changed(&self.node_id, change);
+        Ok(())
+    }
+
+    async fn release(&self, redis: &mut ConnectionManager, shard: i32) -> anyhow::Result<()> {
+        let Some(lease) = self.leases.lock().unwrap().remove(&shard) else {
+            return Ok(());
+        };
+        let _: i64 = Script::new(RELEASE)
+            .key(lease_key(shard))
+            .arg(lease_value(&self.node_id, lease.token))
+            .invoke_async(redis)
+            .await?;
+        SchedulerMetrics::changed(&self.node_id, "released");
+        Ok(())
+    }
+
+    /// Stop completing shards whose lease could not be renewed in time, e.g.
+    /// while Redis is unreachable; another node may hold them by now
+    fn expire_unrenewed(&self) {
+        let mut leases = self.leases.lock().unwrap();
+        let before = leases.len();
+        leases.retain(|_, lease| lease.renewed_at.elapsed() < LEASE_TTL);
+        for _ in leases.len()..before {
+            SchedulerMetrics::changed(&self.node_id, "lost");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(owners: &[Option<&str>], lag: &[(i32, i64)]) -> Vec<ShardView> {
+        owners
+            .iter()
+            .enumerate()
+            .map(|(shard, owner)| ShardView {
+                shard: shard as i32,
+                owner: owner.map(str::to_string),
+                lag_secs: lag.iter().find(|(s, _)| *s == shard as i32).map_or(0, |(_, secs)| *secs),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_shards_and_leases() {
+        assert_eq!(shard_of(33), 1);
+        assert_eq!(shard_of(-1), SHARDS - 1);
+        assert_eq!(fair_share(0), SHARDS as usize);
+        assert_eq!(fair_share(3), 11);
+        assert_eq!(lease_owner(&lease_value("node|a", 7)), Some("node|a"));
+    }
+
+    #[test]
+    fn test_plan_joins_and_rebalances() {
+        let mut owners = vec![None; SHARDS as usize];
+        let first = plan("a", &view(&owners, &[]), 1);
+        assert_eq!(first.acquire.len(), SHARDS as usize);
+
+        // A second node joins: the first gives up half, least behind first
+        owners.iter_mut().for_each(|owner| *owner = Some("a"));
+        let shrink = plan("a", &view(&owners, &[(0, 50)]), 2);
+        assert_eq!(shrink.release.len(), 16);
+        assert!(!shrink.release.contains(&0));
+        assert!(shrink.acquire.is_empty());
+
+        for shard in &shrink.release {
+            owners[*shard as usize] = None;
+        }
+        let grow = plan("b", &view(&owners, &[]), 2);
+        assert_eq!(grow.acquire.len(), 16);
+    }
+
+    #[test]
+    fn test_plan_steals_only_when_keeping_up() {
+        let owners = [Some("a"), Some("a"), Some("b")];
+        assert_eq!(plan("b", &view(&owners, &[(0, 40), (1, 90)]), 2).steal, Some(1));
+        assert_eq!(plan("b", &view(&owners, &[(0, 10)]), 2).steal, None);
+        assert_eq!(plan("b", &view(&owners, &[(1, 90), (2, 45)]), 2).steal, None);
+    }
+}
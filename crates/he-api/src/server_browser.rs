@@ -0,0 +1,153 @@
+//! Server browser and range scans
+//!
+//! Lists NPC servers for players looking for targets, with security details
+//! estimated by [`he_game_mechanics::server_browser`] unless the player has
+//! scanned the server. Range scans add what they find to the player's server
+//! database, and completed port and system scans record what the server runs
+//! there. Both are rate limited per player so the internet can't be scraped.
+
+use he_database::queries::{BrowserFilter, BrowserServerRow, ServerBrowserQueries};
+use he_game_mechanics::config::ServerBrowserConfig;
+use he_game_mechanics::server_browser::{self, BrowserDenied, Difficulty, SecurityDetails, SecurityView};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+
+/// Rows looked at for one page when filtering by difficulty
+const MAX_EXAMINED: usize = 500;
+
+/// A server as one player sees it
+#[derive(Debug, Clone, Serialize)]
+pub struct BrowserEntry {
+    pub server_id: i64,
+    pub ip: String,
+    pub hostname: Option<String>,
+    pub npc_type: Option<String>,
+    pub npc_tier: Option<i16>,
+    pub region: Option<String>,
+    /// Set once the server is in the player's server database
+    pub discovered_at: Option<DateTime<Utc>>,
+    pub scanned_at: Option<DateTime<Utc>>,
+    pub security: SecurityView,
+}
+
+/// One page of the browser
+#[derive(Debug, Clone, Serialize)]
+pub struct BrowserPage {
+    pub servers: Vec<BrowserEntry>,
+    pub next_before_id: Option<i64>,
+}
+
+fn entry(user_id: i64, row: BrowserServerRow, cracker: f64, config: &ServerBrowserConfig) -> BrowserEntry {
+    let actual = SecurityDetails { firewall: row.firewall, cpu: row.cpu, net: row.net };
+    let scanned = match (row.scanned_firewall, row.scanned_cpu, row.scanned_net) {
+        (Some(firewall), Some(cpu), Some(net)) => Some(SecurityDetails { firewall, cpu, net }),
+        _ => None,
+    };
+    BrowserEntry {
+        security: server_browser::view(user_id, row.server_id, &actual, scanned.as_ref(), cracker, config),
+        server_id: row.server_id,
+        ip: row.ip,
+        hostname: row.hostname,
+        npc_type: row.npc_type,
+        npc_tier: row.npc_tier,
+        region: row.region,
+        discovered_at: row.discovered_at,
+        scanned_at: row.scanned_at,
+    }
+}
+
+/// A page of NPC servers matching `filter` and, when set, the estimated
+/// `difficulty`
+pub async fn browse(
+    pool: &PgPool,
+    user_id: i64,
+    filter: &BrowserFilter<'_>,
+    difficulty: Option<Difficulty>,
+    before_id: Option<i64>,
+    limit: Option<i64>,
+) -> anyhow::Result<Result<BrowserPage, BrowserDenied>> {
+    let config = &crate::balance::current().server_browser;
+    if !ServerBrowserQueries::take_request(pool, user_id, "browse", config.browse_per_minute, 1).await? {
+        return Ok(Err(BrowserDenied::RateLimited { limit: config.browse_per_minute, window_minutes: 1 }));
+    }
+
+    let limit = limit.unwrap_or(config.page_size).clamp(1, config.max_page_size);
+    let cracker = ServerBrowserQueries::cracker(pool, user_id).await?;
+    // Without a difficulty filter every row is shown, so one fetch is a page
+    let batch = if difficulty.is_some() { limit * 4 } else { limit };
+
+    let mut servers = Vec::new();
+    let mut cursor = before_id;
+    let mut examined = 0;
+    loop {
+        let rows = ServerBrowserQueries::browse(pool, user_id, filter, cursor, batch).await?;
+        let exhausted = (rows.len() as i64) < batch;
+        for row in rows {
+            examined += 1;
+            cursor = Some(row.server_id);
+            let entry = entry(user_id, row, cracker, config);
+            if difficulty.map_or(true, |d| entry.security.difficulty == d) {
+                servers.push(entry);
+                if servers.len() as i64 == limit {
+                    return Ok(Ok(BrowserPage { servers, next_before_id: cursor }));
+                }
+            }
+        }
+        if exhausted {
+            return Ok(Ok(BrowserPage { servers, next_before_id: None }));
+        }
+        if examined >= MAX_EXAMINED {
+            // Hand back what was found; the client carries on from here
+            return Ok(Ok(BrowserPage { servers, next_before_id: cursor }));
+        }
+    }
+}
+
+/// Scan `range` for NPC servers and add them to the player's database
+pub async fn scan_range(pool: &PgPool, user_id: i64, range: &str) -> anyhow::Result<Result<Vec<BrowserEntry>, BrowserDenied>> {
+    let config = &crate::balance::current().server_browser;
+    let range = match server_browser::parse_range(range, config) {
+        Ok(range) => range,
+        Err(denied) => return Ok(Err(denied)),
+    };
+    if !ServerBrowserQueries::take_request(pool, user_id, "scan_range", config.range_scans_per_hour, 60).await? {
+        return Ok(Err(BrowserDenied::RateLimited { limit: config.range_scans_per_hour, window_minutes: 60 }));
+    }
+
+    let cracker = ServerBrowserQueries::cracker(pool, user_id).await?;
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let rows = ServerBrowserQueries::in_range(&mut *tx, user_id, &range.cidr()).await?;
+    let found: Vec<i64> = rows.iter().map(|r| r.server_id).collect();
+    ServerBrowserQueries::discover(&mut *tx, user_id, &found).await?;
+    tx.commit().await?;
+
+    let now = Utc::now();
+    Ok(Ok(rows
+        .into_iter()
+        .map(|row| BrowserEntry {
+            discovered_at: row.discovered_at.or(Some(now)),
+            ..entry(user_id, row, cracker, config)
+        })
+        .collect()))
+}
+
+/// The player's server database
+pub async fn known(
+    pool: &PgPool,
+    user_id: i64,
+    before: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+) -> anyhow::Result<Vec<BrowserEntry>> {
+    let config = &crate::balance::current().server_browser;
+    let limit = limit.unwrap_or(config.page_size).clamp(1, config.max_page_size);
+    let cracker = ServerBrowserQueries::cracker(pool, user_id).await?;
+    let rows = ServerBrowserQueries::known(pool, user_id, before, limit).await?;
+    Ok(rows.into_iter().map(|row| entry(user_id, row, cracker, config)).collect())
+}
+
+/// Keep what a completed scan found, inside the completion transaction
+pub(crate) async fn record_scan(conn: &mut PgConnection, user_id: i64, target_ip: &str) -> anyhow::Result<()> {
+    ServerBrowserQueries::record_scan(conn, user_id, target_ip).await?;
+    Ok(())
+}
@@ -0,0 +1,93 @@
+//! Component health for the public status page
+//!
+//! Every minute the components on the status page are probed through the same
+//! [`HealthRegistry`] as `/health/detailed`. The results are counted towards
+//! the uptime history in the log database, and the cached [`StatusReport`]
+//! served by `/status` and `/status.json` is rebuilt.
+
+use chrono::Utc;
+use he_monitoring::{HealthRegistry, HealthStatus};
+use he_status::{StatusReport, StatusStore, COMPONENTS};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+pub struct StatusMonitor {
+    store: StatusStore,
+    health: Arc<HealthRegistry>,
+    latest: RwLock<Vec<HealthStatus>>,
+    report: RwLock<Arc<StatusReport>>,
+}
+
+impl StatusMonitor {
+    pub fn new(store: StatusStore, health: Arc<HealthRegistry>) -> Self {
+        let empty = StatusReport::build(&[], &HashMap::new(), Vec::new(), Utc::now());
+        Self {
+            store,
+            health,
+            latest: RwLock::new(Vec::new()),
+            report: RwLock::new(Arc::new(empty)),
+        }
+    }
+
+    pub fn store(&self) -> &StatusStore {
+        &self.store
+    }
+
+    pub async fn report(&self) -> Arc<StatusReport> {
+        self.report.read().await.clone()
+    }
+
+    /// Probe the components shown on the status page
+    async fn probe(&self) -> Vec<HealthStatus> {
+        let mut statuses = self.health.check().await.statuses();
+        statuses.retain(|status| COMPONENTS.iter().any(|(key, _)| *key == status.name));
+        statuses
+    }
+
+    /// Take one round of health checks and record it
+    pub async fn sample(&self) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let statuses = self.probe().await;
+        *self.latest.write().await = statuses.clone();
+
+        self.store.record(&statuses, now).await?;
+        self.refresh().await
+    }
+
+    /// Rebuild the cached report, e.g. after an incident changed
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let history = self.store.history(now).await?;
+        let incidents = self.store.incidents(now).await?;
+        let latest = self.latest.read().await.clone();
+
+        let report = StatusReport::build(&latest, &history, incidents, now);
+        *self.report.write().await = Arc::new(report);
+        Ok(())
+    }
+
+    /// Sample every `interval`, pruning old history once a day. The first
+    /// sample waits an interval, so the API probe finds the server listening.
+    pub fn spawn_sampler(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            let mut pruned_on = None;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.sample().await {
+                    tracing::error!("Failed to record status checks: {}", e);
+                }
+
+                let today = Utc::now().date_naive();
+                if pruned_on != Some(today) {
+                    match self.store.prune(Utc::now()).await {
+                        Ok(_) => pruned_on = Some(today),
+                        Err(e) => tracing::warn!("Failed to prune status history: {}", e),
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,107 @@
+//! Collections streamed over the WebSocket
+//!
+//! Each source scopes its query to the session's user, so a cursor replayed on
+//! another account only ever pages through that account's own rows.
+
+use futures_util::future::BoxFuture;
+use he_database::queries::{LogQueries, ProcessQueries};
+use he_helix_websocket_handlers::stream::{Page, StreamContext, StreamRegistry, StreamSource};
+use he_helix_websocket_handlers::{WebSocketError, WebSocketResult};
+use serde_json::Value;
+use sqlx::PgPool;
+
+pub fn registry(pool: &PgPool) -> StreamRegistry {
+    let mut registry = StreamRegistry::new();
+    registry
+        .register("processes", ProcessStream { pool: pool.clone() })
+        .register("logs", LogStream { pool: pool.clone() });
+    registry
+}
+
+fn parse_position(after: Option<&str>) -> WebSocketResult<Option<i64>> {
+    after
+        .map(|p| p.parse::<i64>())
+        .transpose()
+        .map_err(|_| WebSocketError::InvalidRequest {
+            message: "Malformed cursor".to_string(),
+        })
+}
+
+fn source_failed(e: anyhow::Error) -> WebSocketError {
+    tracing::error!("Stream query failed: {}", e);
+    WebSocketError::SourceFailed {
+        message: "Failed to load page".to_string(),
+    }
+}
+
+/// Running processes, by pid
+struct ProcessStream {
+    pool: PgPool,
+}
+
+impl StreamSource for ProcessStream {
+    fn fetch(
+        &self,
+        ctx: &StreamContext,
+        _params: &Value,
+        after: Option<&str>,
+        limit: usize,
+    ) -> BoxFuture<'static, WebSocketResult<Page>> {
+        let pool = self.pool.clone();
+        let user_id = ctx.user_id;
+        let after = parse_position(after);
+
+        Box::pin(async move {
+            let processes = ProcessQueries::page_for_user(&pool, user_id, after?, limit as i64)
+                .await
+                .map_err(source_failed)?;
+
+            let next = match processes.last() {
+                Some(last) if processes.len() == limit => Some(last.pid.to_string()),
+                _ => None,
+            };
+            let items = processes
+                .iter()
+                .map(|p| serde_json::to_value(p).unwrap_or(Value::Null))
+                .collect();
+
+            Ok(Page { items, next })
+        })
+    }
+}
+
+/// The user's logs, newest first
+struct LogStream {
+    pool: PgPool,
+}
+
+impl StreamSource for LogStream {
+    fn fetch(
+        &self,
+        ctx: &StreamContext,
+        _params: &Value,
+        after: Option<&str>,
+        limit: usize,
+    ) -> BoxFuture<'static, WebSocketResult<Page>> {
+        let pool = self.pool.clone();
+        let user_id = ctx.user_id;
+        let after = parse_position(after);
+
+        Box::pin(async move {
+            let logs = LogQueries::page_for_user(&pool, user_id, after?, limit as i64)
+                .await
+                .map_err(source_failed)?;
+
+            let next = match logs.last() {
+                Some(last) if logs.len() == limit => Some(last.id.to_string()),
+                _ => None,
+            };
+            let items = logs
+                .iter()
+                .map(|l| serde_json::to_value(l).unwrap_or(Value::Null))
+                .collect();
+
+            Ok(Page { items, next })
+        })
+    }
+}
@@ -0,0 +1,282 @@
+//! Player terminal
+//!
+//! Runs command lines from the terminal against the commands plugins added,
+//! with the player's aliases expanded and `&&` chains run stage by stage:
+//! each stage is checked when its turn comes (the command still exists, its
+//! cooldown passed) and sees the game state as the stage before left it.
+//! The first failing stage stops the chain. Rules are in
+//! [`he_game_mechanics::terminal`].
+//!
+//! Each stage is parsed against the grammar its plugin declared before it
+//! runs (see [`he_game_mechanics::terminal_grammar`]), so malformed lines
+//! are refused with a suggestion without spending a cooldown. `help`, and
+//! `--help` on commands with a grammar, answer from the grammar, translated
+//! for the player's locale.
+//!
+//! `nslookup` and `whois` are answered by the whois registry
+//! ([`crate::dns`]) rather than a plugin.
+
+use he_database::queries::{PluginQueries, TerminalAliasRow, TerminalQueries};
+use he_game_mechanics::config::TerminalConfig;
+use he_game_mechanics::dns;
+use he_game_mechanics::terminal::{self, TerminalDenied};
+use he_game_mechanics::terminal_grammar::{self as grammar, CommandSpec, HELP_COMMAND};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use crate::plugins::wasm::{PluginHost, WasmPluginError};
+
+type TerminalResult<T> = anyhow::Result<Result<T, TerminalDenied>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+    Ok,
+    Failed,
+    /// Not run because an earlier stage failed
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stage {
+    pub line: String,
+    pub status: StageStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Why the stage was refused before running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denied: Option<TerminalDenied>,
+    /// How the command failed when it ran
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "plugin_error")]
+    pub error: Option<WasmPluginError>,
+}
+
+fn plugin_error<S: serde::Serializer>(error: &Option<WasmPluginError>, serializer: S) -> Result<S::Ok, S::Error> {
+    match error {
+        Some(e) => serializer.serialize_str(&e.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// The player's aliases with how many they may keep and how long their
+/// chains may be
+#[derive(Debug, Serialize)]
+pub struct Aliases {
+    pub aliases: Vec<TerminalAliasRow>,
+    pub alias_slots: usize,
+    pub max_chain_len: usize,
+}
+
+fn alias_map(aliases: &[TerminalAliasRow]) -> HashMap<String, String> {
+    aliases.iter().map(|alias| (alias.name.clone(), alias.expansion.clone())).collect()
+}
+
+/// Plugin commands and the registry's `nslookup` and `whois`
+fn command_names(host: &PluginHost) -> Vec<String> {
+    host.commands()
+        .into_iter()
+        .map(|(command, _)| command)
+        .chain(dns::COMMANDS.map(str::to_string))
+        .collect()
+}
+
+/// The locale of the request being served
+pub(crate) fn locale() -> String {
+    he_core::context::RequestContext::with_current(|ctx| {
+        ctx.map_or_else(|| he_core::context::DEFAULT_LOCALE.to_string(), |ctx| ctx.locale.clone())
+    })
+}
+
+/// The grammar of `command`; commands without one accept anything
+fn command_spec(host: &PluginHost, command: &str) -> CommandSpec {
+    dns::command_spec(command)
+        .or_else(|| host.grammar(command))
+        .unwrap_or_else(|| CommandSpec::open(command))
+}
+
+/// `help` on its own lists every command; `help <command> [subcommand...]`
+/// details one
+fn help(host: &PluginHost, words: &[String], translations: &HashMap<String, String>) -> Result<String, TerminalDenied> {
+    let Some(command) = words.get(1) else {
+        let specs: Vec<_> = command_names(host).iter().map(|command| command_spec(host, command)).collect();
+        return Ok(grammar::help_index(&specs, translations));
+    };
+    let commands = command_names(host);
+    if !commands.contains(command) {
+        return Err(unknown_command(command, &commands));
+    }
+    Ok(grammar::help(&command_spec(host, command), &words[1..], translations))
+}
+
+fn unknown_command(command: &str, commands: &[String]) -> TerminalDenied {
+    let candidates = commands.iter().map(String::as_str).chain([HELP_COMMAND]);
+    TerminalDenied::UnknownCommand { command: command.to_string(), suggestion: grammar::suggest(command, candidates) }
+}
+
+/// Run `line` for `user_id`, with help in `locale`. The line as a whole is
+/// refused if it is too long or chains too many commands; after that each
+/// stage reports its own outcome.
+pub async fn run(pool: &PgPool, host: &PluginHost, user_id: i64, line: &str, locale: &str) -> TerminalResult<Vec<Stage>> {
+    let config = &crate::balance::current().terminal;
+    let level = TerminalQueries::level(pool, user_id).await?;
+    let aliases = alias_map(&TerminalQueries::aliases(pool, user_id).await?);
+    let lines = match terminal::expand(line.trim(), &aliases, level, config) {
+        Ok(lines) => lines,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let translations = host.translations(locale);
+    let mut stages = Vec::with_capacity(lines.len());
+    let mut failed = false;
+    for line in lines {
+        if failed {
+            stages.push(Stage { line, status: StageStatus::Skipped, output: None, denied: None, error: None });
+            continue;
+        }
+        let stage = run_stage(pool, host, user_id, line, config, &translations).await?;
+        failed = stage.status == StageStatus::Failed;
+        stages.push(stage);
+    }
+    Ok(Ok(stages))
+}
+
+async fn run_stage(
+    pool: &PgPool,
+    host: &PluginHost,
+    user_id: i64,
+    line: String,
+    config: &TerminalConfig,
+    translations: &HashMap<String, String>,
+) -> anyhow::Result<Stage> {
+    let refused = |line: String, denied: TerminalDenied| Stage {
+        line,
+        status: StageStatus::Failed,
+        output: None,
+        denied: Some(denied),
+        error: None,
+    };
+    let answered = |line: String, output: String| Stage {
+        line,
+        status: StageStatus::Ok,
+        output: Some(output),
+        denied: None,
+        error: None,
+    };
+
+    let words = match grammar::tokenize(&line) {
+        Ok(words) => words,
+        Err(error) => return Ok(refused(line, TerminalDenied::Syntax { error })),
+    };
+    let command = words.first().cloned().unwrap_or_default();
+    if command == HELP_COMMAND {
+        return Ok(match help(host, &words, translations) {
+            Ok(output) => answered(line, output),
+            Err(denied) => refused(line, denied),
+        });
+    }
+
+    let commands = command_names(host);
+    if !commands.contains(&command) {
+        return Ok(refused(line, unknown_command(&command, &commands)));
+    }
+    let spec = command_spec(host, &command);
+    let parsed = match grammar::parse(&words, &spec) {
+        Ok(parsed) => parsed,
+        Err(error) => return Ok(refused(line, TerminalDenied::Syntax { error })),
+    };
+    // Commands without a grammar answer --help themselves
+    if parsed.help && !spec.open {
+        return Ok(answered(line, grammar::help(&spec, &parsed.path, translations)));
+    }
+
+    let cooldown = terminal::cooldown_ms(&command, config);
+    if let Some(retry_after_ms) = TerminalQueries::claim_cooldown(pool, user_id, &command, cooldown).await? {
+        return Ok(refused(line, TerminalDenied::CoolingDown { command, retry_after_ms }));
+    }
+
+    if let Some(output) = crate::dns::run_command(pool, user_id, &parsed).await {
+        return Ok(match output? {
+            Ok(output) => answered(line, output),
+            Err(denied) => refused(line, TerminalDenied::Lookup { denied }),
+        });
+    }
+
+    let snapshot = PluginQueries::player_snapshot(pool, user_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Player {} not found", user_id))?;
+    let game_state = serde_json::to_value(&snapshot)?;
+
+    Ok(match host.run_command(&line, &game_state).await {
+        Some(Ok(output)) => Stage { line, status: StageStatus::Ok, output: Some(output), denied: None, error: None },
+        Some(Err(e)) => Stage { line, status: StageStatus::Failed, output: None, denied: None, error: Some(e) },
+        // Unloaded since the check
+        None => refused(line, TerminalDenied::UnknownCommand { command, suggestion: None }),
+    })
+}
+
+pub async fn aliases(pool: &PgPool, user_id: i64) -> anyhow::Result<Aliases> {
+    let config = &crate::balance::current().terminal;
+    let level = TerminalQueries::level(pool, user_id).await?;
+    Ok(Aliases {
+        aliases: TerminalQueries::aliases(pool, user_id).await?,
+        alias_slots: terminal::alias_slots(level, config),
+        max_chain_len: terminal::max_chain_len(level, config),
+    })
+}
+
+/// Create or replace alias `name`
+pub async fn save_alias(
+    pool: &PgPool,
+    host: &PluginHost,
+    user_id: i64,
+    name: &str,
+    expansion: &str,
+) -> TerminalResult<TerminalAliasRow> {
+    let config = &crate::balance::current().terminal;
+    let level = TerminalQueries::level(pool, user_id).await?;
+    let expansion = expansion.trim();
+
+    let mut tx = he_database::tagging::begin(pool).await?;
+    let existing = alias_map(&TerminalQueries::lock_aliases(&mut tx, user_id).await?);
+    if let Err(denied) = terminal::check_alias(name, expansion, &command_names(host), &existing, level, config) {
+        return Ok(Err(denied));
+    }
+    let alias = TerminalQueries::save_alias(&mut tx, user_id, name, expansion).await?;
+    tx.commit().await?;
+
+    Ok(Ok(alias))
+}
+
+pub async fn delete_alias(pool: &PgPool, user_id: i64, name: &str) -> TerminalResult<()> {
+    if TerminalQueries::delete_alias(pool, user_id, name).await? {
+        Ok(Ok(()))
+    } else {
+        Ok(Err(TerminalDenied::AliasNotFound))
+    }
+}
+
+/// Commands, `help` and aliases starting with `prefix`
+pub async fn complete_commands(pool: &PgPool, host: &PluginHost, user_id: i64, prefix: &str) -> anyhow::Result<Vec<String>> {
+    let limit = crate::balance::current().terminal.completion_limit as usize;
+    let aliases = TerminalQueries::aliases(pool, user_id).await?;
+    let mut names: Vec<String> = command_names(host)
+        .into_iter()
+        .chain([HELP_COMMAND.to_string()])
+        .chain(aliases.into_iter().map(|alias| alias.name))
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names.dedup();
+    names.truncate(limit);
+    Ok(names)
+}
+
+pub async fn complete_ips(pool: &PgPool, user_id: i64, prefix: &str) -> anyhow::Result<Vec<String>> {
+    TerminalQueries::known_ips(pool, user_id, prefix, crate::balance::current().terminal.completion_limit).await
+}
+
+/// File names on the server the player is connected to, with its address;
+/// `None` when not connected anywhere
+pub async fn complete_files(pool: &PgPool, user_id: i64, prefix: &str) -> anyhow::Result<Option<(String, Vec<String>)>> {
+    TerminalQueries::connected_files(pool, user_id, prefix, crate::balance::current().terminal.completion_limit).await
+}
@@ -0,0 +1,117 @@
+//! New-player tutorial
+//!
+//! Progress is driven by process completions, not by the client: every
+//! completion is checked against the player's current step inside the
+//! completion transaction, so a step only advances once the server has
+//! actually finished the scan, crack, download or log deletion against the
+//! whois server. The next step's hint is queued in the outbox by the same
+//! transaction and pushed over the player's socket. Finishing the last step
+//! pays the reward once per account.
+
+use he_database::models::Process;
+use he_database::queries::{BankQueries, LedgerAccount, LedgerReason, ProgressionQueries, TutorialQueries, TutorialRow};
+use he_game_mechanics::process::{CompletionReward, ProcessType};
+use he_game_mechanics::tutorial::{self, TutorialHint, TutorialStep};
+use serde::Serialize;
+use sqlx::PgConnection;
+use crate::outbox::OutboxMessage;
+
+/// A step the completion worker just advanced
+pub(crate) struct TutorialAdvance {
+    pub step: TutorialStep,
+    /// Paid when this advance finished the tutorial for the first time
+    pub reward: Option<CompletionReward>,
+}
+
+/// What the client is shown for a player's tutorial
+#[derive(Debug, Serialize)]
+pub struct TutorialState {
+    pub step: TutorialStep,
+    pub hint: TutorialHint,
+    pub rewarded: bool,
+}
+
+impl TutorialState {
+    pub fn from_row(row: &TutorialRow) -> Self {
+        let step = TutorialStep::from_str(&row.step).unwrap_or(TutorialStep::Skipped);
+        Self {
+            step,
+            hint: step.hint(),
+            rewarded: row.rewarded_at.is_some(),
+        }
+    }
+}
+
+/// Advance the owner's tutorial if `process` completes their current step
+pub(crate) async fn advance(conn: &mut PgConnection, process: &Process) -> anyhow::Result<Option<TutorialAdvance>> {
+    let Some(row) = TutorialQueries::lock_active(conn, process.user_id).await? else {
+        return Ok(None);
+    };
+    let Some(current) = TutorialStep::from_str(&row.step) else {
+        return Ok(None);
+    };
+
+    let process_type = ProcessType::from_str(&process.process_type);
+    let Some(step) = tutorial::advance(current, &process_type, process.target_pc_id.as_deref()) else {
+        return Ok(None);
+    };
+
+    if step != TutorialStep::Completed {
+        TutorialQueries::set_step(conn, process.user_id, step.as_str()).await?;
+        return Ok(Some(TutorialAdvance { step, reward: None }));
+    }
+
+    let mut reward = None;
+    if row.rewarded_at.is_none() {
+        let mut paid = tutorial::completion_reward(&crate::balance::current().tutorial);
+        paid.experience = crate::rules::current().experience(paid.experience);
+        let reference = format!("tutorial:{}", process.user_id);
+        let credited = BankQueries::credit_primary_account(
+            conn,
+            process.user_id,
+            paid.money,
+            LedgerAccount::Mint,
+            LedgerReason::TutorialReward,
+            &reference,
+        )
+        .await?;
+        if !credited {
+            // Nowhere to pay out; the experience is still granted
+            paid.money = 0;
+        }
+        if paid.experience > 0 {
+            ProgressionQueries::add_experience(conn, process.user_id, paid.experience).await?;
+        }
+        reward = Some(paid);
+    }
+    TutorialQueries::complete(conn, process.user_id, reward.is_some()).await?;
+
+    Ok(Some(TutorialAdvance { step, reward }))
+}
+
+/// The hint for the player's new step, and the reward if one was paid
+pub(crate) fn outbox_messages(pid: i64, user_id: i64, advance: &TutorialAdvance) -> Vec<OutboxMessage> {
+    let hint = hint_event(advance.step, advance.reward);
+    let mut messages = vec![OutboxMessage::to_user(format!("process:{}:tutorial", pid), user_id, &hint)];
+
+    if let Some(reward) = advance.reward.filter(|reward| reward.money > 0) {
+        let event = he_websocket::EventBuilder::money_received(reward.money, "Tutorial".to_string());
+        messages.push(OutboxMessage::to_user(format!("process:{}:tutorial_reward", pid), user_id, &event));
+    }
+    messages
+}
+
+/// Send `step`'s hint to every socket the player has open
+pub fn push_hint(ws_manager: &he_websocket::ConnectionManager, user_id: i64, step: TutorialStep) {
+    ws_manager.send_to_user(user_id, hint_event(step, None).to_server_message());
+}
+
+fn hint_event(step: TutorialStep, reward: Option<CompletionReward>) -> he_websocket::GameEvent {
+    he_websocket::GameEvent::Custom {
+        event_name: "tutorial".to_string(),
+        payload: serde_json::json!({
+            "hint": step.hint(),
+            "reward": reward,
+        }),
+    }
+}
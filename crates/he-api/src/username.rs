@@ -0,0 +1,144 @@
+//! Username changes
+//!
+//! A change runs in one transaction: the player's row and the new name are
+//! locked, the name is checked against everyone else's in any case and
+//! against recently given-up names, and the rename, its history row and the
+//! reservation of the old name are written together. The rules are in
+//! [`he_game_mechanics::username`].
+//!
+//! After commit the change is pushed out: cached profiles and leaderboards
+//! holding the old name are dropped, and the player's sockets and clan are
+//! told so open chat views relabel their messages. The forum bridge picks up
+//! the rename from the `users` trigger on its own, and renames made on the
+//! forum come back through [`change_from_forum`] under the same rules.
+
+use chrono::Utc;
+use he_cache::versions::VersionStore;
+use he_cache::{CacheKeys, CacheManager};
+use he_database::queries::{ClanServerQueries, UsernameChangeRow, UsernameQueries};
+use he_game_mechanics::username::{self, RenameDenied};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use he_database::models::legacy_user_uuid;
+
+type RenameResult<T> = anyhow::Result<Result<T, RenameDenied>>;
+
+/// Change the player's username to `new_name`
+pub async fn change(pool: &PgPool, user_id: i64, new_name: &str) -> RenameResult<UsernameChangeRow> {
+    let tx = he_database::tagging::begin(pool).await?;
+    apply(tx, user_id, new_name).await
+}
+
+/// Take over a rename made on the forum. The change is marked as the
+/// forum's, so the sync trigger does not send it back.
+pub async fn change_from_forum(pool: &PgPool, user_id: i64, new_name: &str) -> RenameResult<UsernameChangeRow> {
+    let mut tx = he_database::tagging::begin(pool).await?;
+    UsernameQueries::mark_forum_origin(&mut *tx).await?;
+    apply(tx, user_id, new_name).await
+}
+
+async fn apply(mut tx: Transaction<'static, Postgres>, user_id: i64, new_name: &str) -> RenameResult<UsernameChangeRow> {
+    let config = &crate::balance::current().username;
+    let offensive_terms = &crate::balance::current().moderation.offensive_terms;
+    if let Err(denied) = username::validate(new_name, config, offensive_terms) {
+        return Ok(Err(denied));
+    }
+
+    let Some(current) = UsernameQueries::lock_user(&mut *tx, user_id).await? else {
+        return Ok(Err(RenameDenied::UserNotFound));
+    };
+    if current == new_name {
+        return Ok(Err(RenameDenied::SameName));
+    }
+    let last_change = UsernameQueries::last_change(&mut *tx, user_id).await?;
+    if let Err(denied) = username::check_cooldown(last_change, Utc::now(), config) {
+        return Ok(Err(denied));
+    }
+
+    UsernameQueries::lock_name(&mut *tx, new_name).await?;
+    if UsernameQueries::taken(&mut *tx, new_name, user_id).await? {
+        return Ok(Err(RenameDenied::Taken));
+    }
+    if let Some(until) = UsernameQueries::reserved_for_other(&mut *tx, new_name, user_id).await? {
+        return Ok(Err(RenameDenied::Reserved { until }));
+    }
+
+    let reserved_until = username::reserved_until(Utc::now(), config);
+    let change = UsernameQueries::rename(&mut *tx, user_id, &current, new_name, reserved_until).await?;
+    tx.commit().await?;
+
+    Ok(Ok(change))
+}
+
+/// Whether a new account may take `name`: not blocked, and not given up
+/// recently by someone else
+pub async fn check_new(pool: &PgPool, name: &str) -> RenameResult<()> {
+    if username::is_blocked(name, &crate::balance::current().moderation.offensive_terms) {
+        return Ok(Err(RenameDenied::Blocked));
+    }
+    let mut conn = pool.acquire().await?;
+    // Nobody has id 0, so every reservation counts
+    if let Some(until) = UsernameQueries::reserved_for_other(&mut conn, name, 0).await? {
+        return Ok(Err(RenameDenied::Reserved { until }));
+    }
+    Ok(Ok(()))
+}
+
+/// Where a committed change is pushed to
+pub struct RenamePropagation {
+    cache: Option<Arc<CacheManager>>,
+    versions: Arc<dyn VersionStore>,
+}
+
+impl RenamePropagation {
+    pub fn new(cache: Option<Arc<CacheManager>>, versions: Arc<dyn VersionStore>) -> Self {
+        Self { cache, versions }
+    }
+
+    /// Drop cached copies of the old name and tell everyone showing it.
+    /// Failures are logged: the change itself is already committed.
+    pub async fn propagate(
+        &self,
+        pool: &PgPool,
+        ws_manager: Option<&he_websocket::ConnectionManager>,
+        change: &UsernameChangeRow,
+    ) {
+        let account = legacy_user_uuid(change.user_id);
+        let keys = [
+            CacheKeys::user_profile(account),
+            CacheKeys::leaderboard("players"),
+            CacheKeys::leaderboard("level"),
+            CacheKeys::leaderboard("pvp"),
+        ];
+        for key in keys {
+            if let Some(cache) = &self.cache {
+                if let Err(e) = cache.delete(&key).await {
+                    tracing::warn!("Failed to invalidate {} after rename of user {}: {}", key, change.user_id, e);
+                }
+            }
+            // Bumped on its own so ETags move on without Redis too
+            if let Err(e) = self.versions.bump(&key).await {
+                tracing::warn!("Failed to bump {} after rename of user {}: {}", key, change.user_id, e);
+            }
+        }
+
+        let Some(ws_manager) = ws_manager else {
+            return;
+        };
+        let message = he_websocket::GameEvent::Custom {
+            event_name: "username_changed".to_string(),
+            payload: serde_json::json!({
+                "user_id": change.user_id,
+                "old_username": change.old_login,
+                "new_username": change.new_login,
+            }),
+        }
+        .to_server_message();
+        ws_manager.send_to_user(change.user_id, message.clone());
+        match ClanServerQueries::membership(pool, change.user_id).await {
+            Ok(Some(clan)) => ws_manager.send_to_entity(he_websocket::Entity::Clan(clan.clan_id), message),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to find the clan of renamed user {}: {}", change.user_id, e),
+        }
+    }
+}
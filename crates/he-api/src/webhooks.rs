@@ -0,0 +1,409 @@
+//! Outbound clan and player webhooks
+//!
+//! Clan leaders and officers register webhooks for their clan, and players
+//! for themselves; the rules are in [`he_game_mechanics::webhooks`]. Events
+//! are queued per webhook in the transaction that caused them (clan joins by
+//! a trigger, since legacy pages add members too), and a worker on every
+//! node posts them. Posts are JSON with a Discord-ready `content` line, and
+//! are signed like payment provider webhooks: `X-HE-Signature` carries
+//! `t=<unix time>,v1=<hex HMAC-SHA256 of "<t>.<body>">` under the webhook's
+//! secret, which is shown once when registering.
+//!
+//! Failed posts are retried with exponential backoff. A webhook failing too
+//! many times in a row is disabled until its owner turns it back on; owners
+//! see each delivery's attempts, status and last error.
+
+use he_database::models::Process;
+use he_database::queries::{ClanServerQueries, ClanWarRow, WebhookDeliveryRow, WebhookJob, WebhookQueries, WebhookRow};
+use he_game_mechanics::config::WebhookConfig;
+use he_game_mechanics::process::{CompletionReward, ProcessType};
+use he_game_mechanics::webhooks::{self, WebhookDenied, WebhookEvent, WebhookOwner};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
+use crate::bounty::{BountyEvent, BountyEventKind};
+
+pub const SIGNATURE_HEADER: &str = "X-HE-Signature";
+pub const EVENT_HEADER: &str = "X-HE-Event";
+/// The delivery's dedup key, the same on every retry
+pub const DELIVERY_HEADER: &str = "X-HE-Delivery";
+
+/// How often due deliveries are picked up
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH: i64 = 50;
+/// How long a leased delivery is left alone before another node retries it
+const LEASE_SECS: i64 = 60;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// Response body kept as a failed delivery's error
+const ERROR_CHARS: usize = 200;
+
+type HmacSha256 = Hmac<Sha256>;
+type WebhookResult<T> = anyhow::Result<Result<T, WebhookDenied>>;
+
+/// A webhook just registered, with the secret it is signed with
+#[derive(Debug, Clone, Serialize)]
+pub struct Registered {
+    #[serde(flatten)]
+    pub webhook: WebhookRow,
+    pub secret: String,
+}
+
+/// `t=<timestamp>,v1=<hex HMAC-SHA256 of "<timestamp>.<body>">`
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let digest: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("t={},v1={}", timestamp, digest)
+}
+
+fn new_secret() -> String {
+    let mut rng = rand::thread_rng();
+    let hex: String = (0..32).map(|_| format!("{:02x}", rng.gen::<u8>())).collect();
+    format!("whsec_{}", hex)
+}
+
+/// The owner id of `owner` webhooks `user_id` may manage
+async fn owner_id(pool: &PgPool, user_id: i64, owner: WebhookOwner) -> WebhookResult<i64> {
+    match owner {
+        WebhookOwner::Player => Ok(Ok(user_id)),
+        WebhookOwner::Clan => match ClanServerQueries::membership(pool, user_id).await? {
+            Some(clan) if clan.is_officer() => Ok(Ok(clan.clan_id)),
+            Some(_) => Ok(Err(WebhookDenied::NotOfficer)),
+            None => Ok(Err(WebhookDenied::NoClan)),
+        },
+    }
+}
+
+/// Webhook `webhook_id`, if `user_id` may manage it
+async fn managed(pool: &PgPool, user_id: i64, webhook_id: i64) -> WebhookResult<WebhookRow> {
+    let Some(webhook) = WebhookQueries::get(pool, webhook_id).await? else {
+        return Ok(Err(WebhookDenied::NotFound));
+    };
+    let Some(owner) = WebhookOwner::from_str(&webhook.owner_kind) else {
+        return Ok(Err(WebhookDenied::NotFound));
+    };
+    // Someone else's webhook is reported as missing
+    match owner_id(pool, user_id, owner).await? {
+        Ok(owner_id) if owner_id == webhook.owner_id => Ok(Ok(webhook)),
+        Ok(_) => Ok(Err(WebhookDenied::NotFound)),
+        Err(denied) => Ok(Err(denied)),
+    }
+}
+
+pub async fn register(pool: &PgPool, user_id: i64, owner: &str, url: &str, events: &[String]) -> WebhookResult<Registered> {
+    let config = &crate::balance::current().webhooks;
+    let Some(owner) = WebhookOwner::from_str(owner) else {
+        return Ok(Err(WebhookDenied::UnknownOwner));
+    };
+    let events = match webhooks::check_events(owner, events) {
+        Ok(events) => events,
+        Err(denied) => return Ok(Err(denied)),
+    };
+    if let Err(denied) = webhooks::check_url(url, config) {
+        return Ok(Err(denied));
+    }
+    let owner_id = match owner_id(pool, user_id, owner).await? {
+        Ok(owner_id) => owner_id,
+        Err(denied) => return Ok(Err(denied)),
+    };
+
+    let secret = new_secret();
+    let events: Vec<String> = events.iter().map(|event| event.as_str().to_string()).collect();
+    let created = WebhookQueries::create(
+        pool,
+        owner.as_str(),
+        owner_id,
+        url,
+        &secret,
+        &events,
+        user_id,
+        config.max_per_owner,
+    )
+    .await?;
+
+    Ok(match created {
+        Some(webhook) => Ok(Registered { webhook, secret }),
+        None => Err(WebhookDenied::TooManyWebhooks { max: config.max_per_owner }),
+    })
+}
+
+pub async fn list(pool: &PgPool, user_id: i64, owner: &str) -> WebhookResult<Vec<WebhookRow>> {
+    let Some(owner) = WebhookOwner::from_str(owner) else {
+        return Ok(Err(WebhookDenied::UnknownOwner));
+    };
+    match owner_id(pool, user_id, owner).await? {
+        Ok(owner_id) => Ok(Ok(WebhookQueries::list(pool, owner.as_str(), owner_id).await?)),
+        Err(denied) => Ok(Err(denied)),
+    }
+}
+
+pub async fn remove(pool: &PgPool, user_id: i64, webhook_id: i64) -> WebhookResult<()> {
+    if let Err(denied) = managed(pool, user_id, webhook_id).await? {
+        return Ok(Err(denied));
+    }
+    match WebhookQueries::delete(pool, webhook_id).await? {
+        true => Ok(Ok(())),
+        false => Ok(Err(WebhookDenied::NotFound)),
+    }
+}
+
+/// Turn a webhook off, or back on after it was disabled
+pub async fn set_enabled(pool: &PgPool, user_id: i64, webhook_id: i64, enabled: bool) -> WebhookResult<WebhookRow> {
+    if let Err(denied) = managed(pool, user_id, webhook_id).await? {
+        return Ok(Err(denied));
+    }
+    Ok(WebhookQueries::set_enabled(pool, webhook_id, enabled).await?.ok_or(WebhookDenied::NotFound))
+}
+
+/// The webhook's latest deliveries, newest first
+pub async fn deliveries(pool: &PgPool, user_id: i64, webhook_id: i64) -> WebhookResult<Vec<WebhookDeliveryRow>> {
+    if let Err(denied) = managed(pool, user_id, webhook_id).await? {
+        return Ok(Err(denied));
+    }
+    let limit = crate::balance::current().webhooks.log_limit;
+    Ok(Ok(WebhookQueries::deliveries(pool, webhook_id, limit).await?))
+}
+
+/// Tell both clans' webhooks about a war, in the declaring transaction
+pub(crate) async fn war_declared(conn: &mut PgConnection, war: &ClanWarRow) -> anyhow::Result<()> {
+    let attacker = WebhookQueries::clan_name(&mut *conn, war.attacker_clan_id).await?;
+    let defender = WebhookQueries::clan_name(&mut *conn, war.defender_clan_id).await?;
+    let payload = json!({
+        "war_id": war.id,
+        "attacker_clan_id": war.attacker_clan_id,
+        "attacker_clan": attacker,
+        "defender_clan_id": war.defender_clan_id,
+        "defender_clan": defender,
+        "ends_at": war.ends_at,
+    });
+
+    let event = WebhookEvent::WarDeclared.as_str();
+    let dedup_key = format!("war:{}:declared", war.id);
+    for clan_id in [war.attacker_clan_id, war.defender_clan_id] {
+        WebhookQueries::emit(&mut *conn, WebhookOwner::Clan.as_str(), clan_id, event, &dedup_key, &payload).await?;
+    }
+    Ok(())
+}
+
+/// Tell the hacker's and their clan's webhooks about a completed login hack
+/// big enough to announce, in the completion transaction
+pub(crate) async fn big_hack(
+    conn: &mut PgConnection,
+    process: &Process,
+    reward: &CompletionReward,
+    bounties: &[BountyEvent],
+) -> anyhow::Result<()> {
+    if ProcessType::from_str(&process.process_type).target_log_type() != Some("login") {
+        return Ok(());
+    }
+    let bounty_reward: i64 = bounties
+        .iter()
+        .filter(|event| matches!(event.kind, BountyEventKind::Claimed { .. }))
+        .map(|event| event.reward)
+        .sum();
+    if !webhooks::is_big_hack(reward.experience, bounty_reward, &crate::balance::current().webhooks) {
+        return Ok(());
+    }
+
+    let username = WebhookQueries::username(&mut *conn, process.user_id).await?;
+    // Only the hacker's name; the target stays private
+    let payload = json!({
+        "user_id": process.user_id,
+        "username": username,
+        "process_type": process.process_type,
+        "experience": reward.experience,
+        "bounty_reward": bounty_reward,
+    });
+
+    let event = WebhookEvent::BigHack.as_str();
+    let dedup_key = format!("process:{}:big_hack", process.pid);
+    WebhookQueries::emit(&mut *conn, WebhookOwner::Player.as_str(), process.user_id, event, &dedup_key, &payload).await?;
+    if let Some(clan_id) = WebhookQueries::clan_of(&mut *conn, process.user_id).await? {
+        WebhookQueries::emit(&mut *conn, WebhookOwner::Clan.as_str(), clan_id, event, &dedup_key, &payload).await?;
+    }
+    Ok(())
+}
+
+/// One line describing the event, posted as Discord's `content`
+fn summary(event: &str, payload: &Value) -> String {
+    let name = |name_key: &str, id_key: &str| match payload[name_key].as_str() {
+        Some(name) => name.to_string(),
+        None => format!("#{}", payload[id_key]),
+    };
+    match WebhookEvent::from_str(event) {
+        Some(WebhookEvent::WarDeclared) => format!(
+            "⚔️ {} declared war on {}",
+            name("attacker_clan", "attacker_clan_id"),
+            name("defender_clan", "defender_clan_id")
+        ),
+        Some(WebhookEvent::MemberJoined) => format!("👋 {} joined the clan", name("username", "user_id")),
+        Some(WebhookEvent::BigHack) => {
+            let mut line = format!("💻 {} pulled off a big hack", name("username", "user_id"));
+            let bounty = payload["bounty_reward"].as_i64().unwrap_or(0);
+            if bounty > 0 {
+                line.push_str(&format!(", claiming ${:.2} in bounties", bounty as f64 / 100.0));
+            }
+            line
+        }
+        None => event.to_string(),
+    }
+}
+
+fn body(job: &WebhookJob) -> Vec<u8> {
+    serde_json::to_vec(&json!({
+        "id": job.dedup_key,
+        "event": job.event,
+        "content": summary(&job.event, &job.payload),
+        "data": job.payload,
+    }))
+    .unwrap_or_default()
+}
+
+/// Refuse to post to addresses inside our network, whatever the name
+/// resolved to since the webhook was registered
+async fn check_resolved(url: &str) -> anyhow::Result<()> {
+    let parsed = reqwest::Url::parse(url)?;
+    let host = parsed.host_str().ok_or_else(|| anyhow::anyhow!("no host"))?;
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    for addr in tokio::net::lookup_host((host, port)).await? {
+        if !webhooks::is_public(addr.ip()) {
+            anyhow::bail!("{} resolves to a private address", host);
+        }
+    }
+    Ok(())
+}
+
+/// Post one delivery; the response status, or why there is none
+async fn post(client: &reqwest::Client, job: &WebhookJob) -> Result<u16, (Option<u16>, String)> {
+    check_resolved(&job.url).await.map_err(|e| (None, e.to_string()))?;
+
+    let body = body(job);
+    let signature = sign(&job.secret, chrono::Utc::now().timestamp(), &body);
+    let response = client
+        .post(&job.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, signature)
+        .header(EVENT_HEADER, &job.event)
+        .header(DELIVERY_HEADER, &job.dedup_key)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(status.as_u16());
+    }
+    let text = response.text().await.unwrap_or_default();
+    let error: String = text.chars().take(ERROR_CHARS).collect();
+    Err((Some(status.as_u16()), format!("HTTP {}: {}", status.as_u16(), error)))
+}
+
+/// Lease, post and record one batch; returns how many were leased
+async fn deliver_batch(pool: &PgPool, client: &reqwest::Client, config: &WebhookConfig) -> anyhow::Result<usize> {
+    let jobs = WebhookQueries::lease_due(pool, BATCH, LEASE_SECS).await?;
+    for job in &jobs {
+        match post(client, job).await {
+            Ok(status) => WebhookQueries::mark_delivered(pool, job.id, job.webhook_id, status as i32).await?,
+            Err((status, error)) => {
+                let retry_in = (job.attempts < config.max_attempts).then(|| webhooks::backoff_secs(job.attempts, config));
+                let disabled = WebhookQueries::record_failure(
+                    pool,
+                    job.id,
+                    job.webhook_id,
+                    status.map(i32::from),
+                    &error,
+                    retry_in,
+                    config.disable_after_failures,
+                )
+                .await?;
+                if disabled {
+                    tracing::info!("Disabled webhook {} after repeated failures: {}", job.webhook_id, error);
+                } else {
+                    tracing::debug!("Webhook delivery {} failed: {}", job.dedup_key, error);
+                }
+            }
+        }
+    }
+    Ok(jobs.len())
+}
+
+/// Post queued deliveries until the process exits
+pub fn start(pool: PgPool) {
+    let config = &crate::balance::current().webhooks;
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        // A redirect could point anywhere, including inside our network
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent("HackerExperience-Webhooks/1.0")
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to build the webhook client, webhooks disabled: {}", e);
+            return;
+        }
+    };
+
+    tokio::spawn({
+        let pool = pool.clone();
+        let keep_days = config.keep_deliveries_days;
+        async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match WebhookQueries::prune(&pool, keep_days).await {
+                    Ok(pruned) if pruned > 0 => tracing::debug!("Pruned {} webhook deliveries", pruned),
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Webhook delivery pruning failed: {}", e),
+                }
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let started = std::time::Instant::now();
+            let leased = match deliver_batch(&pool, &client, config).await {
+                Ok(leased) => leased,
+                Err(e) => {
+                    tracing::warn!("Webhook delivery failed: {}", e);
+                    0
+                }
+            };
+            crate::live_ops::record_tick("webhooks", started.elapsed());
+
+            // A full batch likely left more behind
+            if leased as i64 >= BATCH {
+                continue;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_and_summary() {
+        let signature = sign("whsec_test", 1_700_000_000, b"{}");
+        let (timestamp, digest) = signature.split_once(",v1=").unwrap();
+        assert_eq!(timestamp, "t=1700000000");
+        assert_eq!(digest.len(), 64);
+        assert_ne!(signature, sign("whsec_other", 1_700_000_000, b"{}"));
+
+        let war = json!({ "attacker_clan": "Zer0", "defender_clan_id": 9 });
+        assert_eq!(summary("war_declared", &war), "⚔️ Zer0 declared war on #9");
+        let hack = json!({ "username": "neo", "bounty_reward": 150_000 });
+        assert_eq!(summary("big_hack", &hack), "💻 neo pulled off a big hack, claiming $1500.00 in bounties");
+    }
+}
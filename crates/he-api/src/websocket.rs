@@ -235,4 +235,21 @@ pub async fn websocket_handler(
     stream: web::Payload,
 ) -> Result<HttpResponse, Error> {
     ws::start(WebSocketSession::new(), &req, stream)
-}
\ No newline at end of file
+}
+#[derive(Deserialize)]
+pub struct ProtocolQuery {
+    /// `markdown` for the human-readable form; JSON otherwise
+    pub format: Option<String>,
+}
+
+/// The socket protocol, generated from the same samples the contract tests
+/// hold to the golden files
+pub async fn protocol_document(query: web::Query<ProtocolQuery>) -> HttpResponse {
+    let document = he_websocket::ProtocolDocument::generate();
+    match query.format.as_deref() {
+        Some("markdown") | Some("md") => HttpResponse::Ok()
+            .content_type("text/markdown; charset=utf-8")
+            .body(document.to_markdown()),
+        _ => HttpResponse::Ok().json(document),
+    }
+}
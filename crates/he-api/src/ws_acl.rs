@@ -0,0 +1,97 @@
+//! Who may receive which WebSocket events
+//!
+//! Backs [`he_websocket::acl`] with the database: players see their own
+//! processes and servers, and the clans they are in. Lookup failures deny.
+//! Moderator channels need the `reports:view` permission instead.
+
+use he_database::queries::EntityAccessQueries;
+use he_auth::AuthService;
+use he_websocket::acl::MODERATION_ACL_TTL;
+use he_websocket::{ConnectionManager, Entity, EntityResolver, ModChannel, ResolveFuture};
+use sqlx::PgPool;
+use std::sync::Arc;
+use he_database::models::legacy_user_uuid;
+
+const MODERATION_PERMISSION: &str = "reports:view";
+
+pub struct DatabaseResolver {
+    pool: PgPool,
+    auth: Arc<AuthService>,
+}
+
+impl DatabaseResolver {
+    pub fn new(pool: PgPool, auth: Arc<AuthService>) -> Self {
+        Self { pool, auth }
+    }
+
+    async fn owned(&self, user_id: i64) -> anyhow::Result<Vec<Entity>> {
+        let processes = EntityAccessQueries::running_processes(&self.pool, user_id).await?;
+        let servers = EntityAccessQueries::servers(&self.pool, user_id).await?;
+        let clans = EntityAccessQueries::clans(&self.pool, user_id).await?;
+
+        Ok(processes
+            .into_iter()
+            .map(Entity::Process)
+            .chain(servers.into_iter().map(Entity::Server))
+            .chain(clans.into_iter().map(Entity::Clan))
+            .collect())
+    }
+
+    async fn allowed(&self, user_id: i64, entity: Entity) -> anyhow::Result<bool> {
+        Ok(match entity {
+            Entity::User(id) => id == user_id,
+            Entity::Process(pid) => EntityAccessQueries::process_owner(&self.pool, pid).await? == Some(user_id),
+            Entity::Server(id) => EntityAccessQueries::server_owner(&self.pool, id).await? == Some(user_id),
+            Entity::Clan(id) => EntityAccessQueries::is_clan_member(&self.pool, id, user_id).await?,
+            Entity::Moderation(_) => {
+                self.auth.check_permission(&legacy_user_uuid(user_id), MODERATION_PERMISSION).await?
+            }
+        })
+    }
+}
+
+impl EntityResolver for DatabaseResolver {
+    fn entities(&self, user_id: i64) -> ResolveFuture<'_, Vec<Entity>> {
+        Box::pin(async move {
+            self.owned(user_id).await.unwrap_or_else(|e| {
+                tracing::warn!("Failed to load entities of user {}: {}", user_id, e);
+                Vec::new()
+            })
+        })
+    }
+
+    fn may_receive(&self, user_id: i64, entity: Entity) -> ResolveFuture<'_, bool> {
+        Box::pin(async move {
+            self.allowed(user_id, entity).await.unwrap_or_else(|e| {
+                tracing::warn!("Failed to check {} for user {}: {}", entity.channel(), user_id, e);
+                false
+            })
+        })
+    }
+}
+
+/// Connection manager routing entity events by database ownership
+pub fn connection_manager(pool: PgPool, auth: Arc<AuthService>) -> Arc<ConnectionManager> {
+    Arc::new(ConnectionManager::with_resolver(Arc::new(DatabaseResolver::new(pool, auth))))
+}
+
+/// Re-check moderator channel subscribers every [`MODERATION_ACL_TTL`], so
+/// a revoked `reports:view` stops reports even while none are filed
+pub(crate) fn spawn_revalidation(ws_manager: Option<Arc<ConnectionManager>>) {
+    let Some(ws_manager) = ws_manager else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(MODERATION_ACL_TTL);
+        loop {
+            ticker.tick().await;
+            for channel in ModChannel::ALL {
+                let dropped = ws_manager.revalidate(Entity::Moderation(channel)).await;
+                if dropped > 0 {
+                    tracing::info!("Dropped {} sessions from {}", dropped, channel.name());
+                }
+            }
+        }
+    });
+}
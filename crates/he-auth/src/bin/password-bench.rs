@@ -0,0 +1,85 @@
+//! Argon2id Parameter Benchmark
+//!
+//! Measures password hashing on this host and suggests the strongest
+//! parameters that stay within a target time per hash. Run it on the
+//! production hardware, then set the printed variables and bump
+//! `PASSWORD_POLICY_VERSION` so existing hashes are upgraded at login.
+//!
+//! Usage: password-bench [--target-ms N] [--parallelism N] [--json]
+
+use he_auth::password::{suggest_params, PasswordConfig};
+use std::time::Duration;
+
+const DEFAULT_TARGET_MS: u64 = 250;
+
+fn parse_args() -> Result<(Duration, u32, bool), String> {
+    let mut target_ms = DEFAULT_TARGET_MS;
+    let mut parallelism = PasswordConfig::default().argon2_parallelism;
+    let mut json = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--target-ms" => {
+                target_ms = args.next()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or("--target-ms needs a positive number")?;
+            }
+            "--parallelism" => {
+                parallelism = args.next()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or("--parallelism needs a positive number")?;
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+
+    Ok((Duration::from_millis(target_ms), parallelism, json))
+}
+
+fn main() {
+    let (target, parallelism, json) = match parse_args() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\nusage: password-bench [--target-ms N] [--parallelism N] [--json]", e);
+            std::process::exit(64);
+        }
+    };
+
+    let suggestion = match suggest_params(target, parallelism) {
+        Ok(suggestion) => suggestion,
+        Err(e) => {
+            eprintln!("benchmark failed: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&suggestion).expect("suggestion serialises"));
+        return;
+    }
+
+    println!("{:>12} {:>10} {:>11} {:>10}", "memory KiB", "iterations", "parallelism", "time ms");
+    for result in &suggestion.measurements {
+        println!(
+            "{:>12} {:>10} {:>11} {:>10.1}",
+            result.memory_kib,
+            result.iterations,
+            result.parallelism,
+            result.hash_time.as_secs_f64() * 1000.0
+        );
+    }
+
+    let best = suggestion.suggested;
+    if best.hash_time > target {
+        println!("\nEven the minimum parameters take longer than {} ms on this host.", target.as_millis());
+    }
+    println!("\nSuggested for {} ms per hash:", target.as_millis());
+    println!("ARGON2_MEMORY_KIB={}", best.memory_kib);
+    println!("ARGON2_ITERATIONS={}", best.iterations);
+    println!("ARGON2_PARALLELISM={}", best.parallelism);
+    println!("PASSWORD_POLICY_VERSION={}", PasswordConfig::from_env().policy_version + 1);
+}
@@ -301,7 +301,7 @@ impl JwtManager {
 
         let access_claims = JwtClaims {
             user_id: refresh_claims.user_id,
-            email: user.email,
+            email: he_helix_security::encryption::open_stored(&user.email)?,
             roles: user.roles,
             session_id: None,
             exp: now + self.config.expiration_seconds as usize,
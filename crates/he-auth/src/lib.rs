@@ -7,6 +7,7 @@
 //! - Rate limiting
 //! - Multi-factor authentication (MFA)
 //! - OAuth integration
+//! - OpenID Connect provider for companion services
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
@@ -15,6 +16,7 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
+use he_core::RequestContext;
 
 pub mod jwt;
 pub mod session;
@@ -25,6 +27,7 @@ pub mod rbac;
 pub mod rate_limit;
 pub mod mfa;
 pub mod oauth;
+pub mod oidc;
 pub mod password;
 pub mod middleware;
 
@@ -35,7 +38,8 @@ pub use rbac::{RoleManager, Permission, Role, AccessControl};
 pub use rate_limit::{RateLimiter, RateLimit, RateLimitConfig};
 pub use mfa::{MfaManager, MfaMethod, MfaConfig};
 pub use oauth::{OAuthProvider, OAuthConfig, OAuthManager};
-pub use password::{PasswordManager, PasswordConfig, PasswordStrength};
+pub use oidc::{OidcProvider, OidcConfig, OidcClient, OidcError, OidcSubject, OidcStore, MemoryOidcStore, OidcCode, OidcToken};
+pub use password::{PasswordManager, PasswordConfig, PasswordStrength, HashParams, HashedPassword, VerifyOutcome};
 pub use middleware::{AuthMiddleware, RequireAuth, RequireRole};
 
 /// Main authentication service
@@ -163,6 +167,9 @@ impl AuthService {
         client_ip: Option<String>,
         pool: &sqlx::PgPool,
     ) -> Result<AuthenticationResult> {
+        let request = RequestContext::current();
+        let client_ip = client_ip.or_else(|| request.as_ref().and_then(|r| r.client_ip.clone()));
+
         // Check rate limiting
         if let Some(ip) = &client_ip {
             if !self.rate_limiter.check_login_rate(ip).await {
@@ -172,18 +179,20 @@ impl AuthService {
 
         debug!("Attempting authentication for user: {}", email);
 
-        // Get user from database
+        // Get user from database; sealed addresses are found by blind index
+        let email_index = he_helix_security::encryption::email_index(email);
         let user = sqlx::query!(
             r#"
-            SELECT u.id, u.email, u.pwd as password_hash, u.active, u.email_verified,
+            SELECT u.id, u.email, u.pwd as password_hash, u.pwd_policy_version, u.active, u.email_verified,
                    COALESCE(array_agg(r.name) FILTER (WHERE r.name IS NOT NULL), '{}') as "roles!"
             FROM users u
             LEFT JOIN user_roles ur ON u.id = ur.user_id
             LEFT JOIN roles r ON ur.role_id = r.id
-            WHERE u.email = $1
-            GROUP BY u.id, u.email, u.pwd, u.active, u.email_verified
+            WHERE u.email_index = $2 OR (u.email_index IS NULL AND u.email = $1)
+            GROUP BY u.id, u.email, u.pwd, u.pwd_policy_version, u.active, u.email_verified
             "#,
-            email
+            email,
+            email_index
         )
         .fetch_optional(pool)
         .await
@@ -210,13 +219,14 @@ impl AuthService {
             return Ok(AuthenticationResult::EmailNotVerified);
         }
 
-        // Verify password using Argon2id
-        let password_valid = self.password_manager.verify_password_argon2id(
+        // Verify password, upgrading hashes made under an older policy
+        let outcome = self.password_manager.verify_and_upgrade(
             password,
-            &user.password_hash
+            &user.password_hash,
+            user.pwd_policy_version as u32,
         ).await?;
 
-        if !password_valid {
+        if !outcome.valid {
             // Record failed login attempt
             if let Some(ip) = &client_ip {
                 self.rate_limiter.record_failed_login(&ip).await;
@@ -233,6 +243,24 @@ impl AuthService {
             return Ok(AuthenticationResult::InvalidCredentials);
         }
 
+        if let Some(rehashed) = outcome.rehashed {
+            sqlx::query!(
+                r#"
+                UPDATE users
+                SET pwd = $2, pwd_policy_version = $3, pwd_memory_kib = $4, pwd_iterations = $5, pwd_parallelism = $6
+                WHERE id = $1
+                "#,
+                user.id,
+                rehashed.hash,
+                rehashed.params.policy_version as i32,
+                rehashed.params.memory_kib as i32,
+                rehashed.params.iterations as i32,
+                rehashed.params.parallelism as i32
+            )
+            .execute(pool)
+            .await?;
+        }
+
         // Reset failed login attempts on successful login
         sqlx::query!(
             "UPDATE users SET failed_login_attempts = 0, last_login = NOW() WHERE id = $1",
@@ -257,7 +285,7 @@ impl AuthService {
             login_time: chrono::Utc::now(),
             last_activity: chrono::Utc::now(),
             ip_address: client_ip,
-            metadata: HashMap::new(),
+            metadata: request.as_ref().map(session_metadata).unwrap_or_default(),
         };
 
         let session_id = self.session_manager.create_session(session_data).await?;
@@ -273,7 +301,11 @@ impl AuthService {
 
         let token = self.jwt_manager.generate_token(&jwt_claims)?;
 
-        info!("User {} authenticated successfully", email);
+        info!(
+            "User {} authenticated successfully [{}]",
+            email,
+            request.as_ref().map_or_else(|| "-".to_string(), RequestContext::tag)
+        );
         Ok(AuthenticationResult::Success {
             token,
             user_id,
@@ -318,6 +350,13 @@ impl AuthService {
         Ok(())
     }
 
+    /// Logout user everywhere (invalidate all of their sessions)
+    pub async fn logout_all(&self, user_id: &Uuid) -> Result<usize> {
+        let count = self.session_manager.invalidate_user_sessions(user_id).await?;
+        info!("All sessions invalidated for user {}", user_id);
+        Ok(count)
+    }
+
     /// Check if user has permission
     pub async fn check_permission(&self, user_id: &Uuid, permission: &str) -> Result<bool> {
         let user_roles = self.get_user_roles(user_id).await?;
@@ -358,6 +397,19 @@ impl AuthService {
     }
 }
 
+/// What a new session remembers of the request that signed it in
+fn session_metadata(request: &RequestContext) -> HashMap<String, String> {
+    let mut metadata = HashMap::from([
+        ("request_id".to_string(), request.request_id.0.to_string()),
+        ("trace_id".to_string(), request.trace_id.clone()),
+        ("locale".to_string(), request.locale.clone()),
+    ]);
+    if let Some(shard) = &request.shard {
+        metadata.insert("shard".to_string(), shard.clone());
+    }
+    metadata
+}
+
 /// Authentication result
 #[derive(Debug, Clone)]
 pub enum AuthenticationResult {
@@ -13,7 +13,9 @@ use argon2::{
     Argon2, Argon2id, Algorithm, Params, Version
 };
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, warn};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 use zeroize::Zeroize;
 
 /// Password configuration
@@ -39,6 +41,9 @@ pub struct PasswordConfig {
     pub argon2_parallelism: u32,
     /// Salt length for Argon2
     pub salt_length: usize,
+    /// Version of the hashing parameters above. Bump it whenever they change;
+    /// hashes made under an older version are replaced at the next login.
+    pub policy_version: u32,
 }
 
 impl Default for PasswordConfig {
@@ -55,10 +60,106 @@ impl Default for PasswordConfig {
             argon2_time_cost: 3,         // 3 iterations
             argon2_parallelism: 4,       // 4 parallel threads
             salt_length: 32,             // 32 bytes salt
+            policy_version: 1,
         }
     }
 }
 
+impl PasswordConfig {
+    /// Defaults, with the Argon2 parameters and policy version taken from
+    /// `PASSWORD_POLICY_VERSION`, `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS` and
+    /// `ARGON2_PARALLELISM` when set (see `password-bench` for suggestions)
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u32>().ok());
+
+        if let Some(version) = var("PASSWORD_POLICY_VERSION") {
+            config.policy_version = version;
+        }
+        if let Some(memory) = var("ARGON2_MEMORY_KIB") {
+            config.argon2_memory_cost = memory;
+        }
+        if let Some(iterations) = var("ARGON2_ITERATIONS") {
+            config.argon2_time_cost = iterations;
+        }
+        if let Some(parallelism) = var("ARGON2_PARALLELISM") {
+            config.argon2_parallelism = parallelism;
+        }
+
+        if Params::new(config.argon2_memory_cost, config.argon2_time_cost, config.argon2_parallelism, None).is_err() {
+            warn!("Invalid Argon2 parameters in environment, using defaults");
+            let defaults = Self::default();
+            config.argon2_memory_cost = defaults.argon2_memory_cost;
+            config.argon2_time_cost = defaults.argon2_time_cost;
+            config.argon2_parallelism = defaults.argon2_parallelism;
+        }
+
+        config
+    }
+}
+
+/// Hashing parameters stored next to a password hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashParams {
+    pub policy_version: u32,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+/// A new password hash and the parameters it was made with
+#[derive(Debug, Clone)]
+pub struct HashedPassword {
+    pub hash: String,
+    pub params: HashParams,
+}
+
+/// Result of checking a password at login
+#[derive(Debug, Clone)]
+pub struct VerifyOutcome {
+    pub valid: bool,
+    /// Replacement hash when the stored one was made under an older policy;
+    /// the caller should store it
+    pub rehashed: Option<HashedPassword>,
+}
+
+/// Running totals for password verification time, across all managers
+static VERIFY_COUNT: AtomicU64 = AtomicU64::new(0);
+static VERIFY_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// Password verification latency since startup
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct VerifyLatency {
+    pub count: u64,
+    pub total_seconds: f64,
+}
+
+impl VerifyLatency {
+    pub fn average(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.total_seconds / self.count as f64)
+    }
+}
+
+pub fn verify_latency() -> VerifyLatency {
+    VerifyLatency {
+        count: VERIFY_COUNT.load(Ordering::Relaxed),
+        total_seconds: VERIFY_MICROS.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+    }
+}
+
+fn record_verify(started: Instant) {
+    VERIFY_COUNT.fetch_add(1, Ordering::Relaxed);
+    VERIFY_MICROS.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Legacy accounts still carry the bcrypt hashes of the original game
+fn is_bcrypt(hash: &str) -> bool {
+    hash.starts_with("$2a$") || hash.starts_with("$2b$") || hash.starts_with("$2y$")
+}
+
 /// Password strength levels
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum PasswordStrength {
@@ -96,11 +197,32 @@ impl PasswordManager {
         Self { config, argon2 }
     }
 
+    /// Parameters new hashes are made with
+    pub fn params(&self) -> HashParams {
+        HashParams {
+            policy_version: self.config.policy_version,
+            memory_kib: self.config.argon2_memory_cost,
+            iterations: self.config.argon2_time_cost,
+            parallelism: self.config.argon2_parallelism,
+        }
+    }
+
     /// Hash a password using Argon2id
     pub async fn hash_password(&self, password: &str) -> Result<String> {
         // Validate password
         self.validate_password(password)?;
+        self.hash_unchecked(password)
+    }
+
+    /// Hash a password and return the parameters to store next to it
+    pub async fn hash_password_versioned(&self, password: &str) -> Result<HashedPassword> {
+        let hash = self.hash_password(password).await?;
+        Ok(HashedPassword { hash, params: self.params() })
+    }
 
+    /// Hash without the strength rules, for re-hashing passwords that
+    /// predate them
+    fn hash_unchecked(&self, password: &str) -> Result<String> {
         // Generate a random salt
         let salt = SaltString::generate(&mut OsRng);
 
@@ -119,8 +241,13 @@ impl PasswordManager {
         let parsed_hash = PasswordHash::new(hash)
             .map_err(|e| anyhow!("Invalid password hash format: {}", e))?;
 
-        // Verify the password
-        match self.argon2.verify_password(password.as_bytes(), &parsed_hash) {
+        // Verify the password; the hash's own parameters are used, so hashes
+        // made under an older policy still verify
+        let started = Instant::now();
+        let result = self.argon2.verify_password(password.as_bytes(), &parsed_hash);
+        record_verify(started);
+
+        match result {
             Ok(_) => {
                 debug!("Password verified successfully");
                 Ok(true)
@@ -132,6 +259,40 @@ impl PasswordManager {
         }
     }
 
+    /// Verify a password at login. `policy_version` is the version stored
+    /// with the hash (0 for legacy bcrypt hashes); a valid password whose hash
+    /// is older than the current policy, or made with other parameters, comes
+    /// back with a replacement hash.
+    pub async fn verify_and_upgrade(&self, password: &str, hash: &str, policy_version: u32) -> Result<VerifyOutcome> {
+        let valid = if is_bcrypt(hash) {
+            let started = Instant::now();
+            let valid = he_core::security::BCrypt::default()
+                .verify_password(password, hash)
+                .map_err(|e| anyhow!("Invalid bcrypt hash: {}", e))?;
+            record_verify(started);
+            valid
+        } else {
+            self.verify_password_argon2id(password, hash).await?
+        };
+
+        if !valid {
+            return Ok(VerifyOutcome { valid, rehashed: None });
+        }
+
+        let outdated = is_bcrypt(hash) || policy_version < self.config.policy_version || self.needs_rehash(hash);
+        let rehashed = if outdated {
+            info!("Re-hashing password from policy v{} to v{}", policy_version, self.config.policy_version);
+            Some(HashedPassword {
+                hash: self.hash_unchecked(password)?,
+                params: self.params(),
+            })
+        } else {
+            None
+        };
+
+        Ok(VerifyOutcome { valid, rehashed })
+    }
+
     /// Legacy verify method (for compatibility)
     pub async fn verify_password(&self, password: &str, hash: &str) -> Result<bool> {
         self.verify_password_argon2id(password, hash).await
@@ -280,6 +441,86 @@ impl PasswordManager {
     }
 }
 
+/// Measured cost of one Argon2id parameter set
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BenchmarkResult {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub hash_time: Duration,
+}
+
+/// Smallest memory cost tried; the OWASP minimum for Argon2id
+pub const BENCH_MIN_MEMORY_KIB: u32 = 19 * 1024;
+/// Largest memory cost tried
+pub const BENCH_MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const BENCH_MAX_ITERATIONS: u32 = 10;
+
+/// Time one hash with the given parameters on this host
+pub fn benchmark_params(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<BenchmarkResult> {
+    let params = Params::new(memory_kib, iterations, parallelism, None)
+        .map_err(|e| anyhow!("Invalid Argon2 parameters: {}", e))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let salt = SaltString::generate(&mut OsRng);
+
+    let started = Instant::now();
+    argon2
+        .hash_password(b"password-benchmark", &salt)
+        .map_err(|e| anyhow!("Failed to hash password: {}", e))?;
+
+    Ok(BenchmarkResult {
+        memory_kib,
+        iterations,
+        parallelism,
+        hash_time: started.elapsed(),
+    })
+}
+
+/// Parameters suggested by [`suggest_params`] and the measurements behind them
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamSuggestion {
+    pub target: Duration,
+    pub suggested: BenchmarkResult,
+    pub measurements: Vec<BenchmarkResult>,
+}
+
+/// Find the strongest parameters that hash within `target` on this host.
+/// Memory is doubled first, since it is what makes GPU attacks expensive, then
+/// iterations are added. If even the minimum is slower than `target`, the
+/// minimum is suggested.
+pub fn suggest_params(target: Duration, parallelism: u32) -> Result<ParamSuggestion> {
+    let mut results = vec![benchmark_params(BENCH_MIN_MEMORY_KIB, 2, parallelism)?];
+    let mut best = results[0];
+
+    let mut memory_kib = BENCH_MIN_MEMORY_KIB * 2;
+    while memory_kib <= BENCH_MAX_MEMORY_KIB {
+        let result = benchmark_params(memory_kib, best.iterations, parallelism)?;
+        results.push(result);
+        if result.hash_time > target {
+            break;
+        }
+        best = result;
+        memory_kib *= 2;
+    }
+
+    let mut iterations = best.iterations + 1;
+    while iterations <= BENCH_MAX_ITERATIONS {
+        let result = benchmark_params(best.memory_kib, iterations, parallelism)?;
+        results.push(result);
+        if result.hash_time > target {
+            break;
+        }
+        best = result;
+        iterations += 1;
+    }
+
+    Ok(ParamSuggestion {
+        target,
+        suggested: best,
+        measurements: results,
+    })
+}
+
 /// Secure password wrapper that zeroizes on drop
 pub struct SecurePassword {
     inner: String,
@@ -347,6 +588,40 @@ mod tests {
         assert!(manager.validate_password(&password).is_ok());
     }
 
+    #[tokio::test]
+    async fn test_verify_upgrades_outdated_policy() {
+        let old = PasswordManager::new(PasswordConfig {
+            argon2_memory_cost: 8192,
+            argon2_time_cost: 1,
+            argon2_parallelism: 1,
+            ..PasswordConfig::default()
+        });
+        let current = PasswordManager::new(PasswordConfig {
+            argon2_memory_cost: 16384,
+            argon2_time_cost: 2,
+            argon2_parallelism: 1,
+            policy_version: 2,
+            ..PasswordConfig::default()
+        });
+
+        let stored = old.hash_password_versioned("SecurePassword123").await.unwrap();
+        assert_eq!(stored.params.policy_version, 1);
+
+        let outcome = current.verify_and_upgrade("WrongPassword1", &stored.hash, 1).await.unwrap();
+        assert!(!outcome.valid);
+        assert!(outcome.rehashed.is_none());
+
+        let outcome = current.verify_and_upgrade("SecurePassword123", &stored.hash, 1).await.unwrap();
+        assert!(outcome.valid);
+        let rehashed = outcome.rehashed.expect("outdated hash is replaced");
+        assert_eq!(rehashed.params, current.params());
+
+        let outcome = current.verify_and_upgrade("SecurePassword123", &rehashed.hash, 2).await.unwrap();
+        assert!(outcome.valid);
+        assert!(outcome.rehashed.is_none());
+        assert!(verify_latency().count >= 3);
+    }
+
     #[test]
     fn test_needs_rehash() {
         let manager = PasswordManager::new(PasswordConfig::default());
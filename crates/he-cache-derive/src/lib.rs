@@ -0,0 +1,158 @@
+//! `#[derive(CacheKey)]` for HackerExperience cache keys
+//!
+//! Generates a `he_cache::keys::CacheKey` impl whose key is built from the
+//! struct's fields in declaration order:
+//!
+//! ```ignore
+//! #[derive(CacheKey)]
+//! #[cache_key(prefix = "user", name = "profile", version = 1)]
+//! pub struct UserProfileKey {
+//!     pub user_id: Uuid,
+//! }
+//!
+//! // "user:<user_id>:profile:v1"
+//! UserProfileKey { user_id }.cache_key();
+//! ```
+//!
+//! `name` is optional and `version` defaults to 1. Fields marked
+//! `#[cache_key(skip)]` are not part of the key.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitInt, LitStr};
+
+#[proc_macro_derive(CacheKey, attributes(cache_key))]
+pub fn derive_cache_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+struct KeyAttrs {
+    prefix: String,
+    name: Option<String>,
+    version: u32,
+}
+
+fn parse_struct_attrs(input: &DeriveInput) -> syn::Result<KeyAttrs> {
+    let mut prefix = None;
+    let mut name = None;
+    let mut version = 1u32;
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("cache_key")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("prefix") {
+                prefix = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("version") {
+                version = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else {
+                return Err(meta.error("expected `prefix`, `name` or `version`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let prefix = prefix.ok_or_else(|| {
+        syn::Error::new(Span::call_site(), "missing #[cache_key(prefix = \"...\")]")
+    })?;
+
+    for part in std::iter::once(&prefix).chain(name.as_ref()) {
+        if part.is_empty() || part.contains('*') {
+            return Err(syn::Error::new(Span::call_site(), "cache key prefix/name must be non-empty and contain no `*`"));
+        }
+    }
+
+    if version == 0 {
+        return Err(syn::Error::new(Span::call_site(), "cache key versions start at 1"));
+    }
+
+    Ok(KeyAttrs { prefix, name, version })
+}
+
+fn is_skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("cache_key")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let attrs = parse_struct_attrs(&input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(&input.ident, "CacheKey can only be derived for structs"));
+    };
+
+    let mut accessors = Vec::new();
+    let mut field_names = Vec::new();
+    match &data.fields {
+        Fields::Named(fields) => {
+            for field in &fields.named {
+                if is_skipped(field)? {
+                    continue;
+                }
+                let field_ident = field.ident.as_ref().expect("named field");
+                field_names.push(field_ident.to_string());
+                accessors.push(quote!(&self.#field_ident));
+            }
+        }
+        Fields::Unnamed(fields) => {
+            for (i, field) in fields.unnamed.iter().enumerate() {
+                if is_skipped(field)? {
+                    continue;
+                }
+                let index = syn::Index::from(i);
+                field_names.push(i.to_string());
+                accessors.push(quote!(&self.#index));
+            }
+        }
+        Fields::Unit => {}
+    }
+
+    let type_name = ident.to_string();
+    let prefix = &attrs.prefix;
+    let version = attrs.version;
+    let name = match &attrs.name {
+        Some(name) => quote!(::core::option::Option::Some(#name)),
+        None => quote!(::core::option::Option::None),
+    };
+
+    // "{prefix}:{field}:...:{name}:v{version}"
+    let mut template = String::from("{}");
+    for _ in &accessors {
+        template.push_str(":{}");
+    }
+    if let Some(name) = &attrs.name {
+        template.push(':');
+        template.push_str(&name.replace('{', "{{").replace('}', "}}"));
+    }
+    template.push_str(":v{}");
+
+    Ok(quote! {
+        impl #impl_generics ::he_cache::keys::CacheKey for #ident #ty_generics #where_clause {
+            const DESCRIPTOR: ::he_cache::keys::KeyDescriptor = ::he_cache::keys::KeyDescriptor {
+                type_name: #type_name,
+                prefix: #prefix,
+                name: #name,
+                version: #version,
+                fields: &[#(#field_names),*],
+            };
+
+            fn cache_key(&self) -> ::std::string::String {
+                ::std::format!(#template, #prefix, #(#accessors,)* #version)
+            }
+        }
+    })
+}
@@ -0,0 +1,370 @@
+//! Versioned cache keys
+//!
+//! Key types derive [`CacheKey`] (see `he-cache-derive`) instead of
+//! hand-writing format strings. Every key type is registered in a
+//! [`KeyRegistry`] at startup so two types that would produce overlapping keys
+//! are caught before they can read each other's data.
+//!
+//! Bumping `version` in a key's `#[cache_key(...)]` attribute changes every key
+//! it produces. [`CacheManager::migrate_key_versions`](crate::CacheManager::migrate_key_versions)
+//! compares the registry against the versions recorded in Redis and bulk-deletes
+//! entries written under the old version.
+
+use std::collections::HashMap;
+use std::fmt;
+use uuid::Uuid;
+
+pub use he_cache_derive::CacheKey;
+
+/// Redis hash holding the last migrated version of each key type
+pub const KEY_VERSIONS_HASH: &str = "cache:key_versions";
+
+/// Static description of a key type, generated by the derive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyDescriptor {
+    pub type_name: &'static str,
+    pub prefix: &'static str,
+    pub name: Option<&'static str>,
+    pub version: u32,
+    pub fields: &'static [&'static str],
+}
+
+impl KeyDescriptor {
+    /// Stable identity of the key shape, independent of version
+    pub fn id(&self) -> String {
+        match self.name {
+            Some(name) => format!("{}/{}/{}", self.prefix, name, self.fields.len()),
+            None => format!("{}/{}", self.prefix, self.fields.len()),
+        }
+    }
+
+    /// Redis glob matching every key of this type written under `version`
+    pub fn pattern(&self, version: u32) -> String {
+        let mut pattern = String::from(self.prefix);
+        for _ in self.fields {
+            pattern.push_str(":*");
+        }
+        if let Some(name) = self.name {
+            pattern.push(':');
+            pattern.push_str(name);
+        }
+        pattern.push_str(&format!(":v{}", version));
+        pattern
+    }
+
+    /// Metrics label for every key of this type, e.g. `user:profile`
+    pub fn keyspace(&self) -> String {
+        match self.name {
+            Some(name) => format!("{}:{}", self.prefix, name),
+            None => self.prefix.to_string(),
+        }
+    }
+
+    /// Whether `key` could have been produced by this type, at any version
+    pub fn matches(&self, key: &str) -> bool {
+        let Some(rest) = key.strip_prefix(self.prefix).and_then(|rest| rest.strip_prefix(':')) else {
+            return false;
+        };
+        let (rest, version) = rest.rsplit_once(':').unwrap_or(("", rest));
+        if !version.strip_prefix('v').is_some_and(|v| !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit())) {
+            return false;
+        }
+        let fields = match self.name {
+            Some(name) if rest == name => "",
+            Some(name) => match rest.strip_suffix(name).and_then(|rest| rest.strip_suffix(':')) {
+                Some(fields) => fields,
+                None => return false,
+            },
+            None => rest,
+        };
+        // Field values may contain `:` themselves (IPv6 addresses), so there
+        // are at least as many segments as fields
+        match self.fields.len() {
+            0 => fields.is_empty(),
+            n => !fields.is_empty() && fields.split(':').count() >= n,
+        }
+    }
+
+    fn collides_with(&self, other: &KeyDescriptor) -> bool {
+        self.type_name != other.type_name && self.id() == other.id()
+    }
+}
+
+/// A struct that renders to a cache key
+pub trait CacheKey {
+    const DESCRIPTOR: KeyDescriptor;
+
+    fn cache_key(&self) -> String;
+
+    /// Glob matching every key of this type at the current version
+    fn pattern() -> String {
+        Self::DESCRIPTOR.pattern(Self::DESCRIPTOR.version)
+    }
+}
+
+/// Two key types that would produce the same keys
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("cache key {existing} collides with {incoming} (both {id})")]
+pub struct KeyCollision {
+    pub existing: &'static str,
+    pub incoming: &'static str,
+    pub id: String,
+}
+
+/// A key type whose version changed since the last migration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    pub descriptor: KeyDescriptor,
+    pub from: u32,
+    pub to: u32,
+}
+
+impl VersionChange {
+    /// Glob for the entries that are now stale
+    pub fn stale_pattern(&self) -> String {
+        self.descriptor.pattern(self.from)
+    }
+}
+
+/// Registry of every key type in use
+#[derive(Debug, Default)]
+pub struct KeyRegistry {
+    keys: HashMap<String, KeyDescriptor>,
+}
+
+impl KeyRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with all of he-cache's built-in key types
+    pub fn with_builtin_keys() -> Result<Self, KeyCollision> {
+        let mut registry = Self::new();
+        registry.register::<UserProfileKey>()?;
+        registry.register::<UserStatsKey>()?;
+        registry.register::<UserProcessesKey>()?;
+        registry.register::<ServerInfoKey>()?;
+        registry.register::<ServerFilesKey>()?;
+        registry.register::<LeaderboardKey>()?;
+        registry.register::<ClanInfoKey>()?;
+        registry.register::<PvpMatchKey>()?;
+        registry.register::<MarketListingsKey>()?;
+        registry.register::<ChatMessagesKey>()?;
+        registry.register::<SoftwareCatalogKey>()?;
+        Ok(registry)
+    }
+
+    /// Register a key type, failing if another type produces the same keys
+    pub fn register<K: CacheKey>(&mut self) -> Result<(), KeyCollision> {
+        self.register_descriptor(K::DESCRIPTOR)
+    }
+
+    pub fn register_descriptor(&mut self, descriptor: KeyDescriptor) -> Result<(), KeyCollision> {
+        let id = descriptor.id();
+        if let Some(existing) = self.keys.get(&id) {
+            if existing.collides_with(&descriptor) {
+                return Err(KeyCollision {
+                    existing: existing.type_name,
+                    incoming: descriptor.type_name,
+                    id,
+                });
+            }
+        }
+        self.keys.insert(id, descriptor);
+        Ok(())
+    }
+
+    pub fn descriptors(&self) -> impl Iterator<Item = &KeyDescriptor> {
+        self.keys.values()
+    }
+
+    /// The key type `key` belongs to. When several could have produced it,
+    /// the one with the longest prefix and a name wins.
+    pub fn descriptor_of(&self, key: &str) -> Option<&KeyDescriptor> {
+        self.keys
+            .values()
+            .filter(|d| d.matches(key))
+            .max_by_key(|d| (d.prefix.len(), d.name.is_some(), d.type_name))
+    }
+
+    /// Versions to record after a migration, keyed by [`KeyDescriptor::id`]
+    pub fn versions(&self) -> HashMap<String, u32> {
+        self.keys.iter().map(|(id, d)| (id.clone(), d.version)).collect()
+    }
+
+    /// Compare against previously recorded versions.
+    ///
+    /// Key types seen for the first time are not reported; there is nothing
+    /// stale to delete for them.
+    pub fn version_changes(&self, recorded: &HashMap<String, u32>) -> Vec<VersionChange> {
+        let mut changes: Vec<VersionChange> = self
+            .keys
+            .iter()
+            .filter_map(|(id, descriptor)| {
+                let from = *recorded.get(id)?;
+                (from != descriptor.version).then_some(VersionChange {
+                    descriptor: *descriptor,
+                    from,
+                    to: descriptor.version,
+                })
+            })
+            .collect();
+        changes.sort_by(|a, b| a.descriptor.type_name.cmp(b.descriptor.type_name));
+        changes
+    }
+}
+
+impl fmt::Display for KeyDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} v{})", self.type_name, self.id(), self.version)
+    }
+}
+
+// Built-in keys
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "user", name = "profile", version = 1)]
+pub struct UserProfileKey {
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "user", name = "stats", version = 1)]
+pub struct UserStatsKey {
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "user", name = "processes", version = 1)]
+pub struct UserProcessesKey {
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "server", name = "info", version = 1)]
+pub struct ServerInfoKey<'a> {
+    pub ip: &'a str,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "server", name = "files", version = 1)]
+pub struct ServerFilesKey<'a> {
+    pub ip: &'a str,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "leaderboard", version = 1)]
+pub struct LeaderboardKey<'a> {
+    pub board_type: &'a str,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "clan", name = "info", version = 1)]
+pub struct ClanInfoKey {
+    pub clan_id: Uuid,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "pvp:match", version = 1)]
+pub struct PvpMatchKey {
+    pub match_id: Uuid,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "market:listings", version = 1)]
+pub struct MarketListingsKey<'a> {
+    pub category: &'a str,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "chat", name = "messages", version = 1)]
+pub struct ChatMessagesKey<'a> {
+    pub room_id: &'a str,
+}
+
+#[derive(Debug, Clone, CacheKey)]
+#[cache_key(prefix = "software", name = "catalog", version = 1)]
+pub struct SoftwareCatalogKey;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(CacheKey)]
+    #[cache_key(prefix = "user", name = "profile", version = 2)]
+    struct ShadowProfileKey {
+        user_id: Uuid,
+    }
+
+    #[derive(CacheKey)]
+    #[cache_key(prefix = "session", version = 3)]
+    struct SessionKey {
+        token: String,
+        #[cache_key(skip)]
+        _debug_label: &'static str,
+    }
+
+    #[test]
+    fn test_derived_key_format() {
+        let user_id = Uuid::new_v4();
+        assert_eq!(UserProfileKey { user_id }.cache_key(), format!("user:{}:profile:v1", user_id));
+        assert_eq!(LeaderboardKey { board_type: "level" }.cache_key(), "leaderboard:level:v1");
+        assert_eq!(UserProfileKey::pattern(), "user:*:profile:v1");
+    }
+
+    #[test]
+    fn test_skipped_fields_are_not_in_key() {
+        let key = SessionKey { token: "abc".to_string(), _debug_label: "ignored" };
+        assert_eq!(key.cache_key(), "session:abc:v3");
+        assert_eq!(SessionKey::DESCRIPTOR.fields, &["token"]);
+    }
+
+    #[test]
+    fn test_builtin_keys_do_not_collide() {
+        let registry = KeyRegistry::with_builtin_keys().expect("built-in keys collide");
+        assert_eq!(registry.descriptors().count(), 11);
+    }
+
+    #[test]
+    fn test_collision_detected() {
+        let mut registry = KeyRegistry::with_builtin_keys().unwrap();
+        let err = registry.register::<ShadowProfileKey>().unwrap_err();
+        assert_eq!(err.existing, "UserProfileKey");
+        assert_eq!(err.incoming, "ShadowProfileKey");
+
+        // Re-registering the same type is harmless
+        assert!(registry.register::<UserProfileKey>().is_ok());
+    }
+
+    #[test]
+    fn test_keyspaces() {
+        let registry = KeyRegistry::with_builtin_keys().unwrap();
+        let keyspace = |key: &str| registry.descriptor_of(key).map(|d| d.keyspace());
+        let user_id = Uuid::new_v4();
+
+        assert_eq!(keyspace(&UserStatsKey { user_id }.cache_key()).as_deref(), Some("user:stats"));
+        assert_eq!(keyspace(&ServerInfoKey { ip: "::1" }.cache_key()).as_deref(), Some("server:info"));
+        assert_eq!(keyspace(&PvpMatchKey { match_id: user_id }.cache_key()).as_deref(), Some("pvp:match"));
+        assert_eq!(keyspace(&SoftwareCatalogKey.cache_key()).as_deref(), Some("software:catalog"));
+        assert_eq!(keyspace("leaderboard:level:v7").as_deref(), Some("leaderboard"));
+        assert_eq!(keyspace("leaderboard:level"), None);
+        assert_eq!(keyspace("session:abc:v1"), None);
+    }
+
+    #[test]
+    fn test_version_changes() {
+        let registry = KeyRegistry::with_builtin_keys().unwrap();
+        let mut recorded = registry.versions();
+        assert!(registry.version_changes(&recorded).is_empty());
+
+        recorded.insert(UserStatsKey::DESCRIPTOR.id(), 0);
+        let changes = registry.version_changes(&recorded);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].to, 1);
+        assert_eq!(changes[0].stale_pattern(), "user:*:stats:v0");
+
+        // Never-recorded key types have nothing stale
+        recorded.clear();
+        assert!(registry.version_changes(&recorded).is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+//! Per-keyspace cache statistics and TTLs
+//!
+//! A keyspace is every key of one [`CacheKey`](crate::keys::CacheKey) type,
+//! labelled by [`KeyDescriptor::keyspace`] (`user:profile`, `leaderboard`).
+//! Raw keys passed to the [`CacheManager`](crate::CacheManager) are matched
+//! against the [`KeyRegistry`]; keys of no registered type count under
+//! [`OTHER`], so metric labels stay bounded whatever callers pass.
+//!
+//! Each keyspace has its own default TTL, and its hits, misses, writes and
+//! invalidations are counted so [`Keyspaces::worst`] can point at the
+//! keyspaces that miss most, with a suggested TTL for each.
+
+use crate::keys::{KeyDescriptor, KeyRegistry};
+use prometheus::{register_gauge_vec, GaugeVec};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Label for keys of no registered type
+pub const OTHER: &str = "other";
+
+/// TTL used when neither the caller nor the environment sets one
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+/// Longest TTL ever suggested
+const MAX_SUGGESTED_TTL: Duration = Duration::from_secs(24 * 3600);
+/// Below this hit ratio a keyspace is worth looking at
+const LOW_HIT_RATIO: f64 = 0.5;
+
+lazy_static::lazy_static! {
+    static ref CACHE_HIT_RATIO: GaugeVec = register_gauge_vec!(
+        "cache_hit_ratio",
+        "Share of cache reads that hit, per keyspace, since startup",
+        &["keyspace"]
+    ).unwrap();
+}
+
+/// Default TTL of each keyspace
+#[derive(Debug, Clone)]
+pub struct KeyspaceTtls {
+    pub default: Duration,
+    pub overrides: HashMap<String, Duration>,
+}
+
+impl Default for KeyspaceTtls {
+    fn default() -> Self {
+        Self { default: DEFAULT_TTL, overrides: HashMap::new() }
+    }
+}
+
+impl KeyspaceTtls {
+    /// `CACHE_TTL_SECS` for the default (5 minutes when unset), and
+    /// `CACHE_TTL_SECS_<KEYSPACE>` for each keyspace in `registry`, e.g.
+    /// `CACHE_TTL_SECS_USER_PROFILE` for `user:profile`
+    pub fn from_env(registry: &KeyRegistry) -> Self {
+        let secs = |name: &str| std::env::var(name).ok().and_then(|s| s.parse().ok()).map(Duration::from_secs);
+        let overrides = registry
+            .descriptors()
+            .filter_map(|d| {
+                let keyspace = d.keyspace();
+                secs(&format!("CACHE_TTL_SECS_{}", env_suffix(&keyspace))).map(|ttl| (keyspace, ttl))
+            })
+            .collect();
+        Self { default: secs("CACHE_TTL_SECS").unwrap_or(DEFAULT_TTL), overrides }
+    }
+
+    pub fn ttl(&self, keyspace: &str) -> Duration {
+        self.overrides.get(keyspace).copied().unwrap_or(self.default)
+    }
+}
+
+fn env_suffix(keyspace: &str) -> String {
+    keyspace.to_ascii_uppercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    hits: u64,
+    misses: u64,
+    writes: u64,
+    invalidations: u64,
+}
+
+/// What a keyspace's TTL should become
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TtlSuggestion {
+    /// Entries expire before they are read again
+    Raise { ttl_secs: u64 },
+    /// Writes drop entries before they expire; a longer TTL would not help
+    Keep,
+}
+
+/// Counts for one keyspace since startup or the last reset
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyspaceStats {
+    pub keyspace: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub writes: u64,
+    pub invalidations: u64,
+    pub hit_ratio: f64,
+    pub ttl_secs: u64,
+    /// Set for keyspaces that miss more than they hit
+    pub suggestion: Option<TtlSuggestion>,
+}
+
+/// Suggested TTL for a keyspace reading at `hit_ratio`, `None` when it is
+/// doing fine
+pub fn suggest(hit_ratio: f64, writes: u64, invalidations: u64, ttl: Duration) -> Option<TtlSuggestion> {
+    if hit_ratio >= LOW_HIT_RATIO {
+        return None;
+    }
+    if invalidations.saturating_mul(2) >= writes && writes > 0 {
+        return Some(TtlSuggestion::Keep);
+    }
+    let raised = (ttl * 2).clamp(Duration::from_secs(60), MAX_SUGGESTED_TTL);
+    (raised > ttl).then_some(TtlSuggestion::Raise { ttl_secs: raised.as_secs() })
+}
+
+/// Keyspace classification, TTLs and counts for one cache
+pub struct Keyspaces {
+    registry: KeyRegistry,
+    ttls: KeyspaceTtls,
+    counts: Mutex<HashMap<String, Counts>>,
+}
+
+impl Keyspaces {
+    pub fn new(registry: KeyRegistry, ttls: KeyspaceTtls) -> Self {
+        Self { registry, ttls, counts: Mutex::new(HashMap::new()) }
+    }
+
+    /// The built-in key types, TTLs from the environment
+    pub fn from_env() -> Self {
+        let registry = KeyRegistry::with_builtin_keys().expect("built-in cache keys collide");
+        let ttls = KeyspaceTtls::from_env(&registry);
+        Self::new(registry, ttls)
+    }
+
+    /// Keyspace of `key`, [`OTHER`] when no registered type produces it
+    pub fn classify(&self, key: &str) -> String {
+        self.registry.descriptor_of(key).map_or_else(|| OTHER.to_string(), KeyDescriptor::keyspace)
+    }
+
+    /// Default TTL for entries of `keyspace`
+    pub fn ttl(&self, keyspace: &str) -> Duration {
+        self.ttls.ttl(keyspace)
+    }
+
+    pub fn record_read(&self, keyspace: &str, hit: bool) {
+        let mut counts = self.counts.lock().unwrap();
+        let c = counts.entry(keyspace.to_string()).or_default();
+        if hit {
+            c.hits += 1;
+        } else {
+            c.misses += 1;
+        }
+        CACHE_HIT_RATIO.with_label_values(&[keyspace]).set(c.hits as f64 / (c.hits + c.misses) as f64);
+    }
+
+    pub fn record_write(&self, keyspace: &str) {
+        self.counts.lock().unwrap().entry(keyspace.to_string()).or_default().writes += 1;
+    }
+
+    pub fn record_invalidation(&self, keyspace: &str) {
+        self.counts.lock().unwrap().entry(keyspace.to_string()).or_default().invalidations += 1;
+    }
+
+    /// Every keyspace read at least once, worst hit ratio first
+    pub fn stats(&self) -> Vec<KeyspaceStats> {
+        let counts = self.counts.lock().unwrap().clone();
+        let mut stats: Vec<KeyspaceStats> = counts
+            .into_iter()
+            .filter(|(_, c)| c.hits + c.misses > 0)
+            .map(|(keyspace, c)| {
+                let hit_ratio = c.hits as f64 / (c.hits + c.misses) as f64;
+                let ttl = self.ttl(&keyspace);
+                KeyspaceStats {
+                    suggestion: suggest(hit_ratio, c.writes, c.invalidations, ttl),
+                    keyspace,
+                    hits: c.hits,
+                    misses: c.misses,
+                    writes: c.writes,
+                    invalidations: c.invalidations,
+                    hit_ratio,
+                    ttl_secs: ttl.as_secs(),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.hit_ratio.total_cmp(&b.hit_ratio).then_with(|| b.misses.cmp(&a.misses)));
+        stats
+    }
+
+    /// The `n` worst keyspaces read at least `min_reads` times, so a
+    /// keyspace read twice does not top the list
+    pub fn worst(&self, n: usize, min_reads: u64) -> Vec<KeyspaceStats> {
+        self.stats().into_iter().filter(|s| s.hits + s.misses >= min_reads).take(n).collect()
+    }
+
+    /// Forget all counts, e.g. after changing a TTL
+    pub fn reset(&self) {
+        self.counts.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyspaces() -> Keyspaces {
+        let registry = KeyRegistry::with_builtin_keys().unwrap();
+        let mut ttls = KeyspaceTtls::default();
+        ttls.overrides.insert("leaderboard".to_string(), Duration::from_secs(60));
+        Keyspaces::new(registry, ttls)
+    }
+
+    #[test]
+    fn test_classify_and_ttl() {
+        let keyspaces = keyspaces();
+        assert_eq!(keyspaces.classify("leaderboard:level:v1"), "leaderboard");
+        assert_eq!(keyspaces.classify("some:raw:key"), OTHER);
+        assert_eq!(keyspaces.ttl("leaderboard"), Duration::from_secs(60));
+        assert_eq!(keyspaces.ttl("user:profile"), DEFAULT_TTL);
+        assert_eq!(env_suffix("pvp:match"), "PVP_MATCH");
+    }
+
+    #[test]
+    fn test_worst_keyspaces_with_suggestions() {
+        let keyspaces = keyspaces();
+        for hit in [false, false, false, true] {
+            keyspaces.record_read("leaderboard", hit);
+        }
+        keyspaces.record_write("leaderboard");
+        for hit in [false, false, true] {
+            keyspaces.record_read("user:stats", hit);
+        }
+        keyspaces.record_write("user:stats");
+        keyspaces.record_invalidation("user:stats");
+        for _ in 0..4 {
+            keyspaces.record_read("software:catalog", true);
+        }
+
+        let worst = keyspaces.worst(10, 3);
+        assert_eq!(worst.len(), 3);
+        assert_eq!(worst[0].keyspace, "leaderboard");
+        assert_eq!(worst[0].suggestion, Some(TtlSuggestion::Raise { ttl_secs: 120 }));
+        assert_eq!(worst[1].suggestion, Some(TtlSuggestion::Keep));
+        assert_eq!(worst[2].suggestion, None);
+        assert_eq!(keyspaces.worst(10, 4).len(), 2);
+
+        keyspaces.reset();
+        assert!(keyspaces.stats().is_empty());
+    }
+}
@@ -1,24 +1,45 @@
 //! High-performance caching layer for HackerExperience
 
+// Lets `#[derive(CacheKey)]` refer to `::he_cache` from inside this crate
+extern crate self as he_cache;
+
+pub mod keys;
+pub mod keyspace;
+pub mod schema;
+pub mod versions;
+pub mod warm;
+
 use bb8_redis::{bb8, RedisConnectionManager};
+use he_degradation::{Degradation, Dependency, DependencyPolicy, FailureMode, Unavailable};
 use redis::{AsyncCommands, RedisError};
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::time::Duration;
-use tracing::{debug, warn};
+use std::collections::HashMap;
+use tracing::{debug, info, warn};
 use uuid::Uuid;
-use prometheus::{IntCounter, Histogram, register_int_counter, register_histogram};
+use keys::{CacheKey, KeyRegistry, KEY_VERSIONS_HASH};
+use keyspace::Keyspaces;
+use schema::SchemaRegistry;
+use versions::{ContentVersion, VersionStore, CONTENT_MODIFIED_HASH, CONTENT_VERSIONS_HASH};
+use prometheus::{IntCounterVec, Histogram, register_int_counter_vec, register_histogram};
+use std::future::Future;
+use std::sync::Arc;
+
+pub use warm::{CacheWarmer, WarmGroup, WarmManifest, WarmProgress, WarmSource};
 
 pub type RedisPool = bb8::Pool<RedisConnectionManager>;
 
 lazy_static::lazy_static! {
-    static ref CACHE_HITS: IntCounter = register_int_counter!(
+    static ref CACHE_HITS: IntCounterVec = register_int_counter_vec!(
         "cache_hits_total",
-        "Total number of cache hits"
+        "Total number of cache hits",
+        &["keyspace"]
     ).unwrap();
 
-    static ref CACHE_MISSES: IntCounter = register_int_counter!(
+    static ref CACHE_MISSES: IntCounterVec = register_int_counter_vec!(
         "cache_misses_total",
-        "Total number of cache misses"
+        "Total number of cache misses",
+        &["keyspace"]
     ).unwrap();
 
     static ref CACHE_LATENCY: Histogram = register_histogram!(
@@ -30,7 +51,9 @@ lazy_static::lazy_static! {
 /// Main cache manager
 pub struct CacheManager {
     redis_pool: RedisPool,
-    default_ttl: Duration,
+    keyspaces: Arc<Keyspaces>,
+    schemas: Arc<SchemaRegistry>,
+    dependency: Option<Arc<Dependency>>,
 }
 
 impl CacheManager {
@@ -45,33 +68,100 @@ impl CacheManager {
 
         Ok(Self {
             redis_pool: pool,
-            default_ttl: Duration::from_secs(300), // 5 minutes default
+            keyspaces: Arc::new(Keyspaces::from_env()),
+            schemas: Arc::new(SchemaRegistry::default()),
+            dependency: None,
         })
     }
 
-    /// Get from cache
+    /// Guard Redis as the fail-open `redis` dependency: while it is degraded
+    /// reads miss, writes are dropped and content versions are
+    /// [`CacheError::Unavailable`]. `DEGRADE_REDIS=closed` fails every call
+    /// instead.
+    pub fn guarded(mut self, degradation: &Degradation) -> Self {
+        let pool = self.redis_pool.clone();
+        let dependency = degradation.register("redis", DependencyPolicy::fail_open(), move || {
+            let pool = pool.clone();
+            async move {
+                let mut conn = pool.get().await.map_err(|e| e.to_string())?;
+                let _: String = redis::cmd("PING").query_async(&mut *conn).await.map_err(|e| e.to_string())?;
+                Ok(())
+            }
+        });
+        self.dependency = Some(dependency);
+        self
+    }
+
+    /// Run a Redis operation under the degradation policy, answering
+    /// `fallback` when fail-open Redis is down. Only Redis and pool errors
+    /// count against the breaker.
+    async fn guard<T>(&self, fallback: T, op: impl Future<Output = Result<T, CacheError>>) -> Result<T, CacheError> {
+        let Some(dependency) = &self.dependency else {
+            return op.await;
+        };
+        if !dependency.admit()? {
+            return Ok(fallback);
+        }
+        match op.await {
+            Err(e @ (CacheError::Redis(_) | CacheError::Pool(_))) => {
+                dependency.record_failure(&e);
+                match dependency.mode() {
+                    FailureMode::Open => {
+                        warn!("Redis failed, continuing without the cache: {}", e);
+                        Ok(fallback)
+                    }
+                    FailureMode::Closed => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+            Ok(value) => {
+                dependency.record_success();
+                Ok(value)
+            }
+        }
+    }
+
+    /// Per-keyspace TTLs and hit counts
+    pub fn keyspaces(&self) -> Arc<Keyspaces> {
+        self.keyspaces.clone()
+    }
+
+    /// Payload schema of each keyspace; register versions and upcasters at
+    /// startup
+    pub fn schemas(&self) -> Arc<SchemaRegistry> {
+        self.schemas.clone()
+    }
+
+    /// Get from cache. Entries whose payload no longer matches its keyspace's
+    /// schema are misses.
     pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, CacheError> {
         let timer = CACHE_LATENCY.start_timer();
-        let mut conn = self.redis_pool.get().await?;
-
-        let result: Option<String> = conn.get(key).await?;
+        let result: Option<String> = self
+            .guard(None, async {
+                let mut conn = self.redis_pool.get().await?;
+                Ok(conn.get(key).await?)
+            })
+            .await?;
         timer.observe_duration();
 
-        match result {
-            Some(data) => {
-                CACHE_HITS.inc();
+        let keyspace = self.keyspaces.classify(key);
+        let value = result.and_then(|data| self.schemas.open(&keyspace, &data));
+        self.keyspaces.record_read(&keyspace, value.is_some());
+        match value {
+            Some(value) => {
+                CACHE_HITS.with_label_values(&[&keyspace]).inc();
                 debug!("Cache hit for key: {}", key);
-                Ok(Some(serde_json::from_str(&data)?))
+                Ok(Some(value))
             }
             None => {
-                CACHE_MISSES.inc();
+                CACHE_MISSES.with_label_values(&[&keyspace]).inc();
                 debug!("Cache miss for key: {}", key);
                 Ok(None)
             }
         }
     }
 
-    /// Set in cache with TTL
+    /// Set in cache with TTL, the keyspace's default when `None`
     pub async fn set<T: Serialize>(
         &self,
         key: &str,
@@ -79,12 +169,17 @@ impl CacheManager {
         ttl: Option<Duration>,
     ) -> Result<(), CacheError> {
         let timer = CACHE_LATENCY.start_timer();
-        let mut conn = self.redis_pool.get().await?;
-
-        let ttl = ttl.unwrap_or(self.default_ttl);
-        let data = serde_json::to_string(value)?;
-
-        conn.set_ex(key, data, ttl.as_secs() as u64).await?;
+        let keyspace = self.keyspaces.classify(key);
+        let ttl = ttl.unwrap_or_else(|| self.keyspaces.ttl(&keyspace));
+        self.keyspaces.record_write(&keyspace);
+        let data = self.schemas.seal(&keyspace, value, ttl)?;
+
+        self.guard((), async {
+            let mut conn = self.redis_pool.get().await?;
+            let _: () = conn.set_ex(key, data, ttl.as_secs() as u64).await?;
+            Ok(())
+        })
+        .await?;
         timer.observe_duration();
 
         debug!("Cached key: {} with TTL: {:?}", key, ttl);
@@ -93,25 +188,41 @@ impl CacheManager {
 
     /// Delete from cache
     pub async fn delete(&self, key: &str) -> Result<(), CacheError> {
-        let mut conn = self.redis_pool.get().await?;
-        conn.del(key).await?;
+        self.guard((), async {
+            let mut conn = self.redis_pool.get().await?;
+            let _: () = conn.del(key).await?;
+            Ok(())
+        })
+        .await?;
+        self.keyspaces.record_invalidation(&self.keyspaces.classify(key));
         debug!("Deleted cache key: {}", key);
         Ok(())
     }
 
     /// Delete multiple keys by pattern
     pub async fn delete_pattern(&self, pattern: &str) -> Result<u64, CacheError> {
-        let mut conn = self.redis_pool.get().await?;
-        let keys: Vec<String> = conn.keys(pattern).await?;
+        self.guard(0, async {
+            let mut conn = self.redis_pool.get().await?;
+            let keys: Vec<String> = conn.keys(pattern).await?;
 
-        if keys.is_empty() {
-            return Ok(0);
-        }
+            if keys.is_empty() {
+                return Ok(0);
+            }
 
-        let count = keys.len() as u64;
-        conn.del(keys).await?;
-        debug!("Deleted {} keys matching pattern: {}", count, pattern);
-        Ok(count)
+            let count = keys.len() as u64;
+            let _: () = conn.del(keys).await?;
+            debug!("Deleted {} keys matching pattern: {}", count, pattern);
+            Ok(count)
+        })
+        .await
+    }
+
+    /// Drop a cached entry and bump its content version, so HTTP clients
+    /// revalidating against it get the new data. Call after the change is
+    /// committed.
+    pub async fn invalidate(&self, key: &str) -> Result<ContentVersion, CacheError> {
+        self.delete(key).await?;
+        self.bump(key).await
     }
 
     /// Invalidate user cache
@@ -127,69 +238,145 @@ impl CacheManager {
         self.delete_pattern(&pattern).await?;
         Ok(())
     }
+
+    /// Bulk-invalidate entries of key types whose version was bumped.
+    ///
+    /// Run at startup after building the [`KeyRegistry`]. Returns the number of
+    /// deleted entries.
+    pub async fn migrate_key_versions(&self, registry: &KeyRegistry) -> Result<u64, CacheError> {
+        let mut conn = self.redis_pool.get().await?;
+        let recorded: HashMap<String, u32> = conn.hgetall(KEY_VERSIONS_HASH).await?;
+        drop(conn);
+
+        let mut deleted = 0;
+        for change in registry.version_changes(&recorded) {
+            let count = self.delete_pattern(&change.stale_pattern()).await?;
+            info!(
+                "Cache key {} migrated v{} -> v{}, dropped {} entries",
+                change.descriptor.type_name, change.from, change.to, count
+            );
+            deleted += count;
+        }
+
+        let versions: Vec<(String, u32)> = registry.versions().into_iter().collect();
+        if !versions.is_empty() {
+            let mut conn = self.redis_pool.get().await?;
+            conn.hset_multiple(KEY_VERSIONS_HASH, &versions).await?;
+        }
+
+        Ok(deleted)
+    }
 }
 
-/// Cache keys builder
+#[async_trait::async_trait]
+impl VersionStore for CacheManager {
+    // There is no version to fall back to, so these fail while Redis is down
+    async fn current(&self, key: &str) -> Result<ContentVersion, CacheError> {
+        self.guard(None, async {
+            let mut conn = self.redis_pool.get().await?;
+            let (version, modified): (u64, i64) = redis::pipe()
+                .atomic()
+                .hset_nx(CONTENT_VERSIONS_HASH, key, 1).ignore()
+                .hset_nx(CONTENT_MODIFIED_HASH, key, chrono::Utc::now().timestamp_millis()).ignore()
+                .hget(CONTENT_VERSIONS_HASH, key)
+                .hget(CONTENT_MODIFIED_HASH, key)
+                .query_async(&mut *conn)
+                .await?;
+            Ok(Some(versions::from_millis(version, modified)))
+        })
+        .await?
+        .ok_or(CacheError::Unavailable)
+    }
+
+    async fn bump(&self, key: &str) -> Result<ContentVersion, CacheError> {
+        self.guard(None, async {
+            let mut conn = self.redis_pool.get().await?;
+            let modified = chrono::Utc::now().timestamp_millis();
+            let (version,): (u64,) = redis::pipe()
+                .atomic()
+                .hincr(CONTENT_VERSIONS_HASH, key, 1)
+                .hset(CONTENT_MODIFIED_HASH, key, modified).ignore()
+                .query_async(&mut *conn)
+                .await?;
+            debug!("Content version of {} is now {}", key, version);
+            Ok(Some(versions::from_millis(version, modified)))
+        })
+        .await?
+        .ok_or(CacheError::Unavailable)
+    }
+}
+
+/// Cache keys builder, backed by the derived key types in [`keys`]
 pub struct CacheKeys;
 
 impl CacheKeys {
     /// User profile key
     pub fn user_profile(user_id: Uuid) -> String {
-        format!("user:{}:profile", user_id)
+        keys::UserProfileKey { user_id }.cache_key()
     }
 
     /// User stats key
     pub fn user_stats(user_id: Uuid) -> String {
-        format!("user:{}:stats", user_id)
+        keys::UserStatsKey { user_id }.cache_key()
     }
 
     /// User processes key
     pub fn user_processes(user_id: Uuid) -> String {
-        format!("user:{}:processes", user_id)
+        keys::UserProcessesKey { user_id }.cache_key()
     }
 
     /// Server info key
     pub fn server_info(ip: &str) -> String {
-        format!("server:{}:info", ip)
+        keys::ServerInfoKey { ip }.cache_key()
     }
 
     /// Server files key
     pub fn server_files(ip: &str) -> String {
-        format!("server:{}:files", ip)
+        keys::ServerFilesKey { ip }.cache_key()
     }
 
     /// Leaderboard key
     pub fn leaderboard(board_type: &str) -> String {
-        format!("leaderboard:{}", board_type)
+        keys::LeaderboardKey { board_type }.cache_key()
     }
 
     /// Clan info key
     pub fn clan_info(clan_id: Uuid) -> String {
-        format!("clan:{}:info", clan_id)
+        keys::ClanInfoKey { clan_id }.cache_key()
     }
 
     /// PvP match key
     pub fn pvp_match(match_id: Uuid) -> String {
-        format!("pvp:match:{}", match_id)
+        keys::PvpMatchKey { match_id }.cache_key()
     }
 
     /// Market listings key
     pub fn market_listings(category: &str) -> String {
-        format!("market:listings:{}", category)
+        keys::MarketListingsKey { category }.cache_key()
     }
 
     /// Chat room messages key
     pub fn chat_messages(room_id: &str) -> String {
-        format!("chat:{}:messages", room_id)
+        keys::ChatMessagesKey { room_id }.cache_key()
+    }
+
+    /// Software catalog key
+    pub fn software_catalog() -> String {
+        keys::SoftwareCatalogKey.cache_key()
     }
 }
 
-/// Cached data wrapper with metadata
+/// Cached data wrapper with metadata, the envelope every cached value is
+/// stored in (see [`schema`])
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachedData<T> {
     pub data: T,
     pub cached_at: chrono::DateTime<chrono::Utc>,
     pub ttl: u64,
+    #[serde(default)]
+    pub type_tag: String,
+    #[serde(default)]
+    pub schema_version: u32,
 }
 
 impl<T> CachedData<T> {
@@ -198,59 +385,28 @@ impl<T> CachedData<T> {
             data,
             cached_at: chrono::Utc::now(),
             ttl: ttl.as_secs(),
+            type_tag: String::new(),
+            schema_version: 0,
         }
     }
 
+    /// Tag the payload with its type and schema version
+    pub fn with_schema(mut self, type_tag: impl Into<String>, schema_version: u32) -> Self {
+        self.type_tag = type_tag.into();
+        self.schema_version = schema_version;
+        self
+    }
+
     pub fn is_expired(&self) -> bool {
         let age = chrono::Utc::now() - self.cached_at;
         age.num_seconds() as u64 > self.ttl
     }
 }
 
-/// Cache warming service
-pub struct CacheWarmer {
-    cache: CacheManager,
-}
-
-impl CacheWarmer {
-    pub fn new(cache: CacheManager) -> Self {
-        Self { cache }
-    }
-
-    /// Warm up frequently accessed data
-    pub async fn warm_up(&self) -> Result<(), CacheError> {
-        // Warm leaderboards
-        self.warm_leaderboards().await?;
-
-        // Warm popular servers
-        self.warm_popular_servers().await?;
-
-        // Warm active clan data
-        self.warm_active_clans().await?;
-
-        Ok(())
-    }
-
-    async fn warm_leaderboards(&self) -> Result<(), CacheError> {
-        debug!("Warming leaderboard cache...");
-        // Implementation would fetch from database and cache
-        Ok(())
-    }
-
-    async fn warm_popular_servers(&self) -> Result<(), CacheError> {
-        debug!("Warming popular servers cache...");
-        // Implementation would fetch from database and cache
-        Ok(())
-    }
-
-    async fn warm_active_clans(&self) -> Result<(), CacheError> {
-        debug!("Warming active clans cache...");
-        // Implementation would fetch from database and cache
-        Ok(())
-    }
-}
-
 /// Cache invalidation rules
+///
+/// Entries are dropped and their content versions bumped, so run these after
+/// the triggering change is committed.
 pub struct CacheInvalidator {
     cache: CacheManager,
 }
@@ -262,32 +418,32 @@ impl CacheInvalidator {
 
     /// Invalidate on user level up
     pub async fn on_level_up(&self, user_id: Uuid) -> Result<(), CacheError> {
-        self.cache.delete(&CacheKeys::user_profile(user_id)).await?;
-        self.cache.delete(&CacheKeys::user_stats(user_id)).await?;
-        self.cache.delete(&CacheKeys::leaderboard("level")).await?;
+        self.cache.invalidate(&CacheKeys::user_profile(user_id)).await?;
+        self.cache.invalidate(&CacheKeys::user_stats(user_id)).await?;
+        self.cache.invalidate(&CacheKeys::leaderboard("level")).await?;
         Ok(())
     }
 
     /// Invalidate on hack complete
     pub async fn on_hack_complete(&self, user_id: Uuid, server_ip: &str) -> Result<(), CacheError> {
-        self.cache.delete(&CacheKeys::user_stats(user_id)).await?;
-        self.cache.delete(&CacheKeys::server_info(server_ip)).await?;
+        self.cache.invalidate(&CacheKeys::user_stats(user_id)).await?;
+        self.cache.invalidate(&CacheKeys::server_info(server_ip)).await?;
         Ok(())
     }
 
     /// Invalidate on PvP match end
     pub async fn on_pvp_end(&self, match_id: Uuid, winner_id: Uuid, loser_id: Uuid) -> Result<(), CacheError> {
-        self.cache.delete(&CacheKeys::pvp_match(match_id)).await?;
-        self.cache.delete(&CacheKeys::user_stats(winner_id)).await?;
-        self.cache.delete(&CacheKeys::user_stats(loser_id)).await?;
-        self.cache.delete(&CacheKeys::leaderboard("pvp")).await?;
+        self.cache.invalidate(&CacheKeys::pvp_match(match_id)).await?;
+        self.cache.invalidate(&CacheKeys::user_stats(winner_id)).await?;
+        self.cache.invalidate(&CacheKeys::user_stats(loser_id)).await?;
+        self.cache.invalidate(&CacheKeys::leaderboard("pvp")).await?;
         Ok(())
     }
 
     /// Invalidate on clan change
     pub async fn on_clan_change(&self, clan_id: Uuid, user_id: Uuid) -> Result<(), CacheError> {
-        self.cache.delete(&CacheKeys::clan_info(clan_id)).await?;
-        self.cache.delete(&CacheKeys::user_profile(user_id)).await?;
+        self.cache.invalidate(&CacheKeys::clan_info(clan_id)).await?;
+        self.cache.invalidate(&CacheKeys::user_profile(user_id)).await?;
         Ok(())
     }
 }
@@ -306,6 +462,15 @@ pub enum CacheError {
 
     #[error("Cache key not found")]
     KeyNotFound,
+
+    #[error("Cache unavailable")]
+    Unavailable,
+}
+
+impl From<Unavailable> for CacheError {
+    fn from(_: Unavailable) -> Self {
+        CacheError::Unavailable
+    }
 }
 
 #[cfg(test)]
@@ -317,7 +482,7 @@ mod tests {
         let user_id = Uuid::new_v4();
         let key = CacheKeys::user_profile(user_id);
         assert!(key.starts_with("user:"));
-        assert!(key.ends_with(":profile"));
+        assert_eq!(key, format!("user:{}:profile:v1", user_id));
     }
 
     #[test]
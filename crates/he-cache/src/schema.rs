@@ -0,0 +1,204 @@
+//! Versioned cache payloads
+//!
+//! [`CacheManager::set`](crate::CacheManager::set) wraps every value in a
+//! [`CachedData`] envelope tagged with its keyspace's payload type and schema
+//! version. [`CacheManager::get`](crate::CacheManager::get) treats anything
+//! else as a miss instead of an error: entries written before envelopes
+//! existed, another type or version, or data that no longer fits the type
+//! it is read as. Each is counted in `cache_schema_mismatches_total`, so a
+//! deploy that changes a payload costs one refill instead of an error storm.
+//!
+//! Bump a keyspace's version with [`SchemaRegistry::register`] when its
+//! payload changes shape. Keyspaces expensive to refill, like user profiles,
+//! can register an [`Upcaster`] that rewrites older payloads instead.
+
+use crate::CachedData;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Schema version of keyspaces never registered
+pub const DEFAULT_SCHEMA_VERSION: u32 = 1;
+
+lazy_static::lazy_static! {
+    static ref CACHE_SCHEMA_MISMATCHES: IntCounterVec = register_int_counter_vec!(
+        "cache_schema_mismatches_total",
+        "Cache entries read as a miss because their payload did not match, by reason",
+        &["keyspace", "reason"]
+    ).unwrap();
+
+    static ref CACHE_UPCASTS: IntCounterVec = register_int_counter_vec!(
+        "cache_upcasts_total",
+        "Cache entries of an older schema version rewritten on read",
+        &["keyspace"]
+    ).unwrap();
+}
+
+/// Rewrites a payload of the given older version to the current one, `None`
+/// when it cannot
+pub type Upcaster = Arc<dyn Fn(u32, Value) -> Option<Value> + Send + Sync>;
+
+/// Why a cached entry was read as a miss
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Not an envelope, e.g. written before envelopes existed
+    Legacy,
+    /// An envelope of another payload type
+    Type,
+    /// Another schema version, with no upcaster able to bring it up
+    Version,
+    /// The payload does not deserialize as the type read
+    Shape,
+}
+
+impl Mismatch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mismatch::Legacy => "legacy",
+            Mismatch::Type => "type",
+            Mismatch::Version => "version",
+            Mismatch::Shape => "shape",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Schema {
+    type_tag: String,
+    version: u32,
+    upcaster: Option<Upcaster>,
+}
+
+/// Payload type and schema version of each keyspace. A keyspace not
+/// registered is its own type tag at [`DEFAULT_SCHEMA_VERSION`].
+#[derive(Default)]
+pub struct SchemaRegistry {
+    schemas: RwLock<HashMap<String, Schema>>,
+}
+
+impl SchemaRegistry {
+    /// Set the payload type and current schema version of `keyspace`,
+    /// keeping any upcaster
+    pub fn register(&self, keyspace: &str, type_tag: &str, version: u32) {
+        let mut schemas = self.schemas.write().unwrap();
+        let schema = schemas.entry(keyspace.to_string()).or_insert_with(|| default_schema(keyspace));
+        schema.type_tag = type_tag.to_string();
+        schema.version = version;
+    }
+
+    /// Upcast older payloads of `keyspace` on read instead of dropping them
+    pub fn register_upcaster<F>(&self, keyspace: &str, upcaster: F)
+    where
+        F: Fn(u32, Value) -> Option<Value> + Send + Sync + 'static,
+    {
+        let mut schemas = self.schemas.write().unwrap();
+        let schema = schemas.entry(keyspace.to_string()).or_insert_with(|| default_schema(keyspace));
+        schema.upcaster = Some(Arc::new(upcaster));
+    }
+
+    fn schema(&self, keyspace: &str) -> Schema {
+        self.schemas.read().unwrap().get(keyspace).cloned().unwrap_or_else(|| default_schema(keyspace))
+    }
+
+    /// `value` in an envelope of the keyspace's current schema
+    pub fn seal<T: Serialize>(&self, keyspace: &str, value: &T, ttl: Duration) -> Result<String, serde_json::Error> {
+        let schema = self.schema(keyspace);
+        let envelope = CachedData::new(serde_json::to_value(value)?, ttl).with_schema(schema.type_tag, schema.version);
+        serde_json::to_string(&envelope)
+    }
+
+    /// The value in `raw`, `None` on any mismatch, which is counted
+    pub fn open<T: DeserializeOwned>(&self, keyspace: &str, raw: &str) -> Option<T> {
+        match self.decode(keyspace, raw) {
+            Ok((value, upcast)) => {
+                if upcast {
+                    CACHE_UPCASTS.with_label_values(&[keyspace]).inc();
+                }
+                Some(value)
+            }
+            Err(mismatch) => {
+                CACHE_SCHEMA_MISMATCHES.with_label_values(&[keyspace, mismatch.as_str()]).inc();
+                tracing::debug!("Cache entry in {} read as a miss: {} mismatch", keyspace, mismatch.as_str());
+                None
+            }
+        }
+    }
+
+    /// The value in `raw`, and whether it was upcast
+    fn decode<T: DeserializeOwned>(&self, keyspace: &str, raw: &str) -> Result<(T, bool), Mismatch> {
+        let envelope: CachedData<Value> = serde_json::from_str(raw).map_err(|_| Mismatch::Legacy)?;
+        let schema = self.schema(keyspace);
+        if envelope.type_tag != schema.type_tag {
+            return Err(Mismatch::Type);
+        }
+
+        let (data, upcast) = if envelope.schema_version == schema.version {
+            (envelope.data, false)
+        } else {
+            let upcaster = schema.upcaster.filter(|_| envelope.schema_version < schema.version);
+            let data = upcaster.and_then(|upcast| upcast(envelope.schema_version, envelope.data));
+            (data.ok_or(Mismatch::Version)?, true)
+        };
+        let value = serde_json::from_value(data).map_err(|_| Mismatch::Shape)?;
+        Ok((value, upcast))
+    }
+}
+
+fn default_schema(keyspace: &str) -> Schema {
+    Schema { type_tag: keyspace.to_string(), version: DEFAULT_SCHEMA_VERSION, upcaster: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Profile {
+        name: String,
+        level: u32,
+    }
+
+    fn profile() -> Profile {
+        Profile { name: "neo".to_string(), level: 7 }
+    }
+
+    #[test]
+    fn test_round_trip_and_mismatches() {
+        let schemas = SchemaRegistry::default();
+        let ttl = Duration::from_secs(60);
+        let sealed = schemas.seal("user:profile", &profile(), ttl).unwrap();
+        assert_eq!(schemas.decode::<Profile>("user:profile", &sealed), Ok((profile(), false)));
+
+        let bare = serde_json::to_string(&profile()).unwrap();
+        assert_eq!(schemas.decode::<Profile>("user:profile", &bare), Err(Mismatch::Legacy));
+        assert_eq!(schemas.decode::<Profile>("user:stats", &sealed), Err(Mismatch::Type));
+        assert_eq!(schemas.decode::<Vec<u32>>("user:profile", &sealed), Err(Mismatch::Shape));
+        assert_eq!(schemas.open::<Profile>("user:stats", &sealed), None);
+
+        schemas.register("user:profile", "user:profile", 2);
+        assert_eq!(schemas.decode::<Profile>("user:profile", &sealed), Err(Mismatch::Version));
+    }
+
+    #[test]
+    fn test_upcaster_brings_old_payloads_up() {
+        let schemas = SchemaRegistry::default();
+        let old = schemas.seal("user:profile", &serde_json::json!({ "name": "neo" }), Duration::from_secs(60)).unwrap();
+
+        schemas.register("user:profile", "user:profile", 2);
+        schemas.register_upcaster("user:profile", |from, mut data| {
+            (from == 1).then(|| {
+                data["level"] = Value::from(7);
+                data
+            })
+        });
+        assert_eq!(schemas.decode::<Profile>("user:profile", &old), Ok((profile(), true)));
+
+        // Newer payloads are never upcast down
+        schemas.register("user:profile", "user:profile", 0);
+        assert_eq!(schemas.decode::<Profile>("user:profile", &old), Err(Mismatch::Version));
+    }
+}
@@ -0,0 +1,169 @@
+//! Content versions for HTTP revalidation
+//!
+//! Every cache key also has a content version, bumped by
+//! [`CacheManager::invalidate`](crate::CacheManager::invalidate) whenever the
+//! data behind the key changes. Responses built from that data carry an ETag
+//! and Last-Modified derived from the version, so clients can revalidate with
+//! a version lookup instead of downloading the body again.
+//!
+//! Under concurrent writes the order matters: writers bump the version after
+//! their change is committed, and readers take the version before loading the
+//! data. A body is then never older than the version it is tagged with, and
+//! the worst a race can do is cost the client one extra full response.
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::CacheError;
+
+/// Redis hash of content version per cache key
+pub const CONTENT_VERSIONS_HASH: &str = "cache:content_versions";
+/// Redis hash of last modification (unix millis) per cache key
+pub const CONTENT_MODIFIED_HASH: &str = "cache:content_modified";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentVersion {
+    pub version: u64,
+    pub modified_at: DateTime<Utc>,
+}
+
+impl ContentVersion {
+    pub fn new(version: u64, modified_at: DateTime<Utc>) -> Self {
+        Self { version, modified_at }
+    }
+
+    /// Strong ETag. The modification time is part of it so a version counter
+    /// that starts over (e.g. after a Redis flush) does not repeat old tags.
+    pub fn etag(&self) -> String {
+        format!("\"{}-{:x}\"", self.version, self.modified_at.timestamp_millis())
+    }
+
+    /// `Last-Modified` as seen at `now`, or `None` while the version is less
+    /// than a second old. HTTP dates have one-second resolution, so a second
+    /// write within the same second would otherwise be invisible to
+    /// `If-Modified-Since`.
+    pub fn last_modified(&self, now: DateTime<Utc>) -> Option<String> {
+        if now - self.modified_at < chrono::Duration::seconds(1) {
+            return None;
+        }
+        Some(self.modified_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+    }
+
+    /// Whether a client sending these validators already has this version.
+    /// `If-None-Match` takes precedence over `If-Modified-Since` when both
+    /// are present (RFC 9110 section 13.2.2).
+    pub fn is_fresh(&self, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> bool {
+        if let Some(tags) = if_none_match {
+            let etag = self.etag();
+            return tags
+                .split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+        }
+
+        match if_modified_since.and_then(|since| DateTime::parse_from_rfc2822(since).ok()) {
+            Some(since) => self.modified_at.timestamp() <= since.timestamp(),
+            None => false,
+        }
+    }
+}
+
+/// Where content versions are kept
+#[async_trait]
+pub trait VersionStore: Send + Sync {
+    /// Current version of `key`; a key never bumped starts at version 1
+    async fn current(&self, key: &str) -> Result<ContentVersion, CacheError>;
+
+    /// Record that the data behind `key` changed
+    async fn bump(&self, key: &str) -> Result<ContentVersion, CacheError>;
+}
+
+/// Process-local versions, for single-node setups without Redis and tests
+#[derive(Default)]
+pub struct MemoryVersions {
+    versions: Mutex<HashMap<String, ContentVersion>>,
+}
+
+impl MemoryVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl VersionStore for MemoryVersions {
+    async fn current(&self, key: &str) -> Result<ContentVersion, CacheError> {
+        let mut versions = self.versions.lock().unwrap();
+        Ok(*versions
+            .entry(key.to_string())
+            .or_insert_with(|| ContentVersion::new(1, Utc::now())))
+    }
+
+    async fn bump(&self, key: &str) -> Result<ContentVersion, CacheError> {
+        let mut versions = self.versions.lock().unwrap();
+        let version = versions
+            .entry(key.to_string())
+            .and_modify(|v| *v = ContentVersion::new(v.version + 1, Utc::now()))
+            .or_insert_with(|| ContentVersion::new(2, Utc::now()));
+        Ok(*version)
+    }
+}
+
+pub(crate) fn from_millis(version: u64, millis: i64) -> ContentVersion {
+    let modified_at = Utc.timestamp_millis_opt(millis).single().unwrap_or_else(Utc::now);
+    ContentVersion::new(version, modified_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_validators() {
+        let modified_at = Utc.with_ymd_and_hms(2024, 10, 2, 12, 30, 15).unwrap();
+        let version = ContentVersion::new(7, modified_at);
+        let etag = version.etag();
+
+        assert!(version.is_fresh(Some(&etag), None));
+        assert!(version.is_fresh(Some(&format!("\"other\", W/{}", etag)), None));
+        assert!(version.is_fresh(Some("*"), None));
+        assert!(!version.is_fresh(Some("\"6-0\""), None));
+        // If-None-Match wins over a matching If-Modified-Since
+        assert!(!version.is_fresh(Some("\"6-0\""), Some("Wed, 02 Oct 2024 12:30:15 GMT")));
+
+        assert!(version.is_fresh(None, Some("Wed, 02 Oct 2024 12:30:15 GMT")));
+        assert!(!version.is_fresh(None, Some("Wed, 02 Oct 2024 12:30:14 GMT")));
+        assert!(!version.is_fresh(None, Some("yesterday")));
+        assert!(!version.is_fresh(None, None));
+
+        assert_eq!(version.last_modified(modified_at), None);
+        assert_eq!(
+            version.last_modified(modified_at + chrono::Duration::seconds(2)).as_deref(),
+            Some("Wed, 02 Oct 2024 12:30:15 GMT")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_bumps_are_not_lost() {
+        let store = Arc::new(MemoryVersions::new());
+        let first = store.current("user:1:profile:v1").await.unwrap();
+
+        let bumps: Vec<_> = (0..50)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move { store.bump("user:1:profile:v1").await.unwrap() })
+            })
+            .collect();
+        let mut etags = Vec::new();
+        for bump in bumps {
+            etags.push(bump.await.unwrap().etag());
+        }
+        etags.sort();
+        etags.dedup();
+
+        assert_eq!(etags.len(), 50);
+        assert_eq!(store.current("user:1:profile:v1").await.unwrap().version, first.version + 50);
+    }
+}
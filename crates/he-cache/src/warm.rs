@@ -0,0 +1,350 @@
+//! Cache warming on deploy
+//!
+//! A [`WarmManifest`] lists the groups of keys a fresh node should have in
+//! the cache before it takes traffic, each with a priority (0 first). Groups
+//! of the same priority load in parallel and every priority finishes before
+//! the next starts; writes to Redis are capped at the manifest's concurrency
+//! across all groups so a deploy does not flood the cache.
+//!
+//! [`WarmProgress`] is what orchestration polls. It is ready once every group
+//! at or above the manifest's gate priority has finished, whether it succeeded
+//! or not: a group that failed to load leaves its keys cold, which is slower
+//! but still correct, so it must not hold the node out of rotation forever.
+
+use crate::CacheManager;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter_vec, Histogram, HistogramVec,
+    IntCounterVec,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+/// Top leaderboards
+pub const LEADERBOARDS: &str = "leaderboards";
+
+lazy_static::lazy_static! {
+    static ref WARM_DURATION: Histogram = register_histogram!(
+        "cache_warm_duration_seconds",
+        "Time taken by a full cache warm",
+        vec![0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0]
+    ).unwrap();
+
+    static ref WARM_GROUP_DURATION: HistogramVec = register_histogram_vec!(
+        "cache_warm_group_duration_seconds",
+        "Time taken to warm one manifest group",
+        &["group"]
+    ).unwrap();
+
+    static ref WARM_KEYS: IntCounterVec = register_int_counter_vec!(
+        "cache_warm_keys_total",
+        "Keys written by cache warming",
+        &["group", "outcome"]
+    ).unwrap();
+}
+
+/// One group of keys to warm
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmGroup {
+    pub name: String,
+    /// Lower priorities warm first
+    pub priority: u8,
+    /// Most entries the source should return
+    pub limit: usize,
+    pub ttl_secs: u64,
+}
+
+impl WarmGroup {
+    pub fn new(name: &str, priority: u8, limit: usize, ttl_secs: u64) -> Self {
+        Self { name: name.to_string(), priority, limit, ttl_secs }
+    }
+}
+
+/// Which keys to warm, in what order and how fast
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmManifest {
+    pub groups: Vec<WarmGroup>,
+    /// Most cache writes in flight at once
+    pub concurrency: usize,
+    /// The node is ready once every group up to this priority has finished
+    pub gate_priority: u8,
+}
+
+impl Default for WarmManifest {
+    fn default() -> Self {
+        Self {
+            groups: vec![WarmGroup::new(LEADERBOARDS, 0, 100, 300)],
+            concurrency: 16,
+            gate_priority: 0,
+        }
+    }
+}
+
+impl WarmManifest {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Groups bucketed by priority, highest priority first
+    pub fn stages(&self) -> Vec<Vec<&WarmGroup>> {
+        let mut priorities: Vec<u8> = self.groups.iter().map(|g| g.priority).collect();
+        priorities.sort_unstable();
+        priorities.dedup();
+
+        priorities
+            .into_iter()
+            .map(|p| self.groups.iter().filter(|g| g.priority == p).collect())
+            .collect()
+    }
+}
+
+/// Loads the entries of a manifest group from the source of truth
+#[async_trait]
+pub trait WarmSource: Send + Sync {
+    /// At most `group.limit` cache keys with their values
+    async fn load(&self, group: &WarmGroup) -> anyhow::Result<Vec<(String, serde_json::Value)>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupState {
+    Pending,
+    Warming,
+    Done,
+    /// The source could not load the group; its keys stay cold
+    Failed,
+}
+
+impl GroupState {
+    pub fn is_finished(self) -> bool {
+        matches!(self, GroupState::Done | GroupState::Failed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupProgress {
+    pub name: String,
+    pub priority: u8,
+    pub state: GroupState,
+    pub keys_written: usize,
+    pub keys_failed: usize,
+    pub duration_ms: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Snapshot of a warm in progress
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmProgress {
+    pub gate_priority: u8,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<f64>,
+    pub groups: Vec<GroupProgress>,
+}
+
+impl WarmProgress {
+    fn new(manifest: &WarmManifest) -> Self {
+        Self {
+            gate_priority: manifest.gate_priority,
+            started_at: None,
+            finished_at: None,
+            duration_ms: None,
+            groups: manifest
+                .groups
+                .iter()
+                .map(|g| GroupProgress {
+                    name: g.name.clone(),
+                    priority: g.priority,
+                    state: GroupState::Pending,
+                    keys_written: 0,
+                    keys_failed: 0,
+                    duration_ms: None,
+                    error: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether the node may take traffic
+    pub fn is_ready(&self) -> bool {
+        self.groups
+            .iter()
+            .filter(|g| g.priority <= self.gate_priority)
+            .all(|g| g.state.is_finished())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.groups.iter().all(|g| g.state.is_finished())
+    }
+
+    pub fn keys_written(&self) -> usize {
+        self.groups.iter().map(|g| g.keys_written).sum()
+    }
+
+    fn group(&mut self, name: &str) -> Option<&mut GroupProgress> {
+        self.groups.iter_mut().find(|g| g.name == name)
+    }
+}
+
+/// Warms the cache from a [`WarmManifest`]
+pub struct CacheWarmer {
+    cache: Arc<CacheManager>,
+    manifest: WarmManifest,
+    progress: RwLock<WarmProgress>,
+}
+
+impl CacheWarmer {
+    pub fn new(cache: Arc<CacheManager>, manifest: WarmManifest) -> Self {
+        let progress = RwLock::new(WarmProgress::new(&manifest));
+        Self { cache, manifest, progress }
+    }
+
+    pub fn progress(&self) -> WarmProgress {
+        self.progress.read().unwrap().clone()
+    }
+
+    /// Warm every group of the manifest, returning the final progress
+    pub async fn warm_up(&self, source: &dyn WarmSource) -> WarmProgress {
+        let started = Instant::now();
+        *self.progress.write().unwrap() = WarmProgress {
+            started_at: Some(Utc::now()),
+            ..WarmProgress::new(&self.manifest)
+        };
+
+        let writes = Semaphore::new(self.manifest.concurrency.max(1));
+        for stage in self.manifest.stages() {
+            futures::future::join_all(stage.into_iter().map(|group| self.warm_group(group, source, &writes))).await;
+        }
+
+        let elapsed = started.elapsed();
+        WARM_DURATION.observe(elapsed.as_secs_f64());
+
+        let mut progress = self.progress.write().unwrap();
+        progress.finished_at = Some(Utc::now());
+        progress.duration_ms = Some(elapsed.as_secs_f64() * 1000.0);
+        info!("Cache warm finished in {:?}, {} keys written", elapsed, progress.keys_written());
+        progress.clone()
+    }
+
+    async fn warm_group(&self, group: &WarmGroup, source: &dyn WarmSource, writes: &Semaphore) {
+        let started = Instant::now();
+        self.update(&group.name, |g| g.state = GroupState::Warming);
+
+        let entries = match source.load(group).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to load cache warm group {}: {}", group.name, e);
+                self.finish(group, started, |g| {
+                    g.state = GroupState::Failed;
+                    g.error = Some(e.to_string());
+                });
+                return;
+            }
+        };
+
+        let ttl = Duration::from_secs(group.ttl_secs);
+        stream::iter(entries.into_iter().take(group.limit))
+            .for_each_concurrent(self.manifest.concurrency.max(1), |(key, value)| async move {
+                let Ok(_permit) = writes.acquire().await else {
+                    return;
+                };
+                let written = match self.cache.set(&key, &value, Some(ttl)).await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("Failed to warm cache key {}: {}", key, e);
+                        false
+                    }
+                };
+
+                let outcome = if written { "written" } else { "failed" };
+                WARM_KEYS.with_label_values(&[&group.name, outcome]).inc();
+                self.update(&group.name, |g| {
+                    if written {
+                        g.keys_written += 1;
+                    } else {
+                        g.keys_failed += 1;
+                    }
+                });
+            })
+            .await;
+
+        self.finish(group, started, |g| g.state = GroupState::Done);
+    }
+
+    fn finish(&self, group: &WarmGroup, started: Instant, f: impl FnOnce(&mut GroupProgress)) {
+        let elapsed = started.elapsed();
+        WARM_GROUP_DURATION.with_label_values(&[&group.name]).observe(elapsed.as_secs_f64());
+        self.update(&group.name, |g| {
+            g.duration_ms = Some(elapsed.as_secs_f64() * 1000.0);
+            f(g);
+        });
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut GroupProgress)) {
+        if let Some(group) = self.progress.write().unwrap().group(name) {
+            f(group);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_stages_by_priority() {
+        let manifest = WarmManifest::from_json(
+            r#"{
+                "groups": [
+                    {"name": "npc_servers", "priority": 2, "limit": 50, "ttl_secs": 600},
+                    {"name": "leaderboards", "priority": 0, "limit": 100, "ttl_secs": 300},
+                    {"name": "software_catalog", "priority": 0, "limit": 1, "ttl_secs": 3600}
+                ],
+                "concurrency": 8,
+                "gate_priority": 0
+            }"#,
+        )
+        .unwrap();
+
+        let stages: Vec<Vec<&str>> = manifest
+            .stages()
+            .iter()
+            .map(|stage| stage.iter().map(|g| g.name.as_str()).collect())
+            .collect();
+        assert_eq!(stages, vec![vec!["leaderboards", "software_catalog"], vec!["npc_servers"]]);
+    }
+
+    #[test]
+    fn test_progress_gates_on_priority() {
+        let manifest = WarmManifest {
+            groups: vec![
+                WarmGroup::new(LEADERBOARDS, 0, 100, 300),
+                WarmGroup::new("clans", 0, 100, 300),
+                WarmGroup::new("profiles", 1, 100, 600),
+                WarmGroup::new("servers", 2, 50, 600),
+            ],
+            concurrency: 16,
+            gate_priority: 1,
+        };
+        let mut progress = WarmProgress::new(&manifest);
+        assert!(!progress.is_ready());
+
+        for name in [LEADERBOARDS, "clans"] {
+            progress.group(name).unwrap().state = GroupState::Done;
+        }
+        assert!(!progress.is_ready());
+
+        // A failed group does not hold the node back
+        progress.group("profiles").unwrap().state = GroupState::Failed;
+        assert!(progress.is_ready());
+        assert!(!progress.is_complete());
+
+        progress.group("servers").unwrap().state = GroupState::Done;
+        assert!(progress.is_complete());
+    }
+}
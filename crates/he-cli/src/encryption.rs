@@ -0,0 +1,156 @@
+//! `he-cli encryption`: field encryption keys and rotation
+//!
+//! Run with the same `FIELD_ENCRYPTION_KEYS` as the API nodes, including the
+//! version to rotate to. Rotation refuses to start unless every live node
+//! already holds that key; retiring refuses while any value is still sealed
+//! under the version.
+
+use anyhow::Result;
+use he_database::encryption::{self, KeyChangeBlocked};
+use he_database::queries::EncryptionQueries;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub const USAGE: &str = "usage: he-cli encryption status [--json]
+       he-cli encryption rotate <version> [--dry-run]
+       he-cli encryption retire <version>";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Status { json: bool },
+    Rotate { version: u32, dry_run: bool },
+    Retire { version: u32 },
+}
+
+/// Parse the arguments after `encryption`
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let (name, rest) = args.split_first().ok_or("missing encryption command")?;
+    let mut version = None;
+    let mut json = false;
+    let mut dry_run = false;
+    for arg in rest {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--dry-run" => dry_run = true,
+            v if version.is_none() && !v.starts_with('-') => {
+                version = Some(v.parse::<u32>().ok().filter(|v| *v > 0).ok_or("version must be a positive number")?);
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+
+    let command = match name.as_str() {
+        "status" if version.is_none() && !dry_run => Command::Status { json },
+        "rotate" if !json => Command::Rotate { version: version.ok_or("rotate needs a version")?, dry_run },
+        "retire" if !json && !dry_run => Command::Retire { version: version.ok_or("retire needs a version")? },
+        "status" | "rotate" | "retire" => return Err(format!("invalid arguments for {}", name)),
+        other => return Err(format!("unknown encryption command '{}'", other)),
+    };
+    Ok(command)
+}
+
+/// Run `command`, printing its outcome. Returns the exit status: 0, or 2 if
+/// the change was refused.
+pub async fn run(pool: &PgPool, command: Command, operator: &str) -> Result<i32> {
+    match command {
+        Command::Status { json } => {
+            let status = status(pool).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+            } else {
+                print!("{}", render_status(&status));
+            }
+            Ok(0)
+        }
+        Command::Rotate { version, dry_run: true } => Ok(match encryption::preflight(pool, version).await? {
+            Ok(_) => {
+                println!("Rotation to key v{} can start", version);
+                0
+            }
+            Err(blocked) => refused(&blocked),
+        }),
+        Command::Rotate { version, dry_run: false } => Ok(match encryption::rotate(pool, version, operator).await? {
+            Ok(rotation) => {
+                println!(
+                    "Rotation {} started: new values are sealed under v{}, older ones are being re-encrypted",
+                    rotation.id, version
+                );
+                0
+            }
+            Err(blocked) => refused(&blocked),
+        }),
+        Command::Retire { version } => Ok(match encryption::retire(pool, version).await? {
+            Ok(()) => {
+                println!("Key v{} retired; it can be removed from FIELD_ENCRYPTION_KEYS", version);
+                0
+            }
+            Err(blocked) => refused(&blocked),
+        }),
+    }
+}
+
+fn refused(blocked: &KeyChangeBlocked) -> i32 {
+    eprintln!("Refused: {}", blocked.message());
+    2
+}
+
+async fn status(pool: &PgPool) -> Result<serde_json::Value> {
+    Ok(serde_json::json!({
+        "keys": EncryptionQueries::key_versions(pool).await?,
+        "nodes": EncryptionQueries::live_nodes(pool, encryption::LIVE_NODE_SECS).await?,
+        "rotation": EncryptionQueries::open_rotation(pool).await?,
+        "usage": EncryptionQueries::key_usage(pool).await?,
+    }))
+}
+
+fn render_status(status: &serde_json::Value) -> String {
+    let mut out = String::new();
+    let rows = |key: &str| status[key].as_array().cloned().unwrap_or_default();
+
+    let _ = writeln!(out, "Keys:");
+    for key in rows("keys") {
+        let _ = writeln!(out, "  v{:<4} {:<9} {}", key["version"], key["state"].as_str().unwrap_or(""), key["fingerprint"].as_str().unwrap_or(""));
+    }
+    let _ = writeln!(out, "Live nodes:");
+    for node in rows("nodes") {
+        let _ = writeln!(out, "  {:<24} holds {} active v{}", node["node_id"].as_str().unwrap_or(""), node["versions"], node["active_version"]);
+    }
+    match status["rotation"].as_object() {
+        Some(rotation) => {
+            let _ = writeln!(out, "Rotation {} to v{}: {} rows re-encrypted", rotation["id"], rotation["to_version"], rotation["rows_resealed"]);
+        }
+        None => {
+            let _ = writeln!(out, "No rotation in progress");
+        }
+    }
+    let _ = writeln!(out, "Values by key:");
+    for usage in rows("usage") {
+        let version = match usage["key_version"].as_i64() {
+            Some(v) => format!("v{}", v),
+            None => "plaintext".to_string(),
+        };
+        let _ = writeln!(out, "  {:<20} {:<10} {}", usage["field"].as_str().unwrap_or(""), version, usage["rows"]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(&args("status")), Ok(Command::Status { json: false }));
+        assert_eq!(parse(&args("rotate 2 --dry-run")), Ok(Command::Rotate { version: 2, dry_run: true }));
+        assert_eq!(parse(&args("retire 1")), Ok(Command::Retire { version: 1 }));
+        assert!(parse(&args("rotate")).is_err());
+        assert!(parse(&args("rotate 0")).is_err());
+        assert!(parse(&args("retire 1 --dry-run")).is_err());
+        assert!(parse(&args("status 2")).is_err());
+        assert!(parse(&args("shred")).is_err());
+    }
+}
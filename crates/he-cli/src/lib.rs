@@ -1 +1,3 @@
-pub fn hello() { println!("Hello from he-cli!"); }
+//! Operator commands for HackerExperience
+
+pub mod encryption;
@@ -0,0 +1,58 @@
+//! HackerExperience operator CLI
+//!
+//! Reads `DATABASE_URL`, and `FIELD_ENCRYPTION_KEYS` with
+//! `FIELD_ENCRYPTION_INDEX_KEY` for encryption commands.
+//!
+//! Usage: he-cli encryption <status | rotate <version> [--dry-run] | retire <version>>
+
+use he_cli::encryption;
+use he_helix_security::encryption::{install_keyring, FieldEncryption};
+use sqlx::postgres::PgPoolOptions;
+use tracing::error;
+use tracing_subscriber::EnvFilter;
+
+fn parse_args() -> Result<encryption::Command, String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.split_first() {
+        Some((group, rest)) if group == "encryption" => encryption::parse(rest),
+        Some((group, _)) => Err(format!("unknown command '{}'", group)),
+        None => Err("missing command".to_string()),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env().add_directive("warn".parse().unwrap()))
+        .init();
+
+    let command = match parse_args() {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n{}", e, encryption::USAGE);
+            std::process::exit(64);
+        }
+    };
+
+    match FieldEncryption::from_env() {
+        Ok(Some(keyring)) => install_keyring(keyring),
+        Ok(None) => {}
+        Err(e) => {
+            error!("Invalid field encryption keys: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let pool = PgPoolOptions::new().max_connections(2).connect(&url).await
+        .expect("Failed to connect to the database");
+
+    let operator = std::env::var("USER").unwrap_or_else(|_| "he-cli".to_string());
+    match encryption::run(&pool, command, &operator).await {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            error!("Command failed: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! The REST client
+
+use crate::error::{SdkError, SdkResult};
+use crate::events::EventStream;
+use crate::retry::{retryable_error, retryable_status, RetryPolicy};
+use crate::session::Session;
+use crate::types::{AuthResponse, Cancelled, ProcessList, StartProcess, StartedProcess};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Mutex;
+
+/// Refresh tokens this long before they expire
+const DEFAULT_REFRESH_MARGIN: Duration = Duration::from_secs(120);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct ClientBuilder {
+    base_url: String,
+    ws_url: Option<String>,
+    retry: RetryPolicy,
+    refresh_margin: Duration,
+    timeout: Duration,
+}
+
+impl ClientBuilder {
+    /// The game socket; `ws(s)://<host>/ws` of the base URL by default
+    pub fn ws_url(mut self, url: impl Into<String>) -> Self {
+        self.ws_url = Some(url.into());
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    pub fn refresh_margin(mut self, margin: Duration) -> Self {
+        self.refresh_margin = margin;
+        self
+    }
+
+    /// How long one attempt of a request may take
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> SdkResult<Client> {
+        let base_url = self.base_url.trim_end_matches('/').to_string();
+        let ws_url = match self.ws_url {
+            Some(url) => url,
+            None => {
+                let (scheme, rest) = base_url
+                    .split_once("://")
+                    .ok_or_else(|| SdkError::Url(format!("{} has no scheme", base_url)))?;
+                let scheme = match scheme {
+                    "https" => "wss",
+                    "http" => "ws",
+                    other => return Err(SdkError::Url(format!("unsupported scheme {}", other))),
+                };
+                format!("{}://{}/ws", scheme, rest)
+            }
+        };
+        let http = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .user_agent(concat!("he-client-sdk/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        Ok(Client {
+            inner: Arc::new(Inner {
+                http,
+                base_url,
+                ws_url,
+                retry: self.retry,
+                refresh_margin: self.refresh_margin,
+                session: Mutex::new(None),
+            }),
+        })
+    }
+}
+
+struct Inner {
+    http: reqwest::Client,
+    base_url: String,
+    ws_url: String,
+    retry: RetryPolicy,
+    refresh_margin: Duration,
+    /// Held across a refresh, so concurrent requests refresh once
+    session: Mutex<Option<Session>>,
+}
+
+/// A player's connection to one server. Cheap to clone; clones share the
+/// session.
+#[derive(Clone)]
+pub struct Client {
+    inner: Arc<Inner>,
+}
+
+impl Client {
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.into(),
+            ws_url: None,
+            retry: RetryPolicy::default(),
+            refresh_margin: DEFAULT_REFRESH_MARGIN,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub(crate) fn ws_url(&self) -> &str {
+        &self.inner.ws_url
+    }
+
+    pub(crate) fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.inner.base_url, path)
+    }
+
+    pub async fn login(&self, email: &str, password: &str) -> SdkResult<()> {
+        let body = serde_json::json!({ "email": email, "password": password });
+        let response = self
+            .send(|| self.inner.http.post(self.url("/api/auth/login")).json(&body), true)
+            .await?;
+        let token = auth_token(response).await?;
+        *self.inner.session.lock().await = Some(Session::new(token));
+        Ok(())
+    }
+
+    /// Sign out on the server and forget the session
+    pub async fn logout(&self) -> SdkResult<()> {
+        let session = self.inner.session.lock().await.take();
+        if let Some(session) = session {
+            let response = self
+                .send(|| self.inner.http.post(self.url("/api/auth/logout")).bearer_auth(&session.token), true)
+                .await?;
+            if !response.status().is_success() && response.status() != StatusCode::UNAUTHORIZED {
+                return Err(api_error(response).await);
+            }
+        }
+        Ok(())
+    }
+
+    /// A session token good for at least the refresh margin
+    pub async fn token(&self) -> SdkResult<String> {
+        let token = {
+            let session = self.inner.session.lock().await;
+            let session = session.as_ref().ok_or(SdkError::NotSignedIn)?;
+            if !session.needs_refresh(SystemTime::now(), self.inner.refresh_margin) {
+                return Ok(session.token.clone());
+            }
+            session.token.clone()
+        };
+        self.refresh_from(&token).await
+    }
+
+    /// Trade the session token for a fresh one now
+    pub async fn refresh(&self) -> SdkResult<()> {
+        let token = self.inner.session.lock().await.as_ref().map(|session| session.token.clone());
+        self.refresh_from(&token.ok_or(SdkError::NotSignedIn)?).await.map(|_| ())
+    }
+
+    /// Refresh `stale`, unless another request already replaced it
+    async fn refresh_from(&self, stale: &str) -> SdkResult<String> {
+        let mut session = self.inner.session.lock().await;
+        match session.as_ref() {
+            None => return Err(SdkError::NotSignedIn),
+            Some(current) if current.token != stale => return Ok(current.token.clone()),
+            Some(_) => {}
+        }
+
+        let response = self
+            .send(|| self.inner.http.post(self.url("/api/auth/refresh")).bearer_auth(stale), true)
+            .await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(SdkError::SessionExpired);
+        }
+        let token = auth_token(response).await?;
+        tracing::debug!("Session token refreshed");
+        *session = Some(Session::new(token.clone()));
+        Ok(token)
+    }
+
+    /// The player's processes, and other players' processes on their servers
+    pub async fn processes(&self) -> SdkResult<ProcessList> {
+        self.call(Method::GET, "/api/processes", None::<&()>).await
+    }
+
+    /// Not retried on answers that may come after the process started
+    pub async fn start_process(&self, request: &StartProcess) -> SdkResult<StartedProcess> {
+        self.call(Method::POST, "/api/processes", Some(request)).await
+    }
+
+    /// Cancel a process; the refund or penalty is in the answer
+    pub async fn cancel_process(&self, pid: i64) -> SdkResult<Cancelled> {
+        self.call(Method::DELETE, &format!("/api/processes/{}/cancel", pid), None::<&()>).await
+    }
+
+    /// Open the game socket and subscribe to `channels`, e.g. `user:42` or
+    /// `process:1001`
+    pub async fn subscribe_events(&self, channels: &[&str]) -> SdkResult<EventStream> {
+        EventStream::open(self.clone(), channels.iter().map(|channel| channel.to_string()).collect()).await
+    }
+
+    /// Any endpoint without a typed method, signed in and retried like the
+    /// typed ones. Only GET, PUT and DELETE are retried on answers that may
+    /// come after the request was carried out.
+    pub async fn call<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> SdkResult<T> {
+        let idempotent = matches!(method, Method::GET | Method::HEAD | Method::PUT | Method::DELETE);
+        let mut token = self.token().await?;
+        let mut refreshed = false;
+        loop {
+            let response = self
+                .send(
+                    || {
+                        let request = self.inner.http.request(method.clone(), self.url(path)).bearer_auth(&token);
+                        match body {
+                            Some(body) => request.json(body),
+                            None => request,
+                        }
+                    },
+                    idempotent,
+                )
+                .await?;
+
+            // A token the server refused early gets one refresh
+            if response.status() == StatusCode::UNAUTHORIZED {
+                if refreshed {
+                    return Err(SdkError::SessionExpired);
+                }
+                refreshed = true;
+                token = self.refresh_from(&token).await?;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+            return Ok(response.json().await?);
+        }
+    }
+
+    /// Send a request until it gets an answer that is not worth retrying
+    async fn send(&self, build: impl Fn() -> RequestBuilder, idempotent: bool) -> SdkResult<Response> {
+        let policy = self.inner.retry;
+        let mut attempt = 1;
+        loop {
+            let (delay, error) = match build().send().await {
+                Ok(response) if retryable_status(response.status(), idempotent) => {
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse().ok())
+                        .map(Duration::from_secs);
+                    match policy.delay(attempt, retry_after) {
+                        Some(delay) => (delay, format!("answered {}", response.status())),
+                        None => return Ok(response),
+                    }
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if retryable_error(&e, idempotent) => match policy.delay(attempt, None) {
+                    Some(delay) => (delay, e.to_string()),
+                    None => return Err(e.into()),
+                },
+                Err(e) => return Err(e.into()),
+            };
+            tracing::debug!("Attempt {} {}, retrying in {:?}", attempt, error, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// The token of a successful login or refresh
+async fn auth_token(response: Response) -> SdkResult<String> {
+    if !response.status().is_success() {
+        return Err(api_error(response).await);
+    }
+    let body: AuthResponse = response.json().await?;
+    match body.token {
+        Some(token) if body.success => Ok(token),
+        _ => Err(SdkError::Protocol(format!("no token in auth answer: {}", body.message))),
+    }
+}
+
+/// The server's `message` or `error`, or the status reason when the body has
+/// neither
+async fn api_error(response: Response) -> SdkError {
+    let status = response.status();
+    let body: Option<Value> = response.json().await.ok();
+    let message = body
+        .as_ref()
+        .and_then(|body| body.get("message").or_else(|| body.get("error")))
+        .and_then(|message| message.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| status.canonical_reason().unwrap_or("request failed").to_string());
+    SdkError::Api { status: status.as_u16(), message }
+}
@@ -0,0 +1,35 @@
+//! SDK errors
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SdkError {
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The server answered with an error status
+    #[error("Server answered {status}: {message}")]
+    Api { status: u16, message: String },
+    #[error("Not signed in")]
+    NotSignedIn,
+    /// The token expired and the server would not refresh it; sign in again
+    #[error("Session expired")]
+    SessionExpired,
+    #[error("Socket error: {0}")]
+    Socket(#[from] Box<tokio_tungstenite::tungstenite::Error>),
+    /// The socket refused the session token
+    #[error("Socket authentication failed: {0}")]
+    SocketAuth(String),
+    /// The server sent something the protocol does not allow
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("Invalid URL: {0}")]
+    Url(String),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for SdkError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        SdkError::Socket(Box::new(e))
+    }
+}
+
+pub type SdkResult<T> = Result<T, SdkError>;
@@ -0,0 +1,178 @@
+//! The game socket
+
+use crate::client::Client;
+use crate::error::{SdkError, SdkResult};
+use crate::protocol::{ClientEvent, ControlEvent, ServerEvent};
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Events on the channels the stream subscribed to. A dropped socket is
+/// reopened under the client's retry policy, signed in with a fresh token
+/// and subscribed again; only when that fails does [`next`](Self::next)
+/// give an error, after which the stream ends.
+pub struct EventStream {
+    client: Client,
+    channels: Vec<String>,
+    socket: Option<Socket>,
+    user_id: i64,
+    /// Connects or connections lost in a row without an event in between
+    failures: u32,
+    closed: bool,
+}
+
+impl EventStream {
+    pub(crate) async fn open(client: Client, channels: Vec<String>) -> SdkResult<EventStream> {
+        let mut stream = EventStream { client, channels, socket: None, user_id: 0, failures: 0, closed: false };
+        stream.reconnect().await?;
+        Ok(stream)
+    }
+
+    /// The player the socket is signed in as
+    pub fn user_id(&self) -> i64 {
+        self.user_id
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    /// The next event, including the server's replies to subscriptions.
+    /// `None` once the stream is closed.
+    pub async fn next(&mut self) -> Option<SdkResult<ServerEvent>> {
+        loop {
+            if self.closed {
+                return None;
+            }
+            let Some(socket) = self.socket.as_mut() else {
+                if let Err(e) = self.reconnect().await {
+                    self.closed = true;
+                    return Some(Err(e));
+                }
+                continue;
+            };
+
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    self.failures = 0;
+                    return Some(ServerEvent::decode(&text).map_err(|e| SdkError::Protocol(e.to_string())));
+                }
+                Some(Ok(Message::Binary(_))) => {
+                    return Some(Err(SdkError::Protocol("binary frame on a JSON socket".to_string())));
+                }
+                // Pings are answered by the socket itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Close(_))) | None => {
+                    tracing::debug!("Socket closed by the server, reconnecting");
+                    self.socket = None;
+                    self.failures += 1;
+                }
+                Some(Err(e)) => {
+                    tracing::debug!("Socket failed, reconnecting: {}", e);
+                    self.socket = None;
+                    self.failures += 1;
+                }
+            }
+        }
+    }
+
+    /// Watch another channel; the server's reply comes as an event
+    pub async fn subscribe(&mut self, channel: &str) -> SdkResult<()> {
+        if !self.channels.iter().any(|known| known == channel) {
+            self.channels.push(channel.to_string());
+        }
+        self.send(&ClientEvent::Subscribe { channel: channel.to_string() }).await
+    }
+
+    pub async fn unsubscribe(&mut self, channel: &str) -> SdkResult<()> {
+        self.channels.retain(|known| known != channel);
+        self.send(&ClientEvent::Unsubscribe { channel: channel.to_string() }).await
+    }
+
+    /// Close the socket; the stream ends
+    pub async fn close(&mut self) {
+        self.closed = true;
+        if let Some(mut socket) = self.socket.take() {
+            let _ = socket.close(None).await;
+        }
+    }
+
+    async fn send(&mut self, event: &ClientEvent) -> SdkResult<()> {
+        // A message sent while reconnecting is covered by the resubscribe
+        let Some(socket) = self.socket.as_mut() else {
+            return Ok(());
+        };
+        let text = serde_json::to_string(event).map_err(|e| SdkError::Protocol(e.to_string()))?;
+        socket.send(Message::Text(text)).await?;
+        Ok(())
+    }
+
+    /// Connect until it works or the retry policy gives up. A refused token
+    /// is not retried.
+    async fn reconnect(&mut self) -> SdkResult<()> {
+        let policy = self.client.retry_policy();
+        let mut last_error = None;
+        loop {
+            if self.failures > 0 {
+                match policy.delay(self.failures, None) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => {
+                        return Err(last_error.unwrap_or_else(|| SdkError::Protocol("socket keeps closing".to_string())))
+                    }
+                }
+            }
+            match self.connect().await {
+                Ok((socket, user_id)) => {
+                    self.socket = Some(socket);
+                    self.user_id = user_id;
+                    return Ok(());
+                }
+                Err(e @ (SdkError::SocketAuth(_) | SdkError::NotSignedIn | SdkError::SessionExpired)) => return Err(e),
+                Err(e) => {
+                    tracing::debug!("Socket connect failed: {}", e);
+                    self.failures += 1;
+                    last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Open the socket, sign in and subscribe
+    async fn connect(&self) -> SdkResult<(Socket, i64)> {
+        let token = self.client.token().await?;
+        let (mut socket, _) = tokio_tungstenite::connect_async(self.client.ws_url()).await?;
+
+        let auth = serde_json::to_string(&ClientEvent::auth(&token)).map_err(|e| SdkError::Protocol(e.to_string()))?;
+        socket.send(Message::Text(auth)).await?;
+        let user_id = loop {
+            let text = match socket.next().await {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err(SdkError::Protocol("socket closed before signing in".to_string()))
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.into()),
+            };
+            match ServerEvent::decode(&text) {
+                Ok(ServerEvent::Control(ControlEvent::AuthSuccess { user_id, .. })) => break user_id,
+                Ok(ServerEvent::Control(ControlEvent::AuthError { error })) => return Err(SdkError::SocketAuth(error)),
+                Ok(ServerEvent::Control(ControlEvent::ProtocolError { error, .. })) => {
+                    return Err(SdkError::Protocol(error))
+                }
+                // Broadcasts sent before the sign-in was answered
+                Ok(_) => continue,
+                Err(e) => return Err(SdkError::Protocol(e.to_string())),
+            }
+        };
+
+        for channel in &self.channels {
+            let subscribe = ClientEvent::Subscribe { channel: channel.clone() };
+            let text = serde_json::to_string(&subscribe).map_err(|e| SdkError::Protocol(e.to_string()))?;
+            socket.send(Message::Text(text)).await?;
+        }
+        Ok((socket, user_id))
+    }
+}
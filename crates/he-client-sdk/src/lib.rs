@@ -0,0 +1,48 @@
+//! # HackerExperience client SDK
+//!
+//! An async client for the REST API and the game socket, for desktop and
+//! terminal clients that would otherwise have to reverse-engineer both.
+//!
+//! ```no_run
+//! use he_client_sdk::{Client, ServerEvent, StartProcess};
+//!
+//! # async fn play() -> he_client_sdk::SdkResult<()> {
+//! let client = Client::builder("https://play.example.com").build()?;
+//! client.login("player@example.com", "hunter2").await?;
+//!
+//! let started = client.start_process(&StartProcess::new("crack").target("10.0.0.2")).await?;
+//! let mut events = client.subscribe_events(&[&format!("process:{}", started.pid)]).await?;
+//! while let Some(event) = events.next().await {
+//!     if let ServerEvent::Process(process) = event? {
+//!         println!("{:?}", process);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The session token is refreshed before it expires, and once more if the
+//! server still turns it down. Requests the server failed to take are
+//! retried under a [`RetryPolicy`]; ones that may have been carried out,
+//! like a process start answered with a 500, are only retried when they are
+//! safe to repeat. A dropped socket reconnects, signs in again and
+//! resubscribes by itself.
+//!
+//! The socket messages mirror the server's typed protocol, whose document is
+//! served at `/api/ws-protocol`; the contract tests hold them to the
+//! server's golden samples.
+
+pub mod client;
+pub mod error;
+pub mod events;
+pub mod protocol;
+pub mod retry;
+pub mod session;
+pub mod types;
+
+pub use client::{Client, ClientBuilder};
+pub use error::{SdkError, SdkResult};
+pub use events::EventStream;
+pub use protocol::{ClientEvent, ControlEvent, ProcessEvent, ServerEvent};
+pub use retry::RetryPolicy;
+pub use types::{Cancelled, HostedProcess, ProcessInfo, ProcessList, StartProcess, StartedProcess};
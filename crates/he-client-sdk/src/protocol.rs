@@ -0,0 +1,235 @@
+//! Socket messages
+//!
+//! What the client sends mirrors the server's `ClientEvent`, and the
+//! server's replies its `ControlEvent`, field for field. Of the game events
+//! only the process lifecycle is typed; everything else arrives as
+//! [`ServerEvent::Other`] with its payload, so a server that adds events
+//! never breaks an older client.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Client -> Server messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "msg_type", content = "data", rename_all = "snake_case")]
+pub enum ClientEvent {
+    Auth {
+        token: String,
+        /// The SDK always speaks `json`
+        encoding: Option<String>,
+        compression: Option<String>,
+        /// Have the server refuse fields it does not know
+        #[serde(default)]
+        strict: bool,
+    },
+    Subscribe {
+        channel: String,
+    },
+    Unsubscribe {
+        channel: String,
+    },
+}
+
+impl ClientEvent {
+    /// Sign the socket in with JSON frames, uncompressed and strict
+    pub fn auth(token: &str) -> Self {
+        ClientEvent::Auth {
+            token: token.to_string(),
+            encoding: Some("json".to_string()),
+            compression: Some("none".to_string()),
+            strict: true,
+        }
+    }
+}
+
+/// Server -> Client replies to client messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event_type", content = "data", rename_all = "snake_case")]
+pub enum ControlEvent {
+    AuthSuccess {
+        user_id: i64,
+        message: String,
+        encoding: String,
+        compression: bool,
+        strict: bool,
+    },
+    AuthError {
+        error: String,
+    },
+    Subscribed {
+        channel: String,
+    },
+    /// The channel is not the player's to watch
+    SubscribeDenied {
+        channel: String,
+    },
+    Unsubscribed {
+        channel: String,
+    },
+    /// A message the client sent could not be read
+    ProtocolError {
+        msg_type: String,
+        error: String,
+    },
+}
+
+impl ControlEvent {
+    const TYPES: [&'static str; 6] =
+        ["auth_success", "auth_error", "subscribed", "subscribe_denied", "unsubscribed", "protocol_error"];
+}
+
+/// A process the player can see started, moved or ended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum ProcessEvent {
+    ProcessStarted {
+        pid: i64,
+        process_type: String,
+        estimated_time: u64,
+    },
+    ProcessProgress {
+        pid: i64,
+        progress: f32,
+        remaining_time: u64,
+    },
+    ProcessCompleted {
+        pid: i64,
+        process_type: String,
+        result: String,
+    },
+    ProcessCancelled {
+        pid: i64,
+    },
+    /// A random event changed the process; no `remaining_time` means it
+    /// killed it
+    ProcessComplication {
+        pid: i64,
+        process_type: String,
+        complication: String,
+        message: String,
+        remaining_time: Option<u64>,
+    },
+}
+
+impl ProcessEvent {
+    pub fn pid(&self) -> i64 {
+        match self {
+            ProcessEvent::ProcessStarted { pid, .. }
+            | ProcessEvent::ProcessProgress { pid, .. }
+            | ProcessEvent::ProcessCompleted { pid, .. }
+            | ProcessEvent::ProcessCancelled { pid }
+            | ProcessEvent::ProcessComplication { pid, .. } => *pid,
+        }
+    }
+
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            ProcessEvent::ProcessStarted { .. } => "process_started",
+            ProcessEvent::ProcessProgress { .. } => "process_progress",
+            ProcessEvent::ProcessCompleted { .. } => "process_completed",
+            ProcessEvent::ProcessCancelled { .. } => "process_cancelled",
+            ProcessEvent::ProcessComplication { .. } => "process_complication",
+        }
+    }
+
+    /// Whether the process is gone after this event
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            ProcessEvent::ProcessCompleted { .. }
+                | ProcessEvent::ProcessCancelled { .. }
+                | ProcessEvent::ProcessComplication { remaining_time: None, .. }
+        )
+    }
+}
+
+/// Anything the server sends
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    Control(ControlEvent),
+    Process(ProcessEvent),
+    /// A game event the SDK does not type; `data` is its `{type, data}`
+    /// payload as sent
+    Other { event_type: String, data: Value },
+}
+
+/// The envelope every server message comes in
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    event_type: String,
+    data: Value,
+}
+
+impl ServerEvent {
+    /// Read one message. Only a message that is not an envelope at all, or
+    /// a reply whose fields do not match, is an error; a process event the
+    /// SDK cannot read comes through as [`ServerEvent::Other`].
+    pub fn decode(text: &str) -> Result<ServerEvent, serde_json::Error> {
+        let envelope: Envelope = serde_json::from_str(text)?;
+        if ControlEvent::TYPES.contains(&envelope.event_type.as_str()) {
+            return serde_json::from_value(serde_json::to_value(envelope)?).map(ServerEvent::Control);
+        }
+        if envelope.event_type.starts_with("process_") {
+            if let Ok(event) = ProcessEvent::deserialize(&envelope.data) {
+                return Ok(ServerEvent::Process(event));
+            }
+        }
+        Ok(ServerEvent::Other { event_type: envelope.event_type, data: envelope.data })
+    }
+
+    pub fn event_type(&self) -> String {
+        match self {
+            ServerEvent::Control(event) => Envelope::from_control(event).event_type,
+            ServerEvent::Process(event) => event.event_type().to_string(),
+            ServerEvent::Other { event_type, .. } => event_type.clone(),
+        }
+    }
+
+    /// The message as the server sends it
+    pub fn to_value(&self) -> Value {
+        let envelope = match self {
+            ServerEvent::Control(event) => Envelope::from_control(event),
+            ServerEvent::Process(event) => Envelope {
+                event_type: event.event_type().to_string(),
+                data: serde_json::to_value(event).expect("process events serialize"),
+            },
+            ServerEvent::Other { event_type, data } => Envelope { event_type: event_type.clone(), data: data.clone() },
+        };
+        serde_json::to_value(envelope).expect("envelopes serialize")
+    }
+}
+
+impl Envelope {
+    fn from_control(event: &ControlEvent) -> Envelope {
+        serde_json::to_value(event)
+            .and_then(serde_json::from_value)
+            .expect("control events serialize to envelopes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_events_pass_through() {
+        let text = r#"{"event_type":"heist_loot","data":{"type":"HeistLoot","data":{"amount":5}}}"#;
+        let event = ServerEvent::decode(text).unwrap();
+        assert_eq!(event.event_type(), "heist_loot");
+        assert_eq!(event.to_value(), serde_json::from_str::<Value>(text).unwrap());
+    }
+
+    #[test]
+    fn test_final_process_events() {
+        let killed = ProcessEvent::ProcessComplication {
+            pid: 3,
+            process_type: "Download".to_string(),
+            complication: "power_cut".to_string(),
+            message: "The power went out".to_string(),
+            remaining_time: None,
+        };
+        assert!(killed.is_final());
+        assert!(!ProcessEvent::ProcessProgress { pid: 3, progress: 0.5, remaining_time: 9 }.is_final());
+        assert_eq!(killed.pid(), 3);
+    }
+}
@@ -0,0 +1,93 @@
+//! When and how often failed requests are sent again
+
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Exponential backoff between attempts of one request or socket reconnect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first one included; 1 never retries
+    pub max_attempts: u32,
+    /// Wait after the first failure, doubled after each one after it
+    pub base_delay: Duration,
+    /// Longest wait, also the cap on a server's `Retry-After`
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Every request is tried once
+    pub fn none() -> Self {
+        Self { max_attempts: 1, ..Self::default() }
+    }
+
+    /// How long to wait after `attempt` (counted from 1) failed, or `None`
+    /// when it was the last one. A `Retry-After` from the server wins over
+    /// the backoff, up to `max_delay`.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        Some(retry_after.unwrap_or(backoff).min(self.max_delay))
+    }
+}
+
+/// Whether a request answered with `status` may be sent again. 429 and 503
+/// mean the server turned it away untouched; other gateway and server
+/// errors may have come after it was carried out, so only requests that
+/// are safe to repeat are retried on them.
+pub(crate) fn retryable_status(status: StatusCode, idempotent: bool) -> bool {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => true,
+        StatusCode::INTERNAL_SERVER_ERROR | StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT => idempotent,
+        _ => false,
+    }
+}
+
+/// Whether a request that got no answer may be sent again: one that never
+/// connected can be, one that timed out only if it is safe to repeat
+pub(crate) fn retryable_error(error: &reqwest::Error, idempotent: bool) -> bool {
+    error.is_connect() || (idempotent && error.is_timeout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_backs_off_and_stops() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, None), Some(Duration::from_millis(250)));
+        assert_eq!(policy.delay(2, None), Some(Duration::from_millis(500)));
+        assert_eq!(policy.delay(3, None), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(4, None), None);
+        assert_eq!(RetryPolicy::none().delay(1, None), None);
+
+        // Retry-After wins, capped at the longest wait
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(3))), Some(Duration::from_secs(3)));
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(60))), Some(Duration::from_secs(10)));
+
+        let patient = RetryPolicy { max_attempts: 40, ..policy };
+        assert_eq!(patient.delay(39, None), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_only_untouched_requests_are_always_retried() {
+        assert!(retryable_status(StatusCode::SERVICE_UNAVAILABLE, false));
+        assert!(retryable_status(StatusCode::TOO_MANY_REQUESTS, false));
+        assert!(!retryable_status(StatusCode::BAD_GATEWAY, false));
+        assert!(retryable_status(StatusCode::BAD_GATEWAY, true));
+        assert!(!retryable_status(StatusCode::BAD_REQUEST, true));
+        assert!(!retryable_status(StatusCode::UNAUTHORIZED, true));
+    }
+}
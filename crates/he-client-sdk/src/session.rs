@@ -0,0 +1,67 @@
+//! The signed-in session and when its token needs refreshing
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A session token and when it expires, if the token says
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub token: String,
+    /// Unix seconds
+    pub expires_at: Option<u64>,
+}
+
+impl Session {
+    pub fn new(token: String) -> Self {
+        let expires_at = token_expiry(&token);
+        Self { token, expires_at }
+    }
+
+    /// Whether the token expires within `margin` of `now`. A token that
+    /// does not say when it expires is used until the server refuses it.
+    pub fn needs_refresh(&self, now: SystemTime, margin: Duration) -> bool {
+        let Some(expires_at) = self.expires_at else {
+            return false;
+        };
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        now + margin >= Duration::from_secs(expires_at)
+    }
+}
+
+/// The `exp` claim of a JWT. The signature is not checked; the client only
+/// needs to know when to refresh, and the server checks the rest.
+pub fn token_expiry(token: &str) -> Option<u64> {
+    let payload = token.split('.').nth(1)?;
+    let claims: serde_json::Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?).ok()?;
+    claims.get("exp")?.as_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(claims: serde_json::Value) -> String {
+        format!("eyJhbGciOiJIUzI1NiJ9.{}.signature", URL_SAFE_NO_PAD.encode(claims.to_string()))
+    }
+
+    #[test]
+    fn test_refresh_ahead_of_expiry() {
+        let session = Session::new(token(serde_json::json!({ "sub": "42", "exp": 1_000_000 })));
+        assert_eq!(session.expires_at, Some(1_000_000));
+
+        let margin = Duration::from_secs(60);
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        assert!(!session.needs_refresh(at(999_939), margin));
+        assert!(session.needs_refresh(at(999_940), margin));
+        assert!(session.needs_refresh(at(2_000_000), margin));
+    }
+
+    #[test]
+    fn test_opaque_tokens_never_refresh_early() {
+        assert_eq!(token_expiry("not-a-jwt"), None);
+        assert_eq!(token_expiry(&token(serde_json::json!({ "sub": "42" }))), None);
+        let session = Session::new("opaque".to_string());
+        assert!(!session.needs_refresh(SystemTime::now(), Duration::from_secs(60)));
+    }
+}
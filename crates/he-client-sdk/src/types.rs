@@ -0,0 +1,95 @@
+//! REST request and response bodies
+
+use serde::{Deserialize, Serialize};
+
+/// Start a process on the player's gateway, or on a server they own or
+/// hacked
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StartProcess {
+    pub process_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_pc_id: Option<String>,
+    /// Run on this server instead of the gateway
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_ip: Option<String>,
+    /// Start in a process slot held by this reservation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reservation_id: Option<i64>,
+}
+
+impl StartProcess {
+    pub fn new(process_type: impl Into<String>) -> Self {
+        Self { process_type: process_type.into(), ..Self::default() }
+    }
+
+    pub fn target(mut self, ip: impl Into<String>) -> Self {
+        self.target_pc_id = Some(ip.into());
+        self
+    }
+
+    pub fn host(mut self, ip: impl Into<String>) -> Self {
+        self.host_ip = Some(ip.into());
+        self
+    }
+
+    pub fn reservation(mut self, reservation_id: i64) -> Self {
+        self.reservation_id = Some(reservation_id);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StartedProcess {
+    pub pid: i64,
+    /// Seconds until it completes
+    pub duration: i32,
+    pub cpu_usage: i32,
+    pub ram_usage: i32,
+    pub net_usage: i32,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: i64,
+    pub process_type: String,
+    pub pc_id: String,
+    pub target_pc_id: Option<String>,
+    pub start_time: String,
+    pub end_time: String,
+    pub priority: i32,
+}
+
+/// Another player's process running on one of the player's servers
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HostedProcess {
+    pub pid: i64,
+    pub process_type: String,
+    pub host_ip: String,
+    pub origin_ip: Option<String>,
+    pub target_pc_id: Option<String>,
+    pub start_time: String,
+    pub end_time: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProcessList {
+    pub processes: Vec<ProcessInfo>,
+    #[serde(default)]
+    pub hosted: Vec<HostedProcess>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Cancelled {
+    pub message: String,
+    /// The refund or penalty settled for the work already done
+    pub cancellation: serde_json::Value,
+}
+
+/// What the auth endpoints answer
+#[derive(Debug, Deserialize)]
+pub(crate) struct AuthResponse {
+    pub success: bool,
+    pub token: Option<String>,
+    pub message: String,
+}
@@ -0,0 +1,239 @@
+//! The client against an in-process stub of the API and socket
+//!
+//! The stub answers with the same bodies the server does and pushes the
+//! server's golden samples over the socket.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use he_client_sdk::{Client, ControlEvent, ProcessEvent, RetryPolicy, SdkError, ServerEvent, StartProcess};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SERVER_GOLDEN: &str = include_str!("../../he-websocket/tests/golden/server_events.json");
+
+#[derive(Default)]
+struct Stub {
+    /// The only token the API takes
+    accepted: Mutex<String>,
+    /// Seconds new tokens are good for
+    lifetime: AtomicU32,
+    issued: AtomicU32,
+    logins: AtomicU32,
+    refreshes: AtomicU32,
+    starts: AtomicU32,
+    sockets: AtomicU32,
+}
+
+impl Stub {
+    fn issue(&self) -> String {
+        let n = self.issued.fetch_add(1, Ordering::SeqCst);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let claims = json!({ "sub": "42", "n": n, "exp": now + self.lifetime.load(Ordering::SeqCst) as u64 });
+        let token = format!("eyJhbGciOiJIUzI1NiJ9.{}.signature", URL_SAFE_NO_PAD.encode(claims.to_string()));
+        *self.accepted.lock().unwrap() = token.clone();
+        token
+    }
+
+    fn signed_in(&self, headers: &HeaderMap) -> bool {
+        let bearer = headers
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        bearer == Some(self.accepted.lock().unwrap().as_str())
+    }
+}
+
+fn sample(name: &str) -> String {
+    let samples: BTreeMap<String, Value> = serde_json::from_str(SERVER_GOLDEN).unwrap();
+    samples[name].to_string()
+}
+
+fn unauthorized() -> Response {
+    (StatusCode::UNAUTHORIZED, Json(json!({ "success": false, "message": "Unauthorized" }))).into_response()
+}
+
+async fn login(State(stub): State<Arc<Stub>>, Json(body): Json<Value>) -> Response {
+    stub.logins.fetch_add(1, Ordering::SeqCst);
+    if body["password"] != "hunter2" {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "success": false, "token": null, "message": "Authentication failed" })),
+        )
+            .into_response();
+    }
+    Json(json!({ "success": true, "token": stub.issue(), "message": "Login successful" })).into_response()
+}
+
+async fn refresh(State(stub): State<Arc<Stub>>) -> Response {
+    stub.refreshes.fetch_add(1, Ordering::SeqCst);
+    Json(json!({ "success": true, "token": stub.issue(), "message": "Token refreshed" })).into_response()
+}
+
+async fn processes(State(stub): State<Arc<Stub>>, headers: HeaderMap) -> Response {
+    if !stub.signed_in(&headers) {
+        return unauthorized();
+    }
+    Json(json!({ "success": true, "processes": [], "hosted": [] })).into_response()
+}
+
+async fn start(State(stub): State<Arc<Stub>>, headers: HeaderMap, Json(body): Json<Value>) -> Response {
+    if !stub.signed_in(&headers) {
+        return unauthorized();
+    }
+    // The first start is turned away, as by a busy node
+    if stub.starts.fetch_add(1, Ordering::SeqCst) == 0 {
+        return (StatusCode::SERVICE_UNAVAILABLE, [("retry-after", "0")], "busy").into_response();
+    }
+    if body["process_type"] == "explode" {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "success": false, "message": "Failed to create process" })))
+            .into_response();
+    }
+    Json(json!({
+        "success": true,
+        "pid": 1001,
+        "duration": 120,
+        "cpu_usage": 40,
+        "ram_usage": 512,
+        "net_usage": 5,
+        "message": "Process crack started (ETA: 120 seconds)"
+    }))
+    .into_response()
+}
+
+async fn socket(State(stub): State<Arc<Stub>>, upgrade: WebSocketUpgrade) -> Response {
+    let connection = stub.sockets.fetch_add(1, Ordering::SeqCst);
+    upgrade.on_upgrade(move |socket| play(socket, connection))
+}
+
+/// Sign in and subscribe, then push one process event; the first
+/// connection is dropped after it
+async fn play(mut socket: WebSocket, connection: u32) {
+    let Some(Ok(Message::Text(auth))) = socket.recv().await else { return };
+    let auth: Value = serde_json::from_str(&auth).unwrap();
+    assert_eq!(auth["msg_type"], "auth");
+    assert_eq!(auth["data"]["encoding"], "json");
+    assert_eq!(auth["data"]["strict"], true);
+    let success = json!({
+        "event_type": "auth_success",
+        "data": { "user_id": 42, "message": "Authentication successful", "encoding": "json", "compression": false, "strict": true }
+    });
+    socket.send(Message::Text(success.to_string())).await.unwrap();
+
+    let Some(Ok(Message::Text(subscribe))) = socket.recv().await else { return };
+    let subscribe: Value = serde_json::from_str(&subscribe).unwrap();
+    let reply = json!({ "event_type": "subscribed", "data": { "channel": subscribe["data"]["channel"] } });
+    socket.send(Message::Text(reply.to_string())).await.unwrap();
+
+    let event = if connection == 0 { "process_progress" } else { "process_completed" };
+    socket.send(Message::Text(sample(event))).await.unwrap();
+    if connection == 0 {
+        let _ = socket.close().await;
+    } else {
+        while let Some(Ok(_)) = socket.recv().await {}
+    }
+}
+
+async fn serve(lifetime: u32) -> (Arc<Stub>, Client) {
+    let stub = Arc::new(Stub::default());
+    stub.lifetime.store(lifetime, Ordering::SeqCst);
+    let app = Router::new()
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/refresh", post(refresh))
+        .route("/api/processes", get(processes).post(start))
+        .route("/ws", get(socket))
+        .with_state(stub.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let retry = RetryPolicy { max_attempts: 3, base_delay: Duration::from_millis(10), max_delay: Duration::from_millis(50) };
+    let client = Client::builder(format!("http://{}", address)).retry(retry).build().unwrap();
+    (stub, client)
+}
+
+#[tokio::test]
+async fn test_login_and_refresh_ahead_of_expiry() {
+    let (stub, client) = serve(3600).await;
+    assert!(matches!(client.processes().await, Err(SdkError::NotSignedIn)));
+    let refused = client.login("player@example.com", "wrong").await;
+    assert!(matches!(refused, Err(SdkError::Api { status: 401, .. })));
+
+    client.login("player@example.com", "hunter2").await.unwrap();
+    assert!(client.processes().await.unwrap().processes.is_empty());
+    assert_eq!(stub.refreshes.load(Ordering::SeqCst), 0);
+
+    // Tokens inside the refresh margin are refreshed before they are used
+    stub.lifetime.store(30, Ordering::SeqCst);
+    client.refresh().await.unwrap();
+    client.processes().await.unwrap();
+    assert_eq!(stub.refreshes.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_refused_token_is_refreshed_once() {
+    let (stub, client) = serve(3600).await;
+    client.login("player@example.com", "hunter2").await.unwrap();
+
+    // The server revoked the token early
+    *stub.accepted.lock().unwrap() = String::new();
+    client.processes().await.unwrap();
+    assert_eq!(stub.refreshes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_start_process_retries_only_untouched_answers() {
+    let (stub, client) = serve(3600).await;
+    client.login("player@example.com", "hunter2").await.unwrap();
+
+    let started = client.start_process(&StartProcess::new("crack").target("10.0.0.2")).await.unwrap();
+    assert_eq!(started.pid, 1001);
+    assert_eq!(started.duration, 120);
+    assert_eq!(stub.starts.load(Ordering::SeqCst), 2);
+
+    // A 500 may have come after the process started, so it is not retried
+    let failed = client.start_process(&StartProcess::new("explode")).await;
+    match failed {
+        Err(SdkError::Api { status, message }) => {
+            assert_eq!(status, 500);
+            assert_eq!(message, "Failed to create process");
+        }
+        other => panic!("expected an API error, got {:?}", other.map(|started| started.pid)),
+    }
+    assert_eq!(stub.starts.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_events_resubscribe_after_a_dropped_socket() {
+    let (stub, client) = serve(3600).await;
+    client.login("player@example.com", "hunter2").await.unwrap();
+
+    let mut events = client.subscribe_events(&["process:1001"]).await.unwrap();
+    assert_eq!(events.user_id(), 42);
+
+    let mut received = Vec::new();
+    while received.len() < 4 {
+        received.push(events.next().await.unwrap().unwrap());
+    }
+    let subscribed = ServerEvent::Control(ControlEvent::Subscribed { channel: "process:1001".to_string() });
+    assert_eq!(received[0], subscribed);
+    assert!(matches!(received[1], ServerEvent::Process(ProcessEvent::ProcessProgress { pid: 1001, .. })));
+    // The server dropped the socket; the stream signed in and subscribed again
+    assert_eq!(received[2], subscribed);
+    match &received[3] {
+        ServerEvent::Process(event) => assert!(event.is_final()),
+        other => panic!("expected a process event, got {:?}", other),
+    }
+    assert_eq!(stub.sockets.load(Ordering::SeqCst), 2);
+
+    events.close().await;
+    assert!(events.next().await.is_none());
+}
@@ -0,0 +1,51 @@
+//! The SDK's socket messages against the server's golden samples
+//!
+//! The samples are the files the server's own contract tests check, so a
+//! protocol change the SDK does not follow fails here as well.
+
+use he_client_sdk::{ClientEvent, ServerEvent};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+const CLIENT_GOLDEN: &str = include_str!("../../he-websocket/tests/golden/client_events.json");
+const SERVER_GOLDEN: &str = include_str!("../../he-websocket/tests/golden/server_events.json");
+
+fn golden(text: &str) -> BTreeMap<String, Value> {
+    serde_json::from_str(text).expect("golden files are JSON objects")
+}
+
+#[test]
+fn test_client_events_match_the_server() {
+    for (name, sample) in golden(CLIENT_GOLDEN) {
+        let event: ClientEvent = serde_json::from_value(sample.clone())
+            .unwrap_or_else(|e| panic!("client sample {} does not decode: {}", name, e));
+        assert_eq!(serde_json::to_value(&event).unwrap(), sample, "client sample {}", name);
+    }
+}
+
+#[test]
+fn test_every_server_event_decodes_and_round_trips() {
+    let samples = golden(SERVER_GOLDEN);
+    for (name, sample) in &samples {
+        let event = ServerEvent::decode(&sample.to_string())
+            .unwrap_or_else(|e| panic!("server sample {} does not decode: {}", name, e));
+        assert_eq!(&event.event_type(), name);
+        assert_eq!(&event.to_value(), sample, "server sample {}", name);
+    }
+}
+
+#[test]
+fn test_replies_and_process_events_are_typed() {
+    let samples = golden(SERVER_GOLDEN);
+    for (name, sample) in &samples {
+        let event = ServerEvent::decode(&sample.to_string()).unwrap();
+        let typed = match &event {
+            ServerEvent::Control(_) | ServerEvent::Process(_) => true,
+            ServerEvent::Other { .. } => false,
+        };
+        let expected = name.starts_with("process_")
+            || ["auth_success", "auth_error", "subscribed", "subscribe_denied", "unsubscribed", "protocol_error"]
+                .contains(&name.as_str());
+        assert_eq!(typed, expected, "server sample {} decoded as {:?}", name, event);
+    }
+}
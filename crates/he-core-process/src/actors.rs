@@ -11,7 +11,8 @@ use he_core::entities::EntityId;
 use async_trait::async_trait;
 use std::collections::HashMap;
 use tokio::sync::RwLock;
-use chrono::{Utc, Duration};
+use chrono::Duration;
+use he_core::clock::{self, SharedClock};
 use std::sync::Arc;
 use tracing::{info, error, warn, debug};
 use serde::{Serialize, Deserialize};
@@ -166,6 +167,8 @@ pub struct ProcessActor {
     process_hierarchy: Arc<RwLock<HashMap<ProcessId, Vec<ProcessId>>>>,
     /// Server to processes mapping for efficient lookup
     server_processes: Arc<RwLock<HashMap<ServerId, Vec<ProcessId>>>>,
+    /// Time source for process timestamps
+    clock: SharedClock,
 }
 
 impl ProcessActor {
@@ -176,9 +179,16 @@ impl ProcessActor {
             execution_contexts: Arc::new(RwLock::new(HashMap::new())),
             process_hierarchy: Arc::new(RwLock::new(HashMap::new())),
             server_processes: Arc::new(RwLock::new(HashMap::new())),
+            clock: clock::current(),
         }
     }
 
+    /// Use `clock` instead of the installed clock
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
     /// Generate a new unique process ID
     fn generate_process_id(&self) -> ProcessId {
         ProcessId::new()
@@ -209,6 +219,7 @@ impl ProcessActor {
         let processes = self.processes.clone();
         let contexts = self.execution_contexts.clone();
         let resource_allocations = self.resource_allocations.clone();
+        let clock = self.clock.clone();
 
         tokio::spawn(async move {
             let execution_result = {
@@ -262,13 +273,13 @@ impl ProcessActor {
                     if let Some(process) = processes_guard.get_mut(&process_id) {
                         process.state = ProcessState::Completed;
                         process.progress = ProcessProgress::new(1.0); // 100% complete
-                        process.completion_date = Some(Utc::now());
+                        process.completion_date = Some(clock.now());
                         process.time_left = Some(0);
                     }
 
                     if let Some(context) = contexts_guard.get_mut(&process_id) {
                         context.execution_state = ProcessExecutionState::Completing;
-                        context.last_checkpoint = Utc::now();
+                        context.last_checkpoint = clock.now();
                     }
                 }
 
@@ -315,7 +326,7 @@ impl Handler<CreateProcess> for ProcessActor {
         let mut server_processes = self.server_processes.write().await;
         
         let process_id = self.generate_process_id();
-        let now = Utc::now();
+        let now = self.clock.now();
         
         let process = Process {
             process_id,
@@ -392,7 +403,7 @@ impl Handler<UpdateProcess> for ProcessActor {
             process.data = Some(data);
         }
         
-        process.last_checkpoint_time = Utc::now();
+        process.last_checkpoint_time = self.clock.now();
         
         debug!("Process updated: {}", msg.process_id);
         Ok(process.clone())
@@ -423,8 +434,8 @@ impl Handler<StartProcess> for ProcessActor {
         let context = ProcessExecutionContext {
             process_id: msg.process_id,
             allocated_resources: resources.clone(),
-            start_time: Utc::now(),
-            last_checkpoint: Utc::now(),
+            start_time: self.clock.now(),
+            last_checkpoint: self.clock.now(),
             execution_state: ProcessExecutionState::Initializing,
         };
         
@@ -432,7 +443,7 @@ impl Handler<StartProcess> for ProcessActor {
         
         // Update process state
         process.state = ProcessState::Running;
-        process.last_checkpoint_time = Utc::now();
+        process.last_checkpoint_time = self.clock.now();
         
         // Calculate estimated completion time
         let completion_time = self.calculate_completion_time(&process.process_type, &resources);
@@ -464,12 +475,12 @@ impl Handler<PauseProcess> for ProcessActor {
         }
         
         process.state = ProcessState::Paused;
-        process.last_checkpoint_time = Utc::now();
+        process.last_checkpoint_time = self.clock.now();
         
         // Update execution context
         if let Some(context) = contexts.get_mut(&msg.process_id) {
             context.execution_state = ProcessExecutionState::Suspended;
-            context.last_checkpoint = Utc::now();
+            context.last_checkpoint = self.clock.now();
         }
         
         info!("Process paused: {}", msg.process_id);
@@ -491,12 +502,12 @@ impl Handler<ResumeProcess> for ProcessActor {
         }
         
         process.state = ProcessState::Running;
-        process.last_checkpoint_time = Utc::now();
+        process.last_checkpoint_time = self.clock.now();
         
         // Update execution context
         if let Some(context) = contexts.get_mut(&msg.process_id) {
             context.execution_state = ProcessExecutionState::Running;
-            context.last_checkpoint = Utc::now();
+            context.last_checkpoint = self.clock.now();
         }
         
         info!("Process resumed: {}", msg.process_id);
@@ -513,7 +524,7 @@ impl Handler<KillProcess> for ProcessActor {
         
         if let Some(process) = processes.get_mut(&msg.process_id) {
             process.state = ProcessState::Killed;
-            process.completion_date = Some(Utc::now());
+            process.completion_date = Some(self.clock.now());
             process.time_left = Some(0);
             
             // Remove execution context and resources
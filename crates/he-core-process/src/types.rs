@@ -3,6 +3,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use he_core::clock;
 use he_core_server::ServerId;
 use he_core_network::{ConnectionId, NetworkId};
 use he_core_software::FileId;
@@ -291,7 +292,7 @@ impl DynamicResourceAllocation {
         Self {
             base_allocation,
             multiplier: 1.0,
-            last_updated: Utc::now(),
+            last_updated: clock::now(),
         }
     }
     
@@ -306,7 +307,7 @@ impl DynamicResourceAllocation {
     
     pub fn update_multiplier(&mut self, multiplier: f32) {
         self.multiplier = multiplier;
-        self.last_updated = Utc::now();
+        self.last_updated = clock::now();
     }
 }
 
@@ -343,13 +344,13 @@ impl ProcessProgress {
             total_steps: 1,
             bytes_processed: None,
             total_bytes: None,
-            last_updated: Utc::now(),
+            last_updated: clock::now(),
         }
     }
     
     pub fn update_percentage(&mut self, percentage: u8) {
         self.percentage = percentage.min(100);
-        self.last_updated = Utc::now();
+        self.last_updated = clock::now();
     }
     
     pub fn update_step(&mut self, step: String, completed: u32, total: u32) {
@@ -357,7 +358,7 @@ impl ProcessProgress {
         self.steps_completed = completed;
         self.total_steps = total;
         self.percentage = if total > 0 { ((completed * 100) / total) as u8 } else { 0 };
-        self.last_updated = Utc::now();
+        self.last_updated = clock::now();
     }
     
     pub fn is_complete(&self) -> bool {
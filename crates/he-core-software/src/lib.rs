@@ -37,6 +37,7 @@ pub use model::{CryptoKey, File, Software, SoftwareType, TextFile, Virus};
 pub use types::*;
 
 use anyhow::Result;
+use he_core::registry::{BoundedMap, Footprint, RegistryConfig, RegistryLoader, RegistryStats};
 use once_cell::sync::Lazy;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -45,122 +46,197 @@ use tokio::sync::RwLock;
 pub static SOFTWARE_REGISTRY: Lazy<Arc<RwLock<SoftwareRegistry>>> = 
     Lazy::new(|| Arc::new(RwLock::new(SoftwareRegistry::new())));
 
-/// Software registry for tracking software instances, files, and viruses
-#[derive(Debug, Default)]
+/// Environment prefix for the registry limits, see [`RegistryConfig::from_env`]
+const CONFIG_PREFIX: &str = "HE_SOFTWARE_REGISTRY";
+
+/// How often idle entries are expired
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Hot entries preloaded per map at startup
+const WARM_LIMIT: usize = 1_000;
+
+impl Footprint for Software {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.name.len()
+            + self.version.len()
+            + self.modules.len() * std::mem::size_of::<SoftwareModule>()
+    }
+}
+
+impl Footprint for File {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.name.len() + self.path.len() + self.content.len()
+    }
+}
+
+impl Footprint for Virus {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.name.len()
+            + self.collected_data.len() * std::mem::size_of::<VirusCollectionData>()
+    }
+}
+
+impl Footprint for CryptoKey {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.name.len() + self.key_data.len()
+    }
+}
+
+/// Software registry for tracking software instances, files, and viruses.
+/// Each map is bounded; see [`he_core::registry`].
+#[derive(Debug)]
 pub struct SoftwareRegistry {
-    software: dashmap::DashMap<SoftwareId, Arc<Software>>,
-    files: dashmap::DashMap<FileId, Arc<File>>,
-    viruses: dashmap::DashMap<VirusId, Arc<Virus>>,
-    crypto_keys: dashmap::DashMap<CryptoKeyId, Arc<CryptoKey>>,
+    software: Arc<BoundedMap<SoftwareId, Software>>,
+    files: Arc<BoundedMap<FileId, File>>,
+    viruses: Arc<BoundedMap<VirusId, Virus>>,
+    crypto_keys: Arc<BoundedMap<CryptoKeyId, CryptoKey>>,
+}
+
+impl Default for SoftwareRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SoftwareRegistry {
     pub fn new() -> Self {
+        Self::with_config(RegistryConfig::from_env(CONFIG_PREFIX))
+    }
+
+    pub fn with_config(config: RegistryConfig) -> Self {
+        let viruses = BoundedMap::new("software.viruses", config.clone());
+        // Active viruses are collecting and must not be dropped mid-run
+        viruses.pin_when(Virus::is_active);
+
         Self {
-            software: dashmap::DashMap::new(),
-            files: dashmap::DashMap::new(),
-            viruses: dashmap::DashMap::new(),
-            crypto_keys: dashmap::DashMap::new(),
+            software: BoundedMap::new("software.software", config.clone()),
+            files: BoundedMap::new("software.files", config.clone()),
+            viruses,
+            crypto_keys: BoundedMap::new("software.crypto_keys", config),
         }
     }
 
+    /// Reload evicted entries from the database on a miss. Until a loader is
+    /// installed nothing is evicted.
+    pub fn set_loaders(
+        &self,
+        software: Arc<dyn RegistryLoader<SoftwareId, Software>>,
+        files: Arc<dyn RegistryLoader<FileId, File>>,
+        viruses: Arc<dyn RegistryLoader<VirusId, Virus>>,
+        crypto_keys: Arc<dyn RegistryLoader<CryptoKeyId, CryptoKey>>,
+    ) {
+        self.software.set_loader(software);
+        self.files.set_loader(files);
+        self.viruses.set_loader(viruses);
+        self.crypto_keys.set_loader(crypto_keys);
+    }
+
+    /// Preload up to `limit` hot entries per map
+    pub async fn warm(&self, limit: usize) -> Result<()> {
+        self.software.warm(limit).await?;
+        self.files.warm(limit).await?;
+        self.viruses.warm(limit).await?;
+        self.crypto_keys.warm(limit).await?;
+        Ok(())
+    }
+
+    /// Expire idle entries every `interval`
+    pub fn spawn_sweepers(&self, interval: std::time::Duration) {
+        self.software.spawn_sweeper(interval);
+        self.files.spawn_sweeper(interval);
+        self.viruses.spawn_sweeper(interval);
+        self.crypto_keys.spawn_sweeper(interval);
+    }
+
+    pub fn stats(&self) -> Vec<RegistryStats> {
+        vec![
+            self.software.stats(),
+            self.files.stats(),
+            self.viruses.stats(),
+            self.crypto_keys.stats(),
+        ]
+    }
+
     pub async fn register_software(&self, software: Software) -> Arc<Software> {
-        let software_arc = Arc::new(software);
-        self.software.insert(software_arc.software_id.clone(), software_arc.clone());
-        software_arc
+        self.software.insert(software.software_id, software)
     }
 
     pub async fn register_file(&self, file: File) -> Arc<File> {
-        let file_arc = Arc::new(file);
-        self.files.insert(file_arc.file_id.clone(), file_arc.clone());
-        file_arc
+        self.files.insert(file.file_id, file)
     }
 
     pub async fn register_virus(&self, virus: Virus) -> Arc<Virus> {
-        let virus_arc = Arc::new(virus);
-        self.viruses.insert(virus_arc.virus_id.clone(), virus_arc.clone());
-        virus_arc
+        self.viruses.insert(virus.virus_id, virus)
     }
 
     pub async fn register_crypto_key(&self, crypto_key: CryptoKey) -> Arc<CryptoKey> {
-        let crypto_key_arc = Arc::new(crypto_key);
-        self.crypto_keys.insert(crypto_key_arc.key_id.clone(), crypto_key_arc.clone());
-        crypto_key_arc
+        self.crypto_keys.insert(crypto_key.key_id, crypto_key)
     }
 
-    pub async fn get_software(&self, software_id: &SoftwareId) -> Option<Arc<Software>> {
-        self.software.get(software_id).map(|entry| entry.clone())
+    pub async fn get_software(&self, software_id: &SoftwareId) -> Result<Option<Arc<Software>>> {
+        self.software.get_or_load(software_id).await
     }
 
-    pub async fn get_file(&self, file_id: &FileId) -> Option<Arc<File>> {
-        self.files.get(file_id).map(|entry| entry.clone())
+    pub async fn get_file(&self, file_id: &FileId) -> Result<Option<Arc<File>>> {
+        self.files.get_or_load(file_id).await
     }
 
-    pub async fn get_virus(&self, virus_id: &VirusId) -> Option<Arc<Virus>> {
-        self.viruses.get(virus_id).map(|entry| entry.clone())
+    pub async fn get_virus(&self, virus_id: &VirusId) -> Result<Option<Arc<Virus>>> {
+        self.viruses.get_or_load(virus_id).await
     }
 
-    pub async fn get_crypto_key(&self, crypto_key_id: &CryptoKeyId) -> Option<Arc<CryptoKey>> {
-        self.crypto_keys.get(crypto_key_id).map(|entry| entry.clone())
+    pub async fn get_crypto_key(&self, crypto_key_id: &CryptoKeyId) -> Result<Option<Arc<CryptoKey>>> {
+        self.crypto_keys.get_or_load(crypto_key_id).await
     }
 
     pub async fn remove_software(&self, software_id: &SoftwareId) -> Option<Arc<Software>> {
-        self.software.remove(software_id).map(|(_, software)| software)
+        self.software.remove(software_id)
     }
 
     pub async fn remove_file(&self, file_id: &FileId) -> Option<Arc<File>> {
-        self.files.remove(file_id).map(|(_, file)| file)
+        self.files.remove(file_id)
     }
 
     pub async fn remove_virus(&self, virus_id: &VirusId) -> Option<Arc<Virus>> {
-        self.viruses.remove(virus_id).map(|(_, virus)| virus)
+        self.viruses.remove(virus_id)
     }
 
     pub async fn remove_crypto_key(&self, crypto_key_id: &CryptoKeyId) -> Option<Arc<CryptoKey>> {
-        self.crypto_keys.remove(crypto_key_id).map(|(_, key)| key)
+        self.crypto_keys.remove(crypto_key_id)
     }
 
+    /// Software currently in memory; evicted entries are not listed
     pub async fn list_software(&self) -> Vec<Arc<Software>> {
-        self.software.iter().map(|entry| entry.value().clone()).collect()
+        self.software.values()
     }
 
     pub async fn list_files(&self) -> Vec<Arc<File>> {
-        self.files.iter().map(|entry| entry.value().clone()).collect()
+        self.files.values()
     }
 
     pub async fn list_viruses(&self) -> Vec<Arc<Virus>> {
-        self.viruses.iter().map(|entry| entry.value().clone()).collect()
+        self.viruses.values()
     }
 
     pub async fn list_crypto_keys(&self) -> Vec<Arc<CryptoKey>> {
-        self.crypto_keys.iter().map(|entry| entry.value().clone()).collect()
+        self.crypto_keys.values()
     }
 
     /// Get software instances by type
     pub async fn get_software_by_type(&self, software_type: SoftwareType) -> Vec<Arc<Software>> {
-        self.software
-            .iter()
-            .filter(|entry| entry.software_type == software_type)
-            .map(|entry| entry.value().clone())
-            .collect()
+        self.software.filter(|software| software.software_type == software_type)
     }
 
     /// Get files by type
     pub async fn get_files_by_type(&self, file_type: FileType) -> Vec<Arc<File>> {
-        self.files
-            .iter()
-            .filter(|entry| entry.file_type == file_type)
-            .map(|entry| entry.value().clone())
-            .collect()
+        self.files.filter(|file| file.file_type == file_type)
     }
 
-    /// Get active viruses
+    /// Get active viruses. Active viruses are pinned, so the list is complete.
     pub async fn get_active_viruses(&self) -> Vec<Arc<Virus>> {
-        self.viruses
-            .iter()
-            .filter(|entry| entry.is_active())
-            .map(|entry| entry.value().clone())
-            .collect()
+        self.viruses.filter(Virus::is_active)
     }
 }
 
@@ -168,8 +244,14 @@ impl SoftwareRegistry {
 pub async fn init() -> Result<()> {
     tracing::info!("Initializing Core Software subsystem");
     
-    // Initialize the software registry
-    let _registry = SOFTWARE_REGISTRY.clone();
+    // Initialize the software registry. Loaders installed before this point
+    // are used to preload hot entries.
+    let registry = SOFTWARE_REGISTRY.read().await;
+    registry.spawn_sweepers(SWEEP_INTERVAL);
+    if let Err(e) = registry.warm(WARM_LIMIT).await {
+        tracing::warn!("Failed to warm the software registry: {}", e);
+    }
+    drop(registry);
     
     tracing::info!("Core Software subsystem initialized successfully");
     Ok(())
@@ -0,0 +1,256 @@
+//! Request-scoped context
+//!
+//! The web layer builds one [`RequestContext`] per request (who is asking,
+//! which trace it belongs to, their locale and the shard serving them) and
+//! runs the handler inside [`RequestContext::scope`]. Library crates read it
+//! back with [`RequestContext::current`] to tag queries, events and log lines
+//! without depending on the web framework or threading it through every call.
+//!
+//! The context is a tokio task-local: work handed to `tokio::spawn` does not
+//! inherit it unless wrapped in its own `scope`.
+//!
+//! It also carries the request's [`RelayCache`], where henforcer guards keep
+//! the objects they load so later guards in the same request reuse them.
+
+use crate::RequestId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+/// Locale used when the client does not ask for one
+pub const DEFAULT_LOCALE: &str = "en";
+
+tokio::task_local! {
+    static CURRENT: RequestContext;
+}
+
+/// Who a request is for and where it is being served
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestContext {
+    pub request_id: RequestId,
+    /// Trace the request belongs to: the caller's `traceparent` trace id when
+    /// given, otherwise the request id
+    pub trace_id: String,
+    /// Authenticated player, if any
+    pub user_id: Option<i64>,
+    pub locale: String,
+    /// Node serving the request, as `region/node`
+    pub shard: Option<String>,
+    pub client_ip: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// Objects loaded by henforcers during this request
+    #[serde(skip)]
+    pub relay: RelayCache,
+}
+
+type RelayEntry = Arc<dyn Any + Send + Sync>;
+
+/// Objects henforcers loaded during one request, keyed by
+/// `(object_type, id)`. Clones share their entries, so every clone of a
+/// request's context sees the same cache.
+#[derive(Clone, Default)]
+pub struct RelayCache(Arc<Mutex<HashMap<(&'static str, String), RelayEntry>>>);
+
+impl RelayCache {
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<(&'static str, String), RelayEntry>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The cached object, if one of type `T` is stored under the key
+    pub fn get<T: Clone + Send + Sync + 'static>(&self, object_type: &'static str, id: &str) -> Option<T> {
+        let entry = self.entries().get(&(object_type, id.to_string())).cloned()?;
+        entry.downcast_ref::<T>().cloned()
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&self, object_type: &'static str, id: &str, object: T) {
+        self.entries().insert((object_type, id.to_string()), Arc::new(object));
+    }
+
+    /// Drop a cached object; true if there was one
+    pub fn remove(&self, object_type: &'static str, id: &str) -> bool {
+        self.entries().remove(&(object_type, id.to_string())).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl std::fmt::Debug for RelayCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RelayCache").field("entries", &self.len()).finish()
+    }
+}
+
+/// Two caches are equal when they are the same request's
+impl PartialEq for RelayCache {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for RelayCache {}
+
+impl RequestContext {
+    /// A fresh context for an anonymous request
+    pub fn new(request_id: RequestId) -> Self {
+        Self {
+            trace_id: request_id.0.simple().to_string(),
+            request_id,
+            user_id: None,
+            locale: DEFAULT_LOCALE.to_string(),
+            shard: None,
+            client_ip: None,
+            started_at: Utc::now(),
+            relay: RelayCache::default(),
+        }
+    }
+
+    pub fn with_trace(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = trace_id.into();
+        self
+    }
+
+    pub fn with_user(mut self, user_id: Option<i64>) -> Self {
+        self.user_id = user_id;
+        self
+    }
+
+    pub fn with_locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = locale.into();
+        self
+    }
+
+    pub fn with_shard(mut self, shard: Option<String>) -> Self {
+        self.shard = shard;
+        self
+    }
+
+    pub fn with_client_ip(mut self, client_ip: Option<String>) -> Self {
+        self.client_ip = client_ip;
+        self
+    }
+
+    /// Run `fut` with this as the current context
+    pub async fn scope<F: Future>(self, fut: F) -> F::Output {
+        CURRENT.scope(self, fut).await
+    }
+
+    /// The context of the request being served, if any
+    pub fn current() -> Option<RequestContext> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// Borrow the current context without cloning it
+    pub fn with_current<R>(f: impl FnOnce(Option<&RequestContext>) -> R) -> R {
+        let mut f = Some(f);
+        match CURRENT.try_with(|ctx| (f.take().expect("called once"))(Some(ctx))) {
+            Ok(result) => result,
+            Err(_) => (f.take().expect("called once"))(None),
+        }
+    }
+
+    /// Short tag identifying the request, e.g. for `application_name` or log
+    /// prefixes: `req=<id> user=<id>`
+    pub fn tag(&self) -> String {
+        match self.user_id {
+            Some(user_id) => format!("req={} user={}", self.request_id.0.simple(), user_id),
+            None => format!("req={}", self.request_id.0.simple()),
+        }
+    }
+}
+
+/// The trace id out of a W3C `traceparent` header
+/// (`version-traceid-parentid-flags`)
+pub fn parse_traceparent(header: &str) -> Option<String> {
+    let mut parts = header.trim().split('-');
+    let _version = parts.next()?;
+    let trace_id = parts.next()?;
+    let valid = trace_id.len() == 32
+        && trace_id.bytes().all(|b| b.is_ascii_hexdigit())
+        && trace_id.bytes().any(|b| b != b'0');
+    valid.then(|| trace_id.to_ascii_lowercase())
+}
+
+/// The preferred language out of an `Accept-Language` header, reduced to its
+/// primary subtag (`pt-BR;q=0.9` becomes `pt`)
+pub fn parse_locale(accept_language: &str) -> String {
+    accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.trim().split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let primary = tag.split('-').next()?;
+            let valid = !primary.is_empty() && primary.len() <= 8 && primary.bytes().all(|b| b.is_ascii_alphabetic());
+            (valid && quality > 0.0).then(|| (primary.to_ascii_lowercase(), quality))
+        })
+        .fold(None::<(String, f32)>, |best, (tag, q)| match best {
+            Some((_, best_q)) if best_q >= q => best,
+            _ => Some((tag, q)),
+        })
+        .map(|(tag, _)| tag)
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope_sets_current() {
+        assert!(RequestContext::current().is_none());
+
+        let ctx = RequestContext::new(RequestId::new()).with_user(Some(42)).with_locale("pt");
+        let seen = ctx
+            .clone()
+            .scope(async {
+                tokio::task::yield_now().await;
+                RequestContext::current()
+            })
+            .await;
+        assert_eq!(seen, Some(ctx.clone()));
+        assert_eq!(RequestContext::with_current(|c| c.map(|c| c.user_id)), None);
+        assert!(ctx.tag().ends_with(" user=42"));
+    }
+
+    #[test]
+    fn test_relay_cache_is_shared_by_clones() {
+        let ctx = RequestContext::new(RequestId::new());
+        let clone = ctx.clone();
+        clone.relay.insert("standing", "7", 42_i32);
+
+        assert_eq!(ctx.relay.get::<i32>("standing", "7"), Some(42));
+        // Wrong type or key is a miss
+        assert_eq!(ctx.relay.get::<i64>("standing", "7"), None);
+        assert_eq!(ctx.relay.get::<i32>("standing", "8"), None);
+        assert!(ctx.relay.remove("standing", "7"));
+        assert!(clone.relay.is_empty());
+        assert_ne!(ctx, RequestContext::new(ctx.request_id));
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        assert_eq!(parse_locale("pt-BR,pt;q=0.9,en;q=0.8"), "pt");
+        assert_eq!(parse_locale("en;q=0.5, de"), "de");
+        assert_eq!(parse_locale("*"), DEFAULT_LOCALE);
+        assert_eq!(parse_locale(""), DEFAULT_LOCALE);
+
+        assert_eq!(
+            parse_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").as_deref(),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+        assert_eq!(parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"), None);
+        assert_eq!(parse_traceparent("garbage"), None);
+    }
+}
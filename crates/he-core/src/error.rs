@@ -1,34 +1,250 @@
+//! Unified error type
+//!
+//! Crates keep their own `thiserror` enums internally and provide
+//! `From<TheirError> for HeError` next to them, so `?` lifts any of them into
+//! `HeError` at the boundary. Every variant maps to a stable [`ErrorCode`] and an
+//! HTTP status, so handlers don't have to stringify errors themselves.
+//!
+//! Context is attached with [`ResultExt::context`], which wraps the error
+//! instead of replacing it. The source chain stays intact, and a backtrace is
+//! captured when the context is added (if `RUST_BACKTRACE` is set).
+
+use serde::{Deserialize, Serialize};
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error as StdError;
 use thiserror::Error;
 
+/// Boxed error from another crate
+pub type BoxError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// Machine-readable error code, stable across releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    Internal,
+    Database,
+    Unauthenticated,
+    Forbidden,
+    NotFound,
+    Validation,
+    Conflict,
+    InsufficientResources,
+    GameRule,
+    RateLimited,
+    Unavailable,
+    Configuration,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Internal => "INTERNAL",
+            ErrorCode::Database => "DATABASE",
+            ErrorCode::Unauthenticated => "UNAUTHENTICATED",
+            ErrorCode::Forbidden => "FORBIDDEN",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::Validation => "VALIDATION",
+            ErrorCode::Conflict => "CONFLICT",
+            ErrorCode::InsufficientResources => "INSUFFICIENT_RESOURCES",
+            ErrorCode::GameRule => "GAME_RULE",
+            ErrorCode::RateLimited => "RATE_LIMITED",
+            ErrorCode::Unavailable => "UNAVAILABLE",
+            ErrorCode::Configuration => "CONFIGURATION",
+        }
+    }
+
+    pub fn http_status(&self) -> u16 {
+        match self {
+            ErrorCode::Unauthenticated => 401,
+            ErrorCode::Forbidden => 403,
+            ErrorCode::NotFound => 404,
+            ErrorCode::Validation => 400,
+            ErrorCode::Conflict => 409,
+            ErrorCode::InsufficientResources | ErrorCode::GameRule => 422,
+            ErrorCode::RateLimited => 429,
+            ErrorCode::Unavailable => 503,
+            ErrorCode::Internal | ErrorCode::Database | ErrorCode::Configuration => 500,
+        }
+    }
+
+    /// Server-side faults, as opposed to problems with the request
+    pub fn is_server_error(&self) -> bool {
+        self.http_status() >= 500
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum HeError {
     #[error("Database error: {0}")]
     Database(#[from] anyhow::Error),
-    
+
+    #[error("SQL error: {0}")]
+    Sql(#[from] sqlx::Error),
+
     #[error("Authentication failed")]
     AuthenticationFailed,
-    
+
     #[error("User not found")]
     UserNotFound,
-    
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
     #[error("Invalid process: {0}")]
     InvalidProcess(String),
-    
+
     #[error("Insufficient resources: {0}")]
     InsufficientResources(String),
-    
+
     #[error("Permission denied")]
     PermissionDenied,
-    
+
     #[error("Invalid input: {0}")]
     InvalidInput(String),
-    
+
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Rate limit exceeded")]
+    RateLimited,
+
     #[error("Game logic error: {0}")]
     GameLogic(String),
+
+    /// Error from another crate, see [`HeError::domain`]
+    #[error("{domain}: {source}")]
+    Domain {
+        domain: &'static str,
+        code: ErrorCode,
+        #[source]
+        source: BoxError,
+    },
+
+    /// Error wrapped with context, see [`ResultExt`]
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<HeError>,
+        trace: Box<Backtrace>,
+    },
 }
 
 pub type HeResult<T> = Result<T, HeError>;
 
+impl HeError {
+    /// Wrap another crate's error, keeping it as the source
+    pub fn domain(domain: &'static str, code: ErrorCode, source: impl Into<BoxError>) -> Self {
+        HeError::Domain {
+            domain,
+            code,
+            source: source.into(),
+        }
+    }
+
+    /// Add a context message, capturing a backtrace
+    pub fn context(self, context: impl Into<String>) -> Self {
+        HeError::Context {
+            context: context.into(),
+            source: Box::new(self),
+            trace: Box::new(Backtrace::capture()),
+        }
+    }
+
+    /// Innermost error under any context wrappers
+    pub fn root(&self) -> &HeError {
+        match self {
+            HeError::Context { source, .. } => source.root(),
+            other => other,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            HeError::Database(_) => ErrorCode::Database,
+            HeError::Sql(sqlx::Error::RowNotFound) => ErrorCode::NotFound,
+            HeError::Sql(sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed) => ErrorCode::Unavailable,
+            HeError::Sql(sqlx::Error::Database(db)) if db.is_unique_violation() => ErrorCode::Conflict,
+            HeError::Sql(_) => ErrorCode::Database,
+            HeError::AuthenticationFailed => ErrorCode::Unauthenticated,
+            HeError::UserNotFound | HeError::NotFound(_) => ErrorCode::NotFound,
+            HeError::InvalidProcess(_) | HeError::GameLogic(_) => ErrorCode::GameRule,
+            HeError::InsufficientResources(_) => ErrorCode::InsufficientResources,
+            HeError::PermissionDenied => ErrorCode::Forbidden,
+            HeError::InvalidInput(_) | HeError::ValidationError(_) => ErrorCode::Validation,
+            HeError::Conflict(_) => ErrorCode::Conflict,
+            HeError::RateLimited => ErrorCode::RateLimited,
+            HeError::Domain { code, .. } => *code,
+            HeError::Context { source, .. } => source.code(),
+        }
+    }
+
+    pub fn http_status(&self) -> u16 {
+        self.code().http_status()
+    }
+
+    /// Variant name of the root error, used to group error reports
+    pub fn variant(&self) -> &'static str {
+        match self.root() {
+            HeError::Database(_) => "Database",
+            HeError::Sql(_) => "Sql",
+            HeError::AuthenticationFailed => "AuthenticationFailed",
+            HeError::UserNotFound => "UserNotFound",
+            HeError::NotFound(_) => "NotFound",
+            HeError::InvalidProcess(_) => "InvalidProcess",
+            HeError::InsufficientResources(_) => "InsufficientResources",
+            HeError::PermissionDenied => "PermissionDenied",
+            HeError::InvalidInput(_) => "InvalidInput",
+            HeError::ValidationError(_) => "ValidationError",
+            HeError::Conflict(_) => "Conflict",
+            HeError::RateLimited => "RateLimited",
+            HeError::GameLogic(_) => "GameLogic",
+            HeError::Domain { domain, .. } => *domain,
+            HeError::Context { .. } => unreachable!("root() never returns a context wrapper"),
+        }
+    }
+
+    /// Messages from the outermost context down to the original cause
+    pub fn chain(&self) -> Vec<String> {
+        let mut messages = vec![self.to_string()];
+        let mut current = self.source();
+        while let Some(err) = current {
+            messages.push(err.to_string());
+            current = err.source();
+        }
+        messages
+    }
+
+    /// Backtrace from the outermost context that captured one
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self {
+            HeError::Context { trace, .. } if trace.status() == BacktraceStatus::Captured => Some(trace),
+            HeError::Context { source, .. } => source.backtrace(),
+            _ => None,
+        }
+    }
+}
+
+/// `.context()` for results whose error converts into `HeError`
+pub trait ResultExt<T> {
+    fn context(self, context: impl Into<String>) -> HeResult<T>;
+
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> HeResult<T>;
+}
+
+impl<T, E: Into<HeError>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: impl Into<String>) -> HeResult<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> HeResult<T> {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,4 +300,30 @@ mod tests {
         let he_error = HeError::Database(anyhow_error);
         assert!(he_error.to_string().contains("Database error"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_context_chain() {
+        let result: HeResult<()> = Err(HeError::UserNotFound);
+        let err = result
+            .context("loading attacker")
+            .with_context(|| format!("starting hack on {}", "1.2.3.4"))
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "starting hack on 1.2.3.4");
+        assert_eq!(err.chain(), vec!["starting hack on 1.2.3.4", "loading attacker", "User not found"]);
+        assert_eq!(err.code(), ErrorCode::NotFound);
+        assert_eq!(err.variant(), "UserNotFound");
+        assert_eq!(err.http_status(), 404);
+    }
+
+    #[test]
+    fn test_domain_error_code() {
+        let io = std::io::Error::new(std::io::ErrorKind::Other, "disk full");
+        let err = HeError::domain("Storage", ErrorCode::Unavailable, io);
+
+        assert_eq!(err.code(), ErrorCode::Unavailable);
+        assert_eq!(err.variant(), "Storage");
+        assert_eq!(err.chain(), vec!["Storage: disk full", "disk full"]);
+        assert!(err.code().is_server_error());
+    }
+}
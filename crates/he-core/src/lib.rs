@@ -5,6 +5,7 @@ pub mod error;
 pub mod types;
 pub mod id;
 pub mod entity_core;
+pub mod context;
 
 // Infrastructure modules (Priority 1)
 pub mod database;
@@ -25,6 +26,7 @@ pub use error::*;
 pub use types::*;
 pub use id::*;
 pub use entity_core::*;
+pub use context::RequestContext;
 
 // Re-export infrastructure modules
 pub use database::*;
@@ -58,6 +60,8 @@ mod tests {
 // Helix compatibility re-exports for normalization
 // Allow downstream crates to import helix units/process APIs via the `he-core` facade.
 pub use he_helix_core::units;
+pub use he_helix_core::registry;
 pub use he_helix_core::process_cancel;
+pub use he_helix_core::clock;
 pub use he_helix_core::{HelixError, HelixResult};
 pub use he_helix_core::types::{HelixId, RequestId, ProcessId};
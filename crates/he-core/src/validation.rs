@@ -18,6 +18,10 @@ static EMAIL_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}$").map_err(|e| anyhow::anyhow!("Error: {}", e))?
 });
 
+static HOSTNAME_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(\.[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$").map_err(|e| anyhow::anyhow!("Error: {}", e))?
+});
+
 static IP_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)$").map_err(|e| anyhow::anyhow!("Error: {}", e))?
 });
@@ -214,6 +218,66 @@ pub struct MissionActionInput {
     pub data: Option<String>,
 }
 
+/// Player server customization input validation.
+///
+/// `None` leaves a field unchanged and an empty string clears it, so only
+/// non-empty values are checked against the patterns.
+#[derive(Debug, Clone, Deserialize, Serialize, Validate)]
+pub struct ServerCustomizationInput {
+    #[validate(length(max = 253), custom = "validate_hostname")]
+    pub hostname: Option<String>,
+
+    #[validate(length(max = 280), custom = "validate_no_xss")]
+    pub motd: Option<String>,
+
+    #[validate(length(max = 2000), custom = "validate_no_xss")]
+    pub public_profile: Option<String>,
+}
+
+fn validate_hostname(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() || HOSTNAME_REGEX.is_match(value) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid_hostname"))
+    }
+}
+
+/// Terms that get player-written text reported for moderation, unless the
+/// game configuration lists others
+pub const DEFAULT_OFFENSIVE_TERMS: &[&str] = &[
+    "asshole", "bastard", "bitch", "cunt", "dickhead", "fag", "faggot",
+    "fuck", "motherfucker", "nazi", "retard", "shit", "slut", "whore",
+];
+
+/// Which of `terms` are found in `text`, for moderation reports. Matching is
+/// on whole words after folding case and common letter substitutions.
+/// Text is not rejected for these; a moderator decides.
+pub fn find_offensive_terms<'a>(text: &str, terms: &'a [String]) -> Vec<&'a str> {
+    let folded: String = text
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            '0' => 'o',
+            '1' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .collect();
+
+    let words: Vec<&str> = folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    terms
+        .iter()
+        .map(String::as_str)
+        .filter(|term| words.iter().any(|word| word.eq_ignore_ascii_case(term)))
+        .collect()
+}
+
 /// Sanitizes user input by removing potentially dangerous characters
 pub fn sanitize_input(input: &str) -> String {
     input
@@ -315,4 +379,38 @@ mod tests {
         };
         assert!(invalid_input.validate().is_err());
     }
+
+    #[test]
+    fn test_server_customization_validation() {
+        let valid = ServerCustomizationInput {
+            hostname: Some("gateway-01.example".to_string()),
+            motd: Some("Authorized users only. Everyone else: hi!".to_string()),
+            public_profile: None,
+        };
+        assert!(valid.validate().is_ok());
+
+        let cleared = ServerCustomizationInput {
+            hostname: Some(String::new()),
+            motd: Some(String::new()),
+            public_profile: Some(String::new()),
+        };
+        assert!(cleared.validate().is_ok());
+
+        let invalid = ServerCustomizationInput {
+            hostname: Some("-bad host".to_string()),
+            motd: Some("<script>alert(1)</script>".to_string()),
+            public_profile: None,
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_offensive_terms() {
+        let terms: Vec<String> = DEFAULT_OFFENSIVE_TERMS.iter().map(|t| t.to_string()).collect();
+        assert!(find_offensive_terms("Welcome to my server", &terms).is_empty());
+        assert!(find_offensive_terms("Shitake mushrooms", &terms).is_empty());
+        assert_eq!(find_offensive_terms("get lost, B1TCH", &terms), vec!["bitch"]);
+        assert_eq!(find_offensive_terms("$hit happens", &terms), vec!["shit"]);
+        assert_eq!(find_offensive_terms("Mushrooms", &["mushrooms".to_string()]), vec!["mushrooms"]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,337 @@
+//! Daily analytics jobs
+//!
+//! Each job computes one day's aggregates map-reduce style: its input is
+//! split into id ranges of [`PARTITION_SIZE`], every range is aggregated by
+//! its own query (a few at a time) and the partial results are added up
+//! before the day's row in the `analytics` schema is replaced. Ranges are
+//! disjoint, so even distinct counts such as DAU add up exactly.
+//!
+//! A job finishes a day by recording it in `analytics.job_days`; days missed
+//! within the last [`CATCH_UP_DAYS`] are computed on the next run.
+
+use crate::error::{CronError, CronResult};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use he_database::queries::{AnalyticsQueries, DailyActivityRow, EconomyDayRow, FunnelCohortRow};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tracing::{error, info};
+
+/// Ids aggregated by one map query
+pub const PARTITION_SIZE: i64 = 5_000;
+/// Map queries running at the same time, per job
+const MAP_CONCURRENCY: usize = 4;
+/// How far back missed days are computed
+pub const CATCH_UP_DAYS: i64 = 7;
+
+fn db(e: anyhow::Error) -> CronError {
+    CronError::Database(e.to_string())
+}
+
+fn start_of(day: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.and_time(NaiveTime::MIN))
+}
+
+/// A daily aggregate computed over id ranges
+#[async_trait]
+pub trait AnalyticsJob: Sync {
+    /// One range's result; `Default` is the empty aggregate
+    type Partial: Default + Send;
+
+    /// Stable identifier, recorded in `analytics.job_days`
+    fn name(&self) -> &'static str;
+
+    /// The last day that can be computed on `today`
+    fn latest_day(&self, today: NaiveDate) -> NaiveDate {
+        today - Duration::days(1)
+    }
+
+    /// Lowest and highest id to split for `day`; `None` if there is nothing
+    /// to read
+    async fn bounds(&self, pool: &PgPool, day: NaiveDate) -> CronResult<Option<(i64, i64)>>;
+
+    /// Aggregate the ids in `[ids.0, ids.1)` for `day`
+    async fn map(&self, pool: &PgPool, day: NaiveDate, ids: (i64, i64)) -> CronResult<Self::Partial>;
+
+    /// Fold one range's result into the running total
+    fn reduce(total: &mut Self::Partial, part: Self::Partial);
+
+    /// Replace `day`'s aggregates with `total`
+    async fn write(&self, pool: &PgPool, day: NaiveDate, total: Self::Partial) -> CronResult<()>;
+}
+
+/// `[low, high]` as half-open ranges of at most `size` ids
+pub fn partitions(low: i64, high: i64, size: i64) -> Vec<(i64, i64)> {
+    let mut ranges = Vec::new();
+    let mut start = low;
+    while start <= high {
+        let end = start.saturating_add(size).min(high.saturating_add(1));
+        ranges.push((start, end));
+        start = end;
+    }
+    ranges
+}
+
+/// Days up to `latest`, within the catch-up window, not yet in `finished`
+pub fn pending_days(latest: NaiveDate, finished: &[NaiveDate]) -> Vec<NaiveDate> {
+    (0..CATCH_UP_DAYS)
+        .rev()
+        .map(|back| latest - Duration::days(back))
+        .filter(|day| !finished.contains(day))
+        .collect()
+}
+
+/// Compute and store `day`; returns the number of partitions
+pub async fn run_day<J: AnalyticsJob>(job: &J, pool: &PgPool, day: NaiveDate) -> CronResult<usize> {
+    let started = Instant::now();
+    let ranges = match job.bounds(pool, day).await? {
+        Some((low, high)) => partitions(low, high, PARTITION_SIZE),
+        None => Vec::new(),
+    };
+
+    let total = stream::iter(ranges.iter().copied())
+        .map(|ids| job.map(pool, day, ids))
+        .buffer_unordered(MAP_CONCURRENCY)
+        .try_fold(J::Partial::default(), |mut total, part| async move {
+            J::reduce(&mut total, part);
+            Ok(total)
+        })
+        .await?;
+    job.write(pool, day, total).await?;
+
+    let duration_ms = started.elapsed().as_millis() as i64;
+    AnalyticsQueries::finish_day(pool, job.name(), day, ranges.len() as i32, duration_ms)
+        .await
+        .map_err(db)?;
+    Ok(ranges.len())
+}
+
+/// Compute every pending day of `job`, oldest first
+pub async fn catch_up<J: AnalyticsJob>(job: &J, pool: &PgPool, today: NaiveDate) -> CronResult<()> {
+    let latest = job.latest_day(today);
+    let finished = AnalyticsQueries::finished_days(pool, job.name(), latest - Duration::days(CATCH_UP_DAYS), latest)
+        .await
+        .map_err(db)?;
+
+    for day in pending_days(latest, &finished) {
+        let partitions = run_day(job, pool, day).await?;
+        info!("Analytics {} computed for {} over {} partitions", job.name(), day, partitions);
+    }
+    Ok(())
+}
+
+/// Run every analytics job; one failing does not stop the others
+pub async fn run_all(pool: &PgPool, today: NaiveDate) -> CronResult<()> {
+    let outcomes = [
+        (DailyActivity.name(), catch_up(&DailyActivity, pool, today).await),
+        (FunnelCohorts.name(), catch_up(&FunnelCohorts, pool, today).await),
+        (EconomyDaily.name(), catch_up(&EconomyDaily, pool, today).await),
+    ];
+
+    let failed: Vec<String> = outcomes
+        .into_iter()
+        .filter_map(|(name, outcome)| outcome.err().map(|e| format!("{}: {}", name, e)))
+        .collect();
+    if failed.is_empty() {
+        return Ok(());
+    }
+    for failure in &failed {
+        error!("Analytics job failed: {}", failure);
+    }
+    Err(CronError::Runtime(failed.join("; ")))
+}
+
+/// DAU, WAU and registrations, partitioned by user id
+pub struct DailyActivity;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ActivityCounts {
+    pub dau: i64,
+    pub wau: i64,
+    pub new_players: i64,
+}
+
+#[async_trait]
+impl AnalyticsJob for DailyActivity {
+    type Partial = ActivityCounts;
+
+    fn name(&self) -> &'static str {
+        "daily_activity"
+    }
+
+    async fn bounds(&self, pool: &PgPool, _day: NaiveDate) -> CronResult<Option<(i64, i64)>> {
+        AnalyticsQueries::user_bounds(pool).await.map_err(db)
+    }
+
+    async fn map(&self, pool: &PgPool, day: NaiveDate, ids: (i64, i64)) -> CronResult<ActivityCounts> {
+        let from = start_of(day);
+        let week = from - Duration::days(6);
+        let (dau, wau, new_players) =
+            AnalyticsQueries::activity(pool, ids, week, from, from + Duration::days(1)).await.map_err(db)?;
+        Ok(ActivityCounts { dau, wau, new_players })
+    }
+
+    fn reduce(total: &mut ActivityCounts, part: ActivityCounts) {
+        total.dau += part.dau;
+        total.wau += part.wau;
+        total.new_players += part.new_players;
+    }
+
+    async fn write(&self, pool: &PgPool, day: NaiveDate, total: ActivityCounts) -> CronResult<()> {
+        let row = DailyActivityRow { day, dau: total.dau, wau: total.wau, new_players: total.new_players };
+        AnalyticsQueries::write_activity(pool, &row).await.map_err(db)
+    }
+}
+
+/// Register → first hack → day-7 return, per registration day
+pub struct FunnelCohorts;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FunnelCounts {
+    pub registered: i64,
+    pub first_hack: i64,
+    pub returned_day7: i64,
+}
+
+#[async_trait]
+impl AnalyticsJob for FunnelCohorts {
+    type Partial = FunnelCounts;
+
+    fn name(&self) -> &'static str {
+        "funnel_cohorts"
+    }
+
+    /// Someone registering at the end of a day has their day-7 window close
+    /// at the end of the ninth day after
+    fn latest_day(&self, today: NaiveDate) -> NaiveDate {
+        today - Duration::days(9)
+    }
+
+    async fn bounds(&self, pool: &PgPool, _day: NaiveDate) -> CronResult<Option<(i64, i64)>> {
+        AnalyticsQueries::user_bounds(pool).await.map_err(db)
+    }
+
+    async fn map(&self, pool: &PgPool, day: NaiveDate, ids: (i64, i64)) -> CronResult<FunnelCounts> {
+        let from = start_of(day);
+        let (registered, first_hack, returned_day7) =
+            AnalyticsQueries::funnel(pool, ids, from, from + Duration::days(1)).await.map_err(db)?;
+        Ok(FunnelCounts { registered, first_hack, returned_day7 })
+    }
+
+    fn reduce(total: &mut FunnelCounts, part: FunnelCounts) {
+        total.registered += part.registered;
+        total.first_hack += part.first_hack;
+        total.returned_day7 += part.returned_day7;
+    }
+
+    async fn write(&self, pool: &PgPool, day: NaiveDate, total: FunnelCounts) -> CronResult<()> {
+        let row = FunnelCohortRow {
+            cohort_day: day,
+            registered: total.registered,
+            first_hack: total.first_hack,
+            returned_day7: total.returned_day7,
+        };
+        AnalyticsQueries::write_funnel(pool, &row).await.map_err(db)
+    }
+}
+
+/// Money minted, sunk and moved per ledger reason, partitioned by ledger
+/// transaction id
+pub struct EconomyDaily;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EconomyTotals {
+    pub transactions: i64,
+    pub minted: i64,
+    pub sunk: i64,
+    pub volume: i64,
+}
+
+#[async_trait]
+impl AnalyticsJob for EconomyDaily {
+    type Partial = BTreeMap<String, EconomyTotals>;
+
+    fn name(&self) -> &'static str {
+        "economy_daily"
+    }
+
+    async fn bounds(&self, pool: &PgPool, day: NaiveDate) -> CronResult<Option<(i64, i64)>> {
+        let from = start_of(day);
+        AnalyticsQueries::ledger_bounds(pool, from, from + Duration::days(1)).await.map_err(db)
+    }
+
+    async fn map(&self, pool: &PgPool, day: NaiveDate, ids: (i64, i64)) -> CronResult<Self::Partial> {
+        let from = start_of(day);
+        let rows = AnalyticsQueries::economy(pool, ids, from, from + Duration::days(1)).await.map_err(db)?;
+        Ok(rows
+            .into_iter()
+            .map(|(reason, transactions, minted, sunk, volume)| {
+                (reason, EconomyTotals { transactions, minted, sunk, volume })
+            })
+            .collect())
+    }
+
+    fn reduce(total: &mut Self::Partial, part: Self::Partial) {
+        for (reason, part) in part {
+            let total = total.entry(reason).or_default();
+            total.transactions += part.transactions;
+            total.minted += part.minted;
+            total.sunk += part.sunk;
+            total.volume += part.volume;
+        }
+    }
+
+    async fn write(&self, pool: &PgPool, day: NaiveDate, total: Self::Partial) -> CronResult<()> {
+        let rows: Vec<EconomyDayRow> = total
+            .into_iter()
+            .map(|(reason, totals)| EconomyDayRow {
+                day,
+                reason,
+                transactions: totals.transactions,
+                minted: totals.minted,
+                sunk: totals.sunk,
+                volume: totals.volume,
+            })
+            .collect();
+        AnalyticsQueries::write_economy(pool, day, &rows).await.map_err(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 11, d).unwrap()
+    }
+
+    #[test]
+    fn partitions_cover_the_bounds_once() {
+        assert_eq!(partitions(1, 12_000, 5_000), vec![(1, 5_001), (5_001, 10_001), (10_001, 12_001)]);
+        assert_eq!(partitions(7, 7, 5_000), vec![(7, 8)]);
+        assert!(partitions(10, 9, 5_000).is_empty());
+    }
+
+    #[test]
+    fn pending_days_skip_finished_ones() {
+        let pending = pending_days(day(10), &[day(5), day(9)]);
+        assert_eq!(pending, vec![day(4), day(6), day(7), day(8), day(10)]);
+        assert_eq!(FunnelCohorts.latest_day(day(20)), day(11));
+        assert_eq!(DailyActivity.latest_day(day(20)), day(19));
+    }
+
+    #[test]
+    fn economy_partials_merge_by_reason() {
+        let totals = |transactions, minted| EconomyTotals { transactions, minted, sunk: 0, volume: minted };
+        let mut total = BTreeMap::from([("mission".to_string(), totals(2, 500))]);
+        EconomyDaily::reduce(
+            &mut total,
+            BTreeMap::from([("mission".to_string(), totals(1, 100)), ("bounty".to_string(), totals(3, 0))]),
+        );
+
+        assert_eq!(total["mission"], totals(3, 600));
+        assert_eq!(total["bounty"], totals(3, 0));
+    }
+}
@@ -0,0 +1,129 @@
+//! Cold storage archival
+//!
+//! Completed processes and logs older than their table's retention window
+//! (in `archive.retention`, tuned by operators) are moved into the monthly
+//! partitions of the `archive` schema so the hot tables stay small. Each
+//! batch moves the oldest rows in one transaction, so a row is always in
+//! exactly one of the two places. A pass stops after [`MAX_BATCHES`] batches
+//! or [`PASS_BUDGET`], whichever comes first, and the next run carries on.
+
+use crate::error::{CronError, CronResult};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use he_database::queries::{ArchiveQueries, ArchiveTable, RetentionRow};
+use sqlx::PgPool;
+use std::time::Instant;
+use tracing::{error, info};
+
+/// Batches one pass over a table may move
+pub const MAX_BATCHES: usize = 200;
+/// Wall-clock time one pass over a table may take
+pub const PASS_BUDGET: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+fn db(e: anyhow::Error) -> CronError {
+    CronError::Database(e.to_string())
+}
+
+/// Rows from before this are archived
+pub fn cutoff(now: DateTime<Utc>, retention_days: i32) -> DateTime<Utc> {
+    now - Duration::days(retention_days.into())
+}
+
+/// The first day of every month from the month of `from` to that of `to`
+pub fn months(from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<NaiveDate> {
+    let first = |at: DateTime<Utc>| NaiveDate::from_ymd_opt(at.year(), at.month(), 1).expect("first of month");
+    let (mut month, last) = (first(from), first(to));
+    let mut months = Vec::new();
+    while month <= last {
+        months.push(month);
+        month = month.checked_add_months(chrono::Months::new(1)).expect("month in range");
+    }
+    months
+}
+
+/// Outcome of one pass over a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pass {
+    pub moved: u64,
+    /// Stopped at the batch or time limit with rows left to move
+    pub more_pending: bool,
+}
+
+/// Move what `retention` says is due out of its table
+pub async fn archive_table(pool: &PgPool, table: ArchiveTable, retention: &RetentionRow, now: DateTime<Utc>) -> CronResult<Pass> {
+    let started = Instant::now();
+    let cutoff = cutoff(now, retention.retention_days.max(table.min_retention_days()));
+    let mut pass = Pass { moved: 0, more_pending: false };
+
+    if let Some(oldest) = ArchiveQueries::oldest(pool, table).await.map_err(db)?.filter(|oldest| *oldest < cutoff) {
+        for month in months(oldest, cutoff) {
+            ArchiveQueries::ensure_partition(pool, table, month).await.map_err(db)?;
+        }
+
+        let batch_size = i64::from(retention.batch_size.max(1));
+        for batch in 1..=MAX_BATCHES {
+            let moved = ArchiveQueries::archive_batch(pool, table, cutoff, batch_size).await.map_err(db)?;
+            pass.moved += moved;
+            if moved < batch_size as u64 {
+                break;
+            }
+            if batch == MAX_BATCHES || started.elapsed() >= PASS_BUDGET {
+                pass.more_pending = true;
+                break;
+            }
+        }
+    }
+
+    let duration_ms = started.elapsed().as_millis() as i64;
+    ArchiveQueries::record_run(pool, table, cutoff, pass.moved as i64, pass.more_pending, duration_ms)
+        .await
+        .map_err(db)?;
+    Ok(pass)
+}
+
+/// Archive every enabled table; one failing does not stop the others
+pub async fn run_all(pool: &PgPool, now: DateTime<Utc>) -> CronResult<()> {
+    let retention = ArchiveQueries::retention(pool).await.map_err(db)?;
+
+    let mut failed = Vec::new();
+    for row in retention.iter().filter(|row| row.enabled) {
+        let Some(table) = ArchiveTable::from_str(&row.table_name) else {
+            continue;
+        };
+        match archive_table(pool, table, row, now).await {
+            Ok(pass) => info!(
+                "Archived {} {} older than {} days{}",
+                pass.moved,
+                table.as_str(),
+                row.retention_days,
+                if pass.more_pending { ", more pending" } else { "" }
+            ),
+            Err(e) => failed.push(format!("{}: {}", table.as_str(), e)),
+        }
+    }
+
+    if failed.is_empty() {
+        return Ok(());
+    }
+    for failure in &failed {
+        error!("Archive pass failed: {}", failure);
+    }
+    Err(CronError::Runtime(failed.join("; ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_months_and_cutoff() {
+        let from = Utc.with_ymd_and_hms(2024, 11, 20, 8, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2025, 2, 3, 0, 0, 0).unwrap();
+        let firsts: Vec<String> = months(from, to).iter().map(|m| m.to_string()).collect();
+        assert_eq!(firsts, vec!["2024-11-01", "2024-12-01", "2025-01-01", "2025-02-01"]);
+        assert_eq!(months(to, to).len(), 1);
+        assert!(months(to, from).is_empty());
+
+        assert_eq!(cutoff(to, 30), Utc.with_ymd_and_hms(2025, 1, 4, 0, 0, 0).unwrap());
+    }
+}
@@ -28,6 +28,10 @@ pub enum CronError {
     #[error("File system error: {0}")]
     FileSystem(String),
 
+    /// Distributed lock (Redis) errors
+    #[error("Lock error: {0}")]
+    Lock(String),
+
     /// Serialization/deserialization errors
     #[error("Serialization error: {0}")]
     Serialization(String),
@@ -39,6 +43,12 @@ impl From<sqlx::Error> for CronError {
     }
 }
 
+impl From<redis::RedisError> for CronError {
+    fn from(err: redis::RedisError) -> Self {
+        CronError::Lock(err.to_string())
+    }
+}
+
 impl From<std::io::Error> for CronError {
     fn from(err: std::io::Error) -> Self {
         CronError::FileSystem(err.to_string())
@@ -49,4 +59,18 @@ impl From<serde_json::Error> for CronError {
     fn from(err: serde_json::Error) -> Self {
         CronError::Serialization(err.to_string())
     }
-}
\ No newline at end of file
+}
+
+impl From<CronError> for he_core::HeError {
+    fn from(err: CronError) -> Self {
+        use he_core::ErrorCode;
+
+        let code = match &err {
+            CronError::Database(_) => ErrorCode::Database,
+            CronError::Config(_) => ErrorCode::Configuration,
+            CronError::S3(_) | CronError::Lock(_) => ErrorCode::Unavailable,
+            _ => ErrorCode::Internal,
+        };
+        he_core::HeError::domain("Cron", code, err)
+    }
+}
@@ -0,0 +1,19 @@
+//! Daily analytics job
+//!
+//! Runs the [`crate::analytics`] jobs for every day they have not finished
+//! yet. Works on the Postgres game database.
+
+use crate::analytics;
+use crate::error::CronResult;
+use chrono::Utc;
+use sqlx::PgPool;
+
+/// Daily analytics job implementation
+pub struct DailyAnalyticsJob;
+
+impl DailyAnalyticsJob {
+    /// Execute the daily analytics job
+    pub async fn execute(db_pool: PgPool) -> CronResult<()> {
+        analytics::run_all(&db_pool, Utc::now().date_naive()).await
+    }
+}
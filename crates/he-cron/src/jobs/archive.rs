@@ -0,0 +1,19 @@
+//! Cold storage archive job
+//!
+//! Moves completed processes and old logs into the `archive` schema with
+//! [`crate::archive`]. Works on the Postgres game database.
+
+use crate::archive;
+use crate::error::CronResult;
+use chrono::Utc;
+use sqlx::PgPool;
+
+/// Archive job implementation
+pub struct ArchiveJob;
+
+impl ArchiveJob {
+    /// Execute the archive job
+    pub async fn execute(db_pool: PgPool) -> CronResult<()> {
+        archive::run_all(&db_pool, Utc::now()).await
+    }
+}
@@ -7,7 +7,7 @@ use crate::error::{CronError, CronResult};
 use crate::utils::{execute_command, format_backup_timestamp};
 use aws_config::meta::region::RegionProviderChain;
 use aws_sdk_s3::{Client, primitives::ByteStream};
-use chrono::Utc;
+use he_core::clock;
 use sqlx::MySqlPool;
 use std::path::Path;
 use std::sync::Arc;
@@ -20,7 +20,7 @@ pub struct ForumBackupJob;
 impl ForumBackupJob {
     /// Execute the forum backup job
     pub async fn execute(db_pool: Arc<MySqlPool>) -> CronResult<()> {
-        let timestamp = format_backup_timestamp(Utc::now());
+        let timestamp = format_backup_timestamp(clock::now());
         let backup_name = format!("{}_forum", timestamp);
         let local_path = format!("/var/web/backup/forum/{}.sql", backup_name);
         
@@ -81,7 +81,7 @@ impl ForumBackupJob {
         let bucket = std::env::var("S3_BACKUP_BUCKET")
             .map_err(|_| CronError::Config("S3_BACKUP_BUCKET environment variable not set".to_string()))?;
         
-        let now = Utc::now();
+        let now = clock::now();
         let s3_key = format!(
             "/{}/{}/{}/{}",
             now.format("%Y"),
@@ -113,6 +113,7 @@ impl ForumBackupJob {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Utc;
     
     #[tokio::test]
     async fn test_backup_name_format() {
@@ -0,0 +1,42 @@
+//! Bank heists job
+//!
+//! Opens a heist window on a top-tier NPC bank whenever none is open, and
+//! fails raids whose window closed or whose crew was traced without anyone
+//! touching them since. Works on the Postgres game database.
+
+use crate::error::{CronError, CronResult};
+use he_database::queries::HeistQueries;
+use he_game_mechanics::config::HeistConfig;
+use he_game_mechanics::heist::RaidFailure;
+use sqlx::PgPool;
+use tracing::info;
+
+/// Bank heists job implementation
+pub struct BankHeistsJob;
+
+impl BankHeistsJob {
+    /// Execute the bank heists job
+    pub async fn execute(db_pool: PgPool) -> CronResult<()> {
+        let db = |e: anyhow::Error| CronError::Database(e.to_string());
+        let config = HeistConfig::default();
+
+        let failed = HeistQueries::fail_expired(
+            &db_pool,
+            RaidFailure::WindowClosed.reason(),
+            RaidFailure::Traced.reason(),
+        )
+        .await
+        .map_err(db)?;
+        if !failed.is_empty() {
+            info!("Failed {} heist raids that ran out of time", failed.len());
+        }
+
+        let opened = HeistQueries::open(&db_pool, config.min_bank_tier, config.window_minutes, config.vault)
+            .await
+            .map_err(db)?;
+        if let Some(heist) = opened {
+            info!("Opened heist {} on {} until {}", heist.id, heist.bank_name, heist.closes_at);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! Clan chat retention job
+//!
+//! Deletes clan chat messages older than the history window, archived rooms
+//! included, keeping pinned ones. Works on the Postgres game database.
+
+use crate::error::{CronError, CronResult};
+use chrono::Utc;
+use he_database::queries::ClanChatQueries;
+use he_game_mechanics::clan_chat;
+use he_game_mechanics::config::ClanChatConfig;
+use sqlx::PgPool;
+use tracing::info;
+
+/// Messages deleted per statement
+const BATCH_SIZE: i64 = 5_000;
+/// Batches one run may delete; the next run carries on
+const MAX_BATCHES: usize = 100;
+
+/// Clan chat retention job implementation
+pub struct ClanChatRetentionJob;
+
+impl ClanChatRetentionJob {
+    /// Execute the clan chat retention job
+    pub async fn execute(db_pool: PgPool) -> CronResult<()> {
+        let cutoff = clan_chat::history_cutoff(Utc::now(), &ClanChatConfig::default());
+
+        let mut deleted = 0;
+        for _ in 0..MAX_BATCHES {
+            let batch = ClanChatQueries::prune(&db_pool, cutoff, BATCH_SIZE)
+                .await
+                .map_err(|e| CronError::Database(e.to_string()))?;
+            deleted += batch;
+            if batch < BATCH_SIZE as u64 {
+                break;
+            }
+        }
+
+        if deleted > 0 {
+            info!("Deleted {} clan chat messages from before {}", deleted, cutoff);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+//! Expire premium entitlements job
+//!
+//! Takes the premium_player role from players whose subscription access,
+//! grace included, has run out, and gives it where a webhook granted premium
+//! but the role was not assigned. Unlike the legacy jobs this one works on
+//! the Postgres game database.
+
+use crate::error::{CronError, CronResult};
+use he_database::queries::EntitlementQueries;
+use sqlx::PgPool;
+use tracing::info;
+
+/// Expire entitlements job implementation
+pub struct ExpireEntitlementsJob;
+
+impl ExpireEntitlementsJob {
+    /// Execute the expire entitlements job
+    pub async fn execute(db_pool: PgPool) -> CronResult<()> {
+        let sync = EntitlementQueries::sync_roles(&db_pool)
+            .await
+            .map_err(|e| CronError::Database(e.to_string()))?;
+
+        if sync.revoked.is_empty() && sync.granted.is_empty() {
+            return Ok(());
+        }
+        info!(
+            "Premium role revoked from {} players and granted to {}",
+            sync.revoked.len(),
+            sync.granted.len()
+        );
+        Ok(())
+    }
+}
@@ -12,9 +12,14 @@ pub mod end_war;
 pub mod generate_missions;
 pub mod defcon;
 pub mod update_premium;
+pub mod expire_entitlements;
 pub mod safenet_update;
 pub mod doom_updater;
 pub mod finish_round;
+pub mod analytics;
+pub mod archive;
+pub mod bank_heists;
+pub mod clan_chat_retention;
 
 // Re-export all job modules for easier access
 pub use backup_forum::*;
@@ -25,6 +30,11 @@ pub use end_war::*;
 pub use generate_missions::*;
 pub use defcon::*;
 pub use update_premium::*;
+pub use expire_entitlements::*;
 pub use safenet_update::*;
 pub use doom_updater::*;
-pub use finish_round::*;
\ No newline at end of file
+pub use finish_round::*;
+pub use analytics::*;
+pub use archive::*;
+pub use bank_heists::*;
+pub use clan_chat_retention::*;
\ No newline at end of file
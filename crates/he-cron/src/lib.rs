@@ -9,8 +9,15 @@ pub mod jobs;
 pub mod error;
 pub mod traits;
 pub mod utils;
-
-pub use scheduler::CronScheduler;
+pub mod registry;
+pub mod lock;
+pub mod runner;
+pub mod analytics;
+pub mod archive;
+
+pub use scheduler::{CronScheduler, JobSpec, JOBS};
+pub use registry::JobRegistry;
+pub use runner::JobRunner;
 pub use error::{CronError, CronResult};
 
 use tokio_cron_scheduler::{JobScheduler, Job};
@@ -0,0 +1,135 @@
+//! Locks that keep a job from running twice at the same time
+//!
+//! Within one process a job is guarded by an in-memory set. When Redis is
+//! configured, a lease key is taken as well so that several he-cron instances
+//! can run side by side. Leases expire after [`LOCK_TTL`] unless renewed, so a
+//! crashed instance does not block a job forever.
+
+use crate::error::CronResult;
+use redis::aio::ConnectionManager;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Lifetime of a Redis lease; the runner renews it while the job runs
+pub const LOCK_TTL: Duration = Duration::from_secs(60);
+
+const KEY_PREFIX: &str = "he:cron:lock:";
+
+/// Delete or extend the key only while it still holds our token
+const RELEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+const RENEW_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Proof that a job's lock is held; hand it back to [`JobLocks::release`]
+#[derive(Debug, Clone)]
+pub struct JobLease {
+    name: &'static str,
+    token: String,
+}
+
+impl JobLease {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+pub struct JobLocks {
+    redis: Option<ConnectionManager>,
+    local: Mutex<HashSet<&'static str>>,
+}
+
+impl JobLocks {
+    /// In-process locking only
+    pub fn local() -> Self {
+        Self { redis: None, local: Mutex::new(HashSet::new()) }
+    }
+
+    pub async fn connect(redis_url: &str) -> CronResult<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let redis = ConnectionManager::new(client).await?;
+        Ok(Self { redis: Some(redis), local: Mutex::new(HashSet::new()) })
+    }
+
+    pub fn is_distributed(&self) -> bool {
+        self.redis.is_some()
+    }
+
+    /// `Ok(None)` if the job is already running here or on another instance.
+    /// A Redis failure is an error: running without the lock could process
+    /// the same rows twice.
+    pub async fn acquire(&self, name: &'static str) -> CronResult<Option<JobLease>> {
+        if !self.local.lock().unwrap().insert(name) {
+            return Ok(None);
+        }
+
+        let token = Uuid::new_v4().to_string();
+        let Some(redis) = &self.redis else {
+            return Ok(Some(JobLease { name, token }));
+        };
+
+        let acquired: Result<Option<String>, redis::RedisError> = redis::cmd("SET")
+            .arg(key(name))
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(LOCK_TTL.as_millis() as u64)
+            .query_async(&mut redis.clone())
+            .await;
+
+        match acquired {
+            Ok(Some(_)) => Ok(Some(JobLease { name, token })),
+            Ok(None) => {
+                self.local.lock().unwrap().remove(name);
+                Ok(None)
+            }
+            Err(e) => {
+                self.local.lock().unwrap().remove(name);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Push the lease expiry back; false if the lease was lost
+    pub async fn renew(&self, lease: &JobLease) -> CronResult<bool> {
+        let Some(redis) = &self.redis else {
+            return Ok(true);
+        };
+
+        let renewed: i64 = redis::Script::new(RENEW_SCRIPT)
+            .key(key(lease.name))
+            .arg(&lease.token)
+            .arg(LOCK_TTL.as_millis() as u64)
+            .invoke_async(&mut redis.clone())
+            .await?;
+        Ok(renewed == 1)
+    }
+
+    pub async fn release(&self, lease: JobLease) -> CronResult<()> {
+        self.local.lock().unwrap().remove(lease.name);
+
+        if let Some(redis) = &self.redis {
+            let _: i64 = redis::Script::new(RELEASE_SCRIPT)
+                .key(key(lease.name))
+                .arg(&lease.token)
+                .invoke_async(&mut redis.clone())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn key(name: &str) -> String {
+    format!("{}{}", KEY_PREFIX, name)
+}
@@ -0,0 +1,294 @@
+//! Job registry persisted in the Postgres game database
+//!
+//! Every job is registered on startup and every run is recorded, so the admin
+//! dashboard can show last/next run, duration and outcome. The dashboard
+//! writes back through `paused` and `trigger_requested_at`, polled by the
+//! supervisor in [`crate::runner`].
+
+use crate::error::CronResult;
+use crate::scheduler::JobSpec;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// What started a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunTrigger {
+    Schedule,
+    Manual,
+}
+
+impl RunTrigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunTrigger::Schedule => "schedule",
+            RunTrigger::Manual => "manual",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    /// A scheduled run did not happen
+    Missed,
+    /// A run took longer than its time budget
+    OverBudget,
+}
+
+impl AlertKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::Missed => "missed",
+            AlertKind::OverBudget => "over_budget",
+        }
+    }
+}
+
+/// A run still marked as running after its time budget
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OverdueRun {
+    pub job_name: String,
+    pub run_id: i64,
+    pub host: String,
+    pub started_at: DateTime<Utc>,
+    pub time_budget_seconds: i32,
+}
+
+pub struct JobRegistry {
+    pool: PgPool,
+    host: String,
+}
+
+impl JobRegistry {
+    /// `host` identifies this scheduler instance in the run history
+    pub fn new(pool: PgPool, host: String) -> Self {
+        Self { pool, host }
+    }
+
+    pub async fn connect(database_url: &str, host: String) -> CronResult<Self> {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(5)
+            .connect(database_url)
+            .await?;
+        Ok(Self::new(pool, host))
+    }
+
+    /// The game database the registry lives in
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Insert or refresh a job definition. Pause state and statistics are
+    /// kept across restarts. Returns the previously expected next run if it
+    /// passed more than `grace` ago, i.e. runs were missed while no scheduler
+    /// was up.
+    pub async fn register(
+        &self,
+        job: &JobSpec,
+        next_run: Option<DateTime<Utc>>,
+        grace: Duration,
+    ) -> CronResult<Option<DateTime<Utc>>> {
+        let mut tx = self.pool.begin().await?;
+
+        let previous: Option<(Option<DateTime<Utc>>, bool)> = sqlx::query_as(
+            "SELECT next_run_at, paused FROM cron_jobs WHERE name = $1 FOR UPDATE"
+        )
+        .bind(job.name)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO cron_jobs (name, schedule, time_budget_seconds, next_run_at) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (name) DO UPDATE SET schedule = EXCLUDED.schedule, \
+                 time_budget_seconds = EXCLUDED.time_budget_seconds, \
+                 next_run_at = EXCLUDED.next_run_at, updated_at = NOW()"
+        )
+        .bind(job.name)
+        .bind(job.schedule)
+        .bind(job.time_budget.as_secs() as i32)
+        .bind(next_run)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        let cutoff = Utc::now() - to_chrono(grace);
+        Ok(match previous {
+            Some((Some(due), false)) if due < cutoff => Some(due),
+            _ => None,
+        })
+    }
+
+    pub async fn is_paused(&self, name: &str) -> CronResult<bool> {
+        let paused: Option<bool> = sqlx::query_scalar("SELECT paused FROM cron_jobs WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(paused.unwrap_or(false))
+    }
+
+    /// A scheduled tick happened; record when the following one is due
+    pub async fn scheduled(&self, name: &str, next_run: Option<DateTime<Utc>>) -> CronResult<()> {
+        sqlx::query("UPDATE cron_jobs SET next_run_at = $2, updated_at = NOW() WHERE name = $1")
+            .bind(name)
+            .bind(next_run)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the start of a run and return its id
+    pub async fn start_run(&self, name: &str, trigger: RunTrigger) -> CronResult<i64> {
+        let mut tx = self.pool.begin().await?;
+
+        let run_id: i64 = sqlx::query_scalar(
+            "INSERT INTO cron_job_runs (job_name, trigger, status, host) VALUES ($1, $2, 'running', $3) \
+             RETURNING id"
+        )
+        .bind(name)
+        .bind(trigger.as_str())
+        .bind(&self.host)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query("UPDATE cron_jobs SET last_started_at = NOW(), updated_at = NOW() WHERE name = $1")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(run_id)
+    }
+
+    /// Record a run that did not happen, e.g. because another is in progress
+    pub async fn skip_run(&self, name: &str, trigger: RunTrigger, reason: &str) -> CronResult<()> {
+        sqlx::query(
+            "INSERT INTO cron_job_runs (job_name, trigger, status, host, finished_at, duration_ms, error) \
+             VALUES ($1, $2, 'skipped', $3, NOW(), 0, $4)"
+        )
+        .bind(name)
+        .bind(trigger.as_str())
+        .bind(&self.host)
+        .bind(reason)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn finish_run(
+        &self,
+        run_id: i64,
+        name: &str,
+        duration: Duration,
+        outcome: Result<(), String>,
+    ) -> CronResult<()> {
+        let duration_ms = duration.as_millis() as i64;
+        let error = outcome.err();
+        let success = error.is_none();
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE cron_job_runs SET status = $2, finished_at = NOW(), duration_ms = $3, error = $4 \
+             WHERE id = $1"
+        )
+        .bind(run_id)
+        .bind(if success { "succeeded" } else { "failed" })
+        .bind(duration_ms)
+        .bind(&error)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE cron_jobs SET last_finished_at = NOW(), last_duration_ms = $2, last_success = $3, \
+                 last_error = $4, run_count = run_count + 1, \
+                 failure_count = failure_count + CASE WHEN $3 THEN 0 ELSE 1 END, updated_at = NOW() \
+             WHERE name = $1"
+        )
+        .bind(name)
+        .bind(duration_ms)
+        .bind(success)
+        .bind(&error)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Names of jobs an operator asked to run now. Each request is consumed
+    /// by exactly one scheduler instance.
+    pub async fn take_triggers(&self) -> CronResult<Vec<String>> {
+        let names = sqlx::query_scalar(
+            "UPDATE cron_jobs SET trigger_requested_at = NULL, updated_at = NOW() \
+             WHERE name IN (SELECT name FROM cron_jobs WHERE trigger_requested_at IS NOT NULL FOR UPDATE SKIP LOCKED) \
+             RETURNING name"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(names)
+    }
+
+    /// Unpaused jobs whose next run is more than `grace` overdue
+    pub async fn overdue_jobs(&self, grace: Duration) -> CronResult<Vec<(String, DateTime<Utc>)>> {
+        let jobs = sqlx::query_as(
+            "SELECT name, next_run_at FROM cron_jobs \
+             WHERE NOT paused AND next_run_at IS NOT NULL AND next_run_at < $1"
+        )
+        .bind(Utc::now() - to_chrono(grace))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(jobs)
+    }
+
+    /// Runs in progress for longer than their job's time budget
+    pub async fn overdue_runs(&self) -> CronResult<Vec<OverdueRun>> {
+        let runs = sqlx::query_as(
+            "SELECT r.job_name, r.id AS run_id, r.host, r.started_at, j.time_budget_seconds \
+             FROM cron_job_runs r JOIN cron_jobs j ON j.name = r.job_name \
+             WHERE r.status = 'running' \
+               AND r.started_at < NOW() - make_interval(secs => j.time_budget_seconds)"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(runs)
+    }
+
+    /// Open an alert unless one of the same kind is already open for the job.
+    /// Returns whether a new alert was raised.
+    pub async fn raise_alert(&self, name: &str, kind: AlertKind, detail: &str) -> CronResult<bool> {
+        let inserted = sqlx::query(
+            "INSERT INTO cron_job_alerts (job_name, kind, detail) VALUES ($1, $2, $3) \
+             ON CONFLICT (job_name, kind) WHERE resolved_at IS NULL DO NOTHING"
+        )
+        .bind(name)
+        .bind(kind.as_str())
+        .bind(detail)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    /// Close open alerts of `kind` once the job is healthy again
+    pub async fn resolve_alerts(&self, name: &str, kind: AlertKind) -> CronResult<u64> {
+        let resolved = sqlx::query(
+            "UPDATE cron_job_alerts SET resolved_at = NOW() \
+             WHERE job_name = $1 AND kind = $2 AND resolved_at IS NULL"
+        )
+        .bind(name)
+        .bind(kind.as_str())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(resolved)
+    }
+}
+
+fn to_chrono(duration: Duration) -> ChronoDuration {
+    ChronoDuration::from_std(duration).unwrap_or_else(|_| ChronoDuration::zero())
+}
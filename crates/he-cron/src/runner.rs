@@ -0,0 +1,237 @@
+//! Tracked job execution and the supervisor loop
+//!
+//! Scheduled ticks and manual triggers both go through [`JobRunner::run`],
+//! which honours the pause flag, takes the job lock, records the run in the
+//! registry and raises alerts. The job itself runs in its own task, so a
+//! panicking job is recorded as a failed run and its lock is still released.
+//! The supervisor picks up manual triggers and looks for jobs that missed
+//! their schedule or overran their budget.
+
+use crate::error::CronError;
+use crate::lock::{JobLease, JobLocks, LOCK_TTL};
+use crate::registry::{AlertKind, JobRegistry, RunTrigger};
+use crate::scheduler::{self, JobContext, JobSpec};
+use chrono::Utc;
+use sqlx::MySqlPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// How late a scheduled run may be before it counts as missed
+pub const MISSED_GRACE: Duration = Duration::from_secs(60);
+
+pub struct JobRunner {
+    db_pool: Arc<MySqlPool>,
+    registry: JobRegistry,
+    locks: JobLocks,
+}
+
+impl JobRunner {
+    pub fn new(db_pool: Arc<MySqlPool>, registry: JobRegistry, locks: JobLocks) -> Self {
+        Self { db_pool, registry, locks }
+    }
+
+    pub fn registry(&self) -> &JobRegistry {
+        &self.registry
+    }
+
+    /// Run `job` once. Registry failures are logged and do not stop the job;
+    /// a lock failure does, since overlapping runs are not safe.
+    pub async fn run(self: &Arc<Self>, job: &'static JobSpec, trigger: RunTrigger) {
+        if trigger == RunTrigger::Schedule {
+            if let Err(e) = self.registry.scheduled(job.name, job.next_run(Utc::now())).await {
+                warn!("Failed to record next run of {}: {}", job.name, e);
+            }
+
+            // Manual triggers run paused jobs on purpose
+            match self.registry.is_paused(job.name).await {
+                Ok(true) => {
+                    debug!("Job {} is paused, skipping", job.name);
+                    return;
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to read pause state of {}: {}", job.name, e),
+            }
+        }
+
+        let lease = match self.locks.acquire(job.name).await {
+            Ok(Some(lease)) => lease,
+            Ok(None) => {
+                self.skip(job, trigger, "previous run still in progress").await;
+                return;
+            }
+            Err(e) => {
+                warn!("Lock of {} unavailable: {}", job.name, e);
+                self.skip(job, trigger, &format!("lock unavailable: {}", e)).await;
+                return;
+            }
+        };
+
+        if trigger == RunTrigger::Schedule {
+            self.resolve(job.name, AlertKind::Missed).await;
+        }
+
+        let run_id = match self.registry.start_run(job.name, trigger).await {
+            Ok(id) => Some(id),
+            Err(e) => {
+                warn!("Failed to record start of {}: {}", job.name, e);
+                None
+            }
+        };
+
+        info!("Starting {} job ({})", job.name, trigger.as_str());
+        let keepalive = self.spawn_keepalive(&lease);
+        let started = Instant::now();
+        let context = JobContext { legacy: Arc::clone(&self.db_pool), game: self.registry.pool().clone() };
+        let outcome = match tokio::spawn((job.run)(context)).await {
+            Ok(outcome) => outcome,
+            Err(e) => Err(CronError::Runtime(join_failure(e))),
+        };
+        let elapsed = started.elapsed();
+        keepalive.abort();
+
+        match &outcome {
+            Ok(()) => info!("{} job completed in {:?}", job.name, elapsed),
+            Err(e) => error!("{} job failed after {:?}: {}", job.name, elapsed, e),
+        }
+
+        if let Some(run_id) = run_id {
+            let outcome = outcome.map_err(|e| e.to_string());
+            if let Err(e) = self.registry.finish_run(run_id, job.name, elapsed, outcome).await {
+                warn!("Failed to record end of {}: {}", job.name, e);
+            }
+        }
+
+        if elapsed > job.time_budget {
+            let detail = format!(
+                "run took {}s, budget is {}s",
+                elapsed.as_secs(),
+                job.time_budget.as_secs()
+            );
+            self.alert(job.name, AlertKind::OverBudget, &detail).await;
+        } else {
+            self.resolve(job.name, AlertKind::OverBudget).await;
+        }
+
+        if let Err(e) = self.locks.release(lease).await {
+            warn!("Failed to release lock of {}: {}", job.name, e);
+        }
+    }
+
+    /// Poll for manual triggers and schedule problems every `interval`
+    pub fn spawn_supervisor(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.supervise().await;
+            }
+        })
+    }
+
+    async fn supervise(self: &Arc<Self>) {
+        match self.registry.take_triggers().await {
+            Ok(names) => {
+                for name in names {
+                    match scheduler::find_job(&name) {
+                        Some(job) => {
+                            info!("Manual trigger for {}", name);
+                            let runner = Arc::clone(self);
+                            tokio::spawn(async move { runner.run(job, RunTrigger::Manual).await });
+                        }
+                        None => warn!("Manual trigger for unknown job {}", name),
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to poll manual triggers: {}", e),
+        }
+
+        match self.registry.overdue_jobs(MISSED_GRACE).await {
+            Ok(jobs) => {
+                for (name, due) in jobs {
+                    let detail = format!("run due at {} has not started", due.to_rfc3339());
+                    self.alert(&name, AlertKind::Missed, &detail).await;
+                }
+            }
+            Err(e) => warn!("Failed to check for missed runs: {}", e),
+        }
+
+        match self.registry.overdue_runs().await {
+            Ok(runs) => {
+                for run in runs {
+                    let detail = format!(
+                        "run {} on {} started at {} and is still going, budget is {}s",
+                        run.run_id,
+                        run.host,
+                        run.started_at.to_rfc3339(),
+                        run.time_budget_seconds
+                    );
+                    self.alert(&run.job_name, AlertKind::OverBudget, &detail).await;
+                }
+            }
+            Err(e) => warn!("Failed to check for overrunning jobs: {}", e),
+        }
+    }
+
+    /// Every node ticks every schedule and all but the lock holder skip, so
+    /// a skip is no alert; runs that really did not happen show up as
+    /// overdue in [`supervise`](Self::supervise)
+    async fn skip(&self, job: &JobSpec, trigger: RunTrigger, reason: &str) {
+        debug!("Skipping {} job: {}", job.name, reason);
+        if let Err(e) = self.registry.skip_run(job.name, trigger, reason).await {
+            warn!("Failed to record skipped run of {}: {}", job.name, e);
+        }
+    }
+
+    /// Record an alert; only a newly opened one is logged
+    pub(crate) async fn alert(&self, name: &str, kind: AlertKind, detail: &str) {
+        match self.registry.raise_alert(name, kind, detail).await {
+            Ok(true) => error!("Cron alert [{}] {}: {}", kind.as_str(), name, detail),
+            Ok(false) => {}
+            Err(e) => error!("Cron alert [{}] {}: {} (not recorded: {})", kind.as_str(), name, detail, e),
+        }
+    }
+
+    async fn resolve(&self, name: &str, kind: AlertKind) {
+        match self.registry.resolve_alerts(name, kind).await {
+            Ok(0) => {}
+            Ok(_) => info!("Cron alert [{}] {} resolved", kind.as_str(), name),
+            Err(e) => warn!("Failed to resolve {} alerts of {}: {}", kind.as_str(), name, e),
+        }
+    }
+
+    /// Keep the Redis lease alive for as long as the job runs
+    fn spawn_keepalive(self: &Arc<Self>, lease: &JobLease) -> tokio::task::JoinHandle<()> {
+        let runner = Arc::clone(self);
+        let lease = lease.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(LOCK_TTL / 3);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                match runner.locks.renew(&lease).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        error!("Lost the lock of {} while it was running", lease.name());
+                        return;
+                    }
+                    Err(e) => warn!("Failed to renew the lock of {}: {}", lease.name(), e),
+                }
+            }
+        })
+    }
+}
+
+/// Why a job task ended without an outcome
+fn join_failure(e: tokio::task::JoinError) -> String {
+    if !e.is_panic() {
+        return "job task was cancelled".to_string();
+    }
+    let panic = e.into_panic();
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|m| m.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "no message".to_string());
+    format!("job panicked: {}", message)
+}
@@ -1,23 +1,103 @@
 //! Tokio-based cron scheduler for HackerExperience
-//! 
+//!
 //! This module provides the main scheduler that orchestrates all cron jobs
 //! using tokio-cron-scheduler for precise timing and async execution.
+//! Every job is listed in [`JOBS`] and runs through [`JobRunner`], which
+//! records it in the job registry shown on the admin dashboard.
 
 use crate::error::{CronError, CronResult};
 use crate::jobs::*;
+use crate::lock::JobLocks;
+use crate::registry::{AlertKind, JobRegistry, RunTrigger};
+use crate::runner::{JobRunner, MISSED_GRACE};
+use chrono::{DateTime, Utc};
 use tokio_cron_scheduler::{JobScheduler, Job};
-use tracing::{info, error, warn};
+use tracing::{info, warn};
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::Arc;
-use sqlx::MySqlPool;
+use std::time::Duration;
+use sqlx::{MySqlPool, PgPool};
+
+/// How often manual triggers and schedule problems are checked
+const SUPERVISOR_INTERVAL: Duration = Duration::from_secs(15);
+
+pub type JobFuture = Pin<Box<dyn Future<Output = CronResult<()>> + Send>>;
+
+/// Databases a job may use
+#[derive(Clone)]
+pub struct JobContext {
+    /// The legacy MySQL database most jobs were written against
+    pub legacy: Arc<MySqlPool>,
+    /// The Postgres game database, which also holds the job registry
+    pub game: PgPool,
+}
+
+/// A periodic job and its schedule
+pub struct JobSpec {
+    /// Stable identifier, used in the registry and the admin API
+    pub name: &'static str,
+    /// Cron expression with a seconds field
+    pub schedule: &'static str,
+    /// A run taking longer than this raises an alert; it is not cancelled
+    pub time_budget: Duration,
+    pub run: fn(JobContext) -> JobFuture,
+}
+
+impl JobSpec {
+    /// First scheduled time strictly after `after`
+    pub fn next_run(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        cron::Schedule::from_str(self.schedule).ok()?.after(&after).next()
+    }
+}
+
+const fn minutes(n: u64) -> Duration {
+    Duration::from_secs(n * 60)
+}
+
+/// All scheduled jobs; the ported ones keep the schedules of the original
+/// PHP crontab
+pub static JOBS: &[JobSpec] = &[
+    // Backups
+    JobSpec { name: "backup_forum", schedule: "0 0 */4 * * *", time_budget: minutes(30), run: |ctx| Box::pin(ForumBackupJob::execute(ctx.legacy)) },
+    JobSpec { name: "backup_game", schedule: "0 0 */2 * * *", time_budget: minutes(30), run: |ctx| Box::pin(GameBackupJob::execute(ctx.legacy)) },
+    // Game maintenance
+    JobSpec { name: "restore_software", schedule: "0 */30 * * * *", time_budget: minutes(10), run: |ctx| Box::pin(RestoreSoftwareJob::execute(ctx.legacy)) },
+    JobSpec { name: "generate_missions", schedule: "0 0 * * * *", time_budget: minutes(10), run: |ctx| Box::pin(GenerateMissionsJob::execute(ctx.legacy)) },
+    JobSpec { name: "update_premium", schedule: "0 */15 * * * *", time_budget: minutes(5), run: |ctx| Box::pin(UpdatePremiumJob::execute(ctx.legacy)) },
+    JobSpec { name: "expire_entitlements", schedule: "0 */5 * * * *", time_budget: minutes(4), run: |ctx| Box::pin(ExpireEntitlementsJob::execute(ctx.game)) },
+    JobSpec { name: "bank_heists", schedule: "0 */5 * * * *", time_budget: minutes(4), run: |ctx| Box::pin(BankHeistsJob::execute(ctx.game)) },
+    // War management
+    JobSpec { name: "defcon", schedule: "0 */5 * * * *", time_budget: minutes(4), run: |ctx| Box::pin(DefconJob::execute(ctx.legacy)) },
+    JobSpec { name: "end_war", schedule: "0 * * * * *", time_budget: Duration::from_secs(50), run: |ctx| Box::pin(EndWarJob::execute(ctx.legacy)) },
+    // Statistics
+    JobSpec { name: "update_server_stats", schedule: "0 */10 * * * *", time_budget: minutes(5), run: |ctx| Box::pin(UpdateServerStatsJob::execute(ctx.legacy)) },
+    JobSpec { name: "daily_analytics", schedule: "0 30 0 * * *", time_budget: minutes(60), run: |ctx| Box::pin(DailyAnalyticsJob::execute(ctx.game)) },
+    // Cleanup
+    JobSpec { name: "archive", schedule: "0 15 * * * *", time_budget: minutes(30), run: |ctx| Box::pin(ArchiveJob::execute(ctx.game)) },
+    JobSpec { name: "clan_chat_retention", schedule: "0 45 * * * *", time_budget: minutes(10), run: |ctx| Box::pin(ClanChatRetentionJob::execute(ctx.game)) },
+    JobSpec { name: "safenet_update", schedule: "0 */30 * * * *", time_budget: minutes(10), run: |ctx| Box::pin(SafeNetUpdateJob::execute(ctx.legacy)) },
+    JobSpec { name: "doom_updater", schedule: "0 * * * * *", time_budget: Duration::from_secs(50), run: |ctx| Box::pin(DoomUpdaterJob::execute(ctx.legacy)) },
+];
+
+pub fn find_job(name: &str) -> Option<&'static JobSpec> {
+    JOBS.iter().find(|job| job.name == name)
+}
 
 /// Main cron scheduler that manages all periodic tasks
 pub struct CronScheduler {
     scheduler: JobScheduler,
-    db_pool: Arc<MySqlPool>,
+    runner: Arc<JobRunner>,
 }
 
 impl CronScheduler {
-    /// Create a new cron scheduler instance
+    /// Create a new cron scheduler instance.
+    ///
+    /// Ported jobs run against the legacy MySQL database; the job registry
+    /// and newer jobs use the Postgres game database at
+    /// `CRON_REGISTRY_DATABASE_URL`. Set
+    /// `REDIS_URL` to lock jobs across several scheduler instances.
     pub async fn new() -> CronResult<Self> {
         let scheduler = JobScheduler::new()
             .await
@@ -30,9 +110,23 @@ impl CronScheduler {
                 .map_err(|e| CronError::Database(format!("Failed to connect to database: {}", e)))?
         );
 
+        let registry_url = std::env::var("CRON_REGISTRY_DATABASE_URL")
+            .map_err(|_| CronError::Config("CRON_REGISTRY_DATABASE_URL is not set".to_string()))?;
+        let host = std::env::var("HOSTNAME")
+            .unwrap_or_else(|_| format!("he-cron-{}", uuid::Uuid::new_v4().simple()));
+        let registry = JobRegistry::connect(&registry_url, host).await?;
+
+        let locks = match std::env::var("REDIS_URL") {
+            Ok(url) => JobLocks::connect(&url).await?,
+            Err(_) => {
+                warn!("REDIS_URL is not set; jobs are only locked within this process");
+                JobLocks::local()
+            }
+        };
+
         Ok(Self {
             scheduler,
-            db_pool,
+            runner: Arc::new(JobRunner::new(db_pool, registry, locks)),
         })
     }
 
@@ -40,12 +134,11 @@ impl CronScheduler {
     pub async fn start(&self) -> CronResult<()> {
         info!("Registering cron jobs...");
 
-        // Register all jobs with their schedules
-        self.register_backup_jobs().await?;
-        self.register_game_maintenance_jobs().await?;
-        self.register_war_management_jobs().await?;
-        self.register_statistics_jobs().await?;
-        self.register_cleanup_jobs().await?;
+        for job in JOBS {
+            self.register(job).await?;
+        }
+
+        Arc::clone(&self.runner).spawn_supervisor(SUPERVISOR_INTERVAL);
 
         // Start the scheduler
         self.scheduler
@@ -57,223 +150,28 @@ impl CronScheduler {
         Ok(())
     }
 
-    /// Register backup-related cron jobs
-    async fn register_backup_jobs(&self) -> CronResult<()> {
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // Backup forum database every 4 hours
-        let backup_forum_job = Job::new_async("0 0 */4 * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting forum backup job");
-                if let Err(e) = backup_forum::execute(pool).await {
-                    error!("Forum backup job failed: {}", e);
-                } else {
-                    info!("Forum backup job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create backup forum job: {}", e)))?;
-
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // Backup game database every 2 hours
-        let backup_game_job = Job::new_async("0 0 */2 * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
+    /// Record the job in the registry and add it to the scheduler
+    async fn register(&self, job: &'static JobSpec) -> CronResult<()> {
+        let next_run = job.next_run(Utc::now());
+        let missed = self.runner.registry().register(job, next_run, MISSED_GRACE).await?;
+        if let Some(due) = missed {
+            let detail = format!("run due at {} was missed while no scheduler was running", due.to_rfc3339());
+            self.runner.alert(job.name, AlertKind::Missed, &detail).await;
+        }
+
+        let runner = Arc::clone(&self.runner);
+        let scheduled = Job::new_async(job.schedule, move |_uuid, _l| {
+            let runner = Arc::clone(&runner);
             Box::pin(async move {
-                info!("Starting game backup job");
-                if let Err(e) = backup_game::execute(pool).await {
-                    error!("Game backup job failed: {}", e);
-                } else {
-                    info!("Game backup job completed successfully");
-                }
+                runner.run(job, RunTrigger::Schedule).await;
             })
         })
-        .map_err(|e| CronError::Runtime(format!("Failed to create backup game job: {}", e)))?;
+        .map_err(|e| CronError::Runtime(format!("Failed to create {} job: {}", job.name, e)))?;
 
-        self.scheduler.add(backup_forum_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add backup forum job: {}", e)))?;
-        
-        self.scheduler.add(backup_game_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add backup game job: {}", e)))?;
+        self.scheduler.add(scheduled).await
+            .map_err(|e| CronError::Runtime(format!("Failed to add {} job: {}", job.name, e)))?;
 
-        info!("Registered backup jobs");
-        Ok(())
-    }
-
-    /// Register game maintenance jobs
-    async fn register_game_maintenance_jobs(&self) -> CronResult<()> {
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // Restore NPC software every 30 minutes
-        let restore_software_job = Job::new_async("0 */30 * * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting restore software job");
-                if let Err(e) = restore_software::execute(pool).await {
-                    error!("Restore software job failed: {}", e);
-                } else {
-                    info!("Restore software job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create restore software job: {}", e)))?;
-
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // Generate missions every hour
-        let generate_missions_job = Job::new_async("0 0 * * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting generate missions job");
-                if let Err(e) = generate_missions::execute(pool).await {
-                    error!("Generate missions job failed: {}", e);
-                } else {
-                    info!("Generate missions job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create generate missions job: {}", e)))?;
-
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // Update premium status every 15 minutes
-        let update_premium_job = Job::new_async("0 */15 * * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting update premium job");
-                if let Err(e) = update_premium::execute(pool).await {
-                    error!("Update premium job failed: {}", e);
-                } else {
-                    info!("Update premium job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create update premium job: {}", e)))?;
-
-        self.scheduler.add(restore_software_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add restore software job: {}", e)))?;
-        
-        self.scheduler.add(generate_missions_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add generate missions job: {}", e)))?;
-        
-        self.scheduler.add(update_premium_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add update premium job: {}", e)))?;
-
-        info!("Registered game maintenance jobs");
-        Ok(())
-    }
-
-    /// Register war management jobs
-    async fn register_war_management_jobs(&self) -> CronResult<()> {
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // DEFCON processing every 5 minutes
-        let defcon_job = Job::new_async("0 */5 * * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting DEFCON job");
-                if let Err(e) = defcon::execute(pool).await {
-                    error!("DEFCON job failed: {}", e);
-                } else {
-                    info!("DEFCON job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create DEFCON job: {}", e)))?;
-
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // End war processing every minute
-        let end_war_job = Job::new_async("0 * * * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting end war job");
-                if let Err(e) = end_war::execute(pool).await {
-                    error!("End war job failed: {}", e);
-                } else {
-                    info!("End war job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create end war job: {}", e)))?;
-
-        self.scheduler.add(defcon_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add DEFCON job: {}", e)))?;
-        
-        self.scheduler.add(end_war_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add end war job: {}", e)))?;
-
-        info!("Registered war management jobs");
-        Ok(())
-    }
-
-    /// Register statistics jobs
-    async fn register_statistics_jobs(&self) -> CronResult<()> {
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // Update server statistics every 10 minutes
-        let update_server_stats_job = Job::new_async("0 */10 * * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting update server stats job");
-                if let Err(e) = update_server_stats::execute(pool).await {
-                    error!("Update server stats job failed: {}", e);
-                } else {
-                    info!("Update server stats job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create update server stats job: {}", e)))?;
-
-        self.scheduler.add(update_server_stats_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add update server stats job: {}", e)))?;
-
-        info!("Registered statistics jobs");
-        Ok(())
-    }
-
-    /// Register cleanup jobs
-    async fn register_cleanup_jobs(&self) -> CronResult<()> {
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // SafeNet update every 30 minutes
-        let safenet_update_job = Job::new_async("0 */30 * * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting SafeNet update job");
-                if let Err(e) = safenet_update::execute(pool).await {
-                    error!("SafeNet update job failed: {}", e);
-                } else {
-                    info!("SafeNet update job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create SafeNet update job: {}", e)))?;
-
-        let db_pool = Arc::clone(&self.db_pool);
-
-        // Doom updater every minute (for checking doom virus countdown)
-        let doom_updater_job = Job::new_async("0 * * * * *", move |_uuid, _l| {
-            let pool = Arc::clone(&db_pool);
-            Box::pin(async move {
-                info!("Starting doom updater job");
-                if let Err(e) = doom_updater::execute(pool).await {
-                    error!("Doom updater job failed: {}", e);
-                } else {
-                    info!("Doom updater job completed successfully");
-                }
-            })
-        })
-        .map_err(|e| CronError::Runtime(format!("Failed to create doom updater job: {}", e)))?;
-
-        self.scheduler.add(safenet_update_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add SafeNet update job: {}", e)))?;
-        
-        self.scheduler.add(doom_updater_job).await
-            .map_err(|e| CronError::Runtime(format!("Failed to add doom updater job: {}", e)))?;
-
-        info!("Registered cleanup jobs");
+        info!("Registered {} job ({})", job.name, job.schedule);
         Ok(())
     }
 
@@ -286,4 +184,32 @@ impl CronScheduler {
             .map_err(|e| CronError::Runtime(format!("Failed to shutdown scheduler: {}", e)))?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::HashSet;
+
+    #[test]
+    fn job_table_is_consistent() {
+        let mut names = HashSet::new();
+        for job in JOBS {
+            assert!(names.insert(job.name), "duplicate job {}", job.name);
+            assert!(job.next_run(Utc::now()).is_some(), "bad schedule for {}", job.name);
+            assert!(job.name.len() <= 64);
+        }
+    }
+
+    #[test]
+    fn next_run_follows_schedule() {
+        let defcon = find_job("defcon").unwrap();
+        let at = Utc.with_ymd_and_hms(2024, 9, 28, 10, 3, 12).unwrap();
+        assert_eq!(defcon.next_run(at), Some(Utc.with_ymd_and_hms(2024, 9, 28, 10, 5, 0).unwrap()));
+
+        // Strictly after, so a tick does not report itself as next
+        let on_tick = Utc.with_ymd_and_hms(2024, 9, 28, 10, 5, 0).unwrap();
+        assert_eq!(defcon.next_run(on_tick), Some(Utc.with_ymd_and_hms(2024, 9, 28, 10, 10, 0).unwrap()));
+    }
+}
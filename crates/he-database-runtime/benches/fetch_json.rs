@@ -0,0 +1,75 @@
+//! Benchmarks for turning query results into JSON: the old per-row type
+//! matching against the decoder table
+//!
+//! Needs a database; set DATABASE_URL to run them.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use he_database_runtime::SimpleDatabase;
+use sqlx::postgres::PgRow;
+use sqlx::{Column, Row, TypeInfo};
+
+/// Rows with the types game tables use most
+const QUERY: &str = "SELECT n::int4 AS id, n::int8 * 1000 AS money, 'player_' || n AS name, n % 2 = 0 AS online, \
+     now() - n * interval '1 minute' AS created_at, (n * 1.25)::numeric(12, 2) AS balance, \
+     md5(n::text)::uuid AS token, jsonb_build_object('level', n % 100) AS stats \
+     FROM generate_series(1, $1::int4) AS n";
+
+/// `SimpleDatabase::fetch_json` before the decoder table
+fn legacy_row_to_json(row: &PgRow) -> serde_json::Value {
+    let mut json_obj = serde_json::Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        let value = match column.type_info().name() {
+            "INT4" => row.try_get::<i32, _>(i).map(Into::into).unwrap_or(serde_json::Value::Null),
+            "INT8" => row.try_get::<i64, _>(i).map(Into::into).unwrap_or(serde_json::Value::Null),
+            "VARCHAR" | "TEXT" => row.try_get::<String, _>(i).map(Into::into).unwrap_or(serde_json::Value::Null),
+            "BOOL" => row.try_get::<bool, _>(i).map(Into::into).unwrap_or(serde_json::Value::Null),
+            "TIMESTAMP" | "TIMESTAMPTZ" => row
+                .try_get::<chrono::DateTime<chrono::Utc>, _>(i)
+                .map(|val| val.to_rfc3339().into())
+                .unwrap_or(serde_json::Value::Null),
+            _ => row.try_get::<String, _>(i).map(Into::into).unwrap_or(serde_json::Value::Null),
+        };
+        json_obj.insert(column.name().to_string(), value);
+    }
+    serde_json::Value::Object(json_obj)
+}
+
+fn benchmark_fetch_json(c: &mut Criterion) {
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        println!("DATABASE_URL is not set, skipping the fetch_json benchmarks");
+        return;
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let db = runtime.block_on(SimpleDatabase::connect(&url)).unwrap();
+    let mut group = c.benchmark_group("fetch_json");
+    group.sample_size(20);
+
+    for rows in [1_000, 50_000] {
+        let params = vec![rows.to_string()];
+        group.bench_with_input(BenchmarkId::new("legacy", rows), &params, |b, params| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let rows = sqlx::query(QUERY).bind(&params[0]).fetch_all(db.pool()).await.unwrap();
+                    rows.iter().map(legacy_row_to_json).collect::<Vec<_>>()
+                })
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("decoder_table", rows), &params, |b, params| {
+            b.iter(|| runtime.block_on(db.fetch_json(QUERY, params)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("export_csv", rows), &params, |b, params| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let mut out = Vec::new();
+                    db.export(QUERY, params, he_database_runtime::ExportFormat::Csv, &mut out).await.unwrap();
+                    out
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_fetch_json);
+criterion_main!(benches);
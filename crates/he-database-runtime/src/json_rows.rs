@@ -0,0 +1,366 @@
+//! Rows to JSON without per-row type matching
+//!
+//! A [`RowDecoder`] looks at a result set's column types once and keeps a
+//! decoder per column, so converting a row is a straight walk over the
+//! table. Values keep their meaning in JSON:
+//!
+//! - NUMERIC becomes a string, since a JSON number would lose precision
+//! - UUIDs, dates and times become their usual text forms
+//! - JSON and JSONB are embedded as they are
+//! - BYTEA becomes Postgres' hex form, `\x0a1b`
+//! - one-dimensional arrays of the scalar types become JSON arrays
+//!
+//! Anything else is read as text where Postgres allows it and null
+//! otherwise, as before.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde_json::{Map, Number, Value};
+use sqlx::postgres::{PgColumn, PgRow, PgValueFormat, PgValueRef};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+use std::fmt::Write;
+
+/// A column type with a decoder of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scalar {
+    Bool,
+    Int2,
+    Int4,
+    Int8,
+    Float4,
+    Float8,
+    Numeric,
+    Text,
+    Uuid,
+    Json,
+    Bytea,
+    Timestamp,
+    TimestampTz,
+    Date,
+    Time,
+}
+
+impl Scalar {
+    fn from_type(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "BOOL" => Scalar::Bool,
+            "INT2" => Scalar::Int2,
+            "INT4" => Scalar::Int4,
+            "INT8" => Scalar::Int8,
+            "FLOAT4" => Scalar::Float4,
+            "FLOAT8" => Scalar::Float8,
+            "NUMERIC" => Scalar::Numeric,
+            "TEXT" | "VARCHAR" | "CHAR" | "BPCHAR" | "NAME" => Scalar::Text,
+            "UUID" => Scalar::Uuid,
+            "JSON" | "JSONB" => Scalar::Json,
+            "BYTEA" => Scalar::Bytea,
+            "TIMESTAMP" => Scalar::Timestamp,
+            "TIMESTAMPTZ" => Scalar::TimestampTz,
+            "DATE" => Scalar::Date,
+            "TIME" => Scalar::Time,
+            _ => return None,
+        })
+    }
+
+    /// Whether arrays of this type decode element by element
+    fn arrays(&self) -> bool {
+        !matches!(self, Scalar::Numeric | Scalar::Bytea | Scalar::Json | Scalar::Timestamp | Scalar::Time)
+    }
+}
+
+/// How one column is turned into JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnDecoder {
+    Scalar(Scalar),
+    Array(Scalar),
+    /// Text if Postgres sends it as text, otherwise null
+    Fallback,
+}
+
+impl ColumnDecoder {
+    /// The decoder for a column of the type sqlx names `name`, e.g. `INT4`
+    /// or `TEXT[]`
+    pub fn for_type(name: &str) -> ColumnDecoder {
+        if let Some(element) = name.strip_suffix("[]") {
+            return match Scalar::from_type(element) {
+                Some(scalar) if scalar.arrays() => ColumnDecoder::Array(scalar),
+                _ => ColumnDecoder::Fallback,
+            };
+        }
+        Scalar::from_type(name).map(ColumnDecoder::Scalar).unwrap_or(ColumnDecoder::Fallback)
+    }
+
+    fn decode(&self, row: &PgRow, index: usize) -> Result<Value, sqlx::Error> {
+        let raw = row.try_get_raw(index)?;
+        if raw.is_null() {
+            return Ok(Value::Null);
+        }
+        match self {
+            ColumnDecoder::Scalar(Scalar::Numeric) => Ok(numeric(raw).map(Value::String).unwrap_or(Value::Null)),
+            ColumnDecoder::Scalar(scalar) => scalar_value(row, index, *scalar),
+            ColumnDecoder::Array(scalar) => array_value(row, index, *scalar),
+            ColumnDecoder::Fallback => Ok(fallback(row, index, raw)),
+        }
+    }
+}
+
+fn scalar_value(row: &PgRow, index: usize, scalar: Scalar) -> Result<Value, sqlx::Error> {
+    Ok(match scalar {
+        Scalar::Bool => Value::Bool(row.try_get(index)?),
+        Scalar::Int2 => row.try_get::<i16, _>(index)?.into(),
+        Scalar::Int4 => row.try_get::<i32, _>(index)?.into(),
+        Scalar::Int8 => row.try_get::<i64, _>(index)?.into(),
+        Scalar::Float4 => float(row.try_get::<f32, _>(index)? as f64),
+        Scalar::Float8 => float(row.try_get(index)?),
+        Scalar::Text => Value::String(row.try_get(index)?),
+        Scalar::Uuid => Value::String(row.try_get::<uuid::Uuid, _>(index)?.to_string()),
+        Scalar::Json => row.try_get(index)?,
+        Scalar::Bytea => Value::String(hex(&row.try_get::<Vec<u8>, _>(index)?)),
+        Scalar::Timestamp => Value::String(timestamp(row.try_get(index)?)),
+        Scalar::TimestampTz => Value::String(row.try_get::<DateTime<Utc>, _>(index)?.to_rfc3339()),
+        Scalar::Date => Value::String(row.try_get::<NaiveDate, _>(index)?.to_string()),
+        Scalar::Time => Value::String(row.try_get::<NaiveTime, _>(index)?.to_string()),
+        // Read from the raw value in `ColumnDecoder::decode`
+        Scalar::Numeric => Value::Null,
+    })
+}
+
+fn array_value(row: &PgRow, index: usize, scalar: Scalar) -> Result<Value, sqlx::Error> {
+    fn list<T>(items: Vec<Option<T>>, to_value: impl Fn(T) -> Value) -> Value {
+        Value::Array(items.into_iter().map(|item| item.map(&to_value).unwrap_or(Value::Null)).collect())
+    }
+
+    Ok(match scalar {
+        Scalar::Bool => list::<bool>(row.try_get(index)?, Value::Bool),
+        Scalar::Int2 => list::<i16>(row.try_get(index)?, Value::from),
+        Scalar::Int4 => list::<i32>(row.try_get(index)?, Value::from),
+        Scalar::Int8 => list::<i64>(row.try_get(index)?, Value::from),
+        Scalar::Float4 => list::<f32>(row.try_get(index)?, |f| float(f as f64)),
+        Scalar::Float8 => list::<f64>(row.try_get(index)?, float),
+        Scalar::Text => list::<String>(row.try_get(index)?, Value::String),
+        Scalar::Uuid => list::<uuid::Uuid>(row.try_get(index)?, |id| Value::String(id.to_string())),
+        Scalar::TimestampTz => list::<DateTime<Utc>>(row.try_get(index)?, |at| Value::String(at.to_rfc3339())),
+        Scalar::Date => list::<NaiveDate>(row.try_get(index)?, |date| Value::String(date.to_string())),
+        // `Scalar::arrays` sends these to the fallback
+        Scalar::Numeric | Scalar::Bytea | Scalar::Json | Scalar::Timestamp | Scalar::Time => Value::Null,
+    })
+}
+
+fn fallback(row: &PgRow, index: usize, raw: PgValueRef<'_>) -> Value {
+    if let Ok(text) = row.try_get::<String, _>(index) {
+        return Value::String(text);
+    }
+    match raw.format() {
+        PgValueFormat::Text => raw.as_bytes().ok().and_then(|bytes| std::str::from_utf8(bytes).ok()).map(Value::from),
+        PgValueFormat::Binary => None,
+    }
+    .unwrap_or(Value::Null)
+}
+
+/// Floats JSON cannot hold as numbers are spelt the way Postgres spells them
+fn float(value: f64) -> Value {
+    match Number::from_f64(value) {
+        Some(number) => Value::Number(number),
+        None if value.is_nan() => Value::String("NaN".to_string()),
+        None if value > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+fn timestamp(at: NaiveDateTime) -> String {
+    at.format("%Y-%m-%dT%H:%M:%S%.f").to_string()
+}
+
+/// Postgres' hex form of binary data
+pub fn hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(2 + bytes.len() * 2);
+    text.push_str("\\x");
+    for byte in bytes {
+        let _ = write!(text, "{:02x}", byte);
+    }
+    text
+}
+
+fn numeric(raw: PgValueRef<'_>) -> Option<String> {
+    let bytes = raw.as_bytes().ok()?;
+    match raw.format() {
+        PgValueFormat::Text => std::str::from_utf8(bytes).ok().map(str::to_string),
+        PgValueFormat::Binary => numeric_text(bytes),
+    }
+}
+
+/// The text form of a NUMERIC in Postgres' binary format: digit count,
+/// weight of the first digit, sign and display scale, then the digits in
+/// base 10000
+pub fn numeric_text(bytes: &[u8]) -> Option<String> {
+    let word = |at: usize| bytes.get(at..at + 2).map(|pair| i16::from_be_bytes([pair[0], pair[1]]));
+    let ndigits = word(0)? as usize;
+    let weight = word(2)? as i64;
+    let sign = word(4)? as u16;
+    let dscale = word(6)? as u16 as usize;
+    let digits = (0..ndigits).map(|i| word(8 + i * 2)).collect::<Option<Vec<i16>>>()?;
+    let digit = |i: i64| if i >= 0 && (i as usize) < digits.len() { digits[i as usize] } else { 0 };
+
+    let mut text = String::new();
+    match sign {
+        0x0000 => {}
+        0x4000 => text.push('-'),
+        0xC000 => return Some("NaN".to_string()),
+        0xD000 => return Some("Infinity".to_string()),
+        0xF000 => return Some("-Infinity".to_string()),
+        _ => return None,
+    }
+    if weight < 0 {
+        text.push('0');
+    } else {
+        let _ = write!(text, "{}", digit(0));
+        for i in 1..=weight {
+            let _ = write!(text, "{:04}", digit(i));
+        }
+    }
+    if dscale > 0 {
+        let mut fraction = String::with_capacity(dscale + 4);
+        let mut i = weight + 1;
+        while fraction.len() < dscale {
+            let _ = write!(fraction, "{:04}", digit(i));
+            i += 1;
+        }
+        fraction.truncate(dscale);
+        text.push('.');
+        text.push_str(&fraction);
+    }
+    Some(text)
+}
+
+/// The decoders of one result set, built from its first row
+#[derive(Debug, Clone)]
+pub struct RowDecoder {
+    columns: Vec<(String, ColumnDecoder)>,
+}
+
+impl RowDecoder {
+    pub fn new(columns: &[PgColumn]) -> Self {
+        Self {
+            columns: columns
+                .iter()
+                .map(|column| (column.name().to_string(), ColumnDecoder::for_type(column.type_info().name())))
+                .collect(),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn decoders(&self) -> impl Iterator<Item = ColumnDecoder> + '_ {
+        self.columns.iter().map(|(_, decoder)| *decoder)
+    }
+
+    /// The row as an object keyed by column name
+    pub fn to_object(&self, row: &PgRow) -> anyhow::Result<Value> {
+        let mut object = Map::with_capacity(self.columns.len());
+        for (index, (name, decoder)) in self.columns.iter().enumerate() {
+            let value = decoder.decode(row, index).map_err(|e| anyhow::anyhow!("Failed to decode column {}: {}", name, e))?;
+            object.insert(name.clone(), value);
+        }
+        Ok(Value::Object(object))
+    }
+
+    /// The row's values in column order
+    pub fn to_values(&self, row: &PgRow) -> anyhow::Result<Vec<Value>> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(index, (name, decoder))| {
+                decoder.decode(row, index).map_err(|e| anyhow::anyhow!("Failed to decode column {}: {}", name, e))
+            })
+            .collect()
+    }
+}
+
+/// How [`crate::SimpleDatabase::export`] writes rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line
+    JsonLines,
+    /// A header row of column names, then one line per row. Nulls are empty
+    /// cells; arrays and JSON are written as JSON text.
+    Csv,
+}
+
+/// Append `cells` to `out` as one CSV line
+pub fn csv_line<'a>(out: &mut Vec<u8>, cells: impl Iterator<Item = CsvCell<'a>>) {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        let text = match cell {
+            CsvCell::Name(name) => std::borrow::Cow::Borrowed(name),
+            CsvCell::Value(Value::Null) => std::borrow::Cow::Borrowed(""),
+            CsvCell::Value(Value::String(text)) => std::borrow::Cow::Borrowed(text.as_str()),
+            CsvCell::Value(value) => std::borrow::Cow::Owned(value.to_string()),
+        };
+        if text.contains([',', '"', '\n', '\r']) {
+            out.push(b'"');
+            out.extend_from_slice(text.replace('"', "\"\"").as_bytes());
+            out.push(b'"');
+        } else {
+            out.extend_from_slice(text.as_bytes());
+        }
+    }
+    out.push(b'\n');
+}
+
+/// A header name or a value, for [`csv_line`]
+pub enum CsvCell<'a> {
+    Name(&'a str),
+    Value(&'a Value),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numeric_bytes(weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for word in [digits.len() as i16, weight, sign as i16, dscale as i16].into_iter().chain(digits.iter().copied()) {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_decoders_by_type() {
+        assert_eq!(ColumnDecoder::for_type("INT8"), ColumnDecoder::Scalar(Scalar::Int8));
+        assert_eq!(ColumnDecoder::for_type("JSONB"), ColumnDecoder::Scalar(Scalar::Json));
+        assert_eq!(ColumnDecoder::for_type("BPCHAR"), ColumnDecoder::Scalar(Scalar::Text));
+        assert_eq!(ColumnDecoder::for_type("UUID[]"), ColumnDecoder::Array(Scalar::Uuid));
+        assert_eq!(ColumnDecoder::for_type("NUMERIC[]"), ColumnDecoder::Fallback);
+        assert_eq!(ColumnDecoder::for_type("INET"), ColumnDecoder::Fallback);
+    }
+
+    #[test]
+    fn test_numeric_text() {
+        assert_eq!(numeric_text(&numeric_bytes(1, 0, 3, &[1, 2345, 6780])).as_deref(), Some("12345.678"));
+        assert_eq!(numeric_text(&numeric_bytes(-1, 0x4000, 2, &[500])).as_deref(), Some("-0.05"));
+        assert_eq!(numeric_text(&numeric_bytes(0, 0, 0, &[])).as_deref(), Some("0"));
+        assert_eq!(numeric_text(&numeric_bytes(2, 0, 0, &[12])).as_deref(), Some("1200000000"));
+        // More digits than an f64 holds
+        let big = numeric_bytes(4, 0, 4, &[1234, 5678, 9012, 3456, 7890, 1234]);
+        assert_eq!(numeric_text(&big).as_deref(), Some("12345678901234567890.1234"));
+        assert_eq!(numeric_text(&numeric_bytes(0, 0xC000, 0, &[])).as_deref(), Some("NaN"));
+        assert_eq!(numeric_text(&[0, 1]), None);
+    }
+
+    #[test]
+    fn test_csv_lines_and_hex() {
+        let mut out = Vec::new();
+        csv_line(&mut out, ["id", "note"].into_iter().map(CsvCell::Name));
+        let values = [Value::from(7), Value::from("says \"hi\", twice"), Value::Null, serde_json::json!([1, 2])];
+        csv_line(&mut out, values.iter().map(CsvCell::Value));
+        assert_eq!(String::from_utf8(out).unwrap(), "id,note\n7,\"says \"\"hi\"\", twice\",,\"[1,2]\"\n");
+
+        assert_eq!(hex(&[0x0a, 0x1b, 0xff]), "\\x0a1bff");
+        assert_eq!(float(f64::NAN), Value::from("NaN"));
+        assert_eq!(float(1.5), Value::from(1.5));
+    }
+}
@@ -1,8 +1,19 @@
+pub mod json_rows;
+
 use anyhow::{Result, Context};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use he_degradation::{Degradation, Dependency, DependencyPolicy};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, PgPool, Row, Column, TypeInfo};
+use sqlx::postgres::PgRow;
+use sqlx::{Postgres, PgPool, Row};
+
+pub use json_rows::{ColumnDecoder, ExportFormat, RowDecoder};
+use json_rows::{csv_line, CsvCell};
 use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use tracing::{info, warn, error};
 
 /// Database configuration for runtime connection
@@ -94,16 +105,26 @@ impl DatabaseConfig {
 }
 
 /// Multi-database manager for HackerExperience
+///
+/// Each configured secondary database is a `db_<name>` dependency in the
+/// shared [`Degradation`] registry. By default it fails open: while it is
+/// degraded its traffic goes to the main database. With
+/// `DEGRADE_DB_<NAME>=closed` its calls fail instead. Secondaries that are
+/// not configured always use the main database.
 #[derive(Debug)]
 pub struct DatabaseManager {
     pub pools: HashMap<String, PgPool>,
     pub config: DatabaseConfig,
+    dependencies: HashMap<String, Arc<Dependency>>,
+    degradation: Arc<Degradation>,
 }
 
 impl DatabaseManager {
-    /// Create a new database manager with configuration
-    pub async fn new(config: DatabaseConfig) -> Result<Self> {
+    /// Create a new database manager with configuration, guarding the
+    /// secondary databases in `degradation`
+    pub async fn new(config: DatabaseConfig, degradation: Arc<Degradation>) -> Result<Self> {
         let mut pools = HashMap::new();
+        let mut dependencies = HashMap::new();
         
         // Connect to main database
         info!("Connecting to main database...");
@@ -130,16 +151,27 @@ impl DatabaseManager {
         for (name, url_opt) in db_configs {
             if let Some(url) = url_opt {
                 info!("Connecting to {} database...", name);
-                match create_pool(url, &config).await {
-                    Ok(pool) => {
-                        pools.insert(name.to_string(), pool);
-                    }
+                let (pool, startup_error) = match create_pool(url, &config).await {
+                    Ok(pool) => (pool, None),
                     Err(e) => {
                         warn!("Failed to connect to {} database: {}", name, e);
-                        // Use main database as fallback
-                        pools.insert(name.to_string(), pools.get("main").map_err(|e| anyhow::anyhow!("Error: {}", e))?.clone());
+                        // Connects on first use once the probe sees it back
+                        (create_lazy_pool(url, &config)?, Some(e))
                     }
+                };
+
+                let probe_pool = pool.clone();
+                let dependency = degradation.register(&format!("db_{}", name), DependencyPolicy::fail_open(), move || {
+                    let pool = probe_pool.clone();
+                    async move {
+                        sqlx::query("SELECT 1").execute(&pool).await.map(|_| ()).map_err(|e| e.to_string())
+                    }
+                });
+                if let Some(e) = startup_error {
+                    dependency.trip(format!("{:#}", e));
                 }
+                pools.insert(name.to_string(), pool);
+                dependencies.insert(name.to_string(), dependency);
             } else {
                 // Use main database for undefined specialized databases
                 pools.insert(name.to_string(), pools.get("main").map_err(|e| anyhow::anyhow!("Error: {}", e))?.clone());
@@ -148,12 +180,33 @@ impl DatabaseManager {
         
         info!("Database manager initialized with {} pools", pools.len());
         
-        Ok(Self { pools, config })
+        Ok(Self { pools, config, dependencies, degradation })
     }
     
-    /// Get database pool by name
+    /// Get the pool to use for a database right now: the main pool while a
+    /// fail-open secondary is degraded, `None` while a fail-closed one is
     pub fn get_pool(&self, name: &str) -> Option<&PgPool> {
-        self.pools.get(name)
+        self.route(name).ok().map(|(pool, _)| pool)
+    }
+
+    /// The registry the secondary databases are guarded in
+    pub fn degradation(&self) -> &Arc<Degradation> {
+        &self.degradation
+    }
+
+    /// The pool for `name`, and the dependency to report the call's outcome
+    /// to when it goes to the secondary itself
+    fn route(&self, name: &str) -> Result<(&PgPool, Option<&Dependency>)> {
+        let pool = self.pools.get(name)
+            .ok_or_else(|| anyhow::anyhow!("Database {} not found", name))?;
+        let Some(dependency) = self.dependencies.get(name) else {
+            return Ok((pool, None));
+        };
+        if dependency.admit()? {
+            return Ok((pool, Some(dependency)));
+        }
+        // Fail-open and degraded
+        Ok((self.main_pool(), None))
     }
     
     /// Get main database pool
@@ -186,16 +239,16 @@ impl DatabaseManager {
         query: &str,
         params: &[String],
     ) -> Result<sqlx::postgres::PgQueryResult> {
-        let pool = self.get_pool(db_name)
-            .ok_or_else(|| anyhow::anyhow!("Database {} not found", db_name))?;
+        let (pool, dependency) = self.route(db_name)?;
         
         let mut query_builder = sqlx::query(query);
         for param in params {
             query_builder = query_builder.bind(param);
         }
         
-        query_builder.execute(pool).await
-            .context(format!("Failed to execute query on {} database", db_name))
+        let result = query_builder.execute(pool).await;
+        record_outcome(dependency, &result);
+        result.context(format!("Failed to execute query on {} database", db_name))
     }
     
     /// Fetch rows from a specific database
@@ -205,19 +258,43 @@ impl DatabaseManager {
         query: &str,
         params: &[String],
     ) -> Result<Vec<sqlx::postgres::PgRow>> {
-        let pool = self.get_pool(db_name)
-            .ok_or_else(|| anyhow::anyhow!("Database {} not found", db_name))?;
+        let (pool, dependency) = self.route(db_name)?;
         
         let mut query_builder = sqlx::query(query);
         for param in params {
             query_builder = query_builder.bind(param);
         }
         
-        query_builder.fetch_all(pool).await
-            .context(format!("Failed to fetch rows from {} database", db_name))
+        let result = query_builder.fetch_all(pool).await;
+        record_outcome(dependency, &result);
+        result.context(format!("Failed to fetch rows from {} database", db_name))
     }
 }
 
+/// Report a secondary database call to its breaker. Query errors mean the
+/// database answered, so only connection failures count against it.
+fn record_outcome<T>(dependency: Option<&Dependency>, result: &std::result::Result<T, sqlx::Error>) {
+    let Some(dependency) = dependency else { return };
+    match result {
+        Err(e @ (sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::Protocol(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed)) => dependency.record_failure(e),
+        _ => dependency.record_success(),
+    }
+}
+
+/// Create a connection pool that connects on first use
+fn create_lazy_pool(url: &str, config: &DatabaseConfig) -> Result<PgPool> {
+    sqlx::postgres::PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(std::time::Duration::from_secs(config.connect_timeout))
+        .idle_timeout(std::time::Duration::from_secs(config.idle_timeout))
+        .connect_lazy(url)
+        .context("Invalid database URL")
+}
+
 /// Create a connection pool with configuration
 async fn create_pool(url: &str, config: &DatabaseConfig) -> Result<PgPool> {
     let pool = sqlx::postgres::PgPoolOptions::new()
@@ -261,78 +338,70 @@ impl SimpleDatabase {
         Ok(result.rows_affected())
     }
     
-    /// Fetch rows as JSON values
+    /// Fetch rows as JSON values. Rows are decoded as they arrive, so only
+    /// the JSON is held, not the rows as well.
     pub async fn fetch_json(&self, query: &str, params: &[String]) -> Result<Vec<serde_json::Value>> {
+        self.stream_json(query, params).try_collect().await
+    }
+
+    /// Rows as JSON values, one at a time, for results too big to collect
+    pub fn stream_json<'a>(&'a self, query: &'a str, params: &'a [String]) -> BoxStream<'a, Result<serde_json::Value>> {
+        let mut decoder: Option<RowDecoder> = None;
+        self.fetch_rows(query, params)
+            .map(move |row| {
+                let row = row.context("Failed to fetch rows")?;
+                decoder.get_or_insert_with(|| RowDecoder::new(row.columns())).to_object(&row)
+            })
+            .boxed()
+    }
+
+    /// Write the result of `query` to `writer` without holding it in memory,
+    /// returning the number of rows written. An empty CSV export has no
+    /// header, since the columns are only known from the first row.
+    pub async fn export<W: AsyncWrite + Unpin>(
+        &self,
+        query: &str,
+        params: &[String],
+        format: ExportFormat,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let mut rows = self.fetch_rows(query, params);
+        let mut decoder: Option<RowDecoder> = None;
+        let mut line = Vec::new();
+        let mut written = 0;
+
+        while let Some(row) = rows.try_next().await.context("Failed to fetch rows")? {
+            line.clear();
+            let decoder = decoder.get_or_insert_with(|| {
+                let decoder = RowDecoder::new(row.columns());
+                if format == ExportFormat::Csv {
+                    csv_line(&mut line, decoder.names().map(CsvCell::Name));
+                }
+                decoder
+            });
+            match format {
+                ExportFormat::JsonLines => {
+                    serde_json::to_writer(&mut line, &decoder.to_object(&row)?)?;
+                    line.push(b'\n');
+                }
+                ExportFormat::Csv => csv_line(&mut line, decoder.to_values(&row)?.iter().map(CsvCell::Value)),
+            }
+            writer.write_all(&line).await.context("Failed to write export")?;
+            written += 1;
+        }
+
+        writer.flush().await.context("Failed to write export")?;
+        Ok(written)
+    }
+
+    fn fetch_rows<'a>(&'a self, query: &'a str, params: &'a [String]) -> BoxStream<'a, Result<PgRow, sqlx::Error>> {
         let mut sqlx_query = sqlx::query(query);
-        
+
         for param in params {
             sqlx_query = sqlx_query.bind(param);
         }
-        
-        let rows = sqlx_query.fetch_all(&self.pool).await
-            .context("Failed to fetch rows")?;
-        
-        let mut results = Vec::new();
-        for row in rows {
-            let mut json_obj = serde_json::Map::new();
-            
-            for (i, column) in row.columns().iter().enumerate() {
-                let column_name = column.name();
-                
-                // Convert different PostgreSQL types to JSON values
-                let value = match column.type_info().name() {
-                    "INT4" => {
-                        if let Ok(val) = row.try_get::<i32, _>(i) {
-                            serde_json::Value::Number(val.into())
-                        } else {
-                            serde_json::Value::Null
-                        }
-                    }
-                    "INT8" => {
-                        if let Ok(val) = row.try_get::<i64, _>(i) {
-                            serde_json::Value::Number(val.into())
-                        } else {
-                            serde_json::Value::Null
-                        }
-                    }
-                    "VARCHAR" | "TEXT" => {
-                        if let Ok(val) = row.try_get::<String, _>(i) {
-                            serde_json::Value::String(val)
-                        } else {
-                            serde_json::Value::Null
-                        }
-                    }
-                    "BOOL" => {
-                        if let Ok(val) = row.try_get::<bool, _>(i) {
-                            serde_json::Value::Bool(val)
-                        } else {
-                            serde_json::Value::Null
-                        }
-                    }
-                    "TIMESTAMP" | "TIMESTAMPTZ" => {
-                        if let Ok(val) = row.try_get::<chrono::DateTime<chrono::Utc>, _>(i) {
-                            serde_json::Value::String(val.to_rfc3339())
-                        } else {
-                            serde_json::Value::Null
-                        }
-                    }
-                    _ => {
-                        // Try to get as string for unknown types
-                        if let Ok(val) = row.try_get::<String, _>(i) {
-                            serde_json::Value::String(val)
-                        } else {
-                            serde_json::Value::Null
-                        }
-                    }
-                };
-                
-                json_obj.insert(column_name.to_string(), value);
-            }
-            
-            results.push(serde_json::Value::Object(json_obj));
-        }
-        
-        Ok(results)
+
+        sqlx_query.fetch(&self.pool)
     }
     
     /// Health check
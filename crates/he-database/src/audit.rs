@@ -0,0 +1,554 @@
+//! SQL query audit and slow-query logging
+//!
+//! Pools connected with [`connect_options`] have sqlx report every statement
+//! it runs, with its duration and row counts, as a `sqlx::query` tracing
+//! event; statements slower than the pool's [`PoolAuditConfig::slow_threshold`]
+//! are reported as slow. [`QueryAudit::layer`] picks those events up:
+//!
+//! - Every query is passed to the [`QuerySink`] (the Helix log, or tracing
+//!   by default) with its duration and row counts. The normalized SQL goes
+//!   with one query in every `sample_every`, and with every slow one.
+//! - Totals are kept per normalized statement, so literals and placeholders
+//!   don't split one query into many, and the worst offenders are listed by
+//!   [`QueryAudit::top`].
+//! - Outside production, a statement slower than the hard threshold has its
+//!   plan captured once with `EXPLAIN (GENERIC_PLAN)`, which plans it without
+//!   running it or needing its parameters.
+
+use serde::Serialize;
+use sqlx::postgres::{PgConnectOptions, PgPool};
+use sqlx::ConnectOptions;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::{info, warn, Event, Subscriber};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Target sqlx reports statements under
+pub const SQLX_TARGET: &str = "sqlx::query";
+
+/// Distinct statements tracked; anything new after that is counted under
+/// [`OTHER`]
+const MAX_STATEMENTS: usize = 2000;
+const OTHER: &str = "<other>";
+/// Longest normalized statement kept
+const MAX_STATEMENT_LENGTH: usize = 2000;
+
+/// Slow-query settings for one pool
+#[derive(Debug, Clone)]
+pub struct PoolAuditConfig {
+    /// Queries at least this slow are logged as slow
+    pub slow_threshold: Duration,
+}
+
+impl PoolAuditConfig {
+    /// `DB_SLOW_QUERY_MS_<POOL>` for this pool, falling back to
+    /// `DB_SLOW_QUERY_MS`, 200ms when neither is set
+    pub fn from_env(pool: &str) -> Self {
+        let ms = std::env::var(format!("DB_SLOW_QUERY_MS_{}", pool.to_ascii_uppercase()))
+            .or_else(|_| std::env::var("DB_SLOW_QUERY_MS"))
+            .ok()
+            .and_then(|ms| ms.parse().ok())
+            .unwrap_or(200);
+        Self { slow_threshold: Duration::from_millis(ms) }
+    }
+}
+
+/// Connect options for `url` that report every statement to the audit
+pub fn connect_options(url: &str, config: &PoolAuditConfig) -> Result<PgConnectOptions, sqlx::Error> {
+    Ok(url
+        .parse::<PgConnectOptions>()?
+        .log_statements(log::LevelFilter::Debug)
+        .log_slow_statements(log::LevelFilter::Warn, config.slow_threshold))
+}
+
+/// Connect a pool named `pool` to `url` with its slow-query threshold from
+/// the environment
+pub async fn connect(url: &str, pool: &str) -> Result<PgPool, sqlx::Error> {
+    PgPool::connect_with(connect_options(url, &PoolAuditConfig::from_env(pool))?).await
+}
+
+/// Settings shared by all pools
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// Include the normalized SQL with one query in this many
+    pub sample_every: u64,
+    /// Capture the plan of queries at least this slow; `None` in production
+    pub explain_threshold: Option<Duration>,
+}
+
+impl AuditConfig {
+    /// `DB_QUERY_SAMPLE_EVERY` (default 100) and `DB_EXPLAIN_QUERY_MS`
+    /// (default 1000). Plans are never captured when `APP_ENVIRONMENT` is
+    /// production or unset.
+    pub fn from_env() -> Self {
+        let production = std::env::var("APP_ENVIRONMENT").map_or(true, |env| env.eq_ignore_ascii_case("production"));
+        let sample_every = std::env::var("DB_QUERY_SAMPLE_EVERY")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(100);
+        let explain_ms = std::env::var("DB_EXPLAIN_QUERY_MS")
+            .ok()
+            .and_then(|ms| ms.parse().ok())
+            .unwrap_or(1000);
+        Self {
+            sample_every: sample_every.max(1),
+            explain_threshold: (!production).then(|| Duration::from_millis(explain_ms)),
+        }
+    }
+}
+
+/// One statement as sqlx reported it
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryRecord {
+    /// First words of the statement, e.g. `SELECT id, name …`
+    pub summary: String,
+    /// Normalized SQL, when this query was sampled or slow
+    pub statement: Option<String>,
+    pub duration: Duration,
+    pub rows_affected: u64,
+    pub rows_returned: u64,
+    pub slow: bool,
+    /// The request that ran it, if any (see [`crate::tagging::log_tag`])
+    pub request: String,
+}
+
+/// Where each query is logged
+pub trait QuerySink: Send + Sync {
+    fn record(&self, record: &QueryRecord);
+}
+
+/// Logs queries through tracing: slow ones as warnings, the rest at debug
+pub struct TracingSink;
+
+impl QuerySink for TracingSink {
+    fn record(&self, record: &QueryRecord) {
+        let statement = record.statement.as_deref().unwrap_or(&record.summary);
+        if record.slow {
+            warn!(
+                target: "he_database::audit",
+                duration_ms = record.duration.as_millis() as u64,
+                rows_affected = record.rows_affected,
+                rows_returned = record.rows_returned,
+                "Slow query [{}]: {}",
+                record.request,
+                statement
+            );
+        } else {
+            tracing::debug!(
+                target: "he_database::audit",
+                duration_ms = record.duration.as_millis() as u64,
+                rows_affected = record.rows_affected,
+                rows_returned = record.rows_returned,
+                "Query [{}]: {}",
+                record.request,
+                statement
+            );
+        }
+    }
+}
+
+/// Totals for one normalized statement
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryStat {
+    pub statement: String,
+    pub calls: u64,
+    pub slow_calls: u64,
+    pub total_ms: f64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    pub rows_returned: u64,
+    pub rows_affected: u64,
+    /// Plan captured when it passed the hard threshold
+    pub plan: Option<serde_json::Value>,
+}
+
+/// How [`QueryAudit::top`] ranks statements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopOrder {
+    TotalTime,
+    MaxTime,
+    Calls,
+    SlowCalls,
+}
+
+impl TopOrder {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "total_time" => Some(TopOrder::TotalTime),
+            "max_time" => Some(TopOrder::MaxTime),
+            "calls" => Some(TopOrder::Calls),
+            "slow_calls" => Some(TopOrder::SlowCalls),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    calls: u64,
+    slow_calls: u64,
+    total: Duration,
+    max: Duration,
+    rows_returned: u64,
+    rows_affected: u64,
+    /// A plan was asked for, whether or not it came back
+    explained: bool,
+    plan: Option<serde_json::Value>,
+}
+
+/// Collects what sqlx reports about every query
+pub struct QueryAudit {
+    config: AuditConfig,
+    sink: Box<dyn QuerySink>,
+    seen: AtomicU64,
+    totals: Arc<Mutex<HashMap<String, Totals>>>,
+    /// Pool plans are captured on
+    explain_pool: OnceLock<PgPool>,
+}
+
+impl QueryAudit {
+    pub fn new(config: AuditConfig, sink: Box<dyn QuerySink>) -> Self {
+        Self {
+            config,
+            sink,
+            seen: AtomicU64::new(0),
+            totals: Arc::new(Mutex::new(HashMap::new())),
+            explain_pool: OnceLock::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(AuditConfig::from_env(), Box::new(TracingSink))
+    }
+
+    /// Capture plans on `pool`. Until this is called none are.
+    pub fn attach_pool(&self, pool: PgPool) {
+        let _ = self.explain_pool.set(pool);
+    }
+
+    /// Tracing layer feeding this audit, listening only to sqlx's statements
+    pub fn layer<S>(self: &Arc<Self>) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        AuditLayer { audit: Arc::clone(self) }.with_filter(Targets::new().with_target(SQLX_TARGET, LevelFilter::DEBUG))
+    }
+
+    /// Queries seen since startup
+    pub fn queries_seen(&self) -> u64 {
+        self.seen.load(Ordering::Relaxed)
+    }
+
+    /// The `n` worst statements by `order`
+    pub fn top(&self, n: usize, order: TopOrder) -> Vec<QueryStat> {
+        let totals = self.totals.lock().unwrap();
+        let mut stats: Vec<QueryStat> = totals
+            .iter()
+            .map(|(statement, t)| {
+                let total_ms = t.total.as_secs_f64() * 1000.0;
+                QueryStat {
+                    statement: statement.clone(),
+                    calls: t.calls,
+                    slow_calls: t.slow_calls,
+                    total_ms,
+                    mean_ms: if t.calls == 0 { 0.0 } else { total_ms / t.calls as f64 },
+                    max_ms: t.max.as_secs_f64() * 1000.0,
+                    rows_returned: t.rows_returned,
+                    rows_affected: t.rows_affected,
+                    plan: t.plan.clone(),
+                }
+            })
+            .collect();
+        drop(totals);
+
+        match order {
+            TopOrder::TotalTime => stats.sort_by(|a, b| b.total_ms.total_cmp(&a.total_ms)),
+            TopOrder::MaxTime => stats.sort_by(|a, b| b.max_ms.total_cmp(&a.max_ms)),
+            TopOrder::Calls => stats.sort_by(|a, b| b.calls.cmp(&a.calls)),
+            TopOrder::SlowCalls => stats.sort_by(|a, b| b.slow_calls.cmp(&a.slow_calls)),
+        }
+        stats.truncate(n);
+        stats
+    }
+
+    /// Forget all totals and captured plans
+    pub fn reset(&self) {
+        self.totals.lock().unwrap().clear();
+    }
+
+    fn observe(&self, statement: Statement) {
+        let Some(duration) = statement.elapsed else {
+            return;
+        };
+        let sql = statement.sql();
+        if is_explain(sql) {
+            // Our own plan captures
+            return;
+        }
+
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        let normalized = normalize(sql);
+        let explain = self.record_totals(&normalized, &statement, duration);
+
+        let sampled = seen % self.config.sample_every == 0;
+        self.sink.record(&QueryRecord {
+            summary: statement.summary.clone(),
+            statement: (sampled || statement.slow).then(|| normalized.clone()),
+            duration,
+            rows_affected: statement.rows_affected,
+            rows_returned: statement.rows_returned,
+            slow: statement.slow,
+            request: crate::tagging::log_tag(),
+        });
+
+        if explain {
+            self.capture_plan(normalized, sql.to_string());
+        }
+    }
+
+    /// Add a query to its statement's totals; true if its plan should be
+    /// captured now
+    fn record_totals(&self, normalized: &str, statement: &Statement, duration: Duration) -> bool {
+        let mut totals = self.totals.lock().unwrap();
+        let key = if totals.len() >= MAX_STATEMENTS && !totals.contains_key(normalized) {
+            OTHER.to_string()
+        } else {
+            normalized.to_string()
+        };
+        let t = totals.entry(key.clone()).or_default();
+        t.calls += 1;
+        t.total += duration;
+        t.max = t.max.max(duration);
+        t.rows_returned += statement.rows_returned;
+        t.rows_affected += statement.rows_affected;
+        if statement.slow {
+            t.slow_calls += 1;
+        }
+
+        let over = self.config.explain_threshold.is_some_and(|threshold| duration >= threshold);
+        let explain = over && !t.explained && key != OTHER && self.explain_pool.get().is_some();
+        if explain {
+            t.explained = true;
+        }
+        explain
+    }
+
+    /// Plan `sql` in the background and keep it with its statement's totals
+    fn capture_plan(&self, normalized: String, sql: String) {
+        let (Some(pool), Ok(runtime)) = (self.explain_pool.get(), tokio::runtime::Handle::try_current()) else {
+            return;
+        };
+        let pool = pool.clone();
+        let totals = Arc::clone(&self.totals);
+
+        runtime.spawn(async move {
+            let explain = format!("EXPLAIN (GENERIC_PLAN, FORMAT JSON) {}", sql);
+            match sqlx::query_scalar::<_, serde_json::Value>(&explain).fetch_one(&pool).await {
+                Ok(plan) => {
+                    info!(target: "he_database::audit", "Captured plan of slow query: {}", normalized);
+                    if let Some(t) = totals.lock().unwrap().get_mut(&normalized) {
+                        t.plan = Some(plan);
+                    }
+                }
+                Err(e) => warn!(target: "he_database::audit", "Could not explain slow query {}: {}", normalized, e),
+            }
+        });
+    }
+}
+
+/// The fields of one `sqlx::query` event
+#[derive(Debug, Default)]
+struct Statement {
+    summary: String,
+    /// Full SQL; sqlx leaves it empty when the summary is the whole statement
+    statement: String,
+    rows_affected: u64,
+    rows_returned: u64,
+    elapsed: Option<Duration>,
+    /// sqlx only sets `slow_threshold` on slow statements
+    slow: bool,
+}
+
+impl Statement {
+    fn sql(&self) -> &str {
+        let statement = self.statement.trim();
+        if statement.is_empty() {
+            self.summary.trim()
+        } else {
+            statement
+        }
+    }
+}
+
+impl Visit for Statement {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "summary" => self.summary = value.to_string(),
+            "db.statement" => self.statement = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "rows_affected" => self.rows_affected = value,
+            "rows_returned" => self.rows_returned = value,
+            _ => {}
+        }
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        if field.name() == "elapsed_secs" && value.is_finite() && value >= 0.0 {
+            self.elapsed = Some(Duration::from_secs_f64(value));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, _value: &dyn fmt::Debug) {
+        if field.name() == "slow_threshold" {
+            self.slow = true;
+        }
+    }
+}
+
+struct AuditLayer {
+    audit: Arc<QueryAudit>,
+}
+
+impl<S: Subscriber> Layer<S> for AuditLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if event.metadata().target() != SQLX_TARGET {
+            return;
+        }
+        let mut statement = Statement::default();
+        event.record(&mut statement);
+        self.audit.observe(statement);
+    }
+}
+
+fn is_explain(sql: &str) -> bool {
+    sql.get(..7).is_some_and(|start| start.eq_ignore_ascii_case("EXPLAIN"))
+}
+
+/// `sql` with literals and placeholders replaced by `?`, lists of them
+/// collapsed to one, and whitespace squeezed
+pub fn normalize(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len().min(MAX_STATEMENT_LENGTH));
+    let mut chars = sql.chars().peekable();
+    let mut space = false;
+
+    while let Some(c) = chars.next() {
+        if out.len() >= MAX_STATEMENT_LENGTH {
+            out.push_str(" …");
+            break;
+        }
+        match c {
+            c if c.is_whitespace() => {
+                space = !out.is_empty();
+                continue;
+            }
+            // String literal, '' escapes included
+            '\'' => {
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                push_token(&mut out, &mut space, "?");
+            }
+            // Placeholder
+            '$' if chars.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                while chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    chars.next();
+                }
+                push_token(&mut out, &mut space, "?");
+            }
+            // Number, unless it ends an identifier like `t1`
+            c if c.is_ascii_digit() && (space || !out.ends_with(|p: char| p.is_alphanumeric() || p == '_')) => {
+                while chars.peek().is_some_and(|c| c.is_ascii_digit() || *c == '.') {
+                    chars.next();
+                }
+                push_token(&mut out, &mut space, "?");
+            }
+            c => {
+                if space {
+                    out.push(' ');
+                    space = false;
+                }
+                out.push(c);
+            }
+        }
+        collapse_list(&mut out);
+    }
+    out
+}
+
+fn push_token(out: &mut String, space: &mut bool, token: &str) {
+    if *space {
+        out.push(' ');
+        *space = false;
+    }
+    out.push_str(token);
+}
+
+/// `(?, ?, ?` becomes `(?`
+fn collapse_list(out: &mut String) {
+    while out.ends_with("?, ?") || out.ends_with("?,?") {
+        let cut = if out.ends_with("?, ?") { 3 } else { 2 };
+        out.truncate(out.len() - cut);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("SELECT * FROM users\n   WHERE id = $1 AND name = 'o''brien' AND level > 10"),
+            "SELECT * FROM users WHERE id = ? AND name = ? AND level > ?"
+        );
+        assert_eq!(normalize("DELETE FROM t1 WHERE id IN (1, 2, 3)"), "DELETE FROM t1 WHERE id IN (?)");
+        assert!(is_explain("explain select 1"));
+        assert!(!is_explain("SELECT 1"));
+    }
+
+    #[test]
+    fn test_top_ranks_statements() {
+        let audit = QueryAudit::new(AuditConfig { sample_every: 1, explain_threshold: None }, Box::new(TracingSink));
+        let query = |sql: &str, ms: u64, slow: bool| Statement {
+            summary: sql.to_string(),
+            elapsed: Some(Duration::from_millis(ms)),
+            rows_returned: 1,
+            slow,
+            ..Default::default()
+        };
+        audit.observe(query("SELECT a FROM t WHERE id = 1", 5, false));
+        audit.observe(query("SELECT a FROM t WHERE id = 2", 5, false));
+        audit.observe(query("SELECT b FROM u", 50, true));
+        audit.observe(query("EXPLAIN SELECT b FROM u", 500, true));
+
+        assert_eq!(audit.queries_seen(), 3);
+        let by_total = audit.top(10, TopOrder::TotalTime);
+        assert_eq!(by_total.len(), 2);
+        assert_eq!(by_total[0].statement, "SELECT b FROM u");
+        assert_eq!(by_total[0].slow_calls, 1);
+
+        let by_calls = audit.top(1, TopOrder::Calls);
+        assert_eq!(by_calls[0].statement, "SELECT a FROM t WHERE id = ?");
+        assert_eq!(by_calls[0].calls, 2);
+        assert_eq!(by_calls[0].rows_returned, 2);
+
+        audit.reset();
+        assert!(audit.top(10, TopOrder::Calls).is_empty());
+    }
+}
@@ -53,7 +53,7 @@ impl UserBatchQueries {
             let user_with_stats = UserWithStats {
                 user_id: row.user_id,
                 login: row.login,
-                email: row.email,
+                email: crate::encryption::open(&row.email)?,
                 created: row.created,
                 last_login: row.last_login,
                 premium: row.premium,
@@ -265,6 +265,14 @@ pub mod cache_keys {
     pub fn session_key(session_id: &str) -> String {
         format!("session:{}", session_id)
     }
+
+    pub fn marketplace_search_key(query: &str, software_type: &str, min_version: f64, page: i64) -> String {
+        format!("marketplace:search:{}:{}:{}:{}", query, software_type, min_version, page)
+    }
+
+    pub fn marketplace_listing_key(listing_id: i64) -> String {
+        format!("marketplace:listing:{}", listing_id)
+    }
 }
 
 /// Cache warming strategies
@@ -0,0 +1,368 @@
+//! Encrypted account fields and key rotation
+//!
+//! Emails and IP addresses are sealed with the process keyring from
+//! `he_helix_security::encryption`, tagged with the key version they are
+//! sealed under. Without a keyring they are stored in plaintext as before.
+//!
+//! Rotating keys: every node is configured with the new version alongside
+//! the old ones, reports what it holds to `encryption_nodes`, and an
+//! operator starts the rotation with `he-cli encryption rotate`. Nodes pick
+//! the new active version up on their next sync, and the re-encryption job
+//! reseals rows still under an older version until none are left.
+
+use crate::models::User;
+use crate::queries::{EncryptionQueries, KeyVersionRow, NodeKeysRow, RotationRow};
+use anyhow::Result;
+use he_helix_security::encryption::{self as keyring, FieldEncryption};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+/// Nodes that reported within this long count as live for rotations
+pub const LIVE_NODE_SECS: i64 = 180;
+
+/// A value as written to the database
+#[derive(Debug, Clone)]
+pub struct Stored {
+    pub value: String,
+    /// `None` when stored in plaintext
+    pub key_version: Option<i32>,
+}
+
+/// `plaintext` sealed under the active key, or as is without a keyring
+pub fn seal(plaintext: &str) -> Result<Stored> {
+    match keyring::with_keyring(|keyring| keyring.seal(plaintext).map(|v| (v, keyring.active_version()))) {
+        Some(sealed) => {
+            let (value, version) = sealed?;
+            Ok(Stored { value, key_version: Some(version as i32) })
+        }
+        None => Ok(Stored { value: plaintext.to_string(), key_version: None }),
+    }
+}
+
+pub fn open(stored: &str) -> Result<String> {
+    keyring::open_stored(stored)
+}
+
+pub fn email_index(email: &str) -> Option<String> {
+    keyring::email_index(email)
+}
+
+pub fn ip_index(ip: &str) -> Option<String> {
+    keyring::with_keyring(|keyring| keyring.blind_index(ip.trim()))
+}
+
+/// `user` with its email and last IP decrypted
+pub fn reveal(mut user: User) -> Result<User> {
+    user.email = open(&user.email)?;
+    user.last_ip = open(&user.last_ip)?;
+    Ok(user)
+}
+
+/// Why a key change was refused
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum KeyChangeBlocked {
+    /// The keyring running the command does not hold the version
+    KeyNotConfigured { version: u32 },
+    AlreadyActive { version: u32 },
+    /// Registered before with a different key
+    FingerprintMismatch { version: u32 },
+    RotationInProgress { rotation_id: i64 },
+    /// Live nodes without the version, or with a different key under it
+    NodesMissingKey { nodes: Vec<String> },
+    UnknownVersion { version: u32 },
+    NotRetiring { version: u32 },
+    StillInUse { version: u32, rows: i64 },
+}
+
+impl KeyChangeBlocked {
+    pub fn message(&self) -> String {
+        match self {
+            KeyChangeBlocked::KeyNotConfigured { version } => {
+                format!("Key version {} is not in FIELD_ENCRYPTION_KEYS here", version)
+            }
+            KeyChangeBlocked::AlreadyActive { version } => format!("Key version {} is already active", version),
+            KeyChangeBlocked::FingerprintMismatch { version } => {
+                format!("Key version {} was registered with a different key", version)
+            }
+            KeyChangeBlocked::RotationInProgress { rotation_id } => {
+                format!("Rotation {} has not finished re-encrypting", rotation_id)
+            }
+            KeyChangeBlocked::NodesMissingKey { nodes } => {
+                format!("These nodes do not hold the new key: {}", nodes.join(", "))
+            }
+            KeyChangeBlocked::UnknownVersion { version } => format!("Key version {} is not registered", version),
+            KeyChangeBlocked::NotRetiring { version } => {
+                format!("Key version {} is not being retired", version)
+            }
+            KeyChangeBlocked::StillInUse { version, rows } => {
+                format!("{} values are still sealed under key version {}", rows, version)
+            }
+        }
+    }
+}
+
+/// Whether rotating to `target` is safe: the key is the one registered
+/// under its version if any, every live node holds it, and the previous
+/// rotation finished
+pub fn check_rotation(
+    target: u32,
+    local_fingerprint: Option<&str>,
+    registered: &[KeyVersionRow],
+    live_nodes: &[NodeKeysRow],
+    open_rotation: Option<&RotationRow>,
+) -> Result<(), KeyChangeBlocked> {
+    let Some(fingerprint) = local_fingerprint else {
+        return Err(KeyChangeBlocked::KeyNotConfigured { version: target });
+    };
+    if let Some(rotation) = open_rotation {
+        return Err(KeyChangeBlocked::RotationInProgress { rotation_id: rotation.id });
+    }
+    if let Some(key) = registered.iter().find(|key| key.version == target as i32) {
+        if key.fingerprint != fingerprint {
+            return Err(KeyChangeBlocked::FingerprintMismatch { version: target });
+        }
+        if key.state == "active" {
+            return Err(KeyChangeBlocked::AlreadyActive { version: target });
+        }
+    }
+
+    let missing: Vec<String> = live_nodes
+        .iter()
+        .filter(|node| {
+            let held = node.versions.iter().position(|&v| v == target as i32);
+            held.and_then(|i| node.fingerprints.get(i)).map(String::as_str) != Some(fingerprint)
+        })
+        .map(|node| node.node_id.clone())
+        .collect();
+    if !missing.is_empty() {
+        return Err(KeyChangeBlocked::NodesMissingKey { nodes: missing });
+    }
+    Ok(())
+}
+
+/// Whether `version` can be retired, with `rows` values still sealed under it
+pub fn check_retire(version: u32, registered: &[KeyVersionRow], rows: i64) -> Result<(), KeyChangeBlocked> {
+    let Some(key) = registered.iter().find(|key| key.version == version as i32) else {
+        return Err(KeyChangeBlocked::UnknownVersion { version });
+    };
+    if key.state != "retiring" {
+        return Err(KeyChangeBlocked::NotRetiring { version });
+    }
+    if rows > 0 {
+        return Err(KeyChangeBlocked::StillInUse { version, rows });
+    }
+    Ok(())
+}
+
+/// [`check_rotation`] against the registry and this process's keyring.
+/// Returns the fingerprint of the target key.
+pub async fn preflight(pool: &PgPool, target: u32) -> Result<Result<String, KeyChangeBlocked>> {
+    let fingerprint = keyring::with_keyring(|keyring| keyring.fingerprint(target)).flatten();
+    let registered = EncryptionQueries::key_versions(pool).await?;
+    let nodes = EncryptionQueries::live_nodes(pool, LIVE_NODE_SECS).await?;
+    let open = EncryptionQueries::open_rotation(pool).await?;
+    Ok(check_rotation(target, fingerprint.as_deref(), &registered, &nodes, open.as_ref())
+        .map(|()| fingerprint.unwrap_or_default()))
+}
+
+/// Start rotating to `target` if [`preflight`] allows it
+pub async fn rotate(pool: &PgPool, target: u32, started_by: &str) -> Result<Result<RotationRow, KeyChangeBlocked>> {
+    let fingerprint = match preflight(pool, target).await? {
+        Ok(fingerprint) => fingerprint,
+        Err(blocked) => return Ok(Err(blocked)),
+    };
+
+    match EncryptionQueries::start_rotation(pool, target as i32, &fingerprint, started_by).await? {
+        Some(rotation) => Ok(Ok(rotation)),
+        // Another rotation started since the check
+        None => {
+            let rotation_id = EncryptionQueries::open_rotation(pool).await?.map(|r| r.id).unwrap_or_default();
+            Ok(Err(KeyChangeBlocked::RotationInProgress { rotation_id }))
+        }
+    }
+}
+
+/// Retire `version` once nothing is sealed under it any more
+pub async fn retire(pool: &PgPool, version: u32) -> Result<Result<(), KeyChangeBlocked>> {
+    let registered = EncryptionQueries::key_versions(pool).await?;
+    let rows: i64 = EncryptionQueries::key_usage(pool)
+        .await?
+        .iter()
+        .filter(|usage| usage.key_version == Some(version as i32))
+        .map(|usage| usage.rows)
+        .sum();
+    if let Err(blocked) = check_retire(version, &registered, rows) {
+        return Ok(Err(blocked));
+    }
+
+    if EncryptionQueries::retire(pool, version as i32).await? {
+        Ok(Ok(()))
+    } else {
+        Ok(Err(KeyChangeBlocked::NotRetiring { version }))
+    }
+}
+
+/// Report this node's keys and seal with the registered active version.
+/// Returns the version sealed under, `None` without a keyring.
+pub async fn sync(pool: &PgPool, node_id: &str) -> Result<Option<u32>> {
+    let Some((versions, fingerprints)) = keyring::with_keyring(|keyring| {
+        let versions = keyring.versions();
+        let fingerprints: Vec<String> = versions.iter().filter_map(|&v| keyring.fingerprint(v)).collect();
+        (versions, fingerprints)
+    }) else {
+        return Ok(None);
+    };
+
+    let mut registered = EncryptionQueries::key_versions(pool).await?;
+    if registered.is_empty() {
+        let first = versions[0];
+        if EncryptionQueries::bootstrap(pool, first as i32, &fingerprints[0], node_id).await? {
+            info!("Registered field encryption key v{}, sealing existing account fields", first);
+        }
+        registered = EncryptionQueries::key_versions(pool).await?;
+    }
+
+    for key in &registered {
+        let held = versions.iter().position(|&v| v as i32 == key.version);
+        if let Some(i) = held {
+            if fingerprints[i] != key.fingerprint {
+                error!("Field encryption key v{} differs from the registered one", key.version);
+            }
+        }
+    }
+
+    if let Some(active) = registered.iter().find(|key| key.state == "active") {
+        let version = active.version as u32;
+        let matches = keyring::with_keyring(|keyring| keyring.fingerprint(version)).flatten().as_deref()
+            == Some(active.fingerprint.as_str());
+        if !matches {
+            error!("Field encryption key v{} is active but not configured on this node", version);
+        } else if keyring::with_keyring(FieldEncryption::active_version) != Some(version) {
+            keyring::set_active_version(version)?;
+            info!("Sealing account fields with key v{}", version);
+        }
+    }
+
+    let active = keyring::with_keyring(FieldEncryption::active_version);
+    let versions: Vec<i32> = versions.iter().map(|&v| v as i32).collect();
+    EncryptionQueries::report_node(pool, node_id, &versions, &fingerprints, active.map(|v| v as i32)).await?;
+
+    Ok(active)
+}
+
+/// Reseal up to `limit` accounts and IP history entries under the active
+/// key while a rotation is open, closing it once none are left. Returns
+/// how many were resealed, `None` when there is nothing to do.
+pub async fn reseal_batch(pool: &PgPool, limit: i64) -> Result<Option<usize>> {
+    let Some(rotation) = EncryptionQueries::open_rotation(pool).await? else {
+        return Ok(None);
+    };
+    let Some(active) = keyring::with_keyring(FieldEncryption::active_version) else {
+        return Ok(None);
+    };
+    if active as i32 != rotation.to_version {
+        // Not synced to the new version yet
+        return Ok(None);
+    }
+    let version = active as i32;
+
+    let mut tx = crate::tagging::begin(pool).await?;
+    let accounts = EncryptionQueries::lock_stale_accounts(&mut tx, version, limit).await?;
+    for account in &accounts {
+        let email = open(&account.email)?;
+        let sealed_email = seal(&email)?;
+        let sealed_ip = match &account.last_ip {
+            Some(ip) => Some(seal(&open(ip)?)?),
+            None => None,
+        };
+        EncryptionQueries::update_account_fields(
+            &mut tx,
+            account.id,
+            &sealed_email.value,
+            email_index(&email).as_deref(),
+            sealed_email.key_version,
+            sealed_ip.as_ref().map(|ip| ip.value.as_str()),
+            sealed_ip.as_ref().and_then(|ip| ip.key_version),
+        )
+        .await?;
+    }
+
+    let history = EncryptionQueries::lock_stale_ip_history(&mut tx, version, limit).await?;
+    for (id, stored) in &history {
+        let ip = open(stored)?;
+        let sealed = seal(&ip)?;
+        EncryptionQueries::update_ip_history(&mut tx, *id, &sealed.value, ip_index(&ip).as_deref(), sealed.key_version)
+            .await?;
+    }
+
+    // Done once nothing is left and no live node still seals under an
+    // older version
+    let resealed = accounts.len() + history.len();
+    let complete = resealed == 0
+        && EncryptionQueries::live_nodes(pool, LIVE_NODE_SECS)
+            .await?
+            .iter()
+            .all(|node| node.active_version == Some(version));
+    EncryptionQueries::record_resealed(&mut tx, rotation.id, resealed as i64, complete).await?;
+    tx.commit().await?;
+
+    if complete {
+        info!("Rotation {} to key v{} finished after {} rows", rotation.id, version, rotation.rows_resealed);
+    }
+    Ok(Some(resealed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn key(version: i32, fingerprint: &str, state: &str) -> KeyVersionRow {
+        KeyVersionRow {
+            version,
+            fingerprint: fingerprint.to_string(),
+            state: state.to_string(),
+            registered_at: Utc::now(),
+            activated_at: None,
+            retired_at: None,
+        }
+    }
+
+    fn node(id: &str, keys: &[(i32, &str)]) -> NodeKeysRow {
+        NodeKeysRow {
+            node_id: id.to_string(),
+            versions: keys.iter().map(|(v, _)| *v).collect(),
+            fingerprints: keys.iter().map(|(_, f)| f.to_string()).collect(),
+            active_version: Some(1),
+            seen_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_rotation_checks() {
+        let registered = vec![key(1, "aa", "active")];
+        let ready = vec![node("a", &[(1, "aa"), (2, "bb")]), node("b", &[(1, "aa"), (2, "bb")])];
+        assert_eq!(check_rotation(2, Some("bb"), &registered, &ready, None), Ok(()));
+        assert_eq!(
+            check_rotation(2, None, &registered, &ready, None),
+            Err(KeyChangeBlocked::KeyNotConfigured { version: 2 })
+        );
+        assert_eq!(
+            check_rotation(1, Some("aa"), &registered, &ready, None),
+            Err(KeyChangeBlocked::AlreadyActive { version: 1 })
+        );
+
+        let behind = vec![node("a", &[(1, "aa"), (2, "bb")]), node("b", &[(1, "aa")]), node("c", &[(2, "cc")])];
+        assert_eq!(
+            check_rotation(2, Some("bb"), &registered, &behind, None),
+            Err(KeyChangeBlocked::NodesMissingKey { nodes: vec!["b".into(), "c".into()] })
+        );
+
+        let retiring = vec![key(1, "aa", "retiring"), key(2, "bb", "active")];
+        assert_eq!(check_retire(1, &retiring, 3), Err(KeyChangeBlocked::StillInUse { version: 1, rows: 3 }));
+        assert_eq!(check_retire(1, &retiring, 0), Ok(()));
+        assert_eq!(check_retire(2, &retiring, 0), Err(KeyChangeBlocked::NotRetiring { version: 2 }));
+    }
+}
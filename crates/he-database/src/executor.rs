@@ -0,0 +1,503 @@
+//! Query execution with timeout budgets, hedged reads and circuit breakers
+//!
+//! Wrap a query in [`QueryExecutor::read`] or [`QueryExecutor::write`] with a
+//! [`QueryBudget`]:
+//!
+//! - Every attempt runs against a deadline. A query that blows its budget fails
+//!   with [`QueryError::Timeout`] instead of holding up the request.
+//! - Reads with `hedge_after` set, and a replica configured, start a second
+//!   attempt on the replica once the primary is slower than that threshold.
+//!   The first success wins and the other attempt is dropped.
+//! - Each pool has a circuit breaker. When the error rate in the current window
+//!   passes the threshold the breaker opens, and queries against that pool fail
+//!   fast with [`QueryError::CircuitOpen`] (reads go to the replica instead). After
+//!   `open_for`, one probe query is let through to decide whether to close it.
+//!
+//! Timeouts, errors, rejections and hedges are counted separately in
+//! [`ExecutorMetrics`] and exported to Prometheus as `db_queries_total`,
+//! `db_query_hedges_total` and `db_circuit_state`.
+
+use prometheus::{register_int_counter_vec, register_int_gauge_vec, IntCounterVec, IntGaugeVec};
+use serde::Serialize;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+lazy_static::lazy_static! {
+    static ref QUERIES: IntCounterVec = register_int_counter_vec!(
+        "db_queries_total",
+        "Queries run through the executor by outcome",
+        &["query", "outcome"]
+    ).unwrap();
+
+    static ref HEDGES: IntCounterVec = register_int_counter_vec!(
+        "db_query_hedges_total",
+        "Replica attempts started for slow reads, and how many won",
+        &["result"]
+    ).unwrap();
+
+    static ref CIRCUIT_STATE: IntGaugeVec = register_int_gauge_vec!(
+        "db_circuit_state",
+        "Circuit breaker state per pool: 0 closed, 1 half-open, 2 open",
+        &["pool"]
+    ).unwrap();
+}
+
+/// Time allowed for one query, and when to hedge it
+#[derive(Debug, Clone, Copy)]
+pub struct QueryBudget {
+    pub timeout: Duration,
+    /// Start a replica attempt if the primary hasn't answered by then (reads only)
+    pub hedge_after: Option<Duration>,
+}
+
+impl QueryBudget {
+    pub const fn new(timeout: Duration) -> Self {
+        Self { timeout, hedge_after: None }
+    }
+
+    pub const fn with_hedge(mut self, after: Duration) -> Self {
+        self.hedge_after = Some(after);
+        self
+    }
+
+    /// Point lookups on indexed columns
+    pub const fn lookup() -> Self {
+        Self::new(Duration::from_millis(500)).with_hedge(Duration::from_millis(100))
+    }
+
+    /// Lists, joins and aggregates
+    pub const fn standard() -> Self {
+        Self::new(Duration::from_secs(2)).with_hedge(Duration::from_millis(400))
+    }
+
+    /// Writes and reports, never hedged
+    pub const fn slow() -> Self {
+        Self::new(Duration::from_secs(10))
+    }
+}
+
+#[derive(Debug)]
+pub enum QueryError {
+    /// The budget ran out before any attempt finished
+    Timeout { query: &'static str, budget: Duration },
+    /// The breaker for this pool is open
+    CircuitOpen { query: &'static str, pool: &'static str },
+    Database(sqlx::Error),
+}
+
+impl QueryError {
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, QueryError::Timeout { .. })
+    }
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Timeout { query, budget } => write!(f, "Query {} exceeded its {:?} budget", query, budget),
+            QueryError::CircuitOpen { query, pool } => write!(f, "Query {} rejected: {} circuit open", query, pool),
+            QueryError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QueryError::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for QueryError {
+    fn from(err: sqlx::Error) -> Self {
+        QueryError::Database(err)
+    }
+}
+
+/// Circuit breaker settings
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    /// Outcomes are counted per window and reset when it ends
+    pub window: Duration,
+    /// Don't trip on a handful of requests
+    pub min_requests: u32,
+    /// Failure ratio (errors + timeouts) that opens the breaker
+    pub error_rate: f64,
+    pub open_for: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(10),
+            min_requests: 20,
+            error_rate: 0.5,
+            open_for: Duration::from_secs(15),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    /// One probe is in flight
+    HalfOpen,
+}
+
+impl BreakerState {
+    fn gauge(self) -> i64 {
+        match self {
+            BreakerState::Closed => 0,
+            BreakerState::HalfOpen => 1,
+            BreakerState::Open => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    window_start: Instant,
+    requests: u32,
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// Error-rate circuit breaker for one pool
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    config: BreakerConfig,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, config: BreakerConfig) -> Self {
+        CIRCUIT_STATE.with_label_values(&[name]).set(BreakerState::Closed.gauge());
+        Self {
+            name,
+            config,
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                window_start: Instant::now(),
+                requests: 0,
+                failures: 0,
+                opened_at: None,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Whether a query may run now. Moves an expired open breaker to half-open
+    /// and lets exactly one probe through.
+    pub fn allow(&self, now: Instant) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            BreakerState::Closed => true,
+            // A probe that never reported back (dropped by a hedge race) expires too
+            BreakerState::Open | BreakerState::HalfOpen => {
+                let expired = inner.opened_at.map_or(true, |at| now.duration_since(at) >= self.config.open_for);
+                if expired {
+                    inner.state = BreakerState::HalfOpen;
+                    inner.opened_at = Some(now);
+                    CIRCUIT_STATE.with_label_values(&[self.name]).set(inner.state.gauge());
+                }
+                expired
+            }
+        }
+    }
+
+    pub fn record(&self, success: bool, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+
+        match inner.state {
+            BreakerState::HalfOpen => {
+                if success {
+                    info!("Circuit for {} closed after successful probe", self.name);
+                    inner.state = BreakerState::Closed;
+                    inner.opened_at = None;
+                    inner.window_start = now;
+                    inner.requests = 0;
+                    inner.failures = 0;
+                } else {
+                    inner.state = BreakerState::Open;
+                    inner.opened_at = Some(now);
+                }
+            }
+            BreakerState::Open => {}
+            BreakerState::Closed => {
+                if now.duration_since(inner.window_start) >= self.config.window {
+                    inner.window_start = now;
+                    inner.requests = 0;
+                    inner.failures = 0;
+                }
+
+                inner.requests += 1;
+                if !success {
+                    inner.failures += 1;
+                }
+
+                let rate = inner.failures as f64 / inner.requests as f64;
+                if inner.requests >= self.config.min_requests && rate >= self.config.error_rate {
+                    warn!(
+                        "Circuit for {} opened: {}/{} queries failed",
+                        self.name, inner.failures, inner.requests
+                    );
+                    inner.state = BreakerState::Open;
+                    inner.opened_at = Some(now);
+                }
+            }
+        }
+
+        CIRCUIT_STATE.with_label_values(&[self.name]).set(inner.state.gauge());
+    }
+}
+
+/// Counters for all queries through one executor
+#[derive(Debug, Default)]
+pub struct ExecutorMetrics {
+    queries: AtomicU64,
+    successes: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+    circuit_rejections: AtomicU64,
+    hedges_started: AtomicU64,
+    hedges_won: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutorStats {
+    pub queries: u64,
+    pub successes: u64,
+    pub errors: u64,
+    pub timeouts: u64,
+    pub circuit_rejections: u64,
+    pub hedges_started: u64,
+    pub hedges_won: u64,
+    pub primary_circuit: BreakerState,
+    pub replica_circuit: Option<BreakerState>,
+}
+
+struct Target {
+    name: &'static str,
+    pool: PgPool,
+    breaker: CircuitBreaker,
+}
+
+/// Runs queries against a primary pool and an optional read replica
+pub struct QueryExecutor {
+    primary: Target,
+    replica: Option<Target>,
+    metrics: Arc<ExecutorMetrics>,
+}
+
+impl QueryExecutor {
+    pub fn new(primary: PgPool, replica: Option<PgPool>, breaker: BreakerConfig) -> Self {
+        Self {
+            primary: Target {
+                name: "primary",
+                pool: primary,
+                breaker: CircuitBreaker::new("primary", breaker.clone()),
+            },
+            replica: replica.map(|pool| Target {
+                name: "replica",
+                pool,
+                breaker: CircuitBreaker::new("replica", breaker),
+            }),
+            metrics: Arc::new(ExecutorMetrics::default()),
+        }
+    }
+
+    /// Use `DATABASE_REPLICA_URL` as the hedge target when it is set
+    pub async fn from_env(primary: PgPool) -> anyhow::Result<Self> {
+        let replica = match std::env::var("DATABASE_REPLICA_URL") {
+            Ok(url) => {
+                let pool = PgPoolOptions::new()
+                    .max_connections(primary.options().get_max_connections())
+                    .acquire_timeout(Duration::from_secs(5))
+                    .connect_with(crate::audit::connect_options(&url, &crate::audit::PoolAuditConfig::from_env("replica"))?)
+                    .await?;
+                info!("Read replica configured for hedged reads");
+                Some(pool)
+            }
+            Err(_) => None,
+        };
+
+        Ok(Self::new(primary, replica, BreakerConfig::default()))
+    }
+
+    pub fn stats(&self) -> ExecutorStats {
+        let m = &self.metrics;
+        ExecutorStats {
+            queries: m.queries.load(Ordering::Relaxed),
+            successes: m.successes.load(Ordering::Relaxed),
+            errors: m.errors.load(Ordering::Relaxed),
+            timeouts: m.timeouts.load(Ordering::Relaxed),
+            circuit_rejections: m.circuit_rejections.load(Ordering::Relaxed),
+            hedges_started: m.hedges_started.load(Ordering::Relaxed),
+            hedges_won: m.hedges_won.load(Ordering::Relaxed),
+            primary_circuit: self.primary.breaker.state(),
+            replica_circuit: self.replica.as_ref().map(|r| r.breaker.state()),
+        }
+    }
+
+    /// Run a write, or a read that must see the primary, on the primary.
+    /// Never hedged: a write isn't safe to run twice.
+    pub async fn write<T, F, Fut>(&self, query: &'static str, budget: QueryBudget, f: F) -> Result<T, QueryError>
+    where
+        F: FnOnce(PgPool) -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        self.metrics.queries.fetch_add(1, Ordering::Relaxed);
+        let deadline = Instant::now() + budget.timeout;
+        let result = self.attempt(&self.primary, query, budget, deadline, f).await;
+        self.finish(query, &result);
+        result
+    }
+
+    /// Run a read. `f` is called once per attempt, so it may run twice when
+    /// the query is hedged.
+    pub async fn read<T, F, Fut>(&self, query: &'static str, budget: QueryBudget, f: F) -> Result<T, QueryError>
+    where
+        F: Fn(PgPool) -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        self.metrics.queries.fetch_add(1, Ordering::Relaxed);
+        let deadline = Instant::now() + budget.timeout;
+
+        let result = match (&self.replica, budget.hedge_after) {
+            (Some(replica), Some(hedge_after)) => {
+                self.hedged(replica, query, budget, deadline, hedge_after, &f).await
+            }
+            (Some(replica), None) if self.primary.breaker.state() != BreakerState::Closed => {
+                // No hedging requested, but the primary is known to be failing
+                self.attempt(replica, query, budget, deadline, &f).await
+            }
+            _ => self.attempt(&self.primary, query, budget, deadline, &f).await,
+        };
+
+        self.finish(query, &result);
+        result
+    }
+
+    async fn hedged<T, F, Fut>(
+        &self,
+        replica: &Target,
+        query: &'static str,
+        budget: QueryBudget,
+        deadline: Instant,
+        hedge_after: Duration,
+        f: &F,
+    ) -> Result<T, QueryError>
+    where
+        F: Fn(PgPool) -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        let primary = self.attempt(&self.primary, query, budget, deadline, f);
+        tokio::pin!(primary);
+
+        let primary_result = tokio::select! {
+            result = &mut primary => Some(result),
+            _ = tokio::time::sleep(hedge_after) => None,
+        };
+
+        match primary_result {
+            Some(Ok(value)) => return Ok(value),
+            // Primary failed fast (error or open circuit): the replica is the only option left
+            Some(Err(primary_err)) => {
+                return self.attempt(replica, query, budget, deadline, f).await.map_err(|_| primary_err);
+            }
+            None => {}
+        }
+
+        self.metrics.hedges_started.fetch_add(1, Ordering::Relaxed);
+        HEDGES.with_label_values(&["started"]).inc();
+        let hedge = self.attempt(replica, query, budget, deadline, f);
+        tokio::pin!(hedge);
+
+        tokio::select! {
+            result = &mut primary => match result {
+                Ok(value) => Ok(value),
+                Err(primary_err) => hedge.await.map_err(|_| primary_err).inspect(|_| {
+                    self.metrics.hedges_won.fetch_add(1, Ordering::Relaxed);
+                    HEDGES.with_label_values(&["won"]).inc();
+                }),
+            },
+            result = &mut hedge => match result {
+                Ok(value) => {
+                    self.metrics.hedges_won.fetch_add(1, Ordering::Relaxed);
+                    HEDGES.with_label_values(&["won"]).inc();
+                    Ok(value)
+                }
+                Err(_) => primary.await,
+            },
+        }
+    }
+
+    /// One attempt on one pool, checked against its breaker and the deadline
+    async fn attempt<T, F, Fut>(
+        &self,
+        target: &Target,
+        query: &'static str,
+        budget: QueryBudget,
+        deadline: Instant,
+        f: F,
+    ) -> Result<T, QueryError>
+    where
+        F: FnOnce(PgPool) -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        if !target.breaker.allow(Instant::now()) {
+            return Err(QueryError::CircuitOpen { query, pool: target.name });
+        }
+
+        match tokio::time::timeout_at(deadline, f(target.pool.clone())).await {
+            Ok(Ok(value)) => {
+                target.breaker.record(true, Instant::now());
+                Ok(value)
+            }
+            // A missing row is an answer, not a sick database
+            Ok(Err(sqlx::Error::RowNotFound)) => {
+                target.breaker.record(true, Instant::now());
+                Err(QueryError::Database(sqlx::Error::RowNotFound))
+            }
+            Ok(Err(e)) => {
+                target.breaker.record(false, Instant::now());
+                Err(QueryError::Database(e))
+            }
+            Err(_) => {
+                target.breaker.record(false, Instant::now());
+                Err(QueryError::Timeout { query, budget: budget.timeout })
+            }
+        }
+    }
+
+    fn finish<T>(&self, query: &'static str, result: &Result<T, QueryError>) {
+        let (counter, outcome) = match result {
+            Ok(_) | Err(QueryError::Database(sqlx::Error::RowNotFound)) => (&self.metrics.successes, "success"),
+            Err(QueryError::Timeout { .. }) => {
+                warn!("Query {} timed out [{}]", query, crate::tagging::log_tag());
+                (&self.metrics.timeouts, "timeout")
+            }
+            Err(QueryError::CircuitOpen { .. }) => (&self.metrics.circuit_rejections, "circuit_open"),
+            Err(QueryError::Database(e)) => {
+                warn!("Query {} failed [{}]: {}", query, crate::tagging::log_tag(), e);
+                (&self.metrics.errors, "error")
+            }
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        QUERIES.with_label_values(&[query, outcome]).inc();
+    }
+}
@@ -11,6 +11,10 @@ pub mod queries_optimized;
 pub mod cache;
 pub mod batch_queries;
 pub mod redis_cache;
+pub mod executor;
+pub mod tagging;
+pub mod audit;
+pub mod encryption;
 
 #[cfg(test)]
 mod tests;
@@ -21,6 +25,7 @@ pub use queries_optimized::*;
 pub use cache::*;
 pub use batch_queries::*;
 pub use redis_cache::{RedisCache, RedisCacheConfig, QueryCache};
+pub use executor::{ExecutorStats, QueryBudget, QueryError, QueryExecutor};
 
 /// Database connection pool
 #[derive(Debug, Clone)]
@@ -49,7 +54,7 @@ impl Database {
             .idle_timeout(Duration::from_secs(300))   // Connection idle for 5 minutes
             .max_lifetime(Duration::from_secs(3600))  // Connection lifetime of 1 hour
             .test_before_acquire(true)  // Test connection health before use
-            .connect(database_url)
+            .connect_with(audit::connect_options(database_url, &audit::PoolAuditConfig::from_env("primary"))?)
             .await?;
 
         info!(
@@ -73,7 +78,7 @@ impl Database {
             .idle_timeout(Duration::from_secs(300))
             .max_lifetime(Duration::from_secs(3600))
             .test_before_acquire(true)
-            .connect(database_url)
+            .connect_with(audit::connect_options(database_url, &audit::PoolAuditConfig::from_env("primary"))?)
             .await?;
 
         info!(
@@ -99,6 +104,11 @@ impl Database {
         &self.pool
     }
 
+    /// Begin a transaction tagged with the current request (see [`tagging`])
+    pub async fn begin(&self) -> Result<sqlx::Transaction<'static, sqlx::Postgres>> {
+        Ok(tagging::begin(&self.pool).await?)
+    }
+
     /// Health check
     pub async fn health_check(&self) -> Result<()> {
         sqlx::query("SELECT 1")
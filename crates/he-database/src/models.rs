@@ -5,6 +5,12 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+/// he-auth keys users by UUID; a numeric game account maps to the UUID
+/// holding its id in the low 64 bits
+pub fn legacy_user_uuid(user_id: i64) -> Uuid {
+    Uuid::from_u64_pair(0, user_id as u64)
+}
+
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
@@ -31,6 +37,10 @@ pub struct Process {
     pub priority: i32,
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
+    /// Set by the completion worker once rewards and logs were applied
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Set while a power spike has the process stopped
+    pub paused_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
@@ -118,4 +128,124 @@ pub struct Virus {
     pub version: String,
     pub status: String,
     pub installed_at: DateTime<Utc>,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct SoftwareListing {
+    pub id: i64,
+    pub seller_id: i64,
+    pub software_id: i64,
+    pub name: String,
+    pub software_type: String,
+    pub version: f64,
+    pub price: i64,
+    pub copies_total: i32,
+    pub copies_sold: i32,
+    pub allow_resale: bool,
+    pub royalty_percent: i16,
+    pub origin_listing_id: Option<i64>,
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SoftwareListing {
+    pub fn copies_left(&self) -> i32 {
+        (self.copies_total - self.copies_sold).max(0)
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct SoftwareLicense {
+    pub id: i64,
+    pub software_id: i64,
+    pub listing_id: i64,
+    pub buyer_id: i64,
+    pub license_key: String,
+    pub price_paid: i64,
+    pub purchased_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct EmailChangeRequest {
+    pub id: i64,
+    pub user_id: i64,
+    pub old_email: String,
+    pub new_email: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revocable_until: DateTime<Utc>,
+    pub old_confirmed_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl EmailChangeRequest {
+    pub fn is_pending(&self) -> bool {
+        self.status == "awaiting_old" || self.status == "awaiting_new"
+    }
+
+    /// Pending requests stop accepting tokens once they expire
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.is_pending() && now >= self.expires_at
+    }
+
+    /// The old address can cancel or roll back the change until `revocable_until`
+    pub fn is_revocable(&self, now: DateTime<Utc>) -> bool {
+        (self.is_pending() || self.status == "completed") && now < self.revocable_until
+    }
+}
+
+/// A he-cron job as recorded in the job registry
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct CronJob {
+    pub name: String,
+    pub schedule: String,
+    pub time_budget_seconds: i32,
+    pub paused: bool,
+    pub trigger_requested_at: Option<DateTime<Utc>>,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_started_at: Option<DateTime<Utc>>,
+    pub last_finished_at: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<i64>,
+    pub last_success: Option<bool>,
+    pub last_error: Option<String>,
+    pub run_count: i64,
+    pub failure_count: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl CronJob {
+    /// Started and not finished since
+    pub fn is_running(&self) -> bool {
+        match (self.last_started_at, self.last_finished_at) {
+            (Some(started), Some(finished)) => started > finished,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct CronJobRun {
+    pub id: i64,
+    pub job_name: String,
+    pub trigger: String,
+    pub status: String,
+    pub host: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct CronJobAlert {
+    pub id: i64,
+    pub job_name: String,
+    pub kind: String,
+    pub detail: String,
+    pub raised_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub acknowledged_by: Option<i64>,
+}
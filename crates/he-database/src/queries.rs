@@ -4,8 +4,8 @@ use crate::models::*;
 use anyhow::Result;
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use argon2::password_hash::{rand_core::OsRng, SaltString};
-use chrono::Utc;
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
 pub struct UserQueries;
@@ -23,45 +23,61 @@ impl UserQueries {
         let password_hash = argon2.hash_password(password.as_bytes(), &salt)?
             .to_string();
 
+        let sealed_email = crate::encryption::seal(email)?;
+        let sealed_ip = crate::encryption::seal("127.0.0.1")?;
         let user = sqlx::query_as!(
             User,
             r#"
-            INSERT INTO users (login, pwd, email, online, last_login, created, last_act, last_ip)
-            VALUES ($1, $2, $3, false, NOW(), NOW(), NOW(), '127.0.0.1')
-            RETURNING *
+            INSERT INTO users (login, pwd, email, email_index, email_key_version, online, last_login, created, last_act,
+                               last_ip, last_ip_key_version)
+            VALUES ($1, $2, $3, $4, $5, false, NOW(), NOW(), NOW(), $6, $7)
+            RETURNING id, login, pwd, email, online, last_login, created, last_act, last_ip AS "last_ip!"
             "#,
             login,
             password_hash,
-            email
+            sealed_email.value,
+            crate::encryption::email_index(email),
+            sealed_email.key_version,
+            sealed_ip.value,
+            sealed_ip.key_version
         )
         .fetch_one(pool)
         .await?;
 
-        Ok(user)
+        crate::encryption::reveal(user)
     }
 
+    /// Looked up by blind index, or by address for rows not sealed yet
     pub async fn get_user_by_email(pool: &PgPool, email: &str) -> Result<Option<User>> {
         let user = sqlx::query_as!(
             User,
-            "SELECT * FROM users WHERE email = $1",
-            email
+            r#"
+            SELECT id, login, pwd, email, online, last_login, created, last_act, last_ip AS "last_ip!"
+            FROM users
+            WHERE email_index = $2 OR (email_index IS NULL AND email = $1)
+            "#,
+            email,
+            crate::encryption::email_index(email)
         )
         .fetch_optional(pool)
         .await?;
 
-        Ok(user)
+        user.map(crate::encryption::reveal).transpose()
     }
 
     pub async fn get_user_by_id(pool: &PgPool, id: i64) -> Result<Option<User>> {
         let user = sqlx::query_as!(
             User,
-            "SELECT * FROM users WHERE id = $1",
+            r#"
+            SELECT id, login, pwd, email, online, last_login, created, last_act, last_ip AS "last_ip!"
+            FROM users WHERE id = $1
+            "#,
             id
         )
         .fetch_optional(pool)
         .await?;
 
-        Ok(user)
+        user.map(crate::encryption::reveal).transpose()
     }
 
     pub async fn verify_password(user: &User, password: &str) -> Result<bool> {
@@ -71,18 +87,77 @@ impl UserQueries {
             .is_ok())
     }
 
+    /// Also records `ip` in the account's IP history
     pub async fn update_last_login(pool: &PgPool, user_id: i64, ip: &str) -> Result<()> {
+        let sealed = crate::encryption::seal(ip)?;
+        let mut tx = crate::tagging::begin(pool).await?;
         sqlx::query!(
-            "UPDATE users SET last_login = NOW(), last_ip = $1, online = true WHERE id = $2",
-            ip,
+            "UPDATE users SET last_login = NOW(), last_ip = $1, last_ip_key_version = $2, online = true WHERE id = $3",
+            sealed.value,
+            sealed.key_version,
             user_id
         )
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+        sqlx::query!(
+            "INSERT INTO user_ip_history (user_id, ip, ip_index, key_version) VALUES ($1, $2, $3, $4)",
+            user_id,
+            sealed.value,
+            crate::encryption::ip_index(ip),
+            sealed.key_version
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
 
         Ok(())
     }
 
+    /// Addresses the account logged in from, newest first
+    pub async fn ip_history(pool: &PgPool, user_id: i64, limit: i64) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let rows = sqlx::query!(
+            "SELECT ip, seen_at FROM user_ip_history WHERE user_id = $1 ORDER BY seen_at DESC LIMIT $2",
+            user_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| Ok((crate::encryption::open(&row.ip)?, row.seen_at)))
+            .collect()
+    }
+
+    /// Other accounts that logged in from `ip`. Only finds entries sealed
+    /// since a keyring was installed.
+    pub async fn users_sharing_ip(pool: &PgPool, user_id: i64, ip: &str) -> Result<Vec<i64>> {
+        let Some(index) = crate::encryption::ip_index(ip) else {
+            return Ok(Vec::new());
+        };
+        let users = sqlx::query_scalar!(
+            "SELECT DISTINCT user_id FROM user_ip_history WHERE ip_index = $1 AND user_id <> $2 ORDER BY user_id",
+            index,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    /// Reason and end (`None` if permanent) of a ban still in effect
+    pub async fn active_ban(pool: &PgPool, user_id: i64) -> Result<Option<(String, Option<DateTime<Utc>>)>> {
+        let ban = sqlx::query!(
+            "SELECT reason, banned_until FROM user_bans
+             WHERE user_id = $1 AND (banned_until IS NULL OR banned_until > NOW())",
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(ban.map(|ban| (ban.reason, ban.banned_until)))
+    }
+
     pub async fn set_offline(pool: &PgPool, user_id: i64) -> Result<()> {
         sqlx::query!(
             "UPDATE users SET online = false WHERE id = $1",
@@ -95,6 +170,22 @@ impl UserQueries {
     }
 }
 
+/// A cancellation as recorded
+#[derive(Debug, Clone)]
+pub struct NewProcessCancellation<'a> {
+    pub pid: i64,
+    pub user_id: i64,
+    pub process_type: &'a str,
+    pub target_ip: Option<&'a str>,
+    pub host_server_id: Option<i64>,
+    pub cpu_released: i32,
+    pub ram_released: i32,
+    pub net_released: i32,
+    pub progress: f64,
+    pub cost: i64,
+    pub refund: i64,
+}
+
 pub struct ProcessQueries;
 
 impl ProcessQueries {
@@ -130,6 +221,20 @@ impl ProcessQueries {
         pc_id: &str,
         target_pc_id: Option<String>,
         duration_seconds: i32,
+    ) -> Result<Process> {
+        let mut conn = pool.acquire().await?;
+        Self::create_process_in(&mut conn, user_id, process_type, pc_id, target_pc_id, duration_seconds).await
+    }
+
+    /// [`create_process_with_duration`](Self::create_process_with_duration)
+    /// inside the caller's transaction, e.g. the one that checked the quota
+    pub async fn create_process_in(
+        conn: &mut PgConnection,
+        user_id: i64,
+        process_type: &str,
+        pc_id: &str,
+        target_pc_id: Option<String>,
+        duration_seconds: i32,
     ) -> Result<Process> {
         let process = sqlx::query_as!(
             Process,
@@ -144,6 +249,34 @@ impl ProcessQueries {
             process_type,
             duration_seconds as f64
         )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(process)
+    }
+
+    /// A process acting on one file, carried in `target_file_id`
+    pub async fn create_file_process(
+        pool: &PgPool,
+        user_id: i64,
+        process_type: &str,
+        pc_id: &str,
+        target_file_id: &str,
+        duration_seconds: i32,
+    ) -> Result<Process> {
+        let process = sqlx::query_as!(
+            Process,
+            r#"
+            INSERT INTO processes (user_id, pc_id, target_file_id, process_type, priority, start_time, end_time)
+            VALUES ($1, $2, $3, $4, 0, NOW(), NOW() + make_interval(secs => $5))
+            RETURNING *
+            "#,
+            user_id,
+            pc_id,
+            target_file_id,
+            process_type,
+            duration_seconds as f64
+        )
         .fetch_one(pool)
         .await?;
 
@@ -153,7 +286,11 @@ impl ProcessQueries {
     pub async fn get_user_processes(pool: &PgPool, user_id: i64) -> Result<Vec<Process>> {
         let processes = sqlx::query_as!(
             Process,
-            "SELECT * FROM processes WHERE user_id = $1 AND end_time > NOW() ORDER BY priority DESC",
+            r#"
+            SELECT * FROM processes
+            WHERE user_id = $1 AND completed_at IS NULL AND (end_time > NOW() OR paused_at IS NOT NULL)
+            ORDER BY priority DESC
+            "#,
             user_id
         )
         .fetch_all(pool)
@@ -162,6 +299,108 @@ impl ProcessQueries {
         Ok(processes)
     }
 
+    /// Running processes after `after_pid`, in pid order, for cursor pagination
+    pub async fn page_for_user(
+        pool: &PgPool,
+        user_id: i64,
+        after_pid: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Process>> {
+        let processes = sqlx::query_as!(
+            Process,
+            r#"
+            SELECT * FROM processes
+            WHERE user_id = $1 AND completed_at IS NULL AND (end_time > NOW() OR paused_at IS NOT NULL)
+              AND pid > COALESCE($2, 0)
+            ORDER BY pid
+            LIMIT $3
+            "#,
+            user_id,
+            after_pid,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(processes)
+    }
+
+    /// Processes the user has running right now
+    pub async fn count_active(conn: &mut PgConnection, user_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM processes
+            WHERE user_id = $1 AND completed_at IS NULL AND (end_time > NOW() OR paused_at IS NOT NULL)
+            "#,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Lock the player's row so quota checks and the inserts they allow
+    /// happen one at a time per player
+    pub async fn lock_quota(conn: &mut PgConnection, user_id: i64) -> Result<()> {
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", user_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Processes the user started since `since` whose type is one of
+    /// `spellings` (lower case). Counted from `process_starts`, so cancelled
+    /// and deleted processes still count.
+    pub async fn count_started_since(
+        conn: &mut PgConnection,
+        user_id: i64,
+        spellings: &[String],
+        since: DateTime<Utc>,
+    ) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM process_starts
+            WHERE user_id = $1 AND process_type = ANY($2) AND started_at >= $3
+            "#,
+            user_id,
+            spellings,
+            since
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Forget starts older than any quota window
+    pub async fn prune_starts(pool: &PgPool, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query!("DELETE FROM process_starts WHERE started_at < $1", before)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Cancel every unfinished process whose type is one of `spellings`
+    /// (lower case) for all users. Returns `(pid, user_id)` of each cancelled
+    /// process.
+    pub async fn cancel_all_of_type(pool: &PgPool, spellings: &[String]) -> Result<Vec<(i64, i64)>> {
+        let rows = sqlx::query!(
+            r#"
+            DELETE FROM processes
+            WHERE lower(process_type) = ANY($1) AND completed_at IS NULL
+            RETURNING pid, user_id
+            "#,
+            spellings
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.pid, r.user_id)).collect())
+    }
+
     pub async fn cancel_process(pool: &PgPool, pid: i64, user_id: i64) -> Result<bool> {
         let result = sqlx::query!(
             "DELETE FROM processes WHERE pid = $1 AND user_id = $2",
@@ -173,100 +412,686 @@ impl ProcessQueries {
 
         Ok(result.rows_affected() > 0)
     }
-}
 
-pub struct HardwareQueries;
-
-impl HardwareQueries {
-    pub async fn get_hardware(pool: &PgPool, pc_id: &str) -> Result<Option<Hardware>> {
-        let hardware = sqlx::query_as!(
-            Hardware,
-            "SELECT * FROM hardware WHERE pc_id = $1",
-            pc_id
+    /// Restart a paused process where it stopped. Returns its new end time,
+    /// or `None` if it is not the player's or not paused.
+    pub async fn resume(pool: &PgPool, pid: i64, user_id: i64) -> Result<Option<DateTime<Utc>>> {
+        let end_time = sqlx::query_scalar!(
+            r#"
+            UPDATE processes SET end_time = end_time + (NOW() - paused_at), paused_at = NULL
+            WHERE pid = $1 AND user_id = $2 AND paused_at IS NOT NULL AND completed_at IS NULL
+            RETURNING end_time
+            "#,
+            pid,
+            user_id
         )
         .fetch_optional(pool)
         .await?;
 
-        Ok(hardware)
+        Ok(end_time)
     }
 
-    pub async fn get_user_hardware(pool: &PgPool, user_id: i64) -> Result<Hardware> {
-        // Try to get existing hardware or create default one
-        let hardware = sqlx::query_as!(
-            Hardware,
-            "SELECT * FROM hardware WHERE user_id = $1 LIMIT 1",
+    /// Lock one of the player's processes that has not completed
+    pub async fn lock_unfinished(conn: &mut PgConnection, pid: i64, user_id: i64) -> Result<Option<Process>> {
+        let process = sqlx::query_as!(
+            Process,
+            "SELECT * FROM processes WHERE pid = $1 AND user_id = $2 AND completed_at IS NULL FOR UPDATE",
+            pid,
             user_id
         )
-        .fetch_optional(pool)
+        .fetch_optional(conn)
         .await?;
 
-        match hardware {
-            Some(hw) => Ok(hw),
-            None => {
-                // Create default hardware for user
-                let hw = sqlx::query_as!(
-                    Hardware,
-                    r#"
-                    INSERT INTO hardware (user_id, cpu_mhz, ram_mb, hdd_mb, net_mbps, gpu_cores, total_slots, used_slots)
-                    VALUES ($1, 1000, 1024, 10240, 10, 1, 5, 1)
-                    RETURNING *
-                    "#,
-                    user_id
-                )
-                .fetch_one(pool)
-                .await?;
-                Ok(hw)
-            }
-        }
+        Ok(process)
     }
 
-    pub async fn update_hardware(
+    /// What the player paid to start the process, net of refunds: money the
+    /// ledger moved into the sink for it
+    pub async fn cost(conn: &mut PgConnection, pid: i64) -> Result<i64> {
+        let reference = format!("process:{}", pid);
+        let cost = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(SUM(e.amount), 0)::BIGINT AS "cost!"
+            FROM ledger_entries e
+            JOIN ledger_transactions t ON t.id = e.transaction_id
+            WHERE t.reference = $1 AND e.account = 'system:sink'
+            "#,
+            reference
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(cost)
+    }
+
+    /// Delete a cancelled process and keep what its cancellation settled to
+    pub async fn record_cancellation(conn: &mut PgConnection, cancellation: &NewProcessCancellation<'_>) -> Result<()> {
+        sqlx::query!("DELETE FROM processes WHERE pid = $1", cancellation.pid)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO process_cancellations (
+                pid, user_id, process_type, target_ip, host_server_id,
+                cpu_released, ram_released, net_released, progress, cost, refund
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+            cancellation.pid,
+            cancellation.user_id,
+            cancellation.process_type,
+            cancellation.target_ip,
+            cancellation.host_server_id,
+            cancellation.cpu_released,
+            cancellation.ram_released,
+            cancellation.net_released,
+            cancellation.progress,
+            cancellation.cost,
+            cancellation.refund
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Processes still running across all players
+    pub async fn count_running(pool: &PgPool) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM processes WHERE completed_at IS NULL AND end_time > NOW()"#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Unfinished processes whose end time is before `until`, soonest first
+    pub async fn pending_completions(
         pool: &PgPool,
-        pc_id: &str,
-        cpu_speed: Option<f64>,
-        ram_size: Option<i64>,
-        hdd_size: Option<i64>,
-        net_speed: Option<f64>,
-    ) -> Result<()> {
-        // Use parameterized queries to prevent SQL injection
-        if cpu_speed.is_none() && ram_size.is_none() && hdd_size.is_none() && net_speed.is_none() {
-            return Ok(());
+        until: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<(i64, DateTime<Utc>)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT pid, end_time FROM processes
+            WHERE completed_at IS NULL AND paused_at IS NULL AND end_time <= $1
+            ORDER BY end_time
+            LIMIT $2
+            "#,
+            until,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.pid, r.end_time)).collect())
+    }
+
+    /// Running processes after `after_pid` that last at least `min_seconds`
+    /// and have not had a complication yet, in pid order
+    pub async fn complication_candidates(
+        pool: &PgPool,
+        min_seconds: i64,
+        after_pid: i64,
+        limit: i64,
+    ) -> Result<Vec<Process>> {
+        let processes = sqlx::query_as!(
+            Process,
+            r#"
+            SELECT p.* FROM processes p
+            WHERE p.completed_at IS NULL
+              AND p.end_time > NOW()
+              AND p.end_time - p.start_time >= make_interval(secs => $1)
+              AND p.pid > $2
+              AND NOT EXISTS (SELECT 1 FROM process_complications c WHERE c.pid = p.pid)
+            ORDER BY p.pid
+            LIMIT $3
+            "#,
+            min_seconds as f64,
+            after_pid,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(processes)
+    }
+
+    /// Record a complication and move the process to `new_end_time`, or pause
+    /// it if `None`. Returns false, changing nothing, if the process already
+    /// had a complication, finished, or its end time moved since it was read.
+    pub async fn apply_complication(
+        pool: &PgPool,
+        process: &Process,
+        kind: &str,
+        new_end_time: Option<DateTime<Utc>>,
+    ) -> Result<bool> {
+        let mut tx = pool.begin().await?;
+
+        let recorded = sqlx::query!(
+            r#"
+            INSERT INTO process_complications (pid, user_id, process_type, kind, previous_end_time, new_end_time)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (pid) DO NOTHING
+            "#,
+            process.pid,
+            process.user_id,
+            process.process_type,
+            kind,
+            process.end_time,
+            new_end_time
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if recorded.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
         }
 
-        // Build the query dynamically but safely with parameters
-        match (cpu_speed, ram_size, hdd_size, net_speed) {
-            (Some(cpu), Some(ram), Some(hdd), Some(net)) => {
-                sqlx::query!(
-                    "UPDATE hardware SET cpu_speed = $1, ram_size = $2, hdd_size = $3, net_speed = $4 WHERE pc_id = $5",
-                    cpu, ram, hdd, net, pc_id
-                )
-                .execute(pool)
-                .await?;
-            }
-            (Some(cpu), Some(ram), Some(hdd), None) => {
-                sqlx::query!(
-                    "UPDATE hardware SET cpu_speed = $1, ram_size = $2, hdd_size = $3 WHERE pc_id = $4",
-                    cpu, ram, hdd, pc_id
-                )
-                .execute(pool)
-                .await?;
-            }
-            (Some(cpu), Some(ram), None, Some(net)) => {
-                sqlx::query!(
-                    "UPDATE hardware SET cpu_speed = $1, ram_size = $2, net_speed = $3 WHERE pc_id = $4",
-                    cpu, ram, net, pc_id
-                )
-                .execute(pool)
-                .await?;
-            }
-            (Some(cpu), None, Some(hdd), Some(net)) => {
-                sqlx::query!(
-                    "UPDATE hardware SET cpu_speed = $1, hdd_size = $2, net_speed = $3 WHERE pc_id = $4",
-                    cpu, hdd, net, pc_id
-                )
-                .execute(pool)
-                .await?;
-            }
+        let changed = match new_end_time {
+            Some(end_time) => sqlx::query!(
+                r#"
+                UPDATE processes SET end_time = $3
+                WHERE pid = $1 AND end_time = $2 AND completed_at IS NULL
+                "#,
+                process.pid,
+                process.end_time,
+                end_time
+            )
+            .execute(&mut *tx)
+            .await?,
+            None => sqlx::query!(
+                r#"
+                UPDATE processes SET paused_at = NOW()
+                WHERE pid = $1 AND end_time = $2 AND completed_at IS NULL AND paused_at IS NULL
+                "#,
+                process.pid,
+                process.end_time
+            )
+            .execute(&mut *tx)
+            .await?,
+        };
+
+        if changed.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Mark a finished process as completed. Returns `None` if it is not due yet,
+    /// was cancelled, or another worker already claimed it.
+    pub async fn claim_completion(conn: &mut PgConnection, pid: i64) -> Result<Option<Process>> {
+        let process = sqlx::query_as!(
+            Process,
+            r#"
+            UPDATE processes SET completed_at = NOW()
+            WHERE pid = $1 AND completed_at IS NULL AND paused_at IS NULL AND end_time <= NOW()
+            RETURNING *
+            "#,
+            pid
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(process)
+    }
+
+    /// Overdue and soon-due processes of the given completion shards, where
+    /// a process's shard is its pid modulo `shards`
+    pub async fn pending_completions_in(
+        pool: &PgPool,
+        until: DateTime<Utc>,
+        shards: i32,
+        owned: &[i32],
+        limit: i64,
+    ) -> Result<Vec<(i64, DateTime<Utc>)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT pid, end_time FROM processes
+            WHERE completed_at IS NULL AND paused_at IS NULL AND end_time <= $1 AND (pid % $2)::INT = ANY($3)
+            ORDER BY end_time
+            LIMIT $4
+            "#,
+            until,
+            shards as i64,
+            owned,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.pid, r.end_time)).collect())
+    }
+
+    /// Record a completion under `shard`'s lease `token`. False if a newer
+    /// lease has completed in the shard, i.e. this one was superseded; the
+    /// row stays locked until the transaction ends.
+    pub async fn fence_shard(conn: &mut PgConnection, shard: i32, token: i64) -> Result<bool> {
+        let fenced = sqlx::query_scalar!(
+            r#"
+            INSERT INTO completion_shards (shard, fence)
+            VALUES ($1, $2)
+            ON CONFLICT (shard) DO UPDATE SET fence = EXCLUDED.fence, fenced_at = NOW()
+            WHERE completion_shards.fence <= EXCLUDED.fence
+            RETURNING shard
+            "#,
+            shard,
+            token
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(fenced.is_some())
+    }
+}
+
+/// A log line as listed to its owner
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LogEntry {
+    pub id: i64,
+    pub server_id: i64,
+    pub log_type: String,
+    pub message: String,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct LogQueries;
+
+impl LogQueries {
+    /// The user's logs older than `before_id`, newest first, for cursor pagination
+    pub async fn page_for_user(
+        pool: &PgPool,
+        user_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<LogEntry>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, server_id, type AS log_type, message, ip_address::text AS ip_address, created_at
+            FROM logs
+            WHERE user_id = $1 AND is_deleted = FALSE AND ($2::bigint IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3
+            "#,
+            user_id,
+            before_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| LogEntry {
+                id: r.id,
+                server_id: r.server_id,
+                log_type: r.log_type,
+                message: r.message,
+                ip_address: r.ip_address,
+                created_at: r.created_at,
+            })
+            .collect())
+    }
+
+    /// Append a log entry to the user's main server; false if they have no server
+    pub async fn add_server_log(
+        conn: &mut PgConnection,
+        user_id: i64,
+        log_type: &str,
+        message: &str,
+        ip_address: Option<&str>,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO logs (server_id, user_id, type, message, ip_address)
+            SELECT id, $1, $2, $3, $4::inet FROM servers
+            WHERE user_id = $1 AND is_npc = FALSE
+            ORDER BY id
+            LIMIT 1
+            "#,
+            user_id,
+            log_type,
+            message,
+            ip_address
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Log an access on the server at `target_ip`, recording the actor's
+    /// gateway IP, or `origin_ip` for a process that ran on another host
+    pub async fn add_remote_access_log(
+        conn: &mut PgConnection,
+        target_ip: &str,
+        actor_id: i64,
+        log_type: &str,
+        origin_ip: Option<&str>,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO logs (server_id, user_id, type, message, ip_address)
+            SELECT t.id, t.user_id, $3,
+                'Remote ' || $3 || ' from ' || COALESCE(host(a.ip_address), 'unknown'), a.ip_address
+            FROM servers t
+            CROSS JOIN LATERAL (
+                SELECT COALESCE($4::text::inet, (
+                    SELECT ip_address FROM servers
+                    WHERE user_id = $2 AND is_npc = FALSE
+                    ORDER BY id
+                    LIMIT 1
+                )) AS ip_address
+            ) a
+            WHERE host(t.ip_address) = $1
+            "#,
+            target_ip,
+            actor_id,
+            log_type,
+            origin_ip
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Log on the server at `target_ip` that `actor_id` gave up a remote
+    /// `log_type` from `origin_ip`, or their gateway
+    pub async fn add_aborted_access_log(
+        conn: &mut PgConnection,
+        target_ip: &str,
+        actor_id: i64,
+        log_type: &str,
+        origin_ip: Option<&str>,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO logs (server_id, user_id, type, message, ip_address)
+            SELECT t.id, t.user_id, $3,
+                'Aborted remote ' || $3 || ' from ' || COALESCE(host(a.ip_address), 'unknown'), a.ip_address
+            FROM servers t
+            CROSS JOIN LATERAL (
+                SELECT COALESCE($4::text::inet, (
+                    SELECT ip_address FROM servers
+                    WHERE user_id = $2 AND is_npc = FALSE
+                    ORDER BY id
+                    LIMIT 1
+                )) AS ip_address
+            ) a
+            WHERE host(t.ip_address) = $1
+            "#,
+            target_ip,
+            actor_id,
+            log_type,
+            origin_ip
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Log a connection passing each `(server_ip, from_ip)` hop of a route,
+    /// on the hop's server and showing where it came from
+    pub async fn add_trail(conn: &mut PgConnection, hops: &[(String, String)]) -> Result<u64> {
+        let (servers, sources): (Vec<String>, Vec<String>) = hops.iter().cloned().unzip();
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO logs (server_id, user_id, type, message, ip_address)
+            SELECT s.id, s.user_id, 'connection', 'Connection from ' || h.from_ip, h.from_ip::inet
+            FROM unnest($1::text[], $2::text[]) AS h(server_ip, from_ip)
+            JOIN servers s ON host(s.ip_address) = h.server_ip
+            "#,
+            &servers,
+            &sources
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Intact and wiped log lines since `since` on each `(server_ip, from_ip)`
+    /// hop showing the connection coming from `from_ip`, in hop order
+    pub async fn trail(pool: &PgPool, hops: &[(String, String)], since: DateTime<Utc>) -> Result<Vec<(i64, i64)>> {
+        let (servers, sources): (Vec<String>, Vec<String>) = hops.iter().cloned().unzip();
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(l.id) FILTER (WHERE NOT l.is_deleted) AS "intact!",
+                COUNT(l.id) FILTER (WHERE l.is_deleted) AS "wiped!"
+            FROM unnest($1::text[], $2::text[]) WITH ORDINALITY AS h(server_ip, from_ip, n)
+            LEFT JOIN servers s ON host(s.ip_address) = h.server_ip
+            LEFT JOIN logs l ON l.server_id = s.id AND l.ip_address = h.from_ip::inet AND l.created_at >= $3
+            GROUP BY h.n
+            ORDER BY h.n
+            "#,
+            &servers,
+            &sources,
+            since
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.intact, r.wiped)).collect())
+    }
+
+    /// Wipe up to `limit` of the newest lines since `since` on the server at
+    /// `server_ip` showing a connection from `from_ip`
+    pub async fn wipe_trail(
+        pool: &PgPool,
+        server_ip: &str,
+        from_ip: &str,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE logs SET is_deleted = TRUE
+            WHERE id IN (
+                SELECT l.id FROM logs l
+                JOIN servers s ON s.id = l.server_id
+                WHERE host(s.ip_address) = $1 AND l.ip_address = $2::text::inet
+                  AND l.created_at >= $3 AND NOT l.is_deleted
+                ORDER BY l.id DESC
+                LIMIT $4
+            )
+            "#,
+            server_ip,
+            from_ip,
+            since,
+            limit
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+pub struct ProgressionQueries;
+
+impl ProgressionQueries {
+    pub async fn add_experience(conn: &mut PgConnection, user_id: i64, amount: i64) -> Result<()> {
+        // Progression rows are keyed by UUID; legacy numeric IDs map into the low bits
+        let player_id = legacy_user_uuid(user_id);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO player_progression (player_id, current_experience, total_experience)
+            VALUES ($1, $2, $2)
+            ON CONFLICT (player_id) DO UPDATE SET
+                current_experience = player_progression.current_experience + EXCLUDED.current_experience,
+                total_experience = player_progression.total_experience + EXCLUDED.total_experience,
+                updated_at = NOW()
+            "#,
+            player_id,
+            amount
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Move the player's standing with a faction by `amount`
+    pub async fn add_reputation(conn: &mut PgConnection, user_id: i64, faction_id: &str, amount: i32) -> Result<()> {
+        let player_id = legacy_user_uuid(user_id);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO player_reputation (player_id, faction_id, reputation_points)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (player_id, faction_id) DO UPDATE SET
+                reputation_points = COALESCE(player_reputation.reputation_points, 0) + EXCLUDED.reputation_points,
+                updated_at = NOW()
+            "#,
+            player_id,
+            faction_id,
+            amount
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The player's current experience, locked for the caller's transaction
+    pub async fn experience(conn: &mut PgConnection, user_id: i64) -> Result<i64> {
+        let player_id = legacy_user_uuid(user_id);
+
+        let experience = sqlx::query_scalar!(
+            "SELECT current_experience FROM player_progression WHERE player_id = $1 FOR UPDATE",
+            player_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(experience.unwrap_or(0))
+    }
+
+    /// The player's standing with a faction, locked for the caller's
+    /// transaction
+    pub async fn reputation(conn: &mut PgConnection, user_id: i64, faction_id: &str) -> Result<i64> {
+        let player_id = legacy_user_uuid(user_id);
+
+        let points = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(reputation_points, 0)::BIGINT AS "points!"
+            FROM player_reputation
+            WHERE player_id = $1 AND faction_id = $2
+            FOR UPDATE
+            "#,
+            player_id,
+            faction_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(points.unwrap_or(0))
+    }
+}
+
+pub struct HardwareQueries;
+
+impl HardwareQueries {
+    pub async fn get_hardware(pool: &PgPool, pc_id: &str) -> Result<Option<Hardware>> {
+        let hardware = sqlx::query_as!(
+            Hardware,
+            "SELECT * FROM hardware WHERE pc_id = $1",
+            pc_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(hardware)
+    }
+
+    pub async fn get_user_hardware(pool: &PgPool, user_id: i64) -> Result<Hardware> {
+        // Try to get existing hardware or create default one
+        let hardware = sqlx::query_as!(
+            Hardware,
+            "SELECT * FROM hardware WHERE user_id = $1 LIMIT 1",
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        match hardware {
+            Some(hw) => Ok(hw),
+            None => {
+                // Create default hardware for user
+                let hw = sqlx::query_as!(
+                    Hardware,
+                    r#"
+                    INSERT INTO hardware (user_id, cpu_mhz, ram_mb, hdd_mb, net_mbps, gpu_cores, total_slots, used_slots)
+                    VALUES ($1, 1000, 1024, 10240, 10, 1, 5, 1)
+                    RETURNING *
+                    "#,
+                    user_id
+                )
+                .fetch_one(pool)
+                .await?;
+                Ok(hw)
+            }
+        }
+    }
+
+    pub async fn update_hardware(
+        pool: &PgPool,
+        pc_id: &str,
+        cpu_speed: Option<f64>,
+        ram_size: Option<i64>,
+        hdd_size: Option<i64>,
+        net_speed: Option<f64>,
+    ) -> Result<()> {
+        // Use parameterized queries to prevent SQL injection
+        if cpu_speed.is_none() && ram_size.is_none() && hdd_size.is_none() && net_speed.is_none() {
+            return Ok(());
+        }
+
+        // Build the query dynamically but safely with parameters
+        match (cpu_speed, ram_size, hdd_size, net_speed) {
+            (Some(cpu), Some(ram), Some(hdd), Some(net)) => {
+                sqlx::query!(
+                    "UPDATE hardware SET cpu_speed = $1, ram_size = $2, hdd_size = $3, net_speed = $4 WHERE pc_id = $5",
+                    cpu, ram, hdd, net, pc_id
+                )
+                .execute(pool)
+                .await?;
+            }
+            (Some(cpu), Some(ram), Some(hdd), None) => {
+                sqlx::query!(
+                    "UPDATE hardware SET cpu_speed = $1, ram_size = $2, hdd_size = $3 WHERE pc_id = $4",
+                    cpu, ram, hdd, pc_id
+                )
+                .execute(pool)
+                .await?;
+            }
+            (Some(cpu), Some(ram), None, Some(net)) => {
+                sqlx::query!(
+                    "UPDATE hardware SET cpu_speed = $1, ram_size = $2, net_speed = $3 WHERE pc_id = $4",
+                    cpu, ram, net, pc_id
+                )
+                .execute(pool)
+                .await?;
+            }
+            (Some(cpu), None, Some(hdd), Some(net)) => {
+                sqlx::query!(
+                    "UPDATE hardware SET cpu_speed = $1, hdd_size = $2, net_speed = $3 WHERE pc_id = $4",
+                    cpu, hdd, net, pc_id
+                )
+                .execute(pool)
+                .await?;
+            }
             (None, Some(ram), Some(hdd), Some(net)) => {
                 sqlx::query!(
                     "UPDATE hardware SET ram_size = $1, hdd_size = $2, net_speed = $3 WHERE pc_id = $4",
@@ -365,97 +1190,13068 @@ impl HardwareQueries {
     }
 }
 
-pub struct BankQueries;
+/// A player server's customizable fields, as shown in the in-game browser
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ServerPage {
+    pub server_id: i64,
+    pub owner_id: i64,
+    pub owner: String,
+    pub ip_address: String,
+    pub hostname: Option<String>,
+    pub motd: Option<String>,
+    pub public_profile: Option<String>,
+}
 
-impl BankQueries {
-    pub async fn get_user_accounts(pool: &PgPool, user_id: i64) -> Result<Vec<BankAccount>> {
-        let accounts = sqlx::query_as!(
-            BankAccount,
-            "SELECT * FROM bank_accounts WHERE user_id = $1",
-            user_id
+pub struct ServerQueries;
+
+impl ServerQueries {
+    /// Page for the player server at `ip`; `None` for NPC or unknown servers
+    pub async fn get_public_page(pool: &PgPool, ip: &str) -> Result<Option<ServerPage>> {
+        let page = sqlx::query_as!(
+            ServerPage,
+            r#"
+            SELECT s.id AS server_id, s.user_id AS owner_id, u.login AS owner,
+                   host(s.ip_address) AS "ip_address!", s.hostname, s.motd, s.public_profile
+            FROM servers s
+            JOIN users u ON u.id = s.user_id
+            WHERE host(s.ip_address) = $1 AND s.is_npc = FALSE AND s.is_active = TRUE
+            "#,
+            ip
         )
-        .fetch_all(pool)
+        .fetch_optional(pool)
         .await?;
 
-        Ok(accounts)
+        Ok(page)
     }
 
-    pub async fn transfer_money(
-        pool: &PgPool,
-        from_account: &str,
-        to_account: &str,
-        amount: i64,
+    /// The user's own main server, with its customization
+    pub async fn get_customization(pool: &PgPool, user_id: i64) -> Result<Option<ServerPage>> {
+        let page = sqlx::query_as!(
+            ServerPage,
+            r#"
+            SELECT s.id AS server_id, s.user_id AS owner_id, u.login AS owner,
+                   host(s.ip_address) AS "ip_address!", s.hostname, s.motd, s.public_profile
+            FROM servers s
+            JOIN users u ON u.id = s.user_id
+            WHERE s.user_id = $1 AND s.is_npc = FALSE
+            ORDER BY s.id
+            LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(page)
+    }
+
+    /// Update the user's main server. `None` keeps a field, an empty string
+    /// clears it. Returns the server id and IP, or `None` if the user has no
+    /// server.
+    pub async fn update_customization(
+        pool: &PgPool,
+        user_id: i64,
+        hostname: Option<&str>,
+        motd: Option<&str>,
+        public_profile: Option<&str>,
+    ) -> Result<Option<(i64, String)>> {
+        let server = sqlx::query!(
+            r#"
+            UPDATE servers SET
+                hostname = CASE WHEN $2::text IS NULL THEN hostname ELSE NULLIF($2, '') END,
+                motd = CASE WHEN $3::text IS NULL THEN motd ELSE NULLIF($3, '') END,
+                public_profile = CASE WHEN $4::text IS NULL THEN public_profile ELSE NULLIF($4, '') END
+            WHERE id = (
+                SELECT id FROM servers WHERE user_id = $1 AND is_npc = FALSE ORDER BY id LIMIT 1
+            )
+            RETURNING id, host(ip_address) AS "ip_address!"
+            "#,
+            user_id,
+            hostname,
+            motd,
+            public_profile
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(server.map(|s| (s.id, s.ip_address)))
+    }
+
+    /// Owner of a player server by IP; NPC servers have no one to alert
+    pub async fn get_player_owner_by_ip(pool: &PgPool, ip: &str) -> Result<Option<i64>> {
+        let owner = sqlx::query_scalar!(
+            "SELECT user_id FROM servers WHERE host(ip_address) = $1 AND is_npc = FALSE AND is_active = TRUE",
+            ip
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(owner)
+    }
+}
+
+/// Why money moved; stored on every ledger transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerReason {
+    /// Money an account held before the ledger, or was opened with
+    OpeningBalance,
+    Transfer,
+    MiningPayout,
+    TutorialReward,
+    CoopReward,
+    ReferralReward,
+    BountyEscrow,
+    BountyPayout,
+    BountyRefund,
+    /// The reward into escrow and the posting fee out of the game
+    ContractEscrow,
+    ContractPayout,
+    ContractRefund,
+    IpReset,
+    MarketplacePurchase,
+    /// Money moved out of an account by someone who hacked its bank
+    Theft,
+    /// The fee for laundering stolen money
+    Laundering,
+    /// A moderator accepting a balance the ledger did not explain
+    Adjustment,
+    /// A member paying into their clan's treasury
+    ClanDeposit,
+    ClanServerUpgrade,
+    /// Paid for finishing a generated mission
+    MissionReward,
+    /// Part of a cancelled process's cost paid back
+    ProcessRefund,
+    /// Restoring or replacing a hardware component
+    HardwareRepair,
+    /// Paid for finishing a storyline puzzle
+    PuzzleReward,
+    /// A crew member's cut of a bank heist
+    HeistLoot,
+    /// The fee for registering a hostname with the whois registry
+    HostnameRegistration,
+    /// Money moved by an admin bulk operation, or its rollback
+    BulkFix,
+    /// Paid for disarming or detonating a Doom virus
+    DoomReward,
+}
+
+impl LedgerReason {
+    pub const ALL: [LedgerReason; 27] = [
+        LedgerReason::OpeningBalance,
+        LedgerReason::Transfer,
+        LedgerReason::MiningPayout,
+        LedgerReason::TutorialReward,
+        LedgerReason::CoopReward,
+        LedgerReason::ReferralReward,
+        LedgerReason::BountyEscrow,
+        LedgerReason::BountyPayout,
+        LedgerReason::BountyRefund,
+        LedgerReason::ContractEscrow,
+        LedgerReason::ContractPayout,
+        LedgerReason::ContractRefund,
+        LedgerReason::IpReset,
+        LedgerReason::MarketplacePurchase,
+        LedgerReason::Theft,
+        LedgerReason::Laundering,
+        LedgerReason::Adjustment,
+        LedgerReason::ClanDeposit,
+        LedgerReason::ClanServerUpgrade,
+        LedgerReason::MissionReward,
+        LedgerReason::ProcessRefund,
+        LedgerReason::HardwareRepair,
+        LedgerReason::PuzzleReward,
+        LedgerReason::HeistLoot,
+        LedgerReason::HostnameRegistration,
+        LedgerReason::BulkFix,
+        LedgerReason::DoomReward,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LedgerReason::OpeningBalance => "opening_balance",
+            LedgerReason::Transfer => "transfer",
+            LedgerReason::MiningPayout => "mining_payout",
+            LedgerReason::TutorialReward => "tutorial_reward",
+            LedgerReason::CoopReward => "coop_reward",
+            LedgerReason::ReferralReward => "referral_reward",
+            LedgerReason::BountyEscrow => "bounty_escrow",
+            LedgerReason::BountyPayout => "bounty_payout",
+            LedgerReason::BountyRefund => "bounty_refund",
+            LedgerReason::ContractEscrow => "contract_escrow",
+            LedgerReason::ContractPayout => "contract_payout",
+            LedgerReason::ContractRefund => "contract_refund",
+            LedgerReason::IpReset => "ip_reset",
+            LedgerReason::MarketplacePurchase => "marketplace_purchase",
+            LedgerReason::Theft => "theft",
+            LedgerReason::Laundering => "laundering",
+            LedgerReason::Adjustment => "adjustment",
+            LedgerReason::ClanDeposit => "clan_deposit",
+            LedgerReason::ClanServerUpgrade => "clan_server_upgrade",
+            LedgerReason::MissionReward => "mission_reward",
+            LedgerReason::ProcessRefund => "process_refund",
+            LedgerReason::HardwareRepair => "hardware_repair",
+            LedgerReason::PuzzleReward => "puzzle_reward",
+            LedgerReason::HeistLoot => "heist_loot",
+            LedgerReason::HostnameRegistration => "hostname_registration",
+            LedgerReason::BulkFix => "bulk_fix",
+            LedgerReason::DoomReward => "doom_reward",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.as_str() == s)
+    }
+}
+
+/// One side of a ledger entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerAccount {
+    Bank(i64),
+    /// Money the game creates: rewards and payouts
+    Mint,
+    /// Money the game destroys: fees and purchases from it
+    Sink,
+    BountyEscrow,
+    ContractEscrow,
+    /// A clan's treasury, by clan id
+    ClanTreasury(i64),
+}
+
+impl LedgerAccount {
+    pub fn key(&self) -> String {
+        match self {
+            LedgerAccount::Bank(id) => format!("bank:{}", id),
+            LedgerAccount::Mint => "system:mint".to_string(),
+            LedgerAccount::Sink => "system:sink".to_string(),
+            LedgerAccount::BountyEscrow => "escrow:bounties".to_string(),
+            LedgerAccount::ContractEscrow => "escrow:contracts".to_string(),
+            LedgerAccount::ClanTreasury(id) => format!("clan:{}", id),
+        }
+    }
+}
+
+/// Check that `legs` sum to zero and drop the empty ones, debits first
+pub fn balanced_legs(legs: &[(LedgerAccount, i64)]) -> Result<Vec<(LedgerAccount, i64)>> {
+    let total = legs.iter().try_fold(0i64, |sum, (_, amount)| sum.checked_add(*amount));
+    if total != Some(0) {
+        anyhow::bail!("ledger legs do not balance: {:?}", legs);
+    }
+    let mut legs: Vec<_> = legs.iter().copied().filter(|(_, amount)| *amount != 0).collect();
+    legs.sort_by_key(|(_, amount)| *amount > 0);
+    Ok(legs)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LedgerEntryRow {
+    pub id: i64,
+    pub transaction_id: i64,
+    pub reason: String,
+    pub reference: String,
+    pub account: String,
+    pub bank_account_id: Option<i64>,
+    /// Owner of the bank account, if it still exists
+    pub user_id: Option<i64>,
+    pub amount: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What to look for in the ledger; every field narrows the search
+#[derive(Debug, Clone, Default)]
+pub struct LedgerFilter {
+    pub user_id: Option<i64>,
+    pub account: Option<String>,
+    pub reason: Option<String>,
+    pub reference: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Entries with a smaller id, for paging back
+    pub before_id: Option<i64>,
+}
+
+/// A bank account whose cached balance disagrees with the ledger
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct LedgerDrift {
+    pub bank_account_id: i64,
+    pub user_id: i64,
+    pub balance: i64,
+    pub ledger_balance: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LedgerDiscrepancy {
+    pub id: i64,
+    pub bank_account_id: i64,
+    pub user_id: Option<i64>,
+    pub balance: i64,
+    pub ledger_balance: i64,
+    pub found_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub resolution: Option<String>,
+    pub resolved_by: Option<i64>,
+    pub resolution_note: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+pub struct LedgerQueries;
+
+impl LedgerQueries {
+    /// Move money between accounts in the caller's transaction, keeping the
+    /// bank and clan treasury balances in step. False if one of those cannot
+    /// cover its debit, and the caller should roll back. Legs must sum to zero.
+    pub async fn post(
+        conn: &mut PgConnection,
+        reason: LedgerReason,
+        reference: &str,
+        legs: &[(LedgerAccount, i64)],
     ) -> Result<bool> {
-        let mut tx = pool.begin().await?;
+        let legs = balanced_legs(legs)?;
+        if legs.is_empty() {
+            return Ok(true);
+        }
 
-        // Deduct from sender
-        let result = sqlx::query!(
-            "UPDATE bank_accounts SET balance = balance - $1 WHERE account_number = $2 AND balance >= $1",
-            amount,
-            from_account
+        for (account, amount) in &legs {
+            let updated = match account {
+                LedgerAccount::Bank(bank_account_id) => sqlx::query!(
+                    "UPDATE bank_accounts SET balance = balance + $1 WHERE id = $2 AND balance + $1 >= 0",
+                    amount,
+                    bank_account_id
+                )
+                .execute(&mut *conn)
+                .await?,
+                LedgerAccount::ClanTreasury(clan_id) => sqlx::query!(
+                    "UPDATE clans SET treasury = treasury + $1 WHERE id = $2 AND treasury + $1 >= 0",
+                    amount,
+                    clan_id
+                )
+                .execute(&mut *conn)
+                .await?,
+                _ => continue,
+            };
+            if updated.rows_affected() == 0 {
+                return Ok(false);
+            }
+        }
+
+        let transaction_id = sqlx::query_scalar!(
+            "INSERT INTO ledger_transactions (reason, reference) VALUES ($1, $2) RETURNING id",
+            reason.as_str(),
+            reference
         )
-        .execute(&mut *tx)
+        .fetch_one(&mut *conn)
         .await?;
 
-        if result.rows_affected() == 0 {
-            tx.rollback().await?;
-            return Ok(false);
+        let accounts: Vec<String> = legs.iter().map(|(account, _)| account.key()).collect();
+        let amounts: Vec<i64> = legs.iter().map(|(_, amount)| *amount).collect();
+        sqlx::query!(
+            r#"
+            INSERT INTO ledger_entries (transaction_id, account, amount)
+            SELECT $1, account, amount FROM UNNEST($2::TEXT[], $3::BIGINT[]) AS leg(account, amount)
+            "#,
+            transaction_id,
+            &accounts,
+            &amounts
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(true)
+    }
+
+    /// An account's balance as the ledger has it
+    pub async fn balance(pool: &PgPool, account: &str) -> Result<i64> {
+        let balance = sqlx::query_scalar!(
+            r#"SELECT COALESCE(SUM(amount), 0)::BIGINT AS "balance!" FROM ledger_entries WHERE account = $1"#,
+            account
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(balance)
+    }
+
+    /// Entries matching `filter`, newest first
+    pub async fn entries(pool: &PgPool, filter: &LedgerFilter, limit: i64) -> Result<Vec<LedgerEntryRow>> {
+        let rows = sqlx::query_as!(
+            LedgerEntryRow,
+            r#"
+            SELECT e.id, e.transaction_id, t.reason, t.reference, e.account, e.bank_account_id,
+                b.user_id AS "user_id?", e.amount, e.created_at
+            FROM ledger_entries e
+            JOIN ledger_transactions t ON t.id = e.transaction_id
+            LEFT JOIN bank_accounts b ON b.id = e.bank_account_id
+            WHERE ($1::BIGINT IS NULL OR e.bank_account_id IN (SELECT id FROM bank_accounts WHERE user_id = $1))
+              AND ($2::TEXT IS NULL OR e.account = $2)
+              AND ($3::TEXT IS NULL OR t.reason = $3)
+              AND ($4::TEXT IS NULL OR t.reference = $4)
+              AND ($5::TIMESTAMPTZ IS NULL OR e.created_at >= $5)
+              AND ($6::TIMESTAMPTZ IS NULL OR e.created_at < $6)
+              AND ($7::BIGINT IS NULL OR e.id < $7)
+            ORDER BY e.id DESC
+            LIMIT $8
+            "#,
+            filter.user_id,
+            filter.account.as_deref(),
+            filter.reason.as_deref(),
+            filter.reference.as_deref(),
+            filter.since,
+            filter.until,
+            filter.before_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Every entry of one transaction
+    pub async fn transaction(pool: &PgPool, transaction_id: i64) -> Result<Vec<LedgerEntryRow>> {
+        let rows = sqlx::query_as!(
+            LedgerEntryRow,
+            r#"
+            SELECT e.id, e.transaction_id, t.reason, t.reference, e.account, e.bank_account_id,
+                b.user_id AS "user_id?", e.amount, e.created_at
+            FROM ledger_entries e
+            JOIN ledger_transactions t ON t.id = e.transaction_id
+            LEFT JOIN bank_accounts b ON b.id = e.bank_account_id
+            WHERE e.transaction_id = $1
+            ORDER BY e.id
+            "#,
+            transaction_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Bank accounts whose cached balance is not the sum of their entries
+    pub async fn drift(pool: &PgPool) -> Result<Vec<LedgerDrift>> {
+        let rows = sqlx::query_as!(
+            LedgerDrift,
+            r#"
+            SELECT b.id AS bank_account_id, b.user_id, b.balance, COALESCE(l.total, 0)::BIGINT AS "ledger_balance!"
+            FROM bank_accounts b
+            LEFT JOIN (
+                SELECT bank_account_id, SUM(amount) AS total
+                FROM ledger_entries
+                WHERE bank_account_id IS NOT NULL
+                GROUP BY bank_account_id
+            ) l ON l.bank_account_id = b.id
+            WHERE b.balance <> COALESCE(l.total, 0)
+            ORDER BY b.id
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Record a full [`Self::drift`] pass: open a discrepancy per new account,
+    /// refresh the open ones and clear those that agree again. Returns how
+    /// many were opened.
+    pub async fn record_drift(pool: &PgPool, drift: &[LedgerDrift]) -> Result<u64> {
+        let mut tx = pool.begin().await?;
+        let mut opened = 0;
+
+        for found in drift {
+            let inserted = sqlx::query_scalar!(
+                r#"
+                INSERT INTO ledger_discrepancies (bank_account_id, user_id, balance, ledger_balance)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (bank_account_id) WHERE resolved_at IS NULL
+                DO UPDATE SET balance = EXCLUDED.balance, ledger_balance = EXCLUDED.ledger_balance,
+                    last_seen_at = NOW()
+                RETURNING (xmax = 0) AS "inserted!"
+                "#,
+                found.bank_account_id,
+                found.user_id,
+                found.balance,
+                found.ledger_balance
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            if inserted {
+                opened += 1;
+            }
         }
 
-        // Add to receiver
+        let drifting: Vec<i64> = drift.iter().map(|found| found.bank_account_id).collect();
         sqlx::query!(
-            "UPDATE bank_accounts SET balance = balance + $1 WHERE account_number = $2",
-            amount,
-            to_account
+            r#"
+            UPDATE ledger_discrepancies SET resolution = 'cleared', resolved_at = NOW()
+            WHERE resolved_at IS NULL AND bank_account_id <> ALL($1::BIGINT[])
+            "#,
+            &drifting
         )
         .execute(&mut *tx)
         .await?;
 
         tx.commit().await?;
-        Ok(true)
+        Ok(opened)
+    }
+
+    pub async fn discrepancies(pool: &PgPool, open_only: bool, limit: i64) -> Result<Vec<LedgerDiscrepancy>> {
+        let rows = sqlx::query_as!(
+            LedgerDiscrepancy,
+            r#"
+            SELECT id, bank_account_id, user_id, balance, ledger_balance, found_at, last_seen_at,
+                resolution, resolved_by, resolution_note, resolved_at
+            FROM ledger_discrepancies
+            WHERE NOT $1 OR resolved_at IS NULL
+            ORDER BY found_at DESC
+            LIMIT $2
+            "#,
+            open_only,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Settle an open discrepancy. `restore` resets the balance to what the
+    /// ledger has; otherwise the cached balance is accepted and the
+    /// difference posted as an adjustment. `None` if it is not open.
+    pub async fn resolve(
+        pool: &PgPool,
+        discrepancy_id: i64,
+        moderator_id: i64,
+        restore: bool,
+        note: &str,
+    ) -> Result<Option<LedgerDiscrepancy>> {
+        let mut tx = pool.begin().await?;
+
+        let open = sqlx::query!(
+            "SELECT bank_account_id FROM ledger_discrepancies WHERE id = $1 AND resolved_at IS NULL FOR UPDATE",
+            discrepancy_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(open) = open else {
+            return Ok(None);
+        };
+
+        // Measured again under the account lock, not taken from the sweep
+        let account = sqlx::query!(
+            r#"
+            SELECT b.balance,
+                (SELECT COALESCE(SUM(amount), 0) FROM ledger_entries WHERE bank_account_id = b.id)::BIGINT
+                    AS "ledger_balance!"
+            FROM bank_accounts b WHERE b.id = $1 FOR UPDATE
+            "#,
+            open.bank_account_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(account) = account {
+            let difference = account.balance - account.ledger_balance;
+            if restore {
+                sqlx::query!(
+                    "UPDATE bank_accounts SET balance = $2 WHERE id = $1",
+                    open.bank_account_id,
+                    account.ledger_balance
+                )
+                .execute(&mut *tx)
+                .await?;
+            } else if difference != 0 {
+                let (from, to) = if difference > 0 {
+                    (LedgerAccount::Mint, LedgerAccount::Bank(open.bank_account_id))
+                } else {
+                    (LedgerAccount::Bank(open.bank_account_id), LedgerAccount::Sink)
+                };
+                let amount = difference.abs();
+                // Only the entries are posted: the balance already is what they explain
+                let transaction_id = sqlx::query_scalar!(
+                    "INSERT INTO ledger_transactions (reason, reference) VALUES ($1, $2) RETURNING id",
+                    LedgerReason::Adjustment.as_str(),
+                    format!("ledger_discrepancy:{}", discrepancy_id)
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+                sqlx::query!(
+                    "INSERT INTO ledger_entries (transaction_id, account, amount) VALUES ($1, $2, $3), ($1, $4, $5)",
+                    transaction_id,
+                    from.key(),
+                    -amount,
+                    to.key(),
+                    amount
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        let resolved = sqlx::query_as!(
+            LedgerDiscrepancy,
+            r#"
+            UPDATE ledger_discrepancies
+            SET resolution = $2, resolved_by = $3, resolution_note = $4, resolved_at = NOW()
+            WHERE id = $1
+            RETURNING id, bank_account_id, user_id, balance, ledger_balance, found_at, last_seen_at,
+                resolution, resolved_by, resolution_note, resolved_at
+            "#,
+            discrepancy_id,
+            if restore { "restored" } else { "adjusted" },
+            moderator_id,
+            note
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(resolved))
     }
 }
 
-pub struct MissionQueries;
+/// A mining payout, held until the miner has a bank account
+#[derive(Debug, Clone)]
+pub struct HeldMiningPayout {
+    pub pid: i64,
+    pub user_id: i64,
+    pub money: i64,
+}
 
-impl MissionQueries {
-    pub async fn get_user_missions(pool: &PgPool, user_id: i64) -> Result<Vec<Mission>> {
-        let missions = sqlx::query_as!(
-            Mission,
-            "SELECT * FROM missions WHERE user_id = $1 ORDER BY id DESC",
+pub struct BankQueries;
+
+impl BankQueries {
+    /// The user's oldest active account, locked for the caller's transaction
+    pub async fn primary_account(conn: &mut PgConnection, user_id: i64) -> Result<Option<i64>> {
+        let id = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM bank_accounts
+            WHERE user_id = $1 AND is_active = TRUE
+            ORDER BY id
+            LIMIT 1
+            FOR UPDATE
+            "#,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// An account's cached balance; `None` if it does not exist
+    pub async fn account_balance(conn: &mut PgConnection, bank_account_id: i64) -> Result<Option<i64>> {
+        let balance = sqlx::query_scalar!(
+            "SELECT balance FROM bank_accounts WHERE id = $1",
+            bank_account_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(balance)
+    }
+
+    /// Credit the user's oldest active account from `from`; false if they
+    /// have none
+    pub async fn credit_primary_account(
+        conn: &mut PgConnection,
+        user_id: i64,
+        amount: i64,
+        from: LedgerAccount,
+        reason: LedgerReason,
+        reference: &str,
+    ) -> Result<bool> {
+        let Some(account_id) = Self::primary_account(&mut *conn, user_id).await? else {
+            return Ok(false);
+        };
+
+        LedgerQueries::post(conn, reason, reference, &[(from, -amount), (LedgerAccount::Bank(account_id), amount)]).await
+    }
+
+    /// Debit the user's oldest active account into `to`; false if they have
+    /// none or it cannot cover `amount`
+    pub async fn debit_primary_account(
+        conn: &mut PgConnection,
+        user_id: i64,
+        amount: i64,
+        to: LedgerAccount,
+        reason: LedgerReason,
+        reference: &str,
+    ) -> Result<bool> {
+        let Some(account_id) = Self::primary_account(&mut *conn, user_id).await? else {
+            return Ok(false);
+        };
+
+        LedgerQueries::post(conn, reason, reference, &[(LedgerAccount::Bank(account_id), -amount), (to, amount)]).await
+    }
+
+    /// Hold the payout of a mining process whose owner has no bank account
+    pub async fn hold_mining_payout(conn: &mut PgConnection, pid: i64, user_id: i64, money: i64) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO mining_held_payouts (pid, user_id, money) VALUES ($1, $2, $3) ON CONFLICT (pid) DO NOTHING",
+            pid,
+            user_id,
+            money
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Pay held mining payouts whose miner now has a bank account. Returns
+    /// the payouts paid.
+    pub async fn pay_held_mining_payouts(conn: &mut PgConnection, limit: i64) -> Result<Vec<HeldMiningPayout>> {
+        let held = sqlx::query_as!(
+            HeldMiningPayout,
+            r#"
+            SELECT h.pid, h.user_id, h.money
+            FROM mining_held_payouts h
+            WHERE h.paid_at IS NULL AND EXISTS (
+                SELECT 1 FROM bank_accounts a WHERE a.user_id = h.user_id AND a.is_active = TRUE
+            )
+            ORDER BY h.pid
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut paid = Vec::with_capacity(held.len());
+        for payout in held {
+            // Same reference as an immediate payout, so it is never paid twice
+            let credited = Self::credit_primary_account(
+                &mut *conn,
+                payout.user_id,
+                payout.money,
+                LedgerAccount::Mint,
+                LedgerReason::MiningPayout,
+                &format!("process:{}", payout.pid),
+            )
+            .await?;
+            if !credited {
+                continue;
+            }
+
+            sqlx::query!("UPDATE mining_held_payouts SET paid_at = NOW() WHERE pid = $1", payout.pid)
+                .execute(&mut *conn)
+                .await?;
+            paid.push(payout);
+        }
+
+        Ok(paid)
+    }
+
+    pub async fn get_user_accounts(pool: &PgPool, user_id: i64) -> Result<Vec<BankAccount>> {
+        let accounts = sqlx::query_as!(
+            BankAccount,
+            "SELECT * FROM bank_accounts WHERE user_id = $1",
             user_id
         )
         .fetch_all(pool)
         .await?;
 
-        Ok(missions)
+        Ok(accounts)
     }
 
-    pub async fn update_mission_progress(
+    pub async fn transfer_money(
         pool: &PgPool,
-        mission_id: i64,
-        progress: i32,
-    ) -> Result<()> {
-        sqlx::query!(
+        from_account: &str,
+        to_account: &str,
+        amount: i64,
+    ) -> Result<bool> {
+        if amount <= 0 {
+            return Ok(false);
+        }
+        let mut tx = pool.begin().await?;
+
+        // Locked in id order so opposite transfers cannot deadlock
+        let accounts = sqlx::query!(
             r#"
-            UPDATE missions
-            SET progress = $1,
-                status = CASE
-                    WHEN $1 >= total_steps THEN 'completed'
-                    ELSE status
-                END,
-                completed_at = CASE
-                    WHEN $1 >= total_steps THEN NOW()
-                    ELSE completed_at
-                END
-            WHERE id = $2
+            SELECT id, account_number FROM bank_accounts
+            WHERE account_number = $1 OR account_number = $2
+            ORDER BY id
+            FOR UPDATE
             "#,
-            progress,
-            mission_id
+            from_account,
+            to_account
         )
-        .execute(pool)
+        .fetch_all(&mut *tx)
         .await?;
 
-        Ok(())
+        let find = |number: &str| accounts.iter().find(|a| a.account_number == number).map(|a| a.id);
+        let (Some(from_id), Some(to_id)) = (find(from_account), find(to_account)) else {
+            tx.rollback().await?;
+            return Ok(false);
+        };
+
+        let reference = format!("transfer:{}:{}", from_account, to_account);
+        let legs = [(LedgerAccount::Bank(from_id), -amount), (LedgerAccount::Bank(to_id), amount)];
+        if !LedgerQueries::post(&mut *tx, LedgerReason::Transfer, &reference, &legs).await? {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+        Ok(true)
     }
-}
\ No newline at end of file
+}
+
+/// An NPC bank and what it charges
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BankRow {
+    pub id: i64,
+    pub name: String,
+    pub security_tier: i16,
+    pub transfer_fee_bps: i32,
+    pub min_transfer_fee: i64,
+    pub laundering_fee_bps: Option<i32>,
+    /// Address of the bank's server, if it has one
+    pub ip_address: Option<String>,
+}
+
+/// A bank account as its owner sees it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BankAccountRow {
+    pub id: i64,
+    pub account_number: String,
+    pub balance: i64,
+    pub bank_id: i64,
+    pub bank_name: String,
+    pub security_tier: i16,
+    /// Stolen money the account holds
+    pub stolen: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A bank account locked for a transfer, with its bank's terms
+#[derive(Debug, Clone)]
+pub struct LockedBankAccount {
+    pub id: i64,
+    pub user_id: i64,
+    pub account_number: String,
+    pub balance: i64,
+    pub bank_id: i64,
+    pub security_tier: i16,
+    pub transfer_fee_bps: i32,
+    pub min_transfer_fee: i64,
+    /// Whether the player moving money has hacked the account's bank
+    pub bank_hacked: bool,
+}
+
+/// A transfer to log
+#[derive(Debug, Clone)]
+pub struct NewBankTransfer<'a> {
+    pub kind: &'a str,
+    pub initiator_id: i64,
+    pub initiator_ip: Option<&'a str>,
+    pub from: &'a LockedBankAccount,
+    pub to: &'a LockedBankAccount,
+    pub via_bank_id: Option<i64>,
+    pub amount: i64,
+    pub fee: i64,
+    pub stolen: i64,
+}
+
+/// A transfer in an account's log
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BankTransferRow {
+    pub id: i64,
+    pub kind: String,
+    pub from_account: String,
+    pub from_bank: String,
+    pub to_account: String,
+    pub to_bank: String,
+    /// The laundering bank, for laundering
+    pub via_bank: Option<String>,
+    pub amount: i64,
+    pub fee: i64,
+    pub stolen: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Money stolen from one of the player's accounts
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TheftRow {
+    pub id: i64,
+    pub from_account: String,
+    pub amount: i64,
+    /// Where the thief sent it
+    pub to_account: String,
+    pub to_bank: String,
+    /// Logged only by banks whose tier keeps it
+    pub thief_ip: Option<String>,
+    /// Stolen money still marked, wherever it is now
+    pub traceable: i64,
+    pub laundered: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Where a theft's money sits now
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StolenHolding {
+    pub account_number: String,
+    pub bank_name: String,
+    pub amount: i64,
+}
+
+/// One transfer that carried a theft's money
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TheftHop {
+    pub transfer_id: i64,
+    pub kind: String,
+    pub from_account: String,
+    /// `None` for laundering, where the trail ends
+    pub to_account: Option<String>,
+    pub bank_name: String,
+    pub amount: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// NPC banks, transfers between their accounts and stolen money
+pub struct BankNetworkQueries;
+
+impl BankNetworkQueries {
+    pub async fn banks(pool: &PgPool) -> Result<Vec<BankRow>> {
+        let banks = sqlx::query_as!(
+            BankRow,
+            r#"
+            SELECT b.id, b.name, b.security_tier, b.transfer_fee_bps, b.min_transfer_fee, b.laundering_fee_bps,
+                host(s.ip_address) AS ip_address
+            FROM banks b
+            LEFT JOIN servers s ON s.id = b.server_id
+            ORDER BY b.id
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(banks)
+    }
+
+    pub async fn bank(conn: &mut PgConnection, bank_id: i64) -> Result<Option<BankRow>> {
+        let bank = sqlx::query_as!(
+            BankRow,
+            r#"
+            SELECT b.id, b.name, b.security_tier, b.transfer_fee_bps, b.min_transfer_fee, b.laundering_fee_bps,
+                host(s.ip_address) AS ip_address
+            FROM banks b
+            LEFT JOIN servers s ON s.id = b.server_id
+            WHERE b.id = $1
+            "#,
+            bank_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(bank)
+    }
+
+    /// The player's active accounts, oldest first
+    pub async fn accounts(pool: &PgPool, user_id: i64) -> Result<Vec<BankAccountRow>> {
+        let accounts = sqlx::query_as!(
+            BankAccountRow,
+            r#"
+            SELECT a.id, a.account_number, a.balance, a.bank_id, b.name AS bank_name, b.security_tier,
+                LEAST(a.balance, COALESCE((
+                    SELECT SUM(f.amount) FROM stolen_funds f WHERE f.account_id = a.id
+                ), 0))::BIGINT AS "stolen!",
+                a.created_at
+            FROM bank_accounts a
+            JOIN banks b ON b.id = a.bank_id
+            WHERE a.user_id = $1 AND a.is_active = TRUE
+            ORDER BY a.id
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(accounts)
+    }
+
+    /// Lock the player's accounts and say how many they hold and whether one
+    /// is at `bank_id`
+    pub async fn lock_customer(conn: &mut PgConnection, user_id: i64, bank_id: i64) -> Result<(i64, bool)> {
+        let banks = sqlx::query_scalar!(
+            "SELECT bank_id FROM bank_accounts WHERE user_id = $1 AND is_active = TRUE FOR UPDATE",
+            user_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok((banks.len() as i64, banks.contains(&bank_id)))
+    }
+
+    /// Open an empty account at `bank_id` under a fresh account number
+    pub async fn open_account(conn: &mut PgConnection, user_id: i64, bank_id: i64) -> Result<BankAccountRow> {
+        // Numbers are random; a clash just draws another
+        for _ in 0..5 {
+            let account = sqlx::query_as!(
+                BankAccountRow,
+                r#"
+                WITH opened AS (
+                    INSERT INTO bank_accounts (user_id, bank_id, account_number, routing_number)
+                    VALUES ($1, $2, lpad(floor(random() * 1000000000)::BIGINT::TEXT, 9, '0'), lpad($2::TEXT, 9, '0'))
+                    ON CONFLICT (account_number) DO NOTHING
+                    RETURNING id, account_number, balance, bank_id, created_at
+                )
+                SELECT o.id, o.account_number, o.balance, o.bank_id, b.name AS bank_name, b.security_tier,
+                    0::BIGINT AS "stolen!", o.created_at
+                FROM opened o
+                JOIN banks b ON b.id = o.bank_id
+                "#,
+                user_id,
+                bank_id
+            )
+            .fetch_optional(&mut *conn)
+            .await?;
+            if let Some(account) = account {
+                return Ok(account);
+            }
+        }
+        anyhow::bail!("no free account number at bank {}", bank_id)
+    }
+
+    /// Lock two accounts by number, in id order so opposite transfers cannot
+    /// deadlock. `initiator_id` is who moves the money.
+    pub async fn lock_pair(
+        conn: &mut PgConnection,
+        from_number: &str,
+        to_number: &str,
+        initiator_id: i64,
+    ) -> Result<(Option<LockedBankAccount>, Option<LockedBankAccount>)> {
+        let accounts = sqlx::query_as!(
+            LockedBankAccount,
+            r#"
+            SELECT a.id, a.user_id, a.account_number, a.balance, a.bank_id, b.security_tier,
+                b.transfer_fee_bps, b.min_transfer_fee,
+                EXISTS (
+                    SELECT 1 FROM servers s
+                    JOIN hacked_database d ON d.ip_address = s.ip_address
+                    WHERE s.id = b.server_id AND d.user_id = $3 AND d.invalidated_at IS NULL
+                ) AS "bank_hacked!"
+            FROM bank_accounts a
+            JOIN banks b ON b.id = a.bank_id
+            WHERE a.account_number IN ($1, $2) AND a.is_active = TRUE
+            ORDER BY a.id
+            FOR UPDATE OF a
+            "#,
+            from_number,
+            to_number,
+            initiator_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        let find = |number: &str| accounts.iter().find(|a| a.account_number == number).cloned();
+        Ok((find(from_number), find(to_number)))
+    }
+
+    /// Lock one of the player's accounts by number
+    pub async fn lock_own(conn: &mut PgConnection, user_id: i64, number: &str) -> Result<Option<LockedBankAccount>> {
+        let (account, _) = Self::lock_pair(conn, number, number, user_id).await?;
+        Ok(account.filter(|a| a.user_id == user_id))
+    }
+
+    /// Whether the player stole from the account within `hours`
+    pub async fn recent_theft(conn: &mut PgConnection, thief_id: i64, account_id: i64, hours: i64) -> Result<bool> {
+        let recent = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM bank_transfers
+                WHERE kind = 'theft' AND initiator_id = $1 AND from_account_id = $2
+                  AND created_at > NOW() - make_interval(hours => $3::INT)
+            ) AS "recent!"
+            "#,
+            thief_id,
+            account_id,
+            hours as i32
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(recent)
+    }
+
+    pub async fn record_transfer(conn: &mut PgConnection, transfer: &NewBankTransfer<'_>) -> Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO bank_transfers (kind, initiator_id, initiator_ip, from_account_id, from_user_id,
+                to_account_id, to_user_id, via_bank_id, amount, fee, stolen)
+            VALUES ($1, $2, $3::TEXT::INET, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING id
+            "#,
+            transfer.kind,
+            transfer.initiator_id,
+            transfer.initiator_ip,
+            transfer.from.id,
+            transfer.from.user_id,
+            transfer.to.id,
+            transfer.to.user_id,
+            transfer.via_bank_id,
+            transfer.amount,
+            transfer.fee,
+            transfer.stolen
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// The account's stolen money as `(theft_id, amount)` lots, locked
+    pub async fn stolen_lots(conn: &mut PgConnection, account_id: i64) -> Result<Vec<(i64, i64)>> {
+        let lots = sqlx::query!(
+            "SELECT theft_id, amount FROM stolen_funds WHERE account_id = $1 AND amount > 0 ORDER BY theft_id FOR UPDATE",
+            account_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(lots.into_iter().map(|lot| (lot.theft_id, lot.amount)).collect())
+    }
+
+    /// Take `(theft_id, amount)` lots of stolen money off an account
+    pub async fn release_stolen(conn: &mut PgConnection, account_id: i64, taken: &[(i64, i64)]) -> Result<()> {
+        let (thefts, amounts): (Vec<i64>, Vec<i64>) = taken.iter().copied().unzip();
+        sqlx::query!(
+            r#"
+            UPDATE stolen_funds f SET amount = f.amount - t.amount, updated_at = NOW()
+            FROM UNNEST($2::BIGINT[], $3::BIGINT[]) AS t(theft_id, amount)
+            WHERE f.account_id = $1 AND f.theft_id = t.theft_id
+            "#,
+            account_id,
+            &thefts,
+            &amounts
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record that `transfer_id` carried the lots, and put them on
+    /// `to_account` unless the transfer cleaned them
+    pub async fn carry_stolen(
+        conn: &mut PgConnection,
+        transfer_id: i64,
+        to_account: Option<i64>,
+        carried: &[(i64, i64)],
+    ) -> Result<()> {
+        let (thefts, amounts): (Vec<i64>, Vec<i64>) = carried.iter().copied().unzip();
+        sqlx::query!(
+            r#"
+            INSERT INTO stolen_fund_moves (transfer_id, theft_id, amount)
+            SELECT $1, theft_id, amount FROM UNNEST($2::BIGINT[], $3::BIGINT[]) AS t(theft_id, amount)
+            "#,
+            transfer_id,
+            &thefts,
+            &amounts
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        if let Some(to_account) = to_account {
+            sqlx::query!(
+                r#"
+                INSERT INTO stolen_funds (theft_id, account_id, amount)
+                SELECT theft_id, $1, amount FROM UNNEST($2::BIGINT[], $3::BIGINT[]) AS t(theft_id, amount)
+                ON CONFLICT (theft_id, account_id)
+                DO UPDATE SET amount = stolen_funds.amount + EXCLUDED.amount, updated_at = NOW()
+                "#,
+                to_account,
+                &thefts,
+                &amounts
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// One of the player's accounts by number
+    pub async fn own_account_id(pool: &PgPool, user_id: i64, number: &str) -> Result<Option<i64>> {
+        let id = sqlx::query_scalar!(
+            "SELECT id FROM bank_accounts WHERE user_id = $1 AND account_number = $2",
+            user_id,
+            number
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Transfers in and out of an account, newest first
+    pub async fn account_transfers(pool: &PgPool, account_id: i64, before_id: Option<i64>, limit: i64) -> Result<Vec<BankTransferRow>> {
+        let transfers = sqlx::query_as!(
+            BankTransferRow,
+            r#"
+            SELECT t.id, t.kind, fa.account_number AS from_account, fb.name AS from_bank,
+                ta.account_number AS to_account, tb.name AS to_bank, vb.name AS "via_bank?",
+                t.amount, t.fee, t.stolen, t.created_at
+            FROM bank_transfers t
+            JOIN bank_accounts fa ON fa.id = t.from_account_id
+            JOIN banks fb ON fb.id = fa.bank_id
+            JOIN bank_accounts ta ON ta.id = t.to_account_id
+            JOIN banks tb ON tb.id = ta.bank_id
+            LEFT JOIN banks vb ON vb.id = t.via_bank_id
+            WHERE (t.from_account_id = $1 OR t.to_account_id = $1)
+              AND ($2::BIGINT IS NULL OR t.id < $2)
+            ORDER BY t.id DESC
+            LIMIT $3
+            "#,
+            account_id,
+            before_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(transfers)
+    }
+
+    /// Thefts from the player's accounts, newest first
+    pub async fn thefts_from(pool: &PgPool, user_id: i64, limit: i64) -> Result<Vec<TheftRow>> {
+        let thefts = sqlx::query_as!(
+            TheftRow,
+            r#"
+            SELECT t.id, fa.account_number AS from_account, t.amount, ta.account_number AS to_account,
+                tb.name AS to_bank, host(t.initiator_ip) AS thief_ip,
+                COALESCE((SELECT SUM(f.amount) FROM stolen_funds f WHERE f.theft_id = t.id), 0)::BIGINT AS "traceable!",
+                COALESCE((
+                    SELECT SUM(m.amount) FROM stolen_fund_moves m
+                    JOIN bank_transfers l ON l.id = m.transfer_id
+                    WHERE m.theft_id = t.id AND l.kind = 'laundering'
+                ), 0)::BIGINT AS "laundered!",
+                t.created_at
+            FROM bank_transfers t
+            JOIN bank_accounts fa ON fa.id = t.from_account_id
+            JOIN bank_accounts ta ON ta.id = t.to_account_id
+            JOIN banks tb ON tb.id = ta.bank_id
+            WHERE t.kind = 'theft' AND t.from_user_id = $1
+            ORDER BY t.id DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(thefts)
+    }
+
+    /// Where a theft from the player's accounts went: every transfer that
+    /// carried its money and where the rest sits now. `None` if the theft
+    /// was not from the player.
+    pub async fn theft_trace(
+        pool: &PgPool,
+        user_id: i64,
+        theft_id: i64,
+    ) -> Result<Option<(Vec<TheftHop>, Vec<StolenHolding>)>> {
+        let victim = sqlx::query_scalar!(
+            "SELECT from_user_id FROM bank_transfers WHERE id = $1 AND kind = 'theft'",
+            theft_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        if victim != Some(user_id) {
+            return Ok(None);
+        }
+
+        let hops = sqlx::query_as!(
+            TheftHop,
+            r#"
+            SELECT t.id AS transfer_id, t.kind, fa.account_number AS from_account,
+                CASE WHEN t.kind = 'laundering' THEN NULL ELSE ta.account_number END AS to_account,
+                COALESCE(vb.name, tb.name) AS "bank_name!", m.amount, t.created_at
+            FROM stolen_fund_moves m
+            JOIN bank_transfers t ON t.id = m.transfer_id
+            JOIN bank_accounts fa ON fa.id = t.from_account_id
+            JOIN bank_accounts ta ON ta.id = t.to_account_id
+            JOIN banks tb ON tb.id = ta.bank_id
+            LEFT JOIN banks vb ON vb.id = t.via_bank_id
+            WHERE m.theft_id = $1
+            ORDER BY t.id
+            "#,
+            theft_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let holdings = sqlx::query_as!(
+            StolenHolding,
+            r#"
+            SELECT a.account_number, b.name AS bank_name, f.amount
+            FROM stolen_funds f
+            JOIN bank_accounts a ON a.id = f.account_id
+            JOIN banks b ON b.id = a.bank_id
+            WHERE f.theft_id = $1 AND f.amount > 0
+            ORDER BY f.amount DESC
+            "#,
+            theft_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Some((hops, holdings)))
+    }
+}
+
+pub struct MissionQueries;
+
+impl MissionQueries {
+    pub async fn get_user_missions(pool: &PgPool, user_id: i64) -> Result<Vec<Mission>> {
+        let missions = sqlx::query_as!(
+            Mission,
+            "SELECT * FROM missions WHERE user_id = $1 ORDER BY id DESC",
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(missions)
+    }
+
+    pub async fn update_mission_progress(
+        pool: &PgPool,
+        mission_id: i64,
+        progress: i32,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE missions
+            SET progress = $1,
+                status = CASE
+                    WHEN $1 >= total_steps THEN 'completed'
+                    ELSE status
+                END,
+                completed_at = CASE
+                    WHEN $1 >= total_steps THEN NOW()
+                    ELSE completed_at
+                END
+            WHERE id = $2
+            "#,
+            progress,
+            mission_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+/// Outcome of a marketplace purchase attempt
+#[derive(Debug, Clone, PartialEq)]
+pub enum PurchaseOutcome {
+    Purchased { software_id: i64, license_key: String },
+    NotFound,
+    SoldOut,
+    OwnListing,
+    InsufficientFunds,
+    NoServer,
+    NoSpace,
+    /// The given reservation is not the buyer's or no longer held
+    ReservationInvalid,
+    /// The seller has no active bank account to receive the proceeds
+    SellerUnavailable,
+}
+
+/// How a sale price is split between the seller and the original author
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaleSplit {
+    pub seller: i64,
+    pub royalty: i64,
+}
+
+pub struct MarketplaceQueries;
+
+impl MarketplaceQueries {
+    /// Split `price` between the seller and, for resales, the original author.
+    ///
+    /// Royalties are only paid when the origin listing has a non-zero royalty percent.
+    pub fn split_sale(price: i64, origin_royalty_percent: Option<i16>) -> SaleSplit {
+        let percent = origin_royalty_percent.unwrap_or(0).clamp(0, 100) as i64;
+        let royalty = price * percent / 100;
+        SaleSplit { seller: price - royalty, royalty }
+    }
+
+    pub async fn create_listing(
+        pool: &PgPool,
+        seller_id: i64,
+        software_id: i64,
+        price: i64,
+        copies_total: i32,
+        allow_resale: bool,
+        royalty_percent: i16,
+    ) -> Result<Option<SoftwareListing>> {
+        let mut tx = pool.begin().await?;
+
+        // Seller must own the file through one of their servers
+        let software = sqlx::query!(
+            r#"
+            SELECT sw.name, sw.type, sw.version::FLOAT8 as "version!", sl.listing_id as "listing_id?"
+            FROM software sw
+            JOIN servers s ON s.id = sw.server_id
+            LEFT JOIN software_licenses sl ON sl.software_id = sw.id
+            WHERE sw.id = $1 AND s.user_id = $2
+            "#,
+            software_id,
+            seller_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(software) = software else {
+            return Ok(None);
+        };
+
+        // A licensed copy can only be put back on the market if its origin listing allows it
+        let origin_listing_id = match software.listing_id {
+            Some(listing_id) => {
+                let origin = sqlx::query!(
+                    "SELECT COALESCE(origin_listing_id, id) as \"origin_id!\", allow_resale FROM software_listings WHERE id = $1",
+                    listing_id
+                )
+                .fetch_one(&mut *tx)
+                .await?;
+
+                if !origin.allow_resale {
+                    return Ok(None);
+                }
+                Some(origin.origin_id)
+            }
+            None => None,
+        };
+
+        let listing = sqlx::query_as!(
+            SoftwareListing,
+            r#"
+            INSERT INTO software_listings
+                (seller_id, software_id, name, type, version, price, copies_total,
+                 allow_resale, royalty_percent, origin_listing_id)
+            VALUES ($1, $2, $3, $4, $5::FLOAT8::DECIMAL, $6, $7, $8, $9, $10)
+            RETURNING id, seller_id, software_id, name, type as software_type,
+                      version::FLOAT8 as "version!", price, copies_total, copies_sold,
+                      allow_resale, royalty_percent, origin_listing_id, is_active, created_at
+            "#,
+            seller_id,
+            software_id,
+            software.name,
+            software.r#type,
+            software.version,
+            price,
+            copies_total,
+            allow_resale,
+            royalty_percent,
+            origin_listing_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(listing))
+    }
+
+    pub async fn search_listings(
+        pool: &PgPool,
+        query: Option<&str>,
+        software_type: Option<&str>,
+        min_version: Option<f64>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SoftwareListing>> {
+        let pattern = query.map(|q| format!("%{}%", q.to_lowercase()));
+
+        let listings = sqlx::query_as!(
+            SoftwareListing,
+            r#"
+            SELECT id, seller_id, software_id, name, type as software_type,
+                   version::FLOAT8 as "version!", price, copies_total, copies_sold,
+                   allow_resale, royalty_percent, origin_listing_id, is_active, created_at
+            FROM software_listings
+            WHERE is_active = TRUE
+              AND ($1::TEXT IS NULL OR LOWER(name) LIKE $1)
+              AND ($2::TEXT IS NULL OR type = $2)
+              AND ($3::FLOAT8 IS NULL OR version >= $3::FLOAT8::DECIMAL)
+            ORDER BY version DESC, price ASC
+            LIMIT $4 OFFSET $5
+            "#,
+            pattern,
+            software_type,
+            min_version,
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(listings)
+    }
+
+    pub async fn get_listing(pool: &PgPool, listing_id: i64) -> Result<Option<SoftwareListing>> {
+        let listing = sqlx::query_as!(
+            SoftwareListing,
+            r#"
+            SELECT id, seller_id, software_id, name, type as software_type,
+                   version::FLOAT8 as "version!", price, copies_total, copies_sold,
+                   allow_resale, royalty_percent, origin_listing_id, is_active, created_at
+            FROM software_listings
+            WHERE id = $1
+            "#,
+            listing_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(listing)
+    }
+
+    pub async fn cancel_listing(pool: &PgPool, listing_id: i64, seller_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE software_listings SET is_active = FALSE WHERE id = $1 AND seller_id = $2 AND is_active = TRUE",
+            listing_id,
+            seller_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Buy one copy: charge the buyer, pay seller (and royalties), clone the file
+    /// into the buyer's main server and stamp it with a license.
+    /// Money and disk space held by other reservations cannot be spent; a
+    /// live `reservation` of the buyer's is spent first and its holds used.
+    /// Refused while the seller has no active bank account.
+    pub async fn purchase(pool: &PgPool, listing_id: i64, buyer_id: i64, reservation: Option<i64>) -> Result<PurchaseOutcome> {
+        let mut tx = pool.begin().await?;
+
+        let listing = sqlx::query!(
+            r#"
+            SELECT l.seller_id, l.software_id, l.price, l.copies_total, l.copies_sold,
+                   o.seller_id as "origin_seller_id?", o.royalty_percent as "origin_royalty_percent?"
+            FROM software_listings l
+            LEFT JOIN software_listings o ON o.id = l.origin_listing_id
+            WHERE l.id = $1 AND l.is_active = TRUE
+            FOR UPDATE OF l
+            "#,
+            listing_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(listing) = listing else {
+            return Ok(PurchaseOutcome::NotFound);
+        };
+        if listing.seller_id == buyer_id {
+            return Ok(PurchaseOutcome::OwnListing);
+        }
+        if listing.copies_sold >= listing.copies_total {
+            return Ok(PurchaseOutcome::SoldOut);
+        }
+
+        ReservationQueries::lock_user(&mut tx, buyer_id).await?;
+        let reservation = match reservation {
+            Some(id) => match ReservationQueries::lock(&mut tx, id, buyer_id).await? {
+                Some(row) if row.status == "held" && row.expires_at > Utc::now() => Some(row),
+                _ => return Ok(PurchaseOutcome::ReservationInvalid),
+            },
+            None => None,
+        };
+        let except = reservation.as_ref().map(|row| row.id);
+
+        let buyer_server_id = match reservation.as_ref().and_then(|row| row.server_id) {
+            Some(id) => Some(id),
+            None => sqlx::query_scalar!(
+                "SELECT id FROM servers WHERE user_id = $1 AND is_npc = FALSE ORDER BY id LIMIT 1",
+                buyer_id
+            )
+            .fetch_optional(&mut *tx)
+            .await?,
+        };
+
+        let Some(buyer_server_id) = buyer_server_id else {
+            return Ok(PurchaseOutcome::NoServer);
+        };
+
+        let size = sqlx::query_scalar!("SELECT size FROM software WHERE id = $1", listing.software_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if ReservationQueries::free_space(&mut tx, buyer_server_id, except).await? < size as i64 {
+            return Ok(PurchaseOutcome::NoSpace);
+        }
+
+        let reserved_account = match reservation.as_ref().and_then(|row| row.bank_account_id) {
+            Some(id) if ReservationQueries::spendable_on(&mut tx, id, except).await? >= listing.price => Some(id),
+            _ => None,
+        };
+        let buyer_account = match reserved_account {
+            Some(id) => Some(id),
+            None => ReservationQueries::spendable_account(&mut tx, buyer_id, except)
+                .await?
+                .filter(|(_, spendable)| *spendable >= listing.price)
+                .map(|(id, _)| id),
+        };
+
+        let Some(buyer_account) = buyer_account else {
+            tx.rollback().await?;
+            return Ok(PurchaseOutcome::InsufficientFunds);
+        };
+
+        // Proceeds are never sent out of the game: the sale waits until the
+        // seller has an account to receive them
+        let Some(seller_account) = BankQueries::primary_account(&mut *tx, listing.seller_id).await? else {
+            tx.rollback().await?;
+            return Ok(PurchaseOutcome::SellerUnavailable);
+        };
+
+        let split = Self::split_sale(listing.price, listing.origin_royalty_percent);
+        let author_account = match listing.origin_seller_id {
+            Some(author_id) => BankQueries::primary_account(&mut *tx, author_id).await?,
+            None => None,
+        };
+        let mut legs = vec![(LedgerAccount::Bank(buyer_account), -listing.price)];
+        match (listing.origin_seller_id, author_account) {
+            (Some(_), Some(author_account)) => legs.extend([
+                (LedgerAccount::Bank(seller_account), split.seller),
+                (LedgerAccount::Bank(author_account), split.royalty),
+            ]),
+            // An author with no account to pay forgoes the royalty to the seller
+            (Some(_), None) => legs.push((LedgerAccount::Bank(seller_account), listing.price)),
+            (None, _) => legs.extend([
+                (LedgerAccount::Bank(seller_account), split.seller),
+                (LedgerAccount::Sink, split.royalty),
+            ]),
+        }
+        let reference = format!("software_listing:{}", listing_id);
+        if !LedgerQueries::post(&mut *tx, LedgerReason::MarketplacePurchase, &reference, &legs).await? {
+            tx.rollback().await?;
+            return Ok(PurchaseOutcome::InsufficientFunds);
+        }
+
+        let software_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO software (server_id, name, type, version, size, effectiveness)
+            SELECT $1, name, type, version, size, effectiveness FROM software WHERE id = $2
+            RETURNING id
+            "#,
+            buyer_server_id,
+            listing.software_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query!("UPDATE servers SET hdd_used = hdd_used + $1 WHERE id = $2", size, buyer_server_id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some(reservation) = &reservation {
+            ReservationQueries::take_money_and_space(&mut tx, reservation.id).await?;
+        }
+
+        let license_key = Uuid::new_v4().simple().to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO software_licenses (software_id, listing_id, buyer_id, license_key, price_paid)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            software_id,
+            listing_id,
+            buyer_id,
+            &license_key,
+            listing.price
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE software_listings
+            SET copies_sold = copies_sold + 1,
+                is_active = copies_sold + 1 < copies_total
+            WHERE id = $1
+            "#,
+            listing_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(PurchaseOutcome::Purchased { software_id, license_key })
+    }
+
+}
+
+/// Pending email change requests expire after this long
+pub const EMAIL_CHANGE_TOKEN_TTL_HOURS: i64 = 24;
+/// How long the old address can cancel or roll back a change
+pub const EMAIL_CHANGE_REVOCATION_HOURS: i64 = 72;
+
+#[derive(Debug, Clone)]
+pub enum StartEmailChange {
+    /// Request created; `confirm_token` and `revoke_token` go to the old address
+    Started {
+        request: EmailChangeRequest,
+        confirm_token: String,
+        revoke_token: String,
+    },
+    EmailInUse,
+}
+
+pub struct EmailChangeQueries;
+
+impl EmailChangeQueries {
+    /// Random token for email links; only its SHA256 is stored
+    pub fn generate_token() -> String {
+        format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+    }
+
+    /// Start a change, replacing any change already in flight for the user
+    pub async fn start(pool: &PgPool, user_id: i64, old_email: &str, new_email: &str) -> Result<StartEmailChange> {
+        let mut tx = pool.begin().await?;
+
+        let in_use = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM users
+                WHERE email_index = $2 OR (email_index IS NULL AND LOWER(email) = LOWER($1))
+            ) as "exists!"
+            "#,
+            new_email,
+            crate::encryption::email_index(new_email)
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if in_use {
+            return Ok(StartEmailChange::EmailInUse);
+        }
+
+        sqlx::query!(
+            "UPDATE email_change_requests SET status = 'expired'
+             WHERE user_id = $1 AND status IN ('awaiting_old', 'awaiting_new')",
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let confirm_token = Self::generate_token();
+        let revoke_token = Self::generate_token();
+
+        let request = sqlx::query_as!(
+            EmailChangeRequest,
+            r#"
+            INSERT INTO email_change_requests
+                (user_id, old_email, new_email, confirm_token_hash, revoke_token_hash, expires_at, revocable_until)
+            VALUES ($1, $2, $3,
+                    encode(sha256(convert_to($4, 'UTF8')), 'hex'),
+                    encode(sha256(convert_to($5, 'UTF8')), 'hex'),
+                    NOW() + make_interval(hours => $6), NOW() + make_interval(hours => $7))
+            RETURNING id, user_id, old_email, new_email, status, created_at, expires_at,
+                      revocable_until, old_confirmed_at, completed_at, revoked_at
+            "#,
+            user_id,
+            old_email,
+            new_email,
+            confirm_token,
+            revoke_token,
+            EMAIL_CHANGE_TOKEN_TTL_HOURS as i32,
+            EMAIL_CHANGE_REVOCATION_HOURS as i32
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(StartEmailChange::Started { request, confirm_token, revoke_token })
+    }
+
+    /// Latest change request for a user, pending or not
+    pub async fn latest_for_user(pool: &PgPool, user_id: i64) -> Result<Option<EmailChangeRequest>> {
+        let request = sqlx::query_as!(
+            EmailChangeRequest,
+            r#"
+            SELECT id, user_id, old_email, new_email, status, created_at, expires_at,
+                   revocable_until, old_confirmed_at, completed_at, revoked_at
+            FROM email_change_requests
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(request)
+    }
+
+    /// Old address confirmed the change. Returns the token to mail to the new address.
+    pub async fn confirm_old_address(
+        pool: &PgPool,
+        user_id: i64,
+        token: &str,
+    ) -> Result<Option<(EmailChangeRequest, String)>> {
+        let verify_token = Self::generate_token();
+
+        let request = sqlx::query_as!(
+            EmailChangeRequest,
+            r#"
+            UPDATE email_change_requests
+            SET status = 'awaiting_new',
+                old_confirmed_at = NOW(),
+                verify_token_hash = encode(sha256(convert_to($3, 'UTF8')), 'hex')
+            WHERE user_id = $1
+              AND confirm_token_hash = encode(sha256(convert_to($2, 'UTF8')), 'hex')
+              AND status = 'awaiting_old'
+              AND expires_at > NOW()
+            RETURNING id, user_id, old_email, new_email, status, created_at, expires_at,
+                      revocable_until, old_confirmed_at, completed_at, revoked_at
+            "#,
+            user_id,
+            token,
+            verify_token
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(request.map(|r| (r, verify_token)))
+    }
+
+    /// New address verified: switch the email and drop every session of the user.
+    ///
+    /// Returns `None` if the token is wrong, expired, or the address was taken in the meantime.
+    pub async fn complete(pool: &PgPool, user_id: i64, token: &str) -> Result<Option<EmailChangeRequest>> {
+        let mut tx = pool.begin().await?;
+
+        let request = sqlx::query_as!(
+            EmailChangeRequest,
+            r#"
+            UPDATE email_change_requests
+            SET status = 'completed', completed_at = NOW()
+            WHERE user_id = $1
+              AND verify_token_hash = encode(sha256(convert_to($2, 'UTF8')), 'hex')
+              AND status = 'awaiting_new'
+              AND expires_at > NOW()
+            RETURNING id, user_id, old_email, new_email, status, created_at, expires_at,
+                      revocable_until, old_confirmed_at, completed_at, revoked_at
+            "#,
+            user_id,
+            token
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(request) = request else {
+            return Ok(None);
+        };
+
+        let sealed = crate::encryption::seal(&request.new_email)?;
+        let index = crate::encryption::email_index(&request.new_email);
+        let switched = sqlx::query!(
+            r#"
+            UPDATE users SET email = $1, email_index = $3, email_key_version = $4
+            WHERE id = $2
+              AND NOT EXISTS (
+                  SELECT 1 FROM users
+                  WHERE id <> $2 AND (email_index = $3 OR (email_index IS NULL AND LOWER(email) = LOWER($5)))
+              )
+            "#,
+            sealed.value,
+            user_id,
+            index,
+            sealed.key_version,
+            request.new_email
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if switched.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        Self::drop_sessions(&mut tx, user_id).await?;
+        tx.commit().await?;
+
+        Ok(Some(request))
+    }
+
+    /// Revoke via the link sent to the old address. No login needed: the
+    /// account owner may already be locked out.
+    ///
+    /// Pending requests are cancelled; completed ones are rolled back to the old
+    /// address and all sessions are dropped again.
+    pub async fn revoke(pool: &PgPool, token: &str) -> Result<Option<EmailChangeRequest>> {
+        let mut tx = pool.begin().await?;
+
+        let previous_status = sqlx::query_scalar!(
+            r#"
+            SELECT status FROM email_change_requests
+            WHERE revoke_token_hash = encode(sha256(convert_to($1, 'UTF8')), 'hex')
+              AND status IN ('awaiting_old', 'awaiting_new', 'completed')
+              AND revocable_until > NOW()
+            FOR UPDATE
+            "#,
+            token
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(previous_status) = previous_status else {
+            return Ok(None);
+        };
+
+        let request = sqlx::query_as!(
+            EmailChangeRequest,
+            r#"
+            UPDATE email_change_requests
+            SET status = 'revoked', revoked_at = NOW()
+            WHERE revoke_token_hash = encode(sha256(convert_to($1, 'UTF8')), 'hex')
+            RETURNING id, user_id, old_email, new_email, status, created_at, expires_at,
+                      revocable_until, old_confirmed_at, completed_at, revoked_at
+            "#,
+            token
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if previous_status == "completed" {
+            let sealed = crate::encryption::seal(&request.old_email)?;
+            sqlx::query!(
+                r#"
+                UPDATE users SET email = $1, email_index = $4, email_key_version = $5
+                WHERE id = $2
+                  AND (email_index = $6 OR (email_index IS NULL AND email = $3))
+                "#,
+                sealed.value,
+                request.user_id,
+                request.new_email,
+                crate::encryption::email_index(&request.old_email),
+                sealed.key_version,
+                crate::encryption::email_index(&request.new_email)
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            Self::drop_sessions(&mut tx, request.user_id).await?;
+        }
+
+        tx.commit().await?;
+        Ok(Some(request))
+    }
+
+    async fn drop_sessions(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, user_id: i64) -> Result<u64> {
+        let result = sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user_id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Job registry written by he-cron; the admin API reads it and queues
+/// pause and trigger requests, which he-cron polls for
+pub struct CronJobQueries;
+
+impl CronJobQueries {
+    pub async fn list(pool: &PgPool) -> Result<Vec<CronJob>> {
+        let jobs = sqlx::query_as!(
+            CronJob,
+            r#"
+            SELECT name, schedule, time_budget_seconds, paused, trigger_requested_at, next_run_at,
+                   last_started_at, last_finished_at, last_duration_ms, last_success, last_error,
+                   run_count, failure_count, updated_at
+            FROM cron_jobs
+            ORDER BY name
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(jobs)
+    }
+
+    pub async fn get(pool: &PgPool, name: &str) -> Result<Option<CronJob>> {
+        let job = sqlx::query_as!(
+            CronJob,
+            r#"
+            SELECT name, schedule, time_budget_seconds, paused, trigger_requested_at, next_run_at,
+                   last_started_at, last_finished_at, last_duration_ms, last_success, last_error,
+                   run_count, failure_count, updated_at
+            FROM cron_jobs
+            WHERE name = $1
+            "#,
+            name
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(job)
+    }
+
+    /// Latest runs of a job, newest first
+    pub async fn recent_runs(pool: &PgPool, name: &str, limit: i64) -> Result<Vec<CronJobRun>> {
+        let runs = sqlx::query_as!(
+            CronJobRun,
+            r#"
+            SELECT id, job_name, trigger, status, host, started_at, finished_at, duration_ms, error
+            FROM cron_job_runs
+            WHERE job_name = $1
+            ORDER BY started_at DESC
+            LIMIT $2
+            "#,
+            name,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(runs)
+    }
+
+    /// Returns false if the job does not exist
+    pub async fn set_paused(pool: &PgPool, name: &str, paused: bool, admin_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE cron_jobs
+            SET paused = $2, paused_by = CASE WHEN $2 THEN $3 ELSE NULL END, updated_at = NOW()
+            WHERE name = $1
+            "#,
+            name,
+            paused,
+            admin_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Ask he-cron to run the job now. Returns false if the job does not exist.
+    pub async fn request_trigger(pool: &PgPool, name: &str, admin_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE cron_jobs
+            SET trigger_requested_at = NOW(), trigger_requested_by = $2, updated_at = NOW()
+            WHERE name = $1
+            "#,
+            name,
+            admin_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn open_alerts(pool: &PgPool) -> Result<Vec<CronJobAlert>> {
+        let alerts = sqlx::query_as!(
+            CronJobAlert,
+            r#"
+            SELECT id, job_name, kind, detail, raised_at, resolved_at, acknowledged_by
+            FROM cron_job_alerts
+            WHERE resolved_at IS NULL
+            ORDER BY raised_at DESC
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(alerts)
+    }
+
+    /// Close an open alert by hand. Returns the alert's job, or `None` if the
+    /// alert is unknown or already closed.
+    ///
+    /// Acknowledging an over-budget alert also marks the job's overrunning
+    /// runs as abandoned, otherwise the scheduler would raise it again. A run
+    /// that was only slow overwrites this with its real outcome when it ends.
+    pub async fn acknowledge_alert(pool: &PgPool, alert_id: i64, admin_id: i64) -> Result<Option<String>> {
+        let mut tx = pool.begin().await?;
+
+        let alert = sqlx::query!(
+            r#"
+            UPDATE cron_job_alerts
+            SET resolved_at = NOW(), acknowledged_by = $2
+            WHERE id = $1 AND resolved_at IS NULL
+            RETURNING job_name, kind
+            "#,
+            alert_id,
+            admin_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(alert) = alert else {
+            return Ok(None);
+        };
+
+        if alert.kind == "over_budget" {
+            sqlx::query!(
+                r#"
+                UPDATE cron_job_runs r
+                SET status = 'failed', finished_at = NOW(), error = 'abandoned, acknowledged by an operator'
+                FROM cron_jobs j
+                WHERE j.name = r.job_name AND r.job_name = $1 AND r.status = 'running'
+                  AND r.started_at < NOW() - make_interval(secs => j.time_budget_seconds)
+                "#,
+                alert.job_name
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(Some(alert.job_name))
+    }
+}
+
+/// What decides whether a player may reset their IP
+#[derive(Debug, Clone)]
+pub struct IpResetState {
+    pub last_reset: Option<DateTime<Utc>>,
+    pub in_progress: bool,
+    /// Balance of the account the reset is paid from
+    pub balance: i64,
+}
+
+/// What a completed reset changed for other players
+#[derive(Debug, Clone, Default)]
+pub struct IpResetScrub {
+    /// Players whose hacked-database entry for the old address was invalidated
+    pub lost_by: Vec<i64>,
+    /// The player's own tunnels moved to the new address
+    pub tunnels_rewritten: u64,
+    /// Players whose tunnel to or through the old address was closed
+    pub tunnels_closed_for: Vec<i64>,
+}
+
+pub struct IpResetQueries;
+
+impl IpResetQueries {
+    pub async fn state(pool: &PgPool, user_id: i64) -> Result<IpResetState> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                (SELECT MAX(completed_at) FROM ip_resets WHERE user_id = $1) AS last_reset,
+                EXISTS (
+                    SELECT 1 FROM ip_resets r JOIN processes p ON p.pid = r.pid
+                    WHERE r.user_id = $1 AND r.completed_at IS NULL AND p.completed_at IS NULL
+                ) AS "in_progress!",
+                COALESCE((
+                    SELECT balance FROM bank_accounts
+                    WHERE user_id = $1 AND is_active = TRUE
+                    ORDER BY id
+                    LIMIT 1
+                ), 0) AS "balance!"
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(IpResetState {
+            last_reset: row.last_reset,
+            in_progress: row.in_progress,
+            balance: row.balance,
+        })
+    }
+
+    /// Charge for the reset started as process `pid`. False if another reset
+    /// is running, the last one is within the cooldown, or the player cannot
+    /// pay. The player row is locked so two resets cannot both be charged.
+    pub async fn charge(pool: &PgPool, user_id: i64, pid: i64, cost: i64, cooldown_hours: i64) -> Result<bool> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let allowed = sqlx::query_scalar!(
+            r#"
+            SELECT NOT EXISTS (
+                SELECT 1 FROM ip_resets r
+                LEFT JOIN processes p ON p.pid = r.pid
+                WHERE r.user_id = $1 AND (
+                    r.completed_at > NOW() - make_interval(hours => $2)
+                    OR (r.completed_at IS NULL AND p.completed_at IS NULL AND p.pid IS NOT NULL)
+                )
+            ) AS "allowed!"
+            "#,
+            user_id,
+            cooldown_hours as i32
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if !allowed {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        let reference = format!("process:{}", pid);
+        let debited =
+            BankQueries::debit_primary_account(&mut *tx, user_id, cost, LedgerAccount::Sink, LedgerReason::IpReset, &reference)
+                .await?;
+
+        if !debited {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            "INSERT INTO ip_resets (user_id, pid, cost) VALUES ($1, $2, $3)",
+            user_id,
+            pid,
+            cost
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Address of the player's gateway, their first server
+    pub async fn gateway_ip(conn: &mut PgConnection, user_id: i64) -> Result<Option<String>> {
+        let ip = sqlx::query_scalar!(
+            r#"
+            SELECT host(ip_address) AS "ip!" FROM servers
+            WHERE user_id = $1 AND is_npc = FALSE
+            ORDER BY id
+            LIMIT 1
+            FOR UPDATE
+            "#,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(ip)
+    }
+
+    /// Move the player's gateway from `old_ip` to `new_ip`; false if `new_ip` is taken
+    pub async fn move_gateway(conn: &mut PgConnection, user_id: i64, old_ip: &str, new_ip: &str) -> Result<bool> {
+        let moved = sqlx::query!(
+            r#"
+            UPDATE servers SET ip_address = $3::text::inet
+            WHERE user_id = $1 AND host(ip_address) = $2
+              AND NOT EXISTS (SELECT 1 FROM servers WHERE ip_address = $3::text::inet)
+            "#,
+            user_id,
+            old_ip,
+            new_ip
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected() > 0;
+
+        if moved {
+            // The legacy schema keeps the address as an integer too
+            sqlx::query!(
+                "UPDATE users SET game_ip = $2::text::inet - '0.0.0.0'::inet WHERE id = $1",
+                user_id,
+                new_ip
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(moved)
+    }
+
+    /// Invalidate other players' hacked-database entries for `old_ip` and
+    /// close every tunnel other players had opened to or through it, so none
+    /// of them learns `new_ip`. The player's own tunnels move to `new_ip`.
+    /// Logs are not touched and keep `old_ip`.
+    pub async fn scrub(
+        conn: &mut PgConnection,
+        user_id: i64,
+        old_ip: &str,
+        new_ip: &str,
+    ) -> Result<IpResetScrub> {
+        let lost_by = sqlx::query_scalar!(
+            r#"
+            UPDATE hacked_database SET invalidated_at = NOW()
+            WHERE ip_address = $1::text::inet AND user_id <> $2 AND invalidated_at IS NULL
+            RETURNING user_id
+            "#,
+            old_ip,
+            user_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut tunnels_closed_for = sqlx::query_scalar!(
+            r#"
+            UPDATE tunnels SET closed_at = NOW()
+            WHERE user_id <> $2 AND closed_at IS NULL
+              AND (gateway_ip = $1::text::inet OR target_ip = $1::text::inet OR $1::text::inet = ANY(bounce_ips))
+            RETURNING user_id
+            "#,
+            old_ip,
+            user_id
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+        tunnels_closed_for.sort_unstable();
+        tunnels_closed_for.dedup();
+
+        let tunnels_rewritten = sqlx::query!(
+            r#"
+            UPDATE tunnels SET
+                gateway_ip = CASE WHEN gateway_ip = $1::text::inet THEN $2::text::inet ELSE gateway_ip END,
+                target_ip = CASE WHEN target_ip = $1::text::inet THEN $2::text::inet ELSE target_ip END,
+                bounce_ips = array_replace(bounce_ips, $1::text::inet, $2::text::inet)
+            WHERE user_id = $3 AND closed_at IS NULL
+              AND (gateway_ip = $1::text::inet OR target_ip = $1::text::inet OR $1::text::inet = ANY(bounce_ips))
+            "#,
+            old_ip,
+            new_ip,
+            user_id
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+
+        Ok(IpResetScrub { lost_by, tunnels_rewritten, tunnels_closed_for })
+    }
+
+    pub async fn complete(conn: &mut PgConnection, pid: i64, old_ip: &str, new_ip: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE ip_resets SET old_ip = $2::text::inet, new_ip = $3::text::inet, completed_at = NOW()
+            WHERE pid = $1
+            "#,
+            pid,
+            old_ip,
+            new_ip
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+pub struct HackedDatabaseQueries;
+
+impl HackedDatabaseQueries {
+    /// Remember that the player got into the server at `ip`. Their own servers
+    /// and addresses nobody holds are skipped.
+    pub async fn record(conn: &mut PgConnection, user_id: i64, ip: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO hacked_database (user_id, ip_address)
+            SELECT $1, ip_address FROM servers WHERE host(ip_address) = $2 AND user_id <> $1
+            ON CONFLICT (user_id, ip_address) WHERE invalidated_at IS NULL
+            DO UPDATE SET hacked_at = NOW()
+            "#,
+            user_id,
+            ip
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// A honeypot trip as recorded
+pub struct NewHoneypotTrip<'a> {
+    pub server_id: i64,
+    pub user_id: i64,
+    pub pid: i64,
+    pub process_type: &'a str,
+    pub gateway_ip: Option<&'a str>,
+    pub bounce_ips: &'a [String],
+    pub host_ip: Option<&'a str>,
+    pub target_ip: &'a str,
+    pub anomalous: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HoneypotTripRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub login: String,
+    pub pid: i64,
+    pub process_type: String,
+    pub gateway_ip: Option<String>,
+    pub bounce_ips: Vec<String>,
+    pub host_ip: Option<String>,
+    pub target_ip: String,
+    pub anomalous: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Honeypot servers and the players they traced
+pub struct HoneypotQueries;
+
+impl HoneypotQueries {
+    /// The honeypot at `ip`, if it is one
+    pub async fn at(conn: &mut PgConnection, ip: &str) -> Result<Option<i64>> {
+        let id = sqlx::query_scalar!(
+            "SELECT id FROM servers WHERE host(ip_address) = $1 AND is_honeypot",
+            ip
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// The player's gateway and the bounces of their newest open tunnel to
+    /// `target_ip`, if they have one
+    pub async fn route(conn: &mut PgConnection, user_id: i64, target_ip: &str) -> Result<(Option<String>, Vec<String>)> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                (SELECT host(ip_address) FROM servers
+                 WHERE user_id = $1 AND is_npc = FALSE
+                 ORDER BY id LIMIT 1) AS gateway_ip,
+                (SELECT ARRAY(SELECT host(b) FROM unnest(t.bounce_ips) WITH ORDINALITY AS u(b, n) ORDER BY n)
+                 FROM tunnels t
+                 WHERE t.user_id = $1 AND t.target_ip = $2::text::inet AND t.closed_at IS NULL
+                 ORDER BY t.created_at DESC LIMIT 1) AS bounce_ips
+            "#,
+            user_id,
+            target_ip
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok((row.gateway_ip, row.bounce_ips.unwrap_or_default()))
+    }
+
+    /// Honeypots the player tripped within the last `hours`
+    pub async fn recent_trips(conn: &mut PgConnection, user_id: i64, hours: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM honeypot_trips
+            WHERE user_id = $1 AND created_at > NOW() - make_interval(hours => $2::int)
+            "#,
+            user_id,
+            hours as i32
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Lock the player's trace, returning when it ends if they have one
+    pub async fn lock_traced(conn: &mut PgConnection, user_id: i64) -> Result<Option<DateTime<Utc>>> {
+        let until = sqlx::query_scalar!(
+            "SELECT traced_until FROM traced_players WHERE user_id = $1 FOR UPDATE",
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(until)
+    }
+
+    /// Whether the player is traced right now
+    pub async fn is_traced(conn: &mut PgConnection, user_id: i64) -> Result<bool> {
+        let traced = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM traced_players WHERE user_id = $1 AND traced_until > NOW()) AS "traced!""#,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(traced)
+    }
+
+    /// Returns None if the process already tripped the honeypot
+    pub async fn record_trip(conn: &mut PgConnection, trip: &NewHoneypotTrip<'_>) -> Result<Option<i64>> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO honeypot_trips
+                (server_id, user_id, pid, process_type, gateway_ip, bounce_ips, host_ip, target_ip, anomalous)
+            VALUES ($1, $2, $3, $4, $5::text::inet, $6::text[]::inet[], $7::text::inet, $8::text::inet, $9)
+            ON CONFLICT (pid) DO NOTHING
+            RETURNING id
+            "#,
+            trip.server_id,
+            trip.user_id,
+            trip.pid,
+            trip.process_type,
+            trip.gateway_ip,
+            trip.bounce_ips,
+            trip.host_ip,
+            trip.target_ip,
+            trip.anomalous
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn set_traced(conn: &mut PgConnection, user_id: i64, until: DateTime<Utc>, trip_id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO traced_players (user_id, traced_until, last_trip_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE SET
+                traced_until = EXCLUDED.traced_until,
+                last_trip_id = EXCLUDED.last_trip_id,
+                updated_at = NOW()
+            "#,
+            user_id,
+            until,
+            trip_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Trips newest first, optionally only the anomalous ones
+    pub async fn trips(pool: &PgPool, anomalous_only: bool, before_id: Option<i64>, limit: i64) -> Result<Vec<HoneypotTripRow>> {
+        let trips = sqlx::query_as!(
+            HoneypotTripRow,
+            r#"
+            SELECT h.id, h.user_id, u.login, h.pid, h.process_type,
+                   host(h.gateway_ip) AS gateway_ip,
+                   ARRAY(SELECT host(b) FROM unnest(h.bounce_ips) WITH ORDINALITY AS x(b, n) ORDER BY n) AS "bounce_ips!",
+                   host(h.host_ip) AS host_ip, host(h.target_ip) AS "target_ip!", h.anomalous, h.created_at
+            FROM honeypot_trips h
+            JOIN users u ON u.id = h.user_id
+            WHERE ($1 = FALSE OR h.anomalous) AND ($2::BIGINT IS NULL OR h.id < $2)
+            ORDER BY h.id DESC
+            LIMIT $3
+            "#,
+            anomalous_only,
+            before_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(trips)
+    }
+}
+
+/// The clan a player belongs to and their rank in it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClanMembership {
+    pub clan_id: i64,
+    pub role: String,
+}
+
+impl ClanMembership {
+    /// Leaders and officers spend the treasury and declare wars
+    pub fn is_officer(&self) -> bool {
+        self.role == "leader" || self.role == "officer"
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClanServerRow {
+    pub clan_id: i64,
+    pub server_id: i64,
+    pub ip: String,
+    pub cpu_level: i32,
+    pub ram_level: i32,
+    pub hdd_level: i32,
+    pub net_level: i32,
+    pub cpu_total: i32,
+    pub ram_total: i32,
+    pub hdd_total: i32,
+    pub net_total: i32,
+    pub hdd_used: i32,
+    pub treasury: i64,
+}
+
+/// Software stored on a clan server
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClanFileRow {
+    pub software_id: i64,
+    pub name: String,
+    pub software_type: String,
+    pub version: f64,
+    pub size: i32,
+    pub uploaded_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClanWarRow {
+    pub id: i64,
+    pub attacker_clan_id: i64,
+    pub defender_clan_id: i64,
+    pub status: String,
+    pub attacker_score: i64,
+    pub defender_score: i64,
+    pub attacker_integrity: i32,
+    pub defender_integrity: i32,
+    pub winner_clan_id: Option<i64>,
+    pub started_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+/// Clan servers, their shared storage and lent defense, and clan wars
+pub struct ClanServerQueries;
+
+impl ClanServerQueries {
+    /// The player's clan, if they are in one
+    pub async fn membership(pool: &PgPool, user_id: i64) -> Result<Option<ClanMembership>> {
+        let membership = sqlx::query_as!(
+            ClanMembership,
+            "SELECT clan_id, role FROM clan_members WHERE user_id = $1 ORDER BY joined_at LIMIT 1",
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(membership)
+    }
+
+    /// A member's role in the clan, locking their membership
+    pub async fn lock_member(conn: &mut PgConnection, clan_id: i64, user_id: i64) -> Result<Option<String>> {
+        let role = sqlx::query_scalar!(
+            "SELECT role FROM clan_members WHERE clan_id = $1 AND user_id = $2 FOR UPDATE",
+            clan_id,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(role)
+    }
+
+    /// Take `user_id` out of the clan; false if they were not in it
+    pub async fn remove_member(conn: &mut PgConnection, clan_id: i64, user_id: i64) -> Result<bool> {
+        let removed = sqlx::query!(
+            "DELETE FROM clan_members WHERE clan_id = $1 AND user_id = $2",
+            clan_id,
+            user_id
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected()
+            > 0;
+        if removed {
+            sqlx::query!(
+                "UPDATE clans SET member_count = GREATEST(member_count - 1, 0) WHERE id = $1",
+                clan_id
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Lock an active clan, returning its leader
+    pub async fn lock_clan(conn: &mut PgConnection, clan_id: i64) -> Result<Option<i64>> {
+        let leader = sqlx::query_scalar!(
+            "SELECT leader_id FROM clans WHERE id = $1 AND is_active = TRUE FOR UPDATE",
+            clan_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(leader)
+    }
+
+    pub async fn server(conn: &mut PgConnection, clan_id: i64) -> Result<Option<ClanServerRow>> {
+        let server = sqlx::query_as!(
+            ClanServerRow,
+            r#"
+            SELECT cs.clan_id, cs.server_id, host(s.ip_address) AS "ip!",
+                cs.cpu_level, cs.ram_level, cs.hdd_level, cs.net_level,
+                s.cpu_total, s.ram_total, s.hdd_total, s.net_total, s.hdd_used, c.treasury
+            FROM clan_servers cs
+            JOIN servers s ON s.id = cs.server_id
+            JOIN clans c ON c.id = cs.clan_id
+            WHERE cs.clan_id = $1
+            "#,
+            clan_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(server)
+    }
+
+    /// Give the clan its server at `ip`, owned by `leader_id`. False if the
+    /// address is taken.
+    pub async fn create_server(
+        conn: &mut PgConnection,
+        clan_id: i64,
+        leader_id: i64,
+        ip: &str,
+        capacity: [i32; 4],
+    ) -> Result<bool> {
+        let server_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO servers (user_id, ip_address, hostname, cpu_total, ram_total, hdd_total, net_total, is_npc)
+            SELECT $1, $2::text::inet, 'clan-' || c.tag, $3, $4, $5, $6, TRUE FROM clans c WHERE c.id = $7
+            ON CONFLICT (ip_address) DO NOTHING
+            RETURNING id
+            "#,
+            leader_id,
+            ip,
+            capacity[0],
+            capacity[1],
+            capacity[2],
+            capacity[3],
+            clan_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let Some(server_id) = server_id else {
+            return Ok(false);
+        };
+        sqlx::query!("INSERT INTO clan_servers (clan_id, server_id) VALUES ($1, $2)", clan_id, server_id)
+            .execute(conn)
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Set one part's level and what it provides
+    pub async fn set_level(conn: &mut PgConnection, clan_id: i64, part: &str, level: i32, capacity: i32) -> Result<()> {
+        sqlx::query!(
+            r#"
+            WITH upgraded AS (
+                UPDATE clan_servers SET
+                    cpu_level = CASE WHEN $2 = 'cpu' THEN $3 ELSE cpu_level END,
+                    ram_level = CASE WHEN $2 = 'ram' THEN $3 ELSE ram_level END,
+                    hdd_level = CASE WHEN $2 = 'hdd' THEN $3 ELSE hdd_level END,
+                    net_level = CASE WHEN $2 = 'net' THEN $3 ELSE net_level END
+                WHERE clan_id = $1
+                RETURNING server_id
+            )
+            UPDATE servers SET
+                cpu_total = CASE WHEN $2 = 'cpu' THEN $4 ELSE cpu_total END,
+                ram_total = CASE WHEN $2 = 'ram' THEN $4 ELSE ram_total END,
+                hdd_total = CASE WHEN $2 = 'hdd' THEN $4 ELSE hdd_total END,
+                net_total = CASE WHEN $2 = 'net' THEN $4 ELSE net_total END
+            WHERE id = (SELECT server_id FROM upgraded)
+            "#,
+            clan_id,
+            part,
+            level,
+            capacity
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_upgrade(
+        conn: &mut PgConnection,
+        clan_id: i64,
+        part: &str,
+        level: i32,
+        cost: i64,
+        bought_by: i64,
+    ) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO clan_server_upgrades (clan_id, part, level, cost, bought_by) VALUES ($1, $2, $3, $4, $5)",
+            clan_id,
+            part,
+            level,
+            cost,
+            bought_by
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Software on the clan server, newest first
+    pub async fn files(pool: &PgPool, clan_id: i64) -> Result<Vec<ClanFileRow>> {
+        let files = sqlx::query_as!(
+            ClanFileRow,
+            r#"
+            SELECT f.software_id, sw.name, sw.type AS software_type, sw.version::FLOAT8 AS "version!",
+                sw.size, f.uploaded_by, f.created_at
+            FROM clan_server_files f
+            JOIN software sw ON sw.id = f.software_id
+            WHERE f.clan_id = $1
+            ORDER BY f.created_at DESC
+            "#,
+            clan_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    /// A file on the clan server: who stored it and its size
+    pub async fn file(conn: &mut PgConnection, clan_id: i64, software_id: i64) -> Result<Option<(Option<i64>, i32)>> {
+        let row = sqlx::query!(
+            r#"
+            SELECT f.uploaded_by, sw.size FROM clan_server_files f
+            JOIN software sw ON sw.id = f.software_id
+            WHERE f.clan_id = $1 AND f.software_id = $2
+            "#,
+            clan_id,
+            software_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row.map(|r| (r.uploaded_by, r.size)))
+    }
+
+    /// Size of a piece of software on one of the player's own servers
+    pub async fn own_software_size(conn: &mut PgConnection, user_id: i64, software_id: i64) -> Result<Option<i32>> {
+        let size = sqlx::query_scalar!(
+            r#"
+            SELECT sw.size FROM software sw
+            JOIN servers s ON s.id = sw.server_id
+            WHERE sw.id = $1 AND s.user_id = $2 AND s.is_npc = FALSE
+            "#,
+            software_id,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(size)
+    }
+
+    /// The player's gateway
+    pub async fn gateway(conn: &mut PgConnection, user_id: i64) -> Result<Option<i64>> {
+        let id = sqlx::query_scalar!(
+            "SELECT id FROM servers WHERE user_id = $1 AND is_npc = FALSE ORDER BY id LIMIT 1",
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Lock a server's disk, returning the free space on it
+    pub async fn lock_free_space(conn: &mut PgConnection, server_id: i64) -> Result<i32> {
+        let free = sqlx::query_scalar!(
+            r#"SELECT hdd_total - hdd_used AS "free!" FROM servers WHERE id = $1 FOR UPDATE"#,
+            server_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(free)
+    }
+
+    /// Copy software onto a server, uninstalled, and take its disk space
+    pub async fn copy_software(conn: &mut PgConnection, software_id: i64, server_id: i64) -> Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO software (server_id, name, type, version, size, effectiveness)
+            SELECT $2, name, type, version, size, effectiveness FROM software WHERE id = $1
+            RETURNING id
+            "#,
+            software_id,
+            server_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE servers SET hdd_used = hdd_used + (SELECT size FROM software WHERE id = $1) WHERE id = $2",
+            id,
+            server_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn record_file(conn: &mut PgConnection, software_id: i64, clan_id: i64, uploaded_by: i64) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO clan_server_files (software_id, clan_id, uploaded_by) VALUES ($1, $2, $3)",
+            software_id,
+            clan_id,
+            uploaded_by
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete a file from the clan server and free its space
+    pub async fn delete_file(conn: &mut PgConnection, server_id: i64, software_id: i64, size: i32) -> Result<()> {
+        sqlx::query!("DELETE FROM software WHERE id = $1 AND server_id = $2", software_id, server_id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(
+            "UPDATE servers SET hdd_used = GREATEST(hdd_used - $1, 0) WHERE id = $2",
+            size,
+            server_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Highest version of `software_type` on the player's own servers, not
+    /// counting copies locked to another server
+    pub async fn best_software(conn: &mut PgConnection, user_id: i64, software_type: &str) -> Result<Option<f64>> {
+        let version = sqlx::query_scalar!(
+            r#"
+            SELECT MAX(sw.version)::FLOAT8 FROM software sw
+            JOIN servers s ON s.id = sw.server_id
+            WHERE s.user_id = $1 AND s.is_npc = FALSE AND sw.type = $2
+              AND (sw.license_server_id IS NULL OR sw.license_server_id = sw.server_id)
+            "#,
+            user_id,
+            software_type
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(version)
+    }
+
+    /// Lend the player's firewall to the clan server, replacing what they
+    /// lent before
+    pub async fn lend_firewall(conn: &mut PgConnection, clan_id: i64, user_id: i64, version: f64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO clan_server_defenders (clan_id, user_id, firewall_version) VALUES ($1, $2, $3)
+            ON CONFLICT (clan_id, user_id) DO UPDATE SET firewall_version = EXCLUDED.firewall_version, lent_at = NOW()
+            "#,
+            clan_id,
+            user_id,
+            version
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Firewalls lent by the clan's current members
+    pub async fn defender_versions(conn: &mut PgConnection, clan_id: i64) -> Result<Vec<f64>> {
+        let versions = sqlx::query_scalar!(
+            r#"
+            SELECT d.firewall_version FROM clan_server_defenders d
+            JOIN clan_members m ON m.clan_id = d.clan_id AND m.user_id = d.user_id
+            WHERE d.clan_id = $1
+            "#,
+            clan_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(versions)
+    }
+
+    /// Start a war; None if the clans are already at war
+    pub async fn declare_war(
+        conn: &mut PgConnection,
+        attacker_clan_id: i64,
+        defender_clan_id: i64,
+        integrity: i32,
+        hours: i64,
+        declared_by: i64,
+    ) -> Result<Option<ClanWarRow>> {
+        let war = sqlx::query_as!(
+            ClanWarRow,
+            r#"
+            INSERT INTO clan_wars
+                (attacker_clan_id, defender_clan_id, attacker_integrity, defender_integrity, declared_by, ends_at)
+            VALUES ($1, $2, $3, $3, $4, NOW() + make_interval(hours => $5::int))
+            ON CONFLICT DO NOTHING
+            RETURNING id, attacker_clan_id, defender_clan_id, status, attacker_score, defender_score,
+                attacker_integrity, defender_integrity, winner_clan_id, started_at, ends_at, ended_at
+            "#,
+            attacker_clan_id,
+            defender_clan_id,
+            integrity,
+            declared_by,
+            hours as i32
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(war)
+    }
+
+    pub async fn war(pool: &PgPool, war_id: i64) -> Result<Option<ClanWarRow>> {
+        let war = sqlx::query_as!(
+            ClanWarRow,
+            r#"
+            SELECT id, attacker_clan_id, defender_clan_id, status, attacker_score, defender_score,
+                attacker_integrity, defender_integrity, winner_clan_id, started_at, ends_at, ended_at
+            FROM clan_wars WHERE id = $1
+            "#,
+            war_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(war)
+    }
+
+    pub async fn lock_war(conn: &mut PgConnection, war_id: i64) -> Result<Option<ClanWarRow>> {
+        let war = sqlx::query_as!(
+            ClanWarRow,
+            r#"
+            SELECT id, attacker_clan_id, defender_clan_id, status, attacker_score, defender_score,
+                attacker_integrity, defender_integrity, winner_clan_id, started_at, ends_at, ended_at
+            FROM clan_wars WHERE id = $1
+            FOR UPDATE
+            "#,
+            war_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(war)
+    }
+
+    /// The clan's wars, newest first
+    pub async fn wars(pool: &PgPool, clan_id: i64, before_id: Option<i64>, limit: i64) -> Result<Vec<ClanWarRow>> {
+        let wars = sqlx::query_as!(
+            ClanWarRow,
+            r#"
+            SELECT id, attacker_clan_id, defender_clan_id, status, attacker_score, defender_score,
+                attacker_integrity, defender_integrity, winner_clan_id, started_at, ends_at, ended_at
+            FROM clan_wars
+            WHERE (attacker_clan_id = $1 OR defender_clan_id = $1)
+              AND ($2::BIGINT IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3
+            "#,
+            clan_id,
+            before_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(wars)
+    }
+
+    /// When the player last struck in this war
+    pub async fn last_strike(conn: &mut PgConnection, war_id: i64, user_id: i64) -> Result<Option<DateTime<Utc>>> {
+        let at = sqlx::query_scalar!(
+            "SELECT MAX(created_at) FROM clan_war_strikes WHERE war_id = $1 AND user_id = $2",
+            war_id,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(at)
+    }
+
+    /// Record a strike by `side`: its score goes up and the other side's
+    /// server drops to `integrity`
+    pub async fn record_strike(
+        conn: &mut PgConnection,
+        war_id: i64,
+        user_id: i64,
+        side: &str,
+        damage: i32,
+        points: i64,
+        integrity: i32,
+    ) -> Result<ClanWarRow> {
+        sqlx::query!(
+            "INSERT INTO clan_war_strikes (war_id, user_id, side, damage, points) VALUES ($1, $2, $3, $4, $5)",
+            war_id,
+            user_id,
+            side,
+            damage,
+            points
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let war = sqlx::query_as!(
+            ClanWarRow,
+            r#"
+            UPDATE clan_wars SET
+                attacker_score = attacker_score + CASE WHEN $2 = 'attacker' THEN $3 ELSE 0 END,
+                defender_score = defender_score + CASE WHEN $2 = 'defender' THEN $3 ELSE 0 END,
+                defender_integrity = CASE WHEN $2 = 'attacker' THEN $4 ELSE defender_integrity END,
+                attacker_integrity = CASE WHEN $2 = 'defender' THEN $4 ELSE attacker_integrity END
+            WHERE id = $1
+            RETURNING id, attacker_clan_id, defender_clan_id, status, attacker_score, defender_score,
+                attacker_integrity, defender_integrity, winner_clan_id, started_at, ends_at, ended_at
+            "#,
+            war_id,
+            side,
+            points,
+            integrity
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(war)
+    }
+
+    pub async fn end_war(conn: &mut PgConnection, war_id: i64, winner_clan_id: Option<i64>) -> Result<ClanWarRow> {
+        let war = sqlx::query_as!(
+            ClanWarRow,
+            r#"
+            UPDATE clan_wars SET status = 'ended', winner_clan_id = $2, ended_at = NOW()
+            WHERE id = $1
+            RETURNING id, attacker_clan_id, defender_clan_id, status, attacker_score, defender_score,
+                attacker_integrity, defender_integrity, winner_clan_id, started_at, ends_at, ended_at
+            "#,
+            war_id,
+            winner_clan_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(war)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GeneratedMissionRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub generation_id: i64,
+    pub kind: String,
+    pub tier: String,
+    pub target_server_id: Option<i64>,
+    pub target_ip: String,
+    pub objectives: serde_json::Value,
+    pub completed_nodes: Vec<i32>,
+    pub reward_money: i64,
+    pub bonus_money: i64,
+    pub reward_exp: i64,
+    pub status: String,
+    pub offered_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+/// One mission generation with every attempt it made
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MissionGenerationLogRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub seed: i64,
+    pub player_level: i32,
+    pub cracker_version: f64,
+    pub recent: serde_json::Value,
+    pub attempts: serde_json::Value,
+    pub outcome: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An NPC server missions can be set on
+#[derive(Debug, Clone)]
+pub struct MissionTargetRow {
+    pub server_id: i64,
+    pub ip: String,
+    pub firewall: f64,
+    pub files: i64,
+    pub bank_id: Option<i64>,
+}
+
+/// The player's generated missions by state
+#[derive(Debug, Clone)]
+pub struct GeneratedMissionCounts {
+    pub offered: i64,
+    pub active: i64,
+    /// Generated in the last day
+    pub today: i64,
+}
+
+/// A generated mission the player finished, with what it paid
+#[derive(Debug, Clone)]
+pub struct MissionCompletionRow {
+    pub kind: String,
+    pub ended_at: DateTime<Utc>,
+    pub paid_money: i64,
+    pub paid_exp: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewGenerationLog<'a> {
+    pub user_id: i64,
+    pub seed: i64,
+    pub player_level: i32,
+    pub cracker_version: f64,
+    pub recent: &'a serde_json::Value,
+    pub attempts: &'a serde_json::Value,
+    pub outcome: &'a str,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewGeneratedMission<'a> {
+    pub user_id: i64,
+    pub generation_id: i64,
+    pub kind: &'a str,
+    pub tier: &'a str,
+    pub target_server_id: i64,
+    pub target_ip: &'a str,
+    pub objectives: &'a serde_json::Value,
+    pub reward_money: i64,
+    pub bonus_money: i64,
+    pub reward_exp: i64,
+    pub offer_hours: i64,
+}
+
+pub struct GeneratedMissionQueries;
+
+impl GeneratedMissionQueries {
+    /// Hold the player's row so two requests do not top up their offers
+    /// at once
+    pub async fn lock_player(conn: &mut PgConnection, user_id: i64) -> Result<bool> {
+        let found = sqlx::query_scalar!("SELECT id FROM users WHERE id = $1 FOR UPDATE", user_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(found.is_some())
+    }
+
+    /// The player's level and best cracker on their own servers
+    pub async fn profile(conn: &mut PgConnection, user_id: i64) -> Result<(i32, f64)> {
+        let player_id = legacy_user_uuid(user_id);
+
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                COALESCE((SELECT level FROM player_progression WHERE player_id = $2), 1) AS "level!",
+                COALESCE((
+                    SELECT MAX(sw.version) FROM software sw
+                    JOIN servers s ON s.id = sw.server_id
+                    WHERE s.user_id = $1 AND s.is_npc = FALSE AND sw.type = 'cracker'
+                      AND (sw.license_server_id IS NULL OR sw.license_server_id = sw.server_id)
+                ), 0)::FLOAT8 AS "cracker!"
+            "#,
+            user_id,
+            player_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok((row.level, row.cracker))
+    }
+
+    /// Kind and target of the player's latest `limit` missions, newest first
+    pub async fn recent(conn: &mut PgConnection, user_id: i64, limit: i64) -> Result<Vec<(String, Option<i64>)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT kind, target_server_id FROM generated_missions
+            WHERE user_id = $1
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.kind, r.target_server_id)).collect())
+    }
+
+    /// NPC servers missions can be set on: not honeypots or clan servers
+    pub async fn targets(conn: &mut PgConnection) -> Result<Vec<MissionTargetRow>> {
+        let targets = sqlx::query_as!(
+            MissionTargetRow,
+            r#"
+            SELECT s.id AS server_id, host(s.ip_address) AS "ip!",
+                COALESCE(MAX(sw.version) FILTER (WHERE sw.type = 'firewall'), 0)::FLOAT8 AS "firewall!",
+                COUNT(sw.id) AS "files!",
+                (SELECT b.id FROM banks b WHERE b.server_id = s.id ORDER BY b.id LIMIT 1) AS bank_id
+            FROM servers s
+            LEFT JOIN software sw ON sw.server_id = s.id
+            WHERE s.is_npc = TRUE AND s.is_honeypot = FALSE
+              AND NOT EXISTS (SELECT 1 FROM clan_servers cs WHERE cs.server_id = s.id)
+            GROUP BY s.id
+            "#
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(targets)
+    }
+
+    pub async fn counts(conn: &mut PgConnection, user_id: i64) -> Result<GeneratedMissionCounts> {
+        let counts = sqlx::query_as!(
+            GeneratedMissionCounts,
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE status = 'offered') AS "offered!",
+                COUNT(*) FILTER (WHERE status = 'active') AS "active!",
+                COUNT(*) FILTER (WHERE offered_at > NOW() - INTERVAL '1 day') AS "today!"
+            FROM generated_missions
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(counts)
+    }
+
+    /// Expire the player's offers that ran out
+    pub async fn expire_offers(conn: &mut PgConnection, user_id: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE generated_missions SET status = 'expired', ended_at = NOW()
+            WHERE user_id = $1 AND status = 'offered' AND expires_at <= NOW()
+            "#,
+            user_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn log_generation(conn: &mut PgConnection, log: &NewGenerationLog<'_>) -> Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO mission_generation_log (user_id, seed, player_level, cracker_version, recent, attempts, outcome)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+            log.user_id,
+            log.seed,
+            log.player_level,
+            log.cracker_version,
+            log.recent,
+            log.attempts,
+            log.outcome
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn insert(conn: &mut PgConnection, mission: &NewGeneratedMission<'_>) -> Result<GeneratedMissionRow> {
+        let row = sqlx::query_as!(
+            GeneratedMissionRow,
+            r#"
+            INSERT INTO generated_missions (
+                user_id, generation_id, kind, tier, target_server_id, target_ip, objectives,
+                reward_money, bonus_money, reward_exp, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW() + make_interval(hours => $11))
+            RETURNING id, user_id, generation_id, kind, tier, target_server_id, target_ip, objectives,
+                completed_nodes, reward_money, bonus_money, reward_exp, status, offered_at, expires_at,
+                accepted_at, ended_at
+            "#,
+            mission.user_id,
+            mission.generation_id,
+            mission.kind,
+            mission.tier,
+            mission.target_server_id,
+            mission.target_ip,
+            mission.objectives,
+            mission.reward_money,
+            mission.bonus_money,
+            mission.reward_exp,
+            mission.offer_hours as i32
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// The player's offered and active missions, newest first
+    pub async fn open(pool: &PgPool, user_id: i64) -> Result<Vec<GeneratedMissionRow>> {
+        let rows = sqlx::query_as!(
+            GeneratedMissionRow,
+            r#"
+            SELECT id, user_id, generation_id, kind, tier, target_server_id, target_ip, objectives,
+                completed_nodes, reward_money, bonus_money, reward_exp, status, offered_at, expires_at,
+                accepted_at, ended_at
+            FROM generated_missions
+            WHERE user_id = $1 AND status IN ('offered', 'active')
+            ORDER BY id DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Lock one of the player's missions
+    pub async fn lock(conn: &mut PgConnection, id: i64, user_id: i64) -> Result<Option<GeneratedMissionRow>> {
+        let row = sqlx::query_as!(
+            GeneratedMissionRow,
+            r#"
+            SELECT id, user_id, generation_id, kind, tier, target_server_id, target_ip, objectives,
+                completed_nodes, reward_money, bonus_money, reward_exp, status, offered_at, expires_at,
+                accepted_at, ended_at
+            FROM generated_missions
+            WHERE id = $1 AND user_id = $2
+            FOR UPDATE
+            "#,
+            id,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn accept(conn: &mut PgConnection, id: i64) -> Result<GeneratedMissionRow> {
+        let row = sqlx::query_as!(
+            GeneratedMissionRow,
+            r#"
+            UPDATE generated_missions SET status = 'active', accepted_at = NOW()
+            WHERE id = $1
+            RETURNING id, user_id, generation_id, kind, tier, target_server_id, target_ip, objectives,
+                completed_nodes, reward_money, bonus_money, reward_exp, status, offered_at, expires_at,
+                accepted_at, ended_at
+            "#,
+            id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn complete_node(conn: &mut PgConnection, id: i64, node: i32) -> Result<GeneratedMissionRow> {
+        let row = sqlx::query_as!(
+            GeneratedMissionRow,
+            r#"
+            UPDATE generated_missions SET completed_nodes = array_append(completed_nodes, $2)
+            WHERE id = $1
+            RETURNING id, user_id, generation_id, kind, tier, target_server_id, target_ip, objectives,
+                completed_nodes, reward_money, bonus_money, reward_exp, status, offered_at, expires_at,
+                accepted_at, ended_at
+            "#,
+            id,
+            node
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Median level of all players, for the catch-up bonus
+    pub async fn median_level(conn: &mut PgConnection) -> Result<i32> {
+        let median = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(percentile_disc(0.5) WITHIN GROUP (ORDER BY level), 1) AS "median!"
+            FROM player_progression
+            "#
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(median)
+    }
+
+    /// The player's missions completed since `since`, oldest first
+    pub async fn completions(
+        conn: &mut PgConnection,
+        user_id: i64,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<MissionCompletionRow>> {
+        let rows = sqlx::query_as!(
+            MissionCompletionRow,
+            r#"
+            SELECT kind, ended_at AS "ended_at!",
+                COALESCE(paid_money, reward_money) AS "paid_money!",
+                COALESCE(paid_exp, reward_exp) AS "paid_exp!"
+            FROM generated_missions
+            WHERE user_id = $1 AND status = 'completed' AND ended_at > $2
+            ORDER BY ended_at
+            "#,
+            user_id,
+            since
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Record what finishing a mission paid after scaling
+    pub async fn record_payout(conn: &mut PgConnection, id: i64, money: i64, experience: i64) -> Result<()> {
+        sqlx::query!(
+            "UPDATE generated_missions SET paid_money = $2, paid_exp = $3 WHERE id = $1",
+            id,
+            money,
+            experience
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// End a mission as `completed` or `abandoned`
+    pub async fn finish(conn: &mut PgConnection, id: i64, status: &str) -> Result<GeneratedMissionRow> {
+        let row = sqlx::query_as!(
+            GeneratedMissionRow,
+            r#"
+            UPDATE generated_missions SET status = $2, ended_at = NOW()
+            WHERE id = $1
+            RETURNING id, user_id, generation_id, kind, tier, target_server_id, target_ip, objectives,
+                completed_nodes, reward_money, bonus_money, reward_exp, status, offered_at, expires_at,
+                accepted_at, ended_at
+            "#,
+            id,
+            status
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Generations, newest first, optionally for one player or outcome
+    pub async fn generation_log(
+        pool: &PgPool,
+        user_id: Option<i64>,
+        outcome: Option<&str>,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<MissionGenerationLogRow>> {
+        let rows = sqlx::query_as!(
+            MissionGenerationLogRow,
+            r#"
+            SELECT id, user_id, seed, player_level, cracker_version, recent, attempts, outcome, created_at
+            FROM mission_generation_log
+            WHERE ($1::BIGINT IS NULL OR user_id = $1)
+              AND ($2::TEXT IS NULL OR outcome = $2)
+              AND ($3::BIGINT IS NULL OR id < $3)
+            ORDER BY id DESC
+            LIMIT $4
+            "#,
+            user_id,
+            outcome,
+            before_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// One generation and the mission it produced, if any
+    pub async fn generation(
+        pool: &PgPool,
+        id: i64,
+    ) -> Result<Option<(MissionGenerationLogRow, Option<GeneratedMissionRow>)>> {
+        let log = sqlx::query_as!(
+            MissionGenerationLogRow,
+            r#"
+            SELECT id, user_id, seed, player_level, cracker_version, recent, attempts, outcome, created_at
+            FROM mission_generation_log
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        let Some(log) = log else {
+            return Ok(None);
+        };
+        let mission = sqlx::query_as!(
+            GeneratedMissionRow,
+            r#"
+            SELECT id, user_id, generation_id, kind, tier, target_server_id, target_ip, objectives,
+                completed_nodes, reward_money, bonus_money, reward_exp, status, offered_at, expires_at,
+                accepted_at, ended_at
+            FROM generated_missions
+            WHERE generation_id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(Some((log, mission)))
+    }
+}
+
+/// Ownership lookups for WebSocket event routing
+pub struct EntityAccessQueries;
+
+impl EntityAccessQueries {
+    /// Unfinished processes the user started
+    pub async fn running_processes(pool: &PgPool, user_id: i64) -> Result<Vec<i64>> {
+        let pids = sqlx::query_scalar!(
+            "SELECT pid FROM processes WHERE user_id = $1 AND completed_at IS NULL",
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(pids)
+    }
+
+    pub async fn servers(pool: &PgPool, user_id: i64) -> Result<Vec<i64>> {
+        let ids = sqlx::query_scalar!("SELECT id FROM servers WHERE user_id = $1", user_id)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(ids)
+    }
+
+    pub async fn clans(pool: &PgPool, user_id: i64) -> Result<Vec<i64>> {
+        let ids = sqlx::query_scalar!("SELECT clan_id FROM clan_members WHERE user_id = $1", user_id)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(ids)
+    }
+
+    /// Owner of a process. Killed processes are deleted, so the complication
+    /// that killed one is consulted too.
+    pub async fn process_owner(pool: &PgPool, pid: i64) -> Result<Option<i64>> {
+        let owner = sqlx::query_scalar!(
+            r#"
+            SELECT user_id AS "user_id!" FROM processes WHERE pid = $1
+            UNION ALL
+            SELECT user_id FROM process_complications WHERE pid = $1
+            LIMIT 1
+            "#,
+            pid
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(owner)
+    }
+
+    pub async fn server_owner(pool: &PgPool, server_id: i64) -> Result<Option<i64>> {
+        let owner = sqlx::query_scalar!("SELECT user_id FROM servers WHERE id = $1", server_id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(owner)
+    }
+
+    pub async fn is_clan_member(pool: &PgPool, clan_id: i64, user_id: i64) -> Result<bool> {
+        let member = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM clan_members WHERE clan_id = $1 AND user_id = $2) AS "member!""#,
+            clan_id,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(member)
+    }
+}
+
+/// A mission template that can be run as a clan co-op mission
+#[derive(Debug, Clone)]
+pub struct CoopTemplate {
+    pub id: i64,
+    pub name: String,
+    pub reward_money: i64,
+    pub reward_exp: i32,
+    pub max_members: i32,
+    pub target: i32,
+    /// Process type whose completions count, e.g. `download`; `None` for any
+    /// process acting on another server
+    pub objective: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CoopMissionRow {
+    pub id: i64,
+    pub mission_id: i64,
+    pub clan_id: i64,
+    pub leader_id: i64,
+    pub thread_id: i64,
+    pub status: String,
+    pub max_members: i32,
+    pub progress: i32,
+    pub target: i32,
+    pub reward_money: i64,
+    pub reward_exp: i32,
+    pub failure_reason: Option<String>,
+    pub objective: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CoopMemberRow {
+    pub user_id: i64,
+    pub contribution: i32,
+    pub abandoned: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ChatThreadMessage {
+    pub id: i64,
+    /// `None` for messages the game posted
+    pub user_id: Option<i64>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Clan co-op missions and their chat threads
+pub struct CoopMissionQueries;
+
+impl CoopMissionQueries {
+    /// The template, if it is active and allows co-op
+    pub async fn template(pool: &PgPool, mission_id: i64) -> Result<Option<CoopTemplate>> {
+        let template = sqlx::query_as!(
+            CoopTemplate,
+            r#"
+            SELECT id, name, reward_money, reward_exp,
+                   coop_max_members AS "max_members!", coop_target AS target, coop_objective AS objective
+            FROM missions
+            WHERE id = $1 AND is_active = TRUE AND coop_max_members IS NOT NULL
+            "#,
+            mission_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(template)
+    }
+
+    /// Whether the user is in a co-op mission that has not ended. Locks the
+    /// user row, so concurrent joins by the same player queue up.
+    pub async fn in_running(conn: &mut PgConnection, user_id: i64) -> Result<bool> {
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", user_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+        let running = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM coop_mission_members m
+                JOIN coop_missions c ON c.id = m.coop_id
+                WHERE m.user_id = $1 AND m.abandoned_at IS NULL
+                  AND c.status IN ('recruiting', 'active')
+            ) AS "running!"
+            "#,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(running)
+    }
+
+    /// Open a co-op mission led by `leader_id`, with its chat thread
+    pub async fn create(
+        conn: &mut PgConnection,
+        template: &CoopTemplate,
+        clan_id: i64,
+        leader_id: i64,
+        max_members: i32,
+    ) -> Result<CoopMissionRow> {
+        let thread_id = sqlx::query_scalar!(
+            "INSERT INTO chat_threads (title) VALUES ($1) RETURNING id",
+            format!("Co-op: {}", template.name)
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let coop = sqlx::query_as!(
+            CoopMissionRow,
+            r#"
+            INSERT INTO coop_missions
+                (mission_id, clan_id, leader_id, thread_id, max_members, target, reward_money, reward_exp, objective)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING id, mission_id, clan_id, leader_id, thread_id, status, max_members,
+                      progress, target, reward_money, reward_exp, failure_reason, objective
+            "#,
+            template.id,
+            clan_id,
+            leader_id,
+            thread_id,
+            max_members,
+            template.target,
+            template.reward_money,
+            template.reward_exp,
+            template.objective
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Self::add_member(conn, &coop, leader_id).await?;
+        Ok(coop)
+    }
+
+    /// Lock a co-op mission for the rest of the transaction
+    pub async fn lock(conn: &mut PgConnection, coop_id: i64) -> Result<Option<CoopMissionRow>> {
+        let coop = sqlx::query_as!(
+            CoopMissionRow,
+            r#"
+            SELECT id, mission_id, clan_id, leader_id, thread_id, status, max_members,
+                   progress, target, reward_money, reward_exp, failure_reason, objective
+            FROM coop_missions WHERE id = $1
+            FOR UPDATE
+            "#,
+            coop_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(coop)
+    }
+
+    /// Lock the running mission `user_id` is an active member of, if it
+    /// started by `since`
+    pub async fn lock_running_for(
+        conn: &mut PgConnection,
+        user_id: i64,
+        since: DateTime<Utc>,
+    ) -> Result<Option<CoopMissionRow>> {
+        let coop = sqlx::query_as!(
+            CoopMissionRow,
+            r#"
+            SELECT c.id, c.mission_id, c.clan_id, c.leader_id, c.thread_id, c.status, c.max_members,
+                   c.progress, c.target, c.reward_money, c.reward_exp, c.failure_reason, c.objective
+            FROM coop_missions c
+            JOIN coop_mission_members m ON m.coop_id = c.id
+            WHERE m.user_id = $1 AND m.abandoned_at IS NULL
+              AND c.status = 'active' AND c.started_at <= $2
+            FOR UPDATE OF c
+            "#,
+            user_id,
+            since
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(coop)
+    }
+
+    pub async fn get(pool: &PgPool, coop_id: i64) -> Result<Option<CoopMissionRow>> {
+        let coop = sqlx::query_as!(
+            CoopMissionRow,
+            r#"
+            SELECT id, mission_id, clan_id, leader_id, thread_id, status, max_members,
+                   progress, target, reward_money, reward_exp, failure_reason, objective
+            FROM coop_missions WHERE id = $1
+            "#,
+            coop_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(coop)
+    }
+
+    pub async fn members(conn: &mut PgConnection, coop_id: i64) -> Result<Vec<CoopMemberRow>> {
+        let members = sqlx::query_as!(
+            CoopMemberRow,
+            r#"
+            SELECT user_id, contribution, abandoned_at IS NOT NULL AS "abandoned!"
+            FROM coop_mission_members WHERE coop_id = $1
+            ORDER BY joined_at
+            "#,
+            coop_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(members)
+    }
+
+    /// Add a member to the mission and its chat thread
+    pub async fn add_member(conn: &mut PgConnection, coop: &CoopMissionRow, user_id: i64) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO coop_mission_members (coop_id, user_id) VALUES ($1, $2)",
+            coop.id,
+            user_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO chat_thread_members (thread_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            coop.thread_id,
+            user_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn start(conn: &mut PgConnection, coop_id: i64) -> Result<()> {
+        sqlx::query!(
+            "UPDATE coop_missions SET status = 'active', started_at = NOW() WHERE id = $1",
+            coop_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Credit `amount` of progress to the member and the shared total, which
+    /// stops at the target. Returns the new total.
+    pub async fn add_progress(conn: &mut PgConnection, coop_id: i64, user_id: i64, amount: i32) -> Result<i32> {
+        sqlx::query!(
+            "UPDATE coop_mission_members SET contribution = contribution + $3 WHERE coop_id = $1 AND user_id = $2",
+            coop_id,
+            user_id,
+            amount
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let progress = sqlx::query_scalar!(
+            "UPDATE coop_missions SET progress = LEAST(progress + $2, target) WHERE id = $1 RETURNING progress",
+            coop_id,
+            amount
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(progress)
+    }
+
+    /// Mark the member as gone; false if they were not an active member
+    pub async fn abandon(conn: &mut PgConnection, coop_id: i64, user_id: i64) -> Result<bool> {
+        let abandoned = sqlx::query!(
+            r#"
+            UPDATE coop_mission_members SET abandoned_at = NOW()
+            WHERE coop_id = $1 AND user_id = $2 AND abandoned_at IS NULL
+            "#,
+            coop_id,
+            user_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() > 0;
+
+        Ok(abandoned)
+    }
+
+    pub async fn record_share(conn: &mut PgConnection, coop_id: i64, user_id: i64, money: i64, experience: i64) -> Result<()> {
+        sqlx::query!(
+            "UPDATE coop_mission_members SET reward_money = $3, reward_exp = $4 WHERE coop_id = $1 AND user_id = $2",
+            coop_id,
+            user_id,
+            money,
+            experience
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// End the mission as `completed` or `failed` and close its chat thread
+    pub async fn finish(conn: &mut PgConnection, coop: &CoopMissionRow, status: &str, reason: Option<&str>) -> Result<()> {
+        sqlx::query!(
+            "UPDATE coop_missions SET status = $2, failure_reason = $3, ended_at = NOW() WHERE id = $1",
+            coop.id,
+            status,
+            reason
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!("UPDATE chat_threads SET closed_at = NOW() WHERE id = $1", coop.thread_id)
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn is_thread_member(pool: &PgPool, thread_id: i64, user_id: i64) -> Result<bool> {
+        let member = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM chat_thread_members WHERE thread_id = $1 AND user_id = $2) AS "member!""#,
+            thread_id,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    /// Post to a thread; `None` as the author posts as the game, which can
+    /// still post to closed threads. The new message's id, `None` if the
+    /// thread is closed.
+    pub async fn post_message(pool: &PgPool, thread_id: i64, user_id: Option<i64>, body: &str) -> Result<Option<i64>> {
+        let message_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO chat_thread_messages (thread_id, user_id, body)
+            SELECT id, $2, $3 FROM chat_threads WHERE id = $1 AND (closed_at IS NULL OR $2::BIGINT IS NULL)
+            RETURNING id
+            "#,
+            thread_id,
+            user_id,
+            body
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(message_id)
+    }
+
+    /// Messages after `after_id`, oldest first
+    pub async fn messages(pool: &PgPool, thread_id: i64, after_id: i64, limit: i64) -> Result<Vec<ChatThreadMessage>> {
+        let messages = sqlx::query_as!(
+            ChatThreadMessage,
+            r#"
+            SELECT id, user_id, body, created_at FROM chat_thread_messages
+            WHERE thread_id = $1 AND id > $2
+            ORDER BY id
+            LIMIT $3
+            "#,
+            thread_id,
+            after_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(messages)
+    }
+}
+
+/// A clan's private chat room: its general and announcements threads
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClanChatRoomRow {
+    pub clan_id: i64,
+    pub thread_id: i64,
+    pub announcements_thread_id: i64,
+    /// Set when the clan disbanded
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PinnedMessageRow {
+    pub message_id: i64,
+    pub user_id: Option<i64>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub pinned_by: Option<i64>,
+    pub pinned_at: DateTime<Utc>,
+}
+
+/// Clan chat rooms and pinned messages. Rooms are provisioned, kept in step
+/// with clan membership and archived by triggers on clans and clan_members;
+/// messages are posted and read with [`CoopMissionQueries`]' thread queries.
+pub struct ClanChatQueries;
+
+impl ClanChatQueries {
+    pub async fn room(pool: &PgPool, clan_id: i64) -> Result<Option<ClanChatRoomRow>> {
+        let room = sqlx::query_as!(
+            ClanChatRoomRow,
+            r#"
+            SELECT clan_id, thread_id, announcements_thread_id, archived_at
+            FROM clan_chat_rooms WHERE clan_id = $1
+            "#,
+            clan_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(room)
+    }
+
+    /// The user's role in a thread, `None` if they are not in it
+    pub async fn member_role(pool: &PgPool, thread_id: i64, user_id: i64) -> Result<Option<String>> {
+        let role = sqlx::query_scalar!(
+            "SELECT role FROM chat_thread_members WHERE thread_id = $1 AND user_id = $2",
+            thread_id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(role)
+    }
+
+    /// Lock a thread so pins are counted and added one at a time
+    pub async fn lock_thread(conn: &mut PgConnection, thread_id: i64) -> Result<()> {
+        sqlx::query!("SELECT id FROM chat_threads WHERE id = $1 FOR UPDATE", thread_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn pin_count(conn: &mut PgConnection, thread_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM chat_thread_pins WHERE thread_id = $1"#,
+            thread_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Pin a message of the thread; false if there is no such message.
+    /// Pinning it again keeps the original pin.
+    pub async fn pin(conn: &mut PgConnection, thread_id: i64, message_id: i64, user_id: i64) -> Result<bool> {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM chat_thread_messages WHERE id = $1 AND thread_id = $2) AS "exists!""#,
+            message_id,
+            thread_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        if !exists {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO chat_thread_pins (message_id, thread_id, pinned_by)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (message_id) DO NOTHING
+            "#,
+            message_id,
+            thread_id,
+            user_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(true)
+    }
+
+    /// False if the message was not pinned in the thread
+    pub async fn unpin(pool: &PgPool, thread_id: i64, message_id: i64) -> Result<bool> {
+        let unpinned = sqlx::query!(
+            "DELETE FROM chat_thread_pins WHERE message_id = $1 AND thread_id = $2",
+            message_id,
+            thread_id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected() > 0;
+
+        Ok(unpinned)
+    }
+
+    /// The thread's pinned messages, latest pin first
+    pub async fn pins(pool: &PgPool, thread_id: i64) -> Result<Vec<PinnedMessageRow>> {
+        let pins = sqlx::query_as!(
+            PinnedMessageRow,
+            r#"
+            SELECT m.id AS message_id, m.user_id, m.body, m.created_at, p.pinned_by, p.pinned_at
+            FROM chat_thread_pins p
+            JOIN chat_thread_messages m ON m.id = p.message_id
+            WHERE p.thread_id = $1
+            ORDER BY p.pinned_at DESC
+            "#,
+            thread_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(pins)
+    }
+
+    /// Delete up to `limit` unpinned clan chat messages from before `cutoff`;
+    /// returns how many were deleted
+    pub async fn prune(pool: &PgPool, cutoff: DateTime<Utc>, limit: i64) -> Result<u64> {
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM chat_thread_messages
+            WHERE id IN (
+                SELECT m.id FROM chat_thread_messages m
+                JOIN clan_chat_rooms r ON m.thread_id IN (r.thread_id, r.announcements_thread_id)
+                WHERE m.created_at < $1
+                  AND NOT EXISTS (SELECT 1 FROM chat_thread_pins p WHERE p.message_id = m.id)
+                ORDER BY m.created_at
+                LIMIT $2
+            )
+            "#,
+            cutoff,
+            limit
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        Ok(deleted)
+    }
+}
+
+/// One username change
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UsernameChangeRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub old_login: String,
+    pub new_login: String,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Username changes, their history and the names they left reserved
+pub struct UsernameQueries;
+
+impl UsernameQueries {
+    /// Lock the player's row for a change; returns their current name
+    pub async fn lock_user(conn: &mut PgConnection, user_id: i64) -> Result<Option<String>> {
+        let login = sqlx::query_scalar!("SELECT login FROM users WHERE id = $1 FOR UPDATE", user_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(login)
+    }
+
+    /// Serialize changes to one name, whatever its case, until commit
+    pub async fn lock_name(conn: &mut PgConnection, name: &str) -> Result<()> {
+        sqlx::query!("SELECT pg_advisory_xact_lock(hashtext('username:' || lower($1)))", name)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn last_change(conn: &mut PgConnection, user_id: i64) -> Result<Option<DateTime<Utc>>> {
+        let changed_at = sqlx::query_scalar!("SELECT MAX(changed_at) FROM username_history WHERE user_id = $1", user_id)
+            .fetch_one(conn)
+            .await?;
+
+        Ok(changed_at)
+    }
+
+    /// Whether another player has the name in any case
+    pub async fn taken(conn: &mut PgConnection, name: &str, user_id: i64) -> Result<bool> {
+        let taken = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM users WHERE lower(login) = lower($1) AND id <> $2) AS "taken!""#,
+            name,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(taken)
+    }
+
+    /// Until when the name is reserved for another player, if it is
+    pub async fn reserved_for_other(conn: &mut PgConnection, name: &str, user_id: i64) -> Result<Option<DateTime<Utc>>> {
+        let until = sqlx::query_scalar!(
+            r#"
+            SELECT reserved_until FROM reserved_usernames
+            WHERE name_key = lower($1) AND user_id <> $2 AND reserved_until > NOW()
+            "#,
+            name,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(until)
+    }
+
+    /// Mark the transaction's writes as the forum's, so the forum sync
+    /// triggers do not queue them back
+    pub async fn mark_forum_origin(conn: &mut PgConnection) -> Result<()> {
+        sqlx::query!("SELECT set_config('he.sync_origin', 'forum', true)")
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Rename the player, record the change and reserve the old name for
+    /// them until `reserved_until`. Taking back one's own reserved name
+    /// releases its reservation.
+    pub async fn rename(
+        conn: &mut PgConnection,
+        user_id: i64,
+        old_login: &str,
+        new_login: &str,
+        reserved_until: DateTime<Utc>,
+    ) -> Result<UsernameChangeRow> {
+        sqlx::query!("UPDATE users SET login = $2 WHERE id = $1", user_id, new_login)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!("DELETE FROM reserved_usernames WHERE name_key = lower($1)", new_login)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO reserved_usernames (name_key, user_id, reserved_until) VALUES (lower($1), $2, $3)
+            ON CONFLICT (name_key) DO UPDATE SET user_id = EXCLUDED.user_id, reserved_until = EXCLUDED.reserved_until
+            "#,
+            old_login,
+            user_id,
+            reserved_until
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let change = sqlx::query_as!(
+            UsernameChangeRow,
+            r#"
+            INSERT INTO username_history (user_id, old_login, new_login) VALUES ($1, $2, $3)
+            RETURNING id, user_id, old_login, new_login, changed_at
+            "#,
+            user_id,
+            old_login,
+            new_login
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(change)
+    }
+
+    /// A player's changes, newest first
+    pub async fn history(pool: &PgPool, user_id: i64) -> Result<Vec<UsernameChangeRow>> {
+        let changes = sqlx::query_as!(
+            UsernameChangeRow,
+            r#"
+            SELECT id, user_id, old_login, new_login, changed_at FROM username_history
+            WHERE user_id = $1 ORDER BY changed_at DESC, id DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(changes)
+    }
+
+    /// Every change away from `name`, whatever its case, newest first
+    pub async fn former_holders(pool: &PgPool, name: &str) -> Result<Vec<UsernameChangeRow>> {
+        let changes = sqlx::query_as!(
+            UsernameChangeRow,
+            r#"
+            SELECT id, user_id, old_login, new_login, changed_at FROM username_history
+            WHERE lower(old_login) = lower($1) ORDER BY changed_at DESC, id DESC
+            "#,
+            name
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(changes)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BountyPlacementState {
+    pub target_exists: bool,
+    pub open_placed: i64,
+    pub balance: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BountyRow {
+    pub id: i64,
+    pub placer_id: i64,
+    pub target_id: i64,
+    pub reward: i64,
+    pub status: String,
+    pub expires_at: DateTime<Utc>,
+    pub claimed_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Open bounties on one target, summed for the board
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BountyBoardEntry {
+    pub target_id: i64,
+    pub target_login: String,
+    pub total_reward: i64,
+    pub bounties: i64,
+    /// When the soonest of them closes
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A hunter's ties to a bounty's placer and target
+#[derive(Debug, Clone)]
+pub struct BountyClaimSignals {
+    pub hunter_is_placer: bool,
+    pub clanmate_of_placer: bool,
+    pub clanmate_of_target: bool,
+    pub shares_ip_with_placer: bool,
+    pub shares_ip_with_target: bool,
+    pub hunter_account_age_hours: i64,
+    pub transfers_with_placer: i64,
+}
+
+pub struct BountyQueries;
+
+impl BountyQueries {
+    pub async fn placement_state(pool: &PgPool, placer_id: i64, target_id: i64) -> Result<BountyPlacementState> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                EXISTS (SELECT 1 FROM users WHERE id = $2) AS "target_exists!",
+                (SELECT COUNT(*) FROM bounties WHERE placer_id = $1 AND status = 'open') AS "open_placed!",
+                COALESCE((
+                    SELECT balance FROM bank_accounts
+                    WHERE user_id = $1 AND is_active = TRUE
+                    ORDER BY id
+                    LIMIT 1
+                ), 0) AS "balance!"
+            "#,
+            placer_id,
+            target_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(BountyPlacementState {
+            target_exists: row.target_exists,
+            open_placed: row.open_placed,
+            balance: row.balance,
+        })
+    }
+
+    /// Take `reward` into escrow and open the bounty, in the caller's
+    /// transaction. `None` if the placer reached `max_open` or cannot pay, and
+    /// the caller should roll back; the placer row is locked so two placements
+    /// cannot both pass.
+    pub async fn place(
+        conn: &mut PgConnection,
+        placer_id: i64,
+        target_id: i64,
+        reward: i64,
+        window_hours: i64,
+        max_open: i64,
+    ) -> Result<Option<BountyRow>> {
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", placer_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+        let open = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "open!" FROM bounties WHERE placer_id = $1 AND status = 'open'"#,
+            placer_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        if open >= max_open {
+            return Ok(None);
+        }
+
+        let bounty = sqlx::query_as!(
+            BountyRow,
+            r#"
+            INSERT INTO bounties (placer_id, target_id, reward, expires_at)
+            VALUES ($1, $2, $3, NOW() + make_interval(hours => $4))
+            RETURNING id, placer_id, target_id, reward, status, expires_at, claimed_by, created_at
+            "#,
+            placer_id,
+            target_id,
+            reward,
+            window_hours as i32
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let reference = format!("bounty:{}", bounty.id);
+        let escrowed = BankQueries::debit_primary_account(
+            &mut *conn,
+            placer_id,
+            reward,
+            LedgerAccount::BountyEscrow,
+            LedgerReason::BountyEscrow,
+            &reference,
+        )
+        .await?;
+
+        Ok(escrowed.then_some(bounty))
+    }
+
+    /// Open bounties on whoever owns the gateway at `ip`, locked for a claim.
+    /// Bounties another transaction is settling are skipped.
+    pub async fn open_on_ip(conn: &mut PgConnection, ip: &str) -> Result<Vec<BountyRow>> {
+        let bounties = sqlx::query_as!(
+            BountyRow,
+            r#"
+            SELECT b.id, b.placer_id, b.target_id, b.reward, b.status, b.expires_at, b.claimed_by, b.created_at
+            FROM bounties b
+            JOIN servers s ON s.user_id = b.target_id AND s.is_npc = FALSE
+            WHERE host(s.ip_address) = $1 AND b.status = 'open' AND b.expires_at > NOW()
+            ORDER BY b.id
+            FOR UPDATE OF b SKIP LOCKED
+            "#,
+            ip
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(bounties)
+    }
+
+    /// What ties `hunter_id` to the bounty's placer and target. Transfers
+    /// count in either direction within the last `lookback_days`.
+    pub async fn claim_signals(
+        conn: &mut PgConnection,
+        bounty_id: i64,
+        hunter_id: i64,
+        lookback_days: i64,
+    ) -> Result<BountyClaimSignals> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                b.placer_id = $2 AS "hunter_is_placer!",
+                EXISTS (
+                    SELECT 1 FROM clan_members h JOIN clan_members o ON o.clan_id = h.clan_id
+                    WHERE h.user_id = $2 AND o.user_id = b.placer_id
+                ) AS "clanmate_of_placer!",
+                EXISTS (
+                    SELECT 1 FROM clan_members h JOIN clan_members o ON o.clan_id = h.clan_id
+                    WHERE h.user_id = $2 AND o.user_id = b.target_id
+                ) AS "clanmate_of_target!",
+                EXISTS (
+                    SELECT 1 FROM users h JOIN users o ON o.real_ip = h.real_ip
+                    WHERE h.id = $2 AND o.id = b.placer_id
+                ) AS "shares_ip_with_placer!",
+                EXISTS (
+                    SELECT 1 FROM users h JOIN users o ON o.real_ip = h.real_ip
+                    WHERE h.id = $2 AND o.id = b.target_id
+                ) AS "shares_ip_with_target!",
+                COALESCE((
+                    SELECT (EXTRACT(EPOCH FROM NOW() - created_at) / 3600)::BIGINT
+                    FROM users WHERE id = $2
+                ), 0) AS "hunter_account_age_hours!",
+                (
+                    SELECT COUNT(*) FROM bank_transactions t
+                    WHERE t.created_at > NOW() - make_interval(days => $3)
+                      AND (
+                        (t.from_user_id = b.placer_id
+                            AND t.to_account IN (SELECT account_number FROM bank_accounts WHERE user_id = $2))
+                        OR (t.from_user_id = $2
+                            AND t.to_account IN (SELECT account_number FROM bank_accounts WHERE user_id = b.placer_id))
+                      )
+                ) AS "transfers_with_placer!"
+            FROM bounties b
+            WHERE b.id = $1
+            "#,
+            bounty_id,
+            hunter_id,
+            lookback_days as i32
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(BountyClaimSignals {
+            hunter_is_placer: row.hunter_is_placer,
+            clanmate_of_placer: row.clanmate_of_placer,
+            clanmate_of_target: row.clanmate_of_target,
+            shares_ip_with_placer: row.shares_ip_with_placer,
+            shares_ip_with_target: row.shares_ip_with_target,
+            hunter_account_age_hours: row.hunter_account_age_hours,
+            transfers_with_placer: row.transfers_with_placer,
+        })
+    }
+
+    /// Close a locked open bounty as claimed by `hunter_id`. Paying the
+    /// hunter is up to the caller, in the same transaction.
+    pub async fn mark_claimed(conn: &mut PgConnection, bounty_id: i64, hunter_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE bounties SET status = 'claimed', claimed_by = $2, settled_at = NOW()
+            WHERE id = $1 AND status = 'open'
+            "#,
+            bounty_id,
+            hunter_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Keep a refused claim for moderators
+    pub async fn record_blocked(conn: &mut PgConnection, bounty_id: i64, hunter_id: i64, reason: &str) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO bounty_blocked_claims (bounty_id, hunter_id, reason) VALUES ($1, $2, $3)",
+            bounty_id,
+            hunter_id,
+            reason
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Expire up to `limit` bounties whose window closed and refund their
+    /// placers, in the caller's transaction. Returns the bounties settled and
+    /// whether each was refunded. A placer without a bank account keeps the
+    /// reward held in escrow: the bounty is closed but left unsettled, and a
+    /// later sweep refunds it once they open an account.
+    pub async fn expire_due(conn: &mut PgConnection, limit: i64) -> Result<Vec<(BountyRow, bool)>> {
+        let due = sqlx::query_as!(
+            BountyRow,
+            r#"
+            SELECT id, placer_id, target_id, reward, status, expires_at, claimed_by, created_at
+            FROM bounties b
+            WHERE (b.status = 'open' AND b.expires_at <= NOW())
+               OR (b.status = 'expired' AND b.settled_at IS NULL AND EXISTS (
+                    SELECT 1 FROM bank_accounts a WHERE a.user_id = b.placer_id AND a.is_active = TRUE
+               ))
+            ORDER BY b.expires_at
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut settled = Vec::with_capacity(due.len());
+        for bounty in due {
+            let refunded = BankQueries::credit_primary_account(
+                &mut *conn,
+                bounty.placer_id,
+                bounty.reward,
+                LedgerAccount::BountyEscrow,
+                LedgerReason::BountyRefund,
+                &format!("bounty:{}", bounty.id),
+            )
+            .await?;
+
+            let bounty = sqlx::query_as!(
+                BountyRow,
+                r#"
+                UPDATE bounties
+                SET status = 'expired', settled_at = CASE WHEN $2 THEN NOW() END
+                WHERE id = $1
+                RETURNING id, placer_id, target_id, reward, status, expires_at, claimed_by, created_at
+                "#,
+                bounty.id,
+                refunded
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+            settled.push((bounty, refunded));
+        }
+
+        Ok(settled)
+    }
+
+    /// Open bounties by target, largest total first
+    pub async fn board(pool: &PgPool, limit: i64) -> Result<Vec<BountyBoardEntry>> {
+        let entries = sqlx::query_as!(
+            BountyBoardEntry,
+            r#"
+            SELECT
+                b.target_id,
+                u.login AS target_login,
+                SUM(b.reward)::BIGINT AS "total_reward!",
+                COUNT(*) AS "bounties!",
+                MIN(b.expires_at) AS "expires_at!"
+            FROM bounties b
+            JOIN users u ON u.id = b.target_id
+            WHERE b.status = 'open' AND b.expires_at > NOW()
+            GROUP BY b.target_id, u.login
+            ORDER BY SUM(b.reward) DESC
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(entries)
+    }
+
+    /// Bounties the player placed or claimed, newest first
+    pub async fn for_user(pool: &PgPool, user_id: i64, limit: i64) -> Result<Vec<BountyRow>> {
+        let bounties = sqlx::query_as!(
+            BountyRow,
+            r#"
+            SELECT id, placer_id, target_id, reward, status, expires_at, claimed_by, created_at
+            FROM bounties
+            WHERE placer_id = $1 OR claimed_by = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(bounties)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TutorialRow {
+    pub user_id: i64,
+    pub step: String,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Set once the completion reward was paid; survives resets
+    pub rewarded_at: Option<DateTime<Utc>>,
+}
+
+pub struct TutorialQueries;
+
+impl TutorialQueries {
+    /// Put a new player on the first step
+    pub async fn start(pool: &PgPool, user_id: i64) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO player_tutorials (user_id) VALUES ($1) ON CONFLICT (user_id) DO NOTHING",
+            user_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &PgPool, user_id: i64) -> Result<Option<TutorialRow>> {
+        let row = sqlx::query_as!(
+            TutorialRow,
+            r#"
+            SELECT user_id, step, started_at, completed_at, rewarded_at
+            FROM player_tutorials
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Lock the player's tutorial if it is still in progress
+    pub async fn lock_active(conn: &mut PgConnection, user_id: i64) -> Result<Option<TutorialRow>> {
+        let row = sqlx::query_as!(
+            TutorialRow,
+            r#"
+            SELECT user_id, step, started_at, completed_at, rewarded_at
+            FROM player_tutorials
+            WHERE user_id = $1 AND step NOT IN ('completed', 'skipped')
+            FOR UPDATE
+            "#,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn set_step(conn: &mut PgConnection, user_id: i64, step: &str) -> Result<()> {
+        sqlx::query!(
+            "UPDATE player_tutorials SET step = $2, updated_at = NOW() WHERE user_id = $1",
+            user_id,
+            step
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Finish the tutorial, recording the reward as paid when `rewarded`
+    pub async fn complete(conn: &mut PgConnection, user_id: i64, rewarded: bool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE player_tutorials
+            SET step = 'completed',
+                completed_at = NOW(),
+                updated_at = NOW(),
+                rewarded_at = CASE WHEN $2 THEN COALESCE(rewarded_at, NOW()) ELSE rewarded_at END
+            WHERE user_id = $1
+            "#,
+            user_id,
+            rewarded
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Skip the tutorial. `None` if it was already finished or never started.
+    pub async fn skip(pool: &PgPool, user_id: i64) -> Result<Option<TutorialRow>> {
+        let row = sqlx::query_as!(
+            TutorialRow,
+            r#"
+            UPDATE player_tutorials
+            SET step = 'skipped', updated_at = NOW()
+            WHERE user_id = $1 AND step NOT IN ('completed', 'skipped')
+            RETURNING user_id, step, started_at, completed_at, rewarded_at
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Put the player back on the first step, starting a tutorial for players
+    /// who predate it. The reward marker is kept, so a replay pays nothing.
+    pub async fn reset(pool: &PgPool, user_id: i64) -> Result<TutorialRow> {
+        let row = sqlx::query_as!(
+            TutorialRow,
+            r#"
+            INSERT INTO player_tutorials (user_id) VALUES ($1)
+            ON CONFLICT (user_id) DO UPDATE
+            SET step = 'scan_whois', started_at = NOW(), updated_at = NOW(), completed_at = NULL
+            RETURNING user_id, step, started_at, completed_at, rewarded_at
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(row)
+    }
+}
+
+/// A notification waiting in the outbox
+#[derive(Debug, Clone)]
+pub struct OutboxRow {
+    pub id: i64,
+    pub dedup_key: String,
+    pub channel: String,
+    pub recipient: Option<i64>,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+}
+
+pub struct OutboxQueries;
+
+impl OutboxQueries {
+    /// Queue a notification in the caller's transaction. A key already in
+    /// the outbox is ignored, so retried transactions queue it once.
+    pub async fn enqueue(
+        conn: &mut PgConnection,
+        dedup_key: &str,
+        channel: &str,
+        recipient: Option<i64>,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO notification_outbox (dedup_key, channel, recipient, payload)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (dedup_key) DO NOTHING
+            "#,
+            dedup_key,
+            channel,
+            recipient,
+            payload
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Lease up to `limit` due notifications, oldest first. A leased row is
+    /// not due again for `lease_secs`, so a relay that dies mid-batch has its
+    /// rows picked up by another once the lease runs out.
+    pub async fn lease_due(pool: &PgPool, limit: i64, lease_secs: i64) -> Result<Vec<OutboxRow>> {
+        let rows = sqlx::query_as!(
+            OutboxRow,
+            r#"
+            UPDATE notification_outbox
+            SET attempts = attempts + 1, next_attempt_at = NOW() + make_interval(secs => $2)
+            WHERE id IN (
+                SELECT id FROM notification_outbox
+                WHERE sent_at IS NULL AND failed_at IS NULL AND next_attempt_at <= NOW()
+                ORDER BY id
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, dedup_key, channel, recipient, payload, attempts
+            "#,
+            limit,
+            lease_secs as f64
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn mark_sent(pool: &PgPool, ids: &[i64]) -> Result<()> {
+        sqlx::query!(
+            "UPDATE notification_outbox SET sent_at = NOW() WHERE id = ANY($1)",
+            ids
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Give up on a notification that keeps failing
+    pub async fn mark_failed(pool: &PgPool, id: i64, error: &str) -> Result<()> {
+        sqlx::query!(
+            "UPDATE notification_outbox SET failed_at = NOW(), last_error = $2 WHERE id = $1",
+            id,
+            error
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete notifications sent more than `keep_hours` ago; their keys can
+    /// no longer be redelivered
+    pub async fn prune_sent(pool: &PgPool, keep_hours: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM notification_outbox WHERE sent_at < NOW() - make_interval(hours => $1)",
+            keep_hours as i32
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ContractRow {
+    pub id: i64,
+    pub poster_id: i64,
+    pub kind: String,
+    pub target_ip: String,
+    pub target_owner_id: i64,
+    pub target_file_id: String,
+    pub file_name: String,
+    pub description: Option<String>,
+    pub reward: i64,
+    pub fee: i64,
+    pub status: String,
+    pub expires_at: DateTime<Utc>,
+    pub contractor_id: Option<i64>,
+    pub work_deadline: Option<DateTime<Utc>>,
+    pub fulfilled_at: Option<DateTime<Utc>>,
+    pub proof_pid: Option<i64>,
+    pub dispute_until: Option<DateTime<Utc>>,
+    pub dispute_reason: Option<String>,
+    pub resolution_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContractPostingState {
+    pub level: i32,
+    pub open_posted: i64,
+    pub balance: i64,
+}
+
+/// A file a contract can name: on a player's server, with its owner
+#[derive(Debug, Clone)]
+pub struct ContractTarget {
+    pub owner_id: i64,
+    pub file_name: String,
+}
+
+/// A new contract, before escrow is taken
+#[derive(Debug, Clone)]
+pub struct NewContract<'a> {
+    pub poster_id: i64,
+    pub kind: &'a str,
+    pub target_ip: &'a str,
+    pub target_owner_id: i64,
+    pub target_file_id: &'a str,
+    pub file_name: &'a str,
+    pub description: Option<&'a str>,
+    pub reward: i64,
+    pub fee: i64,
+    pub window_hours: i64,
+}
+
+pub struct ContractQueries;
+
+impl ContractQueries {
+    pub async fn posting_state(pool: &PgPool, poster_id: i64) -> Result<ContractPostingState> {
+        let player_id = legacy_user_uuid(poster_id);
+
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                COALESCE((SELECT level FROM player_progression WHERE player_id = $2), 1) AS "level!",
+                (
+                    SELECT COUNT(*) FROM player_contracts
+                    WHERE poster_id = $1 AND status IN ('open', 'accepted')
+                ) AS "open_posted!",
+                COALESCE((
+                    SELECT balance FROM bank_accounts
+                    WHERE user_id = $1 AND is_active = TRUE
+                    ORDER BY id
+                    LIMIT 1
+                ), 0) AS "balance!"
+            "#,
+            poster_id,
+            player_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(ContractPostingState {
+            level: row.level,
+            open_posted: row.open_posted,
+            balance: row.balance,
+        })
+    }
+
+    /// The file `file_id` on the player server at `ip`. NPC servers cannot be
+    /// named; their files are missions' business.
+    pub async fn target(pool: &PgPool, ip: &str, file_id: &str) -> Result<Option<ContractTarget>> {
+        let target = sqlx::query_as!(
+            ContractTarget,
+            r#"
+            SELECT s.user_id AS "owner_id!", f.name AS file_name
+            FROM software f
+            JOIN servers s ON s.id = f.server_id
+            WHERE host(s.ip_address) = $1 AND f.id::TEXT = $2 AND s.is_npc = FALSE AND s.user_id IS NOT NULL
+            "#,
+            ip,
+            file_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(target)
+    }
+
+    /// Take the reward and fee and put the contract up, in the caller's
+    /// transaction. `None` if the poster reached `max_open` or cannot pay, and
+    /// the caller should roll back; the poster row is locked so two posts
+    /// cannot both pass.
+    pub async fn post(conn: &mut PgConnection, contract: &NewContract<'_>, max_open: i64) -> Result<Option<ContractRow>> {
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", contract.poster_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+        let open = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "open!" FROM player_contracts
+            WHERE poster_id = $1 AND status IN ('open', 'accepted')
+            "#,
+            contract.poster_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        if open >= max_open {
+            return Ok(None);
+        }
+
+        let row = sqlx::query_as!(
+            ContractRow,
+            r#"
+            INSERT INTO player_contracts (
+                poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, NOW() + make_interval(hours => $10))
+            RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            "#,
+            contract.poster_id,
+            contract.kind,
+            contract.target_ip,
+            contract.target_owner_id,
+            contract.target_file_id,
+            contract.file_name,
+            contract.description,
+            contract.reward,
+            contract.fee,
+            contract.window_hours as i32
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let Some(account_id) = BankQueries::primary_account(&mut *conn, contract.poster_id).await? else {
+            return Ok(None);
+        };
+        let legs = [
+            (LedgerAccount::Bank(account_id), -(contract.reward + contract.fee)),
+            (LedgerAccount::ContractEscrow, contract.reward),
+            (LedgerAccount::Sink, contract.fee),
+        ];
+        let reference = format!("contract:{}", row.id);
+        let escrowed = LedgerQueries::post(&mut *conn, LedgerReason::ContractEscrow, &reference, &legs).await?;
+
+        Ok(escrowed.then_some(row))
+    }
+
+    pub async fn lock(conn: &mut PgConnection, contract_id: i64) -> Result<Option<ContractRow>> {
+        let row = sqlx::query_as!(
+            ContractRow,
+            r#"
+            SELECT id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            FROM player_contracts
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            contract_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn accepted_count(conn: &mut PgConnection, contractor_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM player_contracts WHERE contractor_id = $1 AND status = 'accepted'"#,
+            contractor_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Give a locked open contract to `contractor_id` for `work_hours`, or
+    /// until it expires if that is sooner
+    pub async fn accept(conn: &mut PgConnection, contract_id: i64, contractor_id: i64, work_hours: i64) -> Result<ContractRow> {
+        let row = sqlx::query_as!(
+            ContractRow,
+            r#"
+            UPDATE player_contracts
+            SET status = 'accepted', contractor_id = $2, accepted_at = NOW(),
+                work_deadline = LEAST(expires_at, NOW() + make_interval(hours => $3))
+            WHERE id = $1 AND status = 'open'
+            RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            "#,
+            contract_id,
+            contractor_id,
+            work_hours as i32
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Take down an open contract and refund its reward; the fee is kept.
+    /// `None` unless the poster owns it and nobody has accepted it. The bool
+    /// is false when the poster has no bank account and the refund is held.
+    pub async fn cancel(conn: &mut PgConnection, contract_id: i64, poster_id: i64) -> Result<Option<(ContractRow, bool)>> {
+        let row = sqlx::query_as!(
+            ContractRow,
+            r#"
+            UPDATE player_contracts SET status = 'cancelled'
+            WHERE id = $1 AND poster_id = $2 AND status = 'open'
+            RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            "#,
+            contract_id,
+            poster_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let refunded = Self::settle(&mut *conn, &row, row.poster_id, LedgerReason::ContractRefund).await?;
+
+        Ok(Some((row, refunded)))
+    }
+
+    /// Mark every contract `contractor_id` holds on the file fulfilled by
+    /// process `pid`, in the completion transaction. The reward stays in
+    /// escrow until `dispute_hours` pass.
+    pub async fn fulfil(
+        conn: &mut PgConnection,
+        contractor_id: i64,
+        kind: &str,
+        target_ip: &str,
+        target_file_id: &str,
+        pid: i64,
+        dispute_hours: i64,
+    ) -> Result<Vec<ContractRow>> {
+        let rows = sqlx::query_as!(
+            ContractRow,
+            r#"
+            UPDATE player_contracts
+            SET status = 'fulfilled', fulfilled_at = NOW(), proof_pid = $5,
+                dispute_until = NOW() + make_interval(hours => $6)
+            WHERE contractor_id = $1 AND kind = $2 AND target_ip = $3 AND target_file_id = $4
+              AND status = 'accepted' AND work_deadline > NOW()
+            RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            "#,
+            contractor_id,
+            kind,
+            target_ip,
+            target_file_id,
+            pid,
+            dispute_hours as i32
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Hold a locked fulfilled contract for a moderator
+    pub async fn dispute(conn: &mut PgConnection, contract_id: i64, reason: &str) -> Result<ContractRow> {
+        let row = sqlx::query_as!(
+            ContractRow,
+            r#"
+            UPDATE player_contracts SET status = 'disputed', dispute_reason = $2, disputed_at = NOW()
+            WHERE id = $1 AND status = 'fulfilled'
+            RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            "#,
+            contract_id,
+            reason
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Settle a disputed contract: pay the contractor or refund the poster.
+    /// `None` if it is not disputed. The bool is false when the payee had no
+    /// bank account; the reward stays in escrow until [`Self::settle_held`]
+    /// pays it.
+    pub async fn resolve(
+        conn: &mut PgConnection,
+        contract_id: i64,
+        moderator_id: i64,
+        pay_contractor: bool,
+        note: &str,
+    ) -> Result<Option<(ContractRow, bool)>> {
+        let status = if pay_contractor { "paid" } else { "refunded" };
+        let row = sqlx::query_as!(
+            ContractRow,
+            r#"
+            UPDATE player_contracts
+            SET status = $2, resolved_by = $3, resolution_note = $4
+            WHERE id = $1 AND status = 'disputed'
+            RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            "#,
+            contract_id,
+            status,
+            moderator_id,
+            note
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let payee = match (pay_contractor, row.contractor_id) {
+            (true, Some(contractor_id)) => contractor_id,
+            _ => row.poster_id,
+        };
+        let reason = if payee == row.poster_id { LedgerReason::ContractRefund } else { LedgerReason::ContractPayout };
+        let credited = Self::settle(&mut *conn, &row, payee, reason).await?;
+
+        Ok(Some((row, credited)))
+    }
+
+    /// Pay up to `limit` fulfilled contracts whose dispute window closed, in
+    /// the caller's transaction. The bool is false when the contractor has no
+    /// bank account and the payout is held.
+    pub async fn pay_due(conn: &mut PgConnection, limit: i64) -> Result<Vec<(ContractRow, bool)>> {
+        let rows = sqlx::query_as!(
+            ContractRow,
+            r#"
+            UPDATE player_contracts SET status = 'paid'
+            WHERE id IN (
+                SELECT id FROM player_contracts
+                WHERE status = 'fulfilled' AND dispute_until <= NOW()
+                ORDER BY dispute_until
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            "#,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut settled = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Some(contractor_id) = row.contractor_id else {
+                settled.push((row, false));
+                continue;
+            };
+            let paid = Self::settle(&mut *conn, &row, contractor_id, LedgerReason::ContractPayout).await?;
+            settled.push((row, paid));
+        }
+
+        Ok(settled)
+    }
+
+    /// Put accepted contracts whose contractor ran out of time back on the
+    /// board. Returns each contract with the contractor who lost it.
+    pub async fn reopen_lapsed(conn: &mut PgConnection, limit: i64) -> Result<Vec<(ContractRow, i64)>> {
+        let lapsed = sqlx::query!(
+            r#"
+            SELECT id, contractor_id AS "contractor_id!" FROM player_contracts
+            WHERE status = 'accepted' AND work_deadline <= NOW() AND expires_at > NOW()
+            ORDER BY work_deadline
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut reopened = Vec::with_capacity(lapsed.len());
+        for lapsed in lapsed {
+            let row = sqlx::query_as!(
+                ContractRow,
+                r#"
+                UPDATE player_contracts
+                SET status = 'open', contractor_id = NULL, accepted_at = NULL, work_deadline = NULL
+                WHERE id = $1
+                RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                    reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                    dispute_until, dispute_reason, resolution_note, created_at
+                "#,
+                lapsed.id
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+            reopened.push((row, lapsed.contractor_id));
+        }
+
+        Ok(reopened)
+    }
+
+    /// Expire up to `limit` open or accepted contracts past their window and
+    /// refund the reward to their posters; the fee is kept. The bool is false
+    /// when the poster has no bank account and the refund is held.
+    pub async fn expire_due(conn: &mut PgConnection, limit: i64) -> Result<Vec<(ContractRow, bool)>> {
+        let rows = sqlx::query_as!(
+            ContractRow,
+            r#"
+            UPDATE player_contracts SET status = 'expired'
+            WHERE id IN (
+                SELECT id FROM player_contracts
+                WHERE status IN ('open', 'accepted') AND expires_at <= NOW()
+                ORDER BY expires_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            "#,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut settled = Vec::with_capacity(rows.len());
+        for row in rows {
+            let refunded = Self::settle(&mut *conn, &row, row.poster_id, LedgerReason::ContractRefund).await?;
+            settled.push((row, refunded));
+        }
+
+        Ok(settled)
+    }
+
+    /// Credit a closed contract's reward from escrow to `payee` and mark it
+    /// settled. False, leaving it unsettled, if they have no bank account.
+    async fn settle(conn: &mut PgConnection, row: &ContractRow, payee: i64, reason: LedgerReason) -> Result<bool> {
+        let credited = BankQueries::credit_primary_account(
+            &mut *conn,
+            payee,
+            row.reward,
+            LedgerAccount::ContractEscrow,
+            reason,
+            &format!("contract:{}", row.id),
+        )
+        .await?;
+        if credited {
+            sqlx::query!("UPDATE player_contracts SET settled_at = NOW() WHERE id = $1", row.id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(credited)
+    }
+
+    /// Pay up to `limit` closed contracts whose payout or refund was held
+    /// because the payee had no bank account and who now has one. Returns
+    /// each contract and whether it went to the contractor.
+    pub async fn settle_held(conn: &mut PgConnection, limit: i64) -> Result<Vec<(ContractRow, bool)>> {
+        let rows = sqlx::query_as!(
+            ContractRow,
+            r#"
+            SELECT id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            FROM player_contracts c
+            WHERE c.status IN ('paid', 'refunded', 'expired', 'cancelled') AND c.settled_at IS NULL
+              AND EXISTS (
+                SELECT 1 FROM bank_accounts a
+                WHERE a.is_active = TRUE
+                  AND a.user_id = CASE WHEN c.status = 'paid' THEN c.contractor_id ELSE c.poster_id END
+              )
+            ORDER BY c.id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut settled = Vec::with_capacity(rows.len());
+        for row in rows {
+            let (payee, reason) = match (row.status.as_str(), row.contractor_id) {
+                ("paid", Some(contractor_id)) => (contractor_id, LedgerReason::ContractPayout),
+                _ => (row.poster_id, LedgerReason::ContractRefund),
+            };
+            if Self::settle(&mut *conn, &row, payee, reason).await? {
+                let to_contractor = payee != row.poster_id;
+                settled.push((row, to_contractor));
+            }
+        }
+
+        Ok(settled)
+    }
+
+    /// Open contracts, largest reward first
+    pub async fn board(pool: &PgPool, limit: i64) -> Result<Vec<ContractRow>> {
+        let rows = sqlx::query_as!(
+            ContractRow,
+            r#"
+            SELECT id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            FROM player_contracts
+            WHERE status = 'open' AND expires_at > NOW()
+            ORDER BY reward DESC
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Contracts the player posted or took, newest first
+    pub async fn for_user(pool: &PgPool, user_id: i64, limit: i64) -> Result<Vec<ContractRow>> {
+        let rows = sqlx::query_as!(
+            ContractRow,
+            r#"
+            SELECT id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            FROM player_contracts
+            WHERE poster_id = $1 OR contractor_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// The moderators' queue, oldest dispute first
+    pub async fn disputes(pool: &PgPool, limit: i64) -> Result<Vec<ContractRow>> {
+        let rows = sqlx::query_as!(
+            ContractRow,
+            r#"
+            SELECT id, poster_id, kind, target_ip, target_owner_id, target_file_id, file_name, description,
+                reward, fee, status, expires_at, contractor_id, work_deadline, fulfilled_at, proof_pid,
+                dispute_until, dispute_reason, resolution_note, created_at
+            FROM player_contracts
+            WHERE status = 'disputed'
+            ORDER BY disputed_at
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+/// A gateway region as discovery sees it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GatewayRegionRow {
+    pub region: String,
+    /// `None` if no live node in the region advertises a URL
+    pub ws_url: Option<String>,
+    pub healthy_nodes: i64,
+    pub draining_nodes: i64,
+    pub sessions: i64,
+}
+
+/// A node a player has sessions on
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresencePeer {
+    pub node_id: String,
+    pub region: String,
+}
+
+/// A player's presence across the gateway nodes
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PlayerPresenceRow {
+    pub online: bool,
+    /// `None` once a player has been gone long enough to be pruned
+    pub last_seen_at: Option<DateTime<Utc>>,
+}
+
+pub struct GatewayQueries;
+
+impl GatewayQueries {
+    /// Regions with a node seen in the last `live_secs`. Draining nodes
+    /// count towards the region but do not give it its URL.
+    pub async fn regions(pool: &PgPool, live_secs: i64) -> Result<Vec<GatewayRegionRow>> {
+        let regions = sqlx::query_as!(
+            GatewayRegionRow,
+            r#"
+            SELECT
+                n.region AS "region!",
+                MIN(n.ws_url) FILTER (WHERE NOT n.draining) AS ws_url,
+                COUNT(*) FILTER (WHERE NOT n.draining) AS "healthy_nodes!",
+                COUNT(*) FILTER (WHERE n.draining) AS "draining_nodes!",
+                COALESCE(SUM(p.sessions), 0)::BIGINT AS "sessions!"
+            FROM live_ops_nodes n
+            LEFT JOIN (
+                SELECT node_id, SUM(sessions) AS sessions FROM ws_presence GROUP BY node_id
+            ) p ON p.node_id = n.node_id
+            WHERE n.last_seen_at > NOW() - make_interval(secs => $1)
+            GROUP BY n.region
+            ORDER BY n.region
+            "#,
+            live_secs as f64
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(regions)
+    }
+
+    /// A node restarted; sessions it recorded before are gone
+    pub async fn reset_node(pool: &PgPool, node_id: &str) -> Result<()> {
+        sqlx::query!("UPDATE ws_presence SET sessions = 0 WHERE node_id = $1 AND sessions > 0", node_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn session_opened(pool: &PgPool, user_id: i64, node_id: &str, region: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO ws_presence (user_id, node_id, region, sessions) VALUES ($1, $2, $3, 1)
+            ON CONFLICT (user_id, node_id) DO UPDATE
+                SET sessions = ws_presence.sessions + 1, region = EXCLUDED.region,
+                    connected_at = CASE WHEN ws_presence.sessions = 0 THEN NOW() ELSE ws_presence.connected_at END,
+                    last_seen_at = NOW()
+            "#,
+            user_id,
+            node_id,
+            region
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn session_closed(pool: &PgPool, user_id: i64, node_id: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE ws_presence SET sessions = GREATEST(sessions - 1, 0), last_seen_at = NOW()
+            WHERE user_id = $1 AND node_id = $2
+            "#,
+            user_id,
+            node_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The region the player last had a session in, within `ttl_secs`
+    pub async fn affinity(pool: &PgPool, user_id: i64, ttl_secs: i64) -> Result<Option<String>> {
+        let region = sqlx::query_scalar!(
+            r#"
+            SELECT region FROM ws_presence
+            WHERE user_id = $1 AND last_seen_at > NOW() - make_interval(secs => $2)
+            ORDER BY sessions > 0 DESC, last_seen_at DESC
+            LIMIT 1
+            "#,
+            user_id,
+            ttl_secs as f64
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(region)
+    }
+
+    /// Live nodes other than `exclude_node` where the player has sessions
+    pub async fn peers(pool: &PgPool, user_id: i64, exclude_node: &str, live_secs: i64) -> Result<Vec<PresencePeer>> {
+        let peers = sqlx::query_as!(
+            PresencePeer,
+            r#"
+            SELECT p.node_id, p.region
+            FROM ws_presence p
+            JOIN live_ops_nodes n ON n.node_id = p.node_id
+            WHERE p.user_id = $1 AND p.sessions > 0 AND p.node_id <> $2
+              AND n.last_seen_at > NOW() - make_interval(secs => $3)
+            "#,
+            user_id,
+            exclude_node,
+            live_secs as f64
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(peers)
+    }
+
+    /// Whether the player has a session anywhere, and when one was last seen
+    pub async fn presence(pool: &PgPool, user_id: i64) -> Result<PlayerPresenceRow> {
+        let presence = sqlx::query_as!(
+            PlayerPresenceRow,
+            r#"
+            SELECT COALESCE(BOOL_OR(sessions > 0), FALSE) AS "online!", MAX(last_seen_at) AS last_seen_at
+            FROM ws_presence WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(presence)
+    }
+
+    /// Forget players gone for longer than `keep_secs`
+    pub async fn prune(pool: &PgPool, keep_secs: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM ws_presence WHERE sessions = 0 AND last_seen_at < NOW() - make_interval(secs => $1)",
+            keep_secs as f64
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Active processes of one type
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProcessTypeCount {
+    pub process_type: String,
+    pub active: i64,
+}
+
+/// An audit trail entry as the admin dashboard lists it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub event_type: String,
+    pub severity: String,
+    pub user_id: Option<i64>,
+    pub event_data: serde_json::Value,
+}
+
+/// Read-only figures for the admin dashboard
+pub struct AdminDashboardQueries;
+
+impl AdminDashboardQueries {
+    /// Players with a session on a node seen within `live_secs`
+    pub async fn online_players(pool: &PgPool, live_secs: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(DISTINCT p.user_id) AS "count!"
+            FROM ws_presence p
+            JOIN live_ops_nodes n ON n.node_id = p.node_id
+            WHERE p.sessions > 0 AND n.last_seen_at > NOW() - make_interval(secs => $1)
+            "#,
+            live_secs as f64
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Running processes grouped by type, most first
+    pub async fn processes_by_type(pool: &PgPool) -> Result<Vec<ProcessTypeCount>> {
+        let counts = sqlx::query_as!(
+            ProcessTypeCount,
+            r#"
+            SELECT lower(process_type) AS "process_type!", COUNT(*) AS "active!"
+            FROM processes
+            WHERE completed_at IS NULL AND end_time > NOW()
+            GROUP BY lower(process_type)
+            ORDER BY 2 DESC, 1
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(counts)
+    }
+
+    /// The latest audit events, newest first
+    pub async fn recent_audit(pool: &PgPool, limit: i64) -> Result<Vec<AuditEntry>> {
+        let entries = sqlx::query_as!(
+            AuditEntry,
+            r#"
+            SELECT id, timestamp, event_type, severity, user_id, event_data
+            FROM audit_logs
+            ORDER BY timestamp DESC
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(entries)
+    }
+}
+
+/// A referral as stored
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReferralRow {
+    pub id: i64,
+    pub referrer_id: i64,
+    pub referred_id: i64,
+    pub status: String,
+    pub signals: serde_json::Value,
+    pub milestones_paid: i32,
+    pub rewarded: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A new referral with the checks already made
+#[derive(Debug, Clone)]
+pub struct NewReferral<'a> {
+    pub referrer_id: i64,
+    pub referred_id: i64,
+    pub code: &'a str,
+    pub status: &'a str,
+    pub signals: serde_json::Value,
+    pub signup_ip: Option<&'a str>,
+    pub signup_network: Option<&'a str>,
+}
+
+/// What a sign-up shares with the referrer's sign-in history
+#[derive(Debug, Clone)]
+pub struct ReferralSignalsRow {
+    pub shares_ip: bool,
+    pub shares_network: bool,
+    pub shares_device: bool,
+    pub recent_from_network: i64,
+    pub referrer_age_hours: i64,
+}
+
+/// How far a pending referral's account has come
+#[derive(Debug, Clone)]
+pub struct ReferralProgress {
+    pub referral: ReferralRow,
+    pub level: i32,
+    pub playtime_secs: i64,
+    pub banned: bool,
+    /// The referred account has since signed in from one of the
+    /// referrer's IPs or devices
+    pub shares_sign_in: bool,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ReferralStats {
+    pub total: i64,
+    pub pending: i64,
+    pub flagged: i64,
+    pub rejected: i64,
+    pub completed: i64,
+    /// In cents
+    pub rewarded: i64,
+}
+
+/// A referral as its referrer sees it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReferralSummary {
+    pub id: i64,
+    pub referred_login: String,
+    pub status: String,
+    pub milestones_paid: i32,
+    pub rewarded: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct ReferralQueries;
+
+impl ReferralQueries {
+    pub async fn code_for(pool: &PgPool, user_id: i64) -> Result<Option<String>> {
+        let code = sqlx::query_scalar!("SELECT code FROM referral_codes WHERE user_id = $1", user_id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(code)
+    }
+
+    /// False if the code is taken or the player already has one
+    pub async fn create_code(pool: &PgPool, user_id: i64, code: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            "INSERT INTO referral_codes (user_id, code) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            user_id,
+            code
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn owner_of(pool: &PgPool, code: &str) -> Result<Option<i64>> {
+        let owner = sqlx::query_scalar!("SELECT user_id FROM referral_codes WHERE code = $1", code)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(owner)
+    }
+
+    /// Remember a sign-in's IP and, when the client sent one, its device
+    pub async fn record_sign_in(
+        pool: &PgPool,
+        user_id: i64,
+        ip: &str,
+        network: &str,
+        device_hash: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_sign_in_ips (user_id, ip, network) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, ip) DO UPDATE SET last_seen_at = NOW()
+            "#,
+            user_id,
+            ip,
+            network
+        )
+        .execute(pool)
+        .await?;
+
+        if let Some(device_hash) = device_hash {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_devices (user_id, device_hash) VALUES ($1, $2)
+                ON CONFLICT (user_id, device_hash) DO UPDATE SET last_seen_at = NOW()
+                "#,
+                user_id,
+                device_hash
+            )
+            .execute(pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Compare a sign-up with everything known about the referrer
+    pub async fn signals(
+        pool: &PgPool,
+        referrer_id: i64,
+        ip: Option<&str>,
+        network: Option<&str>,
+        device_hash: Option<&str>,
+        burst_window_hours: i64,
+    ) -> Result<ReferralSignalsRow> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                EXISTS(SELECT 1 FROM user_sign_in_ips WHERE user_id = $1 AND ip = $2) AS "shares_ip!",
+                EXISTS(SELECT 1 FROM user_sign_in_ips WHERE user_id = $1 AND network = $3) AS "shares_network!",
+                EXISTS(SELECT 1 FROM user_devices WHERE user_id = $1 AND device_hash = $4) AS "shares_device!",
+                (
+                    SELECT COUNT(*) FROM referrals
+                    WHERE referrer_id = $1 AND signup_network = $3
+                      AND created_at > NOW() - make_interval(hours => $5)
+                ) AS "recent_from_network!",
+                COALESCE((
+                    SELECT (EXTRACT(EPOCH FROM NOW() - created_at) / 3600)::BIGINT FROM users WHERE id = $1
+                ), 0) AS "referrer_age_hours!"
+            "#,
+            referrer_id,
+            ip,
+            network,
+            device_hash,
+            burst_window_hours as i32
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(ReferralSignalsRow {
+            shares_ip: row.shares_ip,
+            shares_network: row.shares_network,
+            shares_device: row.shares_device,
+            recent_from_network: row.recent_from_network,
+            referrer_age_hours: row.referrer_age_hours,
+        })
+    }
+
+    /// `None` if the account was already referred
+    pub async fn attribute(pool: &PgPool, referral: &NewReferral<'_>) -> Result<Option<ReferralRow>> {
+        let row = sqlx::query_as!(
+            ReferralRow,
+            r#"
+            INSERT INTO referrals (referrer_id, referred_id, code, status, signals, signup_ip, signup_network)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (referred_id) DO NOTHING
+            RETURNING id, referrer_id, referred_id, status, signals, milestones_paid, rewarded, created_at
+            "#,
+            referral.referrer_id,
+            referral.referred_id,
+            referral.code,
+            referral.status,
+            referral.signals,
+            referral.signup_ip,
+            referral.signup_network
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Pending referrals with their account's progress, locked until the
+    /// transaction ends
+    pub async fn pending(conn: &mut PgConnection, limit: i64) -> Result<Vec<ReferralProgress>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT r.id, r.referrer_id, r.referred_id, r.status, r.signals, r.milestones_paid, r.rewarded,
+                r.created_at,
+                COALESCE((
+                    SELECT level FROM player_progression
+                    WHERE player_id = lpad(to_hex(r.referred_id), 32, '0')::uuid
+                ), 1) AS "level!",
+                COALESCE((SELECT seconds FROM player_playtime WHERE user_id = r.referred_id), 0) AS "playtime_secs!",
+                EXISTS(
+                    SELECT 1 FROM user_bans
+                    WHERE user_id = r.referred_id AND (banned_until IS NULL OR banned_until > NOW())
+                ) AS "banned!",
+                (
+                    EXISTS(
+                        SELECT 1 FROM user_sign_in_ips mine
+                        JOIN user_sign_in_ips theirs ON theirs.ip = mine.ip AND theirs.user_id = r.referrer_id
+                        WHERE mine.user_id = r.referred_id
+                    )
+                    OR EXISTS(
+                        SELECT 1 FROM user_devices mine
+                        JOIN user_devices theirs
+                            ON theirs.device_hash = mine.device_hash AND theirs.user_id = r.referrer_id
+                        WHERE mine.user_id = r.referred_id
+                    )
+                ) AS "shares_sign_in!"
+            FROM referrals r
+            WHERE r.status = 'pending'
+            ORDER BY r.id
+            LIMIT $1
+            FOR UPDATE OF r SKIP LOCKED
+            "#,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ReferralProgress {
+                referral: ReferralRow {
+                    id: row.id,
+                    referrer_id: row.referrer_id,
+                    referred_id: row.referred_id,
+                    status: row.status,
+                    signals: row.signals,
+                    milestones_paid: row.milestones_paid,
+                    rewarded: row.rewarded,
+                    created_at: row.created_at,
+                },
+                level: row.level,
+                playtime_secs: row.playtime_secs,
+                banned: row.banned,
+                shares_sign_in: row.shares_sign_in,
+            })
+            .collect())
+    }
+
+    /// Pay one milestone to the referrer. `None` if it was already paid,
+    /// otherwise whether the referrer had an account to credit; `last`
+    /// completes the referral.
+    pub async fn pay_milestone(
+        conn: &mut PgConnection,
+        referral: &ReferralRow,
+        milestone: i32,
+        amount: i64,
+        last: bool,
+    ) -> Result<Option<bool>> {
+        let claimed = sqlx::query!(
+            r#"
+            INSERT INTO referral_rewards (referral_id, milestone, amount) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+            referral.id,
+            milestone,
+            amount
+        )
+        .execute(&mut *conn)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let paid = BankQueries::credit_primary_account(
+            &mut *conn,
+            referral.referrer_id,
+            amount,
+            LedgerAccount::Mint,
+            LedgerReason::ReferralReward,
+            &format!("referral:{}:{}", referral.id, milestone),
+        )
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE referral_rewards SET paid = $3, paid_at = CASE WHEN $3 THEN NOW() END
+            WHERE referral_id = $1 AND milestone = $2
+            "#,
+            referral.id,
+            milestone,
+            paid
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE referrals
+            SET milestones_paid = $2 + 1,
+                rewarded = rewarded + CASE WHEN $3 THEN $4 ELSE 0 END,
+                status = CASE WHEN $5 THEN 'completed' ELSE status END,
+                completed_at = CASE WHEN $5 THEN NOW() ELSE completed_at END
+            WHERE id = $1
+            "#,
+            referral.id,
+            milestone,
+            paid,
+            amount,
+            last
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(Some(paid))
+    }
+
+    /// Hold or reject a referral with the signals that explain it
+    pub async fn set_status(
+        conn: &mut PgConnection,
+        referral_id: i64,
+        status: &str,
+        signals: serde_json::Value,
+    ) -> Result<()> {
+        sqlx::query!(
+            "UPDATE referrals SET status = $2, signals = $3 WHERE id = $1",
+            referral_id,
+            status,
+            signals
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Clear a flagged referral back to pending, or reject it. `None` if it
+    /// is not flagged.
+    pub async fn review(
+        pool: &PgPool,
+        referral_id: i64,
+        admin_id: i64,
+        approve: bool,
+        note: &str,
+    ) -> Result<Option<ReferralRow>> {
+        let row = sqlx::query_as!(
+            ReferralRow,
+            r#"
+            UPDATE referrals
+            SET status = CASE WHEN $3 THEN 'pending' ELSE 'rejected' END,
+                reviewed_by = $2, reviewed_at = NOW(), review_note = $4
+            WHERE id = $1 AND status = 'flagged'
+            RETURNING id, referrer_id, referred_id, status, signals, milestones_paid, rewarded, created_at
+            "#,
+            referral_id,
+            admin_id,
+            approve,
+            note
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn stats(pool: &PgPool, referrer_id: i64) -> Result<ReferralStats> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) AS "total!",
+                COUNT(*) FILTER (WHERE status = 'pending') AS "pending!",
+                COUNT(*) FILTER (WHERE status = 'flagged') AS "flagged!",
+                COUNT(*) FILTER (WHERE status = 'rejected') AS "rejected!",
+                COUNT(*) FILTER (WHERE status = 'completed') AS "completed!",
+                COALESCE(SUM(rewarded), 0)::BIGINT AS "rewarded!"
+            FROM referrals
+            WHERE referrer_id = $1
+            "#,
+            referrer_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(ReferralStats {
+            total: row.total,
+            pending: row.pending,
+            flagged: row.flagged,
+            rejected: row.rejected,
+            completed: row.completed,
+            rewarded: row.rewarded,
+        })
+    }
+
+    /// The referrer's referrals, newest first
+    pub async fn for_referrer(pool: &PgPool, referrer_id: i64, limit: i64) -> Result<Vec<ReferralSummary>> {
+        let rows = sqlx::query_as!(
+            ReferralSummary,
+            r#"
+            SELECT r.id, u.login AS referred_login, r.status, r.milestones_paid, r.rewarded, r.created_at
+            FROM referrals r
+            JOIN users u ON u.id = r.referred_id
+            WHERE r.referrer_id = $1
+            ORDER BY r.created_at DESC
+            LIMIT $2
+            "#,
+            referrer_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Referrals waiting on a moderator, oldest first
+    pub async fn flagged(pool: &PgPool, limit: i64) -> Result<Vec<ReferralRow>> {
+        let rows = sqlx::query_as!(
+            ReferralRow,
+            r#"
+            SELECT id, referrer_id, referred_id, status, signals, milestones_paid, rewarded, created_at
+            FROM referrals
+            WHERE status = 'flagged'
+            ORDER BY created_at
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+/// Time players spend connected
+pub struct PlaytimeQueries;
+
+impl PlaytimeQueries {
+    pub async fn add(pool: &PgPool, user_id: i64, seconds: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO player_playtime (user_id, seconds) VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+                SET seconds = player_playtime.seconds + EXCLUDED.seconds, updated_at = NOW()
+            "#,
+            user_id,
+            seconds
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// A server a player wants to run a process on, and how they hold it
+#[derive(Debug, Clone)]
+pub struct ProcessHost {
+    pub server_id: i64,
+    pub owner_id: i64,
+    pub ip_address: String,
+    pub cpu_total: i32,
+    pub ram_total: i32,
+    pub net_total: i32,
+    /// "owned", "hacked", or `None` if the player has no access
+    pub access: Option<String>,
+}
+
+/// Resources held on a host by processes still running there
+#[derive(Debug, Clone, Default)]
+pub struct HostUsage {
+    pub cpu: i64,
+    pub ram: i64,
+    pub net: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HostLoad {
+    /// The player's own processes on the host
+    pub running: i64,
+    pub all: HostUsage,
+    /// Only processes of players who hacked the host
+    pub hacked: HostUsage,
+}
+
+/// A process someone runs on one of the player's servers, for their TOP list
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HostedProcess {
+    pub pid: i64,
+    pub process_type: String,
+    pub host_ip: String,
+    /// Gateway of the player running it
+    pub origin_ip: Option<String>,
+    pub target_pc_id: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+/// Processes running on servers other than their player's gateway
+/// Resources a process holds on a server other than the gateway
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProcessHold {
+    pub server_id: i64,
+    pub owner_id: i64,
+    pub ip: String,
+    pub cpu_usage: i32,
+    pub ram_usage: i32,
+    pub net_usage: i32,
+}
+
+pub struct ProcessHostQueries;
+
+impl ProcessHostQueries {
+    /// The server at `ip` as `user_id` holds it; `None` if there is none
+    pub async fn host(pool: &PgPool, user_id: i64, ip: &str) -> Result<Option<ProcessHost>> {
+        let host = sqlx::query_as!(
+            ProcessHost,
+            r#"
+            SELECT s.id AS server_id, s.user_id AS owner_id, host(s.ip_address) AS "ip_address!",
+                s.cpu_total, s.ram_total, s.net_total,
+                CASE
+                    WHEN s.user_id = $1 THEN 'owned'
+                    WHEN EXISTS (
+                        SELECT 1 FROM hacked_database d
+                        WHERE d.user_id = $1 AND d.ip_address = s.ip_address AND d.invalidated_at IS NULL
+                    ) THEN 'hacked'
+                END AS access
+            FROM servers s
+            WHERE host(s.ip_address) = $2 AND s.is_active = TRUE
+            "#,
+            user_id,
+            ip
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(host)
+    }
+
+    /// Lock the host and read what runs on it, so allocations are checked
+    /// one at a time
+    pub async fn lock_load(conn: &mut PgConnection, server_id: i64, user_id: i64) -> Result<HostLoad> {
+        sqlx::query!("SELECT id FROM servers WHERE id = $1 FOR UPDATE", server_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE h.user_id = $2) AS "running!",
+                COALESCE(SUM(h.cpu_usage), 0)::BIGINT AS "cpu!",
+                COALESCE(SUM(h.ram_usage), 0)::BIGINT AS "ram!",
+                COALESCE(SUM(h.net_usage), 0)::BIGINT AS "net!",
+                COALESCE(SUM(h.cpu_usage) FILTER (WHERE h.access = 'hacked'), 0)::BIGINT AS "hacked_cpu!",
+                COALESCE(SUM(h.ram_usage) FILTER (WHERE h.access = 'hacked'), 0)::BIGINT AS "hacked_ram!",
+                COALESCE(SUM(h.net_usage) FILTER (WHERE h.access = 'hacked'), 0)::BIGINT AS "hacked_net!"
+            FROM process_hosts h
+            JOIN processes p ON p.pid = h.pid
+            WHERE h.server_id = $1 AND p.completed_at IS NULL AND p.end_time > NOW()
+            "#,
+            server_id,
+            user_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(HostLoad {
+            running: row.running,
+            all: HostUsage { cpu: row.cpu, ram: row.ram, net: row.net },
+            hacked: HostUsage { cpu: row.hacked_cpu, ram: row.hacked_ram, net: row.hacked_net },
+        })
+    }
+
+    /// Hold resources on the host for process `pid`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn reserve(
+        conn: &mut PgConnection,
+        pid: i64,
+        user_id: i64,
+        server_id: i64,
+        access: &str,
+        cpu_usage: i32,
+        ram_usage: i32,
+        net_usage: i32,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO process_hosts (pid, user_id, server_id, access, cpu_usage, ram_usage, net_usage)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            pid,
+            user_id,
+            server_id,
+            access,
+            cpu_usage,
+            ram_usage,
+            net_usage
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// What process `pid` holds on its host, with the host's owner
+    pub async fn hold(conn: &mut PgConnection, pid: i64) -> Result<Option<ProcessHold>> {
+        let hold = sqlx::query_as!(
+            ProcessHold,
+            r#"
+            SELECT h.server_id, s.user_id AS owner_id, host(s.ip_address) AS "ip!",
+                h.cpu_usage, h.ram_usage, h.net_usage
+            FROM process_hosts h
+            JOIN servers s ON s.id = h.server_id
+            WHERE h.pid = $1
+            "#,
+            pid
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(hold)
+    }
+
+    /// Link speed of the server at `ip`, for transfers to or from it
+    pub async fn net_total(pool: &PgPool, ip: &str) -> Result<Option<i32>> {
+        let net = sqlx::query_scalar!("SELECT net_total FROM servers WHERE host(ip_address) = $1", ip)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(net)
+    }
+
+    /// Other players' processes running on the owner's servers
+    pub async fn hosted_on(pool: &PgPool, owner_id: i64) -> Result<Vec<HostedProcess>> {
+        let processes = sqlx::query_as!(
+            HostedProcess,
+            r#"
+            SELECT p.pid, p.process_type, host(s.ip_address) AS "host_ip!", host(g.ip_address) AS origin_ip,
+                p.target_pc_id, p.start_time, p.end_time
+            FROM process_hosts h
+            JOIN processes p ON p.pid = h.pid
+            JOIN servers s ON s.id = h.server_id
+            LEFT JOIN LATERAL (
+                SELECT ip_address FROM servers
+                WHERE user_id = h.user_id AND is_npc = FALSE
+                ORDER BY id
+                LIMIT 1
+            ) g ON TRUE
+            WHERE s.user_id = $1 AND h.user_id <> $1 AND p.completed_at IS NULL AND p.end_time > NOW()
+            ORDER BY p.end_time
+            "#,
+            owner_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(processes)
+    }
+
+    /// Cancel processes on the owner's servers whose players no longer hold
+    /// the server in their hacked database. Returns `(pid, user_id,
+    /// process_type)` of each.
+    pub async fn revoke_lost(conn: &mut PgConnection, owner_id: i64) -> Result<Vec<(i64, i64, String)>> {
+        let rows = sqlx::query!(
+            r#"
+            DELETE FROM processes p
+            USING process_hosts h, servers s
+            WHERE h.pid = p.pid AND s.id = h.server_id
+              AND s.user_id = $1 AND h.access = 'hacked' AND p.completed_at IS NULL
+              AND NOT EXISTS (
+                  SELECT 1 FROM hacked_database d
+                  WHERE d.user_id = h.user_id AND d.ip_address = s.ip_address AND d.invalidated_at IS NULL
+              )
+            RETURNING p.pid, p.user_id, p.process_type
+            "#,
+            owner_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.pid, r.user_id, r.process_type)).collect())
+    }
+}
+
+/// A reported chat message, with who may see it
+#[derive(Debug, Clone)]
+pub struct ReportedChatMessage {
+    pub id: i64,
+    pub thread_id: i64,
+    pub user_id: Option<i64>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A case opening, or the open case for the same content
+#[derive(Debug, Clone)]
+pub struct NewReportCase<'a> {
+    pub target: &'a str,
+    pub target_key: &'a str,
+    pub fingerprint: &'a str,
+    pub subject_id: Option<i64>,
+    pub evidence: serde_json::Value,
+    pub category: &'a str,
+    pub priority: i32,
+    pub sla_due_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReportCaseRow {
+    pub id: i64,
+    pub target: String,
+    pub target_key: String,
+    pub subject_id: Option<i64>,
+    pub evidence: serde_json::Value,
+    pub category: String,
+    pub priority: i32,
+    pub reporters: i32,
+    pub status: String,
+    pub sla_due_at: DateTime<Utc>,
+    pub resolved_by: Option<i64>,
+    pub action: Option<String>,
+    pub moderation_action_id: Option<i64>,
+    pub resolution_note: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReportRow {
+    pub id: i64,
+    pub reporter_id: Option<i64>,
+    pub category: String,
+    pub description: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// How the queue is keeping up
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReportSlaMetrics {
+    pub open: i64,
+    /// Open past their deadline
+    pub overdue: i64,
+    pub oldest_open_at: Option<DateTime<Utc>>,
+    /// Closed within the window
+    pub resolved: i64,
+    pub resolved_within_sla: i64,
+    pub median_resolution_minutes: Option<f64>,
+    pub p90_resolution_minutes: Option<f64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ModerationActionRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub moderator_id: Option<i64>,
+    pub action: String,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct ReportQueries;
+
+impl ReportQueries {
+    /// The message, if `reporter_id` is in its thread
+    pub async fn chat_message(pool: &PgPool, message_id: i64, reporter_id: i64) -> Result<Option<ReportedChatMessage>> {
+        let message = sqlx::query_as!(
+            ReportedChatMessage,
+            r#"
+            SELECT m.id, m.thread_id, m.user_id, m.body, m.created_at
+            FROM chat_thread_messages m
+            JOIN chat_thread_members t ON t.thread_id = m.thread_id AND t.user_id = $2
+            WHERE m.id = $1
+            "#,
+            message_id,
+            reporter_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(message)
+    }
+
+    /// Messages just before `message_id` in its thread, oldest first
+    pub async fn chat_context(pool: &PgPool, thread_id: i64, message_id: i64, limit: i64) -> Result<Vec<ChatThreadMessage>> {
+        let mut messages = sqlx::query_as!(
+            ChatThreadMessage,
+            r#"
+            SELECT id, user_id, body, created_at FROM chat_thread_messages
+            WHERE thread_id = $1 AND id < $2
+            ORDER BY id DESC
+            LIMIT $3
+            "#,
+            thread_id,
+            message_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        messages.reverse();
+        Ok(messages)
+    }
+
+    pub async fn username(pool: &PgPool, user_id: i64) -> Result<Option<String>> {
+        let login = sqlx::query_scalar!("SELECT login FROM users WHERE id = $1", user_id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(login)
+    }
+
+    /// Name, tag and leader of an active clan
+    pub async fn clan(pool: &PgPool, clan_id: i64) -> Result<Option<(String, String, i64)>> {
+        let clan = sqlx::query!(
+            "SELECT name, tag, leader_id FROM clans WHERE id = $1 AND is_active = TRUE",
+            clan_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(clan.map(|c| (c.name, c.tag, c.leader_id)))
+    }
+
+    /// Reports by `reporter_id` still waiting for a moderator
+    pub async fn open_by_reporter(pool: &PgPool, reporter_id: i64) -> Result<i64> {
+        let open = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "open!" FROM reports r
+            JOIN report_cases c ON c.id = r.case_id
+            WHERE r.reporter_id = $1 AND c.status = 'open'
+            "#,
+            reporter_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(open)
+    }
+
+    /// A case for the same content dismissed within `days`, which takes
+    /// further reports without being queued again
+    pub async fn dismissed_case(
+        conn: &mut PgConnection,
+        target: &str,
+        target_key: &str,
+        fingerprint: &str,
+        days: i64,
+    ) -> Result<Option<i64>> {
+        let id = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM report_cases
+            WHERE target = $1 AND target_key = $2 AND fingerprint = $3
+              AND status = 'dismissed' AND resolved_at > NOW() - make_interval(days => $4)
+            ORDER BY resolved_at DESC
+            LIMIT 1
+            "#,
+            target,
+            target_key,
+            fingerprint,
+            days as i32
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Open a case for the content, or lock the one already open. The bool is
+    /// whether it was opened now; an open case keeps its first evidence.
+    pub async fn open_case(conn: &mut PgConnection, case: &NewReportCase<'_>) -> Result<(ReportCaseRow, bool)> {
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO report_cases
+                (target, target_key, fingerprint, subject_id, evidence, category, priority, sla_due_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (target, target_key, fingerprint) WHERE status = 'open'
+            DO UPDATE SET reporters = report_cases.reporters
+            RETURNING id, target, target_key, subject_id, evidence, category, priority, reporters, status,
+                sla_due_at, resolved_by, action, moderation_action_id, resolution_note, resolved_at, created_at,
+                (xmax = 0) AS "created!"
+            "#,
+            case.target,
+            case.target_key,
+            case.fingerprint,
+            case.subject_id,
+            case.evidence,
+            case.category,
+            case.priority,
+            case.sla_due_at
+        )
+        .fetch_one(conn)
+        .await?;
+
+        let created = row.created;
+        let case = ReportCaseRow {
+            id: row.id,
+            target: row.target,
+            target_key: row.target_key,
+            subject_id: row.subject_id,
+            evidence: row.evidence,
+            category: row.category,
+            priority: row.priority,
+            reporters: row.reporters,
+            status: row.status,
+            sla_due_at: row.sla_due_at,
+            resolved_by: row.resolved_by,
+            action: row.action,
+            moderation_action_id: row.moderation_action_id,
+            resolution_note: row.resolution_note,
+            resolved_at: row.resolved_at,
+            created_at: row.created_at,
+        };
+        Ok((case, created))
+    }
+
+    /// File a report on the case. `None` if the reporter already reported it,
+    /// otherwise how many players have.
+    pub async fn add_report(
+        conn: &mut PgConnection,
+        case_id: i64,
+        reporter_id: Option<i64>,
+        category: &str,
+        description: &str,
+    ) -> Result<Option<i64>> {
+        let added = sqlx::query!(
+            r#"
+            INSERT INTO reports (case_id, reporter_id, category, description)
+            SELECT $1, $2, $3, $4
+            -- The game reports a piece of content once
+            WHERE $2::BIGINT IS NOT NULL
+               OR NOT EXISTS (SELECT 1 FROM reports WHERE case_id = $1 AND reporter_id IS NULL)
+            ON CONFLICT (case_id, reporter_id) WHERE reporter_id IS NOT NULL DO NOTHING
+            "#,
+            case_id,
+            reporter_id,
+            category,
+            description
+        )
+        .execute(&mut *conn)
+        .await?;
+        if added.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let reporters = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "reporters!" FROM reports WHERE case_id = $1"#,
+            case_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(Some(reporters))
+    }
+
+    /// Re-rank an open case after a report joined it
+    pub async fn requeue(
+        conn: &mut PgConnection,
+        case_id: i64,
+        category: &str,
+        priority: i32,
+        reporters: i64,
+        sla_due_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE report_cases SET category = $2, priority = $3, reporters = $4, sla_due_at = $5
+            WHERE id = $1 AND status = 'open'
+            "#,
+            case_id,
+            category,
+            priority,
+            reporters as i32,
+            sla_due_at
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Open cases, most urgent first
+    pub async fn queue(pool: &PgPool, limit: i64) -> Result<Vec<ReportCaseRow>> {
+        let cases = sqlx::query_as!(
+            ReportCaseRow,
+            r#"
+            SELECT id, target, target_key, subject_id, evidence, category, priority, reporters, status,
+                sla_due_at, resolved_by, action, moderation_action_id, resolution_note, resolved_at, created_at
+            FROM report_cases
+            WHERE status = 'open'
+            ORDER BY priority DESC, created_at
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(cases)
+    }
+
+    pub async fn case(pool: &PgPool, case_id: i64) -> Result<Option<ReportCaseRow>> {
+        let case = sqlx::query_as!(
+            ReportCaseRow,
+            r#"
+            SELECT id, target, target_key, subject_id, evidence, category, priority, reporters, status,
+                sla_due_at, resolved_by, action, moderation_action_id, resolution_note, resolved_at, created_at
+            FROM report_cases
+            WHERE id = $1
+            "#,
+            case_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(case)
+    }
+
+    pub async fn reports(pool: &PgPool, case_id: i64) -> Result<Vec<ReportRow>> {
+        let reports = sqlx::query_as!(
+            ReportRow,
+            r#"
+            SELECT id, reporter_id, category, description, created_at
+            FROM reports WHERE case_id = $1
+            ORDER BY id
+            "#,
+            case_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(reports)
+    }
+
+    /// Lock an open case for resolution
+    pub async fn lock_open(conn: &mut PgConnection, case_id: i64) -> Result<Option<ReportCaseRow>> {
+        let case = sqlx::query_as!(
+            ReportCaseRow,
+            r#"
+            SELECT id, target, target_key, subject_id, evidence, category, priority, reporters, status,
+                sla_due_at, resolved_by, action, moderation_action_id, resolution_note, resolved_at, created_at
+            FROM report_cases
+            WHERE id = $1 AND status = 'open'
+            FOR UPDATE
+            "#,
+            case_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(case)
+    }
+
+    /// Close a locked case as `actioned` or `dismissed`
+    pub async fn close(
+        conn: &mut PgConnection,
+        case_id: i64,
+        status: &str,
+        moderator_id: i64,
+        action: &str,
+        moderation_action_id: Option<i64>,
+        note: &str,
+    ) -> Result<ReportCaseRow> {
+        let case = sqlx::query_as!(
+            ReportCaseRow,
+            r#"
+            UPDATE report_cases
+            SET status = $2, resolved_by = $3, action = $4, moderation_action_id = $5,
+                resolution_note = $6, resolved_at = NOW()
+            WHERE id = $1
+            RETURNING id, target, target_key, subject_id, evidence, category, priority, reporters, status,
+                sla_due_at, resolved_by, action, moderation_action_id, resolution_note, resolved_at, created_at
+            "#,
+            case_id,
+            status,
+            moderator_id,
+            action,
+            moderation_action_id,
+            note
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(case)
+    }
+
+    /// Reporters of a case, for telling them it was closed
+    pub async fn reporters(conn: &mut PgConnection, case_id: i64) -> Result<Vec<i64>> {
+        let ids = sqlx::query_scalar!(
+            r#"SELECT reporter_id AS "reporter_id!" FROM reports WHERE case_id = $1 AND reporter_id IS NOT NULL"#,
+            case_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Queue health now, and resolution times over the last `window_days`
+    pub async fn sla_metrics(pool: &PgPool, window_days: i64) -> Result<ReportSlaMetrics> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE status = 'open') AS "open!",
+                COUNT(*) FILTER (WHERE status = 'open' AND sla_due_at < NOW()) AS "overdue!",
+                MIN(created_at) FILTER (WHERE status = 'open') AS oldest_open_at,
+                COUNT(*) FILTER (WHERE resolved_at > NOW() - make_interval(days => $1)) AS "resolved!",
+                COUNT(*) FILTER (
+                    WHERE resolved_at > NOW() - make_interval(days => $1) AND resolved_at <= sla_due_at
+                ) AS "resolved_within_sla!",
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM resolved_at - created_at) / 60)
+                    FILTER (WHERE resolved_at > NOW() - make_interval(days => $1)) AS median_resolution_minutes,
+                percentile_cont(0.9) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM resolved_at - created_at) / 60)
+                    FILTER (WHERE resolved_at > NOW() - make_interval(days => $1)) AS p90_resolution_minutes
+            FROM report_cases
+            WHERE status = 'open' OR resolved_at > NOW() - make_interval(days => $1)
+            "#,
+            window_days as i32
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(ReportSlaMetrics {
+            open: row.open,
+            overdue: row.overdue,
+            oldest_open_at: row.oldest_open_at,
+            resolved: row.resolved,
+            resolved_within_sla: row.resolved_within_sla,
+            median_resolution_minutes: row.median_resolution_minutes,
+            p90_resolution_minutes: row.p90_resolution_minutes,
+        })
+    }
+}
+
+pub struct ModerationQueries;
+
+impl ModerationQueries {
+    /// Record an action taken against a player
+    pub async fn record(
+        conn: &mut PgConnection,
+        user_id: i64,
+        moderator_id: i64,
+        action: &str,
+        reason: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO moderation_actions (user_id, moderator_id, action, reason, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+            "#,
+            user_id,
+            moderator_id,
+            action,
+            reason,
+            expires_at
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Ban a player from the game, replacing any ban they have; `None` for a
+    /// permanent one
+    pub async fn ban(conn: &mut PgConnection, user_id: i64, reason: &str, until: Option<DateTime<Utc>>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_bans (user_id, reason, banned_until, source)
+            VALUES ($1, $2, $3, 'game')
+            ON CONFLICT (user_id) DO UPDATE
+            SET reason = EXCLUDED.reason, banned_until = EXCLUDED.banned_until, source = 'game', created_at = NOW()
+            "#,
+            user_id,
+            reason,
+            until
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_chat_message(conn: &mut PgConnection, message_id: i64, replacement: &str) -> Result<bool> {
+        let removed = sqlx::query!(
+            "UPDATE chat_thread_messages SET body = $2 WHERE id = $1",
+            message_id,
+            replacement
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(removed.rows_affected() > 0)
+    }
+
+    /// Clear the hostname, login banner and public page of a player server
+    pub async fn clear_server_page(conn: &mut PgConnection, ip: &str) -> Result<bool> {
+        let cleared = sqlx::query!(
+            r#"
+            UPDATE servers SET hostname = NULL, motd = NULL, public_profile = NULL
+            WHERE host(ip_address) = $1 AND is_npc = FALSE
+            "#,
+            ip
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(cleared.rows_affected() > 0)
+    }
+
+    /// Replace a clan's name and tag with neutral ones derived from its id
+    pub async fn reset_clan_name(conn: &mut PgConnection, clan_id: i64) -> Result<bool> {
+        let reset = sqlx::query!(
+            "UPDATE clans SET name = 'clan-' || id, tag = 'C' || id WHERE id = $1",
+            clan_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(reset.rows_affected() > 0)
+    }
+
+    /// Actions taken against a player, newest first
+    pub async fn history(pool: &PgPool, user_id: i64, limit: i64) -> Result<Vec<ModerationActionRow>> {
+        let actions = sqlx::query_as!(
+            ModerationActionRow,
+            r#"
+            SELECT id, user_id, moderator_id, action, reason, expires_at, created_at
+            FROM moderation_actions
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(actions)
+    }
+}
+
+/// Role that carries the premium permissions
+pub const PREMIUM_ROLE: &str = "premium_player";
+
+/// A premium subscription as mirrored from the payment provider
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SubscriptionRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub provider: String,
+    pub provider_subscription_id: String,
+    pub provider_customer_id: Option<String>,
+    pub plan: String,
+    pub status: String,
+    pub current_period_start: DateTime<Utc>,
+    pub current_period_end: DateTime<Utc>,
+    pub access_until: Option<DateTime<Utc>>,
+    pub role_granted: bool,
+    pub last_event_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A subscription's state as of one provider event
+#[derive(Debug, Clone)]
+pub struct SubscriptionUpdate<'a> {
+    pub provider: &'a str,
+    pub provider_subscription_id: &'a str,
+    pub provider_customer_id: Option<&'a str>,
+    pub user_id: i64,
+    pub plan: &'a str,
+    pub status: &'a str,
+    pub current_period_start: DateTime<Utc>,
+    pub current_period_end: DateTime<Utc>,
+    pub access_until: Option<DateTime<Utc>>,
+    pub event_at: DateTime<Utc>,
+}
+
+/// Players whose premium role changed in one pass of the expiry job
+#[derive(Debug, Clone, Default)]
+pub struct RoleSync {
+    pub granted: Vec<i64>,
+    pub revoked: Vec<i64>,
+}
+
+pub struct EntitlementQueries;
+
+impl EntitlementQueries {
+    /// Record a webhook. `false` if the event was processed already; one
+    /// that failed before is processed again.
+    pub async fn record_event(
+        pool: &PgPool,
+        provider: &str,
+        event_id: &str,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> Result<bool> {
+        let fresh = sqlx::query_scalar!(
+            r#"
+            INSERT INTO payment_webhook_events (provider, event_id, event_type, payload)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (provider, event_id) DO UPDATE SET received_at = NOW()
+            WHERE payment_webhook_events.processed_at IS NULL
+            RETURNING event_id
+            "#,
+            provider,
+            event_id,
+            event_type,
+            payload
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(fresh.is_some())
+    }
+
+    /// Mark a webhook processed, or record why it failed
+    pub async fn finish_event(pool: &PgPool, provider: &str, event_id: &str, error: Option<&str>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE payment_webhook_events
+            SET processed_at = CASE WHEN $3::TEXT IS NULL THEN NOW() END, error = $3
+            WHERE provider = $1 AND event_id = $2
+            "#,
+            provider,
+            event_id,
+            error
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn lock_subscription(
+        conn: &mut PgConnection,
+        provider: &str,
+        provider_subscription_id: &str,
+    ) -> Result<Option<SubscriptionRow>> {
+        let subscription = sqlx::query_as!(
+            SubscriptionRow,
+            r#"
+            SELECT id, user_id, provider, provider_subscription_id, provider_customer_id, plan, status,
+                   current_period_start, current_period_end, access_until, role_granted, last_event_at,
+                   created_at, updated_at
+            FROM subscriptions
+            WHERE provider = $1 AND provider_subscription_id = $2
+            FOR UPDATE
+            "#,
+            provider,
+            provider_subscription_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(subscription)
+    }
+
+    /// Insert or overwrite a subscription; the caller has checked the event
+    /// is newer than the last one applied. The owner is kept from the first
+    /// event, whatever later ones say.
+    pub async fn upsert(conn: &mut PgConnection, update: &SubscriptionUpdate<'_>) -> Result<SubscriptionRow> {
+        let subscription = sqlx::query_as!(
+            SubscriptionRow,
+            r#"
+            INSERT INTO subscriptions (
+                user_id, provider, provider_subscription_id, provider_customer_id, plan, status,
+                current_period_start, current_period_end, access_until, last_event_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (provider, provider_subscription_id) DO UPDATE SET
+                provider_customer_id = COALESCE(EXCLUDED.provider_customer_id, subscriptions.provider_customer_id),
+                plan = EXCLUDED.plan,
+                status = EXCLUDED.status,
+                current_period_start = EXCLUDED.current_period_start,
+                current_period_end = EXCLUDED.current_period_end,
+                access_until = EXCLUDED.access_until,
+                last_event_at = EXCLUDED.last_event_at,
+                updated_at = NOW()
+            RETURNING id, user_id, provider, provider_subscription_id, provider_customer_id, plan, status,
+                      current_period_start, current_period_end, access_until, role_granted, last_event_at,
+                      created_at, updated_at
+            "#,
+            update.user_id,
+            update.provider,
+            update.provider_subscription_id,
+            update.provider_customer_id,
+            update.plan,
+            update.status,
+            update.current_period_start,
+            update.current_period_end,
+            update.access_until,
+            update.event_at
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(subscription)
+    }
+
+    /// Give or take away the premium role to match whether the subscription
+    /// entitles its player. The role stays while another subscription of
+    /// the player still grants it. Returns whether anything changed.
+    pub async fn sync_role(conn: &mut PgConnection, subscription: &SubscriptionRow, entitled: bool) -> Result<bool> {
+        if subscription.role_granted == entitled {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            "UPDATE subscriptions SET role_granted = $2, updated_at = NOW() WHERE id = $1",
+            subscription.id,
+            entitled
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        if entitled {
+            Self::grant_role(conn, subscription.user_id).await?;
+        } else {
+            Self::revoke_role(conn, subscription.user_id).await?;
+        }
+        Ok(true)
+    }
+
+    async fn grant_role(conn: &mut PgConnection, user_id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_roles (user_id, role_id)
+            SELECT $1, r.id FROM roles r
+            WHERE r.name = $2
+              AND NOT EXISTS (SELECT 1 FROM user_roles ur WHERE ur.user_id = $1 AND ur.role_id = r.id)
+            "#,
+            user_id,
+            PREMIUM_ROLE
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!("UPDATE users SET premium = TRUE WHERE id = $1", user_id)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_role(conn: &mut PgConnection, user_id: i64) -> Result<()> {
+        let still_granted = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM subscriptions WHERE user_id = $1 AND role_granted) AS "granted!""#,
+            user_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        if still_granted {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+            DELETE FROM user_roles
+            WHERE user_id = $1 AND role_id IN (SELECT id FROM roles WHERE name = $2)
+            "#,
+            user_id,
+            PREMIUM_ROLE
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!("UPDATE users SET premium = FALSE WHERE id = $1", user_id)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Bring premium roles in line with subscription access: take the role
+    /// from players whose access ran out, marking those subscriptions
+    /// expired, and give it where a grant was missed
+    pub async fn sync_roles(pool: &PgPool) -> Result<RoleSync> {
+        let mut tx = pool.begin().await?;
+        let lapsed = sqlx::query_as!(
+            SubscriptionRow,
+            r#"
+            UPDATE subscriptions
+            SET status = CASE WHEN status = 'pending' THEN status ELSE 'expired' END, updated_at = NOW()
+            WHERE role_granted AND (access_until IS NULL OR access_until <= NOW())
+            RETURNING id, user_id, provider, provider_subscription_id, provider_customer_id, plan, status,
+                      current_period_start, current_period_end, access_until, role_granted, last_event_at,
+                      created_at, updated_at
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let missing = sqlx::query_as!(
+            SubscriptionRow,
+            r#"
+            SELECT id, user_id, provider, provider_subscription_id, provider_customer_id, plan, status,
+                   current_period_start, current_period_end, access_until, role_granted, last_event_at,
+                   created_at, updated_at
+            FROM subscriptions
+            WHERE NOT role_granted AND access_until > NOW()
+            FOR UPDATE
+            "#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut sync = RoleSync::default();
+        for subscription in &lapsed {
+            Self::sync_role(&mut tx, subscription, false).await?;
+            sync.revoked.push(subscription.user_id);
+        }
+        for subscription in &missing {
+            Self::sync_role(&mut tx, subscription, true).await?;
+            sync.granted.push(subscription.user_id);
+        }
+        tx.commit().await?;
+
+        Ok(sync)
+    }
+
+    /// The subscription a player's premium currently comes from, if any
+    pub async fn entitlement(pool: &PgPool, user_id: i64) -> Result<Option<SubscriptionRow>> {
+        let subscription = sqlx::query_as!(
+            SubscriptionRow,
+            r#"
+            SELECT id, user_id, provider, provider_subscription_id, provider_customer_id, plan, status,
+                   current_period_start, current_period_end, access_until, role_granted, last_event_at,
+                   created_at, updated_at
+            FROM subscriptions
+            WHERE user_id = $1 AND access_until > NOW()
+            ORDER BY access_until DESC
+            LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(subscription)
+    }
+
+    /// A player's subscriptions, newest first
+    pub async fn for_user(pool: &PgPool, user_id: i64) -> Result<Vec<SubscriptionRow>> {
+        let subscriptions = sqlx::query_as!(
+            SubscriptionRow,
+            r#"
+            SELECT id, user_id, provider, provider_subscription_id, provider_customer_id, plan, status,
+                   current_period_start, current_period_end, access_until, role_granted, last_event_at,
+                   created_at, updated_at
+            FROM subscriptions
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(subscriptions)
+    }
+}
+
+/// What a plugin with the `read_game_state` capability sees of a player
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PlayerSnapshot {
+    pub user_id: i64,
+    pub login: String,
+    pub premium: bool,
+    pub gateway_ip: Option<String>,
+    /// Across all of the player's bank accounts
+    pub bank_balance: i64,
+}
+
+/// Game state handed to server-side plugins
+pub struct PluginQueries;
+
+impl PluginQueries {
+    pub async fn player_snapshot(pool: &PgPool, user_id: i64) -> Result<Option<PlayerSnapshot>> {
+        let snapshot = sqlx::query_as!(
+            PlayerSnapshot,
+            r#"
+            SELECT u.id AS user_id, u.login, u.premium,
+                   (SELECT host(s.ip_address) FROM servers s
+                    WHERE s.user_id = u.id AND s.is_npc = FALSE
+                    ORDER BY s.id LIMIT 1) AS gateway_ip,
+                   COALESCE((SELECT SUM(b.balance) FROM bank_accounts b WHERE b.user_id = u.id), 0)::BIGINT AS "bank_balance!"
+            FROM users u
+            WHERE u.id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(snapshot)
+    }
+}
+
+/// What new-account gating needs about a player
+#[derive(Debug, Clone)]
+pub struct AccountStandingRow {
+    pub level: i32,
+    pub created_at: DateTime<Utc>,
+    pub verified: bool,
+    pub exempt: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AccountGatingOverrideRow {
+    pub user_id: i64,
+    pub verified: bool,
+    pub exempt: Vec<String>,
+    pub note: Option<String>,
+    pub set_by: Option<i64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+pub struct AccountGatingQueries;
+
+impl AccountGatingQueries {
+    pub async fn standing(pool: &PgPool, user_id: i64) -> Result<Option<AccountStandingRow>> {
+        let player_id = legacy_user_uuid(user_id);
+
+        let standing = sqlx::query_as!(
+            AccountStandingRow,
+            r#"
+            SELECT
+                COALESCE((SELECT level FROM player_progression WHERE player_id = $2), 1) AS "level!",
+                u.created_at,
+                COALESCE(o.verified, FALSE) AS "verified!",
+                COALESCE(o.exempt, '{}') AS "exempt!"
+            FROM users u
+            LEFT JOIN account_gating_overrides o ON o.user_id = u.id
+            WHERE u.id = $1
+            "#,
+            user_id,
+            player_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(standing)
+    }
+
+    /// Chat messages the player posted in the last `window_minutes`
+    pub async fn recent_chat_messages(pool: &PgPool, user_id: i64, window_minutes: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM chat_thread_messages
+            WHERE user_id = $1 AND created_at > NOW() - make_interval(mins => $2)
+            "#,
+            user_id,
+            window_minutes as i32
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    pub async fn get_override(pool: &PgPool, user_id: i64) -> Result<Option<AccountGatingOverrideRow>> {
+        let row = sqlx::query_as!(
+            AccountGatingOverrideRow,
+            "SELECT user_id, verified, exempt, note, set_by, updated_at FROM account_gating_overrides WHERE user_id = $1",
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Verify or exempt a player; None if there is no such player
+    pub async fn set_override(
+        pool: &PgPool,
+        user_id: i64,
+        verified: bool,
+        exempt: &[String],
+        note: Option<&str>,
+        set_by: i64,
+    ) -> Result<Option<AccountGatingOverrideRow>> {
+        let row = sqlx::query_as!(
+            AccountGatingOverrideRow,
+            r#"
+            INSERT INTO account_gating_overrides (user_id, verified, exempt, note, set_by)
+            SELECT id, $2, $3, $4, $5 FROM users WHERE id = $1
+            ON CONFLICT (user_id) DO UPDATE SET
+                verified = EXCLUDED.verified,
+                exempt = EXCLUDED.exempt,
+                note = EXCLUDED.note,
+                set_by = EXCLUDED.set_by,
+                updated_at = NOW()
+            RETURNING user_id, verified, exempt, note, set_by, updated_at
+            "#,
+            user_id,
+            verified,
+            exempt,
+            note,
+            set_by
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn clear_override(pool: &PgPool, user_id: i64) -> Result<bool> {
+        let result = sqlx::query!("DELETE FROM account_gating_overrides WHERE user_id = $1", user_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// An NPC server in the browser, with what the viewer's last scan found
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BrowserServerRow {
+    pub server_id: i64,
+    pub ip: String,
+    pub hostname: Option<String>,
+    pub npc_type: Option<String>,
+    pub npc_tier: Option<i16>,
+    pub region: Option<String>,
+    pub firewall: f64,
+    pub cpu: i32,
+    pub net: i32,
+    pub discovered_at: Option<DateTime<Utc>>,
+    pub scanned_at: Option<DateTime<Utc>>,
+    pub scanned_firewall: Option<f64>,
+    pub scanned_cpu: Option<i32>,
+    pub scanned_net: Option<i32>,
+}
+
+/// Browser filters; `None` matches everything
+#[derive(Debug, Clone, Default)]
+pub struct BrowserFilter<'a> {
+    pub npc_type: Option<&'a str>,
+    pub npc_tier: Option<i16>,
+    pub region: Option<&'a str>,
+}
+
+pub struct ServerBrowserQueries;
+
+impl ServerBrowserQueries {
+    /// Count a `kind` request against the player's limit of `limit` per
+    /// window; false if they are at it
+    pub async fn take_request(pool: &PgPool, user_id: i64, kind: &str, limit: i64, window_minutes: i64) -> Result<bool> {
+        let taken = sqlx::query_scalar!(
+            r#"
+            INSERT INTO browser_requests (user_id, kind)
+            SELECT $1, $2
+            WHERE (
+                SELECT COUNT(*) FROM browser_requests
+                WHERE user_id = $1 AND kind = $2 AND created_at > NOW() - make_interval(mins => $4)
+            ) < $3
+            RETURNING id
+            "#,
+            user_id,
+            kind,
+            limit,
+            window_minutes as i32
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(taken.is_some())
+    }
+
+    /// Best cracker version on the player's own servers, not counting copies
+    /// locked to another server
+    pub async fn cracker(pool: &PgPool, user_id: i64) -> Result<f64> {
+        let cracker = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(MAX(sw.version), 0)::FLOAT8 AS "cracker!"
+            FROM software sw
+            JOIN servers s ON s.id = sw.server_id
+            WHERE s.user_id = $1 AND s.is_npc = FALSE AND sw.type = 'cracker' AND sw.is_installed
+              AND (sw.license_server_id IS NULL OR sw.license_server_id = sw.server_id)
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(cracker)
+    }
+
+    /// NPC servers matching `filter`, newest first. Clan servers are not
+    /// listed.
+    pub async fn browse(
+        pool: &PgPool,
+        user_id: i64,
+        filter: &BrowserFilter<'_>,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<BrowserServerRow>> {
+        let rows = sqlx::query_as!(
+            BrowserServerRow,
+            r#"
+            SELECT s.id AS server_id, host(s.ip_address) AS "ip!", s.hostname, s.npc_type, s.npc_tier, s.region,
+                COALESCE((SELECT MAX(sw.version) FROM software sw
+                    WHERE sw.server_id = s.id AND sw.type = 'firewall' AND sw.is_running), 0)::FLOAT8 AS "firewall!",
+                s.cpu_total AS cpu, s.net_total AS net,
+                k.discovered_at AS "discovered_at?", k.scanned_at, k.firewall AS scanned_firewall,
+                k.cpu AS scanned_cpu, k.net AS scanned_net
+            FROM servers s
+            LEFT JOIN known_servers k ON k.server_id = s.id AND k.user_id = $1
+            WHERE s.is_npc AND s.is_active
+              AND NOT EXISTS (SELECT 1 FROM clan_servers cs WHERE cs.server_id = s.id)
+              AND ($2::TEXT IS NULL OR s.npc_type = $2)
+              AND ($3::SMALLINT IS NULL OR s.npc_tier = $3)
+              AND ($4::TEXT IS NULL OR s.region = $4)
+              AND ($5::BIGINT IS NULL OR s.id < $5)
+            ORDER BY s.id DESC
+            LIMIT $6
+            "#,
+            user_id,
+            filter.npc_type,
+            filter.npc_tier,
+            filter.region,
+            before_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// NPC servers with an address in `cidr`
+    pub async fn in_range(conn: &mut PgConnection, user_id: i64, cidr: &str) -> Result<Vec<BrowserServerRow>> {
+        let rows = sqlx::query_as!(
+            BrowserServerRow,
+            r#"
+            SELECT s.id AS server_id, host(s.ip_address) AS "ip!", s.hostname, s.npc_type, s.npc_tier, s.region,
+                COALESCE((SELECT MAX(sw.version) FROM software sw
+                    WHERE sw.server_id = s.id AND sw.type = 'firewall' AND sw.is_running), 0)::FLOAT8 AS "firewall!",
+                s.cpu_total AS cpu, s.net_total AS net,
+                k.discovered_at AS "discovered_at?", k.scanned_at, k.firewall AS scanned_firewall,
+                k.cpu AS scanned_cpu, k.net AS scanned_net
+            FROM servers s
+            LEFT JOIN known_servers k ON k.server_id = s.id AND k.user_id = $1
+            WHERE s.is_npc AND s.is_active AND s.ip_address <<= $2::TEXT::CIDR
+              AND NOT EXISTS (SELECT 1 FROM clan_servers cs WHERE cs.server_id = s.id)
+            ORDER BY s.ip_address
+            "#,
+            user_id,
+            cidr
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Add servers a range scan found to the player's database
+    pub async fn discover(conn: &mut PgConnection, user_id: i64, server_ids: &[i64]) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO known_servers (user_id, server_id)
+            SELECT $1, UNNEST($2::BIGINT[])
+            ON CONFLICT (user_id, server_id) DO NOTHING
+            "#,
+            user_id,
+            server_ids
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Store what a completed scan of the NPC server at `ip` found
+    pub async fn record_scan(conn: &mut PgConnection, user_id: i64, ip: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO known_servers (user_id, server_id, scanned_at, firewall, cpu, net)
+            SELECT $1, s.id, NOW(),
+                COALESCE((SELECT MAX(sw.version) FROM software sw
+                    WHERE sw.server_id = s.id AND sw.type = 'firewall' AND sw.is_running), 0)::FLOAT8,
+                s.cpu_total, s.net_total
+            FROM servers s
+            WHERE host(s.ip_address) = $2 AND s.is_npc
+            ON CONFLICT (user_id, server_id) DO UPDATE SET
+                scanned_at = EXCLUDED.scanned_at,
+                firewall = EXCLUDED.firewall,
+                cpu = EXCLUDED.cpu,
+                net = EXCLUDED.net
+            "#,
+            user_id,
+            ip
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The player's server database, latest finds first
+    pub async fn known(
+        pool: &PgPool,
+        user_id: i64,
+        before: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<BrowserServerRow>> {
+        let rows = sqlx::query_as!(
+            BrowserServerRow,
+            r#"
+            SELECT s.id AS server_id, host(s.ip_address) AS "ip!", s.hostname, s.npc_type, s.npc_tier, s.region,
+                COALESCE((SELECT MAX(sw.version) FROM software sw
+                    WHERE sw.server_id = s.id AND sw.type = 'firewall' AND sw.is_running), 0)::FLOAT8 AS "firewall!",
+                s.cpu_total AS cpu, s.net_total AS net,
+                k.discovered_at AS "discovered_at?", k.scanned_at, k.firewall AS scanned_firewall,
+                k.cpu AS scanned_cpu, k.net AS scanned_net
+            FROM known_servers k
+            JOIN servers s ON s.id = k.server_id
+            WHERE k.user_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR k.discovered_at < $2)
+            ORDER BY k.discovered_at DESC
+            LIMIT $3
+            "#,
+            user_id,
+            before,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+pub struct ChatFlagQueries;
+
+impl ChatFlagQueries {
+    /// Flag a message for the terms it matched. `None` if it was flagged
+    /// already.
+    pub async fn flag(conn: &mut PgConnection, message_id: i64, user_id: i64, terms: &[String]) -> Result<Option<i64>> {
+        let flag_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO chat_flags (message_id, user_id, terms)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (message_id) DO NOTHING
+            RETURNING id
+            "#,
+            message_id,
+            user_id,
+            terms
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(flag_id)
+    }
+}
+
+/// What a process running on the player's own hardware uses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessLoad {
+    pub cpu_percent: i32,
+    pub ram_mb: i32,
+    pub hdd_mb: i32,
+    pub net_kbps: i32,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HardwareComponentRow {
+    pub component: String,
+    pub quality: String,
+    pub wear: f64,
+    pub failed_at: Option<DateTime<Utc>>,
+    pub warned_level: i16,
+}
+
+/// Repair state of one component
+#[derive(Debug, Clone)]
+pub struct RepairState {
+    pub component: HardwareComponentRow,
+    pub repairing: bool,
+    pub balance: i64,
+}
+
+pub struct WearQueries;
+
+impl WearQueries {
+    /// Note what process `pid` uses of `hardware_id` until it completes
+    pub async fn record_load(pool: &PgPool, pid: i64, hardware_id: i64, load: &ProcessLoad) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO process_loads (pid, hardware_id, cpu_percent, ram_mb, hdd_mb, net_kbps)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (pid) DO NOTHING
+            "#,
+            pid,
+            hardware_id,
+            load.cpu_percent,
+            load.ram_mb,
+            load.hdd_mb,
+            load.net_kbps
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove and return a completing process's load with its hardware
+    pub async fn take_load(conn: &mut PgConnection, pid: i64) -> Result<Option<(i64, ProcessLoad)>> {
+        let row = sqlx::query!(
+            r#"
+            DELETE FROM process_loads WHERE pid = $1
+            RETURNING hardware_id, cpu_percent, ram_mb, hdd_mb, net_kbps
+            "#,
+            pid
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row.map(|r| {
+            (r.hardware_id, ProcessLoad { cpu_percent: r.cpu_percent, ram_mb: r.ram_mb, hdd_mb: r.hdd_mb, net_kbps: r.net_kbps })
+        }))
+    }
+
+    /// What the other processes still running on `hardware_id` use
+    pub async fn running_load(conn: &mut PgConnection, hardware_id: i64) -> Result<ProcessLoad> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                COALESCE(SUM(l.cpu_percent), 0)::INT AS "cpu!",
+                COALESCE(SUM(l.ram_mb), 0)::INT AS "ram!",
+                COALESCE(SUM(l.hdd_mb), 0)::INT AS "hdd!",
+                COALESCE(SUM(l.net_kbps), 0)::INT AS "net!"
+            FROM process_loads l
+            JOIN processes p ON p.pid = l.pid
+            WHERE l.hardware_id = $1 AND p.completed_at IS NULL
+            "#,
+            hardware_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(ProcessLoad { cpu_percent: row.cpu, ram_mb: row.ram, hdd_mb: row.hdd, net_kbps: row.net })
+    }
+
+    pub async fn hardware(conn: &mut PgConnection, hardware_id: i64) -> Result<Option<Hardware>> {
+        let hardware = sqlx::query_as!(Hardware, "SELECT * FROM hardware WHERE hardware_id = $1", hardware_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(hardware)
+    }
+
+    /// Every component of `hardware_id`, created fresh where missing, locked
+    /// for update
+    pub async fn lock_components(conn: &mut PgConnection, hardware_id: i64) -> Result<Vec<HardwareComponentRow>> {
+        sqlx::query!(
+            r#"
+            INSERT INTO hardware_components (hardware_id, component)
+            SELECT $1, c FROM unnest(ARRAY['cpu', 'ram', 'hdd', 'net']) AS c
+            ON CONFLICT (hardware_id, component) DO NOTHING
+            "#,
+            hardware_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let rows = sqlx::query_as!(
+            HardwareComponentRow,
+            r#"
+            SELECT component, quality, wear, failed_at, warned_level
+            FROM hardware_components WHERE hardware_id = $1
+            ORDER BY component
+            FOR UPDATE
+            "#,
+            hardware_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Components of `hardware_id` that were ever worn; the others are new
+    pub async fn components(pool: &PgPool, hardware_id: i64) -> Result<Vec<HardwareComponentRow>> {
+        let rows = sqlx::query_as!(
+            HardwareComponentRow,
+            r#"
+            SELECT component, quality, wear, failed_at, warned_level
+            FROM hardware_components WHERE hardware_id = $1
+            ORDER BY component
+            "#,
+            hardware_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Set a component's wear and warned condition, marking it failed if
+    /// `failed` and it was not already
+    pub async fn update_component(
+        conn: &mut PgConnection,
+        hardware_id: i64,
+        component: &str,
+        wear: f64,
+        failed: bool,
+        warned_level: i16,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE hardware_components
+            SET wear = $3,
+                failed_at = CASE WHEN $4 THEN COALESCE(failed_at, NOW()) ELSE failed_at END,
+                warned_level = $5,
+                updated_at = NOW()
+            WHERE hardware_id = $1 AND component = $2
+            "#,
+            hardware_id,
+            component,
+            wear,
+            failed,
+            warned_level
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// A component's state, whether a repair of it is running, and the
+    /// player's balance
+    pub async fn repair_state(pool: &PgPool, user_id: i64, hardware_id: i64, component: &str) -> Result<RepairState> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                c.quality AS "quality?",
+                c.wear AS "wear?",
+                c.failed_at,
+                c.warned_level AS "warned_level?",
+                EXISTS (
+                    SELECT 1 FROM hardware_repairs r JOIN processes p ON p.pid = r.pid
+                    WHERE r.hardware_id = $2 AND r.component = $3
+                        AND r.completed_at IS NULL AND p.completed_at IS NULL
+                ) AS "repairing!",
+                COALESCE((
+                    SELECT balance FROM bank_accounts
+                    WHERE user_id = $1 AND is_active = TRUE
+                    ORDER BY id
+                    LIMIT 1
+                ), 0) AS "balance!"
+            FROM (SELECT 1) AS one
+            LEFT JOIN hardware_components c ON c.hardware_id = $2 AND c.component = $3
+            "#,
+            user_id,
+            hardware_id,
+            component
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(RepairState {
+            component: HardwareComponentRow {
+                component: component.to_string(),
+                quality: row.quality.unwrap_or_else(|| "standard".to_string()),
+                wear: row.wear.unwrap_or(0.0),
+                failed_at: row.failed_at,
+                warned_level: row.warned_level.unwrap_or(0),
+            },
+            repairing: row.repairing,
+            balance: row.balance,
+        })
+    }
+
+    /// Pay for the repair started as process `pid`. False if a repair of
+    /// the component is already running or the player cannot pay. The
+    /// component row is locked so two repairs cannot both be charged.
+    pub async fn charge_repair(
+        pool: &PgPool,
+        user_id: i64,
+        pid: i64,
+        hardware_id: i64,
+        component: &str,
+        quality: &str,
+        cost: i64,
+    ) -> Result<bool> {
+        let mut tx = pool.begin().await?;
+        Self::lock_components(&mut *tx, hardware_id).await?;
+
+        let repairing = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM hardware_repairs r JOIN processes p ON p.pid = r.pid
+                WHERE r.hardware_id = $1 AND r.component = $2
+                    AND r.completed_at IS NULL AND p.completed_at IS NULL
+            ) AS "repairing!"
+            "#,
+            hardware_id,
+            component
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if repairing {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        let reference = format!("process:{}", pid);
+        let debited = BankQueries::debit_primary_account(
+            &mut *tx,
+            user_id,
+            cost,
+            LedgerAccount::Sink,
+            LedgerReason::HardwareRepair,
+            &reference,
+        )
+        .await?;
+        if !debited {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO hardware_repairs (pid, user_id, hardware_id, component, quality, cost)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            pid,
+            user_id,
+            hardware_id,
+            component,
+            quality,
+            cost
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Finish the repair run as process `pid`: the component is as new, in
+    /// the quality paid for. Returns its hardware and component.
+    pub async fn finish_repair(conn: &mut PgConnection, pid: i64) -> Result<Option<(i64, String, String)>> {
+        let Some(repair) = sqlx::query!(
+            r#"
+            UPDATE hardware_repairs SET completed_at = NOW()
+            WHERE pid = $1 AND completed_at IS NULL
+            RETURNING hardware_id, component, quality
+            "#,
+            pid
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO hardware_components (hardware_id, component, quality)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (hardware_id, component) DO UPDATE
+            SET quality = EXCLUDED.quality, wear = 0, failed_at = NULL, warned_level = 0, updated_at = NOW()
+            "#,
+            repair.hardware_id,
+            repair.component,
+            repair.quality
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(Some((repair.hardware_id, repair.component, repair.quality)))
+    }
+}
+
+/// A registered webhook, without its secret
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WebhookRow {
+    pub id: i64,
+    pub owner_kind: String,
+    pub owner_id: i64,
+    pub url: String,
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub consecutive_failures: i32,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub disabled_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A leased delivery, with what is needed to post it
+#[derive(Debug, Clone)]
+pub struct WebhookJob {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub url: String,
+    pub secret: String,
+    pub dedup_key: String,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+}
+
+/// A delivery as its webhook's owner sees it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WebhookDeliveryRow {
+    pub id: i64,
+    pub event: String,
+    pub status: String,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+pub struct WebhookQueries;
+
+impl WebhookQueries {
+    /// Register a webhook unless its owner already has `max` of them
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        pool: &PgPool,
+        owner_kind: &str,
+        owner_id: i64,
+        url: &str,
+        secret: &str,
+        events: &[String],
+        created_by: i64,
+        max: i64,
+    ) -> Result<Option<WebhookRow>> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        // Serializes registrations of one owner, so the limit holds
+        sqlx::query!(
+            "SELECT pg_advisory_xact_lock(hashtext('webhooks:' || $1::TEXT || ':' || $2::BIGINT::TEXT))",
+            owner_kind,
+            owner_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let row = sqlx::query_as!(
+            WebhookRow,
+            r#"
+            INSERT INTO webhooks (owner_kind, owner_id, url, secret, events, created_by)
+            SELECT $1, $2, $3, $4, $5, $6
+            WHERE (SELECT COUNT(*) FROM webhooks WHERE owner_kind = $1 AND owner_id = $2) < $7
+            RETURNING id, owner_kind, owner_id, url, events, enabled, consecutive_failures,
+                      disabled_at, disabled_reason, created_at
+            "#,
+            owner_kind,
+            owner_id,
+            url,
+            secret,
+            events,
+            created_by,
+            max
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(row)
+    }
+
+    pub async fn list(pool: &PgPool, owner_kind: &str, owner_id: i64) -> Result<Vec<WebhookRow>> {
+        let rows = sqlx::query_as!(
+            WebhookRow,
+            r#"
+            SELECT id, owner_kind, owner_id, url, events, enabled, consecutive_failures,
+                   disabled_at, disabled_reason, created_at
+            FROM webhooks
+            WHERE owner_kind = $1 AND owner_id = $2
+            ORDER BY id
+            "#,
+            owner_kind,
+            owner_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn get(pool: &PgPool, webhook_id: i64) -> Result<Option<WebhookRow>> {
+        let row = sqlx::query_as!(
+            WebhookRow,
+            r#"
+            SELECT id, owner_kind, owner_id, url, events, enabled, consecutive_failures,
+                   disabled_at, disabled_reason, created_at
+            FROM webhooks
+            WHERE id = $1
+            "#,
+            webhook_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn delete(pool: &PgPool, webhook_id: i64) -> Result<bool> {
+        let result = sqlx::query!("DELETE FROM webhooks WHERE id = $1", webhook_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Turn a webhook on or off. Turning it on forgets its failures.
+    pub async fn set_enabled(pool: &PgPool, webhook_id: i64, enabled: bool) -> Result<Option<WebhookRow>> {
+        let row = sqlx::query_as!(
+            WebhookRow,
+            r#"
+            UPDATE webhooks
+            SET enabled = $2,
+                consecutive_failures = CASE WHEN $2 THEN 0 ELSE consecutive_failures END,
+                disabled_at = CASE WHEN $2 THEN NULL ELSE NOW() END,
+                disabled_reason = CASE WHEN $2 THEN NULL ELSE 'disabled by owner' END
+            WHERE id = $1
+            RETURNING id, owner_kind, owner_id, url, events, enabled, consecutive_failures,
+                      disabled_at, disabled_reason, created_at
+            "#,
+            webhook_id,
+            enabled
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// The clan `user_id` belongs to, if any
+    pub async fn clan_of(conn: &mut PgConnection, user_id: i64) -> Result<Option<i64>> {
+        let clan_id = sqlx::query_scalar!(
+            "SELECT clan_id FROM clan_members WHERE user_id = $1 ORDER BY joined_at LIMIT 1",
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(clan_id)
+    }
+
+    /// Names shown in webhook payloads
+    pub async fn clan_name(conn: &mut PgConnection, clan_id: i64) -> Result<Option<String>> {
+        let name = sqlx::query_scalar!("SELECT name FROM clans WHERE id = $1", clan_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(name)
+    }
+
+    pub async fn username(conn: &mut PgConnection, user_id: i64) -> Result<Option<String>> {
+        let login = sqlx::query_scalar!("SELECT login FROM users WHERE id = $1", user_id)
+            .fetch_optional(conn)
+            .await?;
+
+        Ok(login)
+    }
+
+    /// Queue `event` for every enabled webhook of the owner subscribed to it,
+    /// in the caller's transaction. Returns how many deliveries were queued;
+    /// a key already queued for a webhook is ignored.
+    pub async fn emit(
+        conn: &mut PgConnection,
+        owner_kind: &str,
+        owner_id: i64,
+        event: &str,
+        dedup_key: &str,
+        payload: &serde_json::Value,
+    ) -> Result<i32> {
+        let queued = sqlx::query_scalar!(
+            r#"SELECT webhook_emit($1, $2, $3, $4, $5) AS "queued!""#,
+            owner_kind,
+            owner_id,
+            event,
+            dedup_key,
+            payload
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(queued)
+    }
+
+    /// Lease up to `limit` due deliveries of enabled webhooks, oldest first.
+    /// A leased delivery is not due again for `lease_secs`.
+    pub async fn lease_due(pool: &PgPool, limit: i64, lease_secs: i64) -> Result<Vec<WebhookJob>> {
+        let rows = sqlx::query_as!(
+            WebhookJob,
+            r#"
+            WITH leased AS (
+                UPDATE webhook_deliveries
+                SET attempts = attempts + 1, next_attempt_at = NOW() + make_interval(secs => $2)
+                WHERE id IN (
+                    SELECT d.id FROM webhook_deliveries d
+                    JOIN webhooks w ON w.id = d.webhook_id
+                    WHERE d.status = 'pending' AND d.next_attempt_at <= NOW() AND w.enabled
+                    ORDER BY d.id
+                    LIMIT $1
+                    FOR UPDATE OF d SKIP LOCKED
+                )
+                RETURNING id, webhook_id, dedup_key, event, payload, attempts
+            )
+            SELECT l.id AS "id!", l.webhook_id AS "webhook_id!", w.url, w.secret,
+                   l.dedup_key AS "dedup_key!", l.event AS "event!", l.payload AS "payload!",
+                   l.attempts AS "attempts!"
+            FROM leased l
+            JOIN webhooks w ON w.id = l.webhook_id
+            ORDER BY l.id
+            "#,
+            limit,
+            lease_secs as f64
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Record a successful delivery; the webhook's failure streak ends
+    pub async fn mark_delivered(pool: &PgPool, delivery_id: i64, webhook_id: i64, response_status: i32) -> Result<()> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'delivered', delivered_at = NOW(), response_status = $2, last_error = NULL
+            WHERE id = $1
+            "#,
+            delivery_id,
+            response_status
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("UPDATE webhooks SET consecutive_failures = 0 WHERE id = $1", webhook_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt. The delivery is retried in `retry_in_secs`,
+    /// or given up on when `None`. The webhook is disabled, and its pending
+    /// deliveries given up on, once it has failed `disable_after` times in a
+    /// row; returns whether that happened now.
+    pub async fn record_failure(
+        pool: &PgPool,
+        delivery_id: i64,
+        webhook_id: i64,
+        response_status: Option<i32>,
+        error: &str,
+        retry_in_secs: Option<i64>,
+        disable_after: i32,
+    ) -> Result<bool> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = CASE WHEN $4::FLOAT8 IS NULL THEN 'failed' ELSE 'pending' END,
+                next_attempt_at = NOW() + make_interval(secs => COALESCE($4::FLOAT8, 0)),
+                response_status = $2,
+                last_error = $3
+            WHERE id = $1
+            "#,
+            delivery_id,
+            response_status,
+            error,
+            retry_in_secs.map(|secs| secs as f64)
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let disabled = sqlx::query_scalar!(
+            r#"
+            WITH old AS (SELECT id, enabled FROM webhooks WHERE id = $1 FOR UPDATE)
+            UPDATE webhooks w
+            SET consecutive_failures = w.consecutive_failures + 1,
+                enabled = w.enabled AND w.consecutive_failures + 1 < $2,
+                disabled_at = CASE WHEN w.enabled AND w.consecutive_failures + 1 >= $2
+                    THEN NOW() ELSE w.disabled_at END,
+                disabled_reason = CASE WHEN w.enabled AND w.consecutive_failures + 1 >= $2
+                    THEN 'too many failed deliveries' ELSE w.disabled_reason END
+            FROM old
+            WHERE w.id = old.id
+            RETURNING (old.enabled AND NOT w.enabled) AS "disabled!"
+            "#,
+            webhook_id,
+            disable_after
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .unwrap_or(false);
+
+        if disabled {
+            sqlx::query!(
+                r#"
+                UPDATE webhook_deliveries
+                SET status = 'failed', last_error = 'webhook disabled'
+                WHERE webhook_id = $1 AND status = 'pending'
+                "#,
+                webhook_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(disabled)
+    }
+
+    /// The webhook's latest deliveries, newest first
+    pub async fn deliveries(pool: &PgPool, webhook_id: i64, limit: i64) -> Result<Vec<WebhookDeliveryRow>> {
+        let rows = sqlx::query_as!(
+            WebhookDeliveryRow,
+            r#"
+            SELECT id, event, status, attempts, response_status, last_error,
+                   next_attempt_at, created_at, delivered_at
+            FROM webhook_deliveries
+            WHERE webhook_id = $1
+            ORDER BY id DESC
+            LIMIT $2
+            "#,
+            webhook_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Delete finished deliveries older than `keep_days`
+    pub async fn prune(pool: &PgPool, keep_days: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM webhook_deliveries
+            WHERE status <> 'pending' AND created_at < NOW() - make_interval(days => $1)
+            "#,
+            keep_days as i32
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// A registered field encryption key version
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct KeyVersionRow {
+    pub version: i32,
+    pub fingerprint: String,
+    pub state: String,
+    pub registered_at: DateTime<Utc>,
+    pub activated_at: Option<DateTime<Utc>>,
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+/// The key versions a node last reported holding
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NodeKeysRow {
+    pub node_id: String,
+    pub versions: Vec<i32>,
+    pub fingerprints: Vec<String>,
+    pub active_version: Option<i32>,
+    pub seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RotationRow {
+    pub id: i64,
+    pub from_version: Option<i32>,
+    pub to_version: i32,
+    pub started_by: String,
+    pub started_at: DateTime<Utc>,
+    pub rows_resealed: i64,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// How many values of a field are sealed under a key version; `None` for
+/// plaintext
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct KeyUsageRow {
+    pub field: String,
+    pub key_version: Option<i32>,
+    pub rows: i64,
+}
+
+/// An account's sealed fields, locked for resealing
+#[derive(Debug, Clone)]
+pub struct SealedAccountRow {
+    pub id: i64,
+    pub email: String,
+    pub last_ip: Option<String>,
+}
+
+/// Field encryption key registry, rotations and resealing
+pub struct EncryptionQueries;
+
+impl EncryptionQueries {
+    pub async fn key_versions(pool: &PgPool) -> Result<Vec<KeyVersionRow>> {
+        let rows = sqlx::query_as!(
+            KeyVersionRow,
+            r#"
+            SELECT version, fingerprint, state, registered_at, activated_at, retired_at
+            FROM encryption_keys
+            ORDER BY version
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn active_version(pool: &PgPool) -> Result<Option<i32>> {
+        let version = sqlx::query_scalar!("SELECT version FROM encryption_keys WHERE state = 'active'")
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(version)
+    }
+
+    /// Register `version` as active if no key is registered yet, with a
+    /// rotation sealing the plaintext rows. Returns whether it was.
+    pub async fn bootstrap(pool: &PgPool, version: i32, fingerprint: &str, node_id: &str) -> Result<bool> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        sqlx::query!("LOCK TABLE encryption_keys IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await?;
+
+        let registered = sqlx::query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM encryption_keys) AS "exists!""#)
+            .fetch_one(&mut *tx)
+            .await?;
+        if registered {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO encryption_keys (version, fingerprint, state, activated_at)
+            VALUES ($1, $2, 'active', NOW())
+            "#,
+            version,
+            fingerprint
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO encryption_rotations (from_version, to_version, started_by) VALUES (NULL, $1, $2)",
+            version,
+            format!("bootstrap:{}", node_id)
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    pub async fn report_node(
+        pool: &PgPool,
+        node_id: &str,
+        versions: &[i32],
+        fingerprints: &[String],
+        active_version: Option<i32>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO encryption_nodes (node_id, versions, fingerprints, active_version, seen_at)
+            VALUES ($1, $2, $3, $4, NOW())
+            ON CONFLICT (node_id) DO UPDATE
+            SET versions = $2, fingerprints = $3, active_version = $4, seen_at = NOW()
+            "#,
+            node_id,
+            versions,
+            fingerprints,
+            active_version
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Nodes that reported in the last `within_secs`
+    pub async fn live_nodes(pool: &PgPool, within_secs: i64) -> Result<Vec<NodeKeysRow>> {
+        let rows = sqlx::query_as!(
+            NodeKeysRow,
+            r#"
+            SELECT node_id, versions, fingerprints, active_version, seen_at
+            FROM encryption_nodes
+            WHERE seen_at > NOW() - make_interval(secs => $1)
+            ORDER BY node_id
+            "#,
+            within_secs as f64
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn open_rotation(pool: &PgPool) -> Result<Option<RotationRow>> {
+        let row = sqlx::query_as!(
+            RotationRow,
+            r#"
+            SELECT id, from_version, to_version, started_by, started_at, rows_resealed, completed_at
+            FROM encryption_rotations
+            WHERE completed_at IS NULL
+            "#
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Make `version` active, registering it if needed, and open a rotation
+    /// resealing everything under the previous one. `None` if a rotation is
+    /// already open.
+    pub async fn start_rotation(
+        pool: &PgPool,
+        version: i32,
+        fingerprint: &str,
+        started_by: &str,
+    ) -> Result<Option<RotationRow>> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        sqlx::query!("LOCK TABLE encryption_keys IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await?;
+
+        let open = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM encryption_rotations WHERE completed_at IS NULL) AS "open!""#
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if open {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+
+        let previous = sqlx::query_scalar!(
+            r#"
+            UPDATE encryption_keys SET state = 'retiring'
+            WHERE state = 'active'
+            RETURNING version
+            "#
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO encryption_keys (version, fingerprint, state, activated_at)
+            VALUES ($1, $2, 'active', NOW())
+            ON CONFLICT (version) DO UPDATE SET state = 'active', activated_at = NOW(), retired_at = NULL
+            "#,
+            version,
+            fingerprint
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let rotation = sqlx::query_as!(
+            RotationRow,
+            r#"
+            INSERT INTO encryption_rotations (from_version, to_version, started_by)
+            VALUES ($1, $2, $3)
+            RETURNING id, from_version, to_version, started_by, started_at, rows_resealed, completed_at
+            "#,
+            previous,
+            version,
+            started_by
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some(rotation))
+    }
+
+    /// Count resealed rows of the open rotation, closing it if `complete`
+    pub async fn record_resealed(conn: &mut PgConnection, rotation_id: i64, resealed: i64, complete: bool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE encryption_rotations
+            SET rows_resealed = rows_resealed + $2,
+                completed_at = CASE WHEN $3 THEN NOW() ELSE NULL END
+            WHERE id = $1 AND completed_at IS NULL
+            "#,
+            rotation_id,
+            resealed,
+            complete
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark a retiring version retired. `false` if it is not retiring.
+    pub async fn retire(pool: &PgPool, version: i32) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE encryption_keys SET state = 'retired', retired_at = NOW() WHERE version = $1 AND state = 'retiring'",
+            version
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Sealed and plaintext values per field and key version
+    pub async fn key_usage(pool: &PgPool) -> Result<Vec<KeyUsageRow>> {
+        let rows = sqlx::query_as!(
+            KeyUsageRow,
+            r#"
+            SELECT 'users.email' AS "field!", email_key_version AS key_version, COUNT(*) AS "rows!"
+            FROM users GROUP BY email_key_version
+            UNION ALL
+            SELECT 'users.last_ip', last_ip_key_version, COUNT(*)
+            FROM users WHERE last_ip IS NOT NULL GROUP BY last_ip_key_version
+            UNION ALL
+            SELECT 'user_ip_history.ip', key_version, COUNT(*)
+            FROM user_ip_history GROUP BY key_version
+            ORDER BY 1, 2
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Lock up to `limit` accounts with a field not sealed under `version`
+    pub async fn lock_stale_accounts(conn: &mut PgConnection, version: i32, limit: i64) -> Result<Vec<SealedAccountRow>> {
+        let rows = sqlx::query_as!(
+            SealedAccountRow,
+            r#"
+            SELECT id, email, last_ip
+            FROM users
+            WHERE email_key_version IS DISTINCT FROM $1
+               OR (last_ip IS NOT NULL AND last_ip_key_version IS DISTINCT FROM $1)
+            ORDER BY id
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+            "#,
+            version,
+            limit
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update_account_fields(
+        conn: &mut PgConnection,
+        user_id: i64,
+        email: &str,
+        email_index: Option<&str>,
+        email_key_version: Option<i32>,
+        last_ip: Option<&str>,
+        last_ip_key_version: Option<i32>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET email = $2, email_index = $3, email_key_version = $4,
+                last_ip = $5, last_ip_key_version = $6
+            WHERE id = $1
+            "#,
+            user_id,
+            email,
+            email_index,
+            email_key_version,
+            last_ip,
+            last_ip_key_version
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Lock up to `limit` IP history entries not sealed under `version`
+    pub async fn lock_stale_ip_history(conn: &mut PgConnection, version: i32, limit: i64) -> Result<Vec<(i64, String)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, ip FROM user_ip_history
+            WHERE key_version IS DISTINCT FROM $1
+            ORDER BY id
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+            "#,
+            version,
+            limit
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.ip)).collect())
+    }
+
+    pub async fn update_ip_history(
+        conn: &mut PgConnection,
+        id: i64,
+        ip: &str,
+        ip_index: Option<&str>,
+        key_version: Option<i32>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "UPDATE user_ip_history SET ip = $2, ip_index = $3, key_version = $4 WHERE id = $1",
+            id,
+            ip,
+            ip_index,
+            key_version
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TerminalAliasRow {
+    pub name: String,
+    pub expansion: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Terminal aliases, cooldowns and completion data
+pub struct TerminalQueries;
+
+impl TerminalQueries {
+    /// The player's level, for alias slots and chain length
+    pub async fn level(pool: &PgPool, user_id: i64) -> Result<i32> {
+        let player_id = legacy_user_uuid(user_id);
+        let level = sqlx::query_scalar!(
+            r#"SELECT COALESCE((SELECT level FROM player_progression WHERE player_id = $1), 1) AS "level!""#,
+            player_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(level)
+    }
+
+    pub async fn aliases(pool: &PgPool, user_id: i64) -> Result<Vec<TerminalAliasRow>> {
+        let aliases = sqlx::query_as!(
+            TerminalAliasRow,
+            "SELECT name, expansion, updated_at FROM terminal_aliases WHERE user_id = $1 ORDER BY name",
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(aliases)
+    }
+
+    /// Lock the player's aliases while one is being checked and saved
+    pub async fn lock_aliases(conn: &mut PgConnection, user_id: i64) -> Result<Vec<TerminalAliasRow>> {
+        sqlx::query!("SELECT pg_advisory_xact_lock(hashtext('terminal_aliases:' || $1::BIGINT::TEXT))", user_id)
+            .execute(&mut *conn)
+            .await?;
+        let aliases = sqlx::query_as!(
+            TerminalAliasRow,
+            "SELECT name, expansion, updated_at FROM terminal_aliases WHERE user_id = $1 ORDER BY name",
+            user_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(aliases)
+    }
+
+    pub async fn save_alias(conn: &mut PgConnection, user_id: i64, name: &str, expansion: &str) -> Result<TerminalAliasRow> {
+        let alias = sqlx::query_as!(
+            TerminalAliasRow,
+            r#"
+            INSERT INTO terminal_aliases (user_id, name, expansion)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, name) DO UPDATE SET expansion = $3, updated_at = NOW()
+            RETURNING name, expansion, updated_at
+            "#,
+            user_id,
+            name,
+            expansion
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(alias)
+    }
+
+    pub async fn delete_alias(pool: &PgPool, user_id: i64, name: &str) -> Result<bool> {
+        let result = sqlx::query!("DELETE FROM terminal_aliases WHERE user_id = $1 AND name = $2", user_id, name)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record a run of `command` unless the last one was under `cooldown_ms`
+    /// ago. Returns the milliseconds left to wait, `None` if claimed.
+    pub async fn claim_cooldown(pool: &PgPool, user_id: i64, command: &str, cooldown_ms: i64) -> Result<Option<i64>> {
+        let claimed = sqlx::query_scalar!(
+            r#"
+            INSERT INTO terminal_cooldowns (user_id, command, last_run_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (user_id, command) DO UPDATE SET last_run_at = NOW()
+            WHERE terminal_cooldowns.last_run_at <= NOW() - make_interval(secs => $3)
+            RETURNING 1 AS "claimed!"
+            "#,
+            user_id,
+            command,
+            cooldown_ms as f64 / 1000.0
+        )
+        .fetch_optional(pool)
+        .await?;
+        if claimed.is_some() {
+            return Ok(None);
+        }
+
+        let remaining = sqlx::query_scalar!(
+            r#"
+            SELECT GREATEST(0, EXTRACT(EPOCH FROM (last_run_at + make_interval(secs => $3) - NOW())) * 1000)::BIGINT
+                AS "remaining!"
+            FROM terminal_cooldowns
+            WHERE user_id = $1 AND command = $2
+            "#,
+            user_id,
+            command,
+            cooldown_ms as f64 / 1000.0
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Some(remaining.max(1)))
+    }
+
+    /// Addresses in the player's hacked database starting with `prefix`
+    pub async fn known_ips(pool: &PgPool, user_id: i64, prefix: &str, limit: i64) -> Result<Vec<String>> {
+        let ips = sqlx::query_scalar!(
+            r#"
+            SELECT host(ip_address) AS "ip!"
+            FROM hacked_database
+            WHERE user_id = $1 AND invalidated_at IS NULL AND host(ip_address) LIKE $2 || '%'
+            ORDER BY hacked_at DESC
+            LIMIT $3
+            "#,
+            user_id,
+            prefix.replace(['%', '_', '\\'], ""),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(ips)
+    }
+
+    /// Names of the files starting with `prefix` on the server the player's
+    /// newest open tunnel reaches, with its address
+    pub async fn connected_files(
+        pool: &PgPool,
+        user_id: i64,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Option<(String, Vec<String>)>> {
+        let target = sqlx::query_scalar!(
+            r#"
+            SELECT host(target_ip) AS "ip!"
+            FROM tunnels
+            WHERE user_id = $1 AND closed_at IS NULL
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        let Some(target) = target else {
+            return Ok(None);
+        };
+
+        let files = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT sw.name
+            FROM software sw
+            JOIN servers s ON s.id = sw.server_id
+            WHERE s.ip_address = $1::text::inet AND sw.name ILIKE $2 || '%'
+            ORDER BY sw.name
+            LIMIT $3
+            "#,
+            target,
+            prefix.replace(['%', '_', '\\'], ""),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Some((target, files)))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PanicLeverRow {
+    pub lever: String,
+    pub engaged: bool,
+    pub reason: Option<String>,
+    pub engaged_by: Option<i64>,
+    pub engaged_at: Option<DateTime<Utc>>,
+    pub released_by: Option<i64>,
+    pub released_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PanicLeverEventRow {
+    pub id: i64,
+    pub lever: String,
+    pub action: String,
+    pub admin_id: Option<i64>,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Incident-response levers and their history
+pub struct PanicLeverQueries;
+
+impl PanicLeverQueries {
+    pub async fn list(pool: &PgPool) -> Result<Vec<PanicLeverRow>> {
+        let levers = sqlx::query_as!(
+            PanicLeverRow,
+            r#"
+            SELECT lever, engaged, reason, engaged_by, engaged_at, released_by, released_at
+            FROM panic_levers
+            ORDER BY lever
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(levers)
+    }
+
+    /// Engage or release `lever`. `None` if it already was.
+    pub async fn set(
+        pool: &PgPool,
+        lever: &str,
+        engaged: bool,
+        admin_id: i64,
+        reason: &str,
+    ) -> Result<Option<PanicLeverRow>> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        let row = sqlx::query_as!(
+            PanicLeverRow,
+            r#"
+            UPDATE panic_levers
+            SET engaged = $2,
+                reason = CASE WHEN $2 THEN $4 ELSE reason END,
+                engaged_by = CASE WHEN $2 THEN $3 ELSE engaged_by END,
+                engaged_at = CASE WHEN $2 THEN NOW() ELSE engaged_at END,
+                released_by = CASE WHEN $2 THEN NULL ELSE $3 END,
+                released_at = CASE WHEN $2 THEN NULL ELSE NOW() END
+            WHERE lever = $1 AND engaged <> $2
+            RETURNING lever, engaged, reason, engaged_by, engaged_at, released_by, released_at
+            "#,
+            lever,
+            engaged,
+            admin_id,
+            reason
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if row.is_some() {
+            sqlx::query!(
+                "INSERT INTO panic_lever_events (lever, action, admin_id, reason) VALUES ($1, $2, $3, $4)",
+                lever,
+                if engaged { "engage" } else { "release" },
+                admin_id,
+                reason
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(row)
+    }
+
+    pub async fn events(pool: &PgPool, limit: i64) -> Result<Vec<PanicLeverEventRow>> {
+        let events = sqlx::query_as!(
+            PanicLeverEventRow,
+            r#"
+            SELECT id, lever, action, admin_id, reason, created_at
+            FROM panic_lever_events
+            ORDER BY created_at DESC
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(events)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PuzzleRow {
+    pub id: i64,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub reward_money: i64,
+    pub reward_experience: i64,
+    pub stages: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PuzzleStageRow {
+    pub puzzle_id: i64,
+    pub stage: i32,
+    pub server_ip: String,
+    pub file_name: String,
+    pub content: String,
+    pub cipher_key: Option<String>,
+    pub key_server_ip: Option<String>,
+    pub key_file_name: Option<String>,
+    pub window_start_minute: Option<i32>,
+    pub window_minutes: Option<i32>,
+    pub requires_milestone_id: Option<i64>,
+    pub answer_hash: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PuzzleProgressRow {
+    pub user_id: i64,
+    pub puzzle_id: i64,
+    pub stage: i32,
+    pub wrong_attempts: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub rewarded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PuzzleMilestoneRow {
+    pub id: i64,
+    pub puzzle_id: i64,
+    /// Counts players past this stage; `None` counts those who finished
+    pub stage: Option<i32>,
+    pub players_required: i32,
+    pub title: String,
+    pub message: String,
+    pub reached_at: Option<DateTime<Utc>>,
+    /// Players counting towards it now
+    pub players: i64,
+}
+
+/// Storyline puzzles, player progress and community milestones
+pub struct PuzzleQueries;
+
+impl PuzzleQueries {
+    pub async fn puzzles(pool: &PgPool) -> Result<Vec<PuzzleRow>> {
+        let puzzles = sqlx::query_as!(
+            PuzzleRow,
+            r#"
+            SELECT p.id, p.slug, p.title, p.description, p.reward_money, p.reward_experience,
+                   (SELECT COUNT(*) FROM puzzle_stages s WHERE s.puzzle_id = p.id) AS "stages!"
+            FROM puzzles p
+            WHERE p.active
+            ORDER BY p.id
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(puzzles)
+    }
+
+    pub async fn puzzle(conn: &mut PgConnection, puzzle_id: i64) -> Result<Option<PuzzleRow>> {
+        let puzzle = sqlx::query_as!(
+            PuzzleRow,
+            r#"
+            SELECT p.id, p.slug, p.title, p.description, p.reward_money, p.reward_experience,
+                   (SELECT COUNT(*) FROM puzzle_stages s WHERE s.puzzle_id = p.id) AS "stages!"
+            FROM puzzles p
+            WHERE p.id = $1 AND p.active
+            "#,
+            puzzle_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(puzzle)
+    }
+
+    pub async fn stage(conn: &mut PgConnection, puzzle_id: i64, stage: i32) -> Result<Option<PuzzleStageRow>> {
+        let stage = sqlx::query_as!(
+            PuzzleStageRow,
+            r#"
+            SELECT puzzle_id, stage, host(server_ip) AS "server_ip!", file_name, content,
+                   cipher_key, host(key_server_ip) AS key_server_ip, key_file_name,
+                   window_start_minute, window_minutes, requires_milestone_id, answer_hash
+            FROM puzzle_stages
+            WHERE puzzle_id = $1 AND stage = $2
+            "#,
+            puzzle_id,
+            stage
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(stage)
+    }
+
+    /// The stages `user_id` is working on whose file or key sits on `ip`
+    pub async fn stages_on_server(pool: &PgPool, user_id: i64, ip: &str) -> Result<Vec<PuzzleStageRow>> {
+        let stages = sqlx::query_as!(
+            PuzzleStageRow,
+            r#"
+            SELECT s.puzzle_id, s.stage, host(s.server_ip) AS "server_ip!", s.file_name, s.content,
+                   s.cipher_key, host(s.key_server_ip) AS key_server_ip, s.key_file_name,
+                   s.window_start_minute, s.window_minutes, s.requires_milestone_id, s.answer_hash
+            FROM puzzle_progress pp
+            JOIN puzzles p ON p.id = pp.puzzle_id AND p.active
+            JOIN puzzle_stages s ON s.puzzle_id = pp.puzzle_id AND s.stage = pp.stage
+            WHERE pp.user_id = $1 AND pp.completed_at IS NULL
+              AND (s.server_ip = $2::text::inet OR s.key_server_ip = $2::text::inet)
+            ORDER BY s.puzzle_id
+            "#,
+            user_id,
+            ip
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(stages)
+    }
+
+    /// Whether the player holds valid credentials for `ip`
+    pub async fn has_hacked(conn: &mut PgConnection, user_id: i64, ip: &str) -> Result<bool> {
+        let hacked = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM hacked_database
+                WHERE user_id = $1 AND ip_address = $2::text::inet AND invalidated_at IS NULL
+            ) AS "hacked!"
+            "#,
+            user_id,
+            ip
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(hacked)
+    }
+
+    pub async fn progress(pool: &PgPool, user_id: i64) -> Result<Vec<PuzzleProgressRow>> {
+        let progress = sqlx::query_as!(
+            PuzzleProgressRow,
+            r#"
+            SELECT user_id, puzzle_id, stage, wrong_attempts, locked_until, started_at, completed_at, rewarded_at
+            FROM puzzle_progress
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(progress)
+    }
+
+    /// Start every active puzzle the player has not started
+    pub async fn start_all(pool: &PgPool, user_id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO puzzle_progress (user_id, puzzle_id)
+            SELECT $1, id FROM puzzles WHERE active
+            ON CONFLICT (user_id, puzzle_id) DO NOTHING
+            "#,
+            user_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Lock the player's progress on a puzzle, starting it if needed
+    pub async fn lock_progress(conn: &mut PgConnection, user_id: i64, puzzle_id: i64) -> Result<PuzzleProgressRow> {
+        sqlx::query!(
+            "INSERT INTO puzzle_progress (user_id, puzzle_id) VALUES ($1, $2) ON CONFLICT (user_id, puzzle_id) DO NOTHING",
+            user_id,
+            puzzle_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let progress = sqlx::query_as!(
+            PuzzleProgressRow,
+            r#"
+            SELECT user_id, puzzle_id, stage, wrong_attempts, locked_until, started_at, completed_at, rewarded_at
+            FROM puzzle_progress
+            WHERE user_id = $1 AND puzzle_id = $2
+            FOR UPDATE
+            "#,
+            user_id,
+            puzzle_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(progress)
+    }
+
+    pub async fn record_wrong_answer(
+        conn: &mut PgConnection,
+        user_id: i64,
+        puzzle_id: i64,
+        locked_until: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE puzzle_progress
+            SET wrong_attempts = wrong_attempts + 1, locked_until = $3, updated_at = NOW()
+            WHERE user_id = $1 AND puzzle_id = $2
+            "#,
+            user_id,
+            puzzle_id,
+            locked_until
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Move the player on to `stage`
+    pub async fn advance(conn: &mut PgConnection, user_id: i64, puzzle_id: i64, stage: i32) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE puzzle_progress
+            SET stage = $3, wrong_attempts = 0, locked_until = NULL, updated_at = NOW()
+            WHERE user_id = $1 AND puzzle_id = $2
+            "#,
+            user_id,
+            puzzle_id,
+            stage
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Finish the puzzle, recording the reward as paid when `rewarded`
+    pub async fn complete(conn: &mut PgConnection, user_id: i64, puzzle_id: i64, rewarded: bool) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE puzzle_progress
+            SET wrong_attempts = 0, locked_until = NULL, completed_at = NOW(), updated_at = NOW(),
+                rewarded_at = CASE WHEN $3 THEN NOW() ELSE rewarded_at END
+            WHERE user_id = $1 AND puzzle_id = $2
+            "#,
+            user_id,
+            puzzle_id,
+            rewarded
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// A puzzle's milestones with the players counting towards each
+    pub async fn milestones(conn: &mut PgConnection, puzzle_id: i64) -> Result<Vec<PuzzleMilestoneRow>> {
+        let milestones = sqlx::query_as!(
+            PuzzleMilestoneRow,
+            r#"
+            SELECT m.id, m.puzzle_id, m.stage, m.players_required, m.title, m.message, m.reached_at,
+                   (
+                       SELECT COUNT(*) FROM puzzle_progress pp
+                       WHERE pp.puzzle_id = m.puzzle_id
+                         AND (pp.completed_at IS NOT NULL OR (m.stage IS NOT NULL AND pp.stage > m.stage))
+                   ) AS "players!"
+            FROM puzzle_milestones m
+            WHERE m.puzzle_id = $1
+            ORDER BY m.stage NULLS LAST, m.players_required
+            "#,
+            puzzle_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(milestones)
+    }
+
+    /// Record the milestones of `puzzle_id` the community has just reached
+    /// and announce them to every node. Serialized per puzzle, so each is
+    /// reached and announced once.
+    pub async fn reach_milestones(conn: &mut PgConnection, puzzle_id: i64) -> Result<Vec<PuzzleMilestoneRow>> {
+        sqlx::query!(
+            "SELECT pg_advisory_xact_lock(hashtext('puzzle_milestones:' || $1::BIGINT::TEXT))",
+            puzzle_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let mut reached = Vec::new();
+        for milestone in Self::milestones(&mut *conn, puzzle_id).await? {
+            if milestone.reached_at.is_some() || milestone.players < milestone.players_required as i64 {
+                continue;
+            }
+            let reached_at = sqlx::query_scalar!(
+                "UPDATE puzzle_milestones SET reached_at = NOW() WHERE id = $1 AND reached_at IS NULL RETURNING reached_at",
+                milestone.id
+            )
+            .fetch_optional(&mut *conn)
+            .await?
+            .flatten();
+            let Some(reached_at) = reached_at else {
+                continue;
+            };
+
+            sqlx::query!(
+                "INSERT INTO live_ops_announcements (title, content, priority) VALUES ($1, $2, 'info')",
+                milestone.title,
+                milestone.message
+            )
+            .execute(&mut *conn)
+            .await?;
+            reached.push(PuzzleMilestoneRow { reached_at: Some(reached_at), ..milestone });
+        }
+
+        Ok(reached)
+    }
+}
+
+/// One entry of a player's activity timeline. `seq` orders entries with the
+/// same timestamp within their category.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ActivityRow {
+    #[serde(skip)]
+    pub seq: i64,
+    pub at: DateTime<Utc>,
+    pub kind: String,
+    pub details: serde_json::Value,
+}
+
+/// A player's own activity, one category at a time, newest first. Every
+/// listing continues strictly after `before`, an `(at, seq)` position.
+pub struct ActivityQueries;
+
+impl ActivityQueries {
+    /// Logins, from the account's IP history
+    pub async fn logins(pool: &PgPool, user_id: i64, before: Option<(DateTime<Utc>, i64)>, limit: i64) -> Result<Vec<ActivityRow>> {
+        let (before_at, before_seq) = before.unzip();
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, seen_at, ip
+            FROM user_ip_history
+            WHERE user_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR (seen_at, id) < ($2, $3))
+            ORDER BY seen_at DESC, id DESC
+            LIMIT $4
+            "#,
+            user_id,
+            before_at,
+            before_seq.unwrap_or(0),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ActivityRow {
+                    seq: row.id,
+                    at: row.seen_at,
+                    kind: "login".to_string(),
+                    details: serde_json::json!({ "ip": crate::encryption::open(&row.ip)? }),
+                })
+            })
+            .collect()
+    }
+
+    /// Servers the player cracked
+    pub async fn hacks_performed(pool: &PgPool, user_id: i64, before: Option<(DateTime<Utc>, i64)>, limit: i64) -> Result<Vec<ActivityRow>> {
+        let (before_at, before_seq) = before.unzip();
+        let rows = sqlx::query_as!(
+            ActivityRow,
+            r#"
+            SELECT id AS seq, hacked_at AS at, 'hacked' AS "kind!",
+                jsonb_build_object(
+                    'ip', host(ip_address),
+                    'username', username,
+                    'still_valid', invalidated_at IS NULL
+                ) AS "details!"
+            FROM hacked_database
+            WHERE user_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR (hacked_at, id) < ($2, $3))
+            ORDER BY hacked_at DESC, id DESC
+            LIMIT $4
+            "#,
+            user_id,
+            before_at,
+            before_seq.unwrap_or(0),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Other players cracking the player's servers. Who did it is not
+    /// revealed; that is what logs are for.
+    pub async fn hacks_suffered(pool: &PgPool, user_id: i64, before: Option<(DateTime<Utc>, i64)>, limit: i64) -> Result<Vec<ActivityRow>> {
+        let (before_at, before_seq) = before.unzip();
+        let rows = sqlx::query_as!(
+            ActivityRow,
+            r#"
+            SELECT d.id AS seq, d.hacked_at AS at, 'server_hacked' AS "kind!",
+                jsonb_build_object('ip', host(d.ip_address), 'hostname', s.hostname) AS "details!"
+            FROM hacked_database d
+            JOIN servers s ON s.ip_address = d.ip_address
+            WHERE s.user_id = $1 AND d.user_id <> $1
+              AND ($2::TIMESTAMPTZ IS NULL OR (d.hacked_at, d.id) < ($2, $3))
+            ORDER BY d.hacked_at DESC, d.id DESC
+            LIMIT $4
+            "#,
+            user_id,
+            before_at,
+            before_seq.unwrap_or(0),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Ledger entries on the player's bank accounts
+    pub async fn money(pool: &PgPool, user_id: i64, before: Option<(DateTime<Utc>, i64)>, limit: i64) -> Result<Vec<ActivityRow>> {
+        let (before_at, before_seq) = before.unzip();
+        let rows = sqlx::query_as!(
+            ActivityRow,
+            r#"
+            SELECT e.id AS seq, e.created_at AS at,
+                CASE WHEN e.amount > 0 THEN 'money_in' ELSE 'money_out' END AS "kind!",
+                jsonb_build_object(
+                    'account', a.account_number,
+                    'amount', e.amount,
+                    'reason', t.reason,
+                    'reference', t.reference
+                ) AS "details!"
+            FROM ledger_entries e
+            JOIN ledger_transactions t ON t.id = e.transaction_id
+            JOIN bank_accounts a ON a.id = e.bank_account_id
+            WHERE e.account = ANY(SELECT 'bank:' || id FROM bank_accounts WHERE user_id = $1)
+              AND ($2::TIMESTAMPTZ IS NULL OR (e.created_at, e.id) < ($2, $3))
+            ORDER BY e.created_at DESC, e.id DESC
+            LIMIT $4
+            "#,
+            user_id,
+            before_at,
+            before_seq.unwrap_or(0),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Clans joined, the player's war strikes, and wars of the clans they
+    /// are in starting and ending. Each source gets its own residue of `seq`
+    /// so positions stay unique across them.
+    pub async fn clan(pool: &PgPool, user_id: i64, before: Option<(DateTime<Utc>, i64)>, limit: i64) -> Result<Vec<ActivityRow>> {
+        let (before_at, before_seq) = before.unzip();
+        let rows = sqlx::query_as!(
+            ActivityRow,
+            r#"
+            SELECT seq AS "seq!", at AS "at!", kind AS "kind!", details AS "details!"
+            FROM (
+                SELECT m.clan_id * 4 AS seq, m.joined_at AS at, 'clan_joined' AS kind,
+                    jsonb_build_object('clan_id', c.id, 'clan', c.name, 'tag', c.tag, 'role', m.role) AS details
+                FROM clan_members m
+                JOIN clans c ON c.id = m.clan_id
+                WHERE m.user_id = $1
+                UNION ALL
+                SELECT s.id * 4 + 1, s.created_at, 'war_strike',
+                    jsonb_build_object('war_id', s.war_id, 'side', s.side, 'damage', s.damage, 'points', s.points)
+                FROM clan_war_strikes s
+                WHERE s.user_id = $1
+                UNION ALL
+                SELECT w.id * 4 + 2, w.started_at, 'war_started',
+                    jsonb_build_object('war_id', w.id, 'attacker', a.name, 'defender', d.name, 'declared_by_you', w.declared_by IS NOT DISTINCT FROM $1)
+                FROM clan_wars w
+                JOIN clans a ON a.id = w.attacker_clan_id
+                JOIN clans d ON d.id = w.defender_clan_id
+                WHERE EXISTS (
+                    SELECT 1 FROM clan_members m
+                    WHERE m.user_id = $1 AND m.clan_id IN (w.attacker_clan_id, w.defender_clan_id)
+                )
+                UNION ALL
+                SELECT w.id * 4 + 3, w.ended_at, 'war_ended',
+                    jsonb_build_object('war_id', w.id, 'attacker', a.name, 'defender', d.name, 'winner', wc.name)
+                FROM clan_wars w
+                JOIN clans a ON a.id = w.attacker_clan_id
+                JOIN clans d ON d.id = w.defender_clan_id
+                LEFT JOIN clans wc ON wc.id = w.winner_clan_id
+                WHERE w.ended_at IS NOT NULL AND EXISTS (
+                    SELECT 1 FROM clan_members m
+                    WHERE m.user_id = $1 AND m.clan_id IN (w.attacker_clan_id, w.defender_clan_id)
+                )
+            ) e
+            WHERE $2::TIMESTAMPTZ IS NULL OR (e.at, e.seq) < ($2, $3)
+            ORDER BY e.at DESC, e.seq DESC
+            LIMIT $4
+            "#,
+            user_id,
+            before_at,
+            before_seq.unwrap_or(0),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Audit events about the player of the given `SecurityEvent` variants;
+    /// `kind` is the variant name
+    pub async fn security(
+        pool: &PgPool,
+        user_id: i64,
+        variants: &[&str],
+        before: Option<(DateTime<Utc>, i64)>,
+        limit: i64,
+    ) -> Result<Vec<ActivityRow>> {
+        let (before_at, before_seq) = before.unzip();
+        let variants: Vec<String> = variants.iter().map(|v| v.to_string()).collect();
+        let rows = sqlx::query_as!(
+            ActivityRow,
+            r#"
+            SELECT l.id AS seq, l.timestamp AS at, v.key AS "kind!", v.value AS "details!"
+            FROM audit_logs l
+            CROSS JOIN LATERAL jsonb_each(l.event_data) v
+            WHERE l.user_id = $1 AND v.key = ANY($2::TEXT[])
+              AND ($3::TIMESTAMPTZ IS NULL OR (l.timestamp, l.id) < ($3, $4))
+            ORDER BY l.timestamp DESC, l.id DESC
+            LIMIT $5
+            "#,
+            user_id,
+            &variants,
+            before_at,
+            before_seq.unwrap_or(0),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+/// A hold on money, disk space and process slots as stored
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReservationRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub bank_account_id: Option<i64>,
+    pub server_id: Option<i64>,
+    pub money: i64,
+    pub hdd_mb: i64,
+    pub process_slots: i32,
+    pub purpose: Option<String>,
+    pub status: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub settled_at: Option<DateTime<Utc>>,
+}
+
+/// A hold with the account and server it is taken on already chosen
+#[derive(Debug, Clone)]
+pub struct NewReservation<'a> {
+    pub user_id: i64,
+    pub bank_account_id: Option<i64>,
+    pub server_id: Option<i64>,
+    pub money: i64,
+    pub hdd_mb: i64,
+    pub process_slots: i32,
+    pub purpose: Option<&'a str>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Holds count while they are 'held' and not past `expires_at`. Holds other
+/// than `except` are taken out of what is free, so a step can spend its own.
+pub struct ReservationQueries;
+
+impl ReservationQueries {
+    /// Serialize taking holds against spending for one player, until the
+    /// transaction ends
+    pub async fn lock_user(conn: &mut PgConnection, user_id: i64) -> Result<()> {
+        sqlx::query!(
+            "SELECT pg_advisory_xact_lock(hashtext('reservations:' || $1::BIGINT::TEXT))",
+            user_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn open_count(conn: &mut PgConnection, user_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM resource_reservations
+            WHERE user_id = $1 AND status = 'held' AND expires_at > NOW()
+            "#,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// The player's active bank account with the most money not held by
+    /// other reservations, locked, and that amount
+    pub async fn spendable_account(conn: &mut PgConnection, user_id: i64, except: Option<i64>) -> Result<Option<(i64, i64)>> {
+        let row = sqlx::query!(
+            r#"
+            SELECT a.id, a.balance - COALESCE((
+                SELECT SUM(r.money) FROM resource_reservations r
+                WHERE r.bank_account_id = a.id AND r.status = 'held' AND r.expires_at > NOW()
+                  AND r.id IS DISTINCT FROM $2
+            ), 0)::BIGINT AS "spendable!"
+            FROM bank_accounts a
+            WHERE a.user_id = $1 AND a.is_active = TRUE
+            ORDER BY 2 DESC, a.id
+            LIMIT 1
+            FOR UPDATE OF a
+            "#,
+            user_id,
+            except
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row.map(|row| (row.id, row.spendable)))
+    }
+
+    /// Money on one account not held by reservations other than `except`,
+    /// with the account locked
+    pub async fn spendable_on(conn: &mut PgConnection, bank_account_id: i64, except: Option<i64>) -> Result<i64> {
+        let spendable = sqlx::query_scalar!(
+            r#"
+            SELECT a.balance - COALESCE((
+                SELECT SUM(r.money) FROM resource_reservations r
+                WHERE r.bank_account_id = a.id AND r.status = 'held' AND r.expires_at > NOW()
+                  AND r.id IS DISTINCT FROM $2
+            ), 0)::BIGINT AS "spendable!"
+            FROM bank_accounts a
+            WHERE a.id = $1
+            FOR UPDATE OF a
+            "#,
+            bank_account_id,
+            except
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(spendable)
+    }
+
+    /// Disk space on a server not used or held by reservations other than
+    /// `except`, with the server locked
+    pub async fn free_space(conn: &mut PgConnection, server_id: i64, except: Option<i64>) -> Result<i64> {
+        let free = sqlx::query_scalar!(
+            r#"
+            SELECT (s.hdd_total - s.hdd_used)::BIGINT - COALESCE((
+                SELECT SUM(r.hdd_mb) FROM resource_reservations r
+                WHERE r.server_id = s.id AND r.status = 'held' AND r.expires_at > NOW()
+                  AND r.id IS DISTINCT FROM $2
+            ), 0)::BIGINT AS "free!"
+            FROM servers s
+            WHERE s.id = $1
+            FOR UPDATE OF s
+            "#,
+            server_id,
+            except
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(free)
+    }
+
+    /// Process slots the player holds outside `except`
+    pub async fn held_slots(conn: &mut PgConnection, user_id: i64, except: Option<i64>) -> Result<i64> {
+        let held = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(SUM(process_slots), 0)::BIGINT AS "held!" FROM resource_reservations
+            WHERE user_id = $1 AND status = 'held' AND expires_at > NOW() AND id IS DISTINCT FROM $2
+            "#,
+            user_id,
+            except
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(held)
+    }
+
+    pub async fn create(conn: &mut PgConnection, reservation: &NewReservation<'_>) -> Result<ReservationRow> {
+        let row = sqlx::query_as!(
+            ReservationRow,
+            r#"
+            INSERT INTO resource_reservations
+                (user_id, bank_account_id, server_id, money, hdd_mb, process_slots, purpose, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING *
+            "#,
+            reservation.user_id,
+            reservation.bank_account_id,
+            reservation.server_id,
+            reservation.money,
+            reservation.hdd_mb,
+            reservation.process_slots,
+            reservation.purpose,
+            reservation.expires_at
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// The player's reservations, newest first
+    pub async fn list(pool: &PgPool, user_id: i64, limit: i64) -> Result<Vec<ReservationRow>> {
+        let rows = sqlx::query_as!(
+            ReservationRow,
+            "SELECT * FROM resource_reservations WHERE user_id = $1 ORDER BY id DESC LIMIT $2",
+            user_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// One of the player's reservations, locked
+    pub async fn lock(conn: &mut PgConnection, id: i64, user_id: i64) -> Result<Option<ReservationRow>> {
+        let row = sqlx::query_as!(
+            ReservationRow,
+            "SELECT * FROM resource_reservations WHERE id = $1 AND user_id = $2 FOR UPDATE",
+            id,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Close a held reservation as 'committed' or 'released'
+    pub async fn settle(conn: &mut PgConnection, id: i64, status: &str) -> Result<ReservationRow> {
+        let row = sqlx::query_as!(
+            ReservationRow,
+            r#"
+            UPDATE resource_reservations SET status = $2, settled_at = NOW()
+            WHERE id = $1 AND status = 'held'
+            RETURNING *
+            "#,
+            id,
+            status
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Use one held process slot of a live reservation, committing it once
+    /// nothing is left on it. False if it has no slot to give.
+    pub async fn take_slot(conn: &mut PgConnection, id: i64, user_id: i64) -> Result<bool> {
+        let taken = sqlx::query!(
+            r#"
+            UPDATE resource_reservations
+            SET process_slots = process_slots - 1,
+                status = CASE WHEN process_slots = 1 AND money = 0 AND hdd_mb = 0 THEN 'committed' ELSE status END,
+                settled_at = CASE WHEN process_slots = 1 AND money = 0 AND hdd_mb = 0 THEN NOW() ELSE settled_at END
+            WHERE id = $1 AND user_id = $2 AND status = 'held' AND expires_at > NOW() AND process_slots > 0
+            "#,
+            id,
+            user_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(taken.rows_affected() == 1)
+    }
+
+    /// Use the money and disk space held by a reservation, committing it
+    /// unless process slots are still held on it
+    pub async fn take_money_and_space(conn: &mut PgConnection, id: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE resource_reservations
+            SET money = 0, hdd_mb = 0,
+                status = CASE WHEN process_slots = 0 THEN 'committed' ELSE status END,
+                settled_at = CASE WHEN process_slots = 0 THEN NOW() ELSE settled_at END
+            WHERE id = $1 AND status = 'held'
+            "#,
+            id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Close the player's live holds taken for `purpose`
+    pub async fn settle_purpose(conn: &mut PgConnection, user_id: i64, purpose: &str, status: &str) -> Result<u64> {
+        let settled = sqlx::query!(
+            r#"
+            UPDATE resource_reservations SET status = $3, settled_at = NOW()
+            WHERE user_id = $1 AND purpose = $2 AND status = 'held' AND expires_at > NOW()
+            "#,
+            user_id,
+            purpose,
+            status
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(settled.rows_affected())
+    }
+
+    /// Mark holds past their expiry as 'expired'
+    pub async fn expire(pool: &PgPool) -> Result<u64> {
+        let expired = sqlx::query!(
+            r#"
+            UPDATE resource_reservations SET status = 'expired', settled_at = expires_at
+            WHERE status = 'held' AND expires_at <= NOW()
+            "#
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(expired.rows_affected())
+    }
+}
+
+/// A login attempt as the player sees it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LoginHistoryRow {
+    pub id: i64,
+    pub at: DateTime<Utc>,
+    pub ip_masked: String,
+    pub country: Option<String>,
+    pub network: Option<String>,
+    pub user_agent: Option<String>,
+    pub device: Option<String>,
+    pub fingerprint: Option<String>,
+    pub success: bool,
+    pub failure_reason: Option<String>,
+    pub disowned_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewLogin<'a> {
+    pub user_id: i64,
+    pub ip_masked: &'a str,
+    pub ip_index: Option<&'a str>,
+    pub country: Option<&'a str>,
+    pub network: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub device: Option<&'a str>,
+    pub fingerprint: Option<&'a str>,
+    pub success: bool,
+    pub failure_reason: Option<&'a str>,
+}
+
+/// Password reset tokens mailed after a disowned login expire after this long
+pub const PASSWORD_RESET_TOKEN_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Clone)]
+pub enum DisownLogin {
+    /// Sessions dropped and a new password required; `reset_token` goes to
+    /// the player's address
+    Disowned {
+        login: LoginHistoryRow,
+        ip_index: Option<String>,
+        reset_token: String,
+    },
+    AlreadyDisowned,
+    NotFound,
+}
+
+/// Login history read model and the password resets it can force
+pub struct LoginHistoryQueries;
+
+impl LoginHistoryQueries {
+    pub async fn record(pool: &PgPool, login: &NewLogin<'_>) -> Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO login_history
+                (user_id, ip_masked, ip_index, country, network, user_agent, device, fingerprint, success, failure_reason)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id
+            "#,
+            login.user_id,
+            login.ip_masked,
+            login.ip_index,
+            login.country,
+            login.network,
+            login.user_agent,
+            login.device,
+            login.fingerprint,
+            login.success,
+            login.failure_reason
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// The player's attempts older than `before`, newest first
+    pub async fn list(
+        pool: &PgPool,
+        user_id: i64,
+        before: Option<(DateTime<Utc>, i64)>,
+        limit: i64,
+    ) -> Result<Vec<LoginHistoryRow>> {
+        let (before_at, before_id) = before.unzip();
+        let rows = sqlx::query_as!(
+            LoginHistoryRow,
+            r#"
+            SELECT id, at, ip_masked, country, network, user_agent, device, fingerprint, success, failure_reason,
+                   disowned_at
+            FROM login_history
+            WHERE user_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR (at, id) < ($2, $3))
+            ORDER BY at DESC, id DESC
+            LIMIT $4
+            "#,
+            user_id,
+            before_at,
+            before_id.unwrap_or(0),
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// "This wasn't me": flag the login, drop every session of the player and
+    /// require a new password
+    pub async fn disown(pool: &PgPool, id: i64, user_id: i64) -> Result<DisownLogin> {
+        let mut tx = crate::tagging::begin(pool).await?;
+
+        let disowned_at = sqlx::query_scalar!(
+            "SELECT disowned_at FROM login_history WHERE id = $1 AND user_id = $2 FOR UPDATE",
+            id,
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        match disowned_at {
+            None => return Ok(DisownLogin::NotFound),
+            Some(Some(_)) => return Ok(DisownLogin::AlreadyDisowned),
+            Some(None) => {}
+        }
+
+        let row = sqlx::query!(
+            r#"
+            UPDATE login_history SET disowned_at = NOW()
+            WHERE id = $1
+            RETURNING id, at, ip_masked, ip_index, country, network, user_agent, device, fingerprint, success,
+                      failure_reason, disowned_at
+            "#,
+            id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let reset_token = EmailChangeQueries::generate_token();
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_reset_required = TRUE,
+                password_reset_token_hash = encode(sha256(convert_to($2, 'UTF8')), 'hex'),
+                password_reset_expires_at = NOW() + make_interval(hours => $3)
+            WHERE id = $1
+            "#,
+            user_id,
+            reset_token,
+            PASSWORD_RESET_TOKEN_TTL_HOURS as i32
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(DisownLogin::Disowned {
+            login: LoginHistoryRow {
+                id: row.id,
+                at: row.at,
+                ip_masked: row.ip_masked,
+                country: row.country,
+                network: row.network,
+                user_agent: row.user_agent,
+                device: row.device,
+                fingerprint: row.fingerprint,
+                success: row.success,
+                failure_reason: row.failure_reason,
+                disowned_at: row.disowned_at,
+            },
+            ip_index: row.ip_index,
+            reset_token,
+        })
+    }
+
+    pub async fn reset_required(pool: &PgPool, user_id: i64) -> Result<bool> {
+        let required = sqlx::query_scalar!("SELECT password_reset_required FROM users WHERE id = $1", user_id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(required.unwrap_or(false))
+    }
+
+    /// Set a new password with a mailed reset token, dropping every session.
+    /// Returns the account, or `None` if the token is wrong or expired.
+    pub async fn reset_password(pool: &PgPool, token: &str, password: &str) -> Result<Option<i64>> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::default().hash_password(password.as_bytes(), &salt)?.to_string();
+
+        let mut tx = crate::tagging::begin(pool).await?;
+        let user_id = sqlx::query_scalar!(
+            r#"
+            UPDATE users
+            SET pwd = $2, password_reset_required = FALSE,
+                password_reset_token_hash = NULL, password_reset_expires_at = NULL
+            WHERE password_reset_token_hash = encode(sha256(convert_to($1, 'UTF8')), 'hex')
+              AND password_reset_expires_at > NOW()
+            RETURNING id
+            "#,
+            token,
+            password_hash
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+        sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Some(user_id))
+    }
+}
+
+/// Daily actives from `analytics.daily_activity`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DailyActivityRow {
+    pub day: chrono::NaiveDate,
+    pub dau: i64,
+    pub wau: i64,
+    pub new_players: i64,
+}
+
+/// Register → first hack → day-7 return for one registration day
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FunnelCohortRow {
+    pub cohort_day: chrono::NaiveDate,
+    pub registered: i64,
+    pub first_hack: i64,
+    pub returned_day7: i64,
+}
+
+/// Money moved for one ledger reason on one day, in cents
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EconomyDayRow {
+    pub day: chrono::NaiveDate,
+    pub reason: String,
+    pub transactions: i64,
+    pub minted: i64,
+    pub sunk: i64,
+    pub volume: i64,
+}
+
+/// Map-phase reads over the logs and writes to the `analytics` schema. Map
+/// queries cover the ids in `[ids.0, ids.1)` and the times in `[from, to)`.
+pub struct AnalyticsQueries;
+
+impl AnalyticsQueries {
+    /// Lowest and highest user id, `None` without users
+    pub async fn user_bounds(pool: &PgPool) -> Result<Option<(i64, i64)>> {
+        let row = sqlx::query!(r#"SELECT MIN(id) AS "low", MAX(id) AS "high" FROM users"#)
+            .fetch_one(pool)
+            .await?;
+
+        Ok(row.low.zip(row.high))
+    }
+
+    /// Lowest and highest id of the ledger transactions made in `[from, to)`
+    pub async fn ledger_bounds(pool: &PgPool, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Option<(i64, i64)>> {
+        let row = sqlx::query!(
+            r#"
+            SELECT MIN(id) AS "low", MAX(id) AS "high"
+            FROM ledger_transactions
+            WHERE created_at >= $1 AND created_at < $2
+            "#,
+            from,
+            to
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(row.low.zip(row.high))
+    }
+
+    /// Players active in `[day, to)` and in `[week, to)`, and players who
+    /// registered in `[day, to)`
+    pub async fn activity(
+        pool: &PgPool,
+        ids: (i64, i64),
+        week: DateTime<Utc>,
+        day: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<(i64, i64, i64)> {
+        let row = sqlx::query!(
+            r#"
+            WITH active AS (
+                SELECT user_id, at FROM login_history
+                WHERE success AND user_id >= $1 AND user_id < $2 AND at >= $3 AND at < $5
+                UNION ALL
+                SELECT user_id, created_at FROM logs
+                WHERE user_id >= $1 AND user_id < $2 AND created_at >= $3 AND created_at < $5
+            )
+            SELECT
+                (SELECT COUNT(DISTINCT user_id) FROM active WHERE at >= $4) AS "dau!",
+                (SELECT COUNT(DISTINCT user_id) FROM active) AS "wau!",
+                (SELECT COUNT(*) FROM users
+                 WHERE id >= $1 AND id < $2 AND created_at >= $4 AND created_at < $5) AS "new_players!"
+            "#,
+            ids.0,
+            ids.1,
+            week,
+            day,
+            to
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok((row.dau, row.wau, row.new_players))
+    }
+
+    /// Of the players who registered in `[from, to)`: how many, how many
+    /// hacked within seven days and how many signed in on day seven
+    pub async fn funnel(pool: &PgPool, ids: (i64, i64), from: DateTime<Utc>, to: DateTime<Utc>) -> Result<(i64, i64, i64)> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) AS "registered!",
+                COUNT(*) FILTER (WHERE EXISTS (
+                    SELECT 1 FROM hacked_database h
+                    WHERE h.user_id = u.id AND h.hacked_at < u.created_at + INTERVAL '7 days'
+                )) AS "first_hack!",
+                COUNT(*) FILTER (WHERE EXISTS (
+                    SELECT 1 FROM login_history l
+                    WHERE l.user_id = u.id AND l.success
+                      AND l.at >= u.created_at + INTERVAL '7 days'
+                      AND l.at < u.created_at + INTERVAL '8 days'
+                )) AS "returned_day7!"
+            FROM users u
+            WHERE u.id >= $1 AND u.id < $2 AND u.created_at >= $3 AND u.created_at < $4
+            "#,
+            ids.0,
+            ids.1,
+            from,
+            to
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok((row.registered, row.first_hack, row.returned_day7))
+    }
+
+    /// Per reason: transactions, minted, sunk and volume of the ledger
+    /// transactions made in `[from, to)`
+    pub async fn economy(
+        pool: &PgPool,
+        ids: (i64, i64),
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<(String, i64, i64, i64, i64)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT t.reason,
+                   COUNT(DISTINCT t.id) AS "transactions!",
+                   COALESCE(SUM(-e.amount) FILTER (WHERE e.account = 'system:mint'), 0)::BIGINT AS "minted!",
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.account = 'system:sink'), 0)::BIGINT AS "sunk!",
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.amount > 0), 0)::BIGINT AS "volume!"
+            FROM ledger_transactions t
+            JOIN ledger_entries e ON e.transaction_id = t.id
+            WHERE t.id >= $1 AND t.id < $2 AND t.created_at >= $3 AND t.created_at < $4
+            GROUP BY t.reason
+            "#,
+            ids.0,
+            ids.1,
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.reason, row.transactions, row.minted, row.sunk, row.volume))
+            .collect())
+    }
+
+    pub async fn write_activity(pool: &PgPool, row: &DailyActivityRow) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO analytics.daily_activity (day, dau, wau, new_players)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (day) DO UPDATE
+            SET dau = EXCLUDED.dau, wau = EXCLUDED.wau, new_players = EXCLUDED.new_players, computed_at = NOW()
+            "#,
+            row.day,
+            row.dau,
+            row.wau,
+            row.new_players
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn write_funnel(pool: &PgPool, row: &FunnelCohortRow) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO analytics.funnel_cohorts (cohort_day, registered, first_hack, returned_day7)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (cohort_day) DO UPDATE
+            SET registered = EXCLUDED.registered, first_hack = EXCLUDED.first_hack,
+                returned_day7 = EXCLUDED.returned_day7, computed_at = NOW()
+            "#,
+            row.cohort_day,
+            row.registered,
+            row.first_hack,
+            row.returned_day7
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace every reason's row for `day`
+    pub async fn write_economy(pool: &PgPool, day: chrono::NaiveDate, rows: &[EconomyDayRow]) -> Result<()> {
+        let mut tx = crate::tagging::begin(pool).await?;
+
+        sqlx::query!("DELETE FROM analytics.economy_daily WHERE day = $1", day)
+            .execute(&mut *tx)
+            .await?;
+        for row in rows {
+            sqlx::query!(
+                r#"
+                INSERT INTO analytics.economy_daily (day, reason, transactions, minted, sunk, volume)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+                day,
+                row.reason,
+                row.transactions,
+                row.minted,
+                row.sunk,
+                row.volume
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Days in `[from, to]` that `job` has finished
+    pub async fn finished_days(
+        pool: &PgPool,
+        job: &str,
+        from: chrono::NaiveDate,
+        to: chrono::NaiveDate,
+    ) -> Result<Vec<chrono::NaiveDate>> {
+        let days = sqlx::query_scalar!(
+            "SELECT day FROM analytics.job_days WHERE job = $1 AND day >= $2 AND day <= $3 ORDER BY day",
+            job,
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(days)
+    }
+
+    pub async fn finish_day(
+        pool: &PgPool,
+        job: &str,
+        day: chrono::NaiveDate,
+        partitions: i32,
+        duration_ms: i64,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO analytics.job_days (job, day, partitions, duration_ms)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (job, day) DO UPDATE
+            SET partitions = EXCLUDED.partitions, duration_ms = EXCLUDED.duration_ms, computed_at = NOW()
+            "#,
+            job,
+            day,
+            partitions,
+            duration_ms
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn activity_range(
+        pool: &PgPool,
+        from: chrono::NaiveDate,
+        to: chrono::NaiveDate,
+    ) -> Result<Vec<DailyActivityRow>> {
+        let rows = sqlx::query_as!(
+            DailyActivityRow,
+            r#"
+            SELECT day, dau, wau, new_players FROM analytics.daily_activity
+            WHERE day >= $1 AND day <= $2
+            ORDER BY day
+            "#,
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn funnel_range(
+        pool: &PgPool,
+        from: chrono::NaiveDate,
+        to: chrono::NaiveDate,
+    ) -> Result<Vec<FunnelCohortRow>> {
+        let rows = sqlx::query_as!(
+            FunnelCohortRow,
+            r#"
+            SELECT cohort_day, registered, first_hack, returned_day7 FROM analytics.funnel_cohorts
+            WHERE cohort_day >= $1 AND cohort_day <= $2
+            ORDER BY cohort_day
+            "#,
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn economy_range(
+        pool: &PgPool,
+        from: chrono::NaiveDate,
+        to: chrono::NaiveDate,
+    ) -> Result<Vec<EconomyDayRow>> {
+        let rows = sqlx::query_as!(
+            EconomyDayRow,
+            r#"
+            SELECT day, reason, transactions, minted, sunk, volume FROM analytics.economy_daily
+            WHERE day >= $1 AND day <= $2
+            ORDER BY day, reason
+            "#,
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+/// A copy of software on one of the player's own servers
+#[derive(Debug, Clone)]
+pub struct OwnedSoftwareRow {
+    pub id: i64,
+    pub server_id: i64,
+    pub name: String,
+    pub version: f64,
+    pub license_server_id: Option<i64>,
+    pub pirated_from: Option<i64>,
+}
+
+/// A copy about to be downloaded
+#[derive(Debug, Clone)]
+pub struct PirateSourceRow {
+    pub id: i64,
+    pub owner_id: Option<i64>,
+    pub name: String,
+    pub size: i32,
+    pub license_server_id: Option<i64>,
+}
+
+/// A copy whose license was cracked
+#[derive(Debug, Clone)]
+pub struct UnlockedSoftwareRow {
+    pub name: String,
+    pub trojan_owner_id: Option<i64>,
+    /// Gateway of the holder, where the trojan opens a way in
+    pub gateway_ip: Option<String>,
+}
+
+/// License locks, stolen copies and keygens
+pub struct PiracyQueries;
+
+impl PiracyQueries {
+    pub async fn owned(pool: &PgPool, user_id: i64, software_id: i64) -> Result<Option<OwnedSoftwareRow>> {
+        let row = sqlx::query_as!(
+            OwnedSoftwareRow,
+            r#"
+            SELECT sw.id, sw.server_id, sw.name, sw.version::FLOAT8 AS "version!", sw.license_server_id,
+                sw.pirated_from
+            FROM software sw
+            JOIN servers s ON s.id = sw.server_id
+            WHERE sw.id = $1 AND s.user_id = $2 AND s.is_npc = FALSE
+            "#,
+            software_id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Lock a copy to the server it is on, unless it is locked or stolen
+    pub async fn lock(pool: &PgPool, software_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE software SET license_server_id = server_id
+            WHERE id = $1 AND license_server_id IS NULL AND pirated_from IS NULL
+            "#,
+            software_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Best cracker on the player's own servers that runs where it is
+    pub async fn cracker(pool: &PgPool, user_id: i64) -> Result<Option<f64>> {
+        let version = sqlx::query_scalar!(
+            r#"
+            SELECT MAX(sw.version)::FLOAT8 FROM software sw
+            JOIN servers s ON s.id = sw.server_id
+            WHERE s.user_id = $1 AND s.is_npc = FALSE AND sw.type = 'cracker'
+              AND (sw.license_server_id IS NULL OR sw.license_server_id = sw.server_id)
+            "#,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(version)
+    }
+
+    pub async fn keygen_running(pool: &PgPool, software_id: i64) -> Result<bool> {
+        let running = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM processes
+                WHERE process_type = 'keygen' AND target_file_id = $1::BIGINT::TEXT AND completed_at IS NULL
+            ) AS "running!"
+            "#,
+            software_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(running)
+    }
+
+    /// The copy `software_id` on the server at `ip`, with the owner of that
+    /// server when it is a player's
+    pub async fn source(conn: &mut PgConnection, ip: &str, software_id: i64) -> Result<Option<PirateSourceRow>> {
+        let row = sqlx::query_as!(
+            PirateSourceRow,
+            r#"
+            SELECT sw.id, CASE WHEN s.is_npc THEN NULL ELSE s.user_id END AS owner_id, sw.name, sw.size,
+                sw.license_server_id
+            FROM software sw
+            JOIN servers s ON s.id = sw.server_id
+            WHERE sw.id = $2 AND host(s.ip_address) = $1
+            "#,
+            ip,
+            software_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Store a stolen copy on `server_id`, keeping its lock, and take its
+    /// disk space
+    pub async fn store_copy(
+        conn: &mut PgConnection,
+        source_id: i64,
+        server_id: i64,
+        trojan_owner_id: Option<i64>,
+    ) -> Result<i64> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO software (server_id, name, type, version, size, effectiveness, license_server_id,
+                pirated_from, trojan_owner_id)
+            SELECT $2, name, type, version, size, effectiveness, license_server_id, id, $3
+            FROM software WHERE id = $1
+            RETURNING id
+            "#,
+            source_id,
+            server_id,
+            trojan_owner_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE servers SET hdd_used = hdd_used + (SELECT size FROM software WHERE id = $1) WHERE id = $2",
+            id,
+            server_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Clear the lock and trojan of one of the player's copies
+    pub async fn unlock(conn: &mut PgConnection, user_id: i64, software_id: i64) -> Result<Option<UnlockedSoftwareRow>> {
+        let row = sqlx::query_as!(
+            UnlockedSoftwareRow,
+            r#"
+            UPDATE software sw SET license_server_id = NULL, trojan_owner_id = NULL
+            FROM servers s, software before
+            WHERE sw.id = $1 AND before.id = sw.id AND s.id = sw.server_id AND s.user_id = $2
+              AND sw.license_server_id IS NOT NULL
+            RETURNING sw.name, before.trojan_owner_id,
+                (SELECT host(g.ip_address) FROM servers g
+                 WHERE g.user_id = $2 AND g.is_npc = FALSE ORDER BY g.id LIMIT 1) AS gateway_ip
+            "#,
+            software_id,
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+}
+
+/// A hot table the archive job moves old rows out of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveTable {
+    /// Completed processes, by completion time
+    Processes,
+    /// Game logs, by creation time
+    Logs,
+}
+
+impl ArchiveTable {
+    pub const ALL: [ArchiveTable; 2] = [ArchiveTable::Processes, ArchiveTable::Logs];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArchiveTable::Processes => "processes",
+            ArchiveTable::Logs => "logs",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|table| table.as_str() == s)
+    }
+
+    /// Shortest retention window operators may set; analytics re-reads the
+    /// last week of logs
+    pub fn min_retention_days(&self) -> i32 {
+        match self {
+            ArchiveTable::Processes => 1,
+            ArchiveTable::Logs => 14,
+        }
+    }
+}
+
+/// Retention of one hot table, from `archive.retention`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RetentionRow {
+    pub table_name: String,
+    pub retention_days: i32,
+    pub batch_size: i32,
+    pub enabled: bool,
+    pub updated_by: Option<i64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One archive job pass over a table
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ArchiveRunRow {
+    pub table_name: String,
+    pub cutoff: DateTime<Utc>,
+    pub moved: i64,
+    pub more_pending: bool,
+    pub duration_ms: i64,
+    pub ran_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ArchivedProcessRow {
+    pub pid: i64,
+    pub process_type: String,
+    pub target_pc_id: Option<String>,
+    pub target_file_id: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ArchivedLogRow {
+    pub id: i64,
+    pub server_id: i64,
+    pub log_type: String,
+    pub message: String,
+    pub ip_address: Option<String>,
+    pub is_deleted: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct ArchiveQueries;
+
+impl ArchiveQueries {
+    pub async fn retention(pool: &PgPool) -> Result<Vec<RetentionRow>> {
+        let rows = sqlx::query_as!(
+            RetentionRow,
+            r#"
+            SELECT table_name, retention_days, batch_size, enabled, updated_by, updated_at
+            FROM archive.retention
+            ORDER BY table_name
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn set_retention(
+        pool: &PgPool,
+        table: ArchiveTable,
+        retention_days: i32,
+        batch_size: i32,
+        enabled: bool,
+        admin_id: i64,
+    ) -> Result<RetentionRow> {
+        let row = sqlx::query_as!(
+            RetentionRow,
+            r#"
+            INSERT INTO archive.retention (table_name, retention_days, batch_size, enabled, updated_by)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (table_name) DO UPDATE SET
+                retention_days = EXCLUDED.retention_days,
+                batch_size = EXCLUDED.batch_size,
+                enabled = EXCLUDED.enabled,
+                updated_by = EXCLUDED.updated_by,
+                updated_at = NOW()
+            RETURNING table_name, retention_days, batch_size, enabled, updated_by, updated_at
+            "#,
+            table.as_str(),
+            retention_days,
+            batch_size,
+            enabled,
+            admin_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// When the oldest row of `table` that could be archived was completed
+    /// or written
+    pub async fn oldest(pool: &PgPool, table: ArchiveTable) -> Result<Option<DateTime<Utc>>> {
+        let oldest = match table {
+            ArchiveTable::Processes => {
+                sqlx::query_scalar!("SELECT MIN(completed_at) FROM processes WHERE completed_at IS NOT NULL")
+                    .fetch_one(pool)
+                    .await?
+            }
+            ArchiveTable::Logs => sqlx::query_scalar!("SELECT MIN(created_at) FROM logs").fetch_one(pool).await?,
+        };
+
+        Ok(oldest)
+    }
+
+    /// Create the archive partition of `table` for the month of `month`
+    pub async fn ensure_partition(pool: &PgPool, table: ArchiveTable, month: chrono::NaiveDate) -> Result<()> {
+        sqlx::query!("SELECT archive.ensure_month($1, $2)", table.as_str(), month)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Move up to `limit` of the oldest rows of `table` from before `cutoff`
+    /// into the archive in one transaction; returns how many moved. Rows
+    /// locked by a running transaction wait for the next batch.
+    pub async fn archive_batch(pool: &PgPool, table: ArchiveTable, cutoff: DateTime<Utc>, limit: i64) -> Result<u64> {
+        let result = match table {
+            ArchiveTable::Processes => {
+                sqlx::query!(
+                    r#"
+                    WITH moved AS (
+                        DELETE FROM processes
+                        WHERE pid IN (
+                            SELECT pid FROM processes
+                            WHERE completed_at IS NOT NULL AND completed_at < $1
+                            ORDER BY completed_at
+                            LIMIT $2
+                            FOR UPDATE SKIP LOCKED
+                        )
+                        RETURNING pid, user_id, game_id, pc_id, target_pc_id, target_file_id, target_folder,
+                            process_type, priority, start_time, end_time, completed_at
+                    )
+                    INSERT INTO archive.processes (pid, user_id, game_id, pc_id, target_pc_id, target_file_id,
+                        target_folder, process_type, priority, start_time, end_time, completed_at)
+                    SELECT pid, user_id, game_id, pc_id, target_pc_id, target_file_id, target_folder,
+                        process_type, priority, start_time, end_time, completed_at
+                    FROM moved
+                    "#,
+                    cutoff,
+                    limit
+                )
+                .execute(pool)
+                .await?
+            }
+            ArchiveTable::Logs => {
+                sqlx::query!(
+                    r#"
+                    WITH moved AS (
+                        DELETE FROM logs
+                        WHERE id IN (
+                            SELECT id FROM logs
+                            WHERE created_at < $1
+                            ORDER BY created_at
+                            LIMIT $2
+                            FOR UPDATE SKIP LOCKED
+                        )
+                        RETURNING id, server_id, user_id, type, message, ip_address, is_deleted, created_at
+                    )
+                    INSERT INTO archive.logs (id, server_id, user_id, type, message, ip_address, is_deleted, created_at)
+                    SELECT id, server_id, user_id, type, message, ip_address, is_deleted, created_at
+                    FROM moved
+                    "#,
+                    cutoff,
+                    limit
+                )
+                .execute(pool)
+                .await?
+            }
+        };
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn record_run(
+        pool: &PgPool,
+        table: ArchiveTable,
+        cutoff: DateTime<Utc>,
+        moved: i64,
+        more_pending: bool,
+        duration_ms: i64,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO archive.runs (table_name, cutoff, moved, more_pending, duration_ms)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            table.as_str(),
+            cutoff,
+            moved,
+            more_pending,
+            duration_ms
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Latest archive job passes, newest first
+    pub async fn runs(pool: &PgPool, limit: i64) -> Result<Vec<ArchiveRunRow>> {
+        let rows = sqlx::query_as!(
+            ArchiveRunRow,
+            r#"
+            SELECT table_name, cutoff, moved, more_pending, duration_ms, ran_at
+            FROM archive.runs
+            ORDER BY ran_at DESC
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// The player's archived processes completed in `[from, to)`, newest
+    /// first, from before process `before` when paging
+    pub async fn processes(
+        pool: &PgPool,
+        user_id: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        before: Option<(DateTime<Utc>, i64)>,
+        limit: i64,
+    ) -> Result<Vec<ArchivedProcessRow>> {
+        let (before_at, before_pid) = before.unzip();
+        let rows = sqlx::query_as!(
+            ArchivedProcessRow,
+            r#"
+            SELECT pid, process_type, target_pc_id, target_file_id, start_time, end_time, completed_at
+            FROM archive.processes
+            WHERE user_id = $1 AND completed_at >= $2 AND completed_at < $3
+              AND ($4::timestamptz IS NULL OR (completed_at, pid) < ($4, $5::bigint))
+            ORDER BY completed_at DESC, pid DESC
+            LIMIT $6
+            "#,
+            user_id,
+            from,
+            to,
+            before_at,
+            before_pid,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Archived logs written in `[from, to)` on the player's own servers,
+    /// newest first, from before log `before` when paging
+    pub async fn logs(
+        pool: &PgPool,
+        user_id: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        before: Option<(DateTime<Utc>, i64)>,
+        limit: i64,
+    ) -> Result<Vec<ArchivedLogRow>> {
+        let (before_at, before_id) = before.unzip();
+        let rows = sqlx::query_as!(
+            ArchivedLogRow,
+            r#"
+            SELECT l.id, l.server_id, l.type AS log_type, l.message, host(l.ip_address) AS ip_address,
+                l.is_deleted, l.created_at
+            FROM archive.logs l
+            JOIN servers s ON s.id = l.server_id AND s.user_id = $1
+            WHERE l.created_at >= $2 AND l.created_at < $3
+              AND ($4::timestamptz IS NULL OR (l.created_at, l.id) < ($4, $5::bigint))
+            ORDER BY l.created_at DESC, l.id DESC
+            LIMIT $6
+            "#,
+            user_id,
+            from,
+            to,
+            before_at,
+            before_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+/// A heist window on an NPC bank
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HeistRow {
+    pub id: i64,
+    pub bank_id: i64,
+    pub bank_name: String,
+    pub opens_at: DateTime<Utc>,
+    pub closes_at: DateTime<Utc>,
+    pub vault: i64,
+}
+
+/// A crew's raid instance, with the end of its heist's window
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HeistRaidRow {
+    pub id: i64,
+    pub heist_id: i64,
+    pub leader_id: i64,
+    pub stage: String,
+    pub closes_at: DateTime<Utc>,
+    pub firewall_down_until: Option<DateTime<Utc>>,
+    pub trace_at: Option<DateTime<Utc>>,
+    pub loot: Option<i64>,
+    pub failure_reason: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HeistMemberRow {
+    pub user_id: i64,
+    pub role: String,
+    pub actions: i32,
+    pub share: Option<i64>,
+}
+
+pub struct HeistQueries;
+
+impl HeistQueries {
+    /// Open a heist window on a random bank of `min_tier` or above that runs
+    /// on a server, unless a window is already open
+    pub async fn open(pool: &PgPool, min_tier: i16, window_minutes: i64, vault: i64) -> Result<Option<HeistRow>> {
+        let heist = sqlx::query_as!(
+            HeistRow,
+            r#"
+            WITH opened AS (
+                INSERT INTO bank_heists (bank_id, closes_at, vault)
+                SELECT b.id, NOW() + make_interval(mins => $2::int), $3
+                FROM banks b
+                WHERE b.security_tier >= $1 AND b.server_id IS NOT NULL
+                  AND NOT EXISTS (SELECT 1 FROM bank_heists WHERE closes_at > NOW())
+                ORDER BY random()
+                LIMIT 1
+                RETURNING id, bank_id, opens_at, closes_at, vault
+            )
+            SELECT o.id AS "id!", o.bank_id AS "bank_id!", b.name AS bank_name,
+                   o.opens_at AS "opens_at!", o.closes_at AS "closes_at!", o.vault AS "vault!"
+            FROM opened o JOIN banks b ON b.id = o.bank_id
+            "#,
+            min_tier,
+            window_minutes as i32,
+            vault
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(heist)
+    }
+
+    /// Heists whose window is open now
+    pub async fn open_heists(pool: &PgPool) -> Result<Vec<HeistRow>> {
+        let heists = sqlx::query_as!(
+            HeistRow,
+            r#"
+            SELECT h.id, h.bank_id, b.name AS bank_name, h.opens_at, h.closes_at, h.vault
+            FROM bank_heists h JOIN banks b ON b.id = h.bank_id
+            WHERE h.opens_at <= NOW() AND h.closes_at > NOW()
+            ORDER BY h.closes_at
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(heists)
+    }
+
+    /// Lock a heist, and so its vault, for the rest of the transaction
+    pub async fn lock_heist(conn: &mut PgConnection, heist_id: i64) -> Result<Option<HeistRow>> {
+        let heist = sqlx::query_as!(
+            HeistRow,
+            r#"
+            SELECT h.id, h.bank_id, b.name AS bank_name, h.opens_at, h.closes_at, h.vault
+            FROM bank_heists h JOIN banks b ON b.id = h.bank_id
+            WHERE h.id = $1
+            FOR UPDATE OF h
+            "#,
+            heist_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(heist)
+    }
+
+    /// Whether the user is in a crew whose raid has not ended. Locks the user
+    /// row, so concurrent joins by the same player queue up.
+    pub async fn in_raid(conn: &mut PgConnection, user_id: i64) -> Result<bool> {
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", user_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+        let in_raid = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM heist_raid_members m
+                JOIN heist_raids r ON r.id = m.raid_id
+                WHERE m.user_id = $1 AND r.stage NOT IN ('escaped', 'failed')
+            ) AS "in_raid!"
+            "#,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(in_raid)
+    }
+
+    /// Form a crew against a heist, led by `leader_id` in `role`
+    pub async fn create_raid(conn: &mut PgConnection, heist_id: i64, leader_id: i64, role: &str) -> Result<i64> {
+        let raid_id = sqlx::query_scalar!(
+            "INSERT INTO heist_raids (heist_id, leader_id) VALUES ($1, $2) RETURNING id",
+            heist_id,
+            leader_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Self::add_member(conn, raid_id, leader_id, role).await?;
+        Ok(raid_id)
+    }
+
+    /// Lock a raid for the rest of the transaction
+    pub async fn lock_raid(conn: &mut PgConnection, raid_id: i64) -> Result<Option<HeistRaidRow>> {
+        let raid = sqlx::query_as!(
+            HeistRaidRow,
+            r#"
+            SELECT r.id, r.heist_id, r.leader_id, r.stage, h.closes_at, r.firewall_down_until, r.trace_at,
+                   r.loot, r.failure_reason, r.started_at, r.ended_at
+            FROM heist_raids r JOIN bank_heists h ON h.id = r.heist_id
+            WHERE r.id = $1
+            FOR UPDATE OF r
+            "#,
+            raid_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(raid)
+    }
+
+    pub async fn raid(pool: &PgPool, raid_id: i64) -> Result<Option<HeistRaidRow>> {
+        let raid = sqlx::query_as!(
+            HeistRaidRow,
+            r#"
+            SELECT r.id, r.heist_id, r.leader_id, r.stage, h.closes_at, r.firewall_down_until, r.trace_at,
+                   r.loot, r.failure_reason, r.started_at, r.ended_at
+            FROM heist_raids r JOIN bank_heists h ON h.id = r.heist_id
+            WHERE r.id = $1
+            "#,
+            raid_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(raid)
+    }
+
+    pub async fn crew(conn: &mut PgConnection, raid_id: i64) -> Result<Vec<HeistMemberRow>> {
+        let crew = sqlx::query_as!(
+            HeistMemberRow,
+            r#"
+            SELECT user_id, role, actions, share
+            FROM heist_raid_members WHERE raid_id = $1
+            ORDER BY joined_at
+            "#,
+            raid_id
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(crew)
+    }
+
+    pub async fn add_member(conn: &mut PgConnection, raid_id: i64, user_id: i64, role: &str) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO heist_raid_members (raid_id, user_id, role) VALUES ($1, $2, $3)",
+            raid_id,
+            user_id,
+            role
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Take a member out of a crew that is still recruiting
+    pub async fn remove_member(conn: &mut PgConnection, raid_id: i64, user_id: i64) -> Result<bool> {
+        let removed = sqlx::query!(
+            "DELETE FROM heist_raid_members WHERE raid_id = $1 AND user_id = $2",
+            raid_id,
+            user_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected();
+
+        Ok(removed > 0)
+    }
+
+    /// Store where a running raid is; leaving recruiting stamps the start
+    pub async fn save_state(
+        conn: &mut PgConnection,
+        raid_id: i64,
+        stage: &str,
+        firewall_down_until: Option<DateTime<Utc>>,
+        trace_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE heist_raids
+            SET stage = $2, firewall_down_until = $3, trace_at = $4,
+                started_at = CASE WHEN $2 <> 'recruiting' THEN COALESCE(started_at, NOW()) END
+            WHERE id = $1
+            "#,
+            raid_id,
+            stage,
+            firewall_down_until,
+            trace_at
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_action(conn: &mut PgConnection, raid_id: i64, user_id: i64) -> Result<()> {
+        sqlx::query!(
+            "UPDATE heist_raid_members SET actions = actions + 1 WHERE raid_id = $1 AND user_id = $2",
+            raid_id,
+            user_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Take `amount` out of a locked heist's vault
+    pub async fn take(conn: &mut PgConnection, heist_id: i64, amount: i64) -> Result<()> {
+        sqlx::query!(
+            "UPDATE bank_heists SET vault = vault - $2 WHERE id = $1",
+            heist_id,
+            amount
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// End a raid as escaped, with its loot, or failed, with the reason
+    pub async fn finish(
+        conn: &mut PgConnection,
+        raid_id: i64,
+        stage: &str,
+        loot: Option<i64>,
+        failure_reason: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE heist_raids
+            SET stage = $2, loot = $3, failure_reason = $4, ended_at = NOW()
+            WHERE id = $1
+            "#,
+            raid_id,
+            stage,
+            loot,
+            failure_reason
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_share(conn: &mut PgConnection, raid_id: i64, user_id: i64, share: i64) -> Result<()> {
+        sqlx::query!(
+            "UPDATE heist_raid_members SET share = $3 WHERE raid_id = $1 AND user_id = $2",
+            raid_id,
+            user_id,
+            share
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fail raids their heist window or trace ran out on, returning their ids
+    pub async fn fail_expired(pool: &PgPool, window_reason: &str, trace_reason: &str) -> Result<Vec<i64>> {
+        let failed = sqlx::query_scalar!(
+            r#"
+            UPDATE heist_raids r
+            SET stage = 'failed', ended_at = NOW(),
+                failure_reason = CASE WHEN h.closes_at <= NOW() THEN $1 ELSE $2 END
+            FROM bank_heists h
+            WHERE h.id = r.heist_id
+              AND r.stage NOT IN ('escaped', 'failed')
+              AND (h.closes_at <= NOW() OR (r.stage = 'cleanup' AND r.trace_at <= NOW()))
+            RETURNING r.id
+            "#,
+            window_reason,
+            trace_reason
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(failed)
+    }
+}
+
+// ===== REQUEST LOGGING =====
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RequestLogSettingsRow {
+    pub sample_rate: f64,
+    pub max_body_bytes: i32,
+    pub updated_by: Option<i64>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct VerboseLoggingRow {
+    pub user_id: i64,
+    pub enabled_by: Option<i64>,
+    pub reason: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct RequestLogQueries;
+
+impl RequestLogQueries {
+    pub async fn settings(pool: &PgPool) -> Result<RequestLogSettingsRow> {
+        let settings = sqlx::query_as!(
+            RequestLogSettingsRow,
+            "SELECT sample_rate, max_body_bytes, updated_by, updated_at FROM request_log_settings"
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(settings)
+    }
+
+    pub async fn set_settings(
+        pool: &PgPool,
+        sample_rate: f64,
+        max_body_bytes: i32,
+        admin_id: i64,
+    ) -> Result<RequestLogSettingsRow> {
+        let settings = sqlx::query_as!(
+            RequestLogSettingsRow,
+            r#"
+            UPDATE request_log_settings
+            SET sample_rate = $1, max_body_bytes = $2, updated_by = $3, updated_at = NOW()
+            RETURNING sample_rate, max_body_bytes, updated_by, updated_at
+            "#,
+            sample_rate,
+            max_body_bytes,
+            admin_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(settings)
+    }
+
+    /// Users in verbose mode that has not expired
+    pub async fn verbose(pool: &PgPool) -> Result<Vec<VerboseLoggingRow>> {
+        let users = sqlx::query_as!(
+            VerboseLoggingRow,
+            r#"
+            SELECT user_id, enabled_by, reason, expires_at, created_at
+            FROM request_log_verbose
+            WHERE expires_at > NOW()
+            ORDER BY expires_at
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    /// Turn verbose mode on for `user_id` until `expires_at`, replacing any
+    /// earlier expiry
+    pub async fn set_verbose(
+        pool: &PgPool,
+        user_id: i64,
+        admin_id: i64,
+        reason: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<VerboseLoggingRow> {
+        let row = sqlx::query_as!(
+            VerboseLoggingRow,
+            r#"
+            INSERT INTO request_log_verbose (user_id, enabled_by, reason, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id) DO UPDATE
+            SET enabled_by = $2, reason = $3, expires_at = $4, created_at = NOW()
+            RETURNING user_id, enabled_by, reason, expires_at, created_at
+            "#,
+            user_id,
+            admin_id,
+            reason,
+            expires_at
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Turn verbose mode off for `user_id`; false if it was not on
+    pub async fn clear_verbose(pool: &PgPool, user_id: i64) -> Result<bool> {
+        let active = sqlx::query_scalar!(
+            r#"DELETE FROM request_log_verbose WHERE user_id = $1 RETURNING expires_at > NOW() AS "active!""#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(active.unwrap_or(false))
+    }
+}
+
+// ===== WHOIS REGISTRY =====
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DnsRecordRow {
+    pub hostname: String,
+    pub ip_address: String,
+    pub server_id: i64,
+    /// `None` for NPC hostnames
+    pub owner_id: Option<i64>,
+    pub owner: Option<String>,
+    pub fee_paid: i64,
+    pub registered_at: DateTime<Utc>,
+}
+
+pub struct DnsQueries;
+
+impl DnsQueries {
+    pub async fn resolve(pool: &PgPool, hostname: &str) -> Result<Option<DnsRecordRow>> {
+        let record = sqlx::query_as!(
+            DnsRecordRow,
+            r#"
+            SELECT d.hostname, host(s.ip_address) AS "ip_address!", d.server_id, d.owner_id,
+                   u.login AS "owner?", d.fee_paid, d.registered_at
+            FROM dns_records d
+            JOIN servers s ON s.id = d.server_id
+            LEFT JOIN users u ON u.id = d.owner_id
+            WHERE d.hostname = $1 AND s.is_active = TRUE
+            "#,
+            hostname
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Hostnames pointing at `ip`
+    pub async fn reverse(pool: &PgPool, ip: &str) -> Result<Vec<String>> {
+        let hostnames = sqlx::query_scalar!(
+            r#"
+            SELECT d.hostname
+            FROM dns_records d
+            JOIN servers s ON s.id = d.server_id
+            WHERE host(s.ip_address) = $1 AND s.is_active = TRUE
+            ORDER BY d.hostname
+            "#,
+            ip
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(hostnames)
+    }
+
+    /// Hostnames the player registered
+    pub async fn owned(pool: &PgPool, user_id: i64) -> Result<Vec<DnsRecordRow>> {
+        let records = sqlx::query_as!(
+            DnsRecordRow,
+            r#"
+            SELECT d.hostname, host(s.ip_address) AS "ip_address!", d.server_id, d.owner_id,
+                   u.login AS "owner?", d.fee_paid, d.registered_at
+            FROM dns_records d
+            JOIN servers s ON s.id = d.server_id
+            LEFT JOIN users u ON u.id = d.owner_id
+            WHERE d.owner_id = $1
+            ORDER BY d.registered_at
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    pub async fn count_owned(conn: &mut PgConnection, user_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM dns_records WHERE owner_id = $1"#,
+            user_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// The player's main server, the one their hostnames point at
+    pub async fn main_server(conn: &mut PgConnection, user_id: i64) -> Result<Option<i64>> {
+        let id = sqlx::query_scalar!(
+            "SELECT id FROM servers WHERE user_id = $1 AND is_npc = FALSE ORDER BY id LIMIT 1",
+            user_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Register `hostname`; false if it is taken
+    pub async fn register(
+        conn: &mut PgConnection,
+        hostname: &str,
+        server_id: i64,
+        owner_id: i64,
+        fee: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO dns_records (hostname, server_id, owner_id, fee_paid)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (hostname) DO NOTHING
+            "#,
+            hostname,
+            server_id,
+            owner_id,
+            fee
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Give up `hostname`; false unless `owner_id` held it
+    pub async fn release(pool: &PgPool, hostname: &str, owner_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM dns_records WHERE hostname = $1 AND owner_id = $2",
+            hostname,
+            owner_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Log `message` on the registry server at `registry_ip`, with the
+    /// player's gateway address
+    pub async fn log_lookup(pool: &PgPool, registry_ip: &str, user_id: i64, message: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO logs (server_id, user_id, type, message, ip_address)
+            SELECT r.id, r.user_id, 'whois',
+                $3 || ' from ' || COALESCE(host(g.ip_address), 'unknown'), g.ip_address
+            FROM servers r
+            LEFT JOIN LATERAL (
+                SELECT ip_address FROM servers
+                WHERE user_id = $2 AND is_npc = FALSE
+                ORDER BY id
+                LIMIT 1
+            ) g ON TRUE
+            WHERE host(r.ip_address) = $1
+            "#,
+            registry_ip,
+            user_id,
+            message
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Who a bulk operation applies to; every field narrows the match
+#[derive(Debug, Clone, Default)]
+pub struct BulkFilter {
+    pub user_ids: Option<Vec<i64>>,
+    pub registered_since: Option<DateTime<Utc>>,
+    pub registered_until: Option<DateTime<Utc>>,
+    /// Players with a bank ledger entry for this reason
+    pub ledger_reason: Option<String>,
+    /// Players with a bank ledger entry under this reference
+    pub ledger_reference: Option<String>,
+    /// Window for the ledger entry; ignored without a reason or reference
+    pub ledger_since: Option<DateTime<Utc>>,
+    pub ledger_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BulkOperationRow {
+    pub id: i64,
+    pub title: String,
+    pub reason: String,
+    pub filter: serde_json::Value,
+    pub mutation: serde_json::Value,
+    pub chunk_size: i32,
+    pub throttle_ms: i32,
+    pub status: String,
+    pub matched: i32,
+    pub preview: serde_json::Value,
+    pub applied: i32,
+    pub skipped: i32,
+    pub rolled_back: i32,
+    pub rollback_failed: i32,
+    pub created_by: i64,
+    pub planned_at: DateTime<Utc>,
+    pub executed_by: Option<i64>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub rolled_back_by: Option<i64>,
+    pub rollback_reason: Option<String>,
+    pub rollback_started_at: Option<DateTime<Utc>>,
+    pub rollback_finished_at: Option<DateTime<Utc>>,
+    pub next_chunk_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+/// One matched player and what the operation did to them
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BulkTargetRow {
+    pub operation_id: i64,
+    pub user_id: i64,
+    pub status: String,
+    pub bank_account_id: Option<i64>,
+    pub before_value: Option<i64>,
+    pub after_value: Option<i64>,
+    pub note: Option<String>,
+    pub applied_at: Option<DateTime<Utc>>,
+    pub rolled_back_at: Option<DateTime<Utc>>,
+}
+
+/// Counts a worker chunk adds to its operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BulkChunkCounts {
+    pub applied: i32,
+    pub skipped: i32,
+    pub rolled_back: i32,
+    pub rollback_failed: i32,
+}
+
+pub struct BulkOperationQueries;
+
+impl BulkOperationQueries {
+    /// Record a planned operation and snapshot the players `filter` matches
+    /// as its pending rows. Returns its id and how many players matched.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn plan(
+        conn: &mut PgConnection,
+        title: &str,
+        reason: &str,
+        filter: &BulkFilter,
+        filter_spec: &serde_json::Value,
+        mutation: &serde_json::Value,
+        chunk_size: i32,
+        throttle_ms: i32,
+        created_by: i64,
+    ) -> Result<(i64, i64)> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO bulk_operations (title, reason, filter, mutation, chunk_size, throttle_ms, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+            title,
+            reason,
+            filter_spec,
+            mutation,
+            chunk_size,
+            throttle_ms,
+            created_by
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let matched = sqlx::query!(
+            r#"
+            INSERT INTO bulk_operation_rows (operation_id, user_id)
+            SELECT $1, u.id FROM users u
+            WHERE ($2::BIGINT[] IS NULL OR u.id = ANY($2))
+              AND ($3::TIMESTAMPTZ IS NULL OR u.created_at >= $3)
+              AND ($4::TIMESTAMPTZ IS NULL OR u.created_at < $4)
+              AND (($5::TEXT IS NULL AND $6::TEXT IS NULL) OR EXISTS (
+                  SELECT 1 FROM ledger_entries e
+                  JOIN ledger_transactions t ON t.id = e.transaction_id
+                  JOIN bank_accounts b ON b.id = e.bank_account_id
+                  WHERE b.user_id = u.id
+                    AND ($5::TEXT IS NULL OR t.reason = $5)
+                    AND ($6::TEXT IS NULL OR t.reference = $6)
+                    AND ($7::TIMESTAMPTZ IS NULL OR e.created_at >= $7)
+                    AND ($8::TIMESTAMPTZ IS NULL OR e.created_at < $8)
+              ))
+            "#,
+            id,
+            filter.user_ids.as_deref(),
+            filter.registered_since,
+            filter.registered_until,
+            filter.ledger_reason,
+            filter.ledger_reference,
+            filter.ledger_since,
+            filter.ledger_until
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected() as i64;
+
+        sqlx::query!("UPDATE bulk_operations SET matched = $2 WHERE id = $1", id, matched as i32)
+            .execute(conn)
+            .await?;
+
+        Ok((id, matched))
+    }
+
+    /// The first `limit` players an operation matched
+    pub async fn sample(conn: &mut PgConnection, operation_id: i64, limit: i64) -> Result<Vec<i64>> {
+        let user_ids = sqlx::query_scalar!(
+            "SELECT user_id FROM bulk_operation_rows WHERE operation_id = $1 ORDER BY user_id LIMIT $2",
+            operation_id,
+            limit
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(user_ids)
+    }
+
+    pub async fn set_preview(conn: &mut PgConnection, operation_id: i64, preview: &serde_json::Value) -> Result<()> {
+        sqlx::query!("UPDATE bulk_operations SET preview = $2 WHERE id = $1", operation_id, preview)
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &PgPool, operation_id: i64) -> Result<Option<BulkOperationRow>> {
+        let operation = sqlx::query_as!(
+            BulkOperationRow,
+            "SELECT * FROM bulk_operations WHERE id = $1",
+            operation_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(operation)
+    }
+
+    /// Operations, most recently planned first
+    pub async fn list(pool: &PgPool, limit: i64) -> Result<Vec<BulkOperationRow>> {
+        let operations = sqlx::query_as!(
+            BulkOperationRow,
+            "SELECT * FROM bulk_operations ORDER BY planned_at DESC, id DESC LIMIT $1",
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(operations)
+    }
+
+    /// An operation's rows by player id, optionally in one status
+    pub async fn rows(
+        pool: &PgPool,
+        operation_id: i64,
+        status: Option<&str>,
+        after_user_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<BulkTargetRow>> {
+        let rows = sqlx::query_as!(
+            BulkTargetRow,
+            r#"
+            SELECT * FROM bulk_operation_rows
+            WHERE operation_id = $1
+              AND ($2::TEXT IS NULL OR status = $2)
+              AND ($3::BIGINT IS NULL OR user_id > $3)
+            ORDER BY user_id
+            LIMIT $4
+            "#,
+            operation_id,
+            status,
+            after_user_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Start a planned operation, or resume a failed one that was never
+    /// rolled back. `None` if it is in neither state, its dry run matched a
+    /// different count or it was planned before `planned_since`.
+    pub async fn start(
+        pool: &PgPool,
+        operation_id: i64,
+        admin_id: i64,
+        matched: i32,
+        planned_since: DateTime<Utc>,
+    ) -> Result<Option<BulkOperationRow>> {
+        let operation = sqlx::query_as!(
+            BulkOperationRow,
+            r#"
+            UPDATE bulk_operations SET
+                status = 'running',
+                executed_by = $2,
+                started_at = COALESCE(started_at, NOW()),
+                next_chunk_at = NOW(),
+                error = NULL
+            WHERE id = $1
+              AND (status = 'planned' OR (status = 'failed' AND rollback_started_at IS NULL))
+              AND matched = $3
+              AND planned_at >= $4
+            RETURNING *
+            "#,
+            operation_id,
+            admin_id,
+            matched,
+            planned_since
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(operation)
+    }
+
+    /// Stop a running operation before its next chunk; `None` if it is not
+    /// running
+    pub async fn cancel(pool: &PgPool, operation_id: i64) -> Result<Option<BulkOperationRow>> {
+        let operation = sqlx::query_as!(
+            BulkOperationRow,
+            r#"
+            UPDATE bulk_operations SET status = 'cancelled', next_chunk_at = NULL, finished_at = NOW()
+            WHERE id = $1 AND status = 'running'
+            RETURNING *
+            "#,
+            operation_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(operation)
+    }
+
+    /// Start undoing an operation that has stopped and applied something;
+    /// `None` if it cannot be rolled back
+    pub async fn start_rollback(
+        pool: &PgPool,
+        operation_id: i64,
+        admin_id: i64,
+        reason: &str,
+    ) -> Result<Option<BulkOperationRow>> {
+        let operation = sqlx::query_as!(
+            BulkOperationRow,
+            r#"
+            UPDATE bulk_operations SET
+                status = 'rolling_back',
+                rolled_back_by = $2,
+                rollback_reason = $3,
+                rollback_started_at = COALESCE(rollback_started_at, NOW()),
+                next_chunk_at = NOW(),
+                error = NULL
+            WHERE id = $1
+              AND status IN ('completed', 'cancelled', 'failed')
+              AND applied > rolled_back
+            RETURNING *
+            "#,
+            operation_id,
+            admin_id,
+            reason
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(operation)
+    }
+
+    /// The running or rolling back operation whose next chunk is most
+    /// overdue, locked so no other node takes it
+    pub async fn claim_due(conn: &mut PgConnection) -> Result<Option<BulkOperationRow>> {
+        let operation = sqlx::query_as!(
+            BulkOperationRow,
+            r#"
+            SELECT * FROM bulk_operations
+            WHERE status IN ('running', 'rolling_back') AND next_chunk_at <= NOW()
+            ORDER BY next_chunk_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+            "#
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(operation)
+    }
+
+    /// The next `limit` rows in `status`, locked for the caller's transaction
+    pub async fn chunk(conn: &mut PgConnection, operation_id: i64, status: &str, limit: i64) -> Result<Vec<BulkTargetRow>> {
+        let rows = sqlx::query_as!(
+            BulkTargetRow,
+            r#"
+            SELECT * FROM bulk_operation_rows
+            WHERE operation_id = $1 AND status = $2
+            ORDER BY user_id
+            LIMIT $3
+            FOR UPDATE
+            "#,
+            operation_id,
+            status,
+            limit
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(rows)
+    }
+
+    pub async fn record_applied(
+        conn: &mut PgConnection,
+        operation_id: i64,
+        user_id: i64,
+        bank_account_id: Option<i64>,
+        before: i64,
+        after: i64,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE bulk_operation_rows SET
+                status = 'applied', bank_account_id = $3, before_value = $4, after_value = $5, applied_at = NOW()
+            WHERE operation_id = $1 AND user_id = $2
+            "#,
+            operation_id,
+            user_id,
+            bank_account_id,
+            before,
+            after
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_skipped(
+        conn: &mut PgConnection,
+        operation_id: i64,
+        user_id: i64,
+        before: Option<i64>,
+        note: &str,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE bulk_operation_rows SET status = 'skipped', before_value = $3, note = $4, applied_at = NOW()
+            WHERE operation_id = $1 AND user_id = $2
+            "#,
+            operation_id,
+            user_id,
+            before,
+            note
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark an applied row undone, or that undoing it failed and why
+    pub async fn record_rollback(
+        conn: &mut PgConnection,
+        operation_id: i64,
+        user_id: i64,
+        failure: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE bulk_operation_rows SET
+                status = CASE WHEN $3::TEXT IS NULL THEN 'rolled_back' ELSE 'rollback_failed' END,
+                note = COALESCE($3, note),
+                rolled_back_at = NOW()
+            WHERE operation_id = $1 AND user_id = $2
+            "#,
+            operation_id,
+            user_id,
+            failure
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Add a chunk's counts to its operation, and either schedule the next
+    /// chunk or, when `next_chunk_at` is `None`, finish the run or rollback
+    pub async fn finish_chunk(
+        conn: &mut PgConnection,
+        operation_id: i64,
+        counts: BulkChunkCounts,
+        next_chunk_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE bulk_operations SET
+                applied = applied + $2,
+                skipped = skipped + $3,
+                rolled_back = rolled_back + $4,
+                rollback_failed = rollback_failed + $5,
+                next_chunk_at = $6,
+                status = CASE
+                    WHEN $6::TIMESTAMPTZ IS NOT NULL THEN status
+                    WHEN status = 'running' THEN 'completed'
+                    ELSE 'rolled_back'
+                END,
+                finished_at = CASE
+                    WHEN $6::TIMESTAMPTZ IS NULL AND status = 'running' THEN NOW()
+                    ELSE finished_at
+                END,
+                rollback_finished_at = CASE
+                    WHEN $6::TIMESTAMPTZ IS NULL AND status = 'rolling_back' THEN NOW()
+                    ELSE rollback_finished_at
+                END
+            WHERE id = $1
+            "#,
+            operation_id,
+            counts.applied,
+            counts.skipped,
+            counts.rolled_back,
+            counts.rollback_failed,
+            next_chunk_at
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stop a running or rolling back operation after an error
+    pub async fn fail(pool: &PgPool, operation_id: i64, error: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE bulk_operations SET status = 'failed', next_chunk_at = NULL, error = $2
+            WHERE id = $1 AND status IN ('running', 'rolling_back')
+            "#,
+            operation_id,
+            error
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// One ledger reason's share of a batch the economy stream folded in
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EconomyFlowRow {
+    pub reason: String,
+    pub transactions: i64,
+    pub minted: i64,
+    pub sunk: i64,
+    pub volume: i64,
+}
+
+/// Every reason's totals for one minute
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EconomyMinuteRow {
+    pub minute: DateTime<Utc>,
+    pub transactions: i64,
+    pub minted: i64,
+    pub sunk: i64,
+    pub volume: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TopEarnerRow {
+    pub user_id: i64,
+    /// `None` if the account has since been deleted
+    pub login: Option<String>,
+    pub earned: i64,
+    pub spent: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EconomyAnomalyRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub login: Option<String>,
+    pub window_start: DateTime<Utc>,
+    pub earned: i64,
+    pub baseline: f64,
+    pub ratio: f64,
+    pub flagged_at: DateTime<Utc>,
+}
+
+/// A player's earnings in the spike window against their baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpikeCandidate {
+    pub user_id: i64,
+    pub earned: i64,
+    /// Everything they earned over the baseline period
+    pub baseline_earned: i64,
+}
+
+pub struct EconomyStreamQueries;
+
+impl EconomyStreamQueries {
+    /// The stream's cursor, locked for the caller's transaction; `None` if
+    /// another node holds it
+    pub async fn claim_cursor(conn: &mut PgConnection) -> Result<Option<i64>> {
+        let cursor = sqlx::query_scalar!(
+            "SELECT last_transaction_id FROM economy_stream_cursor WHERE id FOR UPDATE SKIP LOCKED"
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(cursor)
+    }
+
+    /// The last id of the next `limit` transactions after `after` made
+    /// before `settled_before`; `None` if there are none
+    pub async fn batch_end(
+        conn: &mut PgConnection,
+        after: i64,
+        settled_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Option<i64>> {
+        let end = sqlx::query_scalar!(
+            r#"
+            SELECT MAX(id) FROM (
+                SELECT id FROM ledger_transactions
+                WHERE id > $1 AND created_at < $2
+                ORDER BY id
+                LIMIT $3
+            ) batch
+            "#,
+            after,
+            settled_before,
+            limit
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(end)
+    }
+
+    /// Fold the transactions in `(after, upto]` into the minute series,
+    /// move the cursor to `upto` and return the batch's totals per reason
+    pub async fn fold(conn: &mut PgConnection, after: i64, upto: i64) -> Result<Vec<EconomyFlowRow>> {
+        sqlx::query!(
+            r#"
+            INSERT INTO economy_minutes (minute, reason, transactions, minted, sunk, volume)
+            SELECT date_trunc('minute', t.created_at), t.reason,
+                   COUNT(DISTINCT t.id),
+                   COALESCE(SUM(-e.amount) FILTER (WHERE e.account = 'system:mint'), 0)::BIGINT,
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.account = 'system:sink'), 0)::BIGINT,
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.amount > 0), 0)::BIGINT
+            FROM ledger_transactions t
+            JOIN ledger_entries e ON e.transaction_id = t.id
+            WHERE t.id > $1 AND t.id <= $2
+            GROUP BY 1, 2
+            ON CONFLICT (minute, reason) DO UPDATE SET
+                transactions = economy_minutes.transactions + EXCLUDED.transactions,
+                minted = economy_minutes.minted + EXCLUDED.minted,
+                sunk = economy_minutes.sunk + EXCLUDED.sunk,
+                volume = economy_minutes.volume + EXCLUDED.volume
+            "#,
+            after,
+            upto
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        // Net per player and transaction first, so money moved between one
+        // player's own accounts is neither earned nor spent
+        sqlx::query!(
+            r#"
+            INSERT INTO economy_earner_minutes (minute, user_id, earned, spent)
+            SELECT date_trunc('minute', t.created_at), n.user_id,
+                   SUM(GREATEST(n.amount, 0))::BIGINT,
+                   SUM(GREATEST(-n.amount, 0))::BIGINT
+            FROM (
+                SELECT e.transaction_id, b.user_id, SUM(e.amount) AS amount
+                FROM ledger_entries e
+                JOIN bank_accounts b ON b.id = e.bank_account_id
+                WHERE e.transaction_id > $1 AND e.transaction_id <= $2
+                GROUP BY e.transaction_id, b.user_id
+            ) n
+            JOIN ledger_transactions t ON t.id = n.transaction_id
+            GROUP BY 1, 2
+            ON CONFLICT (minute, user_id) DO UPDATE SET
+                earned = economy_earner_minutes.earned + EXCLUDED.earned,
+                spent = economy_earner_minutes.spent + EXCLUDED.spent
+            "#,
+            after,
+            upto
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let flows = sqlx::query_as!(
+            EconomyFlowRow,
+            r#"
+            SELECT t.reason,
+                   COUNT(DISTINCT t.id) AS "transactions!",
+                   COALESCE(SUM(-e.amount) FILTER (WHERE e.account = 'system:mint'), 0)::BIGINT AS "minted!",
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.account = 'system:sink'), 0)::BIGINT AS "sunk!",
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.amount > 0), 0)::BIGINT AS "volume!"
+            FROM ledger_transactions t
+            JOIN ledger_entries e ON e.transaction_id = t.id
+            WHERE t.id > $1 AND t.id <= $2
+            GROUP BY t.reason
+            "#,
+            after,
+            upto
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE economy_stream_cursor SET last_transaction_id = $1, updated_at = NOW() WHERE id",
+            upto
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(flows)
+    }
+
+    /// Every reason's totals per minute from `since`, oldest first
+    pub async fn minutes(pool: &PgPool, since: DateTime<Utc>) -> Result<Vec<EconomyMinuteRow>> {
+        let rows = sqlx::query_as!(
+            EconomyMinuteRow,
+            r#"
+            SELECT minute,
+                   SUM(transactions)::BIGINT AS "transactions!",
+                   SUM(minted)::BIGINT AS "minted!",
+                   SUM(sunk)::BIGINT AS "sunk!",
+                   SUM(volume)::BIGINT AS "volume!"
+            FROM economy_minutes
+            WHERE minute >= $1
+            GROUP BY minute
+            ORDER BY minute
+            "#,
+            since
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Totals per reason from `since`, most money moved first
+    pub async fn reasons(pool: &PgPool, since: DateTime<Utc>) -> Result<Vec<EconomyFlowRow>> {
+        let rows = sqlx::query_as!(
+            EconomyFlowRow,
+            r#"
+            SELECT reason,
+                   SUM(transactions)::BIGINT AS "transactions!",
+                   SUM(minted)::BIGINT AS "minted!",
+                   SUM(sunk)::BIGINT AS "sunk!",
+                   SUM(volume)::BIGINT AS "volume!"
+            FROM economy_minutes
+            WHERE minute >= $1
+            GROUP BY reason
+            ORDER BY SUM(volume) DESC
+            "#,
+            since
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Players who earned the most from `since`
+    pub async fn top_earners(pool: &PgPool, since: DateTime<Utc>, limit: i64) -> Result<Vec<TopEarnerRow>> {
+        let rows = sqlx::query_as!(
+            TopEarnerRow,
+            r#"
+            SELECT m.user_id, u.login AS "login?",
+                   SUM(m.earned)::BIGINT AS "earned!",
+                   SUM(m.spent)::BIGINT AS "spent!"
+            FROM economy_earner_minutes m
+            LEFT JOIN users u ON u.id = m.user_id
+            WHERE m.minute >= $1
+            GROUP BY m.user_id, u.login
+            ORDER BY SUM(m.earned) DESC
+            LIMIT $2
+            "#,
+            since,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Players who earned at least `floor` from `window_since`, with what
+    /// they earned over `[baseline_since, window_since)`
+    pub async fn spike_candidates(
+        pool: &PgPool,
+        window_since: DateTime<Utc>,
+        baseline_since: DateTime<Utc>,
+        floor: i64,
+    ) -> Result<Vec<SpikeCandidate>> {
+        let rows = sqlx::query_as!(
+            SpikeCandidate,
+            r#"
+            SELECT r.user_id AS "user_id!", r.earned AS "earned!",
+                   COALESCE(SUM(b.earned), 0)::BIGINT AS "baseline_earned!"
+            FROM (
+                SELECT user_id, SUM(earned)::BIGINT AS earned
+                FROM economy_earner_minutes
+                WHERE minute >= $1
+                GROUP BY user_id
+                HAVING SUM(earned) >= $3
+            ) r
+            LEFT JOIN economy_earner_minutes b
+                ON b.user_id = r.user_id AND b.minute >= $2 AND b.minute < $1
+            GROUP BY r.user_id, r.earned
+            "#,
+            window_since,
+            baseline_since,
+            floor
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Flag a spike; `None` if this player was already flagged for the
+    /// window
+    pub async fn flag(
+        pool: &PgPool,
+        user_id: i64,
+        window_start: DateTime<Utc>,
+        earned: i64,
+        baseline: f64,
+        ratio: f64,
+    ) -> Result<Option<i64>> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO economy_anomalies (user_id, window_start, earned, baseline, ratio)
+            SELECT $1, $2, $3, $4, $5
+            WHERE EXISTS (SELECT 1 FROM users WHERE id = $1)
+            ON CONFLICT (user_id, window_start) DO NOTHING
+            RETURNING id
+            "#,
+            user_id,
+            window_start,
+            earned,
+            baseline,
+            ratio
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Anomalies nobody has acknowledged, newest first
+    pub async fn open_anomalies(pool: &PgPool, limit: i64) -> Result<Vec<EconomyAnomalyRow>> {
+        let rows = sqlx::query_as!(
+            EconomyAnomalyRow,
+            r#"
+            SELECT a.id, a.user_id, u.login AS "login?", a.window_start, a.earned, a.baseline, a.ratio, a.flagged_at
+            FROM economy_anomalies a
+            LEFT JOIN users u ON u.id = a.user_id
+            WHERE a.acknowledged_at IS NULL
+            ORDER BY a.flagged_at DESC
+            LIMIT $1
+            "#,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// False if the anomaly does not exist or was acknowledged already
+    pub async fn acknowledge(pool: &PgPool, anomaly_id: i64, admin_id: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE economy_anomalies SET acknowledged_by = $2, acknowledged_at = NOW()
+            WHERE id = $1 AND acknowledged_at IS NULL
+            "#,
+            anomaly_id,
+            admin_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Drop minute series older than `minutes_before` and per-player
+    /// minutes older than `earners_before`
+    pub async fn prune(pool: &PgPool, minutes_before: DateTime<Utc>, earners_before: DateTime<Utc>) -> Result<u64> {
+        let minutes = sqlx::query!("DELETE FROM economy_minutes WHERE minute < $1", minutes_before)
+            .execute(pool)
+            .await?
+            .rows_affected();
+        let earners = sqlx::query!("DELETE FROM economy_earner_minutes WHERE minute < $1", earners_before)
+            .execute(pool)
+            .await?
+            .rows_affected();
+
+        Ok(minutes + earners)
+    }
+}
+
+// ===== OPENID CONNECT =====
+
+#[derive(Debug, Clone)]
+pub struct OidcClientRow {
+    pub client_id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub confidential: bool,
+    pub secret_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An authorization code or access token, with the player it was issued for
+#[derive(Debug, Clone)]
+pub struct OidcGrantRow {
+    pub client_id: String,
+    pub redirect_uri: Option<String>,
+    pub scopes: Vec<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub user_id: Uuid,
+    pub email: String,
+    pub roles: Vec<String>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub struct OidcQueries;
+
+impl OidcQueries {
+    pub async fn insert_client(pool: &PgPool, client: &OidcClientRow) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO oidc_clients (client_id, name, redirect_uris, confidential, secret_hash, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            client.client_id,
+            client.name,
+            &client.redirect_uris,
+            client.confidential,
+            client.secret_hash,
+            client.created_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn client(pool: &PgPool, client_id: &str) -> Result<Option<OidcClientRow>> {
+        let client = sqlx::query_as!(
+            OidcClientRow,
+            r#"
+            SELECT client_id, name, redirect_uris, confidential, secret_hash, created_at
+            FROM oidc_clients WHERE client_id = $1
+            "#,
+            client_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(client)
+    }
+
+    pub async fn list_clients(pool: &PgPool) -> Result<Vec<OidcClientRow>> {
+        let clients = sqlx::query_as!(
+            OidcClientRow,
+            r#"
+            SELECT client_id, name, redirect_uris, confidential, secret_hash, created_at
+            FROM oidc_clients ORDER BY created_at
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(clients)
+    }
+
+    /// Codes and tokens go with the client through the foreign keys
+    pub async fn remove_client(pool: &PgPool, client_id: &str) -> Result<bool> {
+        let result = sqlx::query!("DELETE FROM oidc_clients WHERE client_id = $1", client_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn insert_code(pool: &PgPool, code_hash: &str, code: &OidcGrantRow) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO oidc_codes (code_hash, client_id, redirect_uri, scopes, nonce, code_challenge,
+                                    user_id, email, roles, issued_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+            code_hash,
+            code.client_id,
+            code.redirect_uri,
+            &code.scopes,
+            code.nonce,
+            code.code_challenge,
+            code.user_id,
+            code.email,
+            &code.roles,
+            code.issued_at,
+            code.expires_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete and return a code, so only one exchange can get it
+    pub async fn take_code(pool: &PgPool, code_hash: &str) -> Result<Option<OidcGrantRow>> {
+        let code = sqlx::query_as!(
+            OidcGrantRow,
+            r#"
+            DELETE FROM oidc_codes WHERE code_hash = $1
+            RETURNING client_id, redirect_uri AS "redirect_uri?", scopes, nonce,
+                      code_challenge AS "code_challenge?", user_id, email, roles, issued_at, expires_at
+            "#,
+            code_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(code)
+    }
+
+    pub async fn insert_token(pool: &PgPool, token_hash: &str, token: &OidcGrantRow) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO oidc_tokens (token_hash, client_id, scopes, user_id, email, roles, issued_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            token_hash,
+            token.client_id,
+            &token.scopes,
+            token.user_id,
+            token.email,
+            &token.roles,
+            token.issued_at,
+            token.expires_at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn token(pool: &PgPool, token_hash: &str) -> Result<Option<OidcGrantRow>> {
+        let token = sqlx::query_as!(
+            OidcGrantRow,
+            r#"
+            SELECT client_id, NULL::TEXT AS "redirect_uri?", scopes, NULL::TEXT AS "nonce?",
+                   NULL::TEXT AS "code_challenge?", user_id, email, roles, issued_at, expires_at
+            FROM oidc_tokens WHERE token_hash = $1
+            "#,
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(token)
+    }
+
+    pub async fn revoke_token(pool: &PgPool, token_hash: &str, client_id: &str) -> Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM oidc_tokens WHERE token_hash = $1 AND client_id = $2",
+            token_hash,
+            client_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn revoke_user(pool: &PgPool, user_id: Uuid) -> Result<u64> {
+        let result = sqlx::query!("DELETE FROM oidc_tokens WHERE user_id = $1", user_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn purge_expired(pool: &PgPool, now: DateTime<Utc>) -> Result<u64> {
+        let codes = sqlx::query!("DELETE FROM oidc_codes WHERE expires_at <= $1", now)
+            .execute(pool)
+            .await?
+            .rows_affected();
+        let tokens = sqlx::query!("DELETE FROM oidc_tokens WHERE expires_at <= $1", now)
+            .execute(pool)
+            .await?
+            .rows_affected();
+
+        Ok(codes + tokens)
+    }
+}
+
+/// A Doom virus campaign; `campaign` is the serialized `DoomCampaign`
+#[derive(Debug, Clone)]
+pub struct DoomCampaignRow {
+    pub id: i64,
+    pub instigator_id: i64,
+    pub campaign: serde_json::Value,
+    pub step_started_at: Option<DateTime<Utc>>,
+    pub detonates_at: Option<DateTime<Utc>>,
+}
+
+/// A Doom payout, held until the winner has a bank account
+#[derive(Debug, Clone)]
+pub struct DoomRewardRow {
+    pub id: i64,
+    pub campaign_id: i64,
+    pub user_id: i64,
+    pub money: i64,
+}
+
+/// Doom virus campaigns, their clues and payouts
+pub struct DoomQueries;
+
+impl DoomQueries {
+    /// The player's campaign, locked for the action
+    pub async fn lock_own(conn: &mut PgConnection, instigator_id: i64) -> Result<Option<DoomCampaignRow>> {
+        let row = sqlx::query_as!(
+            DoomCampaignRow,
+            r#"
+            SELECT id, instigator_id, campaign, step_started_at, detonates_at
+            FROM doom_campaigns
+            WHERE instigator_id = $1 AND active
+            FOR UPDATE
+            "#,
+            instigator_id
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn own(pool: &PgPool, instigator_id: i64) -> Result<Option<DoomCampaignRow>> {
+        let row = sqlx::query_as!(
+            DoomCampaignRow,
+            r#"
+            SELECT id, instigator_id, campaign, step_started_at, detonates_at
+            FROM doom_campaigns
+            WHERE instigator_id = $1 AND active
+            "#,
+            instigator_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// The campaign counting down, if any
+    pub async fn countdown(conn: &mut PgConnection) -> Result<Option<DoomCampaignRow>> {
+        let row = sqlx::query_as!(
+            DoomCampaignRow,
+            r#"
+            SELECT id, instigator_id, campaign, step_started_at, detonates_at
+            FROM doom_campaigns
+            WHERE active AND detonates_at IS NOT NULL
+            "#
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    pub async fn lock_countdown(conn: &mut PgConnection) -> Result<Option<DoomCampaignRow>> {
+        let row = sqlx::query_as!(
+            DoomCampaignRow,
+            r#"
+            SELECT id, instigator_id, campaign, step_started_at, detonates_at
+            FROM doom_campaigns
+            WHERE active AND detonates_at IS NOT NULL
+            FOR UPDATE
+            "#
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// The countdown once it has run out; skipped while another node holds it
+    pub async fn lock_detonating(conn: &mut PgConnection, now: DateTime<Utc>) -> Result<Option<DoomCampaignRow>> {
+        let row = sqlx::query_as!(
+            DoomCampaignRow,
+            r#"
+            SELECT id, instigator_id, campaign, step_started_at, detonates_at
+            FROM doom_campaigns
+            WHERE active AND detonates_at <= $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            now
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Start the player's campaign; `None` when they already have one
+    pub async fn create(
+        conn: &mut PgConnection,
+        instigator_id: i64,
+        campaign: &serde_json::Value,
+    ) -> Result<Option<DoomCampaignRow>> {
+        let row = sqlx::query_as!(
+            DoomCampaignRow,
+            r#"
+            INSERT INTO doom_campaigns (instigator_id, campaign)
+            VALUES ($1, $2)
+            ON CONFLICT (instigator_id) WHERE active DO NOTHING
+            RETURNING id, instigator_id, campaign, step_started_at, detonates_at
+            "#,
+            instigator_id,
+            campaign
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Store the campaign's new state; a resolved campaign is no longer active
+    pub async fn save(
+        conn: &mut PgConnection,
+        id: i64,
+        campaign: &serde_json::Value,
+        step_started_at: Option<DateTime<Utc>>,
+        detonates_at: Option<DateTime<Utc>>,
+        active: bool,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE doom_campaigns
+            SET campaign = $2, step_started_at = $3, detonates_at = $4, active = $5, updated_at = NOW()
+            WHERE id = $1
+            "#,
+            id,
+            campaign,
+            step_started_at,
+            detonates_at,
+            active
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Leave a clue in the NPC's logs, where defenders can read it
+    pub async fn plant_clue(conn: &mut PgConnection, npc_ip: &str, log_line: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO logs (server_id, user_id, type, message)
+            SELECT id, user_id, 'doom', $2 FROM servers
+            WHERE host(ip_address) = $1 AND is_npc = TRUE
+            "#,
+            npc_ip,
+            log_line
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Doom lines still in the NPC's logs; a deleted line is a lost clue
+    pub async fn clue_logs(conn: &mut PgConnection, npc_ip: &str) -> Result<Vec<String>> {
+        let lines = sqlx::query_scalar!(
+            r#"
+            SELECT l.message FROM logs l
+            JOIN servers s ON s.id = l.server_id
+            WHERE host(s.ip_address) = $1 AND s.is_npc = TRUE AND l.type = 'doom' AND l.is_deleted = FALSE
+            ORDER BY l.id
+            "#,
+            npc_ip
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(lines)
+    }
+
+    pub async fn add_reward(conn: &mut PgConnection, campaign_id: i64, user_id: i64, money: i64) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO doom_rewards (campaign_id, user_id, money) VALUES ($1, $2, $3)",
+            campaign_id,
+            user_id,
+            money
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Pay held rewards whose winner now has a bank account. Returns the
+    /// rewards paid.
+    pub async fn pay_held_rewards(conn: &mut PgConnection, limit: i64) -> Result<Vec<DoomRewardRow>> {
+        let held = sqlx::query_as!(
+            DoomRewardRow,
+            r#"
+            SELECT r.id, r.campaign_id, r.user_id, r.money
+            FROM doom_rewards r
+            WHERE r.paid_at IS NULL AND EXISTS (
+                SELECT 1 FROM bank_accounts a WHERE a.user_id = r.user_id AND a.is_active = TRUE
+            )
+            ORDER BY r.id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            limit
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut paid = Vec::with_capacity(held.len());
+        for reward in held {
+            let credited = BankQueries::credit_primary_account(
+                &mut *conn,
+                reward.user_id,
+                reward.money,
+                LedgerAccount::Mint,
+                LedgerReason::DoomReward,
+                &format!("doom:{}:{}", reward.campaign_id, reward.id),
+            )
+            .await?;
+            if !credited {
+                continue;
+            }
+
+            sqlx::query!("UPDATE doom_rewards SET paid_at = NOW() WHERE id = $1", reward.id)
+                .execute(&mut *conn)
+                .await?;
+            paid.push(reward);
+        }
+
+        Ok(paid)
+    }
+
+    /// Tell every node's players, through the live-ops announcements
+    pub async fn announce(conn: &mut PgConnection, title: &str, content: &str, priority: &str) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO live_ops_announcements (title, content, priority) VALUES ($1, $2, $3)",
+            title,
+            content,
+            priority
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Content version of a cache key, behind HTTP ETags
+#[derive(Debug, Clone)]
+pub struct ContentVersionRow {
+    pub version: i64,
+    pub modified_at: DateTime<Utc>,
+}
+
+/// Content versions shared by every node when there is no Redis
+pub struct ContentVersionQueries;
+
+impl ContentVersionQueries {
+    /// Current version of `key`, starting it at 1 if it was never bumped
+    pub async fn current(pool: &PgPool, key: &str) -> Result<ContentVersionRow> {
+        let row = sqlx::query_as!(
+            ContentVersionRow,
+            r#"
+            WITH started AS (
+                INSERT INTO content_versions (key) VALUES ($1)
+                ON CONFLICT (key) DO NOTHING
+                RETURNING version, modified_at
+            )
+            SELECT version AS "version!", modified_at AS "modified_at!" FROM started
+            UNION ALL
+            SELECT version, modified_at FROM content_versions WHERE key = $1
+            LIMIT 1
+            "#,
+            key
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Record that the data behind `key` changed
+    pub async fn bump(pool: &PgPool, key: &str) -> Result<ContentVersionRow> {
+        let row = sqlx::query_as!(
+            ContentVersionRow,
+            r#"
+            INSERT INTO content_versions (key, version, modified_at) VALUES ($1, 2, NOW())
+            ON CONFLICT (key) DO UPDATE
+            SET version = content_versions.version + 1, modified_at = NOW()
+            RETURNING version, modified_at
+            "#,
+            key
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(row)
+    }
+}
+
+/// A stored formula override script
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FormulaScriptRow {
+    pub formula: String,
+    pub source: String,
+    pub updated_by: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Formula override scripts shared by every node
+pub struct FormulaScriptQueries;
+
+impl FormulaScriptQueries {
+    /// Latest revision of the script set, 0 before the first change
+    pub async fn revision(pool: &PgPool) -> Result<i64> {
+        let revision = sqlx::query_scalar!(
+            r#"SELECT COALESCE(MAX(id), 0) AS "id!" FROM formula_script_revisions"#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(revision)
+    }
+
+    pub async fn all(pool: &PgPool) -> Result<Vec<FormulaScriptRow>> {
+        let rows = sqlx::query_as!(
+            FormulaScriptRow,
+            "SELECT formula, source, updated_by, updated_at FROM formula_scripts ORDER BY formula"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Store `formula`'s script; returns the new revision
+    pub async fn save(pool: &PgPool, formula: &str, source: &str, admin_id: i64) -> Result<i64> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO formula_scripts (formula, source, updated_by) VALUES ($1, $2, $3)
+            ON CONFLICT (formula) DO UPDATE
+            SET source = EXCLUDED.source, updated_by = EXCLUDED.updated_by, updated_at = NOW()
+            "#,
+            formula,
+            source,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        let revision = Self::add_revision(&mut *tx, admin_id).await?;
+        tx.commit().await?;
+
+        Ok(revision)
+    }
+
+    /// Drop `formula`'s script so it runs natively again; returns the new
+    /// revision, or None if it had no script
+    pub async fn remove(pool: &PgPool, formula: &str, admin_id: i64) -> Result<Option<i64>> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        let removed = sqlx::query!("DELETE FROM formula_scripts WHERE formula = $1", formula)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if removed == 0 {
+            return Ok(None);
+        }
+        let revision = Self::add_revision(&mut *tx, admin_id).await?;
+        tx.commit().await?;
+
+        Ok(Some(revision))
+    }
+
+    /// Add a revision without changing a script, so every node recompiles
+    pub async fn bump(pool: &PgPool, admin_id: i64) -> Result<i64> {
+        let mut tx = crate::tagging::begin(pool).await?;
+        let revision = Self::add_revision(&mut *tx, admin_id).await?;
+        tx.commit().await?;
+
+        Ok(revision)
+    }
+
+    async fn add_revision(conn: &mut PgConnection, admin_id: i64) -> Result<i64> {
+        let revision = sqlx::query_scalar!(
+            "INSERT INTO formula_script_revisions (created_by) VALUES ($1) RETURNING id",
+            admin_id
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(revision)
+    }
+}
@@ -17,7 +17,8 @@ impl OptimizedUserQueries {
         let users = sqlx::query_as!(
             User,
             r#"
-            SELECT * FROM users
+            SELECT id, login, pwd, email, online, last_login, created, last_act, last_ip AS "last_ip!"
+            FROM users
             WHERE id = ANY($1)
             ORDER BY id
             "#,
@@ -26,7 +27,10 @@ impl OptimizedUserQueries {
         .fetch_all(pool)
         .await?;
 
-        Ok(users.into_iter().map(|u| (u.id, u)).collect())
+        users
+            .into_iter()
+            .map(|u| crate::encryption::reveal(u).map(|u| (u.id, u)))
+            .collect()
     }
 
     /// Get user with related data in single query using JOINs
@@ -35,7 +39,8 @@ impl OptimizedUserQueries {
         let result = sqlx::query!(
             r#"
             SELECT
-                u.*,
+                u.id, u.login, u.pwd, u.email, u.online, u.last_login, u.created, u.last_act,
+                u.last_ip AS "last_ip!",
                 COUNT(DISTINCT p.pid) as process_count,
                 COUNT(DISTINCT s.id) as software_count,
                 COALESCE(SUM(b.balance), 0) as total_balance
@@ -51,22 +56,26 @@ impl OptimizedUserQueries {
         .fetch_optional(pool)
         .await?;
 
-        Ok(result.map(|r| UserWithStats {
-            user: User {
-                id: r.id,
-                login: r.login,
-                pwd: r.pwd,
-                email: r.email,
-                online: r.online,
-                last_login: r.last_login,
-                created: r.created,
-                last_act: r.last_act,
-                last_ip: r.last_ip,
-            },
-            active_processes: r.process_count.unwrap_or(0),
-            software_count: r.software_count.unwrap_or(0),
-            total_balance: r.total_balance.unwrap_or(0),
-        }))
+        result
+            .map(|r| {
+                Ok(UserWithStats {
+                    user: crate::encryption::reveal(User {
+                        id: r.id,
+                        login: r.login,
+                        pwd: r.pwd,
+                        email: r.email,
+                        online: r.online,
+                        last_login: r.last_login,
+                        created: r.created,
+                        last_act: r.last_act,
+                        last_ip: r.last_ip,
+                    })?,
+                    active_processes: r.process_count.unwrap_or(0),
+                    software_count: r.software_count.unwrap_or(0),
+                    total_balance: r.total_balance.unwrap_or(0),
+                })
+            })
+            .transpose()
     }
 
     /// Update user activity with single atomic query
@@ -76,17 +85,20 @@ impl OptimizedUserQueries {
         user_id: i64,
         ip: &str
     ) -> Result<PgQueryResult> {
+        let sealed = crate::encryption::seal(ip)?;
         Ok(sqlx::query!(
             r#"
             UPDATE users
             SET
                 last_act = NOW(),
                 last_ip = $2,
+                last_ip_key_version = $3,
                 online = true
             WHERE id = $1
             "#,
             user_id,
-            ip
+            sealed.value,
+            sealed.key_version
         )
         .execute(pool)
         .await?)
@@ -324,7 +336,16 @@ pub struct ServerWithLoad {
     pub avg_process_time: i32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PopularServer {
+    pub server_id: i64,
+    pub ip: String,
+    pub hostname: Option<String>,
+    /// Players holding valid credentials for the server
+    pub hacked_by: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct LeaderboardEntry {
     pub user_id: i64,
     pub username: String,
@@ -335,7 +356,7 @@ pub struct LeaderboardEntry {
     pub rank: i32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct ClanLeaderboardEntry {
     pub clan_id: i64,
     pub name: String,
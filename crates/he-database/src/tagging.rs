@@ -0,0 +1,68 @@
+//! Per-request query tagging
+//!
+//! Transactions opened with [`begin`] carry the current request's tag (see
+//! [`RequestContext::tag`]) as their `application_name`, so a slow or stuck
+//! query in `pg_stat_activity` or the server log can be traced back to the
+//! player and request that ran it. Outside a request nothing is tagged.
+
+use he_core::RequestContext;
+use sqlx::postgres::{PgConnection, PgPool, Postgres};
+use sqlx::Transaction;
+
+/// Prefix of every tagged `application_name`
+pub const APPLICATION: &str = "he-api";
+
+/// `application_name` for the current request, if there is one. Postgres keeps
+/// 63 bytes of it.
+pub fn application_name() -> Option<String> {
+    RequestContext::with_current(|ctx| {
+        ctx.map(|ctx| {
+            let mut name = format!("{} {}", APPLICATION, ctx.tag());
+            name.truncate(63);
+            name
+        })
+    })
+}
+
+/// Tag the transaction `conn` is in with the current request. The setting is
+/// transaction-local, so a pooled connection does not keep it.
+pub async fn tag(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    let Some(name) = application_name() else {
+        return Ok(());
+    };
+    sqlx::query("SELECT set_config('application_name', $1, true)")
+        .bind(name)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Begin a transaction tagged with the current request
+pub async fn begin(pool: &PgPool) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    tag(&mut tx).await?;
+    Ok(tx)
+}
+
+/// The current request's tag for log lines, or `-` outside a request
+pub fn log_tag() -> String {
+    RequestContext::with_current(|ctx| ctx.map_or_else(|| "-".to_string(), RequestContext::tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use he_core::RequestId;
+
+    #[tokio::test]
+    async fn test_application_name_follows_context() {
+        assert_eq!(application_name(), None);
+        assert_eq!(log_tag(), "-");
+
+        let ctx = RequestContext::new(RequestId::new()).with_user(Some(7));
+        let name = ctx.scope(async { application_name() }).await.unwrap();
+        assert!(name.starts_with("he-api req="));
+        assert!(name.ends_with(" user=7"));
+        assert!(name.len() <= 63);
+    }
+}
@@ -298,6 +298,44 @@ mod tests {
                 .execute(&pool)
                 .await;
         }
+
+        #[tokio::test]
+        async fn test_claim_completion_once() {
+            let pool = match create_test_pool().await {
+                Ok(p) => p,
+                Err(_) => return,
+            };
+
+            let user_id = create_test_user_for_process(&pool).await;
+
+            let process = ProcessQueries::create_process_with_duration(
+                &pool,
+                user_id,
+                "download",
+                &format!("pc_{}", user_id),
+                None,
+                0
+            ).await.unwrap();
+
+            let pending = ProcessQueries::pending_completions(&pool, Utc::now(), 100).await.unwrap();
+            assert!(pending.iter().any(|(pid, _)| *pid == process.pid));
+
+            let mut conn = pool.acquire().await.unwrap();
+            let first = ProcessQueries::claim_completion(&mut conn, process.pid).await.unwrap();
+            assert!(first.and_then(|p| p.completed_at).is_some());
+
+            // A second worker must not complete it again
+            let second = ProcessQueries::claim_completion(&mut conn, process.pid).await.unwrap();
+            assert!(second.is_none());
+
+            // Cleanup
+            let _ = sqlx::query!("DELETE FROM processes WHERE user_id = $1", user_id)
+                .execute(&pool)
+                .await;
+            let _ = sqlx::query!("DELETE FROM users WHERE id = $1", user_id)
+                .execute(&pool)
+                .await;
+        }
     }
 
     mod hardware_tests {
@@ -378,6 +416,26 @@ mod tests {
             (acc1, acc2, user1.id)
         }
 
+        #[test]
+        fn test_ledger_legs_must_balance() {
+            let legs = [
+                (LedgerAccount::Sink, 50),
+                (LedgerAccount::Bank(1), -550),
+                (LedgerAccount::ContractEscrow, 500),
+                (LedgerAccount::Mint, 0),
+            ];
+            let balanced = balanced_legs(&legs).unwrap();
+            assert_eq!(balanced.len(), 3);
+            assert_eq!(balanced[0], (LedgerAccount::Bank(1), -550));
+
+            assert!(balanced_legs(&[(LedgerAccount::Bank(1), -10), (LedgerAccount::Sink, 9)]).is_err());
+            assert!(balanced_legs(&[(LedgerAccount::Mint, i64::MIN), (LedgerAccount::Sink, -1)]).is_err());
+            assert_eq!(LedgerAccount::Bank(7).key(), "bank:7");
+            for reason in LedgerReason::ALL {
+                assert_eq!(LedgerReason::from_str(reason.as_str()), Some(reason));
+            }
+        }
+
         #[tokio::test]
         async fn test_money_transfer() {
             let pool = match create_test_pool().await {
@@ -409,6 +467,17 @@ mod tests {
             let failed = BankQueries::transfer_money(&pool, &acc1, &acc2, 20000).await.unwrap();
             assert!(!failed);
 
+            // The transfer is in the ledger and the balances agree with it
+            let reference = format!("transfer:{}:{}", acc1, acc2);
+            let filter = LedgerFilter { reference: Some(reference), ..Default::default() };
+            let entries = LedgerQueries::entries(&pool, &filter, 10).await.unwrap();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries.iter().map(|e| e.amount).sum::<i64>(), 0);
+            assert!(entries.iter().all(|e| e.reason == "transfer"));
+
+            let drift = LedgerQueries::drift(&pool).await.unwrap();
+            assert!(drift.iter().all(|d| entries.iter().all(|e| e.bank_account_id != Some(d.bank_account_id))));
+
             // Cleanup
             let _ = sqlx::query!("DELETE FROM bank_accounts WHERE account_number LIKE 'ACC%'")
                 .execute(&pool)
@@ -555,4 +624,156 @@ mod tests {
                 .await;
         }
     }
-}
\ No newline at end of file
+    mod marketplace_tests {
+        use super::*;
+
+        #[test]
+        fn test_split_sale_without_royalty() {
+            let split = MarketplaceQueries::split_sale(10_000, None);
+            assert_eq!(split, SaleSplit { seller: 10_000, royalty: 0 });
+        }
+
+        #[test]
+        fn test_split_sale_with_royalty() {
+            let split = MarketplaceQueries::split_sale(10_000, Some(15));
+            assert_eq!(split.royalty, 1_500);
+            assert_eq!(split.seller + split.royalty, 10_000);
+        }
+
+        #[tokio::test]
+        async fn test_purchase_missing_listing() {
+            let pool = match create_test_pool().await {
+                Ok(p) => p,
+                Err(_) => return,
+            };
+
+            let outcome = MarketplaceQueries::purchase(&pool, -1, 1, None).await.unwrap();
+            assert_eq!(outcome, PurchaseOutcome::NotFound);
+        }
+    }
+
+    mod email_change_tests {
+        use super::*;
+        use chrono::Duration;
+
+        fn request(status: &str) -> EmailChangeRequest {
+            let now = Utc::now();
+            EmailChangeRequest {
+                id: 1,
+                user_id: 1,
+                old_email: "old@example.com".to_string(),
+                new_email: "new@example.com".to_string(),
+                status: status.to_string(),
+                created_at: now,
+                expires_at: now + Duration::hours(EMAIL_CHANGE_TOKEN_TTL_HOURS),
+                revocable_until: now + Duration::hours(EMAIL_CHANGE_REVOCATION_HOURS),
+                old_confirmed_at: None,
+                completed_at: None,
+                revoked_at: None,
+            }
+        }
+
+        #[test]
+        fn test_tokens_are_unique() {
+            let a = EmailChangeQueries::generate_token();
+            let b = EmailChangeQueries::generate_token();
+            assert_eq!(a.len(), 64);
+            assert_ne!(a, b);
+        }
+
+        #[test]
+        fn test_revocation_window_outlives_completion() {
+            let completed = request("completed");
+            let after_expiry = completed.expires_at + Duration::hours(1);
+
+            assert!(!completed.is_expired(after_expiry));
+            assert!(completed.is_revocable(after_expiry));
+            assert!(!completed.is_revocable(completed.revocable_until));
+            assert!(!request("revoked").is_revocable(Utc::now()));
+        }
+
+        #[test]
+        fn test_pending_request_expires() {
+            let pending = request("awaiting_new");
+            assert!(!pending.is_expired(Utc::now()));
+            assert!(pending.is_expired(pending.expires_at));
+        }
+
+        #[tokio::test]
+        async fn test_bogus_tokens_rejected() {
+            let pool = match create_test_pool().await {
+                Ok(p) => p,
+                Err(_) => return,
+            };
+
+            assert!(EmailChangeQueries::revoke(&pool, "bogus").await.unwrap().is_none());
+            assert!(EmailChangeQueries::complete(&pool, 1, "bogus").await.unwrap().is_none());
+        }
+    }
+
+    mod executor_tests {
+        use crate::executor::*;
+        use std::time::Duration;
+        use tokio::time::Instant;
+
+        fn breaker() -> CircuitBreaker {
+            CircuitBreaker::new("test", BreakerConfig {
+                window: Duration::from_secs(10),
+                min_requests: 4,
+                error_rate: 0.5,
+                open_for: Duration::from_secs(5),
+            })
+        }
+
+        #[test]
+        fn test_breaker_trips_on_error_rate() {
+            let breaker = breaker();
+            let now = Instant::now();
+
+            breaker.record(true, now);
+            breaker.record(false, now);
+            breaker.record(true, now);
+            assert_eq!(breaker.state(), BreakerState::Closed);
+
+            breaker.record(false, now);
+            assert_eq!(breaker.state(), BreakerState::Open);
+            assert!(!breaker.allow(now + Duration::from_secs(1)));
+        }
+
+        #[test]
+        fn test_breaker_half_open_probe() {
+            let breaker = breaker();
+            let now = Instant::now();
+            for _ in 0..4 {
+                breaker.record(false, now);
+            }
+
+            // One probe after open_for, everything else still rejected
+            let later = now + Duration::from_secs(6);
+            assert!(breaker.allow(later));
+            assert_eq!(breaker.state(), BreakerState::HalfOpen);
+            assert!(!breaker.allow(later));
+
+            breaker.record(false, later);
+            assert_eq!(breaker.state(), BreakerState::Open);
+
+            let retry = later + Duration::from_secs(6);
+            assert!(breaker.allow(retry));
+            breaker.record(true, retry);
+            assert_eq!(breaker.state(), BreakerState::Closed);
+        }
+
+        #[test]
+        fn test_breaker_window_resets() {
+            let breaker = breaker();
+            let now = Instant::now();
+            for _ in 0..3 {
+                breaker.record(false, now);
+            }
+
+            // Old failures fall out with the window
+            breaker.record(false, now + Duration::from_secs(11));
+            assert_eq!(breaker.state(), BreakerState::Closed);
+        }
+    }
+}
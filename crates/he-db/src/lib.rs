@@ -8,7 +8,7 @@
 //! - **Multi-Database Support**: Manages 13 game databases
 //! - **Connection Pooling**: Efficient connection management with SQLx
 //! - **Migration Management**: Automated schema migrations
-//! - **Query Builders**: Type-safe query construction
+//! - **Query Builders**: Typed table/column tokens with bound parameters
 //! - **Repository Pattern**: Domain-specific data access patterns
 //! - **Transaction Support**: ACID transaction management
 //! - **Health Monitoring**: Database health checks and metrics
@@ -24,6 +24,7 @@ pub mod connection;
 pub mod migrations;
 pub mod repositories;
 pub mod query_builder;
+pub mod schema;
 pub mod transactions;
 pub mod health;
 pub mod metrics;
@@ -1,356 +1,661 @@
 //! Query builder for type-safe SQL construction
-
-use sqlx::{MySql, QueryBuilder, Arguments, mysql::MySqlArguments};
+//!
+//! Tables and columns are declared once with [`table!`](crate::table) (see
+//! [`crate::schema`]) and queries are assembled from those tokens, so a misspelled
+//! column fails to compile and every value goes through a bind parameter. Only
+//! identifiers generated by the macro ever reach the SQL text.
+
+use chrono::NaiveDateTime;
+use sqlx::mysql::{MySqlQueryResult, MySqlRow};
+use sqlx::{Executor, FromRow, MySql, QueryBuilder};
 use std::marker::PhantomData;
 
-/// Type-safe query builder for MySQL
-pub struct HeQueryBuilder<'a> {
-    builder: QueryBuilder<'a, MySql>,
-    _phantom: PhantomData<&'a ()>,
+/// Declare a table and its typed columns
+///
+/// ```ignore
+/// table! {
+///     users {
+///         id: i64,
+///         login: String,
+///     }
+/// }
+///
+/// SelectBuilder::new(users::TABLE).filter(users::login.eq("neo"));
+/// ```
+#[macro_export]
+macro_rules! table {
+    ($(#[$meta:meta])* $table:ident { $($column:ident : $ty:ty),* $(,)? }) => {
+        $(#[$meta])*
+        #[allow(non_upper_case_globals, dead_code)]
+        pub mod $table {
+            use super::*;
+
+            pub const TABLE: $crate::query_builder::Table =
+                $crate::query_builder::Table(stringify!($table));
+
+            $(
+                pub const $column: $crate::query_builder::Column<$ty> =
+                    $crate::query_builder::Column::new(stringify!($table), stringify!($column));
+            )*
+        }
+    };
 }
 
-impl<'a> HeQueryBuilder<'a> {
-    /// Create a new query builder with initial SQL
-    pub fn new(sql: &'a str) -> Self {
-        Self {
-            builder: QueryBuilder::new(sql),
-            _phantom: PhantomData,
-        }
+/// Table token generated by [`table!`](crate::table)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Table(pub &'static str);
+
+impl Table {
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+}
+
+/// Untyped column reference, used where columns of different types are mixed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnRef {
+    pub table: &'static str,
+    pub name: &'static str,
+}
+
+impl ColumnRef {
+    fn qualified(&self) -> String {
+        format!("`{}`.`{}`", self.table, self.name)
+    }
+
+    fn unqualified(&self) -> String {
+        format!("`{}`", self.name)
+    }
+}
+
+/// Typed column token generated by [`table!`](crate::table)
+#[derive(Debug)]
+pub struct Column<T> {
+    table: &'static str,
+    name: &'static str,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Column<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Column<T> {}
+
+impl<T> From<Column<T>> for ColumnRef {
+    fn from(column: Column<T>) -> Self {
+        ColumnRef { table: column.table, name: column.name }
+    }
+}
+
+impl<T> Column<T> {
+    pub const fn new(table: &'static str, name: &'static str) -> Self {
+        Self { table, name, _type: PhantomData }
     }
 
-    /// Add a parameter to the query
-    pub fn bind<T>(&mut self, value: T) -> &mut Self
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn compare<V: Into<T>>(self, op: &'static str, value: V) -> Condition
     where
-        T: 'a + Send + sqlx::Encode<'a, MySql> + sqlx::Type<MySql>,
+        T: Into<Value>,
     {
-        self.builder.push_bind(value);
-        self
+        Condition::Compare { column: self.into(), op, value: value.into().into() }
     }
 
-    /// Add literal SQL to the query
-    pub fn push(&mut self, sql: &str) -> &mut Self {
-        self.builder.push(sql);
-        self
+    pub fn eq<V: Into<T>>(self, value: V) -> Condition where T: Into<Value> {
+        self.compare("=", value)
     }
 
-    /// Add a separator (like comma or AND)
-    pub fn push_separator(&mut self, separator: &str) -> &mut Self {
-        self.builder.push(separator);
-        self
+    pub fn ne<V: Into<T>>(self, value: V) -> Condition where T: Into<Value> {
+        self.compare("<>", value)
+    }
+
+    pub fn lt<V: Into<T>>(self, value: V) -> Condition where T: Into<Value> {
+        self.compare("<", value)
+    }
+
+    pub fn le<V: Into<T>>(self, value: V) -> Condition where T: Into<Value> {
+        self.compare("<=", value)
+    }
+
+    pub fn gt<V: Into<T>>(self, value: V) -> Condition where T: Into<Value> {
+        self.compare(">", value)
     }
 
-    /// Build the final query
-    pub fn build(self) -> sqlx::query::Query<'a, MySql, MySqlArguments> {
-        self.builder.build()
+    pub fn ge<V: Into<T>>(self, value: V) -> Condition where T: Into<Value> {
+        self.compare(">=", value)
     }
 
-    /// Build a query that returns results
-    pub fn build_query_as<T>(self) -> sqlx::query::QueryAs<'a, MySql, T, MySqlArguments>
+    /// `LIKE` with a bound pattern; the caller is responsible for `%` wildcards
+    pub fn like<V: Into<T>>(self, pattern: V) -> Condition where T: Into<Value> {
+        self.compare("LIKE", pattern)
+    }
+
+    pub fn is_in<V, I>(self, values: I) -> Condition
     where
-        T: for<'r> sqlx::FromRow<'r, sqlx::mysql::MySqlRow>,
+        T: Into<Value>,
+        V: Into<T>,
+        I: IntoIterator<Item = V>,
     {
-        self.builder.build_query_as()
+        Condition::In {
+            column: self.into(),
+            values: values.into_iter().map(|v| v.into().into()).collect(),
+        }
+    }
+
+    pub fn is_null(self) -> Condition {
+        Condition::Null { column: self.into(), negated: false }
+    }
+
+    pub fn is_not_null(self) -> Condition {
+        Condition::Null { column: self.into(), negated: true }
+    }
+
+    /// Column-to-column equality, mainly for join conditions
+    pub fn eq_col(self, other: Column<T>) -> Condition {
+        Condition::ColumnsEq { left: self.into(), right: other.into() }
     }
 }
 
-/// Select query builder
-pub struct SelectBuilder<'a> {
-    query: HeQueryBuilder<'a>,
-    has_where: bool,
-    has_order: bool,
+/// A bindable value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Text(String),
+    DateTime(NaiveDateTime),
 }
 
-impl<'a> SelectBuilder<'a> {
-    /// Create a new SELECT query
-    pub fn new() -> Self {
-        Self {
-            query: HeQueryBuilder::new("SELECT "),
-            has_where: false,
-            has_order: false,
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<i16> for Value {
+    fn from(v: i16) -> Self {
+        Value::I32(v as i32)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::I32(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::I64(v)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Self {
+        Value::U64(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::F64(v)
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<NaiveDateTime> for Value {
+    fn from(v: NaiveDateTime) -> Self {
+        Value::DateTime(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+fn push_value(qb: &mut QueryBuilder<'static, MySql>, value: Value) {
+    match value {
+        Value::Null => qb.push_bind(None::<i64>),
+        Value::Bool(v) => qb.push_bind(v),
+        Value::I32(v) => qb.push_bind(v),
+        Value::I64(v) => qb.push_bind(v),
+        Value::U64(v) => qb.push_bind(v),
+        Value::F64(v) => qb.push_bind(v),
+        Value::Text(v) => qb.push_bind(v),
+        Value::DateTime(v) => qb.push_bind(v),
+    };
+}
+
+/// WHERE / ON condition tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare { column: ColumnRef, op: &'static str, value: Value },
+    ColumnsEq { left: ColumnRef, right: ColumnRef },
+    In { column: ColumnRef, values: Vec<Value> },
+    Null { column: ColumnRef, negated: bool },
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    pub fn and(self, other: Condition) -> Condition {
+        match self {
+            Condition::And(mut conditions) => {
+                conditions.push(other);
+                Condition::And(conditions)
+            }
+            condition => Condition::And(vec![condition, other]),
         }
     }
 
-    /// Add columns to select
-    pub fn columns(mut self, columns: &[&str]) -> Self {
-        for (i, column) in columns.iter().enumerate() {
-            if i > 0 {
-                self.query.push(", ");
+    pub fn or(self, other: Condition) -> Condition {
+        match self {
+            Condition::Or(mut conditions) => {
+                conditions.push(other);
+                Condition::Or(conditions)
             }
-            self.query.push(column);
+            condition => Condition::Or(vec![condition, other]),
         }
-        self
     }
 
-    /// Add FROM clause
-    pub fn from(mut self, table: &str) -> Self {
-        self.query.push(" FROM ");
-        self.query.push(table);
-        self
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Condition {
+        Condition::Not(Box::new(self))
     }
 
-    /// Add WHERE clause
-    pub fn where_clause(mut self, condition: &str) -> Self {
-        if !self.has_where {
-            self.query.push(" WHERE ");
-            self.has_where = true;
-        } else {
-            self.query.push(" AND ");
+    fn push_to(self, qb: &mut QueryBuilder<'static, MySql>) {
+        match self {
+            Condition::Compare { column, op, value } => {
+                qb.push(column.qualified()).push(" ").push(op).push(" ");
+                push_value(qb, value);
+            }
+            Condition::ColumnsEq { left, right } => {
+                qb.push(left.qualified()).push(" = ").push(right.qualified());
+            }
+            // `IN ()` is a syntax error; an empty set matches nothing
+            Condition::In { values, .. } if values.is_empty() => {
+                qb.push("1 = 0");
+            }
+            Condition::In { column, values } => {
+                qb.push(column.qualified()).push(" IN (");
+                for (i, value) in values.into_iter().enumerate() {
+                    if i > 0 {
+                        qb.push(", ");
+                    }
+                    push_value(qb, value);
+                }
+                qb.push(")");
+            }
+            Condition::Null { column, negated } => {
+                qb.push(column.qualified())
+                    .push(if negated { " IS NOT NULL" } else { " IS NULL" });
+            }
+            Condition::And(conditions) => push_group(qb, conditions, " AND ", "1 = 1"),
+            Condition::Or(conditions) => push_group(qb, conditions, " OR ", "1 = 0"),
+            Condition::Not(condition) => {
+                qb.push("NOT (");
+                condition.push_to(qb);
+                qb.push(")");
+            }
         }
-        self.query.push(condition);
-        self
     }
+}
 
-    /// Add WHERE clause with parameter binding
-    pub fn where_bind<T>(mut self, condition: &str, value: T) -> Self
-    where
-        T: 'a + Send + sqlx::Encode<'a, MySql> + sqlx::Type<MySql>,
-    {
-        if !self.has_where {
-            self.query.push(" WHERE ");
-            self.has_where = true;
-        } else {
-            self.query.push(" AND ");
+fn push_group(qb: &mut QueryBuilder<'static, MySql>, conditions: Vec<Condition>, separator: &str, empty: &str) {
+    if conditions.is_empty() {
+        qb.push(empty);
+        return;
+    }
+
+    qb.push("(");
+    for (i, condition) in conditions.into_iter().enumerate() {
+        if i > 0 {
+            qb.push(separator);
         }
-        self.query.push(condition);
-        self.query.bind(value);
-        self
+        condition.push_to(qb);
     }
+    qb.push(")");
+}
 
-    /// Add OR condition
-    pub fn or_where(mut self, condition: &str) -> Self {
-        if self.has_where {
-            self.query.push(" OR ");
-        } else {
-            self.query.push(" WHERE ");
-            self.has_where = true;
+fn push_where(qb: &mut QueryBuilder<'static, MySql>, filter: Option<Condition>) {
+    if let Some(filter) = filter {
+        qb.push(" WHERE ");
+        filter.push_to(qb);
+    }
+}
+
+fn and_filter(current: Option<Condition>, condition: Condition) -> Option<Condition> {
+    Some(match current {
+        Some(existing) => existing.and(condition),
+        None => condition,
+    })
+}
+
+/// Sort direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+impl Order {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC",
         }
-        self.query.push(condition);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JoinKind {
+    Inner,
+    Left,
+}
+
+#[derive(Debug, Clone)]
+struct Join {
+    kind: JoinKind,
+    table: Table,
+    on: Condition,
+}
+
+/// Select query builder
+#[derive(Debug, Clone)]
+pub struct SelectBuilder {
+    table: Table,
+    columns: Vec<ColumnRef>,
+    joins: Vec<Join>,
+    filter: Option<Condition>,
+    order: Vec<(ColumnRef, Order)>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+impl SelectBuilder {
+    /// Create a new SELECT query; selects `table.*` until columns are added
+    pub fn new(table: Table) -> Self {
+        Self {
+            table,
+            columns: Vec::new(),
+            joins: Vec::new(),
+            filter: None,
+            order: Vec::new(),
+            limit: None,
+            offset: None,
+        }
+    }
+
+    pub fn column(mut self, column: impl Into<ColumnRef>) -> Self {
+        self.columns.push(column.into());
         self
     }
 
-    /// Add JOIN clause
-    pub fn join(mut self, join_type: &str, table: &str, condition: &str) -> Self {
-        self.query.push(" ");
-        self.query.push(join_type);
-        self.query.push(" JOIN ");
-        self.query.push(table);
-        self.query.push(" ON ");
-        self.query.push(condition);
+    pub fn inner_join(mut self, table: Table, on: Condition) -> Self {
+        self.joins.push(Join { kind: JoinKind::Inner, table, on });
         self
     }
 
-    /// Add INNER JOIN
-    pub fn inner_join(self, table: &str, condition: &str) -> Self {
-        self.join("INNER", table, condition)
+    pub fn left_join(mut self, table: Table, on: Condition) -> Self {
+        self.joins.push(Join { kind: JoinKind::Left, table, on });
+        self
     }
 
-    /// Add LEFT JOIN
-    pub fn left_join(self, table: &str, condition: &str) -> Self {
-        self.join("LEFT", table, condition)
+    /// Add a WHERE condition; repeated calls are AND-ed together
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.filter = and_filter(self.filter, condition);
+        self
     }
 
-    /// Add ORDER BY clause
-    pub fn order_by(mut self, column: &str, direction: &str) -> Self {
-        if !self.has_order {
-            self.query.push(" ORDER BY ");
-            self.has_order = true;
-        } else {
-            self.query.push(", ");
-        }
-        self.query.push(column);
-        self.query.push(" ");
-        self.query.push(direction);
+    pub fn order_by(mut self, column: impl Into<ColumnRef>, order: Order) -> Self {
+        self.order.push((column.into(), order));
         self
     }
 
-    /// Add LIMIT clause
     pub fn limit(mut self, count: u64) -> Self {
-        self.query.push(" LIMIT ");
-        self.query.bind(count);
+        self.limit = Some(count);
         self
     }
 
-    /// Add OFFSET clause
     pub fn offset(mut self, count: u64) -> Self {
-        self.query.push(" OFFSET ");
-        self.query.bind(count);
+        self.offset = Some(count);
         self
     }
 
-    /// Build the final query
-    pub fn build(self) -> sqlx::query::Query<'a, MySql, MySqlArguments> {
-        self.query.build()
+    /// Assemble the underlying sqlx builder
+    pub fn into_builder(self) -> QueryBuilder<'static, MySql> {
+        let mut qb = QueryBuilder::new("SELECT ");
+
+        if self.columns.is_empty() {
+            qb.push(format!("`{}`.*", self.table.name()));
+        } else {
+            let columns: Vec<String> = self.columns.iter().map(ColumnRef::qualified).collect();
+            qb.push(columns.join(", "));
+        }
+
+        qb.push(format!(" FROM `{}`", self.table.name()));
+
+        for join in self.joins {
+            qb.push(match join.kind {
+                JoinKind::Inner => " INNER JOIN ",
+                JoinKind::Left => " LEFT JOIN ",
+            });
+            qb.push(format!("`{}` ON ", join.table.name()));
+            join.on.push_to(&mut qb);
+        }
+
+        push_where(&mut qb, self.filter);
+
+        if !self.order.is_empty() {
+            let order: Vec<String> = self
+                .order
+                .iter()
+                .map(|(column, order)| format!("{} {}", column.qualified(), order.as_sql()))
+                .collect();
+            qb.push(" ORDER BY ").push(order.join(", "));
+        }
+
+        if let Some(limit) = self.limit {
+            qb.push(" LIMIT ").push_bind(limit);
+        }
+        if let Some(offset) = self.offset {
+            // MySQL has no OFFSET without LIMIT
+            if self.limit.is_none() {
+                qb.push(" LIMIT 18446744073709551615");
+            }
+            qb.push(" OFFSET ").push_bind(offset);
+        }
+
+        qb
+    }
+
+    /// Generated SQL with `?` placeholders, for logging and tests
+    pub fn to_sql(self) -> String {
+        self.into_builder().into_sql()
+    }
+
+    pub async fn fetch_all<'c, T, E>(self, executor: E) -> Result<Vec<T>, sqlx::Error>
+    where
+        T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+        E: Executor<'c, Database = MySql>,
+    {
+        let mut qb = self.into_builder();
+        qb.build_query_as::<T>().fetch_all(executor).await
     }
 
-    /// Build query that returns specific type
-    pub fn build_as<T>(self) -> sqlx::query::QueryAs<'a, MySql, T, MySqlArguments>
+    pub async fn fetch_optional<'c, T, E>(self, executor: E) -> Result<Option<T>, sqlx::Error>
     where
-        T: for<'r> sqlx::FromRow<'r, sqlx::mysql::MySqlRow>,
+        T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin,
+        E: Executor<'c, Database = MySql>,
     {
-        self.query.build_query_as()
+        let mut qb = self.limit(1).into_builder();
+        qb.build_query_as::<T>().fetch_optional(executor).await
     }
 }
 
 /// Insert query builder
-pub struct InsertBuilder<'a> {
-    query: HeQueryBuilder<'a>,
-    table: String,
-    columns: Vec<String>,
-    value_count: usize,
+#[derive(Debug, Clone)]
+pub struct InsertBuilder {
+    table: Table,
+    values: Vec<(ColumnRef, Value)>,
 }
 
-impl<'a> InsertBuilder<'a> {
-    /// Create a new INSERT query
-    pub fn new(table: &str) -> Self {
-        Self {
-            query: HeQueryBuilder::new("INSERT INTO "),
-            table: table.to_string(),
-            columns: Vec::new(),
-            value_count: 0,
-        }
+impl InsertBuilder {
+    pub fn new(table: Table) -> Self {
+        Self { table, values: Vec::new() }
     }
 
-    /// Add a column and value
-    pub fn column_value<T>(mut self, column: &str, value: T) -> Self
+    pub fn value<T, V>(mut self, column: Column<T>, value: V) -> Self
     where
-        T: 'a + Send + sqlx::Encode<'a, MySql> + sqlx::Type<MySql>,
+        T: Into<Value>,
+        V: Into<T>,
     {
-        self.columns.push(column.to_string());
-        
-        if self.value_count == 0 {
-            self.query.push(&self.table);
-            self.query.push(" (");
-            
-            for (i, col) in self.columns.iter().enumerate() {
-                if i > 0 {
-                    self.query.push(", ");
-                }
-                self.query.push(col);
-            }
-            
-            self.query.push(") VALUES (");
-        } else {
-            self.query.push(", ");
-        }
-        
-        self.query.push("?");
-        self.query.bind(value);
-        self.value_count += 1;
-        
+        self.values.push((column.into(), value.into().into()));
         self
     }
 
-    /// Build the final query
-    pub fn build(mut self) -> sqlx::query::Query<'a, MySql, MySqlArguments> {
-        if self.value_count > 0 {
-            self.query.push(")");
+    pub fn into_builder(self) -> QueryBuilder<'static, MySql> {
+        let mut qb = QueryBuilder::new(format!("INSERT INTO `{}` (", self.table.name()));
+
+        let columns: Vec<String> = self.values.iter().map(|(column, _)| column.unqualified()).collect();
+        qb.push(columns.join(", ")).push(") VALUES (");
+
+        for (i, (_, value)) in self.values.into_iter().enumerate() {
+            if i > 0 {
+                qb.push(", ");
+            }
+            push_value(&mut qb, value);
         }
-        self.query.build()
+        qb.push(")");
+
+        qb
+    }
+
+    pub fn to_sql(self) -> String {
+        self.into_builder().into_sql()
+    }
+
+    pub async fn execute<'c, E>(self, executor: E) -> Result<MySqlQueryResult, sqlx::Error>
+    where
+        E: Executor<'c, Database = MySql>,
+    {
+        let mut qb = self.into_builder();
+        qb.build().execute(executor).await
     }
 }
 
 /// Update query builder
-pub struct UpdateBuilder<'a> {
-    query: HeQueryBuilder<'a>,
-    has_set: bool,
-    has_where: bool,
-}
-
-impl<'a> UpdateBuilder<'a> {
-    /// Create a new UPDATE query
-    pub fn new(table: &str) -> Self {
-        let mut query = HeQueryBuilder::new("UPDATE ");
-        query.push(table);
-        
-        Self {
-            query,
-            has_set: false,
-            has_where: false,
-        }
+#[derive(Debug, Clone)]
+pub struct UpdateBuilder {
+    table: Table,
+    values: Vec<(ColumnRef, Value)>,
+    filter: Option<Condition>,
+}
+
+impl UpdateBuilder {
+    pub fn new(table: Table) -> Self {
+        Self { table, values: Vec::new(), filter: None }
     }
 
-    /// Add SET clause
-    pub fn set<T>(mut self, column: &str, value: T) -> Self
+    pub fn set<T, V>(mut self, column: Column<T>, value: V) -> Self
     where
-        T: 'a + Send + sqlx::Encode<'a, MySql> + sqlx::Type<MySql>,
+        T: Into<Value>,
+        V: Into<T>,
     {
-        if !self.has_set {
-            self.query.push(" SET ");
-            self.has_set = true;
-        } else {
-            self.query.push(", ");
-        }
-        
-        self.query.push(column);
-        self.query.push(" = ?");
-        self.query.bind(value);
-        
+        self.values.push((column.into(), value.into().into()));
         self
     }
 
-    /// Add WHERE clause
-    pub fn where_bind<T>(mut self, condition: &str, value: T) -> Self
-    where
-        T: 'a + Send + sqlx::Encode<'a, MySql> + sqlx::Type<MySql>,
-    {
-        if !self.has_where {
-            self.query.push(" WHERE ");
-            self.has_where = true;
-        } else {
-            self.query.push(" AND ");
-        }
-        
-        self.query.push(condition);
-        self.query.bind(value);
-        
+    /// Add a WHERE condition; repeated calls are AND-ed together
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.filter = and_filter(self.filter, condition);
         self
     }
 
-    /// Build the final query
-    pub fn build(self) -> sqlx::query::Query<'a, MySql, MySqlArguments> {
-        self.query.build()
+    pub fn into_builder(self) -> QueryBuilder<'static, MySql> {
+        let mut qb = QueryBuilder::new(format!("UPDATE `{}` SET ", self.table.name()));
+
+        for (i, (column, value)) in self.values.into_iter().enumerate() {
+            if i > 0 {
+                qb.push(", ");
+            }
+            qb.push(column.unqualified()).push(" = ");
+            push_value(&mut qb, value);
+        }
+
+        push_where(&mut qb, self.filter);
+        qb
+    }
+
+    pub fn to_sql(self) -> String {
+        self.into_builder().into_sql()
+    }
+
+    pub async fn execute<'c, E>(self, executor: E) -> Result<MySqlQueryResult, sqlx::Error>
+    where
+        E: Executor<'c, Database = MySql>,
+    {
+        let mut qb = self.into_builder();
+        qb.build().execute(executor).await
     }
 }
 
 /// Delete query builder
-pub struct DeleteBuilder<'a> {
-    query: HeQueryBuilder<'a>,
-    has_where: bool,
+#[derive(Debug, Clone)]
+pub struct DeleteBuilder {
+    table: Table,
+    filter: Option<Condition>,
 }
 
-impl<'a> DeleteBuilder<'a> {
-    /// Create a new DELETE query
-    pub fn new(table: &str) -> Self {
-        let mut query = HeQueryBuilder::new("DELETE FROM ");
-        query.push(table);
-        
-        Self {
-            query,
-            has_where: false,
-        }
+impl DeleteBuilder {
+    pub fn new(table: Table) -> Self {
+        Self { table, filter: None }
     }
 
-    /// Add WHERE clause
-    pub fn where_bind<T>(mut self, condition: &str, value: T) -> Self
-    where
-        T: 'a + Send + sqlx::Encode<'a, MySql> + sqlx::Type<MySql>,
-    {
-        if !self.has_where {
-            self.query.push(" WHERE ");
-            self.has_where = true;
-        } else {
-            self.query.push(" AND ");
-        }
-        
-        self.query.push(condition);
-        self.query.bind(value);
-        
+    /// Add a WHERE condition; repeated calls are AND-ed together
+    pub fn filter(mut self, condition: Condition) -> Self {
+        self.filter = and_filter(self.filter, condition);
         self
     }
 
-    /// Build the final query
-    pub fn build(self) -> sqlx::query::Query<'a, MySql, MySqlArguments> {
-        self.query.build()
+    pub fn into_builder(self) -> QueryBuilder<'static, MySql> {
+        let mut qb = QueryBuilder::new(format!("DELETE FROM `{}`", self.table.name()));
+        push_where(&mut qb, self.filter);
+        qb
+    }
+
+    pub fn to_sql(self) -> String {
+        self.into_builder().into_sql()
+    }
+
+    pub async fn execute<'c, E>(self, executor: E) -> Result<MySqlQueryResult, sqlx::Error>
+    where
+        E: Executor<'c, Database = MySql>,
+    {
+        let mut qb = self.into_builder();
+        qb.build().execute(executor).await
     }
 }
 
@@ -358,43 +663,102 @@ impl<'a> DeleteBuilder<'a> {
 mod tests {
     use super::*;
 
+    crate::table! {
+        users {
+            id: i64,
+            login: String,
+            email: String,
+            premium: bool,
+        }
+    }
+
+    crate::table! {
+        users_stats {
+            user_id: i64,
+            money: i64,
+        }
+    }
+
     #[test]
     fn test_select_builder() {
-        let query = SelectBuilder::new()
-            .columns(&["id", "name", "email"])
-            .from("users")
-            .where_clause("active = 1")
-            .order_by("name", "ASC")
-            .limit(10);
-            
-        // Test that the builder works without panicking
-        let _built = query.build();
+        let sql = SelectBuilder::new(users::TABLE)
+            .column(users::id)
+            .column(users::login)
+            .filter(users::premium.eq(true))
+            .filter(users::login.like("neo%"))
+            .order_by(users::login, Order::Asc)
+            .limit(10)
+            .to_sql();
+
+        assert_eq!(
+            sql,
+            "SELECT `users`.`id`, `users`.`login` FROM `users` \
+             WHERE (`users`.`premium` = ? AND `users`.`login` LIKE ?) \
+             ORDER BY `users`.`login` ASC LIMIT ?"
+        );
+    }
+
+    #[test]
+    fn test_select_join_and_or() {
+        let sql = SelectBuilder::new(users::TABLE)
+            .left_join(users_stats::TABLE, users::id.eq_col(users_stats::user_id))
+            .filter(users_stats::money.gt(1000).or(users::id.is_in([1, 2, 3])))
+            .to_sql();
+
+        assert_eq!(
+            sql,
+            "SELECT `users`.* FROM `users` \
+             LEFT JOIN `users_stats` ON `users`.`id` = `users_stats`.`user_id` \
+             WHERE (`users_stats`.`money` > ? OR `users`.`id` IN (?, ?, ?))"
+        );
+    }
+
+    #[test]
+    fn test_values_are_bound_not_inlined() {
+        let sql = SelectBuilder::new(users::TABLE)
+            .filter(users::login.eq("'; DROP TABLE users; --"))
+            .to_sql();
+
+        assert!(!sql.contains("DROP"));
+        assert!(sql.ends_with("WHERE `users`.`login` = ?"));
+    }
+
+    #[test]
+    fn test_empty_in_matches_nothing() {
+        let sql = SelectBuilder::new(users::TABLE)
+            .filter(users::id.is_in(Vec::<i64>::new()))
+            .to_sql();
+
+        assert!(sql.ends_with("WHERE 1 = 0"));
     }
 
     #[test]
     fn test_insert_builder() {
-        let query = InsertBuilder::new("users")
-            .column_value("name", "John Doe")
-            .column_value("email", "john@example.com");
-            
-        let _built = query.build();
+        let sql = InsertBuilder::new(users::TABLE)
+            .value(users::login, "john")
+            .value(users::email, "john@example.com")
+            .to_sql();
+
+        assert_eq!(sql, "INSERT INTO `users` (`login`, `email`) VALUES (?, ?)");
     }
 
     #[test]
     fn test_update_builder() {
-        let query = UpdateBuilder::new("users")
-            .set("name", "Jane Doe")
-            .set("email", "jane@example.com")
-            .where_bind("id = ?", 123);
-            
-        let _built = query.build();
+        let sql = UpdateBuilder::new(users::TABLE)
+            .set(users::login, "jane")
+            .set(users::email, "jane@example.com")
+            .filter(users::id.eq(123))
+            .to_sql();
+
+        assert_eq!(sql, "UPDATE `users` SET `login` = ?, `email` = ? WHERE `users`.`id` = ?");
     }
 
     #[test]
     fn test_delete_builder() {
-        let query = DeleteBuilder::new("users")
-            .where_bind("id = ?", 123);
-            
-        let _built = query.build();
+        let sql = DeleteBuilder::new(users::TABLE)
+            .filter(users::id.eq(123).not())
+            .to_sql();
+
+        assert_eq!(sql, "DELETE FROM `users` WHERE NOT (`users`.`id` = ?)");
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,27 @@
 use anyhow::{anyhow, Result};
 use sqlx::{MySql, Pool};
 use he_core::{Process, ProcessId, UserId, ProcessAction, ProcessStatus, HeResult, HeError};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::query_builder::{Order, SelectBuilder, UpdateBuilder};
+use crate::schema::processes;
+
+// Columns of `processes` needed to build a Process
+#[derive(sqlx::FromRow)]
+struct ProcessRow {
+    pid: i64,
+    p_creator_id: i64,
+    p_victim_id: i64,
+    p_action: i16,
+    p_soft_id: i64,
+    p_info: String,
+    p_info_str: String,
+    p_time_start: NaiveDateTime,
+    p_time_end: NaiveDateTime,
+    cpu_usage: f64,
+    net_usage: f64,
+    p_npc: bool,
+    is_paused: bool,
+}
 
 // Process repository - replaces PHP Process.class.php database methods
 // "This is the most complex part of Legacy and HE2." - Original comment
@@ -47,40 +67,18 @@ impl ProcessRepository {
     
     // Get active processes for user
     pub async fn get_active_processes(&self, user_id: UserId) -> HeResult<Vec<Process>> {
-        let rows = sqlx::query!(
-            r#"
-            SELECT * FROM processes 
-            WHERE p_creator_id = ? AND p_time_end > NOW() AND is_paused = 0
-            ORDER BY p_time_start ASC
-            "#,
-            user_id
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| HeError::Database(e.into()))?;
+        let rows: Vec<ProcessRow> = SelectBuilder::new(processes::TABLE)
+            .filter(processes::p_creator_id.eq(user_id))
+            .filter(processes::p_time_end.gt(Utc::now().naive_utc()))
+            .filter(processes::is_paused.eq(false))
+            .order_by(processes::p_time_start, Order::Asc)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| HeError::Database(e.into()))?;
         
         let mut processes = Vec::new();
         for row in rows {
-            if let Some(action) = ProcessAction::from_i32(row.p_action as i32) {
-                let process = Process {
-                    id: row.pid as ProcessId,
-                    creator_id: row.p_creator_id as UserId,
-                    victim_id: if row.p_victim_id == 0 { None } else { Some(row.p_victim_id as UserId) },
-                    action,
-                    software_id: if row.p_soft_id == 0 { None } else { Some(row.p_soft_id) },
-                    target_ip: "0.0.0.0".to_string(), // TODO: Map from p_local
-                    time_left: self.calculate_time_left(&row.p_time_end).await?,
-                    info: if row.p_info.is_empty() { None } else { Some(row.p_info) },
-                    info_str: if row.p_info_str.is_empty() { None } else { Some(row.p_info_str) },
-                    is_npc: row.p_npc != 0,
-                    cpu_usage: row.cpu_usage as i32,
-                    net_usage: row.net_usage as i32,
-                    created_at: DateTime::from_timestamp(row.p_time_start.and_utc().timestamp(), 0).map_err(|e| anyhow::anyhow!("Error: {}", e))?,
-                    started_at: Some(DateTime::from_timestamp(row.p_time_start.and_utc().timestamp(), 0).map_err(|e| anyhow::anyhow!("Error: {}", e))?),
-                    completed_at: None,
-                    status: if row.is_paused != 0 { ProcessStatus::Paused } else { ProcessStatus::Running },
-                    priority: 5, // Default priority
-                };
+            if let Some(process) = self.row_to_process(row).await? {
                 processes.push(process);
             }
         }
@@ -90,67 +88,39 @@ impl ProcessRepository {
     
     // Get process by ID
     pub async fn get_process(&self, process_id: ProcessId) -> HeResult<Option<Process>> {
-        let row = sqlx::query!(
-            "SELECT * FROM processes WHERE pid = ?",
-            process_id
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| HeError::Database(e.into()))?;
+        let row: Option<ProcessRow> = SelectBuilder::new(processes::TABLE)
+            .filter(processes::pid.eq(process_id))
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| HeError::Database(e.into()))?;
         
-        if let Some(row) = row {
-            if let Some(action) = ProcessAction::from_i32(row.p_action as i32) {
-                let process = Process {
-                    id: row.pid as ProcessId,
-                    creator_id: row.p_creator_id as UserId,
-                    victim_id: if row.p_victim_id == 0 { None } else { Some(row.p_victim_id as UserId) },
-                    action,
-                    software_id: if row.p_soft_id == 0 { None } else { Some(row.p_soft_id) },
-                    target_ip: "0.0.0.0".to_string(), // TODO: Map from p_local
-                    time_left: self.calculate_time_left(&row.p_time_end).await?,
-                    info: if row.p_info.is_empty() { None } else { Some(row.p_info) },
-                    info_str: if row.p_info_str.is_empty() { None } else { Some(row.p_info_str) },
-                    is_npc: row.p_npc != 0,
-                    cpu_usage: row.cpu_usage as i32,
-                    net_usage: row.net_usage as i32,
-                    created_at: DateTime::from_timestamp(row.p_time_start.and_utc().timestamp(), 0).map_err(|e| anyhow::anyhow!("Error: {}", e))?,
-                    started_at: Some(DateTime::from_timestamp(row.p_time_start.and_utc().timestamp(), 0).map_err(|e| anyhow::anyhow!("Error: {}", e))?),
-                    completed_at: None,
-                    status: if row.is_paused != 0 { ProcessStatus::Paused } else { ProcessStatus::Running },
-                    priority: 5,
-                };
-                return Ok(Some(process));
-            }
+        match row {
+            Some(row) => self.row_to_process(row).await,
+            None => Ok(None),
         }
-        
-        Ok(None)
     }
     
     // Update process status
     pub async fn update_process_status(&self, process_id: ProcessId, is_paused: bool) -> HeResult<()> {
-        let paused_flag = if is_paused { 1 } else { 0 };
-        
-        sqlx::query!(
-            "UPDATE processes SET is_paused = ?, p_time_pause = NOW() WHERE pid = ?",
-            paused_flag,
-            process_id
-        )
-        .execute(&self.pool)
-        .await
-        .map_err(|e| HeError::Database(e.into()))?;
+        UpdateBuilder::new(processes::TABLE)
+            .set(processes::is_paused, is_paused)
+            .set(processes::p_time_pause, Utc::now().naive_utc())
+            .filter(processes::pid.eq(process_id))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| HeError::Database(e.into()))?;
         
         Ok(())
     }
     
     // Complete process - mark as finished
     pub async fn complete_process(&self, process_id: ProcessId) -> HeResult<()> {
-        sqlx::query!(
-            "UPDATE processes SET p_time_end = NOW() WHERE pid = ?",
-            process_id
-        )
-        .execute(&self.pool)
-        .await
-        .map_err(|e| HeError::Database(e.into()))?;
+        UpdateBuilder::new(processes::TABLE)
+            .set(processes::p_time_end, Utc::now().naive_utc())
+            .filter(processes::pid.eq(process_id))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| HeError::Database(e.into()))?;
         
         Ok(())
     }
@@ -168,6 +138,33 @@ impl ProcessRepository {
         Ok(result.rows_affected())
     }
     
+    // Helper: Map a processes row; unknown actions are skipped
+    async fn row_to_process(&self, row: ProcessRow) -> HeResult<Option<Process>> {
+        let Some(action) = ProcessAction::from_i32(row.p_action as i32) else {
+            return Ok(None);
+        };
+        
+        Ok(Some(Process {
+            id: row.pid as ProcessId,
+            creator_id: row.p_creator_id as UserId,
+            victim_id: if row.p_victim_id == 0 { None } else { Some(row.p_victim_id as UserId) },
+            action,
+            software_id: if row.p_soft_id == 0 { None } else { Some(row.p_soft_id) },
+            target_ip: "0.0.0.0".to_string(), // TODO: Map from p_local
+            time_left: self.calculate_time_left(&row.p_time_end).await?,
+            info: if row.p_info.is_empty() { None } else { Some(row.p_info) },
+            info_str: if row.p_info_str.is_empty() { None } else { Some(row.p_info_str) },
+            is_npc: row.p_npc,
+            cpu_usage: row.cpu_usage as i32,
+            net_usage: row.net_usage as i32,
+            created_at: DateTime::from_timestamp(row.p_time_start.and_utc().timestamp(), 0).map_err(|e| anyhow::anyhow!("Error: {}", e))?,
+            started_at: Some(DateTime::from_timestamp(row.p_time_start.and_utc().timestamp(), 0).map_err(|e| anyhow::anyhow!("Error: {}", e))?),
+            completed_at: None,
+            status: if row.is_paused { ProcessStatus::Paused } else { ProcessStatus::Running },
+            priority: 5, // Default priority
+        }))
+    }
+    
     // Helper: Calculate time left for process
     async fn calculate_time_left(&self, end_time: &chrono::NaiveDateTime) -> HeResult<i32> {
         let now = Utc::now().naive_utc();
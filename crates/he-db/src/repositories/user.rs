@@ -1,7 +1,21 @@
 use anyhow::{anyhow, Result};
 use sqlx::{MySql, Pool};
 use he_core::{User, UserStats, UserId, HeResult, HeError};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::query_builder::{Condition, SelectBuilder, UpdateBuilder};
+use crate::schema::users;
+
+// Columns of `users` needed to build a User
+#[derive(sqlx::FromRow)]
+struct UserRow {
+    id: i64,
+    login: String,
+    email: String,
+    password: String,
+    game_ip: u64,
+    premium: bool,
+    last_login: NaiveDateTime,
+}
 
 // User repository - replaces PHP Player.class.php database methods
 pub struct UserRepository {
@@ -40,13 +54,33 @@ impl UserRepository {
     
     // Find user by ID - core lookup method
     pub async fn find_by_id(&self, user_id: UserId) -> HeResult<Option<User>> {
-        let row = sqlx::query!(
-            "SELECT * FROM users WHERE id = ?",
-            user_id
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| HeError::Database(e.into()))?;
+        self.find_one(users::id.eq(user_id)).await
+    }
+    
+    // Find user by login name - for authentication
+    pub async fn find_by_login(&self, login: &str) -> HeResult<Option<User>> {
+        self.find_one(users::login.eq(login)).await
+    }
+    
+    // Update user's last login time
+    pub async fn update_last_login(&self, user_id: UserId) -> HeResult<()> {
+        UpdateBuilder::new(users::TABLE)
+            .set(users::last_login, Utc::now().naive_utc())
+            .filter(users::id.eq(user_id))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| HeError::Database(e.into()))?;
+        
+        Ok(())
+    }
+    
+    // Shared lookup for the find_by_* methods
+    async fn find_one(&self, condition: Condition) -> HeResult<Option<User>> {
+        let row: Option<UserRow> = SelectBuilder::new(users::TABLE)
+            .filter(condition)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| HeError::Database(e.into()))?;
         
         if let Some(row) = row {
             let user = User {
@@ -58,7 +92,7 @@ impl UserRepository {
                 clan_id: None, // TODO: Join with clan_members
                 created_at: Utc::now(), // TODO: Add to migration
                 last_login: Some(DateTime::from_timestamp(row.last_login.and_utc().timestamp(), 0).map_err(|e| anyhow::anyhow!("Error: {}", e))?),
-                is_premium: row.premium != 0,
+                is_premium: row.premium,
                 is_online: false, // TODO: Check sessions table
                 password_hash: row.password,
             };
@@ -68,49 +102,6 @@ impl UserRepository {
         }
     }
     
-    // Find user by login name - for authentication
-    pub async fn find_by_login(&self, login: &str) -> HeResult<Option<User>> {
-        let row = sqlx::query!(
-            "SELECT * FROM users WHERE login = ?",
-            login
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| HeError::Database(e.into()))?;
-        
-        if let Some(row) = row {
-            let user = User {
-                id: row.id as UserId,
-                name: row.login,
-                email: row.email,
-                game_ip: row.game_ip.to_string(),
-                cur_round: 1,
-                clan_id: None,
-                created_at: Utc::now(),
-                last_login: Some(DateTime::from_timestamp(row.last_login.and_utc().timestamp(), 0).map_err(|e| anyhow::anyhow!("Error: {}", e))?),
-                is_premium: row.premium != 0,
-                is_online: false,
-                password_hash: row.password,
-            };
-            Ok(Some(user))
-        } else {
-            Ok(None)
-        }
-    }
-    
-    // Update user's last login time
-    pub async fn update_last_login(&self, user_id: UserId) -> HeResult<()> {
-        sqlx::query!(
-            "UPDATE users SET last_login = NOW() WHERE id = ?",
-            user_id
-        )
-        .execute(&self.pool)
-        .await
-        .map_err(|e| HeError::Database(e.into()))?;
-        
-        Ok(())
-    }
-    
     // Get user stats - separate table like original
     pub async fn get_user_stats(&self, user_id: UserId) -> HeResult<Option<UserStats>> {
         let row = sqlx::query!(
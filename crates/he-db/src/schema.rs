@@ -0,0 +1,65 @@
+// Table and column tokens for the query builder - mirrors ../../migrations
+
+use chrono::NaiveDateTime;
+
+crate::table! {
+    /// Accounts - migrations/001_create_users_table.sql
+    users {
+        id: i64,
+        login: String,
+        password: String,
+        email: String,
+        game_pass: String,
+        game_ip: u64,
+        real_ip: u64,
+        home_ip: u64,
+        learning: bool,
+        premium: bool,
+        last_login: NaiveDateTime,
+        created_at: NaiveDateTime,
+        updated_at: NaiveDateTime,
+    }
+}
+
+crate::table! {
+    /// Per-user game stats - migrations/002_create_users_stats_table.sql
+    users_stats {
+        id: i64,
+        user_id: i64,
+        reputation: i64,
+        money: i64,
+        experience: i64,
+        total_hacks: i64,
+        successful_hacks: i64,
+        failed_hacks: i64,
+        viruses_uploaded: i64,
+        round_stats_id: Option<i64>,
+        created_at: NaiveDateTime,
+        updated_at: NaiveDateTime,
+    }
+}
+
+crate::table! {
+    /// Running game actions - migrations/005_create_processes_table.sql
+    processes {
+        pid: i64,
+        p_creator_id: i64,
+        p_victim_id: i64,
+        p_action: i16,
+        p_soft_id: i64,
+        p_info: String,
+        p_info_str: String,
+        p_time_start: NaiveDateTime,
+        p_time_pause: NaiveDateTime,
+        p_time_end: NaiveDateTime,
+        p_time_ideal: i32,
+        p_time_worked: i32,
+        cpu_usage: f64,
+        net_usage: f64,
+        p_local: bool,
+        p_npc: bool,
+        is_paused: bool,
+        created_at: NaiveDateTime,
+        updated_at: NaiveDateTime,
+    }
+}
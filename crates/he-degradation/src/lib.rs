@@ -0,0 +1,432 @@
+//! Degradation policies for the dependencies the server can limp along without
+//!
+//! Redis and the secondary databases are registered in one [`Degradation`]
+//! registry, each with a policy. A fail-open dependency that is down is
+//! skipped: cache reads miss, writes are dropped, secondary database traffic
+//! goes to the main database. A fail-closed one turns into an
+//! [`Unavailable`] error instead, so callers refuse rather than guess.
+//!
+//! After `failure_threshold` consecutive failures a dependency's breaker
+//! opens and calls short-circuit. It only closes again once the dependency's
+//! health probe passes, which [`Degradation::spawn_recovery`] tries every
+//! `open_for`; live traffic never probes a broken dependency. Degraded flags
+//! are served in `/health`, and the time each dependency spends degraded is
+//! exported as `dependency_degraded_seconds_total`.
+//!
+//! Policies can be overridden per dependency with `DEGRADE_<NAME>=open|closed`,
+//! `DEGRADE_<NAME>_THRESHOLD` and `DEGRADE_<NAME>_OPEN_SECS`.
+
+use chrono::{DateTime, Utc};
+use prometheus::{register_counter_vec, register_int_counter_vec, register_int_gauge_vec, CounterVec, IntCounterVec, IntGaugeVec};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+lazy_static::lazy_static! {
+    static ref DEGRADED: IntGaugeVec = register_int_gauge_vec!(
+        "dependency_degraded",
+        "Whether a dependency is degraded (1) or healthy (0)",
+        &["dependency"]
+    ).unwrap();
+
+    static ref DEGRADED_SECONDS: CounterVec = register_counter_vec!(
+        "dependency_degraded_seconds_total",
+        "Time a dependency has spent degraded",
+        &["dependency"]
+    ).unwrap();
+
+    static ref SHORT_CIRCUITS: IntCounterVec = register_int_counter_vec!(
+        "dependency_short_circuits_total",
+        "Calls not made because the dependency was degraded",
+        &["dependency"]
+    ).unwrap();
+}
+
+/// What callers get while a dependency is degraded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureMode {
+    /// Carry on without it
+    Open,
+    /// Fail the call with [`Unavailable`]
+    Closed,
+}
+
+impl FromStr for FailureMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "open" | "fail_open" => Ok(Self::Open),
+            "closed" | "fail_closed" => Ok(Self::Closed),
+            other => Err(format!("unknown failure mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DependencyPolicy {
+    pub mode: FailureMode,
+    /// Consecutive failures that open the breaker
+    pub failure_threshold: u32,
+    /// Time between recovery probes while the breaker is open
+    pub open_for: Duration,
+}
+
+impl DependencyPolicy {
+    pub fn fail_open() -> Self {
+        Self { mode: FailureMode::Open, failure_threshold: 3, open_for: Duration::from_secs(10) }
+    }
+
+    pub fn fail_closed() -> Self {
+        Self { mode: FailureMode::Closed, ..Self::fail_open() }
+    }
+
+    /// Apply the `DEGRADE_<NAME>*` overrides for dependency `name`
+    pub fn with_env(mut self, name: &str) -> Self {
+        let prefix = format!("DEGRADE_{}", env_name(name));
+        if let Some(mode) = std::env::var(&prefix).ok().and_then(|v| v.parse().ok()) {
+            self.mode = mode;
+        }
+        if let Some(threshold) = std::env::var(format!("{}_THRESHOLD", prefix)).ok().and_then(|v| v.parse().ok()) {
+            self.failure_threshold = u32::max(threshold, 1);
+        }
+        if let Some(secs) = std::env::var(format!("{}_OPEN_SECS", prefix)).ok().and_then(|v| v.parse().ok()) {
+            self.open_for = Duration::from_secs(secs);
+        }
+        self
+    }
+}
+
+fn env_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect()
+}
+
+/// A fail-closed dependency is degraded
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{dependency} is unavailable")]
+pub struct Unavailable {
+    pub dependency: String,
+}
+
+type ProbeFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+type Probe = Box<dyn Fn() -> ProbeFuture + Send + Sync>;
+
+#[derive(Debug, Default)]
+struct Inner {
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    /// Set while degraded
+    degraded_at: Option<DateTime<Utc>>,
+    next_probe: Option<Instant>,
+    /// Degraded time up to here is already counted
+    accounted_to: Option<Instant>,
+    degraded_total: Duration,
+}
+
+impl Inner {
+    fn account(&mut self, name: &str, now: Instant) {
+        if let Some(from) = self.accounted_to {
+            let spent = now.saturating_duration_since(from);
+            self.degraded_total += spent;
+            DEGRADED_SECONDS.with_label_values(&[name]).inc_by(spent.as_secs_f64());
+            self.accounted_to = Some(now);
+        }
+    }
+}
+
+/// One guarded dependency and its breaker
+pub struct Dependency {
+    name: String,
+    policy: DependencyPolicy,
+    probe: Probe,
+    inner: Mutex<Inner>,
+}
+
+impl std::fmt::Debug for Dependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dependency").field("name", &self.name).field("policy", &self.policy).finish()
+    }
+}
+
+impl Dependency {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mode(&self) -> FailureMode {
+        self.policy.mode
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.inner.lock().unwrap().degraded_at.is_some()
+    }
+
+    /// Whether a call may go through. While degraded, fail-open dependencies
+    /// answer `Ok(false)` and the caller skips them; fail-closed ones answer
+    /// [`Unavailable`].
+    pub fn admit(&self) -> Result<bool, Unavailable> {
+        if !self.is_degraded() {
+            return Ok(true);
+        }
+        SHORT_CIRCUITS.with_label_values(&[&self.name]).inc();
+        match self.policy.mode {
+            FailureMode::Open => Ok(false),
+            FailureMode::Closed => Err(Unavailable { dependency: self.name.clone() }),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        // A call admitted just before the breaker opened proves nothing; only
+        // the probe closes it
+        if inner.degraded_at.is_none() {
+            inner.consecutive_failures = 0;
+        }
+    }
+
+    pub fn record_failure(&self, error: impl Display) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        inner.last_error = Some(error.to_string());
+        if inner.degraded_at.is_none() && inner.consecutive_failures >= self.policy.failure_threshold {
+            self.open(&mut inner);
+        }
+    }
+
+    /// Record the outcome of a call made after [`Dependency::admit`]
+    pub fn record<T, E: Display>(&self, result: &Result<T, E>) {
+        match result {
+            Ok(_) => self.record_success(),
+            Err(e) => self.record_failure(e),
+        }
+    }
+
+    /// Open the breaker without waiting for the threshold, e.g. when the
+    /// dependency could not be reached at startup
+    pub fn trip(&self, error: impl Display) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.last_error = Some(error.to_string());
+        if inner.degraded_at.is_none() {
+            self.open(&mut inner);
+        }
+    }
+
+    fn open(&self, inner: &mut Inner) {
+        let now = Instant::now();
+        inner.degraded_at = Some(Utc::now());
+        inner.next_probe = Some(now + self.policy.open_for);
+        inner.accounted_to = Some(now);
+        DEGRADED.with_label_values(&[&self.name]).set(1);
+        warn!(
+            "{} degraded ({:?}): {}",
+            self.name,
+            self.policy.mode,
+            inner.last_error.as_deref().unwrap_or("unknown error")
+        );
+    }
+
+    /// Probe a degraded dependency whose retry time has come, closing the
+    /// breaker if the probe passes. Returns whether it is healthy now.
+    pub async fn recover(&self) -> bool {
+        let now = Instant::now();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.degraded_at.is_none() {
+                return true;
+            }
+            inner.account(&self.name, now);
+            if inner.next_probe.is_some_and(|at| now < at) {
+                return false;
+            }
+            inner.next_probe = Some(now + self.policy.open_for);
+        }
+
+        let probe = (self.probe)().await;
+        let mut inner = self.inner.lock().unwrap();
+        match probe {
+            Ok(()) => {
+                inner.account(&self.name, Instant::now());
+                let since = inner.degraded_at.take();
+                inner.accounted_to = None;
+                inner.next_probe = None;
+                inner.consecutive_failures = 0;
+                DEGRADED.with_label_values(&[&self.name]).set(0);
+                if let Some(since) = since {
+                    info!("{} recovered after {}s degraded", self.name, (Utc::now() - since).num_seconds());
+                }
+                true
+            }
+            Err(e) => {
+                inner.last_error = Some(e);
+                false
+            }
+        }
+    }
+
+    pub fn status(&self) -> DependencyStatus {
+        let mut inner = self.inner.lock().unwrap();
+        inner.account(&self.name, Instant::now());
+        DependencyStatus {
+            name: self.name.clone(),
+            mode: self.policy.mode,
+            degraded: inner.degraded_at.is_some(),
+            degraded_since: inner.degraded_at,
+            degraded_seconds: inner.degraded_total.as_secs_f64(),
+            consecutive_failures: inner.consecutive_failures,
+            last_error: inner.last_error.clone(),
+        }
+    }
+}
+
+/// A dependency as reported in `/health`
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyStatus {
+    pub name: String,
+    pub mode: FailureMode,
+    pub degraded: bool,
+    pub degraded_since: Option<DateTime<Utc>>,
+    /// Total time degraded since startup
+    pub degraded_seconds: f64,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+}
+
+/// Every guarded dependency of the process
+#[derive(Debug, Default)]
+pub struct Degradation {
+    dependencies: RwLock<Vec<Arc<Dependency>>>,
+}
+
+impl Degradation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Guard dependency `name` with `policy`, after its `DEGRADE_<NAME>*`
+    /// overrides. `probe` decides when a degraded dependency has recovered.
+    /// Registering a name twice returns the first registration.
+    pub fn register<P, F>(&self, name: &str, policy: DependencyPolicy, probe: P) -> Arc<Dependency>
+    where
+        P: Fn() -> F + Send + Sync + 'static,
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let mut dependencies = self.dependencies.write().unwrap();
+        if let Some(existing) = dependencies.iter().find(|d| d.name == name) {
+            return existing.clone();
+        }
+        let dependency = Arc::new(Dependency {
+            name: name.to_string(),
+            policy: policy.with_env(name),
+            probe: Box::new(move || Box::pin(probe())),
+            inner: Mutex::new(Inner::default()),
+        });
+        DEGRADED.with_label_values(&[name]).set(0);
+        dependencies.push(dependency.clone());
+        dependency
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Dependency>> {
+        self.dependencies.read().unwrap().iter().find(|d| d.name == name).cloned()
+    }
+
+    pub fn status(&self) -> Vec<DependencyStatus> {
+        self.dependencies.read().unwrap().iter().map(|d| d.status()).collect()
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.dependencies.read().unwrap().iter().any(|d| d.is_degraded())
+    }
+
+    /// Probe every degraded dependency that is due
+    pub async fn recover(&self) {
+        let dependencies = self.dependencies.read().unwrap().clone();
+        for dependency in dependencies {
+            dependency.recover().await;
+        }
+    }
+
+    /// Run [`Degradation::recover`] every `interval`; this also keeps the
+    /// degraded-time metric current
+    pub fn spawn_recovery(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                self.recover().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn policy(mode: FailureMode) -> DependencyPolicy {
+        DependencyPolicy { mode, failure_threshold: 2, open_for: Duration::ZERO }
+    }
+
+    #[tokio::test]
+    async fn test_breaker_opens_after_threshold() {
+        let degradation = Degradation::new();
+        let cache = degradation.register("test_cache", policy(FailureMode::Open), || async { Ok(()) });
+        let bank = degradation.register("test_bank", policy(FailureMode::Closed), || async { Ok(()) });
+
+        cache.record_failure("refused");
+        assert_eq!(cache.admit(), Ok(true));
+        cache.record_failure("refused");
+        assert_eq!(cache.admit(), Ok(false));
+
+        bank.trip("no route to host");
+        assert_eq!(bank.admit(), Err(Unavailable { dependency: "test_bank".to_string() }));
+
+        let status = degradation.status();
+        assert!(status.iter().all(|s| s.degraded));
+        assert_eq!(status[0].last_error.as_deref(), Some("refused"));
+        assert!(degradation.is_degraded());
+    }
+
+    #[tokio::test]
+    async fn test_only_a_passing_probe_recovers() {
+        let healthy = Arc::new(AtomicBool::new(false));
+        let degradation = Degradation::new();
+        let probe_healthy = healthy.clone();
+        let redis = degradation.register("test_redis", policy(FailureMode::Open), move || {
+            let healthy = probe_healthy.load(Ordering::SeqCst);
+            async move { if healthy { Ok(()) } else { Err("still down".to_string()) } }
+        });
+
+        redis.trip("connection reset");
+        // Successful calls racing the trip do not close the breaker
+        redis.record_success();
+        assert!(!redis.recover().await);
+        assert_eq!(redis.status().last_error.as_deref(), Some("still down"));
+
+        healthy.store(true, Ordering::SeqCst);
+        assert!(redis.recover().await);
+        assert_eq!(redis.admit(), Ok(true));
+        let status = redis.status();
+        assert!(!status.degraded && status.degraded_since.is_none());
+        assert!(!degradation.is_degraded());
+    }
+
+    #[test]
+    fn test_policy_env_overrides() {
+        std::env::set_var("DEGRADE_DB_TEST_POLICY", "closed");
+        std::env::set_var("DEGRADE_DB_TEST_POLICY_THRESHOLD", "0");
+        std::env::set_var("DEGRADE_DB_TEST_POLICY_OPEN_SECS", "30");
+
+        let policy = DependencyPolicy::fail_open().with_env("db-test-policy");
+        assert_eq!(policy.mode, FailureMode::Closed);
+        assert_eq!(policy.failure_threshold, 1);
+        assert_eq!(policy.open_for, Duration::from_secs(30));
+        assert_eq!("fail_open".parse(), Ok(FailureMode::Open));
+    }
+}
@@ -0,0 +1,208 @@
+//! New-account restrictions
+//!
+//! Fresh accounts are where most abuse comes from: throwaway accounts used to
+//! move money, spam chat or place bounties on someone. Until an account is
+//! both old enough and high enough level, some features are closed to it and
+//! its chat is rate limited. Nothing has to lift a restriction; once the
+//! account passes both thresholds it is simply no longer restricted.
+//!
+//! Staff can mark an account verified, which lifts every restriction, or
+//! exempt it from single ones.
+
+use crate::config::{AccountGatingConfig, Gate};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Restriction {
+    /// Marketplace listings and purchases
+    Trading,
+    /// Chat is rate limited and messages are shorter, not closed
+    Chat,
+    BountyPlacement,
+}
+
+impl Restriction {
+    pub const ALL: [Restriction; 3] = [Restriction::Trading, Restriction::Chat, Restriction::BountyPlacement];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Restriction::Trading => "trading",
+            Restriction::Chat => "chat",
+            Restriction::BountyPlacement => "bounty_placement",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    fn gate(self, config: &AccountGatingConfig) -> &Gate {
+        match self {
+            Restriction::Trading => &config.trading,
+            Restriction::Chat => &config.chat,
+            Restriction::BountyPlacement => &config.bounty_placement,
+        }
+    }
+}
+
+/// What gating knows about an account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountStanding {
+    pub level: i32,
+    pub created_at: DateTime<Utc>,
+    /// Verified accounts are never restricted
+    pub verified: bool,
+    /// Restrictions staff lifted for this account
+    pub exempt: Vec<Restriction>,
+}
+
+/// A restriction in force and what lifts it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActiveRestriction {
+    pub restriction: Restriction,
+    /// Set while the account's level is too low
+    pub min_level: Option<i32>,
+    /// Set while the account is too new
+    pub old_enough_at: Option<DateTime<Utc>>,
+}
+
+/// The restriction, if `restriction` applies to the account at `now`
+pub fn check(
+    restriction: Restriction,
+    standing: &AccountStanding,
+    now: DateTime<Utc>,
+    config: &AccountGatingConfig,
+) -> Option<ActiveRestriction> {
+    if standing.verified || standing.exempt.contains(&restriction) {
+        return None;
+    }
+    let gate = restriction.gate(config);
+    let old_enough_at = standing.created_at + Duration::hours(gate.min_age_hours);
+    let min_level = (standing.level < gate.min_level).then_some(gate.min_level);
+    let old_enough_at = (now < old_enough_at).then_some(old_enough_at);
+
+    if min_level.is_none() && old_enough_at.is_none() {
+        return None;
+    }
+    Some(ActiveRestriction { restriction, min_level, old_enough_at })
+}
+
+/// Every restriction on the account at `now`
+pub fn restrictions(standing: &AccountStanding, now: DateTime<Utc>, config: &AccountGatingConfig) -> Vec<ActiveRestriction> {
+    Restriction::ALL.into_iter().filter_map(|r| check(r, standing, now, config)).collect()
+}
+
+/// Why a restricted account was refused
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum GatingDenied {
+    Restricted(ActiveRestriction),
+    ChatLimited { messages: i64, window_minutes: i64 },
+    MessageTooLong { max: usize },
+}
+
+impl GatingDenied {
+    pub fn message(&self) -> String {
+        match self {
+            GatingDenied::Restricted(active) => {
+                let what = match active.restriction {
+                    Restriction::Trading => "Trading",
+                    Restriction::Chat => "Chat",
+                    Restriction::BountyPlacement => "Placing bounties",
+                };
+                match (active.min_level, active.old_enough_at) {
+                    (Some(level), Some(at)) => {
+                        format!("{} opens at level {} once your account is old enough ({})", what, level, at.format("%Y-%m-%d %H:%M UTC"))
+                    }
+                    (Some(level), None) => format!("{} opens at level {}", what, level),
+                    (None, Some(at)) => format!("{} opens on {}", what, at.format("%Y-%m-%d %H:%M UTC")),
+                    (None, None) => format!("{} is not available to new accounts", what),
+                }
+            }
+            GatingDenied::ChatLimited { messages, window_minutes } => {
+                format!("New accounts can send {} messages every {} minutes", messages, window_minutes)
+            }
+            GatingDenied::MessageTooLong { max } => {
+                format!("Messages from new accounts are limited to {} characters", max)
+            }
+        }
+    }
+}
+
+/// Whether an account may send a message of `length` characters, having sent
+/// `recent` within the chat window
+pub fn check_chat(
+    standing: &AccountStanding,
+    recent: i64,
+    length: usize,
+    now: DateTime<Utc>,
+    config: &AccountGatingConfig,
+) -> Result<(), GatingDenied> {
+    if check(Restriction::Chat, standing, now, config).is_none() {
+        return Ok(());
+    }
+    if length > config.restricted_chat_max_length {
+        return Err(GatingDenied::MessageTooLong { max: config.restricted_chat_max_length });
+    }
+    if recent >= config.restricted_chat_messages {
+        return Err(GatingDenied::ChatLimited {
+            messages: config.restricted_chat_messages,
+            window_minutes: config.restricted_chat_window_minutes,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standing(level: i32, age_hours: i64, now: DateTime<Utc>) -> AccountStanding {
+        AccountStanding { level, created_at: now - Duration::hours(age_hours), verified: false, exempt: Vec::new() }
+    }
+
+    #[test]
+    fn test_restrictions_lift_with_level_and_age() {
+        let config = AccountGatingConfig::default();
+        let now = Utc::now();
+
+        let fresh = standing(1, 1, now);
+        assert_eq!(restrictions(&fresh, now, &config).len(), Restriction::ALL.len());
+        let trading = check(Restriction::Trading, &fresh, now, &config).unwrap();
+        assert_eq!(trading.min_level, Some(config.trading.min_level));
+        assert!(trading.old_enough_at.is_some());
+
+        // Level alone is not enough while the account is new
+        let levelled = standing(config.bounty_placement.min_level, 1, now);
+        assert!(check(Restriction::Trading, &levelled, now, &config).unwrap().min_level.is_none());
+
+        let veteran = standing(config.bounty_placement.min_level, 24 * 365, now);
+        assert!(restrictions(&veteran, now, &config).is_empty());
+    }
+
+    #[test]
+    fn test_overrides_and_chat() {
+        let config = AccountGatingConfig::default();
+        let now = Utc::now();
+        let mut fresh = standing(1, 1, now);
+
+        assert!(check_chat(&fresh, 0, 10, now, &config).is_ok());
+        assert!(matches!(
+            check_chat(&fresh, config.restricted_chat_messages, 10, now, &config),
+            Err(GatingDenied::ChatLimited { .. })
+        ));
+        assert!(matches!(
+            check_chat(&fresh, 0, config.restricted_chat_max_length + 1, now, &config),
+            Err(GatingDenied::MessageTooLong { .. })
+        ));
+
+        fresh.exempt = vec![Restriction::Chat];
+        assert!(check_chat(&fresh, config.restricted_chat_messages, 10, now, &config).is_ok());
+        assert!(check(Restriction::Trading, &fresh, now, &config).is_some());
+
+        fresh.verified = true;
+        assert!(restrictions(&fresh, now, &config).is_empty());
+    }
+}
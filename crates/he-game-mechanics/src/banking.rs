@@ -0,0 +1,327 @@
+//! NPC bank network
+//!
+//! Players hold accounts at several NPC banks, each with a security tier and
+//! its own fees. Moving money to another bank costs the sending bank's
+//! transfer fee; moves within one bank are free. Every transfer is logged.
+//!
+//! A player who has hacked a bank's server can move money out of any account
+//! there they know the number of. That money is stolen: it stays marked with
+//! the theft wherever later transfers take it, and stolen money is the first
+//! to leave an account, so the victim can follow it. The bank's tier limits
+//! how much one theft takes and whether the thief's address is logged.
+//! Laundering stolen money through a bank that offers it clears the mark for
+//! a fee, and the victim's trail ends at that bank.
+
+use crate::config::BankingConfig;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityTier {
+    Basic,
+    Secure,
+    Fortified,
+}
+
+impl SecurityTier {
+    pub fn level(&self) -> i16 {
+        match self {
+            SecurityTier::Basic => 1,
+            SecurityTier::Secure => 2,
+            SecurityTier::Fortified => 3,
+        }
+    }
+
+    pub fn from_level(level: i16) -> Option<Self> {
+        match level {
+            1 => Some(SecurityTier::Basic),
+            2 => Some(SecurityTier::Secure),
+            3 => Some(SecurityTier::Fortified),
+            _ => None,
+        }
+    }
+
+    /// Most of an account's balance one theft can take, in percent
+    pub fn theft_share_percent(&self, config: &BankingConfig) -> i64 {
+        config.theft_share_percent[self.level() as usize - 1]
+    }
+
+    /// Whether the bank logs the address a theft came from for the victim
+    pub fn logs_thief_ip(&self, config: &BankingConfig) -> bool {
+        self.level() >= config.thief_ip_min_tier
+    }
+}
+
+/// What a bank charges
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BankTerms {
+    pub bank_id: i64,
+    pub tier: SecurityTier,
+    /// In hundredths of a percent of the amount
+    pub transfer_fee_bps: i64,
+    pub min_transfer_fee: i64,
+    /// `None` if the bank does not launder
+    pub laundering_fee_bps: Option<i64>,
+}
+
+/// What the sending bank charges to move `amount` to `to_bank_id`; `None`
+/// if the fee overflows
+pub fn transfer_fee(amount: i64, from: &BankTerms, to_bank_id: i64) -> Option<i64> {
+    if from.bank_id == to_bank_id {
+        return Some(0);
+    }
+    Some((amount.checked_mul(from.transfer_fee_bps)? / 10_000).max(from.min_transfer_fee))
+}
+
+/// What laundering `amount` through `bank` costs; `None` if the bank does
+/// not launder or the fee overflows
+pub fn laundering_fee(amount: i64, bank: &BankTerms) -> Option<i64> {
+    Some(amount.checked_mul(bank.laundering_fee_bps?)? / 10_000)
+}
+
+/// Take up to `amount` of stolen money out of an account's lots, oldest
+/// theft first. Lots are `(theft_id, amount)` and keep what is left; returns
+/// what left each theft.
+pub fn take_stolen(lots: &mut Vec<(i64, i64)>, amount: i64) -> Vec<(i64, i64)> {
+    lots.retain(|(_, held)| *held > 0);
+    lots.sort_by_key(|(theft_id, _)| *theft_id);
+
+    let mut left = amount.max(0);
+    let mut taken = Vec::new();
+    for (theft_id, held) in lots.iter_mut() {
+        if left == 0 {
+            break;
+        }
+        let take = (*held).min(left);
+        taken.push((*theft_id, take));
+        *held -= take;
+        left -= take;
+    }
+    lots.retain(|(_, held)| *held > 0);
+    taken
+}
+
+/// Why an account cannot be opened
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum OpenDenied {
+    BankNotFound,
+    AlreadyCustomer,
+    TooManyAccounts { max: usize },
+}
+
+impl OpenDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            OpenDenied::BankNotFound => "Bank not found".to_string(),
+            OpenDenied::AlreadyCustomer => "You already have an account at this bank".to_string(),
+            OpenDenied::TooManyAccounts { max } => format!("You can hold at most {} bank accounts", max),
+        }
+    }
+}
+
+pub fn check_open(already_customer: bool, open_accounts: usize, config: &BankingConfig) -> Result<(), OpenDenied> {
+    if already_customer {
+        return Err(OpenDenied::AlreadyCustomer);
+    }
+    if open_accounts >= config.max_accounts_per_player {
+        return Err(OpenDenied::TooManyAccounts { max: config.max_accounts_per_player });
+    }
+    Ok(())
+}
+
+/// Why money cannot be moved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum TransferDenied {
+    AccountNotFound,
+    SameAccount,
+    AmountTooSmall { min: i64 },
+    AmountTooLarge { max: i64 },
+    /// Not the player's account, and they have not hacked its bank
+    NoAccess,
+    InsufficientFunds { needed: i64, balance: i64 },
+    /// More than the bank's tier lets one theft take
+    TheftLimit { max: i64 },
+    /// The player stole from this account too recently
+    TheftCooldown { hours: i64 },
+}
+
+impl TransferDenied {
+    pub fn message(&self) -> String {
+        match self {
+            TransferDenied::AccountNotFound => "Bank account not found".to_string(),
+            TransferDenied::SameAccount => "Cannot transfer to the same account".to_string(),
+            TransferDenied::AmountTooSmall { min } => format!("Transfers must be at least {}", dollars(*min)),
+            TransferDenied::AmountTooLarge { max } => format!("Transfers are limited to {}", dollars(*max)),
+            TransferDenied::NoAccess => "You have no access to that account".to_string(),
+            TransferDenied::InsufficientFunds { needed, .. } => {
+                format!("The account needs {} to cover the transfer and its fee", dollars(*needed))
+            }
+            TransferDenied::TheftLimit { max } => format!("The bank stops transfers over {} from this account", dollars(*max)),
+            TransferDenied::TheftCooldown { hours } => {
+                format!("The bank is watching this account; wait {} hours before trying again", hours)
+            }
+        }
+    }
+}
+
+fn dollars(cents: i64) -> String {
+    format!("${}.{:02}", cents / 100, cents % 100)
+}
+
+/// A transfer as the server sees it once both accounts are locked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferCheck {
+    pub amount: i64,
+    pub fee: i64,
+    pub balance: i64,
+    /// The source bank's tier if the player does not own the account
+    pub theft: Option<SecurityTier>,
+    /// The player stole from the account within the cooldown
+    pub recent_theft: bool,
+}
+
+pub fn check_transfer(check: &TransferCheck, config: &BankingConfig) -> Result<(), TransferDenied> {
+    if check.amount < config.min_transfer {
+        return Err(TransferDenied::AmountTooSmall { min: config.min_transfer });
+    }
+    let too_large = TransferDenied::AmountTooLarge { max: config.max_transfer };
+    if check.amount > config.max_transfer {
+        return Err(too_large);
+    }
+    let needed = check.amount.checked_add(check.fee).ok_or(too_large)?;
+
+    if let Some(tier) = check.theft {
+        if check.recent_theft {
+            return Err(TransferDenied::TheftCooldown { hours: config.theft_cooldown_hours });
+        }
+        let share = i128::from(check.balance) * i128::from(tier.theft_share_percent(config)) / 100;
+        let max = i64::try_from(share).unwrap_or(i64::MAX);
+        if needed > max {
+            return Err(TransferDenied::TheftLimit { max: max.saturating_sub(check.fee).max(0) });
+        }
+    }
+
+    if check.balance < needed {
+        return Err(TransferDenied::InsufficientFunds { needed, balance: check.balance });
+    }
+    Ok(())
+}
+
+/// Why money cannot be laundered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum LaunderDenied {
+    AccountNotFound,
+    BankNotFound,
+    /// The bank does not launder money
+    NotOffered,
+    AmountTooSmall { min: i64 },
+    AmountTooLarge { max: i64 },
+    /// The account holds less stolen money than asked
+    NotEnoughStolen { stolen: i64 },
+}
+
+impl LaunderDenied {
+    pub fn message(&self) -> String {
+        match self {
+            LaunderDenied::AccountNotFound => "Bank account not found".to_string(),
+            LaunderDenied::BankNotFound => "Bank not found".to_string(),
+            LaunderDenied::NotOffered => "This bank does not launder money".to_string(),
+            LaunderDenied::AmountTooSmall { min } => format!("Laundering starts at {}", dollars(*min)),
+            LaunderDenied::AmountTooLarge { max } => format!("Laundering is limited to {}", dollars(*max)),
+            LaunderDenied::NotEnoughStolen { stolen } => {
+                format!("The account only holds {} of stolen money", dollars(*stolen))
+            }
+        }
+    }
+}
+
+/// Check laundering `amount` of an account's `stolen` money through `bank`;
+/// the fee is taken from the laundered amount
+pub fn check_launder(amount: i64, stolen: i64, bank: &BankTerms, config: &BankingConfig) -> Result<i64, LaunderDenied> {
+    if bank.laundering_fee_bps.is_none() {
+        return Err(LaunderDenied::NotOffered);
+    }
+    if amount < config.min_laundering {
+        return Err(LaunderDenied::AmountTooSmall { min: config.min_laundering });
+    }
+    let too_large = LaunderDenied::AmountTooLarge { max: config.max_transfer };
+    if amount > config.max_transfer {
+        return Err(too_large);
+    }
+    if amount > stolen {
+        return Err(LaunderDenied::NotEnoughStolen { stolen });
+    }
+    laundering_fee(amount, bank).ok_or(too_large)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank(bank_id: i64, tier: SecurityTier, laundering_fee_bps: Option<i64>) -> BankTerms {
+        BankTerms { bank_id, tier, transfer_fee_bps: 100, min_transfer_fee: 500, laundering_fee_bps }
+    }
+
+    #[test]
+    fn test_fees_and_stolen_lots() {
+        let basic = bank(1, SecurityTier::Basic, None);
+        assert_eq!(transfer_fee(1_000_000, &basic, 1), Some(0));
+        assert_eq!(transfer_fee(1_000_000, &basic, 2), Some(10_000));
+        assert_eq!(transfer_fee(1_000, &basic, 2), Some(500));
+        assert_eq!(transfer_fee(i64::MAX, &basic, 2), None);
+        assert_eq!(laundering_fee(100_000, &basic), None);
+        assert_eq!(laundering_fee(100_000, &bank(3, SecurityTier::Basic, Some(2_000))), Some(20_000));
+
+        let mut lots = vec![(7, 300), (4, 200), (9, 0)];
+        assert_eq!(take_stolen(&mut lots, 350), vec![(4, 200), (7, 150)]);
+        assert_eq!(lots, vec![(7, 150)]);
+        assert_eq!(take_stolen(&mut lots, 500), vec![(7, 150)]);
+        assert!(lots.is_empty());
+        assert!(take_stolen(&mut vec![(4, 200)], 0).is_empty());
+    }
+
+    #[test]
+    fn test_check_transfer_and_launder() {
+        let config = BankingConfig::default();
+        let own = TransferCheck { amount: 10_000, fee: 500, balance: 10_500, theft: None, recent_theft: false };
+        assert_eq!(check_transfer(&own, &config), Ok(()));
+        assert_eq!(
+            check_transfer(&TransferCheck { balance: 10_000, ..own }, &config),
+            Err(TransferDenied::InsufficientFunds { needed: 10_500, balance: 10_000 })
+        );
+
+        let theft = TransferCheck { amount: 40_000, fee: 0, balance: 100_000, theft: Some(SecurityTier::Secure), recent_theft: false };
+        assert_eq!(check_transfer(&theft, &config), Ok(()));
+        assert!(matches!(
+            check_transfer(&TransferCheck { theft: Some(SecurityTier::Fortified), ..theft }, &config),
+            Err(TransferDenied::TheftLimit { .. })
+        ));
+        assert!(matches!(
+            check_transfer(&TransferCheck { recent_theft: true, ..theft }, &config),
+            Err(TransferDenied::TheftCooldown { .. })
+        ));
+        assert_eq!(
+            check_transfer(&TransferCheck { amount: i64::MAX, fee: 500, balance: i64::MAX, ..own }, &config),
+            Err(TransferDenied::AmountTooLarge { max: config.max_transfer })
+        );
+        assert_eq!(
+            check_transfer(&TransferCheck { amount: config.max_transfer, fee: i64::MAX, balance: i64::MAX, ..own }, &config),
+            Err(TransferDenied::AmountTooLarge { max: config.max_transfer })
+        );
+        assert!(!SecurityTier::Basic.logs_thief_ip(&config));
+        assert!(SecurityTier::Fortified.logs_thief_ip(&config));
+
+        let launderer = bank(3, SecurityTier::Basic, Some(2_000));
+        assert_eq!(check_launder(100_000, 150_000, &launderer, &config), Ok(20_000));
+        assert_eq!(check_launder(100_000, 50_000, &launderer, &config), Err(LaunderDenied::NotEnoughStolen { stolen: 50_000 }));
+        assert_eq!(check_launder(100_000, 150_000, &bank(1, SecurityTier::Basic, None), &config), Err(LaunderDenied::NotOffered));
+        assert_eq!(
+            check_launder(i64::MAX, i64::MAX, &launderer, &config),
+            Err(LaunderDenied::AmountTooLarge { max: config.max_transfer })
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Bounties: player-placed hit contracts
+//!
+//! A player escrows a reward on another player for a limited window. The
+//! target is listed on the bounty board, and the first eligible player to
+//! break into the target's gateway claims the reward. Unclaimed bounties go
+//! back to the placer when the window closes.
+//!
+//! Claims are where collusion would pay, so a hunter is refused when they
+//! are the placer, share a clan with the placer or target, share a login
+//! address with either, moved money to or from the placer recently, or hold
+//! an account too young to be anything but a fresh alt. A refused claim
+//! leaves the bounty open for someone else.
+
+use crate::config::BountyConfig;
+use serde::{Deserialize, Serialize};
+
+/// Why a bounty cannot be placed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum PlaceDenied {
+    SelfTarget,
+    TargetNotFound,
+    RewardOutOfRange { min: i64, max: i64 },
+    WindowOutOfRange { min_hours: i64, max_hours: i64 },
+    TooManyOpen { max: usize },
+    InsufficientFunds { reward: i64, balance: i64 },
+    /// The server's rules turn bounties off
+    Disabled,
+}
+
+impl PlaceDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            PlaceDenied::SelfTarget => "You cannot place a bounty on yourself".to_string(),
+            PlaceDenied::TargetNotFound => "Target player not found".to_string(),
+            PlaceDenied::RewardOutOfRange { min, max } => {
+                format!("Rewards must be between {} and {}", dollars(*min), dollars(*max))
+            }
+            PlaceDenied::WindowOutOfRange { min_hours, max_hours } => {
+                format!("Bounties must run between {} and {} hours", min_hours, max_hours)
+            }
+            PlaceDenied::TooManyOpen { max } => format!("You can have at most {} open bounties", max),
+            PlaceDenied::InsufficientFunds { reward, .. } => {
+                format!("You need {} in your bank account to escrow this bounty", dollars(*reward))
+            }
+            PlaceDenied::Disabled => "Bounties are disabled on this server".to_string(),
+        }
+    }
+}
+
+fn dollars(cents: i64) -> String {
+    format!("${}.{:02}", cents / 100, cents % 100)
+}
+
+/// Whether `placer_id` may put `reward` on `target_id` for `window_hours`
+pub fn check_place(
+    placer_id: i64,
+    target_id: i64,
+    reward: i64,
+    window_hours: i64,
+    open_placed: usize,
+    balance: i64,
+    config: &BountyConfig,
+) -> Result<(), PlaceDenied> {
+    if placer_id == target_id {
+        return Err(PlaceDenied::SelfTarget);
+    }
+
+    if !(config.min_reward..=config.max_reward).contains(&reward) {
+        return Err(PlaceDenied::RewardOutOfRange { min: config.min_reward, max: config.max_reward });
+    }
+
+    if !(config.min_window_hours..=config.max_window_hours).contains(&window_hours) {
+        return Err(PlaceDenied::WindowOutOfRange {
+            min_hours: config.min_window_hours,
+            max_hours: config.max_window_hours,
+        });
+    }
+
+    if open_placed >= config.max_open_per_placer {
+        return Err(PlaceDenied::TooManyOpen { max: config.max_open_per_placer });
+    }
+
+    if balance < reward {
+        return Err(PlaceDenied::InsufficientFunds { reward, balance });
+    }
+
+    Ok(())
+}
+
+/// What is known about a hunter's ties to a bounty's placer and target
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClaimSignals {
+    pub hunter_is_placer: bool,
+    pub clanmate_of_placer: bool,
+    pub clanmate_of_target: bool,
+    pub shares_ip_with_placer: bool,
+    pub shares_ip_with_target: bool,
+    pub hunter_account_age_hours: i64,
+    /// Bank transfers between hunter and placer within the lookback
+    pub transfers_with_placer: i64,
+}
+
+/// Why a successful hack did not claim a bounty
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ClaimBlocked {
+    Placer,
+    Clanmate,
+    /// Same login address as the placer or target, likely the same person
+    SharedIp,
+    NewAccount { min_age_hours: i64 },
+    /// Money moved between hunter and placer
+    MoneyTrail,
+    /// Nowhere to pay the reward
+    NoBankAccount,
+}
+
+impl ClaimBlocked {
+    /// What the hunter is told
+    pub fn message(&self) -> String {
+        match self {
+            ClaimBlocked::Placer => "You cannot claim your own bounty".to_string(),
+            ClaimBlocked::Clanmate => "Bounties cannot be claimed within a clan".to_string(),
+            ClaimBlocked::SharedIp | ClaimBlocked::MoneyTrail => {
+                "This bounty cannot be claimed from your account".to_string()
+            }
+            ClaimBlocked::NewAccount { min_age_hours } => {
+                format!("Accounts must be {} hours old to claim bounties", min_age_hours)
+            }
+            ClaimBlocked::NoBankAccount => "You need a bank account to receive the reward".to_string(),
+        }
+    }
+}
+
+/// Whether a hunter with these ties may claim
+pub fn check_claim(signals: &ClaimSignals, config: &BountyConfig) -> Result<(), ClaimBlocked> {
+    if signals.hunter_is_placer {
+        return Err(ClaimBlocked::Placer);
+    }
+
+    if signals.clanmate_of_placer || signals.clanmate_of_target {
+        return Err(ClaimBlocked::Clanmate);
+    }
+
+    if signals.shares_ip_with_placer || signals.shares_ip_with_target {
+        return Err(ClaimBlocked::SharedIp);
+    }
+
+    if signals.hunter_account_age_hours < config.min_hunter_account_age_hours {
+        return Err(ClaimBlocked::NewAccount { min_age_hours: config.min_hunter_account_age_hours });
+    }
+
+    if signals.transfers_with_placer > 0 {
+        return Err(ClaimBlocked::MoneyTrail);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_place() {
+        let config = BountyConfig::default();
+        let reward = config.min_reward;
+
+        assert_eq!(check_place(1, 2, reward, 24, 0, reward, &config), Ok(()));
+        assert_eq!(check_place(1, 1, reward, 24, 0, reward, &config), Err(PlaceDenied::SelfTarget));
+        assert!(matches!(
+            check_place(1, 2, reward - 1, 24, 0, reward, &config),
+            Err(PlaceDenied::RewardOutOfRange { .. })
+        ));
+        assert!(matches!(
+            check_place(1, 2, reward, config.max_window_hours + 1, 0, reward, &config),
+            Err(PlaceDenied::WindowOutOfRange { .. })
+        ));
+        assert_eq!(
+            check_place(1, 2, reward, 24, config.max_open_per_placer, reward, &config),
+            Err(PlaceDenied::TooManyOpen { max: config.max_open_per_placer })
+        );
+        assert_eq!(
+            check_place(1, 2, reward, 24, 0, reward - 1, &config),
+            Err(PlaceDenied::InsufficientFunds { reward, balance: reward - 1 })
+        );
+    }
+
+    #[test]
+    fn test_check_claim() {
+        let config = BountyConfig::default();
+        let clean = ClaimSignals {
+            hunter_account_age_hours: config.min_hunter_account_age_hours,
+            ..Default::default()
+        };
+        assert_eq!(check_claim(&clean, &config), Ok(()));
+
+        let clanmate = ClaimSignals { clanmate_of_target: true, ..clean.clone() };
+        assert_eq!(check_claim(&clanmate, &config), Err(ClaimBlocked::Clanmate));
+
+        let alt = ClaimSignals { shares_ip_with_placer: true, ..clean.clone() };
+        assert_eq!(check_claim(&alt, &config), Err(ClaimBlocked::SharedIp));
+
+        let fresh = ClaimSignals { hunter_account_age_hours: 1, ..clean.clone() };
+        assert!(matches!(check_claim(&fresh, &config), Err(ClaimBlocked::NewAccount { .. })));
+
+        let paid = ClaimSignals { transfers_with_placer: 2, ..clean };
+        assert_eq!(check_claim(&paid, &config), Err(ClaimBlocked::MoneyTrail));
+    }
+}
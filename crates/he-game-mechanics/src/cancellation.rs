@@ -0,0 +1,75 @@
+//! Process cancellation
+//!
+//! A cancelled process gives back part of what was paid to start it: the
+//! share of its run still ahead, less a cancellation fee. Nothing is paid
+//! back once it has run its course. Whatever is not refunded is the penalty
+//! for cancelling.
+
+use crate::config::CancellationConfig;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How much of a process ran by `now`, from 0 to 1
+pub fn progress(start: DateTime<Utc>, end: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+    let total = (end - start).num_milliseconds();
+    if total <= 0 {
+        return 1.0;
+    }
+    ((now - start).num_milliseconds() as f64 / total as f64).clamp(0.0, 1.0)
+}
+
+/// What cancelling a process settles to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Settlement {
+    pub progress: f64,
+    /// Paid to start the process, in cents
+    pub cost: i64,
+    pub refund: i64,
+    /// Kept as the penalty for cancelling
+    pub penalty: i64,
+}
+
+/// Settle cancelling a process that cost `cost` at `progress`
+pub fn settle(cost: i64, progress: f64, config: &CancellationConfig) -> Settlement {
+    let cost = cost.max(0);
+    let progress = progress.clamp(0.0, 1.0);
+    let remaining = 1.0 - progress;
+    let refund = (cost as f64 * remaining * config.refund_percent as f64 / 100.0).floor() as i64;
+    let refund = if refund < config.min_refund { 0 } else { refund.min(cost) };
+
+    Settlement { progress, cost, refund, penalty: cost - refund }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_refund_shrinks_with_progress() {
+        let config = CancellationConfig::default();
+        let cost = 100_000;
+
+        let fresh = settle(cost, 0.0, &config);
+        assert_eq!(fresh.refund, cost * config.refund_percent / 100);
+        assert_eq!(fresh.refund + fresh.penalty, cost);
+
+        let half = settle(cost, 0.5, &config);
+        assert!(half.refund < fresh.refund);
+        assert!(half.penalty > fresh.penalty);
+
+        assert_eq!(settle(cost, 1.0, &config).refund, 0);
+        assert_eq!(settle(0, 0.0, &config), Settlement { progress: 0.0, cost: 0, refund: 0, penalty: 0 });
+    }
+
+    #[test]
+    fn test_progress() {
+        let start = Utc::now();
+        let end = start + Duration::seconds(100);
+
+        assert_eq!(progress(start, end, start - Duration::seconds(5)), 0.0);
+        assert!((progress(start, end, start + Duration::seconds(25)) - 0.25).abs() < 1e-9);
+        assert_eq!(progress(start, end, end + Duration::seconds(5)), 1.0);
+        assert_eq!(progress(start, start, start), 1.0);
+    }
+}
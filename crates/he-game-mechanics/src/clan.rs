@@ -6,6 +6,7 @@ use crate::types::*;
 use crate::{GameMechanicsError, Result};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use he_core::clock;
 
 #[derive(Debug, Clone)]
 pub struct ClanMechanics {
@@ -137,7 +138,7 @@ impl ClanMechanics {
             rank_multiplier,
             loyalty_bonus,
             recent_activity_score: activities.iter()
-                .filter(|a| chrono::Utc::now().signed_duration_since(a.timestamp).num_days() < 30)
+                .filter(|a| clock::now().signed_duration_since(a.timestamp).num_days() < 30)
                 .map(|a| a.quality_score)
                 .sum::<f64>(),
         })
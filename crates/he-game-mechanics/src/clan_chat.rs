@@ -0,0 +1,108 @@
+//! Clan chat
+//!
+//! Every clan has a private room with two channels. Members talk in the
+//! general channel; the announcements channel is read by everyone but only
+//! the leader and officers post to it. Officers also pin messages, which
+//! keeps them past the history window the rest of the room is pruned to.
+//! A disbanded clan's room stays readable as an archive.
+
+use crate::config::ClanChatConfig;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A channel of a clan's room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    General,
+    Announcements,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 2] = [Channel::General, Channel::Announcements];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Channel::General => "general",
+            Channel::Announcements => "announcements",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|channel| channel.as_str() == s)
+    }
+}
+
+/// Why a clan chat action was refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ClanChatDenied {
+    NoClan,
+    UnknownChannel,
+    /// The clan disbanded; its history can be read but not added to
+    Archived,
+    /// Only the leader and officers post announcements and pin messages
+    NotOfficer,
+    MessageNotFound,
+    NotPinned,
+    TooManyPins { max: usize },
+}
+
+impl ClanChatDenied {
+    pub fn message(&self) -> String {
+        match self {
+            ClanChatDenied::NoClan => "You need to be in a clan".to_string(),
+            ClanChatDenied::UnknownChannel => "Unknown clan chat channel".to_string(),
+            ClanChatDenied::Archived => "The clan has disbanded; its chat is read-only".to_string(),
+            ClanChatDenied::NotOfficer => "Only the clan leader and officers can do that".to_string(),
+            ClanChatDenied::MessageNotFound => "Message not found".to_string(),
+            ClanChatDenied::NotPinned => "That message is not pinned".to_string(),
+            ClanChatDenied::TooManyPins { max } => format!("A channel holds at most {} pinned messages", max),
+        }
+    }
+}
+
+/// Leaders and officers run the room
+pub fn is_officer(role: &str) -> bool {
+    role == "leader" || role == "officer"
+}
+
+/// Whether a member with clan role `role` may post in `channel`
+pub fn can_post(channel: Channel, role: &str) -> bool {
+    match channel {
+        Channel::General => true,
+        Channel::Announcements => is_officer(role),
+    }
+}
+
+/// Unpinned messages from before this are deleted
+pub fn history_cutoff(now: DateTime<Utc>, config: &ClanChatConfig) -> DateTime<Utc> {
+    now - Duration::days(config.history_days.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_only_officers_announce() {
+        assert!(can_post(Channel::General, "member"));
+        assert!(can_post(Channel::Announcements, "leader"));
+        assert!(can_post(Channel::Announcements, "officer"));
+        assert!(!can_post(Channel::Announcements, "member"));
+        assert_eq!(Channel::from_str("announcements"), Some(Channel::Announcements));
+        assert_eq!(Channel::from_str("officers"), None);
+    }
+
+    #[test]
+    fn test_history_cutoff() {
+        let now = Utc.with_ymd_and_hms(2024, 11, 30, 12, 0, 0).unwrap();
+        let config = ClanChatConfig { history_days: 30, ..ClanChatConfig::default() };
+        assert_eq!(history_cutoff(now, &config), Utc.with_ymd_and_hms(2024, 10, 31, 12, 0, 0).unwrap());
+
+        // A zero window would delete a message as soon as it was posted
+        let config = ClanChatConfig { history_days: 0, ..config };
+        assert_eq!(history_cutoff(now, &config), now - Duration::days(1));
+    }
+}
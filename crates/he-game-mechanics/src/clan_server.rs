@@ -0,0 +1,253 @@
+//! Clan servers
+//!
+//! Every clan runs one shared server. Officers buy its hardware from the clan
+//! treasury, one level of one part at a time, each level costing twice the
+//! one before. Members keep software on it for each other within its disk
+//! space, and lend it their best firewall: the strongest count in full and
+//! every further one a little less, scaled up by the server's CPU.
+//!
+//! In a war each clan's server is the other side's target. A strike compares
+//! the attacker's best cracker against the defense and takes integrity off
+//! the server; whoever takes the other server down wins the war outright,
+//! otherwise the score decides when it runs out.
+
+use crate::config::ClanServerConfig;
+use serde::{Deserialize, Serialize};
+
+/// Hardware a clan can upgrade
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Part {
+    Cpu,
+    Ram,
+    Hdd,
+    Net,
+}
+
+impl Part {
+    pub const ALL: [Part; 4] = [Part::Cpu, Part::Ram, Part::Hdd, Part::Net];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Part::Cpu => "cpu",
+            Part::Ram => "ram",
+            Part::Hdd => "hdd",
+            Part::Net => "net",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|part| part.as_str() == s)
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Upgrade level of each part, 0 for stock hardware
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Levels {
+    pub cpu: i32,
+    pub ram: i32,
+    pub hdd: i32,
+    pub net: i32,
+}
+
+impl Levels {
+    pub fn get(&self, part: Part) -> i32 {
+        match part {
+            Part::Cpu => self.cpu,
+            Part::Ram => self.ram,
+            Part::Hdd => self.hdd,
+            Part::Net => self.net,
+        }
+    }
+
+    pub fn raise(&mut self, part: Part) {
+        match part {
+            Part::Cpu => self.cpu += 1,
+            Part::Ram => self.ram += 1,
+            Part::Hdd => self.hdd += 1,
+            Part::Net => self.net += 1,
+        }
+    }
+}
+
+/// What a part provides at `level`: MHz, MB, MB and Mbps
+pub fn capacity(part: Part, level: i32, config: &ClanServerConfig) -> i32 {
+    config.base_capacity[part.index()] + config.capacity_per_level[part.index()] * level.max(0)
+}
+
+/// Price of raising `part` from `level` to the next, or `None` once it is
+/// at the top
+pub fn upgrade_cost(part: Part, level: i32, config: &ClanServerConfig) -> Option<i64> {
+    if level < 0 || level >= config.max_level {
+        return None;
+    }
+    config.base_upgrade_cost[part.index()].checked_mul(1i64.checked_shl(level as u32)?)
+}
+
+/// Defense of a server whose members lent firewalls of `versions`, with its
+/// CPU at `cpu_level`
+pub fn defense(versions: &[f64], cpu_level: i32, config: &ClanServerConfig) -> i64 {
+    let mut versions: Vec<f64> = versions.iter().copied().filter(|v| *v > 0.0).collect();
+    versions.sort_by(|a, b| b.total_cmp(a));
+
+    let mut weight = 1.0;
+    let mut total = 0.0;
+    for version in versions.into_iter().take(config.max_defenders) {
+        total += version * config.defense_per_version * weight;
+        weight *= config.defender_falloff_percent as f64 / 100.0;
+    }
+
+    let cpu_bonus = 1.0 + (config.cpu_defense_percent_per_level * cpu_level.max(0)) as f64 / 100.0;
+    (total * cpu_bonus).round() as i64
+}
+
+/// What one strike on a clan server did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Strike {
+    pub damage: i32,
+    pub integrity: i32,
+    /// The strike took the server down
+    pub downed: bool,
+    /// Score for the attacking side
+    pub points: i64,
+}
+
+/// Strike a server at `integrity` with a cracker of `cracker_version`
+pub fn strike(cracker_version: f64, defense: i64, integrity: i32, config: &ClanServerConfig) -> Strike {
+    let attack = cracker_version.max(0.0) * config.attack_per_version;
+    let damage = if defense <= 0 {
+        config.max_damage
+    } else {
+        (config.even_damage as f64 * attack / defense as f64).round() as i32
+    };
+    let damage = damage.clamp(config.min_damage, config.max_damage).min(integrity.max(0));
+
+    let integrity = integrity - damage;
+    let downed = integrity <= 0 && damage > 0;
+    Strike {
+        damage,
+        integrity,
+        downed,
+        points: damage as i64 + if downed { config.downed_bonus } else { 0 },
+    }
+}
+
+/// Why a clan server action was refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ClanServerDenied {
+    NoClan,
+    ClanNotFound,
+    /// Only the leader and officers spend the treasury and declare wars
+    NotOfficer,
+    UnknownPart,
+    MaxLevel { part: Part },
+    TreasuryTooLow { needed: i64, balance: i64 },
+    AmountTooSmall { min: i64 },
+    InsufficientFunds,
+    SoftwareNotFound,
+    FileNotFound,
+    /// Only whoever stored a file, or an officer, removes it
+    NotUploader,
+    StorageFull { free_mb: i32 },
+    NoFirewall,
+    NoCracker,
+    SameClan,
+    AlreadyAtWar,
+    WarNotFound,
+    /// The player's clan is not fighting this war, or it is over
+    NotAtWar,
+    AttackCooldown { minutes: i64 },
+    /// The server's rules turn clan wars off
+    WarsDisabled,
+    /// The leader hands the clan over before leaving it
+    LeaderCannotLeave,
+    MemberNotFound,
+}
+
+impl ClanServerDenied {
+    pub fn message(&self) -> String {
+        match self {
+            ClanServerDenied::NoClan => "You need to be in a clan".to_string(),
+            ClanServerDenied::ClanNotFound => "Clan not found".to_string(),
+            ClanServerDenied::NotOfficer => "Only the clan leader and officers can do that".to_string(),
+            ClanServerDenied::UnknownPart => "Unknown hardware part".to_string(),
+            ClanServerDenied::MaxLevel { part } => format!("The clan server's {} is fully upgraded", part.as_str()),
+            ClanServerDenied::TreasuryTooLow { needed, .. } => {
+                format!("The clan treasury needs {} for this upgrade", dollars(*needed))
+            }
+            ClanServerDenied::AmountTooSmall { min } => format!("Deposits must be at least {}", dollars(*min)),
+            ClanServerDenied::InsufficientFunds => "Your bank account cannot cover that".to_string(),
+            ClanServerDenied::SoftwareNotFound => "Software not found".to_string(),
+            ClanServerDenied::FileNotFound => "File not found on the clan server".to_string(),
+            ClanServerDenied::NotUploader => "Only whoever stored the file or an officer can remove it".to_string(),
+            ClanServerDenied::StorageFull { free_mb } => format!("Not enough disk space: {} MB free", free_mb),
+            ClanServerDenied::NoFirewall => "You have no firewall to lend the clan server".to_string(),
+            ClanServerDenied::NoCracker => "You need a cracker to attack".to_string(),
+            ClanServerDenied::SameClan => "A clan cannot go to war with itself".to_string(),
+            ClanServerDenied::AlreadyAtWar => "These clans are already at war".to_string(),
+            ClanServerDenied::WarNotFound => "War not found".to_string(),
+            ClanServerDenied::NotAtWar => "Your clan is not fighting this war".to_string(),
+            ClanServerDenied::AttackCooldown { minutes } => {
+                format!("You attacked recently; wait {} minutes", minutes)
+            }
+            ClanServerDenied::WarsDisabled => "Clan wars are disabled on this server".to_string(),
+            ClanServerDenied::LeaderCannotLeave => "The clan leader cannot leave the clan".to_string(),
+            ClanServerDenied::MemberNotFound => "That player is not in your clan".to_string(),
+        }
+    }
+}
+
+fn dollars(cents: i64) -> String {
+    format!("${}.{:02}", cents / 100, cents % 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrades_double_in_price_up_to_max() {
+        let config = ClanServerConfig::default();
+        let first = upgrade_cost(Part::Cpu, 0, &config).unwrap();
+        assert_eq!(upgrade_cost(Part::Cpu, 1, &config), Some(first * 2));
+        assert_eq!(upgrade_cost(Part::Cpu, config.max_level, &config), None);
+        assert!(capacity(Part::Hdd, 2, &config) > capacity(Part::Hdd, 0, &config));
+
+        let mut levels = Levels::default();
+        levels.raise(Part::Net);
+        assert_eq!(levels.get(Part::Net), 1);
+        assert_eq!(Part::from_str("ram"), Some(Part::Ram));
+    }
+
+    #[test]
+    fn test_defense_weights_strongest_firewalls() {
+        let config = ClanServerConfig::default();
+        assert_eq!(defense(&[], 0, &config), 0);
+
+        let one = defense(&[2.0], 0, &config);
+        let two = defense(&[1.0, 2.0], 0, &config);
+        assert_eq!(one, 20);
+        assert_eq!(two, 20 + 8);
+        assert!(defense(&[2.0], 4, &config) > one);
+    }
+
+    #[test]
+    fn test_strike_downs_server() {
+        let config = ClanServerConfig::default();
+        let even = strike(2.0, 20, 100, &config);
+        assert_eq!(even.damage, config.even_damage);
+        assert!(!even.downed);
+
+        let undefended = strike(1.0, 0, 5, &config);
+        assert_eq!(undefended.damage, 5);
+        assert!(undefended.downed);
+        assert_eq!(undefended.points, 5 + config.downed_bonus);
+
+        assert_eq!(strike(0.1, 1000, 100, &config).damage, config.min_damage);
+    }
+}
@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
+use he_core::clock;
 
 /// Clan ranks
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -149,8 +150,8 @@ impl ClanMember {
             player_id,
             player_name,
             rank: ClanRank::Recruit,
-            joined_at: SystemTime::now(),
-            last_active: SystemTime::now(),
+            joined_at: clock::system_now(),
+            last_active: clock::system_now(),
             contribution_points: 0,
             wars_participated: 0,
             resources_donated: 0,
@@ -189,11 +190,11 @@ impl ClanMember {
     
     pub fn add_contribution(&mut self, points: i64) {
         self.contribution_points += points;
-        self.last_active = SystemTime::now();
+        self.last_active = clock::system_now();
     }
     
     pub fn calculate_activity_score(&self) -> f32 {
-        let days_since_join = SystemTime::now()
+        let days_since_join = clock::system_now()
             .duration_since(self.joined_at)
             .unwrap_or_default()
             .as_secs() / 86400;
@@ -306,8 +307,8 @@ impl ClanWar {
             attacker_clan_id,
             defender_clan_id,
             status: WarStatus::Pending,
-            started_at: SystemTime::now(),
-            ends_at: SystemTime::now() + duration,
+            started_at: clock::system_now(),
+            ends_at: clock::system_now() + duration,
             attacker_score: 0,
             defender_score: 0,
             battles: Vec::new(),
@@ -320,7 +321,7 @@ impl ClanWar {
     
     pub fn start(&mut self) {
         self.status = WarStatus::Active;
-        self.started_at = SystemTime::now();
+        self.started_at = clock::system_now();
     }
     
     pub fn add_battle(&mut self, battle: WarBattle) {
@@ -333,7 +334,7 @@ impl ClanWar {
     }
     
     pub fn check_war_end(&mut self) -> bool {
-        if SystemTime::now() > self.ends_at {
+        if clock::system_now() > self.ends_at {
             if self.attacker_score > self.defender_score {
                 self.status = WarStatus::Victory;
             } else if self.defender_score > self.attacker_score {
@@ -515,7 +516,7 @@ impl Clan {
             tag,
             description: String::new(),
             motto: String::new(),
-            founded_at: SystemTime::now(),
+            founded_at: clock::system_now(),
             founder_id,
             leader_id: founder_id,
             members,
@@ -550,7 +551,7 @@ impl Clan {
         self.members.insert(player_id, member);
         
         self.log_activity(ClanActivityLog {
-            timestamp: SystemTime::now(),
+            timestamp: clock::system_now(),
             activity_type: ActivityType::MemberJoined,
             actor_id: player_id,
             details: format!("{} joined the clan", player_name),
@@ -574,7 +575,7 @@ impl Clan {
         }
         
         self.log_activity(ClanActivityLog {
-            timestamp: SystemTime::now(),
+            timestamp: clock::system_now(),
             activity_type: ActivityType::MemberLeft,
             actor_id: player_id,
             details: format!("{} left the clan", member.player_name),
@@ -604,7 +605,7 @@ impl Clan {
         member.promote()?;
         
         self.log_activity(ClanActivityLog {
-            timestamp: SystemTime::now(),
+            timestamp: clock::system_now(),
             activity_type: ActivityType::Promotion,
             actor_id: promoter_id,
             details: format!("{} promoted from {:?} to {:?}", 
@@ -626,7 +627,7 @@ impl Clan {
         self.enemies.insert(target_clan_id);
         
         self.log_activity(ClanActivityLog {
-            timestamp: SystemTime::now(),
+            timestamp: clock::system_now(),
             activity_type: ActivityType::WarDeclared,
             actor_id: self.leader_id,
             details: format!("War declared against clan {}", target_clan_id),
@@ -647,7 +648,7 @@ impl Clan {
         self.add_experience(contribution_points / 10);
         
         self.log_activity(ClanActivityLog {
-            timestamp: SystemTime::now(),
+            timestamp: clock::system_now(),
             activity_type: ActivityType::Donation,
             actor_id: player_id,
             details: format!("{} donated ${}", member.player_name, money),
@@ -679,7 +680,7 @@ impl Clan {
             self.experience -= required_exp;
             
             self.log_activity(ClanActivityLog {
-                timestamp: SystemTime::now(),
+                timestamp: clock::system_now(),
                 activity_type: ActivityType::LevelUp,
                 actor_id: 0,
                 details: format!("Clan reached level {}", self.level),
@@ -792,7 +793,7 @@ pub fn calculate_clan_reputation(clan: &Clan, config: &ClanConfig) -> i64 {
     // Activity bonus
     let active_members = clan.members.values()
         .filter(|m| {
-            SystemTime::now().duration_since(m.last_active)
+            clock::system_now().duration_since(m.last_active)
                 .unwrap_or_default().as_secs() < 86400
         })
         .count() as i64;
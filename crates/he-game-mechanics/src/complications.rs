@@ -0,0 +1,218 @@
+//! Complications: random events during long-running processes
+//!
+//! Every few seconds each process running for at least
+//! [`ComplicationConfig::min_process_seconds`] gets a roll. At most one
+//! complication hits a process:
+//! - **Firewall update**: the target patches mid-process, adding time
+//! - **Power spike**: the process stops until its owner resumes it
+//! - **Lucky exploit**: a shortcut turns up, cutting the remaining time
+//!
+//! Base chances per minute are he-helix-factor factors. Complex processes are
+//! more eventful, and processes against another server more so, which only
+//! firewall updates and exploits care about.
+
+use crate::config::ComplicationConfig;
+use crate::process::ProcessType;
+use chrono::{DateTime, Utc};
+use he_helix_factor::{factors, Factor};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplicationKind {
+    FirewallUpdate,
+    PowerSpike,
+    LuckyExploit,
+}
+
+impl ComplicationKind {
+    pub const ALL: [ComplicationKind; 3] = [
+        ComplicationKind::FirewallUpdate,
+        ComplicationKind::PowerSpike,
+        ComplicationKind::LuckyExploit,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComplicationKind::FirewallUpdate => "firewall_update",
+            ComplicationKind::PowerSpike => "power_spike",
+            ComplicationKind::LuckyExploit => "lucky_exploit",
+        }
+    }
+
+    /// What the player is told
+    pub fn message(&self) -> &'static str {
+        match self {
+            ComplicationKind::FirewallUpdate => "The target updated its firewall mid-process. It will take longer.",
+            ComplicationKind::PowerSpike => "A power spike knocked the process offline. Resume it to carry on.",
+            ComplicationKind::LuckyExploit => "You found an unpatched exploit. The remaining time is cut short.",
+        }
+    }
+
+    fn base_factor(&self) -> Factor {
+        match self {
+            ComplicationKind::FirewallUpdate => factors::firewall_update_factor(),
+            ComplicationKind::PowerSpike => factors::power_spike_factor(),
+            ComplicationKind::LuckyExploit => factors::lucky_exploit_factor(),
+        }
+    }
+
+    /// Whether the complication can happen to a process at all
+    fn applies_to(&self, remote: bool) -> bool {
+        match self {
+            ComplicationKind::FirewallUpdate | ComplicationKind::LuckyExploit => remote,
+            ComplicationKind::PowerSpike => true,
+        }
+    }
+}
+
+/// A running process as far as complications care
+#[derive(Debug, Clone)]
+pub struct ProcessContext {
+    pub process_type: ProcessType,
+    /// Aimed at another server
+    pub remote: bool,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+impl ProcessContext {
+    /// Long enough to have complications
+    pub fn is_eligible(&self, config: &ComplicationConfig) -> bool {
+        (self.end_time - self.start_time).num_seconds() >= config.min_process_seconds
+    }
+}
+
+/// Chance per minute of `kind` for a process, with its modifiers applied
+pub fn chance_per_minute(kind: ComplicationKind, process: &ProcessContext, config: &ComplicationConfig) -> f64 {
+    if !kind.applies_to(process.remote) {
+        return 0.0;
+    }
+
+    let mut factor = kind.base_factor();
+    let complexity = process.process_type.base_complexity() as f64;
+    factor.add_modifier("complexity", (1.0 + (complexity - 1.0) * config.complexity_weight).max(0.5));
+    if process.remote && kind != ComplicationKind::PowerSpike {
+        factor.add_modifier("remote", config.remote_multiplier);
+    }
+
+    factor.calculate().clamp(0.0, 1.0)
+}
+
+/// Chance of `kind` within one roll covering `interval`
+pub fn chance_per_roll(
+    kind: ComplicationKind,
+    process: &ProcessContext,
+    interval: Duration,
+    config: &ComplicationConfig,
+) -> f64 {
+    let per_minute = chance_per_minute(kind, process, config);
+    let minutes = interval.as_secs_f64() / 60.0;
+    1.0 - (1.0 - per_minute).powf(minutes)
+}
+
+/// Which complication, if any, a uniform `sample` in [0, 1) selects for this roll
+pub fn pick(
+    process: &ProcessContext,
+    interval: Duration,
+    sample: f64,
+    config: &ComplicationConfig,
+) -> Option<ComplicationKind> {
+    if !process.is_eligible(config) {
+        return None;
+    }
+
+    let mut threshold = 0.0;
+    for kind in ComplicationKind::ALL {
+        threshold += chance_per_roll(kind, process, interval, config);
+        if sample < threshold {
+            return Some(kind);
+        }
+    }
+    None
+}
+
+/// New end time after `kind` hits at `now`, or `None` if the process is paused
+pub fn apply(
+    kind: ComplicationKind,
+    now: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    config: &ComplicationConfig,
+) -> Option<DateTime<Utc>> {
+    let remaining_ms = (end_time - now).num_milliseconds().max(0) as f64;
+    let scaled = |fraction: f64| chrono::Duration::milliseconds((remaining_ms * fraction).round() as i64);
+
+    match kind {
+        ComplicationKind::FirewallUpdate => Some(end_time + scaled(config.delay_fraction)),
+        ComplicationKind::PowerSpike => None,
+        ComplicationKind::LuckyExploit => Some(now + scaled(1.0 - config.speedup_fraction)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(process_type: ProcessType, remote: bool, minutes: i64) -> ProcessContext {
+        let start_time = Utc::now();
+        ProcessContext {
+            process_type,
+            remote,
+            start_time,
+            end_time: start_time + chrono::Duration::minutes(minutes),
+        }
+    }
+
+    #[test]
+    fn test_short_and_local_processes() {
+        let config = ComplicationConfig::default();
+        let interval = Duration::from_secs(60);
+
+        // Too short for anything to happen
+        assert_eq!(pick(&process(ProcessType::Crack, true, 2), interval, 0.0, &config), None);
+
+        // Only power spikes hit local processes
+        let local = process(ProcessType::Research, false, 30);
+        assert_eq!(chance_per_minute(ComplicationKind::FirewallUpdate, &local, &config), 0.0);
+        assert_eq!(chance_per_minute(ComplicationKind::LuckyExploit, &local, &config), 0.0);
+        assert_eq!(pick(&local, interval, 0.0, &config), Some(ComplicationKind::PowerSpike));
+        assert_eq!(pick(&local, interval, 0.5, &config), None);
+    }
+
+    #[test]
+    fn test_factors_drive_chances() {
+        let config = ComplicationConfig::default();
+        let crack = process(ProcessType::Crack, true, 30);
+        let download = process(ProcessType::Download, true, 30);
+
+        let base = factors::firewall_update_factor().calculate();
+        assert!(chance_per_minute(ComplicationKind::FirewallUpdate, &crack, &config) > base);
+        assert!(
+            chance_per_minute(ComplicationKind::FirewallUpdate, &crack, &config)
+                > chance_per_minute(ComplicationKind::FirewallUpdate, &download, &config)
+        );
+
+        let minute = chance_per_roll(ComplicationKind::PowerSpike, &crack, Duration::from_secs(60), &config);
+        let second = chance_per_roll(ComplicationKind::PowerSpike, &crack, Duration::from_secs(1), &config);
+        assert!((minute - chance_per_minute(ComplicationKind::PowerSpike, &crack, &config)).abs() < 1e-12);
+        assert!(second < minute / 50.0);
+    }
+
+    #[test]
+    fn test_effects() {
+        let config = ComplicationConfig::default();
+        let now = Utc::now();
+        let end_time = now + chrono::Duration::minutes(10);
+
+        assert_eq!(
+            apply(ComplicationKind::FirewallUpdate, now, end_time, &config),
+            Some(end_time + chrono::Duration::minutes(5))
+        );
+        assert_eq!(
+            apply(ComplicationKind::LuckyExploit, now, end_time, &config),
+            Some(now + chrono::Duration::minutes(5))
+        );
+        assert_eq!(apply(ComplicationKind::PowerSpike, now, end_time, &config), None);
+    }
+}
@@ -20,6 +20,69 @@ pub struct GameConfig {
     pub network: NetworkConfig,
     pub missions: MissionConfig,
     pub clans: ClanConfig,
+    #[serde(default)]
+    pub doom: DoomConfig,
+    #[serde(default)]
+    pub detection: DetectionConfig,
+    #[serde(default)]
+    pub stealth: StealthConfig,
+    #[serde(default)]
+    pub complications: ComplicationConfig,
+    #[serde(default)]
+    pub ip_reset: IpResetConfig,
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    #[serde(default)]
+    pub bounty: BountyConfig,
+    #[serde(default)]
+    pub tutorial: TutorialConfig,
+    #[serde(default)]
+    pub contracts: ContractConfig,
+    #[serde(default)]
+    pub referrals: ReferralConfig,
+    #[serde(default)]
+    pub hosting: HostingConfig,
+    #[serde(default)]
+    pub reports: ReportConfig,
+    #[serde(default)]
+    pub entitlements: EntitlementConfig,
+    #[serde(default)]
+    pub banking: BankingConfig,
+    #[serde(default)]
+    pub honeypot: HoneypotConfig,
+    #[serde(default)]
+    pub piracy: PiracyConfig,
+    #[serde(default)]
+    pub clan_server: ClanServerConfig,
+    #[serde(default)]
+    pub clan_chat: ClanChatConfig,
+    #[serde(default)]
+    pub mission_gen: MissionGenConfig,
+    #[serde(default)]
+    pub account_gating: AccountGatingConfig,
+    #[serde(default)]
+    pub cancellation: CancellationConfig,
+    #[serde(default)]
+    pub server_browser: ServerBrowserConfig,
+    #[serde(default)]
+    pub hardware_wear: HardwareWearConfig,
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
+    #[serde(default)]
+    pub terminal: TerminalConfig,
+    #[serde(default)]
+    pub puzzles: PuzzleConfig,
+    #[serde(default)]
+    pub reservations: ReservationConfig,
+    #[serde(default)]
+    pub heist: HeistConfig,
+    #[serde(default)]
+    pub dns: DnsConfig,
+    #[serde(default)]
+    pub username: UsernameConfig,
+    /// The server's custom rules, scaling the values above
+    #[serde(default)]
+    pub rules: crate::rules::GameRules,
 }
 
 impl Default for GameConfig {
@@ -35,6 +98,37 @@ impl Default for GameConfig {
             network: NetworkConfig::default(),
             missions: MissionConfig::default(),
             clans: ClanConfig::default(),
+            doom: DoomConfig::default(),
+            detection: DetectionConfig::default(),
+            stealth: StealthConfig::default(),
+            complications: ComplicationConfig::default(),
+            ip_reset: IpResetConfig::default(),
+            moderation: ModerationConfig::default(),
+            bounty: BountyConfig::default(),
+            tutorial: TutorialConfig::default(),
+            contracts: ContractConfig::default(),
+            referrals: ReferralConfig::default(),
+            hosting: HostingConfig::default(),
+            reports: ReportConfig::default(),
+            entitlements: EntitlementConfig::default(),
+            banking: BankingConfig::default(),
+            honeypot: HoneypotConfig::default(),
+            piracy: PiracyConfig::default(),
+            clan_server: ClanServerConfig::default(),
+            clan_chat: ClanChatConfig::default(),
+            mission_gen: MissionGenConfig::default(),
+            account_gating: AccountGatingConfig::default(),
+            cancellation: CancellationConfig::default(),
+            server_browser: ServerBrowserConfig::default(),
+            hardware_wear: HardwareWearConfig::default(),
+            webhooks: WebhookConfig::default(),
+            terminal: TerminalConfig::default(),
+            puzzles: PuzzleConfig::default(),
+            reservations: ReservationConfig::default(),
+            heist: HeistConfig::default(),
+            dns: DnsConfig::default(),
+            username: UsernameConfig::default(),
+            rules: crate::rules::GameRules::default(),
         }
     }
 }
@@ -331,6 +425,7 @@ impl Default for NetworkConfig {
 
 /// Mission system configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MissionConfig {
     pub difficulty_scaling_factor: Decimal,
     pub reward_scaling_factor: Decimal,
@@ -340,6 +435,19 @@ pub struct MissionConfig {
     pub failure_penalty_percentage: Decimal,
     pub daily_mission_limit: i32,
     pub reputation_requirement_scaling: Decimal,
+    pub repeat_decay_factor: Decimal,
+    pub repeat_decay_floor: Decimal,
+    pub repeat_window_hours: u64,
+    pub template_cooldown_minutes: u64,
+    pub daily_experience_cap: i32,
+    pub daily_money_cap: i32,
+    pub catch_up_bonus_per_level: Decimal,
+    pub catch_up_bonus_max: Decimal,
+    pub coop_max_members: usize,
+    pub coop_min_members: usize,
+    pub coop_equal_share: Decimal,
+    /// Shared progress each completed objective process adds
+    pub coop_progress_per_process: i32,
 }
 
 impl Default for MissionConfig {
@@ -353,6 +461,18 @@ impl Default for MissionConfig {
             failure_penalty_percentage: dec!(0.10), // 10% penalty for failure
             daily_mission_limit: 10,              // 10 missions per day
             reputation_requirement_scaling: dec!(1.1), // Reputation requirements scale 10%
+            repeat_decay_factor: dec!(0.75),      // Each recent repeat of a template pays 25% less
+            repeat_decay_floor: dec!(0.10),       // Farmed templates still pay 10%
+            repeat_window_hours: 24,              // Repeats older than a day are forgiven
+            template_cooldown_minutes: 30,        // Same template at most every 30 minutes
+            daily_experience_cap: 5_000,          // Mission XP per day
+            daily_money_cap: 50_000,              // Mission money per day
+            catch_up_bonus_per_level: dec!(0.02), // +2% per level below the median
+            catch_up_bonus_max: dec!(0.50),       // Catch-up bonus capped at +50%
+            coop_max_members: 5,                  // No co-op template takes more than 5
+            coop_min_members: 2,                  // A co-op mission fails below 2 members
+            coop_equal_share: dec!(0.30),         // 30% of a co-op reward is split evenly
+            coop_progress_per_process: 10,        // 10 objective processes finish a 100-point mission
         }
     }
 }
@@ -385,6 +505,273 @@ impl Default for ClanConfig {
     }
 }
 
+/// Doom virus endgame configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DoomConfig {
+    pub min_level: i32,
+    pub countdown_seconds: i64,
+    pub clue_count: usize,
+    pub max_wrong_guesses: u32,
+    pub instigator_reward: i64,
+    pub defender_reward_pool: i64,
+    pub clue_finder_bonus: Decimal,
+}
+
+impl Default for DoomConfig {
+    fn default() -> Self {
+        Self {
+            min_level: 50,                    // Doom is locked until level 50
+            countdown_seconds: 604_800,       // 1 week, same as the SafeNet doom window
+            clue_count: 4,                    // One clue per IP octet
+            max_wrong_guesses: 3,             // Defenders lose their shot after 3 misses
+            instigator_reward: 10_000_000,    // Paid out when the round resets
+            defender_reward_pool: 5_000_000,  // Split between defenders on disarm
+            clue_finder_bonus: dec!(0.25),    // 25% bonus share per clue found
+        }
+    }
+}
+
+/// Hack attempt detection configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DetectionConfig {
+    pub port_scan_base_chance: f64,
+    pub firewall_weight: f64,
+    pub stealth_weight: f64,
+    pub base_chance: f64,
+    pub rating_margin_scale: f64,
+    pub ids_bonus: f64,
+    pub min_chance: f64,
+    pub max_chance: f64,
+    pub checkpoints_per_process: u32,
+    pub reveal_ip_margin: i32,
+    pub trace_base_seconds: i64,
+    pub trace_seconds_per_stealth: i64,
+    pub trace_seconds_per_bounce: i64,
+    pub trace_min_seconds: i64,
+    /// Seconds to read one hop of a trace route with intact logs
+    pub trace_hop_seconds: i64,
+    /// Extra time per hop at zero integrity, as a multiple of the full-integrity time
+    pub trace_integrity_slowdown: f64,
+    /// Below this integrity a hop cannot be read and the trace breaks
+    pub trace_break_integrity: f64,
+    /// Reputation a defender earns for a completed trace
+    pub trace_reputation: i32,
+    /// Seconds an attacker needs to wipe their trail from one hop
+    pub log_wipe_seconds: i64,
+    /// Log lines one wipe removes
+    pub log_wipe_batch: i64,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            port_scan_base_chance: 0.10,  // Even a bare firewall notices some scans
+            firewall_weight: 0.006,       // +0.6% per firewall level
+            stealth_weight: 0.004,        // -0.4% per stealth point (halved for scans)
+            base_chance: 0.25,            // Chance per checkpoint when rating == stealth
+            rating_margin_scale: 20.0,    // Rating/stealth gap that moves the curve noticeably
+            ids_bonus: 0.10,              // Running IDS adds 10% per checkpoint
+            min_chance: 0.02,
+            max_chance: 0.90,
+            checkpoints_per_process: 4,   // Rolls at 25/50/75/100% progress
+            reveal_ip_margin: 30,         // Rating must beat stealth by 30 to show the IP
+            trace_base_seconds: 300,
+            trace_seconds_per_stealth: 6,
+            trace_seconds_per_bounce: 120,
+            trace_min_seconds: 30,
+            trace_hop_seconds: 90,
+            trace_integrity_slowdown: 2.0, // A half-wiped hop takes twice as long
+            trace_break_integrity: 0.25,
+            trace_reputation: 15,
+            log_wipe_seconds: 45,
+            log_wipe_batch: 2,
+        }
+    }
+}
+
+/// Stealth model: evidence an attacker leaves on a target and when it gets noticed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StealthConfig {
+    pub firewall_evidence_weight: f64,
+    pub risk_scale: f64,
+    pub skill_scale_weight: f64,
+    pub cloak_scale_weight: f64,
+    pub detection_threshold: f64,
+    pub log_delete_base: f64,
+    pub log_delete_per_cleaner_point: f64,
+    pub log_delete_max: f64,
+    pub flag_hours: i64,
+}
+
+impl Default for StealthConfig {
+    fn default() -> Self {
+        Self {
+            firewall_evidence_weight: 1.5,     // Firewall 100 makes every action leave 2.5x evidence
+            risk_scale: 10.0,                  // Evidence at which risk reaches ~63% with no stealth
+            skill_scale_weight: 0.01,          // Stealth skill 100 doubles the scale
+            cloak_scale_weight: 0.015,         // Cloaking software 100 adds another 1.5x
+            detection_threshold: 0.60,         // Defender is notified once risk crosses 60%
+            log_delete_base: 0.50,             // Deleting logs removes half the evidence
+            log_delete_per_cleaner_point: 0.004, // +0.4% per log cleaner effectiveness point
+            log_delete_max: 0.90,              // Some evidence always survives
+            flag_hours: 24,                    // How long a detected IP stays flagged
+        }
+    }
+}
+
+/// Random events during long-running processes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComplicationConfig {
+    pub min_process_seconds: i64,
+    pub delay_fraction: f64,
+    pub speedup_fraction: f64,
+    pub complexity_weight: f64,
+    pub remote_multiplier: f64,
+}
+
+impl Default for ComplicationConfig {
+    fn default() -> Self {
+        Self {
+            min_process_seconds: 300,  // Only processes of 5+ minutes are affected
+            delay_fraction: 0.5,       // A firewall update adds half the remaining time
+            speedup_fraction: 0.5,     // A lucky exploit halves the remaining time
+            complexity_weight: 0.25,   // +25% chance per point of process complexity above 1
+            remote_multiplier: 1.5,    // Processes against another server are riskier
+        }
+    }
+}
+
+/// Paid IP resets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpResetConfig {
+    /// In cents, like bank balances
+    pub cost: i64,
+    pub cooldown_hours: i64,
+}
+
+impl Default for IpResetConfig {
+    fn default() -> Self {
+        Self {
+            cost: 50_000,       // $500
+            cooldown_hours: 24, // One reset a day
+        }
+    }
+}
+
+/// What player-written text is screened for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModerationConfig {
+    /// Words that get text reported and names refused, matched whole after
+    /// folding case and common letter substitutions
+    pub offensive_terms: Vec<String>,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            offensive_terms: he_core::validation::DEFAULT_OFFENSIVE_TERMS.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+}
+
+/// Player-placed bounties
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BountyConfig {
+    /// In cents, like bank balances
+    pub min_reward: i64,
+    pub max_reward: i64,
+    pub min_window_hours: i64,
+    pub max_window_hours: i64,
+    pub default_window_hours: i64,
+    /// Open bounties one player may have placed at once
+    pub max_open_per_placer: usize,
+    /// Younger accounts cannot claim, so fresh alts are useless for collusion
+    pub min_hunter_account_age_hours: i64,
+    /// Money moved between hunter and placer this far back blocks a claim
+    pub transfer_lookback_days: i64,
+}
+
+impl Default for BountyConfig {
+    fn default() -> Self {
+        Self {
+            min_reward: 10_000,          // $100
+            max_reward: 100_000_000,     // $1,000,000
+            min_window_hours: 1,
+            max_window_hours: 168,       // One week
+            default_window_hours: 48,
+            max_open_per_placer: 5,
+            min_hunter_account_age_hours: 72,
+            transfer_lookback_days: 30,
+        }
+    }
+}
+
+/// New-player tutorial
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TutorialConfig {
+    /// In cents, like bank balances
+    pub reward_money: i64,
+    pub reward_experience: i64,
+}
+
+impl Default for TutorialConfig {
+    fn default() -> Self {
+        Self {
+            reward_money: 10_000,    // $100
+            reward_experience: 500,
+        }
+    }
+}
+
+/// Player-posted contracts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContractConfig {
+    pub min_poster_level: i32,
+    /// In cents, like bank balances
+    pub min_reward: i64,
+    pub max_reward: i64,
+    /// Posting fee as a percentage of the reward, never refunded
+    pub fee_percent: i64,
+    pub min_fee: i64,
+    pub min_window_hours: i64,
+    pub max_window_hours: i64,
+    pub default_window_hours: i64,
+    pub max_open_per_poster: usize,
+    pub max_accepted_per_contractor: usize,
+    /// How long a contractor has after accepting before the job reopens
+    pub work_hours: i64,
+    /// How long after fulfilment the poster may dispute
+    pub dispute_window_hours: i64,
+}
+
+impl Default for ContractConfig {
+    fn default() -> Self {
+        Self {
+            min_poster_level: 25,
+            min_reward: 100_000,         // $1,000
+            max_reward: 500_000_000,     // $5,000,000
+            fee_percent: 5,
+            min_fee: 5_000,              // $50
+            min_window_hours: 6,
+            max_window_hours: 336,       // Two weeks
+            default_window_hours: 72,
+            max_open_per_poster: 3,
+            max_accepted_per_contractor: 2,
+            work_hours: 24,
+            dispute_window_hours: 24,
+        }
+    }
+}
+
 /// Load configuration from file or environment
 pub fn load_config() -> Result<GameConfig, Box<dyn std::error::Error>> {
     // Try to load from file first
@@ -440,4 +827,778 @@ mod tests {
         
         assert_eq!(config.hacking.base_success_rate, deserialized.hacking.base_success_rate);
     }
-}
\ No newline at end of file
+}
+
+/// A referral reward, paid once the referred account has both
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferralMilestone {
+    pub level: i32,
+    pub playtime_hours: i64,
+    /// In cents, paid to the referrer
+    pub reward: i64,
+}
+
+/// Referral codes and rewards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReferralConfig {
+    pub code_length: usize,
+    /// In order; each needs the previous one paid
+    pub milestones: Vec<ReferralMilestone>,
+    /// Sign-ups from one network within the window before they are held
+    pub network_burst_limit: usize,
+    pub burst_window_hours: i64,
+    /// Referrers younger than this have their referrals held
+    pub min_referrer_age_hours: i64,
+}
+
+impl Default for ReferralConfig {
+    fn default() -> Self {
+        Self {
+            code_length: 8,
+            milestones: vec![
+                ReferralMilestone { level: 5, playtime_hours: 2, reward: 50_000 },     // $500
+                ReferralMilestone { level: 15, playtime_hours: 10, reward: 200_000 },  // $2,000
+                ReferralMilestone { level: 30, playtime_hours: 40, reward: 750_000 },  // $7,500
+            ],
+            network_burst_limit: 3,
+            burst_window_hours: 24,
+            min_referrer_age_hours: 72,
+        }
+    }
+}
+
+/// Processes launched on servers other than the player's gateway
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostingConfig {
+    /// Share of a hacked server's hardware its hackers may use between them
+    pub hacked_share_percent: i32,
+    /// Processes one player may run on one host at once
+    pub max_processes_per_host: usize,
+}
+
+impl Default for HostingConfig {
+    fn default() -> Self {
+        Self {
+            hacked_share_percent: 50,
+            max_processes_per_host: 5,
+        }
+    }
+}
+
+/// Player reports and the moderation queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    pub max_description_chars: usize,
+    /// Reports one player may have waiting at once
+    pub max_open_per_reporter: usize,
+    /// Priority added per reporter after the first, up to the bonus cap
+    pub priority_per_reporter: i32,
+    pub max_reporter_bonus: usize,
+    /// Hours moderators should answer within, by category
+    pub urgent_sla_hours: i64,
+    pub high_sla_hours: i64,
+    pub normal_sla_hours: i64,
+    /// A dismissed case keeps taking reports of its content this long
+    pub dismissed_dedup_days: i64,
+    /// Longest temporary ban; longer ones are permanent
+    pub max_ban_hours: i64,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            max_description_chars: 500,
+            max_open_per_reporter: 10,
+            priority_per_reporter: 5,
+            max_reporter_bonus: 6,
+            urgent_sla_hours: 4,
+            high_sla_hours: 12,
+            normal_sla_hours: 48,
+            dismissed_dedup_days: 30,
+            max_ban_hours: 24 * 90,
+        }
+    }
+}
+
+/// Premium subscriptions from the payment provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntitlementConfig {
+    /// Premium kept past the paid period while a renewal is retried
+    pub grace_hours: i64,
+    /// How far a webhook signature's timestamp may be from now
+    pub webhook_tolerance_secs: i64,
+}
+
+impl Default for EntitlementConfig {
+    fn default() -> Self {
+        Self {
+            grace_hours: 72,
+            webhook_tolerance_secs: 300,
+        }
+    }
+}
+
+/// NPC banks, transfers between them, thefts and laundering
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BankingConfig {
+    pub max_accounts_per_player: usize,
+    /// In cents, like bank balances
+    pub min_transfer: i64,
+    /// Largest amount one transfer or laundering moves
+    pub max_transfer: i64,
+    /// Most of an account's balance one theft can take, in percent, for
+    /// security tiers 1 to 3
+    pub theft_share_percent: [i64; 3],
+    /// How long a thief must wait before stealing from the same account again
+    pub theft_cooldown_hours: i64,
+    /// Banks from this security tier up log the address a theft came from
+    pub thief_ip_min_tier: i16,
+    pub min_laundering: i64,
+}
+
+impl Default for BankingConfig {
+    fn default() -> Self {
+        Self {
+            max_accounts_per_player: 5,
+            min_transfer: 100,           // $1
+            max_transfer: 100_000_000_000, // $1B
+            theft_share_percent: [100, 50, 20],
+            theft_cooldown_hours: 6,
+            thief_ip_min_tier: 2,
+            min_laundering: 10_000,      // $100
+        }
+    }
+}
+
+/// Honeypot servers and the trace they leave on attackers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HoneypotConfig {
+    /// How long one trip leaves the attacker traced
+    pub traced_hours: i64,
+    /// Repeated trips extend the trace up to this far ahead
+    pub max_traced_hours: i64,
+    /// Reputation lost with the Underground per trip
+    pub reputation_penalty: i32,
+    /// Trips counted together when looking for scripted attacks
+    pub anomaly_window_hours: i64,
+    /// This many trips within the window flag the account
+    pub anomaly_trips: u32,
+}
+
+impl Default for HoneypotConfig {
+    fn default() -> Self {
+        Self {
+            traced_hours: 6,
+            max_traced_hours: 24,
+            reputation_penalty: 10,
+            anomaly_window_hours: 24,
+            anomaly_trips: 3,
+        }
+    }
+}
+
+/// Copy protection on software and the keygens that crack it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PiracyConfig {
+    /// Weakest cracker that can run a keygen
+    pub keygen_min_cracker: f64,
+    /// Keygen time per version point of the locked software, squared and
+    /// divided by the cracker's version
+    pub keygen_secs_per_version: i64,
+    pub keygen_min_secs: i64,
+    /// Chance a stolen locked copy carries its owner's trojan
+    pub trojan_chance: f64,
+}
+
+impl Default for PiracyConfig {
+    fn default() -> Self {
+        Self {
+            keygen_min_cracker: 4.0,
+            keygen_secs_per_version: 900,
+            keygen_min_secs: 300,
+            trojan_chance: 0.25,
+        }
+    }
+}
+
+/// Clan servers, their upgrades and wars fought over them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClanServerConfig {
+    /// Stock CPU (MHz), RAM (MB), HDD (MB) and NET (Mbps)
+    pub base_capacity: [i32; 4],
+    /// Added by each upgrade level, in the same order
+    pub capacity_per_level: [i32; 4],
+    /// Price of each part's first upgrade in cents, doubling per level
+    pub base_upgrade_cost: [i64; 4],
+    pub max_level: i32,
+    pub min_deposit: i64,
+    /// Defense per firewall version point
+    pub defense_per_version: f64,
+    /// Each further firewall counts this percent of the one before it
+    pub defender_falloff_percent: i64,
+    pub max_defenders: usize,
+    pub cpu_defense_percent_per_level: i32,
+    /// Attack per cracker version point, on the same scale as defense
+    pub attack_per_version: f64,
+    /// Damage a strike does when attack and defense are even
+    pub even_damage: i32,
+    pub min_damage: i32,
+    pub max_damage: i32,
+    /// Integrity each server starts a war with
+    pub max_integrity: i32,
+    /// Extra points for taking the other server down
+    pub downed_bonus: i64,
+    pub war_hours: i64,
+    /// How long a member waits between strikes in one war
+    pub attack_cooldown_minutes: i64,
+}
+
+impl Default for ClanServerConfig {
+    fn default() -> Self {
+        Self {
+            base_capacity: [2_000, 2_048, 50_000, 100],
+            capacity_per_level: [1_000, 1_024, 25_000, 50],
+            base_upgrade_cost: [500_000, 400_000, 300_000, 400_000], // $5,000 / $4,000 / $3,000 / $4,000
+            max_level: 10,
+            min_deposit: 100,            // $1
+            defense_per_version: 10.0,
+            defender_falloff_percent: 80,
+            max_defenders: 10,
+            cpu_defense_percent_per_level: 5,
+            attack_per_version: 10.0,
+            even_damage: 10,
+            min_damage: 1,
+            max_damage: 25,
+            max_integrity: 100,
+            downed_bonus: 100,
+            war_hours: 48,
+            attack_cooldown_minutes: 10,
+        }
+    }
+}
+
+/// Procedural mission generation. The rates can be tuned from the
+/// `[missions]` table of the balance file (see [`MissionGenConfig::from_balance`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MissionGenConfig {
+    /// Weight of the easy, medium, hard and expert tiers
+    pub tier_weights: [f64; 4],
+    /// Lowest player level offered each tier
+    pub tier_min_level: [i32; 4],
+    /// Target firewall range of each tier, as fractions of the player's best
+    /// cracker
+    pub tier_firewall_ratio: [(f64, f64); 4],
+    /// Targets whose firewall is over this multiple of the player's cracker
+    /// are impossible
+    pub max_firewall_ratio: f64,
+    /// Weight of data theft, sabotage and heist jobs
+    pub kind_weights: [f64; 3],
+    /// Reward of each tier in cents, before scaling
+    pub base_reward: [i64; 4],
+    pub base_experience: [i64; 4],
+    pub reward_per_objective_percent: i64,
+    pub reward_per_level_percent: i64,
+    /// Money reward varies by up to this fraction either way
+    pub reward_variance: f64,
+    /// Chance of a bonus objective to clear the logs
+    pub cover_tracks_chance: f64,
+    /// Finishing every bonus objective multiplies the money reward by this
+    pub bonus_objective_multiplier: f64,
+    /// Offers kept open for each player
+    pub open_offers: i64,
+    /// Missions generated per player per day
+    pub missions_per_day: i64,
+    /// How long an offer stays open
+    pub offer_hours: i64,
+    pub max_active: i64,
+    /// Recent missions whose kinds and targets are avoided
+    pub variety_window: usize,
+    /// Each recent mission of a kind multiplies its weight by this
+    pub variety_penalty: f64,
+    /// Plans tried before generation gives up
+    pub attempts: usize,
+    pub max_objectives: usize,
+}
+
+impl Default for MissionGenConfig {
+    fn default() -> Self {
+        Self {
+            tier_weights: [0.4, 0.35, 0.2, 0.05],
+            tier_min_level: [1, 5, 15, 30],
+            tier_firewall_ratio: [(0.0, 0.6), (0.6, 0.9), (0.9, 1.1), (1.1, 1.4)],
+            max_firewall_ratio: 1.5,
+            kind_weights: [1.0, 1.0, 0.5],
+            base_reward: [50_000, 150_000, 400_000, 1_000_000], // $500 / $1,500 / $4,000 / $10,000
+            base_experience: [50, 150, 400, 1_000],
+            reward_per_objective_percent: 10,
+            reward_per_level_percent: 2,
+            reward_variance: 0.25,
+            cover_tracks_chance: 0.5,
+            bonus_objective_multiplier: 1.5,
+            open_offers: 3,
+            missions_per_day: 5,
+            offer_hours: 6,
+            max_active: 3,
+            variety_window: 5,
+            variety_penalty: 0.5,
+            attempts: 5,
+            max_objectives: 8,
+        }
+    }
+}
+
+/// The `[missions]` keys of the balance file generation reads
+#[derive(Debug, Default, Deserialize)]
+struct BalanceMissions {
+    missions_per_day: Option<i64>,
+    mission_refresh_hours: Option<i64>,
+    simultaneous_missions: Option<i64>,
+    open_offers: Option<i64>,
+    easy_weight: Option<f64>,
+    medium_weight: Option<f64>,
+    hard_weight: Option<f64>,
+    expert_weight: Option<f64>,
+    data_theft_weight: Option<f64>,
+    sabotage_weight: Option<f64>,
+    heist_weight: Option<f64>,
+    reward_variance: Option<f64>,
+    bonus_objective_multiplier: Option<f64>,
+    cover_tracks_chance: Option<f64>,
+    variety_window: Option<usize>,
+    variety_penalty: Option<f64>,
+    generation_attempts: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BalanceFile {
+    #[serde(default)]
+    missions: BalanceMissions,
+}
+
+impl MissionGenConfig {
+    /// Defaults overridden by the `[missions]` table of a balance file such
+    /// as `game-balance.toml`
+    pub fn from_balance(contents: &str) -> Result<Self, toml::de::Error> {
+        let missions = toml::from_str::<BalanceFile>(contents)?.missions;
+        let mut config = Self::default();
+
+        let set = |value: Option<f64>, field: &mut f64| {
+            if let Some(value) = value {
+                *field = value;
+            }
+        };
+        set(missions.easy_weight, &mut config.tier_weights[0]);
+        set(missions.medium_weight, &mut config.tier_weights[1]);
+        set(missions.hard_weight, &mut config.tier_weights[2]);
+        set(missions.expert_weight, &mut config.tier_weights[3]);
+        set(missions.data_theft_weight, &mut config.kind_weights[0]);
+        set(missions.sabotage_weight, &mut config.kind_weights[1]);
+        set(missions.heist_weight, &mut config.kind_weights[2]);
+        set(missions.reward_variance, &mut config.reward_variance);
+        set(missions.bonus_objective_multiplier, &mut config.bonus_objective_multiplier);
+        set(missions.cover_tracks_chance, &mut config.cover_tracks_chance);
+        set(missions.variety_penalty, &mut config.variety_penalty);
+
+        config.missions_per_day = missions.missions_per_day.unwrap_or(config.missions_per_day);
+        config.offer_hours = missions.mission_refresh_hours.unwrap_or(config.offer_hours);
+        config.max_active = missions.simultaneous_missions.unwrap_or(config.max_active);
+        config.open_offers = missions.open_offers.unwrap_or(config.open_offers);
+        config.variety_window = missions.variety_window.unwrap_or(config.variety_window);
+        config.attempts = missions.generation_attempts.unwrap_or(config.attempts);
+        Ok(config)
+    }
+}
+
+/// The level and account age that lift one new-account restriction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gate {
+    pub min_level: i32,
+    pub min_age_hours: i64,
+}
+
+/// New-account restrictions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountGatingConfig {
+    pub trading: Gate,
+    pub chat: Gate,
+    pub bounty_placement: Gate,
+    /// Messages a chat-restricted account may send per window
+    pub restricted_chat_messages: i64,
+    pub restricted_chat_window_minutes: i64,
+    pub restricted_chat_max_length: usize,
+}
+
+impl Default for AccountGatingConfig {
+    fn default() -> Self {
+        Self {
+            trading: Gate { min_level: 5, min_age_hours: 72 },
+            chat: Gate { min_level: 2, min_age_hours: 24 },
+            bounty_placement: Gate { min_level: 10, min_age_hours: 168 },
+            restricted_chat_messages: 5,
+            restricted_chat_window_minutes: 10,
+            restricted_chat_max_length: 200,
+        }
+    }
+}
+
+/// Refunds for cancelled processes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CancellationConfig {
+    /// Share of the unused part of the cost refunded; the rest is the
+    /// cancellation fee
+    pub refund_percent: i64,
+    /// Refunds below this many cents are not paid
+    pub min_refund: i64,
+}
+
+impl Default for CancellationConfig {
+    fn default() -> Self {
+        Self {
+            refund_percent: 90,
+            min_refund: 100,
+        }
+    }
+}
+
+/// Server browser and range scans
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerBrowserConfig {
+    pub page_size: i64,
+    pub max_page_size: i64,
+    /// Browser pages a player may load per minute
+    pub browse_per_minute: i64,
+    /// Range scans a player may run per hour
+    pub range_scans_per_hour: i64,
+    /// Widest range a scan covers, as the shortest prefix length (`/24` is
+    /// 256 addresses)
+    pub min_range_prefix: u8,
+    /// Half-width of the band unscanned security details are shown in, as a
+    /// share of the real value
+    pub fuzz_percent: f64,
+}
+
+impl Default for ServerBrowserConfig {
+    fn default() -> Self {
+        Self {
+            page_size: 25,
+            max_page_size: 100,
+            browse_per_minute: 30,
+            range_scans_per_hour: 10,
+            min_range_prefix: 24,
+            fuzz_percent: 25.0,
+        }
+    }
+}
+
+/// Hardware wear, failures and repairs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HardwareWearConfig {
+    /// Share of a component's capacity in use above which it wears
+    pub heavy_load: f64,
+    /// Wear per hour at full load on a standard part; 1.0 is worn out
+    pub wear_per_hour: f64,
+    pub premium_wear_factor: f64,
+    pub industrial_wear_factor: f64,
+    /// Wear below which a component never fails
+    pub failure_onset: f64,
+    /// Chance a fully worn component fails per completed process using it
+    pub max_failure_chance: f64,
+    /// Capacity a failed component keeps, in percent
+    pub degraded_percent: i64,
+    pub worn_at: f64,
+    pub critical_at: f64,
+    /// New standard parts, in cents
+    pub cpu_cost: i64,
+    pub ram_cost: i64,
+    pub hdd_cost: i64,
+    pub net_cost: i64,
+    pub premium_price_factor: f64,
+    pub industrial_price_factor: f64,
+    /// A repair costs this share of the part's price at most
+    pub repair_share: f64,
+    /// Share of that paid however little the part is worn
+    pub repair_base_share: f64,
+    /// Multiplier on the repair cost of a failed part
+    pub failed_surcharge: f64,
+    /// Wear below which there is nothing to repair
+    pub min_repair_wear: f64,
+    pub repair_base_secs: i64,
+}
+
+impl Default for HardwareWearConfig {
+    fn default() -> Self {
+        Self {
+            heavy_load: 0.7,
+            wear_per_hour: 0.02,
+            premium_wear_factor: 0.6,
+            industrial_wear_factor: 0.35,
+            failure_onset: 0.5,
+            max_failure_chance: 0.15,
+            degraded_percent: 40,
+            worn_at: 0.6,
+            critical_at: 0.85,
+            cpu_cost: 200_000,
+            ram_cost: 100_000,
+            hdd_cost: 80_000,
+            net_cost: 120_000,
+            premium_price_factor: 3.0,
+            industrial_price_factor: 8.0,
+            repair_share: 0.5,
+            repair_base_share: 0.2,
+            failed_surcharge: 1.5,
+            min_repair_wear: 0.1,
+            repair_base_secs: 600,
+        }
+    }
+}
+
+/// Outbound clan and player webhooks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub max_per_owner: i64,
+    pub max_url_len: usize,
+    /// Delivery attempts before an event is given up on
+    pub max_attempts: i32,
+    pub backoff_base_secs: i64,
+    pub backoff_max_secs: i64,
+    /// Failed attempts in a row, across events, that disable a webhook
+    pub disable_after_failures: i32,
+    pub timeout_secs: u64,
+    /// Deliveries shown to the owner
+    pub log_limit: i64,
+    pub keep_deliveries_days: i64,
+    /// Experience a login hack must earn to count as big
+    pub big_hack_experience: i64,
+    /// Bounties, in cents, a login hack must claim to count as big
+    pub big_hack_bounty: i64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_per_owner: 5,
+            max_url_len: 512,
+            max_attempts: 8,
+            backoff_base_secs: 30,
+            backoff_max_secs: 3600,
+            disable_after_failures: 20,
+            timeout_secs: 10,
+            log_limit: 50,
+            keep_deliveries_days: 7,
+            big_hack_experience: 100,
+            big_hack_bounty: 100_000,
+        }
+    }
+}
+
+/// Terminal aliases, command chains and cooldowns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerminalConfig {
+    pub max_line_len: usize,
+    pub max_alias_name_len: usize,
+    /// Aliases every player has, and one more every `levels_per_alias` levels
+    pub base_aliases: usize,
+    pub levels_per_alias: i32,
+    pub max_aliases: usize,
+    /// Commands one line may chain with `&&`, growing the same way
+    pub base_chain_len: usize,
+    pub levels_per_chain_stage: i32,
+    pub max_chain_len: usize,
+    /// Between two runs of the same command by a player
+    pub command_cooldown_ms: i64,
+    /// Commands with their own cooldown
+    pub cooldown_overrides_ms: Vec<(String, i64)>,
+    pub completion_limit: i64,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            max_line_len: 512,
+            max_alias_name_len: 24,
+            base_aliases: 3,
+            levels_per_alias: 5,
+            max_aliases: 20,
+            base_chain_len: 2,
+            levels_per_chain_stage: 10,
+            max_chain_len: 6,
+            command_cooldown_ms: 1000,
+            cooldown_overrides_ms: Vec::new(),
+            completion_limit: 20,
+        }
+    }
+}
+
+/// Storyline puzzles
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PuzzleConfig {
+    pub max_answer_len: usize,
+    /// Wait after a wrong answer, doubling with each one in a row
+    pub wrong_answer_cooldown_secs: i64,
+    pub max_cooldown_secs: i64,
+}
+
+impl Default for PuzzleConfig {
+    fn default() -> Self {
+        Self {
+            max_answer_len: 128,
+            wrong_answer_cooldown_secs: 30,
+            max_cooldown_secs: 3600,
+        }
+    }
+}
+
+/// Short-lived holds on money, disk space and process slots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReservationConfig {
+    pub default_ttl_secs: i64,
+    pub min_ttl_secs: i64,
+    pub max_ttl_secs: i64,
+    /// Holds one player may have open at once
+    pub max_open: i64,
+    pub max_purpose_len: usize,
+}
+
+impl Default for ReservationConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl_secs: 120,
+            min_ttl_secs: 10,
+            max_ttl_secs: 900,
+            max_open: 5,
+            max_purpose_len: 64,
+        }
+    }
+}
+
+/// Bank heists: timed raid windows on high-tier NPC banks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeistConfig {
+    /// Banks from this security tier up are raided
+    pub min_bank_tier: i16,
+    pub window_minutes: i64,
+    /// Money in a vault when its window opens, in cents
+    pub vault: i64,
+    /// Part of the vault one crew gets away with, in hundredths of a percent
+    pub loot_bps: i64,
+    /// Players one role takes in a crew
+    pub max_per_role: usize,
+    /// How long a DDoS keeps the firewall down for the vault to be cracked
+    pub firewall_down_secs: i64,
+    /// How long after the transfer the logs must be wiped before the crew
+    /// is traced
+    pub trace_secs: i64,
+    /// Loot weight of each role, in the order of `HeistRole::ALL`
+    pub role_weights: [i64; 4],
+}
+
+impl Default for HeistConfig {
+    fn default() -> Self {
+        Self {
+            min_bank_tier: 3,
+            window_minutes: 60,
+            vault: 50_000_000,           // $500,000
+            loot_bps: 2_000,
+            max_per_role: 2,
+            firewall_down_secs: 300,
+            trace_secs: 180,
+            role_weights: [20, 35, 25, 20],
+        }
+    }
+}
+
+/// Whois registry and hostname resolution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    /// The registry server; every lookup is logged there
+    pub registry_ip: String,
+    /// Paid once to register a hostname, in cents
+    pub registration_fee: i64,
+    /// Hostnames one player may hold
+    pub max_names_per_player: usize,
+    /// Top-level domains players may register under
+    pub tlds: Vec<String>,
+    /// Software a player needs for address-to-hostname lookups
+    pub reverse_lookup_software: String,
+    pub reverse_lookup_min_version: f64,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            registry_ip: crate::tutorial::WHOIS_IP.to_string(),
+            registration_fee: 25_000,    // $250
+            max_names_per_player: 3,
+            tlds: ["com", "net", "org", "io"].map(str::to_string).to_vec(),
+            reverse_lookup_software: "analyzer".to_string(),
+            reverse_lookup_min_version: 1.0,
+        }
+    }
+}
+
+/// Private clan chat rooms
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClanChatConfig {
+    /// Messages older than this are deleted unless pinned
+    pub history_days: i64,
+    /// Pinned messages one channel may hold
+    pub max_pins: usize,
+    pub max_message_length: usize,
+    /// Most messages returned per request
+    pub page_size: i64,
+}
+
+impl Default for ClanChatConfig {
+    fn default() -> Self {
+        Self {
+            history_days: 30,
+            max_pins: 10,
+            max_message_length: 500,
+            page_size: 100,
+        }
+    }
+}
+
+/// Username changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsernameConfig {
+    pub min_length: usize,
+    /// `users.login` is a VARCHAR(15)
+    pub max_length: usize,
+    /// Days between two changes
+    pub cooldown_days: i64,
+    /// Days a given-up name stays reserved for the player who gave it up
+    pub reservation_days: i64,
+}
+
+impl Default for UsernameConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 3,
+            max_length: 15,
+            cooldown_days: 30,
+            reservation_days: 90,
+        }
+    }
+}
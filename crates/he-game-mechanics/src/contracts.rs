@@ -0,0 +1,328 @@
+//! Player contracts: jobs posted by players for other players
+//!
+//! A high-level player posts a contract against a file on someone else's
+//! server, to steal it or to delete it, and escrows the reward. A contractor
+//! accepts it and has a limited time to do the job. The contract is
+//! fulfilled only when the server completes the contractor's matching
+//! download or delete of that very file, never on the contractor's word.
+//!
+//! The reward is held through a dispute window after fulfilment, in which
+//! the poster can send the contract to moderators, and paid out when it
+//! closes. Posting costs a fee on top of the reward that is never refunded,
+//! which takes money out of the economy.
+
+use crate::config::ContractConfig;
+use crate::process::ProcessType;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractKind {
+    StealFile,
+    DeleteFile,
+}
+
+impl ContractKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContractKind::StealFile => "steal_file",
+            ContractKind::DeleteFile => "delete_file",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "steal_file" => Some(ContractKind::StealFile),
+            "delete_file" => Some(ContractKind::DeleteFile),
+            _ => None,
+        }
+    }
+
+    /// The process whose completion proves the job was done
+    pub fn proof_process(&self) -> ProcessType {
+        match self {
+            ContractKind::StealFile => ProcessType::Download,
+            ContractKind::DeleteFile => ProcessType::Delete,
+        }
+    }
+
+    /// The kind of contract a completed process can fulfil, if any. The
+    /// process must also have targeted the contract's server and file.
+    pub fn proved_by(process_type: &ProcessType) -> Option<Self> {
+        [ContractKind::StealFile, ContractKind::DeleteFile]
+            .into_iter()
+            .find(|kind| kind.proof_process() == *process_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    Open,
+    Accepted,
+    /// Done, reward held through the dispute window
+    Fulfilled,
+    /// Waiting on a moderator
+    Disputed,
+    Paid,
+    Refunded,
+    /// Nobody fulfilled it in time; the reward went back
+    Expired,
+    Cancelled,
+}
+
+impl ContractStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContractStatus::Open => "open",
+            ContractStatus::Accepted => "accepted",
+            ContractStatus::Fulfilled => "fulfilled",
+            ContractStatus::Disputed => "disputed",
+            ContractStatus::Paid => "paid",
+            ContractStatus::Refunded => "refunded",
+            ContractStatus::Expired => "expired",
+            ContractStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "open" => Some(ContractStatus::Open),
+            "accepted" => Some(ContractStatus::Accepted),
+            "fulfilled" => Some(ContractStatus::Fulfilled),
+            "disputed" => Some(ContractStatus::Disputed),
+            "paid" => Some(ContractStatus::Paid),
+            "refunded" => Some(ContractStatus::Refunded),
+            "expired" => Some(ContractStatus::Expired),
+            "cancelled" => Some(ContractStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// How a moderator settles a dispute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    PayContractor,
+    RefundPoster,
+}
+
+/// The non-refundable fee for posting `reward`
+pub fn posting_fee(reward: i64, config: &ContractConfig) -> i64 {
+    (reward * config.fee_percent / 100).max(config.min_fee)
+}
+
+/// Why a contract cannot be posted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum PostDenied {
+    LevelTooLow { min_level: i32 },
+    FileNotFound,
+    /// The file is on the poster's own server
+    OwnFile,
+    RewardOutOfRange { min: i64, max: i64 },
+    WindowOutOfRange { min_hours: i64, max_hours: i64 },
+    TooManyOpen { max: usize },
+    InsufficientFunds { needed: i64, balance: i64 },
+}
+
+impl PostDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            PostDenied::LevelTooLow { min_level } => format!("You must be level {} to post contracts", min_level),
+            PostDenied::FileNotFound => "No such file on that server".to_string(),
+            PostDenied::OwnFile => "You cannot post a contract against your own server".to_string(),
+            PostDenied::RewardOutOfRange { min, max } => {
+                format!("Rewards must be between {} and {}", dollars(*min), dollars(*max))
+            }
+            PostDenied::WindowOutOfRange { min_hours, max_hours } => {
+                format!("Contracts must run between {} and {} hours", min_hours, max_hours)
+            }
+            PostDenied::TooManyOpen { max } => format!("You can have at most {} contracts posted at once", max),
+            PostDenied::InsufficientFunds { needed, .. } => {
+                format!("You need {} in your bank account to post this contract", dollars(*needed))
+            }
+        }
+    }
+}
+
+fn dollars(cents: i64) -> String {
+    format!("${}.{:02}", cents / 100, cents % 100)
+}
+
+/// Whether a player may post a contract with this reward and window. File
+/// checks are left to the caller, which knows the server.
+pub fn check_post(
+    level: i32,
+    reward: i64,
+    window_hours: i64,
+    open_posted: usize,
+    balance: i64,
+    config: &ContractConfig,
+) -> Result<(), PostDenied> {
+    if level < config.min_poster_level {
+        return Err(PostDenied::LevelTooLow { min_level: config.min_poster_level });
+    }
+
+    if !(config.min_reward..=config.max_reward).contains(&reward) {
+        return Err(PostDenied::RewardOutOfRange { min: config.min_reward, max: config.max_reward });
+    }
+
+    if !(config.min_window_hours..=config.max_window_hours).contains(&window_hours) {
+        return Err(PostDenied::WindowOutOfRange {
+            min_hours: config.min_window_hours,
+            max_hours: config.max_window_hours,
+        });
+    }
+
+    if open_posted >= config.max_open_per_poster {
+        return Err(PostDenied::TooManyOpen { max: config.max_open_per_poster });
+    }
+
+    let needed = reward + posting_fee(reward, config);
+    if balance < needed {
+        return Err(PostDenied::InsufficientFunds { needed, balance });
+    }
+
+    Ok(())
+}
+
+/// Why a contract cannot be accepted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum AcceptDenied {
+    NotFound,
+    NotOpen,
+    OwnContract,
+    /// Players cannot take jobs against their own servers
+    Target,
+    TooManyAccepted { max: usize },
+}
+
+impl AcceptDenied {
+    pub fn message(&self) -> String {
+        match self {
+            AcceptDenied::NotFound => "Contract not found".to_string(),
+            AcceptDenied::NotOpen => "This contract is no longer open".to_string(),
+            AcceptDenied::OwnContract => "You cannot accept your own contract".to_string(),
+            AcceptDenied::Target => "This contract is against you".to_string(),
+            AcceptDenied::TooManyAccepted { max } => format!("You can work at most {} contracts at once", max),
+        }
+    }
+}
+
+pub fn check_accept(
+    status: ContractStatus,
+    poster_id: i64,
+    target_owner_id: i64,
+    contractor_id: i64,
+    accepted_by_contractor: usize,
+    config: &ContractConfig,
+) -> Result<(), AcceptDenied> {
+    if status != ContractStatus::Open {
+        return Err(AcceptDenied::NotOpen);
+    }
+    if contractor_id == poster_id {
+        return Err(AcceptDenied::OwnContract);
+    }
+    if contractor_id == target_owner_id {
+        return Err(AcceptDenied::Target);
+    }
+    if accepted_by_contractor >= config.max_accepted_per_contractor {
+        return Err(AcceptDenied::TooManyAccepted { max: config.max_accepted_per_contractor });
+    }
+    Ok(())
+}
+
+/// Why a contract cannot be disputed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DisputeDenied {
+    NotFound,
+    NotPoster,
+    NotFulfilled,
+    WindowClosed,
+}
+
+impl DisputeDenied {
+    pub fn message(&self) -> String {
+        match self {
+            DisputeDenied::NotFound => "Contract not found".to_string(),
+            DisputeDenied::NotPoster => "Only the poster can dispute a contract".to_string(),
+            DisputeDenied::NotFulfilled => "Only fulfilled contracts can be disputed".to_string(),
+            DisputeDenied::WindowClosed => "The dispute window has closed".to_string(),
+        }
+    }
+}
+
+pub fn check_dispute(
+    status: ContractStatus,
+    poster_id: i64,
+    user_id: i64,
+    fulfilled_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    config: &ContractConfig,
+) -> Result<(), DisputeDenied> {
+    if user_id != poster_id {
+        return Err(DisputeDenied::NotPoster);
+    }
+    let (ContractStatus::Fulfilled, Some(fulfilled_at)) = (status, fulfilled_at) else {
+        return Err(DisputeDenied::NotFulfilled);
+    };
+    if now >= fulfilled_at + Duration::hours(config.dispute_window_hours) {
+        return Err(DisputeDenied::WindowClosed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_post_and_fee() {
+        let config = ContractConfig::default();
+        let reward = config.min_reward;
+        let needed = reward + posting_fee(reward, &config);
+        let level = config.min_poster_level;
+
+        assert!(posting_fee(reward, &config) >= config.min_fee);
+        assert_eq!(check_post(level, reward, 24, 0, needed, &config), Ok(()));
+        assert!(matches!(check_post(level - 1, reward, 24, 0, needed, &config), Err(PostDenied::LevelTooLow { .. })));
+        assert_eq!(
+            check_post(level, reward, 24, 0, reward, &config),
+            Err(PostDenied::InsufficientFunds { needed, balance: reward })
+        );
+        assert!(matches!(
+            check_post(level, reward, 24, config.max_open_per_poster, needed, &config),
+            Err(PostDenied::TooManyOpen { .. })
+        ));
+    }
+
+    #[test]
+    fn test_accept_proof_and_dispute() {
+        let config = ContractConfig::default();
+        assert_eq!(check_accept(ContractStatus::Open, 1, 2, 3, 0, &config), Ok(()));
+        assert_eq!(check_accept(ContractStatus::Open, 1, 2, 2, 0, &config), Err(AcceptDenied::Target));
+        assert_eq!(check_accept(ContractStatus::Accepted, 1, 2, 3, 0, &config), Err(AcceptDenied::NotOpen));
+
+        assert_eq!(ContractKind::proved_by(&ProcessType::Download), Some(ContractKind::StealFile));
+        assert_eq!(ContractKind::proved_by(&ProcessType::Delete), Some(ContractKind::DeleteFile));
+        assert_eq!(ContractKind::proved_by(&ProcessType::Crack), None);
+
+        let fulfilled = Utc::now();
+        let late = fulfilled + Duration::hours(config.dispute_window_hours);
+        assert_eq!(check_dispute(ContractStatus::Fulfilled, 1, 1, Some(fulfilled), fulfilled, &config), Ok(()));
+        assert_eq!(
+            check_dispute(ContractStatus::Fulfilled, 1, 1, Some(fulfilled), late, &config),
+            Err(DisputeDenied::WindowClosed)
+        );
+        assert_eq!(
+            check_dispute(ContractStatus::Fulfilled, 1, 3, Some(fulfilled), fulfilled, &config),
+            Err(DisputeDenied::NotPoster)
+        );
+    }
+}
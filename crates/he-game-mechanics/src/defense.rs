@@ -1,15 +1,19 @@
 //! Defense system mechanics
 //! 
 //! Implements firewall strength, intrusion detection, security ratings,
-//! and detection algorithms from the original HackerExperience game.
+//! and detection algorithms from the original HackerExperience game, plus the
+//! stealth model: the evidence an attacker leaves behind over a whole intrusion.
 
 use crate::{PlayerState, HardwareSpecs, SoftwareInstance, Result, GameMechanicsError};
-use crate::config::DefenseConfig;
+use crate::config::{DefenseConfig, StealthConfig};
+use crate::detection::HostileActivity;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use rand::Rng;
 use chrono::{DateTime, Utc, Duration};
 use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use he_core::clock;
 
 /// Defense system state
 #[derive(Debug, Clone)]
@@ -235,7 +239,7 @@ pub fn process_trace_decay(
     traces: &mut Vec<TraceInfo>,
     config: &DefenseConfig,
 ) {
-    let now = Utc::now();
+    let now = clock::now();
     
     // Remove expired traces
     traces.retain(|trace| trace.expires_at > now);
@@ -275,7 +279,7 @@ pub fn block_ip(
     duration_hours: i32,
     reason: String,
 ) {
-    let now = Utc::now();
+    let now = clock::now();
     let blocked_ip = BlockedIP {
         ip_address,
         blocked_at: now,
@@ -289,7 +293,7 @@ pub fn block_ip(
 
 /// Check if IP is blocked
 pub fn is_ip_blocked(blocked_ips: &[BlockedIP], ip_address: &str) -> bool {
-    let now = Utc::now();
+    let now = clock::now();
     
     blocked_ips.iter().any(|blocked| {
         blocked.ip_address == ip_address && blocked.blocked_until > now
@@ -310,7 +314,7 @@ pub fn process_security_event(
         source_ip,
         severity,
         detected,
-        timestamp: Utc::now(),
+        timestamp: clock::now(),
         details,
     };
     
@@ -398,7 +402,7 @@ pub fn apply_attack_damage(
         }
         "log_wipe" => {
             // Clear recent security events
-            let cutoff = Utc::now() - Duration::hours(damage_amount as i64);
+            let cutoff = clock::now() - Duration::hours(damage_amount as i64);
             defense_state.security_events.retain(|e| e.timestamp < cutoff);
         }
         _ => {
@@ -468,6 +472,181 @@ pub fn generate_defense_report(defense_state: &DefenseState) -> HashMap<String,
     report
 }
 
+/// Evidence a single hostile action leaves in the target's logs, before
+/// firewall and stealth modifiers
+fn action_evidence(activity: HostileActivity) -> f64 {
+    match activity {
+        HostileActivity::PortScan => 1.0,
+        HostileActivity::Crack => 3.0,
+        HostileActivity::Download => 2.0,
+        HostileActivity::Upload => 2.5,
+        HostileActivity::LogTampering => 0.5,
+        HostileActivity::DDoS => 6.0,
+    }
+}
+
+/// Attacker side of the stealth model
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StealthProfile {
+    /// Stealth skill, 0-100
+    pub skill: i32,
+    /// Best cloaking software (proxy chain), effectiveness 0-100
+    pub cloak: i32,
+    /// Best log cleaner, effectiveness 0-100
+    pub log_cleaner: i32,
+}
+
+impl StealthProfile {
+    pub fn from_player(player: &PlayerState) -> Self {
+        let best = |software_type: &str| {
+            player.software_installed.iter()
+                .filter(|s| s.software_type == software_type)
+                .map(|s| s.effectiveness)
+                .max()
+                .unwrap_or(0)
+                .clamp(0, 100)
+        };
+
+        Self {
+            skill: player.reputation.get("stealth").copied().unwrap_or(0).clamp(0, 100),
+            cloak: best("proxy_chain"),
+            log_cleaner: best("log_cleaner"),
+        }
+    }
+
+    /// Evidence needed for the same risk; stealth flattens the curve
+    fn risk_scale(&self, config: &StealthConfig) -> f64 {
+        config.risk_scale
+            * (1.0 + self.skill.clamp(0, 100) as f64 * config.skill_scale_weight)
+            * (1.0 + self.cloak.clamp(0, 100) as f64 * config.cloak_scale_weight)
+    }
+
+    /// Share of the evidence a log deletion removes
+    fn log_delete_share(&self, config: &StealthConfig) -> f64 {
+        (config.log_delete_base + self.log_cleaner.clamp(0, 100) as f64 * config.log_delete_per_cleaner_point)
+            .clamp(0.0, config.log_delete_max)
+    }
+}
+
+/// Sent to the defender when an intrusion's risk crosses the threshold
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectionNotice {
+    pub defender_id: u64,
+    pub attacker_id: u64,
+    /// The IP the defender sees: the last bounce, or the attacker's own
+    pub flagged_ip: String,
+    pub activity: HostileActivity,
+    pub risk: f64,
+}
+
+/// Evidence one attacker has left on one defender's server.
+///
+/// Each hostile action adds evidence; the detection risk is
+/// `1 - exp(-evidence / scale)`, where the scale grows with the attacker's
+/// stealth. Deleting logs removes part of the evidence, but once the risk has
+/// crossed the threshold the defender has already been told.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntrusionFootprint {
+    pub attacker_id: u64,
+    pub defender_id: u64,
+    pub bounced_ip: String,
+    pub evidence: f64,
+    pub detected: bool,
+    pub actions: u32,
+}
+
+impl IntrusionFootprint {
+    pub fn new(attacker_id: u64, defender_id: u64, bounced_ip: String) -> Self {
+        Self { attacker_id, defender_id, bounced_ip, evidence: 0.0, detected: false, actions: 0 }
+    }
+
+    /// Current detection risk, 0-1
+    pub fn risk(&self, stealth: &StealthProfile, config: &StealthConfig) -> f64 {
+        1.0 - (-self.evidence.max(0.0) / stealth.risk_scale(config)).exp()
+    }
+
+    /// Record one hostile action against a server with `firewall_level`
+    /// (0-100). Returns a notice the first time the risk crosses the threshold.
+    pub fn record(
+        &mut self,
+        activity: HostileActivity,
+        firewall_level: i32,
+        stealth: &StealthProfile,
+        config: &StealthConfig,
+    ) -> Option<DetectionNotice> {
+        let firewall_factor = 1.0 + firewall_level.clamp(0, 100) as f64 / 100.0 * config.firewall_evidence_weight;
+        self.evidence += action_evidence(activity) * firewall_factor;
+        self.actions += 1;
+
+        let risk = self.risk(stealth, config);
+        if self.detected || risk < config.detection_threshold {
+            return None;
+        }
+
+        self.detected = true;
+        Some(DetectionNotice {
+            defender_id: self.defender_id,
+            attacker_id: self.attacker_id,
+            flagged_ip: self.bounced_ip.clone(),
+            activity,
+            risk,
+        })
+    }
+
+    /// Delete the target's logs. Removes a share of the evidence, then counts
+    /// as log tampering itself, which can still give the attacker away.
+    /// Returns the evidence removed and a notice if tampering crossed the threshold.
+    pub fn delete_logs(
+        &mut self,
+        firewall_level: i32,
+        stealth: &StealthProfile,
+        config: &StealthConfig,
+    ) -> (f64, Option<DetectionNotice>) {
+        let removed = self.evidence * stealth.log_delete_share(config);
+        self.evidence -= removed;
+        let notice = self.record(HostileActivity::LogTampering, firewall_level, stealth, config);
+        (removed, notice)
+    }
+}
+
+/// Flag the attacker's IP on the defender's side after a detection: a trace
+/// the defender can follow and a security event in their log
+pub fn apply_detection_notice(
+    defense_state: &mut DefenseState,
+    notice: &DetectionNotice,
+    config: &StealthConfig,
+) {
+    let now = clock::now();
+    let trace_strength = (notice.risk * 100.0).round() as i32;
+
+    match defense_state.active_traces.iter_mut()
+        .find(|t| t.attacker_ip == notice.flagged_ip && t.trace_type == "stealth_detection") {
+        Some(trace) => {
+            trace.trace_strength = trace.trace_strength.max(trace_strength);
+            trace.expires_at = now + Duration::hours(config.flag_hours);
+        }
+        None => defense_state.active_traces.push(TraceInfo {
+            attacker_ip: notice.flagged_ip.clone(),
+            trace_strength,
+            created_at: now,
+            expires_at: now + Duration::hours(config.flag_hours),
+            trace_type: "stealth_detection".to_string(),
+        }),
+    }
+
+    let mut details = HashMap::new();
+    details.insert("activity".to_string(), format!("{:?}", notice.activity));
+    details.insert("risk".to_string(), format!("{:.2}", notice.risk));
+    process_security_event(
+        &mut defense_state.security_events,
+        "intrusion_detected".to_string(),
+        notice.flagged_ip.clone(),
+        trace_strength / 10,
+        true,
+        details,
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -616,4 +795,85 @@ mod tests {
         assert!(is_ip_blocked(&blocked_ips, "192.168.1.100"));
         assert!(!is_ip_blocked(&blocked_ips, "192.168.1.101"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_noisy_actions_get_detected_once() {
+        let config = StealthConfig::default();
+        let stealth = StealthProfile::default();
+        let mut footprint = IntrusionFootprint::new(1, 2, "10.0.0.5".to_string());
+
+        let notices: Vec<_> = (0..5)
+            .filter_map(|_| footprint.record(HostileActivity::Crack, 80, &stealth, &config))
+            .collect();
+
+        assert_eq!(notices.len(), 1);
+        assert_eq!(notices[0].flagged_ip, "10.0.0.5");
+        assert!(notices[0].risk >= config.detection_threshold);
+        assert!(footprint.detected);
+    }
+
+    #[test]
+    fn test_stealth_flattens_risk_curve() {
+        let config = StealthConfig::default();
+        let actions_until_detected = |stealth: StealthProfile| {
+            let mut footprint = IntrusionFootprint::new(1, 2, "10.0.0.5".to_string());
+            (1..100)
+                .find(|_| footprint.record(HostileActivity::Download, 50, &stealth, &config).is_some())
+                .unwrap()
+        };
+
+        let sloppy = actions_until_detected(StealthProfile::default());
+        let careful = actions_until_detected(StealthProfile { skill: 80, cloak: 60, log_cleaner: 0 });
+        assert!(careful > sloppy);
+    }
+
+    #[test]
+    fn test_log_deletion_reduces_evidence() {
+        let config = StealthConfig::default();
+        let basic = StealthProfile::default();
+        let cleaner = StealthProfile { log_cleaner: 100, ..StealthProfile::default() };
+
+        let mut a = IntrusionFootprint::new(1, 2, "10.0.0.5".to_string());
+        let mut b = a.clone();
+        a.record(HostileActivity::Crack, 0, &basic, &config);
+        b.record(HostileActivity::Crack, 0, &cleaner, &config);
+        let before = a.risk(&basic, &config);
+
+        let (removed_basic, _) = a.delete_logs(0, &basic, &config);
+        let (removed_cleaner, _) = b.delete_logs(0, &cleaner, &config);
+
+        assert!(a.risk(&basic, &config) < before);
+        assert!(removed_cleaner > removed_basic);
+        assert!(b.evidence > 0.0); // Some evidence always survives
+    }
+
+    #[test]
+    fn test_detection_flags_bounced_ip() {
+        let config = StealthConfig::default();
+        let mut defense_state = DefenseState {
+            firewall_strength: 70,
+            ids_active: false,
+            ids_sensitivity: 0,
+            honeypot_active: false,
+            log_monitoring: true,
+            security_rating: 50,
+            active_traces: vec![],
+            blocked_ips: vec![],
+            security_events: vec![],
+        };
+        let notice = DetectionNotice {
+            defender_id: 2,
+            attacker_id: 1,
+            flagged_ip: "10.0.0.5".to_string(),
+            activity: HostileActivity::Crack,
+            risk: 0.7,
+        };
+
+        apply_detection_notice(&mut defense_state, &notice, &config);
+        apply_detection_notice(&mut defense_state, &notice, &config);
+
+        assert_eq!(defense_state.active_traces.len(), 1);
+        assert_eq!(defense_state.active_traces[0].attacker_ip, "10.0.0.5");
+        assert_eq!(defense_state.security_events.len(), 2);
+    }
+}
@@ -0,0 +1,385 @@
+//! Hack attempt detection and defender alerts
+//!
+//! While a hostile process (port scan, crack, download...) runs against a
+//! player's server, the defender gets a detection roll at each progress
+//! checkpoint:
+//! - Port scans are caught by the firewall, so the roll scales with firewall level
+//! - Everything else pits the defender's security rating against the attacker's
+//!   stealth skill
+//!
+//! Once detected, the defender is alerted in real time and can start a
+//! trace to reveal the attacker's IP: the [`crate::trace_route`] mini-game,
+//! or the plain [`TraceAttacker`] timer.
+
+use crate::{Result, GameMechanicsError};
+use crate::config::DetectionConfig;
+use crate::process::ProcessType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Hostile activity a defender can notice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostileActivity {
+    PortScan,
+    Crack,
+    Download,
+    Upload,
+    LogTampering,
+    DDoS,
+}
+
+impl HostileActivity {
+    /// Map a process aimed at another player's server to the activity it represents
+    pub fn from_process(process_type: &ProcessType) -> Option<Self> {
+        match process_type {
+            ProcessType::PortScan | ProcessType::SystemScan | ProcessType::FirewallAnalysis => {
+                Some(HostileActivity::PortScan)
+            }
+            ProcessType::Crack | ProcessType::BruteForce | ProcessType::Decrypt | ProcessType::Hijack => {
+                Some(HostileActivity::Crack)
+            }
+            ProcessType::Download => Some(HostileActivity::Download),
+            ProcessType::Upload | ProcessType::Install => Some(HostileActivity::Upload),
+            ProcessType::HideLog | ProcessType::DeleteLog => Some(HostileActivity::LogTampering),
+            ProcessType::DDoSAttack => Some(HostileActivity::DDoS),
+            _ => None,
+        }
+    }
+
+    /// How noisy the activity is; added to the detection chance
+    fn noise(&self) -> f64 {
+        match self {
+            HostileActivity::PortScan => 0.0,
+            HostileActivity::Crack => 0.10,
+            HostileActivity::Download => 0.0,
+            HostileActivity::Upload => 0.05,
+            HostileActivity::LogTampering => -0.10,
+            HostileActivity::DDoS => 0.40,
+        }
+    }
+}
+
+/// Defender side of a detection roll
+#[derive(Debug, Clone, Copy)]
+pub struct DefenderProfile {
+    /// Firewall level, 0-100 (see `defense::calculate_firewall_strength`)
+    pub firewall_level: i32,
+    /// Security rating, 0-100 (see `defense::calculate_security_rating`)
+    pub security_rating: i32,
+    pub ids_active: bool,
+}
+
+/// Chance that one checkpoint of `activity` is noticed, in [min, max] from config
+pub fn detection_chance(
+    activity: HostileActivity,
+    defender: &DefenderProfile,
+    attacker_stealth: i32,
+    config: &DetectionConfig,
+) -> f64 {
+    let chance = match activity {
+        HostileActivity::PortScan => {
+            config.port_scan_base_chance
+                + defender.firewall_level.clamp(0, 100) as f64 * config.firewall_weight
+                - attacker_stealth.max(0) as f64 * config.stealth_weight / 2.0
+        }
+        _ => {
+            // Logistic curve: equal rating and stealth gives the base chance
+            let margin = (defender.security_rating - attacker_stealth) as f64 / config.rating_margin_scale;
+            let curve = 1.0 / (1.0 + (-margin).exp());
+            config.base_chance * 2.0 * curve + activity.noise()
+        }
+    };
+
+    let ids_bonus = if defender.ids_active { config.ids_bonus } else { 0.0 };
+
+    (chance + ids_bonus).clamp(config.min_chance, config.max_chance)
+}
+
+/// Kind of alert sent to the defender
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// First time this attack was noticed
+    Detected,
+    /// Further progress of an attack already detected
+    InProgress,
+    /// The hostile process finished or was cancelled
+    Ended,
+}
+
+/// Real-time alert for the defender
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DefenderAlert {
+    pub kind: AlertKind,
+    pub activity: HostileActivity,
+    pub process_id: u64,
+    pub defender_id: u64,
+    /// Only revealed when the defender clearly out-classes the attacker
+    pub attacker_ip: Option<String>,
+    pub progress: f32,
+}
+
+/// Watches a single hostile process on behalf of the defender
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostileProcessMonitor {
+    pub process_id: u64,
+    pub defender_id: u64,
+    pub activity: HostileActivity,
+    pub attacker_ip: String,
+    pub chance_per_check: f64,
+    pub reveal_ip: bool,
+    pub detected: bool,
+    checkpoints_seen: u32,
+    checkpoints: u32,
+    ended: bool,
+}
+
+impl HostileProcessMonitor {
+    pub fn new(
+        process_id: u64,
+        defender_id: u64,
+        activity: HostileActivity,
+        attacker_ip: String,
+        defender: &DefenderProfile,
+        attacker_stealth: i32,
+        config: &DetectionConfig,
+    ) -> Self {
+        Self {
+            process_id,
+            defender_id,
+            activity,
+            attacker_ip,
+            chance_per_check: detection_chance(activity, defender, attacker_stealth, config),
+            reveal_ip: defender.security_rating - attacker_stealth >= config.reveal_ip_margin,
+            detected: false,
+            checkpoints_seen: 0,
+            checkpoints: config.checkpoints_per_process.max(1),
+            ended: false,
+        }
+    }
+
+    fn alert(&self, kind: AlertKind, progress: f32) -> DefenderAlert {
+        DefenderAlert {
+            kind,
+            activity: self.activity,
+            process_id: self.process_id,
+            defender_id: self.defender_id,
+            attacker_ip: self.reveal_ip.then(|| self.attacker_ip.clone()),
+            progress,
+        }
+    }
+
+    /// Feed the hostile process' progress (0.0-1.0). Rolls once per checkpoint
+    /// crossed and returns the alerts the defender should receive.
+    pub fn observe<R: Rng>(&mut self, progress: f32, rng: &mut R) -> Vec<DefenderAlert> {
+        let mut alerts = Vec::new();
+        if self.ended {
+            return alerts;
+        }
+
+        let progress = progress.clamp(0.0, 1.0);
+        let reached = (progress * self.checkpoints as f32).floor() as u32;
+
+        while self.checkpoints_seen < reached.min(self.checkpoints) {
+            self.checkpoints_seen += 1;
+            let checkpoint_progress = self.checkpoints_seen as f32 / self.checkpoints as f32;
+
+            if self.detected {
+                alerts.push(self.alert(AlertKind::InProgress, checkpoint_progress));
+            } else if rng.gen::<f64>() < self.chance_per_check {
+                self.detected = true;
+                alerts.push(self.alert(AlertKind::Detected, checkpoint_progress));
+            }
+        }
+
+        alerts
+    }
+
+    /// The hostile process finished or was cancelled
+    pub fn finish(&mut self, progress: f32) -> Option<DefenderAlert> {
+        if self.ended {
+            return None;
+        }
+        self.ended = true;
+        self.detected.then(|| self.alert(AlertKind::Ended, progress.clamp(0.0, 1.0)))
+    }
+}
+
+/// Defensive counter-process that follows the attacker's connection back
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceAttacker {
+    pub defender_id: u64,
+    pub attacker_ip: String,
+    pub duration_seconds: i64,
+    pub elapsed_seconds: i64,
+}
+
+/// Outcome of a finished trace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceOutcome {
+    pub defender_id: u64,
+    pub attacker_ip: String,
+}
+
+impl TraceAttacker {
+    pub const PROCESS_TYPE: &'static str = "trace_attacker";
+
+    /// Start a trace. Only attacks that were actually detected can be traced.
+    pub fn start(
+        monitor: &HostileProcessMonitor,
+        defender_cpu: i32,
+        attacker_stealth: i32,
+        bounces: u32,
+        config: &DetectionConfig,
+    ) -> Result<Self> {
+        if !monitor.detected {
+            return Err(GameMechanicsError::PreconditionFailed(
+                "Attack has not been detected".to_string(),
+            ));
+        }
+        if defender_cpu <= 0 {
+            return Err(GameMechanicsError::InvalidParameter("CPU must be positive".to_string()));
+        }
+
+        Ok(Self {
+            defender_id: monitor.defender_id,
+            attacker_ip: monitor.attacker_ip.clone(),
+            duration_seconds: trace_duration(defender_cpu, attacker_stealth, bounces, config),
+            elapsed_seconds: 0,
+        })
+    }
+
+    pub fn progress(&self) -> f32 {
+        (self.elapsed_seconds as f32 / self.duration_seconds.max(1) as f32).min(1.0)
+    }
+
+    /// Advance the trace; returns the attacker's IP once it completes
+    pub fn advance(&mut self, seconds: i64) -> Option<TraceOutcome> {
+        if self.elapsed_seconds >= self.duration_seconds {
+            return None;
+        }
+        self.elapsed_seconds = (self.elapsed_seconds + seconds.max(0)).min(self.duration_seconds);
+
+        (self.elapsed_seconds >= self.duration_seconds).then(|| TraceOutcome {
+            defender_id: self.defender_id,
+            attacker_ip: self.attacker_ip.clone(),
+        })
+    }
+}
+
+/// Seconds needed to trace an attacker: stealth and bounce hops slow it down,
+/// defender CPU speeds it up
+pub fn trace_duration(defender_cpu: i32, attacker_stealth: i32, bounces: u32, config: &DetectionConfig) -> i64 {
+    let base = config.trace_base_seconds
+        + attacker_stealth.max(0) as i64 * config.trace_seconds_per_stealth
+        + bounces as i64 * config.trace_seconds_per_bounce;
+
+    // 1000 MHz is the reference CPU
+    let cpu_factor = 1000.0 / defender_cpu.max(1) as f64;
+    ((base as f64 * cpu_factor) as i64).max(config.trace_min_seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn defender(firewall_level: i32, security_rating: i32) -> DefenderProfile {
+        DefenderProfile { firewall_level, security_rating, ids_active: false }
+    }
+
+    #[test]
+    fn test_port_scan_scales_with_firewall() {
+        let config = DetectionConfig::default();
+        let weak = detection_chance(HostileActivity::PortScan, &defender(10, 50), 20, &config);
+        let strong = detection_chance(HostileActivity::PortScan, &defender(90, 50), 20, &config);
+        assert!(strong > weak);
+    }
+
+    #[test]
+    fn test_stealth_beats_rating() {
+        let config = DetectionConfig::default();
+        let d = defender(50, 60);
+        let sloppy = detection_chance(HostileActivity::Crack, &d, 10, &config);
+        let stealthy = detection_chance(HostileActivity::Crack, &d, 95, &config);
+        assert!(sloppy > stealthy);
+        assert!(stealthy >= config.min_chance);
+        assert!(sloppy <= config.max_chance);
+    }
+
+    #[test]
+    fn test_monitor_emits_detected_then_progress() {
+        let mut config = DetectionConfig::default();
+        config.min_chance = 1.0;
+        config.max_chance = 1.0;
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let mut monitor = HostileProcessMonitor::new(
+            1, 2, HostileActivity::Crack, "10.0.0.1".to_string(), &defender(50, 90), 10, &config,
+        );
+
+        let alerts = monitor.observe(0.5, &mut rng);
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].kind, AlertKind::Detected);
+        assert_eq!(alerts[1].kind, AlertKind::InProgress);
+        assert_eq!(alerts[0].attacker_ip.as_deref(), Some("10.0.0.1"));
+
+        // Same progress again must not re-roll or re-alert
+        assert!(monitor.observe(0.5, &mut rng).is_empty());
+        assert_eq!(monitor.finish(1.0).unwrap().kind, AlertKind::Ended);
+        assert!(monitor.finish(1.0).is_none());
+    }
+
+    #[test]
+    fn test_undetected_attack_is_silent_and_untraceable() {
+        let mut config = DetectionConfig::default();
+        config.min_chance = 0.0;
+        config.max_chance = 0.0;
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let mut monitor = HostileProcessMonitor::new(
+            1, 2, HostileActivity::Download, "10.0.0.1".to_string(), &defender(50, 20), 80, &config,
+        );
+
+        assert!(monitor.observe(1.0, &mut rng).is_empty());
+        assert!(monitor.finish(1.0).is_none());
+        assert!(TraceAttacker::start(&monitor, 2000, 80, 0, &config).is_err());
+    }
+
+    #[test]
+    fn test_trace_reveals_attacker() {
+        let mut config = DetectionConfig::default();
+        config.min_chance = 1.0;
+        config.max_chance = 1.0;
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let mut monitor = HostileProcessMonitor::new(
+            1, 2, HostileActivity::PortScan, "10.0.0.9".to_string(), &defender(50, 20), 80, &config,
+        );
+        monitor.observe(0.25, &mut rng);
+
+        let mut trace = TraceAttacker::start(&monitor, 1000, 80, 2, &config).unwrap();
+        assert!(trace.advance(trace.duration_seconds - 1).is_none());
+        let outcome = trace.advance(1).unwrap();
+        assert_eq!(outcome.attacker_ip, "10.0.0.9");
+        assert_eq!(trace.progress(), 1.0);
+        assert!(trace.advance(10).is_none());
+    }
+
+    #[test]
+    fn test_trace_duration() {
+        let config = DetectionConfig::default();
+        assert!(trace_duration(1000, 50, 3, &config) > trace_duration(1000, 10, 0, &config));
+        assert!(trace_duration(4000, 50, 3, &config) < trace_duration(1000, 50, 3, &config));
+        assert!(trace_duration(i32::MAX, 0, 0, &config) >= config.trace_min_seconds);
+    }
+
+    #[test]
+    fn test_hostile_activity_mapping() {
+        assert_eq!(HostileActivity::from_process(&ProcessType::PortScan), Some(HostileActivity::PortScan));
+        assert_eq!(HostileActivity::from_process(&ProcessType::BruteForce), Some(HostileActivity::Crack));
+        assert_eq!(HostileActivity::from_process(&ProcessType::Research), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Whois registry and hostname resolution
+//!
+//! The First Whois server runs the game's name registry. NPC servers come
+//! with their hostnames; players register their own for a one-time fee,
+//! each name unique and a few per player. Anyone can resolve a hostname to
+//! its address or ask who registered it. Going the other way, from an
+//! address to its hostnames, takes a log analyzer. Every lookup is logged on
+//! the registry server, so a player who breaks into it sees who was looking
+//! for whom.
+//!
+//! The terminal answers `nslookup` and `whois` itself; the browser resolves
+//! names through the API before it opens a page.
+
+use crate::config::DnsConfig;
+use crate::terminal_grammar::{ArgSpec, CommandSpec};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+pub const NSLOOKUP_COMMAND: &str = "nslookup";
+pub const WHOIS_COMMAND: &str = "whois";
+/// Terminal commands answered by the registry rather than a plugin
+pub const COMMANDS: [&str; 2] = [NSLOOKUP_COMMAND, WHOIS_COMMAND];
+
+const MAX_HOSTNAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+/// Labels no player may register, to keep impersonating the registry hard
+const RESERVED_LABELS: &[&str] = &["whois", "registry", "localhost", "admin", "root"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DnsDenied {
+    InvalidHostname,
+    UnknownTld { allowed: Vec<String> },
+    ReservedHostname,
+    HostnameTaken,
+    NotFound,
+    TooManyNames { max: usize },
+    InsufficientFunds,
+    /// Only players with a server of their own register names
+    NoServer,
+    NotOwner,
+    /// Reverse lookups need this software
+    NeedsSoftware { software: String, min_version: f64 },
+}
+
+impl DnsDenied {
+    pub fn message(&self) -> String {
+        match self {
+            DnsDenied::InvalidHostname => {
+                "Hostnames are dot-separated labels of letters, digits and -".to_string()
+            }
+            DnsDenied::UnknownTld { allowed } => format!("Hostnames must end in .{}", allowed.join(", .")),
+            DnsDenied::ReservedHostname => "That hostname is reserved".to_string(),
+            DnsDenied::HostnameTaken => "That hostname is already registered".to_string(),
+            DnsDenied::NotFound => "No such hostname".to_string(),
+            DnsDenied::TooManyNames { max } => format!("You can hold at most {} hostnames", max),
+            DnsDenied::InsufficientFunds => "Not enough money for the registration fee".to_string(),
+            DnsDenied::NoServer => "You need a server to point the hostname at".to_string(),
+            DnsDenied::NotOwner => "You did not register that hostname".to_string(),
+            DnsDenied::NeedsSoftware { software, min_version } => {
+                format!("Reverse lookups need a {} of version {:.1} or better", software, min_version)
+            }
+        }
+    }
+}
+
+/// What a lookup asks about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    Hostname(String),
+    Address(String),
+}
+
+/// `name` lowercased and without a trailing dot, if it is a hostname under
+/// one of the configured top-level domains
+pub fn normalize(name: &str, config: &DnsConfig) -> Result<String, DnsDenied> {
+    let name = name.trim().trim_end_matches('.').to_ascii_lowercase();
+    if name.is_empty() || name.len() > MAX_HOSTNAME_LEN {
+        return Err(DnsDenied::InvalidHostname);
+    }
+    let labels: Vec<&str> = name.split('.').collect();
+    let valid_label = |label: &&str| {
+        !label.is_empty()
+            && label.len() <= MAX_LABEL_LEN
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    };
+    if labels.len() < 2 || !labels.iter().all(valid_label) {
+        return Err(DnsDenied::InvalidHostname);
+    }
+    let tld = labels[labels.len() - 1];
+    if !config.tlds.iter().any(|allowed| allowed == tld) {
+        return Err(DnsDenied::UnknownTld { allowed: config.tlds.clone() });
+    }
+    Ok(name)
+}
+
+/// A hostname as a player may register it
+pub fn check_registration(name: &str, config: &DnsConfig) -> Result<String, DnsDenied> {
+    let name = normalize(name, config)?;
+    if name.split('.').any(|label| RESERVED_LABELS.contains(&label)) {
+        return Err(DnsDenied::ReservedHostname);
+    }
+    Ok(name)
+}
+
+/// Whether `query` is an address or a hostname
+pub fn parse_query(query: &str, config: &DnsConfig) -> Result<Query, DnsDenied> {
+    match query.trim().parse::<IpAddr>() {
+        Ok(ip) => Ok(Query::Address(ip.to_string())),
+        Err(_) => normalize(query, config).map(Query::Hostname),
+    }
+}
+
+/// Whether a player whose best reverse lookup software is `best_version`
+/// may look up an address's hostnames
+pub fn can_reverse(best_version: Option<f64>, config: &DnsConfig) -> Result<(), DnsDenied> {
+    if best_version.is_some_and(|version| version >= config.reverse_lookup_min_version) {
+        Ok(())
+    } else {
+        Err(DnsDenied::NeedsSoftware {
+            software: config.reverse_lookup_software.clone(),
+            min_version: config.reverse_lookup_min_version,
+        })
+    }
+}
+
+/// Grammar of the registry's terminal commands
+pub fn command_spec(command: &str) -> Option<CommandSpec> {
+    let host = |description: &str| ArgSpec {
+        name: "host".to_string(),
+        description: description.to_string(),
+        ..ArgSpec::default()
+    };
+    match command {
+        NSLOOKUP_COMMAND => Some(CommandSpec {
+            name: NSLOOKUP_COMMAND.to_string(),
+            description: "Resolve a hostname to its address, or an address to its hostnames".to_string(),
+            args: vec![host("Hostname, or an address with a log analyzer installed")],
+            ..CommandSpec::default()
+        }),
+        WHOIS_COMMAND => Some(CommandSpec {
+            name: WHOIS_COMMAND.to_string(),
+            description: "Show who registered a hostname and when".to_string(),
+            args: vec![host("Hostname to look up")],
+            ..CommandSpec::default()
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let config = DnsConfig::default();
+        assert_eq!(normalize("  Shop.Example.COM. ", &config), Ok("shop.example.com".to_string()));
+        assert_eq!(normalize("localhost", &config), Err(DnsDenied::InvalidHostname));
+        assert_eq!(normalize("-bad.com", &config), Err(DnsDenied::InvalidHostname));
+        assert_eq!(normalize("a..com", &config), Err(DnsDenied::InvalidHostname));
+        assert_eq!(normalize("under_score.com", &config), Err(DnsDenied::InvalidHostname));
+        assert!(matches!(normalize("example.xyz", &config), Err(DnsDenied::UnknownTld { .. })));
+
+        assert_eq!(check_registration("whois.example.org", &config), Err(DnsDenied::ReservedHostname));
+        assert_eq!(check_registration("my-box.io", &config), Ok("my-box.io".to_string()));
+    }
+
+    #[test]
+    fn test_queries_and_reverse_gate() {
+        let config = DnsConfig::default();
+        assert_eq!(parse_query("1.2.3.4", &config), Ok(Query::Address("1.2.3.4".to_string())));
+        assert_eq!(parse_query("First.org", &config), Ok(Query::Hostname("first.org".to_string())));
+
+        assert!(matches!(can_reverse(None, &config), Err(DnsDenied::NeedsSoftware { .. })));
+        assert!(can_reverse(Some(0.5), &config).is_err());
+        assert_eq!(can_reverse(Some(1.0), &config), Ok(()));
+        assert!(COMMANDS.iter().all(|command| command_spec(command).is_some()));
+    }
+}
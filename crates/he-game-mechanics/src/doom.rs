@@ -0,0 +1,508 @@
+//! Doom virus endgame mechanics
+//!
+//! Implements the round-ending Doom virus from the original HackerExperience game:
+//! - A long research/build chain gated behind high software versions
+//! - Installation (process action INSTALL_DOOM) starts a world-wide countdown
+//! - Clues about the hosting server are planted in NPC logs for defenders
+//! - Defenders who locate and disarm the virus split a reward pool
+//! - If the countdown expires the round resets and the instigator is rewarded
+
+use crate::{Result, GameMechanicsError};
+use crate::config::DoomConfig;
+use chrono::{DateTime, Duration, Utc};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A single step of the Doom research/build chain
+#[derive(Debug, Clone, Serialize)]
+pub struct DoomResearchStep {
+    pub name: &'static str,
+    pub required_software: &'static str,
+    pub required_version: f32,
+    pub research_hours: i64,
+}
+
+/// Research chain that must be completed, in order, before Doom can be installed.
+///
+/// Each step requires the previous one plus a high-version piece of software,
+/// which keeps the virus out of reach until late in the round.
+pub const DOOM_RESEARCH_CHAIN: &[DoomResearchStep] = &[
+    DoomResearchStep { name: "Polymorphic Core", required_software: "cracker", required_version: 8.0, research_hours: 48 },
+    DoomResearchStep { name: "Kernel Rootkit", required_software: "hider", required_version: 8.0, research_hours: 72 },
+    DoomResearchStep { name: "Distributed Payload", required_software: "ddos", required_version: 9.0, research_hours: 96 },
+    DoomResearchStep { name: "Firewall Bypass Matrix", required_software: "firewall", required_version: 9.5, research_hours: 120 },
+    DoomResearchStep { name: "Doom Compiler", required_software: "cracker", required_version: 10.0, research_hours: 168 },
+];
+
+/// Total research time for the whole chain, in hours
+pub fn total_research_hours() -> i64 {
+    DOOM_RESEARCH_CHAIN.iter().map(|step| step.research_hours).sum()
+}
+
+/// Return the next research step a player can start, or `None` when the chain is complete.
+///
+/// Fails when the player's installed software is below the step requirement.
+pub fn next_research_step(
+    completed_steps: usize,
+    software_versions: &HashMap<String, f32>,
+) -> Result<Option<&'static DoomResearchStep>> {
+    let Some(step) = DOOM_RESEARCH_CHAIN.get(completed_steps) else {
+        return Ok(None);
+    };
+
+    let installed = software_versions.get(step.required_software).copied().unwrap_or(0.0);
+    if installed < step.required_version {
+        return Err(GameMechanicsError::PreconditionFailed(format!(
+            "{} requires {} v{:.1} (installed v{:.1})",
+            step.name, step.required_software, step.required_version, installed
+        )));
+    }
+
+    Ok(Some(step))
+}
+
+/// Lifecycle of a Doom campaign
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DoomPhase {
+    Researching { completed_steps: usize },
+    Countdown { started_at: DateTime<Utc>, detonates_at: DateTime<Utc> },
+    Disarmed { at: DateTime<Utc>, by: i32 },
+    Detonated { at: DateTime<Utc> },
+}
+
+/// A hint about the Doom host planted in an NPC's logs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoomClue {
+    pub npc_ip: String,
+    pub octet_index: usize,
+    pub octet_value: u8,
+    pub log_line: String,
+}
+
+/// Events emitted by the campaign, to be broadcast to every connected player
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DoomEvent {
+    CountdownStarted { instigator_id: i32, detonates_at: DateTime<Utc> },
+    ClueFound { defender_id: i32, npc_ip: String },
+    WrongLocation { defender_id: i32, guesses_left: u32 },
+    Disarmed { defender_id: i32, rewards: Vec<DoomReward> },
+    ServerReset { instigator_id: i32, rewards: Vec<DoomReward> },
+}
+
+/// Money payout produced when a campaign resolves
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoomReward {
+    pub user_id: i32,
+    pub money: i64,
+}
+
+/// Why a Doom action was refused
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DoomDenied {
+    /// The campaign's own rules refused: level, software, phase
+    Refused { message: String },
+    NoCampaign,
+    ResearchRunning { ready_at: DateTime<Utc> },
+    NotYourServer,
+    CountdownRunning,
+    NoCountdown,
+    ServerNotHacked { ip: String },
+}
+
+impl DoomDenied {
+    pub fn message(&self) -> String {
+        match self {
+            DoomDenied::Refused { message } => message.clone(),
+            DoomDenied::NoCampaign => "You are not researching a Doom virus".to_string(),
+            DoomDenied::ResearchRunning { ready_at } => {
+                format!("The current research step finishes at {} UTC", ready_at.format("%Y-%m-%d %H:%M"))
+            }
+            DoomDenied::NotYourServer => "Doom can only be installed on one of your own servers".to_string(),
+            DoomDenied::CountdownRunning => "A Doom virus is already counting down".to_string(),
+            DoomDenied::NoCountdown => "No Doom countdown is running".to_string(),
+            DoomDenied::ServerNotHacked { ip } => format!("You need access to {} first", ip),
+        }
+    }
+}
+
+impl From<GameMechanicsError> for DoomDenied {
+    fn from(e: GameMechanicsError) -> Self {
+        let message = match e {
+            GameMechanicsError::InvalidParameter(message)
+            | GameMechanicsError::InsufficientResources(message)
+            | GameMechanicsError::PreconditionFailed(message)
+            | GameMechanicsError::CalculationError(message)
+            | GameMechanicsError::ConfigurationError(message) => message,
+        };
+        DoomDenied::Refused { message }
+    }
+}
+
+/// A single Doom virus campaign for the current round
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoomCampaign {
+    pub instigator_id: i32,
+    pub host_ip: String,
+    pub phase: DoomPhase,
+    pub clues: Vec<DoomClue>,
+    /// Clue NPCs each defender has already read
+    pub clues_found: HashMap<i32, Vec<String>>,
+    pub wrong_guesses: HashMap<i32, u32>,
+}
+
+impl DoomCampaign {
+    /// Start a new campaign in the research phase
+    pub fn new(instigator_id: i32, player_level: i32, config: &DoomConfig) -> Result<Self> {
+        if player_level < config.min_level {
+            return Err(GameMechanicsError::PreconditionFailed(format!(
+                "Doom requires level {} (current level {})",
+                config.min_level, player_level
+            )));
+        }
+
+        Ok(Self {
+            instigator_id,
+            host_ip: String::new(),
+            phase: DoomPhase::Researching { completed_steps: 0 },
+            clues: Vec::new(),
+            clues_found: HashMap::new(),
+            wrong_guesses: HashMap::new(),
+        })
+    }
+
+    /// Mark the current research step as finished
+    pub fn complete_research_step(&mut self, software_versions: &HashMap<String, f32>) -> Result<()> {
+        let DoomPhase::Researching { completed_steps } = self.phase else {
+            return Err(GameMechanicsError::PreconditionFailed("Doom research already finished".to_string()));
+        };
+
+        if next_research_step(completed_steps, software_versions)?.is_none() {
+            return Err(GameMechanicsError::PreconditionFailed("Doom research already finished".to_string()));
+        }
+
+        self.phase = DoomPhase::Researching { completed_steps: completed_steps + 1 };
+        Ok(())
+    }
+
+    /// Whether the research chain is complete and the virus can be installed
+    pub fn is_ready_to_install(&self) -> bool {
+        matches!(self.phase, DoomPhase::Researching { completed_steps } if completed_steps >= DOOM_RESEARCH_CHAIN.len())
+    }
+
+    /// Install Doom on `host_ip`, plant clues in NPC logs and start the world-wide countdown
+    pub fn install<R: Rng>(
+        &mut self,
+        host_ip: &str,
+        npc_ips: &[String],
+        now: DateTime<Utc>,
+        rng: &mut R,
+        config: &DoomConfig,
+    ) -> Result<DoomEvent> {
+        if !self.is_ready_to_install() {
+            return Err(GameMechanicsError::PreconditionFailed("Doom research chain is not complete".to_string()));
+        }
+
+        let octets = parse_octets(host_ip)?;
+        if npc_ips.len() < config.clue_count {
+            return Err(GameMechanicsError::InvalidParameter(format!(
+                "Need at least {} NPC servers to plant clues",
+                config.clue_count
+            )));
+        }
+
+        let hosts: Vec<&String> = npc_ips.choose_multiple(rng, config.clue_count).collect();
+        self.clues = hosts
+            .into_iter()
+            .enumerate()
+            .map(|(i, npc_ip)| {
+                let octet_index = i % octets.len();
+                DoomClue {
+                    npc_ip: npc_ip.clone(),
+                    octet_index,
+                    octet_value: octets[octet_index],
+                    log_line: format!(
+                        "[unknown] relayed encrypted payload fragment #{} -> *.{}",
+                        octet_index + 1,
+                        octets[octet_index]
+                    ),
+                }
+            })
+            .collect();
+
+        let detonates_at = now + Duration::seconds(config.countdown_seconds);
+        self.host_ip = host_ip.to_string();
+        self.phase = DoomPhase::Countdown { started_at: now, detonates_at };
+
+        Ok(DoomEvent::CountdownStarted { instigator_id: self.instigator_id, detonates_at })
+    }
+
+    /// Record that a defender read the logs of `npc_ip`; returns an event if a clue was there
+    pub fn inspect_npc_log(&mut self, defender_id: i32, npc_ip: &str) -> Option<DoomEvent> {
+        if !matches!(self.phase, DoomPhase::Countdown { .. }) || defender_id == self.instigator_id {
+            return None;
+        }
+
+        self.clues.iter().find(|clue| clue.npc_ip == npc_ip)?;
+
+        let found = self.clues_found.entry(defender_id).or_default();
+        if found.iter().any(|ip| ip == npc_ip) {
+            return None;
+        }
+        found.push(npc_ip.to_string());
+
+        Some(DoomEvent::ClueFound { defender_id, npc_ip: npc_ip.to_string() })
+    }
+
+    /// Defender attempts to disarm Doom at `guessed_ip`
+    pub fn attempt_disarm(
+        &mut self,
+        defender_id: i32,
+        guessed_ip: &str,
+        now: DateTime<Utc>,
+        config: &DoomConfig,
+    ) -> Result<DoomEvent> {
+        let DoomPhase::Countdown { detonates_at, .. } = self.phase else {
+            return Err(GameMechanicsError::PreconditionFailed("No Doom countdown is running".to_string()));
+        };
+
+        if now >= detonates_at {
+            return Err(GameMechanicsError::PreconditionFailed("Doom has already detonated".to_string()));
+        }
+
+        if defender_id == self.instigator_id {
+            return Err(GameMechanicsError::InvalidParameter("The instigator cannot disarm their own virus".to_string()));
+        }
+
+        let misses = self.wrong_guesses.entry(defender_id).or_insert(0);
+        if *misses >= config.max_wrong_guesses {
+            return Err(GameMechanicsError::PreconditionFailed("No disarm attempts left".to_string()));
+        }
+
+        if guessed_ip != self.host_ip {
+            *misses += 1;
+            return Ok(DoomEvent::WrongLocation {
+                defender_id,
+                guesses_left: config.max_wrong_guesses - *misses,
+            });
+        }
+
+        self.phase = DoomPhase::Disarmed { at: now, by: defender_id };
+        Ok(DoomEvent::Disarmed { defender_id, rewards: self.defender_rewards(defender_id, config) })
+    }
+
+    /// Advance the countdown; returns the reset event once the timer expires
+    pub fn tick(&mut self, now: DateTime<Utc>, config: &DoomConfig) -> Option<DoomEvent> {
+        let DoomPhase::Countdown { detonates_at, .. } = self.phase else {
+            return None;
+        };
+
+        if now < detonates_at {
+            return None;
+        }
+
+        self.phase = DoomPhase::Detonated { at: now };
+
+        // Defenders who made progress get a share of the pool as a consolation prize
+        let mut rewards = vec![DoomReward { user_id: self.instigator_id, money: config.instigator_reward }];
+        rewards.extend(self.clue_rewards(config.defender_reward_pool / 2, config));
+
+        Some(DoomEvent::ServerReset { instigator_id: self.instigator_id, rewards })
+    }
+
+    /// Seconds remaining on the countdown, if it is running
+    pub fn seconds_remaining(&self, now: DateTime<Utc>) -> Option<i64> {
+        match self.phase {
+            DoomPhase::Countdown { detonates_at, .. } => Some((detonates_at - now).num_seconds().max(0)),
+            _ => None,
+        }
+    }
+
+    /// Disarming defender takes half the pool, the rest is split by clues found
+    fn defender_rewards(&self, disarmer_id: i32, config: &DoomConfig) -> Vec<DoomReward> {
+        let disarmer_share = config.defender_reward_pool / 2;
+        let mut rewards = self.clue_rewards(config.defender_reward_pool - disarmer_share, config);
+
+        match rewards.iter_mut().find(|r| r.user_id == disarmer_id) {
+            Some(reward) => reward.money += disarmer_share,
+            None => rewards.push(DoomReward { user_id: disarmer_id, money: disarmer_share }),
+        }
+
+        rewards
+    }
+
+    /// Split `pool` between defenders weighted by clues found (with a per-clue bonus)
+    fn clue_rewards(&self, pool: i64, config: &DoomConfig) -> Vec<DoomReward> {
+        let weights: Vec<(i32, Decimal)> = self
+            .clues_found
+            .iter()
+            .map(|(user_id, clues)| {
+                let count = Decimal::from(clues.len());
+                (*user_id, count * (dec!(1.0) + config.clue_finder_bonus * count))
+            })
+            .collect();
+
+        let total: Decimal = weights.iter().map(|(_, w)| *w).sum();
+        if total.is_zero() {
+            return Vec::new();
+        }
+
+        let mut rewards: Vec<DoomReward> = weights
+            .into_iter()
+            .map(|(user_id, weight)| DoomReward {
+                user_id,
+                money: (Decimal::from(pool) * weight / total).floor().to_i64().unwrap_or(0),
+            })
+            .collect();
+        rewards.sort_by_key(|r| r.user_id);
+        rewards
+    }
+}
+
+fn parse_octets(ip: &str) -> Result<Vec<u8>> {
+    let octets: Vec<u8> = ip
+        .split('.')
+        .map(|part| part.parse::<u8>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| GameMechanicsError::InvalidParameter(format!("Invalid IP address: {}", ip)))?;
+
+    if octets.len() != 4 {
+        return Err(GameMechanicsError::InvalidParameter(format!("Invalid IP address: {}", ip)));
+    }
+
+    Ok(octets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn maxed_software() -> HashMap<String, f32> {
+        ["cracker", "hider", "ddos", "firewall"]
+            .iter()
+            .map(|s| (s.to_string(), 10.0))
+            .collect()
+    }
+
+    fn npc_ips() -> Vec<String> {
+        (1..=6).map(|i| format!("10.0.0.{}", i)).collect()
+    }
+
+    fn counting_campaign(config: &DoomConfig, now: DateTime<Utc>) -> DoomCampaign {
+        let mut campaign = DoomCampaign::new(1, 60, config).unwrap();
+        for _ in DOOM_RESEARCH_CHAIN {
+            campaign.complete_research_step(&maxed_software()).unwrap();
+        }
+        let mut rng = StdRng::seed_from_u64(7);
+        campaign.install("200.10.20.30", &npc_ips(), now, &mut rng, config).unwrap();
+        campaign
+    }
+
+    #[test]
+    fn test_level_and_research_gates() {
+        let config = DoomConfig::default();
+        assert!(DoomCampaign::new(1, 10, &config).is_err());
+
+        let mut campaign = DoomCampaign::new(1, 60, &config).unwrap();
+        assert!(!campaign.is_ready_to_install());
+        assert!(campaign.complete_research_step(&HashMap::new()).is_err());
+
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(campaign.install("1.2.3.4", &npc_ips(), Utc::now(), &mut rng, &config).is_err());
+        assert!(total_research_hours() > 24 * 7 * 2);
+    }
+
+    #[test]
+    fn test_install_plants_clues_and_starts_countdown() {
+        let config = DoomConfig::default();
+        let now = Utc::now();
+        let campaign = counting_campaign(&config, now);
+
+        assert_eq!(campaign.clues.len(), config.clue_count);
+        assert_eq!(campaign.seconds_remaining(now), Some(config.countdown_seconds));
+        let octets: Vec<u8> = campaign.clues.iter().map(|c| c.octet_value).collect();
+        assert_eq!(octets, vec![200, 10, 20, 30]);
+    }
+
+    #[test]
+    fn test_defenders_disarm_and_split_rewards() {
+        let config = DoomConfig::default();
+        let now = Utc::now();
+        let mut campaign = counting_campaign(&config, now);
+
+        let clue_ip = campaign.clues[0].npc_ip.clone();
+        assert!(campaign.inspect_npc_log(2, &clue_ip).is_some());
+        assert!(campaign.inspect_npc_log(2, &clue_ip).is_none());
+        assert!(campaign.inspect_npc_log(1, &clue_ip).is_none());
+
+        let miss = campaign.attempt_disarm(3, "1.1.1.1", now, &config).unwrap();
+        assert_eq!(miss, DoomEvent::WrongLocation { defender_id: 3, guesses_left: 2 });
+
+        match campaign.attempt_disarm(3, "200.10.20.30", now, &config).unwrap() {
+            DoomEvent::Disarmed { defender_id, rewards } => {
+                assert_eq!(defender_id, 3);
+                let total: i64 = rewards.iter().map(|r| r.money).sum();
+                assert!(total <= config.defender_reward_pool);
+                assert!(rewards.iter().any(|r| r.user_id == 2));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(campaign.tick(now + Duration::days(30), &config).is_none());
+    }
+
+    #[test]
+    fn test_countdown_expiry_resets_server() {
+        let config = DoomConfig::default();
+        let now = Utc::now();
+        let mut campaign = counting_campaign(&config, now);
+
+        assert!(campaign.tick(now + Duration::seconds(10), &config).is_none());
+        match campaign.tick(now + Duration::seconds(config.countdown_seconds), &config) {
+            Some(DoomEvent::ServerReset { instigator_id, rewards }) => {
+                assert_eq!(instigator_id, 1);
+                assert_eq!(rewards[0].money, config.instigator_reward);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(matches!(campaign.phase, DoomPhase::Detonated { .. }));
+    }
+
+    #[test]
+    fn test_campaign_survives_json_round_trip() {
+        let config = DoomConfig::default();
+        let now = Utc::now();
+        let mut campaign = counting_campaign(&config, now);
+        let npc_ip = campaign.clues[0].npc_ip.clone();
+        campaign.inspect_npc_log(3, &npc_ip).unwrap();
+
+        let stored = serde_json::to_value(&campaign).unwrap();
+        let loaded: DoomCampaign = serde_json::from_value(stored).unwrap();
+        assert_eq!(loaded.phase, campaign.phase);
+        assert_eq!(loaded.clues_found.get(&3), Some(&vec![npc_ip]));
+    }
+
+    #[test]
+    fn test_config_without_doom_settings_uses_defaults() {
+        let partial: DoomConfig = serde_json::from_str(r#"{"min_level": 60}"#).unwrap();
+        assert_eq!(partial.min_level, 60);
+        assert_eq!(partial.clue_count, DoomConfig::default().clue_count);
+
+        let mut stored = serde_json::to_value(crate::config::GameConfig::default()).unwrap();
+        stored.as_object_mut().unwrap().remove("doom");
+        let loaded: crate::config::GameConfig = serde_json::from_value(stored).unwrap();
+        assert_eq!(loaded.doom.countdown_seconds, DoomConfig::default().countdown_seconds);
+    }
+
+    #[test]
+    fn test_refusals_keep_the_rules_message() {
+        let denied = DoomDenied::from(GameMechanicsError::PreconditionFailed("Doom requires level 50".to_string()));
+        assert_eq!(denied.message(), "Doom requires level 50");
+    }
+}
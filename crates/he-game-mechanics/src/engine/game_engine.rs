@@ -13,6 +13,7 @@ use std::collections::HashMap;
 use std::net::Ipv4Addr;
 use std::time::{Duration, SystemTime};
 use uuid::Uuid;
+use he_core::clock;
 
 /// Player state in the game
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,8 +45,8 @@ impl Player {
             level: 1,
             reputation: 0,
             clan_id: None,
-            created_at: SystemTime::now(),
-            last_login: SystemTime::now(),
+            created_at: clock::system_now(),
+            last_login: clock::system_now(),
         }
     }
 
@@ -190,7 +191,7 @@ impl GameEngine {
             active_sessions: HashMap::new(),
             event_queue: Vec::new(),
             tick: 0,
-            last_update: SystemTime::now(),
+            last_update: clock::system_now(),
             tick_rate: Duration::from_millis(100),
         }
     }
@@ -433,7 +434,7 @@ impl EngineComponent for GameEngine {
         }
 
         self.tick += 1;
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
 
         Ok(())
     }
@@ -462,7 +463,7 @@ impl EngineComponent for GameEngine {
         self.active_sessions.clear();
         self.event_queue.clear();
         self.tick = 0;
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
 
         Ok(())
     }
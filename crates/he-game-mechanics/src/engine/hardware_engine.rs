@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::{Duration, SystemTime};
 use uuid::Uuid;
+use he_core::clock;
 
 /// Hardware component types
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -269,7 +270,7 @@ impl HardwareEngine {
             configurations: HashMap::new(),
             component_catalog: HashMap::new(),
             calculator: HardwareCalculator,
-            last_update: SystemTime::now(),
+            last_update: clock::system_now(),
         };
         engine.initialize_catalog();
         engine
@@ -366,7 +367,7 @@ impl EngineComponent for HardwareEngine {
             }
             config.recalculate_performance();
         }
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
         Ok(())
     }
 
@@ -385,7 +386,7 @@ impl EngineComponent for HardwareEngine {
     fn reset(&mut self) -> EngineResult<()> {
         self.configurations.clear();
         self.initialize_catalog();
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
         Ok(())
     }
 }
\ No newline at end of file
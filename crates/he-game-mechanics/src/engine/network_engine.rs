@@ -7,6 +7,7 @@ use std::net::Ipv4Addr;
 use std::time::{Duration, SystemTime};
 use uuid::Uuid;
 use rand::Rng;
+use he_core::clock;
 
 /// Server types in the network
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -135,7 +136,7 @@ impl NetworkLog {
     pub fn new(source_ip: Ipv4Addr, action: LogAction, message: String) -> Self {
         Self {
             id: Uuid::new_v4(),
-            timestamp: SystemTime::now(),
+            timestamp: clock::system_now(),
             source_ip,
             action,
             message,
@@ -361,7 +362,7 @@ impl NetworkEngine {
             topology: NetworkTopology::new(),
             active_connections: HashMap::new(),
             ddos_attacks: HashMap::new(),
-            last_update: SystemTime::now(),
+            last_update: clock::system_now(),
         }
     }
 
@@ -507,7 +508,7 @@ impl EngineComponent for NetworkEngine {
             }
         }
 
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
         Ok(())
     }
 
@@ -528,7 +529,7 @@ impl EngineComponent for NetworkEngine {
         self.topology = NetworkTopology::new();
         self.active_connections.clear();
         self.ddos_attacks.clear();
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
         Ok(())
     }
 }
\ No newline at end of file
@@ -7,6 +7,7 @@ use std::collections::{HashMap, VecDeque, BinaryHeap};
 use std::cmp::Ordering;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
+use he_core::clock;
 
 /// Process priority levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -147,7 +148,7 @@ impl ProcessScheduler {
     pub fn schedule(&mut self, process: Process) {
         let queued = QueuedProcess {
             process,
-            queue_time: SystemTime::now(),
+            queue_time: clock::system_now(),
         };
         self.ready_queue.push(queued);
     }
@@ -209,7 +210,7 @@ impl ProcessExecutor {
         available_resources.deallocate(&process.resources_required);
         process.resources_allocated = process.resources_required;
         process.state = ProcessState::Running;
-        process.time_started = Some(SystemTime::now());
+        process.time_started = Some(clock::system_now());
 
         self.running.insert(process.id, process);
         Ok(())
@@ -329,7 +330,7 @@ impl ProcessEngine {
             executor: ProcessExecutor::new(100, Duration::from_millis(100)),
             available_resources,
             max_concurrent,
-            last_update: SystemTime::now(),
+            last_update: clock::system_now(),
         }
     }
 
@@ -410,7 +411,7 @@ impl EngineComponent for ProcessEngine {
 
         // Try to execute more processes
         self.try_execute_next()?;
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
 
         Ok(())
     }
@@ -433,7 +434,7 @@ impl EngineComponent for ProcessEngine {
     fn reset(&mut self) -> EngineResult<()> {
         self.scheduler = ProcessScheduler::new(self.max_concurrent);
         self.executor = ProcessExecutor::new(100, Duration::from_millis(100));
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
         Ok(())
     }
 }
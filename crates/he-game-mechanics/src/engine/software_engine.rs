@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::{Duration, SystemTime};
 use uuid::Uuid;
+use he_core::clock;
 
 /// Software categories
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -355,7 +356,7 @@ impl SoftwareEngine {
             inventories: HashMap::new(),
             software_library: HashMap::new(),
             resolver: DependencyResolver,
-            last_update: SystemTime::now(),
+            last_update: clock::system_now(),
         };
         engine.initialize_library();
         engine
@@ -509,7 +510,7 @@ impl EngineComponent for SoftwareEngine {
     }
 
     fn update(&mut self, _delta: Duration) -> EngineResult<()> {
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
         Ok(())
     }
 
@@ -528,7 +529,7 @@ impl EngineComponent for SoftwareEngine {
     fn reset(&mut self) -> EngineResult<()> {
         self.inventories.clear();
         self.initialize_library();
-        self.last_update = SystemTime::now();
+        self.last_update = clock::system_now();
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,184 @@
+//! Premium entitlements
+//!
+//! A subscription bought through the payment provider makes its player
+//! premium until the end of the period paid for. When a renewal payment
+//! fails the subscription is past due while the provider retries, and
+//! premium carries on for a grace period so a card problem does not take the
+//! perks away at once. A cancelled subscription lasts to the end of the
+//! period it paid for, without grace.
+//!
+//! The provider reports changes through signed webhooks, which may arrive
+//! late, twice or out of order; an event older than the last one applied to
+//! a subscription is ignored.
+
+use crate::config::EntitlementConfig;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    /// Checkout started but the first payment has not gone through
+    Pending,
+    Active,
+    /// A renewal failed and the provider is retrying
+    PastDue,
+    /// Will not renew; paid time is still honoured
+    Canceled,
+    /// Access has ended and the premium role was taken away
+    Expired,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::Pending => "pending",
+            SubscriptionStatus::Active => "active",
+            SubscriptionStatus::PastDue => "past_due",
+            SubscriptionStatus::Canceled => "canceled",
+            SubscriptionStatus::Expired => "expired",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(SubscriptionStatus::Pending),
+            "active" => Some(SubscriptionStatus::Active),
+            "past_due" => Some(SubscriptionStatus::PastDue),
+            "canceled" => Some(SubscriptionStatus::Canceled),
+            "expired" => Some(SubscriptionStatus::Expired),
+            _ => None,
+        }
+    }
+
+    /// Map a Stripe subscription status
+    pub fn from_provider(s: &str) -> Option<Self> {
+        match s {
+            "incomplete" | "paused" => Some(SubscriptionStatus::Pending),
+            "active" | "trialing" => Some(SubscriptionStatus::Active),
+            "past_due" | "unpaid" => Some(SubscriptionStatus::PastDue),
+            "canceled" | "incomplete_expired" => Some(SubscriptionStatus::Canceled),
+            _ => None,
+        }
+    }
+}
+
+/// When premium from a subscription ends, or `None` if it gives none
+pub fn access_until(
+    status: SubscriptionStatus,
+    period_end: DateTime<Utc>,
+    config: &EntitlementConfig,
+) -> Option<DateTime<Utc>> {
+    match status {
+        // Renewal webhooks can lag the period end, so active ones get grace too
+        SubscriptionStatus::Active | SubscriptionStatus::PastDue => {
+            Some(period_end + Duration::hours(config.grace_hours))
+        }
+        SubscriptionStatus::Canceled => Some(period_end),
+        SubscriptionStatus::Pending | SubscriptionStatus::Expired => None,
+    }
+}
+
+pub fn is_entitled(access_until: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    access_until.is_some_and(|until| now < until)
+}
+
+/// Whether a provider event created at `event_at` should be applied to a
+/// subscription last updated by an event at `last_event_at`
+pub fn is_newer(event_at: DateTime<Utc>, last_event_at: Option<DateTime<Utc>>) -> bool {
+    !matches!(last_event_at, Some(last) if event_at < last)
+}
+
+/// Why a webhook is refused before its payload is read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum WebhookDenied {
+    MissingSignature,
+    BadSignature,
+    /// Signed too long ago, or in the future; a replay
+    Stale { tolerance_secs: i64 },
+}
+
+impl WebhookDenied {
+    pub fn message(&self) -> String {
+        match self {
+            WebhookDenied::MissingSignature => "Missing or malformed signature header".to_string(),
+            WebhookDenied::BadSignature => "Signature does not match".to_string(),
+            WebhookDenied::Stale { tolerance_secs } => {
+                format!("Signature timestamp is more than {}s away", tolerance_secs)
+            }
+        }
+    }
+}
+
+/// A `t=<unix>,v1=<hex>[,v1=<hex>...]` signature header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHeader {
+    pub timestamp: i64,
+    /// The provider sends one per active secret while secrets are rolled
+    pub signatures: Vec<String>,
+}
+
+pub fn parse_signature_header(header: &str) -> Result<SignatureHeader, WebhookDenied> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse().ok(),
+            Some(("v1", value)) if !value.is_empty() => signatures.push(value.to_lowercase()),
+            _ => {}
+        }
+    }
+    match timestamp {
+        Some(timestamp) if !signatures.is_empty() => Ok(SignatureHeader { timestamp, signatures }),
+        _ => Err(WebhookDenied::MissingSignature),
+    }
+}
+
+/// Refuse signatures outside the tolerance, so a captured webhook cannot be
+/// replayed later
+pub fn check_timestamp(timestamp: i64, now: DateTime<Utc>, config: &EntitlementConfig) -> Result<(), WebhookDenied> {
+    if (now.timestamp() - timestamp).abs() > config.webhook_tolerance_secs {
+        return Err(WebhookDenied::Stale { tolerance_secs: config.webhook_tolerance_secs });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_and_grace() {
+        let config = EntitlementConfig::default();
+        let period_end = Utc::now();
+        let grace = Duration::hours(config.grace_hours);
+
+        assert_eq!(access_until(SubscriptionStatus::PastDue, period_end, &config), Some(period_end + grace));
+        assert_eq!(access_until(SubscriptionStatus::Canceled, period_end, &config), Some(period_end));
+        assert_eq!(access_until(SubscriptionStatus::Pending, period_end, &config), None);
+
+        let past_due = access_until(SubscriptionStatus::PastDue, period_end, &config);
+        assert!(is_entitled(past_due, period_end + Duration::hours(1)));
+        assert!(!is_entitled(past_due, period_end + grace));
+        assert!(!is_entitled(None, period_end));
+
+        assert!(is_newer(period_end, None));
+        assert!(!is_newer(period_end - Duration::seconds(1), Some(period_end)));
+        assert_eq!(SubscriptionStatus::from_provider("trialing"), Some(SubscriptionStatus::Active));
+    }
+
+    #[test]
+    fn test_signature_header() {
+        let config = EntitlementConfig::default();
+        let header = parse_signature_header("t=1700000000,v1=ABC123,v0=old,v1=def456").unwrap();
+        assert_eq!(header.timestamp, 1_700_000_000);
+        assert_eq!(header.signatures, vec!["abc123", "def456"]);
+        assert_eq!(parse_signature_header("v1=abc"), Err(WebhookDenied::MissingSignature));
+        assert_eq!(parse_signature_header("t=1700000000"), Err(WebhookDenied::MissingSignature));
+
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        assert_eq!(check_timestamp(1_700_000_000 - config.webhook_tolerance_secs, now, &config), Ok(()));
+        assert!(check_timestamp(1_700_000_000 - config.webhook_tolerance_secs - 1, now, &config).is_err());
+    }
+}
@@ -5,6 +5,7 @@ use crate::config::HardwareConfig;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use he_core::clock;
 
 /// Hardware component types
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -187,7 +188,7 @@ impl HardwareComponent {
             firmware_version: "1.0.0".to_string(),
             is_damaged: false,
             damage_level: 0.0,
-            warranty_expires: Some(SystemTime::now() + Duration::from_secs(365 * 24 * 3600)),
+            warranty_expires: Some(clock::system_now() + Duration::from_secs(365 * 24 * 3600)),
             custom_mods: Vec::new(),
         }
     }
@@ -272,7 +273,7 @@ impl HardwareComponent {
             self.damage_level = 0.0;
         }
         
-        self.last_maintained = Some(SystemTime::now());
+        self.last_maintained = Some(clock::system_now());
         self.temperature = 30.0; // Reset temperature after maintenance
     }
     
@@ -386,7 +387,7 @@ impl HardwareInventory {
         
         // Remove from inventory and install
         let mut component = components.remove(component_index);
-        component.installed_at = Some(SystemTime::now());
+        component.installed_at = Some(clock::system_now());
         
         // Update power consumption
         self.total_power_consumption += component.power_consumption;
@@ -0,0 +1,391 @@
+//! Bank heists
+//!
+//! A high-tier NPC bank opens for a timed heist window, and crews form raid
+//! instances against it. Every role has to be filled before a raid starts;
+//! after that each role acts in its own stage, in order. The DDoS takes the
+//! firewall down, the vault has to be cracked before the firewall comes back
+//! up, the transfer moves the loot out, and the logs have to be wiped before
+//! the trace lands. A raid still running when the window closes fails. A crew
+//! that gets away splits its loot by role weight and by who acted.
+
+use crate::config::HeistConfig;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What a crew member does in a raid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeistRole {
+    Ddos,
+    Crack,
+    Transfer,
+    Wipe,
+}
+
+impl HeistRole {
+    pub const ALL: [HeistRole; 4] = [HeistRole::Ddos, HeistRole::Crack, HeistRole::Transfer, HeistRole::Wipe];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeistRole::Ddos => "ddos",
+            HeistRole::Crack => "crack",
+            HeistRole::Transfer => "transfer",
+            HeistRole::Wipe => "wipe",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|role| role.as_str() == s)
+    }
+
+    fn weight(&self, config: &HeistConfig) -> i64 {
+        let index = Self::ALL.iter().position(|role| role == self).expect("every role is in ALL");
+        config.role_weights[index].max(0)
+    }
+}
+
+/// Where a raid is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeistStage {
+    Recruiting,
+    /// Waiting for the DDoS
+    Firewall,
+    /// The firewall is down; waiting for the vault to be cracked
+    Vault,
+    Transfer,
+    /// The loot is out; waiting for the logs to be wiped
+    Cleanup,
+    Escaped,
+    Failed,
+}
+
+impl HeistStage {
+    pub const ALL: [HeistStage; 7] = [
+        HeistStage::Recruiting,
+        HeistStage::Firewall,
+        HeistStage::Vault,
+        HeistStage::Transfer,
+        HeistStage::Cleanup,
+        HeistStage::Escaped,
+        HeistStage::Failed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeistStage::Recruiting => "recruiting",
+            HeistStage::Firewall => "firewall",
+            HeistStage::Vault => "vault",
+            HeistStage::Transfer => "transfer",
+            HeistStage::Cleanup => "cleanup",
+            HeistStage::Escaped => "escaped",
+            HeistStage::Failed => "failed",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|stage| stage.as_str() == s)
+    }
+
+    pub fn is_over(&self) -> bool {
+        matches!(self, HeistStage::Escaped | HeistStage::Failed)
+    }
+
+    /// The role that acts next
+    pub fn role(&self) -> Option<HeistRole> {
+        match self {
+            HeistStage::Firewall => Some(HeistRole::Ddos),
+            HeistStage::Vault => Some(HeistRole::Crack),
+            HeistStage::Transfer => Some(HeistRole::Transfer),
+            HeistStage::Cleanup => Some(HeistRole::Wipe),
+            HeistStage::Recruiting | HeistStage::Escaped | HeistStage::Failed => None,
+        }
+    }
+}
+
+/// The part of a raid that moves on its own as time passes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RaidState {
+    pub stage: HeistStage,
+    /// End of the heist window
+    pub closes_at: DateTime<Utc>,
+    pub firewall_down_until: Option<DateTime<Utc>>,
+    pub trace_at: Option<DateTime<Utc>>,
+}
+
+/// Why a raid failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RaidFailure {
+    WindowClosed,
+    /// The logs were not wiped in time
+    Traced,
+}
+
+impl RaidFailure {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            RaidFailure::WindowClosed => "the heist window closed",
+            RaidFailure::Traced => "the crew was traced before the logs were wiped",
+        }
+    }
+}
+
+/// What time did to a raid since it was last looked at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Settled {
+    Unchanged,
+    /// The vault was not cracked in time; the DDoS has to go again
+    FirewallRestored,
+    Failed(RaidFailure),
+}
+
+/// Bring `state` up to `now`
+pub fn settle(state: &RaidState, now: DateTime<Utc>) -> (RaidState, Settled) {
+    let mut settled = *state;
+    if state.stage.is_over() {
+        return (settled, Settled::Unchanged);
+    }
+    if now >= state.closes_at {
+        settled.stage = HeistStage::Failed;
+        return (settled, Settled::Failed(RaidFailure::WindowClosed));
+    }
+    match state.stage {
+        HeistStage::Cleanup if state.trace_at.is_some_and(|at| now >= at) => {
+            settled.stage = HeistStage::Failed;
+            (settled, Settled::Failed(RaidFailure::Traced))
+        }
+        HeistStage::Vault if state.firewall_down_until.is_some_and(|until| now >= until) => {
+            settled.stage = HeistStage::Firewall;
+            settled.firewall_down_until = None;
+            (settled, Settled::FirewallRestored)
+        }
+        _ => (settled, Settled::Unchanged),
+    }
+}
+
+/// Why a heist action was refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum HeistDenied {
+    NotFound,
+    WindowClosed,
+    AlreadyInRaid,
+    NotRecruiting,
+    NotRunning,
+    NotMember,
+    NotLeader,
+    RoleFull { role: HeistRole, max: usize },
+    RolesUnfilled { roles: Vec<HeistRole> },
+    NotYourTurn { waiting_for: HeistRole },
+    /// Another crew already emptied the vault
+    VaultEmpty,
+}
+
+impl HeistDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            HeistDenied::NotFound => "Heist not found".to_string(),
+            HeistDenied::WindowClosed => "The heist window has closed".to_string(),
+            HeistDenied::AlreadyInRaid => "You are already in a heist crew".to_string(),
+            HeistDenied::NotRecruiting => "This crew is no longer taking members".to_string(),
+            HeistDenied::NotRunning => "This raid is not running".to_string(),
+            HeistDenied::NotMember => "You are not in this crew".to_string(),
+            HeistDenied::NotLeader => "Only the crew leader can start the raid".to_string(),
+            HeistDenied::RoleFull { role, max } => {
+                format!("The {} role takes at most {} players", role.as_str(), max)
+            }
+            HeistDenied::RolesUnfilled { roles } => format!(
+                "Nobody has taken the {} role yet",
+                roles.iter().map(|r| r.as_str()).collect::<Vec<_>>().join(", ")
+            ),
+            HeistDenied::NotYourTurn { waiting_for } => {
+                format!("The crew is waiting on the {} role", waiting_for.as_str())
+            }
+            HeistDenied::VaultEmpty => "The vault has already been emptied".to_string(),
+        }
+    }
+}
+
+/// Whether a player may take `role` in a crew already holding `crew`
+pub fn check_join(stage: HeistStage, role: HeistRole, crew: &[HeistRole], config: &HeistConfig) -> Result<(), HeistDenied> {
+    if stage != HeistStage::Recruiting {
+        return Err(HeistDenied::NotRecruiting);
+    }
+    if crew.iter().filter(|taken| **taken == role).count() >= config.max_per_role {
+        return Err(HeistDenied::RoleFull { role, max: config.max_per_role });
+    }
+    Ok(())
+}
+
+/// Start a settled raid whose crew holds `crew`
+pub fn start(state: &RaidState, crew: &[HeistRole], now: DateTime<Utc>) -> Result<RaidState, HeistDenied> {
+    if state.stage != HeistStage::Recruiting {
+        return Err(HeistDenied::NotRecruiting);
+    }
+    if now >= state.closes_at {
+        return Err(HeistDenied::WindowClosed);
+    }
+    let missing: Vec<HeistRole> = HeistRole::ALL.into_iter().filter(|role| !crew.contains(role)).collect();
+    if !missing.is_empty() {
+        return Err(HeistDenied::RolesUnfilled { roles: missing });
+    }
+    Ok(RaidState { stage: HeistStage::Firewall, ..*state })
+}
+
+/// A crew member with `role` acting on a settled raid
+pub fn act(state: &RaidState, role: HeistRole, now: DateTime<Utc>, config: &HeistConfig) -> Result<RaidState, HeistDenied> {
+    let Some(waiting_for) = state.stage.role() else {
+        return Err(HeistDenied::NotRunning);
+    };
+    if role != waiting_for {
+        return Err(HeistDenied::NotYourTurn { waiting_for });
+    }
+
+    let mut next = *state;
+    match role {
+        HeistRole::Ddos => {
+            next.stage = HeistStage::Vault;
+            next.firewall_down_until = Some(now + Duration::seconds(config.firewall_down_secs));
+        }
+        HeistRole::Crack => next.stage = HeistStage::Transfer,
+        HeistRole::Transfer => {
+            next.stage = HeistStage::Cleanup;
+            next.trace_at = Some(now + Duration::seconds(config.trace_secs));
+        }
+        HeistRole::Wipe => next.stage = HeistStage::Escaped,
+    }
+    Ok(next)
+}
+
+/// What one crew gets away with from a vault holding `vault`
+pub fn loot(vault: i64, config: &HeistConfig) -> i64 {
+    let vault = vault.max(0);
+    let take = vault as i128 * config.loot_bps.clamp(0, 10_000) as i128 / 10_000;
+    take as i64
+}
+
+/// A crew member and how often they acted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrewMember {
+    pub user_id: i64,
+    pub role: HeistRole,
+    pub actions: i32,
+}
+
+/// A crew member's cut of the loot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeistShare {
+    pub user_id: i64,
+    pub money: i64,
+}
+
+/// Split `loot` between the members who acted, in proportion to their
+/// role's weight times their actions. Rounding leftovers go to the largest
+/// share so the shares add up to the loot.
+pub fn split_loot(loot: i64, crew: &[CrewMember], config: &HeistConfig) -> Vec<HeistShare> {
+    let weighted: Vec<(i64, i64)> = crew
+        .iter()
+        .filter(|m| m.actions > 0)
+        .map(|m| (m.user_id, m.role.weight(config) * m.actions as i64))
+        .collect();
+    let total: i64 = weighted.iter().map(|(_, weight)| weight).sum();
+    let Some(top) = weighted.iter().enumerate().max_by_key(|(_, (_, weight))| *weight).map(|(i, _)| i) else {
+        return Vec::new();
+    };
+
+    let mut shares: Vec<HeistShare> = weighted
+        .iter()
+        .map(|(user_id, weight)| HeistShare {
+            user_id: *user_id,
+            money: if total == 0 { 0 } else { (loot as i128 * *weight as i128 / total as i128) as i64 },
+        })
+        .collect();
+    shares[top].money += loot - shares.iter().map(|s| s.money).sum::<i64>();
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn raid(stage: HeistStage) -> RaidState {
+        RaidState {
+            stage,
+            closes_at: Utc.with_ymd_and_hms(2024, 11, 7, 13, 0, 0).unwrap(),
+            firewall_down_until: None,
+            trace_at: None,
+        }
+    }
+
+    #[test]
+    fn test_raid_runs_through_every_role() {
+        let config = HeistConfig::default();
+        let now = Utc.with_ymd_and_hms(2024, 11, 7, 12, 0, 0).unwrap();
+        let recruiting = raid(HeistStage::Recruiting);
+
+        assert_eq!(check_join(HeistStage::Recruiting, HeistRole::Ddos, &[HeistRole::Ddos], &config), Ok(()));
+        assert_eq!(
+            check_join(HeistStage::Recruiting, HeistRole::Ddos, &[HeistRole::Ddos, HeistRole::Ddos], &config),
+            Err(HeistDenied::RoleFull { role: HeistRole::Ddos, max: 2 })
+        );
+        assert_eq!(
+            start(&recruiting, &[HeistRole::Ddos, HeistRole::Crack], now),
+            Err(HeistDenied::RolesUnfilled { roles: vec![HeistRole::Transfer, HeistRole::Wipe] })
+        );
+
+        let mut state = start(&recruiting, &HeistRole::ALL, now).unwrap();
+        assert_eq!(
+            act(&state, HeistRole::Crack, now, &config),
+            Err(HeistDenied::NotYourTurn { waiting_for: HeistRole::Ddos })
+        );
+        for role in HeistRole::ALL {
+            state = act(&state, role, now, &config).unwrap();
+        }
+        assert_eq!(state.stage, HeistStage::Escaped);
+        assert_eq!(act(&state, HeistRole::Wipe, now, &config), Err(HeistDenied::NotRunning));
+    }
+
+    #[test]
+    fn test_settle_restores_firewall_and_fails_raids() {
+        let config = HeistConfig::default();
+        let now = Utc.with_ymd_and_hms(2024, 11, 7, 12, 0, 0).unwrap();
+        let vault = act(&raid(HeistStage::Firewall), HeistRole::Ddos, now, &config).unwrap();
+        assert_eq!(settle(&vault, now + Duration::seconds(60)).1, Settled::Unchanged);
+        let (restored, settled) = settle(&vault, now + Duration::seconds(config.firewall_down_secs));
+        assert_eq!(settled, Settled::FirewallRestored);
+        assert_eq!(restored.stage, HeistStage::Firewall);
+
+        let cleanup = act(&raid(HeistStage::Transfer), HeistRole::Transfer, now, &config).unwrap();
+        let (failed, settled) = settle(&cleanup, now + Duration::seconds(config.trace_secs));
+        assert_eq!(settled, Settled::Failed(RaidFailure::Traced));
+        assert_eq!(failed.stage, HeistStage::Failed);
+
+        let closed = settle(&raid(HeistStage::Recruiting), Utc.with_ymd_and_hms(2024, 11, 7, 13, 0, 0).unwrap());
+        assert_eq!(closed.1, Settled::Failed(RaidFailure::WindowClosed));
+        assert_eq!(settle(&failed, now).1, Settled::Unchanged);
+    }
+
+    #[test]
+    fn test_loot_split_by_role_and_actions() {
+        let config = HeistConfig::default();
+        assert_eq!(loot(50_000_000, &config), 10_000_000);
+        assert_eq!(loot(-5, &config), 0);
+
+        let crew = vec![
+            CrewMember { user_id: 1, role: HeistRole::Ddos, actions: 2 },
+            CrewMember { user_id: 2, role: HeistRole::Crack, actions: 1 },
+            CrewMember { user_id: 3, role: HeistRole::Transfer, actions: 1 },
+            CrewMember { user_id: 4, role: HeistRole::Wipe, actions: 1 },
+            // Held the role but never acted
+            CrewMember { user_id: 5, role: HeistRole::Ddos, actions: 0 },
+        ];
+        // Weights 40, 35, 25 and 20 out of 120
+        let shares = split_loot(1_000, &crew, &config);
+        assert_eq!(shares.iter().map(|s| s.money).collect::<Vec<_>>(), vec![335, 291, 208, 166]);
+        assert_eq!(shares.iter().map(|s| s.money).sum::<i64>(), 1_000);
+        assert!(split_loot(1_000, &crew[4..], &config).is_empty());
+    }
+}
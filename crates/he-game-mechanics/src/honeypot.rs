@@ -0,0 +1,116 @@
+//! Honeypot servers
+//!
+//! Some NPC servers are bait: they look like rich, poorly defended targets
+//! but pay nothing out. Whatever an attacker does there is recorded with the
+//! real route it came through, from their gateway over each bounce, and
+//! leaves them traced for a while. A traced attacker's real address shows up
+//! in the logs of every server they touch, and each trip costs them standing
+//! with the Underground. Players rarely fall for the same trap twice, so an
+//! account that keeps tripping honeypots is flagged as likely scripted.
+
+use crate::config::HoneypotConfig;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The faction that frowns on getting caught
+pub const REPUTATION_FACTION: &str = "underground";
+
+/// How an attacker reached a server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Route {
+    pub gateway_ip: String,
+    /// In the order the connection passes them
+    pub bounce_ips: Vec<String>,
+    /// The server the process ran on, if not the gateway
+    pub host_ip: Option<String>,
+    pub target_ip: String,
+}
+
+impl Route {
+    /// Every address the connection passed, gateway first
+    pub fn hops(&self) -> Vec<&str> {
+        std::iter::once(self.gateway_ip.as_str())
+            .chain(self.bounce_ips.iter().map(String::as_str))
+            .chain(self.host_ip.as_deref())
+            .chain(std::iter::once(self.target_ip.as_str()))
+            .collect()
+    }
+
+    /// The address the target would normally log: the last hop before it
+    pub fn apparent_origin(&self) -> &str {
+        let hops = self.hops();
+        hops[hops.len() - 2]
+    }
+}
+
+/// What tripping a honeypot does to the attacker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trap {
+    pub traced_until: DateTime<Utc>,
+    pub reputation_change: i32,
+    /// Enough trips within the window to look scripted
+    pub anomalous: bool,
+}
+
+/// Spring a honeypot on an attacker who is traced until `traced_until`, if
+/// at all, and tripped `recent_trips` others within the anomaly window
+pub fn spring(
+    now: DateTime<Utc>,
+    traced_until: Option<DateTime<Utc>>,
+    recent_trips: u32,
+    config: &HoneypotConfig,
+) -> Trap {
+    let from = traced_until.filter(|until| *until > now).unwrap_or(now);
+    let traced_until = (from + Duration::hours(config.traced_hours)).min(now + Duration::hours(config.max_traced_hours));
+
+    Trap {
+        traced_until,
+        reputation_change: -config.reputation_penalty,
+        anomalous: recent_trips + 1 >= config.anomaly_trips,
+    }
+}
+
+pub fn is_traced(traced_until: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    traced_until.map_or(false, |until| until > now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_hops() {
+        let route = Route {
+            gateway_ip: "10.0.0.1".to_string(),
+            bounce_ips: vec!["10.0.0.2".to_string(), "10.0.0.3".to_string()],
+            host_ip: None,
+            target_ip: "192.0.2.9".to_string(),
+        };
+        assert_eq!(route.hops(), vec!["10.0.0.1", "10.0.0.2", "10.0.0.3", "192.0.2.9"]);
+        assert_eq!(route.apparent_origin(), "10.0.0.3");
+
+        let direct = Route { bounce_ips: Vec::new(), host_ip: Some("10.0.0.7".to_string()), ..route };
+        assert_eq!(direct.apparent_origin(), "10.0.0.7");
+    }
+
+    #[test]
+    fn test_spring_extends_trace_up_to_cap() {
+        let config = HoneypotConfig::default();
+        let now = Utc::now();
+
+        let first = spring(now, None, 0, &config);
+        assert_eq!(first.traced_until, now + Duration::hours(6));
+        assert_eq!(first.reputation_change, -10);
+        assert!(!first.anomalous);
+
+        let second = spring(now, Some(first.traced_until), 1, &config);
+        assert_eq!(second.traced_until, now + Duration::hours(12));
+        assert!(spring(now, Some(now + Duration::hours(23)), 2, &config).anomalous);
+        assert_eq!(spring(now, Some(now + Duration::hours(23)), 2, &config).traced_until, now + Duration::hours(24));
+
+        // An expired trace starts over
+        assert_eq!(spring(now, Some(now - Duration::hours(1)), 0, &config).traced_until, now + Duration::hours(6));
+        assert!(is_traced(Some(first.traced_until), now));
+        assert!(!is_traced(None, now));
+    }
+}
@@ -0,0 +1,202 @@
+//! Process hosts: running processes on other servers
+//!
+//! A process runs on its host. That is the player's gateway unless they
+//! launch it on another server they own or have hacked. The host's hardware
+//! decides how fast the process runs: its CPU and RAM for computation, and
+//! for transfers the slower of the host's link and the target's.
+//!
+//! Processes launched on a host are allocated against its hardware and are
+//! refused once it is full. A hacked host only lends a share of its hardware
+//! to its hackers between them, so its owner keeps enough to play, and the
+//! owner sees every process running on it. Losing access to a hacked host
+//! cancels what the player runs there.
+
+use crate::config::HostingConfig;
+use crate::{HardwareSpecs, ResourceUsage};
+use serde::{Deserialize, Serialize};
+
+/// How the player holds the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostAccess {
+    Owned,
+    /// In the player's hacked database
+    Hacked,
+}
+
+impl HostAccess {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HostAccess::Owned => "owned",
+            HostAccess::Hacked => "hacked",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "owned" => Some(HostAccess::Owned),
+            "hacked" => Some(HostAccess::Hacked),
+            _ => None,
+        }
+    }
+}
+
+/// A host's hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostHardware {
+    pub cpu_mhz: i32,
+    pub ram_mb: i32,
+    pub net_mbps: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostResource {
+    Cpu,
+    Ram,
+    Net,
+}
+
+/// Why a process cannot run on a host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum HostDenied {
+    HostNotFound,
+    /// Neither owned nor in the player's hacked database
+    NoAccess,
+    TooManyProcesses { max: usize },
+    /// In the resource's own unit: CPU percent, RAM MB, network Kbps
+    Exhausted { resource: HostResource, needed: i32, available: i32 },
+}
+
+impl HostDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            HostDenied::HostNotFound => "No server at that address".to_string(),
+            HostDenied::NoAccess => "You have no access to that server".to_string(),
+            HostDenied::TooManyProcesses { max } => {
+                format!("At most {} of your processes can run on that server at once", max)
+            }
+            HostDenied::Exhausted { resource, .. } => {
+                let resource = match resource {
+                    HostResource::Cpu => "CPU",
+                    HostResource::Ram => "RAM",
+                    HostResource::Net => "bandwidth",
+                };
+                format!("That server does not have enough free {} for this process", resource)
+            }
+        }
+    }
+}
+
+/// Share of a host's hardware the player may use, in percent
+pub fn share_percent(access: HostAccess, config: &HostingConfig) -> i32 {
+    match access {
+        HostAccess::Owned => 100,
+        HostAccess::Hacked => config.hacked_share_percent,
+    }
+}
+
+/// What the host can give this player's processes in all, in
+/// [`ResourceUsage`] units
+pub fn capacity(hardware: &HostHardware, access: HostAccess, config: &HostingConfig) -> ResourceUsage {
+    let share = i64::from(share_percent(access, config));
+    let part = |total: i64| (total * share / 100) as i32;
+    ResourceUsage {
+        cpu_usage: part(100),
+        ram_usage: part(i64::from(hardware.ram_mb)),
+        net_usage: part(i64::from(hardware.net_mbps) * 1000),
+        hdd_usage: 0,
+    }
+}
+
+/// Whether `request` fits on the host. `running` is how many processes the
+/// player already has there; `used` is what runs there against the same
+/// capacity: every hosted process for the owner, every hacker's for a hacker.
+pub fn check_allocation(
+    hardware: &HostHardware,
+    access: HostAccess,
+    running: usize,
+    used: &ResourceUsage,
+    request: &ResourceUsage,
+    config: &HostingConfig,
+) -> Result<(), HostDenied> {
+    if running >= config.max_processes_per_host {
+        return Err(HostDenied::TooManyProcesses { max: config.max_processes_per_host });
+    }
+
+    let capacity = capacity(hardware, access, config);
+    let checks = [
+        (HostResource::Cpu, capacity.cpu_usage, used.cpu_usage, request.cpu_usage),
+        (HostResource::Ram, capacity.ram_usage, used.ram_usage, request.ram_usage),
+        (HostResource::Net, capacity.net_usage, used.net_usage, request.net_usage),
+    ];
+    for (resource, capacity, used, needed) in checks {
+        let available = (capacity - used).max(0);
+        if needed > available {
+            return Err(HostDenied::Exhausted { resource, needed, available });
+        }
+    }
+    Ok(())
+}
+
+/// Speed of a transfer between the host and the target: the slower end
+pub fn link_speed(host_mbps: i32, target_mbps: Option<i32>) -> i32 {
+    target_mbps.map_or(host_mbps, |target| host_mbps.min(target)).max(1)
+}
+
+/// The hardware a process on the host runs with
+pub fn host_specs(hardware: &HostHardware, access: HostAccess, config: &HostingConfig) -> HardwareSpecs {
+    let share = share_percent(access, config);
+    let part = |total: i32| (total * share / 100).max(1);
+    HardwareSpecs {
+        cpu: part(hardware.cpu_mhz),
+        ram: part(hardware.ram_mb),
+        hdd: 0,
+        net: part(hardware.net_mbps),
+        security_level: 50,
+        performance_rating: 100,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(cpu: i32, ram: i32, net: i32) -> ResourceUsage {
+        ResourceUsage { cpu_usage: cpu, ram_usage: ram, net_usage: net, hdd_usage: 0 }
+    }
+
+    #[test]
+    fn test_hacked_hosts_lend_a_share() {
+        let config = HostingConfig::default();
+        let hardware = HostHardware { cpu_mhz: 2000, ram_mb: 2048, net_mbps: 100 };
+        let idle = usage(0, 0, 0);
+        let heavy = usage(60, 1024, 5);
+
+        assert_eq!(check_allocation(&hardware, HostAccess::Owned, 0, &idle, &heavy, &config), Ok(()));
+        assert!(matches!(
+            check_allocation(&hardware, HostAccess::Hacked, 0, &idle, &heavy, &config),
+            Err(HostDenied::Exhausted { resource: HostResource::Cpu, .. })
+        ));
+
+        // Processes already running there count against the host
+        let busy = usage(90, 0, 0);
+        assert!(check_allocation(&hardware, HostAccess::Owned, 1, &busy, &usage(20, 0, 0), &config).is_err());
+        assert_eq!(
+            check_allocation(&hardware, HostAccess::Owned, config.max_processes_per_host, &idle, &idle, &config),
+            Err(HostDenied::TooManyProcesses { max: config.max_processes_per_host })
+        );
+
+        assert_eq!(host_specs(&hardware, HostAccess::Hacked, &config).cpu, 2000 * config.hacked_share_percent / 100);
+    }
+
+    #[test]
+    fn test_link_speed_is_the_slower_end() {
+        assert_eq!(link_speed(100, Some(10)), 10);
+        assert_eq!(link_speed(10, Some(1000)), 10);
+        assert_eq!(link_speed(50, None), 50);
+        assert_eq!(link_speed(0, Some(0)), 1);
+    }
+}
@@ -0,0 +1,130 @@
+//! Identity: paid IP resets
+//!
+//! A player can pay to move their gateway to a new address, at most once per
+//! [`IpResetConfig::cooldown_hours`]. Other players lose the old address from
+//! their hacked database. Logs already written elsewhere keep it, so a reset
+//! breaks access but not the trail.
+
+use crate::config::IpResetConfig;
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+
+/// Why a reset cannot start now
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ResetDenied {
+    /// A reset is already running
+    InProgress,
+    Cooldown { ready_at: DateTime<Utc> },
+    InsufficientFunds { cost: i64, balance: i64 },
+}
+
+impl ResetDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            ResetDenied::InProgress => "An IP reset is already in progress".to_string(),
+            ResetDenied::Cooldown { ready_at } => {
+                format!("Your IP can be reset again at {}", ready_at.to_rfc3339())
+            }
+            ResetDenied::InsufficientFunds { cost, .. } => {
+                format!("An IP reset costs ${}.{:02}", cost / 100, cost % 100)
+            }
+        }
+    }
+}
+
+/// Whether a player whose last reset finished at `last_reset` may start one
+pub fn check_reset(
+    last_reset: Option<DateTime<Utc>>,
+    in_progress: bool,
+    balance: i64,
+    now: DateTime<Utc>,
+    config: &IpResetConfig,
+) -> Result<(), ResetDenied> {
+    if in_progress {
+        return Err(ResetDenied::InProgress);
+    }
+
+    if let Some(last_reset) = last_reset {
+        let ready_at = last_reset + Duration::hours(config.cooldown_hours);
+        if now < ready_at {
+            return Err(ResetDenied::Cooldown { ready_at });
+        }
+    }
+
+    if balance < config.cost {
+        return Err(ResetDenied::InsufficientFunds { cost: config.cost, balance });
+    }
+
+    Ok(())
+}
+
+/// Whether `ip` can be handed to a player: public unicast only
+pub fn is_assignable(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || a == 0
+        // Multicast and reserved
+        || a >= 224
+        // Carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+/// A random assignable address. Uniqueness is up to the caller.
+pub fn random_ip<R: Rng + ?Sized>(rng: &mut R) -> Ipv4Addr {
+    loop {
+        let ip = Ipv4Addr::from(rng.gen::<u32>());
+        if is_assignable(ip) {
+            return ip;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_reset() {
+        let config = IpResetConfig::default();
+        let now = Utc::now();
+        let rich = config.cost;
+
+        assert_eq!(check_reset(None, false, rich, now, &config), Ok(()));
+        assert_eq!(check_reset(None, true, rich, now, &config), Err(ResetDenied::InProgress));
+
+        let recent = now - Duration::hours(config.cooldown_hours - 1);
+        assert_eq!(
+            check_reset(Some(recent), false, rich, now, &config),
+            Err(ResetDenied::Cooldown { ready_at: now + Duration::hours(1) })
+        );
+
+        let old = now - Duration::hours(config.cooldown_hours);
+        assert_eq!(check_reset(Some(old), false, rich, now, &config), Ok(()));
+        assert!(matches!(
+            check_reset(Some(old), false, rich - 1, now, &config),
+            Err(ResetDenied::InsufficientFunds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_random_ip_is_public() {
+        assert!(!is_assignable(Ipv4Addr::new(10, 1, 2, 3)));
+        assert!(!is_assignable(Ipv4Addr::new(100, 64, 0, 1)));
+        assert!(!is_assignable(Ipv4Addr::new(239, 0, 0, 1)));
+        assert!(is_assignable(Ipv4Addr::new(8, 8, 8, 8)));
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            assert!(is_assignable(random_ip(&mut rng)));
+        }
+    }
+}
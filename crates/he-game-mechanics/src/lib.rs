@@ -6,27 +6,83 @@
 //! ## Core Systems
 //! 
 //! - **Hacking System**: Difficulty calculations, success rates, intrusion mechanics
-//! - **Defense System**: Firewall strength, security ratings, detection algorithms  
+//! - **Defense System**: Firewall strength, security ratings, stealth and evidence
+//! - **Detection System**: Hack attempt detection, defender alerts, attacker tracing
+//! - **Complication System**: Random events that delay, kill or speed up long processes
+//! - **Identity System**: Paid IP resets and fresh address allocation
+//! - **Bounty System**: Escrowed hit contracts and anti-collusion claim checks
+//! - **Tutorial System**: First-hack walkthrough steps, hints and completion reward
+//! - **Contract System**: Player-posted jobs, escrow fees, proof of work and disputes
+//! - **Referral System**: Invite codes, milestone rewards and self-referral heuristics
+//! - **Report System**: Player reports, case dedup, queue priority and moderator deadlines
+//! - **Entitlement System**: Premium subscriptions, grace periods and webhook signatures
+//! - **Account Gating**: New-account restrictions lifted by level and age, staff overrides
+//! - **Banking System**: NPC bank tiers and fees, traceable stolen money and laundering
+//! - **Honeypot System**: Bait servers that trace attackers and flag scripted attacks
+//! - **Server Browser**: NPC target discovery, fuzzed security details and range scans
 //! - **Experience System**: Level progression, skill development, learning curves
 //! - **Financial System**: Economy balance, market dynamics, transaction processing
 //! - **Process System**: Time calculations, resource management, scheduling
+//! - **Cancellation System**: Progress-based refunds and penalties for cancelled processes
+//! - **Hosting System**: Processes on owned or hacked servers, host allocation
 //! - **Hardware System**: Performance ratings, compatibility, upgrade mechanics
+//! - **Wear System**: Hardware wear under heavy load, failures, quality tiers and repairs
 //! - **Software System**: Dependencies, effectiveness, installation mechanics
 //! - **Network System**: Connection protocols, routing, bandwidth calculations
 //! - **Mission System**: Difficulty scaling, reward calculations, prerequisites
+//! - **Mission Generation**: Procedural targets, objective graphs and generation audit
 //! - **Clan System**: Warfare mechanics, reputation formulas, contribution tracking
+//! - **Clan Server System**: Shared clan hardware, lent defense and war strikes
+//! - **Webhook System**: Clan and player event subscriptions, retry backoff and auto-disable
+//! - **Terminal System**: Player aliases, `&&` command chains and per-command cooldowns
+//! - **Puzzle System**: Storyline puzzles with hidden files, encrypted hints, access windows and community milestones
+//! - **Reservation System**: Expiring holds on money, disk space and process slots for multi-step actions
+//! - **Doom System**: Endgame virus research chain, world countdown, round reset
+//! - **Formula Scripts**: Sandboxed Rhai overrides of success rate, duration and rewards
 
 pub mod hacking;
 pub mod defense;
+pub mod detection;
+pub mod trace_route;
+pub mod complications;
+pub mod identity;
+pub mod bounty;
+pub mod tutorial;
+pub mod contracts;
+pub mod referrals;
+pub mod reports;
+pub mod entitlements;
+pub mod account_gating;
+pub mod banking;
+pub mod honeypot;
+pub mod piracy;
+pub mod server_browser;
 pub mod experience;
 pub mod financial;
 pub mod process;
+pub mod cancellation;
+pub mod hosting;
 pub mod hardware;
+pub mod wear;
 pub mod software;
 pub mod network;
 pub mod missions;
 pub mod missions_safe;  // Safe, original mission system - no AGPL content
+pub mod mission_gen;
 pub mod clans;
+pub mod clan_server;
+pub mod clan_chat;
+pub mod username;
+pub mod webhooks;
+pub mod terminal;
+pub mod terminal_grammar;
+pub mod puzzles;
+pub mod reservations;
+pub mod heist;
+pub mod dns;
+pub mod doom;
+pub mod rules;
+pub mod scripting;
 pub mod config;
 pub mod extended;
 
@@ -67,6 +123,21 @@ pub enum GameMechanicsError {
 
 pub type Result<T> = std::result::Result<T, GameMechanicsError>;
 
+impl From<GameMechanicsError> for he_core::HeError {
+    fn from(err: GameMechanicsError) -> Self {
+        use he_core::ErrorCode;
+
+        let code = match &err {
+            GameMechanicsError::InvalidParameter(_) => ErrorCode::Validation,
+            GameMechanicsError::InsufficientResources(_) => ErrorCode::InsufficientResources,
+            GameMechanicsError::PreconditionFailed(_) => ErrorCode::GameRule,
+            GameMechanicsError::CalculationError(_) => ErrorCode::Internal,
+            GameMechanicsError::ConfigurationError(_) => ErrorCode::Configuration,
+        };
+        he_core::HeError::domain("GameMechanics", code, err)
+    }
+}
+
 /// Player statistics and current state
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerState {
@@ -225,7 +296,8 @@ impl GameMechanics for GameEngine {
     }
     
     fn calculate_process_duration(&self, process_type: &str, player: &PlayerState, target: &TargetInfo) -> i32 {
-        process::calculate_duration(process_type, player, target, &self.config.process)
+        let duration = process::calculate_duration(process_type, player, target, &self.config.process);
+        self.config.rules.duration(duration)
     }
     
     fn calculate_success_rate(&self, player: &PlayerState, target: &TargetInfo) -> Decimal {
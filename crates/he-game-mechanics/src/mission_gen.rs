@@ -0,0 +1,583 @@
+//! Procedural missions
+//!
+//! Besides the hand-written templates, players are offered missions put
+//! together for them. Each generation picks a difficulty tier the player's
+//! level allows, a kind of job weighted away from what they did recently,
+//! and an NPC server whose firewall suits the tier and the player's best
+//! cracker. The job's goal is then expanded into an objective graph: every
+//! block names the facts it needs (a located server, access to it, stolen
+//! data) and the blocks providing them are pulled in as its dependencies.
+//!
+//! Every plan is checked before it is offered. One that cannot be finished
+//! is thrown away and another tried, and [`Generation`] keeps every attempt
+//! with what was wrong with it, so a bad offer, or none, can be explained
+//! from the generation log. Generation is seeded, so a logged one can be
+//! replayed.
+
+use crate::config::MissionGenConfig;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// What the player is hired to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissionKind {
+    /// Steal a file and hand it to the contact
+    DataTheft,
+    /// Destroy a file on the target
+    Sabotage,
+    /// Move money out of a bank
+    Heist,
+}
+
+impl MissionKind {
+    pub const ALL: [MissionKind; 3] = [MissionKind::DataTheft, MissionKind::Sabotage, MissionKind::Heist];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MissionKind::DataTheft => "data_theft",
+            MissionKind::Sabotage => "sabotage",
+            MissionKind::Heist => "heist",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == s)
+    }
+
+    /// The objective that finishes the job
+    pub fn goal(self) -> Block {
+        match self {
+            MissionKind::DataTheft => Block::Deliver,
+            MissionKind::Sabotage => Block::DeleteFile,
+            MissionKind::Heist => Block::Transfer,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// How hard the target is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 4] = [Tier::Easy, Tier::Medium, Tier::Hard, Tier::Expert];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Tier::Easy => "easy",
+            Tier::Medium => "medium",
+            Tier::Hard => "hard",
+            Tier::Expert => "expert",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// What an objective establishes for the ones after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fact {
+    Located,
+    Access,
+    Data,
+    Delivered,
+    Destroyed,
+    Money,
+    Clean,
+}
+
+/// A reusable objective
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Block {
+    /// Find the target on the network
+    Locate,
+    Crack,
+    Download,
+    /// Upload the stolen file to the contact
+    Deliver,
+    DeleteFile,
+    Transfer,
+    /// Remove the player's entries from the target's log
+    ClearLogs,
+}
+
+impl Block {
+    pub fn requires(self) -> &'static [Fact] {
+        match self {
+            Block::Locate => &[],
+            Block::Crack => &[Fact::Located],
+            Block::Download | Block::DeleteFile | Block::Transfer | Block::ClearLogs => &[Fact::Access],
+            Block::Deliver => &[Fact::Data],
+        }
+    }
+
+    pub fn provides(self) -> Fact {
+        match self {
+            Block::Locate => Fact::Located,
+            Block::Crack => Fact::Access,
+            Block::Download => Fact::Data,
+            Block::Deliver => Fact::Delivered,
+            Block::DeleteFile => Fact::Destroyed,
+            Block::Transfer => Fact::Money,
+            Block::ClearLogs => Fact::Clean,
+        }
+    }
+
+    /// The block that establishes `fact`
+    fn provider(fact: Fact) -> Block {
+        match fact {
+            Fact::Located => Block::Locate,
+            Fact::Access => Block::Crack,
+            Fact::Data => Block::Download,
+            Fact::Delivered => Block::Deliver,
+            Fact::Destroyed => Block::DeleteFile,
+            Fact::Money => Block::Transfer,
+            Fact::Clean => Block::ClearLogs,
+        }
+    }
+
+    fn describe(self, ip: &str) -> String {
+        match self {
+            Block::Locate => format!("Locate the server at {}", ip),
+            Block::Crack => format!("Crack the root password of {}", ip),
+            Block::Download => format!("Download a file from {}", ip),
+            Block::Deliver => "Upload the stolen file to your contact".to_string(),
+            Block::DeleteFile => format!("Delete a file on {}", ip),
+            Block::Transfer => format!("Transfer money out of the bank at {}", ip),
+            Block::ClearLogs => format!("Clear your entries from the log of {}", ip),
+        }
+    }
+}
+
+/// One node of a mission's objective graph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Objective {
+    pub node: usize,
+    pub block: Block,
+    /// Nodes that must be done first
+    pub depends_on: Vec<usize>,
+    /// Bonus objectives are not needed to finish the mission
+    pub optional: bool,
+    pub description: String,
+}
+
+/// Objectives in dependency order: every node depends only on earlier ones
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ObjectiveGraph {
+    pub nodes: Vec<Objective>,
+}
+
+impl ObjectiveGraph {
+    /// The graph reaching `goal`, plus the `bonus` objectives, against the
+    /// server at `ip`
+    pub fn assemble(goal: Block, bonus: &[Block], ip: &str) -> Self {
+        let mut graph = Self::default();
+        graph.add(goal, false, ip);
+        for block in bonus {
+            graph.add(*block, true, ip);
+        }
+        graph
+    }
+
+    /// Add `block` after whatever provides what it needs, reusing nodes
+    /// already in the graph
+    fn add(&mut self, block: Block, optional: bool, ip: &str) -> usize {
+        if let Some(existing) = self.nodes.iter().find(|o| o.block == block) {
+            return existing.node;
+        }
+        let depends_on = block
+            .requires()
+            .iter()
+            .map(|fact| {
+                let provider = self.nodes.iter().find(|o| o.block.provides() == *fact).map(|o| o.node);
+                provider.unwrap_or_else(|| self.add(Block::provider(*fact), optional, ip))
+            })
+            .collect();
+
+        let node = self.nodes.len();
+        self.nodes.push(Objective { node, block, depends_on, optional, description: block.describe(ip) });
+        node
+    }
+
+    pub fn required(&self) -> usize {
+        self.nodes.iter().filter(|o| !o.optional).count()
+    }
+
+    pub fn optional(&self) -> usize {
+        self.nodes.len() - self.required()
+    }
+
+    /// Nodes `node` still waits on, given the ones `done`
+    pub fn waiting_on(&self, node: usize, done: &[usize]) -> Vec<usize> {
+        self.nodes
+            .get(node)
+            .map(|o| o.depends_on.iter().copied().filter(|d| !done.contains(d)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Every required objective is in `done`
+    pub fn is_complete(&self, done: &[usize]) -> bool {
+        self.nodes.iter().filter(|o| !o.optional).all(|o| done.contains(&o.node))
+    }
+
+    /// Every optional objective is in `done`
+    pub fn bonus_complete(&self, done: &[usize]) -> bool {
+        self.optional() > 0 && self.nodes.iter().filter(|o| o.optional).all(|o| done.contains(&o.node))
+    }
+}
+
+/// An NPC server a mission could be set on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Target {
+    pub server_id: i64,
+    pub ip: String,
+    /// Best firewall installed, 0 for none
+    pub firewall: f64,
+    /// Files on the server
+    pub files: i64,
+    /// The bank running on it, if any
+    pub bank_id: Option<i64>,
+}
+
+impl Target {
+    fn suits(&self, kind: MissionKind) -> bool {
+        match kind {
+            MissionKind::DataTheft | MissionKind::Sabotage => self.files > 0,
+            MissionKind::Heist => self.bank_id.is_some(),
+        }
+    }
+}
+
+/// A mission the player was offered recently
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentMission {
+    pub kind: MissionKind,
+    pub server_id: Option<i64>,
+}
+
+/// What generation knows about the player
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    pub level: i32,
+    /// Best cracker the player owns, 0 for none
+    pub cracker: f64,
+    /// Newest first
+    pub recent: Vec<RecentMission>,
+}
+
+/// A mission ready to offer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub kind: MissionKind,
+    pub tier: Tier,
+    pub target: Target,
+    pub objectives: ObjectiveGraph,
+    pub reward_money: i64,
+    /// Paid on top when every bonus objective is done
+    pub bonus_money: i64,
+    pub reward_experience: i64,
+}
+
+/// Why a plan could not be offered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+pub enum Issue {
+    /// The level allows no tier, or every allowed tier weighs nothing
+    NoTierForLevel { level: i32 },
+    /// No server suits the tier and kind, or all of them were used recently
+    NoTarget { firewall_min: f64, firewall_max: f64 },
+    CrackerTooWeak { firewall: f64, cracker: f64 },
+    NothingToSteal,
+    NoBank,
+    /// A node depends on a later node, or on nothing providing what it needs
+    BrokenGraph { node: usize },
+    MissingGoal,
+    TooManyObjectives { count: usize, max: usize },
+    NoReward,
+}
+
+/// One try at a plan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attempt {
+    pub tier: Option<Tier>,
+    pub kind: Option<MissionKind>,
+    pub target_id: Option<i64>,
+    /// Servers that suited the tier and kind
+    pub candidates: usize,
+    /// Empty for the attempt that was offered
+    pub issues: Vec<Issue>,
+}
+
+/// Everything one generation tried, for the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Generation {
+    pub seed: u64,
+    pub attempts: Vec<Attempt>,
+    pub plan: Option<Plan>,
+}
+
+/// Generate a mission for `player` among `targets`
+pub fn generate(player: &PlayerProfile, targets: &[Target], seed: u64, config: &MissionGenConfig) -> Generation {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut attempts = Vec::new();
+
+    for _ in 0..config.attempts.max(1) {
+        let (attempt, plan) = attempt(player, targets, &mut rng, config);
+        // Retrying cannot help a level no tier allows
+        let hopeless = matches!(attempt.issues.first(), Some(Issue::NoTierForLevel { .. }));
+        attempts.push(attempt);
+        if plan.is_some() || hopeless {
+            return Generation { seed, attempts, plan };
+        }
+    }
+
+    Generation { seed, attempts, plan: None }
+}
+
+fn attempt(player: &PlayerProfile, targets: &[Target], rng: &mut StdRng, config: &MissionGenConfig) -> (Attempt, Option<Plan>) {
+    let mut attempt = Attempt { tier: None, kind: None, target_id: None, candidates: 0, issues: Vec::new() };
+
+    let tier_weights: Vec<f64> = Tier::ALL
+        .iter()
+        .map(|tier| {
+            let allowed = player.level >= config.tier_min_level[tier.index()];
+            if allowed { config.tier_weights[tier.index()].max(0.0) } else { 0.0 }
+        })
+        .collect();
+    let Some(tier) = pick(&tier_weights, rng).map(|i| Tier::ALL[i]) else {
+        attempt.issues.push(Issue::NoTierForLevel { level: player.level });
+        return (attempt, None);
+    };
+    attempt.tier = Some(tier);
+
+    let kind_weights: Vec<f64> = MissionKind::ALL
+        .iter()
+        .map(|kind| {
+            let recent = player.recent.iter().take(config.variety_window).filter(|r| r.kind == *kind).count();
+            config.kind_weights[kind.index()].max(0.0) * config.variety_penalty.powi(recent as i32)
+        })
+        .collect();
+    let kind = MissionKind::ALL[pick(&kind_weights, rng).unwrap_or(0)];
+    attempt.kind = Some(kind);
+
+    let (low, high) = config.tier_firewall_ratio[tier.index()];
+    let (firewall_min, firewall_max) = (low * player.cracker, high * player.cracker);
+    let recent_targets: Vec<i64> =
+        player.recent.iter().take(config.variety_window).filter_map(|r| r.server_id).collect();
+    let candidates: Vec<&Target> = targets
+        .iter()
+        .filter(|t| t.firewall >= firewall_min && t.firewall <= firewall_max)
+        .filter(|t| !recent_targets.contains(&t.server_id))
+        .filter(|t| t.suits(kind))
+        .collect();
+    attempt.candidates = candidates.len();
+    if candidates.is_empty() {
+        attempt.issues.push(Issue::NoTarget { firewall_min, firewall_max });
+        return (attempt, None);
+    }
+    let target = candidates[rng.gen_range(0..candidates.len())].clone();
+    attempt.target_id = Some(target.server_id);
+
+    let bonus: &[Block] = if rng.gen_bool(config.cover_tracks_chance.clamp(0.0, 1.0)) { &[Block::ClearLogs] } else { &[] };
+    let objectives = ObjectiveGraph::assemble(kind.goal(), bonus, &target.ip);
+
+    let (reward_money, reward_experience) = reward(tier, objectives.required(), player.level, config);
+    let variance = 1.0 + config.reward_variance * rng.gen_range(-1.0..=1.0);
+    let reward_money = (reward_money as f64 * variance).round() as i64;
+    let bonus_money = if objectives.optional() > 0 {
+        (reward_money as f64 * (config.bonus_objective_multiplier - 1.0).max(0.0)).round() as i64
+    } else {
+        0
+    };
+
+    let plan = Plan { kind, tier, target, objectives, reward_money, bonus_money, reward_experience };
+    attempt.issues = validate(&plan, player, config);
+    if attempt.issues.is_empty() {
+        (attempt, Some(plan))
+    } else {
+        (attempt, None)
+    }
+}
+
+/// Money and experience for a mission of `tier` with `objectives` required
+/// objectives, before variance
+pub fn reward(tier: Tier, objectives: usize, level: i32, config: &MissionGenConfig) -> (i64, i64) {
+    let scale = |base: i64| {
+        let objectives = 100 + config.reward_per_objective_percent * objectives as i64;
+        let level = 100 + config.reward_per_level_percent * (level.max(1) - 1) as i64;
+        base * objectives / 100 * level / 100
+    };
+    (scale(config.base_reward[tier.index()]), scale(config.base_experience[tier.index()]))
+}
+
+/// What keeps `plan` from being finished by `player`
+pub fn validate(plan: &Plan, player: &PlayerProfile, config: &MissionGenConfig) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let graph = &plan.objectives;
+
+    if plan.target.firewall > player.cracker * config.max_firewall_ratio {
+        issues.push(Issue::CrackerTooWeak { firewall: plan.target.firewall, cracker: player.cracker });
+    }
+    let needs_files = graph.nodes.iter().any(|o| matches!(o.block, Block::Download | Block::DeleteFile));
+    if needs_files && plan.target.files <= 0 {
+        issues.push(Issue::NothingToSteal);
+    }
+    if graph.nodes.iter().any(|o| o.block == Block::Transfer) && plan.target.bank_id.is_none() {
+        issues.push(Issue::NoBank);
+    }
+
+    for objective in &graph.nodes {
+        let ordered = objective.depends_on.iter().all(|d| *d < objective.node);
+        let satisfied = objective.block.requires().iter().all(|fact| {
+            objective.depends_on.iter().any(|d| graph.nodes.get(*d).is_some_and(|o| o.block.provides() == *fact))
+        });
+        if !ordered || !satisfied {
+            issues.push(Issue::BrokenGraph { node: objective.node });
+        }
+    }
+    if !graph.nodes.iter().any(|o| o.block == plan.kind.goal() && !o.optional) {
+        issues.push(Issue::MissingGoal);
+    }
+    if graph.nodes.len() > config.max_objectives {
+        issues.push(Issue::TooManyObjectives { count: graph.nodes.len(), max: config.max_objectives });
+    }
+    if plan.reward_money <= 0 && plan.reward_experience <= 0 {
+        issues.push(Issue::NoReward);
+    }
+
+    issues
+}
+
+/// Index picked with probability proportional to its weight
+fn pick(weights: &[f64], rng: &mut StdRng) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut roll = rng.gen_range(0.0..total);
+    for (i, weight) in weights.iter().enumerate() {
+        if roll < *weight {
+            return Some(i);
+        }
+        roll -= weight;
+    }
+    weights.iter().rposition(|w| *w > 0.0)
+}
+
+/// Why a generated mission action was refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum GeneratedMissionDenied {
+    NotFound,
+    /// Only offered missions can be accepted
+    NotOffered,
+    NotActive,
+    Expired,
+    TooManyActive { max: i64 },
+    UnknownObjective,
+    ObjectiveDone,
+    /// The objective waits on these nodes
+    ObjectiveLocked { waiting_on: Vec<usize> },
+    /// A mission of this kind was finished too recently
+    OnCooldown { minutes: u64 },
+}
+
+impl GeneratedMissionDenied {
+    pub fn message(&self) -> String {
+        match self {
+            GeneratedMissionDenied::NotFound => "Mission not found".to_string(),
+            GeneratedMissionDenied::NotOffered => "This mission is no longer on offer".to_string(),
+            GeneratedMissionDenied::NotActive => "This mission is not in progress".to_string(),
+            GeneratedMissionDenied::Expired => "This offer has expired".to_string(),
+            GeneratedMissionDenied::TooManyActive { max } => {
+                format!("You can run at most {} generated missions at once", max)
+            }
+            GeneratedMissionDenied::UnknownObjective => "Unknown objective".to_string(),
+            GeneratedMissionDenied::ObjectiveDone => "Objective already done".to_string(),
+            GeneratedMissionDenied::ObjectiveLocked { .. } => "Finish the earlier objectives first".to_string(),
+            GeneratedMissionDenied::OnCooldown { minutes } => {
+                format!("You can take another mission like this in {} minutes", minutes)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(server_id: i64, firewall: f64, bank_id: Option<i64>) -> Target {
+        Target { server_id, ip: format!("10.0.0.{}", server_id), firewall, files: 3, bank_id }
+    }
+
+    #[test]
+    fn test_graph_pulls_in_dependencies() {
+        let graph = ObjectiveGraph::assemble(Block::Deliver, &[Block::ClearLogs], "1.2.3.4");
+        let blocks: Vec<Block> = graph.nodes.iter().map(|o| o.block).collect();
+        assert_eq!(blocks, [Block::Locate, Block::Crack, Block::Download, Block::Deliver, Block::ClearLogs]);
+        assert_eq!(graph.nodes[4].depends_on, [1]);
+        assert_eq!(graph.required(), 4);
+
+        assert_eq!(graph.waiting_on(3, &[0, 1]), [2]);
+        assert!(graph.is_complete(&[0, 1, 2, 3]));
+        assert!(!graph.bonus_complete(&[0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_generation_is_seeded_and_valid() {
+        let config = MissionGenConfig::default();
+        let player = PlayerProfile { level: 40, cracker: 5.0, recent: Vec::new() };
+        let targets: Vec<Target> = (1..=40).map(|i| target(i, i as f64 * 0.2, (i % 3 == 0).then_some(i))).collect();
+
+        let first = generate(&player, &targets, 7, &config);
+        assert_eq!(first, generate(&player, &targets, 7, &config));
+        let plan = first.plan.expect("a plan");
+        assert!(validate(&plan, &player, &config).is_empty());
+        assert!(plan.reward_money > 0);
+        assert!(first.attempts.last().unwrap().issues.is_empty());
+    }
+
+    #[test]
+    fn test_variety_and_impossible_missions() {
+        let mut config = MissionGenConfig::default();
+        config.kind_weights = [1.0, 1.0, 0.0];
+        config.variety_penalty = 0.0;
+        let recent = vec![RecentMission { kind: MissionKind::DataTheft, server_id: Some(1) }];
+        let player = PlayerProfile { level: 1, cracker: 2.0, recent };
+        let targets = [target(1, 0.5, None), target(2, 0.5, None)];
+
+        let generation = generate(&player, &targets, 1, &config);
+        let plan = generation.plan.expect("a plan");
+        assert_eq!(plan.kind, MissionKind::Sabotage);
+        assert_eq!(plan.target.server_id, 2);
+
+        // No cracker: nothing fits, and every attempt says why
+        let player = PlayerProfile { level: 1, cracker: 0.0, recent: Vec::new() };
+        let generation = generate(&player, &targets, 1, &config);
+        assert!(generation.plan.is_none());
+        assert_eq!(generation.attempts.len(), config.attempts);
+        assert!(matches!(generation.attempts[0].issues[0], Issue::NoTarget { .. }));
+
+        let mut plan = plan;
+        plan.target.firewall = 100.0;
+        plan.target.files = 0;
+        let issues = validate(&plan, &PlayerProfile { level: 1, cracker: 2.0, recent: Vec::new() }, &config);
+        assert!(issues.contains(&Issue::NothingToSteal));
+        assert!(issues.iter().any(|i| matches!(i, Issue::CrackerTooWeak { .. })));
+    }
+}
@@ -2,10 +2,14 @@
 
 use crate::{PlayerState, TargetInfo};
 use crate::config::MissionConfig;
+use crate::process::ProcessType;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
+use he_core::clock;
 
 /// Mission types
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -455,7 +459,7 @@ impl Mission {
         
         // Check cooldown
         if let Some(next) = self.next_available {
-            if SystemTime::now() < next {
+            if clock::system_now() < next {
                 return Err("Mission is on cooldown".to_string());
             }
         }
@@ -469,12 +473,12 @@ impl Mission {
         }
         
         self.status = MissionStatus::Active;
-        self.started_at = Some(SystemTime::now());
+        self.started_at = Some(clock::system_now());
         self.attempts += 1;
         
         // Set expiration if time limit exists
         if let Some(limit) = self.time_limit {
-            self.expires_at = Some(SystemTime::now() + limit);
+            self.expires_at = Some(clock::system_now() + limit);
         }
         
         Ok(())
@@ -493,7 +497,7 @@ impl Mission {
         }
         
         self.status = MissionStatus::Completed;
-        self.completed_at = Some(SystemTime::now());
+        self.completed_at = Some(clock::system_now());
         
         // Calculate bonus rewards for optional objectives
         let mut total_reward = self.rewards.clone();
@@ -511,7 +515,7 @@ impl Mission {
         // Set cooldown for repeatable missions
         if self.is_repeatable {
             if let Some(cooldown) = self.cooldown {
-                self.next_available = Some(SystemTime::now() + cooldown);
+                self.next_available = Some(clock::system_now() + cooldown);
             }
         }
         
@@ -520,11 +524,11 @@ impl Mission {
     
     pub fn fail(&mut self) -> Option<MissionPenalty> {
         self.status = MissionStatus::Failed;
-        self.completed_at = Some(SystemTime::now());
+        self.completed_at = Some(clock::system_now());
         
         // Set cooldown even on failure
         if let Some(cooldown) = self.cooldown {
-            self.next_available = Some(SystemTime::now() + cooldown);
+            self.next_available = Some(clock::system_now() + cooldown);
         }
         
         self.failure_penalty.clone()
@@ -532,12 +536,12 @@ impl Mission {
     
     pub fn abandon(&mut self) {
         self.status = MissionStatus::Abandoned;
-        self.completed_at = Some(SystemTime::now());
+        self.completed_at = Some(clock::system_now());
     }
     
     pub fn get_time_remaining(&self) -> Option<Duration> {
         if let Some(expires) = self.expires_at {
-            SystemTime::now().duration_since(expires).ok()
+            clock::system_now().duration_since(expires).ok()
         } else {
             None
         }
@@ -545,7 +549,7 @@ impl Mission {
     
     pub fn is_expired(&self) -> bool {
         if let Some(expires) = self.expires_at {
-            SystemTime::now() > expires
+            clock::system_now() > expires
         } else {
             false
         }
@@ -694,6 +698,7 @@ pub struct MissionManager {
     pub mission_chains: HashMap<String, Vec<MissionType>>,
     pub daily_missions: Vec<Mission>,
     pub special_events: Vec<Mission>,
+    pub history: MissionHistory,
 }
 
 impl MissionManager {
@@ -706,6 +711,7 @@ impl MissionManager {
             mission_chains: HashMap::new(),
             daily_missions: Vec::new(),
             special_events: Vec::new(),
+            history: MissionHistory::new(),
         }
     }
     
@@ -745,6 +751,38 @@ impl MissionManager {
         Ok(())
     }
     
+    /// Start a mission, also enforcing the per-template cooldown
+    pub fn start_mission_limited(
+        &mut self,
+        mission_id: Uuid,
+        player: &PlayerState,
+        config: &MissionConfig
+    ) -> Result<(), String> {
+        let mission = self.missions.get(&mission_id)
+            .ok_or("Mission not found")?;
+
+        self.history.check_cooldown(&mission.mission_type, clock::system_now(), config)?;
+        self.start_mission(mission_id, player)
+    }
+
+    /// Complete a mission and pay out the anti-farm scaled reward
+    pub fn complete_mission_scaled(
+        &mut self,
+        mission_id: Uuid,
+        player: &PlayerState,
+        median_level: i32,
+        config: &MissionConfig
+    ) -> Result<ScaledReward, String> {
+        let reward = self.complete_mission(mission_id)?;
+        let mission_type = self.missions[&mission_id].mission_type.clone();
+        let now = clock::system_now();
+
+        let scaled = scale_mission_reward(&reward, &mission_type, &self.history, player.level, median_level, now, config);
+        self.history.record(mission_type, &scaled.reward, now, config);
+
+        Ok(scaled)
+    }
+
     pub fn update_mission_progress(
         &mut self,
         mission_id: Uuid,
@@ -893,6 +931,246 @@ impl MissionManager {
     }
 }
 
+/// Completion history used for anti-farm reward scaling
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MissionHistory {
+    /// Completion times per mission template. A list rather than a map so it
+    /// serializes to JSON, whose keys cannot hold templates like `MainStory(3)`.
+    pub completions: Vec<(MissionType, Vec<SystemTime>)>,
+    /// Day (days since the Unix epoch, UTC) the daily totals belong to
+    pub day: u64,
+    pub daily_experience: i32,
+    pub daily_money: i32,
+}
+
+impl MissionHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn completions_of(&self, mission_type: &MissionType) -> Option<&Vec<SystemTime>> {
+        self.completions.iter().find(|(t, _)| t == mission_type).map(|(_, times)| times)
+    }
+
+    fn day_of(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86400).unwrap_or(0)
+    }
+
+    /// Daily totals, reset once the UTC day rolls over
+    pub fn daily_totals(&self, now: SystemTime) -> (i32, i32) {
+        if Self::day_of(now) == self.day {
+            (self.daily_experience, self.daily_money)
+        } else {
+            (0, 0)
+        }
+    }
+
+    /// Completions of this template within the repeat window
+    pub fn recent_repeats(&self, mission_type: &MissionType, now: SystemTime, config: &MissionConfig) -> usize {
+        let window = Duration::from_secs(config.repeat_window_hours * 3600);
+        self.completions_of(mission_type)
+            .map(|times| {
+                times.iter()
+                    .filter(|t| now.duration_since(**t).map_or(true, |age| age < window))
+                    .count()
+            })
+            .unwrap_or(0)
+    }
+
+    /// How long this template is still on cooldown, if at all
+    pub fn cooldown_remaining(&self, mission_type: &MissionType, now: SystemTime, config: &MissionConfig) -> Option<Duration> {
+        let cooldown = Duration::from_secs(config.template_cooldown_minutes * 60);
+        let last = self.completions_of(mission_type).and_then(|times| times.iter().max())?;
+        let elapsed = now.duration_since(*last).unwrap_or(Duration::ZERO);
+
+        cooldown.checked_sub(elapsed).filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Err with the remaining wait if this template is still on cooldown
+    pub fn check_cooldown(&self, mission_type: &MissionType, now: SystemTime, config: &MissionConfig) -> Result<(), String> {
+        match self.cooldown_remaining(mission_type, now, config) {
+            Some(remaining) => {
+                Err(format!("Mission template is on cooldown for {} more minutes", remaining.as_secs().div_ceil(60)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record a completion and the reward actually paid
+    pub fn record(&mut self, mission_type: MissionType, reward: &MissionReward, now: SystemTime, config: &MissionConfig) {
+        let today = Self::day_of(now);
+        if today != self.day {
+            self.day = today;
+            self.daily_experience = 0;
+            self.daily_money = 0;
+        }
+        self.daily_experience += reward.experience;
+        self.daily_money += reward.money;
+
+        // Anything outside the repeat window no longer affects scaling
+        let window = Duration::from_secs(config.repeat_window_hours * 3600);
+        let index = match self.completions.iter().position(|(t, _)| *t == mission_type) {
+            Some(index) => index,
+            None => {
+                self.completions.push((mission_type, Vec::new()));
+                self.completions.len() - 1
+            }
+        };
+        let times = &mut self.completions[index].1;
+        times.retain(|t| now.duration_since(*t).map_or(true, |age| age < window));
+        times.push(now);
+    }
+}
+
+/// Reward after anti-farm scaling, with the factors that produced it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScaledReward {
+    pub reward: MissionReward,
+    pub repeat_multiplier: f64,
+    pub catch_up_multiplier: f64,
+    /// XP or money was cut by the daily cap
+    pub capped: bool,
+}
+
+/// Scale a mission reward for repeats, catch-up and daily caps.
+///
+/// Order: the catch-up bonus and repeat decay multiply money and experience,
+/// then the result is clamped to what is left of today's caps. Reputation,
+/// items and unlocks are not scaled.
+pub fn scale_mission_reward(
+    reward: &MissionReward,
+    mission_type: &MissionType,
+    history: &MissionHistory,
+    player_level: i32,
+    median_level: i32,
+    now: SystemTime,
+    config: &MissionConfig,
+) -> ScaledReward {
+    let levels_behind = (median_level - player_level).max(0);
+    let catch_up_bonus = (config.catch_up_bonus_per_level * Decimal::from(levels_behind)).min(config.catch_up_bonus_max);
+    let catch_up_multiplier = 1.0 + catch_up_bonus.to_f64().unwrap_or(0.0);
+
+    let repeats = history.recent_repeats(mission_type, now, config) as i32;
+    let repeat_multiplier = config.repeat_decay_factor.to_f64().unwrap_or(1.0)
+        .powi(repeats)
+        .max(config.repeat_decay_floor.to_f64().unwrap_or(0.0));
+
+    let multiplier = catch_up_multiplier * repeat_multiplier;
+    let mut scaled = reward.clone();
+    scaled.experience = (reward.experience as f64 * multiplier) as i32;
+    scaled.money = (reward.money as f64 * multiplier) as i32;
+    scaled.bitcoin = (reward.bitcoin as f64 * repeat_multiplier) as f32;
+
+    let (experience_today, money_today) = history.daily_totals(now);
+    let experience_left = (config.daily_experience_cap - experience_today).max(0);
+    let money_left = (config.daily_money_cap - money_today).max(0);
+    let capped = scaled.experience > experience_left || scaled.money > money_left;
+    scaled.experience = scaled.experience.min(experience_left);
+    scaled.money = scaled.money.min(money_left);
+
+    ScaledReward {
+        reward: scaled,
+        repeat_multiplier,
+        catch_up_multiplier,
+        capped,
+    }
+}
+
+/// One member's part in a co-op mission
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoopMember {
+    pub user_id: i64,
+    /// Objective progress this member contributed
+    pub contribution: i32,
+    pub abandoned: bool,
+}
+
+/// A member's cut of a co-op mission reward
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoopShare {
+    pub user_id: i64,
+    pub money: i64,
+    pub experience: i64,
+}
+
+/// What a member abandoning a running co-op mission does to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoopAbandonOutcome {
+    /// Enough members remain; the leaver forfeits their share
+    Continue,
+    /// Too few members remain to finish
+    Fail,
+}
+
+/// How many members a co-op template takes, capped by the config
+pub fn coop_member_limit(template_limit: i32, config: &MissionConfig) -> usize {
+    (template_limit.max(0) as usize).min(config.coop_max_members)
+}
+
+/// Shared progress a member's completed process earns a co-op mission, if it
+/// is the mission's objective. Missions without an objective count any
+/// process that acts on another server.
+pub fn coop_progress(process_type: &ProcessType, objective: Option<&str>, config: &MissionConfig) -> Option<i32> {
+    let counts = match objective {
+        Some(objective) => ProcessType::from_str(objective) == *process_type,
+        None => process_type.target_log_type().is_some(),
+    };
+    counts.then_some(config.coop_progress_per_process.max(0))
+}
+
+/// Whether a running co-op mission survives its members abandoning it
+pub fn coop_abandon_outcome(members: &[CoopMember], config: &MissionConfig) -> CoopAbandonOutcome {
+    let remaining = members.iter().filter(|m| !m.abandoned).count();
+    if remaining < config.coop_min_members {
+        CoopAbandonOutcome::Fail
+    } else {
+        CoopAbandonOutcome::Continue
+    }
+}
+
+/// Split a co-op reward between the members still in the group.
+///
+/// `coop_equal_share` of the money and experience is divided evenly, the rest
+/// by contribution. Members who abandoned get nothing. Rounding leftovers go
+/// to the top contributor so the shares add up to the reward.
+pub fn split_coop_reward(
+    money: i64,
+    experience: i64,
+    members: &[CoopMember],
+    config: &MissionConfig,
+) -> Vec<CoopShare> {
+    let finishers: Vec<&CoopMember> = members.iter().filter(|m| !m.abandoned).collect();
+    let Some(top) = finishers.iter().enumerate().max_by_key(|(_, m)| m.contribution).map(|(i, _)| i) else {
+        return Vec::new();
+    };
+
+    let even = Decimal::ONE / Decimal::from(finishers.len());
+    let total_contribution: i64 = finishers.iter().map(|m| m.contribution.max(0) as i64).sum();
+    let equal_share = config.coop_equal_share.clamp(Decimal::ZERO, Decimal::ONE);
+    let weight = |member: &CoopMember| {
+        if total_contribution == 0 {
+            even
+        } else {
+            equal_share * even
+                + (Decimal::ONE - equal_share) * Decimal::from(member.contribution.max(0)) / Decimal::from(total_contribution)
+        }
+    };
+    let cut = |amount: i64, weight: Decimal| (Decimal::from(amount) * weight).floor().to_i64().unwrap_or(0);
+
+    let mut shares: Vec<CoopShare> = finishers
+        .iter()
+        .map(|member| CoopShare {
+            user_id: member.user_id,
+            money: cut(money, weight(member)),
+            experience: cut(experience, weight(member)),
+        })
+        .collect();
+
+    shares[top].money += money - shares.iter().map(|s| s.money).sum::<i64>();
+    shares[top].experience += experience - shares.iter().map(|s| s.experience).sum::<i64>();
+    shares
+}
+
 /// Calculate mission difficulty with scaling
 pub fn calculate_mission_difficulty(
     mission: &Mission,
@@ -1142,4 +1420,120 @@ mod tests {
         let total_value = reward.calculate_total_value();
         assert!(total_value > 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_repeat_decay_and_cooldown() {
+        let config = MissionConfig::default();
+        let reward = MissionReward::new().with_money(1000).with_experience(400);
+        let mut history = MissionHistory::new();
+        let now = SystemTime::now();
+
+        let first = scale_mission_reward(&reward, &MissionType::HackServer, &history, 10, 10, now, &config);
+        assert_eq!(first.reward.money, 1000);
+        history.record(MissionType::HackServer, &first.reward, now, &config);
+
+        assert!(history.check_cooldown(&MissionType::HackServer, now + Duration::from_secs(60), &config).is_err());
+        assert!(history.check_cooldown(&MissionType::StealData, now, &config).is_ok());
+
+        let later = now + Duration::from_secs(3600);
+        assert!(history.check_cooldown(&MissionType::HackServer, later, &config).is_ok());
+        let second = scale_mission_reward(&reward, &MissionType::HackServer, &history, 10, 10, later, &config);
+        assert_eq!(second.reward.money, 750);
+
+        // Repeats outside the window are forgiven
+        let next_day = now + Duration::from_secs(25 * 3600);
+        let fresh = scale_mission_reward(&reward, &MissionType::HackServer, &history, 10, 10, next_day, &config);
+        assert_eq!(fresh.repeat_multiplier, 1.0);
+    }
+
+    #[test]
+    fn test_history_round_trips_through_json() {
+        let config = MissionConfig::default();
+        let mut history = MissionHistory::new();
+        let now = SystemTime::now();
+        history.record(MissionType::MainStory(3), &MissionReward::new().with_money(100), now, &config);
+        history.record(MissionType::Custom("heist".to_string()), &MissionReward::new().with_money(50), now, &config);
+
+        let json = serde_json::to_string(&history).unwrap();
+        let restored: MissionHistory = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.recent_repeats(&MissionType::MainStory(3), now, &config), 1);
+        assert!(restored.check_cooldown(&MissionType::Custom("heist".to_string()), now, &config).is_err());
+        assert_eq!(restored.daily_totals(now), (0, 150));
+    }
+
+    #[test]
+    fn test_mission_config_defaults_missing_fields() {
+        let config: MissionConfig = serde_json::from_str(r#"{"daily_mission_limit": 5}"#).unwrap();
+        assert_eq!(config.daily_mission_limit, 5);
+        assert_eq!(config.template_cooldown_minutes, MissionConfig::default().template_cooldown_minutes);
+    }
+
+    #[test]
+    fn test_daily_cap_and_catch_up() {
+        let config = MissionConfig::default();
+        let reward = MissionReward::new().with_money(1000).with_experience(1000);
+        let mut history = MissionHistory::new();
+        let now = SystemTime::now();
+
+        // 10 levels behind the median: +20%
+        let boosted = scale_mission_reward(&reward, &MissionType::StealData, &history, 5, 15, now, &config);
+        assert_eq!(boosted.reward.experience, 1200);
+        assert!(!boosted.capped);
+
+        history.record(MissionType::HackServer, &MissionReward::new().with_experience(4500), now, &config);
+        let capped = scale_mission_reward(&reward, &MissionType::StealData, &history, 15, 15, now, &config);
+        assert_eq!(capped.reward.experience, 500);
+        assert!(capped.capped);
+    }
+
+    #[test]
+    fn test_coop_reward_split() {
+        let config = MissionConfig::default();
+        let members = vec![
+            CoopMember { user_id: 1, contribution: 60, abandoned: false },
+            CoopMember { user_id: 2, contribution: 40, abandoned: false },
+            CoopMember { user_id: 3, contribution: 25, abandoned: true },
+        ];
+
+        let shares = split_coop_reward(10_000, 1_001, &members, &config);
+        assert_eq!(shares.len(), 2);
+        // 30% evenly, 70% by contribution: 1500 + 4200 and 1500 + 2800
+        assert_eq!(shares[0], CoopShare { user_id: 1, money: 5_700, experience: 571 });
+        assert_eq!(shares[1], CoopShare { user_id: 2, money: 4_300, experience: 430 });
+
+        // Nobody contributed: an even split
+        let idle = vec![
+            CoopMember { user_id: 1, contribution: 0, abandoned: false },
+            CoopMember { user_id: 2, contribution: 0, abandoned: false },
+        ];
+        let shares = split_coop_reward(100, 0, &idle, &config);
+        assert_eq!(shares.iter().map(|s| s.money).collect::<Vec<_>>(), vec![50, 50]);
+    }
+
+    #[test]
+    fn test_coop_abandon_outcome() {
+        let config = MissionConfig::default();
+        let mut members = vec![
+            CoopMember { user_id: 1, contribution: 10, abandoned: false },
+            CoopMember { user_id: 2, contribution: 0, abandoned: false },
+            CoopMember { user_id: 3, contribution: 5, abandoned: true },
+        ];
+        assert_eq!(coop_abandon_outcome(&members, &config), CoopAbandonOutcome::Continue);
+
+        members[1].abandoned = true;
+        assert_eq!(coop_abandon_outcome(&members, &config), CoopAbandonOutcome::Fail);
+        assert_eq!(coop_member_limit(8, &config), 5);
+    }
+
+    #[test]
+    fn test_coop_progress_counts_objective_processes() {
+        let config = MissionConfig::default();
+        let per_process = Some(config.coop_progress_per_process);
+        assert_eq!(coop_progress(&ProcessType::Download, Some("download"), &config), per_process);
+        assert_eq!(coop_progress(&ProcessType::BruteForce, Some("brute_force"), &config), per_process);
+        assert_eq!(coop_progress(&ProcessType::Upload, Some("download"), &config), None);
+        // Without an objective only processes against another server count
+        assert_eq!(coop_progress(&ProcessType::Crack, None, &config), per_process);
+        assert_eq!(coop_progress(&ProcessType::BitcoinMine, None, &config), None);
+    }
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
+use he_core::clock;
 
 /// Safe, original mission system - no HE2 AGPL content
 /// All content here is original and safe for closed-source/open-core use
@@ -675,7 +676,7 @@ impl MissionProgress {
         Self {
             mission_id,
             player_id,
-            started_at: chrono::Utc::now(),
+            started_at: clock::now(),
             completed_at: None,
             objectives_completed: vec![],
             attempts_used: 1,
@@ -702,7 +703,7 @@ impl MissionProgress {
     }
 
     pub fn complete_mission(&mut self, score: Option<u32>) {
-        self.completed_at = Some(chrono::Utc::now());
+        self.completed_at = Some(clock::now());
         self.score = score;
     }
 
@@ -7,6 +7,7 @@ use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::{IpAddr, Ipv4Addr};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
+use he_core::clock;
 
 /// Network node types
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -132,7 +133,7 @@ impl NetworkNode {
             routing_table: HashMap::new(),
             access_logs: VecDeque::with_capacity(1000),
             intrusion_attempts: 0,
-            last_maintenance: Some(SystemTime::now()),
+            last_maintenance: Some(clock::system_now()),
             uptime: Duration::from_secs(0),
             hardware_specs: None,
         }
@@ -321,7 +322,7 @@ impl NetworkConnection {
             source_node: source,
             destination_node: destination,
             connection_type,
-            established_at: SystemTime::now(),
+            established_at: clock::system_now(),
             latency_ms: 50,
             packet_loss: 0.0,
             bandwidth_allocated: 100,
@@ -636,7 +637,7 @@ pub fn detect_intrusion(node: &mut NetworkNode, connection: &NetworkConnection)
     // Check for port scanning
     let recent_connections = node.access_logs.iter()
         .filter(|log| {
-            SystemTime::now().duration_since(log.timestamp)
+            clock::system_now().duration_since(log.timestamp)
                 .unwrap_or_default().as_secs() < 60
         })
         .count();
@@ -672,7 +673,7 @@ pub fn detect_intrusion(node: &mut NetworkNode, connection: &NetworkConnection)
         node.intrusion_attempts += 1;
         
         Some(IntrusionAlert {
-            timestamp: SystemTime::now(),
+            timestamp: clock::system_now(),
             node_id: node.id,
             source_ip: node.ip_address, // Should be from connection
             intrusion_type: alert_type,
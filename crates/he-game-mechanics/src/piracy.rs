@@ -0,0 +1,155 @@
+//! Software piracy and copy protection
+//!
+//! Owners of copy-protected titles can license-lock a copy to the server it
+//! is on. Downloading a locked copy still works, but the stolen copy stays
+//! locked to the victim's server and does not count on the thief's until a
+//! keygen cracks it, which takes a strong cracker. A locked copy may also
+//! come with its owner's trojan: once the keygen runs it, the owner gets into
+//! the thief's gateway.
+
+use crate::config::PiracyConfig;
+use serde::{Deserialize, Serialize};
+
+/// Whether a copy locked to `license_server_id`, if at all, runs on
+/// `server_id`
+pub fn runs_on(license_server_id: Option<i64>, server_id: i64) -> bool {
+    license_server_id.map_or(true, |licensed| licensed == server_id)
+}
+
+/// Why a copy could not be locked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ProtectDenied {
+    NotFound,
+    /// The title has no copy protection
+    Unsupported,
+    AlreadyLocked,
+    /// Only the licensor locks copies, not whoever stole one
+    Pirated,
+}
+
+impl ProtectDenied {
+    pub fn message(&self) -> String {
+        match self {
+            ProtectDenied::NotFound => "You have no such software".to_string(),
+            ProtectDenied::Unsupported => "That title has no copy protection".to_string(),
+            ProtectDenied::AlreadyLocked => "That copy is already license-locked".to_string(),
+            ProtectDenied::Pirated => "A stolen copy cannot be licensed".to_string(),
+        }
+    }
+}
+
+/// Lock a copy of a title that `supported` copy protection
+pub fn check_protect(supported: bool, locked: bool, pirated: bool) -> Result<(), ProtectDenied> {
+    if pirated {
+        return Err(ProtectDenied::Pirated);
+    }
+    if !supported {
+        return Err(ProtectDenied::Unsupported);
+    }
+    if locked {
+        return Err(ProtectDenied::AlreadyLocked);
+    }
+    Ok(())
+}
+
+/// Why a keygen could not start
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum KeygenDenied {
+    NotFound,
+    /// Not locked, or locked to the server it is on
+    NotLocked,
+    InProgress,
+    CrackerTooWeak { required: f64, best: f64 },
+}
+
+impl KeygenDenied {
+    pub fn message(&self) -> String {
+        match self {
+            KeygenDenied::NotFound => "You have no such software".to_string(),
+            KeygenDenied::NotLocked => "That copy already runs where it is".to_string(),
+            KeygenDenied::InProgress => "A keygen is already running on that copy".to_string(),
+            KeygenDenied::CrackerTooWeak { required, best } => {
+                format!("A keygen needs a cracker of version {:.1}; your best is {:.1}", required, best)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KeygenQuote {
+    pub duration_secs: i64,
+    /// Version of the cracker it runs with
+    pub cracker: f64,
+}
+
+/// Quote a keygen on a copy of version `software_version` that does not run
+/// where it is unless `runs_here`, for a player whose best usable cracker is
+/// `cracker`
+pub fn check_keygen(
+    software_version: f64,
+    runs_here: bool,
+    running: bool,
+    cracker: Option<f64>,
+    config: &PiracyConfig,
+) -> Result<KeygenQuote, KeygenDenied> {
+    if runs_here {
+        return Err(KeygenDenied::NotLocked);
+    }
+    if running {
+        return Err(KeygenDenied::InProgress);
+    }
+    let cracker = cracker.unwrap_or(0.0);
+    if cracker < config.keygen_min_cracker {
+        return Err(KeygenDenied::CrackerTooWeak { required: config.keygen_min_cracker, best: cracker });
+    }
+
+    let secs = config.keygen_secs_per_version as f64 * software_version.max(1.0).powi(2) / cracker;
+    Ok(KeygenQuote { duration_secs: (secs.round() as i64).max(config.keygen_min_secs), cracker })
+}
+
+/// Whether a stolen copy carries its owner's trojan, for a uniform `roll` in
+/// `[0, 1)`. Only locked copies are booby-trapped.
+pub fn trojaned(locked: bool, roll: f64, config: &PiracyConfig) -> bool {
+    locked && roll < config.trojan_chance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locks_and_trojans() {
+        let config = PiracyConfig::default();
+        assert!(runs_on(None, 7));
+        assert!(runs_on(Some(7), 7));
+        assert!(!runs_on(Some(7), 8));
+
+        assert_eq!(check_protect(true, false, false), Ok(()));
+        assert_eq!(check_protect(false, false, false), Err(ProtectDenied::Unsupported));
+        assert_eq!(check_protect(true, true, false), Err(ProtectDenied::AlreadyLocked));
+        assert_eq!(check_protect(true, false, true), Err(ProtectDenied::Pirated));
+
+        assert!(trojaned(true, 0.1, &config));
+        assert!(!trojaned(true, 0.9, &config));
+        assert!(!trojaned(false, 0.0, &config));
+    }
+
+    #[test]
+    fn test_keygen_needs_strong_cracker() {
+        let config = PiracyConfig::default();
+        assert_eq!(check_keygen(4.0, true, false, Some(10.0), &config), Err(KeygenDenied::NotLocked));
+        assert_eq!(check_keygen(4.0, false, true, Some(10.0), &config), Err(KeygenDenied::InProgress));
+        assert_eq!(
+            check_keygen(4.0, false, false, Some(3.0), &config),
+            Err(KeygenDenied::CrackerTooWeak { required: 4.0, best: 3.0 })
+        );
+
+        let slow = check_keygen(6.0, false, false, Some(4.0), &config).unwrap();
+        let fast = check_keygen(6.0, false, false, Some(10.0), &config).unwrap();
+        assert_eq!(slow.duration_secs, 8100);
+        assert!(fast.duration_secs < slow.duration_secs);
+        assert_eq!(check_keygen(1.0, false, false, Some(10.0), &config).unwrap().duration_secs, config.keygen_min_secs);
+    }
+}
@@ -1,10 +1,23 @@
 //! Process system mechanics - Time calculations, resource management, scheduling
 
 use crate::config::ProcessConfig;
+use crate::rules::GameRules;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
+use he_core::clock;
+
+/// Every lower-case spelling [`ProcessType::from_str`] knows, the canonical
+/// one of each type first
+const SPELLINGS: &[&str] = &[
+    "download", "upload", "delete", "install", "uninstall", "crack", "decrypt", "encrypt",
+    "hide_log", "hidelog", "delete_log", "deletelog", "brute_force", "bruteforce",
+    "port_scan", "portscan", "system_scan", "systemscan", "virus_scan", "virusscan",
+    "anti_virus_run", "antivirus", "firewall_analysis", "ddos", "ddos_attack", "hijack",
+    "research", "upgrade", "bank_transfer", "bitcoin_mine", "bitcoin_transfer",
+    "mission_task", "mission", "reset_ip", "resetip", "repair", "keygen",
+];
 
 /// Process types in the game
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -33,6 +46,11 @@ pub enum ProcessType {
     BitcoinMine,
     BitcoinTransfer,
     MissionTask,
+    ResetIp,
+    /// Restores a worn or failed hardware component
+    Repair,
+    /// Cracks the license lock of a stolen copy
+    Keygen,
     Custom(String),
 }
 
@@ -63,9 +81,37 @@ impl ProcessType {
             "bitcoin_mine" => ProcessType::BitcoinMine,
             "bitcoin_transfer" => ProcessType::BitcoinTransfer,
             "mission" | "mission_task" => ProcessType::MissionTask,
+            "reset_ip" | "resetip" => ProcessType::ResetIp,
+            "repair" => ProcessType::Repair,
+            "keygen" => ProcessType::Keygen,
             other => ProcessType::Custom(other.to_string()),
         }
     }
+
+    /// Canonical lower-case name, the same for every spelling of the type
+    pub fn key(&self) -> String {
+        match self {
+            ProcessType::Custom(name) => name.to_lowercase(),
+            known => SPELLINGS
+                .iter()
+                .find(|spelling| &ProcessType::from_str(spelling) == known)
+                .map(|spelling| spelling.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Every lower-case spelling that parses to this type, for matching
+    /// stored process types
+    pub fn spellings(&self) -> Vec<String> {
+        match self {
+            ProcessType::Custom(name) => vec![name.to_lowercase()],
+            known => SPELLINGS
+                .iter()
+                .filter(|spelling| &ProcessType::from_str(spelling) == known)
+                .map(|spelling| spelling.to_string())
+                .collect(),
+        }
+    }
     
     pub fn base_complexity(&self) -> f32 {
         match self {
@@ -93,9 +139,54 @@ impl ProcessType {
             ProcessType::BitcoinMine => 4.0,
             ProcessType::BitcoinTransfer => 1.0,
             ProcessType::MissionTask => 2.0,
+            ProcessType::ResetIp => 2.0,
+            ProcessType::Repair => 1.0,
+            ProcessType::Keygen => 3.5,
             ProcessType::Custom(_) => 1.0,
         }
     }
+
+    /// Rewards granted to the owner when the process completes
+    pub fn completion_reward(&self) -> CompletionReward {
+        // 25 XP per point of complexity; paid IP resets and repairs earn nothing
+        let experience = match self {
+            ProcessType::ResetIp | ProcessType::Repair => 0,
+            _ => (self.base_complexity() * 25.0).round() as i64,
+        };
+        let money = match self {
+            ProcessType::BitcoinMine => 250,
+            _ => 0,
+        };
+        CompletionReward { experience, money }
+    }
+
+    /// [`completion_reward`](Self::completion_reward) with the server's XP
+    /// multiplier applied
+    pub fn completion_reward_under(&self, rules: &GameRules) -> CompletionReward {
+        let reward = self.completion_reward();
+        CompletionReward { experience: rules.experience(reward.experience), ..reward }
+    }
+
+    /// Log type left on the target server when the process completes, if it touches one
+    pub fn target_log_type(&self) -> Option<&'static str> {
+        match self {
+            ProcessType::Download => Some("download"),
+            ProcessType::Upload | ProcessType::Install => Some("upload"),
+            ProcessType::Delete | ProcessType::Uninstall => Some("delete"),
+            ProcessType::Crack | ProcessType::BruteForce | ProcessType::Hijack => Some("login"),
+            ProcessType::DDoSAttack => Some("ddos"),
+            // Log tampering and scans leave no trace
+            _ => None,
+        }
+    }
+}
+
+/// What a finished process grants its owner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletionReward {
+    pub experience: i64,
+    /// In cents, like bank balances
+    pub money: i64,
 }
 
 /// Process priority levels
@@ -213,15 +304,15 @@ impl Process {
     
     pub fn start(&mut self) {
         self.state = ProcessState::Running;
-        self.started_at = Some(SystemTime::now());
+        self.started_at = Some(clock::system_now());
     }
     
     pub fn pause(&mut self) {
         if self.state == ProcessState::Running {
             self.state = ProcessState::Paused;
-            self.paused_at = Some(SystemTime::now());
+            self.paused_at = Some(clock::system_now());
             if let Some(started) = self.started_at {
-                if let Ok(elapsed) = SystemTime::now().duration_since(started) {
+                if let Ok(elapsed) = clock::system_now().duration_since(started) {
                     self.elapsed_duration += elapsed;
                 }
             }
@@ -231,16 +322,16 @@ impl Process {
     pub fn resume(&mut self) {
         if self.state == ProcessState::Paused {
             self.state = ProcessState::Running;
-            self.started_at = Some(SystemTime::now());
+            self.started_at = Some(clock::system_now());
             self.paused_at = None;
         }
     }
     
     pub fn complete(&mut self) {
         self.state = ProcessState::Completed;
-        self.completed_at = Some(SystemTime::now());
+        self.completed_at = Some(clock::system_now());
         if let Some(started) = self.started_at {
-            if let Ok(elapsed) = SystemTime::now().duration_since(started) {
+            if let Ok(elapsed) = clock::system_now().duration_since(started) {
                 self.elapsed_duration += elapsed;
             }
         }
@@ -249,12 +340,12 @@ impl Process {
     pub fn fail(&mut self, error: String) {
         self.state = ProcessState::Failed;
         self.error_message = Some(error);
-        self.completed_at = Some(SystemTime::now());
+        self.completed_at = Some(clock::system_now());
     }
     
     pub fn cancel(&mut self) {
         self.state = ProcessState::Cancelled;
-        self.completed_at = Some(SystemTime::now());
+        self.completed_at = Some(clock::system_now());
     }
     
     pub fn get_progress(&self) -> f32 {
@@ -264,7 +355,7 @@ impl Process {
         
         let current_elapsed = if self.state == ProcessState::Running {
             if let Some(started) = self.started_at {
-                if let Ok(elapsed) = SystemTime::now().duration_since(started) {
+                if let Ok(elapsed) = clock::system_now().duration_since(started) {
                     self.elapsed_duration + elapsed
                 } else {
                     self.elapsed_duration
@@ -286,7 +377,7 @@ impl Process {
         }
         
         let current_elapsed = if let Some(started) = self.started_at {
-            if let Ok(elapsed) = SystemTime::now().duration_since(started) {
+            if let Ok(elapsed) = clock::system_now().duration_since(started) {
                 self.elapsed_duration + elapsed
             } else {
                 self.elapsed_duration
@@ -599,6 +690,17 @@ pub fn calculate_duration_extended(process_type: &str, player: &crate::extended:
     final_duration.min(config.max_process_time)
 }
 
+/// [`calculate_duration_extended`] scaled by the server's process time rule
+pub fn calculate_duration_under(
+    process_type: &str,
+    player: &crate::extended::ExtendedPlayerState,
+    target: &crate::extended::ExtendedTargetInfo,
+    config: &ProcessConfig,
+    rules: &GameRules,
+) -> i32 {
+    rules.duration(calculate_duration_extended(process_type, player, target, config))
+}
+
 /// Calculate resource usage for a process
 pub fn calculate_resource_usage(process_type: &str, target: &crate::TargetInfo, config: &ProcessConfig) -> crate::ResourceUsage {
     let extended_target = crate::extended::extend_target_info(target);
@@ -940,6 +1042,42 @@ mod tests {
         assert_eq!(process.total_duration, Duration::from_secs(300));
     }
     
+    #[test]
+    fn test_spellings_share_a_key() {
+        for spelling in SPELLINGS {
+            assert!(!matches!(ProcessType::from_str(spelling), ProcessType::Custom(_)), "{}", spelling);
+        }
+        assert_eq!(ProcessType::from_str("DDoS_Attack").key(), "ddos");
+        assert_eq!(ProcessType::from_str("HideLog").key(), "hide_log");
+        assert_eq!(ProcessType::DDoSAttack.spellings(), vec!["ddos", "ddos_attack"]);
+        assert_eq!(ProcessType::from_str("Scan").key(), "scan");
+    }
+
+    #[test]
+    fn test_progress_follows_clock() {
+        let clock = clock::ManualClock::starting_now();
+        let _guard = clock::set_thread_clock(std::sync::Arc::new(clock.clone()));
+
+        let mut process = Process::new(
+            ProcessType::Download,
+            1,
+            Duration::from_secs(100),
+            ResourceUsage { cpu_usage: 10, ram_usage: 256, net_usage: 50, hdd_usage: 100 }
+        );
+        process.start();
+
+        clock.advance(Duration::from_secs(40));
+        assert_eq!(process.get_progress(), 40.0);
+
+        process.pause();
+        clock.advance(Duration::from_secs(500));
+        assert_eq!(process.get_remaining_time(), Duration::from_secs(60));
+
+        process.resume();
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(process.get_progress(), 100.0);
+    }
+
     #[test]
     fn test_process_state_transitions() {
         let mut process = Process::new(
@@ -1066,4 +1204,19 @@ mod tests {
         assert_eq!(chain[1].process_type, ProcessType::SystemScan);
         assert_eq!(chain[1].parent_process_id, Some(chain[0].id));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_completion_reward() {
+        let crack = ProcessType::Crack.completion_reward();
+        assert_eq!(crack.experience, 75);
+        assert_eq!(crack.money, 0);
+
+        assert!(ProcessType::BitcoinMine.completion_reward().money > 0);
+        assert_eq!(ProcessType::HideLog.target_log_type(), None);
+        assert_eq!(ProcessType::Download.target_log_type(), Some("download"));
+
+        let boosted = GameRules { xp_multiplier: 10.0, ..GameRules::default() };
+        assert_eq!(ProcessType::Crack.completion_reward_under(&boosted).experience, 750);
+        assert_eq!(ProcessType::BitcoinMine.completion_reward_under(&boosted).money, 250);
+    }
+}
@@ -0,0 +1,225 @@
+//! Storyline puzzles
+//!
+//! Multi-stage puzzles hidden around the Internet, starting from the
+//! 13.37.13.37 mystery server. Each stage leaves a hidden file on a server
+//! that only players on that stage who have cracked the server can read.
+//! The file may be encrypted, with the key hidden on another server, and the
+//! server may only answer during a daily window. Some stages stay shut until
+//! enough players worldwide have got past an earlier one. Answers are checked
+//! on the server; wrong ones cost a cooldown that doubles each time.
+
+use crate::config::PuzzleConfig;
+use chrono::{DateTime, Duration, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The storyline mystery server
+pub const MYSTERY_IP: &str = "13.37.13.37";
+
+const MINUTES_PER_DAY: i32 = 24 * 60;
+
+/// A daily window, in UTC, during which a stage's server gives up its file
+/// and takes answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessWindow {
+    /// Minutes after midnight UTC
+    pub start_minute: i32,
+    pub minutes: i32,
+}
+
+impl AccessWindow {
+    fn minute_of_day(at: DateTime<Utc>) -> i32 {
+        (at.hour() * 60 + at.minute()) as i32
+    }
+
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        let since_start = (Self::minute_of_day(at) - self.start_minute).rem_euclid(MINUTES_PER_DAY);
+        since_start < self.minutes
+    }
+
+    /// When the window next opens; `at` itself if it is open
+    pub fn next_open(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        if self.is_open(at) {
+            return at;
+        }
+        let until = (self.start_minute - Self::minute_of_day(at)).rem_euclid(MINUTES_PER_DAY);
+        let at_minute = at.with_second(0).and_then(|at| at.with_nanosecond(0)).unwrap_or(at);
+        at_minute + Duration::minutes(until as i64)
+    }
+}
+
+/// How close the community is to a milestone a stage waits on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommunityGate {
+    pub milestone: String,
+    pub players: i64,
+    pub required: i64,
+    pub reached: bool,
+}
+
+/// What decides whether a player may work on their current stage right now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageAccess {
+    pub server_ip: String,
+    /// The player holds valid credentials for the stage's server
+    pub server_hacked: bool,
+    pub window: Option<AccessWindow>,
+    pub community: Option<CommunityGate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum PuzzleDenied {
+    PuzzleNotFound,
+    AlreadySolved,
+    ServerNotHacked { ip: String },
+    WindowClosed { opens_at: DateTime<Utc> },
+    AwaitingCommunity { milestone: String, players: i64, required: i64 },
+    AnswerTooLong { max: usize },
+    CoolingDown { retry_after_secs: i64 },
+    WrongAnswer { retry_after_secs: i64 },
+}
+
+impl PuzzleDenied {
+    pub fn message(&self) -> String {
+        match self {
+            PuzzleDenied::PuzzleNotFound => "Puzzle not found".to_string(),
+            PuzzleDenied::AlreadySolved => "You already solved this puzzle".to_string(),
+            PuzzleDenied::ServerNotHacked { ip } => format!("You need access to {} first", ip),
+            PuzzleDenied::WindowClosed { opens_at } => {
+                format!("The server is not answering; try again at {} UTC", opens_at.format("%H:%M"))
+            }
+            PuzzleDenied::AwaitingCommunity { milestone, players, required } => {
+                format!("Waiting for the community: {} ({}/{} players)", milestone, players, required)
+            }
+            PuzzleDenied::AnswerTooLong { max } => format!("Answers must be at most {} characters", max),
+            PuzzleDenied::CoolingDown { retry_after_secs } => {
+                format!("Too many wrong answers, retry in {}s", retry_after_secs)
+            }
+            PuzzleDenied::WrongAnswer { retry_after_secs } => {
+                format!("That is not it. You can answer again in {}s", retry_after_secs)
+            }
+        }
+    }
+}
+
+/// Whether a player may read their stage's files and answer it at `at`
+pub fn check_access(access: &StageAccess, at: DateTime<Utc>) -> Result<(), PuzzleDenied> {
+    if let Some(gate) = access.community.as_ref().filter(|gate| !gate.reached) {
+        return Err(PuzzleDenied::AwaitingCommunity {
+            milestone: gate.milestone.clone(),
+            players: gate.players,
+            required: gate.required,
+        });
+    }
+    if !access.server_hacked {
+        return Err(PuzzleDenied::ServerNotHacked { ip: access.server_ip.clone() });
+    }
+    match access.window {
+        Some(window) if !window.is_open(at) => Err(PuzzleDenied::WindowClosed { opens_at: window.next_open(at) }),
+        _ => Ok(()),
+    }
+}
+
+/// Answers compare case-insensitively with whitespace collapsed
+pub fn normalize_answer(answer: &str) -> String {
+    answer.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// What gets hashed to check an answer to `stage` of puzzle `slug`; the
+/// stored hash is SHA-256 of the same string
+pub fn answer_digest_input(slug: &str, stage: i32, answer: &str) -> String {
+    format!("{}:{}:{}", slug, stage, normalize_answer(answer))
+}
+
+/// Cooldown after `wrong_attempts` wrong answers in a row
+pub fn answer_cooldown_secs(wrong_attempts: i32, config: &PuzzleConfig) -> i64 {
+    if wrong_attempts <= 0 {
+        return 0;
+    }
+    let doublings = (wrong_attempts - 1).min(20) as u32;
+    config
+        .wrong_answer_cooldown_secs
+        .saturating_mul(1 << doublings)
+        .min(config.max_cooldown_secs)
+}
+
+/// Vigenère cipher over ASCII letters, keeping case; everything else, and
+/// the key position, passes through untouched. An empty key leaves the text
+/// as it is.
+pub fn vigenere(text: &str, key: &str, decrypt: bool) -> String {
+    let shifts: Vec<u8> = key
+        .bytes()
+        .filter(u8::is_ascii_alphabetic)
+        .map(|b| b.to_ascii_lowercase() - b'a')
+        .collect();
+    if shifts.is_empty() {
+        return text.to_string();
+    }
+
+    let mut position = 0;
+    text.chars()
+        .map(|c| {
+            if !c.is_ascii_alphabetic() {
+                return c;
+            }
+            let base = if c.is_ascii_uppercase() { b'A' } else { b'a' };
+            let shift = shifts[position % shifts.len()];
+            position += 1;
+            let shift = if decrypt { 26 - shift } else { shift };
+            (((c as u8 - base + shift) % 26) + base) as char
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_window_wraps_midnight() {
+        let window = AccessWindow { start_minute: 23 * 60 + 50, minutes: 20 };
+        let at = |h, m| Utc.with_ymd_and_hms(2024, 10, 31, h, m, 30).unwrap();
+
+        assert!(window.is_open(at(23, 55)));
+        assert!(window.is_open(at(0, 5)));
+        assert!(!window.is_open(at(0, 10)));
+        assert_eq!(window.next_open(at(0, 10)), Utc.with_ymd_and_hms(2024, 10, 31, 23, 50, 0).unwrap());
+    }
+
+    #[test]
+    fn test_access_checks_in_order() {
+        let now = Utc.with_ymd_and_hms(2024, 10, 31, 12, 0, 0).unwrap();
+        let mut access = StageAccess {
+            server_ip: MYSTERY_IP.to_string(),
+            server_hacked: false,
+            window: Some(AccessWindow { start_minute: 3 * 60, minutes: 37 }),
+            community: Some(CommunityGate { milestone: "First wave".into(), players: 3, required: 10, reached: false }),
+        };
+
+        assert!(matches!(check_access(&access, now), Err(PuzzleDenied::AwaitingCommunity { players: 3, .. })));
+        access.community.as_mut().unwrap().reached = true;
+        assert_eq!(check_access(&access, now), Err(PuzzleDenied::ServerNotHacked { ip: MYSTERY_IP.into() }));
+        access.server_hacked = true;
+        assert!(matches!(check_access(&access, now), Err(PuzzleDenied::WindowClosed { .. })));
+        assert_eq!(check_access(&access, now.with_hour(3).unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_answers_and_cooldown() {
+        assert_eq!(answer_digest_input("mystery", 2, "  Leet   SPEAK "), "mystery:2:leet speak");
+
+        let config = PuzzleConfig::default();
+        assert_eq!(answer_cooldown_secs(0, &config), 0);
+        assert_eq!(answer_cooldown_secs(3, &config), config.wrong_answer_cooldown_secs * 4);
+        assert_eq!(answer_cooldown_secs(40, &config), config.max_cooldown_secs);
+    }
+
+    #[test]
+    fn test_vigenere_round_trip() {
+        let sealed = vigenere("Attack at dawn!", "lemon", false);
+        assert_eq!(sealed, "Lxfopv ef rnhr!");
+        assert_eq!(vigenere(&sealed, "LEMON", true), "Attack at dawn!");
+        assert_eq!(vigenere("plain", "", false), "plain");
+    }
+}
@@ -0,0 +1,204 @@
+//! Referrals: players invite others with a personal code
+//!
+//! A sign-up with a code is attributed to the code's owner. The referrer is
+//! paid per milestone, and only once the new account has reached both the
+//! milestone's level and its playtime, so an account created and left idle
+//! pays nothing.
+//!
+//! Sign-ups are checked for signs of a player referring themselves. Sharing
+//! an IP address or a device with the referrer rejects the referral outright;
+//! weaker signs (same network, a burst of sign-ups from one network, a brand
+//! new referrer) hold its rewards until a moderator clears it.
+
+use crate::config::ReferralConfig;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Unambiguous characters only: no 0/O or 1/I/L
+const CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferralStatus {
+    /// Waiting for the referred account to reach its milestones
+    Pending,
+    /// Suspicious; nothing is paid until a moderator clears it
+    Flagged,
+    /// Self-referral; never pays
+    Rejected,
+    /// Every milestone paid
+    Completed,
+}
+
+impl ReferralStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferralStatus::Pending => "pending",
+            ReferralStatus::Flagged => "flagged",
+            ReferralStatus::Rejected => "rejected",
+            ReferralStatus::Completed => "completed",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(ReferralStatus::Pending),
+            "flagged" => Some(ReferralStatus::Flagged),
+            "rejected" => Some(ReferralStatus::Rejected),
+            "completed" => Some(ReferralStatus::Completed),
+            _ => None,
+        }
+    }
+}
+
+/// What a sign-up shares with its referrer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignupSignals {
+    /// The sign-up IP is one the referrer has signed in from
+    pub shares_ip: bool,
+    /// The sign-up device is one the referrer has signed in with
+    pub shares_device: bool,
+    /// The sign-up IP is in a network the referrer has signed in from
+    pub shares_network: bool,
+    /// Earlier sign-ups for this referrer from the same network within
+    /// the burst window
+    pub recent_from_network: usize,
+    pub referrer_age_hours: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "signal", rename_all = "snake_case")]
+pub enum AbuseSignal {
+    SameIp,
+    SameDevice,
+    SameNetwork,
+    NetworkBurst { sign_ups: usize },
+    NewReferrer { age_hours: i64 },
+}
+
+impl AbuseSignal {
+    /// Proof enough of a self-referral to reject it without review
+    pub fn is_conclusive(&self) -> bool {
+        matches!(self, AbuseSignal::SameIp | AbuseSignal::SameDevice)
+    }
+}
+
+/// Everything suspicious about a sign-up
+pub fn assess(signals: &SignupSignals, config: &ReferralConfig) -> Vec<AbuseSignal> {
+    let mut found = Vec::new();
+    if signals.shares_ip {
+        found.push(AbuseSignal::SameIp);
+    }
+    if signals.shares_device {
+        found.push(AbuseSignal::SameDevice);
+    }
+    if signals.shares_network && !signals.shares_ip {
+        found.push(AbuseSignal::SameNetwork);
+    }
+    if signals.recent_from_network >= config.network_burst_limit {
+        found.push(AbuseSignal::NetworkBurst { sign_ups: signals.recent_from_network + 1 });
+    }
+    if signals.referrer_age_hours < config.min_referrer_age_hours {
+        found.push(AbuseSignal::NewReferrer { age_hours: signals.referrer_age_hours });
+    }
+    found
+}
+
+/// The status a new referral starts in
+pub fn initial_status(signals: &[AbuseSignal]) -> ReferralStatus {
+    if signals.iter().any(AbuseSignal::is_conclusive) {
+        ReferralStatus::Rejected
+    } else if signals.is_empty() {
+        ReferralStatus::Pending
+    } else {
+        ReferralStatus::Flagged
+    }
+}
+
+/// The network an address belongs to for the heuristics: its /24 for IPv4,
+/// its /48 for IPv6
+pub fn network_of(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+        }
+    }
+}
+
+/// A fresh referral code
+pub fn generate_code<R: Rng>(rng: &mut R, config: &ReferralConfig) -> String {
+    (0..config.code_length)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// A code as typed by a player, if it could be one
+pub fn normalize_code(code: &str, config: &ReferralConfig) -> Option<String> {
+    let code = code.trim().to_ascii_uppercase();
+    (code.len() == config.code_length && code.bytes().all(|c| CODE_ALPHABET.contains(&c))).then_some(code)
+}
+
+/// Milestones newly reached, as (index, reward), given how many were
+/// already paid. Milestones are reached in order.
+pub fn due_milestones(level: i32, playtime_secs: i64, paid: usize, config: &ReferralConfig) -> Vec<(usize, i64)> {
+    config
+        .milestones
+        .iter()
+        .enumerate()
+        .skip(paid)
+        .take_while(|(_, milestone)| level >= milestone.level && playtime_secs >= milestone.playtime_hours * 3600)
+        .map(|(index, milestone)| (index, milestone.reward))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assess_self_referrals() {
+        let config = ReferralConfig::default();
+        let honest = SignupSignals { referrer_age_hours: 500, ..Default::default() };
+        assert_eq!(initial_status(&assess(&honest, &config)), ReferralStatus::Pending);
+
+        let same_device = SignupSignals { shares_device: true, ..honest.clone() };
+        assert_eq!(initial_status(&assess(&same_device, &config)), ReferralStatus::Rejected);
+
+        let neighbour = SignupSignals { shares_network: true, ..honest.clone() };
+        assert_eq!(assess(&neighbour, &config), vec![AbuseSignal::SameNetwork]);
+        assert_eq!(initial_status(&assess(&neighbour, &config)), ReferralStatus::Flagged);
+
+        let burst = SignupSignals { recent_from_network: config.network_burst_limit, ..honest };
+        assert_eq!(initial_status(&assess(&burst, &config)), ReferralStatus::Flagged);
+
+        assert_eq!(network_of("203.0.113.77".parse().unwrap()), "203.0.113.0/24");
+        let code = generate_code(&mut rand::thread_rng(), &config);
+        assert_eq!(normalize_code(&code.to_lowercase(), &config), Some(code));
+        assert_eq!(normalize_code("O0O0O0O0", &config), None);
+    }
+
+    #[test]
+    fn test_milestones_need_level_and_playtime() {
+        let config = ReferralConfig::default();
+        let first = &config.milestones[0];
+        let second = &config.milestones[1];
+
+        // Levelled fast but barely played
+        assert!(due_milestones(second.level, 60, 0, &config).is_empty());
+        assert_eq!(
+            due_milestones(first.level, first.playtime_hours * 3600, 0, &config),
+            vec![(0, first.reward)]
+        );
+        // Already paid the first; both thresholds of the second reached
+        assert_eq!(
+            due_milestones(second.level, second.playtime_hours * 3600, 1, &config),
+            vec![(1, second.reward)]
+        );
+    }
+}
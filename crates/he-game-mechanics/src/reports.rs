@@ -0,0 +1,299 @@
+//! Player reports: content sent to moderators
+//!
+//! Players report chat messages, usernames, server pages and clan names
+//! under a category. What was reported is snapshotted as evidence when the
+//! first report comes in, so a later edit cannot hide it. Reports of the same
+//! content join one case, which is what moderators work on; a case that was
+//! dismissed recently takes further reports of the same content without
+//! coming back to the queue.
+//!
+//! Cases are queued by priority, from the category and how many players
+//! reported it, and each category has a time moderators should answer
+//! within. A moderator closes a case by dismissing it or acting on the
+//! player responsible.
+
+use crate::config::ReportConfig;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What kind of content is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportTarget {
+    ChatMessage,
+    Username,
+    /// A player server's hostname, login banner and public page
+    ServerPage,
+    ClanName,
+}
+
+impl ReportTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportTarget::ChatMessage => "chat_message",
+            ReportTarget::Username => "username",
+            ReportTarget::ServerPage => "server_page",
+            ReportTarget::ClanName => "clan_name",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "chat_message" => Some(ReportTarget::ChatMessage),
+            "username" => Some(ReportTarget::Username),
+            "server_page" => Some(ReportTarget::ServerPage),
+            "clan_name" => Some(ReportTarget::ClanName),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportCategory {
+    HateSpeech,
+    Harassment,
+    Sexual,
+    Impersonation,
+    Spam,
+    Other,
+}
+
+impl ReportCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportCategory::HateSpeech => "hate_speech",
+            ReportCategory::Harassment => "harassment",
+            ReportCategory::Sexual => "sexual",
+            ReportCategory::Impersonation => "impersonation",
+            ReportCategory::Spam => "spam",
+            ReportCategory::Other => "other",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "hate_speech" => Some(ReportCategory::HateSpeech),
+            "harassment" => Some(ReportCategory::Harassment),
+            "sexual" => Some(ReportCategory::Sexual),
+            "impersonation" => Some(ReportCategory::Impersonation),
+            "spam" => Some(ReportCategory::Spam),
+            "other" => Some(ReportCategory::Other),
+            _ => None,
+        }
+    }
+
+    /// Base queue priority; higher is answered first
+    pub fn severity(&self) -> i32 {
+        match self {
+            ReportCategory::HateSpeech => 40,
+            ReportCategory::Harassment | ReportCategory::Sexual => 30,
+            ReportCategory::Impersonation => 20,
+            ReportCategory::Spam | ReportCategory::Other => 10,
+        }
+    }
+
+    /// Hours moderators should answer within
+    pub fn sla_hours(&self, config: &ReportConfig) -> i64 {
+        match self {
+            ReportCategory::HateSpeech => config.urgent_sla_hours,
+            ReportCategory::Harassment | ReportCategory::Sexual => config.high_sla_hours,
+            ReportCategory::Impersonation | ReportCategory::Spam | ReportCategory::Other => config.normal_sla_hours,
+        }
+    }
+}
+
+/// How a moderator closes a case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ReportAction {
+    /// Nothing wrong; later reports of the same content are not queued
+    Dismiss,
+    Warn,
+    /// Take the content down and warn its author
+    RemoveContent,
+    /// `None` for a permanent ban
+    Ban { hours: Option<i64> },
+}
+
+impl ReportAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportAction::Dismiss => "dismiss",
+            ReportAction::Warn => "warn",
+            ReportAction::RemoveContent => "remove_content",
+            ReportAction::Ban { .. } => "ban",
+        }
+    }
+
+    /// Usernames are also logins, so they cannot be taken down
+    pub fn applies_to(&self, target: ReportTarget) -> bool {
+        !matches!((self, target), (ReportAction::RemoveContent, ReportTarget::Username))
+    }
+}
+
+/// Why a report is refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ReportDenied {
+    /// Missing, or not something the reporter can see
+    TargetNotFound,
+    OwnContent,
+    DescriptionTooLong { max: usize },
+    /// The reporter has this many reports waiting already
+    TooManyOpen { max: usize },
+    AlreadyReported,
+}
+
+impl ReportDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            ReportDenied::TargetNotFound => "There is nothing to report there".to_string(),
+            ReportDenied::OwnContent => "You cannot report your own content".to_string(),
+            ReportDenied::DescriptionTooLong { max } => {
+                format!("Descriptions are limited to {} characters", max)
+            }
+            ReportDenied::TooManyOpen { max } => {
+                format!("You have {} reports waiting for a moderator already", max)
+            }
+            ReportDenied::AlreadyReported => "You have already reported this".to_string(),
+        }
+    }
+}
+
+/// Why a moderator cannot close a case that way
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ResolveDenied {
+    NotOpen,
+    /// The action does not apply to this kind of content
+    NotApplicable,
+    /// Nobody is responsible for the content, e.g. a message the game posted
+    NoSubject,
+    InvalidBanLength { max_hours: i64 },
+}
+
+impl ResolveDenied {
+    pub fn message(&self) -> String {
+        match self {
+            ResolveDenied::NotOpen => "This case is not open".to_string(),
+            ResolveDenied::NotApplicable => "That action does not apply to this content".to_string(),
+            ResolveDenied::NoSubject => "No player is responsible for this content".to_string(),
+            ResolveDenied::InvalidBanLength { max_hours } => {
+                format!("Temporary bans last 1 to {} hours", max_hours)
+            }
+        }
+    }
+}
+
+/// Check that `action` can close a case on `target`; `has_subject` is
+/// whether a player is responsible for the content
+pub fn check_resolution(
+    action: ReportAction,
+    target: ReportTarget,
+    has_subject: bool,
+    config: &ReportConfig,
+) -> Result<(), ResolveDenied> {
+    if !action.applies_to(target) {
+        return Err(ResolveDenied::NotApplicable);
+    }
+    match action {
+        ReportAction::Dismiss => Ok(()),
+        _ if !has_subject => Err(ResolveDenied::NoSubject),
+        ReportAction::Ban { hours: Some(hours) } if !(1..=config.max_ban_hours).contains(&hours) => {
+            Err(ResolveDenied::InvalidBanLength { max_hours: config.max_ban_hours })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check a report before it is filed. `open` is how many of the reporter's
+/// reports are still waiting.
+pub fn check_report(description: &str, open: usize, config: &ReportConfig) -> Result<(), ReportDenied> {
+    if description.chars().count() > config.max_description_chars {
+        return Err(ReportDenied::DescriptionTooLong { max: config.max_description_chars });
+    }
+    if open >= config.max_open_per_reporter {
+        return Err(ReportDenied::TooManyOpen { max: config.max_open_per_reporter });
+    }
+    Ok(())
+}
+
+/// Content as compared between reports: trimmed, lowercased and with runs
+/// of whitespace collapsed, so trivially different copies match
+pub fn normalize_content(content: &str) -> String {
+    content.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Queue priority of a case in `category` reported by `reporters` players
+pub fn priority(category: ReportCategory, reporters: usize, config: &ReportConfig) -> i32 {
+    let extra = reporters.saturating_sub(1).min(config.max_reporter_bonus) as i32;
+    category.severity() + extra * config.priority_per_reporter
+}
+
+/// When a case opened at `opened_at` is due
+pub fn sla_due(opened_at: DateTime<Utc>, category: ReportCategory, config: &ReportConfig) -> DateTime<Utc> {
+    opened_at + Duration::hours(category.sla_hours(config))
+}
+
+/// The more urgent of a case's category and a new report's
+pub fn escalate(current: ReportCategory, reported: ReportCategory) -> ReportCategory {
+    if reported.severity() > current.severity() {
+        reported
+    } else {
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_and_deadlines() {
+        let config = ReportConfig::default();
+        let spam = priority(ReportCategory::Spam, 1, &config);
+        let hate = priority(ReportCategory::HateSpeech, 1, &config);
+        assert!(hate > spam);
+        assert!(priority(ReportCategory::Spam, 3, &config) > spam);
+        // Piling on only helps so far
+        assert_eq!(
+            priority(ReportCategory::Spam, 1000, &config),
+            priority(ReportCategory::Spam, config.max_reporter_bonus + 1, &config)
+        );
+
+        let opened = Utc::now();
+        assert!(sla_due(opened, ReportCategory::HateSpeech, &config) < sla_due(opened, ReportCategory::Spam, &config));
+        assert_eq!(escalate(ReportCategory::Spam, ReportCategory::Harassment), ReportCategory::Harassment);
+        assert_eq!(escalate(ReportCategory::HateSpeech, ReportCategory::Spam), ReportCategory::HateSpeech);
+    }
+
+    #[test]
+    fn test_report_checks() {
+        let config = ReportConfig::default();
+        assert_eq!(normalize_content("  Buy   GOLD\nnow "), "buy gold now");
+        assert_eq!(check_report("spam bot", 0, &config), Ok(()));
+        assert_eq!(
+            check_report("", config.max_open_per_reporter, &config),
+            Err(ReportDenied::TooManyOpen { max: config.max_open_per_reporter })
+        );
+        let long = "x".repeat(config.max_description_chars + 1);
+        assert!(matches!(check_report(&long, 0, &config), Err(ReportDenied::DescriptionTooLong { .. })));
+
+        assert_eq!(
+            check_resolution(ReportAction::RemoveContent, ReportTarget::Username, true, &config),
+            Err(ResolveDenied::NotApplicable)
+        );
+        assert_eq!(check_resolution(ReportAction::Ban { hours: None }, ReportTarget::Username, true, &config), Ok(()));
+        assert_eq!(check_resolution(ReportAction::Dismiss, ReportTarget::ChatMessage, false, &config), Ok(()));
+        assert_eq!(
+            check_resolution(ReportAction::Warn, ReportTarget::ChatMessage, false, &config),
+            Err(ResolveDenied::NoSubject)
+        );
+        assert!(check_resolution(ReportAction::Ban { hours: Some(0) }, ReportTarget::ClanName, true, &config).is_err());
+        for target in [ReportTarget::ChatMessage, ReportTarget::Username, ReportTarget::ServerPage, ReportTarget::ClanName] {
+            assert_eq!(ReportTarget::from_str(target.as_str()), Some(target));
+        }
+    }
+}
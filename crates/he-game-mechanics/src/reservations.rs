@@ -0,0 +1,177 @@
+//! Resource reservations
+//!
+//! A multi-step action, such as buying software and then installing it, can
+//! hold what it will need before its first step: money on one of the
+//! player's bank accounts, disk space on their gateway and process slots.
+//! Held resources count as spent for everything else until the hold is
+//! committed by the step that uses it, released, or runs out.
+
+use crate::config::ReservationConfig;
+use serde::{Deserialize, Serialize};
+
+/// What a player asks to hold
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReservationRequest {
+    /// In cents
+    #[serde(default)]
+    pub money: i64,
+    #[serde(default)]
+    pub hdd_mb: i64,
+    #[serde(default)]
+    pub process_slots: i32,
+}
+
+impl ReservationRequest {
+    pub fn is_empty(&self) -> bool {
+        self.money == 0 && self.hdd_mb == 0 && self.process_slots == 0
+    }
+}
+
+/// What the player has free once their other holds are taken out: the most
+/// on any one bank account, the gateway's disk and process slots
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Available {
+    pub money: i64,
+    pub hdd_mb: i64,
+    pub process_slots: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ReservationDenied {
+    NotFound,
+    NotHeld { status: String },
+    Expired,
+    NothingRequested,
+    NegativeAmount,
+    TtlOutOfRange { min: i64, max: i64 },
+    PurposeTooLong { max: usize },
+    TooManyOpen { max: i64 },
+    NoServer,
+    NoBankAccount,
+    InsufficientFunds { available: i64, requested: i64 },
+    InsufficientSpace { available: i64, requested: i64 },
+    NoProcessSlots { available: i64, requested: i64 },
+}
+
+impl ReservationDenied {
+    pub fn message(&self) -> String {
+        match self {
+            ReservationDenied::NotFound => "Reservation not found".to_string(),
+            ReservationDenied::NotHeld { status } => format!("Reservation is already {}", status),
+            ReservationDenied::Expired => "Reservation has expired".to_string(),
+            ReservationDenied::NothingRequested => "Nothing to reserve".to_string(),
+            ReservationDenied::NegativeAmount => "Amounts cannot be negative".to_string(),
+            ReservationDenied::TtlOutOfRange { min, max } => {
+                format!("Reservations last between {} and {} seconds", min, max)
+            }
+            ReservationDenied::PurposeTooLong { max } => format!("Purpose must be at most {} characters", max),
+            ReservationDenied::TooManyOpen { max } => format!("You can hold at most {} reservations at once", max),
+            ReservationDenied::NoServer => "You need a server to reserve disk space".to_string(),
+            ReservationDenied::NoBankAccount => "You need a bank account to reserve money".to_string(),
+            ReservationDenied::InsufficientFunds { available, requested } => {
+                format!(
+                    "Cannot hold ${}.{:02} with ${}.{:02} available",
+                    requested / 100,
+                    requested % 100,
+                    available / 100,
+                    available % 100
+                )
+            }
+            ReservationDenied::InsufficientSpace { available, requested } => {
+                format!("Cannot hold {} MB with {} MB free", requested, available)
+            }
+            ReservationDenied::NoProcessSlots { available, requested } => {
+                format!("Cannot hold {} process slots with {} free", requested, available)
+            }
+        }
+    }
+}
+
+/// Validate a request and its lifetime before anything is looked up; returns
+/// the lifetime to use
+pub fn check_request(
+    request: &ReservationRequest,
+    ttl_secs: Option<i64>,
+    purpose: Option<&str>,
+    config: &ReservationConfig,
+) -> Result<i64, ReservationDenied> {
+    if request.money < 0 || request.hdd_mb < 0 || request.process_slots < 0 {
+        return Err(ReservationDenied::NegativeAmount);
+    }
+    if request.is_empty() {
+        return Err(ReservationDenied::NothingRequested);
+    }
+    if purpose.is_some_and(|purpose| purpose.chars().count() > config.max_purpose_len) {
+        return Err(ReservationDenied::PurposeTooLong { max: config.max_purpose_len });
+    }
+    let ttl = ttl_secs.unwrap_or(config.default_ttl_secs);
+    if ttl < config.min_ttl_secs || ttl > config.max_ttl_secs {
+        return Err(ReservationDenied::TtlOutOfRange { min: config.min_ttl_secs, max: config.max_ttl_secs });
+    }
+    Ok(ttl)
+}
+
+/// Whether what is free covers the request
+pub fn check_available(request: &ReservationRequest, available: &Available) -> Result<(), ReservationDenied> {
+    if request.money > available.money {
+        return Err(ReservationDenied::InsufficientFunds { available: available.money.max(0), requested: request.money });
+    }
+    if request.hdd_mb > available.hdd_mb {
+        return Err(ReservationDenied::InsufficientSpace { available: available.hdd_mb.max(0), requested: request.hdd_mb });
+    }
+    if request.process_slots as i64 > available.process_slots {
+        return Err(ReservationDenied::NoProcessSlots {
+            available: available.process_slots.max(0),
+            requested: request.process_slots as i64,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_checks() {
+        let config = ReservationConfig::default();
+        let request = ReservationRequest { money: 500, ..Default::default() };
+
+        assert_eq!(check_request(&request, None, None, &config), Ok(config.default_ttl_secs));
+        assert_eq!(check_request(&ReservationRequest::default(), None, None, &config), Err(ReservationDenied::NothingRequested));
+        assert_eq!(
+            check_request(&ReservationRequest { hdd_mb: -1, ..request }, None, None, &config),
+            Err(ReservationDenied::NegativeAmount)
+        );
+        assert!(matches!(
+            check_request(&request, Some(config.max_ttl_secs + 1), None, &config),
+            Err(ReservationDenied::TtlOutOfRange { .. })
+        ));
+        let purpose = "x".repeat(config.max_purpose_len + 1);
+        assert!(matches!(
+            check_request(&request, None, Some(&purpose), &config),
+            Err(ReservationDenied::PurposeTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn test_available_checks_each_resource() {
+        let available = Available { money: 1_000, hdd_mb: 50, process_slots: 1 };
+        let request = ReservationRequest { money: 1_000, hdd_mb: 50, process_slots: 1 };
+        assert_eq!(check_available(&request, &available), Ok(()));
+
+        assert!(matches!(
+            check_available(&ReservationRequest { money: 1_001, ..request }, &available),
+            Err(ReservationDenied::InsufficientFunds { available: 1_000, .. })
+        ));
+        assert!(matches!(
+            check_available(&ReservationRequest { hdd_mb: 51, ..request }, &available),
+            Err(ReservationDenied::InsufficientSpace { .. })
+        ));
+        assert!(matches!(
+            check_available(&ReservationRequest { process_slots: 2, ..request }, &Available { process_slots: -3, ..available }),
+            Err(ReservationDenied::NoProcessSlots { available: 0, requested: 2 })
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+//! Server game rules
+//!
+//! Private servers run with their own rules: faster leveling, shorter or
+//! longer processes, no player-versus-player at all, or a window after a
+//! player logs off during which nobody can hack them. The rules come from
+//! the `[rules]` table of the balance file, checked once at load so a server
+//! never starts with a combination that contradicts itself. Official servers
+//! run the defaults.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Highest XP multiplier a server may run
+pub const MAX_XP_MULTIPLIER: f64 = 100.0;
+/// Process time scale bounds; 0.1 makes every process ten times faster
+pub const MIN_PROCESS_TIME_SCALE: f64 = 0.01;
+pub const MAX_PROCESS_TIME_SCALE: f64 = 10.0;
+/// Longest offline protection window, a week
+pub const MAX_OFFLINE_PROTECTION_HOURS: i64 = 168;
+
+/// The rules a server runs with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameRules {
+    /// Shown to players, e.g. "10x XP, PvE only"
+    pub name: String,
+    /// Applied to every experience reward
+    pub xp_multiplier: f64,
+    /// Applied to every process duration
+    pub process_time_scale: f64,
+    /// Whether players may hack, bounty or fight each other at all
+    pub pvp_enabled: bool,
+    /// Clan wars are PvP, so they need `pvp_enabled`
+    pub clan_wars_enabled: bool,
+    /// Bounties are on players, so they need `pvp_enabled`
+    pub bounties_enabled: bool,
+    /// For this long after logging off a player cannot be targeted by other
+    /// players; 0 turns protection off
+    pub offline_protection_hours: i64,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            name: "Standard".to_string(),
+            xp_multiplier: 1.0,
+            process_time_scale: 1.0,
+            pvp_enabled: true,
+            clan_wars_enabled: true,
+            bounties_enabled: true,
+            offline_protection_hours: 0,
+        }
+    }
+}
+
+/// A rule value, or combination of values, a server cannot run with
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RulesError {
+    #[error("Invalid balance file: {0}")]
+    Parse(String),
+    #[error("xp_multiplier must be above 0 and at most {max}, got {value}")]
+    XpMultiplier { value: f64, max: f64 },
+    #[error("process_time_scale must be between {min} and {max}, got {value}")]
+    ProcessTimeScale { value: f64, min: f64, max: f64 },
+    #[error("offline_protection_hours must be between 0 and {max}, got {value}")]
+    OfflineProtection { value: i64, max: i64 },
+    /// A PvP feature is on while PvP is off
+    #[error("{feature} needs pvp_enabled")]
+    NeedsPvp { feature: &'static str },
+    /// Protecting offline players means nothing without PvP
+    #[error("offline_protection_hours only applies with pvp_enabled")]
+    ProtectionWithoutPvp,
+}
+
+/// Why a player cannot be targeted by another player
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum PvpDenied {
+    /// The server runs without PvP
+    Disabled,
+    /// The target logged off within the protection window
+    OfflineProtected { hours: i64 },
+}
+
+impl PvpDenied {
+    /// What the player is told
+    pub fn message(&self) -> String {
+        match self {
+            PvpDenied::Disabled => "Player versus player is disabled on this server".to_string(),
+            PvpDenied::OfflineProtected { hours } => {
+                format!("That player is offline and protected for up to {} hours after logging off", hours)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct BalanceFile {
+    #[serde(default)]
+    rules: GameRules,
+}
+
+impl GameRules {
+    /// The `[rules]` table of a balance file such as `game-balance.toml`,
+    /// defaults for whatever it leaves out, validated
+    pub fn from_balance(contents: &str) -> Result<Self, RulesError> {
+        let rules = toml::from_str::<BalanceFile>(contents)
+            .map_err(|e| RulesError::Parse(e.to_string()))?
+            .rules;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    pub fn validate(&self) -> Result<(), RulesError> {
+        if !(self.xp_multiplier > 0.0 && self.xp_multiplier <= MAX_XP_MULTIPLIER) {
+            return Err(RulesError::XpMultiplier { value: self.xp_multiplier, max: MAX_XP_MULTIPLIER });
+        }
+        if !(MIN_PROCESS_TIME_SCALE..=MAX_PROCESS_TIME_SCALE).contains(&self.process_time_scale) {
+            return Err(RulesError::ProcessTimeScale {
+                value: self.process_time_scale,
+                min: MIN_PROCESS_TIME_SCALE,
+                max: MAX_PROCESS_TIME_SCALE,
+            });
+        }
+        if !(0..=MAX_OFFLINE_PROTECTION_HOURS).contains(&self.offline_protection_hours) {
+            return Err(RulesError::OfflineProtection {
+                value: self.offline_protection_hours,
+                max: MAX_OFFLINE_PROTECTION_HOURS,
+            });
+        }
+        if !self.pvp_enabled {
+            if self.clan_wars_enabled {
+                return Err(RulesError::NeedsPvp { feature: "clan_wars_enabled" });
+            }
+            if self.bounties_enabled {
+                return Err(RulesError::NeedsPvp { feature: "bounties_enabled" });
+            }
+            if self.offline_protection_hours > 0 {
+                return Err(RulesError::ProtectionWithoutPvp);
+            }
+        }
+        Ok(())
+    }
+
+    /// An experience reward under these rules
+    pub fn experience(&self, experience: i64) -> i64 {
+        (experience as f64 * self.xp_multiplier).round() as i64
+    }
+
+    /// A process duration in seconds under these rules, at least a second
+    pub fn duration(&self, seconds: i32) -> i32 {
+        ((seconds as f64 * self.process_time_scale).round() as i32).max(1)
+    }
+
+    /// Whether another player may target a player who is `online` or was
+    /// last seen at `last_seen`
+    pub fn check_target(&self, online: bool, last_seen: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Result<(), PvpDenied> {
+        if !self.pvp_enabled {
+            return Err(PvpDenied::Disabled);
+        }
+        if self.offline_protected(online, last_seen, now) {
+            return Err(PvpDenied::OfflineProtected { hours: self.offline_protection_hours });
+        }
+        Ok(())
+    }
+
+    /// Whether a player who is offline and was last seen at `last_seen` is
+    /// still inside their protection window
+    pub fn offline_protected(&self, online: bool, last_seen: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        if online || self.offline_protection_hours == 0 {
+            return false;
+        }
+        last_seen.map_or(false, |seen| now - seen < Duration::hours(self.offline_protection_hours))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_balance_fills_defaults() {
+        let rules = GameRules::from_balance(
+            r#"
+            [economy]
+            starting_money = 5000
+
+            [rules]
+            name = "10x"
+            xp_multiplier = 10.0
+            process_time_scale = 0.5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rules.xp_multiplier, 10.0);
+        assert!(rules.pvp_enabled);
+        assert_eq!(rules.experience(25), 250);
+        assert_eq!(rules.duration(61), 31);
+        assert_eq!(rules.duration(1), 1);
+
+        assert_eq!(GameRules::from_balance("[economy]\nstarting_money = 1").unwrap(), GameRules::default());
+    }
+
+    #[test]
+    fn test_contradictory_rules_are_refused() {
+        let pve = GameRules {
+            pvp_enabled: false,
+            clan_wars_enabled: false,
+            bounties_enabled: false,
+            ..GameRules::default()
+        };
+        assert_eq!(pve.validate(), Ok(()));
+        assert_eq!(
+            GameRules { clan_wars_enabled: true, ..pve.clone() }.validate(),
+            Err(RulesError::NeedsPvp { feature: "clan_wars_enabled" })
+        );
+        assert_eq!(
+            GameRules { offline_protection_hours: 8, ..pve.clone() }.validate(),
+            Err(RulesError::ProtectionWithoutPvp)
+        );
+        assert!(matches!(
+            GameRules { xp_multiplier: 0.0, ..GameRules::default() }.validate(),
+            Err(RulesError::XpMultiplier { .. })
+        ));
+        assert!(matches!(
+            GameRules::from_balance("[rules]\nprocess_time_scale = 50.0"),
+            Err(RulesError::ProcessTimeScale { .. })
+        ));
+    }
+
+    #[test]
+    fn test_offline_protection_window() {
+        let now = Utc::now();
+        let rules = GameRules { offline_protection_hours: 8, ..GameRules::default() };
+        assert!(rules.offline_protected(false, Some(now - Duration::hours(2)), now));
+        assert!(!rules.offline_protected(false, Some(now - Duration::hours(9)), now));
+        assert!(!rules.offline_protected(true, Some(now - Duration::hours(2)), now));
+        assert!(!rules.offline_protected(false, None, now));
+        assert!(!GameRules::default().offline_protected(false, Some(now), now));
+
+        assert_eq!(
+            rules.check_target(false, Some(now - Duration::hours(2)), now),
+            Err(PvpDenied::OfflineProtected { hours: 8 })
+        );
+        let pve = GameRules { pvp_enabled: false, clan_wars_enabled: false, bounties_enabled: false, ..rules };
+        assert_eq!(pve.check_target(true, None, now), Err(PvpDenied::Disabled));
+    }
+}
@@ -0,0 +1,343 @@
+//! Formula override scripts
+//!
+//! Operators of private servers can replace a formula the balance file only
+//! scales, like the hack success rate or a process duration, with a short
+//! [Rhai](https://rhai.rs) script. A script sees the native result as
+//! `native` plus the formula's inputs, and returns the value to use instead:
+//!
+//! ```text
+//! // success_rate.rhai: easier early hacks
+//! if player_level < 10 { native + 1500 } else { native }
+//! ```
+//!
+//! Scripts run sandboxed: integer math only (no floats, clock or random, so
+//! the same inputs always give the same result), no modules or `eval`, and
+//! a budget of [`MAX_OPERATIONS`] per call. A script that errors, overflows,
+//! runs out of budget or returns a value outside its formula's range falls
+//! back to the native result, so a broken script never breaks the game.
+//! Scripts are compiled and trial-run on load; a set with any script that
+//! fails is rejected whole.
+
+use rhai::packages::{CorePackage, LogicPackage, Package};
+use rhai::{Engine, Scope, AST, INT};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Operations a script may run per call
+pub const MAX_OPERATIONS: u64 = 10_000;
+/// Longest script source accepted
+pub const MAX_SCRIPT_BYTES: usize = 16 * 1024;
+/// Script files are named after their formula with this extension
+pub const SCRIPT_EXTENSION: &str = "rhai";
+
+/// A formula a script can override
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Formula {
+    /// Hack success chance in basis points (0 to 10000), with
+    /// `player_level`, `target_difficulty` and `security_rating`
+    SuccessRate,
+    /// Process duration in seconds, with `player_level`
+    Duration,
+    /// Experience granted when a process completes
+    ExperienceReward,
+    /// Money in cents granted when a process completes
+    MoneyReward,
+}
+
+impl Formula {
+    pub const ALL: [Formula; 4] = [
+        Formula::SuccessRate,
+        Formula::Duration,
+        Formula::ExperienceReward,
+        Formula::MoneyReward,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Formula::SuccessRate => "success_rate",
+            Formula::Duration => "duration",
+            Formula::ExperienceReward => "experience_reward",
+            Formula::MoneyReward => "money_reward",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Formula> {
+        Formula::ALL.into_iter().find(|formula| formula.name() == name)
+    }
+
+    /// Inputs besides `native` and `process_type`
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            Formula::SuccessRate => &["player_level", "target_difficulty", "security_rating"],
+            Formula::Duration => &["player_level"],
+            Formula::ExperienceReward | Formula::MoneyReward => &[],
+        }
+    }
+
+    /// The values a script may return; anything else falls back
+    pub fn range(&self) -> (i64, i64) {
+        match self {
+            Formula::SuccessRate => (0, 10_000),
+            // One second to 30 days
+            Formula::Duration => (1, 30 * 24 * 3600),
+            Formula::ExperienceReward => (0, 1_000_000),
+            Formula::MoneyReward => (0, 100_000_000),
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Formula::SuccessRate => 0,
+            Formula::Duration => 1,
+            Formula::ExperienceReward => 2,
+            Formula::MoneyReward => 3,
+        }
+    }
+}
+
+impl std::fmt::Display for Formula {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Why a script set was rejected or a script call fell back
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ScriptError {
+    #[error("Could not read scripts from {path}: {message}")]
+    Read { path: String, message: String },
+    /// A file in the script directory that names no formula
+    #[error("{file} is not a formula script; expected one of success_rate, duration, experience_reward, money_reward")]
+    UnknownScript { file: String },
+    #[error("{formula} script is over {max} bytes")]
+    TooLarge { formula: Formula, max: usize },
+    #[error("{formula} script does not compile: {message}")]
+    Compile { formula: Formula, message: String },
+    #[error("{formula} script failed: {message}")]
+    Runtime { formula: Formula, message: String },
+    #[error("{formula} script returned {value}, outside {min} to {max}")]
+    OutOfRange { formula: Formula, value: i64, min: i64, max: i64 },
+}
+
+/// The sandbox every script runs in
+fn sandbox() -> Engine {
+    let mut engine = Engine::new_raw();
+    engine.register_global_module(CorePackage::new().as_shared_module());
+    engine.register_global_module(LogicPackage::new().as_shared_module());
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(16)
+        .set_max_expr_depths(32, 16)
+        .set_max_string_size(256)
+        .set_max_array_size(256)
+        .set_max_map_size(64)
+        .set_max_variables(64)
+        .set_max_functions(32)
+        .set_strict_variables(true);
+    engine.disable_symbol("eval");
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine
+}
+
+/// The variables `formula`'s scripts are compiled against. They are plain
+/// variables rather than constants so the optimizer cannot fold these
+/// placeholder values into the script.
+fn inputs(formula: Formula) -> Scope<'static> {
+    let mut scope = Scope::new();
+    scope.push("native", 0 as INT);
+    scope.push("process_type", String::new());
+    for name in formula.variables() {
+        scope.push(*name, 0 as INT);
+    }
+    scope
+}
+
+/// The formula overrides a server runs with
+pub struct FormulaScripts {
+    engine: Engine,
+    scripts: HashMap<Formula, AST>,
+    /// Calls that fell back to the native result, per formula
+    fallbacks: [AtomicU64; 4],
+}
+
+impl std::fmt::Debug for FormulaScripts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FormulaScripts").field("overridden", &self.overridden()).finish()
+    }
+}
+
+impl Default for FormulaScripts {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl FormulaScripts {
+    /// No overrides; every formula runs natively
+    pub fn none() -> Self {
+        Self {
+            engine: sandbox(),
+            scripts: HashMap::new(),
+            fallbacks: Default::default(),
+        }
+    }
+
+    /// Compile and trial-run every script; any failure rejects them all
+    pub fn compile(sources: &[(Formula, String)]) -> Result<Self, ScriptError> {
+        let mut loaded = Self::none();
+        for (formula, source) in sources {
+            if source.len() > MAX_SCRIPT_BYTES {
+                return Err(ScriptError::TooLarge { formula: *formula, max: MAX_SCRIPT_BYTES });
+            }
+            let ast = loaded.engine.compile_with_scope(&inputs(*formula), source).map_err(|e| ScriptError::Compile {
+                formula: *formula,
+                message: e.to_string(),
+            })?;
+            loaded.scripts.insert(*formula, ast);
+        }
+        for formula in loaded.overridden() {
+            loaded.trial(formula)?;
+        }
+        Ok(loaded)
+    }
+
+    /// Load `<formula>.rhai` files from `dir`; formulas without a file run
+    /// natively, and other `.rhai` files are an error so a misspelt script
+    /// is not silently ignored
+    pub fn load_dir(dir: &Path) -> Result<Self, ScriptError> {
+        let read_error = |e: std::io::Error| ScriptError::Read {
+            path: dir.display().to_string(),
+            message: e.to_string(),
+        };
+        let mut sources = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SCRIPT_EXTENSION) {
+                continue;
+            }
+            let file = path.file_name().and_then(|name| name.to_str()).unwrap_or_default().to_string();
+            let Some(formula) = path.file_stem().and_then(|stem| stem.to_str()).and_then(Formula::from_name) else {
+                return Err(ScriptError::UnknownScript { file });
+            };
+            sources.push((formula, std::fs::read_to_string(&path).map_err(read_error)?));
+        }
+        Self::compile(&sources)
+    }
+
+    /// Formulas with a script, in [`Formula::ALL`] order
+    pub fn overridden(&self) -> Vec<Formula> {
+        Formula::ALL.into_iter().filter(|formula| self.scripts.contains_key(formula)).collect()
+    }
+
+    /// Calls of `formula` that fell back to the native result
+    pub fn fallbacks(&self, formula: Formula) -> u64 {
+        self.fallbacks[formula.index()].load(Ordering::Relaxed)
+    }
+
+    /// Run `formula`'s script, if it has one
+    pub fn run(
+        &self,
+        formula: Formula,
+        native: i64,
+        process_type: &str,
+        values: &[(&str, i64)],
+    ) -> Result<Option<i64>, ScriptError> {
+        let Some(ast) = self.scripts.get(&formula) else {
+            return Ok(None);
+        };
+
+        let mut scope = Scope::new();
+        scope.push_constant("native", native as INT);
+        scope.push_constant("process_type", process_type.to_string());
+        for (name, value) in values {
+            scope.push_constant(name.to_string(), *value as INT);
+        }
+        let value = self
+            .engine
+            .eval_ast_with_scope::<INT>(&mut scope, ast)
+            .map_err(|e| ScriptError::Runtime { formula, message: e.to_string() })?;
+
+        let (min, max) = formula.range();
+        if !(min..=max).contains(&value) {
+            return Err(ScriptError::OutOfRange { formula, value, min, max });
+        }
+        Ok(Some(value))
+    }
+
+    /// `formula`'s script result, or `native` when there is no script or it
+    /// fails
+    pub fn apply(&self, formula: Formula, native: i64, process_type: &str, values: &[(&str, i64)]) -> i64 {
+        match self.run(formula, native, process_type, values) {
+            Ok(Some(value)) => value,
+            Ok(None) => native,
+            Err(e) => {
+                self.fallbacks[formula.index()].fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Formula script fell back to native: {}", e);
+                native
+            }
+        }
+    }
+
+    /// Run a script once on typical inputs, so one that cannot work is
+    /// rejected on load rather than falling back on every call
+    fn trial(&self, formula: Formula) -> Result<(), ScriptError> {
+        let (min, max) = formula.range();
+        let native = min + (max - min) / 100;
+        let values: Vec<(&str, i64)> = formula.variables().iter().map(|name| (*name, 10)).collect();
+        self.run(formula, native, "crack", &values).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scripts(formula: Formula, source: &str) -> Result<FormulaScripts, ScriptError> {
+        FormulaScripts::compile(&[(formula, source.to_string())])
+    }
+
+    #[test]
+    fn test_scripts_override_and_fall_back() {
+        let loaded = scripts(Formula::Duration, "if process_type == \"crack\" { native / 2 } else { native }").unwrap();
+        assert_eq!(loaded.overridden(), vec![Formula::Duration]);
+        assert_eq!(loaded.apply(Formula::Duration, 600, "crack", &[("player_level", 1)]), 300);
+        assert_eq!(loaded.apply(Formula::Duration, 600, "download", &[("player_level", 1)]), 600);
+        // No script for rewards
+        assert_eq!(loaded.apply(Formula::ExperienceReward, 50, "crack", &[]), 50);
+
+        // Halving one second gives zero, outside the duration range
+        assert_eq!(loaded.apply(Formula::Duration, 1, "crack", &[("player_level", 1)]), 1);
+        // A missing input is a runtime error under strict variables
+        assert_eq!(loaded.apply(Formula::Duration, 600, "crack", &[]), 600);
+        assert_eq!(loaded.fallbacks(Formula::Duration), 2);
+    }
+
+    #[test]
+    fn test_scripts_are_sandboxed() {
+        // Runaway loops hit the operation budget
+        let looping = scripts(Formula::MoneyReward, "let x = 0; loop { x += 1; } x");
+        assert!(matches!(looping, Err(ScriptError::Runtime { .. })));
+        // Integer math only
+        assert!(matches!(scripts(Formula::MoneyReward, "native * 1.5"), Err(ScriptError::Compile { .. })));
+        assert!(matches!(scripts(Formula::MoneyReward, "eval(\"native\")"), Err(ScriptError::Compile { .. })));
+        // Overflow is an error rather than wrapping
+        let overflow = scripts(Formula::MoneyReward, "if native < 1000 { native * 9223372036854775807 } else { native }").unwrap();
+        assert_eq!(overflow.apply(Formula::MoneyReward, 250, "bitcoin_mine", &[]), 250);
+    }
+
+    #[test]
+    fn test_trial_run_rejects_out_of_range_scripts() {
+        let negative = scripts(Formula::SuccessRate, "-1");
+        assert_eq!(
+            negative.unwrap_err(),
+            ScriptError::OutOfRange { formula: Formula::SuccessRate, value: -1, min: 0, max: 10_000 }
+        );
+        let bounded = scripts(Formula::SuccessRate, "min(native + player_level * 100, 10000)").unwrap();
+        let values = [("player_level", 5), ("target_difficulty", 50), ("security_rating", 50)];
+        assert_eq!(bounded.apply(Formula::SuccessRate, 9_800, "crack", &values), 10_000);
+    }
+}
@@ -0,0 +1,232 @@
+//! Server browser and range scans
+//!
+//! Players find targets by browsing NPC servers or scanning an address
+//! range. Until a player has scanned a server, its security details are shown
+//! as a band around the real values. The band is offset by an amount fixed
+//! for each player and server, so reloading the page does not narrow it down.
+//! Difficulty is estimated from what the player can see, against the best
+//! cracker on their gateway.
+
+use crate::config::ServerBrowserConfig;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+
+/// What a full scan of a server reveals
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SecurityDetails {
+    /// Best firewall version running
+    pub firewall: f64,
+    pub cpu: i32,
+    pub net: i32,
+}
+
+/// A value as the player sees it. `low == high` once they scanned it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Band {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Band {
+    fn exact(value: f64) -> Self {
+        Self { low: value, high: value }
+    }
+
+    fn mid(&self) -> f64 {
+        (self.low + self.high) / 2.0
+    }
+}
+
+/// Security details as shown to one player
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SecurityView {
+    /// From the player's own scan rather than estimated
+    pub scanned: bool,
+    pub firewall: Band,
+    pub cpu: Band,
+    pub net: Band,
+    pub difficulty: Difficulty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Trivial,
+    Easy,
+    Fair,
+    Hard,
+    Deadly,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 5] =
+        [Difficulty::Trivial, Difficulty::Easy, Difficulty::Fair, Difficulty::Hard, Difficulty::Deadly];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Difficulty::Trivial => "trivial",
+            Difficulty::Easy => "easy",
+            Difficulty::Fair => "fair",
+            Difficulty::Hard => "hard",
+            Difficulty::Deadly => "deadly",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|d| d.as_str() == s)
+    }
+}
+
+/// How hard a server with `firewall` is for a player whose best cracker is
+/// `cracker`
+pub fn difficulty(firewall: f64, cracker: f64) -> Difficulty {
+    let ratio = firewall / cracker.max(1.0);
+    match ratio {
+        r if r < 0.5 => Difficulty::Trivial,
+        r if r < 0.9 => Difficulty::Easy,
+        r if r < 1.1 => Difficulty::Fair,
+        r if r < 1.5 => Difficulty::Hard,
+        _ => Difficulty::Deadly,
+    }
+}
+
+/// Security details of `server_id` as `viewer` sees them. `scanned` holds
+/// what their last scan found, which is shown as is even if it went stale.
+pub fn view(
+    viewer: i64,
+    server_id: i64,
+    actual: &SecurityDetails,
+    scanned: Option<&SecurityDetails>,
+    cracker: f64,
+    config: &ServerBrowserConfig,
+) -> SecurityView {
+    if let Some(known) = scanned {
+        return SecurityView {
+            scanned: true,
+            firewall: Band::exact(known.firewall),
+            cpu: Band::exact(known.cpu as f64),
+            net: Band::exact(known.net as f64),
+            difficulty: difficulty(known.firewall, cracker),
+        };
+    }
+
+    let seed = mix(((viewer as u64) << 32) ^ server_id as u64);
+    let firewall = fuzz(actual.firewall, seed, config.fuzz_percent);
+    SecurityView {
+        scanned: false,
+        firewall,
+        cpu: fuzz(actual.cpu as f64, mix(seed ^ 1), config.fuzz_percent),
+        net: fuzz(actual.net as f64, mix(seed ^ 2), config.fuzz_percent),
+        difficulty: difficulty(firewall.mid(), cracker),
+    }
+}
+
+/// A band `percent` of `value` either side of a centre moved by up to as
+/// much again, so it always holds `value`
+fn fuzz(value: f64, seed: u64, percent: f64) -> Band {
+    let spread = value.abs() * percent / 100.0;
+    // In [-1, 1]
+    let offset = (seed >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0;
+    let centre = value + spread * offset;
+    let round = |v: f64| (v * 10.0).round() / 10.0;
+    Band { low: round((centre - spread).max(0.0)), high: round(centre + spread) }
+}
+
+/// SplitMix64, stable across builds unlike `std`'s hasher
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// An IPv4 range to scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanRange {
+    pub network: Ipv4Addr,
+    pub prefix: u8,
+}
+
+impl ScanRange {
+    /// As a Postgres `cidr`
+    pub fn cidr(&self) -> String {
+        format!("{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Parse `a.b.c.d/n`, no wider than the config allows. Host bits are
+/// dropped.
+pub fn parse_range(range: &str, config: &ServerBrowserConfig) -> Result<ScanRange, BrowserDenied> {
+    let (address, prefix) = range.trim().split_once('/').ok_or(BrowserDenied::BadRange)?;
+    let address: Ipv4Addr = address.parse().map_err(|_| BrowserDenied::BadRange)?;
+    let prefix: u8 = prefix.parse().map_err(|_| BrowserDenied::BadRange)?;
+    if prefix > 32 {
+        return Err(BrowserDenied::BadRange);
+    }
+    if prefix < config.min_range_prefix {
+        return Err(BrowserDenied::RangeTooWide { min_prefix: config.min_range_prefix });
+    }
+
+    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+    Ok(ScanRange { network: Ipv4Addr::from(u32::from(address) & mask), prefix })
+}
+
+/// Why a browse or scan was refused
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum BrowserDenied {
+    BadRange,
+    RangeTooWide { min_prefix: u8 },
+    RateLimited { limit: i64, window_minutes: i64 },
+}
+
+impl BrowserDenied {
+    pub fn message(&self) -> String {
+        match self {
+            BrowserDenied::BadRange => "Ranges look like 10.0.0.0/24".to_string(),
+            BrowserDenied::RangeTooWide { min_prefix } => format!("Ranges can be at most a /{}", min_prefix),
+            BrowserDenied::RateLimited { limit, window_minutes } => {
+                format!("At most {} requests every {} minutes", limit, window_minutes)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzed_view_holds_real_values() {
+        let config = ServerBrowserConfig::default();
+        let actual = SecurityDetails { firewall: 4.0, cpu: 2000, net: 100 };
+
+        let view_of = |viewer| view(viewer, 7, &actual, None, 4.0, &config);
+        let first = view_of(1);
+        assert!(!first.scanned);
+        assert!(first.firewall.low <= 4.0 && first.firewall.high >= 4.0);
+        assert!(first.cpu.low <= 2000.0 && first.cpu.high >= 2000.0);
+        assert!(first.firewall.high - first.firewall.low > 0.0);
+        // Fixed per player, different between players
+        assert_eq!(view_of(1), first);
+        assert!((2..10).any(|viewer| view_of(viewer).firewall != first.firewall));
+
+        let scanned = view(1, 7, &actual, Some(&actual), 4.0, &config);
+        assert!(scanned.scanned);
+        assert_eq!(scanned.firewall, Band::exact(4.0));
+        assert_eq!(scanned.difficulty, Difficulty::Fair);
+        assert_eq!(difficulty(1.0, 4.0), Difficulty::Trivial);
+        assert_eq!(difficulty(9.0, 4.0), Difficulty::Deadly);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let config = ServerBrowserConfig::default();
+        let range = parse_range("10.1.2.77/24", &config).unwrap();
+        assert_eq!(range.cidr(), "10.1.2.0/24");
+        assert_eq!(parse_range("10.1.2.77/32", &config).unwrap().cidr(), "10.1.2.77/32");
+        assert_eq!(parse_range("10.0.0.0/16", &config), Err(BrowserDenied::RangeTooWide { min_prefix: 24 }));
+        assert_eq!(parse_range("10.0.0.0", &config), Err(BrowserDenied::BadRange));
+        assert_eq!(parse_range("10.0.0.0/33", &config), Err(BrowserDenied::BadRange));
+    }
+}
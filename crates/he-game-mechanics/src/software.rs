@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
+use he_core::clock;
 
 /// Software types in the game
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -269,7 +270,7 @@ impl Software {
             license: LicenseType::Cracked,
             license_expires: None,
             installed_at: None,
-            last_updated: Some(SystemTime::now()),
+            last_updated: Some(clock::system_now()),
             is_running: false,
             is_hidden: false,
             is_infected: false,
@@ -285,7 +286,7 @@ impl Software {
     pub fn with_license(mut self, license: LicenseType) -> Self {
         self.license = license.clone();
         if let Some(days) = license.duration_days() {
-            self.license_expires = Some(SystemTime::now() + Duration::from_secs(days as u64 * 86400));
+            self.license_expires = Some(clock::system_now() + Duration::from_secs(days as u64 * 86400));
         }
         self
     }
@@ -300,7 +301,7 @@ impl Software {
             return Err("Software too corrupted to install".to_string());
         }
         
-        self.installed_at = Some(SystemTime::now());
+        self.installed_at = Some(clock::system_now());
         self.is_running = false;
         Ok(())
     }
@@ -315,7 +316,7 @@ impl Software {
         }
         
         if let Some(expires) = self.license_expires {
-            if SystemTime::now() > expires {
+            if clock::system_now() > expires {
                 return Err("License expired".to_string());
             }
         }
@@ -335,7 +336,7 @@ impl Software {
         
         self.version = new_version;
         self.size_mb = (self.size_mb as f32 * 1.1) as i32;
-        self.last_updated = Some(SystemTime::now());
+        self.last_updated = Some(clock::system_now());
         self.effectiveness = (self.effectiveness as f32 * 1.05).min(150.0) as i32;
         
         Ok(())
@@ -714,7 +715,7 @@ pub fn assess_vulnerabilities(software: &Software) -> Vec<String> {
         vulnerabilities.push("Active malware infection detected".to_string());
     }
     
-    if software.last_updated.map(|t| SystemTime::now().duration_since(t).unwrap_or_default().as_secs() > 30 * 86400).unwrap_or(true) {
+    if software.last_updated.map(|t| clock::system_now().duration_since(t).unwrap_or_default().as_secs() > 30 * 86400).unwrap_or(true) {
         vulnerabilities.push("No recent security updates".to_string());
     }
     
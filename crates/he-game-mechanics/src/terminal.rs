@@ -0,0 +1,224 @@
+//! Terminal shortcuts
+//!
+//! Players save aliases for command lines they type often and chain commands
+//! with `&&`: each stage runs only if the one before it succeeded, and is
+//! checked on its own when its turn comes. Experienced players get more
+//! alias slots and longer chains. Every command has a per-player cooldown so
+//! scripts cannot hammer it. How each stage's words are read is in
+//! [`crate::terminal_grammar`].
+
+use crate::config::TerminalConfig;
+use crate::dns::DnsDenied;
+use crate::terminal_grammar::SyntaxError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Separates the stages of a chained line
+pub const CHAIN_OPERATOR: &str = "&&";
+
+/// Aliases a player of `level` may keep
+pub fn alias_slots(level: i32, config: &TerminalConfig) -> usize {
+    let earned = (level.max(1) - 1) / config.levels_per_alias.max(1);
+    (config.base_aliases + earned as usize).min(config.max_aliases)
+}
+
+/// Stages one line may chain for a player of `level`
+pub fn max_chain_len(level: i32, config: &TerminalConfig) -> usize {
+    let earned = (level.max(1) - 1) / config.levels_per_chain_stage.max(1);
+    (config.base_chain_len + earned as usize).min(config.max_chain_len)
+}
+
+/// Milliseconds between two runs of `command` by the same player
+pub fn cooldown_ms(command: &str, config: &TerminalConfig) -> i64 {
+    config
+        .cooldown_overrides_ms
+        .iter()
+        .find(|(name, _)| name == command)
+        .map_or(config.command_cooldown_ms, |(_, ms)| *ms)
+}
+
+/// The command a stage runs: its first word
+pub fn command_of(stage: &str) -> &str {
+    stage.split_whitespace().next().unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum TerminalDenied {
+    EmptyCommand,
+    LineTooLong { max: usize },
+    ChainTooLong { max: usize },
+    UnknownCommand { command: String, suggestion: Option<String> },
+    /// The stage does not fit its command's grammar
+    Syntax { error: SyntaxError },
+    CoolingDown { command: String, retry_after_ms: i64 },
+    InvalidAliasName,
+    /// Aliases cannot hide a command
+    AliasShadowsCommand { name: String },
+    /// Aliases expand one level only
+    NestedAlias { name: String },
+    TooManyAliases { max: usize },
+    AliasNotFound,
+    /// The registry refused an `nslookup` or `whois`
+    Lookup { denied: DnsDenied },
+}
+
+impl TerminalDenied {
+    pub fn message(&self) -> String {
+        match self {
+            TerminalDenied::EmptyCommand => "Empty command".to_string(),
+            TerminalDenied::LineTooLong { max } => format!("Commands must be at most {} bytes", max),
+            TerminalDenied::ChainTooLong { max } => format!("You can chain at most {} commands", max),
+            TerminalDenied::UnknownCommand { command, suggestion: Some(suggestion) } => {
+                format!("Unknown command {}; did you mean {}?", command, suggestion)
+            }
+            TerminalDenied::UnknownCommand { command, suggestion: None } => format!("Unknown command {}", command),
+            TerminalDenied::Syntax { error } => error.message(),
+            TerminalDenied::CoolingDown { command, retry_after_ms } => {
+                format!("{} is cooling down, retry in {:.1}s", command, *retry_after_ms as f64 / 1000.0)
+            }
+            TerminalDenied::InvalidAliasName => {
+                "Alias names use lowercase letters, digits, - and _".to_string()
+            }
+            TerminalDenied::AliasShadowsCommand { name } => format!("{} is already a command", name),
+            TerminalDenied::NestedAlias { name } => format!("Aliases cannot run other aliases ({})", name),
+            TerminalDenied::TooManyAliases { max } => format!("You can keep at most {} aliases", max),
+            TerminalDenied::AliasNotFound => "Alias not found".to_string(),
+            TerminalDenied::Lookup { denied } => denied.message(),
+        }
+    }
+}
+
+/// The stages of `line`, split on `&&`
+pub fn split_chain(line: &str, config: &TerminalConfig) -> Result<Vec<String>, TerminalDenied> {
+    if line.len() > config.max_line_len {
+        return Err(TerminalDenied::LineTooLong { max: config.max_line_len });
+    }
+    let stages: Vec<String> = line.split(CHAIN_OPERATOR).map(|stage| stage.trim().to_string()).collect();
+    if stages.iter().any(String::is_empty) {
+        return Err(TerminalDenied::EmptyCommand);
+    }
+    Ok(stages)
+}
+
+/// Whether a player may save `name` for `expansion`, given the commands
+/// that exist and the aliases they already have
+pub fn check_alias(
+    name: &str,
+    expansion: &str,
+    commands: &[String],
+    aliases: &HashMap<String, String>,
+    level: i32,
+    config: &TerminalConfig,
+) -> Result<(), TerminalDenied> {
+    let valid = !name.is_empty()
+        && name.len() <= config.max_alias_name_len
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        return Err(TerminalDenied::InvalidAliasName);
+    }
+    if commands.iter().any(|command| command == name) {
+        return Err(TerminalDenied::AliasShadowsCommand { name: name.to_string() });
+    }
+
+    let stages = split_chain(expansion, config)?;
+    if let Some(nested) = stages.iter().map(|s| command_of(s)).find(|c| *c == name || aliases.contains_key(*c)) {
+        return Err(TerminalDenied::NestedAlias { name: nested.to_string() });
+    }
+    let max_stages = max_chain_len(level, config);
+    if stages.len() > max_stages {
+        return Err(TerminalDenied::ChainTooLong { max: max_stages });
+    }
+
+    let max = alias_slots(level, config);
+    if !aliases.contains_key(name) && aliases.len() >= max {
+        return Err(TerminalDenied::TooManyAliases { max });
+    }
+    Ok(())
+}
+
+/// The stages `line` runs, with aliases expanded. Arguments after an alias
+/// go to the last stage of its expansion.
+pub fn expand(
+    line: &str,
+    aliases: &HashMap<String, String>,
+    level: i32,
+    config: &TerminalConfig,
+) -> Result<Vec<String>, TerminalDenied> {
+    let mut expanded = Vec::new();
+    for stage in split_chain(line, config)? {
+        let command = command_of(&stage);
+        match aliases.get(command) {
+            Some(expansion) => {
+                let args = stage[command.len()..].trim();
+                let mut stages = split_chain(expansion, config)?;
+                if !args.is_empty() {
+                    if let Some(last) = stages.last_mut() {
+                        last.push(' ');
+                        last.push_str(args);
+                    }
+                }
+                expanded.extend(stages);
+            }
+            None => expanded.push(stage),
+        }
+    }
+
+    let max = max_chain_len(level, config);
+    if expanded.len() > max {
+        return Err(TerminalDenied::ChainTooLong { max });
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let config = TerminalConfig::default();
+        let aliases = HashMap::from([("sc".to_string(), "probe && crack --fast".to_string())]);
+
+        assert_eq!(
+            expand("sc 1.2.3.4", &aliases, 1, &config),
+            Ok(vec!["probe".to_string(), "crack --fast 1.2.3.4".to_string()])
+        );
+        assert_eq!(
+            expand("sc && probe", &aliases, 1, &config),
+            Err(TerminalDenied::ChainTooLong { max: config.base_chain_len })
+        );
+        assert_eq!(expand("sc && probe", &aliases, 11, &config).map(|s| s.len()), Ok(3));
+        assert_eq!(expand("probe &&", &aliases, 50, &config), Err(TerminalDenied::EmptyCommand));
+    }
+
+    #[test]
+    fn test_check_alias() {
+        let config = TerminalConfig::default();
+        let commands = vec!["probe".to_string()];
+        let mut aliases = HashMap::new();
+
+        assert_eq!(check_alias("p", "probe -v", &commands, &aliases, 1, &config), Ok(()));
+        assert_eq!(check_alias("Bad!", "probe", &commands, &aliases, 1, &config), Err(TerminalDenied::InvalidAliasName));
+        assert_eq!(
+            check_alias("probe", "probe -v", &commands, &aliases, 1, &config),
+            Err(TerminalDenied::AliasShadowsCommand { name: "probe".into() })
+        );
+
+        aliases.insert("p".to_string(), "probe -v".to_string());
+        assert_eq!(
+            check_alias("pp", "p && probe", &commands, &aliases, 20, &config),
+            Err(TerminalDenied::NestedAlias { name: "p".into() })
+        );
+        for name in ["a", "b"] {
+            aliases.insert(name.to_string(), "probe".to_string());
+        }
+        assert_eq!(
+            check_alias("c", "probe", &commands, &aliases, 1, &config),
+            Err(TerminalDenied::TooManyAliases { max: config.base_aliases })
+        );
+        assert_eq!(check_alias("a", "probe -q", &commands, &aliases, 1, &config), Ok(()));
+        assert_eq!(alias_slots(6, &config), config.base_aliases + 1);
+        assert_eq!(alias_slots(1000, &config), config.max_aliases);
+    }
+}
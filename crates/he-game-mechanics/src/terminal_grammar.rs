@@ -0,0 +1,583 @@
+//! Terminal command grammar
+//!
+//! A command line is split into words the way a shell would: single quotes
+//! take everything literally, double quotes and bare words honour `\`
+//! escapes. The words are then matched against the command's
+//! [`CommandSpec`]: subcommands first, then `--long`/`-s` flags (with
+//! `--flag=value`, `--flag value` and bundled short switches like `-vq`) and
+//! positional arguments, with `--` ending the flags. Mistyped subcommands
+//! and flags are answered with the closest known one.
+//!
+//! Help is generated from the same specs. Every description and heading goes
+//! through a [`Localizer`] under a stable key, so translations can be added
+//! without touching the grammar; untranslated keys fall back to the English
+//! text in the spec.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Built-in command that prints help generated from the grammar
+pub const HELP_COMMAND: &str = "help";
+
+/// Looks up translated text by key
+pub trait Localizer {
+    /// The text for `key`, or `fallback` when there is no translation
+    fn text(&self, key: &str, fallback: &str) -> String;
+}
+
+/// Every key in its English fallback
+pub struct Untranslated;
+
+impl Localizer for Untranslated {
+    fn text(&self, _key: &str, fallback: &str) -> String {
+        fallback.to_string()
+    }
+}
+
+impl Localizer for HashMap<String, String> {
+    fn text(&self, key: &str, fallback: &str) -> String {
+        self.get(key).cloned().unwrap_or_else(|| fallback.to_string())
+    }
+}
+
+/// One command or subcommand
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CommandSpec {
+    pub name: String,
+    /// English description, translated under `terminal.<path>.description`
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub subcommands: Vec<CommandSpec>,
+    #[serde(default)]
+    pub flags: Vec<FlagSpec>,
+    #[serde(default)]
+    pub args: Vec<ArgSpec>,
+    /// Accepts any flags and arguments; commands without a declared grammar
+    #[serde(default)]
+    pub open: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct FlagSpec {
+    pub long: String,
+    #[serde(default)]
+    pub short: Option<char>,
+    /// Name of the value the flag takes, if it takes one
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Translated under `terminal.<path>.flag.<long>`
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ArgSpec {
+    pub name: String,
+    #[serde(default)]
+    pub optional: bool,
+    /// Takes every remaining word; only the last argument may
+    #[serde(default)]
+    pub variadic: bool,
+    /// Translated under `terminal.<path>.arg.<name>`
+    #[serde(default)]
+    pub description: String,
+}
+
+impl CommandSpec {
+    /// A command that accepts anything
+    pub fn open(name: &str) -> Self {
+        Self { name: name.to_string(), open: true, ..Self::default() }
+    }
+
+    fn subcommand(&self, name: &str) -> Option<&CommandSpec> {
+        self.subcommands.iter().find(|sub| sub.name == name)
+    }
+
+    fn flag(&self, long: &str) -> Option<&FlagSpec> {
+        self.flags.iter().find(|flag| flag.long == long)
+    }
+
+    fn short_flag(&self, short: char) -> Option<&FlagSpec> {
+        self.flags.iter().find(|flag| flag.short == Some(short))
+    }
+}
+
+/// A line matched against its command's grammar
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct ParsedCommand {
+    /// The command and the subcommands chosen, outermost first
+    pub path: Vec<String>,
+    /// Flags given, by long name; `None` for switches
+    pub flags: BTreeMap<String, Option<String>>,
+    pub args: Vec<String>,
+    /// `--help` or `-h` was given
+    pub help: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum SyntaxError {
+    Empty,
+    UnterminatedQuote,
+    /// A `\` with nothing after it
+    DanglingEscape,
+    UnknownSubcommand { command: String, given: String, suggestion: Option<String> },
+    UnknownFlag { command: String, flag: String, suggestion: Option<String> },
+    MissingValue { flag: String },
+    /// A switch was given `--flag=value`
+    UnexpectedValue { flag: String },
+    MissingArgument { command: String, name: String },
+    TooManyArguments { command: String, max: usize },
+}
+
+impl SyntaxError {
+    pub fn message(&self) -> String {
+        let did_you_mean = |suggestion: &Option<String>| {
+            suggestion.as_ref().map(|s| format!("; did you mean {}?", s)).unwrap_or_default()
+        };
+        match self {
+            SyntaxError::Empty => "Empty command".to_string(),
+            SyntaxError::UnterminatedQuote => "Unterminated quote".to_string(),
+            SyntaxError::DanglingEscape => "Nothing to escape after \\".to_string(),
+            SyntaxError::UnknownSubcommand { command, given, suggestion } => {
+                format!("{} has no subcommand {}{}", command, given, did_you_mean(suggestion))
+            }
+            SyntaxError::UnknownFlag { command, flag, suggestion } => {
+                format!("{} has no flag {}{}", command, flag, did_you_mean(suggestion))
+            }
+            SyntaxError::MissingValue { flag } => format!("{} needs a value", flag),
+            SyntaxError::UnexpectedValue { flag } => format!("{} does not take a value", flag),
+            SyntaxError::MissingArgument { command, name } => format!("{} needs <{}>", command, name),
+            SyntaxError::TooManyArguments { command, max } => {
+                format!("{} takes at most {} argument{}", command, max, if *max == 1 { "" } else { "s" })
+            }
+        }
+    }
+}
+
+/// The words of `line`
+pub fn tokenize(line: &str) -> Result<Vec<String>, SyntaxError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // A quoted empty string is still a word
+    let mut in_word = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(SyntaxError::UnterminatedQuote),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.push(chars.next().ok_or(SyntaxError::UnterminatedQuote)?),
+                        Some(c) => word.push(c),
+                        None => return Err(SyntaxError::UnterminatedQuote),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.push(chars.next().ok_or(SyntaxError::DanglingEscape)?);
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Edit distance between `a` and `b`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// The candidate closest to `given`, if any is close enough to be a typo
+pub fn suggest<'a>(given: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let max = (given.chars().count() / 3).clamp(1, 3);
+    candidates
+        .into_iter()
+        .map(|candidate| (distance(given, candidate), candidate))
+        .filter(|(d, _)| *d <= max)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Match the words of a line, command first, against `spec`
+pub fn parse(words: &[String], spec: &CommandSpec) -> Result<ParsedCommand, SyntaxError> {
+    let Some((_, mut rest)) = words.split_first() else {
+        return Err(SyntaxError::Empty);
+    };
+    let mut spec = spec;
+    let mut parsed = ParsedCommand { path: vec![spec.name.clone()], ..ParsedCommand::default() };
+
+    // Subcommands come before any flag or argument
+    while !spec.subcommands.is_empty() {
+        let Some((word, after)) = rest.split_first() else {
+            break;
+        };
+        if word.starts_with('-') {
+            break;
+        }
+        match spec.subcommand(word) {
+            Some(sub) => {
+                spec = sub;
+                parsed.path.push(sub.name.clone());
+                rest = after;
+            }
+            None if spec.args.is_empty() && !spec.open => {
+                return Err(SyntaxError::UnknownSubcommand {
+                    command: parsed.path.join(" "),
+                    given: word.clone(),
+                    suggestion: suggest(word, spec.subcommands.iter().map(|sub| sub.name.as_str())),
+                });
+            }
+            None => break,
+        }
+    }
+
+    let command = parsed.path.join(" ");
+    let unknown_flag = |flag: &str| SyntaxError::UnknownFlag {
+        command: command.clone(),
+        flag: flag.to_string(),
+        suggestion: suggest(
+            flag.trim_start_matches('-'),
+            spec.flags.iter().map(|flag| flag.long.as_str()).chain(["help"]),
+        )
+        .map(|long| format!("--{}", long)),
+    };
+
+    let mut words = rest.iter();
+    let mut flags_done = false;
+    while let Some(word) = words.next() {
+        if flags_done || word == "-" || !word.starts_with('-') {
+            parsed.args.push(word.clone());
+            continue;
+        }
+        if word == "--" {
+            flags_done = true;
+            continue;
+        }
+
+        if let Some(long) = word.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            match spec.flag(name) {
+                Some(flag) => {
+                    let value = take_value(flag, inline, &mut words)?;
+                    parsed.flags.insert(flag.long.clone(), value);
+                }
+                None if name == "help" => parsed.help = true,
+                None if spec.open => {
+                    parsed.flags.insert(name.to_string(), inline);
+                }
+                None => return Err(unknown_flag(word)),
+            }
+            continue;
+        }
+
+        // Short switches, bundled; a flag taking a value ends the bundle
+        let shorts: Vec<char> = word[1..].chars().collect();
+        for (i, short) in shorts.iter().enumerate() {
+            match spec.short_flag(*short) {
+                Some(flag) if flag.value.is_some() => {
+                    let attached: String = shorts[i + 1..].iter().collect();
+                    let inline = (!attached.is_empty()).then_some(attached);
+                    let value = take_value(flag, inline, &mut words)?;
+                    parsed.flags.insert(flag.long.clone(), value);
+                    break;
+                }
+                Some(flag) => {
+                    parsed.flags.insert(flag.long.clone(), None);
+                }
+                None if *short == 'h' => parsed.help = true,
+                None if spec.open => {
+                    parsed.flags.insert(short.to_string(), None);
+                }
+                None => return Err(unknown_flag(&format!("-{}", short))),
+            }
+        }
+    }
+
+    // Help is answered whatever else is missing
+    if parsed.help || spec.open {
+        return Ok(parsed);
+    }
+    let variadic = spec.args.last().is_some_and(|arg| arg.variadic);
+    if !variadic && parsed.args.len() > spec.args.len() {
+        return Err(SyntaxError::TooManyArguments { command, max: spec.args.len() });
+    }
+    if let Some(missing) = spec.args.iter().skip(parsed.args.len()).find(|arg| !arg.optional) {
+        return Err(SyntaxError::MissingArgument { command, name: missing.name.clone() });
+    }
+    Ok(parsed)
+}
+
+fn take_value<'a>(
+    flag: &FlagSpec,
+    inline: Option<String>,
+    words: &mut impl Iterator<Item = &'a String>,
+) -> Result<Option<String>, SyntaxError> {
+    match (&flag.value, inline) {
+        (None, None) => Ok(None),
+        (None, Some(_)) => Err(SyntaxError::UnexpectedValue { flag: format!("--{}", flag.long) }),
+        (Some(_), Some(value)) => Ok(Some(value)),
+        (Some(_), None) => words
+            .next()
+            .map(|value| Some(value.clone()))
+            .ok_or_else(|| SyntaxError::MissingValue { flag: format!("--{}", flag.long) }),
+    }
+}
+
+/// The deepest subcommand named by `words` (command first), with its path.
+/// Words from the first that is not a subcommand on are ignored.
+pub fn resolve<'a>(spec: &'a CommandSpec, words: &[String]) -> (&'a CommandSpec, Vec<String>) {
+    let mut path = vec![spec.name.clone()];
+    let mut spec = spec;
+    for word in words.iter().skip(1) {
+        match spec.subcommand(word) {
+            Some(sub) => {
+                spec = sub;
+                path.push(sub.name.clone());
+            }
+            None => break,
+        }
+    }
+    (spec, path)
+}
+
+fn key(path: &[String], rest: &str) -> String {
+    format!("terminal.{}.{}", path.join("."), rest)
+}
+
+/// Localized description of the command at `path`
+pub fn describe(spec: &CommandSpec, path: &[String], localizer: &dyn Localizer) -> String {
+    localizer.text(&key(path, "description"), &spec.description)
+}
+
+fn usage(spec: &CommandSpec, path: &[String]) -> String {
+    let mut usage = path.join(" ");
+    if !spec.subcommands.is_empty() {
+        usage.push_str(" <command>");
+    }
+    if !spec.flags.is_empty() || spec.open {
+        usage.push_str(" [flags]");
+    }
+    for arg in &spec.args {
+        let dots = if arg.variadic { "..." } else { "" };
+        if arg.optional {
+            usage.push_str(&format!(" [{}{}]", arg.name, dots));
+        } else {
+            usage.push_str(&format!(" <{}{}>", arg.name, dots));
+        }
+    }
+    if spec.open && spec.args.is_empty() {
+        usage.push_str(" [args...]");
+    }
+    usage
+}
+
+/// Help for the (sub)command named by `words`: usage, then its subcommands,
+/// flags and arguments with their descriptions
+pub fn help(spec: &CommandSpec, words: &[String], localizer: &dyn Localizer) -> String {
+    let (spec, path) = resolve(spec, words);
+    let path = path.as_slice();
+    let mut lines = vec![format!("{}: {}", localizer.text("terminal.help.usage", "Usage"), usage(spec, path))];
+
+    let description = describe(spec, path, localizer);
+    if !description.is_empty() {
+        lines.push(description);
+    }
+
+    if !spec.subcommands.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("{}:", localizer.text("terminal.help.commands", "Commands")));
+        for sub in &spec.subcommands {
+            let mut sub_path = path.to_vec();
+            sub_path.push(sub.name.clone());
+            lines.push(format!("  {:<16} {}", sub.name, describe(sub, &sub_path, localizer)));
+        }
+    }
+
+    if !spec.args.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("{}:", localizer.text("terminal.help.arguments", "Arguments")));
+        for arg in &spec.args {
+            let text = localizer.text(&key(path, &format!("arg.{}", arg.name)), &arg.description);
+            lines.push(format!("  {:<16} {}", arg.name, text));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push(format!("{}:", localizer.text("terminal.help.flags", "Flags")));
+    for flag in &spec.flags {
+        let short = flag.short.map(|s| format!("-{}, ", s)).unwrap_or_default();
+        let value = flag.value.as_ref().map(|v| format!(" <{}>", v)).unwrap_or_default();
+        let text = localizer.text(&key(path, &format!("flag.{}", flag.long)), &flag.description);
+        lines.push(format!("  {:<16} {}", format!("{}--{}{}", short, flag.long, value), text));
+    }
+    lines.push(format!("  {:<16} {}", "-h, --help", localizer.text("terminal.help.help_flag", "Show this help")));
+
+    lines.join("\n")
+}
+
+/// Every command with its description, for `help` on its own
+pub fn help_index(specs: &[CommandSpec], localizer: &dyn Localizer) -> String {
+    let mut lines = vec![format!("{}:", localizer.text("terminal.help.commands", "Commands"))];
+    for spec in specs {
+        let path = [spec.name.clone()];
+        let line = format!("  {:<16} {}", spec.name, describe(spec, &path, localizer));
+        lines.push(line.trim_end().to_string());
+    }
+    lines.push(String::new());
+    lines.push(localizer.text("terminal.help.more", "Run help <command> for details"));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crack() -> CommandSpec {
+        CommandSpec {
+            name: "crack".to_string(),
+            description: "Crack a server".to_string(),
+            subcommands: vec![CommandSpec {
+                name: "password".to_string(),
+                description: "Crack the root password".to_string(),
+                flags: vec![
+                    FlagSpec { long: "fast".to_string(), short: Some('f'), ..FlagSpec::default() },
+                    FlagSpec { long: "verbose".to_string(), short: Some('v'), ..FlagSpec::default() },
+                    FlagSpec {
+                        long: "wordlist".to_string(),
+                        short: Some('w'),
+                        value: Some("file".to_string()),
+                        ..FlagSpec::default()
+                    },
+                ],
+                args: vec![ArgSpec { name: "ip".to_string(), ..ArgSpec::default() }],
+                ..CommandSpec::default()
+            }],
+            ..CommandSpec::default()
+        }
+    }
+
+    fn words(line: &str) -> Vec<String> {
+        tokenize(line).unwrap()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(words(r#"say "hello world"  it\'s 'a "b"' "#), vec!["say", "hello world", "it's", r#"a "b""#]);
+        assert_eq!(words(r#"touch "" x"#), vec!["touch", "", "x"]);
+        assert_eq!(tokenize("say \"oops"), Err(SyntaxError::UnterminatedQuote));
+        assert_eq!(tokenize("say oops\\"), Err(SyntaxError::DanglingEscape));
+    }
+
+    #[test]
+    fn test_parse() {
+        let spec = crack();
+        let parsed = parse(&words("crack password -fv --wordlist=rock.txt 1.2.3.4"), &spec).unwrap();
+        assert_eq!(parsed.path, vec!["crack", "password"]);
+        assert_eq!(parsed.flags.get("fast"), Some(&None));
+        assert_eq!(parsed.flags.get("wordlist"), Some(&Some("rock.txt".to_string())));
+        assert_eq!(parsed.args, vec!["1.2.3.4"]);
+
+        let parsed = parse(&words("crack password -wrock.txt -- -odd"), &spec).unwrap();
+        assert_eq!(parsed.flags.get("wordlist"), Some(&Some("rock.txt".to_string())));
+        assert_eq!(parsed.args, vec!["-odd"]);
+
+        assert!(parse(&words("crack password --help"), &spec).unwrap().help);
+        assert_eq!(parse(&words("crack"), &CommandSpec::open("crack")).map(|p| p.path.len()), Ok(1));
+    }
+
+    #[test]
+    fn test_errors_suggest() {
+        let spec = crack();
+        assert_eq!(
+            parse(&words("crack pasword 1.2.3.4"), &spec),
+            Err(SyntaxError::UnknownSubcommand {
+                command: "crack".into(),
+                given: "pasword".into(),
+                suggestion: Some("password".into())
+            })
+        );
+        let error = parse(&words("crack password --verbos 1.2.3.4"), &spec).unwrap_err();
+        assert_eq!(error.message(), "crack password has no flag --verbos; did you mean --verbose?");
+        assert_eq!(
+            parse(&words("crack password -x 1.2.3.4"), &spec).unwrap_err().message(),
+            "crack password has no flag -x"
+        );
+        assert_eq!(
+            parse(&words("crack password --wordlist"), &spec),
+            Err(SyntaxError::MissingValue { flag: "--wordlist".into() })
+        );
+        assert_eq!(
+            parse(&words("crack password --fast=yes 1.2.3.4"), &spec),
+            Err(SyntaxError::UnexpectedValue { flag: "--fast".into() })
+        );
+        assert_eq!(
+            parse(&words("crack password"), &spec),
+            Err(SyntaxError::MissingArgument { command: "crack password".into(), name: "ip".into() })
+        );
+        assert_eq!(
+            parse(&words("crack password a b"), &spec),
+            Err(SyntaxError::TooManyArguments { command: "crack password".into(), max: 1 })
+        );
+        assert_eq!(suggest("scn", ["scan", "crack"]), Some("scan".to_string()));
+        assert_eq!(suggest("zzzz", ["scan", "crack"]), None);
+    }
+
+    #[test]
+    fn test_help_is_localized() {
+        let spec = crack();
+        let path = vec!["crack".to_string(), "password".to_string()];
+        let english = help(&spec, &path, &Untranslated);
+        assert!(english.starts_with("Usage: crack password [flags] <ip>"));
+        assert!(english.contains("-w, --wordlist <file>"));
+
+        let portuguese = HashMap::from([
+            ("terminal.help.usage".to_string(), "Uso".to_string()),
+            ("terminal.crack.password.description".to_string(), "Quebra a senha root".to_string()),
+        ]);
+        let translated = help(&spec, &path, &portuguese);
+        assert!(translated.starts_with("Uso: crack password"));
+        assert!(translated.contains("Quebra a senha root"));
+        assert!(help_index(&[spec], &portuguese).contains("Crack a server"));
+    }
+}
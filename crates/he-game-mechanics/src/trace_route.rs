@@ -0,0 +1,322 @@
+//! Trace-route mini-game
+//!
+//! A defender tracing a detected attacker follows the connection back one
+//! server at a time, starting at their own: reading a hop's logs tells where
+//! the connection came from, which is the next hop, and reading the last
+//! bounce gives the attacker's real gateway. A hop takes longer to read the
+//! less of its log trail is left, and a hop whose trail is nearly gone
+//! cannot be read at all, which breaks the trace.
+//!
+//! The attacker sees the trace coming and can wipe their trail from hops the
+//! trace has not read yet. A wipe takes time, so it is a race: wiping the
+//! hop being read breaks the trace only if the wipe lands before the read
+//! finishes.
+
+use crate::config::DetectionConfig;
+use crate::detection::{HostileProcessMonitor, TraceAttacker};
+use crate::honeypot::Route;
+use crate::{GameMechanicsError, Result};
+use serde::{Deserialize, Serialize};
+
+/// The faction that rewards catching attackers
+pub const REPUTATION_FACTION: &str = "security";
+
+/// A server the trace has to read
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hop {
+    pub ip: String,
+    /// The address the hop's logs show the connection coming from
+    pub from_ip: String,
+    /// Share of the hop's log trail still intact, 0 to 1
+    pub integrity: f64,
+    pub resolve_seconds: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceStatus {
+    Running,
+    Broken,
+    Completed,
+}
+
+/// Something that happened to a trace, for both sides to hear about
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum TraceStep {
+    /// Hop `hop` was read; it showed `from_ip`
+    Resolved { hop: usize, from_ip: String },
+    /// Hop `hop` lost integrity to a wipe; `broken` when that ended the trace
+    Tampered { hop: usize, integrity: f64, broken: bool },
+    /// Hop `hop` had too little trail left to read
+    Broken { hop: usize },
+    Completed { attacker_ip: String },
+}
+
+/// A running trace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceRoute {
+    pub defender_id: u64,
+    /// Defender's own server first, the first bounce last
+    pub hops: Vec<Hop>,
+    /// Seconds to read a hop with an intact trail
+    pub base_seconds: i64,
+    /// The hop being read
+    pub current: usize,
+    pub elapsed_on_hop: i64,
+    pub status: TraceStatus,
+}
+
+/// Share of a hop's trail left when `intact` of its log lines remain and
+/// `wiped` are gone. A hop with no trail at all cannot be read.
+pub fn log_integrity(intact: i64, wiped: i64) -> f64 {
+    let total = intact.max(0) + wiped.max(0);
+    if total == 0 {
+        return 0.0;
+    }
+    intact.max(0) as f64 / total as f64
+}
+
+/// Seconds to read a hop of `integrity` when an intact one takes `base_seconds`
+pub fn hop_seconds(base_seconds: i64, integrity: f64, config: &DetectionConfig) -> i64 {
+    let slowdown = 1.0 + config.trace_integrity_slowdown * (1.0 - integrity.clamp(0.0, 1.0));
+    ((base_seconds as f64 * slowdown).round() as i64).max(1)
+}
+
+impl TraceRoute {
+    pub const PROCESS_TYPE: &'static str = TraceAttacker::PROCESS_TYPE;
+
+    /// Start tracing the attack `monitor` watched back along `route`, with
+    /// `integrity` holding the trail left on each server of the route from
+    /// the target back to the first bounce. Only detected attacks can be traced.
+    pub fn start(
+        monitor: &HostileProcessMonitor,
+        route: &Route,
+        integrity: &[f64],
+        defender_cpu: i32,
+        attacker_stealth: i32,
+        config: &DetectionConfig,
+    ) -> Result<Self> {
+        if !monitor.detected {
+            return Err(GameMechanicsError::PreconditionFailed(
+                "Attack has not been detected".to_string(),
+            ));
+        }
+        if defender_cpu <= 0 {
+            return Err(GameMechanicsError::InvalidParameter("CPU must be positive".to_string()));
+        }
+
+        let servers = Self::servers(route);
+        if integrity.len() != servers.len() {
+            return Err(GameMechanicsError::InvalidParameter(format!(
+                "Expected the integrity of {} hops, got {}",
+                servers.len(),
+                integrity.len()
+            )));
+        }
+
+        // 1000 MHz is the reference CPU, as for the whole-trace estimate
+        let base = config.trace_hop_seconds + attacker_stealth.max(0) as i64 * config.trace_seconds_per_stealth / 2;
+        let base_seconds = ((base as f64 * 1000.0 / defender_cpu as f64) as i64).max(1);
+
+        let hops = servers
+            .into_iter()
+            .zip(integrity)
+            .map(|((ip, from_ip), &integrity)| Hop {
+                ip,
+                from_ip,
+                integrity: integrity.clamp(0.0, 1.0),
+                resolve_seconds: hop_seconds(base_seconds, integrity, config),
+            })
+            .collect();
+
+        Ok(Self {
+            defender_id: monitor.defender_id,
+            hops,
+            base_seconds,
+            current: 0,
+            elapsed_on_hop: 0,
+            status: TraceStatus::Running,
+        })
+    }
+
+    /// The servers a trace of `route` reads, each with the address its logs
+    /// show: the target first, back to the first bounce, which shows the
+    /// gateway
+    pub fn servers(route: &Route) -> Vec<(String, String)> {
+        let hops = route.hops();
+        hops.windows(2).rev().map(|pair| (pair[1].to_string(), pair[0].to_string())).collect()
+    }
+
+    /// Whole seconds left if nothing changes
+    pub fn remaining_seconds(&self) -> i64 {
+        if self.status != TraceStatus::Running {
+            return 0;
+        }
+        let left: i64 = self.hops[self.current..].iter().map(|hop| hop.resolve_seconds).sum();
+        left - self.elapsed_on_hop
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.status == TraceStatus::Completed {
+            return 1.0;
+        }
+        let Some(hop) = self.hops.get(self.current) else {
+            return 1.0;
+        };
+        let within = self.elapsed_on_hop as f32 / hop.resolve_seconds.max(1) as f32;
+        ((self.current as f32 + within) / self.hops.len() as f32).min(1.0)
+    }
+
+    /// Read for `seconds` more
+    pub fn advance(&mut self, seconds: i64, config: &DetectionConfig) -> Vec<TraceStep> {
+        let mut steps = Vec::new();
+        let mut seconds = seconds.max(0);
+
+        while self.status == TraceStatus::Running {
+            let hop = &self.hops[self.current];
+            if hop.integrity < config.trace_break_integrity {
+                self.status = TraceStatus::Broken;
+                steps.push(TraceStep::Broken { hop: self.current });
+                break;
+            }
+
+            let needed = hop.resolve_seconds - self.elapsed_on_hop;
+            if seconds < needed {
+                self.elapsed_on_hop += seconds;
+                break;
+            }
+
+            seconds -= needed;
+            self.elapsed_on_hop = 0;
+            steps.push(TraceStep::Resolved { hop: self.current, from_ip: hop.from_ip.clone() });
+            self.current += 1;
+            if self.current == self.hops.len() {
+                self.status = TraceStatus::Completed;
+                steps.push(TraceStep::Completed { attacker_ip: self.hops[self.current - 1].from_ip.clone() });
+            }
+        }
+
+        steps
+    }
+
+    /// Whether `ip` is a hop the trace has yet to read, so wiping it still matters
+    pub fn unread_hop(&self, ip: &str) -> Option<usize> {
+        if self.status != TraceStatus::Running {
+            return None;
+        }
+        (self.current..self.hops.len()).find(|&index| self.hops[index].ip == ip)
+    }
+
+    /// The attacker's wipe of hop `ip` landed, leaving `integrity`. A hop
+    /// already read is past saving; the one being read breaks the trace if
+    /// too little is left, and later ones are read slower or break when the
+    /// trace gets there.
+    pub fn wipe(&mut self, ip: &str, integrity: f64, config: &DetectionConfig) -> Option<TraceStep> {
+        let index = self.unread_hop(ip)?;
+        let hop = &mut self.hops[index];
+        hop.integrity = integrity.clamp(0.0, 1.0).min(hop.integrity);
+        hop.resolve_seconds = hop_seconds(self.base_seconds, hop.integrity, config);
+
+        let broken = index == self.current && hop.integrity < config.trace_break_integrity;
+        if broken {
+            self.status = TraceStatus::Broken;
+        } else if index == self.current {
+            // Time already spent still counts, but cannot finish the hop by itself
+            self.elapsed_on_hop = self.elapsed_on_hop.min(hop.resolve_seconds - 1);
+        }
+        Some(TraceStep::Tampered { hop: index, integrity: hop.integrity, broken })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::{DefenderProfile, HostileActivity};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn detected_monitor(config: &DetectionConfig) -> HostileProcessMonitor {
+        let config = DetectionConfig { min_chance: 1.0, max_chance: 1.0, ..config.clone() };
+        let defender = DefenderProfile { firewall_level: 50, security_rating: 50, ids_active: false };
+        let mut monitor = HostileProcessMonitor::new(
+            1, 2, HostileActivity::Crack, "10.0.0.3".to_string(), &defender, 0, &config,
+        );
+        monitor.observe(0.25, &mut StdRng::seed_from_u64(1));
+        monitor
+    }
+
+    fn route() -> Route {
+        Route {
+            gateway_ip: "10.0.0.1".to_string(),
+            bounce_ips: vec!["10.0.0.2".to_string(), "10.0.0.3".to_string()],
+            host_ip: None,
+            target_ip: "10.0.0.9".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_trace_reads_back_to_the_gateway() {
+        let config = DetectionConfig::default();
+        let mut trace = TraceRoute::start(&detected_monitor(&config), &route(), &[1.0; 3], 1000, 0, &config).unwrap();
+        assert_eq!(
+            trace.hops.iter().map(|h| (h.ip.as_str(), h.from_ip.as_str())).collect::<Vec<_>>(),
+            vec![("10.0.0.9", "10.0.0.3"), ("10.0.0.3", "10.0.0.2"), ("10.0.0.2", "10.0.0.1")]
+        );
+
+        let per_hop = config.trace_hop_seconds;
+        assert_eq!(trace.advance(per_hop - 1, &config), vec![]);
+        assert_eq!(trace.advance(1, &config), vec![TraceStep::Resolved { hop: 0, from_ip: "10.0.0.3".to_string() }]);
+        let steps = trace.advance(per_hop * 5, &config);
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[2], TraceStep::Completed { attacker_ip: "10.0.0.1".to_string() });
+        assert_eq!(trace.status, TraceStatus::Completed);
+        assert_eq!(trace.progress(), 1.0);
+    }
+
+    #[test]
+    fn test_worn_trails_are_slower_and_bare_ones_break() {
+        let config = DetectionConfig::default();
+        assert!(hop_seconds(100, 0.5, &config) > hop_seconds(100, 1.0, &config));
+        assert_eq!(log_integrity(0, 0), 0.0);
+        assert_eq!(log_integrity(1, 3), 0.25);
+
+        let mut trace = TraceRoute::start(&detected_monitor(&config), &route(), &[1.0, 0.1, 1.0], 1000, 0, &config).unwrap();
+        let steps = trace.advance(10_000, &config);
+        assert_eq!(steps.last(), Some(&TraceStep::Broken { hop: 1 }));
+        assert_eq!(trace.status, TraceStatus::Broken);
+    }
+
+    #[test]
+    fn test_wipe_races_the_read() {
+        let config = DetectionConfig::default();
+        let mut trace = TraceRoute::start(&detected_monitor(&config), &route(), &[1.0; 3], 1000, 0, &config).unwrap();
+        trace.advance(config.trace_hop_seconds + 10, &config);
+
+        // The target was already read
+        assert_eq!(trace.wipe("10.0.0.9", 0.0, &config), None);
+        // A partial wipe of a later hop slows it down
+        let before = trace.remaining_seconds();
+        assert_eq!(
+            trace.wipe("10.0.0.2", 0.5, &config),
+            Some(TraceStep::Tampered { hop: 2, integrity: 0.5, broken: false })
+        );
+        assert!(trace.remaining_seconds() > before);
+        // Wiping the hop being read breaks the trace
+        assert_eq!(
+            trace.wipe("10.0.0.3", 0.0, &config),
+            Some(TraceStep::Tampered { hop: 1, integrity: 0.0, broken: true })
+        );
+        assert_eq!(trace.status, TraceStatus::Broken);
+        assert!(trace.advance(10_000, &config).is_empty());
+    }
+
+    #[test]
+    fn test_only_detected_attacks_with_a_full_route() {
+        let config = DetectionConfig::default();
+        let defender = DefenderProfile { firewall_level: 50, security_rating: 50, ids_active: false };
+        let unseen = HostileProcessMonitor::new(1, 2, HostileActivity::Crack, "10.0.0.3".to_string(), &defender, 0, &config);
+        assert!(TraceRoute::start(&unseen, &route(), &[1.0; 3], 1000, 0, &config).is_err());
+        assert!(TraceRoute::start(&detected_monitor(&config), &route(), &[1.0; 2], 1000, 0, &config).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Tutorial: the first hack, step by step
+//!
+//! New players walk through one hack against the First Whois server: scan
+//! it, crack it, download a file from it and delete the logs left behind.
+//! Steps only advance when the server completes the matching process against
+//! the whois address, so the client cannot skip ahead by claiming progress.
+//! Finishing the last step pays [`TutorialConfig`]'s reward once per account;
+//! skipping pays nothing.
+
+use crate::config::TutorialConfig;
+use crate::process::{CompletionReward, ProcessType};
+use serde::{Deserialize, Serialize};
+
+/// The First Whois server every tutorial targets
+pub const WHOIS_IP: &str = "1.2.3.4";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TutorialStep {
+    ScanWhois,
+    CrackWhois,
+    DownloadFile,
+    DeleteLogs,
+    Completed,
+    Skipped,
+}
+
+impl TutorialStep {
+    pub const FIRST: TutorialStep = TutorialStep::ScanWhois;
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TutorialStep::ScanWhois => "scan_whois",
+            TutorialStep::CrackWhois => "crack_whois",
+            TutorialStep::DownloadFile => "download_file",
+            TutorialStep::DeleteLogs => "delete_logs",
+            TutorialStep::Completed => "completed",
+            TutorialStep::Skipped => "skipped",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "scan_whois" => Some(TutorialStep::ScanWhois),
+            "crack_whois" => Some(TutorialStep::CrackWhois),
+            "download_file" => Some(TutorialStep::DownloadFile),
+            "delete_logs" => Some(TutorialStep::DeleteLogs),
+            "completed" => Some(TutorialStep::Completed),
+            "skipped" => Some(TutorialStep::Skipped),
+            _ => None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, TutorialStep::Completed | TutorialStep::Skipped)
+    }
+
+    /// The step after this one once it is done
+    pub fn next(&self) -> TutorialStep {
+        match self {
+            TutorialStep::ScanWhois => TutorialStep::CrackWhois,
+            TutorialStep::CrackWhois => TutorialStep::DownloadFile,
+            TutorialStep::DownloadFile => TutorialStep::DeleteLogs,
+            TutorialStep::DeleteLogs => TutorialStep::Completed,
+            finished => *finished,
+        }
+    }
+
+    /// Position for progress bars, 0 through [`TutorialStep::COUNT`]
+    pub fn index(&self) -> usize {
+        match self {
+            TutorialStep::ScanWhois => 0,
+            TutorialStep::CrackWhois => 1,
+            TutorialStep::DownloadFile => 2,
+            TutorialStep::DeleteLogs => 3,
+            TutorialStep::Completed | TutorialStep::Skipped => 4,
+        }
+    }
+
+    pub const COUNT: usize = 4;
+
+    /// Whether a completed process finishes this step
+    pub fn completed_by(&self, process_type: &ProcessType, target_ip: Option<&str>) -> bool {
+        if target_ip != Some(WHOIS_IP) {
+            return false;
+        }
+        match self {
+            TutorialStep::ScanWhois => matches!(process_type, ProcessType::PortScan | ProcessType::SystemScan),
+            TutorialStep::CrackWhois => matches!(process_type, ProcessType::Crack | ProcessType::BruteForce),
+            TutorialStep::DownloadFile => matches!(process_type, ProcessType::Download),
+            TutorialStep::DeleteLogs => matches!(process_type, ProcessType::DeleteLog),
+            TutorialStep::Completed | TutorialStep::Skipped => false,
+        }
+    }
+
+    /// What the client shows for this step
+    pub fn hint(&self) -> TutorialHint {
+        let (title, body, highlight) = match self {
+            TutorialStep::ScanWhois => (
+                "Find your first target",
+                "Open the Internet page and scan the First Whois server to see what it runs.",
+                "internet.scan",
+            ),
+            TutorialStep::CrackWhois => (
+                "Break in",
+                "Run your cracker against the whois server to get its password.",
+                "internet.hack",
+            ),
+            TutorialStep::DownloadFile => (
+                "Take something",
+                "You are in. Open the server's software list and download a file.",
+                "internet.software",
+            ),
+            TutorialStep::DeleteLogs => (
+                "Cover your tracks",
+                "Your login and download were logged. Delete the log entries on the whois server.",
+                "internet.logs",
+            ),
+            TutorialStep::Completed => (
+                "Tutorial complete",
+                "You have done your first hack. The rest of the Internet is waiting.",
+                "",
+            ),
+            TutorialStep::Skipped => ("Tutorial skipped", "You can restart the tutorial at any time.", ""),
+        };
+
+        TutorialHint {
+            step: *self,
+            step_number: self.index(),
+            total_steps: Self::COUNT,
+            title,
+            body,
+            highlight,
+            target_ip: (!self.is_finished()).then_some(WHOIS_IP),
+        }
+    }
+}
+
+/// A contextual hint for the client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TutorialHint {
+    pub step: TutorialStep,
+    pub step_number: usize,
+    pub total_steps: usize,
+    pub title: &'static str,
+    pub body: &'static str,
+    /// UI element the client should point at; empty for none
+    pub highlight: &'static str,
+    pub target_ip: Option<&'static str>,
+}
+
+/// The step a completed process moves a player on `step` to, if any
+pub fn advance(step: TutorialStep, process_type: &ProcessType, target_ip: Option<&str>) -> Option<TutorialStep> {
+    step.completed_by(process_type, target_ip).then(|| step.next())
+}
+
+/// What finishing the tutorial pays
+pub fn completion_reward(config: &TutorialConfig) -> CompletionReward {
+    CompletionReward {
+        experience: config.reward_experience,
+        money: config.reward_money,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walkthrough() {
+        let whois = Some(WHOIS_IP);
+        let mut step = TutorialStep::FIRST;
+
+        // Wrong process or wrong server does nothing
+        assert_eq!(advance(step, &ProcessType::Crack, whois), None);
+        assert_eq!(advance(step, &ProcessType::PortScan, Some("10.0.0.1")), None);
+
+        for process in [ProcessType::PortScan, ProcessType::Crack, ProcessType::Download, ProcessType::DeleteLog] {
+            step = advance(step, &process, whois).expect("step advances");
+        }
+        assert_eq!(step, TutorialStep::Completed);
+        assert_eq!(advance(step, &ProcessType::PortScan, whois), None);
+    }
+
+    #[test]
+    fn test_steps_round_trip_and_hint() {
+        for step in [
+            TutorialStep::ScanWhois,
+            TutorialStep::CrackWhois,
+            TutorialStep::DownloadFile,
+            TutorialStep::DeleteLogs,
+            TutorialStep::Completed,
+            TutorialStep::Skipped,
+        ] {
+            assert_eq!(TutorialStep::from_str(step.as_str()), Some(step));
+        }
+
+        let hint = TutorialStep::DeleteLogs.hint();
+        assert_eq!(hint.step_number, 3);
+        assert_eq!(hint.target_ip, Some(WHOIS_IP));
+        assert_eq!(TutorialStep::Skipped.hint().target_ip, None);
+    }
+}
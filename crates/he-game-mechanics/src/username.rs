@@ -0,0 +1,171 @@
+//! Username changes
+//!
+//! Players may change their username once per cooldown. A new name must not
+//! be taken by anyone else in any letter case, must not be on the blocked
+//! list, and must not be a name someone else gave up recently: a given-up
+//! name stays reserved for its old owner for a while, so nobody can pick up
+//! a well-known name the day it is dropped and trade on it. The blocked list
+//! is matched on the name's skeleton, which folds case, separators and the
+//! usual digit-for-letter swaps, so "4dm1n" is as blocked as "admin".
+
+use crate::config::UsernameConfig;
+use he_core::validation::find_offensive_terms;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Names nobody may take, as skeletons
+const BLOCKED: &[&str] = &[
+    "admin", "administrator", "root", "system", "moderator", "mod", "staff", "support", "helpdesk", "official",
+    "server", "gamemaster", "gm", "anonymous", "null", "undefined", "everyone",
+];
+
+/// Skeletons no name may start with; staff titles followed by anything
+const BLOCKED_PREFIXES: &[&str] = &["admin", "moderator", "staff", "official", "gamemaster", "support"];
+
+/// Why a username change was refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RenameDenied {
+    UserNotFound,
+    TooShort { min: usize },
+    TooLong { max: usize },
+    /// Letters, digits, `_`, `-` and `.`, starting with a letter
+    InvalidCharacters,
+    SameName,
+    Blocked,
+    Taken,
+    /// Someone else gave it up recently
+    Reserved { until: DateTime<Utc> },
+    Cooldown { until: DateTime<Utc> },
+}
+
+impl RenameDenied {
+    pub fn message(&self) -> String {
+        match self {
+            RenameDenied::UserNotFound => "User not found".to_string(),
+            RenameDenied::TooShort { min } => format!("Usernames are at least {} characters", min),
+            RenameDenied::TooLong { max } => format!("Usernames are at most {} characters", max),
+            RenameDenied::InvalidCharacters => {
+                "Usernames start with a letter and use only letters, digits, '_', '-' and '.'".to_string()
+            }
+            RenameDenied::SameName => "That is already your username".to_string(),
+            RenameDenied::Blocked => "That username is not allowed".to_string(),
+            RenameDenied::Taken => "That username is taken".to_string(),
+            RenameDenied::Reserved { until } => {
+                format!("That username was given up recently and is reserved until {}", until.format("%Y-%m-%d"))
+            }
+            RenameDenied::Cooldown { until } => {
+                format!("You can change your username again on {}", until.format("%Y-%m-%d %H:%M UTC"))
+            }
+        }
+    }
+}
+
+/// The name folded for lookalike comparison: lowercase, separators dropped,
+/// digits and symbols read as the letters they stand in for
+pub fn skeleton(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '_' | '-' | '.'))
+        .map(|c| match c.to_ascii_lowercase() {
+            '0' => 'o',
+            '1' | '!' | '|' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            '8' => 'b',
+            'l' => 'i',
+            c => c,
+        })
+        .collect()
+}
+
+/// Whether the name, or a lookalike of it, is on the blocked list, or the
+/// name has one of `offensive_terms` in it
+pub fn is_blocked(name: &str, offensive_terms: &[String]) -> bool {
+    let skeleton = skeleton(name);
+    BLOCKED.iter().any(|blocked| skeleton == self::skeleton(blocked))
+        || BLOCKED_PREFIXES.iter().any(|prefix| skeleton.starts_with(&self::skeleton(prefix)))
+        || !find_offensive_terms(name, offensive_terms).is_empty()
+}
+
+/// Whether `name` can be a username at all, before asking who holds it
+pub fn validate(name: &str, config: &UsernameConfig, offensive_terms: &[String]) -> Result<(), RenameDenied> {
+    let length = name.chars().count();
+    if length < config.min_length {
+        return Err(RenameDenied::TooShort { min: config.min_length });
+    }
+    if length > config.max_length {
+        return Err(RenameDenied::TooLong { max: config.max_length });
+    }
+    let mut chars = name.chars();
+    let starts_with_letter = chars.next().is_some_and(|c| c.is_ascii_alphabetic());
+    if !starts_with_letter || !chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+        return Err(RenameDenied::InvalidCharacters);
+    }
+    if is_blocked(name, offensive_terms) {
+        return Err(RenameDenied::Blocked);
+    }
+    Ok(())
+}
+
+/// Whether a player who last changed their name at `last_change` may change
+/// it again at `now`
+pub fn check_cooldown(
+    last_change: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    config: &UsernameConfig,
+) -> Result<(), RenameDenied> {
+    match last_change.map(|at| at + Duration::days(config.cooldown_days)) {
+        Some(until) if until > now => Err(RenameDenied::Cooldown { until }),
+        _ => Ok(()),
+    }
+}
+
+/// Until when a name given up at `now` stays reserved
+pub fn reserved_until(now: DateTime<Utc>, config: &UsernameConfig) -> DateTime<Utc> {
+    now + Duration::days(config.reservation_days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModerationConfig;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_validate() {
+        let config = UsernameConfig::default();
+        let terms = ModerationConfig::default().offensive_terms;
+        assert_eq!(validate("neo", &config, &terms), Ok(()));
+        assert_eq!(validate("zero_cool.99", &config, &terms), Ok(()));
+        assert_eq!(validate("ab", &config, &terms), Err(RenameDenied::TooShort { min: 3 }));
+        assert_eq!(validate("averyveryverylongname", &config, &terms), Err(RenameDenied::TooLong { max: 15 }));
+        assert_eq!(validate("1337", &config, &terms), Err(RenameDenied::InvalidCharacters));
+        assert_eq!(validate("bad name", &config, &terms), Err(RenameDenied::InvalidCharacters));
+    }
+
+    #[test]
+    fn test_lookalikes_of_blocked_names() {
+        let config = UsernameConfig::default();
+        let terms = ModerationConfig::default().offensive_terms;
+        assert_eq!(validate("Admin", &config, &terms), Err(RenameDenied::Blocked));
+        assert_eq!(validate("a-d-m-1-n", &config, &terms), Err(RenameDenied::Blocked));
+        assert_eq!(validate("M0derator", &config, &terms), Err(RenameDenied::Blocked));
+        assert_eq!(validate("staff_bob", &config, &terms), Err(RenameDenied::Blocked));
+        assert_eq!(validate("sh1t_bob", &config, &terms), Err(RenameDenied::Blocked));
+        // Only whole names and staff prefixes, not every name containing one
+        assert_eq!(validate("groot", &config, &terms), Ok(()));
+        assert_eq!(validate("badmint0n", &config, &terms), Ok(()));
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let config = UsernameConfig { cooldown_days: 30, ..UsernameConfig::default() };
+        let changed = Utc.with_ymd_and_hms(2024, 11, 1, 0, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(2024, 12, 1, 0, 0, 0).unwrap();
+        assert_eq!(check_cooldown(None, changed, &config), Ok(()));
+        assert_eq!(check_cooldown(Some(changed), until - Duration::seconds(1), &config), Err(RenameDenied::Cooldown { until }));
+        assert_eq!(check_cooldown(Some(changed), until, &config), Ok(()));
+    }
+}
@@ -0,0 +1,328 @@
+//! Hardware wear, failures and repairs
+//!
+//! A component wears while processes keep it under heavy load, and the more
+//! worn it is the likelier it fails when one of them completes. A failed
+//! component runs at a fraction of its capacity until a paid repair process
+//! restores it. Better quality tiers wear slower but cost more to fit, and a
+//! repair may fit a better tier instead of restoring the current one.
+
+use crate::config::HardwareWearConfig;
+use crate::HardwareSpecs;
+use serde::{Deserialize, Serialize};
+
+/// A part of the player's own hardware that wears
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+    Cpu,
+    Ram,
+    Hdd,
+    Net,
+}
+
+impl Component {
+    pub const ALL: [Component; 4] = [Component::Cpu, Component::Ram, Component::Hdd, Component::Net];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Component::Cpu => "cpu",
+            Component::Ram => "ram",
+            Component::Hdd => "hdd",
+            Component::Net => "net",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.as_str() == s)
+    }
+
+    /// Price of a new standard part, in cents
+    pub fn replacement_cost(&self, config: &HardwareWearConfig) -> i64 {
+        match self {
+            Component::Cpu => config.cpu_cost,
+            Component::Ram => config.ram_cost,
+            Component::Hdd => config.hdd_cost,
+            Component::Net => config.net_cost,
+        }
+    }
+}
+
+/// Build quality of a component
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    Standard,
+    Premium,
+    Industrial,
+}
+
+impl Quality {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Quality::Standard => "standard",
+            Quality::Premium => "premium",
+            Quality::Industrial => "industrial",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        [Quality::Standard, Quality::Premium, Quality::Industrial].into_iter().find(|q| q.as_str() == s)
+    }
+
+    /// Share of the standard wear rate
+    pub fn wear_factor(&self, config: &HardwareWearConfig) -> f64 {
+        match self {
+            Quality::Standard => 1.0,
+            Quality::Premium => config.premium_wear_factor,
+            Quality::Industrial => config.industrial_wear_factor,
+        }
+    }
+
+    /// Multiple of the standard price
+    pub fn price_factor(&self, config: &HardwareWearConfig) -> f64 {
+        match self {
+            Quality::Standard => 1.0,
+            Quality::Premium => config.premium_price_factor,
+            Quality::Industrial => config.industrial_price_factor,
+        }
+    }
+}
+
+/// Wear gained by a component from one process: `own_share` of its capacity
+/// used by the process for `hours`, while everything running used
+/// `total_share`. Load up to the heavy threshold does no harm; above it wear
+/// grows with how far above it is, and is split between the processes by
+/// what each uses.
+pub fn wear_gain(own_share: f64, total_share: f64, hours: f64, quality: Quality, config: &HardwareWearConfig) -> f64 {
+    if total_share <= config.heavy_load || own_share <= 0.0 || hours <= 0.0 {
+        return 0.0;
+    }
+    let intensity = (total_share.min(1.0) - config.heavy_load) / (1.0 - config.heavy_load);
+    let attributed = (own_share / total_share).min(1.0);
+    intensity * attributed * hours * config.wear_per_hour * quality.wear_factor(config)
+}
+
+/// Chance a component at `wear` fails when a process using it completes
+pub fn failure_chance(wear: f64, config: &HardwareWearConfig) -> f64 {
+    if wear <= config.failure_onset {
+        return 0.0;
+    }
+    let past_onset = ((wear - config.failure_onset) / (1.0 - config.failure_onset)).min(1.0);
+    config.max_failure_chance * past_onset * past_onset
+}
+
+/// Whether a component at `wear` fails, for a uniform `roll` in `[0, 1)`
+pub fn fails(wear: f64, roll: f64, config: &HardwareWearConfig) -> bool {
+    roll < failure_chance(wear, config)
+}
+
+/// How bad a component's condition is, as players are warned about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Good,
+    Worn,
+    Critical,
+    Failed,
+}
+
+impl Condition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Condition::Good => "good",
+            Condition::Worn => "worn",
+            Condition::Critical => "critical",
+            Condition::Failed => "failed",
+        }
+    }
+
+    pub fn level(&self) -> i16 {
+        *self as i16
+    }
+}
+
+pub fn condition(wear: f64, failed: bool, config: &HardwareWearConfig) -> Condition {
+    if failed {
+        Condition::Failed
+    } else if wear >= config.critical_at {
+        Condition::Critical
+    } else if wear >= config.worn_at {
+        Condition::Worn
+    } else {
+        Condition::Good
+    }
+}
+
+/// The condition to warn about, if it is worse than the last one warned of
+pub fn warning(current: Condition, warned_level: i16) -> Option<Condition> {
+    (current.level() > warned_level).then_some(current)
+}
+
+/// `specs` with failed components down to the degraded share of capacity
+pub fn degrade(specs: &HardwareSpecs, failed: &[Component], config: &HardwareWearConfig) -> HardwareSpecs {
+    let cut = |value: i32| (value as i64 * config.degraded_percent / 100).max(1) as i32;
+    let mut degraded = specs.clone();
+    for component in failed {
+        match component {
+            Component::Cpu => degraded.cpu = cut(specs.cpu),
+            Component::Ram => degraded.ram = cut(specs.ram),
+            Component::Hdd => degraded.hdd = cut(specs.hdd),
+            Component::Net => degraded.net = cut(specs.net),
+        }
+    }
+    degraded
+}
+
+/// A component as repairs see it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ComponentState {
+    pub component: Component,
+    pub quality: Quality,
+    pub wear: f64,
+    pub failed: bool,
+}
+
+/// What a repair costs and how long it runs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RepairQuote {
+    pub component: Component,
+    /// Quality fitted by the repair
+    pub quality: Quality,
+    /// In cents
+    pub cost: i64,
+    pub duration_secs: i64,
+}
+
+/// Restoring the part costs a share of its price growing with wear, plus a
+/// surcharge when it failed; fitting another tier costs the new part
+pub fn quote(state: &ComponentState, fit: Option<Quality>, config: &HardwareWearConfig) -> RepairQuote {
+    let base = state.component.replacement_cost(config) as f64;
+    let (quality, cost) = match fit.filter(|fit| *fit != state.quality) {
+        Some(fit) => (fit, base * fit.price_factor(config)),
+        None => {
+            let share = config.repair_base_share + (1.0 - config.repair_base_share) * state.wear.clamp(0.0, 1.0);
+            let failed = if state.failed { config.failed_surcharge } else { 1.0 };
+            (state.quality, base * state.quality.price_factor(config) * share * config.repair_share * failed)
+        }
+    };
+    let duration = config.repair_base_secs as f64 * (1.0 + state.wear.clamp(0.0, 1.0)) * if state.failed { 1.5 } else { 1.0 };
+    RepairQuote { component: state.component, quality, cost: cost.round() as i64, duration_secs: duration.round() as i64 }
+}
+
+/// Why a repair was refused
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RepairDenied {
+    UnknownComponent,
+    UnknownQuality,
+    /// Nothing to restore and no other tier asked for
+    NothingToRepair,
+    /// Parts are only swapped for better ones
+    Downgrade,
+    InProgress,
+    InsufficientFunds { needed: i64 },
+}
+
+impl RepairDenied {
+    pub fn message(&self) -> String {
+        match self {
+            RepairDenied::UnknownComponent => "Components are cpu, ram, hdd and net".to_string(),
+            RepairDenied::UnknownQuality => "Qualities are standard, premium and industrial".to_string(),
+            RepairDenied::NothingToRepair => "That component is in good shape".to_string(),
+            RepairDenied::Downgrade => "A part can only be swapped for a better one".to_string(),
+            RepairDenied::InProgress => "That component is already being repaired".to_string(),
+            RepairDenied::InsufficientFunds { needed } => {
+                format!("The repair costs ${}.{:02}", needed / 100, needed % 100)
+            }
+        }
+    }
+}
+
+/// Quote a repair of `state`, fitting `fit` if given, for a player with
+/// `balance` cents and `repairing` whether one is already running
+pub fn check_repair(
+    state: &ComponentState,
+    fit: Option<Quality>,
+    repairing: bool,
+    balance: i64,
+    config: &HardwareWearConfig,
+) -> Result<RepairQuote, RepairDenied> {
+    if repairing {
+        return Err(RepairDenied::InProgress);
+    }
+    match fit {
+        Some(fit) if fit < state.quality => return Err(RepairDenied::Downgrade),
+        Some(fit) if fit > state.quality => {}
+        _ if !state.failed && state.wear < config.min_repair_wear => return Err(RepairDenied::NothingToRepair),
+        _ => {}
+    }
+    let quote = quote(state, fit, config);
+    if balance < quote.cost {
+        return Err(RepairDenied::InsufficientFunds { needed: quote.cost });
+    }
+    Ok(quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wear_and_failures() {
+        let config = HardwareWearConfig::default();
+        assert_eq!(wear_gain(0.5, config.heavy_load, 10.0, Quality::Standard, &config), 0.0);
+
+        let full = wear_gain(1.0, 1.0, 1.0, Quality::Standard, &config);
+        assert!((full - config.wear_per_hour).abs() < 1e-9);
+        // Half the load on a fully loaded machine takes half the wear
+        assert!((wear_gain(0.5, 1.0, 1.0, Quality::Standard, &config) - full / 2.0).abs() < 1e-9);
+        assert!(wear_gain(1.0, 1.0, 1.0, Quality::Premium, &config) < full);
+        assert!(wear_gain(1.0, 1.0, 1.0, Quality::Industrial, &config) < wear_gain(1.0, 1.0, 1.0, Quality::Premium, &config));
+
+        assert_eq!(failure_chance(config.failure_onset, &config), 0.0);
+        assert!((failure_chance(1.0, &config) - config.max_failure_chance).abs() < 1e-9);
+        assert!(failure_chance(0.9, &config) > failure_chance(0.7, &config));
+        assert!(fails(1.0, 0.0, &config));
+        assert!(!fails(1.0, config.max_failure_chance, &config));
+    }
+
+    #[test]
+    fn test_conditions_and_degradation() {
+        let config = HardwareWearConfig::default();
+        assert_eq!(condition(0.1, false, &config), Condition::Good);
+        assert_eq!(condition(config.worn_at, false, &config), Condition::Worn);
+        assert_eq!(condition(config.critical_at, false, &config), Condition::Critical);
+        assert_eq!(condition(0.1, true, &config), Condition::Failed);
+        assert_eq!(warning(Condition::Worn, Condition::Good.level()), Some(Condition::Worn));
+        assert_eq!(warning(Condition::Worn, Condition::Worn.level()), None);
+
+        let specs = HardwareSpecs { cpu: 2000, ram: 1024, hdd: 10240, net: 10, security_level: 50, performance_rating: 100 };
+        let degraded = degrade(&specs, &[Component::Cpu], &config);
+        assert_eq!(degraded.cpu as i64, 2000 * config.degraded_percent / 100);
+        assert_eq!(degraded.ram, specs.ram);
+    }
+
+    #[test]
+    fn test_repairs() {
+        let config = HardwareWearConfig::default();
+        let fresh = ComponentState { component: Component::Cpu, quality: Quality::Standard, wear: 0.05, failed: false };
+        assert_eq!(check_repair(&fresh, None, false, i64::MAX, &config), Err(RepairDenied::NothingToRepair));
+        let upgrade = check_repair(&fresh, Some(Quality::Premium), false, i64::MAX, &config).unwrap();
+        assert_eq!(upgrade.quality, Quality::Premium);
+        assert_eq!(upgrade.cost, (config.cpu_cost as f64 * config.premium_price_factor).round() as i64);
+
+        let premium = ComponentState { quality: Quality::Premium, ..fresh };
+        assert_eq!(check_repair(&premium, Some(Quality::Standard), false, i64::MAX, &config), Err(RepairDenied::Downgrade));
+
+        let worn = ComponentState { wear: 0.8, ..fresh };
+        let broken = ComponentState { failed: true, ..worn };
+        let restore = quote(&worn, None, &config);
+        assert!(quote(&broken, None, &config).cost > restore.cost);
+        assert!(restore.cost < config.cpu_cost);
+        assert_eq!(check_repair(&worn, None, true, i64::MAX, &config), Err(RepairDenied::InProgress));
+        assert_eq!(
+            check_repair(&worn, None, false, restore.cost - 1, &config),
+            Err(RepairDenied::InsufficientFunds { needed: restore.cost })
+        );
+    }
+}
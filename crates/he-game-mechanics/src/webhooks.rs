@@ -0,0 +1,251 @@
+//! Outbound webhooks
+//!
+//! Clans and players register URLs to be told about events, e.g. to post
+//! them in a Discord channel. Each webhook picks the events it wants from
+//! those its owner can receive. Failed deliveries are retried with
+//! exponential backoff; a webhook failing for too long in a row is disabled
+//! until its owner turns it back on.
+
+use crate::config::WebhookConfig;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Who a webhook belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookOwner {
+    Clan,
+    Player,
+}
+
+impl WebhookOwner {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookOwner::Clan => "clan",
+            WebhookOwner::Player => "player",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "clan" => Some(WebhookOwner::Clan),
+            "player" => Some(WebhookOwner::Player),
+            _ => None,
+        }
+    }
+
+    /// Events webhooks of this owner may subscribe to
+    pub fn events(&self) -> &'static [WebhookEvent] {
+        match self {
+            WebhookOwner::Clan => &WebhookEvent::ALL,
+            WebhookOwner::Player => &[WebhookEvent::BigHack],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// The clan declared war or had war declared on it
+    WarDeclared,
+    MemberJoined,
+    /// The player, or a member of the clan, pulled off a big hack
+    BigHack,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 3] = [WebhookEvent::WarDeclared, WebhookEvent::MemberJoined, WebhookEvent::BigHack];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::WarDeclared => "war_declared",
+            WebhookEvent::MemberJoined => "member_joined",
+            WebhookEvent::BigHack => "big_hack",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.as_str() == s)
+    }
+}
+
+/// Whether a completed login hack is worth announcing: one earning at least
+/// `big_hack_experience`, or claiming bounties worth `big_hack_bounty`
+pub fn is_big_hack(experience: i64, bounty_reward: i64, config: &WebhookConfig) -> bool {
+    experience >= config.big_hack_experience || (bounty_reward > 0 && bounty_reward >= config.big_hack_bounty)
+}
+
+/// Seconds before retrying a delivery that failed `attempts` times
+pub fn backoff_secs(attempts: i32, config: &WebhookConfig) -> i64 {
+    let doublings = attempts.saturating_sub(1).clamp(0, 30) as u32;
+    config.backoff_base_secs.saturating_mul(1i64 << doublings).min(config.backoff_max_secs)
+}
+
+/// Whether a webhook failing `consecutive_failures` times in a row is disabled
+pub fn should_disable(consecutive_failures: i32, config: &WebhookConfig) -> bool {
+    consecutive_failures >= config.disable_after_failures
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum WebhookDenied {
+    NoClan,
+    /// Only the leader and officers manage a clan's webhooks
+    NotOfficer,
+    UnknownOwner,
+    InvalidUrl,
+    UnknownEvent { event: String },
+    /// Not an event this owner receives
+    EventNotAllowed { event: String },
+    NoEvents,
+    TooManyWebhooks { max: i64 },
+    NotFound,
+}
+
+impl WebhookDenied {
+    pub fn message(&self) -> String {
+        match self {
+            WebhookDenied::NoClan => "You are not in a clan".to_string(),
+            WebhookDenied::NotOfficer => "Only the clan leader and officers manage its webhooks".to_string(),
+            WebhookDenied::UnknownOwner => "Webhooks belong to a clan or a player".to_string(),
+            WebhookDenied::InvalidUrl => "Webhooks need a public https URL".to_string(),
+            WebhookDenied::UnknownEvent { event } => format!("Unknown webhook event {}", event),
+            WebhookDenied::EventNotAllowed { event } => format!("This webhook cannot receive {}", event),
+            WebhookDenied::NoEvents => "Pick at least one event".to_string(),
+            WebhookDenied::TooManyWebhooks { max } => format!("At most {} webhooks are allowed", max),
+            WebhookDenied::NotFound => "Webhook not found".to_string(),
+        }
+    }
+}
+
+/// The events a new webhook of `owner` subscribes to, deduplicated
+pub fn check_events(owner: WebhookOwner, events: &[String]) -> Result<Vec<WebhookEvent>, WebhookDenied> {
+    let mut checked: Vec<WebhookEvent> = Vec::new();
+    for name in events {
+        let Some(event) = WebhookEvent::from_str(name) else {
+            return Err(WebhookDenied::UnknownEvent { event: name.clone() });
+        };
+        if !owner.events().contains(&event) {
+            return Err(WebhookDenied::EventNotAllowed { event: name.clone() });
+        }
+        if !checked.contains(&event) {
+            checked.push(event);
+        }
+    }
+    if checked.is_empty() {
+        return Err(WebhookDenied::NoEvents);
+    }
+    Ok(checked)
+}
+
+/// Whether `url` may receive webhooks: https, and not obviously pointing
+/// back into our own network. Names are resolved again when delivering.
+pub fn check_url(url: &str, config: &WebhookConfig) -> Result<(), WebhookDenied> {
+    if url.len() > config.max_url_len {
+        return Err(WebhookDenied::InvalidUrl);
+    }
+    let Some(rest) = url.strip_prefix("https://") else {
+        return Err(WebhookDenied::InvalidUrl);
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if authority.is_empty() || authority.contains('@') || authority.chars().any(char::is_whitespace) {
+        return Err(WebhookDenied::InvalidUrl);
+    }
+
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    if host.is_empty() || host == "localhost" || host.ends_with(".localhost") || host.ends_with(".internal") {
+        return Err(WebhookDenied::InvalidUrl);
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        if !is_public(ip) {
+            return Err(WebhookDenied::InvalidUrl);
+        }
+    } else if !host.contains('.') {
+        return Err(WebhookDenied::InvalidUrl);
+    }
+    Ok(())
+}
+
+/// Whether `ip` is reachable on the public internet
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => !is_private_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    // Carrier-grade NAT, 100.64.0.0/10
+    let shared = a == 100 && (64..128).contains(&b);
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || shared
+        || a == 0)
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local fc00::/7 and link local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_url() {
+        let config = WebhookConfig::default();
+        assert!(check_url("https://discord.com/api/webhooks/1/abc", &config).is_ok());
+        assert!(check_url("https://hooks.example.org:8443/x?y=1", &config).is_ok());
+        for bad in [
+            "http://discord.com/api/webhooks/1/abc",
+            "https://localhost/hook",
+            "https://127.0.0.1/hook",
+            "https://10.1.2.3/hook",
+            "https://169.254.169.254/latest",
+            "https://[::1]/hook",
+            "https://[::ffff:192.168.0.1]/hook",
+            "https://user@discord.com/hook",
+            "https://intranet/hook",
+            "https:///hook",
+        ] {
+            assert_eq!(check_url(bad, &config), Err(WebhookDenied::InvalidUrl), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_events_and_backoff() {
+        let picked = check_events(WebhookOwner::Clan, &["war_declared".into(), "war_declared".into()]).unwrap();
+        assert_eq!(picked, vec![WebhookEvent::WarDeclared]);
+        assert_eq!(
+            check_events(WebhookOwner::Player, &["member_joined".into()]),
+            Err(WebhookDenied::EventNotAllowed { event: "member_joined".into() })
+        );
+        assert_eq!(check_events(WebhookOwner::Player, &[]), Err(WebhookDenied::NoEvents));
+
+        let config = WebhookConfig::default();
+        assert_eq!(backoff_secs(1, &config), config.backoff_base_secs);
+        assert_eq!(backoff_secs(2, &config), config.backoff_base_secs * 2);
+        assert_eq!(backoff_secs(40, &config), config.backoff_max_secs);
+        assert!(!should_disable(config.disable_after_failures - 1, &config));
+        assert!(should_disable(config.disable_after_failures, &config));
+    }
+}
@@ -0,0 +1,62 @@
+//! Benchmarks for world persistence: JSON against binary snapshots
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use he_game_world::snapshot::{read_snapshot, write_snapshot};
+use he_game_world::{FileType, GameWorld, ServerFile};
+use uuid::Uuid;
+
+/// The default world, grown by copies of its servers to `servers` servers of
+/// a dozen files each
+fn world(servers: usize) -> GameWorld {
+    let mut world = GameWorld::new();
+    let templates: Vec<_> = world.servers.values().cloned().collect();
+    let mut n = 0;
+    while world.servers.len() < servers {
+        let mut server = templates[n % templates.len()].clone();
+        server.id = Uuid::new_v4();
+        server.ip_address = format!("10.{}.{}.{}", n / 65536 % 256, n / 256 % 256, n % 256);
+        while server.files.len() < 12 {
+            server.files.push(ServerFile {
+                id: Uuid::new_v4(),
+                name: format!("data_{}.dat", server.files.len()),
+                file_type: FileType::Data,
+                size: 1024,
+                content: Some("x".repeat(256)),
+                is_encrypted: false,
+                is_hidden: false,
+            });
+        }
+        world.servers.insert(server.ip_address.clone(), server);
+        n += 1;
+    }
+    world
+}
+
+fn benchmark_world_persistence(c: &mut Criterion) {
+    let mut group = c.benchmark_group("world_persistence");
+
+    for servers in [100, 1_000] {
+        let world = world(servers);
+        let json = serde_json::to_vec(&world).unwrap();
+        let snapshot = write_snapshot(&world, Vec::new()).unwrap();
+        println!("{} servers: JSON {} bytes, snapshot {} bytes", servers, json.len(), snapshot.len());
+
+        group.bench_with_input(BenchmarkId::new("json_encode", servers), &world, |b, world| {
+            b.iter(|| serde_json::to_vec(black_box(world)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("snapshot_encode", servers), &world, |b, world| {
+            b.iter(|| write_snapshot(black_box(world), Vec::new()).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("json_decode", servers), &json, |b, json| {
+            b.iter(|| serde_json::from_slice::<GameWorld>(black_box(json)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("snapshot_decode", servers), &snapshot, |b, snapshot| {
+            b.iter(|| read_snapshot(black_box(snapshot.as_slice())).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_world_persistence);
+criterion_main!(benches);
@@ -11,6 +11,7 @@ pub mod npc_servers;
 pub mod software_catalog;
 pub mod missions;
 pub mod world_generator;
+pub mod snapshot;
 
 pub use npc_servers::*;
 pub use software_catalog::*;
@@ -78,6 +79,12 @@ impl GameWorld {
             self.servers.insert(server.ip_address.clone(), server);
         }
 
+        // Honeypots: dressed as soft tier 3 targets, in the same address range
+        for i in 0..8 {
+            let server = NPCServer::generate_honeypot(i);
+            self.servers.insert(server.ip_address.clone(), server);
+        }
+
         // Special servers
         self.add_special_servers();
     }
@@ -117,6 +124,7 @@ impl GameWorld {
             },
             is_online: true,
             last_reset: Utc::now(),
+            is_honeypot: false,
         };
         self.servers.insert(whois.ip_address.clone(), whois);
 
@@ -153,6 +161,7 @@ impl GameWorld {
             },
             is_online: true,
             last_reset: Utc::now(),
+            is_honeypot: false,
         };
         self.servers.insert(mystery.ip_address.clone(), mystery);
     }
@@ -42,6 +42,9 @@ pub struct NPCServer {
     pub hardware: ServerHardware,
     pub is_online: bool,
     pub last_reset: DateTime<Utc>,
+    /// Bait that pays nothing and traces whoever attacks it
+    #[serde(default)]
+    pub is_honeypot: bool,
 }
 
 /// File on server
@@ -164,6 +167,7 @@ impl NPCServer {
             },
             is_online: true,
             last_reset: Utc::now(),
+            is_honeypot: false,
         }
     }
 
@@ -243,6 +247,7 @@ impl NPCServer {
             },
             is_online: true,
             last_reset: Utc::now(),
+            is_honeypot: false,
         }
     }
 
@@ -353,6 +358,7 @@ impl NPCServer {
             },
             is_online: true,
             last_reset: Utc::now(),
+            is_honeypot: false,
         }
     }
 
@@ -437,9 +443,97 @@ impl NPCServer {
             },
             is_online: true,
             last_reset: Utc::now(),
+            is_honeypot: false,
         }
     }
 
+    /// Generate a honeypot: a bank or exchange that looks rich and barely
+    /// defended, with its valuables in plain sight. It pays nothing out.
+    pub fn generate_honeypot(index: i32) -> Self {
+        let mut rng = rand::thread_rng();
+
+        let server_types = [ServerType::Bank, ServerType::CryptoExchange];
+        let server_type = server_types[rng.gen_range(0..server_types.len())];
+
+        let (hostname, owner) = match server_type {
+            ServerType::Bank => {
+                let banks = ["trustline", "northbank", "unionsavings", "capitalone"];
+                let bank = banks[rng.gen_range(0..banks.len())];
+                (format!("{}.bank", bank), format!("{} International", bank.to_uppercase()))
+            },
+            ServerType::CryptoExchange => {
+                let exchanges = ["coinvault", "hodlex", "satoshix", "chainex"];
+                let exchange = exchanges[rng.gen_range(0..exchanges.len())];
+                (format!("{}.exchange", exchange), format!("{} Exchange", exchange.to_uppercase()))
+            },
+            _ => unreachable!(),
+        };
+
+        let mut files = vec![
+            ServerFile {
+                id: Uuid::new_v4(),
+                name: "accounts.db".to_string(),
+                file_type: FileType::Database,
+                size: rng.gen_range(100000..1000000),
+                content: Some("Bank account database - CONFIDENTIAL".to_string()),
+                is_encrypted: false,
+                is_hidden: false,
+            },
+            ServerFile {
+                id: Uuid::new_v4(),
+                name: "admin_passwords.txt".to_string(),
+                file_type: FileType::Password,
+                size: rng.gen_range(100..1000),
+                content: Some(format!("root:{}", generate_weak_password())),
+                is_encrypted: false,
+                is_hidden: false,
+            },
+        ];
+
+        for i in 0..rng.gen_range(5..10) {
+            files.push(generate_random_file(i));
+        }
+
+        Self {
+            id: Uuid::new_v4(),
+            ip_address: generate_ip_address(192, 100 + index),
+            hostname,
+            owner_name: owner,
+            server_type,
+            tier: 3,
+            security_level: rng.gen_range(15..30),
+            firewall_level: rng.gen_range(1..3),
+            has_encryption: false,
+            money_available: rng.gen_range(100000..500000),
+            files,
+            logs: generate_fake_logs(rng.gen_range(20..50)),
+            running_software: vec![
+                RunningSoftware {
+                    id: Uuid::new_v4(),
+                    name: "Firewall".to_string(),
+                    version: "1.0".to_string(),
+                    software_type: "Defense".to_string(),
+                    ram_usage: 128,
+                    effectiveness: 20,
+                },
+            ],
+            hardware: ServerHardware {
+                cpu: rng.gen_range(5000..10000),
+                ram: rng.gen_range(16384..32768),
+                hdd: rng.gen_range(1000000..5000000),
+                network: rng.gen_range(1000..5000),
+            },
+            is_online: true,
+            last_reset: Utc::now(),
+            is_honeypot: true,
+        }
+    }
+
+    /// Whether what is taken from the server is worth anything
+    pub fn grants_rewards(&self) -> bool {
+        !self.is_honeypot
+    }
+
     /// Simulate being hacked
     pub fn on_hacked(&mut self, hacker_ip: &str) {
         // Add log entry
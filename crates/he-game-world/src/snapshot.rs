@@ -0,0 +1,635 @@
+//! Binary world snapshots
+//!
+//! A snapshot is the magic `HEWS` followed by a stream of length-delimited
+//! protobuf [`proto::Record`]s: a header, one record per NPC server, the
+//! corporations, the software catalog, the mission templates, one record per
+//! network route, the backbone, and an end marker carrying the record count.
+//! Reading and writing go one record at a time, so memory stays bounded by
+//! the largest server rather than the whole file.
+//!
+//! Servers and routes, the bulk of a world, are native protobuf. The
+//! corporations, catalog and mission templates are few and small, and are
+//! stored as JSON inside their records so they evolve with their serde
+//! definitions.
+//!
+//! Schema evolution follows the protobuf rules: fields get new tags and old
+//! tags are never reused, so an old snapshot reads with defaults for fields
+//! added since, and record kinds a reader does not know are skipped. Enums
+//! are stored by their serde variant name. [`FORMAT_VERSION`] only changes if
+//! the framing itself does.
+
+use crate::{Corporation, GameWorld, MissionTemplate, NPCServer, NetworkTopology, SoftwareCatalog};
+use crate::{LogEntry, RunningSoftware, ServerFile, ServerHardware};
+use chrono::{DateTime, Utc};
+use prost::Message;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use thiserror::Error;
+use uuid::Uuid;
+
+pub const MAGIC: &[u8; 4] = b"HEWS";
+pub const FORMAT_VERSION: u32 = 1;
+/// Larger records are refused rather than allocated
+pub const MAX_RECORD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Protobuf messages of the snapshot format
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Record {
+        #[prost(oneof = "record::Kind", tags = "1, 2, 3, 4, 5, 6, 7, 15")]
+        pub kind: Option<record::Kind>,
+    }
+
+    pub mod record {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Kind {
+            #[prost(message, tag = "1")]
+            Header(super::Header),
+            #[prost(message, tag = "2")]
+            Server(super::Server),
+            #[prost(bytes = "vec", tag = "3")]
+            CorporationJson(Vec<u8>),
+            #[prost(bytes = "vec", tag = "4")]
+            CatalogJson(Vec<u8>),
+            #[prost(bytes = "vec", tag = "5")]
+            MissionTemplateJson(Vec<u8>),
+            #[prost(message, tag = "6")]
+            Route(super::Route),
+            #[prost(message, tag = "7")]
+            Backbone(super::Backbone),
+            #[prost(message, tag = "15")]
+            End(super::End),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Header {
+        #[prost(uint32, tag = "1")]
+        pub format_version: u32,
+        #[prost(int64, tag = "2")]
+        pub created_at_micros: i64,
+        #[prost(uint64, tag = "3")]
+        pub servers: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Server {
+        #[prost(bytes = "vec", tag = "1")]
+        pub id: Vec<u8>,
+        #[prost(string, tag = "2")]
+        pub ip_address: String,
+        #[prost(string, tag = "3")]
+        pub hostname: String,
+        #[prost(string, tag = "4")]
+        pub owner_name: String,
+        #[prost(string, tag = "5")]
+        pub server_type: String,
+        #[prost(int32, tag = "6")]
+        pub tier: i32,
+        #[prost(int32, tag = "7")]
+        pub security_level: i32,
+        #[prost(int32, tag = "8")]
+        pub firewall_level: i32,
+        #[prost(bool, tag = "9")]
+        pub has_encryption: bool,
+        #[prost(int64, tag = "10")]
+        pub money_available: i64,
+        #[prost(message, repeated, tag = "11")]
+        pub files: Vec<File>,
+        #[prost(message, repeated, tag = "12")]
+        pub logs: Vec<Log>,
+        #[prost(message, repeated, tag = "13")]
+        pub running_software: Vec<RunningSoftware>,
+        #[prost(message, optional, tag = "14")]
+        pub hardware: Option<Hardware>,
+        #[prost(bool, tag = "15")]
+        pub is_online: bool,
+        #[prost(int64, tag = "16")]
+        pub last_reset_micros: i64,
+        #[prost(bool, tag = "17")]
+        pub is_honeypot: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct File {
+        #[prost(bytes = "vec", tag = "1")]
+        pub id: Vec<u8>,
+        #[prost(string, tag = "2")]
+        pub name: String,
+        #[prost(string, tag = "3")]
+        pub file_type: String,
+        #[prost(int64, tag = "4")]
+        pub size: i64,
+        #[prost(string, optional, tag = "5")]
+        pub content: Option<String>,
+        #[prost(bool, tag = "6")]
+        pub is_encrypted: bool,
+        #[prost(bool, tag = "7")]
+        pub is_hidden: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Log {
+        #[prost(bytes = "vec", tag = "1")]
+        pub id: Vec<u8>,
+        #[prost(int64, tag = "2")]
+        pub timestamp_micros: i64,
+        #[prost(string, tag = "3")]
+        pub action: String,
+        #[prost(string, tag = "4")]
+        pub ip_address: String,
+        #[prost(bool, tag = "5")]
+        pub is_hidden: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct RunningSoftware {
+        #[prost(bytes = "vec", tag = "1")]
+        pub id: Vec<u8>,
+        #[prost(string, tag = "2")]
+        pub name: String,
+        #[prost(string, tag = "3")]
+        pub version: String,
+        #[prost(string, tag = "4")]
+        pub software_type: String,
+        #[prost(int32, tag = "5")]
+        pub ram_usage: i32,
+        #[prost(int32, tag = "6")]
+        pub effectiveness: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Hardware {
+        #[prost(int32, tag = "1")]
+        pub cpu: i32,
+        #[prost(int32, tag = "2")]
+        pub ram: i32,
+        #[prost(int32, tag = "3")]
+        pub hdd: i32,
+        #[prost(int32, tag = "4")]
+        pub network: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Route {
+        #[prost(string, tag = "1")]
+        pub ip: String,
+        #[prost(string, repeated, tag = "2")]
+        pub connected: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Backbone {
+        #[prost(string, repeated, tag = "1")]
+        pub ips: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct End {
+        /// Records before this one, the header included
+        #[prost(uint64, tag = "1")]
+        pub records: u64,
+    }
+}
+
+use proto::record::Kind;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("snapshot I/O failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a world snapshot")]
+    BadMagic,
+    #[error("snapshot format {found} is newer than {FORMAT_VERSION}")]
+    UnsupportedVersion { found: u32 },
+    #[error("snapshot record of {len} bytes exceeds the limit")]
+    RecordTooLarge { len: usize },
+    #[error("snapshot ends before its end marker")]
+    Truncated,
+    #[error("failed to encode snapshot record: {0}")]
+    Encode(#[from] prost::EncodeError),
+    #[error("failed to decode snapshot record: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("failed to (de)serialize snapshot JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid snapshot: {0}")]
+    Invalid(String),
+}
+
+type Result<T> = std::result::Result<T, SnapshotError>;
+
+fn enum_name<T: Serialize>(value: &T) -> Result<String> {
+    match serde_json::to_value(value)? {
+        serde_json::Value::String(name) => Ok(name),
+        other => Err(SnapshotError::Invalid(format!("enum encodes as {}", other))),
+    }
+}
+
+fn enum_from_name<T: DeserializeOwned>(name: &str) -> Result<T> {
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .map_err(|_| SnapshotError::Invalid(format!("unknown variant {}", name)))
+}
+
+fn uuid(bytes: &[u8]) -> Result<Uuid> {
+    Uuid::from_slice(bytes).map_err(|_| SnapshotError::Invalid("malformed id".to_string()))
+}
+
+fn timestamp(micros: i64) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp_micros(micros).ok_or_else(|| SnapshotError::Invalid(format!("bad timestamp {}", micros)))
+}
+
+impl proto::Server {
+    fn from_server(server: &NPCServer) -> Result<Self> {
+        let files = server
+            .files
+            .iter()
+            .map(|file| {
+                Ok(proto::File {
+                    id: file.id.as_bytes().to_vec(),
+                    name: file.name.clone(),
+                    file_type: enum_name(&file.file_type)?,
+                    size: file.size,
+                    content: file.content.clone(),
+                    is_encrypted: file.is_encrypted,
+                    is_hidden: file.is_hidden,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let logs = server
+            .logs
+            .iter()
+            .map(|log| proto::Log {
+                id: log.id.as_bytes().to_vec(),
+                timestamp_micros: log.timestamp.timestamp_micros(),
+                action: log.action.clone(),
+                ip_address: log.ip_address.clone(),
+                is_hidden: log.is_hidden,
+            })
+            .collect();
+        let running_software = server
+            .running_software
+            .iter()
+            .map(|software| proto::RunningSoftware {
+                id: software.id.as_bytes().to_vec(),
+                name: software.name.clone(),
+                version: software.version.clone(),
+                software_type: software.software_type.clone(),
+                ram_usage: software.ram_usage,
+                effectiveness: software.effectiveness,
+            })
+            .collect();
+
+        Ok(Self {
+            id: server.id.as_bytes().to_vec(),
+            ip_address: server.ip_address.clone(),
+            hostname: server.hostname.clone(),
+            owner_name: server.owner_name.clone(),
+            server_type: enum_name(&server.server_type)?,
+            tier: server.tier,
+            security_level: server.security_level,
+            firewall_level: server.firewall_level,
+            has_encryption: server.has_encryption,
+            money_available: server.money_available,
+            files,
+            logs,
+            running_software,
+            hardware: Some(proto::Hardware {
+                cpu: server.hardware.cpu,
+                ram: server.hardware.ram,
+                hdd: server.hardware.hdd,
+                network: server.hardware.network,
+            }),
+            is_online: server.is_online,
+            last_reset_micros: server.last_reset.timestamp_micros(),
+            is_honeypot: server.is_honeypot,
+        })
+    }
+
+    fn into_server(self) -> Result<NPCServer> {
+        let files = self
+            .files
+            .into_iter()
+            .map(|file| {
+                Ok(ServerFile {
+                    id: uuid(&file.id)?,
+                    name: file.name,
+                    file_type: enum_from_name(&file.file_type)?,
+                    size: file.size,
+                    content: file.content,
+                    is_encrypted: file.is_encrypted,
+                    is_hidden: file.is_hidden,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let logs = self
+            .logs
+            .into_iter()
+            .map(|log| {
+                Ok(LogEntry {
+                    id: uuid(&log.id)?,
+                    timestamp: timestamp(log.timestamp_micros)?,
+                    action: log.action,
+                    ip_address: log.ip_address,
+                    is_hidden: log.is_hidden,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let running_software = self
+            .running_software
+            .into_iter()
+            .map(|software| {
+                Ok(RunningSoftware {
+                    id: uuid(&software.id)?,
+                    name: software.name,
+                    version: software.version,
+                    software_type: software.software_type,
+                    ram_usage: software.ram_usage,
+                    effectiveness: software.effectiveness,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let hardware = self.hardware.unwrap_or_default();
+
+        Ok(NPCServer {
+            id: uuid(&self.id)?,
+            ip_address: self.ip_address,
+            hostname: self.hostname,
+            owner_name: self.owner_name,
+            server_type: enum_from_name(&self.server_type)?,
+            tier: self.tier,
+            security_level: self.security_level,
+            firewall_level: self.firewall_level,
+            has_encryption: self.has_encryption,
+            money_available: self.money_available,
+            files,
+            logs,
+            running_software,
+            hardware: ServerHardware {
+                cpu: hardware.cpu,
+                ram: hardware.ram,
+                hdd: hardware.hdd,
+                network: hardware.network,
+            },
+            is_online: self.is_online,
+            last_reset: timestamp(self.last_reset_micros)?,
+            is_honeypot: self.is_honeypot,
+        })
+    }
+}
+
+/// Writes records one at a time to `inner`, which should be buffered
+pub struct SnapshotWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    records: u64,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner.write_all(MAGIC)?;
+        Ok(Self { inner, buf: Vec::new(), records: 0 })
+    }
+
+    pub fn write(&mut self, kind: Kind) -> Result<()> {
+        self.buf.clear();
+        proto::Record { kind: Some(kind) }.encode_length_delimited(&mut self.buf)?;
+        self.inner.write_all(&self.buf)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Write the end marker and hand back the writer, flushed
+    pub fn finish(mut self) -> Result<W> {
+        let records = self.records;
+        self.write(Kind::End(proto::End { records }))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Reads records one at a time from `inner`, which should be buffered
+pub struct SnapshotReader<R: Read> {
+    inner: R,
+    buf: Vec<u8>,
+    records: u64,
+    done: bool,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic).map_err(|_| SnapshotError::BadMagic)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        Ok(Self { inner, buf: Vec::new(), records: 0, done: false })
+    }
+
+    /// The length prefix of the next record; `None` at the end of the input
+    fn read_len(&mut self) -> Result<Option<usize>> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0u8];
+            match self.inner.read_exact(&mut byte) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return if shift == 0 { Ok(None) } else { Err(SnapshotError::Truncated) };
+                }
+                Err(e) => return Err(e.into()),
+            }
+            value |= ((byte[0] & 0x7f) as u64) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(Some(value as usize));
+            }
+        }
+        Err(SnapshotError::Invalid("malformed record length".to_string()))
+    }
+
+    /// The next record this reader understands, or `None` after the end
+    /// marker. Records of unknown kinds are skipped.
+    pub fn next_record(&mut self) -> Result<Option<Kind>> {
+        while !self.done {
+            let Some(len) = self.read_len()? else {
+                return Err(SnapshotError::Truncated);
+            };
+            if len > MAX_RECORD_BYTES {
+                return Err(SnapshotError::RecordTooLarge { len });
+            }
+            self.buf.resize(len, 0);
+            self.inner.read_exact(&mut self.buf).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => SnapshotError::Truncated,
+                _ => e.into(),
+            })?;
+
+            match proto::Record::decode(self.buf.as_slice())?.kind {
+                Some(Kind::End(end)) => {
+                    if end.records != self.records {
+                        return Err(SnapshotError::Invalid(format!(
+                            "end marker counts {} records, read {}",
+                            end.records, self.records
+                        )));
+                    }
+                    self.done = true;
+                }
+                Some(Kind::Header(header)) if header.format_version > FORMAT_VERSION => {
+                    return Err(SnapshotError::UnsupportedVersion { found: header.format_version });
+                }
+                Some(kind) => {
+                    self.records += 1;
+                    return Ok(Some(kind));
+                }
+                None => self.records += 1,
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Stream `world` to `out` as a snapshot
+pub fn write_snapshot<W: Write>(world: &GameWorld, out: W) -> Result<W> {
+    let mut writer = SnapshotWriter::new(out)?;
+    writer.write(Kind::Header(proto::Header {
+        format_version: FORMAT_VERSION,
+        created_at_micros: world.created_at.timestamp_micros(),
+        servers: world.servers.len() as u64,
+    }))?;
+
+    // Sorted so the same world always gives the same bytes
+    let mut servers: Vec<&NPCServer> = world.servers.values().collect();
+    servers.sort_by(|a, b| a.ip_address.cmp(&b.ip_address));
+    for server in servers {
+        writer.write(Kind::Server(proto::Server::from_server(server)?))?;
+    }
+    for corporation in &world.corporations {
+        writer.write(Kind::CorporationJson(serde_json::to_vec(corporation)?))?;
+    }
+    writer.write(Kind::CatalogJson(serde_json::to_vec(&world.software_catalog)?))?;
+    for template in &world.mission_templates {
+        writer.write(Kind::MissionTemplateJson(serde_json::to_vec(template)?))?;
+    }
+    let mut routes: Vec<(&String, &Vec<String>)> = world.network_topology.connections.iter().collect();
+    routes.sort_by(|a, b| a.0.cmp(b.0));
+    for (ip, connected) in routes {
+        writer.write(Kind::Route(proto::Route { ip: ip.clone(), connected: connected.clone() }))?;
+    }
+    writer.write(Kind::Backbone(proto::Backbone { ips: world.network_topology.backbone_nodes.clone() }))?;
+
+    writer.finish()
+}
+
+/// Rebuild a world from a snapshot read from `input`
+pub fn read_snapshot<R: Read>(input: R) -> Result<GameWorld> {
+    let mut reader = SnapshotReader::new(input)?;
+    let Some(Kind::Header(header)) = reader.next_record()? else {
+        return Err(SnapshotError::Invalid("missing header".to_string()));
+    };
+
+    let mut servers = HashMap::with_capacity(header.servers as usize);
+    let mut corporations: Vec<Corporation> = Vec::new();
+    let mut software_catalog: Option<SoftwareCatalog> = None;
+    let mut mission_templates: Vec<MissionTemplate> = Vec::new();
+    let mut network_topology = NetworkTopology::new();
+
+    while let Some(kind) = reader.next_record()? {
+        match kind {
+            Kind::Header(_) => return Err(SnapshotError::Invalid("second header".to_string())),
+            Kind::Server(server) => {
+                let server = server.into_server()?;
+                servers.insert(server.ip_address.clone(), server);
+            }
+            Kind::CorporationJson(json) => corporations.push(serde_json::from_slice(&json)?),
+            Kind::CatalogJson(json) => software_catalog = Some(serde_json::from_slice(&json)?),
+            Kind::MissionTemplateJson(json) => mission_templates.push(serde_json::from_slice(&json)?),
+            Kind::Route(route) => {
+                network_topology.connections.insert(route.ip, route.connected);
+            }
+            Kind::Backbone(backbone) => network_topology.backbone_nodes = backbone.ips,
+            Kind::End(_) => unreachable!("the reader consumes the end marker"),
+        }
+    }
+    if servers.len() as u64 != header.servers {
+        return Err(SnapshotError::Invalid(format!("header lists {} servers, read {}", header.servers, servers.len())));
+    }
+
+    Ok(GameWorld {
+        servers,
+        corporations,
+        software_catalog: software_catalog.ok_or_else(|| SnapshotError::Invalid("missing catalog".to_string()))?,
+        mission_templates,
+        network_topology,
+        created_at: timestamp(header.created_at_micros)?,
+    })
+}
+
+impl GameWorld {
+    /// Save the world to `path` as a binary snapshot
+    pub fn save_snapshot(&self, path: &Path) -> Result<()> {
+        write_snapshot(self, BufWriter::new(File::create(path)?))?;
+        Ok(())
+    }
+
+    /// Load a world saved by [`GameWorld::save_snapshot`]
+    pub fn load_snapshot(path: &Path) -> Result<Self> {
+        read_snapshot(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(world: &GameWorld) -> Vec<u8> {
+        write_snapshot(world, Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let world = GameWorld::new();
+        let bytes = snapshot(&world);
+        let loaded = read_snapshot(bytes.as_slice()).unwrap();
+
+        assert_eq!(loaded.servers.len(), world.servers.len());
+        assert_eq!(loaded.corporations.len(), world.corporations.len());
+        assert_eq!(loaded.mission_templates.len(), world.mission_templates.len());
+        assert_eq!(loaded.network_topology.connections, world.network_topology.connections);
+        assert_eq!(loaded.created_at.timestamp_micros(), world.created_at.timestamp_micros());
+        assert_eq!(snapshot(&loaded).len(), bytes.len());
+
+        let mystery = &loaded.servers["13.37.13.37"];
+        assert_eq!(mystery.server_type, crate::ServerType::Mystery);
+        assert_eq!(mystery.files[0].content.as_deref(), Some("You shouldn't be here..."));
+        assert!(mystery.files[0].is_hidden);
+    }
+
+    #[test]
+    fn test_unknown_records_are_skipped() {
+        let world = GameWorld::new();
+        let bytes = snapshot(&world);
+
+        // Copy the snapshot, with a record of a kind from some future version
+        // (field 9, three bytes) after the header
+        let mut reader = SnapshotReader::new(bytes.as_slice()).unwrap();
+        let mut writer = SnapshotWriter::new(Vec::new()).unwrap();
+        writer.write(reader.next_record().unwrap().unwrap()).unwrap();
+        writer.inner.extend_from_slice(&[5, 0x4a, 3, b'n', b'e', b'w']);
+        writer.records += 1;
+        while let Some(kind) = reader.next_record().unwrap() {
+            writer.write(kind).unwrap();
+        }
+        let future = writer.finish().unwrap();
+
+        let loaded = read_snapshot(future.as_slice()).unwrap();
+        assert_eq!(loaded.servers.len(), world.servers.len());
+        assert_eq!(loaded.network_topology.backbone_nodes, world.network_topology.backbone_nodes);
+    }
+
+    #[test]
+    fn test_truncated_and_foreign_input() {
+        let bytes = snapshot(&GameWorld::new());
+        assert!(matches!(read_snapshot(&bytes[..bytes.len() - 3]), Err(SnapshotError::Truncated)));
+        assert!(matches!(read_snapshot(&b"{\"servers\":{}}"[..]), Err(SnapshotError::BadMagic)));
+    }
+}
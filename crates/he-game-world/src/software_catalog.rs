@@ -1,9 +1,22 @@
 //! Software catalog - all available software in the game
+//!
+//! The catalog is built from software packs written in TOML or JSON. The base
+//! pack (`catalog/base.toml`) is compiled in; server operators can add their
+//! own packs, which load after it and may replace base entries by key. The
+//! dependency graph is checked at load time. See `catalog/README.md` for the
+//! pack format.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
 use uuid::Uuid;
 
+const BASE_PACK: &str = include_str!("../catalog/base.toml");
+
+/// Namespace for catalog ids, so a key maps to the same id on every server
+const CATALOG_NAMESPACE: Uuid = Uuid::from_u128(0x8d3c_51e2_7a4b_4f0e_9c61_2b7d_e0a5_f314);
+
 /// Complete software catalog
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SoftwareCatalog {
@@ -19,12 +32,18 @@ pub struct SoftwareCatalog {
     pub analyzers: Vec<Software>,
     pub collectors: Vec<Software>,
     pub spam_tools: Vec<Software>,
+    pub miners: Vec<Software>,
+    pub spyware: Vec<Software>,
+    /// Packs the catalog was built from, in load order
+    pub packs: Vec<PackInfo>,
 }
 
 /// Individual software item
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Software {
     pub id: Uuid,
+    /// Stable identifier from the pack definition
+    pub key: String,
     pub name: String,
     pub version: f32,
     pub software_type: SoftwareType,
@@ -37,6 +56,15 @@ pub struct Software {
     pub level_required: i32,
     pub research_time: i32, // seconds
     pub install_time: i32, // seconds
+    /// Capabilities the software provides, e.g. `bruteforce` or `enc_log`
+    pub modules: Vec<String>,
+    /// Keys of software that must be installed as well
+    pub requires: Vec<String>,
+    /// Owners can license-lock copies to their server, so stolen copies
+    /// only run once a keygen cracks them
+    pub copy_protection: bool,
+    /// Pack the definition came from
+    pub pack: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -57,369 +85,252 @@ pub enum SoftwareType {
     Spyware,
 }
 
-impl Default for SoftwareCatalog {
-    fn default() -> Self {
-        let mut catalog = Self {
-            crackers: Vec::new(),
-            exploits: Vec::new(),
-            viruses: Vec::new(),
-            firewalls: Vec::new(),
-            antivirus: Vec::new(),
-            encryptors: Vec::new(),
-            decryptors: Vec::new(),
-            log_deleters: Vec::new(),
-            ddos_tools: Vec::new(),
-            analyzers: Vec::new(),
-            collectors: Vec::new(),
-            spam_tools: Vec::new(),
-        };
+/// Header of a software pack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackInfo {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
 
-        catalog.generate_crackers();
-        catalog.generate_exploits();
-        catalog.generate_viruses();
-        catalog.generate_firewalls();
-        catalog.generate_antivirus();
-        catalog.generate_utilities();
-        catalog.generate_attack_tools();
+/// A software pack as written on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoftwarePack {
+    pub pack: PackInfo,
+    #[serde(default)]
+    pub software: Vec<SoftwareDefinition>,
+}
 
-        catalog
-    }
+/// One `[[software]]` entry of a pack
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoftwareDefinition {
+    pub key: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub software_type: SoftwareType,
+    pub version: f32,
+    #[serde(default)]
+    pub description: String,
+    pub size: i64,
+    pub ram_usage: i32,
+    pub cpu_usage: i32,
+    pub effectiveness: i32,
+    pub price: i64,
+    pub level_required: i32,
+    pub research_time: i32,
+    pub install_time: i32,
+    #[serde(default)]
+    pub modules: Vec<String>,
+    #[serde(default)]
+    pub requires: Vec<String>,
+    #[serde(default)]
+    pub copy_protection: bool,
 }
 
-impl SoftwareCatalog {
-    fn generate_crackers(&mut self) {
-        let crackers = vec![
-            ("Basic Cracker", 1.0, 10, 100, 1),
-            ("Password Cracker", 2.0, 20, 500, 3),
-            ("Advanced Cracker", 3.0, 35, 2000, 5),
-            ("Elite Cracker", 4.0, 50, 10000, 10),
-            ("Quantum Cracker", 5.0, 70, 50000, 15),
-            ("Neural Cracker", 6.0, 85, 100000, 20),
-            ("Ultimate Cracker", 10.0, 95, 1000000, 30),
-        ];
-
-        for (name, version, effectiveness, price, level) in crackers {
-            self.crackers.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::Cracker,
-                description: format!("Cracks passwords with {}% success rate", effectiveness),
-                size: (version * 10.0) as i64,
-                ram_usage: (version * 128.0) as i32,
-                cpu_usage: effectiveness / 2,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 3600, // hours to seconds
-                install_time: 60 * level,
-            });
-        }
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error("failed to read {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("failed to parse {origin}: {message}")]
+    Parse { origin: String, message: String },
+    #[error("pack {pack}: {key}: {reason}")]
+    Invalid { pack: String, key: String, reason: String },
+    #[error("pack {pack} defines {key} more than once")]
+    DuplicateKey { pack: String, key: String },
+    #[error("{key} requires unknown software {missing}")]
+    UnknownDependency { key: String, missing: String },
+    #[error("dependency cycle: {}", .0.join(" -> "))]
+    DependencyCycle(Vec<String>),
+}
+
+impl SoftwarePack {
+    pub fn from_toml(source: &str, origin: &str) -> Result<Self, CatalogError> {
+        toml::from_str(source).map_err(|e| CatalogError::Parse {
+            origin: origin.to_string(),
+            message: e.to_string(),
+        })
     }
 
-    fn generate_exploits(&mut self) {
-        let exploits = vec![
-            ("Port Scanner", 1.0, 15, 200, 1),
-            ("SQL Injection", 2.0, 30, 1000, 4),
-            ("Buffer Overflow", 3.0, 45, 5000, 7),
-            ("Zero-Day Exploit", 4.0, 60, 20000, 12),
-            ("Kernel Exploit", 5.0, 75, 100000, 18),
-            ("Hypervisor Escape", 10.0, 90, 500000, 25),
-        ];
-
-        for (name, version, effectiveness, price, level) in exploits {
-            self.exploits.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::Exploit,
-                description: format!("Exploits system vulnerabilities ({}% success)", effectiveness),
-                size: (version * 15.0) as i64,
-                ram_usage: (version * 256.0) as i32,
-                cpu_usage: effectiveness / 3,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 7200,
-                install_time: 120 * level,
-            });
-        }
+    pub fn from_json(source: &str, origin: &str) -> Result<Self, CatalogError> {
+        serde_json::from_str(source).map_err(|e| CatalogError::Parse {
+            origin: origin.to_string(),
+            message: e.to_string(),
+        })
     }
 
-    fn generate_viruses(&mut self) {
-        let viruses = vec![
-            ("Basic Virus", 1.0, 10, 500, 2),
-            ("Worm", 2.0, 25, 2500, 5),
-            ("Trojan Horse", 3.0, 40, 10000, 8),
-            ("Ransomware", 4.0, 55, 50000, 12),
-            ("Rootkit", 5.0, 70, 200000, 16),
-            ("Botnet Client", 6.0, 80, 500000, 20),
-            ("Polymorphic Virus", 10.0, 95, 2000000, 30),
-        ];
-
-        for (name, version, effectiveness, price, level) in viruses {
-            self.viruses.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::Virus,
-                description: format!("Malicious software ({}% stealth)", effectiveness),
-                size: (version * 5.0) as i64,
-                ram_usage: (version * 64.0) as i32,
-                cpu_usage: 10,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 5400,
-                install_time: 30,
-            });
+    /// Read a `.toml` or `.json` pack
+    pub fn from_file(path: &Path) -> Result<Self, CatalogError> {
+        let source = std::fs::read_to_string(path).map_err(|source| CatalogError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let origin = path.display().to_string();
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&source, &origin),
+            _ => Self::from_toml(&source, &origin),
         }
     }
+}
 
-    fn generate_firewalls(&mut self) {
-        let firewalls = vec![
-            ("Basic Firewall", 1.0, 20, 300, 1),
-            ("Advanced Firewall", 2.0, 35, 1500, 3),
-            ("Corporate Firewall", 3.0, 50, 7500, 6),
-            ("Military Firewall", 4.0, 65, 30000, 10),
-            ("Quantum Firewall", 5.0, 80, 150000, 15),
-            ("AI Firewall", 10.0, 95, 1000000, 25),
-        ];
-
-        for (name, version, effectiveness, price, level) in firewalls {
-            self.firewalls.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::Firewall,
-                description: format!("Blocks {}% of attacks", effectiveness),
-                size: (version * 20.0) as i64,
-                ram_usage: (version * 512.0) as i32,
-                cpu_usage: effectiveness / 4,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 3600,
-                install_time: 180 * level,
-            });
+impl SoftwareDefinition {
+    fn validate(&self, pack: &str) -> Result<(), CatalogError> {
+        let invalid = |reason: &str| CatalogError::Invalid {
+            pack: pack.to_string(),
+            key: self.key.clone(),
+            reason: reason.to_string(),
+        };
+        let is_identifier = |s: &str| {
+            !s.is_empty() && s.len() <= 64
+                && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        };
+
+        if !is_identifier(&self.key) {
+            return Err(invalid("key must be 1-64 lower-case letters, digits or _"));
+        }
+        if self.name.trim().is_empty() {
+            return Err(invalid("name is empty"));
         }
+        if !self.version.is_finite() || self.version <= 0.0 {
+            return Err(invalid("version must be positive"));
+        }
+        if !(0..=100).contains(&self.effectiveness) || !(0..=100).contains(&self.cpu_usage) {
+            return Err(invalid("effectiveness and cpu_usage must be 0-100"));
+        }
+        if self.size < 0 || self.ram_usage < 0 || self.price < 0 || self.research_time < 0 || self.install_time < 0 {
+            return Err(invalid("sizes, price and times cannot be negative"));
+        }
+        if self.level_required < 1 {
+            return Err(invalid("level_required must be at least 1"));
+        }
+        if let Some(module) = self.modules.iter().find(|m| !is_identifier(m)) {
+            return Err(invalid(&format!("bad module name {:?}", module)));
+        }
+        if self.requires.contains(&self.key) {
+            return Err(invalid("software cannot require itself"));
+        }
+        Ok(())
     }
 
-    fn generate_antivirus(&mut self) {
-        let antiviruses = vec![
-            ("Basic AntiVirus", 1.0, 25, 400, 2),
-            ("Advanced AntiVirus", 2.0, 40, 2000, 4),
-            ("Premium AntiVirus", 3.0, 55, 10000, 7),
-            ("Enterprise AntiVirus", 4.0, 70, 50000, 11),
-            ("Military AntiVirus", 5.0, 85, 250000, 16),
-            ("Quantum Shield", 10.0, 98, 2000000, 28),
-        ];
-
-        for (name, version, effectiveness, price, level) in antiviruses {
-            self.antivirus.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::AntiVirus,
-                description: format!("Detects {}% of viruses", effectiveness),
-                size: (version * 25.0) as i64,
-                ram_usage: (version * 384.0) as i32,
-                cpu_usage: 15,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 4500,
-                install_time: 240 * level,
-            });
+    fn into_software(self, pack: &str) -> Software {
+        Software {
+            id: Uuid::new_v5(&CATALOG_NAMESPACE, self.key.as_bytes()),
+            key: self.key,
+            name: self.name,
+            version: self.version,
+            software_type: self.software_type,
+            description: self.description,
+            size: self.size,
+            ram_usage: self.ram_usage,
+            cpu_usage: self.cpu_usage,
+            effectiveness: self.effectiveness,
+            price: self.price,
+            level_required: self.level_required,
+            research_time: self.research_time,
+            install_time: self.install_time,
+            modules: self.modules,
+            requires: self.requires,
+            copy_protection: self.copy_protection,
+            pack: pack.to_string(),
         }
     }
+}
 
-    fn generate_utilities(&mut self) {
-        // Log Deleters
-        let log_deleters = vec![
-            ("Log Cleaner", 1.0, 30, 200, 1),
-            ("Trace Remover", 2.0, 50, 1000, 3),
-            ("Ghost Mode", 3.0, 70, 5000, 6),
-            ("Phantom Delete", 5.0, 90, 25000, 10),
-            ("Quantum Eraser", 10.0, 99, 100000, 15),
-        ];
-
-        for (name, version, effectiveness, price, level) in log_deleters {
-            self.log_deleters.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::LogDeleter,
-                description: format!("Removes logs with {}% efficiency", effectiveness),
-                size: (version * 3.0) as i64,
-                ram_usage: (version * 32.0) as i32,
-                cpu_usage: 5,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 1800,
-                install_time: 30,
-            });
-        }
+impl Default for SoftwareCatalog {
+    fn default() -> Self {
+        Self::from_packs(vec![Self::base_pack()]).expect("base software pack is valid")
+    }
+}
 
-        // Encryptors
-        let encryptors = vec![
-            ("Basic Encryptor", 1.0, 40, 500, 2),
-            ("AES Encryptor", 3.0, 60, 5000, 5),
-            ("Military Cipher", 5.0, 80, 50000, 10),
-            ("Quantum Encryption", 10.0, 95, 500000, 20),
-        ];
-
-        for (name, version, effectiveness, price, level) in encryptors {
-            self.encryptors.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::Encryptor,
-                description: format!("{}% encryption strength", effectiveness),
-                size: (version * 8.0) as i64,
-                ram_usage: (version * 128.0) as i32,
-                cpu_usage: effectiveness / 5,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 2700,
-                install_time: 60,
-            });
-        }
+impl SoftwareCatalog {
+    /// The pack compiled into the game
+    pub fn base_pack() -> SoftwarePack {
+        SoftwarePack::from_toml(BASE_PACK, "catalog/base.toml").expect("base software pack parses")
+    }
 
-        // Decryptors (matching encryptors)
-        for encryptor in &self.encryptors {
-            self.decryptors.push(Software {
-                id: Uuid::new_v4(),
-                name: encryptor.name.replace("Encryptor", "Decryptor").replace("Encryption", "Decryption"),
-                version: encryptor.version,
-                software_type: SoftwareType::Decryptor,
-                description: format!("Breaks {}% of encryptions", encryptor.effectiveness),
-                size: encryptor.size * 2,
-                ram_usage: encryptor.ram_usage * 2,
-                cpu_usage: encryptor.cpu_usage * 3,
-                effectiveness: encryptor.effectiveness - 10, // Slightly less effective
-                price: encryptor.price * 3, // More expensive
-                level_required: encryptor.level_required + 2,
-                research_time: encryptor.research_time * 2,
-                install_time: encryptor.install_time,
-            });
+    /// Base pack plus every `.toml` and `.json` pack in `packs_dir`, in file
+    /// name order
+    pub fn load(packs_dir: Option<&Path>) -> Result<Self, CatalogError> {
+        let mut packs = vec![Self::base_pack()];
+
+        if let Some(dir) = packs_dir {
+            let io_error = |source| CatalogError::Io { path: dir.to_path_buf(), source };
+            let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+                .map_err(io_error)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("toml" | "json")))
+                .collect();
+            files.sort();
+
+            for file in files {
+                packs.push(SoftwarePack::from_file(&file)?);
+            }
         }
+
+        Self::from_packs(packs)
     }
 
-    fn generate_attack_tools(&mut self) {
-        // DDoS Tools
-        let ddos_tools = vec![
-            ("Ping Flood", 1.0, 20, 1000, 3),
-            ("SYN Flood", 2.0, 40, 5000, 6),
-            ("Botnet Controller", 3.0, 60, 25000, 10),
-            ("Amplification Attack", 5.0, 80, 100000, 15),
-            ("Quantum DDoS", 10.0, 95, 1000000, 25),
-        ];
-
-        for (name, version, effectiveness, price, level) in ddos_tools {
-            self.ddos_tools.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::DDoS,
-                description: format!("DDoS attack tool ({}% power)", effectiveness),
-                size: (version * 12.0) as i64,
-                ram_usage: (version * 1024.0) as i32,
-                cpu_usage: 80,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 5400,
-                install_time: 120,
-            });
+    /// Build the catalog from packs in load order. A later pack replaces an
+    /// entry by reusing its key; the entry keeps its place in the listing.
+    pub fn from_packs(packs: Vec<SoftwarePack>) -> Result<Self, CatalogError> {
+        let mut order: Vec<String> = Vec::new();
+        let mut entries: HashMap<String, Software> = HashMap::new();
+        let mut infos = Vec::new();
+
+        for pack in packs {
+            let mut seen = HashSet::new();
+            for definition in pack.software {
+                definition.validate(&pack.pack.name)?;
+                if !seen.insert(definition.key.clone()) {
+                    return Err(CatalogError::DuplicateKey {
+                        pack: pack.pack.name.clone(),
+                        key: definition.key,
+                    });
+                }
+
+                let key = definition.key.clone();
+                if let Some(previous) = entries.insert(key.clone(), definition.into_software(&pack.pack.name)) {
+                    tracing::info!("Software pack {} replaces {} from {}", pack.pack.name, key, previous.pack);
+                } else {
+                    order.push(key);
+                }
+            }
+            infos.push(pack.pack);
         }
 
-        // Analyzers
-        let analyzers = vec![
-            ("Network Scanner", 1.0, 50, 300, 1),
-            ("Port Analyzer", 2.0, 65, 1500, 3),
-            ("Vulnerability Scanner", 3.0, 80, 10000, 7),
-            ("Deep Inspector", 5.0, 90, 50000, 12),
-            ("Quantum Analyzer", 10.0, 99, 500000, 20),
-        ];
-
-        for (name, version, effectiveness, price, level) in analyzers {
-            self.analyzers.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::Analyzer,
-                description: format!("Analyzes targets ({}% accuracy)", effectiveness),
-                size: (version * 7.0) as i64,
-                ram_usage: (version * 256.0) as i32,
-                cpu_usage: 30,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 2400,
-                install_time: 90,
-            });
-        }
+        check_dependencies(&order, &entries)?;
 
-        // Collectors (for stealing data/money)
-        let collectors = vec![
-            ("Data Miner", 1.0, 30, 2000, 4),
-            ("Bitcoin Miner", 2.0, 45, 10000, 8),
-            ("Bank Collector", 3.0, 60, 50000, 12),
-            ("Crypto Harvester", 5.0, 75, 250000, 18),
-            ("Quantum Collector", 10.0, 90, 2000000, 30),
-        ];
-
-        for (name, version, effectiveness, price, level) in collectors {
-            self.collectors.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::Collector,
-                description: format!("Collects resources ({}% efficiency)", effectiveness),
-                size: (version * 10.0) as i64,
-                ram_usage: (version * 512.0) as i32,
-                cpu_usage: 50,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 7200,
-                install_time: 300,
-            });
-        }
+        let mut catalog = Self {
+            crackers: Vec::new(),
+            exploits: Vec::new(),
+            viruses: Vec::new(),
+            firewalls: Vec::new(),
+            antivirus: Vec::new(),
+            encryptors: Vec::new(),
+            decryptors: Vec::new(),
+            log_deleters: Vec::new(),
+            ddos_tools: Vec::new(),
+            analyzers: Vec::new(),
+            collectors: Vec::new(),
+            spam_tools: Vec::new(),
+            miners: Vec::new(),
+            spyware: Vec::new(),
+            packs: infos,
+        };
 
-        // Spam Tools
-        let spam_tools = vec![
-            ("Email Spammer", 1.0, 40, 500, 2),
-            ("SMS Bomber", 2.0, 55, 2500, 5),
-            ("Social Spammer", 3.0, 70, 15000, 9),
-            ("Spam Network", 5.0, 85, 100000, 15),
-        ];
-
-        for (name, version, effectiveness, price, level) in spam_tools {
-            self.spam_tools.push(Software {
-                id: Uuid::new_v4(),
-                name: name.to_string(),
-                version,
-                software_type: SoftwareType::SpamTool,
-                description: format!("Spam effectiveness: {}%", effectiveness),
-                size: (version * 4.0) as i64,
-                ram_usage: (version * 64.0) as i32,
-                cpu_usage: 20,
-                effectiveness,
-                price,
-                level_required: level,
-                research_time: level * 1800,
-                install_time: 45,
-            });
+        for key in order {
+            if let Some(software) = entries.remove(&key) {
+                catalog.collection_mut(software.software_type).push(software);
+            }
         }
+
+        Ok(catalog)
     }
 
-    /// Get software by type and version
-    pub fn get_software(&self, software_type: SoftwareType, min_version: f32) -> Vec<&Software> {
-        let collection = match software_type {
+    fn collection(&self, software_type: SoftwareType) -> &Vec<Software> {
+        match software_type {
             SoftwareType::Cracker => &self.crackers,
             SoftwareType::Exploit => &self.exploits,
             SoftwareType::Virus => &self.viruses,
@@ -432,19 +343,33 @@ impl SoftwareCatalog {
             SoftwareType::Analyzer => &self.analyzers,
             SoftwareType::Collector => &self.collectors,
             SoftwareType::SpamTool => &self.spam_tools,
-            _ => return Vec::new(),
-        };
-
-        collection.iter()
-            .filter(|s| s.version >= min_version)
-            .collect()
+            SoftwareType::Miner => &self.miners,
+            SoftwareType::Spyware => &self.spyware,
+        }
     }
 
-    /// Get all software available for a player level
-    pub fn get_available_for_level(&self, level: i32) -> Vec<&Software> {
-        let mut available = Vec::new();
+    fn collection_mut(&mut self, software_type: SoftwareType) -> &mut Vec<Software> {
+        match software_type {
+            SoftwareType::Cracker => &mut self.crackers,
+            SoftwareType::Exploit => &mut self.exploits,
+            SoftwareType::Virus => &mut self.viruses,
+            SoftwareType::Firewall => &mut self.firewalls,
+            SoftwareType::AntiVirus => &mut self.antivirus,
+            SoftwareType::Encryptor => &mut self.encryptors,
+            SoftwareType::Decryptor => &mut self.decryptors,
+            SoftwareType::LogDeleter => &mut self.log_deleters,
+            SoftwareType::DDoS => &mut self.ddos_tools,
+            SoftwareType::Analyzer => &mut self.analyzers,
+            SoftwareType::Collector => &mut self.collectors,
+            SoftwareType::SpamTool => &mut self.spam_tools,
+            SoftwareType::Miner => &mut self.miners,
+            SoftwareType::Spyware => &mut self.spyware,
+        }
+    }
 
-        for software_list in [
+    /// Every software item, grouped by type
+    pub fn all(&self) -> impl Iterator<Item = &Software> {
+        [
             &self.crackers,
             &self.exploits,
             &self.viruses,
@@ -457,13 +382,157 @@ impl SoftwareCatalog {
             &self.analyzers,
             &self.collectors,
             &self.spam_tools,
-        ] {
-            available.extend(
-                software_list.iter()
-                    .filter(|s| s.level_required <= level)
-            );
+            &self.miners,
+            &self.spyware,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Software> {
+        self.all().find(|s| s.key == key)
+    }
+
+    /// The title a copy on a server belongs to; copies carry its name
+    pub fn by_name(&self, name: &str) -> Option<&Software> {
+        self.all().find(|s| s.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Get software by type and version
+    pub fn get_software(&self, software_type: SoftwareType, min_version: f32) -> Vec<&Software> {
+        self.collection(software_type).iter()
+            .filter(|s| s.version >= min_version)
+            .collect()
+    }
+
+    /// Get all software available for a player level
+    pub fn get_available_for_level(&self, level: i32) -> Vec<&Software> {
+        self.all()
+            .filter(|s| s.level_required <= level)
+            .collect()
+    }
+}
+
+/// Every dependency must exist and the graph must be acyclic
+fn check_dependencies(order: &[String], entries: &HashMap<String, Software>) -> Result<(), CatalogError> {
+    for key in order {
+        for dependency in &entries[key].requires {
+            if !entries.contains_key(dependency) {
+                return Err(CatalogError::UnknownDependency {
+                    key: key.clone(),
+                    missing: dependency.clone(),
+                });
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Visiting,
+        Done,
+    }
+
+    fn visit<'a>(
+        key: &'a str,
+        entries: &'a HashMap<String, Software>,
+        marks: &mut HashMap<&'a str, Mark>,
+        path: &mut Vec<&'a str>,
+    ) -> Result<(), CatalogError> {
+        match marks.get(key) {
+            Some(Mark::Done) => return Ok(()),
+            Some(Mark::Visiting) => {
+                let start = path.iter().position(|k| *k == key).unwrap_or(0);
+                let mut cycle: Vec<String> = path[start..].iter().map(|k| k.to_string()).collect();
+                cycle.push(key.to_string());
+                return Err(CatalogError::DependencyCycle(cycle));
+            }
+            None => {}
+        }
+
+        marks.insert(key, Mark::Visiting);
+        path.push(key);
+        for dependency in &entries[key].requires {
+            visit(dependency, entries, marks, path)?;
+        }
+        path.pop();
+        marks.insert(key, Mark::Done);
+        Ok(())
+    }
+
+    let mut marks = HashMap::new();
+    for key in order {
+        visit(key, entries, &mut marks, &mut Vec::new())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(name: &str, body: &str) -> SoftwarePack {
+        let source = format!("[pack]\nname = \"{}\"\nversion = \"1\"\n{}", name, body);
+        SoftwarePack::from_toml(&source, name).unwrap()
+    }
+
+    fn entry(key: &str, requires: &[&str]) -> String {
+        format!(
+            "[[software]]\nkey = \"{}\"\nname = \"{}\"\ntype = \"Cracker\"\nversion = 1.0\nsize = 1\nram_usage = 1\n\
+             cpu_usage = 1\neffectiveness = 1\nprice = 1\nlevel_required = 1\nresearch_time = 1\ninstall_time = 1\n\
+             requires = [{}]\n",
+            key,
+            key,
+            requires.iter().map(|r| format!("\"{}\"", r)).collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    #[test]
+    fn test_base_pack_loads() {
+        let catalog = SoftwareCatalog::default();
+        assert_eq!(catalog.packs.len(), 1);
+        assert!(!catalog.crackers.is_empty());
+
+        let client = catalog.get("botnet_client").unwrap();
+        assert_eq!(client.id, SoftwareCatalog::default().get("botnet_client").unwrap().id);
+        assert!(catalog.get("botnet_controller").unwrap().requires.contains(&client.key));
+
+        assert!(catalog.by_name("elite cracker").unwrap().copy_protection);
+        assert!(!catalog.get("basic_cracker").unwrap().copy_protection);
+    }
+
+    #[test]
+    fn test_operator_pack_replaces_by_key() {
+        let custom = pack("custom", &format!("{}{}", entry("basic_cracker", &[]), entry("frost_cracker", &["basic_cracker"])));
+        let catalog = SoftwareCatalog::from_packs(vec![SoftwareCatalog::base_pack(), custom]).unwrap();
+
+        let replaced = catalog.get("basic_cracker").unwrap();
+        assert_eq!(replaced.pack, "custom");
+        assert_eq!(catalog.crackers[0].key, "basic_cracker");
+        assert_eq!(catalog.all().filter(|s| s.key == "basic_cracker").count(), 1);
+        assert!(catalog.get("frost_cracker").is_some());
+    }
+
+    #[test]
+    fn test_dependency_graph_is_validated() {
+        let missing = pack("broken", &entry("a", &["nowhere"]));
+        assert!(matches!(
+            SoftwareCatalog::from_packs(vec![missing]),
+            Err(CatalogError::UnknownDependency { .. })
+        ));
+
+        let cyclic = pack("cyclic", &format!("{}{}{}", entry("a", &["b"]), entry("b", &["c"]), entry("c", &["a"])));
+        match SoftwareCatalog::from_packs(vec![cyclic]) {
+            Err(CatalogError::DependencyCycle(cycle)) => {
+                assert_eq!(cycle.first(), cycle.last());
+                assert_eq!(cycle.len(), 4);
+            }
+            other => panic!("expected a cycle, got {:?}", other.map(|c| c.packs)),
         }
 
-        available
+        let duplicate = pack("dup", &format!("{}{}", entry("a", &[]), entry("a", &[])));
+        assert!(matches!(
+            SoftwareCatalog::from_packs(vec![duplicate]),
+            Err(CatalogError::DuplicateKey { .. })
+        ));
     }
-}
\ No newline at end of file
+}
@@ -35,6 +35,7 @@ pub use model::{Connection, Network, Tunnel};
 pub use types::*;
 
 use anyhow::Result;
+use he_core::registry::{BoundedMap, Footprint, RegistryConfig, RegistryLoader, RegistryStats};
 use once_cell::sync::Lazy;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -43,93 +44,150 @@ use tokio::sync::RwLock;
 pub static NETWORK_REGISTRY: Lazy<Arc<RwLock<NetworkRegistry>>> = 
     Lazy::new(|| Arc::new(RwLock::new(NetworkRegistry::new())));
 
-/// Network registry for tracking active networks and connections
-#[derive(Debug, Default)]
+/// Environment prefix for the registry limits, see [`RegistryConfig::from_env`]
+const CONFIG_PREFIX: &str = "HE_NETWORK_REGISTRY";
+
+/// How often idle entries are expired
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Hot entries preloaded per map at startup
+const WARM_LIMIT: usize = 1_000;
+
+impl Footprint for Network {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.name.len()
+    }
+}
+
+impl Footprint for Tunnel {
+    fn footprint(&self) -> usize {
+        std::mem::size_of::<Self>() + self.hops.len() * std::mem::size_of::<BounceLink>()
+    }
+}
+
+impl Footprint for Connection {}
+
+/// Network registry for tracking active networks and connections.
+/// Each map is bounded; see [`he_core::registry`].
+#[derive(Debug)]
 pub struct NetworkRegistry {
-    networks: dashmap::DashMap<NetworkId, Arc<Network>>,
-    tunnels: dashmap::DashMap<TunnelId, Arc<Tunnel>>,
-    connections: dashmap::DashMap<ConnectionId, Arc<Connection>>,
+    networks: Arc<BoundedMap<NetworkId, Network>>,
+    tunnels: Arc<BoundedMap<TunnelId, Tunnel>>,
+    connections: Arc<BoundedMap<ConnectionId, Connection>>,
+}
+
+impl Default for NetworkRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NetworkRegistry {
     pub fn new() -> Self {
+        Self::with_config(RegistryConfig::from_env(CONFIG_PREFIX))
+    }
+
+    pub fn with_config(config: RegistryConfig) -> Self {
+        let connections = BoundedMap::new("network.connections", config.clone());
+        // Open connections are live state, only closed ones may be evicted
+        connections.pin_when(Connection::is_active);
+
         Self {
-            networks: dashmap::DashMap::new(),
-            tunnels: dashmap::DashMap::new(),
-            connections: dashmap::DashMap::new(),
+            networks: BoundedMap::new("network.networks", config.clone()),
+            tunnels: BoundedMap::new("network.tunnels", config),
+            connections,
         }
     }
 
+    /// Reload evicted entries from the database on a miss. Until a loader is
+    /// installed nothing is evicted.
+    pub fn set_loaders(
+        &self,
+        networks: Arc<dyn RegistryLoader<NetworkId, Network>>,
+        tunnels: Arc<dyn RegistryLoader<TunnelId, Tunnel>>,
+        connections: Arc<dyn RegistryLoader<ConnectionId, Connection>>,
+    ) {
+        self.networks.set_loader(networks);
+        self.tunnels.set_loader(tunnels);
+        self.connections.set_loader(connections);
+    }
+
+    /// Preload up to `limit` hot entries per map
+    pub async fn warm(&self, limit: usize) -> Result<()> {
+        self.networks.warm(limit).await?;
+        self.tunnels.warm(limit).await?;
+        self.connections.warm(limit).await?;
+        Ok(())
+    }
+
+    /// Expire idle entries every `interval`
+    pub fn spawn_sweepers(&self, interval: std::time::Duration) {
+        self.networks.spawn_sweeper(interval);
+        self.tunnels.spawn_sweeper(interval);
+        self.connections.spawn_sweeper(interval);
+    }
+
+    pub fn stats(&self) -> Vec<RegistryStats> {
+        vec![self.networks.stats(), self.tunnels.stats(), self.connections.stats()]
+    }
+
     pub async fn register_network(&self, network: Network) -> Arc<Network> {
-        let network_arc = Arc::new(network);
-        self.networks.insert(network_arc.network_id.clone(), network_arc.clone());
-        network_arc
+        self.networks.insert(network.network_id, network)
     }
 
     pub async fn register_tunnel(&self, tunnel: Tunnel) -> Arc<Tunnel> {
-        let tunnel_arc = Arc::new(tunnel);
-        self.tunnels.insert(tunnel_arc.tunnel_id.clone(), tunnel_arc.clone());
-        tunnel_arc
+        self.tunnels.insert(tunnel.tunnel_id, tunnel)
     }
 
     pub async fn register_connection(&self, connection: Connection) -> Arc<Connection> {
-        let connection_arc = Arc::new(connection);
-        self.connections.insert(connection_arc.connection_id.clone(), connection_arc.clone());
-        connection_arc
+        self.connections.insert(connection.connection_id, connection)
     }
 
-    pub async fn get_network(&self, network_id: &NetworkId) -> Option<Arc<Network>> {
-        self.networks.get(network_id).map(|entry| entry.clone())
+    pub async fn get_network(&self, network_id: &NetworkId) -> Result<Option<Arc<Network>>> {
+        self.networks.get_or_load(network_id).await
     }
 
-    pub async fn get_tunnel(&self, tunnel_id: &TunnelId) -> Option<Arc<Tunnel>> {
-        self.tunnels.get(tunnel_id).map(|entry| entry.clone())
+    pub async fn get_tunnel(&self, tunnel_id: &TunnelId) -> Result<Option<Arc<Tunnel>>> {
+        self.tunnels.get_or_load(tunnel_id).await
     }
 
-    pub async fn get_connection(&self, connection_id: &ConnectionId) -> Option<Arc<Connection>> {
-        self.connections.get(connection_id).map(|entry| entry.clone())
+    pub async fn get_connection(&self, connection_id: &ConnectionId) -> Result<Option<Arc<Connection>>> {
+        self.connections.get_or_load(connection_id).await
     }
 
     pub async fn remove_network(&self, network_id: &NetworkId) -> Option<Arc<Network>> {
-        self.networks.remove(network_id).map(|(_, network)| network)
+        self.networks.remove(network_id)
     }
 
     pub async fn remove_tunnel(&self, tunnel_id: &TunnelId) -> Option<Arc<Tunnel>> {
-        self.tunnels.remove(tunnel_id).map(|(_, tunnel)| tunnel)
+        self.tunnels.remove(tunnel_id)
     }
 
     pub async fn remove_connection(&self, connection_id: &ConnectionId) -> Option<Arc<Connection>> {
-        self.connections.remove(connection_id).map(|(_, connection)| connection)
+        self.connections.remove(connection_id)
     }
 
+    /// Networks currently in memory; evicted entries are not listed
     pub async fn list_networks(&self) -> Vec<Arc<Network>> {
-        self.networks.iter().map(|entry| entry.value().clone()).collect()
+        self.networks.values()
     }
 
     pub async fn list_tunnels(&self) -> Vec<Arc<Tunnel>> {
-        self.tunnels.iter().map(|entry| entry.value().clone()).collect()
+        self.tunnels.values()
     }
 
     pub async fn list_connections(&self) -> Vec<Arc<Connection>> {
-        self.connections.iter().map(|entry| entry.value().clone()).collect()
+        self.connections.values()
     }
 
     /// Get all tunnels for a specific network
     pub async fn get_network_tunnels(&self, network_id: &NetworkId) -> Vec<Arc<Tunnel>> {
-        self.tunnels
-            .iter()
-            .filter(|entry| &entry.network_id == network_id)
-            .map(|entry| entry.value().clone())
-            .collect()
+        self.tunnels.filter(|tunnel| &tunnel.network_id == network_id)
     }
 
     /// Get all connections for a specific tunnel
     pub async fn get_tunnel_connections(&self, tunnel_id: &TunnelId) -> Vec<Arc<Connection>> {
-        self.connections
-            .iter()
-            .filter(|entry| &entry.tunnel_id == tunnel_id)
-            .map(|entry| entry.value().clone())
-            .collect()
+        self.connections.filter(|connection| &connection.tunnel_id == tunnel_id)
     }
 }
 
@@ -137,8 +195,14 @@ impl NetworkRegistry {
 pub async fn init() -> Result<()> {
     tracing::info!("Initializing Helix Network subsystem");
     
-    // Initialize the network registry
-    let _registry = NETWORK_REGISTRY.clone();
+    // Initialize the network registry. Loaders installed before this point
+    // are used to preload hot entries.
+    let registry = NETWORK_REGISTRY.read().await;
+    registry.spawn_sweepers(SWEEP_INTERVAL);
+    if let Err(e) = registry.warm(WARM_LIMIT).await {
+        tracing::warn!("Failed to warm the network registry: {}", e);
+    }
+    drop(registry);
     
     tracing::info!("Helix Network subsystem initialized successfully");
     Ok(())
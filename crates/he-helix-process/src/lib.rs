@@ -39,6 +39,7 @@ pub use resources::ProcessResources;
 pub use types::*;
 
 use anyhow::Result;
+use he_core::registry::{BoundedMap, Footprint, RegistryConfig, RegistryLoader, RegistryStats};
 use once_cell::sync::Lazy;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -47,27 +48,89 @@ use tokio::sync::RwLock;
 pub static PROCESS_REGISTRY: Lazy<Arc<RwLock<ProcessRegistry>>> = 
     Lazy::new(|| Arc::new(RwLock::new(ProcessRegistry::new())));
 
-/// Process registry for tracking active processes and resource allocation
-#[derive(Debug, Default)]
+/// Environment prefix for the registry limits, see [`RegistryConfig::from_env`]
+const CONFIG_PREFIX: &str = "HE_PROCESS_REGISTRY";
+
+/// How often idle entries are expired
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Hot entries preloaded per map at startup
+const WARM_LIMIT: usize = 1_000;
+
+impl Footprint for Process {
+    fn footprint(&self) -> usize {
+        // The JSON payload is counted at its serialised size
+        std::mem::size_of::<Self>() + self.data.as_ref().map_or(0, |data| data.to_string().len())
+    }
+}
+
+/// Process registry for tracking active processes and resource allocation.
+/// Running processes are pinned; finished ones are evicted like any other
+/// bounded registry entry (see [`he_core::registry`]) together with their
+/// allocation and hierarchy records.
+#[derive(Debug)]
 pub struct ProcessRegistry {
-    processes: dashmap::DashMap<ProcessId, Arc<Process>>,
-    resource_allocations: dashmap::DashMap<ProcessId, ProcessResources>,
-    process_hierarchy: dashmap::DashMap<ProcessId, Vec<ProcessId>>, // parent -> children
+    processes: Arc<BoundedMap<ProcessId, Process>>,
+    resource_allocations: Arc<dashmap::DashMap<ProcessId, ProcessResources>>,
+    process_hierarchy: Arc<dashmap::DashMap<ProcessId, Vec<ProcessId>>>, // parent -> children
+}
+
+impl Default for ProcessRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProcessRegistry {
     pub fn new() -> Self {
+        Self::with_config(RegistryConfig::from_env(CONFIG_PREFIX))
+    }
+
+    pub fn with_config(config: RegistryConfig) -> Self {
+        let processes = BoundedMap::new("process.processes", config);
+        processes.pin_when(Process::is_active);
+
+        let resource_allocations = Arc::new(dashmap::DashMap::new());
+        let process_hierarchy = Arc::new(dashmap::DashMap::new());
+        {
+            let resource_allocations = Arc::clone(&resource_allocations);
+            let process_hierarchy = Arc::clone(&process_hierarchy);
+            processes.set_eviction_listener(move |process_id: &ProcessId, _: &Arc<Process>| {
+                resource_allocations.remove(process_id);
+                process_hierarchy.remove(process_id);
+            });
+        }
+
         Self {
-            processes: dashmap::DashMap::new(),
-            resource_allocations: dashmap::DashMap::new(),
-            process_hierarchy: dashmap::DashMap::new(),
+            processes,
+            resource_allocations,
+            process_hierarchy,
         }
     }
 
+    /// Reload evicted processes from the database on a miss. Until a loader is
+    /// installed nothing is evicted.
+    pub fn set_loader(&self, loader: Arc<dyn RegistryLoader<ProcessId, Process>>) {
+        self.processes.set_loader(loader);
+    }
+
+    /// Preload up to `limit` hot processes
+    pub async fn warm(&self, limit: usize) -> Result<()> {
+        self.processes.warm(limit).await?;
+        Ok(())
+    }
+
+    /// Expire idle finished processes every `interval`
+    pub fn spawn_sweepers(&self, interval: std::time::Duration) {
+        self.processes.spawn_sweeper(interval);
+    }
+
+    pub fn stats(&self) -> Vec<RegistryStats> {
+        vec![self.processes.stats()]
+    }
+
     pub async fn register_process(&self, process: Process) -> Arc<Process> {
-        let process_arc = Arc::new(process);
-        self.processes.insert(process_arc.process_id.clone(), process_arc.clone());
-        process_arc
+        self.processes.insert(process.process_id, process)
     }
 
     pub async fn allocate_resources(&self, process_id: ProcessId, resources: ProcessResources) {
@@ -78,8 +141,8 @@ impl ProcessRegistry {
         self.resource_allocations.remove(process_id).map(|(_, resources)| resources)
     }
 
-    pub async fn get_process(&self, process_id: &ProcessId) -> Option<Arc<Process>> {
-        self.processes.get(process_id).map(|entry| entry.clone())
+    pub async fn get_process(&self, process_id: &ProcessId) -> Result<Option<Arc<Process>>> {
+        self.processes.get_or_load(process_id).await
     }
 
     pub async fn get_resource_allocation(&self, process_id: &ProcessId) -> Option<ProcessResources> {
@@ -94,37 +157,27 @@ impl ProcessRegistry {
         self.resource_allocations.remove(process_id);
         
         // Remove the process itself
-        self.processes.remove(process_id).map(|(_, process)| process)
+        self.processes.remove(process_id)
     }
 
+    /// Processes currently in memory; evicted (finished) ones are not listed
     pub async fn list_processes(&self) -> Vec<Arc<Process>> {
-        self.processes.iter().map(|entry| entry.value().clone()).collect()
+        self.processes.values()
     }
 
+    /// Active processes are pinned, so the list is complete
     pub async fn list_active_processes(&self) -> Vec<Arc<Process>> {
-        self.processes
-            .iter()
-            .filter(|entry| entry.is_active())
-            .map(|entry| entry.value().clone())
-            .collect()
+        self.processes.filter(Process::is_active)
     }
 
     /// Get processes by type
     pub async fn get_processes_by_type(&self, process_type: ProcessType) -> Vec<Arc<Process>> {
-        self.processes
-            .iter()
-            .filter(|entry| entry.process_type == process_type)
-            .map(|entry| entry.value().clone())
-            .collect()
+        self.processes.filter(|process| process.process_type == process_type)
     }
 
     /// Get processes running on a specific server
     pub async fn get_server_processes(&self, server_id: &he_helix_server::ServerId) -> Vec<Arc<Process>> {
-        self.processes
-            .iter()
-            .filter(|entry| &entry.gateway_id == server_id || &entry.target_id == server_id)
-            .map(|entry| entry.value().clone())
-            .collect()
+        self.processes.filter(|process| &process.gateway_id == server_id || &process.target_id == server_id)
     }
 
     /// Get child processes of a parent process
@@ -132,7 +185,7 @@ impl ProcessRegistry {
         if let Some(children) = self.process_hierarchy.get(parent_id) {
             children
                 .iter()
-                .filter_map(|child_id| self.processes.get(child_id).map(|entry| entry.value().clone()))
+                .filter_map(|child_id| self.processes.get(child_id))
                 .collect()
         } else {
             Vec::new()
@@ -164,9 +217,9 @@ impl ProcessRegistry {
     /// Get resource usage for a specific server
     pub async fn get_server_resource_usage(&self, server_id: &he_helix_server::ServerId) -> ProcessResources {
         self.processes
-            .iter()
-            .filter(|entry| &entry.gateway_id == server_id || &entry.target_id == server_id)
-            .filter_map(|entry| self.resource_allocations.get(&entry.process_id))
+            .filter(|process| &process.gateway_id == server_id || &process.target_id == server_id)
+            .into_iter()
+            .filter_map(|process| self.resource_allocations.get(&process.process_id))
             .fold(ProcessResources::new(), |acc, entry| acc + entry.value().clone())
     }
 }
@@ -175,8 +228,14 @@ impl ProcessRegistry {
 pub async fn init() -> Result<()> {
     tracing::info!("Initializing Helix Process subsystem");
     
-    // Initialize the process registry
-    let _registry = PROCESS_REGISTRY.clone();
+    // Initialize the process registry. Loaders installed before this point
+    // are used to preload hot entries.
+    let registry = PROCESS_REGISTRY.read().await;
+    registry.spawn_sweepers(SWEEP_INTERVAL);
+    if let Err(e) = registry.warm(WARM_LIMIT).await {
+        tracing::warn!("Failed to warm the process registry: {}", e);
+    }
+    drop(registry);
     
     // Start the process scheduler
     scheduler::start_scheduler().await?;
@@ -5,7 +5,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use he_helix_server::ServerId;
 use he_helix_network::{ConnectionId, NetworkId};
-use he_helix_software::FileId;
+use he_helix_software::{FileId, ModuleError, ModuleVersion, Software};
 
 /// Process entity model
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -73,6 +73,25 @@ impl Process {
     pub fn is_failed(&self) -> bool {
         matches!(self.state, ProcessState::Failed)
     }
+
+    /// How effective the process is running on `software` against a defence
+    /// at `opposing`. Processes that need no module run at 1.0.
+    pub fn module_effectiveness(&self, software: &Software, opposing: Option<ModuleVersion>) -> Result<f64, ModuleError> {
+        match self.process_type.required_module() {
+            Some(module) => Ok(software.require_module(module)?.effectiveness(opposing)),
+            None => Ok(1.0),
+        }
+    }
+
+    /// Scale the local dynamic allocation by the module's effectiveness, so
+    /// a damaged module slows the process until the software is repaired
+    pub fn apply_module(&mut self, software: &Software, opposing: Option<ModuleVersion>) -> Result<f64, ModuleError> {
+        let effectiveness = self.module_effectiveness(software, opposing)?;
+        if let Some(dynamic) = self.l_dynamic.as_mut() {
+            dynamic.update_multiplier(effectiveness as f32);
+        }
+        Ok(effectiveness)
+    }
 }
 
 /// Processable type enumeration for different process implementations
@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use he_helix_server::ServerId;
 use he_helix_network::{ConnectionId, NetworkId};
-use he_helix_software::FileId;
+use he_helix_software::{FileId, SoftwareModule};
 
 /// Process unique identifier
 pub type ProcessId = Uuid;
@@ -89,6 +89,21 @@ impl ProcessType {
     pub fn is_bank_operation(&self) -> bool {
         matches!(self, ProcessType::BankRevealPassword | ProcessType::WireTransfer)
     }
+
+    /// The software module the process runs on. Its version sets how
+    /// effective the process is; `None` for processes that need no software.
+    pub fn required_module(&self) -> Option<SoftwareModule> {
+        match self {
+            ProcessType::CrackerBruteforce => Some(SoftwareModule::Bruteforce),
+            ProcessType::CrackerOverflow => Some(SoftwareModule::Overflow),
+            ProcessType::InstallVirus | ProcessType::VirusCollect => Some(SoftwareModule::VirSpyware),
+            ProcessType::LogForger => Some(SoftwareModule::LogEdit),
+            ProcessType::FileUpload
+            | ProcessType::FileDownload
+            | ProcessType::BankRevealPassword
+            | ProcessType::WireTransfer => None,
+        }
+    }
 }
 
 impl std::fmt::Display for ProcessType {
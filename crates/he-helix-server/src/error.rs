@@ -176,6 +176,30 @@ impl From<ComponentError> for MotherboardError {
     }
 }
 
+impl From<HeliuServerError> for he_core::HeError {
+    fn from(err: HeliuServerError) -> Self {
+        use he_core::ErrorCode;
+
+        let code = match &err {
+            HeliuServerError::Server { source: ServerError::NotFound { .. } } |
+            HeliuServerError::Component { source: ComponentError::NotFound { .. } } => ErrorCode::NotFound,
+            HeliuServerError::Server { source: ServerError::InvalidServerType { .. } } |
+            HeliuServerError::Server { source: ServerError::InvalidHostname { .. } } => ErrorCode::Validation,
+            HeliuServerError::Resource { .. } => ErrorCode::InsufficientResources,
+            HeliuServerError::DatabaseConnection { .. } => ErrorCode::Database,
+            HeliuServerError::Configuration { .. } => ErrorCode::Configuration,
+            _ => ErrorCode::Internal,
+        };
+        he_core::HeError::domain("Server", code, err)
+    }
+}
+
+impl From<ServerError> for he_core::HeError {
+    fn from(err: ServerError) -> Self {
+        HeliuServerError::from(err).into()
+    }
+}
+
 /// Result type alias for server operations
 pub type ServerResult<T> = Result<T, ServerError>;
 
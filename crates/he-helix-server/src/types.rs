@@ -32,11 +32,13 @@ pub enum ServerType {
     Npc,
     /// Story mission servers
     Story,
+    /// Server shared by a clan's members
+    Clan,
 }
 
 impl ServerType {
     pub fn possible_types() -> &'static [ServerType] {
-        &[ServerType::Desktop, ServerType::Npc, ServerType::Story]
+        &[ServerType::Desktop, ServerType::Npc, ServerType::Story, ServerType::Clan]
     }
     
     pub fn as_str(&self) -> &'static str {
@@ -44,6 +46,7 @@ impl ServerType {
             ServerType::Desktop => "desktop",
             ServerType::Npc => "npc", 
             ServerType::Story => "story",
+            ServerType::Clan => "clan",
         }
     }
 }
@@ -1,4 +1,20 @@
 //! Error types for the software module
 
-// Placeholder for software error types
-// TODO: Implement comprehensive error handling for software operations
\ No newline at end of file
+use thiserror::Error;
+use crate::types::*;
+
+/// Software module errors
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ModuleError {
+    #[error("Software {software_id} has no {module} module")]
+    Missing { software_id: SoftwareId, module: SoftwareModule },
+
+    #[error("The {module} module is corrupted beyond use and must be repaired")]
+    Broken { module: SoftwareModule },
+
+    #[error("The {module} module does not belong to {software_type} software")]
+    WrongType { software_type: SoftwareType, module: SoftwareModule },
+
+    #[error("Invalid module version: {version}")]
+    InvalidVersion { version: String },
+}
@@ -1,5 +1,6 @@
 //! Software model definitions
 
+use crate::modules::ModuleCapability;
 use crate::types::*;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -12,7 +13,8 @@ pub struct Software {
     pub software_type: SoftwareType,
     pub name: String,
     pub version: String,
-    pub modules: Vec<SoftwareModule>,
+    /// One per module of the software's type, see [`crate::modules`]
+    pub modules: Vec<ModuleCapability>,
     pub size: u64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -1,4 +1,217 @@
-//! Software module management
+//! Software modules
+//!
+//! Software carries one capability per module of its type (a cracker has
+//! bruteforce and overflow, a decryptor one per target), each with its own
+//! version. A process names the module it runs on and reads that module's
+//! version to work out how effective it is, usually against the version of
+//! whatever defends the target.
+//!
+//! Corrupting the software's file damages its modules. A damaged module works
+//! at a fraction of its version until it is repaired, and one at zero
+//! integrity cannot run at all.
 
-// Placeholder for software modules
-// TODO: Implement module loading, capabilities, and execution
\ No newline at end of file
+use crate::error::ModuleError;
+use crate::model::Software;
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+
+/// Module version in tenths, as shown in game: 35 is v3.5
+pub type ModuleVersion = u32;
+
+/// Version a module is rated against when nothing opposes it (v1.0)
+pub const BASELINE_VERSION: ModuleVersion = 10;
+
+/// Integrity of an undamaged module
+pub const FULL_INTEGRITY: u8 = 100;
+
+/// Effectiveness is kept within these bounds so a very weak module still
+/// finishes eventually and a very strong one is not instant
+pub const MIN_EFFECTIVENESS: f64 = 0.1;
+pub const MAX_EFFECTIVENESS: f64 = 10.0;
+
+/// A module of a piece of software and its state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleCapability {
+    pub module: SoftwareModule,
+    pub version: ModuleVersion,
+    /// Percent; [`FULL_INTEGRITY`] when intact
+    pub integrity: u8,
+}
+
+impl ModuleCapability {
+    pub fn new(module: SoftwareModule, version: ModuleVersion) -> Self {
+        Self {
+            module,
+            version,
+            integrity: FULL_INTEGRITY,
+        }
+    }
+
+    /// Every module of `software_type` at `version`
+    pub fn for_software_type(software_type: SoftwareType, version: ModuleVersion) -> Vec<Self> {
+        SoftwareModule::for_software_type(software_type)
+            .into_iter()
+            .map(|module| Self::new(module, version))
+            .collect()
+    }
+
+    pub fn is_damaged(&self) -> bool {
+        self.integrity < FULL_INTEGRITY
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.integrity == 0
+    }
+
+    /// The version the module performs at, after damage
+    pub fn effective_version(&self) -> ModuleVersion {
+        self.version * u32::from(self.integrity.min(FULL_INTEGRITY)) / u32::from(FULL_INTEGRITY)
+    }
+
+    /// How well the module does against `opposing` (e.g. a hasher or
+    /// firewall version), or against [`BASELINE_VERSION`] when nothing
+    /// opposes it. 1.0 is an even match; durations are divided by it.
+    pub fn effectiveness(&self, opposing: Option<ModuleVersion>) -> f64 {
+        effectiveness(self.effective_version(), opposing)
+    }
+
+    /// Lose `damage` percentage points of integrity
+    pub fn corrupt(&mut self, damage: u8) {
+        self.integrity = self.integrity.saturating_sub(damage);
+    }
+
+    pub fn repair(&mut self) {
+        self.integrity = FULL_INTEGRITY;
+    }
+}
+
+/// Effectiveness of a module performing at `version` against `opposing`
+pub fn effectiveness(version: ModuleVersion, opposing: Option<ModuleVersion>) -> f64 {
+    let opposing = opposing.unwrap_or(BASELINE_VERSION).max(1);
+    (f64::from(version) / f64::from(opposing)).clamp(MIN_EFFECTIVENESS, MAX_EFFECTIVENESS)
+}
+
+/// Parse a displayed version such as `3.5` or `2` into tenths
+pub fn parse_version(version: &str) -> Result<ModuleVersion, ModuleError> {
+    let invalid = || ModuleError::InvalidVersion { version: version.to_string() };
+    let (whole, tenths) = match version.trim().split_once('.') {
+        Some((whole, tenths)) if tenths.len() == 1 => (whole, tenths),
+        Some(_) => return Err(invalid()),
+        None => (version.trim(), "0"),
+    };
+    let whole: u32 = whole.parse().map_err(|_| invalid())?;
+    let tenths: u32 = tenths.parse().map_err(|_| invalid())?;
+    whole.checked_mul(10).and_then(|v| v.checked_add(tenths)).ok_or_else(invalid)
+}
+
+/// Format tenths for display, e.g. 35 as `3.5`
+pub fn format_version(version: ModuleVersion) -> String {
+    format!("{}.{}", version / 10, version % 10)
+}
+
+impl Software {
+    pub fn module(&self, module: SoftwareModule) -> Option<&ModuleCapability> {
+        self.modules.iter().find(|capability| capability.module == module)
+    }
+
+    /// The module a process needs, if this software has it and it can run
+    pub fn require_module(&self, module: SoftwareModule) -> Result<&ModuleCapability, ModuleError> {
+        let capability = self.module(module).ok_or(ModuleError::Missing {
+            software_id: self.software_id,
+            module,
+        })?;
+        if capability.is_broken() {
+            return Err(ModuleError::Broken { module });
+        }
+        Ok(capability)
+    }
+
+    /// Add or upgrade a module. Only modules of the software's type fit.
+    pub fn set_module(&mut self, module: SoftwareModule, version: ModuleVersion) -> Result<(), ModuleError> {
+        if !SoftwareModule::for_software_type(self.software_type).contains(&module) {
+            return Err(ModuleError::WrongType {
+                software_type: self.software_type,
+                module,
+            });
+        }
+        match self.modules.iter_mut().find(|capability| capability.module == module) {
+            Some(capability) => capability.version = version,
+            None => self.modules.push(ModuleCapability::new(module, version)),
+        }
+        Ok(())
+    }
+
+    /// Damage every module, as when the software's file is corrupted
+    pub fn corrupt(&mut self, damage: u8) {
+        for capability in &mut self.modules {
+            capability.corrupt(damage);
+        }
+    }
+
+    pub fn repair(&mut self) {
+        for capability in &mut self.modules {
+            capability.repair();
+        }
+    }
+
+    pub fn is_damaged(&self) -> bool {
+        self.modules.iter().any(ModuleCapability::is_damaged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn cracker(version: ModuleVersion) -> Software {
+        let now = Utc::now();
+        Software {
+            software_id: Uuid::new_v4(),
+            software_type: SoftwareType::Cracker,
+            name: "Cracker".to_string(),
+            version: format_version(version),
+            modules: ModuleCapability::for_software_type(SoftwareType::Cracker, version),
+            size: 100,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_effectiveness_against_opposing_version() {
+        let software = cracker(30);
+        let bruteforce = software.require_module(SoftwareModule::Bruteforce).unwrap();
+        assert_eq!(bruteforce.effectiveness(Some(30)), 1.0);
+        assert_eq!(bruteforce.effectiveness(Some(15)), 2.0);
+        assert_eq!(bruteforce.effectiveness(None), 3.0);
+        assert_eq!(bruteforce.effectiveness(Some(1000)), MIN_EFFECTIVENESS);
+
+        assert!(matches!(
+            software.require_module(SoftwareModule::LogEdit),
+            Err(ModuleError::Missing { .. })
+        ));
+        assert_eq!(parse_version("3.5"), Ok(35));
+        assert_eq!(parse_version("2"), Ok(20));
+        assert!(parse_version("1.25").is_err());
+    }
+
+    #[test]
+    fn test_corruption_degrades_until_repaired() {
+        let mut software = cracker(40);
+        software.corrupt(25);
+        assert!(software.is_damaged());
+        assert_eq!(software.require_module(SoftwareModule::Overflow).unwrap().effective_version(), 30);
+
+        software.corrupt(200);
+        assert_eq!(
+            software.require_module(SoftwareModule::Overflow),
+            Err(ModuleError::Broken { module: SoftwareModule::Overflow })
+        );
+
+        software.repair();
+        assert!(!software.is_damaged());
+        assert_eq!(software.require_module(SoftwareModule::Overflow).unwrap().effective_version(), 40);
+    }
+}
@@ -0,0 +1,83 @@
+//! Legacy Account Importer
+//!
+//! Imports the original HackerExperience MySQL database into Postgres.
+//! Reads `LEGACY_DATABASE_URL` (MySQL) and `DATABASE_URL` (Postgres).
+//!
+//! Usage: legacy-import [--dry-run] [--batch-size N] [--max-rows N] [--json]
+//!
+//! Run again after an interruption to resume. Exits with status 2 if the
+//! post-import checks found problems.
+
+use he_legacy_compat::import::{ImportOptions, Importer};
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::postgres::PgPoolOptions;
+use tracing::error;
+use tracing_subscriber::EnvFilter;
+
+fn parse_args() -> Result<(ImportOptions, bool), String> {
+    let mut options = ImportOptions::default();
+    let mut json = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--json" => json = true,
+            "--batch-size" => {
+                options.batch_size = args.next()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or("--batch-size needs a positive number")?;
+            }
+            "--max-rows" => {
+                options.max_rows = Some(args.next()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or("--max-rows needs a number")?);
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+
+    Ok((options, json))
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env().add_directive("info".parse().unwrap()))
+        .init();
+
+    let (options, json) = match parse_args() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\nusage: legacy-import [--dry-run] [--batch-size N] [--max-rows N] [--json]", e);
+            std::process::exit(64);
+        }
+    };
+
+    let legacy_url = std::env::var("LEGACY_DATABASE_URL").expect("LEGACY_DATABASE_URL must be set");
+    let target_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+
+    let legacy = MySqlPoolOptions::new().max_connections(4).connect(&legacy_url).await
+        .expect("Failed to connect to the legacy database");
+    let target = PgPoolOptions::new().max_connections(4).connect(&target_url).await
+        .expect("Failed to connect to the target database");
+
+    let report = match Importer::new(legacy, target, options).run().await {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Legacy import failed: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).expect("report serializes"));
+    } else {
+        print!("{}", report.render());
+    }
+
+    if !report.is_clean() {
+        std::process::exit(2);
+    }
+}
@@ -0,0 +1,127 @@
+//! The game side: accounts, bans and roles in Postgres. Renames go through
+//! the game's own rules, see [`super::GameRenamer`].
+//!
+//! Writes made on behalf of the forum run with `he.sync_origin = 'forum'`, which
+//! keeps the sync triggers from sending them straight back.
+
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use super::phpbb::ForumUser;
+
+/// Roles that make a player a forum moderator
+const MODERATOR_ROLES: &[&str] = &["moderator", "admin"];
+
+#[derive(Debug, Clone, FromRow)]
+pub struct GameAccount {
+    pub id: i64,
+    pub login: String,
+    pub email: String,
+    pub premium: bool,
+    pub moderator: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct GameBan {
+    pub reason: String,
+    pub banned_until: Option<DateTime<Utc>>,
+}
+
+/// Last forum state the bridge saw for a player
+#[derive(Debug, Clone, FromRow)]
+pub struct ForumLink {
+    pub user_id: i64,
+    pub forum_user_id: i64,
+    pub forum_username: String,
+    pub forum_banned: bool,
+}
+
+pub async fn account(pool: &PgPool, user_id: i64) -> sqlx::Result<Option<GameAccount>> {
+    sqlx::query_as::<_, GameAccount>(
+        "SELECT u.id, u.login, u.email, u.premium,
+                EXISTS (
+                    SELECT 1 FROM user_roles ur JOIN roles r ON ur.role_id = r.id
+                    WHERE ur.user_id = u.id AND r.name = ANY($2)
+                ) AS moderator
+         FROM users u WHERE u.id = $1",
+    )
+    .bind(user_id)
+    .bind(MODERATOR_ROLES)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The player's ban, if it is still in effect
+pub async fn ban(pool: &PgPool, user_id: i64) -> sqlx::Result<Option<GameBan>> {
+    sqlx::query_as::<_, GameBan>(
+        "SELECT reason, banned_until FROM user_bans
+         WHERE user_id = $1 AND (banned_until IS NULL OR banned_until > NOW())",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn links(pool: &PgPool, user_ids: &[i64]) -> sqlx::Result<Vec<ForumLink>> {
+    sqlx::query_as::<_, ForumLink>(
+        "SELECT user_id, forum_user_id, forum_username, forum_banned FROM forum_accounts WHERE user_id = ANY($1)",
+    )
+    .bind(user_ids)
+    .fetch_all(pool)
+    .await
+}
+
+/// Remember the forum account as it is now. Accounts pointing at a player
+/// that does not exist are ignored.
+pub async fn save_link(pool: &PgPool, forum: &ForumUser) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO forum_accounts (user_id, forum_user_id, forum_username, forum_banned, synced_at)
+         SELECT $1, $2, $3, $4, NOW() WHERE EXISTS (SELECT 1 FROM users WHERE id = $1)
+         ON CONFLICT (user_id) DO UPDATE SET
+             forum_user_id = EXCLUDED.forum_user_id,
+             forum_username = EXCLUDED.forum_username,
+             forum_banned = EXCLUDED.forum_banned,
+             synced_at = NOW()",
+    )
+    .bind(forum.user_game_id)
+    .bind(forum.user_id)
+    .bind(&forum.username)
+    .bind(forum.banned)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn from_forum(pool: &PgPool) -> sqlx::Result<Transaction<'static, Postgres>> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT set_config('he.sync_origin', 'forum', true)")
+        .execute(&mut *tx)
+        .await?;
+    Ok(tx)
+}
+
+/// Ban or unban the player after the forum did
+pub async fn set_ban(pool: &PgPool, user_id: i64, ban: Option<&GameBan>) -> sqlx::Result<()> {
+    let mut tx = from_forum(pool).await?;
+    match ban {
+        Some(ban) => {
+            sqlx::query(
+                "INSERT INTO user_bans (user_id, reason, banned_until, source) VALUES ($1, $2, $3, 'forum')
+                 ON CONFLICT (user_id) DO UPDATE SET
+                     reason = EXCLUDED.reason, banned_until = EXCLUDED.banned_until,
+                     source = 'forum', created_at = NOW()",
+            )
+            .bind(user_id)
+            .bind(&ban.reason)
+            .bind(ban.banned_until)
+            .execute(&mut *tx)
+            .await?;
+        }
+        None => {
+            sqlx::query("DELETE FROM user_bans WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    tx.commit().await
+}
@@ -0,0 +1,449 @@
+//! phpBB forum bridge
+//!
+//! Keeps forum accounts in step with game accounts, in both directions, over
+//! the `forum_sync_queue` table in the game database:
+//!
+//! - **Registration**: every new player gets a forum account in the registered
+//!   group, linked through `phpbb_users.user_game_id`.
+//! - **Roles**: game moderators and admins are put in the forum's moderator
+//!   group, premium players in the premium group. The game is authoritative;
+//!   group changes made on the forum are not taken over.
+//! - **Names and bans**: a rename or ban on either side is applied to the
+//!   other. A forum rename goes through the game's own name rules and
+//!   cooldown (see [`GameRenamer`]); one the game refuses is reverted to the
+//!   player's game name.
+//!
+//! Game changes are enqueued by triggers (see `20241003_forum_sync.sql`). Forum
+//! changes are found by periodically comparing `phpbb_users` and
+//! `phpbb_banlist` with the state recorded in `forum_accounts`. Messages only
+//! say what changed, and applying one copies the current value across, so
+//! retries and reordering are harmless.
+//!
+//! [`ForumBridge::run_once`] does one round; [`status`] reports how the sync is
+//! doing for monitoring.
+
+pub mod game;
+pub mod phpbb;
+pub mod queue;
+pub mod status;
+
+pub use queue::{Direction, SyncKind};
+pub use status::{status, SyncStatus};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use game::{ForumLink, GameAccount, GameBan};
+use phpbb::{ForumBan, ForumUser};
+use sqlx::{MySqlPool, PgPool};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Messages applied per direction and run
+const BATCH: i64 = 200;
+/// Forum accounts compared per query while scanning
+const SCAN_BATCH: i64 = 500;
+
+#[derive(Debug, Clone)]
+pub struct ForumConfig {
+    /// Group every account is created in
+    pub registered_group_id: i64,
+    /// Group of game moderators and admins
+    pub moderator_group_id: i64,
+    /// Group of premium players; phpBB has none by default
+    pub premium_group_id: Option<i64>,
+    /// How often the forum is checked for renames and bans
+    pub scan_interval: Duration,
+}
+
+impl Default for ForumConfig {
+    fn default() -> Self {
+        Self {
+            // phpBB's REGISTERED and GLOBAL_MODERATORS
+            registered_group_id: 2,
+            moderator_group_id: 4,
+            premium_group_id: None,
+            scan_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ForumConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|value| value.parse::<i64>().ok());
+        Self {
+            registered_group_id: var("FORUM_REGISTERED_GROUP_ID").unwrap_or(defaults.registered_group_id),
+            moderator_group_id: var("FORUM_MODERATOR_GROUP_ID").unwrap_or(defaults.moderator_group_id),
+            premium_group_id: var("FORUM_PREMIUM_GROUP_ID"),
+            scan_interval: var("FORUM_SCAN_INTERVAL_SECONDS")
+                .filter(|seconds| *seconds > 0)
+                .map(|seconds| Duration::from_secs(seconds as u64))
+                .unwrap_or(defaults.scan_interval),
+        }
+    }
+}
+
+/// Applies forum renames to the game under the rules and cooldown of a
+/// rename made in the game
+#[async_trait]
+pub trait GameRenamer: Send + Sync {
+    /// Rename `user_id` to `login` without queueing the change back to the
+    /// forum; `Ok(Err(reason))` if the game refuses the name
+    async fn rename(&self, user_id: i64, login: &str) -> anyhow::Result<Result<(), String>>;
+}
+
+pub struct ForumBridge {
+    game: PgPool,
+    forum: MySqlPool,
+    config: ForumConfig,
+    renamer: Arc<dyn GameRenamer>,
+    last_scan: Mutex<Option<Instant>>,
+}
+
+impl ForumBridge {
+    pub fn new(game: PgPool, forum: MySqlPool, config: ForumConfig, renamer: Arc<dyn GameRenamer>) -> Self {
+        status::update(|status| status.enabled = true);
+        Self {
+            game,
+            forum,
+            config,
+            renamer,
+            last_scan: Mutex::new(None),
+        }
+    }
+
+    /// Apply pending game changes, look for forum changes and apply those
+    pub async fn run_once(&self) -> anyhow::Result<()> {
+        let result = self.sync().await;
+        status::update(|status| match &result {
+            Ok(()) => status.last_success = Some(Utc::now()),
+            Err(e) => status.last_error = Some(e.to_string()),
+        });
+        result
+    }
+
+    async fn sync(&self) -> anyhow::Result<()> {
+        self.process(Direction::Forum).await?;
+
+        let scan_due = match *self.last_scan.lock().unwrap() {
+            Some(at) => at.elapsed() >= self.config.scan_interval,
+            None => true,
+        };
+        if scan_due {
+            self.scan().await?;
+            *self.last_scan.lock().unwrap() = Some(Instant::now());
+            status::update(|status| status.last_scan = Some(Utc::now()));
+        }
+
+        self.process(Direction::Game).await?;
+
+        status::record_counts(&queue::counts(&self.game).await?);
+        Ok(())
+    }
+
+    async fn process(&self, direction: Direction) -> anyhow::Result<()> {
+        for message in queue::claim(&self.game, direction, BATCH).await? {
+            let result = match SyncKind::parse(&message.kind) {
+                Some(kind) => match direction {
+                    Direction::Forum => self.to_forum(message.user_id, kind).await,
+                    Direction::Game => self.to_game(message.user_id, kind).await,
+                },
+                None => Err(anyhow::anyhow!("unknown message kind '{}'", message.kind)),
+            };
+
+            match result {
+                Ok(()) => {
+                    queue::complete(&self.game, &message).await?;
+                    status::update(|status| match direction {
+                        Direction::Forum => status.applied_to_forum += 1,
+                        Direction::Game => status.applied_to_game += 1,
+                    });
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    let dead = queue::fail(&self.game, &message, &error).await?;
+                    warn!(
+                        "Forum sync of {} for user {} to the {} failed (attempt {}{}): {}",
+                        message.kind,
+                        message.user_id,
+                        direction.as_str(),
+                        message.attempts,
+                        if dead { ", giving up" } else { "" },
+                        error
+                    );
+                    status::update(|status| {
+                        status.failures += 1;
+                        status.last_error = Some(error);
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the game's state of `kind` to the forum
+    async fn to_forum(&self, user_id: i64, kind: SyncKind) -> anyhow::Result<()> {
+        // Deleted since; nothing left to sync
+        let Some(account) = game::account(&self.game, user_id).await? else {
+            return Ok(());
+        };
+        let forum = self.ensure_forum_account(&account).await?;
+
+        match kind {
+            SyncKind::Provision => {
+                self.push_roles(&account, &forum).await?;
+                self.push_ban(&account, &forum).await?;
+            }
+            SyncKind::Roles => self.push_roles(&account, &forum).await?,
+            SyncKind::Rename => {
+                if forum.username != account.login {
+                    phpbb::rename(&self.forum, forum.user_id, &account.login).await?;
+                }
+            }
+            SyncKind::Ban => self.push_ban(&account, &forum).await?,
+        }
+
+        self.refresh_link(user_id).await
+    }
+
+    async fn ensure_forum_account(&self, account: &GameAccount) -> anyhow::Result<ForumUser> {
+        if let Some(forum) = phpbb::find_by_game_id(&self.forum, account.id).await? {
+            return Ok(forum);
+        }
+
+        let forum_user_id = phpbb::create_user(
+            &self.forum,
+            account.id,
+            &account.login,
+            &account.email,
+            self.config.registered_group_id,
+        )
+        .await?;
+        info!("Created forum account {} for user {}", forum_user_id, account.id);
+
+        phpbb::find_by_game_id(&self.forum, account.id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("forum account {} disappeared after creation", forum_user_id))
+    }
+
+    async fn push_roles(&self, account: &GameAccount, forum: &ForumUser) -> anyhow::Result<()> {
+        phpbb::set_group_member(&self.forum, forum.user_id, self.config.moderator_group_id, account.moderator).await?;
+        if let Some(premium_group_id) = self.config.premium_group_id {
+            phpbb::set_group_member(&self.forum, forum.user_id, premium_group_id, account.premium).await?;
+        }
+        Ok(())
+    }
+
+    async fn push_ban(&self, account: &GameAccount, forum: &ForumUser) -> anyhow::Result<()> {
+        let wanted = game::ban(&self.game, account.id).await?.map(|ban| ForumBan {
+            reason: ban.reason,
+            until: ban.banned_until,
+        });
+        let current = phpbb::ban(&self.forum, forum.user_id).await?;
+
+        if !same_ban(wanted.as_ref(), current.as_ref()) {
+            phpbb::set_ban(&self.forum, forum.user_id, wanted.as_ref()).await?;
+        }
+        Ok(())
+    }
+
+    /// Copy the forum's state of `kind` to the game
+    async fn to_game(&self, user_id: i64, kind: SyncKind) -> anyhow::Result<()> {
+        let Some(account) = game::account(&self.game, user_id).await? else {
+            return Ok(());
+        };
+        let Some(forum) = phpbb::find_by_game_id(&self.forum, user_id).await? else {
+            return Ok(());
+        };
+
+        match kind {
+            SyncKind::Rename if forum.username != account.login => {
+                match self.renamer.rename(user_id, &forum.username).await? {
+                    Ok(()) => info!("Renamed user {} to {} after a forum rename", user_id, forum.username),
+                    Err(reason) => {
+                        warn!(
+                            "Forum name {} cannot be used in the game ({}), reverting user {} to {}",
+                            forum.username, reason, user_id, account.login
+                        );
+                        queue::enqueue(&self.game, Direction::Forum, user_id, SyncKind::Rename).await?;
+                    }
+                }
+            }
+            SyncKind::Ban => {
+                let wanted = phpbb::ban(&self.forum, forum.user_id).await?;
+                let current = game::ban(&self.game, user_id).await?.map(|ban| ForumBan {
+                    reason: ban.reason,
+                    until: ban.banned_until,
+                });
+                if !same_ban(wanted.as_ref(), current.as_ref()) {
+                    let ban = wanted.map(|ban| GameBan {
+                        reason: ban.reason,
+                        banned_until: ban.until,
+                    });
+                    game::set_ban(&self.game, user_id, ban.as_ref()).await?;
+                    info!("Applied forum {} of user {}", if ban.is_some() { "ban" } else { "unban" }, user_id);
+                }
+            }
+            // The game decides roles, and accounts are only created from the game
+            _ => {}
+        }
+
+        self.refresh_link(user_id).await
+    }
+
+    async fn refresh_link(&self, user_id: i64) -> anyhow::Result<()> {
+        if let Some(forum) = phpbb::find_by_game_id(&self.forum, user_id).await? {
+            game::save_link(&self.game, &forum).await?;
+        }
+        Ok(())
+    }
+
+    /// Compare every linked forum account with what was last seen and queue
+    /// the differences for the game. Accounts linked on the forum before the
+    /// bridge ran are adopted.
+    async fn scan(&self) -> anyhow::Result<()> {
+        let mut after = 0;
+        loop {
+            let page = phpbb::scan(&self.forum, after, SCAN_BATCH).await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = last.user_id;
+
+            let user_ids: Vec<i64> = page.iter().map(|forum| forum.user_game_id).collect();
+            let links: HashMap<i64, ForumLink> = game::links(&self.game, &user_ids)
+                .await?
+                .into_iter()
+                .map(|link| (link.user_id, link))
+                .collect();
+
+            for forum in &page {
+                match links.get(&forum.user_game_id) {
+                    Some(link) => {
+                        for kind in forum_changes(link, forum) {
+                            queue::enqueue(&self.game, Direction::Game, forum.user_game_id, kind).await?;
+                        }
+                    }
+                    None => game::save_link(&self.game, forum).await?,
+                }
+            }
+
+            if (page.len() as i64) < SCAN_BATCH {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What changed on the forum since `link` was recorded
+pub fn forum_changes(link: &ForumLink, forum: &ForumUser) -> Vec<SyncKind> {
+    let mut changes = Vec::new();
+    if link.forum_username != forum.username {
+        changes.push(SyncKind::Rename);
+    }
+    if link.forum_banned != forum.banned {
+        changes.push(SyncKind::Ban);
+    }
+    changes
+}
+
+/// Bans match if both sides agree on whether and until when; phpBB keeps
+/// whole seconds
+fn same_ban(a: Option<&ForumBan>, b: Option<&ForumBan>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => a.until.map(|until| until.timestamp()) == b.until.map(|until| until.timestamp()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forum_user(username: &str, banned: bool) -> ForumUser {
+        ForumUser {
+            user_id: 90,
+            user_game_id: 7,
+            username: username.to_string(),
+            banned,
+        }
+    }
+
+    #[test]
+    fn test_forum_changes() {
+        let link = ForumLink {
+            user_id: 7,
+            forum_user_id: 90,
+            forum_username: "phr0zen".to_string(),
+            forum_banned: false,
+        };
+
+        assert!(forum_changes(&link, &forum_user("phr0zen", false)).is_empty());
+        assert_eq!(forum_changes(&link, &forum_user("frozen", false)), vec![SyncKind::Rename]);
+        assert_eq!(
+            forum_changes(&link, &forum_user("frozen", true)),
+            vec![SyncKind::Rename, SyncKind::Ban]
+        );
+    }
+
+    #[test]
+    fn test_same_ban() {
+        use chrono::TimeZone;
+
+        let until = Utc.with_ymd_and_hms(2024, 10, 3, 12, 0, 0).unwrap();
+        let ban = |reason: &str, until| ForumBan { reason: reason.to_string(), until };
+
+        assert!(same_ban(None, None));
+        assert!(!same_ban(Some(&ban("spam", None)), None));
+        assert!(!same_ban(Some(&ban("spam", None)), Some(&ban("spam", Some(until)))));
+        // Only the reason and sub-second precision differ
+        assert!(same_ban(
+            Some(&ban("spam", Some(until + chrono::Duration::milliseconds(400)))),
+            Some(&ban("", Some(until)))
+        ));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(phpbb::clean_username("  Phr0zen   The Great "), "phr0zen the great");
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        assert_eq!(queue::backoff(1), Duration::from_secs(5));
+        assert_eq!(queue::backoff(2), Duration::from_secs(10));
+        assert_eq!(queue::backoff(queue::MAX_ATTEMPTS), Duration::from_secs(640));
+        assert_eq!(queue::backoff(50), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_health() {
+        let now = Utc::now();
+        let mut status = SyncStatus {
+            enabled: true,
+            last_success: Some(now),
+            ..Default::default()
+        };
+        assert!(status.health(now).0);
+
+        status.oldest_pending = Some(now - chrono::Duration::minutes(20));
+        assert!(!status.health(now).0);
+
+        status.oldest_pending = None;
+        status.dead_letters = 2;
+        assert!(!status.health(now).0);
+
+        status.dead_letters = 0;
+        status.last_success = Some(now - chrono::Duration::minutes(10));
+        status.last_error = Some("Connection refused".to_string());
+        let (healthy, message) = status.health(now);
+        assert!(!healthy);
+        assert!(!message.contains("Connection refused"));
+
+        assert!(SyncStatus::default().health(now).0);
+    }
+}
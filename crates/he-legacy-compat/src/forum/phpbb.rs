@@ -0,0 +1,221 @@
+//! The forum side: phpBB 3 tables in MySQL
+//!
+//! Accounts are tied to players through the `user_game_id` column the original
+//! game added to `phpbb_users`. Writes follow what phpBB's own `user_add`,
+//! `group_user_add` and `user_update_name` do, including the denormalized
+//! poster names in forums and topics.
+
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::{FromRow, MySqlPool};
+
+/// `phpbb_users.user_type` of a regular account
+const USER_NORMAL: i32 = 0;
+
+#[derive(Debug, Clone)]
+pub struct ForumUser {
+    pub user_id: i64,
+    pub user_game_id: i64,
+    pub username: String,
+    pub banned: bool,
+}
+
+/// phpBB's ids are unsigned and MySQL has no booleans; both are cast in SQL
+#[derive(FromRow)]
+struct ForumUserRow {
+    user_id: i64,
+    user_game_id: i64,
+    username: String,
+    banned: i64,
+}
+
+impl From<ForumUserRow> for ForumUser {
+    fn from(row: ForumUserRow) -> Self {
+        Self {
+            user_id: row.user_id,
+            user_game_id: row.user_game_id,
+            username: row.username,
+            banned: row.banned != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForumBan {
+    pub reason: String,
+    /// `None` for a permanent ban
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// phpBB's `utf8_clean_string` for the names we allow: case-folded, with
+/// surrounding and repeated whitespace removed. `username_clean` is unique,
+/// which is what keeps two accounts from sharing a name.
+pub fn clean_username(username: &str) -> String {
+    username.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+const USER_COLUMNS: &str = "CAST(u.user_id AS SIGNED) AS user_id, CAST(u.user_game_id AS SIGNED) AS user_game_id,
+    u.username,
+    CAST(EXISTS (
+        SELECT 1 FROM phpbb_banlist b
+        WHERE b.ban_userid = u.user_id AND b.ban_exclude = 0
+          AND (b.ban_end = 0 OR b.ban_end > UNIX_TIMESTAMP())
+    ) AS SIGNED) AS banned";
+
+pub async fn find_by_game_id(pool: &MySqlPool, game_user_id: i64) -> sqlx::Result<Option<ForumUser>> {
+    let row = sqlx::query_as::<_, ForumUserRow>(&format!(
+        "SELECT {} FROM phpbb_users u WHERE u.user_game_id = ? LIMIT 1",
+        USER_COLUMNS
+    ))
+    .bind(game_user_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(ForumUser::from))
+}
+
+/// Linked accounts with a forum id above `after`, in id order
+pub async fn scan(pool: &MySqlPool, after: i64, limit: i64) -> sqlx::Result<Vec<ForumUser>> {
+    let rows = sqlx::query_as::<_, ForumUserRow>(&format!(
+        "SELECT {} FROM phpbb_users u
+         WHERE u.user_id > ? AND u.user_game_id > 0
+         ORDER BY u.user_id
+         LIMIT ?",
+        USER_COLUMNS
+    ))
+    .bind(after)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(ForumUser::from).collect())
+}
+
+/// Create the account in `group_id` (normally REGISTERED). There is no forum
+/// password; players sign in through the game.
+pub async fn create_user(
+    pool: &MySqlPool,
+    game_user_id: i64,
+    username: &str,
+    email: &str,
+    group_id: i64,
+) -> sqlx::Result<i64> {
+    let mut tx = pool.begin().await?;
+
+    let forum_user_id = sqlx::query(
+        "INSERT INTO phpbb_users
+             (user_type, group_id, username, username_clean, user_email, user_regdate, user_game_id,
+              user_password, user_permissions, user_sig, user_occ, user_interests)
+         VALUES (?, ?, ?, ?, ?, UNIX_TIMESTAMP(), ?, '', '', '', '', '')",
+    )
+    .bind(USER_NORMAL)
+    .bind(group_id)
+    .bind(username)
+    .bind(clean_username(username))
+    .bind(email)
+    .bind(game_user_id)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_id() as i64;
+
+    sqlx::query("INSERT INTO phpbb_user_group (group_id, user_id, user_pending) VALUES (?, ?, 0)")
+        .bind(group_id)
+        .bind(forum_user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(forum_user_id)
+}
+
+/// Add to or remove from a group. Cached permissions are cleared so phpBB
+/// rebuilds them on the next page view.
+pub async fn set_group_member(pool: &MySqlPool, forum_user_id: i64, group_id: i64, member: bool) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let changed = if member {
+        sqlx::query("INSERT IGNORE INTO phpbb_user_group (group_id, user_id, user_pending) VALUES (?, ?, 0)")
+    } else {
+        sqlx::query("DELETE FROM phpbb_user_group WHERE group_id = ? AND user_id = ?")
+    }
+    .bind(group_id)
+    .bind(forum_user_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if changed > 0 {
+        sqlx::query("UPDATE phpbb_users SET user_permissions = '', user_perm_from = 0 WHERE user_id = ?")
+            .bind(forum_user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Rename, including the poster names phpBB keeps on forums and topics
+pub async fn rename(pool: &MySqlPool, forum_user_id: i64, username: &str) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE phpbb_users SET username = ?, username_clean = ? WHERE user_id = ?")
+        .bind(username)
+        .bind(clean_username(username))
+        .bind(forum_user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for statement in [
+        "UPDATE phpbb_forums SET forum_last_poster_name = ? WHERE forum_last_poster_id = ?",
+        "UPDATE phpbb_topics SET topic_first_poster_name = ? WHERE topic_poster = ?",
+        "UPDATE phpbb_topics SET topic_last_poster_name = ? WHERE topic_last_poster_id = ?",
+        "UPDATE phpbb_moderator_cache SET username = ? WHERE user_id = ?",
+    ] {
+        sqlx::query(statement)
+            .bind(username)
+            .bind(forum_user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await
+}
+
+pub async fn ban(pool: &MySqlPool, forum_user_id: i64) -> sqlx::Result<Option<ForumBan>> {
+    let row: Option<(String, i64)> = sqlx::query_as(
+        "SELECT ban_give_reason, CAST(ban_end AS SIGNED) FROM phpbb_banlist
+         WHERE ban_userid = ? AND ban_exclude = 0 AND (ban_end = 0 OR ban_end > UNIX_TIMESTAMP())
+         ORDER BY ban_end = 0 DESC, ban_end DESC
+         LIMIT 1",
+    )
+    .bind(forum_user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|(reason, end)| ForumBan {
+        reason,
+        until: (end > 0).then(|| Utc.timestamp_opt(end, 0).single()).flatten(),
+    }))
+}
+
+/// Replace the account's ban, or lift it with `None`
+pub async fn set_ban(pool: &MySqlPool, forum_user_id: i64, ban: Option<&ForumBan>) -> sqlx::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM phpbb_banlist WHERE ban_userid = ? AND ban_exclude = 0")
+        .bind(forum_user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    if let Some(ban) = ban {
+        sqlx::query(
+            "INSERT INTO phpbb_banlist
+                 (ban_userid, ban_ip, ban_email, ban_start, ban_end, ban_exclude, ban_reason, ban_give_reason)
+             VALUES (?, '', '', UNIX_TIMESTAMP(), ?, 0, 'Synchronized from the game', ?)",
+        )
+        .bind(forum_user_id)
+        .bind(ban.until.map(|until| until.timestamp()).unwrap_or(0))
+        .bind(&ban.reason)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
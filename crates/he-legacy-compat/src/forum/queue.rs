@@ -0,0 +1,183 @@
+//! The `forum_sync_queue` table
+//!
+//! Messages are claimed with `SKIP LOCKED` and leased, so several bridges can
+//! share the queue. A message that fails is retried with backoff and marked
+//! dead after [`MAX_ATTEMPTS`]. One that changed again while it was being
+//! applied (`dirty`) is re-armed instead of deleted when it completes.
+
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+use std::time::Duration;
+
+/// Attempts before a message is given up on
+pub const MAX_ATTEMPTS: i32 = 8;
+
+/// How long a claimed message is held before another bridge may retry it
+const LEASE: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Game change, applied to the forum
+    Forum,
+    /// Forum change, applied to the game
+    Game,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Forum => "forum",
+            Direction::Game => "game",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncKind {
+    /// The player needs a forum account
+    Provision,
+    /// Moderator or premium status changed
+    Roles,
+    Rename,
+    /// Banned or unbanned
+    Ban,
+}
+
+impl SyncKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncKind::Provision => "provision",
+            SyncKind::Roles => "roles",
+            SyncKind::Rename => "rename",
+            SyncKind::Ban => "ban",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "provision" => Some(SyncKind::Provision),
+            "roles" => Some(SyncKind::Roles),
+            "rename" => Some(SyncKind::Rename),
+            "ban" => Some(SyncKind::Ban),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct QueuedMessage {
+    pub id: i64,
+    pub user_id: i64,
+    pub kind: String,
+    pub attempts: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueueCounts {
+    pub pending_to_forum: i64,
+    pub pending_to_game: i64,
+    pub dead_letters: i64,
+    pub oldest_pending: Option<DateTime<Utc>>,
+}
+
+/// Delay before retrying a message that failed `attempts` times
+pub fn backoff(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(1, 10) as u32 - 1;
+    Duration::from_secs(5 * 2u64.pow(exponent)).min(Duration::from_secs(3600))
+}
+
+/// Queue `kind` for `user_id`, or mark an already pending message dirty
+pub async fn enqueue(pool: &PgPool, direction: Direction, user_id: i64, kind: SyncKind) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO forum_sync_queue (direction, user_id, kind) VALUES ($1, $2, $3)
+         ON CONFLICT (direction, user_id, kind) WHERE NOT dead
+         DO UPDATE SET dirty = TRUE",
+    )
+    .bind(direction.as_str())
+    .bind(user_id)
+    .bind(kind.as_str())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Lease up to `limit` due messages for `direction`
+pub async fn claim(pool: &PgPool, direction: Direction, limit: i64) -> sqlx::Result<Vec<QueuedMessage>> {
+    sqlx::query_as::<_, QueuedMessage>(
+        "UPDATE forum_sync_queue
+         SET attempts = attempts + 1, dirty = FALSE, available_at = NOW() + make_interval(secs => $3)
+         WHERE id IN (
+             SELECT id FROM forum_sync_queue
+             WHERE direction = $1 AND NOT dead AND available_at <= NOW()
+             ORDER BY available_at
+             LIMIT $2
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING id, user_id, kind, attempts",
+    )
+    .bind(direction.as_str())
+    .bind(limit)
+    .bind(LEASE.as_secs_f64())
+    .fetch_all(pool)
+    .await
+}
+
+/// The message was applied
+pub async fn complete(pool: &PgPool, message: &QueuedMessage) -> sqlx::Result<()> {
+    let deleted = sqlx::query("DELETE FROM forum_sync_queue WHERE id = $1 AND NOT dirty")
+        .bind(message.id)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    if deleted == 0 {
+        // Changed again meanwhile; apply once more with the newer state
+        sqlx::query(
+            "UPDATE forum_sync_queue
+             SET dirty = FALSE, attempts = 0, available_at = NOW(), last_error = NULL, created_at = NOW()
+             WHERE id = $1",
+        )
+        .bind(message.id)
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// The message could not be applied; retry later or give up on it
+pub async fn fail(pool: &PgPool, message: &QueuedMessage, error: &str) -> sqlx::Result<bool> {
+    let dead = message.attempts >= MAX_ATTEMPTS;
+    sqlx::query(
+        "UPDATE forum_sync_queue
+         SET last_error = $2, dead = $3, available_at = NOW() + make_interval(secs => $4)
+         WHERE id = $1",
+    )
+    .bind(message.id)
+    .bind(error)
+    .bind(dead)
+    .bind(backoff(message.attempts).as_secs_f64())
+    .execute(pool)
+    .await?;
+    Ok(dead)
+}
+
+pub async fn counts(pool: &PgPool) -> sqlx::Result<QueueCounts> {
+    let (pending_to_forum, pending_to_game, dead_letters, oldest_pending): (i64, i64, i64, Option<DateTime<Utc>>) =
+        sqlx::query_as(
+            "SELECT
+                 COUNT(*) FILTER (WHERE direction = 'forum' AND NOT dead),
+                 COUNT(*) FILTER (WHERE direction = 'game' AND NOT dead),
+                 COUNT(*) FILTER (WHERE dead),
+                 MIN(created_at) FILTER (WHERE NOT dead)
+             FROM forum_sync_queue",
+        )
+        .fetch_one(pool)
+        .await?;
+
+    Ok(QueueCounts {
+        pending_to_forum,
+        pending_to_game,
+        dead_letters,
+        oldest_pending,
+    })
+}
@@ -0,0 +1,93 @@
+//! Health of the forum sync, for monitoring
+//!
+//! The bridge updates a process-wide snapshot after every run; `/health` and
+//! the Prometheus metrics read it from there.
+
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::sync::RwLock;
+use super::queue::QueueCounts;
+
+/// Without a successful run for this long the sync counts as down
+const STALE_AFTER: Duration = Duration::minutes(5);
+/// A change waiting longer than this counts as the sync falling behind
+const MAX_LAG: Duration = Duration::minutes(15);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStatus {
+    /// A bridge runs in this process
+    pub enabled: bool,
+    pub pending_to_forum: i64,
+    pub pending_to_game: i64,
+    /// Messages given up on, waiting for an operator
+    pub dead_letters: i64,
+    pub oldest_pending: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_scan: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// Messages applied and failed since start, per direction
+    pub applied_to_forum: u64,
+    pub applied_to_game: u64,
+    pub failures: u64,
+}
+
+impl SyncStatus {
+    /// Seconds the oldest pending change has waited
+    pub fn lag_seconds(&self, now: DateTime<Utc>) -> f64 {
+        self.oldest_pending
+            .map(|oldest| (now - oldest).num_milliseconds().max(0) as f64 / 1000.0)
+            .unwrap_or(0.0)
+    }
+
+    /// Whether the sync is keeping up, and why not
+    pub fn health(&self, now: DateTime<Utc>) -> (bool, String) {
+        if !self.enabled {
+            return (true, "Forum sync not configured".to_string());
+        }
+
+        match self.last_success {
+            None => return (false, "Forum sync has not completed a run yet".to_string()),
+            // The error itself stays out of the public health report
+            Some(at) if now - at > STALE_AFTER => {
+                return (false, format!("Forum sync last succeeded at {}", at.to_rfc3339()));
+            }
+            Some(_) => {}
+        }
+
+        if self.dead_letters > 0 {
+            return (false, format!("{} forum sync messages failed permanently", self.dead_letters));
+        }
+        if self.lag_seconds(now) > MAX_LAG.num_seconds() as f64 {
+            return (false, format!("Forum sync is {:.0}s behind", self.lag_seconds(now)));
+        }
+
+        (
+            true,
+            format!(
+                "{} changes pending for the forum, {} for the game",
+                self.pending_to_forum, self.pending_to_game
+            ),
+        )
+    }
+}
+
+static STATUS: Lazy<RwLock<SyncStatus>> = Lazy::new(Default::default);
+
+/// Current snapshot
+pub fn status() -> SyncStatus {
+    STATUS.read().unwrap().clone()
+}
+
+pub(super) fn update(apply: impl FnOnce(&mut SyncStatus)) {
+    apply(&mut STATUS.write().unwrap());
+}
+
+pub(super) fn record_counts(counts: &QueueCounts) {
+    update(|status| {
+        status.pending_to_forum = counts.pending_to_forum;
+        status.pending_to_game = counts.pending_to_game;
+        status.dead_letters = counts.dead_letters;
+        status.oldest_pending = counts.oldest_pending;
+    });
+}
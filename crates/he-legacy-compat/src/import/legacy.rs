@@ -0,0 +1,141 @@
+//! Rows of the original MySQL schema (see `migrations/`), read in id order
+
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, MySqlPool};
+
+#[derive(Debug, Clone, FromRow)]
+pub struct LegacyUser {
+    pub id: i64,
+    pub login: String,
+    pub password: String,
+    pub email: String,
+    pub game_pass: String,
+    pub game_ip: u64,
+    pub real_ip: u64,
+    pub home_ip: u64,
+    pub learning: bool,
+    pub premium: bool,
+    pub last_login: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One hardware row; a player's rows together make up their machine
+#[derive(Debug, Clone, FromRow)]
+pub struct LegacyHardware {
+    pub server_id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub cpu: f32,
+    pub hdd: f32,
+    pub ram: f32,
+    pub net: f32,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct LegacySoftware {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub software_type: String,
+    pub version: String,
+    pub size: i32,
+    pub location: String,
+    pub installed_at: Option<DateTime<Utc>>,
+    pub is_running: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct LegacyClan {
+    pub id: i64,
+    pub name: String,
+    pub leader_id: i64,
+    pub description: Option<String>,
+    pub tag: Option<String>,
+    pub reputation: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct LegacyClanMember {
+    pub id: i64,
+    pub clan_id: i64,
+    pub user_id: i64,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+
+pub async fn users_after(pool: &MySqlPool, after: i64, limit: i64) -> anyhow::Result<Vec<LegacyUser>> {
+    Ok(sqlx::query_as::<_, LegacyUser>(
+        "SELECT id, login, password, email, game_pass, game_ip, real_ip, home_ip, learning, premium, \
+         last_login, created_at FROM users WHERE id > ? ORDER BY id LIMIT ?"
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Player (non-NPC) hardware of the given users
+pub async fn hardware_of(pool: &MySqlPool, user_ids: &[i64]) -> anyhow::Result<Vec<LegacyHardware>> {
+    if user_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let placeholders = vec!["?"; user_ids.len()].join(", ");
+    let sql = format!(
+        "SELECT server_id, user_id, name, cpu, hdd, ram, net FROM hardware \
+         WHERE is_npc = 0 AND user_id IN ({}) ORDER BY server_id",
+        placeholders
+    );
+
+    let mut query = sqlx::query_as::<_, LegacyHardware>(&sql);
+    for id in user_ids {
+        query = query.bind(id);
+    }
+    Ok(query.fetch_all(pool).await?)
+}
+
+pub async fn software_after(pool: &MySqlPool, after: i64, limit: i64) -> anyhow::Result<Vec<LegacySoftware>> {
+    Ok(sqlx::query_as::<_, LegacySoftware>(
+        "SELECT id, user_id, name, software_type, version, size, location, installed_at, is_running, \
+         created_at FROM software WHERE id > ? ORDER BY id LIMIT ?"
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn clans_after(pool: &MySqlPool, after: i64, limit: i64) -> anyhow::Result<Vec<LegacyClan>> {
+    Ok(sqlx::query_as::<_, LegacyClan>(
+        "SELECT id, name, leader_id, description, tag, reputation, created_at \
+         FROM clans WHERE id > ? ORDER BY id LIMIT ?"
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn clan_members_after(pool: &MySqlPool, after: i64, limit: i64) -> anyhow::Result<Vec<LegacyClanMember>> {
+    Ok(sqlx::query_as::<_, LegacyClanMember>(
+        "SELECT id, clan_id, user_id, role, joined_at FROM clan_members WHERE id > ? ORDER BY id LIMIT ?"
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Row count of a legacy table, for verification
+pub async fn count(pool: &MySqlPool, table: &str) -> anyhow::Result<i64> {
+    let sql = match table {
+        "users" => "SELECT COUNT(*) FROM users",
+        "software" => "SELECT COUNT(*) FROM software",
+        "clans" => "SELECT COUNT(*) FROM clans",
+        "clan_members" => "SELECT COUNT(*) FROM clan_members",
+        other => anyhow::bail!("no legacy table '{}'", other),
+    };
+    Ok(sqlx::query_scalar::<_, i64>(sql).fetch_one(pool).await?)
+}
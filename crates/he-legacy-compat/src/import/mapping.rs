@@ -0,0 +1,302 @@
+//! Pure conversions from legacy rows to the new Postgres models
+//!
+//! Each conversion returns the new row plus a list of notes describing anything
+//! that was not copied verbatim, which end up in the dry-run diff.
+
+use super::legacy::{LegacyClan, LegacyHardware, LegacySoftware, LegacyUser};
+use std::net::Ipv4Addr;
+
+/// Software types known to the new game
+const SOFTWARE_TYPES: &[&str] = &[
+    "cracker", "hasher", "firewall", "antivirus", "spam", "warez", "bitcoin_miner",
+];
+
+/// Dotted form of an `INET_ATON` value
+pub fn ip_to_string(ip: u64) -> Option<String> {
+    u32::try_from(ip).ok().map(|ip| Ipv4Addr::from(ip).to_string())
+}
+
+#[derive(Debug, Clone)]
+pub struct UserRow {
+    pub login: String,
+    pub password: String,
+    pub email: String,
+    pub game_pass: String,
+    pub game_ip: i64,
+    pub real_ip: i64,
+    pub home_ip: i64,
+    pub learning: bool,
+    pub premium: bool,
+}
+
+pub fn map_user(user: &LegacyUser) -> Result<(UserRow, Vec<String>), String> {
+    let mut notes = Vec::new();
+
+    for (field, ip) in [("game_ip", user.game_ip), ("real_ip", user.real_ip), ("home_ip", user.home_ip)] {
+        if ip_to_string(ip).is_none() {
+            return Err(format!("{} {} is not an IPv4 address", field, ip));
+        }
+    }
+
+    let email = user.email.trim().to_lowercase();
+    if email != user.email {
+        notes.push(format!("email normalised to '{}'", email));
+    }
+
+    Ok((
+        UserRow {
+            login: user.login.clone(),
+            password: user.password.clone(),
+            email,
+            game_pass: user.game_pass.clone(),
+            game_ip: user.game_ip as i64,
+            real_ip: user.real_ip as i64,
+            home_ip: user.home_ip as i64,
+            learning: user.learning,
+            premium: user.premium,
+        },
+        notes,
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerRow {
+    pub ip_address: String,
+    pub hostname: Option<String>,
+    pub cpu_total: i32,
+    pub ram_total: i32,
+    pub hdd_total: i32,
+    pub net_total: i32,
+}
+
+/// A player's legacy hardware rows become a single server at their game IP:
+/// capacities add up, except the network link, where the fastest one counts.
+/// Players without hardware get a server with the default capacities.
+pub fn map_server(user: &LegacyUser, hardware: &[LegacyHardware]) -> Result<(ServerRow, Vec<String>), String> {
+    let ip_address = ip_to_string(user.game_ip)
+        .ok_or_else(|| format!("game_ip {} is not an IPv4 address", user.game_ip))?;
+
+    if hardware.is_empty() {
+        return Ok((
+            ServerRow {
+                ip_address,
+                hostname: None,
+                cpu_total: 500,
+                ram_total: 256,
+                hdd_total: 10000,
+                net_total: 100,
+            },
+            vec!["no hardware, default server created".to_string()],
+        ));
+    }
+
+    let mut notes = Vec::new();
+    if hardware.len() > 1 {
+        notes.push(format!("{} hardware rows merged into one server", hardware.len()));
+    }
+
+    let sum = |f: fn(&LegacyHardware) -> f32| -> i32 {
+        hardware.iter().map(f).sum::<f32>().round().min(i32::MAX as f32) as i32
+    };
+    let net = hardware.iter().map(|h| h.net).fold(0.0, f32::max).round() as i32;
+
+    Ok((
+        ServerRow {
+            ip_address,
+            hostname: Some(hardware[0].name.clone()).filter(|n| !n.trim().is_empty()),
+            cpu_total: sum(|h| h.cpu),
+            ram_total: sum(|h| h.ram),
+            hdd_total: sum(|h| h.hdd),
+            net_total: net,
+        },
+        notes,
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoftwareRow {
+    pub name: String,
+    pub software_type: String,
+    pub version: f64,
+    pub size: i32,
+    pub is_installed: bool,
+    pub is_running: bool,
+}
+
+/// `owner_ip` is the dotted game IP of the software's owner. Software sitting on
+/// any other machine (an NPC or another player) is not imported.
+pub fn map_software(software: &LegacySoftware, owner_ip: &str) -> Result<(SoftwareRow, Vec<String>), String> {
+    let location = software.location.trim();
+    if !location.is_empty() && location != owner_ip {
+        return Err(format!("stored on another server ({})", location));
+    }
+
+    let mut notes = Vec::new();
+
+    let normalised = software.software_type.trim().to_lowercase().replace([' ', '-'], "_");
+    let software_type = match normalised.as_str() {
+        "miner" | "bitcoin" | "btc_miner" => "bitcoin_miner".to_string(),
+        "av" => "antivirus".to_string(),
+        "crc" | "crack" => "cracker".to_string(),
+        "hash" => "hasher".to_string(),
+        "fwl" => "firewall".to_string(),
+        other => other.to_string(),
+    };
+    if !SOFTWARE_TYPES.contains(&software_type.as_str()) {
+        notes.push(format!("unknown software type '{}' kept as is", software.software_type));
+    } else if software_type != software.software_type {
+        notes.push(format!("type '{}' -> '{}'", software.software_type, software_type));
+    }
+
+    let version = match software.version.trim().parse::<f64>() {
+        Ok(v) if v.is_finite() && v > 0.0 => v,
+        _ => {
+            notes.push(format!("unreadable version '{}' set to 1.0", software.version));
+            1.0
+        }
+    };
+
+    Ok((
+        SoftwareRow {
+            name: software.name.clone(),
+            software_type,
+            version,
+            size: software.size.max(0),
+            is_installed: software.installed_at.is_some(),
+            is_running: software.is_running,
+        },
+        notes,
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClanRow {
+    pub name: String,
+    pub tag: String,
+    pub description: Option<String>,
+    pub reputation: i32,
+}
+
+/// Clan tags are optional in the legacy schema but required (and unique) now;
+/// missing ones are derived from the clan name.
+pub fn map_clan(clan: &LegacyClan) -> Result<(ClanRow, Vec<String>), String> {
+    let mut notes = Vec::new();
+
+    let tag = match clan.tag.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        Some(tag) => tag.to_string(),
+        None => {
+            let derived: String = clan.name
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .take(5)
+                .collect::<String>()
+                .to_uppercase();
+            if derived.is_empty() {
+                return Err("no tag and none can be derived from the name".to_string());
+            }
+            notes.push(format!("tag derived as '{}'", derived));
+            derived
+        }
+    };
+
+    let reputation = i32::try_from(clan.reputation).unwrap_or_else(|_| {
+        notes.push(format!("reputation {} clamped", clan.reputation));
+        if clan.reputation < 0 { i32::MIN } else { i32::MAX }
+    });
+
+    Ok((
+        ClanRow {
+            name: clan.name.clone(),
+            tag,
+            description: clan.description.clone(),
+            reputation,
+        },
+        notes,
+    ))
+}
+
+/// Legacy roles are member/admin/leader; admins are officers now
+pub fn map_clan_role(role: &str) -> &'static str {
+    match role.trim().to_lowercase().as_str() {
+        "leader" => "leader",
+        "admin" | "officer" => "officer",
+        _ => "member",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn user(game_ip: u64) -> LegacyUser {
+        LegacyUser {
+            id: 7,
+            login: "phr0zen".to_string(),
+            password: "$2y$10$abcdefghijklmnopqrstuv".to_string(),
+            email: "Phr0zen@Example.com ".to_string(),
+            game_pass: "a1b2c3d4".to_string(),
+            game_ip,
+            real_ip: 2130706433,
+            home_ip: 2130706433,
+            learning: false,
+            premium: true,
+            last_login: Utc::now(),
+            created_at: Utc::now(),
+        }
+    }
+
+    fn hardware(cpu: f32, net: f32) -> LegacyHardware {
+        LegacyHardware {
+            server_id: 1,
+            user_id: 7,
+            name: "Server #1".to_string(),
+            cpu,
+            hdd: 100.0,
+            ram: 256.0,
+            net,
+        }
+    }
+
+    #[test]
+    fn test_user_mapping() {
+        let (row, notes) = map_user(&user(3232235777)).unwrap();
+        assert_eq!(row.email, "phr0zen@example.com");
+        assert_eq!(row.game_ip, 3232235777);
+        assert_eq!(notes.len(), 1);
+
+        assert!(map_user(&user(u64::from(u32::MAX) + 1)).is_err());
+    }
+
+    #[test]
+    fn test_hardware_merged_into_one_server() {
+        let (server, notes) = map_server(&user(3232235777), &[hardware(500.0, 1.0), hardware(1000.0, 10.0)]).unwrap();
+        assert_eq!(server.ip_address, "192.168.1.1");
+        assert_eq!(server.cpu_total, 1500);
+        assert_eq!(server.ram_total, 512);
+        assert_eq!(server.net_total, 10);
+        assert_eq!(notes.len(), 1);
+
+        let (server, _) = map_server(&user(3232235777), &[]).unwrap();
+        assert_eq!(server.cpu_total, 500);
+    }
+
+    #[test]
+    fn test_clan_tag_and_role() {
+        let clan = LegacyClan {
+            id: 1,
+            name: "The l33t crew".to_string(),
+            leader_id: 7,
+            description: None,
+            tag: None,
+            reputation: 10,
+            created_at: Utc::now(),
+        };
+        let (row, _) = map_clan(&clan).unwrap();
+        assert_eq!(row.tag, "THEL3");
+
+        assert_eq!(map_clan_role("admin"), "officer");
+        assert_eq!(map_clan_role("Leader"), "leader");
+        assert_eq!(map_clan_role("whatever"), "member");
+    }
+}
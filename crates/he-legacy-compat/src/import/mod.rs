@@ -0,0 +1,533 @@
+//! Bulk import from the original HackerExperience MySQL database
+//!
+//! Copies users, their hardware, software and clans from the legacy schema
+//! (`migrations/`) into the Postgres models (`migrations-postgres/`), so veterans
+//! get their accounts back. Tables are imported in dependency order, each in
+//! keyset-paginated batches:
+//!
+//! - Legacy ids are kept when they are free in the target, otherwise the row gets
+//!   a new id. Either way `legacy_id_map` records it, and references in later
+//!   tables are rewritten through it.
+//! - Rows that cannot be imported (login already registered, software sitting on
+//!   an NPC, ...) are recorded in `legacy_import_skips` with the reason.
+//! - Every batch commits together with its checkpoint, so an interrupted import
+//!   resumes at the next batch when run again.
+//! - Afterwards the invariants in [`verify`] are checked.
+//!
+//! A dry run performs the whole import in one transaction that is rolled back,
+//! and reports what would have changed. Use `max_rows` to sample large datasets.
+//!
+//! Passwords are copied as the legacy bcrypt hashes.
+
+pub mod legacy;
+pub mod mapping;
+pub mod report;
+pub mod verify;
+
+pub use report::{ImportReport, RowAction, RowDiff, TableReport, Violation};
+
+use mapping::{map_clan, map_clan_role, map_server, map_software, map_user};
+use sqlx::{Acquire, MySqlPool, PgConnection, PgPool};
+use std::collections::HashMap;
+use tracing::info;
+
+pub const USER: &str = "user";
+pub const SERVER: &str = "server";
+pub const SOFTWARE: &str = "software";
+pub const CLAN: &str = "clan";
+pub const CLAN_MEMBER: &str = "clan_member";
+
+/// Import order; each entity only references the ones before it
+const STEPS: &[&str] = &[USER, SERVER, SOFTWARE, CLAN, CLAN_MEMBER];
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Roll everything back and only report
+    pub dry_run: bool,
+    /// Legacy rows read per batch
+    pub batch_size: i64,
+    /// Stop each table after this many rows
+    pub max_rows: Option<u64>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            batch_size: 1000,
+            max_rows: None,
+        }
+    }
+}
+
+pub struct Importer {
+    legacy: MySqlPool,
+    target: PgPool,
+    options: ImportOptions,
+}
+
+impl Importer {
+    pub fn new(legacy: MySqlPool, target: PgPool, options: ImportOptions) -> Self {
+        Self { legacy, target, options }
+    }
+
+    pub async fn run(&self) -> anyhow::Result<ImportReport> {
+        let mut report = ImportReport {
+            dry_run: self.options.dry_run,
+            ..Default::default()
+        };
+
+        let mut dry = match self.options.dry_run {
+            true => Some(self.target.begin().await?),
+            false => None,
+        };
+
+        let mut complete = true;
+        for &entity in STEPS {
+            complete &= self.import_entity(entity, dry.as_mut(), &mut report).await?;
+        }
+
+        match dry.as_mut() {
+            Some(outer) => {
+                finalize(outer).await?;
+                report.violations = verify::run(&self.legacy, outer, complete).await?;
+            }
+            None => {
+                let mut tx = self.target.begin().await?;
+                finalize(&mut tx).await?;
+                tx.commit().await?;
+
+                let mut conn = self.target.acquire().await?;
+                report.violations = verify::run(&self.legacy, &mut conn, complete).await?;
+            }
+        }
+
+        if let Some(outer) = dry {
+            outer.rollback().await?;
+        }
+
+        Ok(report)
+    }
+
+    /// Import one entity batch by batch. Returns false if `max_rows` cut it short.
+    async fn import_entity(
+        &self,
+        entity: &'static str,
+        mut dry: Option<&mut sqlx::Transaction<'static, sqlx::Postgres>>,
+        report: &mut ImportReport,
+    ) -> anyhow::Result<bool> {
+        let mut read = 0u64;
+
+        loop {
+            // In a dry run each batch is a savepoint inside the outer transaction
+            let mut tx = match dry.as_mut() {
+                Some(outer) => (&mut **outer).begin().await?,
+                None => self.target.begin().await?,
+            };
+
+            let (after, completed) = checkpoint(&mut tx, entity).await?;
+            if completed {
+                return Ok(true);
+            }
+            if self.options.max_rows.is_some_and(|max| read >= max) {
+                return Ok(false);
+            }
+
+            let table = report.table(entity);
+            let last = match entity {
+                USER => self.users_batch(&mut tx, after, table).await?,
+                SERVER => self.servers_batch(&mut tx, after, table).await?,
+                SOFTWARE => self.software_batch(&mut tx, after, table).await?,
+                CLAN => self.clans_batch(&mut tx, after, table).await?,
+                _ => self.clan_members_batch(&mut tx, after, table).await?,
+            };
+
+            match last {
+                Some((last_id, n)) => {
+                    save_checkpoint(&mut tx, entity, last_id, n, false).await?;
+                    tx.commit().await?;
+                    read += n;
+                    info!("Legacy import: {} {} rows (up to legacy id {})", read, entity, last_id);
+                }
+                None => {
+                    save_checkpoint(&mut tx, entity, after, 0, true).await?;
+                    tx.commit().await?;
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    async fn users_batch(&self, conn: &mut PgConnection, after: i64, table: &mut TableReport) -> anyhow::Result<Option<(i64, u64)>> {
+        let users = legacy::users_after(&self.legacy, after, self.options.batch_size).await?;
+
+        for user in &users {
+            let (row, notes) = match map_user(user) {
+                Ok(mapped) => mapped,
+                Err(reason) => {
+                    skip(conn, table, USER, user.id, reason).await?;
+                    continue;
+                }
+            };
+
+            let conflict: bool = sqlx::query_scalar(
+                "SELECT EXISTS(SELECT 1 FROM users WHERE login = $1 OR lower(email) = $2)"
+            )
+            .bind(&row.login)
+            .bind(&row.email)
+            .fetch_one(&mut *conn)
+            .await?;
+            if conflict {
+                skip(conn, table, USER, user.id, "login or email already registered".to_string()).await?;
+                continue;
+            }
+
+            let keep_id = !id_taken(conn, "users", user.id).await?;
+            let new_id: i64 = sqlx::query_scalar(
+                "INSERT INTO users (id, login, password, email, game_pass, game_ip, real_ip, home_ip, \
+                 learning, premium, last_login, created_at) \
+                 VALUES (COALESCE($1, nextval(pg_get_serial_sequence('users', 'id'))), \
+                 $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id"
+            )
+            .bind(keep_id.then_some(user.id))
+            .bind(&row.login)
+            .bind(&row.password)
+            .bind(&row.email)
+            .bind(&row.game_pass)
+            .bind(row.game_ip)
+            .bind(row.real_ip)
+            .bind(row.home_ip)
+            .bind(row.learning)
+            .bind(row.premium)
+            .bind(user.last_login)
+            .bind(user.created_at)
+            .fetch_one(&mut *conn)
+            .await?;
+
+            map_id(conn, USER, user.id, new_id).await?;
+            table.record(user.id, id_action(keep_id, new_id), notes);
+        }
+
+        Ok(users.last().map(|u| (u.id, users.len() as u64)))
+    }
+
+    /// Batches over legacy users: each imported player gets one server
+    async fn servers_batch(&self, conn: &mut PgConnection, after: i64, table: &mut TableReport) -> anyhow::Result<Option<(i64, u64)>> {
+        let users = legacy::users_after(&self.legacy, after, self.options.batch_size).await?;
+        let ids: Vec<i64> = users.iter().map(|u| u.id).collect();
+
+        let mut hardware: HashMap<i64, Vec<_>> = HashMap::new();
+        for h in legacy::hardware_of(&self.legacy, &ids).await? {
+            hardware.entry(h.user_id).or_default().push(h);
+        }
+        let owners = lookup_many(conn, USER, &ids).await?;
+
+        for user in &users {
+            let Some(&owner_id) = owners.get(&user.id) else {
+                skip(conn, table, SERVER, user.id, "owner not imported".to_string()).await?;
+                continue;
+            };
+
+            let parts = hardware.get(&user.id).map(Vec::as_slice).unwrap_or_default();
+            let (row, notes) = match map_server(user, parts) {
+                Ok(mapped) => mapped,
+                Err(reason) => {
+                    skip(conn, table, SERVER, user.id, reason).await?;
+                    continue;
+                }
+            };
+
+            let ip_taken: bool = sqlx::query_scalar(
+                "SELECT EXISTS(SELECT 1 FROM servers WHERE ip_address = $1::inet)"
+            )
+            .bind(&row.ip_address)
+            .fetch_one(&mut *conn)
+            .await?;
+            if ip_taken {
+                skip(conn, table, SERVER, user.id, format!("IP already in use ({})", row.ip_address)).await?;
+                continue;
+            }
+
+            let server_id: i64 = sqlx::query_scalar(
+                "INSERT INTO servers (user_id, ip_address, hostname, cpu_total, ram_total, hdd_total, net_total) \
+                 VALUES ($1, $2::inet, $3, $4, $5, $6, $7) RETURNING id"
+            )
+            .bind(owner_id)
+            .bind(&row.ip_address)
+            .bind(&row.hostname)
+            .bind(row.cpu_total)
+            .bind(row.ram_total)
+            .bind(row.hdd_total)
+            .bind(row.net_total)
+            .fetch_one(&mut *conn)
+            .await?;
+
+            map_id(conn, SERVER, user.id, server_id).await?;
+            table.record(user.id, RowAction::Created { id: server_id }, notes);
+        }
+
+        Ok(users.last().map(|u| (u.id, users.len() as u64)))
+    }
+
+    async fn software_batch(&self, conn: &mut PgConnection, after: i64, table: &mut TableReport) -> anyhow::Result<Option<(i64, u64)>> {
+        let software = legacy::software_after(&self.legacy, after, self.options.batch_size).await?;
+
+        let owners: Vec<i64> = software.iter().map(|s| s.user_id).collect();
+        let servers: HashMap<i64, (i64, String)> = sqlx::query_as::<_, (i64, i64, String)>(
+            "SELECT m.legacy_id, s.id, host(s.ip_address) FROM legacy_id_map m \
+             JOIN servers s ON s.id = m.new_id \
+             WHERE m.entity = $1 AND m.legacy_id = ANY($2)"
+        )
+        .bind(SERVER)
+        .bind(&owners)
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|(legacy_id, id, ip)| (legacy_id, (id, ip)))
+        .collect();
+
+        for sw in &software {
+            let Some((server_id, owner_ip)) = servers.get(&sw.user_id) else {
+                skip(conn, table, SOFTWARE, sw.id, "owner has no imported server".to_string()).await?;
+                continue;
+            };
+
+            let (row, notes) = match map_software(sw, owner_ip) {
+                Ok(mapped) => mapped,
+                Err(reason) => {
+                    skip(conn, table, SOFTWARE, sw.id, reason).await?;
+                    continue;
+                }
+            };
+
+            let keep_id = !id_taken(conn, "software", sw.id).await?;
+            let new_id: i64 = sqlx::query_scalar(
+                "INSERT INTO software (id, server_id, name, type, version, size, is_installed, is_running, created_at) \
+                 VALUES (COALESCE($1, nextval(pg_get_serial_sequence('software', 'id'))), \
+                 $2, $3, $4, $5::float8, $6, $7, $8, $9) RETURNING id"
+            )
+            .bind(keep_id.then_some(sw.id))
+            .bind(server_id)
+            .bind(&row.name)
+            .bind(&row.software_type)
+            .bind(row.version)
+            .bind(row.size)
+            .bind(row.is_installed)
+            .bind(row.is_running)
+            .bind(sw.created_at)
+            .fetch_one(&mut *conn)
+            .await?;
+
+            map_id(conn, SOFTWARE, sw.id, new_id).await?;
+            table.record(sw.id, id_action(keep_id, new_id), notes);
+        }
+
+        Ok(software.last().map(|s| (s.id, software.len() as u64)))
+    }
+
+    async fn clans_batch(&self, conn: &mut PgConnection, after: i64, table: &mut TableReport) -> anyhow::Result<Option<(i64, u64)>> {
+        let clans = legacy::clans_after(&self.legacy, after, self.options.batch_size).await?;
+        let leaders = lookup_many(conn, USER, &clans.iter().map(|c| c.leader_id).collect::<Vec<_>>()).await?;
+
+        for clan in &clans {
+            let Some(&leader_id) = leaders.get(&clan.leader_id) else {
+                skip(conn, table, CLAN, clan.id, "leader not imported".to_string()).await?;
+                continue;
+            };
+
+            let (row, notes) = match map_clan(clan) {
+                Ok(mapped) => mapped,
+                Err(reason) => {
+                    skip(conn, table, CLAN, clan.id, reason).await?;
+                    continue;
+                }
+            };
+
+            let conflict: bool = sqlx::query_scalar(
+                "SELECT EXISTS(SELECT 1 FROM clans WHERE name = $1 OR tag = $2)"
+            )
+            .bind(&row.name)
+            .bind(&row.tag)
+            .fetch_one(&mut *conn)
+            .await?;
+            if conflict {
+                skip(conn, table, CLAN, clan.id, format!("name or tag taken ({})", row.tag)).await?;
+                continue;
+            }
+
+            let keep_id = !id_taken(conn, "clans", clan.id).await?;
+            let new_id: i64 = sqlx::query_scalar(
+                "INSERT INTO clans (id, name, tag, description, leader_id, reputation, created_at) \
+                 VALUES (COALESCE($1, nextval(pg_get_serial_sequence('clans', 'id'))), \
+                 $2, $3, $4, $5, $6, $7) RETURNING id"
+            )
+            .bind(keep_id.then_some(clan.id))
+            .bind(&row.name)
+            .bind(&row.tag)
+            .bind(&row.description)
+            .bind(leader_id)
+            .bind(row.reputation)
+            .bind(clan.created_at)
+            .fetch_one(&mut *conn)
+            .await?;
+
+            map_id(conn, CLAN, clan.id, new_id).await?;
+            table.record(clan.id, id_action(keep_id, new_id), notes);
+        }
+
+        Ok(clans.last().map(|c| (c.id, clans.len() as u64)))
+    }
+
+    async fn clan_members_batch(&self, conn: &mut PgConnection, after: i64, table: &mut TableReport) -> anyhow::Result<Option<(i64, u64)>> {
+        let members = legacy::clan_members_after(&self.legacy, after, self.options.batch_size).await?;
+        let clans = lookup_many(conn, CLAN, &members.iter().map(|m| m.clan_id).collect::<Vec<_>>()).await?;
+        let users = lookup_many(conn, USER, &members.iter().map(|m| m.user_id).collect::<Vec<_>>()).await?;
+
+        for member in &members {
+            let (Some(&clan_id), Some(&user_id)) = (clans.get(&member.clan_id), users.get(&member.user_id)) else {
+                skip(conn, table, CLAN_MEMBER, member.id, "clan or member not imported".to_string()).await?;
+                continue;
+            };
+
+            let role = map_clan_role(&member.role);
+            let inserted = sqlx::query(
+                "INSERT INTO clan_members (clan_id, user_id, role, joined_at) VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT (clan_id, user_id) DO NOTHING"
+            )
+            .bind(clan_id)
+            .bind(user_id)
+            .bind(role)
+            .bind(member.joined_at)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+            if inserted == 0 {
+                skip(conn, table, CLAN_MEMBER, member.id, "duplicate membership".to_string()).await?;
+                continue;
+            }
+
+            let notes = match role == member.role {
+                true => Vec::new(),
+                false => vec![format!("role '{}' -> '{}'", member.role, role)],
+            };
+            map_id(conn, CLAN_MEMBER, member.id, clan_id).await?;
+            table.record(member.id, RowAction::Created { id: clan_id }, notes);
+        }
+
+        Ok(members.last().map(|m| (m.id, members.len() as u64)))
+    }
+}
+
+fn id_action(kept: bool, id: i64) -> RowAction {
+    match kept {
+        true => RowAction::Kept { id },
+        false => RowAction::Remapped { id },
+    }
+}
+
+/// Leaders become members, clan sizes are recounted, and the id sequences
+/// move past the legacy ids that were inserted explicitly
+async fn finalize(conn: &mut PgConnection) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO clan_members (clan_id, user_id, role) \
+         SELECT c.id, c.leader_id, 'leader' FROM clans c \
+         JOIN legacy_id_map m ON m.entity = $1 AND m.new_id = c.id \
+         ON CONFLICT (clan_id, user_id) DO UPDATE SET role = 'leader'"
+    )
+    .bind(CLAN)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "UPDATE clans c SET member_count = (SELECT COUNT(*) FROM clan_members cm WHERE cm.clan_id = c.id) \
+         WHERE c.id IN (SELECT new_id FROM legacy_id_map WHERE entity = $1)"
+    )
+    .bind(CLAN)
+    .execute(&mut *conn)
+    .await?;
+
+    for table in ["users", "software", "clans"] {
+        let sql = format!(
+            "SELECT setval(pg_get_serial_sequence('{0}', 'id'), GREATEST((SELECT MAX(id) FROM {0}), 1))",
+            table
+        );
+        sqlx::query(&sql).execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+async fn checkpoint(conn: &mut PgConnection, entity: &str) -> anyhow::Result<(i64, bool)> {
+    let row: Option<(i64, bool)> = sqlx::query_as(
+        "SELECT last_legacy_id, completed FROM legacy_import_checkpoints WHERE entity = $1"
+    )
+    .bind(entity)
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(row.unwrap_or((0, false)))
+}
+
+async fn save_checkpoint(conn: &mut PgConnection, entity: &str, last_id: i64, read: u64, completed: bool) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO legacy_import_checkpoints (entity, last_legacy_id, rows_read, completed) \
+         VALUES ($1, $2, $3, $4) \
+         ON CONFLICT (entity) DO UPDATE SET last_legacy_id = EXCLUDED.last_legacy_id, \
+         rows_read = legacy_import_checkpoints.rows_read + EXCLUDED.rows_read, \
+         completed = EXCLUDED.completed, updated_at = NOW()"
+    )
+    .bind(entity)
+    .bind(last_id)
+    .bind(read as i64)
+    .bind(completed)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn id_taken(conn: &mut PgConnection, table: &str, id: i64) -> anyhow::Result<bool> {
+    let sql = format!("SELECT EXISTS(SELECT 1 FROM {} WHERE id = $1)", table);
+    Ok(sqlx::query_scalar(&sql).bind(id).fetch_one(&mut *conn).await?)
+}
+
+async fn map_id(conn: &mut PgConnection, entity: &str, legacy_id: i64, new_id: i64) -> anyhow::Result<()> {
+    sqlx::query("INSERT INTO legacy_id_map (entity, legacy_id, new_id) VALUES ($1, $2, $3)")
+        .bind(entity)
+        .bind(legacy_id)
+        .bind(new_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+async fn lookup_many(conn: &mut PgConnection, entity: &str, legacy_ids: &[i64]) -> anyhow::Result<HashMap<i64, i64>> {
+    let rows: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT legacy_id, new_id FROM legacy_id_map WHERE entity = $1 AND legacy_id = ANY($2)"
+    )
+    .bind(entity)
+    .bind(legacy_ids)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(rows.into_iter().collect())
+}
+
+async fn skip(
+    conn: &mut PgConnection,
+    table: &mut TableReport,
+    entity: &str,
+    legacy_id: i64,
+    reason: String,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "INSERT INTO legacy_import_skips (entity, legacy_id, reason) VALUES ($1, $2, $3) \
+         ON CONFLICT (entity, legacy_id) DO UPDATE SET reason = EXCLUDED.reason, skipped_at = NOW()"
+    )
+    .bind(entity)
+    .bind(legacy_id)
+    .bind(&reason)
+    .execute(&mut *conn)
+    .await?;
+
+    table.record(legacy_id, RowAction::Skipped { reason }, Vec::new());
+    Ok(())
+}
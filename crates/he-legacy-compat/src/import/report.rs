@@ -0,0 +1,140 @@
+//! Import report and the dry-run diff
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Rows per table kept in the report for the diff
+const MAX_SAMPLES: usize = 50;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RowAction {
+    /// Imported under its legacy id
+    Kept { id: i64 },
+    /// Imported under a new id because the legacy one was taken
+    Remapped { id: i64 },
+    /// Imported into a table without a legacy id of its own
+    Created { id: i64 },
+    Skipped { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowDiff {
+    pub legacy_id: i64,
+    #[serde(flatten)]
+    pub action: RowAction,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TableReport {
+    pub read: u64,
+    pub imported: u64,
+    pub ids_kept: u64,
+    pub ids_remapped: u64,
+    pub skipped: BTreeMap<String, u64>,
+    /// Rows that were imported with changes, or skipped, up to a limit
+    pub samples: Vec<RowDiff>,
+}
+
+impl TableReport {
+    pub fn record(&mut self, legacy_id: i64, action: RowAction, notes: Vec<String>) {
+        self.read += 1;
+        match &action {
+            RowAction::Kept { .. } => {
+                self.imported += 1;
+                self.ids_kept += 1;
+            }
+            RowAction::Remapped { .. } => {
+                self.imported += 1;
+                self.ids_remapped += 1;
+            }
+            RowAction::Created { .. } => self.imported += 1,
+            RowAction::Skipped { reason } => {
+                *self.skipped.entry(skip_category(reason)).or_default() += 1;
+            }
+        }
+
+        let interesting = !notes.is_empty() || !matches!(action, RowAction::Kept { .. } | RowAction::Created { .. });
+        if interesting && self.samples.len() < MAX_SAMPLES {
+            self.samples.push(RowDiff { legacy_id, action, notes });
+        }
+    }
+
+    pub fn skipped_total(&self) -> u64 {
+        self.skipped.values().sum()
+    }
+}
+
+/// Broken invariant found after the import
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    pub check: &'static str,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub tables: BTreeMap<&'static str, TableReport>,
+    pub violations: Vec<Violation>,
+}
+
+impl ImportReport {
+    pub fn table(&mut self, name: &'static str) -> &mut TableReport {
+        self.tables.entry(name).or_default()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Human-readable summary followed by a diff-style listing of the sampled
+    /// rows: `+` kept id, `~` new id, `-` skipped
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mode = if self.dry_run { "DRY RUN - nothing was written" } else { "import" };
+        let _ = writeln!(out, "Legacy {}", mode);
+
+        for (name, table) in &self.tables {
+            let _ = writeln!(
+                out,
+                "\n{}: {} read, {} imported ({} ids kept, {} remapped), {} skipped",
+                name, table.read, table.imported, table.ids_kept, table.ids_remapped, table.skipped_total()
+            );
+            for (reason, n) in &table.skipped {
+                let _ = writeln!(out, "    skipped {:>8}  {}", n, reason);
+            }
+            for row in &table.samples {
+                let line = match &row.action {
+                    RowAction::Kept { id } => format!("+ {}#{} -> {}", name, row.legacy_id, id),
+                    RowAction::Remapped { id } => format!("~ {}#{} -> {} (id taken)", name, row.legacy_id, id),
+                    RowAction::Created { id } => format!("+ {}#{} -> new {}", name, row.legacy_id, id),
+                    RowAction::Skipped { reason } => format!("- {}#{} skipped: {}", name, row.legacy_id, reason),
+                };
+                let _ = writeln!(out, "  {}", line);
+                for note in &row.notes {
+                    let _ = writeln!(out, "      {}", note);
+                }
+            }
+        }
+
+        if self.violations.is_empty() {
+            let _ = writeln!(out, "\nAll invariants hold");
+        } else {
+            let _ = writeln!(out, "\n{} invariant violation(s):", self.violations.len());
+            for v in &self.violations {
+                let _ = writeln!(out, "  [{}] {}", v.check, v.detail);
+            }
+        }
+
+        out
+    }
+}
+
+/// Group skip reasons without their row-specific details, e.g.
+/// "stored on another server (1.2.3.4)" -> "stored on another server"
+fn skip_category(reason: &str) -> String {
+    reason.split(" (").next().unwrap_or(reason).to_string()
+}
@@ -0,0 +1,129 @@
+//! Invariants checked after an import
+
+use super::report::Violation;
+use super::{CLAN, CLAN_MEMBER, SERVER, SOFTWARE, USER};
+use sqlx::{MySqlPool, PgConnection};
+
+/// Entity and the legacy table its rows come from
+const SOURCES: &[(&str, &str)] = &[
+    (USER, "users"),
+    (SERVER, "users"),
+    (SOFTWARE, "software"),
+    (CLAN, "clans"),
+    (CLAN_MEMBER, "clan_members"),
+];
+
+/// Where mapped ids of each entity must exist
+const TARGETS: &[(&str, &str)] = &[
+    (USER, "users"),
+    (SERVER, "servers"),
+    (SOFTWARE, "software"),
+    (CLAN, "clans"),
+];
+
+/// `complete` is false when the import was cut short with `max_rows`; the
+/// row accounting check is skipped then.
+pub async fn run(legacy: &MySqlPool, conn: &mut PgConnection, complete: bool) -> anyhow::Result<Vec<Violation>> {
+    let mut violations = Vec::new();
+
+    // Every legacy row was either imported or skipped with a reason
+    if complete {
+        for &(entity, table) in SOURCES {
+            let expected = super::legacy::count(legacy, table).await?;
+            let (mapped, skipped): (i64, i64) = sqlx::query_as(
+                "SELECT (SELECT COUNT(*) FROM legacy_id_map WHERE entity = $1), \
+                        (SELECT COUNT(*) FROM legacy_import_skips WHERE entity = $1)"
+            )
+            .bind(entity)
+            .fetch_one(&mut *conn)
+            .await?;
+
+            if mapped + skipped != expected {
+                violations.push(Violation {
+                    check: "accounted",
+                    detail: format!(
+                        "{}: {} legacy rows, but {} imported and {} skipped",
+                        entity, expected, mapped, skipped
+                    ),
+                });
+            }
+        }
+    }
+
+    // The id map only points at rows that exist
+    for &(entity, table) in TARGETS {
+        let sql = format!(
+            "SELECT COUNT(*) FROM legacy_id_map m WHERE m.entity = $1 \
+             AND NOT EXISTS (SELECT 1 FROM {} t WHERE t.id = m.new_id)",
+            table
+        );
+        let dangling: i64 = sqlx::query_scalar(&sql).bind(entity).fetch_one(&mut *conn).await?;
+        if dangling > 0 {
+            violations.push(Violation {
+                check: "dangling_map",
+                detail: format!("{} {} mappings point at missing {} rows", dangling, entity, table),
+            });
+        }
+    }
+
+    // Every imported player can log in to a machine
+    let homeless: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM legacy_id_map m WHERE m.entity = $1 \
+         AND NOT EXISTS (SELECT 1 FROM servers s WHERE s.user_id = m.new_id)"
+    )
+    .bind(USER)
+    .fetch_one(&mut *conn)
+    .await?;
+    if homeless > 0 {
+        violations.push(Violation {
+            check: "player_server",
+            detail: format!("{} imported players have no server", homeless),
+        });
+    }
+
+    let miscounted: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM clans c JOIN legacy_id_map m ON m.entity = $1 AND m.new_id = c.id \
+         WHERE c.member_count <> (SELECT COUNT(*) FROM clan_members cm WHERE cm.clan_id = c.id)"
+    )
+    .bind(CLAN)
+    .fetch_one(&mut *conn)
+    .await?;
+    if miscounted > 0 {
+        violations.push(Violation {
+            check: "clan_member_count",
+            detail: format!("{} imported clans have a wrong member_count", miscounted),
+        });
+    }
+
+    let leaderless: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM clans c JOIN legacy_id_map m ON m.entity = $1 AND m.new_id = c.id \
+         WHERE NOT EXISTS (SELECT 1 FROM clan_members cm \
+                           WHERE cm.clan_id = c.id AND cm.user_id = c.leader_id AND cm.role = 'leader')"
+    )
+    .bind(CLAN)
+    .fetch_one(&mut *conn)
+    .await?;
+    if leaderless > 0 {
+        violations.push(Violation {
+            check: "clan_leader",
+            detail: format!("{} imported clans whose leader is not a member", leaderless),
+        });
+    }
+
+    // Ids inserted explicitly must not be handed out again
+    for table in ["users", "software", "clans"] {
+        let sql = format!(
+            "SELECT COALESCE((SELECT MAX(id) FROM {0}), 0) > (SELECT last_value FROM {0}_id_seq)",
+            table
+        );
+        let behind: bool = sqlx::query_scalar(&sql).fetch_one(&mut *conn).await?;
+        if behind {
+            violations.push(Violation {
+                check: "sequence",
+                detail: format!("{}_id_seq is behind the highest id", table),
+            });
+        }
+    }
+
+    Ok(violations)
+}
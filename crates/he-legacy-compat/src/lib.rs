@@ -9,6 +9,7 @@ pub mod forum;          // phpBB forum integration
 pub mod utils;          // Utility functions and helpers
 pub mod templates;      // HTML template rendering
 pub mod session;        // PHP session compatibility
+pub mod import;         // Bulk import from the original MySQL database
 
 // Re-export main modules
 pub use pages::*;
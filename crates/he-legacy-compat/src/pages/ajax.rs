@@ -1378,7 +1378,23 @@ async fn bank_transfer_handler(db: &DbPool, params: &HashMap<String, String>) ->
         let _ = tx.rollback().await;
         return AjaxResponse::error("Failed to add to destination account");
     }
-    
+
+    // Mirror the movement in the double-entry ledger, fee out of the game
+    let ledger_query = sqlx::query!(
+        "WITH t AS (INSERT INTO ledger_transactions (reason, reference) VALUES ('transfer', $1) RETURNING id)
+         INSERT INTO ledger_entries (transaction_id, account, amount)
+         SELECT t.id, leg.account, leg.amount FROM t, UNNEST($2::TEXT[], $3::BIGINT[]) AS leg(account, amount)
+         WHERE leg.amount <> 0",
+        format!("transfer:{}:{}", from_account, to_account),
+        &[format!("bank:{}", source.id), format!("bank:{}", dest.id), "system:sink".to_string()][..],
+        &[-amount, net_amount, fee][..]
+    );
+
+    if ledger_query.execute(&mut *tx).await.is_err() {
+        let _ = tx.rollback().await;
+        return AjaxResponse::error("Failed to record ledger entries");
+    }
+
     // Record transaction
     let trans_query = sqlx::query!(
         "INSERT INTO transactions (from_account_id, to_account_id, amount, fee, type, status, created_at) 
@@ -80,6 +80,15 @@ pub struct BountyInfo {
     pub difficulty: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerPage {
+    pub ip_address: String,
+    pub hostname: Option<String>,
+    pub motd: Option<String>,
+    pub public_profile: Option<String>,
+    pub is_own: bool,
+}
+
 /// Scan a server
 pub async fn scan_server(target_ip: String) -> Result<ScanResponse, String> {
     let client = reqwest::Client::new();
@@ -175,6 +184,62 @@ pub async fn get_internet_view() -> Result<InternetResponse, String> {
     }
 }
 
+/// Get a player server's page; `None` if the address has no page
+pub async fn get_server_page(ip: String) -> Result<Option<ServerPage>, String> {
+    let client = reqwest::Client::new();
+    let token = get_auth_token();
+
+    match client
+        .get(&format!("{}/api/internet/{}/page", get_api_url(), ip))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+    {
+        Ok(response) => {
+            if response.status().is_success() {
+                response.json().await.map(Some).map_err(|e| e.to_string())
+            } else if response.status() == reqwest::StatusCode::NOT_FOUND {
+                Ok(None)
+            } else {
+                Err(format!("Failed to load page: {}", response.status()))
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Resolution {
+    pub hostname: String,
+    pub ip_address: String,
+}
+
+/// Resolve a hostname through the whois registry; `None` if nothing is
+/// registered under it
+pub async fn resolve_hostname(hostname: String) -> Result<Option<Resolution>, String> {
+    let client = reqwest::Client::new();
+    let token = get_auth_token();
+
+    match client
+        .get(&format!("{}/api/dns/resolve", get_api_url()))
+        .query(&[("name", hostname)])
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+    {
+        Ok(response) => {
+            if response.status().is_success() {
+                response.json().await.map(Some).map_err(|e| e.to_string())
+            } else if response.status() == reqwest::StatusCode::NOT_FOUND {
+                Ok(None)
+            } else {
+                Err(format!("Failed to resolve hostname: {}", response.status()))
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 // Helper functions
 fn get_api_url() -> String {
     // Get from environment or use default
@@ -2,6 +2,7 @@
 
 pub mod hacking;
 pub mod missions;
+pub mod oauth;
 pub mod process;
 pub mod progression;
 pub mod software;
\ No newline at end of file
@@ -0,0 +1,83 @@
+//! OAuth consent API client
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConsentDetails {
+    pub success: bool,
+    pub client_name: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ConsentResult {
+    success: bool,
+    redirect_to: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+/// Check an authorization request; `query` is the page's query string without `?`
+pub async fn get_consent_details(query: &str) -> Result<ConsentDetails, String> {
+    let client = reqwest::Client::new();
+    let token = get_auth_token();
+
+    match client
+        .get(&format!("{}/api/oauth/authorize?{}", get_api_url(), query))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+    {
+        Ok(response) => {
+            if response.status().is_success() {
+                response.json().await.map_err(|e| e.to_string())
+            } else {
+                match response.json::<ErrorResponse>().await {
+                    Ok(err) => Err(err.message),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Send the player's decision; returns the URL to send the browser to
+pub async fn decide_consent(params: Map<String, Value>, approve: bool) -> Result<String, String> {
+    let client = reqwest::Client::new();
+    let token = get_auth_token();
+
+    let mut body = params;
+    body.insert("approve".to_string(), Value::Bool(approve));
+
+    match client
+        .post(&format!("{}/api/oauth/authorize", get_api_url()))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&body)
+        .send()
+        .await
+    {
+        Ok(response) => match response.json::<ConsentResult>().await {
+            Ok(ConsentResult { success: true, redirect_to: Some(url), .. }) => Ok(url),
+            Ok(result) => Err(result.message.unwrap_or_else(|| "Authorization failed".to_string())),
+            Err(e) => Err(e.to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Helper functions
+fn get_api_url() -> String {
+    std::env::var("API_URL").unwrap_or_else(|_| "http://localhost:3000".to_string())
+}
+
+fn get_auth_token() -> String {
+    // Get from local storage or session
+    "mock_token".to_string()
+}
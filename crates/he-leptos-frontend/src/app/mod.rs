@@ -87,6 +87,7 @@ pub fn App() -> impl IntoView {
                         <Route path="/hardware" view=HardwarePage/>
                         <Route path="/university" view=UniversityPage/>
                         <Route path="/utilities" view=UtilitiesPage/>
+                        <Route path="/oauth/authorize" view=OAuthConsentPage/>
                         <Route path="/*any" view=NotFound/>
                     </Routes>
                 </div>
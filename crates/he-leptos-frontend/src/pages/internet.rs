@@ -1,9 +1,9 @@
 use leptos::*;
 use leptos_router::*;
 use crate::api::hacking::{
-    scan_server, hack_server, server_action, get_internet_view,
+    scan_server, hack_server, server_action, get_internet_view, get_server_page, resolve_hostname,
     ScanResponse, HackResponse, ServerActionResponse, InternetResponse,
-    ServerInfo, KnownServer, BountyInfo
+    ServerInfo, ServerPage, KnownServer, BountyInfo
 };
 
 #[component]
@@ -11,6 +11,7 @@ pub fn InternetPage() -> impl IntoView {
     // State for IP input
     let (target_ip, set_target_ip) = create_signal(String::from("1.2.3.4"));
     let (scan_result, set_scan_result) = create_signal::<Option<ServerInfo>>(None);
+    let (server_page, set_server_page) = create_signal::<Option<ServerPage>>(None);
     let (hack_status, set_hack_status) = create_signal::<Option<String>>(None);
     let (server_files, set_server_files) = create_signal::<Vec<String>>(Vec::new());
     let (server_money, set_server_money) = create_signal::<i64>(0);
@@ -48,9 +49,37 @@ pub fn InternetPage() -> impl IntoView {
         async move {
             set_scanning.set(true);
             set_scan_result.set(None);
+            set_server_page.set(None);
             set_hack_status.set(None);
             set_has_access.set(false);
 
+            // Hostnames go through the whois registry first
+            let ip = if ip.parse::<std::net::IpAddr>().is_ok() {
+                ip
+            } else {
+                match resolve_hostname(ip.clone()).await {
+                    Ok(Some(resolution)) => {
+                        set_target_ip.set(resolution.ip_address.clone());
+                        resolution.ip_address
+                    }
+                    Ok(None) => {
+                        set_hack_status.set(Some(format!("Unknown host {}", ip)));
+                        set_scanning.set(false);
+                        return;
+                    }
+                    Err(e) => {
+                        set_hack_status.set(Some(format!("Lookup failed: {}", e)));
+                        set_scanning.set(false);
+                        return;
+                    }
+                }
+            };
+
+            match get_server_page(ip.clone()).await {
+                Ok(page) => set_server_page.set(page),
+                Err(e) => logging::log!("Failed to load server page: {}", e),
+            }
+
             match scan_server(ip).await {
                 Ok(response) => {
                     if let Some(info) = response.server_info {
@@ -205,7 +234,7 @@ pub fn InternetPage() -> impl IntoView {
                     <input
                         type="text"
                         class="flex-1 px-3 py-2 bg-gray-800 border border-gray-600 rounded"
-                        placeholder="Enter IP address (e.g., 1.2.3.4)"
+                        placeholder="Enter IP address or hostname (e.g., 1.2.3.4)"
                         on:input=move |ev| {
                             set_target_ip.set(event_target_value(&ev));
                         }
@@ -230,6 +259,24 @@ pub fn InternetPage() -> impl IntoView {
                 </div>
             })}
 
+            // Player server page
+            {move || server_page.get().map(|page| view! {
+                <div class="server-page mb-6 p-4 bg-gray-800 rounded">
+                    <h2 class="text-xl font-bold mb-1 font-mono">
+                        {page.hostname.clone().unwrap_or_else(|| page.ip_address.clone())}
+                    </h2>
+                    {page.is_own.then(|| view! {
+                        <div class="text-sm text-gray-400 mb-3">"Your server"</div>
+                    })}
+                    {page.motd.clone().map(|motd| view! {
+                        <pre class="mb-3 p-2 bg-black text-green-400 border border-gray-600 rounded whitespace-pre-wrap">{motd}</pre>
+                    })}
+                    <p class="whitespace-pre-wrap">
+                        {page.public_profile.clone().unwrap_or_else(|| "This server has no public page.".to_string())}
+                    </p>
+                </div>
+            })}
+
             // Scan Result
             {move || scan_result.get().map(|info| view! {
                 <div class="server-info mb-6 p-4 bg-gray-800 rounded">
@@ -22,6 +22,7 @@ pub mod profile;
 pub mod bank;
 pub mod marketplace;
 pub mod leaderboard;
+pub mod oauth_consent;
 
 pub use control_panel::*;
 pub use task_manager::*;
@@ -43,4 +44,5 @@ pub use register::*;
 pub use profile::*;
 pub use bank::*;
 pub use marketplace::*;
-pub use leaderboard::*;
\ No newline at end of file
+pub use leaderboard::*;
+pub use oauth_consent::*;
\ No newline at end of file
@@ -0,0 +1,113 @@
+//! OAuth Consent Page - lets a player sign in to a companion service
+
+use leptos::*;
+use leptos_router::*;
+use serde_json::{Map, Value};
+use crate::api::oauth::{decide_consent, get_consent_details, ConsentDetails};
+
+const REQUEST_PARAMS: &[&str] = &[
+    "response_type", "client_id", "redirect_uri", "scope", "state",
+    "nonce", "code_challenge", "code_challenge_method",
+];
+
+fn scope_description(scope: &str) -> &'static str {
+    match scope {
+        "openid" => "Know who you are in the game",
+        "profile" => "See your public profile",
+        "email" => "See your email address",
+        "roles" => "See your game roles (player, moderator, ...)",
+        _ => "Unknown permission",
+    }
+}
+
+#[component]
+pub fn OAuthConsentPage() -> impl IntoView {
+    let location = use_location();
+    let query = use_query_map();
+
+    let (details, set_details) = create_signal::<Option<ConsentDetails>>(None);
+    let (error_message, set_error_message) = create_signal(None::<String>);
+    let (submitting, set_submitting) = create_signal(false);
+
+    // Check the request on mount
+    create_effect(move |_| {
+        let search = location.search.get();
+        spawn_local(async move {
+            match get_consent_details(search.trim_start_matches('?')).await {
+                Ok(data) => set_details.set(Some(data)),
+                Err(e) => set_error_message.set(Some(e)),
+            }
+        });
+    });
+
+    let decide = create_action(move |approve: &bool| {
+        let approve = *approve;
+        let params: Map<String, Value> = query.with_untracked(|q| {
+            REQUEST_PARAMS
+                .iter()
+                .filter_map(|key| q.get(key).map(|v| (key.to_string(), Value::String(v.clone()))))
+                .collect()
+        });
+
+        async move {
+            set_submitting.set(true);
+            match decide_consent(params, approve).await {
+                Ok(url) => {
+                    if let Some(window) = web_sys::window() {
+                        let _ = window.location().set_href(&url);
+                    }
+                }
+                Err(e) => {
+                    set_error_message.set(Some(e));
+                    set_submitting.set(false);
+                }
+            }
+        }
+    });
+
+    view! {
+        <div class="login-container">
+            <div class="login-box consent-box">
+                <div class="login-logo">
+                    <h1>"HackerExperience"</h1>
+                </div>
+
+                {move || error_message.get().map(|msg| view! {
+                    <div class="alert alert-danger">{msg}</div>
+                })}
+
+                {move || details.get().map(|d| view! {
+                    <div class="consent">
+                        <p>
+                            <strong>{d.client_name.clone()}</strong>
+                            " wants to sign you in with your game account. It will be able to:"
+                        </p>
+                        <ul class="consent-scopes">
+                            {d.scopes.iter().map(|s| view! {
+                                <li>{scope_description(s)}</li>
+                            }).collect_view()}
+                        </ul>
+                        <p class="small">"You will be sent to " {d.redirect_uri.clone()}</p>
+
+                        <div class="consent-actions">
+                            <button
+                                class="btn btn-primary"
+                                disabled=move || submitting.get()
+                                on:click=move |_| decide.dispatch(true)
+                            >
+                                "Allow"
+                            </button>
+                            <button
+                                class="btn btn-default"
+                                disabled=move || submitting.get()
+                                on:click=move |_| decide.dispatch(false)
+                            >
+                                "Deny"
+                            </button>
+                        </div>
+                    </div>
+                })}
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,254 @@
+//! Synthetic players
+//!
+//! A bot signs in over HTTP, opens the game WebSocket with the session it was
+//! given and then loops scripts until the run ends. Every exchange is timed
+//! from the request leaving to the matching reply arriving; messages the bot
+//! did not ask for, such as announcements, are skipped.
+
+use crate::scenario::{Scenario, Step};
+use crate::stats::Recorder;
+use anyhow::{anyhow, bail, Result};
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use reqwest::header::{COOKIE, SET_COOKIE};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Instant};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// What one player measured
+#[derive(Debug, Default)]
+pub struct BotOutcome {
+    pub recorder: Recorder,
+    /// Whether the WebSocket was ever open
+    pub connected: bool,
+}
+
+/// Open WebSockets across all bots, and the most there were at once
+#[derive(Debug, Default)]
+pub struct Connections {
+    open: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl Connections {
+    fn opened(&self) {
+        let open = self.open.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(open, Ordering::SeqCst);
+    }
+
+    fn closed(&self) {
+        self.open.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+struct Bot<'a> {
+    scenario: &'a Scenario,
+    http: reqwest::Client,
+    token: String,
+    recorder: Recorder,
+    timeout: Duration,
+}
+
+/// Play as `player` until `deadline`
+pub async fn run(
+    player: usize,
+    scenario: Arc<Scenario>,
+    http: reqwest::Client,
+    connections: Arc<Connections>,
+    deadline: Instant,
+) -> BotOutcome {
+    let mut recorder = Recorder::default();
+    let request_timeout = Duration::from_millis(scenario.request_timeout_ms);
+    let account = scenario.account(player);
+
+    if scenario.register {
+        // An account left over from an earlier run answers with an error;
+        // only a missing reply counts as a failure
+        let started = Instant::now();
+        let body = serde_json::json!({
+            "username": account,
+            "password": scenario.password,
+            "email": format!("{}@loadtest.invalid", account)
+        });
+        match http.post(format!("{}/api/register", scenario.base_url)).json(&body).timeout(request_timeout).send().await {
+            Ok(_) => recorder.record("http:register", started.elapsed(), true),
+            Err(_) => recorder.failed("http:register"),
+        }
+    }
+
+    let token = match login(&scenario, &http, &account, request_timeout, &mut recorder).await {
+        Ok(token) => token,
+        Err(e) => {
+            tracing::debug!("Player {} could not sign in: {}", player, e);
+            return BotOutcome { recorder, connected: false };
+        }
+    };
+
+    let started = Instant::now();
+    let socket = match timeout(request_timeout, connect(&scenario.ws_url, &token)).await {
+        Ok(Ok(socket)) => socket,
+        Ok(Err(e)) => {
+            tracing::debug!("Player {} could not connect: {}", player, e);
+            recorder.failed("ws:connect");
+            return BotOutcome { recorder, connected: false };
+        }
+        Err(_) => {
+            recorder.failed("ws:connect");
+            return BotOutcome { recorder, connected: false };
+        }
+    };
+    recorder.record("ws:connect", started.elapsed(), true);
+    connections.opened();
+
+    let mut bot = Bot { scenario: &scenario, http, token, recorder, timeout: request_timeout };
+    let mut socket = socket;
+    if let Err(e) = bot.play(&mut socket, deadline).await {
+        tracing::debug!("Player {} stopped early: {}", player, e);
+    }
+    let _ = socket.close(None).await;
+    connections.closed();
+
+    BotOutcome { recorder: bot.recorder, connected: true }
+}
+
+/// Sign in; the session token comes back in the body or as the auth cookie
+async fn login(
+    scenario: &Scenario,
+    http: &reqwest::Client,
+    account: &str,
+    request_timeout: Duration,
+    recorder: &mut Recorder,
+) -> Result<String> {
+    let started = Instant::now();
+    let body = serde_json::json!({ "username": account, "password": scenario.password });
+    let response = match http.post(format!("{}/api/login", scenario.base_url)).json(&body).timeout(request_timeout).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            recorder.failed("http:login");
+            return Err(e.into());
+        }
+    };
+    let ok = response.status().is_success();
+    recorder.record("http:login", started.elapsed(), ok);
+    if !ok {
+        bail!("login answered {}", response.status());
+    }
+
+    let cookie = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| value.strip_prefix("auth_token="))
+        .map(|value| value.split(';').next().unwrap_or("").to_string());
+    if let Some(token) = cookie.filter(|token| !token.is_empty()) {
+        return Ok(token);
+    }
+    let body: serde_json::Value = response.json().await?;
+    body.get("token")
+        .and_then(|token| token.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("login gave no session token"))
+}
+
+async fn connect(url: &str, token: &str) -> Result<Socket> {
+    let mut request = url.into_client_request()?;
+    request.headers_mut().insert("Authorization", HeaderValue::from_str(&format!("Bearer {}", token))?);
+    request.headers_mut().insert("Cookie", HeaderValue::from_str(&format!("auth_token={}", token))?);
+    let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+    Ok(socket)
+}
+
+impl Bot<'_> {
+    async fn play(&mut self, socket: &mut Socket, deadline: Instant) -> Result<()> {
+        let scenario = self.scenario;
+        while Instant::now() < deadline {
+            let script = scenario.pick_script(&mut rand::thread_rng());
+            for step in &script.steps {
+                if Instant::now() >= deadline {
+                    return Ok(());
+                }
+                match step {
+                    Step::Ws { message, expect } => self.ws(socket, step, message, expect).await?,
+                    Step::Http { method, path, body } => self.http(step, method, path, body.as_ref()).await,
+                    Step::Think { min_ms, max_ms } => {
+                        let ms = rand::thread_rng().gen_range(*min_ms..=*max_ms);
+                        let wake = (Instant::now() + Duration::from_millis(ms)).min(deadline);
+                        // Keep reading while idle so server pings are answered
+                        while let Ok(Some(frame)) = tokio::time::timeout_at(wake, socket.next()).await {
+                            frame?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send and wait for the reply; a closed socket ends the bot
+    async fn ws(&mut self, socket: &mut Socket, step: &Step, message: &serde_json::Value, expect: &str) -> Result<()> {
+        let label = step.label().unwrap_or_default();
+        let started = Instant::now();
+        if let Err(e) = socket.send(Message::Text(message.to_string())).await {
+            self.recorder.failed(&label);
+            return Err(e.into());
+        }
+
+        let wait = timeout(self.timeout, async {
+            while let Some(frame) = socket.next().await {
+                let Message::Text(text) = frame? else { continue };
+                let reply: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+                match reply.get("type").and_then(|kind| kind.as_str()) {
+                    Some(kind) if kind == expect => return Ok(true),
+                    Some("error") => return Ok(false),
+                    _ => continue,
+                }
+            }
+            Err(anyhow!("connection closed"))
+        });
+        match wait.await {
+            Ok(Ok(ok)) => self.recorder.record(&label, started.elapsed(), ok),
+            Ok(Err(e)) => {
+                self.recorder.failed(&label);
+                return Err(e);
+            }
+            Err(_) => self.recorder.failed(&label),
+        }
+        Ok(())
+    }
+
+    async fn http(&mut self, step: &Step, method: &str, path: &str, body: Option<&serde_json::Value>) {
+        let label = step.label().unwrap_or_default();
+        let Ok(method) = reqwest::Method::from_bytes(method.to_uppercase().as_bytes()) else {
+            self.recorder.failed(&label);
+            return;
+        };
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.scenario.base_url, path))
+            .bearer_auth(&self.token)
+            .header(COOKIE, format!("auth_token={}", self.token))
+            .timeout(self.timeout);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let started = Instant::now();
+        match request.send().await {
+            Ok(response) => self.recorder.record(&label, started.elapsed(), response.status().is_success()),
+            Err(_) => self.recorder.failed(&label),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Command line of `he-loadtest`
+
+use std::path::PathBuf;
+
+pub const USAGE: &str = "usage: he-loadtest run [--scenario <file>] [--players <n>] [--duration <secs>] [--out <file>]
+                        [--baseline <file>] [--max-regression <pct>]
+       he-loadtest compare <baseline> <current> [--max-regression <pct>]
+       he-loadtest scenario";
+
+/// p99 growth allowed before a run counts as a regression
+pub const DEFAULT_MAX_REGRESSION_PCT: f64 = 20.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run {
+        scenario: Option<PathBuf>,
+        players: Option<usize>,
+        duration_secs: Option<u64>,
+        out: Option<PathBuf>,
+        baseline: Option<PathBuf>,
+        max_regression_pct: f64,
+    },
+    Compare { baseline: PathBuf, current: PathBuf, max_regression_pct: f64 },
+    /// Print the default scenario as a starting point
+    Scenario,
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let (name, rest) = args.split_first().ok_or("missing command")?;
+    let mut options = std::collections::HashMap::new();
+    let mut positional = Vec::new();
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--scenario" | "--players" | "--duration" | "--out" | "--baseline" | "--max-regression" => {
+                let value = rest.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                options.insert(arg.as_str(), value.as_str());
+            }
+            other if other.starts_with('-') => return Err(format!("unknown argument '{}'", other)),
+            other => positional.push(PathBuf::from(other)),
+        }
+    }
+
+    let number = |option: &str| -> Result<Option<u64>, String> {
+        options
+            .get(option)
+            .map(|value| value.parse::<u64>().ok().filter(|n| *n > 0).ok_or(format!("{} must be a positive number", option)))
+            .transpose()
+    };
+    let max_regression_pct = match options.get("--max-regression") {
+        Some(value) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|pct| *pct >= 0.0)
+            .ok_or("--max-regression must be a percentage")?,
+        None => DEFAULT_MAX_REGRESSION_PCT,
+    };
+
+    match name.as_str() {
+        "run" if positional.is_empty() => Ok(Command::Run {
+            scenario: options.get("--scenario").map(PathBuf::from),
+            players: number("--players")?.map(|n| n as usize),
+            duration_secs: number("--duration")?,
+            out: options.get("--out").map(PathBuf::from),
+            baseline: options.get("--baseline").map(PathBuf::from),
+            max_regression_pct,
+        }),
+        "compare" if positional.len() == 2 && options.keys().all(|option| *option == "--max-regression") => {
+            let current = positional.pop().unwrap_or_default();
+            let baseline = positional.pop().unwrap_or_default();
+            Ok(Command::Compare { baseline, current, max_regression_pct })
+        }
+        "scenario" if positional.is_empty() && options.is_empty() => Ok(Command::Scenario),
+        "run" | "compare" | "scenario" => Err(format!("invalid arguments for {}", name)),
+        other => Err(format!("unknown command '{}'", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(&args("run --players 10000 --out now.json --baseline before.json")),
+            Ok(Command::Run {
+                scenario: None,
+                players: Some(10_000),
+                duration_secs: None,
+                out: Some(PathBuf::from("now.json")),
+                baseline: Some(PathBuf::from("before.json")),
+                max_regression_pct: DEFAULT_MAX_REGRESSION_PCT,
+            })
+        );
+        assert_eq!(
+            parse(&args("compare a.json b.json --max-regression 10")),
+            Ok(Command::Compare {
+                baseline: PathBuf::from("a.json"),
+                current: PathBuf::from("b.json"),
+                max_regression_pct: 10.0,
+            })
+        );
+        assert!(parse(&args("run --players 0")).is_err());
+        assert!(parse(&args("compare a.json")).is_err());
+        assert!(parse(&args("compare a.json b.json --players 3")).is_err());
+        assert!(parse(&args("run --out")).is_err());
+    }
+}
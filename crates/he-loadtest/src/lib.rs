@@ -0,0 +1,13 @@
+//! WebSocket load testing for HackerExperience
+//!
+//! Runs synthetic players against a node: each signs in, opens the game
+//! WebSocket and loops action scripts (scanning, cracking, chat), while
+//! latencies are collected per message type. Reports are saved as JSON and
+//! compared across runs to catch regressions.
+
+pub mod bot;
+pub mod cli;
+pub mod report;
+pub mod runner;
+pub mod scenario;
+pub mod stats;
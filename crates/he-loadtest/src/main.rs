@@ -0,0 +1,76 @@
+//! HackerExperience WebSocket load tester
+//!
+//! Usage: he-loadtest run [--scenario <file>] [--players <n>] [--duration <secs>] [--out <file>]
+//!                        [--baseline <file>] [--max-regression <pct>]
+//!        he-loadtest compare <baseline> <current> [--max-regression <pct>]
+//!        he-loadtest scenario
+//!
+//! Exits with 2 when the run regressed against the baseline.
+
+use he_loadtest::cli::{self, Command};
+use he_loadtest::report::{self, Report};
+use he_loadtest::runner;
+use he_loadtest::scenario::Scenario;
+use tracing::error;
+use tracing_subscriber::EnvFilter;
+
+async fn execute(command: Command) -> anyhow::Result<i32> {
+    match command {
+        Command::Scenario => {
+            print!("{}", toml::to_string_pretty(&Scenario::default())?);
+            Ok(0)
+        }
+        Command::Compare { baseline, current, max_regression_pct } => {
+            let comparison = report::compare(&Report::load(&baseline)?, &Report::load(&current)?, max_regression_pct);
+            print!("{}", comparison.render());
+            Ok(if comparison.regressed() { 2 } else { 0 })
+        }
+        Command::Run { scenario, players, duration_secs, out, baseline, max_regression_pct } => {
+            let mut scenario = match scenario {
+                Some(path) => Scenario::load(&path)?,
+                None => Scenario::default(),
+            };
+            scenario.players = players.unwrap_or(scenario.players);
+            scenario.duration_secs = duration_secs.unwrap_or(scenario.duration_secs);
+            // Loaded before the run so a bad path fails fast
+            let baseline = baseline.map(|path| Report::load(&path)).transpose()?;
+
+            let report = runner::run(scenario).await?;
+            print!("{}", report.render());
+            if let Some(path) = out {
+                report.save(&path)?;
+            }
+
+            let Some(baseline) = baseline else {
+                return Ok(0);
+            };
+            let comparison = report::compare(&baseline, &report, max_regression_pct);
+            print!("\n{}", comparison.render());
+            Ok(if comparison.regressed() { 2 } else { 0 })
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env().add_directive("info".parse().unwrap()))
+        .init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n{}", e, cli::USAGE);
+            std::process::exit(64);
+        }
+    };
+
+    match execute(command).await {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            error!("Load test failed: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
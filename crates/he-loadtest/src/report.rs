@@ -0,0 +1,210 @@
+//! Run reports and regression checks
+//!
+//! A report is saved as JSON so a later run can be compared with it. A
+//! message type regresses when its p99 grows by more than the allowed share
+//! (and by at least [`MIN_P99_DELTA_MS`], so fast messages do not flap), or
+//! when its error rate grows by more than a percentage point.
+
+use crate::stats::LatencySummary;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// p99 changes below this are noise, whatever the share
+pub const MIN_P99_DELTA_MS: f64 = 5.0;
+const MAX_ERROR_RATE_DELTA: f64 = 0.01;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub scenario: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_secs: f64,
+    pub players: usize,
+    /// Players whose WebSocket opened
+    pub connected: usize,
+    /// Most WebSockets open at the same time
+    pub peak_connections: usize,
+    pub messages: BTreeMap<String, LatencySummary>,
+}
+
+impl Report {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?).with_context(|| format!("writing {}", path.display()))
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}: {} players, {} connected, peak {} connections, {:.0}s",
+            self.scenario, self.players, self.connected, self.peak_connections, self.duration_secs
+        );
+        let _ = writeln!(
+            out,
+            "{:<36} {:>9} {:>7} {:>8} {:>9} {:>9} {:>9} {:>9}",
+            "message", "count", "err%", "per_sec", "p50_ms", "p90_ms", "p99_ms", "max_ms"
+        );
+        for (label, summary) in &self.messages {
+            let _ = writeln!(
+                out,
+                "{:<36} {:>9} {:>7.2} {:>8.1} {:>9.1} {:>9.1} {:>9.1} {:>9.1}",
+                label,
+                summary.count,
+                summary.error_rate() * 100.0,
+                summary.per_sec,
+                summary.p50_ms,
+                summary.p90_ms,
+                summary.p99_ms,
+                summary.max_ms
+            );
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Delta {
+    pub label: String,
+    pub baseline_p99_ms: f64,
+    pub current_p99_ms: f64,
+    pub baseline_error_rate: f64,
+    pub current_error_rate: f64,
+    pub regressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    pub deltas: Vec<Delta>,
+    /// Types in the baseline the current run never sent
+    pub missing: Vec<String>,
+}
+
+impl Comparison {
+    pub fn regressed(&self) -> bool {
+        self.deltas.iter().any(|delta| delta.regressed)
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{:<36} {:>12} {:>12} {:>9} {:>11}", "message", "p99_before", "p99_after", "change", "err%_after");
+        for delta in &self.deltas {
+            let change = if delta.baseline_p99_ms > 0.0 {
+                format!("{:+.1}%", (delta.current_p99_ms / delta.baseline_p99_ms - 1.0) * 100.0)
+            } else {
+                "new".to_string()
+            };
+            let _ = writeln!(
+                out,
+                "{:<36} {:>12.1} {:>12.1} {:>9} {:>11.2}{}",
+                delta.label,
+                delta.baseline_p99_ms,
+                delta.current_p99_ms,
+                change,
+                delta.current_error_rate * 100.0,
+                if delta.regressed { "  REGRESSED" } else { "" }
+            );
+        }
+        for label in &self.missing {
+            let _ = writeln!(out, "{:<36} not sent in this run", label);
+        }
+        out
+    }
+}
+
+/// Compare `current` with `baseline`, allowing p99 to grow by
+/// `max_regression_pct` percent
+pub fn compare(baseline: &Report, current: &Report, max_regression_pct: f64) -> Comparison {
+    let deltas = current
+        .messages
+        .iter()
+        .map(|(label, now)| {
+            let before = baseline.messages.get(label);
+            let baseline_p99_ms = before.map_or(0.0, |before| before.p99_ms);
+            let baseline_error_rate = before.map_or(0.0, LatencySummary::error_rate);
+            let slower = before.is_some()
+                && now.p99_ms - baseline_p99_ms >= MIN_P99_DELTA_MS
+                && now.p99_ms > baseline_p99_ms * (1.0 + max_regression_pct / 100.0);
+            let failing = now.error_rate() - baseline_error_rate > MAX_ERROR_RATE_DELTA;
+
+            Delta {
+                label: label.clone(),
+                baseline_p99_ms,
+                current_p99_ms: now.p99_ms,
+                baseline_error_rate,
+                current_error_rate: now.error_rate(),
+                regressed: slower || failing,
+            }
+        })
+        .collect();
+    let missing = baseline
+        .messages
+        .keys()
+        .filter(|label| !current.messages.contains_key(*label))
+        .cloned()
+        .collect();
+
+    Comparison { deltas, missing }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(p99_ms: f64, count: u64, errors: u64) -> LatencySummary {
+        LatencySummary {
+            count,
+            errors,
+            failed: 0,
+            per_sec: 1.0,
+            mean_ms: p99_ms / 2.0,
+            p50_ms: p99_ms / 2.0,
+            p90_ms: p99_ms,
+            p99_ms,
+            max_ms: p99_ms,
+        }
+    }
+
+    fn report(messages: &[(&str, LatencySummary)]) -> Report {
+        Report {
+            scenario: "test".to_string(),
+            started_at: Utc::now(),
+            duration_secs: 60.0,
+            players: 10,
+            connected: 10,
+            peak_connections: 10,
+            messages: messages.iter().map(|(label, summary)| (label.to_string(), summary.clone())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_p99_regression_needs_share_and_absolute_growth() {
+        let baseline = report(&[("ws:ping", summary(2.0, 100, 0)), ("http:GET /api/state", summary(100.0, 100, 0))]);
+
+        // ws:ping tripled but by only 4 ms; state got 30% slower
+        let current = report(&[("ws:ping", summary(6.0, 100, 0)), ("http:GET /api/state", summary(130.0, 100, 0))]);
+        let comparison = compare(&baseline, &current, 20.0);
+        let regressed: Vec<&str> =
+            comparison.deltas.iter().filter(|delta| delta.regressed).map(|delta| delta.label.as_str()).collect();
+        assert_eq!(regressed, vec!["http:GET /api/state"]);
+        assert!(!compare(&baseline, &current, 50.0).regressed());
+    }
+
+    #[test]
+    fn test_errors_and_missing_types() {
+        let baseline = report(&[("ws:ping", summary(2.0, 100, 0)), ("ws:join", summary(3.0, 100, 0))]);
+        let current = report(&[("ws:ping", summary(2.0, 100, 5))]);
+
+        let comparison = compare(&baseline, &current, 20.0);
+        assert!(comparison.regressed());
+        assert_eq!(comparison.missing, vec!["ws:join".to_string()]);
+        assert!(comparison.render().contains("REGRESSED"));
+    }
+}
@@ -0,0 +1,54 @@
+//! Running a scenario
+//!
+//! Players are started evenly over the ramp-up, then all of them play until
+//! the ramp-up plus the scenario's duration is over.
+
+use crate::bot::{self, Connections};
+use crate::report::Report;
+use crate::scenario::Scenario;
+use crate::stats::Recorder;
+use anyhow::Result;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+pub async fn run(scenario: Scenario) -> Result<Report> {
+    scenario.validate()?;
+    let scenario = Arc::new(scenario);
+    let http = reqwest::Client::builder().pool_max_idle_per_host(scenario.players).build()?;
+    let connections = Arc::new(Connections::default());
+
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let ramp_up = Duration::from_secs(scenario.ramp_up_secs);
+    let deadline = started + ramp_up + Duration::from_secs(scenario.duration_secs);
+    let spacing = ramp_up / scenario.players as u32;
+
+    let mut players = JoinSet::new();
+    for player in 0..scenario.players {
+        tokio::time::sleep_until(started + spacing * player as u32).await;
+        players.spawn(bot::run(player, scenario.clone(), http.clone(), connections.clone(), deadline));
+    }
+    tracing::info!("All {} players started", scenario.players);
+
+    let mut recorder = Recorder::default();
+    let mut connected = 0;
+    while let Some(outcome) = players.join_next().await {
+        let outcome = outcome?;
+        connected += outcome.connected as usize;
+        recorder.merge(outcome.recorder);
+    }
+    let elapsed = started.elapsed();
+
+    Ok(Report {
+        scenario: scenario.name.clone(),
+        started_at,
+        duration_secs: elapsed.as_secs_f64(),
+        players: scenario.players,
+        connected,
+        peak_connections: connections.peak(),
+        messages: recorder.summarize(elapsed),
+    })
+}
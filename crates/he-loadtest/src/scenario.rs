@@ -0,0 +1,233 @@
+//! Load test scenarios
+//!
+//! A scenario names the node to test, how many synthetic players to bring up
+//! and how quickly, and the action scripts they follow. Each player picks one
+//! script by weight and loops it until the run ends. Scenarios are TOML; the
+//! default is a mix of scanning, cracking and chat.
+
+use anyhow::{bail, Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub name: String,
+    pub base_url: String,
+    pub ws_url: String,
+    pub players: usize,
+    /// Players are started evenly over this long
+    pub ramp_up_secs: u64,
+    /// How long the scripts run once the ramp-up is over
+    pub duration_secs: u64,
+    /// Players sign in as `<account_prefix><n>` with `password`
+    pub account_prefix: String,
+    pub password: String,
+    /// Register the accounts first; ones that already exist are used as-is
+    pub register: bool,
+    pub request_timeout_ms: u64,
+    pub scripts: Vec<Script>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Script {
+    pub name: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub steps: Vec<Step>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Step {
+    /// Send `message` on the WebSocket and wait for a reply whose `type` is
+    /// `expect`
+    Ws { message: serde_json::Value, expect: String },
+    /// Call the HTTP API as the player
+    Http {
+        method: String,
+        path: String,
+        #[serde(default)]
+        body: Option<serde_json::Value>,
+    },
+    /// Idle between `min_ms` and `max_ms`, like a player reading the screen
+    Think { min_ms: u64, max_ms: u64 },
+}
+
+impl Step {
+    /// The message type latencies are reported under; `None` for pauses
+    pub fn label(&self) -> Option<String> {
+        match self {
+            Step::Ws { message, .. } => {
+                let kind = message.get("type").and_then(|kind| kind.as_str()).unwrap_or("unknown");
+                Some(format!("ws:{}", kind))
+            }
+            Step::Http { method, path, .. } => Some(format!("http:{} {}", method.to_uppercase(), path)),
+            Step::Think { .. } => None,
+        }
+    }
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        let think = Step::Think { min_ms: 500, max_ms: 3_000 };
+        let start_process = |process_type: &str| Step::Http {
+            method: "POST".to_string(),
+            path: "/api/processes/start".to_string(),
+            body: Some(serde_json::json!({
+                "process_type": process_type,
+                "priority": "normal",
+                "target": "1.2.3.4"
+            })),
+        };
+        let http_get = |path: &str| Step::Http { method: "GET".to_string(), path: path.to_string(), body: None };
+
+        Self {
+            name: "default".to_string(),
+            base_url: "http://127.0.0.1:3005".to_string(),
+            ws_url: "ws://127.0.0.1:3005/ws".to_string(),
+            players: 100,
+            ramp_up_secs: 30,
+            duration_secs: 120,
+            account_prefix: "loadbot".to_string(),
+            password: "LoadTest-Passw0rd".to_string(),
+            register: true,
+            request_timeout_ms: 10_000,
+            scripts: vec![
+                Script {
+                    name: "scan".to_string(),
+                    weight: 4,
+                    steps: vec![http_get("/api/state"), start_process("scan"), think.clone(), http_get("/api/processes")],
+                },
+                Script {
+                    name: "crack".to_string(),
+                    weight: 2,
+                    steps: vec![
+                        http_get("/api/hardware"),
+                        start_process("crack"),
+                        think.clone(),
+                        http_get("/api/processes"),
+                    ],
+                },
+                Script {
+                    name: "chat".to_string(),
+                    weight: 4,
+                    steps: vec![
+                        Step::Ws {
+                            message: serde_json::json!({
+                                "type": "join",
+                                "channel": "chat:global",
+                                "entity_id": "00000000-0000-0000-0000-000000000000"
+                            }),
+                            expect: "joined".to_string(),
+                        },
+                        Step::Ws { message: serde_json::json!({ "type": "ping" }), expect: "pong".to_string() },
+                        think,
+                        Step::Ws {
+                            message: serde_json::json!({ "type": "leave", "channel": "chat:global" }),
+                            expect: "left".to_string(),
+                        },
+                    ],
+                },
+            ],
+        }
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let scenario: Scenario = toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.players == 0 {
+            bail!("a scenario needs at least one player");
+        }
+        if self.scripts.iter().map(|script| script.weight as u64).sum::<u64>() == 0 {
+            bail!("a scenario needs at least one script with a weight");
+        }
+        for script in &self.scripts {
+            if script.steps.iter().all(|step| step.label().is_none()) {
+                bail!("script '{}' sends nothing", script.name);
+            }
+            for step in &script.steps {
+                if let Step::Think { min_ms, max_ms } = step {
+                    if min_ms > max_ms {
+                        bail!("script '{}' thinks for {}..{} ms", script.name, min_ms, max_ms);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn account(&self, player: usize) -> String {
+        format!("{}{}", self.account_prefix, player)
+    }
+
+    /// A script chosen by weight
+    pub fn pick_script<R: Rng>(&self, rng: &mut R) -> &Script {
+        let total: u64 = self.scripts.iter().map(|script| script.weight as u64).sum();
+        let mut roll = rng.gen_range(0..total);
+        for script in &self.scripts {
+            if roll < script.weight as u64 {
+                return script;
+            }
+            roll -= script.weight as u64;
+        }
+        unreachable!("validated scenarios have a positive total weight")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_default_round_trips_through_toml() {
+        let scenario = Scenario::default();
+        scenario.validate().unwrap();
+
+        let parsed: Scenario = toml::from_str(&toml::to_string(&scenario).unwrap()).unwrap();
+        assert_eq!(parsed.scripts.len(), scenario.scripts.len());
+        assert_eq!(parsed.scripts[2].steps, scenario.scripts[2].steps);
+    }
+
+    #[test]
+    fn test_partial_scenario_uses_defaults() {
+        let scenario: Scenario = toml::from_str(
+            r#"
+            players = 10
+
+            [[scripts]]
+            name = "ping"
+            steps = [{ kind = "ws", message = { type = "ping" }, expect = "pong" }]
+            "#,
+        )
+        .unwrap();
+        scenario.validate().unwrap();
+        assert_eq!(scenario.ws_url, Scenario::default().ws_url);
+        assert_eq!(scenario.scripts[0].weight, 1);
+        assert_eq!(scenario.scripts[0].steps[0].label().as_deref(), Some("ws:ping"));
+    }
+
+    #[test]
+    fn test_pick_script_follows_weights() {
+        let mut scenario = Scenario::default();
+        scenario.scripts[0].weight = 0;
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..200 {
+            assert_ne!(scenario.pick_script(&mut rng).name, "scan");
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Latency samples per message type
+//!
+//! Every player keeps its own [`Recorder`]; they are merged once the run is
+//! over, so recording never contends.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+struct Samples {
+    micros: Vec<u64>,
+    errors: u64,
+    failed: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Recorder {
+    samples: HashMap<String, Samples>,
+}
+
+impl Recorder {
+    /// A completed exchange; `ok` is false for error replies, which still
+    /// count towards latency
+    pub fn record(&mut self, label: &str, latency: Duration, ok: bool) {
+        let samples = self.samples.entry(label.to_string()).or_default();
+        samples.micros.push(latency.as_micros() as u64);
+        if !ok {
+            samples.errors += 1;
+        }
+    }
+
+    /// An exchange that never completed: timed out or the connection failed
+    pub fn failed(&mut self, label: &str) {
+        self.samples.entry(label.to_string()).or_default().failed += 1;
+    }
+
+    pub fn merge(&mut self, other: Recorder) {
+        for (label, theirs) in other.samples {
+            let ours = self.samples.entry(label).or_default();
+            ours.micros.extend(theirs.micros);
+            ours.errors += theirs.errors;
+            ours.failed += theirs.failed;
+        }
+    }
+
+    /// Per-type summaries over a run lasting `elapsed`
+    pub fn summarize(mut self, elapsed: Duration) -> BTreeMap<String, LatencySummary> {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        self.samples
+            .drain()
+            .map(|(label, mut samples)| {
+                samples.micros.sort_unstable();
+                let count = samples.micros.len() as u64;
+                let ms = |micros: u64| micros as f64 / 1_000.0;
+                let mean = if count == 0 { 0 } else { samples.micros.iter().sum::<u64>() / count };
+                let summary = LatencySummary {
+                    count,
+                    errors: samples.errors,
+                    failed: samples.failed,
+                    per_sec: count as f64 / secs,
+                    mean_ms: ms(mean),
+                    p50_ms: ms(percentile(&samples.micros, 50.0)),
+                    p90_ms: ms(percentile(&samples.micros, 90.0)),
+                    p99_ms: ms(percentile(&samples.micros, 99.0)),
+                    max_ms: ms(samples.micros.last().copied().unwrap_or(0)),
+                };
+                (label, summary)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    /// Exchanges that got a reply
+    pub count: u64,
+    /// Replies that were errors
+    pub errors: u64,
+    /// Exchanges without a reply: timeouts and failed sends
+    pub failed: u64,
+    pub per_sec: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencySummary {
+    /// Share of attempts that errored or failed, 0 to 1
+    pub fn error_rate(&self) -> f64 {
+        let attempts = self.count + self.failed;
+        if attempts == 0 {
+            0.0
+        } else {
+            (self.errors + self.failed) as f64 / attempts as f64
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted samples; 0 when there are none
+pub fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let samples: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&samples, 50.0), 50);
+        assert_eq!(percentile(&samples, 99.0), 99);
+        assert_eq!(percentile(&samples, 100.0), 100);
+        assert_eq!(percentile(&[7], 0.0), 7);
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+
+    #[test]
+    fn test_merge_and_summarize() {
+        let mut a = Recorder::default();
+        a.record("ws:ping", Duration::from_millis(2), true);
+        a.failed("ws:ping");
+        let mut b = Recorder::default();
+        b.record("ws:ping", Duration::from_millis(4), false);
+        b.record("http:GET /api/state", Duration::from_millis(10), true);
+        a.merge(b);
+
+        let summary = a.summarize(Duration::from_secs(2));
+        let ping = &summary["ws:ping"];
+        assert_eq!((ping.count, ping.errors, ping.failed), (2, 1, 1));
+        assert!((ping.error_rate() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(ping.p50_ms, 2.0);
+        assert_eq!(ping.max_ms, 4.0);
+        assert_eq!(ping.per_sec, 1.0);
+        assert_eq!(summary["http:GET /api/state"].error_rate(), 0.0);
+    }
+}
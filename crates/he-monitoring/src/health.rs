@@ -0,0 +1,365 @@
+//! Component health with criticality and dependencies
+//!
+//! Components register a probe together with a [`Criticality`] and the names
+//! of the components they depend on. [`HealthRegistry::check`] runs every
+//! probe concurrently under a timeout and reports each component's state and
+//! latency along with the dependency graph.
+//!
+//! A component is [`ProbeState::Degraded`] when it works but not fully and
+//! [`ProbeState::Down`] when it does not work at all. One whose own probe
+//! passes but that depends, directly or not, on a component that is not up is
+//! reported as degraded and names what impairs it.
+//!
+//! Liveness and readiness are separate questions. Liveness never runs probes,
+//! so a database outage does not get the node restarted. Readiness is lost
+//! while a critical component is down, which takes the node out of rotation
+//! until it recovers; optional components only ever degrade the node.
+
+use crate::HealthStatus;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+pub type ProbeFuture = Pin<Box<dyn Future<Output = ProbeResult> + Send>>;
+
+type Probe = Arc<dyn Fn() -> ProbeFuture + Send + Sync>;
+
+/// What a component failing means for the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criticality {
+    /// The node cannot serve traffic without it
+    Critical,
+    /// The node keeps serving, degraded
+    Optional,
+}
+
+/// Ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeState {
+    Up,
+    Degraded,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub state: ProbeState,
+    pub message: String,
+}
+
+impl ProbeResult {
+    pub fn up(message: impl Into<String>) -> Self {
+        Self { state: ProbeState::Up, message: message.into() }
+    }
+
+    pub fn degraded(message: impl Into<String>) -> Self {
+        Self { state: ProbeState::Degraded, message: message.into() }
+    }
+
+    pub fn down(message: impl Into<String>) -> Self {
+        Self { state: ProbeState::Down, message: message.into() }
+    }
+}
+
+struct Component {
+    name: String,
+    criticality: Criticality,
+    depends_on: Vec<String>,
+    probe: Probe,
+}
+
+/// One component's result in a [`HealthReport`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub criticality: Criticality,
+    /// The probe's own result, before dependencies are considered
+    pub probe_state: ProbeState,
+    pub state: ProbeState,
+    pub message: String,
+    pub latency_ms: f64,
+    pub depends_on: Vec<String>,
+    /// Dependencies, direct or not, that are not up
+    pub impaired_by: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverallState {
+    Healthy,
+    /// Serving, but something is not up
+    Degraded,
+    /// A critical component is down
+    Unhealthy,
+}
+
+/// A dependency edge: `from` needs `to`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyEdge {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub status: OverallState,
+    pub components: Vec<ComponentHealth>,
+    pub edges: Vec<DependencyEdge>,
+}
+
+impl HealthReport {
+    /// Whether the node should receive traffic
+    pub fn is_ready(&self) -> bool {
+        self.status != OverallState::Unhealthy
+    }
+
+    pub fn component(&self, name: &str) -> Option<&ComponentHealth> {
+        self.components.iter().find(|c| c.name == name)
+    }
+
+    /// The report as plain pass/fail checks; degraded counts as passing
+    pub fn statuses(&self) -> Vec<HealthStatus> {
+        self.components
+            .iter()
+            .map(|c| HealthStatus {
+                name: c.name.clone(),
+                healthy: c.state != ProbeState::Down,
+                message: c.message.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Every component's probe, shared by the health endpoints and the status page
+pub struct HealthRegistry {
+    components: RwLock<Vec<Component>>,
+    timeout: Duration,
+}
+
+impl HealthRegistry {
+    /// A probe slower than `timeout` reports the component down
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            components: RwLock::new(Vec::new()),
+            timeout,
+        }
+    }
+
+    /// Register a component, replacing any registered under the same name.
+    /// Dependencies may be registered later; until then they count as down.
+    pub fn register<F, Fut>(&self, name: &str, criticality: Criticality, depends_on: &[&str], probe: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ProbeResult> + Send + 'static,
+    {
+        let component = Component {
+            name: name.to_string(),
+            criticality,
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            probe: Arc::new(move || Box::pin(probe()) as ProbeFuture),
+        };
+
+        let mut components = self.components.write().unwrap();
+        components.retain(|c| c.name != name);
+        components.push(component);
+    }
+
+    /// Register a synchronous [`HealthStatus`] check, e.g. one written for
+    /// [`crate::HealthCheck`]. Failing checks report the component down.
+    pub fn register_check<F>(&self, name: &str, criticality: Criticality, depends_on: &[&str], check: F)
+    where
+        F: Fn() -> HealthStatus + Send + Sync + 'static,
+    {
+        self.register(name, criticality, depends_on, move || {
+            let status = check();
+            async move {
+                if status.healthy {
+                    ProbeResult::up(status.message)
+                } else {
+                    ProbeResult::down(status.message)
+                }
+            }
+        });
+    }
+
+    /// Run every probe and build the report
+    pub async fn check(&self) -> HealthReport {
+        let registered: Vec<(String, Criticality, Vec<String>, Probe)> = self
+            .components
+            .read()
+            .unwrap()
+            .iter()
+            .map(|c| (c.name.clone(), c.criticality, c.depends_on.clone(), c.probe.clone()))
+            .collect();
+
+        let mut probes = JoinSet::new();
+        for (index, (_, _, _, probe)) in registered.iter().enumerate() {
+            let future = probe();
+            let timeout = self.timeout;
+            probes.spawn(async move {
+                let started = Instant::now();
+                let result = tokio::time::timeout(timeout, future)
+                    .await
+                    .unwrap_or_else(|_| ProbeResult::down(format!("No response within {:?}", timeout)));
+                (index, result, started.elapsed())
+            });
+        }
+
+        let mut results: Vec<Option<(ProbeResult, Duration)>> = vec![None; registered.len()];
+        while let Some(joined) = probes.join_next().await {
+            if let Ok((index, result, latency)) = joined {
+                results[index] = Some((result, latency));
+            }
+        }
+
+        let graph: HashMap<String, Vec<String>> = registered
+            .iter()
+            .map(|(name, _, depends_on, _)| (name.clone(), depends_on.clone()))
+            .collect();
+        let probed: HashMap<String, ProbeState> = registered
+            .iter()
+            .zip(&results)
+            .map(|((name, ..), result)| {
+                (name.clone(), result.as_ref().map_or(ProbeState::Down, |(r, _)| r.state))
+            })
+            .collect();
+
+        let components: Vec<ComponentHealth> = registered
+            .into_iter()
+            .zip(results)
+            .map(|((name, criticality, depends_on, _), result)| {
+                let (result, latency) = result.unwrap_or_else(|| (ProbeResult::down("Probe panicked"), Duration::ZERO));
+                let impaired_by = impaired_by(&name, &graph, &probed);
+
+                let state = if impaired_by.is_empty() {
+                    result.state
+                } else {
+                    result.state.max(ProbeState::Degraded)
+                };
+                let message = if impaired_by.is_empty() || result.state != ProbeState::Up {
+                    result.message
+                } else {
+                    format!("{}; impaired by {}", result.message, impaired_by.join(", "))
+                };
+
+                ComponentHealth {
+                    name,
+                    criticality,
+                    probe_state: result.state,
+                    state,
+                    message,
+                    latency_ms: latency.as_secs_f64() * 1000.0,
+                    depends_on,
+                    impaired_by,
+                }
+            })
+            .collect();
+
+        let edges = components
+            .iter()
+            .flat_map(|c| {
+                c.depends_on.iter().map(|to| DependencyEdge {
+                    from: c.name.clone(),
+                    to: to.clone(),
+                })
+            })
+            .collect();
+
+        HealthReport {
+            status: overall_state(&components),
+            components,
+            edges,
+        }
+    }
+}
+
+/// Unhealthy if a critical probe failed, degraded if anything is not up
+fn overall_state(components: &[ComponentHealth]) -> OverallState {
+    if components
+        .iter()
+        .any(|c| c.criticality == Criticality::Critical && c.probe_state == ProbeState::Down)
+    {
+        OverallState::Unhealthy
+    } else if components.iter().any(|c| c.state != ProbeState::Up) {
+        OverallState::Degraded
+    } else {
+        OverallState::Healthy
+    }
+}
+
+/// Transitive dependencies of `name` that are not up, in the order they are
+/// reached. Unregistered dependencies count as down; cycles are followed once.
+fn impaired_by(
+    name: &str,
+    graph: &HashMap<String, Vec<String>>,
+    probed: &HashMap<String, ProbeState>,
+) -> Vec<String> {
+    let mut seen = HashSet::from([name.to_string()]);
+    let mut pending = graph.get(name).cloned().unwrap_or_default();
+    let mut impaired = Vec::new();
+
+    while let Some(dependency) = pending.pop() {
+        if !seen.insert(dependency.clone()) {
+            continue;
+        }
+        if probed.get(&dependency) != Some(&ProbeState::Up) {
+            impaired.push(dependency.clone());
+        }
+        pending.extend(graph.get(&dependency).cloned().unwrap_or_default());
+    }
+
+    impaired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dependency_degrades_dependents() {
+        let registry = HealthRegistry::new(Duration::from_millis(50));
+        registry.register("database", Criticality::Critical, &[], || async { ProbeResult::up("ok") });
+        registry.register("cache", Criticality::Optional, &[], || async { ProbeResult::down("unreachable") });
+        registry.register("sessions", Criticality::Optional, &["cache"], || async { ProbeResult::up("ok") });
+        registry.register("api", Criticality::Critical, &["database", "sessions"], || async { ProbeResult::up("ok") });
+
+        let report = registry.check().await;
+        assert_eq!(report.status, OverallState::Degraded);
+        assert!(report.is_ready());
+
+        let api = report.component("api").unwrap();
+        assert_eq!(api.state, ProbeState::Degraded);
+        assert_eq!(api.impaired_by, vec!["cache".to_string()]);
+        assert_eq!(report.component("database").unwrap().state, ProbeState::Up);
+        assert_eq!(report.edges.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_critical_failure_and_timeout() {
+        let registry = HealthRegistry::new(Duration::from_millis(20));
+        registry.register("database", Criticality::Critical, &[], || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            ProbeResult::up("too late")
+        });
+        registry.register_check("forum_sync", Criticality::Optional, &["database"], || HealthStatus {
+            name: "forum_sync".to_string(),
+            healthy: true,
+            message: "Forum sync disabled".to_string(),
+        });
+
+        let report = registry.check().await;
+        assert_eq!(report.status, OverallState::Unhealthy);
+        assert!(!report.is_ready());
+        assert_eq!(report.component("database").unwrap().probe_state, ProbeState::Down);
+
+        let statuses = report.statuses();
+        assert!(statuses.iter().any(|s| s.name == "forum_sync" && s.healthy));
+        assert!(statuses.iter().any(|s| s.name == "database" && !s.healthy));
+    }
+}
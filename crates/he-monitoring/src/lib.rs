@@ -6,9 +6,17 @@ use prometheus::{
     register_gauge, register_gauge_vec, register_histogram, register_histogram_vec,
 };
 use sysinfo::{System, SystemExt, CpuExt, DiskExt, NetworkExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use tracing::{info, warn, error};
 
+pub mod health;
+pub mod thresholds;
+
+pub use health::{Criticality, HealthRegistry, HealthReport, OverallState, ProbeResult, ProbeState};
+pub use thresholds::{AlertMetric, AlertThresholds, Threshold, ThresholdError};
+
 lazy_static::lazy_static! {
     // ===========================================
     // HTTP Metrics
@@ -74,6 +82,23 @@ lazy_static::lazy_static! {
         &["type"]
     ).unwrap();
 
+    static ref ECONOMY_MONEY: CounterVec = register_counter_vec!(
+        "economy_money_cents_total",
+        "Cents created and destroyed, by flow (minted, sunk) and ledger reason",
+        &["flow", "reason"]
+    ).unwrap();
+
+    static ref ECONOMY_PER_MINUTE: GaugeVec = register_gauge_vec!(
+        "economy_money_cents_per_minute",
+        "Cents created and destroyed over the last complete minute, by flow",
+        &["flow"]
+    ).unwrap();
+
+    static ref ECONOMY_ANOMALIES: Counter = register_counter!(
+        "economy_anomalies_total",
+        "Players flagged for a sudden wealth spike"
+    ).unwrap();
+
     // ===========================================
     // Database Metrics
     // ===========================================
@@ -90,6 +115,130 @@ lazy_static::lazy_static! {
         vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]
     ).unwrap();
 
+    // ===========================================
+    // In-memory Registry Metrics
+    // ===========================================
+
+    static ref REGISTRY_ENTRIES: GaugeVec = register_gauge_vec!(
+        "registry_entries",
+        "Entries held by in-memory registries",
+        &["registry"]
+    ).unwrap();
+
+    static ref REGISTRY_BYTES: GaugeVec = register_gauge_vec!(
+        "registry_bytes",
+        "Estimated memory used by in-memory registries",
+        &["registry"]
+    ).unwrap();
+
+    static ref REGISTRY_SOFT_CAP: GaugeVec = register_gauge_vec!(
+        "registry_soft_cap_bytes",
+        "Soft memory cap of in-memory registries",
+        &["registry"]
+    ).unwrap();
+
+    static ref REGISTRY_LOOKUPS: CounterVec = register_counter_vec!(
+        "registry_lookups_total",
+        "Registry lookups by result (hit, miss, load, load_failure)",
+        &["registry", "result"]
+    ).unwrap();
+
+    static ref REGISTRY_EVICTIONS: CounterVec = register_counter_vec!(
+        "registry_evictions_total",
+        "Registry evictions by cause (expired, capacity)",
+        &["registry", "cause"]
+    ).unwrap();
+
+    // ===========================================
+    // Password Hashing Metrics
+    // ===========================================
+
+    static ref PASSWORD_VERIFICATIONS: Counter = register_counter!(
+        "password_verifications_total",
+        "Password hash verifications"
+    ).unwrap();
+
+    static ref PASSWORD_VERIFY_SECONDS: Counter = register_counter!(
+        "password_verify_seconds_total",
+        "Time spent verifying password hashes"
+    ).unwrap();
+
+    static ref PASSWORD_VERIFY_AVG: Gauge = register_gauge!(
+        "password_verify_average_seconds",
+        "Average password verification time since startup"
+    ).unwrap();
+
+    // ===========================================
+    // Forum Sync Metrics
+    // ===========================================
+
+    static ref FORUM_SYNC_PENDING: GaugeVec = register_gauge_vec!(
+        "forum_sync_pending_messages",
+        "Forum sync messages waiting to be applied, by the side they go to",
+        &["direction"]
+    ).unwrap();
+
+    static ref FORUM_SYNC_DEAD_LETTERS: Gauge = register_gauge!(
+        "forum_sync_dead_letters",
+        "Forum sync messages given up on"
+    ).unwrap();
+
+    static ref FORUM_SYNC_LAG: Gauge = register_gauge!(
+        "forum_sync_lag_seconds",
+        "Age of the oldest pending forum sync message"
+    ).unwrap();
+
+    static ref FORUM_SYNC_APPLIED: CounterVec = register_counter_vec!(
+        "forum_sync_applied_total",
+        "Forum sync messages applied, by the side they went to",
+        &["direction"]
+    ).unwrap();
+
+    static ref FORUM_SYNC_FAILURES: Counter = register_counter!(
+        "forum_sync_failures_total",
+        "Failed attempts to apply forum sync messages"
+    ).unwrap();
+
+    // ===========================================
+    // WebSocket Gateway Metrics
+    // ===========================================
+
+    static ref GATEWAY_SESSIONS: GaugeVec = register_gauge_vec!(
+        "ws_gateway_sessions",
+        "Open WebSocket sessions, by gateway region and node",
+        &["region", "node"]
+    ).unwrap();
+
+    static ref GATEWAY_FORWARDED: CounterVec = register_counter_vec!(
+        "ws_gateway_forwarded_total",
+        "Events forwarded to players connected to another node, by source and destination region",
+        &["from_region", "to_region"]
+    ).unwrap();
+
+    static ref GATEWAY_MIGRATIONS: CounterVec = register_counter_vec!(
+        "ws_gateway_migrations_total",
+        "Sessions told to reconnect elsewhere because their node is draining, by region",
+        &["region"]
+    ).unwrap();
+
+    static ref COMPLETION_SHARDS_OWNED: GaugeVec = register_gauge_vec!(
+        "completion_shards_owned",
+        "Process completion shards leased, by node",
+        &["node"]
+    ).unwrap();
+
+    static ref COMPLETION_SHARD_CHANGES: CounterVec = register_counter_vec!(
+        "completion_shard_changes_total",
+        "Completion shard leases taken or given up, by node and how",
+        &["node", "change"]
+    ).unwrap();
+
+    static ref COMPLETION_FENCED_OUT: CounterVec = register_counter_vec!(
+        "completion_fenced_out_total",
+        "Completions refused because the node's shard lease was superseded, by node",
+        &["node"]
+    ).unwrap();
+
     // ===========================================
     // System Metrics
     // ===========================================
@@ -109,8 +258,35 @@ lazy_static::lazy_static! {
         "Disk usage by mount point",
         &["mount_point"]
     ).unwrap();
+
+    static ref MEMORY_PERCENT: Gauge = register_gauge!(
+        "system_memory_usage_percent",
+        "Memory usage percentage"
+    ).unwrap();
+
+    static ref DISK_PERCENT: Gauge = register_gauge!(
+        "system_disk_usage_max_percent",
+        "Usage percentage of the fullest disk"
+    ).unwrap();
+
+    // ===========================================
+    // Alerting
+    // ===========================================
+
+    static ref ALERT_THRESHOLD: GaugeVec = register_gauge_vec!(
+        "alert_threshold",
+        "Alert thresholds in force, by metric and level",
+        &["metric", "level"]
+    ).unwrap();
+
+    static ref THRESHOLDS: RwLock<Arc<AlertThresholds>> = RwLock::new(Arc::new(AlertThresholds::default()));
 }
 
+/// Requests since the last threshold check, for the error rate and response time
+static WINDOW_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static WINDOW_ERRORS: AtomicU64 = AtomicU64::new(0);
+static WINDOW_MICROS: AtomicU64 = AtomicU64::new(0);
+
 /// Main monitoring service
 pub struct MonitoringService {
     sentry_guard: Option<sentry::ClientInitGuard>,
@@ -167,13 +343,20 @@ impl MonitoringService {
         // Memory usage
         let memory_used = self.system.used_memory();
         MEMORY_USAGE.set(memory_used as f64);
+        MEMORY_PERCENT.set(percent(memory_used, self.system.total_memory()));
 
         // Disk usage
+        let mut fullest = 0.0f64;
         for disk in self.system.disks() {
             let mount_point = disk.mount_point().to_string_lossy();
             let usage = disk.total_space() - disk.available_space();
             DISK_USAGE.with_label_values(&[&mount_point]).set(usage as f64);
+            fullest = fullest.max(percent(usage, disk.total_space()));
         }
+        DISK_PERCENT.set(fullest);
+
+        RegistryMetrics::collect();
+        PasswordMetrics::collect();
     }
 
     /// Export metrics for Prometheus
@@ -199,6 +382,12 @@ impl RequestTracker {
         HTTP_DURATION
             .with_label_values(&[method, endpoint])
             .observe(duration.as_secs_f64());
+
+        WINDOW_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        WINDOW_MICROS.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        if status >= 500 {
+            WINDOW_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
     }
 
     /// Track WebSocket connection
@@ -276,6 +465,150 @@ impl DatabaseMetrics {
     }
 }
 
+/// Money flow, as the economy stream processor reads it off the ledger
+pub struct EconomyMetrics;
+
+impl EconomyMetrics {
+    /// Count `count` ledger transactions for `reason` and the money they
+    /// created and destroyed
+    pub fn transactions(reason: &str, count: u64, minted: i64, sunk: i64) {
+        TRANSACTIONS.with_label_values(&[reason]).inc_by(count as f64);
+        if minted > 0 {
+            ECONOMY_MONEY.with_label_values(&["minted", reason]).inc_by(minted as f64);
+        }
+        if sunk > 0 {
+            ECONOMY_MONEY.with_label_values(&["sunk", reason]).inc_by(sunk as f64);
+        }
+    }
+
+    /// The last complete minute's totals, checked against the
+    /// `money_minted_per_minute` and `money_sunk_per_minute` thresholds
+    pub fn last_minute(minted: i64, sunk: i64) {
+        ECONOMY_PER_MINUTE.with_label_values(&["minted"]).set(minted as f64);
+        ECONOMY_PER_MINUTE.with_label_values(&["sunk"]).set(sunk as f64);
+    }
+
+    pub fn anomalies_flagged(count: u64) {
+        ECONOMY_ANOMALIES.inc_by(count as f64);
+    }
+}
+
+/// In-memory registry metrics, read from `he_core::registry`
+pub struct RegistryMetrics;
+
+impl RegistryMetrics {
+    /// Copy the current registry stats into the Prometheus metrics
+    pub fn collect() {
+        for stats in he_core::registry::all_stats() {
+            let name = stats.name.as_str();
+            REGISTRY_ENTRIES.with_label_values(&[name]).set(stats.entries as f64);
+            REGISTRY_BYTES.with_label_values(&[name]).set(stats.bytes as f64);
+            REGISTRY_SOFT_CAP.with_label_values(&[name]).set(stats.soft_cap_bytes as f64);
+
+            for (result, total) in [
+                ("hit", stats.hits),
+                ("miss", stats.misses),
+                ("load", stats.loads),
+                ("load_failure", stats.load_failures),
+            ] {
+                advance_to(&REGISTRY_LOOKUPS.with_label_values(&[name, result]), total);
+            }
+            advance_to(&REGISTRY_EVICTIONS.with_label_values(&[name, "expired"]), stats.expired);
+            advance_to(&REGISTRY_EVICTIONS.with_label_values(&[name, "capacity"]), stats.evicted);
+        }
+    }
+}
+
+pub struct PasswordMetrics;
+
+impl PasswordMetrics {
+    /// Copy the password verification totals into the Prometheus metrics
+    pub fn collect() {
+        let latency = he_auth::password::verify_latency();
+        advance_to(&PASSWORD_VERIFICATIONS, latency.count);
+        let delta = latency.total_seconds - PASSWORD_VERIFY_SECONDS.get();
+        if delta > 0.0 {
+            PASSWORD_VERIFY_SECONDS.inc_by(delta);
+        }
+        PASSWORD_VERIFY_AVG.set(latency.average().as_secs_f64());
+    }
+}
+
+/// Registries keep their own running totals; move the counter up to match
+fn advance_to(counter: &Counter, total: u64) {
+    let delta = total as f64 - counter.get();
+    if delta > 0.0 {
+        counter.inc_by(delta);
+    }
+}
+
+/// State of the forum sync, as reported by the bridge
+#[derive(Debug, Clone, Default)]
+pub struct ForumSyncSample {
+    pub pending_to_forum: i64,
+    pub pending_to_game: i64,
+    pub dead_letters: i64,
+    pub lag_seconds: f64,
+    /// Running totals since startup
+    pub applied_to_forum: u64,
+    pub applied_to_game: u64,
+    pub failures: u64,
+}
+
+pub struct ForumSyncMetrics;
+
+impl ForumSyncMetrics {
+    /// Copy the bridge's latest state into the Prometheus metrics
+    pub fn record(sample: &ForumSyncSample) {
+        FORUM_SYNC_PENDING.with_label_values(&["forum"]).set(sample.pending_to_forum as f64);
+        FORUM_SYNC_PENDING.with_label_values(&["game"]).set(sample.pending_to_game as f64);
+        FORUM_SYNC_DEAD_LETTERS.set(sample.dead_letters as f64);
+        FORUM_SYNC_LAG.set(sample.lag_seconds);
+        advance_to(&FORUM_SYNC_APPLIED.with_label_values(&["forum"]), sample.applied_to_forum);
+        advance_to(&FORUM_SYNC_APPLIED.with_label_values(&["game"]), sample.applied_to_game);
+        advance_to(&FORUM_SYNC_FAILURES, sample.failures);
+    }
+}
+
+/// Regional WebSocket gateway tracker
+pub struct GatewayMetrics;
+
+impl GatewayMetrics {
+    pub fn session_opened(region: &str, node: &str) {
+        GATEWAY_SESSIONS.with_label_values(&[region, node]).inc();
+    }
+
+    pub fn session_closed(region: &str, node: &str) {
+        GATEWAY_SESSIONS.with_label_values(&[region, node]).dec();
+    }
+
+    pub fn forwarded(from_region: &str, to_region: &str) {
+        GATEWAY_FORWARDED.with_label_values(&[from_region, to_region]).inc();
+    }
+
+    pub fn migrated(region: &str) {
+        GATEWAY_MIGRATIONS.with_label_values(&[region]).inc();
+    }
+}
+
+/// Completion shard ownership tracker
+pub struct SchedulerMetrics;
+
+impl SchedulerMetrics {
+    pub fn owned(node: &str, shards: usize) {
+        COMPLETION_SHARDS_OWNED.with_label_values(&[node]).set(shards as f64);
+    }
+
+    /// `change` is one of acquired, released, stolen or lost
+    pub fn changed(node: &str, change: &str) {
+        COMPLETION_SHARD_CHANGES.with_label_values(&[node, change]).inc();
+    }
+
+    pub fn fenced_out(node: &str) {
+        COMPLETION_FENCED_OUT.with_label_values(&[node]).inc();
+    }
+}
+
 /// Health check service
 pub struct HealthCheck {
     checks: Vec<Box<dyn Fn() -> HealthStatus + Send + Sync>>,
@@ -339,25 +672,92 @@ impl AlertManager {
         info!("INFO ALERT: {}", message);
     }
 
-    /// Check thresholds and alert
+    /// Thresholds in force
+    pub fn thresholds() -> Arc<AlertThresholds> {
+        THRESHOLDS.read().unwrap().clone()
+    }
+
+    /// Replace the thresholds; the next check uses them
+    pub fn set_thresholds(thresholds: AlertThresholds) {
+        for (metric, threshold) in &thresholds.values {
+            ALERT_THRESHOLD.with_label_values(&[metric.as_str(), "warning"]).set(threshold.warning);
+            ALERT_THRESHOLD.with_label_values(&[metric.as_str(), "critical"]).set(threshold.critical);
+        }
+        *THRESHOLDS.write().unwrap() = Arc::new(thresholds);
+    }
+
+    /// Check every metric against the thresholds in force and alert. The
+    /// error rate and response time cover requests since the previous check.
     pub fn check_thresholds() {
-        // CPU threshold
-        if CPU_USAGE.get() > 90.0 {
-            Self::critical("CPU usage above 90%");
-        } else if CPU_USAGE.get() > 75.0 {
-            Self::warning("CPU usage above 75%");
+        let thresholds = Self::thresholds();
+
+        let requests = WINDOW_REQUESTS.swap(0, Ordering::Relaxed);
+        let errors = WINDOW_ERRORS.swap(0, Ordering::Relaxed);
+        let micros = WINDOW_MICROS.swap(0, Ordering::Relaxed);
+
+        let mut samples = vec![
+            (AlertMetric::CpuPercent, CPU_USAGE.get()),
+            (AlertMetric::MemoryPercent, MEMORY_PERCENT.get()),
+            (AlertMetric::DiskPercent, DISK_PERCENT.get()),
+            (AlertMetric::ActiveConnections, ACTIVE_CONNECTIONS.get()),
+            (AlertMetric::MoneyMintedPerMinute, ECONOMY_PER_MINUTE.with_label_values(&["minted"]).get()),
+            (AlertMetric::MoneySunkPerMinute, ECONOMY_PER_MINUTE.with_label_values(&["sunk"]).get()),
+        ];
+        if requests > 0 {
+            samples.push((AlertMetric::ErrorRatePercent, percent(errors, requests)));
+            samples.push((AlertMetric::ResponseTimeMs, micros as f64 / requests as f64 / 1000.0));
+        }
+
+        for (metric, value) in samples {
+            let threshold = thresholds.get(metric);
+            if value >= threshold.critical {
+                Self::critical(&format!("{} at {:.1}, critical threshold {}", metric, value, threshold.critical));
+            } else if value >= threshold.warning {
+                Self::warning(&format!("{} at {:.1}, warning threshold {}", metric, value, threshold.warning));
+            }
         }
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
 
-        // Memory threshold
-        let memory_gb = MEMORY_USAGE.get() / (1024.0 * 1024.0 * 1024.0);
-        if memory_gb > 14.0 {  // Assuming 16GB system
-            Self::critical("Memory usage critical");
+/// Error reporter - sends server-side `HeError`s to Sentry
+pub struct ErrorReporter;
+
+impl ErrorReporter {
+    /// Report an error if it is a server fault. Events are grouped by the root
+    /// error variant and code, not the message, so ids and names in messages
+    /// don't split one bug into many issues.
+    pub fn capture(err: &he_core::HeError) {
+        let code = err.code();
+        if !code.is_server_error() {
+            return;
         }
 
-        // Connection threshold
-        if ACTIVE_CONNECTIONS.get() > 10000.0 {
-            Self::warning("High number of active connections");
+        error!(code = code.as_str(), variant = err.variant(), chain = ?err.chain(), "Unhandled error");
+
+        if sentry::Hub::current().client().is_none() {
+            return;
         }
+
+        sentry::with_scope(
+            |scope| {
+                scope.set_fingerprint(Some(&["he-error", err.variant(), code.as_str()]));
+                scope.set_tag("error.code", code.as_str());
+                scope.set_tag("error.variant", err.variant());
+                scope.set_extra("chain", err.chain().into());
+                if let Some(trace) = err.backtrace() {
+                    scope.set_extra("backtrace", trace.to_string().into());
+                }
+            },
+            || sentry::capture_error(err),
+        );
     }
 }
 
@@ -0,0 +1,249 @@
+//! Runtime alert thresholds
+//!
+//! Each environment starts from its own defaults, and single metrics can be
+//! overridden while the node runs. Overrides are checked against sane bounds
+//! so a typo cannot silence an alert (a CPU threshold of 1000%) or page on
+//! every sample (a response time threshold of 1ms).
+//!
+//! The thresholds [`crate::AlertManager`] evaluates are swapped in whole, so a
+//! change takes effect on the next check without a restart.
+
+use he_core::config::Environment;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A metric the alert manager checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    CpuPercent,
+    MemoryPercent,
+    DiskPercent,
+    ResponseTimeMs,
+    ErrorRatePercent,
+    ActiveConnections,
+    /// Cents the game created over the last complete minute
+    MoneyMintedPerMinute,
+    /// Cents the game destroyed over the last complete minute
+    MoneySunkPerMinute,
+}
+
+impl AlertMetric {
+    pub const ALL: [AlertMetric; 8] = [
+        AlertMetric::CpuPercent,
+        AlertMetric::MemoryPercent,
+        AlertMetric::DiskPercent,
+        AlertMetric::ResponseTimeMs,
+        AlertMetric::ErrorRatePercent,
+        AlertMetric::ActiveConnections,
+        AlertMetric::MoneyMintedPerMinute,
+        AlertMetric::MoneySunkPerMinute,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertMetric::CpuPercent => "cpu_percent",
+            AlertMetric::MemoryPercent => "memory_percent",
+            AlertMetric::DiskPercent => "disk_percent",
+            AlertMetric::ResponseTimeMs => "response_time_ms",
+            AlertMetric::ErrorRatePercent => "error_rate_percent",
+            AlertMetric::ActiveConnections => "active_connections",
+            AlertMetric::MoneyMintedPerMinute => "money_minted_per_minute",
+            AlertMetric::MoneySunkPerMinute => "money_sunk_per_minute",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|metric| metric.as_str() == s)
+    }
+
+    /// Lowest and highest values a threshold on this metric may take
+    pub fn bounds(&self) -> (f64, f64) {
+        match self {
+            AlertMetric::CpuPercent | AlertMetric::MemoryPercent | AlertMetric::DiskPercent => (10.0, 100.0),
+            AlertMetric::ResponseTimeMs => (50.0, 60_000.0),
+            AlertMetric::ErrorRatePercent => (0.1, 100.0),
+            AlertMetric::ActiveConnections => (10.0, 1_000_000.0),
+            AlertMetric::MoneyMintedPerMinute | AlertMetric::MoneySunkPerMinute => (10_000.0, 1e12),
+        }
+    }
+}
+
+impl fmt::Display for AlertMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Values at which a metric raises a warning and a critical alert
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Threshold {
+    pub warning: f64,
+    pub critical: f64,
+}
+
+impl Threshold {
+    pub const fn new(warning: f64, critical: f64) -> Self {
+        Self { warning, critical }
+    }
+}
+
+/// Why a threshold was refused
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThresholdError {
+    NotFinite { metric: AlertMetric },
+    OutOfBounds { metric: AlertMetric, min: f64, max: f64 },
+    /// The warning would only fire after the critical alert
+    WarningAboveCritical { metric: AlertMetric },
+}
+
+impl fmt::Display for ThresholdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThresholdError::NotFinite { metric } => write!(f, "{} thresholds must be numbers", metric),
+            ThresholdError::OutOfBounds { metric, min, max } => {
+                write!(f, "{} thresholds must be between {} and {}", metric, min, max)
+            }
+            ThresholdError::WarningAboveCritical { metric } => {
+                write!(f, "{} warning threshold must not exceed the critical one", metric)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ThresholdError {}
+
+/// Whether `threshold` is a sane one for `metric`
+pub fn validate(metric: AlertMetric, threshold: &Threshold) -> Result<(), ThresholdError> {
+    if !threshold.warning.is_finite() || !threshold.critical.is_finite() {
+        return Err(ThresholdError::NotFinite { metric });
+    }
+
+    let (min, max) = metric.bounds();
+    if [threshold.warning, threshold.critical].iter().any(|value| !(min..=max).contains(value)) {
+        return Err(ThresholdError::OutOfBounds { metric, min, max });
+    }
+
+    if threshold.warning > threshold.critical {
+        return Err(ThresholdError::WarningAboveCritical { metric });
+    }
+
+    Ok(())
+}
+
+/// The thresholds in force for one environment
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AlertThresholds {
+    pub environment: Environment,
+    pub values: BTreeMap<AlertMetric, Threshold>,
+    /// Metrics whose value differs from the environment default
+    pub overridden: Vec<AlertMetric>,
+}
+
+impl AlertThresholds {
+    /// Defaults for `environment`. Development machines run hot and noisy,
+    /// so only production is strict.
+    pub fn for_environment(environment: Environment) -> Self {
+        let values = AlertMetric::ALL
+            .into_iter()
+            .map(|metric| (metric, default_threshold(&environment, metric)))
+            .collect();
+        Self { environment, values, overridden: Vec::new() }
+    }
+
+    /// The environment defaults with `overrides` applied. Every override is
+    /// validated; one bad value rejects the whole set.
+    pub fn with_overrides(
+        environment: Environment,
+        overrides: impl IntoIterator<Item = (AlertMetric, Threshold)>,
+    ) -> Result<Self, ThresholdError> {
+        let mut thresholds = Self::for_environment(environment);
+        for (metric, threshold) in overrides {
+            validate(metric, &threshold)?;
+            thresholds.values.insert(metric, threshold);
+            if !thresholds.overridden.contains(&metric) {
+                thresholds.overridden.push(metric);
+            }
+        }
+        thresholds.overridden.sort();
+        Ok(thresholds)
+    }
+
+    pub fn get(&self, metric: AlertMetric) -> Threshold {
+        self.values
+            .get(&metric)
+            .copied()
+            .unwrap_or_else(|| default_threshold(&self.environment, metric))
+    }
+}
+
+impl Default for AlertThresholds {
+    fn default() -> Self {
+        Self::for_environment(Environment::Production)
+    }
+}
+
+fn default_threshold(environment: &Environment, metric: AlertMetric) -> Threshold {
+    let production = match metric {
+        AlertMetric::CpuPercent => Threshold::new(75.0, 90.0),
+        AlertMetric::MemoryPercent => Threshold::new(80.0, 90.0),
+        AlertMetric::DiskPercent => Threshold::new(85.0, 95.0),
+        AlertMetric::ResponseTimeMs => Threshold::new(1_000.0, 5_000.0),
+        AlertMetric::ErrorRatePercent => Threshold::new(2.0, 5.0),
+        AlertMetric::ActiveConnections => Threshold::new(8_000.0, 10_000.0),
+        AlertMetric::MoneyMintedPerMinute => Threshold::new(5_000_000.0, 25_000_000.0),
+        AlertMetric::MoneySunkPerMinute => Threshold::new(5_000_000.0, 25_000_000.0),
+    };
+
+    match (environment, metric) {
+        (Environment::Production, _) => production,
+        (_, AlertMetric::ErrorRatePercent) => Threshold::new(10.0, 25.0),
+        (_, AlertMetric::ResponseTimeMs) => Threshold::new(3_000.0, 10_000.0),
+        (Environment::Development, AlertMetric::CpuPercent | AlertMetric::MemoryPercent) => {
+            Threshold::new(95.0, 100.0)
+        }
+        _ => production,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_are_validated() {
+        let thresholds = AlertThresholds::with_overrides(
+            Environment::Staging,
+            [(AlertMetric::CpuPercent, Threshold::new(60.0, 70.0))],
+        )
+        .unwrap();
+        assert_eq!(thresholds.get(AlertMetric::CpuPercent), Threshold::new(60.0, 70.0));
+        assert_eq!(thresholds.overridden, vec![AlertMetric::CpuPercent]);
+
+        assert_eq!(
+            validate(AlertMetric::CpuPercent, &Threshold::new(90.0, 1000.0)),
+            Err(ThresholdError::OutOfBounds { metric: AlertMetric::CpuPercent, min: 10.0, max: 100.0 })
+        );
+        assert_eq!(
+            validate(AlertMetric::ResponseTimeMs, &Threshold::new(5_000.0, 1_000.0)),
+            Err(ThresholdError::WarningAboveCritical { metric: AlertMetric::ResponseTimeMs })
+        );
+        assert!(AlertThresholds::with_overrides(
+            Environment::Production,
+            [(AlertMetric::ErrorRatePercent, Threshold::new(f64::NAN, 5.0))],
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_environment_defaults() {
+        let production = AlertThresholds::for_environment(Environment::Production);
+        let development = AlertThresholds::for_environment(Environment::Development);
+        assert!(development.get(AlertMetric::CpuPercent).critical > production.get(AlertMetric::CpuPercent).critical);
+        for metric in AlertMetric::ALL {
+            assert_eq!(validate(metric, &development.get(metric)), Ok(()));
+            assert_eq!(AlertMetric::from_str(metric.as_str()), Some(metric));
+        }
+    }
+}
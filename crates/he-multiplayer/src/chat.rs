@@ -3,7 +3,16 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+
+/// Longest message a player can post, in characters
+pub const MAX_MESSAGE_LENGTH: usize = 500;
+/// Players notified per message; further mentions still render
+pub const MAX_MENTIONS: usize = 10;
+/// Distinct reactions one message can carry
+pub const MAX_REACTION_KINDS: usize = 20;
+const MAX_REACTION_CHARS: usize = 8;
 
 /// Chat room
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +52,89 @@ pub struct ChatMessage {
     pub edited_at: Option<DateTime<Utc>>,
     pub reactions: HashMap<String, Vec<Uuid>>, // emoji -> list of users
     pub reply_to: Option<Uuid>,
+    /// `content` parsed into text, mentions and entity references; empty
+    /// for system messages, whose content is plain text
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+    /// Deleted messages stay in history as an empty placeholder
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+/// A piece of a message's structured content
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Segment {
+    Text { text: String },
+    /// `@name` of an existing player
+    Mention { user_id: Uuid, username: String },
+    /// `[player:name]`, `[clan:tag]` or `[ip:address]`, resolved when posted
+    Entity { entity: EntityRef },
+}
+
+/// Something a message links to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EntityRef {
+    Player { id: Uuid, name: String },
+    Clan { id: Uuid, tag: String },
+    Ip { address: IpAddr },
+}
+
+/// Looks up what messages refer to, so clients only ever get links to
+/// things that exist
+pub trait EntityResolver {
+    /// Id and canonical name of the player called `username`
+    fn player(&self, username: &str) -> Option<(Uuid, String)>;
+    /// Id and canonical tag of the clan tagged `tag`
+    fn clan(&self, tag: &str) -> Option<(Uuid, String)>;
+    /// Whether `address` is a server players may link to
+    fn ip(&self, address: IpAddr) -> bool;
+}
+
+/// A change to a room, broadcast to its members as-is instead of resending
+/// whole messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ChatEvent {
+    MessagePosted {
+        room: String,
+        message: ChatMessage,
+    },
+    MessageEdited {
+        room: String,
+        message_id: Uuid,
+        content: String,
+        segments: Vec<Segment>,
+        edited_at: DateTime<Utc>,
+    },
+    MessageDeleted {
+        room: String,
+        message_id: Uuid,
+        deleted_by: Uuid,
+    },
+    ReactionAdded {
+        room: String,
+        message_id: Uuid,
+        emoji: String,
+        user_id: Uuid,
+        count: usize,
+    },
+    ReactionRemoved {
+        room: String,
+        message_id: Uuid,
+        emoji: String,
+        user_id: Uuid,
+        count: usize,
+    },
+}
+
+/// Result of posting or editing: the event for the room and a notification
+/// for every newly mentioned player
+#[derive(Debug, Clone)]
+pub struct Posted {
+    pub event: ChatEvent,
+    pub notifications: Vec<ChatNotification>,
 }
 
 /// Type of message
@@ -70,6 +162,8 @@ pub struct RoomSettings {
     pub auto_moderation: bool,
     pub profanity_filter: bool,
     pub link_filter: bool,
+    /// How long authors can edit or delete their messages
+    pub edit_window_seconds: u32,
 }
 
 /// Chat command
@@ -254,6 +348,8 @@ impl ChatRoom {
             edited_at: None,
             reactions: HashMap::new(),
             reply_to: None,
+            segments: Vec::new(),
+            deleted: false,
         };
 
         self.messages.push_back(join_msg);
@@ -276,6 +372,8 @@ impl ChatRoom {
             edited_at: None,
             reactions: HashMap::new(),
             reply_to: None,
+            segments: Vec::new(),
+            deleted: false,
         };
 
         self.messages.push_back(leave_msg);
@@ -316,6 +414,193 @@ impl ChatRoom {
         self.messages.clear();
         Ok(())
     }
+
+    /// Post a player's message, resolving its mentions and entity
+    /// references
+    pub fn post(
+        &mut self,
+        sender_id: Uuid,
+        sender_name: String,
+        content: &str,
+        reply_to: Option<Uuid>,
+        resolver: &dyn EntityResolver,
+    ) -> Result<Posted, ChatError> {
+        if content.chars().count() > MAX_MESSAGE_LENGTH {
+            return Err(ChatError::MessageTooLong);
+        }
+        if let Some(parent) = reply_to {
+            self.find_message(parent)?;
+        }
+
+        let content = if self.settings.profanity_filter {
+            self.filter_profanity(content.to_string())
+        } else {
+            content.to_string()
+        };
+        let segments = parse_content(&content, resolver)?;
+
+        let mut message = ChatMessage::new_text(sender_id, sender_name, content);
+        message.reply_to = reply_to;
+        message.segments = segments;
+        let notifications = self.mention_notifications(&message, &HashSet::new());
+
+        self.add_message(message.clone())?;
+        Ok(Posted {
+            event: ChatEvent::MessagePosted { room: self.id.clone(), message },
+            notifications,
+        })
+    }
+
+    /// Rewrite a message; only its author can, and only within the edit
+    /// window. Players mentioned for the first time are notified.
+    pub fn edit_message(
+        &mut self,
+        message_id: Uuid,
+        editor_id: Uuid,
+        content: &str,
+        resolver: &dyn EntityResolver,
+    ) -> Result<Posted, ChatError> {
+        if content.chars().count() > MAX_MESSAGE_LENGTH {
+            return Err(ChatError::MessageTooLong);
+        }
+        let content = if self.settings.profanity_filter {
+            self.filter_profanity(content.to_string())
+        } else {
+            content.to_string()
+        };
+        let segments = parse_content(&content, resolver)?;
+
+        let window = self.settings.edit_window_seconds as i64;
+        let message = self.find_message(message_id)?;
+        if message.sender_id != editor_id {
+            return Err(ChatError::InsufficientPermissions);
+        }
+        if (Utc::now() - message.timestamp).num_seconds() > window {
+            return Err(ChatError::EditWindowClosed);
+        }
+        let already: HashSet<Uuid> = message.mentions().collect();
+
+        let message = self.find_message_mut(message_id)?;
+        message.edit(content);
+        message.segments = segments;
+        let message = message.clone();
+
+        let notifications = self.mention_notifications(&message, &already);
+        Ok(Posted {
+            event: ChatEvent::MessageEdited {
+                room: self.id.clone(),
+                message_id,
+                content: message.content,
+                segments: message.segments,
+                edited_at: message.edited_at.unwrap_or(message.timestamp),
+            },
+            notifications,
+        })
+    }
+
+    /// Delete a message: its author within the edit window, a moderator at
+    /// any time. A placeholder stays so replies still point somewhere.
+    pub fn delete_message(&mut self, message_id: Uuid, user_id: Uuid) -> Result<ChatEvent, ChatError> {
+        let window = self.settings.edit_window_seconds as i64;
+        let moderator = self.moderators.contains(&user_id);
+        let message = self.find_message_mut(message_id)?;
+
+        if !moderator {
+            if message.sender_id != user_id {
+                return Err(ChatError::InsufficientPermissions);
+            }
+            if (Utc::now() - message.timestamp).num_seconds() > window {
+                return Err(ChatError::EditWindowClosed);
+            }
+        }
+
+        message.content.clear();
+        message.segments.clear();
+        message.reactions.clear();
+        message.deleted = true;
+
+        Ok(ChatEvent::MessageDeleted { room: self.id.clone(), message_id, deleted_by: user_id })
+    }
+
+    /// React to a message; `None` if the player already had
+    pub fn react(&mut self, message_id: Uuid, user_id: Uuid, emoji: &str) -> Result<Option<ChatEvent>, ChatError> {
+        if self.banned_users.contains(&user_id) {
+            return Err(ChatError::UserBanned);
+        }
+        if !is_reaction(emoji) {
+            return Err(ChatError::InvalidReaction);
+        }
+        let message = self.find_message_mut(message_id)?;
+        if !message.reactions.contains_key(emoji) && message.reactions.len() >= MAX_REACTION_KINDS {
+            return Err(ChatError::InvalidReaction);
+        }
+        if !message.add_reaction(emoji.to_string(), user_id) {
+            return Ok(None);
+        }
+
+        let count = message.reactions[emoji].len();
+        Ok(Some(ChatEvent::ReactionAdded {
+            room: self.id.clone(),
+            message_id,
+            emoji: emoji.to_string(),
+            user_id,
+            count,
+        }))
+    }
+
+    /// Take a reaction back; `None` if the player had not reacted so
+    pub fn unreact(&mut self, message_id: Uuid, user_id: Uuid, emoji: &str) -> Result<Option<ChatEvent>, ChatError> {
+        let message = self.find_message_mut(message_id)?;
+        if !message.remove_reaction(emoji, user_id) {
+            return Ok(None);
+        }
+
+        let count = message.reactions.get(emoji).map_or(0, Vec::len);
+        Ok(Some(ChatEvent::ReactionRemoved {
+            room: self.id.clone(),
+            message_id,
+            emoji: emoji.to_string(),
+            user_id,
+            count,
+        }))
+    }
+
+    fn find_message(&self, message_id: Uuid) -> Result<&ChatMessage, ChatError> {
+        self.messages
+            .iter()
+            .find(|m| m.id == message_id && !m.deleted)
+            .ok_or(ChatError::MessageNotFound)
+    }
+
+    fn find_message_mut(&mut self, message_id: Uuid) -> Result<&mut ChatMessage, ChatError> {
+        self.messages
+            .iter_mut()
+            .find(|m| m.id == message_id && !m.deleted)
+            .ok_or(ChatError::MessageNotFound)
+    }
+
+    /// Notifications for players `message` mentions, other than its author
+    /// and those in `skip`. Outside public rooms only members are notified.
+    fn mention_notifications(&self, message: &ChatMessage, skip: &HashSet<Uuid>) -> Vec<ChatNotification> {
+        let mut notified = HashSet::new();
+        message
+            .mentions()
+            .filter(|id| *id != message.sender_id && !skip.contains(id))
+            .filter(|id| self.room_type == RoomType::Public || self.members.contains(id))
+            .filter(|id| notified.insert(*id))
+            .take(MAX_MENTIONS)
+            .map(|recipient_id| ChatNotification {
+                id: Uuid::new_v4(),
+                recipient_id,
+                notification_type: NotificationType::Mention,
+                title: format!("{} mentioned you in {}", message.sender_name, self.name),
+                message: message.content.clone(),
+                timestamp: Utc::now(),
+                read: false,
+                action_url: Some(format!("/chat/{}#{}", self.id, message.id)),
+            })
+            .collect()
+    }
 }
 
 impl Default for RoomSettings {
@@ -329,6 +614,7 @@ impl Default for RoomSettings {
             auto_moderation: true,
             profanity_filter: true,
             link_filter: false,
+            edit_window_seconds: 300,
         }
     }
 }
@@ -347,6 +633,8 @@ impl ChatMessage {
             edited_at: None,
             reactions: HashMap::new(),
             reply_to: None,
+            segments: Vec::new(),
+            deleted: false,
         }
     }
 
@@ -357,23 +645,126 @@ impl ChatMessage {
         self.edited_at = Some(Utc::now());
     }
 
-    /// Add reaction to message
-    pub fn add_reaction(&mut self, emoji: String, user_id: Uuid) {
+    /// Add reaction to message; false if the user already reacted so
+    pub fn add_reaction(&mut self, emoji: String, user_id: Uuid) -> bool {
+        let users = self.reactions.entry(emoji).or_default();
+        if users.contains(&user_id) {
+            return false;
+        }
+        users.push(user_id);
+        true
+    }
+
+    /// Remove reaction from message; false if the user had not reacted so
+    pub fn remove_reaction(&mut self, emoji: &str, user_id: Uuid) -> bool {
+        let Some(users) = self.reactions.get_mut(emoji) else {
+            return false;
+        };
+        let before = users.len();
+        users.retain(|&id| id != user_id);
+        let removed = users.len() < before;
+        if users.is_empty() {
+            self.reactions.remove(emoji);
+        }
+        removed
+    }
+
+    /// How many players reacted with each emoji
+    pub fn reaction_counts(&self) -> BTreeMap<String, usize> {
         self.reactions
-            .entry(emoji)
-            .or_insert_with(Vec::new)
-            .push(user_id);
+            .iter()
+            .map(|(emoji, users)| (emoji.clone(), users.len()))
+            .collect()
+    }
+
+    /// Players the message mentions, in order, repeats included
+    pub fn mentions(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Mention { user_id, .. } => Some(*user_id),
+            _ => None,
+        })
     }
+}
+
+/// Split message text into segments. `@name` becomes a mention when the
+/// player exists and stays text otherwise; `[player:name]`, `[clan:tag]` and
+/// `[ip:address]` must resolve or the message is refused.
+pub fn parse_content(content: &str, resolver: &dyn EntityResolver) -> Result<Vec<Segment>, ChatError> {
+    fn flush(segments: &mut Vec<Segment>, text: &mut String) {
+        if !text.is_empty() {
+            segments.push(Segment::Text { text: std::mem::take(text) });
+        }
+    }
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = content;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '@' && text.chars().last().map_or(true, char::is_whitespace) {
+            let name_len = rest[1..].find(|c: char| !is_name_char(c)).unwrap_or(rest.len() - 1);
+            let name = &rest[1..1 + name_len];
+            if !name.is_empty() {
+                if let Some((user_id, username)) = resolver.player(name) {
+                    flush(&mut segments, &mut text);
+                    segments.push(Segment::Mention { user_id, username });
+                    rest = &rest[1 + name_len..];
+                    continue;
+                }
+            }
+        }
 
-    /// Remove reaction from message
-    pub fn remove_reaction(&mut self, emoji: &str, user_id: Uuid) {
-        if let Some(users) = self.reactions.get_mut(emoji) {
-            users.retain(|&id| id != user_id);
-            if users.is_empty() {
-                self.reactions.remove(emoji);
+        if c == '[' {
+            if let Some(end) = rest.find(']') {
+                if let Some((kind, value)) = rest[1..end].split_once(':') {
+                    if let Some(entity) = resolve_entity(kind, value.trim(), resolver)? {
+                        flush(&mut segments, &mut text);
+                        segments.push(Segment::Entity { entity });
+                        rest = &rest[end + 1..];
+                        continue;
+                    }
+                }
             }
         }
+
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
     }
+
+    flush(&mut segments, &mut text);
+    Ok(segments)
+}
+
+/// `None` for tags that are not entity kinds, which are left as text
+fn resolve_entity(kind: &str, value: &str, resolver: &dyn EntityResolver) -> Result<Option<EntityRef>, ChatError> {
+    let invalid = || ChatError::InvalidReference(format!("{}:{}", kind, value));
+    let entity = match kind {
+        "player" => {
+            let (id, name) = resolver.player(value).ok_or_else(invalid)?;
+            EntityRef::Player { id, name }
+        }
+        "clan" => {
+            let (id, tag) = resolver.clan(value).ok_or_else(invalid)?;
+            EntityRef::Clan { id, tag }
+        }
+        "ip" => {
+            let address: IpAddr = value.parse().map_err(|_| invalid())?;
+            if !resolver.ip(address) {
+                return Err(invalid());
+            }
+            EntityRef::Ip { address }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(entity))
+}
+
+/// A short run of emoji: no letters, digits, spaces or control characters
+fn is_reaction(emoji: &str) -> bool {
+    let len = emoji.chars().count();
+    (1..=MAX_REACTION_CHARS).contains(&len)
+        && emoji.chars().all(|c| !c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace() && !c.is_control())
 }
 
 /// Chat system errors
@@ -391,6 +782,14 @@ pub enum ChatError {
     UserNotFound,
     #[error("Message too long")]
     MessageTooLong,
+    #[error("Message not found")]
+    MessageNotFound,
+    #[error("Message can no longer be changed")]
+    EditWindowClosed,
+    #[error("Unknown reference: {0}")]
+    InvalidReference(String),
+    #[error("Not a valid reaction")]
+    InvalidReaction,
 }
 
 #[cfg(test)]
@@ -432,4 +831,112 @@ mod tests {
         message.remove_reaction("👍", user_id);
         assert!(!message.reactions.contains_key("👍"));
     }
+
+    struct World {
+        alice: Uuid,
+        bob: Uuid,
+        clan: Uuid,
+    }
+
+    impl EntityResolver for World {
+        fn player(&self, username: &str) -> Option<(Uuid, String)> {
+            match username.to_lowercase().as_str() {
+                "alice" => Some((self.alice, "Alice".to_string())),
+                "bob" => Some((self.bob, "Bob".to_string())),
+                _ => None,
+            }
+        }
+
+        fn clan(&self, tag: &str) -> Option<(Uuid, String)> {
+            tag.eq_ignore_ascii_case("h4x").then(|| (self.clan, "H4X".to_string()))
+        }
+
+        fn ip(&self, address: IpAddr) -> bool {
+            address == IpAddr::from([1, 2, 3, 4])
+        }
+    }
+
+    fn world() -> World {
+        World { alice: Uuid::new_v4(), bob: Uuid::new_v4(), clan: Uuid::new_v4() }
+    }
+
+    #[test]
+    fn test_parse_content() {
+        let world = world();
+        let segments = parse_content("hey @bob, [clan:h4x] owns [ip:1.2.3.4]; mail me@x.com @nobody", &world).unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Text { text: "hey ".to_string() },
+                Segment::Mention { user_id: world.bob, username: "Bob".to_string() },
+                Segment::Text { text: ", ".to_string() },
+                Segment::Entity { entity: EntityRef::Clan { id: world.clan, tag: "H4X".to_string() } },
+                Segment::Text { text: " owns ".to_string() },
+                Segment::Entity { entity: EntityRef::Ip { address: IpAddr::from([1, 2, 3, 4]) } },
+                Segment::Text { text: "; mail me@x.com @nobody".to_string() },
+            ]
+        );
+
+        assert!(matches!(parse_content("[player:mallory]", &world), Err(ChatError::InvalidReference(_))));
+        assert!(matches!(parse_content("[ip:5.6.7.8]", &world), Err(ChatError::InvalidReference(_))));
+        assert_eq!(parse_content("[note:hi]", &world).unwrap(), vec![Segment::Text { text: "[note:hi]".to_string() }]);
+    }
+
+    #[test]
+    fn test_mentions_notify_once() {
+        let world = world();
+        let mut room = ChatRoom::new("global", "Global Chat", RoomType::Public);
+        let posted = room.post(world.alice, "Alice".to_string(), "@bob @Bob @alice look", None, &world).unwrap();
+
+        assert_eq!(posted.notifications.len(), 1);
+        assert_eq!(posted.notifications[0].recipient_id, world.bob);
+        let ChatEvent::MessagePosted { message, .. } = posted.event else { panic!("expected a new message") };
+
+        // Bob was notified when the message was posted
+        let edited = room.edit_message(message.id, world.alice, "@bob, see [player:alice]", &world).unwrap();
+        assert!(edited.notifications.is_empty());
+        assert!(matches!(
+            room.edit_message(message.id, world.bob, "mine now", &world),
+            Err(ChatError::InsufficientPermissions)
+        ));
+    }
+
+    #[test]
+    fn test_edit_and_delete_window() {
+        let world = world();
+        let mut room = ChatRoom::new("global", "Global Chat", RoomType::Public);
+        let moderator = Uuid::new_v4();
+        room.add_moderator(moderator);
+        let ChatEvent::MessagePosted { message, .. } =
+            room.post(world.alice, "Alice".to_string(), "typo", None, &world).unwrap().event
+        else {
+            panic!("expected a new message")
+        };
+
+        room.messages.back_mut().unwrap().timestamp = Utc::now() - chrono::Duration::minutes(10);
+        assert!(matches!(room.edit_message(message.id, world.alice, "fixed", &world), Err(ChatError::EditWindowClosed)));
+        assert!(matches!(room.delete_message(message.id, world.alice), Err(ChatError::EditWindowClosed)));
+
+        assert!(matches!(room.delete_message(message.id, moderator), Ok(ChatEvent::MessageDeleted { .. })));
+        assert!(room.messages.back().unwrap().deleted);
+        assert!(matches!(room.react(message.id, world.bob, "👍"), Err(ChatError::MessageNotFound)));
+    }
+
+    #[test]
+    fn test_reaction_counts() {
+        let world = world();
+        let mut room = ChatRoom::new("global", "Global Chat", RoomType::Public);
+        let ChatEvent::MessagePosted { message, .. } =
+            room.post(world.alice, "Alice".to_string(), "gg", None, &world).unwrap().event
+        else {
+            panic!("expected a new message")
+        };
+
+        assert!(matches!(room.react(message.id, world.alice, "🔥"), Ok(Some(ChatEvent::ReactionAdded { count: 1, .. }))));
+        assert!(matches!(room.react(message.id, world.bob, "🔥"), Ok(Some(ChatEvent::ReactionAdded { count: 2, .. }))));
+        assert!(matches!(room.react(message.id, world.bob, "🔥"), Ok(None)));
+        assert!(matches!(room.react(message.id, world.bob, "lol"), Err(ChatError::InvalidReaction)));
+        assert!(matches!(room.unreact(message.id, world.alice, "🔥"), Ok(Some(ChatEvent::ReactionRemoved { count: 1, .. }))));
+        assert_eq!(room.messages.back().unwrap().reaction_counts()["🔥"], 1);
+    }
 }
\ No newline at end of file
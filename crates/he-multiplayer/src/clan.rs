@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use std::collections::{HashMap, HashSet};
+use he_game_mechanics::clan_server::{self, Levels, Part};
+use he_game_mechanics::config::ClanServerConfig;
 
 /// Clan structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +26,9 @@ pub struct Clan {
     pub settings: ClanSettings,
     pub stats: ClanStatistics,
     pub upgrades: ClanUpgrades,
+    /// Hardware levels of the clan server
+    #[serde(default)]
+    pub server: Levels,
 }
 
 /// Clan settings
@@ -161,6 +166,7 @@ impl Clan {
             settings: ClanSettings::default(),
             stats: ClanStatistics::default(),
             upgrades: ClanUpgrades::default(),
+            server: Levels::default(),
         }
     }
 
@@ -285,6 +291,24 @@ impl Clan {
 
         Ok(())
     }
+
+    /// Buy the next level of a clan server part from the clan bank
+    /// (leader/officers only), returning what it cost
+    pub fn upgrade_server(&mut self, part: Part, requester: Uuid) -> Result<i64, ClanError> {
+        if requester != self.leader_id && !self.officers.contains(&requester) {
+            return Err(ClanError::InsufficientPermissions);
+        }
+
+        let cost = clan_server::upgrade_cost(part, self.server.get(part), &ClanServerConfig::default())
+            .ok_or(ClanError::MaxLevel)?;
+        if self.bank_balance < cost {
+            return Err(ClanError::InsufficientFunds);
+        }
+
+        self.bank_balance -= cost;
+        self.server.raise(part);
+        Ok(cost)
+    }
 }
 
 /// Clan upgrade types
@@ -373,6 +397,8 @@ pub enum ClanError {
     NameTaken,
     #[error("Invalid clan tag")]
     InvalidTag,
+    #[error("Clan server part is fully upgraded")]
+    MaxLevel,
 }
 
 #[cfg(test)]
@@ -402,4 +428,18 @@ mod tests {
         assert!(clan.promote_member(member_id).is_ok());
         assert!(clan.officers.contains(&member_id));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_upgrade_server() {
+        let leader_id = Uuid::new_v4();
+        let mut clan = Clan::new("Test Clan".to_string(), "TEST".to_string(), leader_id);
+
+        assert!(matches!(clan.upgrade_server(Part::Cpu, leader_id), Err(ClanError::InsufficientFunds)));
+        clan.deposit(10_000_000);
+        assert!(matches!(clan.upgrade_server(Part::Cpu, Uuid::new_v4()), Err(ClanError::InsufficientPermissions)));
+
+        let cost = clan.upgrade_server(Part::Cpu, leader_id).unwrap();
+        assert_eq!(clan.server.cpu, 1);
+        assert_eq!(clan.bank_balance, 10_000_000 - cost);
+    }
+}
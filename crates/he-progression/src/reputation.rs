@@ -1,7 +1,9 @@
 //! Reputation System - Faction standings and relationships
 
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use uuid::Uuid;
 
 /// Reputation system managing faction relationships
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,6 +12,8 @@ pub struct ReputationSystem {
     pub total_reputation: i32,
     pub highest_standing: Option<String>,
     pub faction_bonuses: HashMap<String, Vec<ReputationBonus>>,
+    #[serde(default)]
+    pub corporation_standings: HashMap<Uuid, CorporationStanding>,
 }
 
 /// Standing with a specific faction
@@ -79,6 +83,7 @@ impl ReputationSystem {
             total_reputation: 0,
             highest_standing: None,
             faction_bonuses: HashMap::new(),
+            corporation_standings: HashMap::new(),
         };
 
         // Initialize default factions
@@ -341,6 +346,221 @@ impl ReputationSystem {
     }
 }
 
+/// Standing gained per point of bounty reputation reward
+const BOUNTY_STANDING_MULTIPLIER: i32 = 1;
+/// Standing lost when a hack against the corporation goes unnoticed
+const HACK_STANDING_PENALTY: i32 = 150;
+/// Standing lost when the corporation traces the hack back to the player
+const DETECTED_HACK_STANDING_PENALTY: i32 = 400;
+/// Days of inactivity before standing starts to decay
+const STANDING_DECAY_GRACE_DAYS: i64 = 7;
+/// Standing moved towards zero per day once decay starts
+const STANDING_DECAY_PER_DAY: i32 = 25;
+const MIN_CORPORATION_STANDING: i32 = -3000;
+const MAX_CORPORATION_STANDING: i32 = 6000;
+
+/// Standing with an NPC corporation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorporationStanding {
+    pub corporation_id: Uuid,
+    pub corporation_name: String,
+    pub standing: i32,
+    pub tier: StandingTier,
+    pub bounties_completed: u32,
+    pub servers_hacked: u32,
+    pub last_activity: DateTime<Utc>,
+    /// Last time decay was applied, so repeated ticks don't double count
+    pub last_decay: DateTime<Utc>,
+}
+
+/// Standing tiers with a corporation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StandingTier {
+    Blacklisted, // -1500 or less
+    Distrusted,  // -1499 to -1
+    Neutral,     // 0 to 499
+    Contractor,  // 500 to 1499
+    Partner,     // 1500 to 3499
+    InnerCircle, // 3500+
+}
+
+impl StandingTier {
+    pub fn from_standing(standing: i32) -> Self {
+        match standing {
+            s if s <= -1500 => StandingTier::Blacklisted,
+            s if s < 0 => StandingTier::Distrusted,
+            s if s < 500 => StandingTier::Neutral,
+            s if s < 1500 => StandingTier::Contractor,
+            s if s < 3500 => StandingTier::Partner,
+            _ => StandingTier::InnerCircle,
+        }
+    }
+
+    /// Discount applied in the corporation's store
+    pub fn store_discount(&self) -> f32 {
+        match self {
+            StandingTier::Contractor => 5.0,
+            StandingTier::Partner => 12.0,
+            StandingTier::InnerCircle => 20.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Highest contract tier the corporation will offer (0 = none)
+    pub fn max_contract_tier(&self) -> u8 {
+        match self {
+            StandingTier::Blacklisted => 0,
+            StandingTier::Distrusted => 1,
+            StandingTier::Neutral => 2,
+            StandingTier::Contractor => 3,
+            StandingTier::Partner => 4,
+            StandingTier::InnerCircle => 5,
+        }
+    }
+}
+
+/// Actions that affect corporation standing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CorporationAction {
+    BountyCompleted { reward_reputation: i32 },
+    ServerHacked { detected: bool },
+}
+
+/// Result of a corporation standing change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StandingChange {
+    pub corporation_id: Uuid,
+    pub change: i32,
+    pub new_standing: i32,
+    pub old_tier: StandingTier,
+    pub new_tier: StandingTier,
+}
+
+impl CorporationStanding {
+    fn new(corporation_id: Uuid, corporation_name: &str, now: DateTime<Utc>) -> Self {
+        Self {
+            corporation_id,
+            corporation_name: corporation_name.to_string(),
+            standing: 0,
+            tier: StandingTier::Neutral,
+            bounties_completed: 0,
+            servers_hacked: 0,
+            last_activity: now,
+            last_decay: now,
+        }
+    }
+
+    fn adjust(&mut self, amount: i32) -> i32 {
+        let old = self.standing;
+        self.standing = (self.standing + amount).clamp(MIN_CORPORATION_STANDING, MAX_CORPORATION_STANDING);
+        self.tier = StandingTier::from_standing(self.standing);
+        self.standing - old
+    }
+}
+
+impl ReputationSystem {
+    /// Record an action against (or for) a corporation
+    pub fn record_corporation_action(
+        &mut self,
+        corporation_id: Uuid,
+        corporation_name: &str,
+        action: CorporationAction,
+        now: DateTime<Utc>,
+    ) -> StandingChange {
+        let standing = self
+            .corporation_standings
+            .entry(corporation_id)
+            .or_insert_with(|| CorporationStanding::new(corporation_id, corporation_name, now));
+
+        let old_tier = standing.tier;
+        let amount = match action {
+            CorporationAction::BountyCompleted { reward_reputation } => {
+                standing.bounties_completed += 1;
+                reward_reputation.max(0) * BOUNTY_STANDING_MULTIPLIER
+            }
+            CorporationAction::ServerHacked { detected } => {
+                standing.servers_hacked += 1;
+                if detected {
+                    -DETECTED_HACK_STANDING_PENALTY
+                } else {
+                    -HACK_STANDING_PENALTY
+                }
+            }
+        };
+
+        let change = standing.adjust(amount);
+        standing.last_activity = now;
+        standing.last_decay = now;
+
+        StandingChange {
+            corporation_id,
+            change,
+            new_standing: standing.standing,
+            old_tier,
+            new_tier: standing.tier,
+        }
+    }
+
+    /// Move idle standings towards neutral. Returns the corporations whose tier changed.
+    pub fn decay_corporation_standings(&mut self, now: DateTime<Utc>) -> Vec<StandingChange> {
+        let mut changes = Vec::new();
+
+        for standing in self.corporation_standings.values_mut() {
+            let decay_start = standing.last_activity + Duration::days(STANDING_DECAY_GRACE_DAYS);
+            let from = standing.last_decay.max(decay_start);
+            let days = (now - from).num_days();
+            if days <= 0 || standing.standing == 0 {
+                continue;
+            }
+
+            let old_tier = standing.tier;
+            let decay = (days as i32).saturating_mul(STANDING_DECAY_PER_DAY).min(standing.standing.abs());
+            let change = standing.adjust(-decay * standing.standing.signum());
+            standing.last_decay = from + Duration::days(days);
+
+            if standing.tier != old_tier {
+                changes.push(StandingChange {
+                    corporation_id: standing.corporation_id,
+                    change,
+                    new_standing: standing.standing,
+                    old_tier,
+                    new_tier: standing.tier,
+                });
+            }
+        }
+
+        changes
+    }
+
+    /// Get standing with a corporation, if the player has interacted with it
+    pub fn corporation_standing(&self, corporation_id: &Uuid) -> Option<&CorporationStanding> {
+        self.corporation_standings.get(corporation_id)
+    }
+
+    /// Standing tier with a corporation (neutral if never interacted)
+    pub fn corporation_tier(&self, corporation_id: &Uuid) -> StandingTier {
+        self.corporation_standing(corporation_id)
+            .map(|s| s.tier)
+            .unwrap_or(StandingTier::Neutral)
+    }
+
+    /// Whether the corporation will offer a contract of this tier and reputation requirement
+    pub fn can_take_contract(&self, corporation_id: &Uuid, contract_tier: u8, reputation_required: i32) -> bool {
+        let standing = self.corporation_standing(corporation_id).map(|s| s.standing).unwrap_or(0);
+        let tier = StandingTier::from_standing(standing);
+
+        tier != StandingTier::Blacklisted
+            && contract_tier <= tier.max_contract_tier()
+            && standing >= reputation_required
+    }
+
+    /// Store discount percentage with a corporation
+    pub fn corporation_store_discount(&self, corporation_id: &Uuid) -> f32 {
+        self.corporation_tier(corporation_id).store_discount()
+    }
+}
+
 /// Result of a reputation change
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReputationChange {
@@ -387,4 +607,66 @@ mod tests {
         assert_eq!(ReputationSystem::calculate_level(1000), ReputationLevel::Friendly);
         assert_eq!(ReputationSystem::calculate_level(5500), ReputationLevel::Exalted);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_corporation_standing_gain_and_loss() {
+        let mut rep_system = ReputationSystem::new();
+        let corp = Uuid::new_v4();
+        let now = Utc::now();
+
+        let change = rep_system.record_corporation_action(
+            corp, "MegaCorp", CorporationAction::BountyCompleted { reward_reputation: 600 }, now,
+        );
+        assert_eq!(change.new_tier, StandingTier::Contractor);
+        assert_eq!(rep_system.corporation_store_discount(&corp), 5.0);
+        assert!(rep_system.can_take_contract(&corp, 3, 500));
+        assert!(!rep_system.can_take_contract(&corp, 4, 500));
+
+        let change = rep_system.record_corporation_action(
+            corp, "MegaCorp", CorporationAction::ServerHacked { detected: true }, now,
+        );
+        assert_eq!(change.change, -DETECTED_HACK_STANDING_PENALTY);
+        assert_eq!(change.new_tier, StandingTier::Neutral);
+        assert_eq!(rep_system.corporation_store_discount(&corp), 0.0);
+    }
+
+    #[test]
+    fn test_blacklisted_corporation_offers_nothing() {
+        let mut rep_system = ReputationSystem::new();
+        let corp = Uuid::new_v4();
+        let now = Utc::now();
+
+        for _ in 0..4 {
+            rep_system.record_corporation_action(corp, "CyberBank", CorporationAction::ServerHacked { detected: true }, now);
+        }
+
+        assert_eq!(rep_system.corporation_tier(&corp), StandingTier::Blacklisted);
+        assert!(!rep_system.can_take_contract(&corp, 0, i32::MIN));
+    }
+
+    #[test]
+    fn test_corporation_standing_decay() {
+        let mut rep_system = ReputationSystem::new();
+        let corp = Uuid::new_v4();
+        let start = Utc::now();
+
+        rep_system.record_corporation_action(corp, "SecureNet", CorporationAction::BountyCompleted { reward_reputation: 520 }, start);
+
+        // Nothing decays inside the grace period
+        assert!(rep_system.decay_corporation_standings(start + Duration::days(STANDING_DECAY_GRACE_DAYS)).is_empty());
+        assert_eq!(rep_system.corporation_standing(&corp).unwrap().standing, 520);
+
+        // One day past the grace period drops the player back to neutral
+        let changes = rep_system.decay_corporation_standings(start + Duration::days(STANDING_DECAY_GRACE_DAYS + 1));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].new_tier, StandingTier::Neutral);
+
+        // Re-running for the same instant doesn't decay again
+        rep_system.decay_corporation_standings(start + Duration::days(STANDING_DECAY_GRACE_DAYS + 1));
+        assert_eq!(rep_system.corporation_standing(&corp).unwrap().standing, 520 - STANDING_DECAY_PER_DAY);
+
+        // Decay never overshoots neutral
+        rep_system.decay_corporation_standings(start + Duration::days(365));
+        assert_eq!(rep_system.corporation_standing(&corp).unwrap().standing, 0);
+    }
+}
@@ -0,0 +1,298 @@
+//! Public status page for HackerExperience
+//!
+//! Shows the current health of each component, 90 days of uptime and the
+//! incidents posted by operators. Health samples and incidents are kept in the
+//! log database by [`StatusStore`]; [`StatusReport`] is what `/status.json`
+//! returns and what [`render_page`] turns into HTML.
+
+mod page;
+mod store;
+
+pub use page::render_page;
+pub use store::StatusStore;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use he_monitoring::HealthStatus;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Days of uptime history kept and shown
+pub const HISTORY_DAYS: i64 = 90;
+
+/// Components shown on the page, as (key, display name)
+pub const COMPONENTS: &[(&str, &str)] = &[
+    ("api", "API"),
+    ("websocket", "WebSocket"),
+    ("database", "Database"),
+    ("cache", "Cache"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentState {
+    Operational,
+    /// Checks pass, but an open incident affects the component
+    Degraded,
+    Outage,
+    /// Not checked since the server started
+    Unknown,
+}
+
+impl ComponentState {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Operational => "Operational",
+            Self::Degraded => "Degraded",
+            Self::Outage => "Outage",
+            Self::Unknown => "Unknown",
+        }
+    }
+
+    fn severity(self) -> u8 {
+        match self {
+            Self::Operational => 0,
+            Self::Unknown => 1,
+            Self::Degraded => 2,
+            Self::Outage => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncidentStatus {
+    Investigating,
+    Identified,
+    Monitoring,
+    Resolved,
+}
+
+impl IncidentStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Investigating => "investigating",
+            Self::Identified => "identified",
+            Self::Monitoring => "monitoring",
+            Self::Resolved => "resolved",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "investigating" => Some(Self::Investigating),
+            "identified" => Some(Self::Identified),
+            "monitoring" => Some(Self::Monitoring),
+            "resolved" => Some(Self::Resolved),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncidentImpact {
+    Minor,
+    Major,
+    Critical,
+}
+
+impl IncidentImpact {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Minor => "minor",
+            Self::Major => "major",
+            Self::Critical => "critical",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "minor" => Some(Self::Minor),
+            "major" => Some(Self::Major),
+            "critical" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IncidentUpdate {
+    pub status: IncidentStatus,
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Incident {
+    pub id: i64,
+    pub title: String,
+    pub impact: IncidentImpact,
+    pub status: IncidentStatus,
+    /// Component keys from [`COMPONENTS`]
+    pub components: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// Newest first
+    pub updates: Vec<IncidentUpdate>,
+}
+
+impl Incident {
+    pub fn is_open(&self) -> bool {
+        self.status != IncidentStatus::Resolved
+    }
+}
+
+/// Health checks for one component on one day
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DayUptime {
+    pub date: NaiveDate,
+    pub checks: i64,
+    pub healthy_checks: i64,
+}
+
+impl DayUptime {
+    /// `None` if the component was not checked that day
+    pub fn uptime(&self) -> Option<f64> {
+        (self.checks > 0).then(|| self.healthy_checks as f64 / self.checks as f64 * 100.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentReport {
+    pub key: String,
+    pub name: String,
+    pub state: ComponentState,
+    pub message: String,
+    /// Percentage over the days that have checks
+    pub uptime: Option<f64>,
+    /// Oldest first, one entry per day of [`HISTORY_DAYS`]
+    pub history: Vec<DayUptime>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusReport {
+    pub state: ComponentState,
+    pub components: Vec<ComponentReport>,
+    /// Open incidents plus those resolved within the history window
+    pub incidents: Vec<Incident>,
+    pub generated_at: DateTime<Utc>,
+}
+
+impl StatusReport {
+    /// Combine the latest health checks with the stored history. `latest` is
+    /// keyed by the [`HealthStatus`] name, which must match a component key.
+    pub fn build(
+        latest: &[HealthStatus],
+        history: &HashMap<String, Vec<DayUptime>>,
+        incidents: Vec<Incident>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let today = now.date_naive();
+        let first_day = today - chrono::Duration::days(HISTORY_DAYS - 1);
+
+        let components: Vec<ComponentReport> = COMPONENTS
+            .iter()
+            .map(|(key, name)| {
+                let check = latest.iter().find(|status| status.name == *key);
+                let affected = incidents
+                    .iter()
+                    .any(|incident| incident.is_open() && incident.components.iter().any(|c| c == key));
+
+                let (state, message) = match check {
+                    Some(status) if !status.healthy => (ComponentState::Outage, status.message.clone()),
+                    Some(status) if affected => (ComponentState::Degraded, status.message.clone()),
+                    Some(status) => (ComponentState::Operational, status.message.clone()),
+                    None => (ComponentState::Unknown, "Not checked yet".to_string()),
+                };
+
+                let days = history.get(*key).map(Vec::as_slice).unwrap_or_default();
+                let history: Vec<DayUptime> = first_day
+                    .iter_days()
+                    .take_while(|date| *date <= today)
+                    .map(|date| {
+                        days.iter()
+                            .find(|day| day.date == date)
+                            .copied()
+                            .unwrap_or(DayUptime { date, checks: 0, healthy_checks: 0 })
+                    })
+                    .collect();
+
+                let checks: i64 = history.iter().map(|day| day.checks).sum();
+                let healthy: i64 = history.iter().map(|day| day.healthy_checks).sum();
+                let uptime = (checks > 0).then(|| healthy as f64 / checks as f64 * 100.0);
+
+                ComponentReport {
+                    key: key.to_string(),
+                    name: name.to_string(),
+                    state,
+                    message,
+                    uptime,
+                    history,
+                }
+            })
+            .collect();
+
+        let state = components
+            .iter()
+            .map(|component| component.state)
+            .max_by_key(|state| state.severity())
+            .unwrap_or(ComponentState::Unknown);
+
+        Self {
+            state,
+            components,
+            incidents,
+            generated_at: now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn status(name: &str, healthy: bool) -> HealthStatus {
+        HealthStatus {
+            name: name.to_string(),
+            healthy,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_report_fills_history_and_ranks_state() {
+        let now = Utc.with_ymd_and_hms(2024, 10, 1, 12, 0, 0).unwrap();
+        let today = now.date_naive();
+
+        let mut history = HashMap::new();
+        history.insert("api".to_string(), vec![
+            DayUptime { date: today, checks: 4, healthy_checks: 3 },
+            DayUptime { date: today - chrono::Duration::days(1), checks: 4, healthy_checks: 4 },
+        ]);
+
+        let incident = Incident {
+            id: 1,
+            title: "Slow logins".to_string(),
+            impact: IncidentImpact::Minor,
+            status: IncidentStatus::Investigating,
+            components: vec!["database".to_string()],
+            created_at: now,
+            resolved_at: None,
+            updates: Vec::new(),
+        };
+
+        let latest = vec![status("api", true), status("websocket", true), status("database", true)];
+        let report = StatusReport::build(&latest, &history, vec![incident], now);
+
+        let api = &report.components[0];
+        assert_eq!(api.history.len(), HISTORY_DAYS as usize);
+        assert_eq!(api.history.last().unwrap().date, today);
+        assert_eq!(api.uptime, Some(87.5));
+        assert_eq!(api.state, ComponentState::Operational);
+
+        assert_eq!(report.components[2].state, ComponentState::Degraded);
+        assert_eq!(report.components[3].state, ComponentState::Unknown);
+        assert_eq!(report.state, ComponentState::Degraded);
+    }
+}
@@ -0,0 +1,160 @@
+//! Server-rendered status page
+
+use crate::{ComponentReport, ComponentState, DayUptime, Incident, StatusReport};
+use leptos::*;
+use leptos_meta::*;
+
+/// Server-side renders the status page for `report`
+pub fn render_page(report: &StatusReport) -> String {
+    let report = report.clone();
+    leptos::ssr::render_to_string(move || {
+        provide_meta_context();
+        view! {
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta charset="utf-8"/>
+                    <meta name="viewport" content="width=device-width, initial-scale=1"/>
+                    <meta http-equiv="refresh" content="60"/>
+                    <Title text="HackerExperience - System Status"/>
+                    <Style>{include_str!("../assets/status.css")}</Style>
+                </head>
+                <body>
+                    <StatusPage report=report.clone()/>
+                </body>
+            </html>
+        }
+    }).to_string()
+}
+
+fn state_class(state: ComponentState) -> &'static str {
+    match state {
+        ComponentState::Operational => "state state--operational",
+        ComponentState::Degraded => "state state--degraded",
+        ComponentState::Outage => "state state--outage",
+        ComponentState::Unknown => "state state--unknown",
+    }
+}
+
+fn summary(state: ComponentState) -> &'static str {
+    match state {
+        ComponentState::Operational => "All systems operational",
+        ComponentState::Degraded => "Some systems are degraded",
+        ComponentState::Outage => "Some systems are down",
+        ComponentState::Unknown => "Status is being checked",
+    }
+}
+
+fn day_class(day: &DayUptime) -> &'static str {
+    match day.uptime() {
+        None => "day day--none",
+        Some(uptime) if uptime >= 99.9 => "day day--up",
+        Some(uptime) if uptime >= 95.0 => "day day--partial",
+        Some(_) => "day day--down",
+    }
+}
+
+fn day_title(day: &DayUptime) -> String {
+    match day.uptime() {
+        Some(uptime) => format!("{}: {:.2}% uptime", day.date, uptime),
+        None => format!("{}: no data", day.date),
+    }
+}
+
+// ================ Components ================
+
+#[component]
+fn StatusPage(report: StatusReport) -> impl IntoView {
+    let (open, past): (Vec<_>, Vec<_>) = report.incidents.into_iter().partition(Incident::is_open);
+
+    view! {
+        <header class="header">
+            <div class="logo">{"HackerExperience"}</div>
+            <h1 class="title">"System Status"</h1>
+            <nav class="nav">
+                <a href="/status.json" class="nav-link">"JSON"</a>
+                <a href="/" class="nav-link">"Back to Game"</a>
+            </nav>
+        </header>
+
+        <main class="main">
+            <section class=format!("panel banner {}", state_class(report.state))>
+                <h2>{summary(report.state)}</h2>
+                <p class="muted">{format!("Updated {}", report.generated_at.format("%Y-%m-%d %H:%M UTC"))}</p>
+            </section>
+
+            {(!open.is_empty()).then(|| view! {
+                <section class="panel">
+                    <h2>"Active incidents"</h2>
+                    {open.into_iter().map(|incident| view! { <IncidentCard incident=incident/> }).collect_view()}
+                </section>
+            })}
+
+            <section class="panel">
+                <h2>"Components"</h2>
+                {report.components.into_iter().map(|component| view! { <ComponentRow component=component/> }).collect_view()}
+                <p class="legend muted">"90 days ago"<span>"Today"</span></p>
+            </section>
+
+            <section class="panel">
+                <h2>"Past incidents"</h2>
+                {if past.is_empty() {
+                    view! { <p class="muted">"No incidents in the last 90 days."</p> }.into_view()
+                } else {
+                    past.into_iter().map(|incident| view! { <IncidentCard incident=incident/> }).collect_view()
+                }}
+            </section>
+        </main>
+    }
+}
+
+#[component]
+fn ComponentRow(component: ComponentReport) -> impl IntoView {
+    let uptime = component
+        .uptime
+        .map(|uptime| format!("{:.2}% uptime", uptime))
+        .unwrap_or_else(|| "No data".to_string());
+
+    view! {
+        <div class="component">
+            <div class="component__head">
+                <strong>{component.name}</strong>
+                <span class=state_class(component.state) title=component.message>
+                    {component.state.label()}
+                </span>
+            </div>
+            <div class="history">
+                {component.history.iter().map(|day| view! {
+                    <span class=day_class(day) title=day_title(day)></span>
+                }).collect_view()}
+            </div>
+            <p class="muted">{uptime}</p>
+        </div>
+    }
+}
+
+#[component]
+fn IncidentCard(incident: Incident) -> impl IntoView {
+    let impact_class = format!("incident incident--{}", incident.impact.as_str());
+    let components = incident.components.join(", ");
+
+    view! {
+        <article class=impact_class>
+            <h3>{incident.title}</h3>
+            <p class="muted">
+                {format!("Opened {}", incident.created_at.format("%Y-%m-%d %H:%M UTC"))}
+                {incident.resolved_at.map(|at| format!(" · Resolved {}", at.format("%Y-%m-%d %H:%M UTC")))}
+                {(!components.is_empty()).then(|| format!(" · Affects {}", components))}
+            </p>
+            <ul class="updates">
+                {incident.updates.into_iter().map(|update| view! {
+                    <li>
+                        <strong>{update.status.as_str()}</strong>
+                        {format!(" ({}) ", update.created_at.format("%H:%M UTC"))}
+                        {update.message}
+                    </li>
+                }).collect_view()}
+            </ul>
+        </article>
+    }
+}
@@ -0,0 +1,301 @@
+//! Uptime history and incidents in the log database
+//!
+//! The tables are created on startup, the same way the audit log creates its
+//! own, because the log database may be separate from the one the migrations
+//! run against. For the same reason the queries are not checked at compile
+//! time.
+
+use crate::{DayUptime, Incident, IncidentImpact, IncidentStatus, IncidentUpdate, HISTORY_DAYS};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use he_monitoring::HealthStatus;
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+
+const SCHEMA: &[&str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS status_checks (
+        component VARCHAR(32) NOT NULL,
+        day DATE NOT NULL,
+        checks BIGINT NOT NULL DEFAULT 0,
+        healthy_checks BIGINT NOT NULL DEFAULT 0,
+        last_checked_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        PRIMARY KEY (component, day)
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS status_incidents (
+        id BIGSERIAL PRIMARY KEY,
+        title VARCHAR(200) NOT NULL,
+        impact VARCHAR(16) NOT NULL,
+        status VARCHAR(16) NOT NULL,
+        components TEXT[] NOT NULL DEFAULT '{}',
+        created_by BIGINT,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+        resolved_at TIMESTAMPTZ
+    )
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS status_incident_updates (
+        id BIGSERIAL PRIMARY KEY,
+        incident_id BIGINT NOT NULL REFERENCES status_incidents(id) ON DELETE CASCADE,
+        status VARCHAR(16) NOT NULL,
+        message TEXT NOT NULL,
+        created_by BIGINT,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+    )
+    "#,
+    "CREATE INDEX IF NOT EXISTS idx_status_incident_updates_incident ON status_incident_updates(incident_id)",
+];
+
+#[derive(FromRow)]
+struct DayRow {
+    component: String,
+    day: NaiveDate,
+    checks: i64,
+    healthy_checks: i64,
+}
+
+#[derive(FromRow)]
+struct IncidentRow {
+    id: i64,
+    title: String,
+    impact: String,
+    status: String,
+    components: Vec<String>,
+    created_at: DateTime<Utc>,
+    resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+struct UpdateRow {
+    incident_id: i64,
+    status: String,
+    message: String,
+    created_at: DateTime<Utc>,
+}
+
+pub struct StatusStore {
+    pool: PgPool,
+}
+
+impl StatusStore {
+    pub async fn new(pool: PgPool) -> Result<Self> {
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        Ok(Self { pool })
+    }
+
+    /// Count one round of health checks towards today's uptime
+    pub async fn record(&self, statuses: &[HealthStatus], now: DateTime<Utc>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for status in statuses {
+            sqlx::query(
+                r#"
+                INSERT INTO status_checks (component, day, checks, healthy_checks, last_checked_at)
+                VALUES ($1, $2, 1, $3, $4)
+                ON CONFLICT (component, day) DO UPDATE
+                    SET checks = status_checks.checks + 1,
+                        healthy_checks = status_checks.healthy_checks + EXCLUDED.healthy_checks,
+                        last_checked_at = EXCLUDED.last_checked_at
+                "#,
+            )
+            .bind(&status.name)
+            .bind(now.date_naive())
+            .bind(status.healthy as i64)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Daily uptime per component within the history window
+    pub async fn history(&self, now: DateTime<Utc>) -> Result<HashMap<String, Vec<DayUptime>>> {
+        let since = now.date_naive() - chrono::Duration::days(HISTORY_DAYS - 1);
+        let rows: Vec<DayRow> = sqlx::query_as(
+            "SELECT component, day, checks, healthy_checks FROM status_checks WHERE day >= $1 ORDER BY day",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut history: HashMap<String, Vec<DayUptime>> = HashMap::new();
+        for row in rows {
+            history.entry(row.component).or_default().push(DayUptime {
+                date: row.day,
+                checks: row.checks,
+                healthy_checks: row.healthy_checks,
+            });
+        }
+        Ok(history)
+    }
+
+    /// Drop uptime rows older than the history window; returns rows removed
+    pub async fn prune(&self, now: DateTime<Utc>) -> Result<u64> {
+        let before = now.date_naive() - chrono::Duration::days(HISTORY_DAYS - 1);
+        let result = sqlx::query("DELETE FROM status_checks WHERE day < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Open incidents and those resolved within the history window, newest first
+    pub async fn incidents(&self, now: DateTime<Utc>) -> Result<Vec<Incident>> {
+        let since = now - chrono::Duration::days(HISTORY_DAYS);
+        let rows: Vec<IncidentRow> = sqlx::query_as(
+            r#"
+            SELECT id, title, impact, status, components, created_at, resolved_at
+            FROM status_incidents
+            WHERE resolved_at IS NULL OR resolved_at >= $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.with_updates(rows).await
+    }
+
+    pub async fn incident(&self, id: i64) -> Result<Option<Incident>> {
+        let row: Option<IncidentRow> = sqlx::query_as(
+            "SELECT id, title, impact, status, components, created_at, resolved_at FROM status_incidents WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(self.with_updates(row.into_iter().collect()).await?.pop())
+    }
+
+    async fn with_updates(&self, rows: Vec<IncidentRow>) -> Result<Vec<Incident>> {
+        let ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+        let updates: Vec<UpdateRow> = sqlx::query_as(
+            r#"
+            SELECT incident_id, status, message, created_at
+            FROM status_incident_updates
+            WHERE incident_id = ANY($1)
+            ORDER BY created_at DESC, id DESC
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut by_incident: HashMap<i64, Vec<IncidentUpdate>> = HashMap::new();
+        for update in updates {
+            let status = parse_status(&update.status)?;
+            by_incident.entry(update.incident_id).or_default().push(IncidentUpdate {
+                status,
+                message: update.message,
+                created_at: update.created_at,
+            });
+        }
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Incident {
+                    id: row.id,
+                    impact: IncidentImpact::parse(&row.impact)
+                        .ok_or_else(|| anyhow!("Unknown incident impact '{}'", row.impact))?,
+                    status: parse_status(&row.status)?,
+                    title: row.title,
+                    components: row.components,
+                    created_at: row.created_at,
+                    resolved_at: row.resolved_at,
+                    updates: by_incident.remove(&row.id).unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    /// Open an incident with its first update; returns its id
+    pub async fn create_incident(
+        &self,
+        title: &str,
+        impact: IncidentImpact,
+        components: &[String],
+        message: &str,
+        admin_id: i64,
+    ) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+
+        let (id,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO status_incidents (title, impact, status, components, created_by)
+            VALUES ($1, $2, 'investigating', $3, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(title)
+        .bind(impact.as_str())
+        .bind(components)
+        .bind(admin_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        insert_update(&mut tx, id, IncidentStatus::Investigating, message, admin_id).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// Post an update. Resolving sets `resolved_at`; a later non-resolved
+    /// update reopens the incident. Returns false if there is no such incident.
+    pub async fn update_incident(
+        &self,
+        id: i64,
+        status: IncidentStatus,
+        message: &str,
+        admin_id: i64,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE status_incidents
+            SET status = $2,
+                resolved_at = CASE WHEN $2 = 'resolved' THEN COALESCE(resolved_at, NOW()) ELSE NULL END
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        insert_update(&mut tx, id, status, message, admin_id).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+}
+
+async fn insert_update(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    incident_id: i64,
+    status: IncidentStatus,
+    message: &str,
+    admin_id: i64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO status_incident_updates (incident_id, status, message, created_by) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(incident_id)
+    .bind(status.as_str())
+    .bind(message)
+    .bind(admin_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+fn parse_status(value: &str) -> Result<IncidentStatus> {
+    IncidentStatus::parse(value).ok_or_else(|| anyhow!("Unknown incident status '{}'", value))
+}
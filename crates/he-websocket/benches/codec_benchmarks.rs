@@ -0,0 +1,51 @@
+//! Bandwidth and CPU comparison of WebSocket codecs
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use he_websocket::codec::{Compression, Encoding, SessionCodec, DEFAULT_COMPRESSION_THRESHOLD};
+use he_websocket::ServerMessage;
+use serde_json::json;
+
+fn world_map_message() -> ServerMessage {
+    let servers: Vec<_> = (0..1000)
+        .map(|i| json!({
+            "ip": format!("10.{}.{}.{}", i / 65536, (i / 256) % 256, i % 256),
+            "hostname": format!("npc-{}", i),
+            "owner": "NPC",
+            "firewall": 3.5,
+            "online": i % 7 != 0,
+        }))
+        .collect();
+
+    ServerMessage {
+        event_type: "world_map".to_string(),
+        data: json!({ "servers": servers }),
+    }
+}
+
+fn codecs() -> Vec<(&'static str, SessionCodec)> {
+    let deflate = Compression::Deflate { threshold: DEFAULT_COMPRESSION_THRESHOLD };
+    vec![
+        ("json", SessionCodec::new(Encoding::Json, Compression::None)),
+        ("msgpack", SessionCodec::new(Encoding::MessagePack, Compression::None)),
+        ("cbor", SessionCodec::new(Encoding::Cbor, Compression::None)),
+        ("json+deflate", SessionCodec::new(Encoding::Json, deflate)),
+        ("msgpack+deflate", SessionCodec::new(Encoding::MessagePack, deflate)),
+    ]
+}
+
+fn benchmark_encode(c: &mut Criterion) {
+    let msg = world_map_message();
+
+    for (name, codec) in codecs() {
+        // Criterion only reports time, so print the bandwidth side once per codec
+        let size = codec.encode(&msg).map(|frame| frame.len()).unwrap_or(0);
+        println!("{:>16}: {} bytes", name, size);
+
+        c.bench_function(&format!("encode_world_map_{}", name), |b| {
+            b.iter(|| codec.encode(black_box(&msg)))
+        });
+    }
+}
+
+criterion_group!(benches, benchmark_encode);
+criterion_main!(benches);
@@ -0,0 +1,142 @@
+//! Ownership-aware event routing
+//!
+//! Events can be addressed to an entity (a server, clan or process) instead of
+//! a user. A session receives an entity's events once it is subscribed to it:
+//! the entities its user owns are granted when it authenticates, the API
+//! grants new ones as it creates them, and a client may ask for any other
+//! entity's channel, which the [`EntityResolver`] has to allow.
+//!
+//! Decisions are cached per session for [`ACL_TTL`] and re-checked after
+//! that, so ownership changes such as leaving a clan take effect within that
+//! time, or straight away through [`ConnectionManager::revoke`].
+//!
+//! Moderator channels (`mod.reports`, `mod.chat-flags`) are entities too,
+//! allowed by permission rather than ownership. Nothing is granted on them at
+//! login; a moderator subscribes, and the decision is re-checked after the
+//! shorter [`MODERATION_ACL_TTL`].
+//!
+//! [`ConnectionManager::revoke`]: crate::ConnectionManager::revoke
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+/// How long a session's access decision is trusted before it is re-checked
+pub const ACL_TTL: Duration = Duration::from_secs(300);
+/// The same for moderator channels, so a revoked permission stops reports
+/// reaching a session quickly
+pub const MODERATION_ACL_TTL: Duration = Duration::from_secs(60);
+
+/// A channel only staff with the right permission may receive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModChannel {
+    /// Report cases opened, requeued and closed
+    Reports,
+    /// Chat messages the filter flagged
+    ChatFlags,
+}
+
+impl ModChannel {
+    pub const ALL: [ModChannel; 2] = [ModChannel::Reports, ModChannel::ChatFlags];
+
+    /// Channel name clients subscribe with
+    pub fn name(self) -> &'static str {
+        match self {
+            ModChannel::Reports => "mod.reports",
+            ModChannel::ChatFlags => "mod.chat-flags",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+/// Something events can be addressed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum Entity {
+    User(i64),
+    Server(i64),
+    Clan(i64),
+    Process(i64),
+    Moderation(ModChannel),
+}
+
+impl Entity {
+    /// Channel name clients subscribe with, e.g. `process:42`
+    pub fn channel(&self) -> String {
+        let (kind, id) = match self {
+            Entity::User(id) => ("user", id),
+            Entity::Server(id) => ("server", id),
+            Entity::Clan(id) => ("clan", id),
+            Entity::Process(id) => ("process", id),
+            Entity::Moderation(channel) => return channel.name().to_string(),
+        };
+        format!("{}:{}", kind, id)
+    }
+
+    /// The entity behind a channel name; `None` for plain channels like `world`
+    pub fn from_channel(channel: &str) -> Option<Self> {
+        if let Some(channel) = ModChannel::from_name(channel) {
+            return Some(Entity::Moderation(channel));
+        }
+        let (kind, id) = channel.split_once(':')?;
+        let id = id.parse().ok()?;
+        match kind {
+            "user" => Some(Entity::User(id)),
+            "server" => Some(Entity::Server(id)),
+            "clan" => Some(Entity::Clan(id)),
+            "process" => Some(Entity::Process(id)),
+            _ => None,
+        }
+    }
+
+    /// How long a decision about this entity is trusted
+    pub fn acl_ttl(&self) -> Duration {
+        match self {
+            Entity::Moderation(_) => MODERATION_ACL_TTL,
+            _ => ACL_TTL,
+        }
+    }
+}
+
+pub type ResolveFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Looks up who may see what. Implementations answer `false` or nothing when
+/// they cannot tell, so a lookup failure never leaks an event.
+pub trait EntityResolver: Send + Sync {
+    /// Entities the user owns or belongs to, granted when a session authenticates
+    fn entities(&self, user_id: i64) -> ResolveFuture<'_, Vec<Entity>>;
+
+    /// Whether the user may receive the entity's events
+    fn may_receive(&self, user_id: i64, entity: Entity) -> ResolveFuture<'_, bool>;
+}
+
+/// Access decisions for one session
+#[derive(Debug, Default)]
+pub struct SessionAcl {
+    decisions: HashMap<Entity, (bool, Instant)>,
+}
+
+impl SessionAcl {
+    /// The cached decision, if it is still fresh at `now`
+    pub fn get(&self, entity: &Entity, now: Instant) -> Option<bool> {
+        self.decisions
+            .get(entity)
+            .filter(|(_, at)| now.duration_since(*at) < entity.acl_ttl())
+            .map(|(allowed, _)| *allowed)
+    }
+
+    pub fn set(&mut self, entity: Entity, allowed: bool, now: Instant) {
+        self.decisions.insert(entity, (allowed, now));
+    }
+
+    /// Entities the session was allowed at some point
+    pub fn allowed(&self) -> impl Iterator<Item = &Entity> {
+        self.decisions.iter().filter(|(_, (allowed, _))| *allowed).map(|(entity, _)| entity)
+    }
+}
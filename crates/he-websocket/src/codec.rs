@@ -0,0 +1,271 @@
+//! Wire encoding for WebSocket messages
+//!
+//! Messages are JSON text frames by default. During the auth handshake a client
+//! can ask for a binary encoding (MessagePack or CBOR) and for deflate
+//! compression; the session then switches codecs after `auth_success`.
+//!
+//! actix-web-actors does not expose the RSV1 bit, so RFC 7692 framing cannot be
+//! used directly. A `permessage-deflate` offer in `Sec-WebSocket-Extensions`
+//! is therefore not consent: browsers send it by default and the handshake
+//! response never accepts it. Only a client that asks for
+//! `"compression": "deflate"` in the auth message gets payloads above a size
+//! threshold deflated at the message level and sent as binary frames.
+//! Client frames never inflate past [`MAX_INFLATED_SIZE`], so a small frame
+//! cannot expand into an unbounded allocation.
+
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use thiserror::Error;
+
+use crate::{ClientMessage, ServerMessage};
+
+/// Payloads smaller than this are not worth compressing
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Largest client payload accepted after inflating
+pub const MAX_INFLATED_SIZE: usize = 256 * 1024;
+
+/// First byte of every binary frame, so the receiver knows how to decode it
+const FLAG_PLAIN: u8 = 0x00;
+const FLAG_DEFLATE: u8 = 0x01;
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("Encode error: {0}")]
+    Encode(String),
+    #[error("Decode error: {0}")]
+    Decode(String),
+    #[error("Unexpected frame type for {0} codec")]
+    UnexpectedFrame(&'static str),
+    #[error("Frame inflates past {0} bytes")]
+    TooLarge(usize),
+}
+
+/// A single outgoing/incoming WebSocket payload
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Frame {
+    pub fn len(&self) -> usize {
+        match self {
+            Frame::Text(text) => text.len(),
+            Frame::Binary(bytes) => bytes.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Serialization strategy for session messages
+pub trait MessageCodec: Send + Sync {
+    /// Name advertised in the handshake (`"json"`, `"msgpack"`, `"cbor"`)
+    fn name(&self) -> &'static str;
+
+    fn encode(&self, msg: &ServerMessage) -> Result<Frame, CodecError>;
+
+    fn decode(&self, frame: Frame) -> Result<ClientMessage, CodecError>;
+}
+
+/// Encoding selected during the auth handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    Cbor,
+}
+
+impl Encoding {
+    /// Parse the `encoding` field of the auth message; unknown values fall back to JSON
+    pub fn from_handshake(value: Option<&str>) -> Self {
+        match value.map(|v| v.to_ascii_lowercase()).as_deref() {
+            Some("msgpack") | Some("messagepack") => Encoding::MessagePack,
+            Some("cbor") => Encoding::Cbor,
+            _ => Encoding::Json,
+        }
+    }
+}
+
+/// Compression selected during the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate { threshold: usize },
+}
+
+impl Compression {
+    /// Parse the `compression` field of the auth message; off unless asked for
+    pub fn from_handshake(value: Option<&str>) -> Self {
+        match value {
+            Some(v) if v.eq_ignore_ascii_case("deflate") => {
+                Compression::Deflate { threshold: DEFAULT_COMPRESSION_THRESHOLD }
+            }
+            _ => Compression::None,
+        }
+    }
+}
+
+/// JSON text frames, the default wire format
+pub struct JsonCodec;
+
+impl MessageCodec for JsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode(&self, msg: &ServerMessage) -> Result<Frame, CodecError> {
+        serde_json::to_string(msg)
+            .map(Frame::Text)
+            .map_err(|e| CodecError::Encode(e.to_string()))
+    }
+
+    fn decode(&self, frame: Frame) -> Result<ClientMessage, CodecError> {
+        match frame {
+            Frame::Text(text) => serde_json::from_str(&text).map_err(|e| CodecError::Decode(e.to_string())),
+            Frame::Binary(bytes) => serde_json::from_slice(&bytes).map_err(|e| CodecError::Decode(e.to_string())),
+        }
+    }
+}
+
+/// MessagePack binary frames
+pub struct MessagePackCodec;
+
+impl MessageCodec for MessagePackCodec {
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode(&self, msg: &ServerMessage) -> Result<Frame, CodecError> {
+        rmp_serde::to_vec_named(msg)
+            .map(Frame::Binary)
+            .map_err(|e| CodecError::Encode(e.to_string()))
+    }
+
+    fn decode(&self, frame: Frame) -> Result<ClientMessage, CodecError> {
+        match frame {
+            Frame::Binary(bytes) => rmp_serde::from_slice(&bytes).map_err(|e| CodecError::Decode(e.to_string())),
+            Frame::Text(_) => Err(CodecError::UnexpectedFrame("msgpack")),
+        }
+    }
+}
+
+/// CBOR binary frames
+pub struct CborCodec;
+
+impl MessageCodec for CborCodec {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode(&self, msg: &ServerMessage) -> Result<Frame, CodecError> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(msg, &mut buf).map_err(|e| CodecError::Encode(e.to_string()))?;
+        Ok(Frame::Binary(buf))
+    }
+
+    fn decode(&self, frame: Frame) -> Result<ClientMessage, CodecError> {
+        match frame {
+            Frame::Binary(bytes) => ciborium::from_reader(bytes.as_slice()).map_err(|e| CodecError::Decode(e.to_string())),
+            Frame::Text(_) => Err(CodecError::UnexpectedFrame("cbor")),
+        }
+    }
+}
+
+/// Codec negotiated for a session: an encoding plus optional compression
+pub struct SessionCodec {
+    inner: Box<dyn MessageCodec>,
+    compression: Compression,
+}
+
+impl Default for SessionCodec {
+    fn default() -> Self {
+        Self::new(Encoding::Json, Compression::None)
+    }
+}
+
+impl SessionCodec {
+    pub fn new(encoding: Encoding, compression: Compression) -> Self {
+        let inner: Box<dyn MessageCodec> = match encoding {
+            Encoding::Json => Box::new(JsonCodec),
+            Encoding::MessagePack => Box::new(MessagePackCodec),
+            Encoding::Cbor => Box::new(CborCodec),
+        };
+        Self { inner, compression }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Encode a message, deflating it when it exceeds the compression threshold.
+    ///
+    /// With compression enabled every frame is binary and starts with a flag byte.
+    pub fn encode(&self, msg: &ServerMessage) -> Result<Frame, CodecError> {
+        let frame = self.inner.encode(msg)?;
+
+        let Compression::Deflate { threshold } = self.compression else {
+            return Ok(frame);
+        };
+
+        let raw = match frame {
+            Frame::Text(text) => text.into_bytes(),
+            Frame::Binary(bytes) => bytes,
+        };
+
+        if raw.len() < threshold {
+            let mut out = Vec::with_capacity(raw.len() + 1);
+            out.push(FLAG_PLAIN);
+            out.extend_from_slice(&raw);
+            return Ok(Frame::Binary(out));
+        }
+
+        let mut encoder = flate2::write::DeflateEncoder::new(vec![FLAG_DEFLATE], flate2::Compression::fast());
+        encoder.write_all(&raw).map_err(|e| CodecError::Encode(e.to_string()))?;
+        encoder.finish().map(Frame::Binary).map_err(|e| CodecError::Encode(e.to_string()))
+    }
+
+    /// Decode a client frame, inflating it first if it carries the deflate flag
+    pub fn decode(&self, frame: Frame) -> Result<ClientMessage, CodecError> {
+        let frame = match (self.compression, frame) {
+            (Compression::Deflate { .. }, Frame::Binary(bytes)) => {
+                let payload = match bytes.split_first() {
+                    Some((&FLAG_DEFLATE, rest)) => {
+                        // One byte over the cap is enough to know it is too big
+                        let mut out = Vec::new();
+                        flate2::read::DeflateDecoder::new(rest)
+                            .take(MAX_INFLATED_SIZE as u64 + 1)
+                            .read_to_end(&mut out)
+                            .map_err(|e| CodecError::Decode(e.to_string()))?;
+                        if out.len() > MAX_INFLATED_SIZE {
+                            return Err(CodecError::TooLarge(MAX_INFLATED_SIZE));
+                        }
+                        out
+                    }
+                    Some((&FLAG_PLAIN, rest)) => rest.to_vec(),
+                    _ => return Err(CodecError::Decode("missing frame flag".to_string())),
+                };
+
+                if self.inner.name() == "json" {
+                    Frame::Text(String::from_utf8(payload).map_err(|e| CodecError::Decode(e.to_string()))?)
+                } else {
+                    Frame::Binary(payload)
+                }
+            }
+            (_, frame) => frame,
+        };
+
+        self.inner.decode(frame)
+    }
+}
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 /// Game events that can be sent via WebSocket
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum GameEvent {
     // Process events
@@ -26,6 +26,15 @@ pub enum GameEvent {
         progress: f32,
         remaining_time: u64,
     },
+    /// A random event changed a running process; `remaining_time` is `None`
+    /// when it killed the process
+    ProcessComplication {
+        pid: i64,
+        process_type: String,
+        complication: String,
+        message: String,
+        remaining_time: Option<u64>,
+    },
 
     // Hardware events
     HardwareUpgraded {
@@ -79,6 +88,64 @@ pub enum GameEvent {
         attacker: String,
         damage: String,
     },
+    AttackAlert {
+        alert_kind: String,
+        attack_type: String,
+        process_id: i64,
+        attacker: Option<String>,
+        progress: f32,
+    },
+    TraceCompleted {
+        process_id: i64,
+        attacker_ip: String,
+    },
+    /// A trace route moved on; the tracing defender and the traced attacker
+    /// each get one with their `role`
+    TraceProgress {
+        trace_id: i64,
+        /// `defender` or `attacker`
+        role: String,
+        /// `started`, `reading`, `hop_resolved`, `tampered`, `broken` or `completed`
+        status: String,
+        hop: u32,
+        hops: u32,
+        progress: f32,
+        remaining_time: u64,
+        /// For the defender, the address a read hop revealed; for the
+        /// attacker, their server the step was about
+        ip: Option<String>,
+    },
+
+    // Identity events
+    IpChanged {
+        old_ip: String,
+        new_ip: String,
+    },
+    /// A server in the player's hacked database moved; where to is not told
+    HackedServerLost {
+        ip: String,
+    },
+
+    // Hardware events
+    /// A component got worse, failed, or was repaired
+    HardwareCondition {
+        component: String,
+        condition: String,
+        quality: String,
+        wear: f64,
+    },
+
+    // Software events
+    /// A stolen copy arrived, a keygen cracked a license, or a trojan in a
+    /// stolen copy opened the thief's gateway to its owner
+    SoftwarePiracy {
+        software_id: i64,
+        name: String,
+        /// `stolen`, `unlocked` or `trojan_triggered`
+        change: String,
+        /// The gateway a triggered trojan opened
+        ip: Option<String>,
+    },
 
     // Log events
     LogCreated {
@@ -136,6 +203,25 @@ pub enum GameEvent {
         username: String,
     },
 
+    // Moderation events, on the moderator channels
+    /// A report case was opened, took another report or was closed
+    ReportCase {
+        case_id: i64,
+        /// `opened`, `requeued` or the status it closed with
+        change: String,
+        category: String,
+        priority: i32,
+        reporters: i32,
+    },
+    /// The chat filter matched a message
+    ChatFlagged {
+        flag_id: i64,
+        message_id: i64,
+        user_id: i64,
+        terms: Vec<String>,
+        excerpt: String,
+    },
+
     // Custom event
     Custom {
         event_name: String,
@@ -159,6 +245,7 @@ impl GameEvent {
             GameEvent::ProcessCompleted { .. } => "process_completed",
             GameEvent::ProcessCancelled { .. } => "process_cancelled",
             GameEvent::ProcessProgress { .. } => "process_progress",
+            GameEvent::ProcessComplication { .. } => "process_complication",
             GameEvent::HardwareUpgraded { .. } => "hardware_upgraded",
             GameEvent::HardwareOverloaded { .. } => "hardware_overloaded",
             GameEvent::MoneyReceived { .. } => "money_received",
@@ -170,6 +257,13 @@ impl GameEvent {
             GameEvent::UnderAttack { .. } => "under_attack",
             GameEvent::AttackBlocked { .. } => "attack_blocked",
             GameEvent::SystemCompromised { .. } => "system_compromised",
+            GameEvent::AttackAlert { .. } => "attack_alert",
+            GameEvent::TraceCompleted { .. } => "trace_completed",
+            GameEvent::TraceProgress { .. } => "trace_progress",
+            GameEvent::IpChanged { .. } => "ip_changed",
+            GameEvent::HackedServerLost { .. } => "hacked_server_lost",
+            GameEvent::HardwareCondition { .. } => "hardware_condition",
+            GameEvent::SoftwarePiracy { .. } => "software_piracy",
             GameEvent::LogCreated { .. } => "log_created",
             GameEvent::LogDeleted { .. } => "log_deleted",
             GameEvent::VirusInstalled { .. } => "virus_installed",
@@ -182,6 +276,8 @@ impl GameEvent {
             GameEvent::Announcement { .. } => "announcement",
             GameEvent::UserOnline { .. } => "user_online",
             GameEvent::UserOffline { .. } => "user_offline",
+            GameEvent::ReportCase { .. } => "report_case",
+            GameEvent::ChatFlagged { .. } => "chat_flagged",
             GameEvent::Custom { event_name, .. } => event_name,
         }
         .to_string()
@@ -218,6 +314,46 @@ impl EventBuilder {
         }
     }
 
+    pub fn process_complication(
+        pid: i64,
+        process_type: String,
+        complication: String,
+        message: String,
+        remaining_time: Option<u64>,
+    ) -> GameEvent {
+        GameEvent::ProcessComplication {
+            pid,
+            process_type,
+            complication,
+            message,
+            remaining_time,
+        }
+    }
+
+    pub fn process_cancelled(pid: i64) -> GameEvent {
+        GameEvent::ProcessCancelled { pid }
+    }
+
+    pub fn report_case(case_id: i64, change: String, category: String, priority: i32, reporters: i32) -> GameEvent {
+        GameEvent::ReportCase {
+            case_id,
+            change,
+            category,
+            priority,
+            reporters,
+        }
+    }
+
+    pub fn chat_flagged(flag_id: i64, message_id: i64, user_id: i64, terms: Vec<String>, excerpt: String) -> GameEvent {
+        GameEvent::ChatFlagged {
+            flag_id,
+            message_id,
+            user_id,
+            terms,
+            excerpt,
+        }
+    }
+
     pub fn money_received(amount: i64, from: String) -> GameEvent {
         GameEvent::MoneyReceived { amount, from }
     }
@@ -229,6 +365,59 @@ impl EventBuilder {
         }
     }
 
+    pub fn attack_alert(
+        alert_kind: String,
+        attack_type: String,
+        process_id: i64,
+        attacker: Option<String>,
+        progress: f32,
+    ) -> GameEvent {
+        GameEvent::AttackAlert {
+            alert_kind,
+            attack_type,
+            process_id,
+            attacker,
+            progress,
+        }
+    }
+
+    pub fn trace_completed(process_id: i64, attacker_ip: String) -> GameEvent {
+        GameEvent::TraceCompleted {
+            process_id,
+            attacker_ip,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn trace_progress(
+        trace_id: i64,
+        role: String,
+        status: String,
+        hop: u32,
+        hops: u32,
+        progress: f32,
+        remaining_time: u64,
+        ip: Option<String>,
+    ) -> GameEvent {
+        GameEvent::TraceProgress { trace_id, role, status, hop, hops, progress, remaining_time, ip }
+    }
+
+    pub fn ip_changed(old_ip: String, new_ip: String) -> GameEvent {
+        GameEvent::IpChanged { old_ip, new_ip }
+    }
+
+    pub fn hacked_server_lost(ip: String) -> GameEvent {
+        GameEvent::HackedServerLost { ip }
+    }
+
+    pub fn hardware_condition(component: String, condition: String, quality: String, wear: f64) -> GameEvent {
+        GameEvent::HardwareCondition { component, condition, quality, wear }
+    }
+
+    pub fn software_piracy(software_id: i64, name: String, change: String, ip: Option<String>) -> GameEvent {
+        GameEvent::SoftwarePiracy { software_id, name, change, ip }
+    }
+
     pub fn announcement(title: String, content: String, priority: String) -> GameEvent {
         GameEvent::Announcement {
             title,
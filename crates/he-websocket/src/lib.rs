@@ -10,14 +10,20 @@ use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+pub mod acl;
+pub mod codec;
 pub mod events;
 pub mod manager;
+pub mod protocol;
 
 #[cfg(test)]
 mod tests;
 
+pub use acl::{Entity, EntityResolver, ModChannel, ResolveFuture};
+pub use codec::{Compression, Encoding, Frame, MessageCodec, SessionCodec};
 pub use events::*;
 pub use manager::*;
+pub use protocol::{ClientEvent, ControlEvent, ProtocolDocument, ProtocolError};
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
@@ -34,6 +40,10 @@ pub struct WebSocketSession {
     pub manager: Arc<ConnectionManager>,
     /// Subscribed event channels
     pub subscriptions: Vec<String>,
+    /// Wire codec, JSON until the auth handshake selects another
+    pub codec: SessionCodec,
+    /// Refuse unknown fields, as asked for in the auth message
+    pub strict: bool,
 }
 
 impl WebSocketSession {
@@ -44,6 +54,25 @@ impl WebSocketSession {
             hb: Instant::now(),
             manager,
             subscriptions: Vec::new(),
+            codec: SessionCodec::default(),
+            strict: false,
+        }
+    }
+
+    /// Encode and send a message with the session codec
+    fn send(&self, ctx: &mut ws::WebsocketContext<Self>, msg: &ServerMessage) {
+        match self.codec.encode(msg) {
+            Ok(Frame::Text(text)) => ctx.text(text),
+            Ok(Frame::Binary(bytes)) => ctx.binary(bytes),
+            Err(e) => error!("Failed to encode {} message: {}", self.codec.name(), e),
+        }
+    }
+
+    /// Decode an incoming frame and forward it to the message handler
+    fn receive(&self, ctx: &mut ws::WebsocketContext<Self>, frame: Frame) {
+        match self.codec.decode(frame) {
+            Ok(msg) => ctx.address().do_send(msg),
+            Err(e) => debug!("Dropping undecodable {} frame: {}", self.codec.name(), e),
         }
     }
 
@@ -112,11 +141,11 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketSession
                 self.hb = Instant::now();
             }
             Ok(ws::Message::Text(text)) => {
-                if let Ok(msg) = serde_json::from_str::<ClientMessage>(&text) {
-                    ctx.address().do_send(msg);
-                }
+                self.receive(ctx, Frame::Text(text.to_string()));
+            }
+            Ok(ws::Message::Binary(bytes)) => {
+                self.receive(ctx, Frame::Binary(bytes.to_vec()));
             }
-            Ok(ws::Message::Binary(_)) => {}
             Ok(ws::Message::Close(reason)) => {
                 ctx.close(reason);
                 ctx.stop();
@@ -138,72 +167,81 @@ impl Handler<ClientMessage> for WebSocketSession {
     type Result = ();
 
     fn handle(&mut self, msg: ClientMessage, ctx: &mut Self::Context) {
-        match msg.msg_type.as_str() {
-            "auth" => {
-                if let Some(token) = msg.data.get("token").and_then(|v| v.as_str()) {
-                    let token = token.to_string();
+        let event = match ClientEvent::parse(&msg, self.strict) {
+            Ok(event) => event,
+            Err(e) => {
+                debug!("Refusing message from session {}: {}", self.id, e);
+                self.send(ctx, &ControlEvent::ProtocolError { msg_type: msg.msg_type, error: e.to_string() }.to_server_message());
+                return;
+            }
+        };
+
+        match event {
+            ClientEvent::Auth { token, encoding, compression, strict } => {
+                let encoding = Encoding::from_handshake(encoding.as_deref());
+                let compression = Compression::from_handshake(compression.as_deref());
+                self.strict = strict;
+                let addr = ctx.address();
+                let session_id = self.id;
+                let manager = self.manager.clone();
+
+                ctx.spawn(
+                    async move {
+                        // Authenticate and get user_id
+                        match WebSocketSession::authenticate(&mut Default::default(), &token).await {
+                            Ok(user_id) => {
+                                manager.authenticate_connection(session_id, user_id);
+                                manager.load_entities(session_id, user_id).await;
+                                addr.do_send(
+                                    ControlEvent::AuthSuccess {
+                                        user_id,
+                                        message: "Authentication successful".to_string(),
+                                        encoding,
+                                        compression: matches!(compression, Compression::Deflate { .. }),
+                                        strict,
+                                    }
+                                    .to_server_message(),
+                                );
+                                // Everything after auth_success uses the negotiated codec
+                                addr.do_send(SwitchCodec { encoding, compression });
+                            }
+                            Err(error) => {
+                                addr.do_send(ControlEvent::AuthError { error }.to_server_message());
+                            }
+                        }
+                    }
+                    .into_actor(self),
+                );
+            }
+            ClientEvent::Subscribe { channel } => {
+                // Entity channels carry other players' data and need the ACL's say-so
+                if let Some(entity) = Entity::from_channel(&channel) {
                     let addr = ctx.address();
                     let session_id = self.id;
                     let manager = self.manager.clone();
-
                     ctx.spawn(
                         async move {
-                            // Authenticate and get user_id
-                            match WebSocketSession::authenticate(&mut Default::default(), &token).await {
-                                Ok(user_id) => {
-                                    manager.authenticate_connection(session_id, user_id);
-                                    addr.do_send(ServerMessage {
-                                        event_type: "auth_success".to_string(),
-                                        data: serde_json::json!({
-                                            "user_id": user_id,
-                                            "message": "Authentication successful"
-                                        }),
-                                    });
-                                }
-                                Err(e) => {
-                                    addr.do_send(ServerMessage {
-                                        event_type: "auth_error".to_string(),
-                                        data: serde_json::json!({
-                                            "error": e
-                                        }),
-                                    });
-                                }
-                            }
+                            let reply = if manager.authorize(session_id, entity).await {
+                                ControlEvent::Subscribed { channel }
+                            } else {
+                                ControlEvent::SubscribeDenied { channel }
+                            };
+                            addr.do_send(reply.to_server_message());
                         }
                         .into_actor(self),
                     );
+                    return;
                 }
+
+                self.subscriptions.push(channel.clone());
+                self.send(ctx, &ControlEvent::Subscribed { channel }.to_server_message());
             }
-            "subscribe" => {
-                if let Some(channel) = msg.data.get("channel").and_then(|v| v.as_str()) {
-                    self.subscriptions.push(channel.to_string());
-                    ctx.text(
-                        serde_json::to_string(&ServerMessage {
-                            event_type: "subscribed".to_string(),
-                            data: serde_json::json!({
-                                "channel": channel
-                            }),
-                        })
-                        .unwrap(),
-                    );
-                }
-            }
-            "unsubscribe" => {
-                if let Some(channel) = msg.data.get("channel").and_then(|v| v.as_str()) {
-                    self.subscriptions.retain(|c| c != channel);
-                    ctx.text(
-                        serde_json::to_string(&ServerMessage {
-                            event_type: "unsubscribed".to_string(),
-                            data: serde_json::json!({
-                                "channel": channel
-                            }),
-                        })
-                        .unwrap(),
-                    );
+            ClientEvent::Unsubscribe { channel } => {
+                if let Some(entity) = Entity::from_channel(&channel) {
+                    self.manager.unsubscribe(self.id, entity);
                 }
-            }
-            _ => {
-                warn!("Unknown message type: {}", msg.msg_type);
+                self.subscriptions.retain(|c| c != &channel);
+                self.send(ctx, &ControlEvent::Unsubscribed { channel }.to_server_message());
             }
         }
     }
@@ -221,7 +259,24 @@ impl Handler<ServerMessage> for WebSocketSession {
     type Result = ();
 
     fn handle(&mut self, msg: ServerMessage, ctx: &mut Self::Context) {
-        ctx.text(serde_json::to_string(&msg).unwrap());
+        self.send(ctx, &msg);
+    }
+}
+
+/// Switch the session to the codec negotiated during the auth handshake
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "()")]
+pub struct SwitchCodec {
+    pub encoding: Encoding,
+    pub compression: Compression,
+}
+
+impl Handler<SwitchCodec> for WebSocketSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: SwitchCodec, _ctx: &mut Self::Context) {
+        self.codec = SessionCodec::new(msg.encoding, msg.compression);
+        debug!("Session {} switched to {} codec", self.id, self.codec.name());
     }
 }
 
@@ -249,12 +304,9 @@ impl Handler<Broadcast> for WebSocketSession {
             }
         }
 
-        ctx.text(
-            serde_json::to_string(&ServerMessage {
-                event_type: msg.event_type,
-                data: msg.data,
-            })
-            .unwrap(),
-        );
+        self.send(ctx, &ServerMessage {
+            event_type: msg.event_type,
+            data: msg.data,
+        });
     }
 }
\ No newline at end of file
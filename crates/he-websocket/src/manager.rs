@@ -2,10 +2,13 @@
 
 use actix::Addr;
 use dashmap::DashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Instant;
 use tracing::{debug, info};
 use uuid::Uuid;
 
+use crate::acl::{Entity, EntityResolver, SessionAcl};
 use crate::{Broadcast, ServerMessage, WebSocketSession};
 
 /// Manages all WebSocket connections
@@ -15,6 +18,12 @@ pub struct ConnectionManager {
     connections: Arc<DashMap<Uuid, ConnectionInfo>>,
     /// User ID to connection mapping
     user_connections: Arc<DashMap<i64, Vec<Uuid>>>,
+    /// Access decisions for entity-addressed events, per session
+    acls: Arc<DashMap<Uuid, SessionAcl>>,
+    /// Sessions subscribed to each entity
+    subscribers: Arc<DashMap<Entity, HashSet<Uuid>>>,
+    /// Without one, sessions only get their own user and what is granted to them
+    resolver: Option<Arc<dyn EntityResolver>>,
 }
 
 #[derive(Clone)]
@@ -29,6 +38,17 @@ impl ConnectionManager {
         Self {
             connections: Arc::new(DashMap::new()),
             user_connections: Arc::new(DashMap::new()),
+            acls: Arc::new(DashMap::new()),
+            subscribers: Arc::new(DashMap::new()),
+            resolver: None,
+        }
+    }
+
+    /// Resolve entity access with `resolver`
+    pub fn with_resolver(resolver: Arc<dyn EntityResolver>) -> Self {
+        Self {
+            resolver: Some(resolver),
+            ..Self::new()
         }
     }
 
@@ -61,6 +81,12 @@ impl ConnectionManager {
                 }
             }
         }
+
+        if let Some((_, acl)) = self.acls.remove(&session_id) {
+            for entity in acl.allowed() {
+                self.unsubscribe(session_id, *entity);
+            }
+        }
     }
 
     /// Authenticate a connection
@@ -76,6 +102,141 @@ impl ConnectionManager {
                 .or_insert_with(Vec::new)
                 .push(session_id);
         }
+
+        self.decide(session_id, Entity::User(user_id), true);
+    }
+
+    /// Subscribe a freshly authenticated session to everything its user owns
+    pub async fn load_entities(&self, session_id: Uuid, user_id: i64) {
+        let Some(resolver) = &self.resolver else {
+            return;
+        };
+        let entities = resolver.entities(user_id).await;
+        debug!("Session {} granted {} entities", session_id, entities.len());
+        for entity in entities {
+            self.decide(session_id, entity, true);
+        }
+    }
+
+    /// Let every session of `user_id` receive `entity`'s events, e.g. for a
+    /// process they just started
+    pub fn grant(&self, user_id: i64, entity: Entity) {
+        for session_id in self.sessions_of(user_id) {
+            self.decide(session_id, entity, true);
+        }
+    }
+
+    /// Stop `user_id`'s sessions receiving `entity`'s events
+    pub fn revoke(&self, user_id: i64, entity: Entity) {
+        for session_id in self.sessions_of(user_id) {
+            self.decide(session_id, entity, false);
+        }
+    }
+
+    /// Whether the session may receive `entity`'s events, subscribing it if
+    /// so. Asks the resolver unless a fresh decision is cached.
+    pub async fn authorize(&self, session_id: Uuid, entity: Entity) -> bool {
+        let Some(user_id) = self.connections.get(&session_id).and_then(|conn| conn.user_id) else {
+            return false;
+        };
+
+        let cached = self.acls.get(&session_id).and_then(|acl| acl.get(&entity, Instant::now()));
+        if let Some(allowed) = cached {
+            if allowed {
+                self.subscribers.entry(entity).or_default().insert(session_id);
+            }
+            return allowed;
+        }
+
+        let allowed = match (entity, &self.resolver) {
+            (Entity::User(id), _) => id == user_id,
+            (_, Some(resolver)) => resolver.may_receive(user_id, entity).await,
+            (_, None) => false,
+        };
+
+        self.decide(session_id, entity, allowed);
+        allowed
+    }
+
+    /// Re-check the sessions subscribed to `entity` whose decision went
+    /// stale, dropping those no longer allowed. For channels with few events,
+    /// where waiting for the next one to re-check would keep a session that
+    /// lost access subscribed. Returns how many were dropped.
+    pub async fn revalidate(&self, entity: Entity) -> usize {
+        let Some(sessions) = self.subscribers.get(&entity).map(|sessions| sessions.clone()) else {
+            return 0;
+        };
+
+        let now = Instant::now();
+        let mut dropped = 0;
+        for session_id in sessions {
+            let fresh = self.acls.get(&session_id).and_then(|acl| acl.get(&entity, now)).is_some();
+            if !fresh && !self.authorize(session_id, entity).await {
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+    /// Stop sending `entity`'s events to the session, keeping its decision
+    pub fn unsubscribe(&self, session_id: Uuid, entity: Entity) {
+        if let Some(mut sessions) = self.subscribers.get_mut(&entity) {
+            sessions.remove(&session_id);
+            if sessions.is_empty() {
+                drop(sessions);
+                self.subscribers.remove_if(&entity, |_, sessions| sessions.is_empty());
+            }
+        }
+    }
+
+    /// Send to every session subscribed to `entity` that may still receive
+    /// it. Sessions whose decision went stale are re-checked first.
+    pub fn send_to_entity(&self, entity: Entity, message: ServerMessage) {
+        let Some(sessions) = self.subscribers.get(&entity).map(|sessions| sessions.clone()) else {
+            return;
+        };
+
+        let now = Instant::now();
+        for session_id in sessions {
+            let cached = self.acls.get(&session_id).and_then(|acl| acl.get(&entity, now));
+            match cached {
+                Some(true) => self.send_to_session(session_id, message.clone()),
+                Some(false) => self.unsubscribe(session_id, entity),
+                None => self.recheck_and_send(session_id, entity, message.clone()),
+            }
+        }
+    }
+
+    fn recheck_and_send(&self, session_id: Uuid, entity: Entity, message: ServerMessage) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let manager = self.clone();
+        runtime.spawn(async move {
+            if manager.authorize(session_id, entity).await {
+                manager.send_to_session(session_id, message);
+            }
+        });
+    }
+
+    fn decide(&self, session_id: Uuid, entity: Entity, allowed: bool) {
+        if !self.connections.contains_key(&session_id) {
+            return;
+        }
+
+        self.acls.entry(session_id).or_default().set(entity, allowed, Instant::now());
+        if allowed {
+            self.subscribers.entry(entity).or_default().insert(session_id);
+        } else {
+            self.unsubscribe(session_id, entity);
+        }
+    }
+
+    fn sessions_of(&self, user_id: i64) -> Vec<Uuid> {
+        self.user_connections
+            .get(&user_id)
+            .map(|sessions| sessions.clone())
+            .unwrap_or_default()
     }
 
     /// Send message to specific user
@@ -0,0 +1,438 @@
+//! Typed wire protocol and its contract
+//!
+//! Every message on the socket is a variant of a typed enum: [`ClientEvent`]
+//! for what clients send, [`ControlEvent`] for the server's replies to them
+//! and [`GameEvent`] for everything the game pushes. Together the last two
+//! are the server events.
+//!
+//! One sample of every variant lives here. The tests hold the samples to the
+//! golden files in `tests/golden`, so any change to what goes over the wire
+//! fails CI until the golden files are regenerated on purpose
+//! (`UPDATE_GOLDEN=1 cargo test -p he-websocket`), and the protocol document
+//! served at `/api/ws-protocol` is generated from the same samples.
+//!
+//! Clients that set `strict` in their auth message get messages with fields
+//! the server does not know rejected with a `protocol_error` instead of the
+//! fields being ignored, so a misspelt option shows up while the client is
+//! being written rather than as an option that silently does nothing.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{ClientMessage, Encoding, GameEvent, ServerMessage};
+
+/// Bumped on changes existing clients cannot ignore
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Client -> Server messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "msg_type", content = "data", rename_all = "snake_case")]
+pub enum ClientEvent {
+    /// Log the socket in and pick its codec
+    Auth {
+        token: String,
+        /// `json`, `msgpack` or `cbor`; JSON when missing or unknown
+        encoding: Option<String>,
+        /// `deflate` or `none`; off when missing
+        compression: Option<String>,
+        /// Reject unknown fields from now on
+        #[serde(default)]
+        strict: bool,
+    },
+    Subscribe {
+        channel: String,
+    },
+    Unsubscribe {
+        channel: String,
+    },
+}
+
+/// Why a client message was refused
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ProtocolError {
+    #[error("unknown message type '{0}'")]
+    UnknownType(String),
+    #[error("unknown field '{field}' in '{msg_type}' message")]
+    UnknownField { msg_type: String, field: String },
+    #[error("invalid '{msg_type}' message: {error}")]
+    Invalid { msg_type: String, error: String },
+}
+
+impl ClientEvent {
+    /// The fields each message type takes, for strict clients
+    fn fields(msg_type: &str) -> Option<&'static [&'static str]> {
+        match msg_type {
+            "auth" => Some(&["token", "encoding", "compression", "strict"]),
+            "subscribe" | "unsubscribe" => Some(&["channel"]),
+            _ => None,
+        }
+    }
+
+    /// Read a decoded message; `strict` refuses fields the type does not take
+    pub fn parse(msg: &ClientMessage, strict: bool) -> Result<ClientEvent, ProtocolError> {
+        let Some(fields) = Self::fields(&msg.msg_type) else {
+            return Err(ProtocolError::UnknownType(msg.msg_type.clone()));
+        };
+        if strict {
+            if let Some(data) = msg.data.as_object() {
+                if let Some(field) = data.keys().find(|key| !fields.contains(&key.as_str())) {
+                    return Err(ProtocolError::UnknownField { msg_type: msg.msg_type.clone(), field: field.clone() });
+                }
+            }
+        }
+        serde_json::to_value(msg)
+            .and_then(serde_json::from_value)
+            .map_err(|e| ProtocolError::Invalid { msg_type: msg.msg_type.clone(), error: e.to_string() })
+    }
+
+    pub fn msg_type(&self) -> &'static str {
+        match self {
+            ClientEvent::Auth { .. } => "auth",
+            ClientEvent::Subscribe { .. } => "subscribe",
+            ClientEvent::Unsubscribe { .. } => "unsubscribe",
+        }
+    }
+
+    pub fn to_client_message(&self) -> ClientMessage {
+        serde_json::to_value(self)
+            .and_then(serde_json::from_value)
+            .expect("client events serialize to client messages")
+    }
+}
+
+/// Server -> Client replies to client messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event_type", content = "data", rename_all = "snake_case")]
+pub enum ControlEvent {
+    AuthSuccess {
+        user_id: i64,
+        message: String,
+        encoding: Encoding,
+        compression: bool,
+        strict: bool,
+    },
+    AuthError {
+        error: String,
+    },
+    Subscribed {
+        channel: String,
+    },
+    /// The ACL refused an entity channel
+    SubscribeDenied {
+        channel: String,
+    },
+    Unsubscribed {
+        channel: String,
+    },
+    /// A client message could not be read
+    ProtocolError {
+        msg_type: String,
+        error: String,
+    },
+}
+
+impl ControlEvent {
+    pub fn to_server_message(&self) -> ServerMessage {
+        serde_json::to_value(self)
+            .and_then(serde_json::from_value)
+            .expect("control events serialize to server messages")
+    }
+}
+
+/// One sample of every client message
+pub fn client_samples() -> Vec<ClientEvent> {
+    vec![
+        ClientEvent::Auth {
+            token: "eyJhbGciOiJIUzI1NiJ9.e30.signature".to_string(),
+            encoding: Some("msgpack".to_string()),
+            compression: Some("deflate".to_string()),
+            strict: true,
+        },
+        ClientEvent::Subscribe { channel: "clan:7".to_string() },
+        ClientEvent::Unsubscribe { channel: "clan:7".to_string() },
+    ]
+}
+
+/// One sample of every reply
+pub fn control_samples() -> Vec<ControlEvent> {
+    vec![
+        ControlEvent::AuthSuccess {
+            user_id: 42,
+            message: "Authentication successful".to_string(),
+            encoding: Encoding::MessagePack,
+            compression: true,
+            strict: true,
+        },
+        ControlEvent::AuthError { error: "Authentication failed: token expired".to_string() },
+        ControlEvent::Subscribed { channel: "clan:7".to_string() },
+        ControlEvent::SubscribeDenied { channel: "user:9".to_string() },
+        ControlEvent::Unsubscribed { channel: "clan:7".to_string() },
+        ControlEvent::ProtocolError {
+            msg_type: "subscribe".to_string(),
+            error: "unknown field 'chanel' in 'subscribe' message".to_string(),
+        },
+    ]
+}
+
+/// Variants of [`GameEvent`]; [`game_variant`] fails to compile when one is
+/// added, and the contract test fails until it has a sample
+pub const GAME_EVENT_VARIANTS: usize = 38;
+
+/// Ordinal of a game event's variant
+pub fn game_variant(event: &GameEvent) -> usize {
+    match event {
+        GameEvent::ProcessStarted { .. } => 0,
+        GameEvent::ProcessCompleted { .. } => 1,
+        GameEvent::ProcessCancelled { .. } => 2,
+        GameEvent::ProcessProgress { .. } => 3,
+        GameEvent::ProcessComplication { .. } => 4,
+        GameEvent::HardwareUpgraded { .. } => 5,
+        GameEvent::HardwareOverloaded { .. } => 6,
+        GameEvent::MoneyReceived { .. } => 7,
+        GameEvent::MoneySent { .. } => 8,
+        GameEvent::BankHacked { .. } => 9,
+        GameEvent::MissionCompleted { .. } => 10,
+        GameEvent::MissionFailed { .. } => 11,
+        GameEvent::MissionProgress { .. } => 12,
+        GameEvent::UnderAttack { .. } => 13,
+        GameEvent::AttackBlocked { .. } => 14,
+        GameEvent::SystemCompromised { .. } => 15,
+        GameEvent::AttackAlert { .. } => 16,
+        GameEvent::TraceCompleted { .. } => 17,
+        GameEvent::IpChanged { .. } => 18,
+        GameEvent::HackedServerLost { .. } => 19,
+        GameEvent::HardwareCondition { .. } => 20,
+        GameEvent::SoftwarePiracy { .. } => 21,
+        GameEvent::LogCreated { .. } => 22,
+        GameEvent::LogDeleted { .. } => 23,
+        GameEvent::VirusInstalled { .. } => 24,
+        GameEvent::VirusDetected { .. } => 25,
+        GameEvent::VirusRemoved { .. } => 26,
+        GameEvent::MessageReceived { .. } => 27,
+        GameEvent::ClanMessage { .. } => 28,
+        GameEvent::ServerRestart { .. } => 29,
+        GameEvent::MaintenanceMode { .. } => 30,
+        GameEvent::Announcement { .. } => 31,
+        GameEvent::UserOnline { .. } => 32,
+        GameEvent::UserOffline { .. } => 33,
+        GameEvent::ReportCase { .. } => 34,
+        GameEvent::ChatFlagged { .. } => 35,
+        GameEvent::Custom { .. } => 36,
+        GameEvent::TraceProgress { .. } => 37,
+    }
+}
+
+/// One sample of every game event. Optional fields are filled in so the
+/// samples show their type; [`OPTIONAL_FIELDS`] says which they are.
+pub fn game_samples() -> Vec<GameEvent> {
+    let s = |value: &str| value.to_string();
+    vec![
+        GameEvent::ProcessStarted { pid: 1001, process_type: s("Crack"), estimated_time: 120 },
+        GameEvent::ProcessCompleted { pid: 1001, process_type: s("Crack"), result: s("Password cracked") },
+        GameEvent::ProcessCancelled { pid: 1001 },
+        GameEvent::ProcessProgress { pid: 1001, progress: 0.5, remaining_time: 60 },
+        GameEvent::ProcessComplication {
+            pid: 1001,
+            process_type: s("Download"),
+            complication: s("connection_drop"),
+            message: s("The connection dropped; the download slowed down"),
+            remaining_time: Some(90),
+        },
+        GameEvent::HardwareUpgraded { component: s("cpu"), old_level: 2, new_level: 3 },
+        GameEvent::HardwareOverloaded { load_percentage: 97.5 },
+        GameEvent::MoneyReceived { amount: 2500, from: s("10.0.0.2") },
+        GameEvent::MoneySent { amount: 1200, to: s("10.0.0.3") },
+        GameEvent::BankHacked { hacker: s("zero_cool"), amount: 5000 },
+        GameEvent::MissionCompleted { mission_id: 31, reward_money: 800, reward_xp: 150 },
+        GameEvent::MissionFailed { mission_id: 32, reason: s("Target went offline") },
+        GameEvent::MissionProgress { mission_id: 33, progress: 2, total: 5 },
+        GameEvent::UnderAttack { attacker: s("10.0.0.4"), attack_type: s("ddos") },
+        GameEvent::AttackBlocked { attacker: s("10.0.0.4") },
+        GameEvent::SystemCompromised { attacker: s("10.0.0.4"), damage: s("logs wiped") },
+        GameEvent::AttackAlert {
+            alert_kind: s("started"),
+            attack_type: s("bruteforce"),
+            process_id: 1002,
+            attacker: Some(s("10.0.0.4")),
+            progress: 0.25,
+        },
+        GameEvent::TraceCompleted { process_id: 1003, attacker_ip: s("10.0.0.4") },
+        GameEvent::IpChanged { old_ip: s("10.0.0.5"), new_ip: s("10.0.0.6") },
+        GameEvent::HackedServerLost { ip: s("10.0.0.7") },
+        GameEvent::HardwareCondition { component: s("hdd"), condition: s("worn"), quality: s("standard"), wear: 0.75 },
+        GameEvent::SoftwarePiracy { software_id: 55, name: s("Cracker v3"), change: s("trojan_triggered"), ip: Some(s("10.0.0.8")) },
+        GameEvent::LogCreated { log_type: s("login"), message: s("localhost logged in"), ip: s("10.0.0.9") },
+        GameEvent::LogDeleted { count: 3 },
+        GameEvent::VirusInstalled { target_pc: s("10.0.0.10"), virus_type: s("spam") },
+        GameEvent::VirusDetected { virus_type: s("spam") },
+        GameEvent::VirusRemoved { virus_type: s("spam") },
+        GameEvent::MessageReceived { from: s("acid_burn"), content: s("nice hack"), timestamp: s("2024-11-13T12:00:00Z") },
+        GameEvent::ClanMessage { from: s("acid_burn"), content: s("war at noon"), clan: s("Phreaks") },
+        GameEvent::ServerRestart { time_until: 300 },
+        GameEvent::MaintenanceMode { enabled: true, message: s("Back in ten minutes") },
+        GameEvent::Announcement { title: s("Round 12"), content: s("A new round starts Friday"), priority: s("high") },
+        GameEvent::UserOnline { username: s("acid_burn") },
+        GameEvent::UserOffline { username: s("acid_burn") },
+        GameEvent::ReportCase { case_id: 8, change: s("opened"), category: s("cheating"), priority: 2, reporters: 3 },
+        GameEvent::ChatFlagged { flag_id: 4, message_id: 900, user_id: 42, terms: vec![s("scam")], excerpt: s("free scam money") },
+        GameEvent::Custom { event_name: s("custom"), payload: json!({ "any": "json" }) },
+        GameEvent::TraceProgress {
+            trace_id: 1004,
+            role: s("defender"),
+            status: s("hop_resolved"),
+            hop: 1,
+            hops: 3,
+            progress: 0.5,
+            remaining_time: 180,
+            ip: Some(s("10.0.0.2")),
+        },
+    ]
+}
+
+/// Fields that may be missing or null, as (message, field)
+pub const OPTIONAL_FIELDS: &[(&str, &str)] = &[
+    ("auth", "encoding"),
+    ("auth", "compression"),
+    ("auth", "strict"),
+    ("process_complication", "remaining_time"),
+    ("attack_alert", "attacker"),
+    ("software_piracy", "ip"),
+    ("trace_progress", "ip"),
+];
+
+/// The name a sample is filed under: the message or event type
+pub fn client_sample_name(event: &ClientEvent) -> String {
+    event.msg_type().to_string()
+}
+
+pub fn server_sample_name(msg: &ServerMessage) -> String {
+    msg.event_type.clone()
+}
+
+/// The wire form of every server sample, replies first
+pub fn server_samples() -> Vec<ServerMessage> {
+    control_samples()
+        .iter()
+        .map(ControlEvent::to_server_message)
+        .chain(game_samples().iter().map(GameEvent::to_server_message))
+        .collect()
+}
+
+/// A field of a documented message
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldDoc {
+    pub name: String,
+    /// JSON type of the sample's value
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub optional: bool,
+}
+
+/// A documented message with its example
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageDoc {
+    pub name: String,
+    pub fields: Vec<FieldDoc>,
+    pub example: Value,
+}
+
+/// The protocol document served at `/api/ws-protocol`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolDocument {
+    pub version: u32,
+    pub client: Vec<MessageDoc>,
+    pub replies: Vec<MessageDoc>,
+    pub events: Vec<MessageDoc>,
+}
+
+fn json_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn message_doc(name: String, fields: &Value, example: Value) -> MessageDoc {
+    let fields = fields
+        .as_object()
+        .map(|fields| {
+            fields
+                .iter()
+                .map(|(field, value)| FieldDoc {
+                    optional: OPTIONAL_FIELDS.contains(&(name.as_str(), field.as_str())),
+                    name: field.clone(),
+                    kind: json_kind(value),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    MessageDoc { name, fields, example }
+}
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+impl ProtocolDocument {
+    pub fn generate() -> Self {
+        let client = client_samples()
+            .iter()
+            .map(|event| {
+                let msg = event.to_client_message();
+                message_doc(client_sample_name(event), &msg.data, to_value(&msg))
+            })
+            .collect();
+        let replies = control_samples()
+            .iter()
+            .map(|event| {
+                let msg = event.to_server_message();
+                message_doc(server_sample_name(&msg), &msg.data, to_value(&msg))
+            })
+            .collect();
+        // Game event fields sit under the variant tag
+        let events = game_samples()
+            .iter()
+            .map(|event| {
+                let msg = event.to_server_message();
+                message_doc(server_sample_name(&msg), &msg.data["data"], to_value(&msg))
+            })
+            .collect();
+        Self { version: PROTOCOL_VERSION, client, replies, events }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# WebSocket protocol, version {}\n\n", self.version);
+        out.push_str(
+            "Messages are JSON text frames until `auth` negotiates MessagePack or CBOR. \
+             Clients send `{\"msg_type\", \"data\"}` and receive `{\"event_type\", \"data\"}`; \
+             game events carry their variant in `data.type` and their fields in `data.data`.\n",
+        );
+        for (title, messages) in [
+            ("Client messages", &self.client),
+            ("Replies", &self.replies),
+            ("Game events", &self.events),
+        ] {
+            out.push_str(&format!("\n## {}\n", title));
+            for message in messages {
+                out.push_str(&format!("\n### `{}`\n\n", message.name));
+                if !message.fields.is_empty() {
+                    out.push_str("| Field | Type | Required |\n|---|---|---|\n");
+                    for field in &message.fields {
+                        let required = if field.optional { "no" } else { "yes" };
+                        out.push_str(&format!("| `{}` | {} | {} |\n", field.name, field.kind, required));
+                    }
+                    out.push('\n');
+                }
+                let example = serde_json::to_string_pretty(&message.example).unwrap_or_default();
+                out.push_str(&format!("```json\n{}\n```\n", example));
+            }
+        }
+        out
+    }
+}
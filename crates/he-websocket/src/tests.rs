@@ -616,4 +616,400 @@ mod tests {
             assert_eq!(manager.total_connections(), 0);
         }
     }
-}
\ No newline at end of file
+
+    mod codec_tests {
+        use super::*;
+        use crate::codec::*;
+
+        fn large_message() -> ServerMessage {
+            let processes: Vec<_> = (0..200)
+                .map(|pid| json!({ "pid": pid, "process_type": "Crack", "progress": 0.5, "target": "10.0.0.1" }))
+                .collect();
+            ServerMessage {
+                event_type: "process_list".to_string(),
+                data: json!({ "processes": processes }),
+            }
+        }
+
+        fn client_frame(encoding: Encoding) -> Frame {
+            let msg = ClientMessage { msg_type: "subscribe".to_string(), data: json!({ "channel": "world" }) };
+            match encoding {
+                Encoding::Json => Frame::Text(serde_json::to_string(&msg).unwrap()),
+                Encoding::MessagePack => Frame::Binary(rmp_serde::to_vec_named(&msg).unwrap()),
+                Encoding::Cbor => {
+                    let mut buf = Vec::new();
+                    ciborium::into_writer(&msg, &mut buf).unwrap();
+                    Frame::Binary(buf)
+                }
+            }
+        }
+
+        #[test]
+        fn test_handshake_negotiation() {
+            assert_eq!(Encoding::from_handshake(Some("MsgPack")), Encoding::MessagePack);
+            assert_eq!(Encoding::from_handshake(Some("cbor")), Encoding::Cbor);
+            assert_eq!(Encoding::from_handshake(Some("xml")), Encoding::Json);
+            assert_eq!(Encoding::from_handshake(None), Encoding::Json);
+
+            assert!(matches!(Compression::from_handshake(Some("Deflate")), Compression::Deflate { .. }));
+            assert_eq!(Compression::from_handshake(Some("none")), Compression::None);
+        }
+
+        #[test]
+        fn test_extensions_offer_alone_keeps_plain_frames() {
+            // What a browser sends: `permessage-deflate` in the upgrade
+            // headers and an auth message without a `compression` field
+            let auth: ClientMessage = serde_json::from_value(json!({
+                "msg_type": "auth",
+                "data": { "token": "t" }
+            }))
+            .unwrap();
+            let ClientEvent::Auth { compression, .. } = ClientEvent::parse(&auth, false).unwrap() else {
+                panic!("not an auth event");
+            };
+            let compression = Compression::from_handshake(compression.as_deref());
+            assert_eq!(compression, Compression::None);
+
+            let codec = SessionCodec::new(Encoding::Json, compression);
+            assert!(matches!(codec.encode(&large_message()).unwrap(), Frame::Text(_)));
+        }
+
+        #[test]
+        fn test_decode_every_encoding() {
+            for encoding in [Encoding::Json, Encoding::MessagePack, Encoding::Cbor] {
+                let codec = SessionCodec::new(encoding, Compression::None);
+                let msg = codec.decode(client_frame(encoding)).unwrap();
+                assert_eq!(msg.msg_type, "subscribe");
+                assert_eq!(msg.data["channel"], "world");
+            }
+        }
+
+        #[test]
+        fn test_binary_encodings_are_smaller_than_json() {
+            let msg = large_message();
+            let json = SessionCodec::new(Encoding::Json, Compression::None).encode(&msg).unwrap();
+            let msgpack = SessionCodec::new(Encoding::MessagePack, Compression::None).encode(&msg).unwrap();
+
+            assert!(matches!(json, Frame::Text(_)));
+            assert!(matches!(msgpack, Frame::Binary(_)));
+            assert!(msgpack.len() < json.len());
+        }
+
+        #[test]
+        fn test_deflate_round_trip() {
+            let codec = SessionCodec::new(Encoding::Json, Compression::Deflate { threshold: DEFAULT_COMPRESSION_THRESHOLD });
+            let msg = large_message();
+
+            let plain = SessionCodec::default().encode(&msg).unwrap();
+            let compressed = codec.encode(&msg).unwrap();
+            assert!(compressed.len() < plain.len() / 2);
+
+            // Client frames use the same flag byte + deflate layout
+            let Frame::Text(text) = client_frame(Encoding::Json) else { unreachable!() };
+            let mut framed = vec![0x00];
+            framed.extend_from_slice(text.as_bytes());
+            assert_eq!(codec.decode(Frame::Binary(framed)).unwrap().msg_type, "subscribe");
+            assert!(codec.decode(Frame::Binary(Vec::new())).is_err());
+        }
+
+        #[test]
+        fn test_inflate_is_capped() {
+            use std::io::Write;
+
+            let codec = SessionCodec::new(Encoding::Json, Compression::Deflate { threshold: DEFAULT_COMPRESSION_THRESHOLD });
+            let mut encoder = flate2::write::DeflateEncoder::new(vec![0x01], flate2::Compression::best());
+            encoder.write_all(&vec![b' '; MAX_INFLATED_SIZE + 1]).unwrap();
+            let bomb = encoder.finish().unwrap();
+
+            assert!(bomb.len() < 4096);
+            assert!(matches!(codec.decode(Frame::Binary(bomb)), Err(CodecError::TooLarge(_))));
+        }
+
+        #[test]
+        fn test_small_messages_skip_compression() {
+            let codec = SessionCodec::new(Encoding::MessagePack, Compression::Deflate { threshold: DEFAULT_COMPRESSION_THRESHOLD });
+            let msg = ServerMessage { event_type: "pong".to_string(), data: json!({}) };
+
+            match codec.encode(&msg).unwrap() {
+                Frame::Binary(bytes) => assert_eq!(bytes[0], 0x00),
+                Frame::Text(_) => panic!("compressed sessions always send binary frames"),
+            }
+        }
+    }
+
+    mod acl_tests {
+        use crate::acl::*;
+        use std::time::{Duration, Instant};
+
+        #[test]
+        fn test_entity_channels() {
+            for entity in [Entity::User(1), Entity::Server(2), Entity::Clan(3), Entity::Process(4)] {
+                assert_eq!(Entity::from_channel(&entity.channel()), Some(entity));
+            }
+            assert_eq!(Entity::from_channel("process:42"), Some(Entity::Process(42)));
+            assert_eq!(Entity::from_channel("world"), None);
+            assert_eq!(Entity::from_channel("process:abc"), None);
+            assert_eq!(Entity::from_channel("bank:1"), None);
+
+            for channel in ModChannel::ALL {
+                assert_eq!(Entity::from_channel(channel.name()), Some(Entity::Moderation(channel)));
+            }
+            assert_eq!(Entity::from_channel("mod.chat-flags"), Some(Entity::Moderation(ModChannel::ChatFlags)));
+            assert_eq!(Entity::from_channel("mod.everything"), None);
+        }
+
+        #[test]
+        fn test_session_acl_expires() {
+            let mut acl = SessionAcl::default();
+            let start = Instant::now();
+            acl.set(Entity::Process(1), true, start);
+            acl.set(Entity::Process(2), false, start);
+
+            assert_eq!(acl.get(&Entity::Process(1), start), Some(true));
+            assert_eq!(acl.get(&Entity::Process(2), start), Some(false));
+            assert_eq!(acl.get(&Entity::Process(3), start), None);
+            assert_eq!(acl.allowed().collect::<Vec<_>>(), vec![&Entity::Process(1)]);
+
+            // Stale decisions have to be checked again
+            assert_eq!(acl.get(&Entity::Process(1), start + ACL_TTL + Duration::from_secs(1)), None);
+
+            // Moderator channels go stale sooner
+            let reports = Entity::Moderation(ModChannel::Reports);
+            acl.set(reports, true, start);
+            assert_eq!(acl.get(&reports, start + MODERATION_ACL_TTL - Duration::from_secs(1)), Some(true));
+            assert_eq!(acl.get(&reports, start + MODERATION_ACL_TTL), None);
+        }
+    }
+
+    mod protocol_tests {
+        use super::*;
+        use crate::codec::*;
+        use crate::protocol::*;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+        use serde_json::Value;
+        use std::collections::BTreeMap;
+
+        /// Compare the samples' wire form with a golden file, or rewrite the
+        /// file when UPDATE_GOLDEN is set
+        fn check_golden(file: &str, samples: &BTreeMap<String, Value>) {
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(file);
+            if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                std::fs::write(&path, serde_json::to_string_pretty(samples).unwrap() + "\n").unwrap();
+                return;
+            }
+            let golden: BTreeMap<String, Value> =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            for (name, wire) in samples {
+                assert_eq!(golden.get(name), Some(wire), "{} changed on the wire; rerun with UPDATE_GOLDEN=1 if intended", name);
+            }
+            for name in golden.keys() {
+                assert!(samples.contains_key(name), "{} is in {} but no longer sent", name, file);
+            }
+        }
+
+        fn client_wire() -> BTreeMap<String, Value> {
+            client_samples()
+                .iter()
+                .map(|event| (client_sample_name(event), serde_json::to_value(event.to_client_message()).unwrap()))
+                .collect()
+        }
+
+        fn server_wire() -> BTreeMap<String, Value> {
+            server_samples()
+                .iter()
+                .map(|msg| (server_sample_name(msg), serde_json::to_value(msg).unwrap()))
+                .collect()
+        }
+
+        #[test]
+        fn test_every_variant_has_a_sample() {
+            let covered: Vec<_> = game_samples().iter().map(game_variant).collect();
+            assert_eq!(covered, (0..GAME_EVENT_VARIANTS).collect::<Vec<_>>());
+            assert_eq!(client_wire().len(), client_samples().len());
+            assert_eq!(server_wire().len(), control_samples().len() + GAME_EVENT_VARIANTS);
+        }
+
+        #[test]
+        fn test_client_golden_samples() {
+            let wire = client_wire();
+            check_golden("client_events.json", &wire);
+            for event in client_samples() {
+                let msg: ClientMessage = serde_json::from_value(wire[&client_sample_name(&event)].clone()).unwrap();
+                assert_eq!(ClientEvent::parse(&msg, true), Ok(event));
+            }
+        }
+
+        #[test]
+        fn test_server_golden_samples() {
+            let wire = server_wire();
+            check_golden("server_events.json", &wire);
+            for event in control_samples() {
+                let golden = &wire[&server_sample_name(&event.to_server_message())];
+                assert_eq!(serde_json::from_value::<ControlEvent>(golden.clone()).unwrap(), event);
+            }
+            for event in game_samples() {
+                let golden = &wire[&event.event_type()];
+                assert_eq!(serde_json::from_value::<GameEvent>(golden["data"].clone()).unwrap(), event);
+            }
+        }
+
+        #[test]
+        fn test_optional_fields_are_the_documented_ones() {
+            let decodes_without = |name: &str, field: &str| -> bool {
+                if let Some(event) = client_samples().into_iter().find(|e| client_sample_name(e) == name) {
+                    let mut msg = event.to_client_message();
+                    msg.data.as_object_mut().unwrap().remove(field);
+                    return ClientEvent::parse(&msg, true).is_ok();
+                }
+                let mut wire = server_wire()[name].clone();
+                if let Some(tag) = wire["data"].get("type").cloned() {
+                    wire["data"]["data"].as_object_mut().unwrap().remove(field);
+                    serde_json::from_value::<GameEvent>(json!({ "type": tag, "data": wire["data"]["data"] })).is_ok()
+                } else {
+                    wire["data"].as_object_mut().unwrap().remove(field);
+                    serde_json::from_value::<ControlEvent>(wire).is_ok()
+                }
+            };
+
+            let document = ProtocolDocument::generate();
+            for message in document.client.iter().chain(&document.replies).chain(&document.events) {
+                for field in &message.fields {
+                    assert_eq!(
+                        decodes_without(&message.name, &field.name),
+                        field.optional,
+                        "{}.{} optional-ness does not match OPTIONAL_FIELDS",
+                        message.name,
+                        field.name
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn test_strict_clients_get_unknown_fields_refused() {
+            let msg = |msg_type: &str, data: Value| ClientMessage { msg_type: msg_type.to_string(), data };
+
+            let extra = msg("subscribe", json!({ "channel": "world", "priority": 1 }));
+            assert_eq!(ClientEvent::parse(&extra, false), Ok(ClientEvent::Subscribe { channel: "world".to_string() }));
+            assert_eq!(
+                ClientEvent::parse(&extra, true),
+                Err(ProtocolError::UnknownField { msg_type: "subscribe".to_string(), field: "priority".to_string() })
+            );
+
+            // A misspelt required field is an error either way
+            let typo = msg("subscribe", json!({ "chanel": "world" }));
+            assert!(matches!(ClientEvent::parse(&typo, false), Err(ProtocolError::Invalid { .. })));
+            assert!(matches!(ClientEvent::parse(&typo, true), Err(ProtocolError::UnknownField { .. })));
+
+            assert_eq!(
+                ClientEvent::parse(&msg("ping", json!({})), false),
+                Err(ProtocolError::UnknownType("ping".to_string()))
+            );
+        }
+
+        #[test]
+        fn test_protocol_document() {
+            let document = ProtocolDocument::generate();
+            assert_eq!(document.version, PROTOCOL_VERSION);
+            assert_eq!(document.events.len(), GAME_EVENT_VARIANTS);
+
+            let auth = &document.client[0];
+            assert_eq!(auth.name, "auth");
+            let token = auth.fields.iter().find(|f| f.name == "token").unwrap();
+            assert_eq!((token.kind, token.optional), ("string", false));
+            assert!(auth.fields.iter().find(|f| f.name == "strict").unwrap().optional);
+
+            let markdown = document.to_markdown();
+            for name in client_wire().keys().chain(server_wire().keys()) {
+                assert!(markdown.contains(&format!("### `{}`", name)), "{} is missing from the document", name);
+            }
+        }
+
+        /// Leaf values to put in place of a sample's
+        #[derive(Debug, Clone)]
+        struct Leaves {
+            strings: Vec<String>,
+            ints: Vec<i32>,
+            floats: Vec<f32>,
+            bools: Vec<bool>,
+        }
+
+        fn leaves() -> impl Strategy<Value = Leaves> {
+            (
+                vec(any::<String>(), 1..8),
+                vec(0..i32::MAX, 1..8),
+                vec(-1.0e6f32..1.0e6f32, 1..8),
+                vec(any::<bool>(), 1..8),
+            )
+                .prop_map(|(strings, ints, floats, bools)| Leaves { strings, ints, floats, bools })
+        }
+
+        /// Replace every leaf of `value` with one of `leaves` of the same JSON
+        /// type. Integers stay non-negative and floats stay f32 so every
+        /// field type can hold them.
+        fn mutate(value: &mut Value, leaves: &Leaves, at: &mut usize) {
+            *at += 1;
+            match value {
+                Value::String(s) => *s = leaves.strings[*at % leaves.strings.len()].clone(),
+                Value::Bool(b) => *b = leaves.bools[*at % leaves.bools.len()],
+                Value::Number(n) if n.is_f64() => {
+                    *n = serde_json::Number::from_f64(leaves.floats[*at % leaves.floats.len()] as f64).unwrap()
+                }
+                Value::Number(n) => *n = leaves.ints[*at % leaves.ints.len()].into(),
+                Value::Array(items) => items.iter_mut().for_each(|item| mutate(item, leaves, at)),
+                Value::Object(fields) => fields.values_mut().for_each(|item| mutate(item, leaves, at)),
+                Value::Null => {}
+            }
+        }
+
+        fn client_frame(encoding: Encoding, msg: &ClientMessage) -> Frame {
+            match encoding {
+                Encoding::Json => Frame::Text(serde_json::to_string(msg).unwrap()),
+                Encoding::MessagePack => Frame::Binary(rmp_serde::to_vec_named(msg).unwrap()),
+                Encoding::Cbor => {
+                    let mut buf = Vec::new();
+                    ciborium::into_writer(msg, &mut buf).unwrap();
+                    Frame::Binary(buf)
+                }
+            }
+        }
+
+        fn decode_server_frame(encoding: Encoding, frame: Frame) -> ServerMessage {
+            match (encoding, frame) {
+                (_, Frame::Text(text)) => serde_json::from_str(&text).unwrap(),
+                (Encoding::Cbor, Frame::Binary(bytes)) => ciborium::from_reader(bytes.as_slice()).unwrap(),
+                (_, Frame::Binary(bytes)) => rmp_serde::from_slice(&bytes).unwrap(),
+            }
+        }
+
+        proptest! {
+            #[test]
+            fn prop_client_events_round_trip(index in 0..client_samples().len(), leaves in leaves()) {
+                let mut msg = client_samples()[index].to_client_message();
+                mutate(&mut msg.data, &leaves, &mut 0);
+                let event = ClientEvent::parse(&msg, true).unwrap();
+
+                for encoding in [Encoding::Json, Encoding::MessagePack, Encoding::Cbor] {
+                    let codec = SessionCodec::new(encoding, Compression::None);
+                    let decoded = codec.decode(client_frame(encoding, &event.to_client_message())).unwrap();
+                    prop_assert_eq!(ClientEvent::parse(&decoded, true), Ok(event.clone()));
+                }
+            }
+
+            #[test]
+            fn prop_game_events_round_trip(index in 0..GAME_EVENT_VARIANTS, leaves in leaves()) {
+                let mut tagged = serde_json::to_value(&game_samples()[index]).unwrap();
+                mutate(&mut tagged["data"], &leaves, &mut 0);
+                let event: GameEvent = serde_json::from_value(tagged).unwrap();
+
+                for encoding in [Encoding::Json, Encoding::MessagePack, Encoding::Cbor] {
+                    let codec = SessionCodec::new(encoding, Compression::None);
+                    let decoded = decode_server_frame(encoding, codec.encode(&event.to_server_message()).unwrap());
+                    prop_assert_eq!(decoded.event_type, event.event_type());
+                    prop_assert_eq!(serde_json::from_value::<GameEvent>(decoded.data).unwrap(), event.clone());
+                }
+            }
+        }
+    }
+}
@@ -11,6 +11,7 @@ pub mod stream;
 pub mod subscriber;
 pub mod publisher;
 pub mod replay;
+pub mod schema;
 
 // Event behavior modules
 pub mod loggable;
@@ -27,6 +28,7 @@ pub use store::{EventStore, EventFilter, EventQuery};
 pub use stream::{EventStream, EventStreamConfig};
 pub use subscriber::{EventSubscriber, SubscriptionConfig};
 pub use publisher::{EventPublisher, PublishConfig};
+pub use schema::{EventSchema, SchemaError, SchemaRegistry, Upcaster};
 
 use he_core::HelixResult;
 
@@ -2,7 +2,8 @@
 
 use crate::event::{Event, EventType, EventData, EventMetadata};
 use crate::dispatcher::EventDispatcher;
-use he_core::{HelixError, HelixResult, HelixId, RequestId, ProcessId};
+use crate::schema::SchemaRegistry;
+use he_core::{HelixError, HelixResult, HelixId, RequestId, ProcessId, RequestContext};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -110,6 +111,8 @@ pub struct DispatcherEventPublisher {
     correlation_context: Arc<RwLock<Option<CorrelationContext>>>,
     /// Batch buffer if batching is enabled
     batch_buffer: Arc<RwLock<Vec<Event>>>,
+    /// Payload schemas events are validated against before dispatch
+    schemas: Option<Arc<SchemaRegistry>>,
 }
 
 impl DispatcherEventPublisher {
@@ -120,9 +123,16 @@ impl DispatcherEventPublisher {
             dispatcher,
             correlation_context: Arc::new(RwLock::new(None)),
             batch_buffer: Arc::new(RwLock::new(Vec::new())),
+            schemas: None,
         }
     }
 
+    /// Validate payloads against `schemas` before they are published
+    pub fn with_schemas(mut self, schemas: Arc<SchemaRegistry>) -> Self {
+        self.schemas = Some(schemas);
+        self
+    }
+
     /// Set correlation context for automatic correlation
     pub async fn set_correlation_context(&self, context: CorrelationContext) {
         let mut ctx = self.correlation_context.write().await;
@@ -141,6 +151,14 @@ impl DispatcherEventPublisher {
         ctx.clone()
     }
 
+    /// Check the payload against its schema, stamping the schema version
+    fn validate_event(&self, event: &mut Event) -> HelixResult<()> {
+        if let Some(ref schemas) = self.schemas {
+            schemas.validate(event)?;
+        }
+        Ok(())
+    }
+
     /// Enhance event with configured defaults and correlation
     async fn enhance_event(&self, mut event: Event) -> Event {
         // Add default source
@@ -173,6 +191,20 @@ impl DispatcherEventPublisher {
             }
         }
 
+        // Events raised while serving a request are caused by it
+        if let Some(request) = RequestContext::current() {
+            let metadata = &mut event.metadata;
+            metadata.request_id.get_or_insert(request.request_id);
+            metadata.causation_id.get_or_insert(request.request_id.0);
+            metadata.custom.entry("trace_id".to_string()).or_insert_with(|| request.trace_id.clone().into());
+            if let Some(user_id) = request.user_id {
+                metadata.custom.entry("user_id".to_string()).or_insert_with(|| user_id.into());
+            }
+            if let Some(shard) = request.shard {
+                metadata.custom.entry("shard".to_string()).or_insert_with(|| shard.into());
+            }
+        }
+
         event
     }
 }
@@ -180,7 +212,8 @@ impl DispatcherEventPublisher {
 #[async_trait]
 impl EventPublisher for DispatcherEventPublisher {
     async fn publish(&self, event: Event) -> HelixResult<()> {
-        let enhanced_event = self.enhance_event(event).await;
+        let mut enhanced_event = self.enhance_event(event).await;
+        self.validate_event(&mut enhanced_event)?;
 
         // Check if batching is enabled
         if let Some(ref batch_config) = self.config.batch_config {
@@ -210,7 +243,10 @@ impl EventPublisher for DispatcherEventPublisher {
 
         let mut enhanced_events = Vec::with_capacity(events.len());
         for event in events {
-            enhanced_events.push(self.enhance_event(event).await);
+            let mut enhanced_event = self.enhance_event(event).await;
+            // Nothing in a batch is published if any payload is invalid
+            self.validate_event(&mut enhanced_event)?;
+            enhanced_events.push(enhanced_event);
         }
 
         self.publish_batch_internal(enhanced_events).await
@@ -293,6 +329,7 @@ impl Default for CorrelationContext {
 /// Event publisher builder for easy configuration
 pub struct EventPublisherBuilder {
     config: PublishConfig,
+    schemas: Option<Arc<SchemaRegistry>>,
 }
 
 impl EventPublisherBuilder {
@@ -300,6 +337,7 @@ impl EventPublisherBuilder {
     pub fn new() -> Self {
         Self {
             config: PublishConfig::default(),
+            schemas: None,
         }
     }
 
@@ -331,9 +369,19 @@ impl EventPublisherBuilder {
         self
     }
 
+    /// Validate payloads against registered schemas
+    pub fn with_schemas(mut self, schemas: Arc<SchemaRegistry>) -> Self {
+        self.schemas = Some(schemas);
+        self
+    }
+
     /// Build the publisher with a dispatcher
     pub fn build(self, dispatcher: Arc<EventDispatcher>) -> DispatcherEventPublisher {
-        DispatcherEventPublisher::new(self.config, dispatcher)
+        let publisher = DispatcherEventPublisher::new(self.config, dispatcher);
+        match self.schemas {
+            Some(schemas) => publisher.with_schemas(schemas),
+            None => publisher,
+        }
     }
 }
 
@@ -460,4 +508,25 @@ mod tests {
         
         assert_eq!(scoped.context().correlation_id.unwrap(), correlation_id);
     }
+
+    #[tokio::test]
+    async fn test_request_context_becomes_causation() {
+        let dispatcher = Arc::new(EventDispatcher::new(DispatchConfig::default()).await.unwrap());
+        let publisher = DispatcherEventPublisher::new(PublishConfig::default(), dispatcher);
+        let request = RequestContext::new(RequestId::new()).with_user(Some(42));
+
+        let event = Event::new(
+            EventType::SystemStarted,
+            EventData::SystemStatus {
+                status: "started".to_string(),
+                details: serde_json::Value::Null,
+            },
+        );
+        let enhanced = request.clone().scope(publisher.enhance_event(event)).await;
+
+        assert_eq!(enhanced.metadata.request_id, Some(request.request_id));
+        assert_eq!(enhanced.metadata.causation_id, Some(request.request_id.0));
+        assert_eq!(enhanced.metadata.custom["user_id"], 42);
+        assert_eq!(enhanced.metadata.custom["trace_id"], request.trace_id.as_str());
+    }
 }
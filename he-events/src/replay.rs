@@ -3,6 +3,7 @@
 use crate::event::{Event, EventType, EventCategory};
 use crate::store::{EventStore, EventQuery, EventFilter, OrderBy, OrderDirection};
 use crate::publisher::EventPublisher;
+use crate::schema::SchemaRegistry;
 use he_core::{HelixError, HelixResult, HelixId};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -44,12 +45,21 @@ pub struct EventReplaySystem {
     store: Arc<EventStore>,
     /// Configuration
     config: ReplayConfig,
+    /// Schemas stored payloads are upcast with before they are replayed
+    schemas: Option<Arc<SchemaRegistry>>,
 }
 
 impl EventReplaySystem {
     /// Create a new replay system
     pub fn new(store: Arc<EventStore>, config: ReplayConfig) -> Self {
-        Self { store, config }
+        Self { store, config, schemas: None }
+    }
+
+    /// Upcast events stored under older schema versions to the current one,
+    /// so consumers only see payloads they can read
+    pub fn with_schemas(mut self, schemas: Arc<SchemaRegistry>) -> Self {
+        self.schemas = Some(schemas);
+        self
     }
 
     /// Replay all events in the store
@@ -276,6 +286,10 @@ impl EventReplaySystem {
     where
         P: EventPublisher,
     {
+        if let Some(ref schemas) = self.schemas {
+            schemas.upcast_event(&mut event)?;
+        }
+
         // Generate new ID for replayed event to avoid conflicts
         event.id = HelixId::new_v4();
         
@@ -352,6 +366,7 @@ pub struct ReplayError {
 /// Event replay builder for easy configuration
 pub struct EventReplayBuilder {
     config: ReplayConfig,
+    schemas: Option<Arc<SchemaRegistry>>,
 }
 
 impl EventReplayBuilder {
@@ -359,6 +374,7 @@ impl EventReplayBuilder {
     pub fn new() -> Self {
         Self {
             config: ReplayConfig::default(),
+            schemas: None,
         }
     }
 
@@ -392,9 +408,19 @@ impl EventReplayBuilder {
         self
     }
 
+    /// Upcast stored payloads with registered schemas
+    pub fn schemas(mut self, schemas: Arc<SchemaRegistry>) -> Self {
+        self.schemas = Some(schemas);
+        self
+    }
+
     /// Build the replay system
     pub fn build(self, store: Arc<EventStore>) -> EventReplaySystem {
-        EventReplaySystem::new(store, self.config)
+        let replay = EventReplaySystem::new(store, self.config);
+        match self.schemas {
+            Some(schemas) => replay.with_schemas(schemas),
+            None => replay,
+        }
     }
 }
 
@@ -0,0 +1,396 @@
+//! Versioned payload schemas for event types
+//!
+//! `EventData::Custom` payloads are free-form JSON, so nothing stops a
+//! producer from publishing a shape its consumers cannot read, or a stored
+//! event from becoming unreadable once its payload type changes. Each event
+//! type can declare the Rust type of its payload in a [`SchemaRegistry`]:
+//!
+//! - producers validate payloads against it on publish, which also stamps
+//!   the schema version into `EventMetadata::version`;
+//! - consumers negotiate the version they read, and events stored under an
+//!   older version are upcast one version at a time on the way, which is
+//!   what replay does;
+//! - [`SchemaRegistry::compatibility_matrix`] decodes a fixture of every
+//!   version, upcast to the current one, and the current fixture with an
+//!   unknown field added, so a test can fail the build when a payload change
+//!   would break replays or consumers still on the previous release.
+
+use crate::event::{Event, EventData, EventType};
+use he_core::HelixError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Turns a payload of one schema version into the next version's
+pub type Upcaster = fn(serde_json::Value) -> Result<serde_json::Value, SchemaError>;
+
+/// Field added to fixtures to check readers ignore fields they do not know
+const UNKNOWN_FIELD: &str = "__schema_check_unknown_field";
+
+/// A payload type with a versioned schema
+pub trait EventSchema: Serialize + DeserializeOwned + 'static {
+    /// Current version; versions start at 1, the version events carried
+    /// before schemas existed
+    const VERSION: u32;
+
+    /// One upcaster per older version, oldest first: the first turns a
+    /// version 1 payload into version 2
+    fn upcasters() -> Vec<Upcaster> {
+        Vec::new()
+    }
+
+    /// A sample payload of every version from 1 to [`Self::VERSION`]
+    fn fixtures() -> Vec<(u32, serde_json::Value)>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SchemaError {
+    #[error("a schema is already registered for {0:?}")]
+    AlreadyRegistered(EventType),
+    #[error("schema for {event_type:?} is at version {version} but has {upcasters} upcasters")]
+    MissingUpcasters { event_type: EventType, version: u32, upcasters: usize },
+    #[error("payload of {event_type:?} does not match schema version {version}: {reason}")]
+    Invalid { event_type: EventType, version: u32, reason: String },
+    #[error("{event_type:?} has no version {version}; the schema is at {current}")]
+    UnknownVersion { event_type: EventType, version: u32, current: u32 },
+    #[error("{event_type:?} events are at version {current}; the reader supports {min} to {max}")]
+    NoCommonVersion { event_type: EventType, current: u32, min: u32, max: u32 },
+    #[error("cannot read {event_type:?} version {from} as older version {to}")]
+    Downcast { event_type: EventType, from: u32, to: u32 },
+    #[error("upcasting {event_type:?} from version {from} failed: {reason}")]
+    Upcast { event_type: EventType, from: u32, reason: String },
+    #[error("{0:?} events do not carry a custom payload")]
+    NotCustom(EventType),
+    #[error("no schema is registered for {0:?}")]
+    Unregistered(EventType),
+}
+
+impl From<SchemaError> for HelixError {
+    fn from(err: SchemaError) -> Self {
+        HelixError::validation(err.to_string())
+    }
+}
+
+/// What the registry keeps of a schema
+#[derive(Clone)]
+struct RegisteredSchema {
+    payload_type: &'static str,
+    version: u32,
+    upcasters: Vec<Upcaster>,
+    decode: fn(serde_json::Value) -> Result<(), serde_json::Error>,
+    fixtures: fn() -> Vec<(u32, serde_json::Value)>,
+}
+
+fn decode_as<T: EventSchema>(payload: serde_json::Value) -> Result<(), serde_json::Error> {
+    serde_json::from_value::<T>(payload).map(|_| ())
+}
+
+/// Payload schemas by event type
+#[derive(Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<EventType, RegisteredSchema>,
+}
+
+impl std::fmt::Debug for SchemaRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let versions: HashMap<_, _> = self.schemas.iter().map(|(t, s)| (t, (s.payload_type, s.version))).collect();
+        f.debug_struct("SchemaRegistry").field("schemas", &versions).finish()
+    }
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare `T` as the payload of `event_type`
+    pub fn register<T: EventSchema>(&mut self, event_type: EventType) -> Result<(), SchemaError> {
+        if self.schemas.contains_key(&event_type) {
+            return Err(SchemaError::AlreadyRegistered(event_type));
+        }
+        let upcasters = T::upcasters();
+        if upcasters.len() + 1 != T::VERSION as usize {
+            return Err(SchemaError::MissingUpcasters { event_type, version: T::VERSION, upcasters: upcasters.len() });
+        }
+
+        self.schemas.insert(event_type, RegisteredSchema {
+            payload_type: std::any::type_name::<T>(),
+            version: T::VERSION,
+            upcasters,
+            decode: decode_as::<T>,
+            fixtures: T::fixtures,
+        });
+        Ok(())
+    }
+
+    /// Current schema version of an event type
+    pub fn version(&self, event_type: &EventType) -> Option<u32> {
+        self.schemas.get(event_type).map(|schema| schema.version)
+    }
+
+    /// Check an event's payload before it is published and stamp the schema
+    /// version. Event types without a schema pass unchanged.
+    pub fn validate(&self, event: &mut Event) -> Result<(), SchemaError> {
+        let Some(schema) = self.schemas.get(&event.event_type) else {
+            return Ok(());
+        };
+        let EventData::Custom { payload, .. } = &event.data else {
+            return Err(SchemaError::NotCustom(event.event_type.clone()));
+        };
+        (schema.decode)(payload.clone()).map_err(|e| SchemaError::Invalid {
+            event_type: event.event_type.clone(),
+            version: schema.version,
+            reason: e.to_string(),
+        })?;
+
+        event.metadata.version = schema.version;
+        Ok(())
+    }
+
+    /// The version a reader supporting `supported` should read an event
+    /// type at: the newest both sides know, as events are only upcast
+    pub fn negotiate(&self, event_type: &EventType, supported: RangeInclusive<u32>) -> Result<u32, SchemaError> {
+        let schema = self.schemas.get(event_type).ok_or_else(|| SchemaError::Unregistered(event_type.clone()))?;
+        let version = schema.version.min(*supported.end());
+        if !supported.contains(&version) {
+            return Err(SchemaError::NoCommonVersion {
+                event_type: event_type.clone(),
+                current: schema.version,
+                min: *supported.start(),
+                max: *supported.end(),
+            });
+        }
+        Ok(version)
+    }
+
+    /// Upcast a payload written at version `from` to version `to`
+    pub fn upcast(
+        &self,
+        event_type: &EventType,
+        mut payload: serde_json::Value,
+        from: u32,
+        to: u32,
+    ) -> Result<serde_json::Value, SchemaError> {
+        let schema = self.schemas.get(event_type).ok_or_else(|| SchemaError::Unregistered(event_type.clone()))?;
+        for version in [from, to] {
+            if version == 0 || version > schema.version {
+                return Err(SchemaError::UnknownVersion { event_type: event_type.clone(), version, current: schema.version });
+            }
+        }
+        if to < from {
+            return Err(SchemaError::Downcast { event_type: event_type.clone(), from, to });
+        }
+
+        for version in from..to {
+            let upcaster = schema.upcasters[(version - 1) as usize];
+            payload = upcaster(payload).map_err(|e| SchemaError::Upcast {
+                event_type: event_type.clone(),
+                from: version,
+                reason: e.to_string(),
+            })?;
+        }
+        Ok(payload)
+    }
+
+    /// Bring a stored event's payload up to the current schema version, as
+    /// replay does before publishing it again
+    pub fn upcast_event(&self, event: &mut Event) -> Result<(), SchemaError> {
+        let Some(current) = self.version(&event.event_type) else {
+            return Ok(());
+        };
+        if event.metadata.version == current {
+            return Ok(());
+        }
+        let EventData::Custom { payload, .. } = &mut event.data else {
+            return Err(SchemaError::NotCustom(event.event_type.clone()));
+        };
+
+        *payload = self.upcast(&event.event_type, payload.take(), event.metadata.version, current)?;
+        event.metadata.version = current;
+        Ok(())
+    }
+
+    /// Read an event's payload as `T`, upcasting it to `T::VERSION`
+    pub fn decode<T: EventSchema>(&self, event: &Event) -> Result<T, SchemaError> {
+        let EventData::Custom { payload, .. } = &event.data else {
+            return Err(SchemaError::NotCustom(event.event_type.clone()));
+        };
+        let payload = self.upcast(&event.event_type, payload.clone(), event.metadata.version, T::VERSION)?;
+        serde_json::from_value(payload).map_err(|e| SchemaError::Invalid {
+            event_type: event.event_type.clone(),
+            version: T::VERSION,
+            reason: e.to_string(),
+        })
+    }
+
+    /// Check every registered schema: each version's fixture must upcast to
+    /// and decode as the current version, and the current fixture must still
+    /// decode with a field added, as a reader one release behind would see it
+    pub fn compatibility_matrix(&self) -> Vec<CompatibilityCheck> {
+        let mut checks = Vec::new();
+        for (event_type, schema) in &self.schemas {
+            let fixtures = (schema.fixtures)();
+            for version in 1..=schema.version {
+                let fixture = fixtures.iter().find(|(v, _)| *v == version).map(|(_, payload)| payload.clone());
+                let outcome = match fixture {
+                    None => Err(format!("no fixture for version {}", version)),
+                    Some(payload) => self
+                        .upcast(event_type, payload, version, schema.version)
+                        .map_err(|e| e.to_string())
+                        .and_then(|upcast| (schema.decode)(upcast).map_err(|e| e.to_string())),
+                };
+                checks.push(CompatibilityCheck::new(event_type, version, CheckKind::Upcast, outcome));
+            }
+
+            let current = fixtures.into_iter().find(|(v, _)| *v == schema.version).map(|(_, payload)| payload);
+            let outcome = match current {
+                Some(serde_json::Value::Object(mut fields)) => {
+                    fields.insert(UNKNOWN_FIELD.to_string(), serde_json::Value::Bool(true));
+                    (schema.decode)(serde_json::Value::Object(fields)).map_err(|e| e.to_string())
+                }
+                Some(_) => Err("current fixture is not an object".to_string()),
+                None => Err(format!("no fixture for version {}", schema.version)),
+            };
+            checks.push(CompatibilityCheck::new(event_type, schema.version, CheckKind::UnknownField, outcome));
+        }
+
+        checks.sort_by(|a, b| (&a.event_type, a.version, a.kind).cmp(&(&b.event_type, b.version, b.kind)));
+        checks
+    }
+
+    /// The failed checks of [`Self::compatibility_matrix`], if any
+    pub fn check_compatibility(&self) -> Result<(), Vec<CompatibilityCheck>> {
+        let failed: Vec<_> = self.compatibility_matrix().into_iter().filter(|check| check.error.is_some()).collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckKind {
+    /// A payload of this version read as the current version
+    Upcast,
+    /// The current payload with a field the reader does not know
+    UnknownField,
+}
+
+/// One cell of the compatibility matrix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompatibilityCheck {
+    /// Event type name, as in `EventType`'s debug output
+    pub event_type: String,
+    pub version: u32,
+    pub kind: CheckKind,
+    /// `None` when the check passed
+    pub error: Option<String>,
+}
+
+impl CompatibilityCheck {
+    fn new(event_type: &EventType, version: u32, kind: CheckKind, outcome: Result<(), String>) -> Self {
+        Self { event_type: format!("{:?}", event_type), version, kind, error: outcome.err() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Version 2 split `name` into `first` and `last` and added `level`
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Joined {
+        first: String,
+        last: String,
+        #[serde(default)]
+        level: u32,
+    }
+
+    fn split_name(mut payload: serde_json::Value) -> Result<serde_json::Value, SchemaError> {
+        let name = payload["name"].as_str().unwrap_or_default().to_string();
+        let (first, last) = name.split_once(' ').unwrap_or((&name, ""));
+        payload["first"] = first.into();
+        payload["last"] = last.into();
+        Ok(payload)
+    }
+
+    impl EventSchema for Joined {
+        const VERSION: u32 = 2;
+
+        fn upcasters() -> Vec<Upcaster> {
+            vec![split_name]
+        }
+
+        fn fixtures() -> Vec<(u32, serde_json::Value)> {
+            vec![
+                (1, serde_json::json!({ "name": "Ada Lovelace" })),
+                (2, serde_json::json!({ "first": "Ada", "last": "Lovelace", "level": 3 })),
+            ]
+        }
+    }
+
+    fn joined_event(payload: serde_json::Value) -> Event {
+        Event::new(
+            EventType::Custom("joined".to_string()),
+            EventData::Custom { data_type: "joined".to_string(), payload },
+        )
+    }
+
+    #[test]
+    fn test_validate_and_upcast() {
+        let event_type = EventType::Custom("joined".to_string());
+        let mut registry = SchemaRegistry::new();
+        registry.register::<Joined>(event_type.clone()).unwrap();
+        assert!(matches!(registry.register::<Joined>(event_type.clone()), Err(SchemaError::AlreadyRegistered(_))));
+
+        let mut event = joined_event(serde_json::json!({ "first": "Grace", "last": "Hopper" }));
+        registry.validate(&mut event).unwrap();
+        assert_eq!(event.metadata.version, 2);
+        assert!(matches!(
+            registry.validate(&mut joined_event(serde_json::json!({ "first": 7 }))),
+            Err(SchemaError::Invalid { .. })
+        ));
+
+        // Stored before the schema existed, so at version 1
+        let mut stored = joined_event(serde_json::json!({ "name": "Ada Lovelace" }));
+        let joined: Joined = registry.decode(&stored).unwrap();
+        assert_eq!(joined, Joined { first: "Ada".to_string(), last: "Lovelace".to_string(), level: 0 });
+        registry.upcast_event(&mut stored).unwrap();
+        assert_eq!(stored.metadata.version, 2);
+
+        assert_eq!(registry.negotiate(&event_type, 1..=5), Ok(2));
+        assert_eq!(registry.negotiate(&event_type, 1..=1), Ok(1));
+        assert!(registry.negotiate(&event_type, 3..=4).is_err());
+        assert!(matches!(registry.upcast(&event_type, serde_json::json!({}), 2, 1), Err(SchemaError::Downcast { .. })));
+    }
+
+    #[test]
+    fn test_compatibility_matrix() {
+        let mut registry = SchemaRegistry::new();
+        registry.register::<Joined>(EventType::Custom("joined".to_string())).unwrap();
+        assert_eq!(registry.compatibility_matrix().len(), 3);
+        assert_eq!(registry.check_compatibility(), Ok(()));
+
+        /// Version 2 with the upcaster forgotten
+        #[derive(Serialize, Deserialize)]
+        struct Broken {
+            first: String,
+        }
+        impl EventSchema for Broken {
+            const VERSION: u32 = 2;
+            fn upcasters() -> Vec<Upcaster> {
+                vec![Ok]
+            }
+            fn fixtures() -> Vec<(u32, serde_json::Value)> {
+                vec![(1, serde_json::json!({ "name": "Ada" })), (2, serde_json::json!({ "first": "Ada" }))]
+            }
+        }
+        registry.register::<Broken>(EventType::Custom("broken".to_string())).unwrap();
+        let failed = registry.check_compatibility().unwrap_err();
+        assert_eq!(failed.len(), 1);
+        assert_eq!((failed[0].version, failed[0].kind), (1, CheckKind::Upcast));
+    }
+}
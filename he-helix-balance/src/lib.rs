@@ -1,11 +1,20 @@
 //! Helix Game Balance System
+//!
+//! Formulas live in `factors.json` as he-helix-factor expressions, next to
+//! the cases designers expect them to meet; the tests check every case.
 
 pub mod software;
 
+use he_helix_factor::expr::{ExprError, FactorError, FactorSet};
 use he_helix_factor::Factor;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// The built-in factor file
+pub const FACTORS_JSON: &str = include_str!("../factors.json");
+/// Factor [`BalanceCalculator::calculate_execution_time`] evaluates
+pub const EXECUTION_TIME: &str = "execution_time";
+
 /// Game balance configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BalanceConfig {
@@ -63,14 +72,44 @@ impl Default for BalanceConfig {
 /// Balance calculator for game mechanics
 pub struct BalanceCalculator {
     config: BalanceConfig,
+    factors: FactorSet,
+}
+
+/// The factors in [`FACTORS_JSON`]
+pub fn builtin_factors() -> FactorSet {
+    FactorSet::from_json(FACTORS_JSON).expect("built-in factors.json is invalid").0
 }
 
 impl BalanceCalculator {
     pub fn new(config: BalanceConfig) -> Self {
-        Self { config }
+        Self { config, factors: builtin_factors() }
+    }
+
+    /// Use designer-supplied factors; they must define [`EXECUTION_TIME`]
+    pub fn with_factors(config: BalanceConfig, factors: FactorSet) -> Result<Self, FactorError> {
+        if !factors.contains(EXECUTION_TIME) {
+            return Err(FactorError { factor: EXECUTION_TIME.to_string(), error: ExprError::UnknownFactor });
+        }
+        Ok(Self { config, factors })
+    }
+
+    pub fn factors(&self) -> &FactorSet {
+        &self.factors
+    }
+
+    /// Evaluate any factor, with the configured multipliers as inputs too
+    pub fn factor(&self, name: &str, inputs: &[(&str, f64)]) -> Result<f64, FactorError> {
+        let mut values = HashMap::from([
+            ("complexity_multiplier".to_string(), self.config.software.complexity_multiplier),
+            ("skill_factor".to_string(), self.config.software.skill_factor),
+            ("cpu_factor".to_string(), self.config.hardware.cpu_factor),
+        ]);
+        values.extend(inputs.iter().map(|(name, value)| (name.to_string(), *value)));
+        self.factors.eval(name, &values)
     }
 
-    /// Calculate execution time for a software process
+    /// Calculate execution time for a software process. Infinite when it
+    /// cannot be worked out, as with no CPU power.
     pub fn calculate_execution_time(
         &self,
         base_time: f64,
@@ -78,11 +117,13 @@ impl BalanceCalculator {
         cpu_power: f64,
         skill_level: f64,
     ) -> f64 {
-        let software_factor = base_time * (1.0 + complexity * self.config.software.complexity_multiplier);
-        let skill_factor = 1.0 + (skill_level * self.config.software.skill_factor);
-        let hardware_factor = cpu_power * self.config.hardware.cpu_factor;
-
-        software_factor / (skill_factor * hardware_factor)
+        let inputs = [
+            ("base_time", base_time),
+            ("complexity", complexity),
+            ("cpu_power", cpu_power),
+            ("skill_level", skill_level),
+        ];
+        self.factor(EXECUTION_TIME, &inputs).unwrap_or(f64::INFINITY)
     }
 }
 
@@ -90,4 +131,26 @@ impl Default for BalanceCalculator {
     fn default() -> Self {
         Self::new(BalanceConfig::default())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_designer_cases() {
+        let (factors, cases) = FactorSet::from_json(FACTORS_JSON).unwrap();
+        let failures: Vec<String> = factors.check(&cases).iter().map(ToString::to_string).collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn test_execution_time() {
+        let calculator = BalanceCalculator::default();
+        assert_eq!(calculator.calculate_execution_time(10.0, 2.0, 2.0, 5.0), 4.0);
+        assert_eq!(calculator.calculate_execution_time(10.0, 2.0, 0.0, 5.0), f64::INFINITY);
+
+        let (factors, _) = FactorSet::from_json(r#"{ "inputs": [], "factors": { "other": { "expr": "1" } } }"#).unwrap();
+        assert!(BalanceCalculator::with_factors(BalanceConfig::default(), factors).is_err());
+    }
+}
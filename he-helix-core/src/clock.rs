@@ -0,0 +1,233 @@
+//! Game clock
+//!
+//! Game logic reads the time through [`Clock`] instead of calling `Utc::now()`
+//! or `SystemTime::now()` directly. Production runs on [`SystemClock`]; tests and
+//! simulations swap in a [`ManualClock`] and move time forward explicitly.
+//!
+//! Code that owns its state takes a [`SharedClock`]. Free functions and plain data
+//! types go through [`now`] / [`system_now`], which read the clock installed with
+//! [`install`], or the per-thread override from [`set_thread_clock`] if one is set.
+//! The override is what tests should use, since tests run in parallel threads.
+//!
+//! [`TimeWarp`] replays the time a player was offline in steps, never running
+//! ahead of the real clock and never further back than a configured bound.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use once_cell::sync::Lazy;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> DateTime<Utc>;
+
+    fn system_time(&self) -> SystemTime {
+        self.now().into()
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Wall-clock time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    /// Start at the current wall-clock time
+    pub fn starting_now() -> Self {
+        Self::new(Utc::now())
+    }
+
+    pub fn set(&self, to: DateTime<Utc>) {
+        *self.now.lock().unwrap() = to;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = *now + to_chrono(by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Clock for offline catch-up.
+///
+/// Starts at the time the player was last seen, or `max_span` before the base
+/// clock if that is earlier, and moves forward in steps until it reaches the
+/// base clock. Time beyond the bound is dropped rather than simulated.
+#[derive(Debug)]
+pub struct TimeWarp {
+    base: SharedClock,
+    cursor: Mutex<DateTime<Utc>>,
+    skipped: ChronoDuration,
+}
+
+impl TimeWarp {
+    pub fn new(base: SharedClock, last_seen: DateTime<Utc>, max_span: Duration) -> Self {
+        let earliest = base.now() - to_chrono(max_span);
+        let start = last_seen.max(earliest);
+        Self {
+            base,
+            cursor: Mutex::new(start),
+            skipped: (start - last_seen).max(ChronoDuration::zero()),
+        }
+    }
+
+    /// Move forward by up to `step`. Returns false once caught up with the base clock.
+    pub fn advance(&self, step: Duration) -> bool {
+        let target = self.base.now();
+        let mut cursor = self.cursor.lock().unwrap();
+        *cursor = (*cursor + to_chrono(step)).min(target);
+        *cursor < target
+    }
+
+    pub fn is_caught_up(&self) -> bool {
+        *self.cursor.lock().unwrap() >= self.base.now()
+    }
+
+    /// Time still to replay
+    pub fn remaining(&self) -> Duration {
+        (self.base.now() - *self.cursor.lock().unwrap())
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// Offline time cut off by the bound
+    pub fn skipped(&self) -> Duration {
+        self.skipped.to_std().unwrap_or_default()
+    }
+}
+
+impl Clock for TimeWarp {
+    fn now(&self) -> DateTime<Utc> {
+        *self.cursor.lock().unwrap()
+    }
+}
+
+static GLOBAL: Lazy<RwLock<SharedClock>> = Lazy::new(|| RwLock::new(Arc::new(SystemClock)));
+
+thread_local! {
+    static THREAD_CLOCK: RefCell<Option<SharedClock>> = const { RefCell::new(None) };
+}
+
+/// Replace the process-wide clock
+pub fn install(clock: SharedClock) {
+    *GLOBAL.write().unwrap() = clock;
+}
+
+/// The clock in effect on this thread
+pub fn current() -> SharedClock {
+    THREAD_CLOCK
+        .with(|c| c.borrow().clone())
+        .unwrap_or_else(|| GLOBAL.read().unwrap().clone())
+}
+
+pub fn now() -> DateTime<Utc> {
+    current().now()
+}
+
+pub fn system_now() -> SystemTime {
+    current().system_time()
+}
+
+/// Use `clock` on this thread until the guard is dropped
+pub fn set_thread_clock(clock: SharedClock) -> ThreadClockGuard {
+    let previous = THREAD_CLOCK.with(|c| c.borrow_mut().replace(clock));
+    ThreadClockGuard { previous }
+}
+
+/// Restores the previous thread clock on drop
+#[must_use = "the clock override ends when the guard is dropped"]
+pub struct ThreadClockGuard {
+    previous: Option<SharedClock>,
+}
+
+impl Drop for ThreadClockGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        THREAD_CLOCK.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+fn to_chrono(d: Duration) -> ChronoDuration {
+    ChronoDuration::from_std(d).unwrap_or(ChronoDuration::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t0() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(t0());
+        let shared = clock.clone();
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(shared.now(), t0() + ChronoDuration::seconds(90));
+        assert_eq!(shared.system_time(), SystemTime::from(t0() + ChronoDuration::seconds(90)));
+    }
+
+    #[test]
+    fn test_thread_override() {
+        let clock = ManualClock::new(t0());
+        {
+            let _guard = set_thread_clock(Arc::new(clock.clone()));
+            assert_eq!(now(), t0());
+            clock.advance(Duration::from_secs(5));
+            assert_eq!(now(), t0() + ChronoDuration::seconds(5));
+        }
+        assert!(now() > t0() + ChronoDuration::days(365));
+    }
+
+    #[test]
+    fn test_time_warp_bounded() {
+        let base = ManualClock::new(t0());
+        let last_seen = t0() - ChronoDuration::hours(10);
+        let warp = TimeWarp::new(Arc::new(base.clone()), last_seen, Duration::from_secs(4 * 3600));
+
+        assert_eq!(warp.now(), t0() - ChronoDuration::hours(4));
+        assert_eq!(warp.skipped(), Duration::from_secs(6 * 3600));
+
+        let mut steps = 0;
+        while warp.advance(Duration::from_secs(3600)) {
+            steps += 1;
+        }
+        assert_eq!(steps, 3);
+        assert!(warp.is_caught_up());
+        assert_eq!(warp.now(), t0());
+
+        // Never runs ahead of the base clock
+        warp.advance(Duration::from_secs(3600));
+        assert_eq!(warp.now(), t0());
+        base.advance(Duration::from_secs(60));
+        assert_eq!(warp.remaining(), Duration::from_secs(60));
+    }
+}
@@ -4,6 +4,7 @@
 //! It includes common types, traits, and utilities that are shared across all other crates.
 
 pub mod actors;
+pub mod clock;
 pub mod distributed;
 pub mod error;
 pub mod events;
@@ -12,6 +13,7 @@ pub mod hot_reload;
 pub mod listener;
 pub mod process;
 pub mod process_cancel;  // NEW: Idempotent cancellation
+pub mod registry;
 pub mod supervisor;
 pub mod supervision;
 pub mod types;
@@ -20,6 +22,7 @@ pub mod utils;
 
 // Re-export commonly used types
 pub use error::{HelixError, HelixResult};
+pub use clock::{Clock, SharedClock};
 pub use distributed::*;
 pub use events::*;
 pub use genserver::*;
@@ -0,0 +1,604 @@
+//! Bounded in-memory registries
+//!
+//! The subsystem registries (software, network, process) keep entities in
+//! memory for fast lookups. [`BoundedMap`] stops them from growing without
+//! limit: entries idle for longer than the TTL are dropped, and once the
+//! estimated size passes the soft cap the least recently used entries are
+//! evicted until the map is back under a low watermark. Entries for which the
+//! pin predicate holds (e.g. running processes) are never evicted.
+//!
+//! Eviction only runs once a [`RegistryLoader`] is installed, so that an
+//! evicted entry is not lost: a miss reloads it from the database. A map
+//! without a loader holds the only copy of its entries and keeps them all,
+//! warning once when it passes the soft cap. The loader also preloads hot
+//! entities at startup through [`BoundedMap::warm`].
+//!
+//! Every map registers itself so [`all_stats`] can report sizes, hit rates and
+//! evictions to the metrics exporter.
+
+use crate::clock::{self, SharedClock};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+use std::time::Duration;
+
+/// Eviction brings the map down to this share of the soft cap, so that a busy
+/// map does not run an eviction pass on every insert
+const LOW_WATERMARK: f64 = 0.9;
+
+/// Rough in-memory size of a registry entry, in bytes
+pub trait Footprint {
+    fn footprint(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+}
+
+/// Source of entries that are not (or no longer) in memory
+#[async_trait]
+pub trait RegistryLoader<K, V>: Send + Sync {
+    async fn load(&self, key: &K) -> anyhow::Result<Option<V>>;
+
+    /// Entries worth having in memory before the first request, hottest first
+    async fn hot(&self, _limit: usize) -> anyhow::Result<Vec<(K, V)>> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegistryConfig {
+    /// Estimated bytes above which least recently used entries are evicted
+    pub soft_cap_bytes: usize,
+    /// Entries not read or written for this long are dropped; `None` keeps
+    /// them until the soft cap is hit
+    pub ttl: Option<Duration>,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            soft_cap_bytes: 64 * 1024 * 1024,
+            ttl: Some(Duration::from_secs(30 * 60)),
+        }
+    }
+}
+
+impl RegistryConfig {
+    /// Defaults overridden by `<PREFIX>_SOFT_CAP_MB` and `<PREFIX>_TTL_SECS`;
+    /// a TTL of 0 disables expiry
+    pub fn from_env(prefix: &str) -> Self {
+        let mut config = Self::default();
+        if let Some(mb) = env_number(&format!("{}_SOFT_CAP_MB", prefix)) {
+            config.soft_cap_bytes = mb as usize * 1024 * 1024;
+        }
+        if let Some(secs) = env_number(&format!("{}_TTL_SECS", prefix)) {
+            config.ttl = (secs > 0).then(|| Duration::from_secs(secs));
+        }
+        config
+    }
+}
+
+fn env_number(name: &str) -> Option<u64> {
+    std::env::var(name).ok()?.parse().ok()
+}
+
+/// Point-in-time counters of one map
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RegistryStats {
+    pub name: String,
+    pub entries: usize,
+    pub bytes: usize,
+    pub soft_cap_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub loads: u64,
+    pub load_failures: u64,
+    pub expired: u64,
+    pub evicted: u64,
+}
+
+impl RegistryStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+trait StatsSource: Send + Sync {
+    fn stats(&self) -> RegistryStats;
+}
+
+static MAPS: Lazy<Mutex<Vec<Weak<dyn StatsSource>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Stats of every live map, for the metrics exporter
+pub fn all_stats() -> Vec<RegistryStats> {
+    let mut maps = MAPS.lock().unwrap();
+    maps.retain(|map| map.strong_count() > 0);
+    maps.iter().filter_map(|map| map.upgrade()).map(|map| map.stats()).collect()
+}
+
+struct Slot<V> {
+    value: Arc<V>,
+    bytes: usize,
+    /// Milliseconds since the epoch of the last read or write
+    touched: AtomicI64,
+}
+
+type EvictionListener<K, V> = Box<dyn Fn(&K, &Arc<V>) + Send + Sync>;
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    loads: AtomicU64,
+    load_failures: AtomicU64,
+    expired: AtomicU64,
+    evicted: AtomicU64,
+}
+
+/// Sharded map with TTL and LRU eviction; see the module docs
+pub struct BoundedMap<K, V> {
+    name: String,
+    config: RegistryConfig,
+    entries: DashMap<K, Slot<V>>,
+    bytes: AtomicUsize,
+    counters: Counters,
+    clock: SharedClock,
+    pinned: OnceLock<fn(&V) -> bool>,
+    loader: RwLock<Option<Arc<dyn RegistryLoader<K, V>>>>,
+    on_evict: RwLock<Option<EvictionListener<K, V>>>,
+    /// Serialises eviction passes; lookups and inserts do not wait for it
+    evicting: Mutex<()>,
+    /// Whether the over-cap warning of a map without a loader was logged
+    warned_unbounded: AtomicBool,
+}
+
+impl<K, V> BoundedMap<K, V>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Footprint + Send + Sync + 'static,
+{
+    pub fn new(name: impl Into<String>, config: RegistryConfig) -> Arc<Self> {
+        Self::with_clock(name, config, clock::current())
+    }
+
+    pub fn with_clock(name: impl Into<String>, config: RegistryConfig, clock: SharedClock) -> Arc<Self> {
+        let map = Arc::new(Self {
+            name: name.into(),
+            config,
+            entries: DashMap::new(),
+            bytes: AtomicUsize::new(0),
+            counters: Counters::default(),
+            clock,
+            pinned: OnceLock::new(),
+            loader: RwLock::new(None),
+            on_evict: RwLock::new(None),
+            evicting: Mutex::new(()),
+            warned_unbounded: AtomicBool::new(false),
+        });
+
+        let source: Arc<dyn StatsSource> = map.clone();
+        MAPS.lock().unwrap().push(Arc::downgrade(&source));
+        map
+    }
+
+    /// Entries matching `pinned` are never expired or evicted. Only the first
+    /// call has an effect.
+    pub fn pin_when(&self, pinned: fn(&V) -> bool) {
+        let _ = self.pinned.set(pinned);
+    }
+
+    /// Install the loader; until then nothing is expired or evicted
+    pub fn set_loader(&self, loader: Arc<dyn RegistryLoader<K, V>>) {
+        *self.loader.write().unwrap() = Some(loader);
+    }
+
+    /// Whether evicted entries can be loaded back
+    pub fn is_reloadable(&self) -> bool {
+        self.loader.read().unwrap().is_some()
+    }
+
+    /// Called for every expired or evicted entry, not for explicit removals
+    pub fn set_eviction_listener(&self, listener: impl Fn(&K, &Arc<V>) + Send + Sync + 'static) {
+        *self.on_evict.write().unwrap() = Some(Box::new(listener));
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn now_ms(&self) -> i64 {
+        self.clock.now().timestamp_millis()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Arc<V> {
+        self.insert_arc(key, Arc::new(value))
+    }
+
+    pub fn insert_arc(&self, key: K, value: Arc<V>) -> Arc<V> {
+        let bytes = value.footprint() + std::mem::size_of::<K>();
+        let slot = Slot {
+            value: value.clone(),
+            bytes,
+            touched: AtomicI64::new(self.now_ms()),
+        };
+
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(previous) = self.entries.insert(key, slot) {
+            self.bytes.fetch_sub(previous.bytes, Ordering::Relaxed);
+        }
+
+        if self.bytes.load(Ordering::Relaxed) > self.config.soft_cap_bytes {
+            self.evict_to_watermark();
+        }
+        value
+    }
+
+    /// In-memory lookup only
+    pub fn get(&self, key: &K) -> Option<Arc<V>> {
+        match self.entries.get(key) {
+            Some(slot) => {
+                slot.touched.store(self.now_ms(), Ordering::Relaxed);
+                self.counters.hits.fetch_add(1, Ordering::Relaxed);
+                Some(slot.value.clone())
+            }
+            None => {
+                self.counters.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Lookup that falls back to the loader on a miss
+    pub async fn get_or_load(&self, key: &K) -> anyhow::Result<Option<Arc<V>>> {
+        if let Some(value) = self.get(key) {
+            return Ok(Some(value));
+        }
+
+        let Some(loader) = self.loader.read().unwrap().clone() else {
+            return Ok(None);
+        };
+
+        match loader.load(key).await {
+            Ok(Some(value)) => {
+                self.counters.loads.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(self.insert(key.clone(), value)))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.counters.load_failures.fetch_add(1, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn remove(&self, key: &K) -> Option<Arc<V>> {
+        self.entries.remove(key).map(|(_, slot)| {
+            self.bytes.fetch_sub(slot.bytes, Ordering::Relaxed);
+            slot.value
+        })
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Every value in memory; does not count as access for LRU
+    pub fn values(&self) -> Vec<Arc<V>> {
+        self.entries.iter().map(|entry| entry.value.clone()).collect()
+    }
+
+    pub fn filter(&self, predicate: impl Fn(&V) -> bool) -> Vec<Arc<V>> {
+        self.entries
+            .iter()
+            .filter(|entry| predicate(&entry.value))
+            .map(|entry| entry.value.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn clear(&self) {
+        self.entries.clear();
+        self.bytes.store(0, Ordering::Relaxed);
+    }
+
+    /// Preload up to `limit` hot entries from the loader; returns how many
+    /// were loaded
+    pub async fn warm(&self, limit: usize) -> anyhow::Result<usize> {
+        let Some(loader) = self.loader.read().unwrap().clone() else {
+            return Ok(0);
+        };
+
+        let hot = loader.hot(limit).await?;
+        let count = hot.len();
+        for (key, value) in hot.into_iter().take(limit) {
+            self.insert(key, value);
+        }
+        tracing::info!("Warmed {} registry with {} entries", self.name, count.min(limit));
+        Ok(count.min(limit))
+    }
+
+    fn is_pinned(&self, value: &V) -> bool {
+        self.pinned.get().map_or(false, |pinned| pinned(value))
+    }
+
+    /// Drop entries idle for longer than the TTL, then enforce the soft cap.
+    /// Does nothing without a loader.
+    pub fn sweep(&self) {
+        if !self.is_reloadable() {
+            return;
+        }
+
+        if let Some(ttl) = self.config.ttl {
+            let cutoff = self.now_ms() - ttl.as_millis() as i64;
+            let expired: Vec<K> = self
+                .entries
+                .iter()
+                .filter(|entry| entry.touched.load(Ordering::Relaxed) < cutoff && !self.is_pinned(&entry.value))
+                .map(|entry| entry.key().clone())
+                .collect();
+
+            for key in expired {
+                if self.evict(&key, |slot| slot.touched.load(Ordering::Relaxed) < cutoff) {
+                    self.counters.expired.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        if self.bytes.load(Ordering::Relaxed) > self.config.soft_cap_bytes {
+            self.evict_to_watermark();
+        }
+    }
+
+    /// Evict least recently used entries until the map is under the low
+    /// watermark or only pinned entries are left
+    fn evict_to_watermark(&self) {
+        if !self.is_reloadable() {
+            if !self.warned_unbounded.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    "{} registry is over its soft cap ({} of {} bytes) but has no loader, so nothing is evicted",
+                    self.name,
+                    self.bytes.load(Ordering::Relaxed),
+                    self.config.soft_cap_bytes
+                );
+            }
+            return;
+        }
+
+        let Ok(_guard) = self.evicting.try_lock() else {
+            return;
+        };
+
+        let target = (self.config.soft_cap_bytes as f64 * LOW_WATERMARK) as usize;
+        let mut candidates: Vec<(i64, K)> = self
+            .entries
+            .iter()
+            .filter(|entry| !self.is_pinned(&entry.value))
+            .map(|entry| (entry.touched.load(Ordering::Relaxed), entry.key().clone()))
+            .collect();
+        candidates.sort_unstable_by_key(|(touched, _)| *touched);
+
+        let mut evicted = 0usize;
+        for (touched, key) in candidates {
+            if self.bytes.load(Ordering::Relaxed) <= target {
+                break;
+            }
+            // Skip entries read since the candidates were collected
+            if self.evict(&key, |slot| slot.touched.load(Ordering::Relaxed) <= touched) {
+                evicted += 1;
+            }
+        }
+
+        if evicted > 0 {
+            self.counters.evicted.fetch_add(evicted as u64, Ordering::Relaxed);
+            tracing::debug!("Evicted {} entries from {} registry", evicted, self.name);
+        }
+        if self.bytes.load(Ordering::Relaxed) > self.config.soft_cap_bytes {
+            tracing::warn!(
+                "{} registry is over its soft cap ({} of {} bytes) with only pinned entries left",
+                self.name,
+                self.bytes.load(Ordering::Relaxed),
+                self.config.soft_cap_bytes
+            );
+        }
+    }
+
+    fn evict(&self, key: &K, still_stale: impl FnOnce(&Slot<V>) -> bool) -> bool {
+        let Some((key, slot)) = self.entries.remove_if(key, |_, slot| still_stale(slot)) else {
+            return false;
+        };
+        self.bytes.fetch_sub(slot.bytes, Ordering::Relaxed);
+        if let Some(listener) = self.on_evict.read().unwrap().as_ref() {
+            listener(&key, &slot.value);
+        }
+        true
+    }
+
+    /// Run [`sweep`](Self::sweep) every `interval` for as long as the map lives
+    pub fn spawn_sweeper(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let map = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match map.upgrade() {
+                    Some(map) => map.sweep(),
+                    None => return,
+                }
+            }
+        })
+    }
+}
+
+impl<K, V> StatsSource for BoundedMap<K, V>
+where
+    K: Eq + Hash + Send + Sync,
+    V: Send + Sync,
+{
+    fn stats(&self) -> RegistryStats {
+        RegistryStats {
+            name: self.name.clone(),
+            entries: self.entries.len(),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            soft_cap_bytes: self.config.soft_cap_bytes,
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            loads: self.counters.loads.load(Ordering::Relaxed),
+            load_failures: self.counters.load_failures.load(Ordering::Relaxed),
+            expired: self.counters.expired.load(Ordering::Relaxed),
+            evicted: self.counters.evicted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<K, V> std::fmt::Debug for BoundedMap<K, V>
+where
+    K: Eq + Hash + Send + Sync,
+    V: Send + Sync,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoundedMap").field("stats", &self.stats()).finish()
+    }
+}
+
+impl<K, V> BoundedMap<K, V>
+where
+    K: Eq + Hash + Send + Sync,
+    V: Send + Sync,
+{
+    pub fn stats(&self) -> RegistryStats {
+        StatsSource::stats(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    
+    #[derive(Debug, PartialEq)]
+    struct Item {
+        size: usize,
+        active: bool,
+    }
+
+    impl Footprint for Item {
+        fn footprint(&self) -> usize {
+            self.size
+        }
+    }
+
+    fn map(cap: usize, clock: &ManualClock) -> Arc<BoundedMap<u32, Item>> {
+        let config = RegistryConfig { soft_cap_bytes: cap, ttl: Some(Duration::from_secs(60)) };
+        let map = BoundedMap::with_clock("test", config, Arc::new(clock.clone()));
+        map.set_loader(Arc::new(Loader));
+        map
+    }
+
+    fn item(size: usize) -> Item {
+        Item { size, active: false }
+    }
+
+    #[test]
+    fn test_lru_eviction_keeps_recently_used() {
+        let clock = ManualClock::starting_now();
+        let map = map(1000 + 3 * std::mem::size_of::<u32>(), &clock);
+
+        for key in 0..3 {
+            map.insert(key, item(300));
+            clock.advance(Duration::from_secs(1));
+        }
+        map.get(&0);
+        clock.advance(Duration::from_secs(1));
+        map.insert(3, item(300));
+
+        assert!(map.contains_key(&0));
+        assert!(!map.contains_key(&1));
+        assert!(map.contains_key(&3));
+        assert!(map.bytes() <= 1000 + 3 * std::mem::size_of::<u32>());
+        assert!(map.stats().evicted >= 1);
+    }
+
+    #[test]
+    fn test_ttl_and_pinning() {
+        let clock = ManualClock::starting_now();
+        let map = map(usize::MAX, &clock);
+        map.pin_when(|item| item.active);
+        let evicted = Arc::new(AtomicUsize::new(0));
+        let counter = evicted.clone();
+        map.set_eviction_listener(move |_, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        map.insert(1, item(10));
+        map.insert(2, Item { size: 10, active: true });
+        clock.advance(Duration::from_secs(61));
+        map.sweep();
+
+        assert!(!map.contains_key(&1));
+        assert!(map.contains_key(&2));
+        assert_eq!(map.stats().expired, 1);
+        assert_eq!(evicted.load(Ordering::Relaxed), 1);
+    }
+
+    struct Loader;
+
+    #[async_trait]
+    impl RegistryLoader<u32, Item> for Loader {
+        async fn load(&self, key: &u32) -> anyhow::Result<Option<Item>> {
+            Ok((*key < 10).then(|| item(*key as usize)))
+        }
+
+        async fn hot(&self, limit: usize) -> anyhow::Result<Vec<(u32, Item)>> {
+            Ok((0..limit as u32).map(|key| (key, item(1))).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload_on_miss_and_warm() {
+        let clock = ManualClock::starting_now();
+        let map = map(usize::MAX, &clock);
+
+        assert_eq!(map.get_or_load(&4).await.unwrap().unwrap().size, 4);
+        assert!(map.get_or_load(&40).await.unwrap().is_none());
+        assert!(map.contains_key(&4));
+        assert_eq!(map.stats().loads, 1);
+
+        assert_eq!(map.warm(3).await.unwrap(), 3);
+        assert_eq!(map.len(), 4);
+    }
+
+    #[test]
+    fn test_no_eviction_without_loader() {
+        let clock = ManualClock::starting_now();
+        let config = RegistryConfig { soft_cap_bytes: 100, ttl: Some(Duration::from_secs(60)) };
+        let map = BoundedMap::with_clock("test", config, Arc::new(clock.clone()));
+
+        for key in 0..3 {
+            map.insert(key, item(300));
+        }
+        clock.advance(Duration::from_secs(61));
+        map.sweep();
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.stats().evicted + map.stats().expired, 0);
+    }
+}
@@ -0,0 +1,702 @@
+//! Factor expressions
+//!
+//! Balance designers define a factor as an expression over named inputs,
+//! such as `cpu_power^0.8 * skill_bonus / target_security`. Expressions are
+//! parsed and checked against the declared inputs when a [`FactorSet`] is
+//! loaded, so a typo fails at startup rather than mid-game. Constant parts
+//! are folded and the rest compiled to a small stack program; recent results
+//! are cached per factor, since the same inputs come up again and again.
+//!
+//! Syntax: numbers, input names, `+ - * / ^` (`^` binds tightest and groups
+//! to the right), unary `-`, parentheses and the functions in [`Func`].
+//!
+//! Definitions carry designer test cases: inputs and the value expected,
+//! checked with [`FactorSet::check`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Results kept per factor before the cache starts over
+const CACHE_CAPACITY: usize = 1024;
+/// Cases match within this much relative error unless they say otherwise
+pub const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ExprError {
+    #[error("{message} at column {position}")]
+    Syntax { position: usize, message: String },
+    #[error("unknown input `{0}`")]
+    UnknownInput(String),
+    #[error("unknown function `{0}`")]
+    UnknownFunction(String),
+    #[error("`{function}` takes {expected} arguments, got {found}")]
+    Arity { function: &'static str, expected: usize, found: usize },
+    #[error("no such factor")]
+    UnknownFactor,
+    #[error("no value for input `{0}`")]
+    MissingInput(String),
+    #[error("result is not a finite number")]
+    NotFinite,
+}
+
+/// An expression error in a named factor
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("factor `{factor}`: {error}")]
+pub struct FactorError {
+    pub factor: String,
+    pub error: ExprError,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Func {
+    Min,
+    Max,
+    Clamp,
+    Abs,
+    Sqrt,
+    Ln,
+    Log10,
+    Exp,
+    Floor,
+    Ceil,
+}
+
+impl Func {
+    fn parse(name: &str) -> Option<Func> {
+        Some(match name {
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "clamp" => Func::Clamp,
+            "abs" => Func::Abs,
+            "sqrt" => Func::Sqrt,
+            "ln" => Func::Ln,
+            "log10" => Func::Log10,
+            "exp" => Func::Exp,
+            "floor" => Func::Floor,
+            "ceil" => Func::Ceil,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Func::Min => "min",
+            Func::Max => "max",
+            Func::Clamp => "clamp",
+            Func::Abs => "abs",
+            Func::Sqrt => "sqrt",
+            Func::Ln => "ln",
+            Func::Log10 => "log10",
+            Func::Exp => "exp",
+            Func::Floor => "floor",
+            Func::Ceil => "ceil",
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Func::Min | Func::Max => 2,
+            Func::Clamp => 3,
+            _ => 1,
+        }
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        match self {
+            Func::Min => args[0].min(args[1]),
+            Func::Max => args[0].max(args[1]),
+            Func::Clamp => args[0].max(args[1]).min(args[2]),
+            Func::Abs => args[0].abs(),
+            Func::Sqrt => args[0].sqrt(),
+            Func::Ln => args[0].ln(),
+            Func::Log10 => args[0].log10(),
+            Func::Exp => args[0].exp(),
+            Func::Floor => args[0].floor(),
+            Func::Ceil => args[0].ceil(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+impl BinOp {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            BinOp::Add => a + b,
+            BinOp::Sub => a - b,
+            BinOp::Mul => a * b,
+            BinOp::Div => a / b,
+            BinOp::Pow => a.powf(b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(f64),
+    Input(usize),
+    Neg(Box<Expr>),
+    Bin(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+impl Expr {
+    /// Evaluate whatever does not depend on an input
+    fn fold(self) -> Expr {
+        match self {
+            Expr::Neg(inner) => match inner.fold() {
+                Expr::Num(n) => Expr::Num(-n),
+                inner => Expr::Neg(Box::new(inner)),
+            },
+            Expr::Bin(op, a, b) => match (a.fold(), b.fold()) {
+                (Expr::Num(a), Expr::Num(b)) => Expr::Num(op.apply(a, b)),
+                (a, b) => Expr::Bin(op, Box::new(a), Box::new(b)),
+            },
+            Expr::Call(func, args) => {
+                let args: Vec<Expr> = args.into_iter().map(Expr::fold).collect();
+                if args.iter().all(|arg| matches!(arg, Expr::Num(_))) {
+                    let values: Vec<f64> = args.iter().map(|arg| if let Expr::Num(n) = arg { *n } else { 0.0 }).collect();
+                    Expr::Num(func.apply(&values))
+                } else {
+                    Expr::Call(func, args)
+                }
+            }
+            expr => expr,
+        }
+    }
+
+    fn compile(&self, ops: &mut Vec<Op>) {
+        match self {
+            Expr::Num(n) => ops.push(Op::Num(*n)),
+            Expr::Input(slot) => ops.push(Op::Input(*slot)),
+            Expr::Neg(inner) => {
+                inner.compile(ops);
+                ops.push(Op::Neg);
+            }
+            Expr::Bin(op, a, b) => {
+                a.compile(ops);
+                b.compile(ops);
+                ops.push(Op::Bin(*op));
+            }
+            Expr::Call(func, args) => {
+                args.iter().for_each(|arg| arg.compile(ops));
+                ops.push(Op::Call(*func));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Num(f64),
+    Input(usize),
+    Neg,
+    Bin(BinOp),
+    Call(Func),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+    Open,
+    Close,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "number {}", n),
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Op(op) => write!(f, "`{}`", op),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+        }
+    }
+}
+
+fn syntax(position: usize, message: impl Into<String>) -> ExprError {
+    ExprError::Syntax { position, message: message.into() }
+}
+
+/// Tokens with their 1-based columns
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let exponent = i;
+                i += 1;
+                if i < chars.len() && (chars[i] == '+' || chars[i] == '-') {
+                    i += 1;
+                }
+                if !chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+                    i = exponent;
+                } else {
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text.parse().map_err(|_| syntax(column, format!("bad number `{}`", text)))?;
+            tokens.push((column, Token::Num(number)));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((column, Token::Ident(chars[start..i].iter().collect())));
+        } else {
+            let token = match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Op(c),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                ',' => Token::Comma,
+                _ => return Err(syntax(column, format!("unexpected `{}`", c))),
+            };
+            tokens.push((column, token));
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+    inputs: &'a [String],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |(column, _)| *column)
+    }
+
+    fn unexpected(&self) -> ExprError {
+        match self.peek() {
+            Some(token) => syntax(self.column(), format!("unexpected {}", token)),
+            None => syntax(self.end, "unexpected end of expression"),
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), ExprError> {
+        if self.peek() == Some(&token) {
+            self.next += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, ExprError> {
+        let mut expr = self.product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            let op = if *op == '+' { BinOp::Add } else { BinOp::Sub };
+            self.next += 1;
+            expr = Expr::Bin(op, Box::new(expr), Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, ExprError> {
+        let mut expr = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek() {
+            let op = if *op == '*' { BinOp::Mul } else { BinOp::Div };
+            self.next += 1;
+            expr = Expr::Bin(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    /// `-a^b` is `-(a^b)`
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        if self.peek() == Some(&Token::Op('-')) {
+            self.next += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expr, ExprError> {
+        let base = self.atom()?;
+        if self.peek() == Some(&Token::Op('^')) {
+            self.next += 1;
+            return Ok(Expr::Bin(BinOp::Pow, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr, ExprError> {
+        match self.peek().cloned() {
+            Some(Token::Num(n)) => {
+                self.next += 1;
+                Ok(Expr::Num(n))
+            }
+            Some(Token::Open) => {
+                self.next += 1;
+                let expr = self.sum()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                self.next += 1;
+                if self.peek() == Some(&Token::Open) {
+                    return self.call(&name);
+                }
+                match self.inputs.iter().position(|input| *input == name) {
+                    Some(slot) => Ok(Expr::Input(slot)),
+                    None => Err(ExprError::UnknownInput(name)),
+                }
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr, ExprError> {
+        let func = Func::parse(name).ok_or_else(|| ExprError::UnknownFunction(name.to_string()))?;
+        self.expect(Token::Open)?;
+        let mut args = Vec::new();
+        if self.peek() != Some(&Token::Close) {
+            args.push(self.sum()?);
+            while self.peek() == Some(&Token::Comma) {
+                self.next += 1;
+                args.push(self.sum()?);
+            }
+        }
+        self.expect(Token::Close)?;
+        if args.len() != func.arity() {
+            return Err(ExprError::Arity { function: func.name(), expected: func.arity(), found: args.len() });
+        }
+        Ok(Expr::Call(func, args))
+    }
+}
+
+/// A compiled expression over a fixed list of inputs
+#[derive(Debug)]
+pub struct FactorExpr {
+    source: String,
+    inputs: Vec<String>,
+    /// Inputs the expression reads, as slots into `inputs`
+    used: Vec<usize>,
+    ops: Vec<Op>,
+    cache: Mutex<HashMap<Vec<u64>, f64>>,
+}
+
+impl Clone for FactorExpr {
+    fn clone(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            inputs: self.inputs.clone(),
+            used: self.used.clone(),
+            ops: self.ops.clone(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl FactorExpr {
+    /// Parse `source`, which may only read the named `inputs`
+    pub fn parse(source: &str, inputs: &[String]) -> Result<Self, ExprError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, next: 0, end: source.chars().count() + 1, inputs };
+        let expr = parser.sum()?;
+        if parser.peek().is_some() {
+            return Err(parser.unexpected());
+        }
+
+        let mut ops = Vec::new();
+        expr.fold().compile(&mut ops);
+        let mut used: Vec<usize> = ops.iter().filter_map(|op| if let Op::Input(slot) = op { Some(*slot) } else { None }).collect();
+        used.sort_unstable();
+        used.dedup();
+        Ok(Self { source: source.to_string(), inputs: inputs.to_vec(), used, ops, cache: Mutex::new(HashMap::new()) })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The inputs the expression actually reads
+    pub fn inputs(&self) -> impl Iterator<Item = &str> {
+        self.used.iter().map(|slot| self.inputs[*slot].as_str())
+    }
+
+    /// Evaluate with input values looked up by name
+    pub fn eval(&self, values: &HashMap<String, f64>) -> Result<f64, ExprError> {
+        let mut slots = vec![0.0; self.inputs.len()];
+        for slot in &self.used {
+            let name = &self.inputs[*slot];
+            slots[*slot] = *values.get(name).ok_or_else(|| ExprError::MissingInput(name.clone()))?;
+        }
+        self.eval_slots(&slots)
+    }
+
+    /// Evaluate with values in the order the inputs were declared
+    pub fn eval_slots(&self, slots: &[f64]) -> Result<f64, ExprError> {
+        if let Some(missing) = self.used.iter().find(|slot| **slot >= slots.len()) {
+            return Err(ExprError::MissingInput(self.inputs[*missing].clone()));
+        }
+        let key: Vec<u64> = self.used.iter().map(|slot| slots[*slot].to_bits()).collect();
+        if let Some(value) = self.cache.lock().unwrap().get(&key) {
+            return Ok(*value);
+        }
+
+        let value = self.run(slots);
+        if !value.is_finite() {
+            return Err(ExprError::NotFinite);
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(key, value);
+        Ok(value)
+    }
+
+    fn run(&self, slots: &[f64]) -> f64 {
+        let mut stack: Vec<f64> = Vec::with_capacity(8);
+        for op in &self.ops {
+            match *op {
+                Op::Num(n) => stack.push(n),
+                Op::Input(slot) => stack.push(slots[slot]),
+                Op::Neg => {
+                    let top = stack.pop().unwrap_or_default();
+                    stack.push(-top);
+                }
+                Op::Bin(op) => {
+                    let b = stack.pop().unwrap_or_default();
+                    let a = stack.pop().unwrap_or_default();
+                    stack.push(op.apply(a, b));
+                }
+                Op::Call(func) => {
+                    let args = stack.split_off(stack.len() - func.arity());
+                    stack.push(func.apply(&args));
+                }
+            }
+        }
+        stack.pop().unwrap_or_default()
+    }
+}
+
+/// A factor as a designer writes it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FactorDef {
+    pub expr: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Results are clamped into `min..=max` when given
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+/// One row of a designer's table of expected values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FactorCase {
+    pub factor: String,
+    pub inputs: HashMap<String, f64>,
+    pub expect: f64,
+    /// Relative error allowed, [`DEFAULT_TOLERANCE`] when unset
+    #[serde(default)]
+    pub tolerance: Option<f64>,
+}
+
+/// Inputs, factors and cases, as kept in a balance file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FactorFile {
+    pub inputs: Vec<String>,
+    pub factors: BTreeMap<String, FactorDef>,
+    #[serde(default)]
+    pub cases: Vec<FactorCase>,
+}
+
+/// A case whose factor did not come out as expected
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseFailure {
+    pub index: usize,
+    pub factor: String,
+    pub expected: f64,
+    pub got: Result<f64, String>,
+}
+
+impl fmt::Display for CaseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.got {
+            Ok(got) => write!(f, "case {} ({}): expected {}, got {}", self.index, self.factor, self.expected, got),
+            Err(e) => write!(f, "case {} ({}): expected {}, failed: {}", self.index, self.factor, self.expected, e),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Compiled {
+    expr: FactorExpr,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+/// Factors compiled against one list of inputs
+#[derive(Debug, Clone, Default)]
+pub struct FactorSet {
+    inputs: Vec<String>,
+    factors: HashMap<String, Compiled>,
+}
+
+impl FactorSet {
+    /// Compile every factor; the first one that does not parse fails the load
+    pub fn load(file: &FactorFile) -> Result<Self, FactorError> {
+        let mut factors = HashMap::new();
+        for (name, def) in &file.factors {
+            let expr = FactorExpr::parse(&def.expr, &file.inputs)
+                .map_err(|error| FactorError { factor: name.clone(), error })?;
+            factors.insert(name.clone(), Compiled { expr, min: def.min, max: def.max });
+        }
+        Ok(Self { inputs: file.inputs.clone(), factors })
+    }
+
+    /// Load a balance file in JSON
+    pub fn from_json(json: &str) -> Result<(Self, Vec<FactorCase>), String> {
+        let file: FactorFile = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let set = Self::load(&file).map_err(|e| e.to_string())?;
+        Ok((set, file.cases))
+    }
+
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    pub fn contains(&self, factor: &str) -> bool {
+        self.factors.contains_key(factor)
+    }
+
+    /// `factor` for the given inputs, clamped to its bounds
+    pub fn eval(&self, factor: &str, values: &HashMap<String, f64>) -> Result<f64, FactorError> {
+        let compiled = self.factors.get(factor).ok_or_else(|| FactorError {
+            factor: factor.to_string(),
+            error: ExprError::UnknownFactor,
+        })?;
+        let value = compiled.expr.eval(values).map_err(|error| FactorError { factor: factor.to_string(), error })?;
+        Ok(clamp(value, compiled.min, compiled.max))
+    }
+
+    /// Every case that fails, in order
+    pub fn check(&self, cases: &[FactorCase]) -> Vec<CaseFailure> {
+        cases
+            .iter()
+            .enumerate()
+            .filter_map(|(index, case)| {
+                let got = self.eval(&case.factor, &case.inputs).map_err(|e| e.to_string());
+                let tolerance = case.tolerance.unwrap_or(DEFAULT_TOLERANCE);
+                let close = |got: f64| (got - case.expect).abs() <= tolerance * case.expect.abs().max(1.0);
+                if got.as_ref().is_ok_and(|got| close(*got)) {
+                    return None;
+                }
+                Some(CaseFailure { index, factor: case.factor.clone(), expected: case.expect, got })
+            })
+            .collect()
+    }
+}
+
+fn clamp(value: f64, min: Option<f64>, max: Option<f64>) -> f64 {
+    let value = min.map_or(value, |min| value.max(min));
+    max.map_or(value, |max| value.min(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_precedence_and_folding() {
+        let names = inputs(&["x"]);
+        let eval = |source: &str, x: f64| FactorExpr::parse(source, &names).unwrap().eval_slots(&[x]).unwrap();
+
+        assert_eq!(eval("1 + 2 * 3", 0.0), 7.0);
+        assert_eq!(eval("2 ^ 3 ^ 2", 0.0), 512.0);
+        assert_eq!(eval("-2 ^ 2", 0.0), -4.0);
+        assert_eq!(eval("2 ^ -1", 0.0), 0.5);
+        assert_eq!(eval("clamp(x * 10, 0, 5)", 0.7), 5.0);
+        assert_eq!(eval("1.5e2 / x", 3.0), 50.0);
+
+        // Constant subexpressions fold into numbers
+        let folded = FactorExpr::parse("x * (2 + 3) * sqrt(16)", &names).unwrap();
+        assert_eq!(folded.ops.len(), 5);
+        assert_eq!(folded.eval_slots(&[2.0]), Ok(40.0));
+    }
+
+    #[test]
+    fn test_validation() {
+        let names = inputs(&["cpu_power", "skill_bonus"]);
+        assert_eq!(FactorExpr::parse("cpu_power * luck", &names).unwrap_err(), ExprError::UnknownInput("luck".into()));
+        assert_eq!(FactorExpr::parse("pow(cpu_power)", &names).unwrap_err(), ExprError::UnknownFunction("pow".into()));
+        assert!(matches!(FactorExpr::parse("min(cpu_power)", &names), Err(ExprError::Arity { expected: 2, found: 1, .. })));
+        assert_eq!(
+            FactorExpr::parse("cpu_power * ", &names).unwrap_err(),
+            ExprError::Syntax { position: 13, message: "unexpected end of expression".into() }
+        );
+        assert!(matches!(FactorExpr::parse("(cpu_power", &names), Err(ExprError::Syntax { .. })));
+        assert!(matches!(FactorExpr::parse("cpu_power skill_bonus", &names), Err(ExprError::Syntax { position: 11, .. })));
+
+        let expr = FactorExpr::parse("cpu_power / skill_bonus", &names).unwrap();
+        assert_eq!(expr.eval_slots(&[1.0, 0.0]), Err(ExprError::NotFinite));
+        assert_eq!(expr.eval(&HashMap::new()), Err(ExprError::MissingInput("cpu_power".into())));
+    }
+
+    #[test]
+    fn test_set_cases() {
+        let (set, cases) = FactorSet::from_json(
+            r#"{
+                "inputs": ["cpu_power", "skill_bonus", "target_security"],
+                "factors": {
+                    "crack_speed": { "expr": "cpu_power^0.8 * skill_bonus / target_security", "max": 10 }
+                },
+                "cases": [
+                    { "factor": "crack_speed", "inputs": { "cpu_power": 1, "skill_bonus": 2, "target_security": 4 }, "expect": 0.5 },
+                    { "factor": "crack_speed", "inputs": { "cpu_power": 1e6, "skill_bonus": 1, "target_security": 1 }, "expect": 10 },
+                    { "factor": "crack_speed", "inputs": { "cpu_power": 1, "skill_bonus": 1, "target_security": 1 }, "expect": 2 }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let failures = set.check(&cases);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].index, 2);
+        assert_eq!(failures[0].got, Ok(1.0));
+        assert!(set.check(&cases[..2]).is_empty());
+    }
+}
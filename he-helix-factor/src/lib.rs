@@ -1,4 +1,9 @@
 //! Helix Factor System - Game balance and calculation factors
+//!
+//! [`Factor`] multiplies a base value by its modifiers. Factors that need more
+//! than a product are written as expressions in [`expr`].
+
+pub mod expr;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -44,4 +49,19 @@ pub mod factors {
     pub fn network_factor() -> Factor {
         Factor::new("network", 1.0)
     }
+
+    /// Chance per minute that the target patches its firewall mid-process
+    pub fn firewall_update_factor() -> Factor {
+        Factor::new("firewall_update", 0.010)
+    }
+
+    /// Chance per minute that a power spike kills a process
+    pub fn power_spike_factor() -> Factor {
+        Factor::new("power_spike", 0.002)
+    }
+
+    /// Chance per minute of stumbling on an exploit that speeds a process up
+    pub fn lucky_exploit_factor() -> Factor {
+        Factor::new("lucky_exploit", 0.006)
+    }
 }
\ No newline at end of file
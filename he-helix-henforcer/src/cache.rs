@@ -0,0 +1,135 @@
+//! Request-scoped relay cache
+//!
+//! A relay only carries objects up one henforcer chain; guards in separate
+//! chains of the same request would each fetch the same account or server
+//! again. [`cached`] keeps what a henforcer fetched in the current request's
+//! [`RelayCache`] (on its [`RequestContext`]) under `(object_type, id)`, so
+//! the next guard asking for it gets it without a query. A request that
+//! changes one of those objects calls [`invalidate`] so its later checks see
+//! the change. Outside a request nothing is cached.
+//!
+//! [`stats`] counts, per object type, the queries saved and made since
+//! startup.
+//!
+//! [`RelayCache`]: he_core::context::RelayCache
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Mutex;
+
+use he_core::context::RequestContext;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+/// How one object type fared in the relay cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheCounts {
+    /// Fetches answered from the cache, i.e. queries saved
+    pub hits: u64,
+    /// Fetches that ran their query
+    pub misses: u64,
+    /// Cached objects dropped because the request changed them
+    pub invalidations: u64,
+}
+
+impl CacheCounts {
+    pub fn hit_ratio(&self) -> f64 {
+        let reads = self.hits + self.misses;
+        if reads == 0 {
+            0.0
+        } else {
+            self.hits as f64 / reads as f64
+        }
+    }
+}
+
+static COUNTS: Lazy<Mutex<BTreeMap<&'static str, CacheCounts>>> = Lazy::new(Default::default);
+
+fn count(object_type: &'static str, bump: impl FnOnce(&mut CacheCounts)) {
+    let mut counts = COUNTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    bump(counts.entry(object_type).or_default());
+}
+
+/// The object of `object_type` with `id`, from the current request's cache
+/// or from `fetch`. Only successful fetches are cached; a `None` is cached
+/// too, so a missing object is not looked up twice either.
+pub async fn cached<T, F, Fut>(object_type: &'static str, id: impl Display, fetch: F) -> anyhow::Result<T>
+where
+    T: Clone + Send + Sync + 'static,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let Some(cache) = RequestContext::with_current(|ctx| ctx.map(|ctx| ctx.relay.clone())) else {
+        return fetch().await;
+    };
+    let id = id.to_string();
+
+    if let Some(object) = cache.get::<T>(object_type, &id) {
+        count(object_type, |c| c.hits += 1);
+        return Ok(object);
+    }
+    count(object_type, |c| c.misses += 1);
+    let object = fetch().await?;
+    cache.insert(object_type, &id, object.clone());
+    Ok(object)
+}
+
+/// Forget the current request's copy of an object it just changed
+pub fn invalidate(object_type: &'static str, id: impl Display) {
+    let removed = RequestContext::with_current(|ctx| ctx.is_some_and(|ctx| ctx.relay.remove(object_type, &id.to_string())));
+    if removed {
+        count(object_type, |c| c.invalidations += 1);
+    }
+}
+
+/// Counts per object type since startup or the last [`reset`]
+pub fn stats() -> BTreeMap<&'static str, CacheCounts> {
+    COUNTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+pub fn reset() {
+    COUNTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use he_core::RequestId;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_cached_within_a_request() {
+        let queries = AtomicUsize::new(0);
+        let fetch = || async {
+            queries.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(5_i32))
+        };
+
+        // Outside a request every call queries
+        cached("test_outside", 1, fetch).await.unwrap();
+        cached("test_outside", 1, fetch).await.unwrap();
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+        assert!(!stats().contains_key("test_outside"));
+
+        RequestContext::new(RequestId::new())
+            .scope(async {
+                assert_eq!(cached("test_object", 1, fetch).await.unwrap(), Some(5));
+                assert_eq!(cached("test_object", 1, fetch).await.unwrap(), Some(5));
+                cached("test_object", 2, fetch).await.unwrap();
+                invalidate("test_object", 1);
+                invalidate("test_object", 3);
+                cached("test_object", 1, fetch).await.unwrap();
+            })
+            .await;
+        assert_eq!(queries.load(Ordering::SeqCst), 5);
+        assert_eq!(stats()["test_object"], CacheCounts { hits: 1, misses: 3, invalidations: 1 });
+
+        // A new request starts empty
+        RequestContext::new(RequestId::new())
+            .scope(cached("test_object", 2, fetch))
+            .await
+            .unwrap();
+        assert_eq!(queries.load(Ordering::SeqCst), 6);
+    }
+}
@@ -22,7 +22,8 @@
 //! Failure: `HenforcerResult::Err(reason, relay)`
 //!
 //! The `relay` contains related data fetched during verification,
-//! passed upstream to prevent redundant queries.
+//! passed upstream to prevent redundant queries. Across chains, [`cache`]
+//! keeps fetched objects for the rest of the request.
 
 use std::collections::HashMap;
 use std::future::Future;
@@ -33,6 +34,8 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
 
+pub mod cache;
+
 /// Result type for henforcer operations
 #[derive(Debug, Clone, PartialEq)]
 pub enum HenforcerResult<T, E> {
@@ -15,6 +15,7 @@ pub mod queries;
 pub mod actions;
 pub mod genserver;
 pub mod logger;
+pub mod query_log;
 pub mod metrics;
 pub mod monitoring;
 pub mod health;
@@ -28,6 +29,7 @@ pub use queries::*;
 pub use actions::*;
 pub use genserver::*;
 pub use logger::*;
+pub use query_log::HelixQuerySink;
 pub use metrics::*;
 pub use monitoring::*;
 pub use health::*;
@@ -0,0 +1,69 @@
+//! SQL audit records in the Helix log
+//!
+//! [`HelixQuerySink`] plugs into `he_database::audit::QueryAudit` and writes
+//! each query it reports as a log entry: slow ones as warnings, the rest at
+//! debug, with duration and row counts as fields.
+
+use crate::logger::{HelixLogger, LogEntry, LogLevel};
+use he_database::audit::{QueryRecord, QuerySink};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Queries waiting to be logged before new ones are dropped
+const QUEUE_SIZE: usize = 10_000;
+
+/// Hands audited queries to a [`HelixLogger`]. The audit reports from inside
+/// sqlx, so entries are queued and logged by a background task.
+pub struct HelixQuerySink {
+    tx: mpsc::Sender<LogEntry>,
+}
+
+impl HelixQuerySink {
+    /// Start logging into `logger`; must be called inside a tokio runtime
+    pub fn start(logger: Arc<HelixLogger>) -> Self {
+        let (tx, mut rx) = mpsc::channel::<LogEntry>(QUEUE_SIZE);
+        tokio::spawn(async move {
+            while let Some(entry) = rx.recv().await {
+                if let Err(e) = logger.log(entry).await {
+                    tracing::warn!("Failed to log query: {}", e);
+                }
+            }
+        });
+        Self { tx }
+    }
+}
+
+impl QuerySink for HelixQuerySink {
+    fn record(&self, record: &QueryRecord) {
+        // A full queue means the logger is behind; the totals still count it
+        let _ = self.tx.try_send(entry(record));
+    }
+}
+
+fn entry(record: &QueryRecord) -> LogEntry {
+    let mut fields = HashMap::new();
+    fields.insert("duration_ms".to_string(), record.duration.as_millis().to_string());
+    fields.insert("rows_affected".to_string(), record.rows_affected.to_string());
+    fields.insert("rows_returned".to_string(), record.rows_returned.to_string());
+    if let Some(statement) = &record.statement {
+        fields.insert("statement".to_string(), statement.clone());
+    }
+
+    LogEntry {
+        timestamp: chrono::Utc::now(),
+        level: if record.slow { LogLevel::Warn } else { LogLevel::Debug },
+        message: if record.slow {
+            format!("Slow query: {}", record.summary)
+        } else {
+            format!("Query: {}", record.summary)
+        },
+        module: Some("sql".to_string()),
+        file: None,
+        line: None,
+        fields,
+        user_id: None,
+        session_id: None,
+        request_id: Some(record.request.clone()),
+    }
+}
@@ -1,7 +1,14 @@
 //! Notification actions
 
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use std::collections::HashMap;
 use uuid::Uuid;
-use crate::model::NotificationClass;
+use crate::collapse::{self, CollapseConfig, Decision};
+use crate::model::{BaseNotification, NotificationClass, NotificationSelection};
+
+type CollapsibleRow = (Uuid, i32, Json<HashMap<String, serde_json::Value>>, DateTime<Utc>, DateTime<Utc>);
 
 /// Mark notification as read
 pub async fn mark_notification_read(
@@ -12,4 +19,175 @@ pub async fn mark_notification_read(
     tracing::info!("Marking notification as read: {}", notification_id);
     // Implementation would update database
     Ok(())
-}
\ No newline at end of file
+}
+
+pub struct NotificationActions {
+    pool: PgPool,
+}
+
+impl NotificationActions {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Store a new notification, or fold it into the unread one sharing its
+    /// `group_key` from within the class's window. Returns the notification
+    /// as the account now sees it, or `None` if the class is over its cap.
+    pub async fn store(
+        &self,
+        notification: &BaseNotification,
+        target_id: Option<Uuid>,
+        group_key: &str,
+        config: &CollapseConfig,
+    ) -> crate::NotificationResult<Option<BaseNotification>> {
+        let policy = config.policy(notification.class);
+        let since = notification.creation_time - config.window(notification.class);
+
+        let mut tx = self.pool.begin().await?;
+        // Two of a burst arriving together must not both start a row
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind(format!("{}:{}", notification.account_id, group_key))
+            .execute(&mut *tx)
+            .await?;
+
+        let existing: Option<CollapsibleRow> = sqlx::query_as(
+            "SELECT notification_id, collapse_count, data, creation_time, first_time FROM notifications \
+             WHERE account_id = $1 AND group_key = $2 AND NOT is_read AND creation_time > $3 \
+             ORDER BY creation_time DESC LIMIT 1 FOR UPDATE"
+        )
+        .bind(notification.account_id)
+        .bind(group_key)
+        .bind(since)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let recent: i64 = match existing {
+            Some(_) => 0,
+            None => {
+                sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM notifications WHERE account_id = $1 AND class = $2 AND first_time > $3"
+                )
+                .bind(notification.account_id)
+                .bind(notification.class.as_str())
+                .bind(since)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+        };
+
+        let stored = match (collapse::decide(policy, existing.is_some(), recent), existing) {
+            (Decision::Collapse, Some((notification_id, count, data, creation_time, first_time))) => {
+                // The first occurrence only gets a row once there is a group to expand
+                if count == 1 {
+                    insert_occurrence(&mut tx, notification_id, &data.0, creation_time).await?;
+                }
+                insert_occurrence(&mut tx, notification_id, &notification.data, notification.creation_time).await?;
+                sqlx::query(
+                    "DELETE FROM notification_occurrences WHERE notification_id = $1 AND id NOT IN ( \
+                         SELECT id FROM notification_occurrences WHERE notification_id = $1 \
+                         ORDER BY occurred_at DESC, id DESC LIMIT $2)"
+                )
+                .bind(notification_id)
+                .bind(config.max_occurrences)
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query(
+                    "UPDATE notifications SET collapse_count = collapse_count + 1, data = $2, creation_time = $3 \
+                     WHERE notification_id = $1"
+                )
+                .bind(notification_id)
+                .bind(Json(&notification.data))
+                .bind(notification.creation_time)
+                .execute(&mut *tx)
+                .await?;
+
+                Some(BaseNotification { notification_id, count: count + 1, first_time, ..notification.clone() })
+            }
+            (Decision::Create, _) | (Decision::Collapse, None) => {
+                sqlx::query(
+                    "INSERT INTO notifications (notification_id, account_id, class, code, target_id, data, is_read, \
+                     creation_time, group_key, collapse_count, first_time) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+                )
+                .bind(notification.notification_id)
+                .bind(notification.account_id)
+                .bind(notification.class.as_str())
+                .bind(&notification.code)
+                .bind(target_id)
+                .bind(Json(&notification.data))
+                .bind(notification.is_read)
+                .bind(notification.creation_time)
+                .bind(group_key)
+                .bind(notification.count)
+                .bind(notification.first_time)
+                .execute(&mut *tx)
+                .await?;
+
+                Some(notification.clone())
+            }
+            (Decision::Drop, _) => None,
+        };
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    /// Returns how many notifications were newly marked read
+    pub async fn mark_read(&self, account_id: Uuid, selection: &NotificationSelection) -> crate::NotificationResult<u64> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("UPDATE notifications SET is_read = TRUE");
+        push_selection(&mut sql, account_id, selection);
+        sql.push(" AND NOT is_read");
+
+        Ok(sql.build().execute(&self.pool).await?.rows_affected())
+    }
+
+    pub async fn delete(&self, account_id: Uuid, selection: &NotificationSelection) -> crate::NotificationResult<u64> {
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new("DELETE FROM notifications");
+        push_selection(&mut sql, account_id, selection);
+
+        Ok(sql.build().execute(&self.pool).await?.rows_affected())
+    }
+
+    pub async fn set_muted(&self, account_id: Uuid, class: NotificationClass, muted: bool) -> crate::NotificationResult<()> {
+        sqlx::query(
+            "INSERT INTO notification_preferences (account_id, class, muted) VALUES ($1, $2, $3) \
+             ON CONFLICT (account_id, class) DO UPDATE SET muted = EXCLUDED.muted, updated_at = NOW()"
+        )
+        .bind(account_id)
+        .bind(class.as_str())
+        .bind(muted)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+async fn insert_occurrence(
+    tx: &mut Transaction<'_, Postgres>,
+    notification_id: Uuid,
+    data: &HashMap<String, serde_json::Value>,
+    occurred_at: DateTime<Utc>,
+) -> crate::NotificationResult<()> {
+    sqlx::query("INSERT INTO notification_occurrences (notification_id, data, occurred_at) VALUES ($1, $2, $3)")
+        .bind(notification_id)
+        .bind(Json(data))
+        .bind(occurred_at)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// `WHERE` clause for a bulk action, always scoped to the account
+fn push_selection(sql: &mut QueryBuilder<Postgres>, account_id: Uuid, selection: &NotificationSelection) {
+    sql.push(" WHERE account_id = ").push_bind(account_id);
+
+    if let Some(ids) = &selection.ids {
+        sql.push(" AND notification_id = ANY(").push_bind(ids.clone()).push(")");
+    }
+    if let Some(class) = selection.class {
+        sql.push(" AND class = ").push_bind(class.as_str());
+    }
+    if let Some(code) = &selection.code {
+        sql.push(" AND code = ").push_bind(code.clone());
+    }
+}
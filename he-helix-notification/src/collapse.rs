@@ -0,0 +1,145 @@
+//! Deduplication and rate collapsing
+//!
+//! A player hacked ten times in a minute should see "attacked 10 times", not
+//! ten rows. A new notification identical to an unread one from within the
+//! class's window is folded into it: the count goes up and the row takes the
+//! newest data. Identical means the same class, code, target and data, or
+//! the same group key when the caller wants looser grouping (every attack on
+//! a server, whoever the attacker).
+//!
+//! Each class also has a cap on new rows per window. Past it, notifications
+//! that would start a new row are dropped; ones that fold into an existing
+//! row are still counted.
+
+use chrono::Duration;
+use std::collections::BTreeMap;
+
+use crate::model::{CreateNotificationParams, NotificationClass};
+
+/// Collapse window and rate cap of one class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassPolicy {
+    /// An unread notification this recent absorbs identical ones
+    pub window_secs: i64,
+    /// New rows allowed per window; 0 is unlimited
+    pub max_per_window: i64,
+}
+
+/// Per-class policies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollapseConfig {
+    pub server: ClassPolicy,
+    pub chat: ClassPolicy,
+    pub entity: ClassPolicy,
+    /// Occurrences kept per collapsed notification for clients to expand;
+    /// the count keeps going past it
+    pub max_occurrences: i64,
+}
+
+impl Default for CollapseConfig {
+    fn default() -> Self {
+        Self {
+            // Attacks and logins come in bursts
+            server: ClassPolicy { window_secs: 600, max_per_window: 30 },
+            chat: ClassPolicy { window_secs: 60, max_per_window: 60 },
+            entity: ClassPolicy { window_secs: 3600, max_per_window: 20 },
+            max_occurrences: 50,
+        }
+    }
+}
+
+impl CollapseConfig {
+    pub fn policy(&self, class: NotificationClass) -> ClassPolicy {
+        match class {
+            NotificationClass::Server => self.server,
+            NotificationClass::Chat => self.chat,
+            NotificationClass::Entity => self.entity,
+        }
+    }
+
+    pub fn window(&self, class: NotificationClass) -> Duration {
+        Duration::seconds(self.policy(class).window_secs.max(1))
+    }
+}
+
+/// What happens to a new notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Fold into an existing unread notification
+    Collapse,
+    /// Store a new row
+    Create,
+    /// Over the class's cap
+    Drop,
+}
+
+/// Decide for a notification with `has_match` telling whether an identical
+/// unread one exists within the window, and `recent` how many rows of the
+/// class the account got within it
+pub fn decide(policy: ClassPolicy, has_match: bool, recent: i64) -> Decision {
+    if has_match {
+        return Decision::Collapse;
+    }
+    if policy.max_per_window > 0 && recent >= policy.max_per_window {
+        return Decision::Drop;
+    }
+    Decision::Create
+}
+
+/// The key identical notifications share: the caller's group key, or the
+/// class, code, target and data. Data keys are sorted so the same data
+/// always gives the same key.
+pub fn group_key(params: &CreateNotificationParams) -> String {
+    let target = params.target_id.map(|id| id.simple().to_string()).unwrap_or_default();
+    match &params.group_key {
+        Some(key) => format!("{}:{}:{}", params.class.as_str(), params.code, key),
+        None => {
+            let data: BTreeMap<_, _> = params.data.iter().collect();
+            let data = serde_json::to_string(&data).unwrap_or_default();
+            format!("{}:{}:{}:{}", params.class.as_str(), params.code, target, data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn params(data: &[(&str, serde_json::Value)], group_key: Option<&str>) -> CreateNotificationParams {
+        CreateNotificationParams {
+            account_id: Uuid::new_v4(),
+            class: NotificationClass::Server,
+            code: "login_attempt".to_string(),
+            data: data.iter().map(|(k, v)| (k.to_string(), v.clone())).collect::<HashMap<_, _>>(),
+            target_id: Some(Uuid::from_u64_pair(0, 7)),
+            group_key: group_key.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_identical_notifications_share_a_key() {
+        let a = params(&[("ip", "1.2.3.4".into()), ("port", 22.into())], None);
+        let b = params(&[("port", 22.into()), ("ip", "1.2.3.4".into())], None);
+        let c = params(&[("ip", "5.6.7.8".into()), ("port", 22.into())], None);
+        assert_eq!(group_key(&a), group_key(&b));
+        assert_ne!(group_key(&a), group_key(&c));
+
+        // An explicit key groups regardless of data
+        let a = params(&[("ip", "1.2.3.4".into())], Some("attacked"));
+        let c = params(&[("ip", "5.6.7.8".into())], Some("attacked"));
+        assert_eq!(group_key(&a), group_key(&c));
+    }
+
+    #[test]
+    fn test_cap_only_stops_new_rows() {
+        let policy = ClassPolicy { window_secs: 600, max_per_window: 3 };
+        assert_eq!(decide(policy, false, 2), Decision::Create);
+        assert_eq!(decide(policy, false, 3), Decision::Drop);
+        assert_eq!(decide(policy, true, 3), Decision::Collapse);
+
+        let unlimited = ClassPolicy { max_per_window: 0, ..policy };
+        assert_eq!(decide(unlimited, false, 1000), Decision::Create);
+    }
+}
@@ -0,0 +1,81 @@
+//! Unread counters cached in Redis
+//!
+//! One hash per account with a field per class. Counts are computed from the
+//! database on a miss and the hash is dropped whenever something changes them.
+//! Redis errors are logged and treated as misses.
+
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::model::NotificationClass;
+use crate::{NotificationError, NotificationResult};
+
+/// Marks a cached entry, so an account with no unread notifications is still a hit
+const PRESENT_FIELD: &str = "_";
+
+pub struct UnreadCounter {
+    redis: redis::aio::ConnectionManager,
+    ttl_seconds: i64,
+}
+
+impl UnreadCounter {
+    pub async fn connect(redis_url: &str, ttl_seconds: i64) -> NotificationResult<Self> {
+        let client = redis::Client::open(redis_url)
+            .map_err(|e| NotificationError::Database { error: e.to_string() })?;
+        let redis = redis::aio::ConnectionManager::new(client)
+            .await
+            .map_err(|e| NotificationError::Database { error: e.to_string() })?;
+        Ok(Self { redis, ttl_seconds })
+    }
+
+    fn key(account_id: Uuid) -> String {
+        format!("notification:unread:{}", account_id)
+    }
+
+    pub async fn get(&self, account_id: Uuid) -> Option<HashMap<NotificationClass, i64>> {
+        let mut conn = self.redis.clone();
+        let fields: HashMap<String, i64> = match conn.hgetall(Self::key(account_id)).await {
+            Ok(fields) => fields,
+            Err(e) => {
+                tracing::warn!("Failed to read unread counters for {}: {}", account_id, e);
+                return None;
+            }
+        };
+
+        if !fields.contains_key(PRESENT_FIELD) {
+            return None;
+        }
+        Some(
+            fields
+                .into_iter()
+                .filter_map(|(class, count)| Some((class.parse().ok()?, count)))
+                .collect(),
+        )
+    }
+
+    pub async fn set(&self, account_id: Uuid, counts: &HashMap<NotificationClass, i64>) {
+        let key = Self::key(account_id);
+        let mut fields: Vec<(&str, i64)> = counts.iter().map(|(class, n)| (class.as_str(), *n)).collect();
+        fields.push((PRESENT_FIELD, 1));
+
+        let mut conn = self.redis.clone();
+        let result: redis::RedisResult<()> = redis::pipe()
+            .atomic()
+            .del(&key)
+            .hset_multiple(&key, &fields)
+            .expire(&key, self.ttl_seconds)
+            .query_async(&mut conn)
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to cache unread counters for {}: {}", account_id, e);
+        }
+    }
+
+    pub async fn invalidate(&self, account_id: Uuid) {
+        let mut conn = self.redis.clone();
+        if let Err(e) = conn.del::<_, ()>(Self::key(account_id)).await {
+            tracing::warn!("Failed to invalidate unread counters for {}: {}", account_id, e);
+        }
+    }
+}
@@ -12,8 +12,12 @@
 //! - Notification reading status tracking
 //! - Custom notification data and rendering
 //! - Event-driven notification creation
+//! - Filtered, cursor-paginated listing with bulk actions and muted classes
+//! - Identical notifications collapsed into one with a count, and per-class rate caps
 
 pub mod action;
+pub mod collapse;
+pub mod counter;
 pub mod event;
 pub mod henforcer;
 pub mod model;
@@ -23,6 +27,7 @@ pub mod supervisor;
 pub mod websocket;
 
 pub use model::{Notification, NotificationClass, NotificationCode};
+pub use public::NotificationCenter;
 
 use thiserror::Error;
 
@@ -55,4 +60,9 @@ pub enum NotificationError {
 }
 
 /// Result type for notification operations
-pub type NotificationResult<T> = Result<T, NotificationError>;
\ No newline at end of file
+pub type NotificationResult<T> = Result<T, NotificationError>;
+impl From<sqlx::Error> for NotificationError {
+    fn from(e: sqlx::Error) -> Self {
+        NotificationError::Database { error: e.to_string() }
+    }
+}
@@ -23,7 +23,12 @@ pub struct BaseNotification {
     pub code: String,
     pub data: HashMap<String, serde_json::Value>,
     pub is_read: bool,
+    /// When it last happened; collapsing moves it back to the top
     pub creation_time: DateTime<Utc>,
+    /// How many identical notifications this one stands for
+    pub count: i32,
+    /// When the first of them happened
+    pub first_time: DateTime<Utc>,
 }
 
 impl BaseNotification {
@@ -34,6 +39,7 @@ impl BaseNotification {
         code: impl Into<String>,
         data: HashMap<String, serde_json::Value>,
     ) -> Self {
+        let now = Utc::now();
         Self {
             notification_id: Uuid::new_v4(),
             account_id,
@@ -41,10 +47,17 @@ impl BaseNotification {
             code: code.into(),
             data,
             is_read: false,
-            creation_time: Utc::now(),
+            creation_time: now,
+            count: 1,
+            first_time: now,
         }
     }
 
+    /// Whether identical notifications were folded into this one
+    pub fn is_collapsed(&self) -> bool {
+        self.count > 1
+    }
+
     /// Mark notification as read
     pub fn mark_read(&mut self) {
         self.is_read = true;
@@ -69,6 +82,17 @@ pub struct CreateNotificationParams {
     pub code: String,
     pub data: HashMap<String, serde_json::Value>,
     pub target_id: Option<Uuid>, // server_id, chat_id, entity_id depending on class
+    /// Collapse with any unread notification of the same class and code
+    /// sharing this key, instead of only identical ones
+    #[serde(default)]
+    pub group_key: Option<String>,
+}
+
+/// One of the notifications a collapsed notification stands for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationOccurrence {
+    pub data: HashMap<String, serde_json::Value>,
+    pub occurred_at: DateTime<Utc>,
 }
 
 /// Notification query parameters
@@ -81,6 +105,8 @@ pub struct NotificationQuery {
     pub target_id: Option<Uuid>,
     pub limit: Option<u32>,
     pub offset: Option<u32>,
+    /// Continue after this cursor instead of using `offset`
+    pub cursor: Option<NotificationCursor>,
 }
 
 impl NotificationQuery {
@@ -98,6 +124,12 @@ impl NotificationQuery {
         self
     }
 
+    /// Add code filter
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
     /// Add unread filter
     pub fn unread_only(mut self) -> Self {
         self.is_read = Some(false);
@@ -109,6 +141,68 @@ impl NotificationQuery {
         self.limit = Some(limit);
         self
     }
+
+    /// Continue a listing from the previous page
+    pub fn after(mut self, cursor: NotificationCursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+}
+
+/// Position in a newest-first listing: the last notification already seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NotificationCursor {
+    pub creation_time: DateTime<Utc>,
+    pub notification_id: Uuid,
+}
+
+impl NotificationCursor {
+    pub fn of(notification: &BaseNotification) -> Self {
+        Self {
+            creation_time: notification.creation_time,
+            notification_id: notification.notification_id,
+        }
+    }
+}
+
+/// Opaque to clients: `<microseconds>.<uuid>`
+impl From<NotificationCursor> for String {
+    fn from(cursor: NotificationCursor) -> String {
+        format!("{}.{}", cursor.creation_time.timestamp_micros(), cursor.notification_id.simple())
+    }
+}
+
+impl TryFrom<String> for NotificationCursor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (micros, id) = value.split_once('.').ok_or("malformed cursor")?;
+        let creation_time = micros
+            .parse::<i64>()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or("malformed cursor")?;
+        let notification_id = Uuid::parse_str(id).map_err(|_| "malformed cursor")?;
+        Ok(Self { creation_time, notification_id })
+    }
+}
+
+/// One page of a listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationPage {
+    pub notifications: Vec<BaseNotification>,
+    /// Pass back to get the next page; `None` on the last page
+    pub next_cursor: Option<NotificationCursor>,
+}
+
+/// Notifications a bulk action applies to. Every given criterion must match;
+/// an empty selection means all of the account's notifications.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotificationSelection {
+    pub ids: Option<Vec<Uuid>>,
+    pub class: Option<NotificationClass>,
+    pub code: Option<String>,
 }
 
 #[cfg(test)]
@@ -132,6 +226,8 @@ mod tests {
         assert_eq!(notification.code, "connection_lost");
         assert!(!notification.is_read());
         assert!(!notification.notification_id.is_nil());
+        assert_eq!(notification.count, 1);
+        assert!(!notification.is_collapsed());
     }
 
     #[test]
@@ -149,6 +245,19 @@ mod tests {
         assert!(notification.is_read());
     }
 
+    #[test]
+    fn test_cursor_round_trip() {
+        // Stored times have microsecond precision
+        let cursor = NotificationCursor {
+            creation_time: DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            notification_id: Uuid::new_v4(),
+        };
+
+        let encoded: String = cursor.into();
+        assert_eq!(NotificationCursor::try_from(encoded).unwrap(), cursor);
+        assert!(NotificationCursor::try_from("garbage".to_string()).is_err());
+    }
+
     #[test]
     fn test_notification_query_builder() {
         let account_id = Uuid::new_v4();
@@ -123,6 +123,18 @@ static CODE_REGISTRY: Lazy<CodeRegistry> = Lazy::new(|| {
     registry.register_code(NotificationCode::new(
         "achievement_unlocked", 203, super::NotificationClass::Entity
     ));
+    registry.register_code(NotificationCode::new(
+        "bounty_placed", 204, super::NotificationClass::Entity
+    ));
+    registry.register_code(NotificationCode::new(
+        "bounty_claimed", 205, super::NotificationClass::Entity
+    ));
+    registry.register_code(NotificationCode::new(
+        "bounty_claim_blocked", 206, super::NotificationClass::Entity
+    ));
+    registry.register_code(NotificationCode::new(
+        "bounty_expired", 207, super::NotificationClass::Entity
+    ));
     
     registry
 });
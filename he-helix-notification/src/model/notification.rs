@@ -30,6 +30,18 @@ impl NotificationClass {
     }
 }
 
+impl std::str::FromStr for NotificationClass {
+    type Err = crate::NotificationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NotificationClass::all()
+            .iter()
+            .copied()
+            .find(|class| class.as_str() == s)
+            .ok_or_else(|| crate::NotificationError::InvalidClass { class: s.to_string() })
+    }
+}
+
 /// Generic notification trait
 pub trait Notification {
     fn notification_id(&self) -> Uuid;
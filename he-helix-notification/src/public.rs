@@ -1,7 +1,16 @@
 //! Public notification API
 
+use sqlx::PgPool;
+use std::collections::HashMap;
 use uuid::Uuid;
-use crate::model::{NotificationQuery, BaseNotification};
+use crate::action::NotificationActions;
+use crate::collapse::{self, CollapseConfig};
+use crate::counter::UnreadCounter;
+use crate::model::{
+    code, BaseNotification, CreateNotificationParams, NotificationClass, NotificationOccurrence, NotificationPage,
+    NotificationQuery, NotificationSelection,
+};
+use crate::query::NotificationQueries;
 
 /// Get notifications for account
 pub async fn get_notifications(
@@ -9,4 +18,116 @@ pub async fn get_notifications(
 ) -> crate::NotificationResult<Vec<BaseNotification>> {
     // Mock implementation
     Ok(vec![])
-}
\ No newline at end of file
+}
+
+/// Notification center: storage, listing and per-account preferences.
+///
+/// Notifications of a class the account has muted are dropped on creation
+/// and left out of listings and unread counts. Identical notifications are
+/// collapsed and each class is rate capped, see [`crate::collapse`].
+pub struct NotificationCenter {
+    queries: NotificationQueries,
+    actions: NotificationActions,
+    counter: Option<UnreadCounter>,
+    collapse: CollapseConfig,
+}
+
+impl NotificationCenter {
+    /// `counter` is optional; without it unread counts always hit the database
+    pub fn new(pool: PgPool, counter: Option<UnreadCounter>) -> Self {
+        Self {
+            queries: NotificationQueries::new(pool.clone()),
+            actions: NotificationActions::new(pool),
+            counter,
+            collapse: CollapseConfig::default(),
+        }
+    }
+
+    /// Replace the default collapse windows and rate caps
+    pub fn with_collapse(mut self, collapse: CollapseConfig) -> Self {
+        self.collapse = collapse;
+        self
+    }
+
+    /// Store a notification, or fold it into an identical unread one.
+    /// Returns what the account now sees: the new notification, or the one
+    /// it collapsed into with its count raised. `None` if the account muted
+    /// the class or the class is over its rate cap.
+    pub async fn create(&self, params: CreateNotificationParams) -> crate::NotificationResult<Option<BaseNotification>> {
+        code::validate_code(params.class, &params.code)?;
+
+        if self.queries.is_muted(params.account_id, params.class).await? {
+            return Ok(None);
+        }
+
+        let group_key = collapse::group_key(&params);
+        let notification = BaseNotification::new(params.account_id, params.class, params.code, params.data);
+        let stored = self.actions.store(&notification, params.target_id, &group_key, &self.collapse).await?;
+        // Collapsing into an unread notification leaves the unread counts as they were
+        if stored.as_ref().is_some_and(|stored| !stored.is_collapsed()) {
+            self.invalidate(params.account_id).await;
+        }
+        Ok(stored)
+    }
+
+    /// What a collapsed notification stands for, newest first
+    pub async fn occurrences(
+        &self,
+        account_id: Uuid,
+        notification_id: Uuid,
+    ) -> crate::NotificationResult<Vec<NotificationOccurrence>> {
+        self.queries.occurrences(account_id, notification_id).await
+    }
+
+    pub async fn list(&self, query: &NotificationQuery) -> crate::NotificationResult<NotificationPage> {
+        self.queries.list(query).await
+    }
+
+    pub async fn unread_counts(&self, account_id: Uuid) -> crate::NotificationResult<HashMap<NotificationClass, i64>> {
+        if let Some(counts) = self.cached_counts(account_id).await {
+            return Ok(counts);
+        }
+
+        let counts = self.queries.unread_counts(account_id).await?;
+        if let Some(counter) = &self.counter {
+            counter.set(account_id, &counts).await;
+        }
+        Ok(counts)
+    }
+
+    pub async fn mark_read(&self, account_id: Uuid, selection: &NotificationSelection) -> crate::NotificationResult<u64> {
+        let changed = self.actions.mark_read(account_id, selection).await?;
+        if changed > 0 {
+            self.invalidate(account_id).await;
+        }
+        Ok(changed)
+    }
+
+    pub async fn delete(&self, account_id: Uuid, selection: &NotificationSelection) -> crate::NotificationResult<u64> {
+        let deleted = self.actions.delete(account_id, selection).await?;
+        if deleted > 0 {
+            self.invalidate(account_id).await;
+        }
+        Ok(deleted)
+    }
+
+    pub async fn muted_classes(&self, account_id: Uuid) -> crate::NotificationResult<Vec<NotificationClass>> {
+        self.queries.muted_classes(account_id).await
+    }
+
+    pub async fn set_muted(&self, account_id: Uuid, class: NotificationClass, muted: bool) -> crate::NotificationResult<()> {
+        self.actions.set_muted(account_id, class, muted).await?;
+        self.invalidate(account_id).await;
+        Ok(())
+    }
+
+    async fn cached_counts(&self, account_id: Uuid) -> Option<HashMap<NotificationClass, i64>> {
+        self.counter.as_ref()?.get(account_id).await
+    }
+
+    async fn invalidate(&self, account_id: Uuid) {
+        if let Some(counter) = &self.counter {
+            counter.invalidate(account_id).await;
+        }
+    }
+}
@@ -1,3 +1,179 @@
 //! Notification queries
 
-pub use crate::public::get_notifications;
\ No newline at end of file
+pub use crate::public::get_notifications;
+
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::model::{
+    BaseNotification, NotificationClass, NotificationCursor, NotificationOccurrence, NotificationPage, NotificationQuery,
+};
+use crate::{NotificationError, NotificationResult};
+
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+type NotificationRow = (
+    Uuid,
+    Uuid,
+    String,
+    String,
+    Json<HashMap<String, serde_json::Value>>,
+    bool,
+    DateTime<Utc>,
+    i32,
+    DateTime<Utc>,
+);
+
+pub struct NotificationQueries {
+    pool: PgPool,
+}
+
+impl NotificationQueries {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// One page of an account's notifications, newest first. Muted classes are
+    /// left out even if they were stored before being muted.
+    pub async fn list(&self, query: &NotificationQuery) -> NotificationResult<NotificationPage> {
+        let account_id = query.account_id.ok_or_else(|| NotificationError::Database {
+            error: "notification listing requires an account".to_string(),
+        })?;
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+        let mut sql: QueryBuilder<Postgres> = QueryBuilder::new(
+            "SELECT notification_id, account_id, class, code, data, is_read, creation_time, collapse_count, first_time \
+             FROM notifications WHERE account_id = "
+        );
+        sql.push_bind(account_id);
+        sql.push(" AND class NOT IN (SELECT class FROM notification_preferences WHERE muted AND account_id = ");
+        sql.push_bind(account_id);
+        sql.push(")");
+
+        if let Some(class) = query.class {
+            sql.push(" AND class = ").push_bind(class.as_str());
+        }
+        if let Some(code) = &query.code {
+            sql.push(" AND code = ").push_bind(code.clone());
+        }
+        if let Some(is_read) = query.is_read {
+            sql.push(" AND is_read = ").push_bind(is_read);
+        }
+        if let Some(target_id) = query.target_id {
+            sql.push(" AND target_id = ").push_bind(target_id);
+        }
+        if let Some(cursor) = query.cursor {
+            sql.push(" AND (creation_time, notification_id) < (")
+                .push_bind(cursor.creation_time)
+                .push(", ")
+                .push_bind(cursor.notification_id)
+                .push(")");
+        }
+
+        sql.push(" ORDER BY creation_time DESC, notification_id DESC LIMIT ")
+            .push_bind(limit as i64 + 1);
+        if query.cursor.is_none() {
+            if let Some(offset) = query.offset {
+                sql.push(" OFFSET ").push_bind(offset as i64);
+            }
+        }
+
+        let rows: Vec<NotificationRow> = sql.build_query_as().fetch_all(&self.pool).await?;
+
+        let mut notifications = rows
+            .into_iter()
+            .map(|(notification_id, account_id, class, code, data, is_read, creation_time, count, first_time)| {
+                Ok(BaseNotification {
+                    notification_id,
+                    account_id,
+                    class: class.parse()?,
+                    code,
+                    data: data.0,
+                    is_read,
+                    creation_time,
+                    count,
+                    first_time,
+                })
+            })
+            .collect::<NotificationResult<Vec<_>>>()?;
+
+        // The extra row only tells whether there is another page
+        let next_cursor = match notifications.len() > limit as usize {
+            true => {
+                notifications.truncate(limit as usize);
+                notifications.last().map(NotificationCursor::of)
+            }
+            false => None,
+        };
+
+        Ok(NotificationPage { notifications, next_cursor })
+    }
+
+    /// Unread notifications per class, muted classes excluded
+    pub async fn unread_counts(&self, account_id: Uuid) -> NotificationResult<HashMap<NotificationClass, i64>> {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT class, COUNT(*) FROM notifications \
+             WHERE account_id = $1 AND NOT is_read \
+             AND class NOT IN (SELECT class FROM notification_preferences WHERE muted AND account_id = $1) \
+             GROUP BY class"
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(class, count)| Ok((class.parse()?, count)))
+            .collect()
+    }
+
+    pub async fn muted_classes(&self, account_id: Uuid) -> NotificationResult<Vec<NotificationClass>> {
+        let rows: Vec<String> = sqlx::query_scalar(
+            "SELECT class FROM notification_preferences WHERE account_id = $1 AND muted ORDER BY class"
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(|class| class.parse()).collect()
+    }
+
+    pub async fn is_muted(&self, account_id: Uuid, class: NotificationClass) -> NotificationResult<bool> {
+        let muted: Option<bool> = sqlx::query_scalar(
+            "SELECT muted FROM notification_preferences WHERE account_id = $1 AND class = $2"
+        )
+        .bind(account_id)
+        .bind(class.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(muted.unwrap_or(false))
+    }
+
+    /// The occurrences folded into one of the account's notifications,
+    /// newest first. Empty for one that never collapsed.
+    pub async fn occurrences(
+        &self,
+        account_id: Uuid,
+        notification_id: Uuid,
+    ) -> NotificationResult<Vec<NotificationOccurrence>> {
+        let rows: Vec<(Json<HashMap<String, serde_json::Value>>, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT o.data, o.occurred_at FROM notification_occurrences o \
+             JOIN notifications n ON n.notification_id = o.notification_id \
+             WHERE o.notification_id = $1 AND n.account_id = $2 \
+             ORDER BY o.occurred_at DESC, o.id DESC"
+        )
+        .bind(notification_id)
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(data, occurred_at)| NotificationOccurrence { data: data.0, occurred_at })
+            .collect())
+    }
+}
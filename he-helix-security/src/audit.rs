@@ -40,6 +40,66 @@ pub enum SecurityEvent {
         email: String,
         ip: IpAddr,
     },
+    EmailChangeRequested {
+        user_id: i64,
+        new_email: String,
+        ip: IpAddr,
+    },
+    EmailChangeConfirmed {
+        user_id: i64,
+        ip: IpAddr,
+    },
+    EmailChanged {
+        user_id: i64,
+        old_email: String,
+        new_email: String,
+        ip: IpAddr,
+    },
+    EmailChangeRevoked {
+        user_id: i64,
+        rolled_back: bool,
+        ip: IpAddr,
+    },
+    UsernameChanged {
+        user_id: i64,
+        old_username: String,
+        new_username: String,
+        ip: IpAddr,
+    },
+    /// The player flagged a login in their history as not theirs
+    LoginDisowned {
+        user_id: i64,
+        login_id: i64,
+        ip_masked: String,
+        /// Blind index of the login's address, shared with the IP history
+        ip_index: Option<String>,
+        ip: IpAddr,
+    },
+
+    // IP policy events
+    IpPolicyDenied {
+        ip: IpAddr,
+        scope: String,
+        rule_id: Option<i64>,
+        reason: String,
+        /// Denials of the same IP and scope not audited since its last row
+        #[serde(default)]
+        held_back: u64,
+    },
+    IpPolicyRuleChanged {
+        admin_id: i64,
+        rule_id: i64,
+        change: String, // "added", "deleted"
+    },
+
+    // Moderation events
+    ContentReported {
+        user_id: i64,
+        server_id: i64,
+        field: String, // "hostname", "motd", "public_profile"
+        terms: Vec<String>,
+        excerpt: String,
+    },
 
     // Authorization events
     PermissionDenied {
@@ -61,6 +121,108 @@ pub enum SecurityEvent {
         action: String, // "cancel", "pause", "modify"
         suspicious: bool,
     },
+    ProcessQuotaExceeded {
+        user_id: i64,
+        process_type: String,
+        quota: String, // "concurrent", "hourly", "kill_switch"
+        limit: Option<i64>,
+        ip: IpAddr,
+    },
+    ProcessKillSwitch {
+        admin_id: i64,
+        process_type: String,
+        engaged: bool,
+        cancelled: usize,
+    },
+    CronJobControlled {
+        admin_id: i64,
+        job_name: String,
+        action: String, // "pause", "resume", "trigger", "acknowledge_alert"
+    },
+    LiveOpsCommand {
+        admin_id: i64,
+        command: String, // "step_up", "announce", "set_flag", "drain"
+        detail: String,
+        accepted: bool,
+    },
+    StatusIncidentChanged {
+        admin_id: i64,
+        incident_id: i64,
+        action: String, // "create", "update", "resolve"
+    },
+    ContractDisputeResolved {
+        admin_id: i64,
+        contract_id: i64,
+        resolution: String, // "pay_contractor", "refund_poster"
+        amount: i64,
+    },
+    ReferralReviewed {
+        admin_id: i64,
+        referral_id: i64,
+        approved: bool,
+    },
+    LedgerDiscrepancyResolved {
+        admin_id: i64,
+        discrepancy_id: i64,
+        bank_account_id: i64,
+        action: String,
+    },
+    ReportResolved {
+        admin_id: i64,
+        case_id: i64,
+        action: String,
+        moderation_action_id: Option<i64>,
+    },
+    PluginChanged {
+        admin_id: i64,
+        plugin: String,
+        action: String,
+    },
+    ArchiveRetentionChanged {
+        admin_id: i64,
+        table: String,
+        retention_days: i32,
+        batch_size: i32,
+        enabled: bool,
+    },
+    RequestLogSamplingChanged {
+        admin_id: i64,
+        sample_rate: f64,
+        max_body_bytes: i32,
+    },
+    VerboseRequestLogging {
+        admin_id: i64,
+        user_id: i64,
+        enabled: bool,
+        reason: Option<String>,
+        expires_at: Option<DateTime<Utc>>,
+    },
+    PanicLever {
+        admin_id: i64,
+        lever: String,
+        action: String, // "engage", "release"
+        reason: String,
+        accepted: bool,
+    },
+    BulkOperation {
+        admin_id: i64,
+        operation_id: i64,
+        title: String,
+        action: String, // "dry_run", "execute", "cancel", "rollback"
+        mutation: serde_json::Value,
+        matched: i32,
+    },
+    FormulaScriptsReloaded {
+        admin_id: i64,
+        overridden: Vec<String>,
+        error: Option<String>, // set when the reload was rejected
+    },
+    FormulaScriptChanged {
+        admin_id: i64,
+        formula: String,
+        removed: bool,
+        error: Option<String>, // set when the script was rejected
+    },
     ResourceOverflow {
         user_id: i64,
         resource_type: String,
@@ -238,8 +400,24 @@ impl AuditLogger {
             SecurityEvent::DDoSAttackDetected { .. } => {
                 ("security_attack".to_string(), "critical", serde_json::to_value(event).unwrap())
             }
+            SecurityEvent::ContentReported { .. } => {
+                ("content_report".to_string(), "warning", serde_json::to_value(event).unwrap())
+            }
+            SecurityEvent::PanicLever { .. } => {
+                ("panic_lever".to_string(), "critical", serde_json::to_value(event).unwrap())
+            }
+            SecurityEvent::VerboseRequestLogging { .. } => {
+                ("verbose_request_logging".to_string(), "warning", serde_json::to_value(event).unwrap())
+            }
+            SecurityEvent::BulkOperation { .. } => {
+                ("bulk_operation".to_string(), "warning", serde_json::to_value(event).unwrap())
+            }
             SecurityEvent::SuspiciousTransfer { .. } |
+            SecurityEvent::ProcessQuotaExceeded { .. } |
             SecurityEvent::ResourceOverflow { .. } |
+            SecurityEvent::EmailChangeRevoked { .. } |
+            SecurityEvent::LoginDisowned { .. } |
+            SecurityEvent::IpPolicyDenied { .. } |
             SecurityEvent::PermissionDenied { .. } => {
                 ("suspicious_activity".to_string(), "warning", serde_json::to_value(event).unwrap())
             }
@@ -258,8 +436,43 @@ impl AuditLogger {
             SecurityEvent::LoginAttempt { ip, .. } => {
                 (None, Some(*ip), None)
             }
+            SecurityEvent::EmailChangeRequested { user_id, ip, .. } |
+            SecurityEvent::EmailChangeConfirmed { user_id, ip } |
+            SecurityEvent::EmailChanged { user_id, ip, .. } |
+            SecurityEvent::EmailChangeRevoked { user_id, ip, .. } |
+            SecurityEvent::UsernameChanged { user_id, ip, .. } |
+            SecurityEvent::LoginDisowned { user_id, ip, .. } |
+            SecurityEvent::PasswordChange { user_id, ip } => {
+                (Some(*user_id), Some(*ip), None)
+            }
+            SecurityEvent::IpPolicyDenied { ip, .. } => {
+                (None, Some(*ip), None)
+            }
+            SecurityEvent::IpPolicyRuleChanged { admin_id, .. } |
+            SecurityEvent::ProcessKillSwitch { admin_id, .. } |
+            SecurityEvent::CronJobControlled { admin_id, .. } |
+            SecurityEvent::LiveOpsCommand { admin_id, .. } |
+            SecurityEvent::StatusIncidentChanged { admin_id, .. } |
+            SecurityEvent::ContractDisputeResolved { admin_id, .. } |
+            SecurityEvent::ReferralReviewed { admin_id, .. } |
+            SecurityEvent::LedgerDiscrepancyResolved { admin_id, .. } |
+            SecurityEvent::ReportResolved { admin_id, .. } |
+            SecurityEvent::PluginChanged { admin_id, .. } |
+            SecurityEvent::ArchiveRetentionChanged { admin_id, .. } |
+            SecurityEvent::RequestLogSamplingChanged { admin_id, .. } |
+            SecurityEvent::VerboseRequestLogging { admin_id, .. } |
+            SecurityEvent::PanicLever { admin_id, .. } |
+            SecurityEvent::BulkOperation { admin_id, .. } |
+            SecurityEvent::FormulaScriptsReloaded { admin_id, .. } |
+            SecurityEvent::FormulaScriptChanged { admin_id, .. } => {
+                (Some(*admin_id), None, None)
+            }
+            SecurityEvent::ProcessQuotaExceeded { user_id, ip, .. } => {
+                (Some(*user_id), Some(*ip), None)
+            }
             SecurityEvent::ProcessManipulation { user_id, .. } |
-            SecurityEvent::ResourceOverflow { user_id, .. } => {
+            SecurityEvent::ResourceOverflow { user_id, .. } |
+            SecurityEvent::ContentReported { user_id, .. } => {
                 (Some(*user_id), None, None)
             }
             _ => (None, None, None)
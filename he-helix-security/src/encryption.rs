@@ -1,16 +1,57 @@
 //! Field-level encryption for sensitive data at rest
+//!
+//! Keys are versioned. [`FieldEncryption::seal`] encrypts under the active
+//! version and writes the version into the stored value, so values sealed
+//! under any version the keyring holds can be opened, and rotating keys only
+//! means re-sealing rows still under an older one. Master keys are stretched
+//! with a fixed salt, so every node derives the same key from the same
+//! secret. Sealed values cannot be searched; [`FieldEncryption::blind_index`]
+//! gives a keyed hash to look them up by instead.
 
 use aes_gcm::{
     aead::{Aead, KeyInit, OsRng},
     Aes256Gcm, Nonce, Key
 };
 use chacha20poly1305::ChaCha20Poly1305;
-use argon2::{Argon2, PasswordHasher, PasswordVerifier, PasswordHash};
-use argon2::password_hash::{rand_core::RngCore, SaltString};
+use argon2::Argon2;
+use argon2::password_hash::rand_core::RngCore;
 use base64::{Engine as _, engine::general_purpose};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::RwLock;
 use anyhow::{Result, anyhow};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Prefix of values sealed by [`FieldEncryption::seal`]
+pub const SEALED_PREFIX: &str = "enc:";
+/// Salt master keys are stretched with. Fixed, so restarts and other nodes
+/// derive the same keys.
+const KEY_SALT: &[u8] = b"he-field-encryption-v1";
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn derive_key(master_key: &[u8]) -> Result<Vec<u8>> {
+    let mut derived_key = vec![0u8; 32];
+    Argon2::default()
+        .hash_password_into(master_key, KEY_SALT, &mut derived_key)
+        .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+    Ok(derived_key)
+}
+
+fn hmac_hex(key: &[u8], value: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(value);
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn key_id(version: u32) -> String {
+    format!("v{}", version)
+}
+
+fn version_of(key_id: &str) -> Option<u32> {
+    key_id.strip_prefix('v')?.parse().ok()
+}
 
 /// Encrypted field wrapper for database storage
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +68,8 @@ pub struct FieldEncryption {
     keys: HashMap<String, Vec<u8>>,
     active_key_id: String,
     algorithm: EncryptionAlgorithm,
+    /// Key of blind indexes; unlike encryption keys it never rotates
+    index_key: Vec<u8>,
 }
 
 #[derive(Debug, Clone)]
@@ -36,27 +79,99 @@ pub enum EncryptionAlgorithm {
 }
 
 impl FieldEncryption {
-    /// Create new encryption handler with master key
+    /// Create new encryption handler with master key, as key version 1
     pub fn new(master_key: &[u8], algorithm: EncryptionAlgorithm) -> Result<Self> {
-        // Derive encryption key from master key using Argon2
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-
-        let mut derived_key = vec![0u8; 32];
-        argon2.hash_password_into(master_key, salt.as_bytes(), &mut derived_key)
-            .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+        Self::from_versions(&[(1, master_key.to_vec())], 1, &[master_key, b":index"].concat(), algorithm)
+    }
 
+    /// A keyring holding every `(version, master key)`, encrypting under
+    /// `active`
+    pub fn from_versions(
+        master_keys: &[(u32, Vec<u8>)],
+        active: u32,
+        index_key: &[u8],
+        algorithm: EncryptionAlgorithm,
+    ) -> Result<Self> {
         let mut keys = HashMap::new();
-        let key_id = format!("key_v1_{}", chrono::Utc::now().timestamp());
-        keys.insert(key_id.clone(), derived_key);
+        for (version, master_key) in master_keys {
+            keys.insert(key_id(*version), derive_key(master_key)?);
+        }
+        if !keys.contains_key(&key_id(active)) {
+            return Err(anyhow!("Active key version {} not configured", active));
+        }
 
         Ok(Self {
             keys,
-            active_key_id: key_id,
+            active_key_id: key_id(active),
             algorithm,
+            index_key: derive_key(index_key)?,
         })
     }
 
+    /// Keyring from `FIELD_ENCRYPTION_KEYS` (`1:secret,2:secret`) and
+    /// `FIELD_ENCRYPTION_INDEX_KEY`, which is required with them. Starts on
+    /// the lowest version; the key registry says which is active. `None`
+    /// when no keys are configured: `ENCRYPTION_KEY` alone never turns field
+    /// encryption on, so an upgrade does not start sealing fields by itself.
+    pub fn from_env() -> Result<Option<Self>> {
+        let mut master_keys: Vec<(u32, Vec<u8>)> = match std::env::var("FIELD_ENCRYPTION_KEYS") {
+            Ok(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| {
+                    let (version, secret) = entry
+                        .split_once(':')
+                        .ok_or_else(|| anyhow!("FIELD_ENCRYPTION_KEYS entries look like <version>:<secret>"))?;
+                    let version: u32 = version.trim().parse().map_err(|_| anyhow!("Bad key version {:?}", version))?;
+                    Ok((version, secret.trim().as_bytes().to_vec()))
+                })
+                .collect::<Result<_>>()?,
+            Err(_) => return Ok(None),
+        };
+        master_keys.sort_by_key(|(version, _)| *version);
+        let Some(lowest) = master_keys.first().map(|(version, _)| *version) else {
+            return Ok(None);
+        };
+
+        // Blind indexes must survive retiring any master key, so the index
+        // key is never derived from one
+        let index_key = std::env::var("FIELD_ENCRYPTION_INDEX_KEY")
+            .map_err(|_| anyhow!("FIELD_ENCRYPTION_INDEX_KEY must be set with FIELD_ENCRYPTION_KEYS"))?;
+        if index_key.trim().is_empty() {
+            return Err(anyhow!("FIELD_ENCRYPTION_INDEX_KEY is empty"));
+        }
+        Self::from_versions(&master_keys, lowest, index_key.as_bytes(), EncryptionAlgorithm::Aes256Gcm).map(Some)
+    }
+
+    /// Version new values are sealed under
+    pub fn active_version(&self) -> u32 {
+        version_of(&self.active_key_id).unwrap_or(0)
+    }
+
+    /// Versions the keyring holds, lowest first
+    pub fn versions(&self) -> Vec<u32> {
+        let mut versions: Vec<u32> = self.keys.keys().filter_map(|id| version_of(id)).collect();
+        versions.sort_unstable();
+        versions
+    }
+
+    /// Seal new values under `version`, which the keyring must hold
+    pub fn set_active_version(&mut self, version: u32) -> Result<()> {
+        if !self.keys.contains_key(&key_id(version)) {
+            return Err(anyhow!("Key version {} not configured", version));
+        }
+        self.active_key_id = key_id(version);
+        Ok(())
+    }
+
+    /// Identifies a key version without revealing it, so nodes and operators
+    /// can check they hold the same key
+    pub fn fingerprint(&self, version: u32) -> Option<String> {
+        let key = self.keys.get(&key_id(version))?;
+        Some(hmac_hex(key, b"he-key-fingerprint")[..32].to_string())
+    }
+
     /// Encrypt a string value
     pub fn encrypt(&self, plaintext: &str) -> Result<EncryptedField> {
         let key = self.keys.get(&self.active_key_id)
@@ -123,17 +238,11 @@ impl FieldEncryption {
             .map_err(|e| anyhow!("Invalid UTF-8: {}", e))
     }
 
-    /// Rotate to a new encryption key
+    /// Rotate to a new encryption key, one version above the highest held
     pub fn rotate_key(&mut self, new_master_key: &[u8]) -> Result<String> {
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-
-        let mut derived_key = vec![0u8; 32];
-        argon2.hash_password_into(new_master_key, salt.as_bytes(), &mut derived_key)
-            .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
-
-        let new_key_id = format!("key_v1_{}", chrono::Utc::now().timestamp());
-        self.keys.insert(new_key_id.clone(), derived_key);
+        let version = self.versions().last().copied().unwrap_or(0) + 1;
+        let new_key_id = key_id(version);
+        self.keys.insert(new_key_id.clone(), derive_key(new_master_key)?);
         self.active_key_id = new_key_id.clone();
 
         Ok(new_key_id)
@@ -144,6 +253,92 @@ impl FieldEncryption {
         let plaintext = self.decrypt(field)?;
         self.encrypt(&plaintext)
     }
+
+    /// `plaintext` encrypted under the active key, as one string for a text
+    /// column: `enc:<key id>:<algorithm>:<nonce>:<ciphertext>`
+    pub fn seal(&self, plaintext: &str) -> Result<String> {
+        let field = self.encrypt(plaintext)?;
+        let algorithm = match field.algorithm.as_str() {
+            "ChaCha20-Poly1305" => "chacha",
+            _ => "aes",
+        };
+        Ok(format!("{}{}:{}:{}:{}", SEALED_PREFIX, field.key_id, algorithm, field.nonce, field.ciphertext))
+    }
+
+    /// The plaintext of a value from [`seal`](Self::seal). Anything else is
+    /// taken to be a plaintext value written before it was encrypted.
+    pub fn open(&self, stored: &str) -> Result<String> {
+        let Some(sealed) = stored.strip_prefix(SEALED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let mut parts = sealed.splitn(4, ':');
+        let (Some(key_id), Some(algorithm), Some(nonce), Some(ciphertext)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(anyhow!("Malformed sealed value"));
+        };
+        let algorithm = match algorithm {
+            "aes" => "AES256-GCM",
+            "chacha" => "ChaCha20-Poly1305",
+            other => return Err(anyhow!("Unknown algorithm: {}", other)),
+        };
+
+        self.decrypt(&EncryptedField {
+            algorithm: algorithm.to_string(),
+            ciphertext: ciphertext.to_string(),
+            nonce: nonce.to_string(),
+            key_id: key_id.to_string(),
+            created_at: chrono::Utc::now(),
+        })
+    }
+
+    /// Key version a stored value is sealed under, `None` for plaintext
+    pub fn sealed_version(stored: &str) -> Option<u32> {
+        let sealed = stored.strip_prefix(SEALED_PREFIX)?;
+        version_of(sealed.split(':').next()?)
+    }
+
+    /// Keyed hash of `value` to look sealed values up by. Callers normalize
+    /// first, e.g. lowercase emails.
+    pub fn blind_index(&self, value: &str) -> String {
+        hmac_hex(&self.index_key, value.as_bytes())
+    }
+}
+
+/// Keyring the process encrypts account fields with, see [`install_keyring`]
+static KEYRING: RwLock<Option<FieldEncryption>> = RwLock::new(None);
+
+/// Make `keyring` the one account fields are sealed with. Until one is
+/// installed they are stored in plaintext.
+pub fn install_keyring(keyring: FieldEncryption) {
+    *KEYRING.write().unwrap() = Some(keyring);
+}
+
+/// Run `f` with the installed keyring, `None` if there is none
+pub fn with_keyring<T>(f: impl FnOnce(&FieldEncryption) -> T) -> Option<T> {
+    KEYRING.read().unwrap().as_ref().map(f)
+}
+
+/// Seal new values under `version` from now on
+pub fn set_active_version(version: u32) -> Result<()> {
+    match KEYRING.write().unwrap().as_mut() {
+        Some(keyring) => keyring.set_active_version(version),
+        None => Err(anyhow!("No keyring installed")),
+    }
+}
+
+/// Blind index of an email address, `None` without a keyring
+pub fn email_index(email: &str) -> Option<String> {
+    with_keyring(|keyring| keyring.blind_index(&email.trim().to_lowercase()))
+}
+
+/// `stored` opened with the installed keyring; plaintext passes through
+pub fn open_stored(stored: &str) -> Result<String> {
+    match with_keyring(|keyring| keyring.open(stored)) {
+        Some(opened) => opened,
+        None if stored.starts_with(SEALED_PREFIX) => Err(anyhow!("Sealed value but no keyring installed")),
+        None => Ok(stored.to_string()),
+    }
 }
 
 /// Helper functions for common encryption operations
@@ -249,6 +444,42 @@ mod tests {
         assert_eq!(decrypted, plaintext);
     }
 
+    #[test]
+    fn test_sealed_values_across_rotation() {
+        let index_key = b"index";
+        let old = FieldEncryption::from_versions(
+            &[(1, b"first".to_vec())],
+            1,
+            index_key,
+            EncryptionAlgorithm::Aes256Gcm,
+        )
+        .unwrap();
+        let sealed = old.seal("user@example.com").unwrap();
+        assert!(sealed.starts_with("enc:v1:aes:"));
+        assert_eq!(FieldEncryption::sealed_version(&sealed), Some(1));
+        assert_eq!(FieldEncryption::sealed_version("user@example.com"), None);
+
+        // Another node with both keys, sealing under the new one
+        let mut both = FieldEncryption::from_versions(
+            &[(1, b"first".to_vec()), (2, b"second".to_vec())],
+            1,
+            index_key,
+            EncryptionAlgorithm::Aes256Gcm,
+        )
+        .unwrap();
+        assert_eq!(both.fingerprint(1), old.fingerprint(1));
+        assert_ne!(both.fingerprint(1), both.fingerprint(2));
+        both.set_active_version(2).unwrap();
+        assert!(both.set_active_version(3).is_err());
+
+        assert_eq!(both.open(&sealed).unwrap(), "user@example.com");
+        let resealed = both.seal(&both.open(&sealed).unwrap()).unwrap();
+        assert_eq!(FieldEncryption::sealed_version(&resealed), Some(2));
+        assert!(old.open(&resealed).is_err());
+        assert_eq!(both.open("plain").unwrap(), "plain");
+        assert_eq!(both.blind_index("user@example.com"), old.blind_index("user@example.com"));
+    }
+
     #[test]
     fn test_transparent_encryption() {
         let master_key = b"test_master_key_32_bytes_long!!!";
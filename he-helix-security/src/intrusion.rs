@@ -35,6 +35,8 @@ pub struct ThreatActor {
 
 pub struct IntrusionDetector {
     actors: Arc<DashMap<IpAddr, ThreatActor>>,
+    /// In-game behaviour that points at automation, by account
+    accounts: Arc<DashMap<i64, Vec<SuspiciousPattern>>>,
     patterns: Arc<DashMap<String, regex::Regex>>,
     thresholds: IntrusionThresholds,
 }
@@ -48,6 +50,8 @@ pub struct IntrusionThresholds {
     pub path_traversal_score: f64,         // Threat score for path traversal
     pub brute_force_score: f64,            // Threat score for brute force
     pub port_scan_score: f64,              // Threat score for port scanning
+    pub quota_violation_score: f64,        // Threat score per process quota violation
+    pub ws_abuse_score: f64,               // Threat score per refused WebSocket handshake
     pub block_threshold_score: f64,        // Auto-block at this score
     pub block_duration_minutes: u64,       // How long to block
 }
@@ -62,6 +66,8 @@ impl Default for IntrusionThresholds {
             path_traversal_score: 40.0,
             brute_force_score: 20.0,
             port_scan_score: 60.0,
+            quota_violation_score: 15.0,
+            ws_abuse_score: 25.0,
             block_threshold_score: 100.0,
             block_duration_minutes: 60,
         }
@@ -100,6 +106,7 @@ impl IntrusionDetector {
 
         Self {
             actors: Arc::new(DashMap::new()),
+            accounts: Arc::new(DashMap::new()),
             patterns,
             thresholds: IntrusionThresholds::default(),
         }
@@ -250,6 +257,113 @@ impl IntrusionDetector {
             });
     }
 
+    /// Record an account hitting a process quota. A client spamming processes
+    /// keeps tripping it, which adds up to a block like any other pattern.
+    pub fn report_quota_violation(&self, ip: IpAddr, user_id: i64, quota: &str) {
+        let now = Instant::now();
+        let detail = format!("User {}: {} quota", user_id, quota);
+
+        let mut actor = self.actors.entry(ip).or_insert_with(|| ThreatActor {
+            ip,
+            threat_score: 0.0,
+            patterns: Vec::new(),
+            blocked: false,
+            block_expiry: None,
+        });
+
+        actor.threat_score += self.thresholds.quota_violation_score;
+        if let Some(pattern) = actor.patterns.iter_mut()
+            .find(|p| p.pattern_type == "quota_violation") {
+            pattern.occurrences += 1;
+            pattern.last_seen = now;
+            pattern.details.push(detail);
+        } else {
+            actor.patterns.push(SuspiciousPattern {
+                pattern_type: "quota_violation".to_string(),
+                occurrences: 1,
+                first_seen: now,
+                last_seen: now,
+                details: vec![detail],
+            });
+        }
+
+        if actor.threat_score >= self.thresholds.block_threshold_score && !actor.blocked {
+            actor.blocked = true;
+            actor.block_expiry = Some(now + Duration::from_secs(self.thresholds.block_duration_minutes * 60));
+            warn!("Blocking IP {} after repeated process quota violations", ip);
+        }
+    }
+
+    /// Record a WebSocket handshake refused for abuse: a bad origin, too
+    /// many handshakes or too many open sockets. Enough of them block the IP,
+    /// and blocked IPs are refused before any other check.
+    pub fn report_ws_abuse(&self, ip: IpAddr, reason: &str) {
+        let now = Instant::now();
+
+        let mut actor = self.actors.entry(ip).or_insert_with(|| ThreatActor {
+            ip,
+            threat_score: 0.0,
+            patterns: Vec::new(),
+            blocked: false,
+            block_expiry: None,
+        });
+
+        actor.threat_score += self.thresholds.ws_abuse_score;
+        if let Some(pattern) = actor.patterns.iter_mut()
+            .find(|p| p.pattern_type == "ws_abuse") {
+            pattern.occurrences += 1;
+            pattern.last_seen = now;
+            pattern.details.push(reason.to_string());
+        } else {
+            actor.patterns.push(SuspiciousPattern {
+                pattern_type: "ws_abuse".to_string(),
+                occurrences: 1,
+                first_seen: now,
+                last_seen: now,
+                details: vec![reason.to_string()],
+            });
+        }
+
+        if actor.threat_score >= self.thresholds.block_threshold_score && !actor.blocked {
+            actor.blocked = true;
+            actor.block_expiry = Some(now + Duration::from_secs(self.thresholds.block_duration_minutes * 60));
+            warn!("Blocking IP {} after repeated WebSocket abuse", ip);
+        }
+    }
+
+    /// Record a player's process tripping a honeypot server. A player who
+    /// keeps falling for them is likely running a script against every
+    /// target it finds; `anomalous` trips are logged for review.
+    pub fn report_honeypot_trip(&self, user_id: i64, server_ip: &str, anomalous: bool) {
+        let now = Instant::now();
+        let pattern_type = if anomalous { "honeypot_anomaly" } else { "honeypot_trip" };
+        let detail = format!("Honeypot {}", server_ip);
+
+        let mut patterns = self.accounts.entry(user_id).or_default();
+        if let Some(pattern) = patterns.iter_mut().find(|p| p.pattern_type == pattern_type) {
+            pattern.occurrences += 1;
+            pattern.last_seen = now;
+            pattern.details.push(detail);
+        } else {
+            patterns.push(SuspiciousPattern {
+                pattern_type: pattern_type.to_string(),
+                occurrences: 1,
+                first_seen: now,
+                last_seen: now,
+                details: vec![detail],
+            });
+        }
+
+        if anomalous {
+            warn!("User {} keeps tripping honeypots, latest {}", user_id, server_ip);
+        }
+    }
+
+    /// In-game patterns recorded against an account
+    pub fn account_patterns(&self, user_id: i64) -> Vec<SuspiciousPattern> {
+        self.accounts.get(&user_id).map(|p| p.clone()).unwrap_or_default()
+    }
+
     pub fn check_rate_anomaly(&self, ip: IpAddr, requests_per_second: f64) -> bool {
         if requests_per_second > self.thresholds.rapid_request_threshold as f64 {
             self.actors.entry(ip)
@@ -328,5 +442,6 @@ impl IntrusionDetector {
             // Keep if seen recently
             actor.patterns.iter().any(|p| p.last_seen > cutoff)
         });
+        self.accounts.retain(|_, patterns| patterns.iter().any(|p| p.last_seen > cutoff));
     }
 }
\ No newline at end of file
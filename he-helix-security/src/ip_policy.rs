@@ -0,0 +1,549 @@
+//! IP allow/deny policy by CIDR, ASN and country
+//!
+//! Rules live in `ip_policy_rules` and are reloaded into memory on an interval,
+//! so admin changes apply without a restart. Evaluation order:
+//!
+//! 1. An active allow rule that matches wins, whatever else matches
+//! 2. Otherwise the first matching deny rule rejects the request
+//! 3. No match means allow
+//!
+//! Country and ASN come from MaxMind databases (`GEOIP_COUNTRY_DB`,
+//! `GEOIP_ASN_DB`). Lookups are cached per IP. Without the databases only
+//! CIDR rules can match.
+//!
+//! A denied client usually keeps retrying, so denials are audited once per IP
+//! and scope per [`DENIAL_AUDIT_WINDOW`]; each row carries how many denials
+//! were held back since the previous one.
+
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use ipnetwork::IpNetwork;
+use maxminddb::{geoip2, Reader};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::audit::{AuditLogger, SecurityEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    Allow,
+    Deny,
+}
+
+/// Where a rule applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyScope {
+    /// Every request
+    All,
+    /// Account registration only
+    Registration,
+}
+
+impl PolicyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyScope::All => "all",
+            PolicyScope::Registration => "registration",
+        }
+    }
+
+    /// Whether a rule with this scope applies to a check for `checked`
+    fn covers(&self, checked: PolicyScope) -> bool {
+        *self == PolicyScope::All || *self == checked
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum RuleTarget {
+    Cidr(IpNetwork),
+    Asn(u32),
+    /// ISO 3166-1 alpha-2 code, upper case
+    Country(String),
+}
+
+impl RuleTarget {
+    /// Parse the `target_kind` / `target_value` columns
+    pub fn parse(kind: &str, value: &str) -> anyhow::Result<Self> {
+        match kind {
+            "cidr" => Ok(RuleTarget::Cidr(value.parse()?)),
+            "asn" => Ok(RuleTarget::Asn(value.trim_start_matches("AS").parse()?)),
+            "country" if value.len() == 2 => Ok(RuleTarget::Country(value.to_ascii_uppercase())),
+            "country" => Err(anyhow::anyhow!("Invalid country code: {}", value)),
+            _ => Err(anyhow::anyhow!("Unknown rule target kind: {}", kind)),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RuleTarget::Cidr(_) => "cidr",
+            RuleTarget::Asn(_) => "asn",
+            RuleTarget::Country(_) => "country",
+        }
+    }
+
+    pub fn value(&self) -> String {
+        match self {
+            RuleTarget::Cidr(network) => network.to_string(),
+            RuleTarget::Asn(asn) => asn.to_string(),
+            RuleTarget::Country(code) => code.clone(),
+        }
+    }
+
+    fn matches(&self, ip: IpAddr, geo: &GeoInfo) -> bool {
+        match self {
+            RuleTarget::Cidr(network) => network.contains(ip),
+            RuleTarget::Asn(asn) => geo.asn == Some(*asn),
+            RuleTarget::Country(code) => geo.country.as_deref() == Some(code.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpRule {
+    pub id: i64,
+    pub target: RuleTarget,
+    pub action: PolicyAction,
+    pub scope: PolicyScope,
+    pub note: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl IpRule {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.map_or(true, |expires_at| expires_at > now)
+    }
+
+    fn applies(&self, ip: IpAddr, geo: &GeoInfo, scope: PolicyScope, now: DateTime<Utc>) -> bool {
+        self.is_active(now) && self.scope.covers(scope) && self.target.matches(ip, geo)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GeoInfo {
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IpDecision {
+    pub ip: IpAddr,
+    pub scope: PolicyScope,
+    pub allowed: bool,
+    /// Rule that decided the outcome, `None` for the default allow
+    pub rule_id: Option<i64>,
+    pub reason: String,
+    pub geo: GeoInfo,
+}
+
+/// Decide whether `ip` may proceed. Pure, so the rule order is easy to test.
+pub fn evaluate(
+    rules: &[IpRule],
+    ip: IpAddr,
+    geo: &GeoInfo,
+    scope: PolicyScope,
+    now: DateTime<Utc>,
+) -> IpDecision {
+    let matching = || rules.iter().filter(|rule| rule.applies(ip, geo, scope, now));
+
+    let decided_by = matching()
+        .find(|rule| rule.action == PolicyAction::Allow)
+        .or_else(|| matching().find(|rule| rule.action == PolicyAction::Deny));
+
+    let (allowed, rule_id, reason) = match decided_by {
+        Some(rule) => (
+            rule.action == PolicyAction::Allow,
+            Some(rule.id),
+            format!(
+                "{} by {} rule {} ({})",
+                if rule.action == PolicyAction::Allow { "allowed" } else { "denied" },
+                rule.target.kind(),
+                rule.id,
+                rule.target.value(),
+            ),
+        ),
+        None => (true, None, "no matching rule".to_string()),
+    };
+
+    IpDecision {
+        ip,
+        scope,
+        allowed,
+        rule_id,
+        reason,
+        geo: geo.clone(),
+    }
+}
+
+/// MaxMind lookups with a per-IP cache
+pub struct GeoIpResolver {
+    country_db: Option<Reader<Vec<u8>>>,
+    asn_db: Option<Reader<Vec<u8>>>,
+    cache: DashMap<IpAddr, (GeoInfo, Instant)>,
+    ttl: Duration,
+}
+
+impl GeoIpResolver {
+    const MAX_CACHE_ENTRIES: usize = 100_000;
+
+    pub fn new(country_db: Option<Reader<Vec<u8>>>, asn_db: Option<Reader<Vec<u8>>>, ttl: Duration) -> Self {
+        Self {
+            country_db,
+            asn_db,
+            cache: DashMap::new(),
+            ttl,
+        }
+    }
+
+    /// Resolver without databases; country and ASN rules never match
+    pub fn disabled() -> Self {
+        Self::new(None, None, Duration::from_secs(3600))
+    }
+
+    /// Open the databases named by `GEOIP_COUNTRY_DB` and `GEOIP_ASN_DB`
+    pub fn from_env() -> Self {
+        let open = |var: &str| {
+            let path = std::env::var(var).ok()?;
+            match Reader::open_readfile(&path) {
+                Ok(reader) => Some(reader),
+                Err(e) => {
+                    warn!("Could not open GeoIP database {} ({}): {}", path, var, e);
+                    None
+                }
+            }
+        };
+
+        Self::new(open("GEOIP_COUNTRY_DB"), open("GEOIP_ASN_DB"), Duration::from_secs(3600))
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        if let Some(entry) = self.cache.get(&ip) {
+            if entry.1.elapsed() < self.ttl {
+                return entry.0.clone();
+            }
+        }
+
+        let mut geo = GeoInfo::default();
+
+        if let Some(db) = &self.country_db {
+            if let Ok(country) = db.lookup::<geoip2::Country>(ip) {
+                geo.country = country
+                    .country
+                    .and_then(|c| c.iso_code)
+                    .map(|code| code.to_ascii_uppercase());
+            }
+        }
+
+        if let Some(db) = &self.asn_db {
+            if let Ok(asn) = db.lookup::<geoip2::Asn>(ip) {
+                geo.asn = asn.autonomous_system_number;
+                geo.as_org = asn.autonomous_system_organization.map(str::to_string);
+            }
+        }
+
+        if self.cache.len() >= Self::MAX_CACHE_ENTRIES {
+            let ttl = self.ttl;
+            self.cache.retain(|_, (_, cached_at)| cached_at.elapsed() < ttl);
+        }
+        self.cache.insert(ip, (geo.clone(), Instant::now()));
+
+        geo
+    }
+}
+
+/// How long one audit row covers the denials of an IP and scope
+pub const DENIAL_AUDIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Denials per IP and scope in the current audit window
+struct DenialThrottle {
+    windows: DashMap<(IpAddr, PolicyScope), (Instant, u64)>,
+    window: Duration,
+}
+
+impl DenialThrottle {
+    const MAX_ENTRIES: usize = 100_000;
+
+    fn new(window: Duration) -> Self {
+        Self { windows: DashMap::new(), window }
+    }
+
+    /// `Some(held)` if this denial opens a new window and is to be audited,
+    /// `held` being the denials counted but not audited in the previous one;
+    /// `None` if it is only counted
+    fn note(&self, ip: IpAddr, scope: PolicyScope, now: Instant) -> Option<u64> {
+        if self.windows.len() >= Self::MAX_ENTRIES {
+            let window = self.window;
+            self.windows.retain(|_, (started, _)| now.duration_since(*started) < window);
+        }
+
+        match self.windows.entry((ip, scope)) {
+            Entry::Occupied(mut entry) => {
+                let (started, held) = entry.get_mut();
+                if now.duration_since(*started) < self.window {
+                    *held += 1;
+                    return None;
+                }
+                let previous = *held;
+                *started = now;
+                *held = 0;
+                Some(previous)
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((now, 0));
+                Some(0)
+            }
+        }
+    }
+}
+
+/// In-memory rule set plus GeoIP resolution
+pub struct IpPolicy {
+    rules: RwLock<Arc<Vec<IpRule>>>,
+    geo: GeoIpResolver,
+    denials: DenialThrottle,
+}
+
+impl IpPolicy {
+    pub fn new(geo: GeoIpResolver) -> Self {
+        Self {
+            rules: RwLock::new(Arc::new(Vec::new())),
+            geo,
+            denials: DenialThrottle::new(DENIAL_AUDIT_WINDOW),
+        }
+    }
+
+    pub async fn rules(&self) -> Arc<Vec<IpRule>> {
+        self.rules.read().await.clone()
+    }
+
+    /// Country and network of `ip`, as far as the databases know
+    pub fn geo(&self, ip: IpAddr) -> GeoInfo {
+        self.geo.lookup(ip)
+    }
+
+    pub async fn check(&self, ip: IpAddr, scope: PolicyScope) -> IpDecision {
+        let rules = self.rules().await;
+        let geo = self.geo.lookup(ip);
+        evaluate(&rules, ip, &geo, scope, Utc::now())
+    }
+
+    /// Check and record denials in the audit trail, at most once per IP and
+    /// scope per [`DENIAL_AUDIT_WINDOW`]
+    pub async fn check_and_audit(&self, ip: IpAddr, scope: PolicyScope, audit: &AuditLogger) -> IpDecision {
+        let decision = self.check(ip, scope).await;
+
+        if !decision.allowed {
+            if let Some(held_back) = self.denials.note(ip, scope, Instant::now()) {
+                audit.log_event(SecurityEvent::IpPolicyDenied {
+                    ip,
+                    scope: scope.as_str().to_string(),
+                    rule_id: decision.rule_id,
+                    reason: decision.reason.clone(),
+                    held_back,
+                }).await;
+            }
+        }
+
+        decision
+    }
+
+    /// Replace the in-memory rules with the active rows from the database
+    pub async fn reload(&self, pool: &PgPool) -> anyhow::Result<usize> {
+        let rules = list_rules(pool).await?;
+        let count = rules.len();
+        *self.rules.write().await = Arc::new(rules);
+        Ok(count)
+    }
+
+    /// Reload on an interval so rule changes from any node are picked up
+    pub fn spawn_reloader(self: Arc<Self>, pool: PgPool, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.reload(&pool).await {
+                    error!("Failed to reload IP policy rules: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// New rule as submitted through the admin API
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewIpRule {
+    pub target: RuleTarget,
+    pub action: PolicyAction,
+    pub scope: PolicyScope,
+    pub note: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Active (unexpired) rules, oldest first
+pub async fn list_rules(pool: &PgPool) -> anyhow::Result<Vec<IpRule>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, target_kind, target_value, action, scope, note, expires_at
+        FROM ip_policy_rules
+        WHERE expires_at IS NULL OR expires_at > NOW()
+        ORDER BY id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut rules = Vec::with_capacity(rows.len());
+    for row in rows {
+        let target = match RuleTarget::parse(&row.target_kind, &row.target_value) {
+            Ok(target) => target,
+            Err(e) => {
+                warn!("Skipping invalid IP policy rule {}: {}", row.id, e);
+                continue;
+            }
+        };
+
+        rules.push(IpRule {
+            id: row.id,
+            target,
+            action: if row.action == "allow" { PolicyAction::Allow } else { PolicyAction::Deny },
+            scope: if row.scope == "registration" { PolicyScope::Registration } else { PolicyScope::All },
+            note: row.note,
+            expires_at: row.expires_at,
+        });
+    }
+
+    Ok(rules)
+}
+
+pub async fn add_rule(pool: &PgPool, rule: &NewIpRule, created_by: i64) -> anyhow::Result<i64> {
+    let action = match rule.action {
+        PolicyAction::Allow => "allow",
+        PolicyAction::Deny => "deny",
+    };
+
+    let id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO ip_policy_rules (target_kind, target_value, action, scope, note, created_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id
+        "#,
+        rule.target.kind(),
+        rule.target.value(),
+        action,
+        rule.scope.as_str(),
+        rule.note,
+        created_by,
+        rule.expires_at
+    )
+    .fetch_one(pool)
+    .await?;
+
+    info!("IP policy rule {} added: {} {} {}", id, action, rule.target.kind(), rule.target.value());
+    Ok(id)
+}
+
+/// Returns false if no rule had that id
+pub async fn delete_rule(pool: &PgPool, id: i64) -> anyhow::Result<bool> {
+    let result = sqlx::query!("DELETE FROM ip_policy_rules WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: i64, target: RuleTarget, action: PolicyAction, scope: PolicyScope) -> IpRule {
+        IpRule { id, target, action, scope, note: None, expires_at: None }
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_denials_audited_once_per_window() {
+        let throttle = DenialThrottle::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let client = ip("10.1.2.3");
+
+        assert_eq!(throttle.note(client, PolicyScope::All, start), Some(0));
+        for i in 1..=5 {
+            assert_eq!(throttle.note(client, PolicyScope::All, start + Duration::from_secs(i)), None);
+        }
+        // Other IPs and scopes have their own windows
+        assert_eq!(throttle.note(ip("10.1.2.4"), PolicyScope::All, start), Some(0));
+        assert_eq!(throttle.note(client, PolicyScope::Registration, start), Some(0));
+
+        assert_eq!(throttle.note(client, PolicyScope::All, start + Duration::from_secs(61)), Some(5));
+        assert_eq!(throttle.note(client, PolicyScope::All, start + Duration::from_secs(62)), None);
+    }
+
+    #[test]
+    fn test_cidr_deny() {
+        let rules = vec![rule(1, RuleTarget::Cidr("10.0.0.0/8".parse().unwrap()), PolicyAction::Deny, PolicyScope::All)];
+        let geo = GeoInfo::default();
+
+        let decision = evaluate(&rules, ip("10.1.2.3"), &geo, PolicyScope::All, Utc::now());
+        assert!(!decision.allowed);
+        assert_eq!(decision.rule_id, Some(1));
+
+        let decision = evaluate(&rules, ip("192.168.1.1"), &geo, PolicyScope::All, Utc::now());
+        assert!(decision.allowed);
+        assert_eq!(decision.rule_id, None);
+    }
+
+    #[test]
+    fn test_allow_overrides_deny() {
+        let rules = vec![
+            rule(1, RuleTarget::Asn(64500), PolicyAction::Deny, PolicyScope::All),
+            rule(2, RuleTarget::Cidr("203.0.113.7/32".parse().unwrap()), PolicyAction::Allow, PolicyScope::All),
+        ];
+        let geo = GeoInfo { asn: Some(64500), ..Default::default() };
+
+        let decision = evaluate(&rules, ip("203.0.113.7"), &geo, PolicyScope::All, Utc::now());
+        assert!(decision.allowed);
+        assert_eq!(decision.rule_id, Some(2));
+
+        let decision = evaluate(&rules, ip("203.0.113.8"), &geo, PolicyScope::All, Utc::now());
+        assert!(!decision.allowed);
+        assert_eq!(decision.rule_id, Some(1));
+    }
+
+    #[test]
+    fn test_country_registration_only() {
+        let rules = vec![rule(1, RuleTarget::Country("XX".to_string()), PolicyAction::Deny, PolicyScope::Registration)];
+        let geo = GeoInfo { country: Some("XX".to_string()), ..Default::default() };
+
+        assert!(!evaluate(&rules, ip("198.51.100.1"), &geo, PolicyScope::Registration, Utc::now()).allowed);
+        assert!(evaluate(&rules, ip("198.51.100.1"), &geo, PolicyScope::All, Utc::now()).allowed);
+    }
+
+    #[test]
+    fn test_expired_rule_ignored() {
+        let mut expired = rule(1, RuleTarget::Cidr("10.0.0.0/8".parse().unwrap()), PolicyAction::Deny, PolicyScope::All);
+        expired.expires_at = Some(Utc::now() - chrono::Duration::minutes(1));
+
+        let decision = evaluate(&[expired], ip("10.0.0.1"), &GeoInfo::default(), PolicyScope::All, Utc::now());
+        assert!(decision.allowed);
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(RuleTarget::parse("asn", "AS13335").unwrap(), RuleTarget::Asn(13335));
+        assert_eq!(RuleTarget::parse("country", "de").unwrap(), RuleTarget::Country("DE".to_string()));
+        assert!(RuleTarget::parse("country", "DEU").is_err());
+        assert!(RuleTarget::parse("cidr", "not-a-network").is_err());
+    }
+}
@@ -1,13 +1,18 @@
 //! Comprehensive security module for HackerExperience
 //!
-//! Provides audit logging, intrusion detection, DDoS protection, and encryption at rest
+//! Provides audit logging, intrusion detection, DDoS protection, WebSocket
+//! admission control, encryption at rest and IP allow/deny policy
 
 pub mod audit;
 pub mod intrusion;
 pub mod ddos;
 pub mod encryption;
+pub mod ip_policy;
+pub mod ws_guard;
 
 pub use audit::{AuditLogger, SecurityEvent};
 pub use intrusion::{IntrusionDetector, ThreatLevel};
 pub use ddos::{DDoSProtection, ConnectionThrottle};
-pub use encryption::{FieldEncryption, encrypt_field, decrypt_field};
\ No newline at end of file
+pub use encryption::{FieldEncryption, EncryptionAlgorithm, encrypt_field, decrypt_field};
+pub use ip_policy::{IpPolicy, PolicyScope};
+pub use ws_guard::{WsGuard, WsGuardConfig, WsPermit, WsRejection};
\ No newline at end of file
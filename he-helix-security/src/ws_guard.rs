@@ -0,0 +1,397 @@
+//! WebSocket admission control
+//!
+//! Every WebSocket handshake is admitted through [`WsGuard::admit`] before a
+//! session actor is spawned. In order, a handshake is refused when:
+//!
+//! - the [`IntrusionDetector`] has the IP blocked,
+//! - its `Origin` is not an allowed one,
+//! - the IP opened too many handshakes in the last minute,
+//! - the IP already holds its cap of open sockets,
+//! - the node holds its overall cap.
+//!
+//! The first three are abuse and are reported to the detector, which blocks
+//! the IP for a while once they add up, so bans live in one place.
+//!
+//! Past the drip threshold a node under load takes new sockets at a fixed
+//! rate instead of all at once: a handshake waits briefly for its turn and is
+//! told to retry later if it does not get one. A reconnect storm after a
+//! restart then fills the node gradually.
+//!
+//! An admitted handshake holds a [`WsPermit`] for as long as its socket is
+//! open; dropping the permit frees the slot.
+//!
+//! Behind a reverse proxy every handshake arrives from the proxy's address.
+//! [`WsGuard::client_ip`] takes the client address from `X-Forwarded-For` or
+//! `X-Real-IP`, but only when the peer is one of the configured trusted
+//! proxies; anyone else could put any address there.
+
+use crate::intrusion::IntrusionDetector;
+use dashmap::DashMap;
+use ipnetwork::IpNetwork;
+use governor::clock::DefaultClock;
+use governor::state::{InMemoryState, NotKeyed};
+use governor::{Quota, RateLimiter};
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+type DirectLimiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+#[derive(Debug, Clone)]
+pub struct WsGuardConfig {
+    pub max_connections_per_ip: u32,
+    pub handshakes_per_minute_per_ip: u32,
+    /// Exact `Origin` values accepted; empty accepts any
+    pub allowed_origins: Vec<String>,
+    /// Native clients send no `Origin`; browsers always do
+    pub allow_missing_origin: bool,
+    pub max_connections: usize,
+    /// Open sockets past which new ones are let in at `drip_per_second`
+    pub drip_threshold: usize,
+    pub drip_per_second: u32,
+    /// How long a handshake waits for its turn before being told to retry
+    pub drip_max_wait: Duration,
+    /// Reverse proxies whose forwarding headers are believed
+    pub trusted_proxies: Vec<IpNetwork>,
+}
+
+impl Default for WsGuardConfig {
+    fn default() -> Self {
+        Self {
+            max_connections_per_ip: 8,
+            handshakes_per_minute_per_ip: 30,
+            allowed_origins: Vec::new(),
+            allow_missing_origin: true,
+            max_connections: 10_000,
+            drip_threshold: 5_000,
+            drip_per_second: 50,
+            drip_max_wait: Duration::from_secs(2),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl WsGuardConfig {
+    /// Defaults, with `WS_ALLOWED_ORIGINS` and `TRUSTED_PROXIES` (comma
+    /// separated addresses or CIDR blocks) and `WS_MAX_CONNECTIONS_PER_IP` /
+    /// `WS_MAX_CONNECTIONS` when set
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(origins) = std::env::var("WS_ALLOWED_ORIGINS") {
+            config.allowed_origins = origins
+                .split(',')
+                .map(|o| o.trim().trim_end_matches('/').to_string())
+                .filter(|o| !o.is_empty())
+                .collect();
+        }
+        if let Some(max) = std::env::var("WS_MAX_CONNECTIONS_PER_IP").ok().and_then(|v| v.parse().ok()) {
+            config.max_connections_per_ip = max;
+        }
+        if let Some(max) = std::env::var("WS_MAX_CONNECTIONS").ok().and_then(|v| v.parse().ok()) {
+            config.max_connections = max;
+            config.drip_threshold = max / 2;
+        }
+        if let Ok(proxies) = std::env::var("TRUSTED_PROXIES") {
+            for proxy in proxies.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                match proxy.parse() {
+                    Ok(network) => config.trusted_proxies.push(network),
+                    Err(e) => warn!("Ignoring trusted proxy '{}': {}", proxy, e),
+                }
+            }
+        }
+        config
+    }
+
+    fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|network| network.contains(ip))
+    }
+
+    fn origin_allowed(&self, origin: Option<&str>) -> bool {
+        match origin {
+            None => self.allow_missing_origin,
+            Some(_) if self.allowed_origins.is_empty() => true,
+            Some(origin) => {
+                let origin = origin.trim_end_matches('/');
+                self.allowed_origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin))
+            }
+        }
+    }
+}
+
+/// Why a handshake was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsRejection {
+    Blocked,
+    BadOrigin,
+    HandshakeRate,
+    TooManyConnections { max: u32 },
+    ServerFull,
+    /// Under load and no turn came up in time
+    Busy { retry_after_secs: u64 },
+}
+
+impl WsRejection {
+    /// What the client is told
+    pub fn message(&self) -> String {
+        match self {
+            WsRejection::Blocked => "Your address is temporarily blocked".to_string(),
+            WsRejection::BadOrigin => "Origin not allowed".to_string(),
+            WsRejection::HandshakeRate => "Too many connection attempts, slow down".to_string(),
+            WsRejection::TooManyConnections { max } => {
+                format!("At most {} connections are allowed per address", max)
+            }
+            WsRejection::ServerFull => "Server is full, try another node".to_string(),
+            WsRejection::Busy { .. } => "Server is busy, retry shortly".to_string(),
+        }
+    }
+
+    /// Seconds the client should wait before retrying, for `Retry-After`
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            WsRejection::Busy { retry_after_secs } => Some(*retry_after_secs),
+            WsRejection::HandshakeRate => Some(60),
+            WsRejection::ServerFull => Some(5),
+            _ => None,
+        }
+    }
+
+    /// Whether the refusal counts as abuse for the intrusion detector
+    fn is_abuse(&self) -> bool {
+        matches!(
+            self,
+            WsRejection::BadOrigin | WsRejection::HandshakeRate | WsRejection::TooManyConnections { .. }
+        )
+    }
+}
+
+/// Open sockets per IP and in total
+#[derive(Default)]
+struct Slots {
+    per_ip: DashMap<IpAddr, u32>,
+    total: AtomicUsize,
+}
+
+impl Slots {
+    fn release(&self, ip: IpAddr) {
+        self.total.fetch_sub(1, Ordering::SeqCst);
+        if let dashmap::mapref::entry::Entry::Occupied(mut entry) = self.per_ip.entry(ip) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+    }
+}
+
+/// A socket's slot, freed on drop
+pub struct WsPermit {
+    slots: Arc<Slots>,
+    ip: IpAddr,
+}
+
+impl Drop for WsPermit {
+    fn drop(&mut self) {
+        self.slots.release(self.ip);
+    }
+}
+
+pub struct WsGuard {
+    config: WsGuardConfig,
+    detector: Arc<IntrusionDetector>,
+    slots: Arc<Slots>,
+    handshakes: DashMap<IpAddr, Arc<DirectLimiter>>,
+    drip: DirectLimiter,
+}
+
+impl WsGuard {
+    pub fn new(config: WsGuardConfig, detector: Arc<IntrusionDetector>) -> Self {
+        let drip = RateLimiter::direct(Quota::per_second(non_zero(config.drip_per_second)));
+        Self {
+            config,
+            detector,
+            slots: Arc::new(Slots::default()),
+            handshakes: DashMap::new(),
+            drip,
+        }
+    }
+
+    /// Address of the client behind `peer`. Forwarding headers are only read
+    /// when `peer` is a trusted proxy; `X-Forwarded-For` is walked from the
+    /// right, skipping further trusted proxies, so entries the client wrote
+    /// itself are never reached.
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: Option<&str>, real_ip: Option<&str>) -> IpAddr {
+        if !self.config.is_trusted_proxy(peer) {
+            return peer;
+        }
+
+        if let Some(forwarded_for) = forwarded_for {
+            for hop in forwarded_for.rsplit(',') {
+                let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                    // A malformed hop ends the chain we can vouch for
+                    return peer;
+                };
+                if !self.config.is_trusted_proxy(ip) {
+                    return ip;
+                }
+            }
+        }
+
+        real_ip
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or(peer)
+    }
+
+    /// Admit a handshake from `ip`, or say why not
+    pub async fn admit(&self, ip: IpAddr, origin: Option<&str>) -> Result<WsPermit, WsRejection> {
+        let result = self.try_admit(ip, origin).await;
+        if let Err(rejection) = &result {
+            if rejection.is_abuse() {
+                warn!("WebSocket handshake from {} refused: {:?}", ip, rejection);
+                self.detector.report_ws_abuse(ip, &format!("{:?}", rejection));
+            }
+        }
+        result
+    }
+
+    async fn try_admit(&self, ip: IpAddr, origin: Option<&str>) -> Result<WsPermit, WsRejection> {
+        if self.detector.is_blocked(ip) {
+            return Err(WsRejection::Blocked);
+        }
+
+        if !self.config.origin_allowed(origin) {
+            return Err(WsRejection::BadOrigin);
+        }
+
+        let handshakes = self
+            .handshakes
+            .entry(ip)
+            .or_insert_with(|| {
+                Arc::new(RateLimiter::direct(Quota::per_minute(non_zero(self.config.handshakes_per_minute_per_ip))))
+            })
+            .clone();
+        if handshakes.check().is_err() {
+            return Err(WsRejection::HandshakeRate);
+        }
+
+        let permit = self.reserve(ip)?;
+
+        if self.slots.total.load(Ordering::SeqCst) > self.config.drip_threshold {
+            let turn = tokio::time::timeout(self.config.drip_max_wait, self.drip.until_ready()).await;
+            if turn.is_err() {
+                return Err(WsRejection::Busy { retry_after_secs: self.config.drip_max_wait.as_secs().max(1) });
+            }
+        }
+
+        Ok(permit)
+    }
+
+    /// Take a slot for `ip` if it and the node are under their caps
+    fn reserve(&self, ip: IpAddr) -> Result<WsPermit, WsRejection> {
+        {
+            let mut open = self.slots.per_ip.entry(ip).or_insert(0);
+            if *open >= self.config.max_connections_per_ip {
+                return Err(WsRejection::TooManyConnections { max: self.config.max_connections_per_ip });
+            }
+            *open += 1;
+        }
+
+        // The permit owns the slot from here, so every refusal below frees it
+        let permit = WsPermit { slots: self.slots.clone(), ip };
+        if self.slots.total.fetch_add(1, Ordering::SeqCst) >= self.config.max_connections {
+            return Err(WsRejection::ServerFull);
+        }
+
+        Ok(permit)
+    }
+
+    pub fn open_connections(&self) -> usize {
+        self.slots.total.load(Ordering::SeqCst)
+    }
+
+    /// Forget handshake limiters of IPs with no open sockets
+    pub fn cleanup(&self) {
+        let slots = &self.slots;
+        self.handshakes.retain(|ip, _| slots.per_ip.contains_key(ip));
+    }
+}
+
+fn non_zero(n: u32) -> NonZeroU32 {
+    NonZeroU32::new(n).unwrap_or(NonZeroU32::MIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(config: WsGuardConfig) -> WsGuard {
+        WsGuard::new(config, Arc::new(IntrusionDetector::new()))
+    }
+
+    #[tokio::test]
+    async fn test_per_ip_cap_and_release() {
+        let guard = guard(WsGuardConfig { max_connections_per_ip: 2, ..Default::default() });
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+        let first = guard.admit(ip, None).await.unwrap();
+        let _second = guard.admit(ip, None).await.unwrap();
+        assert_eq!(
+            guard.admit(ip, None).await.err(),
+            Some(WsRejection::TooManyConnections { max: 2 })
+        );
+
+        drop(first);
+        assert_eq!(guard.open_connections(), 1);
+        assert!(guard.admit(ip, None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_origin_rate_and_ban() {
+        let guard = guard(WsGuardConfig {
+            allowed_origins: vec!["https://game.example".to_string()],
+            handshakes_per_minute_per_ip: 2,
+            ..Default::default()
+        });
+        let ip: IpAddr = "198.51.100.1".parse().unwrap();
+
+        assert_eq!(guard.admit(ip, Some("https://evil.example")).await.err(), Some(WsRejection::BadOrigin));
+        assert!(guard.admit(ip, Some("https://game.example/")).await.is_ok());
+        assert!(guard.admit(ip, None).await.is_ok());
+        assert_eq!(guard.admit(ip, None).await.err(), Some(WsRejection::HandshakeRate));
+
+        // Enough abuse gets the address blocked by the detector
+        for _ in 0..4 {
+            let _ = guard.admit(ip, None).await;
+        }
+        assert_eq!(guard.admit(ip, None).await.err(), Some(WsRejection::Blocked));
+    }
+
+    #[test]
+    fn test_client_ip_only_from_trusted_proxies() {
+        let guard = guard(WsGuardConfig {
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        });
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let client: IpAddr = "203.0.113.9".parse().unwrap();
+
+        // Direct peers cannot claim another address
+        assert_eq!(guard.client_ip(client, Some("198.51.100.1"), Some("198.51.100.1")), client);
+
+        // Spoofed entries left of the proxies' own are skipped
+        assert_eq!(guard.client_ip(proxy, Some("198.51.100.1, 203.0.113.9, 10.0.0.3"), None), client);
+        assert_eq!(guard.client_ip(proxy, None, Some("203.0.113.9")), client);
+        assert_eq!(guard.client_ip(proxy, Some("garbage"), None), proxy);
+    }
+
+    #[tokio::test]
+    async fn test_server_full_frees_slot() {
+        let guard = guard(WsGuardConfig { max_connections: 1, drip_threshold: 1, ..Default::default() });
+        let _only = guard.admit("192.0.2.1".parse().unwrap(), None).await.unwrap();
+
+        assert_eq!(guard.admit("192.0.2.2".parse().unwrap(), None).await.err(), Some(WsRejection::ServerFull));
+        assert_eq!(guard.open_connections(), 1);
+    }
+}
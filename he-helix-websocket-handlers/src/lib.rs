@@ -3,11 +3,14 @@
 pub mod join;
 pub mod request;
 pub mod session;  // WebSocket session with bounded queue
+pub mod stream;   // Cursor-paginated collections with ack backpressure
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
 
+use stream::StreamCursor;
+
 #[derive(Debug, Error)]
 pub enum WebSocketError {
     #[error("Invalid request: {message}")]
@@ -16,6 +19,8 @@ pub enum WebSocketError {
     ChannelNotFound { channel: String },
     #[error("Permission denied")]
     PermissionDenied,
+    #[error("Stream source failed: {message}")]
+    SourceFailed { message: String },
 }
 
 pub type WebSocketResult<T> = Result<T, WebSocketError>;
@@ -26,6 +31,16 @@ pub enum WebSocketRequest {
     Join { channel: String, entity_id: Uuid },
     Leave { channel: String },
     Ping,
+    StreamOpen {
+        collection: String,
+        #[serde(default)]
+        params: serde_json::Value,
+        chunk_size: Option<usize>,
+        window: Option<u32>,
+        resume: Option<StreamCursor>,
+    },
+    StreamAck { stream_id: Uuid, seq: u64 },
+    StreamCancel { stream_id: Uuid },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,6 +50,27 @@ pub enum WebSocketResponse {
     Left { channel: String },
     Pong,
     Error { message: String },
+    StreamOpened { stream_id: Uuid, collection: String },
+    StreamChunk {
+        stream_id: Uuid,
+        seq: u64,
+        items: Vec<serde_json::Value>,
+        cursor: StreamCursor,
+        done: bool,
+    },
+    StreamError { stream_id: Uuid, message: String },
+    /// Operator announcement pushed to every connected client
+    Announcement { id: i64, title: String, content: String, priority: String },
+    /// This node is draining: reconnect to `url` after the delay. No `url`
+    /// means no other gateway is healthy; rediscover before reconnecting.
+    Migrate { url: Option<String>, region: Option<String>, reconnect_after_ms: u64 },
+    /// This node is restarting: reconnect after the delay, to `gateway_hint`
+    /// when given, passing `resume_token` to reopen the session's streams
+    /// where they stopped. No token means there was nothing to resume.
+    ServerRestarting { reconnect_after_ms: u64, gateway_hint: Option<String>, resume_token: Option<String> },
+    /// Operators switched read-only mode: while enabled the server drops
+    /// client messages but keeps broadcasting
+    ReadOnly { enabled: bool },
 }
 
 /// Handle WebSocket requests
@@ -49,5 +85,11 @@ pub async fn handle_request(request: WebSocketRequest) -> WebSocketResult<WebSoc
             Ok(WebSocketResponse::Left { channel })
         }
         WebSocketRequest::Ping => Ok(WebSocketResponse::Pong),
+        // Streams hold per-connection state, see `session::WsSession`
+        WebSocketRequest::StreamOpen { .. }
+        | WebSocketRequest::StreamAck { .. }
+        | WebSocketRequest::StreamCancel { .. } => Err(WebSocketError::InvalidRequest {
+            message: "Streams are only available on a session".to_string(),
+        }),
     }
 }
\ No newline at end of file
@@ -2,9 +2,14 @@
 
 use std::time::{Duration, Instant};
 use std::collections::VecDeque;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, watch};
+use actix::{ActorFutureExt, AsyncContext, WrapFuture};
 use actix_web_actors::ws;
 
+use crate::stream::{ResumableStream, StreamContext, StreamManager, StreamRegistry};
+use crate::{WebSocketRequest, WebSocketResponse};
+
 /// Maximum queued messages per client before dropping oldest
 const MAX_QUEUE: usize = 1024;
 
@@ -14,6 +19,10 @@ const PING_SECS: u64 = 20;
 /// Drop client if no pong received within this duration
 const DROP_AFTER_SECS: u64 = 45;
 
+/// Asks a session for its open streams; the session stops streaming once it
+/// answers
+pub type Handoff = oneshot::Sender<Vec<ResumableStream>>;
+
 /// WebSocket session with bounded queue and health checks
 pub struct WsSession {
     /// Client ID
@@ -27,6 +36,27 @@ pub struct WsSession {
 
     /// Broadcast channel receiver
     pub broadcast_rx: mpsc::UnboundedReceiver<String>,
+
+    /// Open cursor streams
+    pub streams: StreamManager,
+
+    /// Collections this session may stream, and for whom
+    stream_access: Option<(Arc<StreamRegistry>, StreamContext)>,
+
+    /// While true, client messages other than pings are refused
+    read_only: Option<watch::Receiver<bool>>,
+
+    /// Closes the session once it turns true
+    shutdown: Option<watch::Receiver<bool>>,
+
+    /// Hands the open streams over when the node restarts
+    handoff: Option<oneshot::Receiver<Handoff>>,
+
+    /// Streams handed over by the node the client was on before
+    resumed: Vec<ResumableStream>,
+
+    /// Set once the streams were handed over; nothing more is streamed
+    handed_off: bool,
 }
 
 impl WsSession {
@@ -36,9 +66,50 @@ impl WsSession {
             queue: VecDeque::with_capacity(MAX_QUEUE),
             last_heartbeat: Instant::now(),
             broadcast_rx,
+            streams: StreamManager::new(),
+            stream_access: None,
+            read_only: None,
+            shutdown: None,
+            handoff: None,
+            resumed: Vec::new(),
+            handed_off: false,
         }
     }
 
+    /// Enable cursor streams for an authenticated session
+    pub fn with_streams(mut self, registry: Arc<StreamRegistry>, ctx: StreamContext) -> Self {
+        self.stream_access = Some((registry, ctx));
+        self
+    }
+
+    /// Refuse client messages while `read_only` is true; broadcasts still flow
+    pub fn with_read_only(mut self, read_only: watch::Receiver<bool>) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    /// Close the session with a policy close code when `shutdown` turns true
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Hand the open streams over on request, for a restart
+    pub fn with_handoff(mut self, handoff: oneshot::Receiver<Handoff>) -> Self {
+        self.handoff = Some(handoff);
+        self
+    }
+
+    /// Reopen streams handed over by another node once the session starts
+    pub fn with_resumed(mut self, streams: Vec<ResumableStream>) -> Self {
+        self.resumed = streams;
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.as_ref().is_some_and(|read_only| *read_only.borrow())
+    }
+
     /// Queue a message with backpressure handling
     pub fn queue_message(&mut self, msg: String) -> bool {
         // If queue is full, drop oldest message
@@ -74,6 +145,12 @@ impl actix::Actor for WsSession {
 
         // Start broadcast receiver
         self.handle_broadcasts(ctx);
+
+        self.watch_shutdown(ctx);
+
+        self.watch_handoff(ctx);
+
+        self.restore_streams(ctx);
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
@@ -97,22 +174,70 @@ impl WsSession {
         });
     }
 
-    /// Handle broadcast messages with queue management
-    fn handle_broadcasts(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
-        use futures_util::StreamExt;
+    fn watch_shutdown(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(mut shutdown) = self.shutdown.take() else {
+            return;
+        };
+
+        let closed = async move { shutdown.wait_for(|closed| *closed).await.is_ok() };
+        ctx.spawn(closed.into_actor(self).map(|closed, act, ctx| {
+            if closed {
+                tracing::info!("Client {} session revoked, disconnecting", act.id);
+                ctx.close(Some(ws::CloseCode::Policy.into()));
+                ctx.stop();
+            }
+        }));
+    }
+
+    fn watch_handoff(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(handoff) = self.handoff.take() else {
+            return;
+        };
+
+        ctx.spawn(async move { handoff.await.ok() }.into_actor(self).map(|handoff, act, _ctx| {
+            if let Some(reply) = handoff {
+                act.handed_off = true;
+                let _ = reply.send(act.streams.snapshot());
+            }
+        }));
+    }
 
-        let mut rx = std::mem::replace(
+    fn restore_streams(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some((registry, _)) = self.stream_access.clone() else {
+            return;
+        };
+
+        for stream in std::mem::take(&mut self.resumed) {
+            let stream_id = stream.stream_id;
+            let collection = stream.cursor.collection.clone();
+            match self.streams.restore(&registry, stream) {
+                Ok(()) => self.send(&WebSocketResponse::StreamOpened { stream_id, collection }, ctx),
+                Err(e) => self.send(&WebSocketResponse::StreamError { stream_id, message: e.to_string() }, ctx),
+            }
+        }
+        self.pump_streams(ctx);
+    }
+
+    /// Forward messages from the broadcast channel to the client
+    fn handle_broadcasts(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let rx = std::mem::replace(
             &mut self.broadcast_rx,
             mpsc::unbounded_channel().1
         );
 
-        ctx.spawn(async move {
-            while let Some(msg) = rx.recv().await {
-                // Queue message with backpressure handling
-                // This is where MAX_QUEUE enforcement happens
-            }
-        }.into_actor(self));
+        ctx.add_stream(futures_util::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|msg| (msg, rx))
+        }));
+    }
+}
+
+impl actix::StreamHandler<String> for WsSession {
+    fn handle(&mut self, msg: String, ctx: &mut Self::Context) {
+        ctx.text(msg);
     }
+
+    /// The broadcast channel closing does not end the session
+    fn finished(&mut self, _ctx: &mut Self::Context) {}
 }
 
 /// Handle WebSocket messages
@@ -149,10 +274,84 @@ impl WsSession {
             "ping" => {
                 ctx.text("pong");
             }
-            _ => {
-                // Handle other messages
-                tracing::debug!("Client {} sent: {}", self.id, msg);
+            _ if self.is_read_only() => {
+                self.send(&WebSocketResponse::Error { message: "Read-only mode: messages are not accepted".to_string() }, ctx);
+            }
+            _ => match serde_json::from_str::<WebSocketRequest>(&msg) {
+                Ok(request @ (WebSocketRequest::StreamOpen { .. }
+                | WebSocketRequest::StreamAck { .. }
+                | WebSocketRequest::StreamCancel { .. })) => {
+                    self.handle_stream_request(request, ctx);
+                }
+                _ => {
+                    // Handle other messages
+                    tracing::debug!("Client {} sent: {}", self.id, msg);
+                }
+            },
+        }
+    }
+
+    fn send(&self, response: &WebSocketResponse, ctx: &mut ws::WebsocketContext<Self>) {
+        match serde_json::to_string(response) {
+            Ok(json) => ctx.text(json),
+            Err(e) => tracing::error!("Failed to serialize response for {}: {}", self.id, e),
+        }
+    }
+
+    fn handle_stream_request(&mut self, request: WebSocketRequest, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some((registry, _)) = self.stream_access.clone() else {
+            self.send(&WebSocketResponse::Error { message: "Streams are not available".to_string() }, ctx);
+            return;
+        };
+        if self.handed_off {
+            self.send(&WebSocketResponse::Error { message: "Server is restarting, reconnect to resume".to_string() }, ctx);
+            return;
+        }
+
+        let result = match request {
+            WebSocketRequest::StreamOpen { collection, params, chunk_size, window, resume } => {
+                self.streams
+                    .open(&registry, collection.clone(), params, chunk_size, window, resume)
+                    .map(|stream_id| Some(WebSocketResponse::StreamOpened { stream_id, collection }))
+            }
+            WebSocketRequest::StreamAck { stream_id, seq } => self.streams.ack(stream_id, seq).map(|_| None),
+            WebSocketRequest::StreamCancel { stream_id } => {
+                self.streams.cancel(stream_id);
+                Ok(None)
             }
+            _ => Ok(None),
+        };
+
+        match result {
+            Ok(Some(response)) => self.send(&response, ctx),
+            Ok(None) => {}
+            Err(e) => self.send(&WebSocketResponse::Error { message: e.to_string() }, ctx),
+        }
+
+        self.pump_streams(ctx);
+    }
+
+    /// Start every fetch the stream windows allow; each completion pumps again
+    fn pump_streams(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some((registry, stream_ctx)) = &self.stream_access else {
+            return;
+        };
+        if self.handed_off {
+            return;
+        }
+
+        for job in self.streams.ready_fetches(registry) {
+            let stream_id = job.stream_id;
+            ctx.spawn(job.run(stream_ctx).into_actor(self).map(move |result, act, ctx| {
+                // The other node fetches this chunk again from the handed over cursor
+                if act.handed_off {
+                    return;
+                }
+                if let Some(response) = act.streams.fetched(stream_id, result) {
+                    act.send(&response, ctx);
+                }
+                act.pump_streams(ctx);
+            }));
         }
     }
 }
@@ -191,4 +390,15 @@ mod tests {
         session.heartbeat();
         assert!(!session.should_drop());
     }
+
+    #[test]
+    fn test_read_only_follows_watch() {
+        let (_tx, rx) = mpsc::unbounded_channel();
+        let (read_only, watched) = watch::channel(false);
+        let session = WsSession::new("test".to_string(), rx).with_read_only(watched);
+
+        assert!(!session.is_read_only());
+        read_only.send_replace(true);
+        assert!(session.is_read_only());
+    }
 }
\ No newline at end of file
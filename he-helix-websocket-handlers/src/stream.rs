@@ -0,0 +1,444 @@
+//! Cursor streams: large collections pushed over the WebSocket in chunks
+//!
+//! Protocol (JSON, `type` tagged):
+//!
+//! 1. Client sends `stream_open` with a collection name, optional params,
+//!    `chunk_size` and `window`. To pick up after a reconnect it sends the
+//!    `cursor` of the last chunk it processed as `resume`.
+//! 2. Server answers `stream_opened` with a `stream_id`, then pushes
+//!    `stream_chunk`s. Each chunk has a `seq` (starting at 1), its items, and
+//!    the cursor positioned after its last item.
+//! 3. Client acks with `stream_ack { stream_id, seq }`. At most `window` chunks
+//!    are unacknowledged at any time; the server stops fetching until acks
+//!    arrive, so a slow client never builds up a queue.
+//! 4. The chunk with `done: true` ends the stream. `stream_cancel` ends it early.
+//!
+//! When a node restarts, the session's streams are handed over as
+//! [`ResumableStream`]s and reopened on the node the client reconnects to
+//! with the same `stream_id`s, so the client keeps acking as before.
+//!
+//! Handlers opt in by implementing [`StreamSource`] and registering it in a
+//! [`StreamRegistry`] under a collection name. Sources are called with the
+//! session's [`StreamContext`] on every fetch, so they enforce ownership
+//! themselves and a resumed cursor can't read anyone else's data.
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{WebSocketError, WebSocketResponse, WebSocketResult};
+
+pub const MAX_STREAMS_PER_SESSION: usize = 4;
+pub const DEFAULT_CHUNK_SIZE: usize = 50;
+pub const MAX_CHUNK_SIZE: usize = 200;
+pub const DEFAULT_WINDOW: u32 = 2;
+pub const MAX_WINDOW: u32 = 8;
+
+/// Where a stream is in its collection. Opaque to the client, which only keeps
+/// the latest one to resume with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamCursor {
+    pub collection: String,
+    #[serde(default)]
+    pub params: Value,
+    /// Source-defined position after the last delivered item, `None` at the start
+    pub position: Option<String>,
+}
+
+/// An open stream as a session hands it to the node its client reconnects
+/// to. The cursor is after the last chunk sent; chunks still being fetched
+/// are fetched again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResumableStream {
+    pub stream_id: Uuid,
+    pub cursor: StreamCursor,
+    pub chunk_size: usize,
+    pub window: u32,
+    /// Seq of the next chunk to send, so seqs carry on across the reconnect
+    pub next_seq: u64,
+}
+
+/// One fetch from a source
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub items: Vec<Value>,
+    /// Position after the last item, `None` once the collection is exhausted
+    pub next: Option<String>,
+}
+
+/// Who the stream is for
+#[derive(Debug, Clone)]
+pub struct StreamContext {
+    pub user_id: i64,
+}
+
+/// A collection that can be streamed
+pub trait StreamSource: Send + Sync {
+    /// Fetch up to `limit` items after `after` (`None` = from the start)
+    fn fetch(
+        &self,
+        ctx: &StreamContext,
+        params: &Value,
+        after: Option<&str>,
+        limit: usize,
+    ) -> BoxFuture<'static, WebSocketResult<Page>>;
+}
+
+/// Collection name → source
+#[derive(Default, Clone)]
+pub struct StreamRegistry {
+    sources: HashMap<String, Arc<dyn StreamSource>>,
+}
+
+impl StreamRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, collection: impl Into<String>, source: impl StreamSource + 'static) -> &mut Self {
+        self.sources.insert(collection.into(), Arc::new(source));
+        self
+    }
+
+    pub fn get(&self, collection: &str) -> Option<Arc<dyn StreamSource>> {
+        self.sources.get(collection).cloned()
+    }
+}
+
+#[derive(Debug)]
+struct StreamState {
+    cursor: StreamCursor,
+    chunk_size: usize,
+    window: u32,
+    /// Seq of the next chunk to send
+    next_seq: u64,
+    /// Highest seq the client acknowledged
+    acked: u64,
+    fetching: bool,
+}
+
+impl StreamState {
+    fn in_flight(&self) -> u64 {
+        self.next_seq - 1 - self.acked
+    }
+}
+
+/// A fetch to run for one stream; hand the result to [`StreamManager::fetched`]
+pub struct FetchJob {
+    pub stream_id: Uuid,
+    source: Arc<dyn StreamSource>,
+    params: Value,
+    after: Option<String>,
+    limit: usize,
+}
+
+impl FetchJob {
+    pub fn run(self, ctx: &StreamContext) -> BoxFuture<'static, WebSocketResult<Page>> {
+        self.source.fetch(ctx, &self.params, self.after.as_deref(), self.limit)
+    }
+}
+
+/// Open streams of one WebSocket session
+#[derive(Debug, Default)]
+pub struct StreamManager {
+    streams: HashMap<Uuid, StreamState>,
+}
+
+impl StreamManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    pub fn open(
+        &mut self,
+        registry: &StreamRegistry,
+        collection: String,
+        params: Value,
+        chunk_size: Option<usize>,
+        window: Option<u32>,
+        resume: Option<StreamCursor>,
+    ) -> WebSocketResult<Uuid> {
+        if self.streams.len() >= MAX_STREAMS_PER_SESSION {
+            return Err(WebSocketError::InvalidRequest {
+                message: format!("At most {} open streams per connection", MAX_STREAMS_PER_SESSION),
+            });
+        }
+
+        if registry.get(&collection).is_none() {
+            return Err(WebSocketError::ChannelNotFound { channel: collection });
+        }
+
+        let cursor = match resume {
+            Some(cursor) if cursor.collection != collection => {
+                return Err(WebSocketError::InvalidRequest {
+                    message: "Resume cursor belongs to another collection".to_string(),
+                });
+            }
+            Some(cursor) => cursor,
+            None => StreamCursor { collection, params, position: None },
+        };
+
+        let stream_id = Uuid::new_v4();
+        self.streams.insert(stream_id, StreamState {
+            cursor,
+            chunk_size: chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).clamp(1, MAX_CHUNK_SIZE),
+            window: window.unwrap_or(DEFAULT_WINDOW).clamp(1, MAX_WINDOW),
+            next_seq: 1,
+            acked: 0,
+            fetching: false,
+        });
+
+        Ok(stream_id)
+    }
+
+    /// Reopen a stream handed over by another node under its old id. Chunks
+    /// sent before the handover count as acknowledged.
+    pub fn restore(&mut self, registry: &StreamRegistry, stream: ResumableStream) -> WebSocketResult<()> {
+        if self.streams.len() >= MAX_STREAMS_PER_SESSION {
+            return Err(WebSocketError::InvalidRequest {
+                message: format!("At most {} open streams per connection", MAX_STREAMS_PER_SESSION),
+            });
+        }
+        if registry.get(&stream.cursor.collection).is_none() {
+            return Err(WebSocketError::ChannelNotFound { channel: stream.cursor.collection });
+        }
+
+        let next_seq = stream.next_seq.max(1);
+        self.streams.insert(stream.stream_id, StreamState {
+            cursor: stream.cursor,
+            chunk_size: stream.chunk_size.clamp(1, MAX_CHUNK_SIZE),
+            window: stream.window.clamp(1, MAX_WINDOW),
+            next_seq,
+            acked: next_seq - 1,
+            fetching: false,
+        });
+        Ok(())
+    }
+
+    /// Every open stream, for handing the session over
+    pub fn snapshot(&self) -> Vec<ResumableStream> {
+        self.streams
+            .iter()
+            .map(|(stream_id, stream)| ResumableStream {
+                stream_id: *stream_id,
+                cursor: stream.cursor.clone(),
+                chunk_size: stream.chunk_size,
+                window: stream.window,
+                next_seq: stream.next_seq,
+            })
+            .collect()
+    }
+
+    pub fn ack(&mut self, stream_id: Uuid, seq: u64) -> WebSocketResult<()> {
+        let stream = self.streams.get_mut(&stream_id).ok_or_else(|| WebSocketError::InvalidRequest {
+            message: format!("Unknown stream {}", stream_id),
+        })?;
+
+        if seq >= stream.next_seq {
+            return Err(WebSocketError::InvalidRequest {
+                message: format!("Chunk {} has not been sent", seq),
+            });
+        }
+
+        stream.acked = stream.acked.max(seq);
+        Ok(())
+    }
+
+    pub fn cancel(&mut self, stream_id: Uuid) -> bool {
+        self.streams.remove(&stream_id).is_some()
+    }
+
+    /// Fetches allowed by the windows right now. Each stream has at most one
+    /// fetch running, so chunks come out in order.
+    pub fn ready_fetches(&mut self, registry: &StreamRegistry) -> Vec<FetchJob> {
+        let mut jobs = Vec::new();
+
+        for (stream_id, stream) in &mut self.streams {
+            if stream.fetching || stream.in_flight() >= stream.window as u64 {
+                continue;
+            }
+            let Some(source) = registry.get(&stream.cursor.collection) else {
+                continue;
+            };
+
+            stream.fetching = true;
+            jobs.push(FetchJob {
+                stream_id: *stream_id,
+                source,
+                params: stream.cursor.params.clone(),
+                after: stream.cursor.position.clone(),
+                limit: stream.chunk_size,
+            });
+        }
+
+        jobs
+    }
+
+    /// Turn a finished fetch into the message to send. `None` if the stream
+    /// was cancelled while the fetch ran.
+    pub fn fetched(&mut self, stream_id: Uuid, result: WebSocketResult<Page>) -> Option<WebSocketResponse> {
+        let stream = self.streams.get_mut(&stream_id)?;
+        stream.fetching = false;
+
+        let page = match result {
+            Ok(page) => page,
+            Err(e) => {
+                self.streams.remove(&stream_id);
+                return Some(WebSocketResponse::StreamError { stream_id, message: e.to_string() });
+            }
+        };
+
+        let seq = stream.next_seq;
+        stream.next_seq += 1;
+
+        let done = page.next.is_none();
+        if let Some(next) = page.next {
+            stream.cursor.position = Some(next);
+        }
+        let cursor = stream.cursor.clone();
+
+        if done {
+            self.streams.remove(&stream_id);
+        }
+
+        Some(WebSocketResponse::StreamChunk {
+            stream_id,
+            seq,
+            items: page.items,
+            cursor,
+            done,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Numbers 1..=total, position is the last number sent
+    struct Numbers {
+        total: u64,
+    }
+
+    impl StreamSource for Numbers {
+        fn fetch(
+            &self,
+            _ctx: &StreamContext,
+            _params: &Value,
+            after: Option<&str>,
+            limit: usize,
+        ) -> BoxFuture<'static, WebSocketResult<Page>> {
+            let start = after.and_then(|a| a.parse::<u64>().ok()).unwrap_or(0) + 1;
+            let end = (start + limit as u64 - 1).min(self.total);
+            let items = (start..=end).map(Value::from).collect();
+            let next = (end < self.total).then(|| end.to_string());
+            Box::pin(async move { Ok(Page { items, next }) })
+        }
+    }
+
+    fn registry() -> StreamRegistry {
+        let mut registry = StreamRegistry::new();
+        registry.register("numbers", Numbers { total: 5 });
+        registry
+    }
+
+    async fn run(jobs: Vec<FetchJob>, manager: &mut StreamManager) -> Vec<WebSocketResponse> {
+        let ctx = StreamContext { user_id: 1 };
+        let mut responses = Vec::new();
+        for job in jobs {
+            let stream_id = job.stream_id;
+            let result = job.run(&ctx).await;
+            responses.extend(manager.fetched(stream_id, result));
+        }
+        responses
+    }
+
+    #[tokio::test]
+    async fn test_window_and_acks() {
+        let registry = registry();
+        let mut manager = StreamManager::new();
+        let id = manager.open(&registry, "numbers".to_string(), Value::Null, Some(2), Some(1), None).unwrap();
+
+        let responses = run(manager.ready_fetches(&registry), &mut manager).await;
+        assert!(matches!(&responses[..], [WebSocketResponse::StreamChunk { seq: 1, done: false, .. }]));
+
+        // Window of 1: nothing more until chunk 1 is acked
+        assert!(manager.ready_fetches(&registry).is_empty());
+        assert!(manager.ack(id, 2).is_err());
+        manager.ack(id, 1).unwrap();
+
+        let responses = run(manager.ready_fetches(&registry), &mut manager).await;
+        let WebSocketResponse::StreamChunk { seq, items, .. } = &responses[0] else { panic!() };
+        assert_eq!(*seq, 2);
+        assert_eq!(items, &vec![Value::from(3), Value::from(4)]);
+
+        manager.ack(id, 2).unwrap();
+        let responses = run(manager.ready_fetches(&registry), &mut manager).await;
+        assert!(matches!(&responses[..], [WebSocketResponse::StreamChunk { seq: 3, done: true, .. }]));
+        assert!(manager.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_cursor() {
+        let registry = registry();
+        let mut manager = StreamManager::new();
+        manager.open(&registry, "numbers".to_string(), Value::Null, Some(2), None, None).unwrap();
+
+        let responses = run(manager.ready_fetches(&registry), &mut manager).await;
+        let WebSocketResponse::StreamChunk { cursor, .. } = &responses[0] else { panic!() };
+
+        // New connection picks up after the first chunk
+        let mut reconnected = StreamManager::new();
+        reconnected.open(&registry, "numbers".to_string(), Value::Null, Some(10), None, Some(cursor.clone())).unwrap();
+        let responses = run(reconnected.ready_fetches(&registry), &mut reconnected).await;
+        let WebSocketResponse::StreamChunk { items, done, .. } = &responses[0] else { panic!() };
+        assert_eq!(items, &vec![Value::from(3), Value::from(4), Value::from(5)]);
+        assert!(*done);
+    }
+
+    #[tokio::test]
+    async fn test_handover_keeps_id_and_seq() {
+        let registry = registry();
+        let mut manager = StreamManager::new();
+        let id = manager.open(&registry, "numbers".to_string(), Value::Null, Some(2), Some(1), None).unwrap();
+        run(manager.ready_fetches(&registry), &mut manager).await;
+
+        // Chunk 1 went out unacked; the other node carries on from chunk 2
+        let handed = manager.snapshot();
+        let mut restored = StreamManager::new();
+        for stream in handed {
+            restored.restore(&registry, stream).unwrap();
+        }
+        let responses = run(restored.ready_fetches(&registry), &mut restored).await;
+        let WebSocketResponse::StreamChunk { stream_id, seq, items, .. } = &responses[0] else { panic!() };
+        assert_eq!((*stream_id, *seq), (id, 2));
+        assert_eq!(items, &vec![Value::from(3), Value::from(4)]);
+    }
+
+    #[test]
+    fn test_open_limits() {
+        let registry = registry();
+        let mut manager = StreamManager::new();
+
+        assert!(manager.open(&registry, "missing".to_string(), Value::Null, None, None, None).is_err());
+
+        let foreign = StreamCursor { collection: "other".to_string(), params: Value::Null, position: None };
+        assert!(manager.open(&registry, "numbers".to_string(), Value::Null, None, None, Some(foreign)).is_err());
+
+        for _ in 0..MAX_STREAMS_PER_SESSION {
+            manager.open(&registry, "numbers".to_string(), Value::Null, None, None, None).unwrap();
+        }
+        assert!(manager.open(&registry, "numbers".to_string(), Value::Null, None, None, None).is_err());
+    }
+}
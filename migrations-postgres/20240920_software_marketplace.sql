@@ -0,0 +1,42 @@
+-- Player-to-player software marketplace
+-- Date: 2024-09-20
+
+CREATE TABLE IF NOT EXISTS software_listings (
+    id BIGSERIAL PRIMARY KEY,
+    seller_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    software_id BIGINT NOT NULL REFERENCES software(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    type VARCHAR(50) NOT NULL,
+    version DECIMAL(10,2) NOT NULL,
+    price BIGINT NOT NULL CHECK (price > 0), -- In cents, same as bank_accounts.balance
+    copies_total INTEGER NOT NULL CHECK (copies_total > 0),
+    copies_sold INTEGER NOT NULL DEFAULT 0,
+    allow_resale BOOLEAN NOT NULL DEFAULT FALSE,
+    royalty_percent SMALLINT NOT NULL DEFAULT 0 CHECK (royalty_percent BETWEEN 0 AND 50),
+    origin_listing_id BIGINT REFERENCES software_listings(id) ON DELETE SET NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (copies_sold <= copies_total)
+);
+
+CREATE INDEX idx_software_listings_active_type ON software_listings(type, version DESC) WHERE is_active = TRUE;
+CREATE INDEX idx_software_listings_seller ON software_listings(seller_id);
+CREATE INDEX idx_software_listings_name ON software_listings(LOWER(name)) WHERE is_active = TRUE;
+
+-- License stamp attached to every purchased copy
+CREATE TABLE IF NOT EXISTS software_licenses (
+    id BIGSERIAL PRIMARY KEY,
+    software_id BIGINT NOT NULL UNIQUE REFERENCES software(id) ON DELETE CASCADE,
+    listing_id BIGINT NOT NULL REFERENCES software_listings(id) ON DELETE CASCADE,
+    buyer_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    license_key VARCHAR(64) NOT NULL UNIQUE,
+    price_paid BIGINT NOT NULL,
+    purchased_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_software_licenses_buyer ON software_licenses(buyer_id);
+CREATE INDEX idx_software_licenses_listing ON software_licenses(listing_id);
+
+CREATE TRIGGER update_software_listings_updated_at BEFORE UPDATE
+    ON software_listings FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,32 @@
+-- Account email change with dual confirmation
+-- Date: 2024-09-21
+--
+-- Flow: awaiting_old (token mailed to the current address)
+--    -> awaiting_new (verification mailed to the new address)
+--    -> completed (users.email switched, sessions dropped)
+-- The old address also gets a revoke token, valid until revocable_until,
+-- which cancels a pending change or rolls back a completed one.
+
+CREATE TABLE IF NOT EXISTS email_change_requests (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    old_email VARCHAR(255) NOT NULL,
+    new_email VARCHAR(255) NOT NULL,
+    -- SHA256 hex of the tokens, never the tokens themselves
+    confirm_token_hash VARCHAR(64) NOT NULL UNIQUE,
+    verify_token_hash VARCHAR(64) UNIQUE,
+    revoke_token_hash VARCHAR(64) NOT NULL UNIQUE,
+    status VARCHAR(20) NOT NULL DEFAULT 'awaiting_old'
+        CHECK (status IN ('awaiting_old', 'awaiting_new', 'completed', 'revoked', 'expired')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    revocable_until TIMESTAMPTZ NOT NULL,
+    old_confirmed_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+-- At most one in-flight change per account
+CREATE UNIQUE INDEX idx_email_change_pending_user ON email_change_requests(user_id)
+    WHERE status IN ('awaiting_old', 'awaiting_new');
+CREATE INDEX idx_email_change_user ON email_change_requests(user_id, created_at DESC);
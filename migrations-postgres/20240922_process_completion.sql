@@ -0,0 +1,13 @@
+-- Server-side process completion
+-- Date: 2024-09-22
+--
+-- The completion worker claims a finished process by setting completed_at in
+-- the same transaction that applies its rewards and logs, so a process is
+-- completed exactly once even with several workers running.
+
+ALTER TABLE processes ADD COLUMN IF NOT EXISTS completed_at TIMESTAMPTZ;
+
+-- Worker scan: unfinished processes ordered by end time
+CREATE INDEX IF NOT EXISTS idx_processes_pending_completion
+    ON processes(end_time)
+    WHERE completed_at IS NULL;
@@ -0,0 +1,20 @@
+-- IP allow/deny rules by CIDR, ASN or country
+-- Date: 2024-09-23
+--
+-- Loaded by he-helix-security::ip_policy and reloaded periodically.
+-- A matching allow rule overrides any deny; no match means allow.
+-- scope = 'registration' restricts account creation only.
+
+CREATE TABLE IF NOT EXISTS ip_policy_rules (
+    id BIGSERIAL PRIMARY KEY,
+    target_kind VARCHAR(10) NOT NULL CHECK (target_kind IN ('cidr', 'asn', 'country')),
+    target_value VARCHAR(64) NOT NULL,
+    action VARCHAR(10) NOT NULL CHECK (action IN ('allow', 'deny')),
+    scope VARCHAR(20) NOT NULL DEFAULT 'all' CHECK (scope IN ('all', 'registration')),
+    note TEXT,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_ip_policy_rules_expires ON ip_policy_rules(expires_at);
@@ -0,0 +1,8 @@
+-- Player server customization
+-- Date: 2024-09-24
+--
+-- hostname already exists on servers. motd is shown to players who log in to
+-- the server; public_profile is the page shown in the in-game browser.
+
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS motd VARCHAR(280);
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS public_profile TEXT;
@@ -0,0 +1,17 @@
+-- Operator kill-switch for process types
+-- Date: 2024-09-25
+--
+-- While a row exists, no account can start a process of that type. Engaging a
+-- switch also cancels every unfinished process of the type. Each API node caches
+-- this table and reloads it periodically.
+
+CREATE TABLE IF NOT EXISTS process_kill_switches (
+    process_type VARCHAR(50) PRIMARY KEY, -- lower-case
+    reason TEXT NOT NULL,
+    engaged_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    engaged_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Hourly per-type quota check
+CREATE INDEX IF NOT EXISTS idx_processes_user_type_start
+    ON processes(user_id, process_type, start_time);
@@ -0,0 +1,34 @@
+-- Bookkeeping for the legacy HackerExperience (MySQL) importer
+-- Date: 2024-09-26
+--
+-- legacy_id_map records where each imported legacy row ended up, so references
+-- between tables can be rewritten and re-runs skip rows already imported.
+-- legacy_import_skips records rows that were not imported and why.
+-- legacy_import_checkpoints holds the last legacy id committed per table, so an
+-- interrupted import resumes where it stopped.
+
+CREATE TABLE IF NOT EXISTS legacy_id_map (
+    entity VARCHAR(32) NOT NULL, -- 'user', 'server', 'software', 'clan'
+    legacy_id BIGINT NOT NULL,
+    new_id BIGINT NOT NULL,
+    imported_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (entity, legacy_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_legacy_id_map_new ON legacy_id_map(entity, new_id);
+
+CREATE TABLE IF NOT EXISTS legacy_import_skips (
+    entity VARCHAR(32) NOT NULL,
+    legacy_id BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    skipped_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (entity, legacy_id)
+);
+
+CREATE TABLE IF NOT EXISTS legacy_import_checkpoints (
+    entity VARCHAR(32) PRIMARY KEY,
+    last_legacy_id BIGINT NOT NULL,
+    rows_read BIGINT NOT NULL DEFAULT 0,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,30 @@
+-- Notification center storage
+-- Date: 2024-09-27
+--
+-- Notifications are listed newest first with keyset pagination on
+-- (creation_time, notification_id). Classes an account has muted are neither
+-- stored nor listed.
+
+CREATE TABLE IF NOT EXISTS notifications (
+    notification_id UUID PRIMARY KEY,
+    account_id UUID NOT NULL,
+    class VARCHAR(16) NOT NULL, -- 'server', 'chat', 'entity'
+    code VARCHAR(64) NOT NULL,
+    target_id UUID, -- server, chat or entity, depending on class
+    data JSONB NOT NULL DEFAULT '{}',
+    is_read BOOLEAN NOT NULL DEFAULT FALSE,
+    creation_time TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_account_time
+    ON notifications(account_id, creation_time DESC, notification_id DESC);
+CREATE INDEX IF NOT EXISTS idx_notifications_account_unread
+    ON notifications(account_id, class) WHERE NOT is_read;
+
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    account_id UUID NOT NULL,
+    class VARCHAR(16) NOT NULL,
+    muted BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (account_id, class)
+);
@@ -0,0 +1,55 @@
+-- Scheduled job registry for he-cron
+-- Date: 2024-09-28
+--
+-- he-cron registers each of its jobs here on startup and records every run.
+-- The admin API reads these tables for the dashboard and talks back to the
+-- scheduler through `paused` and `trigger_requested_at`, which he-cron polls.
+
+CREATE TABLE IF NOT EXISTS cron_jobs (
+    name VARCHAR(64) PRIMARY KEY,
+    schedule VARCHAR(64) NOT NULL,          -- cron expression with seconds
+    time_budget_seconds INTEGER NOT NULL,
+    paused BOOLEAN NOT NULL DEFAULT FALSE,
+    paused_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    trigger_requested_at TIMESTAMPTZ,
+    trigger_requested_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    next_run_at TIMESTAMPTZ,
+    last_started_at TIMESTAMPTZ,
+    last_finished_at TIMESTAMPTZ,
+    last_duration_ms BIGINT,
+    last_success BOOLEAN,
+    last_error TEXT,
+    run_count BIGINT NOT NULL DEFAULT 0,
+    failure_count BIGINT NOT NULL DEFAULT 0,
+    registered_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS cron_job_runs (
+    id BIGSERIAL PRIMARY KEY,
+    job_name VARCHAR(64) NOT NULL REFERENCES cron_jobs(name) ON DELETE CASCADE,
+    trigger VARCHAR(16) NOT NULL CHECK (trigger IN ('schedule', 'manual')),
+    status VARCHAR(16) NOT NULL CHECK (status IN ('running', 'succeeded', 'failed', 'skipped')),
+    host VARCHAR(255) NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ,
+    duration_ms BIGINT,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_cron_job_runs_job ON cron_job_runs(job_name, started_at DESC);
+CREATE INDEX IF NOT EXISTS idx_cron_job_runs_running ON cron_job_runs(started_at) WHERE status = 'running';
+
+CREATE TABLE IF NOT EXISTS cron_job_alerts (
+    id BIGSERIAL PRIMARY KEY,
+    job_name VARCHAR(64) NOT NULL REFERENCES cron_jobs(name) ON DELETE CASCADE,
+    kind VARCHAR(16) NOT NULL CHECK (kind IN ('missed', 'over_budget')),
+    detail TEXT NOT NULL,
+    raised_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ,
+    acknowledged_by BIGINT REFERENCES users(id) ON DELETE SET NULL
+);
+
+-- At most one open alert per job and kind, so a stuck job is reported once
+CREATE UNIQUE INDEX IF NOT EXISTS idx_cron_job_alerts_open
+    ON cron_job_alerts(job_name, kind) WHERE resolved_at IS NULL;
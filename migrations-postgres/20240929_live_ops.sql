@@ -0,0 +1,33 @@
+-- Live-ops state shared by all API nodes
+-- Date: 2024-09-29
+--
+-- Written by the admin live-ops console. Every node reloads these tables on an
+-- interval, so a flag flipped or a drain requested on one node reaches the rest
+-- within a few seconds.
+
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name VARCHAR(64) PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    updated_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One row per API node, refreshed by the node itself
+CREATE TABLE IF NOT EXISTS live_ops_nodes (
+    node_id VARCHAR(128) PRIMARY KEY,
+    draining BOOLEAN NOT NULL DEFAULT FALSE,
+    drain_requested_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    drain_requested_at TIMESTAMPTZ,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Nodes push announcements with an id above the last one they delivered
+CREATE TABLE IF NOT EXISTS live_ops_announcements (
+    id BIGSERIAL PRIMARY KEY,
+    title VARCHAR(200) NOT NULL,
+    content TEXT NOT NULL,
+    priority VARCHAR(16) NOT NULL CHECK (priority IN ('info', 'warning', 'critical')),
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,15 @@
+-- Password hashing parameters stored with each hash
+-- Date: 2024-09-30
+--
+-- pwd_policy_version is the PasswordConfig::policy_version the hash was made
+-- under. Logins re-hash passwords whose version is older than the current one.
+-- Version 0 marks hashes from before versioning, including the legacy bcrypt
+-- hashes copied by the legacy importer.
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pwd_policy_version INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pwd_memory_kib INTEGER;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pwd_iterations INTEGER;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pwd_parallelism INTEGER;
+
+-- How many accounts are still waiting for an upgrade
+CREATE INDEX IF NOT EXISTS idx_users_pwd_policy_version ON users(pwd_policy_version);
@@ -0,0 +1,22 @@
+-- Clan war battle log for spectators
+-- Date: 2024-10-01
+--
+-- Events are stored already anonymised: players appear as pseudonyms that are
+-- stable within one war. Rows become visible to spectators at visible_at, a
+-- few seconds after the event, so watching a war cannot be used to scout it.
+
+CREATE TABLE IF NOT EXISTS war_battle_log (
+    id BIGSERIAL PRIMARY KEY,
+    war_id BIGINT NOT NULL,
+    kind VARCHAR(32) NOT NULL CHECK (kind IN ('attack_launched', 'server_downed', 'score_change', 'war_ended')),
+    -- Side that caused the event
+    side VARCHAR(16) NOT NULL CHECK (side IN ('attacker', 'defender')),
+    actor VARCHAR(32),
+    target VARCHAR(32),
+    attacker_score BIGINT NOT NULL,
+    defender_score BIGINT NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    visible_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_war_battle_log_war ON war_battle_log(war_id, id);
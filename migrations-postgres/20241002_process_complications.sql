@@ -0,0 +1,20 @@
+-- Random events during long-running processes
+-- Date: 2024-10-02
+--
+-- One row per complication. A process gets at most one, which the unique pid
+-- enforces even when several API nodes roll for it. Killed processes are
+-- deleted, so pid is not a foreign key and the row outlives them.
+
+CREATE TABLE IF NOT EXISTS process_complications (
+    id BIGSERIAL PRIMARY KEY,
+    pid BIGINT NOT NULL UNIQUE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    process_type VARCHAR(64) NOT NULL,
+    kind VARCHAR(32) NOT NULL CHECK (kind IN ('firewall_update', 'power_spike', 'lucky_exploit')),
+    previous_end_time TIMESTAMPTZ NOT NULL,
+    -- NULL when the process was killed
+    new_end_time TIMESTAMPTZ,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_process_complications_user ON process_complications(user_id, occurred_at DESC);
@@ -0,0 +1,125 @@
+-- Account synchronization with the phpBB forum
+-- Date: 2024-10-03
+--
+-- forum_sync_queue carries "this aspect of this user changed" messages in both
+-- directions. Triggers on the game tables enqueue messages for the forum; the
+-- bridge enqueues messages for the game when it sees a change on the forum.
+-- Messages only name what changed: whoever applies one reads the current value
+-- from the other side, so a burst of changes collapses into one pending row
+-- and the order messages are applied in does not matter.
+--
+-- forum_accounts links players to their forum account and remembers the forum
+-- state last seen, which is how the bridge notices changes made on the forum.
+
+CREATE TABLE IF NOT EXISTS user_bans (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL DEFAULT '',
+    -- NULL for a permanent ban
+    banned_until TIMESTAMPTZ,
+    source VARCHAR(16) NOT NULL CHECK (source IN ('game', 'forum')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS forum_accounts (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    forum_user_id BIGINT NOT NULL UNIQUE,
+    forum_username VARCHAR(255) NOT NULL,
+    forum_banned BOOLEAN NOT NULL DEFAULT FALSE,
+    synced_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS forum_sync_queue (
+    id BIGSERIAL PRIMARY KEY,
+    -- Side the message is applied to
+    direction VARCHAR(8) NOT NULL CHECK (direction IN ('forum', 'game')),
+    user_id BIGINT NOT NULL,
+    kind VARCHAR(16) NOT NULL CHECK (kind IN ('provision', 'roles', 'rename', 'ban')),
+    -- Set when the aspect changed again while the message was being applied
+    dirty BOOLEAN NOT NULL DEFAULT FALSE,
+    attempts INT NOT NULL DEFAULT 0,
+    available_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    -- Gave up after too many attempts; kept for operators to inspect
+    dead BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_forum_sync_queue_pending
+    ON forum_sync_queue(direction, user_id, kind) WHERE NOT dead;
+CREATE INDEX IF NOT EXISTS idx_forum_sync_queue_available
+    ON forum_sync_queue(direction, available_at) WHERE NOT dead;
+
+CREATE OR REPLACE FUNCTION forum_sync_enqueue(target_user BIGINT, sync_kind VARCHAR)
+RETURNS VOID AS $$
+BEGIN
+    -- Changes the bridge itself applies from the forum are not sent back
+    IF current_setting('he.sync_origin', true) = 'forum' THEN
+        RETURN;
+    END IF;
+
+    INSERT INTO forum_sync_queue (direction, user_id, kind)
+    VALUES ('forum', target_user, sync_kind)
+    ON CONFLICT (direction, user_id, kind) WHERE NOT dead
+    DO UPDATE SET dirty = TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION forum_sync_users()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        PERFORM forum_sync_enqueue(NEW.id, 'provision');
+        RETURN NEW;
+    END IF;
+
+    IF NEW.login IS DISTINCT FROM OLD.login THEN
+        PERFORM forum_sync_enqueue(NEW.id, 'rename');
+    END IF;
+    IF NEW.premium IS DISTINCT FROM OLD.premium THEN
+        PERFORM forum_sync_enqueue(NEW.id, 'roles');
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS forum_sync_users ON users;
+CREATE TRIGGER forum_sync_users AFTER INSERT OR UPDATE OF login, premium
+    ON users FOR EACH ROW EXECUTE FUNCTION forum_sync_users();
+
+CREATE OR REPLACE FUNCTION forum_sync_user_bans()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM forum_sync_enqueue(OLD.user_id, 'ban');
+        RETURN OLD;
+    END IF;
+    PERFORM forum_sync_enqueue(NEW.user_id, 'ban');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS forum_sync_user_bans ON user_bans;
+CREATE TRIGGER forum_sync_user_bans AFTER INSERT OR UPDATE OR DELETE
+    ON user_bans FOR EACH ROW EXECUTE FUNCTION forum_sync_user_bans();
+
+-- Moderators come from the RBAC roles of he-auth, where that schema exists
+CREATE OR REPLACE FUNCTION forum_sync_user_roles()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM forum_sync_enqueue(OLD.user_id, 'roles');
+        RETURN OLD;
+    END IF;
+    PERFORM forum_sync_enqueue(NEW.user_id, 'roles');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DO $$
+BEGIN
+    IF to_regclass('user_roles') IS NOT NULL THEN
+        DROP TRIGGER IF EXISTS forum_sync_user_roles ON user_roles;
+        CREATE TRIGGER forum_sync_user_roles AFTER INSERT OR UPDATE OR DELETE
+            ON user_roles FOR EACH ROW EXECUTE FUNCTION forum_sync_user_roles();
+    END IF;
+END $$;
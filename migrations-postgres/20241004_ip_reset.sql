@@ -0,0 +1,55 @@
+-- IP resets, the hacked database and tunnels
+-- Date: 2024-10-04
+--
+-- A reset moves a player's gateway to a new address. Hacked-database entries
+-- other players hold for the old address are invalidated rather than deleted,
+-- so the player still sees what they lost. Tunnels through the gateway follow
+-- it to the new address; tunnels other players had opened to it are closed.
+-- Logs are left alone and keep the old address.
+
+-- Servers a player has cracked, with the credentials they found
+CREATE TABLE IF NOT EXISTS hacked_database (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ip_address INET NOT NULL,
+    username VARCHAR(32) NOT NULL DEFAULT 'root',
+    password VARCHAR(32),
+    hacked_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Set when the server moved to another address
+    invalidated_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_hacked_database_valid
+    ON hacked_database(user_id, ip_address) WHERE invalidated_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_hacked_database_ip
+    ON hacked_database(ip_address) WHERE invalidated_at IS NULL;
+
+-- Open connections from a gateway to a target, optionally through bounces
+CREATE TABLE IF NOT EXISTS tunnels (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    gateway_ip INET NOT NULL,
+    target_ip INET NOT NULL,
+    bounce_ips INET[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    closed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_tunnels_open_user ON tunnels(user_id) WHERE closed_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_tunnels_open_target ON tunnels(target_ip) WHERE closed_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS ip_resets (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- The reset process. Killed processes are deleted, so this is not a
+    -- foreign key; a reset whose process is gone never completes.
+    pid BIGINT NOT NULL UNIQUE,
+    cost BIGINT NOT NULL,
+    -- Set on completion
+    old_ip INET,
+    new_ip INET,
+    charged_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_ip_resets_user ON ip_resets(user_id, completed_at DESC);
@@ -0,0 +1,77 @@
+-- Clan co-op missions
+-- Date: 2024-10-05
+--
+-- Templates with a co-op limit can be accepted by a clan member as a group
+-- mission. Clanmates join while it is recruiting; once it runs, everyone's
+-- objective progress adds up to one shared total and each member's part is
+-- kept so the reward can be split by contribution. Every co-op mission gets a
+-- chat thread for its members.
+
+-- NULL keeps a template solo-only
+ALTER TABLE missions ADD COLUMN IF NOT EXISTS coop_max_members INTEGER;
+-- Shared objective progress a co-op run needs
+ALTER TABLE missions ADD COLUMN IF NOT EXISTS coop_target INTEGER NOT NULL DEFAULT 100;
+
+CREATE TABLE IF NOT EXISTS chat_threads (
+    id BIGSERIAL PRIMARY KEY,
+    title VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Closed threads can still be read but not posted to
+    closed_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS chat_thread_members (
+    thread_id BIGINT NOT NULL REFERENCES chat_threads(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (thread_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS chat_thread_messages (
+    id BIGSERIAL PRIMARY KEY,
+    thread_id BIGINT NOT NULL REFERENCES chat_threads(id) ON DELETE CASCADE,
+    -- NULL for messages the game posts
+    user_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_chat_thread_messages_thread ON chat_thread_messages(thread_id, id);
+
+CREATE TABLE IF NOT EXISTS coop_missions (
+    id BIGSERIAL PRIMARY KEY,
+    mission_id BIGINT NOT NULL REFERENCES missions(id) ON DELETE CASCADE,
+    clan_id BIGINT NOT NULL REFERENCES clans(id) ON DELETE CASCADE,
+    leader_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    thread_id BIGINT NOT NULL REFERENCES chat_threads(id),
+    status VARCHAR(20) NOT NULL DEFAULT 'recruiting'
+        CHECK (status IN ('recruiting', 'active', 'completed', 'failed')),
+    max_members INTEGER NOT NULL,
+    progress INTEGER NOT NULL DEFAULT 0,
+    target INTEGER NOT NULL,
+    -- Taken from the template when accepted, so edits do not touch running missions
+    reward_money BIGINT NOT NULL,
+    reward_exp INTEGER NOT NULL,
+    failure_reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    ended_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_coop_missions_clan
+    ON coop_missions(clan_id) WHERE status IN ('recruiting', 'active');
+
+CREATE TABLE IF NOT EXISTS coop_mission_members (
+    coop_id BIGINT NOT NULL REFERENCES coop_missions(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    contribution INTEGER NOT NULL DEFAULT 0,
+    -- The member's cut, once the mission completed
+    reward_money BIGINT,
+    reward_exp BIGINT,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    abandoned_at TIMESTAMPTZ,
+    PRIMARY KEY (coop_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_coop_mission_members_user
+    ON coop_mission_members(user_id) WHERE abandoned_at IS NULL;
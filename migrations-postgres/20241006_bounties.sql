@@ -0,0 +1,37 @@
+-- Player-placed bounties
+-- Date: 2024-10-06
+--
+-- The reward is taken from the placer's bank account when the bounty is
+-- placed and held here until it is claimed or the window closes, when it is
+-- paid to the hunter or refunded. Claims refused by the anti-collusion checks
+-- are recorded for moderators; the bounty stays open.
+
+CREATE TABLE IF NOT EXISTS bounties (
+    id BIGSERIAL PRIMARY KEY,
+    placer_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    target_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- In cents, held in escrow while open
+    reward BIGINT NOT NULL CHECK (reward > 0),
+    status VARCHAR(20) NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'claimed', 'expired')),
+    expires_at TIMESTAMPTZ NOT NULL,
+    claimed_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- When the escrow was paid out or refunded
+    settled_at TIMESTAMPTZ,
+    CHECK (placer_id <> target_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_bounties_open_target ON bounties(target_id) WHERE status = 'open';
+CREATE INDEX IF NOT EXISTS idx_bounties_open_expiry ON bounties(expires_at) WHERE status = 'open';
+CREATE INDEX IF NOT EXISTS idx_bounties_placer ON bounties(placer_id);
+
+CREATE TABLE IF NOT EXISTS bounty_blocked_claims (
+    id BIGSERIAL PRIMARY KEY,
+    bounty_id BIGINT NOT NULL REFERENCES bounties(id) ON DELETE CASCADE,
+    hunter_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reason VARCHAR(32) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_bounty_blocked_claims_hunter ON bounty_blocked_claims(hunter_id, created_at DESC);
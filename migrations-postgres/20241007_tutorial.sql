@@ -0,0 +1,17 @@
+-- New-player tutorial progress
+-- Date: 2024-10-07
+--
+-- One row per player who started the tutorial. Steps advance when the
+-- server completes the matching process against the whois server. The
+-- reward marker survives resets so the reward is paid once per account.
+
+CREATE TABLE IF NOT EXISTS player_tutorials (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    step VARCHAR(20) NOT NULL DEFAULT 'scan_whois'
+        CHECK (step IN ('scan_whois', 'crack_whois', 'download_file', 'delete_logs', 'completed', 'skipped')),
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    rewarded_at TIMESTAMPTZ
+);
+
@@ -0,0 +1,33 @@
+-- Alert threshold overrides per environment
+-- Date: 2024-10-08
+--
+-- Set from the admin live-ops console. Metrics without a row use the
+-- environment's built-in defaults. Nodes reload their environment's rows with
+-- the rest of the live-ops state, so a change reaches every alert manager
+-- within a few seconds. Every change is kept with its old and new values.
+
+CREATE TABLE IF NOT EXISTS alert_threshold_overrides (
+    environment VARCHAR(16) NOT NULL CHECK (environment IN ('development', 'staging', 'production')),
+    metric VARCHAR(32) NOT NULL,
+    warning DOUBLE PRECISION NOT NULL,
+    critical DOUBLE PRECISION NOT NULL CHECK (warning <= critical),
+    updated_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (environment, metric)
+);
+
+-- NULL old values mean the default was in force; NULL new values mean the
+-- override was removed
+CREATE TABLE IF NOT EXISTS alert_threshold_changes (
+    id BIGSERIAL PRIMARY KEY,
+    environment VARCHAR(16) NOT NULL,
+    metric VARCHAR(32) NOT NULL,
+    old_warning DOUBLE PRECISION,
+    old_critical DOUBLE PRECISION,
+    new_warning DOUBLE PRECISION,
+    new_critical DOUBLE PRECISION,
+    changed_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_alert_threshold_changes_recent ON alert_threshold_changes(environment, changed_at DESC);
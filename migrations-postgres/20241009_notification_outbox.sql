@@ -0,0 +1,28 @@
+-- Transactional outbox for socket notifications
+-- Date: 2024-10-09
+--
+-- Notifications are written in the same transaction as the state change they
+-- describe and delivered afterwards by a relay, so a crash between commit and
+-- delivery delays them instead of losing them. The dedup key travels with the
+-- message; a notification delivered again after a relay crash carries the
+-- same key and clients drop it.
+
+CREATE TABLE IF NOT EXISTS notification_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    dedup_key VARCHAR(200) NOT NULL UNIQUE,
+    -- 'user' and 'process' push to sockets; 'event' goes to an event dispatcher
+    channel VARCHAR(16) NOT NULL CHECK (channel IN ('user', 'process', 'event')),
+    recipient BIGINT,
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    sent_at TIMESTAMPTZ,
+    failed_at TIMESTAMPTZ,
+    last_error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_outbox_due ON notification_outbox(next_attempt_at)
+    WHERE sent_at IS NULL AND failed_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_notification_outbox_sent ON notification_outbox(sent_at)
+    WHERE sent_at IS NOT NULL;
@@ -0,0 +1,49 @@
+-- Player-posted contracts
+-- Date: 2024-10-10
+--
+-- The poster pays the reward plus a posting fee when the contract goes up.
+-- The reward is held here until it is paid to the contractor or refunded;
+-- the fee is never refunded. A contract is fulfilled only by the completion
+-- of the contractor's own download or delete of the named file, recorded
+-- as proof_pid, and can be disputed by the poster until dispute_until.
+
+CREATE TABLE IF NOT EXISTS player_contracts (
+    id BIGSERIAL PRIMARY KEY,
+    poster_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('steal_file', 'delete_file')),
+    target_ip VARCHAR(45) NOT NULL,
+    -- Owner of the target server when posted; cannot take the job
+    target_owner_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- software.id as text, the form processes carry it in
+    target_file_id VARCHAR(64) NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    description TEXT,
+    -- In cents; reward is held in escrow, fee was taken out of the economy
+    reward BIGINT NOT NULL CHECK (reward > 0),
+    fee BIGINT NOT NULL CHECK (fee >= 0),
+    status VARCHAR(20) NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'accepted', 'fulfilled', 'disputed', 'paid', 'refunded', 'expired', 'cancelled')),
+    expires_at TIMESTAMPTZ NOT NULL,
+    contractor_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    accepted_at TIMESTAMPTZ,
+    -- When an accepted contract goes back on the board
+    work_deadline TIMESTAMPTZ,
+    fulfilled_at TIMESTAMPTZ,
+    proof_pid BIGINT,
+    dispute_until TIMESTAMPTZ,
+    dispute_reason TEXT,
+    disputed_at TIMESTAMPTZ,
+    resolved_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    resolution_note TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- When the escrow was paid out or refunded
+    settled_at TIMESTAMPTZ,
+    CHECK (poster_id <> target_owner_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_player_contracts_open ON player_contracts(expires_at) WHERE status = 'open';
+CREATE INDEX IF NOT EXISTS idx_player_contracts_accepted ON player_contracts(contractor_id, target_file_id)
+    WHERE status = 'accepted';
+CREATE INDEX IF NOT EXISTS idx_player_contracts_fulfilled ON player_contracts(dispute_until) WHERE status = 'fulfilled';
+CREATE INDEX IF NOT EXISTS idx_player_contracts_disputed ON player_contracts(disputed_at) WHERE status = 'disputed';
+CREATE INDEX IF NOT EXISTS idx_player_contracts_poster ON player_contracts(poster_id);
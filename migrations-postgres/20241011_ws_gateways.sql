@@ -0,0 +1,25 @@
+-- Regional WebSocket gateways
+-- Date: 2024-10-11
+--
+-- Every API node advertises the region it serves and the public WebSocket
+-- URL of that region's gateway; discovery hands players the healthy ones.
+-- Presence is kept per node so events for a player connected elsewhere can
+-- be forwarded to the right node, and the region a player last used is kept
+-- after they disconnect so reconnects stick to it.
+
+ALTER TABLE live_ops_nodes ADD COLUMN IF NOT EXISTS region VARCHAR(32) NOT NULL DEFAULT 'default';
+ALTER TABLE live_ops_nodes ADD COLUMN IF NOT EXISTS ws_url TEXT;
+
+CREATE TABLE IF NOT EXISTS ws_presence (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    node_id VARCHAR(128) NOT NULL,
+    region VARCHAR(32) NOT NULL,
+    -- Open sessions on the node; 0 once the player left, kept for affinity
+    sessions INTEGER NOT NULL DEFAULT 0 CHECK (sessions >= 0),
+    connected_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, node_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ws_presence_online ON ws_presence(node_id) WHERE sessions > 0;
+CREATE INDEX IF NOT EXISTS idx_ws_presence_last_seen ON ws_presence(last_seen_at);
@@ -0,0 +1,80 @@
+-- Referrals and sign-in history
+-- Date: 2024-10-12
+--
+-- Every player gets one referral code. A sign-up with a code is attributed
+-- to its owner; milestones pay the referrer once the referred account has
+-- reached a level and a playtime. The IPs and devices players sign in from
+-- are kept so self-referrals can be recognised; devices are stored hashed.
+
+CREATE TABLE IF NOT EXISTS referral_codes (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    code VARCHAR(16) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS referrals (
+    id BIGSERIAL PRIMARY KEY,
+    referrer_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- An account is referred at most once
+    referred_id BIGINT NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+    code VARCHAR(16) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'flagged', 'rejected', 'completed')),
+    -- Abuse signals found at sign-up or since, see he-game-mechanics
+    signals JSONB NOT NULL DEFAULT '[]',
+    signup_ip VARCHAR(45),
+    signup_network VARCHAR(64),
+    milestones_paid INTEGER NOT NULL DEFAULT 0,
+    -- In cents
+    rewarded BIGINT NOT NULL DEFAULT 0,
+    reviewed_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TIMESTAMPTZ,
+    review_note TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_referrals_referrer ON referrals(referrer_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_referrals_pending ON referrals(id) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_referrals_flagged ON referrals(created_at) WHERE status = 'flagged';
+CREATE INDEX IF NOT EXISTS idx_referrals_network ON referrals(referrer_id, signup_network, created_at);
+
+-- One row per milestone paid, so a milestone can never pay twice
+CREATE TABLE IF NOT EXISTS referral_rewards (
+    referral_id BIGINT NOT NULL REFERENCES referrals(id) ON DELETE CASCADE,
+    milestone INTEGER NOT NULL,
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    -- False if the referrer had no bank account to pay into
+    paid BOOLEAN NOT NULL DEFAULT FALSE,
+    paid_at TIMESTAMPTZ,
+    PRIMARY KEY (referral_id, milestone)
+);
+
+CREATE TABLE IF NOT EXISTS user_sign_in_ips (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ip VARCHAR(45) NOT NULL,
+    network VARCHAR(64) NOT NULL,
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, ip)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_sign_in_ips_network ON user_sign_in_ips(network);
+
+CREATE TABLE IF NOT EXISTS user_devices (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- SHA-256 of the client's device id
+    device_hash CHAR(64) NOT NULL,
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, device_hash)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_devices_hash ON user_devices(device_hash);
+
+-- Time spent connected, summed over WebSocket sessions
+CREATE TABLE IF NOT EXISTS player_playtime (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    seconds BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
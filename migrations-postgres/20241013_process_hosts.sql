@@ -0,0 +1,21 @@
+-- Processes on remote servers
+-- Date: 2024-10-13
+--
+-- A process launched on a server other than the player's gateway records its
+-- host here with the resources it holds there. Rows outlive their process;
+-- only processes still running count against the host.
+
+CREATE TABLE IF NOT EXISTS process_hosts (
+    -- Killed processes are deleted, so this is not a foreign key
+    pid BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    server_id BIGINT NOT NULL REFERENCES servers(id) ON DELETE CASCADE,
+    access VARCHAR(10) NOT NULL CHECK (access IN ('owned', 'hacked')),
+    cpu_usage INTEGER NOT NULL DEFAULT 0,
+    ram_usage INTEGER NOT NULL DEFAULT 0,
+    net_usage INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_process_hosts_server ON process_hosts(server_id);
+CREATE INDEX IF NOT EXISTS idx_process_hosts_user ON process_hosts(user_id);
@@ -0,0 +1,148 @@
+-- Double-entry money ledger
+-- Date: 2024-10-14
+--
+-- Every money movement is a ledger transaction with a reason code and two or
+-- more entries whose amounts sum to zero. Accounts are text keys: a bank
+-- account is 'bank:<id>'; money entering or leaving the game goes through
+-- 'system:mint' and 'system:sink', and money held for bounties and contracts
+-- sits in 'escrow:bounties' and 'escrow:contracts'. A positive amount moves
+-- money into the account, in cents.
+--
+-- bank_accounts.balance stays as a cache of its account's entries, updated
+-- in the same transaction. The reconciler records accounts where the two
+-- disagree in ledger_discrepancies. Both ledger tables are append-only.
+
+CREATE TABLE IF NOT EXISTS ledger_transactions (
+    id BIGSERIAL PRIMARY KEY,
+    reason VARCHAR(32) NOT NULL,
+    -- What the money moved for, e.g. 'bounty:12'
+    reference TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ledger_transactions_reason ON ledger_transactions(reason, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_ledger_transactions_reference ON ledger_transactions(reference) WHERE reference <> '';
+CREATE INDEX IF NOT EXISTS idx_ledger_transactions_created ON ledger_transactions(created_at DESC);
+
+CREATE TABLE IF NOT EXISTS ledger_entries (
+    id BIGSERIAL PRIMARY KEY,
+    transaction_id BIGINT NOT NULL REFERENCES ledger_transactions(id),
+    account VARCHAR(64) NOT NULL,
+    -- Not a foreign key: entries outlive closed accounts
+    bank_account_id BIGINT GENERATED ALWAYS AS (
+        CASE WHEN account LIKE 'bank:%' THEN substring(account FROM 6)::BIGINT END
+    ) STORED,
+    amount BIGINT NOT NULL CHECK (amount <> 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ledger_entries_transaction ON ledger_entries(transaction_id);
+CREATE INDEX IF NOT EXISTS idx_ledger_entries_account ON ledger_entries(account, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_ledger_entries_bank ON ledger_entries(bank_account_id) WHERE bank_account_id IS NOT NULL;
+
+CREATE OR REPLACE FUNCTION ledger_append_only()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION '% is append-only', TG_TABLE_NAME;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS ledger_transactions_append_only ON ledger_transactions;
+CREATE TRIGGER ledger_transactions_append_only BEFORE UPDATE OR DELETE
+    ON ledger_transactions FOR EACH ROW EXECUTE FUNCTION ledger_append_only();
+DROP TRIGGER IF EXISTS ledger_transactions_no_truncate ON ledger_transactions;
+CREATE TRIGGER ledger_transactions_no_truncate BEFORE TRUNCATE
+    ON ledger_transactions FOR EACH STATEMENT EXECUTE FUNCTION ledger_append_only();
+
+DROP TRIGGER IF EXISTS ledger_entries_append_only ON ledger_entries;
+CREATE TRIGGER ledger_entries_append_only BEFORE UPDATE OR DELETE
+    ON ledger_entries FOR EACH ROW EXECUTE FUNCTION ledger_append_only();
+DROP TRIGGER IF EXISTS ledger_entries_no_truncate ON ledger_entries;
+CREATE TRIGGER ledger_entries_no_truncate BEFORE TRUNCATE
+    ON ledger_entries FOR EACH STATEMENT EXECUTE FUNCTION ledger_append_only();
+
+-- Checked at commit, once every entry of the transaction is in
+CREATE OR REPLACE FUNCTION ledger_check_balanced()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF (SELECT SUM(amount) FROM ledger_entries WHERE transaction_id = NEW.transaction_id) <> 0 THEN
+        RAISE EXCEPTION 'ledger transaction % does not balance', NEW.transaction_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS ledger_entries_balanced ON ledger_entries;
+CREATE CONSTRAINT TRIGGER ledger_entries_balanced AFTER INSERT
+    ON ledger_entries DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE FUNCTION ledger_check_balanced();
+
+-- An account opened with money in it gets that money from 'system:opening'
+CREATE OR REPLACE FUNCTION ledger_open_account()
+RETURNS TRIGGER AS $$
+DECLARE
+    opening BIGINT;
+BEGIN
+    IF NEW.balance = 0 THEN
+        RETURN NEW;
+    END IF;
+
+    INSERT INTO ledger_transactions (reason, reference)
+    VALUES ('opening_balance', 'bank_account:' || NEW.id)
+    RETURNING id INTO opening;
+
+    INSERT INTO ledger_entries (transaction_id, account, amount)
+    VALUES (opening, 'bank:' || NEW.id, NEW.balance), (opening, 'system:opening', -NEW.balance);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS ledger_open_account ON bank_accounts;
+CREATE TRIGGER ledger_open_account AFTER INSERT
+    ON bank_accounts FOR EACH ROW EXECUTE FUNCTION ledger_open_account();
+
+-- Existing balances open the ledger in one transaction
+DO $$
+DECLARE
+    opening BIGINT;
+BEGIN
+    IF EXISTS (SELECT 1 FROM ledger_transactions WHERE reason = 'opening_balance' AND reference = 'backfill') THEN
+        RETURN;
+    END IF;
+    IF NOT EXISTS (SELECT 1 FROM bank_accounts WHERE balance <> 0) THEN
+        RETURN;
+    END IF;
+
+    INSERT INTO ledger_transactions (reason, reference)
+    VALUES ('opening_balance', 'backfill')
+    RETURNING id INTO opening;
+
+    INSERT INTO ledger_entries (transaction_id, account, amount)
+    SELECT opening, 'bank:' || id, balance FROM bank_accounts WHERE balance <> 0;
+
+    INSERT INTO ledger_entries (transaction_id, account, amount)
+    SELECT opening, 'system:opening', -SUM(balance) FROM bank_accounts HAVING SUM(balance) <> 0;
+END;
+$$;
+
+-- Bank accounts whose cached balance disagrees with the ledger, one open
+-- row per account
+CREATE TABLE IF NOT EXISTS ledger_discrepancies (
+    id BIGSERIAL PRIMARY KEY,
+    bank_account_id BIGINT NOT NULL,
+    user_id BIGINT,
+    balance BIGINT NOT NULL,
+    ledger_balance BIGINT NOT NULL,
+    found_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- 'restored' reset the balance from the ledger, 'adjusted' posted the
+    -- difference to the ledger, 'cleared' means the two agree again
+    resolution VARCHAR(10) CHECK (resolution IN ('restored', 'adjusted', 'cleared')),
+    resolved_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    resolution_note TEXT,
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_ledger_discrepancies_open
+    ON ledger_discrepancies(bank_account_id) WHERE resolved_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_ledger_discrepancies_found ON ledger_discrepancies(found_at DESC);
@@ -0,0 +1,67 @@
+-- Player reports and the moderation queue
+-- Date: 2024-10-15
+--
+-- Reports of the same content join one case. The case keeps the evidence
+-- snapshotted when it opened and is what moderators resolve. Moderator
+-- actions against players are recorded in moderation_actions, which a case
+-- links to when it is resolved by one.
+
+CREATE TABLE IF NOT EXISTS moderation_actions (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    moderator_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    action VARCHAR(20) NOT NULL CHECK (action IN ('warn', 'remove_content', 'ban')),
+    reason TEXT NOT NULL,
+    -- Bans only; NULL for a permanent one
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_moderation_actions_user ON moderation_actions(user_id, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS report_cases (
+    id BIGSERIAL PRIMARY KEY,
+    target VARCHAR(20) NOT NULL
+        CHECK (target IN ('chat_message', 'username', 'server_page', 'clan_name')),
+    -- Message id, user id, server IP or clan id
+    target_key VARCHAR(64) NOT NULL,
+    -- Hash of the normalized content, so edited content is a new case
+    fingerprint CHAR(64) NOT NULL,
+    -- The player responsible for the content
+    subject_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    evidence JSONB NOT NULL,
+    -- The most severe category reported
+    category VARCHAR(20) NOT NULL,
+    priority INTEGER NOT NULL,
+    reporters INTEGER NOT NULL DEFAULT 1,
+    status VARCHAR(10) NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'actioned', 'dismissed')),
+    sla_due_at TIMESTAMPTZ NOT NULL,
+    resolved_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    action VARCHAR(20),
+    moderation_action_id BIGINT REFERENCES moderation_actions(id) ON DELETE SET NULL,
+    resolution_note TEXT,
+    resolved_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One open case per piece of content
+CREATE UNIQUE INDEX IF NOT EXISTS idx_report_cases_open
+    ON report_cases(target, target_key, fingerprint) WHERE status = 'open';
+CREATE INDEX IF NOT EXISTS idx_report_cases_queue ON report_cases(priority DESC, created_at) WHERE status = 'open';
+CREATE INDEX IF NOT EXISTS idx_report_cases_content ON report_cases(target, target_key, fingerprint, resolved_at DESC);
+CREATE INDEX IF NOT EXISTS idx_report_cases_resolved ON report_cases(resolved_at DESC) WHERE resolved_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_report_cases_subject ON report_cases(subject_id, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS reports (
+    id BIGSERIAL PRIMARY KEY,
+    case_id BIGINT NOT NULL REFERENCES report_cases(id) ON DELETE CASCADE,
+    -- NULL for reports the game files itself
+    reporter_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    category VARCHAR(20) NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A player reports the same case once
+CREATE UNIQUE INDEX IF NOT EXISTS idx_reports_reporter ON reports(case_id, reporter_id) WHERE reporter_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_reports_by_reporter ON reports(reporter_id, created_at DESC);
@@ -0,0 +1,46 @@
+-- Premium subscriptions and payment provider webhooks
+-- Date: 2024-10-16
+--
+-- Subscriptions mirror the payment provider's, updated from its webhooks.
+-- access_until is when premium from the subscription ends; he-cron takes the
+-- premium_player role away from players whose access has run out.
+
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    provider VARCHAR(20) NOT NULL DEFAULT 'stripe',
+    provider_subscription_id VARCHAR(64) NOT NULL,
+    provider_customer_id VARCHAR(64),
+    plan VARCHAR(64) NOT NULL,
+    status VARCHAR(10) NOT NULL
+        CHECK (status IN ('pending', 'active', 'past_due', 'canceled', 'expired')),
+    current_period_start TIMESTAMPTZ NOT NULL,
+    current_period_end TIMESTAMPTZ NOT NULL,
+    -- Period end plus any grace; NULL when the subscription gives no premium
+    access_until TIMESTAMPTZ,
+    -- Whether this subscription is why the player holds the premium role
+    role_granted BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Creation time of the last provider event applied, to skip older ones
+    last_event_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, provider_subscription_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscriptions_user ON subscriptions(user_id, access_until DESC);
+CREATE INDEX IF NOT EXISTS idx_subscriptions_lapsing ON subscriptions(access_until) WHERE role_granted;
+
+-- Every webhook received, so redeliveries are applied once
+CREATE TABLE IF NOT EXISTS payment_webhook_events (
+    provider VARCHAR(20) NOT NULL,
+    event_id VARCHAR(64) NOT NULL,
+    event_type VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    processed_at TIMESTAMPTZ,
+    error TEXT,
+    PRIMARY KEY (provider, event_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_payment_webhook_events_failed
+    ON payment_webhook_events(received_at DESC) WHERE error IS NOT NULL;
@@ -0,0 +1,96 @@
+-- NPC bank network, inter-bank transfers and stolen money
+-- Date: 2024-10-17
+--
+-- Every bank account belongs to an NPC bank; accounts from before the
+-- network, and accounts opened without naming a bank, are at the first one.
+-- A bank runs on an NPC server, and players who hacked that server can move
+-- money out of its accounts. A bank without a server cannot be robbed.
+--
+-- bank_transfers logs every player transfer. Money a theft took is tracked
+-- in stolen_funds as lots per theft and holding account; stolen_fund_moves
+-- records which transfers carried it, so the victim can follow it until it
+-- is laundered or spent.
+
+CREATE TABLE IF NOT EXISTS banks (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    server_id BIGINT REFERENCES servers(id) ON DELETE SET NULL,
+    security_tier SMALLINT NOT NULL CHECK (security_tier BETWEEN 1 AND 3),
+    -- Charged by the sending bank on transfers to other banks, in
+    -- hundredths of a percent
+    transfer_fee_bps INTEGER NOT NULL CHECK (transfer_fee_bps BETWEEN 0 AND 10000),
+    min_transfer_fee BIGINT NOT NULL DEFAULT 0 CHECK (min_transfer_fee >= 0),
+    -- NULL when the bank does not launder
+    laundering_fee_bps INTEGER CHECK (laundering_fee_bps BETWEEN 0 AND 10000),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO banks (name, security_tier, transfer_fee_bps, min_transfer_fee, laundering_fee_bps) VALUES
+    ('First Whois Bank', 1, 50, 100, NULL),
+    ('Secure Trust Savings', 2, 100, 500, NULL),
+    ('Zurich Vault', 3, 200, 2500, NULL),
+    ('Cayman Offshore', 1, 300, 1000, 2500)
+ON CONFLICT (name) DO NOTHING;
+
+-- Accounts opened without naming a bank go to the first one
+CREATE OR REPLACE FUNCTION default_bank_id()
+RETURNS BIGINT AS $$
+    SELECT id FROM banks ORDER BY id LIMIT 1;
+$$ LANGUAGE sql STABLE;
+
+ALTER TABLE bank_accounts ADD COLUMN IF NOT EXISTS bank_id BIGINT REFERENCES banks(id);
+UPDATE bank_accounts SET bank_id = default_bank_id() WHERE bank_id IS NULL;
+ALTER TABLE bank_accounts ALTER COLUMN bank_id SET DEFAULT default_bank_id();
+ALTER TABLE bank_accounts ALTER COLUMN bank_id SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_bank_accounts_bank ON bank_accounts(bank_id, user_id);
+
+CREATE TABLE IF NOT EXISTS bank_transfers (
+    id BIGSERIAL PRIMARY KEY,
+    -- 'transfer' between accounts, 'theft' out of someone else's account,
+    -- 'laundering' of an account's stolen money through a bank
+    kind VARCHAR(10) NOT NULL CHECK (kind IN ('transfer', 'theft', 'laundering')),
+    initiator_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    -- Gateway the transfer was made from, kept only by banks that log it
+    initiator_ip INET,
+    from_account_id BIGINT NOT NULL REFERENCES bank_accounts(id),
+    from_user_id BIGINT NOT NULL,
+    -- The laundering bank for laundering, where the money comes back
+    -- to the same account
+    to_account_id BIGINT NOT NULL REFERENCES bank_accounts(id),
+    to_user_id BIGINT NOT NULL,
+    via_bank_id BIGINT REFERENCES banks(id),
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    fee BIGINT NOT NULL DEFAULT 0 CHECK (fee >= 0),
+    -- Part of the amount that was stolen money
+    stolen BIGINT NOT NULL DEFAULT 0 CHECK (stolen >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_bank_transfers_from ON bank_transfers(from_account_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_bank_transfers_to ON bank_transfers(to_account_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_bank_transfers_thefts
+    ON bank_transfers(from_user_id, created_at DESC) WHERE kind = 'theft';
+CREATE INDEX IF NOT EXISTS idx_bank_transfers_thief
+    ON bank_transfers(initiator_id, from_account_id, created_at DESC) WHERE kind = 'theft';
+
+-- Stolen money an account holds, per theft
+CREATE TABLE IF NOT EXISTS stolen_funds (
+    theft_id BIGINT NOT NULL REFERENCES bank_transfers(id),
+    account_id BIGINT NOT NULL REFERENCES bank_accounts(id),
+    amount BIGINT NOT NULL CHECK (amount >= 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (theft_id, account_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stolen_funds_account ON stolen_funds(account_id) WHERE amount > 0;
+
+-- How much of each theft a transfer carried
+CREATE TABLE IF NOT EXISTS stolen_fund_moves (
+    transfer_id BIGINT NOT NULL REFERENCES bank_transfers(id),
+    theft_id BIGINT NOT NULL REFERENCES bank_transfers(id),
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    PRIMARY KEY (transfer_id, theft_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stolen_fund_moves_theft ON stolen_fund_moves(theft_id);
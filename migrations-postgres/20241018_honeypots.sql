@@ -0,0 +1,40 @@
+-- Honeypot servers, the trips players make into them and traced attackers
+-- Date: 2024-10-18
+--
+-- A honeypot is an NPC server that pays nothing out. Every remote process
+-- that completes against one is logged in honeypot_trips with the route it
+-- really came through: the player's gateway, the bounces of their open
+-- tunnel and the server the process ran on. The player is then traced until
+-- traced_players.traced_until, and the servers they touch meanwhile log
+-- their gateway instead of the address they connected from.
+
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS is_honeypot BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_servers_honeypot ON servers(ip_address) WHERE is_honeypot;
+
+CREATE TABLE IF NOT EXISTS honeypot_trips (
+    id BIGSERIAL PRIMARY KEY,
+    server_id BIGINT NOT NULL REFERENCES servers(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- The process that tripped it. Completed processes may be purged, so
+    -- this is not a foreign key.
+    pid BIGINT NOT NULL UNIQUE,
+    process_type VARCHAR(50) NOT NULL,
+    gateway_ip INET,
+    bounce_ips INET[] NOT NULL DEFAULT '{}',
+    host_ip INET,
+    target_ip INET NOT NULL,
+    -- Enough trips in a short time to look scripted
+    anomalous BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_honeypot_trips_user ON honeypot_trips(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_honeypot_trips_anomalous ON honeypot_trips(id DESC) WHERE anomalous;
+
+CREATE TABLE IF NOT EXISTS traced_players (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    traced_until TIMESTAMPTZ NOT NULL,
+    last_trip_id BIGINT NOT NULL REFERENCES honeypot_trips(id) ON DELETE CASCADE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,92 @@
+-- Clan servers, the clan treasury and wars fought over the servers
+-- Date: 2024-10-19
+--
+-- A clan's server is a row in servers like any other, so its software lives
+-- in the software table. It is marked is_npc so the lookups that find a
+-- player's own servers skip it; clan_servers ties it to the clan and keeps
+-- the upgrade level of each part, from which servers.*_total is set.
+--
+-- The treasury is the ledger account 'clan:<id>'. clans.treasury caches its
+-- balance the way bank_accounts.balance does, updated in the same
+-- transaction as the entries.
+
+ALTER TABLE clans ADD COLUMN IF NOT EXISTS treasury BIGINT NOT NULL DEFAULT 0 CHECK (treasury >= 0);
+
+CREATE TABLE IF NOT EXISTS clan_servers (
+    clan_id BIGINT PRIMARY KEY REFERENCES clans(id) ON DELETE CASCADE,
+    server_id BIGINT NOT NULL UNIQUE REFERENCES servers(id) ON DELETE CASCADE,
+    cpu_level INTEGER NOT NULL DEFAULT 0 CHECK (cpu_level >= 0),
+    ram_level INTEGER NOT NULL DEFAULT 0 CHECK (ram_level >= 0),
+    hdd_level INTEGER NOT NULL DEFAULT 0 CHECK (hdd_level >= 0),
+    net_level INTEGER NOT NULL DEFAULT 0 CHECK (net_level >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS clan_server_upgrades (
+    id BIGSERIAL PRIMARY KEY,
+    clan_id BIGINT NOT NULL REFERENCES clans(id) ON DELETE CASCADE,
+    part VARCHAR(8) NOT NULL CHECK (part IN ('cpu', 'ram', 'hdd', 'net')),
+    -- The level bought
+    level INTEGER NOT NULL,
+    cost BIGINT NOT NULL,
+    bought_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_clan_server_upgrades_clan ON clan_server_upgrades(clan_id, id DESC);
+
+-- Who stored each piece of software on a clan server
+CREATE TABLE IF NOT EXISTS clan_server_files (
+    software_id BIGINT PRIMARY KEY REFERENCES software(id) ON DELETE CASCADE,
+    clan_id BIGINT NOT NULL REFERENCES clans(id) ON DELETE CASCADE,
+    uploaded_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_clan_server_files_clan ON clan_server_files(clan_id);
+
+-- Firewalls members lent the clan server, as the version they had when they
+-- lent it. Only current members count.
+CREATE TABLE IF NOT EXISTS clan_server_defenders (
+    clan_id BIGINT NOT NULL REFERENCES clans(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    firewall_version DOUBLE PRECISION NOT NULL CHECK (firewall_version > 0),
+    lent_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (clan_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS clan_wars (
+    id BIGSERIAL PRIMARY KEY,
+    attacker_clan_id BIGINT NOT NULL REFERENCES clans(id) ON DELETE CASCADE,
+    defender_clan_id BIGINT NOT NULL REFERENCES clans(id) ON DELETE CASCADE CHECK (defender_clan_id <> attacker_clan_id),
+    status VARCHAR(16) NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'ended')),
+    attacker_score BIGINT NOT NULL DEFAULT 0,
+    defender_score BIGINT NOT NULL DEFAULT 0,
+    -- Integrity of each side's clan server in this war
+    attacker_integrity INTEGER NOT NULL,
+    defender_integrity INTEGER NOT NULL,
+    winner_clan_id BIGINT REFERENCES clans(id) ON DELETE SET NULL,
+    declared_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ends_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ
+);
+
+-- One running war per pair of clans, whichever side declared it
+CREATE UNIQUE INDEX IF NOT EXISTS idx_clan_wars_active_pair ON clan_wars(
+    LEAST(attacker_clan_id, defender_clan_id), GREATEST(attacker_clan_id, defender_clan_id)
+) WHERE status = 'active';
+CREATE INDEX IF NOT EXISTS idx_clan_wars_attacker ON clan_wars(attacker_clan_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_clan_wars_defender ON clan_wars(defender_clan_id, id DESC);
+
+CREATE TABLE IF NOT EXISTS clan_war_strikes (
+    id BIGSERIAL PRIMARY KEY,
+    war_id BIGINT NOT NULL REFERENCES clan_wars(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    side VARCHAR(16) NOT NULL CHECK (side IN ('attacker', 'defender')),
+    damage INTEGER NOT NULL,
+    points BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_clan_war_strikes_user ON clan_war_strikes(war_id, user_id, created_at DESC);
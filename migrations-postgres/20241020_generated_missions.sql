@@ -0,0 +1,56 @@
+-- Procedurally generated missions and the log of how each was generated
+-- Date: 2024-10-20
+--
+-- The missions table holds the hand-written templates. Generated missions are
+-- built per player from the NPC servers in the world and are offered,
+-- accepted and finished here; their objectives are a graph stored as JSON
+-- (he_game_mechanics::mission_gen::ObjectiveGraph) with the nodes done so far
+-- in completed_nodes.
+--
+-- Every generation writes a log row with the inputs and every attempt,
+-- including the ones thrown away, so an odd offer or a player who is offered
+-- nothing can be explained. Generation is seeded, so a row can be replayed.
+
+CREATE TABLE IF NOT EXISTS mission_generation_log (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    seed BIGINT NOT NULL,
+    player_level INTEGER NOT NULL,
+    cracker_version DOUBLE PRECISION NOT NULL,
+    -- The recent missions variety was enforced against
+    recent JSONB NOT NULL DEFAULT '[]',
+    -- he_game_mechanics::mission_gen::Attempt, in order
+    attempts JSONB NOT NULL DEFAULT '[]',
+    outcome VARCHAR(16) NOT NULL CHECK (outcome IN ('generated', 'rejected')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_mission_generation_log_user ON mission_generation_log(user_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_mission_generation_log_rejected ON mission_generation_log(id DESC)
+    WHERE outcome = 'rejected';
+
+CREATE TABLE IF NOT EXISTS generated_missions (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    generation_id BIGINT NOT NULL REFERENCES mission_generation_log(id) ON DELETE CASCADE,
+    kind VARCHAR(16) NOT NULL CHECK (kind IN ('data_theft', 'sabotage', 'heist')),
+    tier VARCHAR(8) NOT NULL CHECK (tier IN ('easy', 'medium', 'hard', 'expert')),
+    -- Kept when the server goes so the mission can still be shown
+    target_server_id BIGINT REFERENCES servers(id) ON DELETE SET NULL,
+    target_ip VARCHAR(45) NOT NULL,
+    objectives JSONB NOT NULL,
+    completed_nodes INTEGER[] NOT NULL DEFAULT '{}',
+    reward_money BIGINT NOT NULL CHECK (reward_money >= 0),
+    bonus_money BIGINT NOT NULL DEFAULT 0 CHECK (bonus_money >= 0),
+    reward_exp BIGINT NOT NULL CHECK (reward_exp >= 0),
+    status VARCHAR(16) NOT NULL DEFAULT 'offered'
+        CHECK (status IN ('offered', 'active', 'completed', 'abandoned', 'expired')),
+    offered_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    accepted_at TIMESTAMPTZ,
+    ended_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_generated_missions_user ON generated_missions(user_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_generated_missions_open ON generated_missions(user_id, status)
+    WHERE status IN ('offered', 'active');
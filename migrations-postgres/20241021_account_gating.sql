@@ -0,0 +1,21 @@
+-- Staff overrides of new-account restrictions
+-- Date: 2024-10-21
+--
+-- New accounts are restricted until they pass a level and an account age
+-- (he_game_mechanics::account_gating); nothing is stored for that, the
+-- restrictions are worked out from users.created_at and the player's level
+-- each time. Staff can verify an account, lifting every restriction, or
+-- exempt it from single ones.
+
+CREATE TABLE IF NOT EXISTS account_gating_overrides (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    verified BOOLEAN NOT NULL DEFAULT FALSE,
+    -- he_game_mechanics::account_gating::Restriction names
+    exempt TEXT[] NOT NULL DEFAULT '{}',
+    note TEXT,
+    set_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Restricted chat is limited by how much the player posted recently
+CREATE INDEX IF NOT EXISTS idx_chat_thread_messages_user ON chat_thread_messages(user_id, created_at DESC);
@@ -0,0 +1,27 @@
+-- Cancelled processes
+-- Date: 2024-10-22
+--
+-- Cancelling a process deletes it, like before, inside one transaction that
+-- also refunds part of what it cost and writes logs on the gateway and the
+-- target. This table keeps what the cancellation settled to, and the host
+-- resources it gave back, after the process row is gone.
+
+CREATE TABLE IF NOT EXISTS process_cancellations (
+    -- Not a foreign key: the process is deleted
+    pid BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    process_type VARCHAR(50) NOT NULL,
+    target_ip VARCHAR(45),
+    -- Set when the process ran on a server other than the gateway
+    host_server_id BIGINT REFERENCES servers(id) ON DELETE SET NULL,
+    cpu_released INTEGER NOT NULL DEFAULT 0,
+    ram_released INTEGER NOT NULL DEFAULT 0,
+    net_released INTEGER NOT NULL DEFAULT 0,
+    progress DOUBLE PRECISION NOT NULL CHECK (progress BETWEEN 0 AND 1),
+    -- In cents
+    cost BIGINT NOT NULL DEFAULT 0,
+    refund BIGINT NOT NULL DEFAULT 0 CHECK (refund >= 0 AND refund <= cost),
+    cancelled_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_process_cancellations_user ON process_cancellations(user_id, cancelled_at DESC);
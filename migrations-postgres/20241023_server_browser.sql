@@ -0,0 +1,38 @@
+-- Server browser, range scans and the player's server database
+-- Date: 2024-10-23
+--
+-- NPC servers get a type, tier and region to browse by. Servers a player
+-- found through a range scan go in known_servers; a port or system scan that
+-- completes against one also stores what it found there, which the browser
+-- then shows instead of an estimate. browser_requests is only read for rate
+-- limits and can be pruned after an hour.
+
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS npc_type VARCHAR(20);
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS npc_tier SMALLINT;
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS region VARCHAR(20);
+
+CREATE INDEX IF NOT EXISTS idx_servers_npc_browse ON servers(npc_tier, npc_type, region, id) WHERE is_npc;
+
+CREATE TABLE IF NOT EXISTS known_servers (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    server_id BIGINT NOT NULL REFERENCES servers(id) ON DELETE CASCADE,
+    discovered_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Set by the player's last completed scan, with what it found
+    scanned_at TIMESTAMPTZ,
+    firewall DOUBLE PRECISION,
+    cpu INTEGER,
+    net INTEGER,
+    PRIMARY KEY (user_id, server_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_known_servers_user ON known_servers(user_id, discovered_at DESC);
+
+CREATE TABLE IF NOT EXISTS browser_requests (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- 'browse' or 'scan_range'
+    kind VARCHAR(20) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_browser_requests_user ON browser_requests(user_id, kind, created_at DESC);
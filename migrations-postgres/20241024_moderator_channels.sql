@@ -0,0 +1,22 @@
+-- Moderator socket channels and chat flags
+-- Date: 2024-10-24
+--
+-- Report cases and chat flags are pushed to moderators on the `mod.reports`
+-- and `mod.chat-flags` socket channels, through the outbox like every other
+-- notification. Chat messages matching the offensive-terms list are posted
+-- as written and flagged here for a moderator to look at.
+
+ALTER TABLE notification_outbox DROP CONSTRAINT IF EXISTS notification_outbox_channel_check;
+ALTER TABLE notification_outbox ADD CONSTRAINT notification_outbox_channel_check
+    CHECK (channel IN ('user', 'process', 'event', 'mod.reports', 'mod.chat-flags'));
+
+CREATE TABLE IF NOT EXISTS chat_flags (
+    id BIGSERIAL PRIMARY KEY,
+    message_id BIGINT NOT NULL REFERENCES chat_thread_messages(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    terms TEXT[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (message_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_chat_flags_user ON chat_flags(user_id, created_at DESC);
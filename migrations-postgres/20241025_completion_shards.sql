@@ -0,0 +1,14 @@
+-- Completion shard fencing
+-- Date: 2024-10-25
+--
+-- Nodes lease process completion shards through Redis, each lease with a
+-- fencing token that grows with every change of owner. A completion writes
+-- its lease's token here in the transaction that claims the process, and is
+-- refused if a newer token is already recorded, so a node that lost a shard
+-- without noticing cannot complete anything in it.
+
+CREATE TABLE IF NOT EXISTS completion_shards (
+    shard INTEGER PRIMARY KEY,
+    fence BIGINT NOT NULL,
+    fenced_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
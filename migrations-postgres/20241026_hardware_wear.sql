@@ -0,0 +1,44 @@
+-- Hardware wear, failures and repairs
+-- Date: 2024-10-26
+--
+-- Each component of a player's hardware has a quality tier and wear from
+-- 0 to 1, with failed_at set while it runs degraded. warned_level is the
+-- worst condition the player was told about, so each warning is sent once.
+-- process_loads keeps what a process started on the player's own hardware
+-- uses until it completes, which is how sustained load adds up.
+
+CREATE TABLE IF NOT EXISTS hardware_components (
+    hardware_id BIGINT NOT NULL,
+    component VARCHAR(8) NOT NULL CHECK (component IN ('cpu', 'ram', 'hdd', 'net')),
+    quality VARCHAR(12) NOT NULL DEFAULT 'standard' CHECK (quality IN ('standard', 'premium', 'industrial')),
+    wear DOUBLE PRECISION NOT NULL DEFAULT 0 CHECK (wear >= 0 AND wear <= 1),
+    failed_at TIMESTAMPTZ,
+    warned_level SMALLINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (hardware_id, component)
+);
+
+CREATE TABLE IF NOT EXISTS process_loads (
+    pid BIGINT PRIMARY KEY REFERENCES processes(pid) ON DELETE CASCADE,
+    hardware_id BIGINT NOT NULL,
+    cpu_percent INTEGER NOT NULL,
+    ram_mb INTEGER NOT NULL,
+    hdd_mb INTEGER NOT NULL,
+    net_kbps INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_process_loads_hardware ON process_loads(hardware_id);
+
+CREATE TABLE IF NOT EXISTS hardware_repairs (
+    pid BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    hardware_id BIGINT NOT NULL,
+    component VARCHAR(8) NOT NULL,
+    quality VARCHAR(12) NOT NULL,
+    cost BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_hardware_repairs_open
+    ON hardware_repairs(hardware_id, component) WHERE completed_at IS NULL;
@@ -0,0 +1,102 @@
+-- Outbound clan and player webhooks
+-- Date: 2024-10-27
+--
+-- Clans and players register URLs to receive chosen events. Emitting an event
+-- queues one delivery per enabled webhook of its owner subscribed to it, in
+-- the transaction that caused it; a worker posts them, signed with the
+-- webhook's secret, and retries failures with backoff. Deliveries are the
+-- log shown to the webhook's owner.
+--
+-- Members join clans from several places, some of them legacy pages, so
+-- member_joined is emitted by a trigger on clan_members. War declarations and
+-- big hacks are emitted by the API.
+
+CREATE TABLE IF NOT EXISTS webhooks (
+    id BIGSERIAL PRIMARY KEY,
+    owner_kind VARCHAR(8) NOT NULL CHECK (owner_kind IN ('clan', 'player')),
+    -- A clan id or a user id
+    owner_id BIGINT NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    created_by BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- Failed attempts since the last success, across events
+    consecutive_failures INT NOT NULL DEFAULT 0,
+    disabled_at TIMESTAMPTZ,
+    disabled_reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhooks_owner ON webhooks(owner_kind, owner_id);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    webhook_id BIGINT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    -- Unique per event and stable across retries of the emitting transaction
+    dedup_key TEXT NOT NULL,
+    event VARCHAR(32) NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(12) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'delivered', 'failed')),
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    response_status INT,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ,
+    UNIQUE (webhook_id, dedup_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due
+    ON webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_log
+    ON webhook_deliveries(webhook_id, id DESC);
+
+CREATE OR REPLACE FUNCTION webhook_emit(
+    emit_owner_kind VARCHAR,
+    emit_owner_id BIGINT,
+    emit_event VARCHAR,
+    emit_dedup_key TEXT,
+    emit_payload JSONB
+)
+RETURNS INT AS $$
+DECLARE
+    queued INT;
+BEGIN
+    INSERT INTO webhook_deliveries (webhook_id, dedup_key, event, payload)
+    SELECT id, emit_dedup_key, emit_event, emit_payload
+    FROM webhooks
+    WHERE owner_kind = emit_owner_kind
+      AND owner_id = emit_owner_id
+      AND enabled
+      AND emit_event = ANY(events)
+    ON CONFLICT (webhook_id, dedup_key) DO NOTHING;
+    GET DIAGNOSTICS queued = ROW_COUNT;
+    RETURN queued;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION webhook_clan_member_joined()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM webhook_emit(
+        'clan',
+        NEW.clan_id,
+        'member_joined',
+        'clan:' || NEW.clan_id || ':member:' || NEW.user_id || ':' || extract(epoch FROM NEW.joined_at)::BIGINT,
+        jsonb_build_object(
+            'clan_id', NEW.clan_id,
+            'user_id', NEW.user_id,
+            'username', (SELECT login FROM users WHERE id = NEW.user_id),
+            'role', NEW.role,
+            'joined_at', NEW.joined_at
+        )
+    );
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS webhook_clan_member_joined ON clan_members;
+CREATE TRIGGER webhook_clan_member_joined AFTER INSERT
+    ON clan_members FOR EACH ROW EXECUTE FUNCTION webhook_clan_member_joined();
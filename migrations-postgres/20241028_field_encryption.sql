@@ -0,0 +1,77 @@
+-- Encrypted account fields and key rotation
+-- Date: 2024-10-28
+--
+-- Emails and IP addresses of accounts are sealed with the field encryption
+-- keyring: `enc:v<version>:...` values, decrypted by the account queries.
+-- The key version is kept next to each sealed value so rows still under an
+-- old key are cheap to find. Emails are looked up by email_index, a keyed
+-- hash of the lowercased address. Rows written before this stay plaintext
+-- until the re-encryption job seals them.
+--
+-- Code reading users.email or users.last_ip directly sees sealed values and
+-- must go through UserQueries instead.
+--
+-- Key material never reaches the database. encryption_keys registers each
+-- version by fingerprint and says which one new values are sealed under;
+-- encryption_nodes is where every API node reports the versions it holds, so
+-- a rotation can check all of them hold the new key before it starts.
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_ip TEXT;
+ALTER TABLE users ALTER COLUMN email TYPE TEXT;
+ALTER TABLE users ALTER COLUMN last_ip TYPE TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_index TEXT;
+-- NULL while the value is plaintext
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_key_version INT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_ip_key_version INT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_email_index ON users(email_index) WHERE email_index IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_users_email_key_version ON users(email_key_version);
+
+CREATE TABLE IF NOT EXISTS user_ip_history (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ip TEXT NOT NULL,
+    -- Keyed hash of the address, to find accounts sharing one
+    ip_index TEXT,
+    key_version INT,
+    seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_ip_history_user ON user_ip_history(user_id, seen_at DESC);
+CREATE INDEX IF NOT EXISTS idx_user_ip_history_index ON user_ip_history(ip_index);
+CREATE INDEX IF NOT EXISTS idx_user_ip_history_key_version ON user_ip_history(key_version);
+
+CREATE TABLE IF NOT EXISTS encryption_keys (
+    version INT PRIMARY KEY,
+    fingerprint TEXT NOT NULL,
+    -- One active version at a time; retiring ones may still seal some rows
+    state VARCHAR(10) NOT NULL CHECK (state IN ('active', 'retiring', 'retired')),
+    registered_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    activated_at TIMESTAMPTZ,
+    retired_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_encryption_keys_active ON encryption_keys(state) WHERE state = 'active';
+
+CREATE TABLE IF NOT EXISTS encryption_nodes (
+    node_id TEXT PRIMARY KEY,
+    versions INT[] NOT NULL,
+    -- In the order of versions
+    fingerprints TEXT[] NOT NULL,
+    active_version INT,
+    seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS encryption_rotations (
+    id BIGSERIAL PRIMARY KEY,
+    -- NULL when sealing plaintext rows for the first time
+    from_version INT,
+    to_version INT NOT NULL REFERENCES encryption_keys(version),
+    started_by TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    rows_resealed BIGINT NOT NULL DEFAULT 0,
+    completed_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_encryption_rotations_open
+    ON encryption_rotations((completed_at IS NULL)) WHERE completed_at IS NULL;
@@ -0,0 +1,22 @@
+-- Terminal aliases and command cooldowns
+-- Date: 2024-10-29
+--
+-- Aliases are kept per player so they follow them across browsers and nodes.
+-- Cooldowns are claimed with one conditional upsert per command run, so two
+-- nodes serving the same player cannot both let a command through early.
+
+CREATE TABLE IF NOT EXISTS terminal_aliases (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(24) NOT NULL,
+    expansion TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS terminal_cooldowns (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    command VARCHAR(64) NOT NULL,
+    last_run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, command)
+);
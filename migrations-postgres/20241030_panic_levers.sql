@@ -0,0 +1,36 @@
+-- Incident-response panic levers
+-- Date: 2024-10-30
+--
+-- Operator switches for an incident: revoke every session, freeze the
+-- economy, close registration, require CAPTCHAs on sign-in and sign-up, and
+-- make WebSockets read-only. Each lever is engaged and released on its own;
+-- every node reloads them every few seconds. Revoking sessions rejects
+-- tokens issued before engaged_at, so releasing it lets unexpired tokens
+-- back in. Every engage and release is kept in panic_lever_events.
+
+CREATE TABLE IF NOT EXISTS panic_levers (
+    lever VARCHAR(32) PRIMARY KEY CHECK (lever IN (
+        'revoke_sessions', 'freeze_economy', 'disable_registration', 'force_captcha', 'ws_read_only'
+    )),
+    engaged BOOLEAN NOT NULL DEFAULT FALSE,
+    reason TEXT,
+    engaged_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    engaged_at TIMESTAMPTZ,
+    released_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    released_at TIMESTAMPTZ
+);
+
+INSERT INTO panic_levers (lever) VALUES
+    ('revoke_sessions'), ('freeze_economy'), ('disable_registration'), ('force_captcha'), ('ws_read_only')
+ON CONFLICT (lever) DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS panic_lever_events (
+    id BIGSERIAL PRIMARY KEY,
+    lever VARCHAR(32) NOT NULL REFERENCES panic_levers(lever),
+    action VARCHAR(8) NOT NULL CHECK (action IN ('engage', 'release')),
+    admin_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_panic_lever_events_created ON panic_lever_events(created_at DESC);
@@ -0,0 +1,128 @@
+-- Storyline puzzles around the 13.37.13.37 mystery server
+-- Date: 2024-10-31
+--
+-- A puzzle is a chain of stages. Each stage hides a file on a server that
+-- only players on that stage who have cracked the server can read. When
+-- cipher_key is set the file is served Vigenère-encrypted and the key is
+-- hidden as key_file_name on key_server_ip. A stage with a window only
+-- answers during it, every day, in UTC. A stage with requires_milestone_id
+-- stays shut until that community milestone is reached.
+--
+-- Answers are never stored: answer_hash is the hex SHA-256 of
+-- '<slug>:<stage>:<answer>' with the answer lowercased and its whitespace
+-- collapsed (he_game_mechanics::puzzles::answer_digest_input).
+--
+-- puzzle_progress.stage is the stage a player is working on. Milestones count
+-- the players past a stage, or who finished the puzzle when stage is NULL;
+-- reaching one is recorded once and announced to everyone online.
+
+CREATE TABLE IF NOT EXISTS puzzles (
+    id BIGSERIAL PRIMARY KEY,
+    slug VARCHAR(64) NOT NULL UNIQUE,
+    title VARCHAR(200) NOT NULL,
+    description TEXT NOT NULL,
+    -- In cents, like bank balances
+    reward_money BIGINT NOT NULL DEFAULT 0 CHECK (reward_money >= 0),
+    reward_experience BIGINT NOT NULL DEFAULT 0 CHECK (reward_experience >= 0),
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS puzzle_milestones (
+    id BIGSERIAL PRIMARY KEY,
+    puzzle_id BIGINT NOT NULL REFERENCES puzzles(id) ON DELETE CASCADE,
+    -- Players past this stage; NULL for players who finished
+    stage INTEGER,
+    players_required INTEGER NOT NULL CHECK (players_required > 0),
+    title VARCHAR(200) NOT NULL,
+    message TEXT NOT NULL,
+    reached_at TIMESTAMPTZ,
+    UNIQUE (puzzle_id, title)
+);
+
+CREATE TABLE IF NOT EXISTS puzzle_stages (
+    puzzle_id BIGINT NOT NULL REFERENCES puzzles(id) ON DELETE CASCADE,
+    stage INTEGER NOT NULL CHECK (stage > 0),
+    server_ip INET NOT NULL,
+    file_name VARCHAR(64) NOT NULL,
+    content TEXT NOT NULL,
+    cipher_key VARCHAR(64),
+    key_server_ip INET,
+    key_file_name VARCHAR(64),
+    window_start_minute INTEGER CHECK (window_start_minute BETWEEN 0 AND 1439),
+    window_minutes INTEGER CHECK (window_minutes BETWEEN 1 AND 1440),
+    requires_milestone_id BIGINT REFERENCES puzzle_milestones(id),
+    answer_hash CHAR(64) NOT NULL,
+    PRIMARY KEY (puzzle_id, stage),
+    CHECK ((cipher_key IS NULL) = (key_server_ip IS NULL) AND (cipher_key IS NULL) = (key_file_name IS NULL)),
+    CHECK ((window_start_minute IS NULL) = (window_minutes IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS idx_puzzle_stages_server ON puzzle_stages(server_ip);
+CREATE INDEX IF NOT EXISTS idx_puzzle_stages_key_server ON puzzle_stages(key_server_ip) WHERE key_server_ip IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS puzzle_progress (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    puzzle_id BIGINT NOT NULL REFERENCES puzzles(id) ON DELETE CASCADE,
+    stage INTEGER NOT NULL DEFAULT 1,
+    -- Wrong answers since the last right one
+    wrong_attempts INTEGER NOT NULL DEFAULT 0,
+    locked_until TIMESTAMPTZ,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    rewarded_at TIMESTAMPTZ,
+    PRIMARY KEY (user_id, puzzle_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_puzzle_progress_stage ON puzzle_progress(puzzle_id, stage);
+
+-- The mystery server storyline
+INSERT INTO puzzles (slug, title, description, reward_money, reward_experience) VALUES (
+    'mystery',
+    'The Mystery Server',
+    'Something lives on 13.37.13.37. Crack it and find out what it wants.',
+    500000,  -- $5,000
+    5000
+) ON CONFLICT (slug) DO NOTHING;
+
+INSERT INTO puzzle_milestones (puzzle_id, stage, players_required, title, message)
+SELECT id, m.stage, m.players_required, m.title, m.message
+FROM puzzles, (VALUES
+    (1, 10, 'The echo answers', 'Ten hackers have heard the echo on 13.37.13.37.'),
+    (2, 25, 'The first wave', 'Twenty-five hackers were awake at 3:37. Something on 13.37.13.37 has opened.'),
+    (NULL::INTEGER, 50, 'Legends of 13.37', 'Fifty hackers have reached the heart of 13.37.13.37.')
+) AS m(stage, players_required, title, message)
+WHERE slug = 'mystery'
+ON CONFLICT (puzzle_id, title) DO NOTHING;
+
+INSERT INTO puzzle_stages (
+    puzzle_id, stage, server_ip, file_name, content,
+    cipher_key, key_server_ip, key_file_name,
+    window_start_minute, window_minutes, requires_milestone_id, answer_hash
+)
+SELECT p.id, s.stage, s.server_ip::INET, s.file_name, s.content,
+       s.cipher_key, s.key_server_ip::INET, s.key_file_name,
+       s.window_start_minute, s.window_minutes, m.id, s.answer_hash
+FROM puzzles p
+CROSS JOIN (VALUES
+    (1, '13.37.13.37', '.echo',
+     'Who keeps the oldest records keeps my key. Answer: the ghost in the wires.',
+     'whois', '1.2.3.4', '.keyring',
+     NULL::INTEGER, NULL::INTEGER, NULL,
+     '5f1e1ac4dce250b0e8beb1316827e5a853e2b4ff9965f0f7d48fcbff9ebf5816'),
+    (2, '13.37.13.37', '.clock',
+     'I only wake when the clock reads 3:37. Tell me when, in words.',
+     NULL, NULL, NULL,
+     180, 37, NULL,
+     '3f4efaf4b8d83257d6c2d72c448b6b891e294a775073177f6672dbcff31bce4d'),
+    (3, '13.37.13.37', '.root',
+     'You came back, all of you. No single hacker owns this machine. Say what you are: we are all root.',
+     NULL, NULL, NULL,
+     NULL, NULL, 'The first wave',
+     '197085e87dff9e20b1f7140653b4d991f1496b629df1103b7528e8991438f03f')
+) AS s(stage, server_ip, file_name, content, cipher_key, key_server_ip, key_file_name,
+       window_start_minute, window_minutes, milestone, answer_hash)
+LEFT JOIN puzzle_milestones m ON m.puzzle_id = p.id AND m.title = s.milestone
+WHERE p.slug = 'mystery'
+ON CONFLICT (puzzle_id, stage) DO NOTHING;
@@ -0,0 +1,11 @@
+-- Account activity timeline
+-- Date: 2024-11-01
+--
+-- The timeline pages through each source newest first per player. Logins,
+-- ledger entries and clan membership are already indexed that way; these
+-- cover hacks performed and war strikes.
+
+CREATE INDEX IF NOT EXISTS idx_hacked_database_user_time
+    ON hacked_database(user_id, hacked_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_clan_war_strikes_user_time
+    ON clan_war_strikes(user_id, created_at DESC);
@@ -0,0 +1,38 @@
+-- Resource reservations
+-- Date: 2024-11-02
+--
+-- Short-lived holds a player takes before a multi-step action: money on one
+-- bank account, disk space on their gateway and process slots. A hold counts
+-- against what the player has free while it is 'held' and not past
+-- expires_at, so it lapses on its own; the sweeper only marks lapsed holds
+-- 'expired' afterwards. The step that uses a hold commits it, and the player
+-- may release it early. Holds with a purpose of 'generated_mission:<id>' are
+-- committed when that mission pays out and released if it is abandoned.
+
+CREATE TABLE IF NOT EXISTS resource_reservations (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    bank_account_id BIGINT REFERENCES bank_accounts(id) ON DELETE CASCADE,
+    server_id BIGINT REFERENCES servers(id) ON DELETE CASCADE,
+    -- In cents
+    money BIGINT NOT NULL DEFAULT 0 CHECK (money >= 0),
+    hdd_mb BIGINT NOT NULL DEFAULT 0 CHECK (hdd_mb >= 0),
+    process_slots INT NOT NULL DEFAULT 0 CHECK (process_slots >= 0),
+    purpose VARCHAR(64),
+    status VARCHAR(12) NOT NULL DEFAULT 'held'
+        CHECK (status IN ('held', 'committed', 'released', 'expired')),
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    settled_at TIMESTAMPTZ,
+    CHECK (money = 0 OR bank_account_id IS NOT NULL),
+    CHECK (hdd_mb = 0 OR server_id IS NOT NULL)
+);
+
+CREATE INDEX IF NOT EXISTS idx_resource_reservations_user
+    ON resource_reservations(user_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_resource_reservations_held
+    ON resource_reservations(expires_at) WHERE status = 'held';
+CREATE INDEX IF NOT EXISTS idx_resource_reservations_account
+    ON resource_reservations(bank_account_id) WHERE status = 'held';
+CREATE INDEX IF NOT EXISTS idx_resource_reservations_server
+    ON resource_reservations(server_id) WHERE status = 'held';
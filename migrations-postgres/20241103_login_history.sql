@@ -0,0 +1,39 @@
+-- Player-facing login history
+-- Date: 2024-11-03
+--
+-- Read model of the login events in audit_logs: one row per sign-in attempt on
+-- a known account, with what the player is shown. Only the masked address is
+-- kept in the clear; ip_index is the keyed hash shared with user_ip_history so
+-- the security tooling can correlate a disowned login with other accounts.
+--
+-- Disowning a login ("this wasn't me") signs the account out everywhere and
+-- requires a new password, set with the reset token mailed to the player.
+
+CREATE TABLE IF NOT EXISTS login_history (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ip_masked TEXT NOT NULL,
+    ip_index TEXT,
+    -- ISO country code and network operator from GeoIP, when known
+    country TEXT,
+    network TEXT,
+    user_agent TEXT,
+    -- e.g. "Firefox on Windows"
+    device TEXT,
+    -- SHA256 of the device identifier, or of the user agent and languages
+    fingerprint TEXT,
+    success BOOLEAN NOT NULL,
+    failure_reason TEXT,
+    disowned_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_login_history_user_time ON login_history(user_id, at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_login_history_ip_index ON login_history(ip_index);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS password_reset_required BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS password_reset_token_hash TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS password_reset_expires_at TIMESTAMPTZ;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_password_reset_token
+    ON users(password_reset_token_hash) WHERE password_reset_token_hash IS NOT NULL;
@@ -0,0 +1,63 @@
+-- Daily analytics aggregates
+-- Date: 2024-11-04
+--
+-- he-cron's analytics job computes these once a day from the logs (login
+-- history, game logs, hacks and the money ledger) and the admin dashboard
+-- reads them. Each job splits its input into id ranges, aggregates every range
+-- on its own and adds the partial results up; a day's row is replaced
+-- whenever it is recomputed.
+--
+-- A player is active on a day when they signed in or wrote a game log that
+-- day. Funnel cohorts are the players who registered on a day; a cohort is
+-- written once its day-7 return window has closed.
+
+CREATE SCHEMA IF NOT EXISTS analytics;
+
+CREATE TABLE IF NOT EXISTS analytics.daily_activity (
+    day DATE PRIMARY KEY,
+    dau BIGINT NOT NULL,
+    -- Active in the seven days ending on `day`
+    wau BIGINT NOT NULL,
+    new_players BIGINT NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS analytics.funnel_cohorts (
+    cohort_day DATE PRIMARY KEY,
+    registered BIGINT NOT NULL,
+    -- Hacked a server within seven days of registering
+    first_hack BIGINT NOT NULL,
+    -- Signed in on the seventh day after registering
+    returned_day7 BIGINT NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Money moved per ledger reason, in cents
+CREATE TABLE IF NOT EXISTS analytics.economy_daily (
+    day DATE NOT NULL,
+    reason VARCHAR(32) NOT NULL,
+    transactions BIGINT NOT NULL,
+    -- Paid out of system:mint, i.e. created
+    minted BIGINT NOT NULL,
+    -- Paid into system:sink, i.e. destroyed
+    sunk BIGINT NOT NULL,
+    -- Sum of the positive entries
+    volume BIGINT NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (day, reason)
+);
+
+-- Which job has finished which day, so missed days are caught up
+CREATE TABLE IF NOT EXISTS analytics.job_days (
+    job VARCHAR(64) NOT NULL,
+    day DATE NOT NULL,
+    partitions INTEGER NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (job, day)
+);
+
+-- Map-phase lookups by time within an id range
+CREATE INDEX IF NOT EXISTS idx_logs_user_time ON logs(user_id, created_at) WHERE user_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_login_history_success_time ON login_history(at, user_id) WHERE success;
+CREATE INDEX IF NOT EXISTS idx_users_created_at ON users(created_at);
@@ -0,0 +1,21 @@
+-- Software copy protection
+-- Date: 2024-11-05
+--
+-- Owners of copy-protected titles can lock a copy to the server it is on.
+-- A download of a locked copy keeps the lock, so the stolen copy does not
+-- count on the thief's servers until a keygen process clears it. Stolen
+-- locked copies may carry a trojan of the original owner, which gives them
+-- the thief's gateway once the keygen runs it; the holder cannot see it.
+
+ALTER TABLE software
+    -- Server the copy is licensed to; it only counts there. Not a foreign
+    -- key, so a copy locked to a server that is gone stays locked
+    ADD COLUMN IF NOT EXISTS license_server_id BIGINT,
+    -- The copy it was downloaded from
+    ADD COLUMN IF NOT EXISTS pirated_from BIGINT REFERENCES software(id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS trojan_owner_id BIGINT REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_software_pirated_from ON software(pirated_from) WHERE pirated_from IS NOT NULL;
+-- Running keygens by the copy they crack
+CREATE INDEX IF NOT EXISTS idx_processes_keygen ON processes(target_file_id)
+    WHERE process_type = 'keygen' AND completed_at IS NULL;
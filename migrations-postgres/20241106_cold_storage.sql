@@ -0,0 +1,102 @@
+-- Cold storage for completed processes and old logs
+-- Date: 2024-11-06
+--
+-- he-cron's archive job moves completed processes and logs older than their
+-- retention window out of the hot tables, in batches, into the tables of the
+-- `archive` schema. Those are range-partitioned by month; the job creates a
+-- month's partition before moving rows into it. The archive API reads them
+-- back one bounded time window at a time, so only a few partitions are
+-- scanned.
+--
+-- Operators tune the windows in archive.retention from the admin API. Logs
+-- are kept hot for at least two weeks since the analytics job re-reads the
+-- last seven days of them.
+
+CREATE SCHEMA IF NOT EXISTS archive;
+
+CREATE TABLE IF NOT EXISTS archive.retention (
+    table_name VARCHAR(32) PRIMARY KEY CHECK (table_name IN ('processes', 'logs')),
+    retention_days INTEGER NOT NULL,
+    -- Rows moved per transaction
+    batch_size INTEGER NOT NULL CHECK (batch_size BETWEEN 100 AND 50000),
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    updated_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (retention_days >= CASE table_name WHEN 'logs' THEN 14 ELSE 1 END)
+);
+
+INSERT INTO archive.retention (table_name, retention_days, batch_size) VALUES
+    ('processes', 30, 5000),
+    ('logs', 90, 5000)
+ON CONFLICT (table_name) DO NOTHING;
+
+-- No foreign keys: archived rows outlive the users and servers they name
+CREATE TABLE IF NOT EXISTS archive.processes (
+    pid BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    game_id TEXT,
+    pc_id TEXT NOT NULL,
+    target_pc_id TEXT,
+    target_file_id TEXT,
+    target_folder TEXT,
+    process_type TEXT NOT NULL,
+    priority INTEGER NOT NULL,
+    start_time TIMESTAMPTZ NOT NULL,
+    end_time TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (pid, completed_at)
+) PARTITION BY RANGE (completed_at);
+
+CREATE TABLE IF NOT EXISTS archive.logs (
+    id BIGINT NOT NULL,
+    server_id BIGINT NOT NULL,
+    user_id BIGINT,
+    type TEXT NOT NULL,
+    message TEXT NOT NULL,
+    ip_address INET,
+    is_deleted BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id, created_at)
+) PARTITION BY RANGE (created_at);
+
+-- Indexes on the parents are created on every partition
+CREATE INDEX IF NOT EXISTS idx_archive_processes_user ON archive.processes(user_id, completed_at DESC, pid DESC);
+CREATE INDEX IF NOT EXISTS idx_archive_logs_server ON archive.logs(server_id, created_at DESC, id DESC);
+
+-- The partition of `parent` (processes or logs) for the month of `month`,
+-- e.g. archive.logs_2024_11
+CREATE OR REPLACE FUNCTION archive.ensure_month(parent TEXT, month DATE) RETURNS VOID AS $$
+DECLARE
+    start DATE := date_trunc('month', month)::date;
+BEGIN
+    IF parent NOT IN ('processes', 'logs') THEN
+        RAISE EXCEPTION 'Unknown archive table %', parent;
+    END IF;
+    EXECUTE format(
+        'CREATE TABLE IF NOT EXISTS archive.%I PARTITION OF archive.%I FOR VALUES FROM (%L) TO (%L)',
+        parent || '_' || to_char(start, 'YYYY_MM'),
+        parent,
+        start,
+        (start + INTERVAL '1 month')::date
+    );
+END;
+$$ LANGUAGE plpgsql;
+
+-- One archive job pass per table
+CREATE TABLE IF NOT EXISTS archive.runs (
+    id BIGSERIAL PRIMARY KEY,
+    table_name VARCHAR(32) NOT NULL,
+    cutoff TIMESTAMPTZ NOT NULL,
+    moved BIGINT NOT NULL,
+    -- Stopped at the per-run batch limit with rows left to move
+    more_pending BOOLEAN NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    ran_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_archive_runs_table ON archive.runs(table_name, ran_at DESC);
+
+-- Finding what is due to move
+CREATE INDEX IF NOT EXISTS idx_processes_completed_at ON processes(completed_at) WHERE completed_at IS NOT NULL;
@@ -0,0 +1,56 @@
+-- Bank heists: timed raid windows on high-tier NPC banks
+-- Date: 2024-11-07
+--
+-- he-cron's bank_heists job opens a heist window on a bank of the top
+-- security tier whenever none is open, and fails raids the window or the
+-- trace ran out on. Crews form raid instances against an open heist; every
+-- member takes one role and the raid moves through the stages of
+-- he_game_mechanics::heist as the roles act. The vault shrinks by what each
+-- escaping crew takes.
+
+CREATE TABLE IF NOT EXISTS bank_heists (
+    id BIGSERIAL PRIMARY KEY,
+    bank_id BIGINT NOT NULL REFERENCES banks(id) ON DELETE CASCADE,
+    opens_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    closes_at TIMESTAMPTZ NOT NULL,
+    -- Money left to take, in cents
+    vault BIGINT NOT NULL CHECK (vault >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (closes_at > opens_at)
+);
+
+CREATE INDEX IF NOT EXISTS idx_bank_heists_closes ON bank_heists(closes_at DESC);
+
+CREATE TABLE IF NOT EXISTS heist_raids (
+    id BIGSERIAL PRIMARY KEY,
+    heist_id BIGINT NOT NULL REFERENCES bank_heists(id) ON DELETE CASCADE,
+    leader_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    stage VARCHAR(16) NOT NULL DEFAULT 'recruiting'
+        CHECK (stage IN ('recruiting', 'firewall', 'vault', 'transfer', 'cleanup', 'escaped', 'failed')),
+    -- Until the vault has to be cracked, once the DDoS went through
+    firewall_down_until TIMESTAMPTZ,
+    -- When the crew is traced unless the logs are wiped, once the transfer went through
+    trace_at TIMESTAMPTZ,
+    loot BIGINT,
+    failure_reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    ended_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_heist_raids_running
+    ON heist_raids(heist_id) WHERE stage NOT IN ('escaped', 'failed');
+
+CREATE TABLE IF NOT EXISTS heist_raid_members (
+    raid_id BIGINT NOT NULL REFERENCES heist_raids(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(16) NOT NULL CHECK (role IN ('ddos', 'crack', 'transfer', 'wipe')),
+    -- Times the member's role acted
+    actions INTEGER NOT NULL DEFAULT 0,
+    -- The member's cut, once the crew escaped
+    share BIGINT,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (raid_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_heist_raid_members_user ON heist_raid_members(user_id);
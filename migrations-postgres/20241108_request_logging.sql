@@ -0,0 +1,31 @@
+-- Request/response logging: sampling and per-user verbose mode
+-- Date: 2024-11-08
+--
+-- The RequestLogging middleware logs a sample of requests (method, path,
+-- status, timing, request and trace ids) and every server error. Users in
+-- request_log_verbose also get their headers and bodies logged, redacted,
+-- until expires_at; operators turn that on through the admin API to debug
+-- one account. Every node reloads both tables every few seconds.
+
+CREATE TABLE IF NOT EXISTS request_log_settings (
+    -- One row
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    -- Share of requests logged, from 0 to 1
+    sample_rate DOUBLE PRECISION NOT NULL DEFAULT 0.01 CHECK (sample_rate >= 0 AND sample_rate <= 1),
+    -- Longest body kept per request or response in verbose mode
+    max_body_bytes INTEGER NOT NULL DEFAULT 4096 CHECK (max_body_bytes >= 0),
+    updated_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO request_log_settings (id) VALUES (TRUE) ON CONFLICT (id) DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS request_log_verbose (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    enabled_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    reason TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_request_log_verbose_expires ON request_log_verbose(expires_at);
@@ -0,0 +1,29 @@
+-- Whois registry: hostname to address resolution
+-- Date: 2024-11-09
+--
+-- The First Whois server (1.2.3.4) keeps the game's hostnames. NPC servers
+-- are registered with the hostnames they already have; players register
+-- their own for a one-time fee, each hostname held by one server. Lookups
+-- are logged on the registry server itself, in logs. See
+-- he_game_mechanics::dns.
+
+CREATE TABLE IF NOT EXISTS dns_records (
+    hostname VARCHAR(253) PRIMARY KEY CHECK (hostname = LOWER(hostname)),
+    server_id BIGINT NOT NULL REFERENCES servers(id) ON DELETE CASCADE,
+    -- NULL for the hostnames of NPC servers
+    owner_id BIGINT REFERENCES users(id) ON DELETE CASCADE,
+    -- Registration fee paid, in cents
+    fee_paid BIGINT NOT NULL DEFAULT 0,
+    registered_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_dns_records_server ON dns_records(server_id);
+CREATE INDEX IF NOT EXISTS idx_dns_records_owner ON dns_records(owner_id) WHERE owner_id IS NOT NULL;
+
+INSERT INTO dns_records (hostname, server_id)
+SELECT DISTINCT ON (LOWER(hostname)) LOWER(hostname), id
+FROM servers
+WHERE is_npc = TRUE
+  AND hostname ~* '^[a-z0-9]([a-z0-9-]*[a-z0-9])?(\.[a-z0-9]([a-z0-9-]*[a-z0-9])?)+$'
+ORDER BY LOWER(hostname), id
+ON CONFLICT (hostname) DO NOTHING;
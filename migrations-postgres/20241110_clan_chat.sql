@@ -0,0 +1,132 @@
+-- Private clan chat rooms
+-- Date: 2024-11-10
+--
+-- Every clan gets two chat threads when it is created: the room every member
+-- talks in, and an announcements channel everyone reads but only the leader
+-- and officers post to. Triggers keep chat_thread_members in step with
+-- clan_members, so joining, leaving or being kicked from the clan is joining
+-- or leaving its chat, and a member's clan role is their role in the room.
+--
+-- When a clan disbands (is_active goes false, or the row is deleted) both
+-- threads are closed and the room is marked archived: the history stays
+-- readable but nothing more can be posted. he-cron's clan_chat_retention job
+-- deletes messages older than the history window, except pinned ones.
+
+ALTER TABLE chat_thread_members ADD COLUMN IF NOT EXISTS role VARCHAR(20) NOT NULL DEFAULT 'member';
+
+CREATE TABLE IF NOT EXISTS clan_chat_rooms (
+    -- No foreign key: the room outlives a deleted clan as an archive
+    clan_id BIGINT PRIMARY KEY,
+    thread_id BIGINT NOT NULL UNIQUE REFERENCES chat_threads(id),
+    announcements_thread_id BIGINT NOT NULL UNIQUE REFERENCES chat_threads(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    archived_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS chat_thread_pins (
+    message_id BIGINT PRIMARY KEY REFERENCES chat_thread_messages(id) ON DELETE CASCADE,
+    thread_id BIGINT NOT NULL REFERENCES chat_threads(id) ON DELETE CASCADE,
+    pinned_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    pinned_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_chat_thread_pins_thread ON chat_thread_pins(thread_id, pinned_at DESC);
+-- Finding what the retention job deletes
+CREATE INDEX IF NOT EXISTS idx_chat_thread_messages_created ON chat_thread_messages(thread_id, created_at);
+
+-- Both threads of a new clan's room
+CREATE OR REPLACE FUNCTION clan_chat_provision(clan BIGINT, clan_name VARCHAR)
+RETURNS VOID AS $$
+DECLARE
+    room BIGINT;
+    announcements BIGINT;
+BEGIN
+    IF EXISTS (SELECT 1 FROM clan_chat_rooms WHERE clan_id = clan) THEN
+        RETURN;
+    END IF;
+
+    INSERT INTO chat_threads (title) VALUES (format('[%s] Clan chat', clan_name)) RETURNING id INTO room;
+    INSERT INTO chat_threads (title) VALUES (format('[%s] Announcements', clan_name)) RETURNING id INTO announcements;
+    INSERT INTO clan_chat_rooms (clan_id, thread_id, announcements_thread_id) VALUES (clan, room, announcements);
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION clan_chat_clans()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        PERFORM clan_chat_provision(NEW.id, NEW.name);
+        RETURN NEW;
+    END IF;
+
+    IF TG_OP = 'DELETE' OR (OLD.is_active AND NOT NEW.is_active) THEN
+        UPDATE chat_threads SET closed_at = NOW()
+        WHERE closed_at IS NULL AND id IN (
+            SELECT thread_id FROM clan_chat_rooms WHERE clan_id = OLD.id
+            UNION ALL
+            SELECT announcements_thread_id FROM clan_chat_rooms WHERE clan_id = OLD.id
+        );
+        UPDATE clan_chat_rooms SET archived_at = NOW() WHERE clan_id = OLD.id AND archived_at IS NULL;
+    END IF;
+
+    IF TG_OP = 'DELETE' THEN
+        RETURN OLD;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS clan_chat_clans_trigger ON clans;
+CREATE TRIGGER clan_chat_clans_trigger
+    AFTER INSERT OR UPDATE OF is_active OR DELETE ON clans
+    FOR EACH ROW EXECUTE FUNCTION clan_chat_clans();
+
+CREATE OR REPLACE FUNCTION clan_chat_members()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        DELETE FROM chat_thread_members
+        WHERE user_id = OLD.user_id AND thread_id IN (
+            SELECT thread_id FROM clan_chat_rooms WHERE clan_id = OLD.clan_id
+            UNION ALL
+            SELECT announcements_thread_id FROM clan_chat_rooms WHERE clan_id = OLD.clan_id
+        );
+    END IF;
+
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        INSERT INTO chat_thread_members (thread_id, user_id, role)
+        SELECT t.id, NEW.user_id, NEW.role
+        FROM clan_chat_rooms r
+        CROSS JOIN LATERAL (VALUES (r.thread_id), (r.announcements_thread_id)) AS t(id)
+        WHERE r.clan_id = NEW.clan_id
+        ON CONFLICT (thread_id, user_id) DO UPDATE SET role = EXCLUDED.role;
+        RETURN NEW;
+    END IF;
+
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS clan_chat_members_trigger ON clan_members;
+CREATE TRIGGER clan_chat_members_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON clan_members
+    FOR EACH ROW EXECUTE FUNCTION clan_chat_members();
+
+-- Rooms for the clans that already exist, and their members
+SELECT clan_chat_provision(id, name) FROM clans;
+
+INSERT INTO chat_thread_members (thread_id, user_id, role)
+SELECT t.id, m.user_id, m.role
+FROM clan_members m
+JOIN clan_chat_rooms r ON r.clan_id = m.clan_id
+CROSS JOIN LATERAL (VALUES (r.thread_id), (r.announcements_thread_id)) AS t(id)
+ON CONFLICT (thread_id, user_id) DO UPDATE SET role = EXCLUDED.role;
+
+UPDATE chat_threads SET closed_at = NOW()
+WHERE closed_at IS NULL AND id IN (
+    SELECT r.thread_id FROM clan_chat_rooms r JOIN clans c ON c.id = r.clan_id WHERE NOT c.is_active
+    UNION ALL
+    SELECT r.announcements_thread_id FROM clan_chat_rooms r JOIN clans c ON c.id = r.clan_id WHERE NOT c.is_active
+);
+UPDATE clan_chat_rooms r SET archived_at = NOW()
+FROM clans c WHERE c.id = r.clan_id AND NOT c.is_active AND r.archived_at IS NULL;
@@ -0,0 +1,35 @@
+-- Notification deduplication and collapsing
+-- Date: 2024-11-11
+--
+-- A notification identical to an unread one created within the collapse
+-- window is folded into it instead of stored again: the existing row's count
+-- goes up, it takes the newest data and moves back to the top of the list.
+-- "Identical" is the same class, code, target and data, or the same explicit
+-- group key when the caller gives one. Each folded occurrence is kept in
+-- notification_occurrences (up to a cap per group) so clients can expand it.
+--
+-- Each class also has a rate cap: past it, notifications that would start a
+-- new row are dropped, while ones that collapse into an existing row are
+-- still counted.
+
+ALTER TABLE notifications ADD COLUMN IF NOT EXISTS group_key TEXT;
+ALTER TABLE notifications ADD COLUMN IF NOT EXISTS collapse_count INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE notifications ADD COLUMN IF NOT EXISTS first_time TIMESTAMPTZ;
+
+UPDATE notifications SET first_time = creation_time WHERE first_time IS NULL;
+ALTER TABLE notifications ALTER COLUMN first_time SET DEFAULT NOW();
+ALTER TABLE notifications ALTER COLUMN first_time SET NOT NULL;
+
+-- Finding the unread row a new notification collapses into
+CREATE INDEX IF NOT EXISTS idx_notifications_account_group
+    ON notifications(account_id, group_key, creation_time DESC) WHERE NOT is_read;
+
+CREATE TABLE IF NOT EXISTS notification_occurrences (
+    id BIGSERIAL PRIMARY KEY,
+    notification_id UUID NOT NULL REFERENCES notifications(notification_id) ON DELETE CASCADE,
+    data JSONB NOT NULL DEFAULT '{}',
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_occurrences_notification
+    ON notification_occurrences(notification_id, occurred_at DESC);
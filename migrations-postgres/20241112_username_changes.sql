@@ -0,0 +1,32 @@
+-- Username changes
+-- Date: 2024-11-12
+--
+-- Every change is recorded in username_history for moderators. The name a
+-- player gives up is reserved for them in reserved_usernames until
+-- reserved_until: nobody else can take it, but its old owner can take it
+-- back. Names are compared in lowercase, so "Neo" and "neo" are one name.
+--
+-- The users trigger from 20241003_forum_sync.sql already queues a 'rename'
+-- for the forum bridge when users.login changes.
+
+CREATE TABLE IF NOT EXISTS username_history (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    old_login VARCHAR(15) NOT NULL,
+    new_login VARCHAR(15) NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_username_history_user ON username_history(user_id, changed_at DESC);
+-- Who held a name, for impersonation reports
+CREATE INDEX IF NOT EXISTS idx_username_history_old ON username_history(lower(old_login));
+
+CREATE TABLE IF NOT EXISTS reserved_usernames (
+    -- lower(login)
+    name_key VARCHAR(15) PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    reserved_until TIMESTAMPTZ NOT NULL
+);
+
+-- Case-insensitive uniqueness checks
+CREATE INDEX IF NOT EXISTS idx_users_login_lower ON users(lower(login));
@@ -0,0 +1,10 @@
+-- Trace-route mini-game
+-- Date: 2024-11-13
+--
+-- A hostile process leaves a 'connection' log on the target and on every
+-- bounce it passed, each showing the address the connection came from. A
+-- defender's trace reads these back hop by hop; an attacker wiping them
+-- (is_deleted) slows the trace down or breaks it. Traces count a hop's
+-- lines by server and source address.
+
+CREATE INDEX IF NOT EXISTS idx_logs_server_source ON logs(server_id, ip_address, created_at);
@@ -0,0 +1,66 @@
+-- Bulk admin data fixes
+-- Date: 2024-11-14
+--
+-- A bulk operation applies one mutation (a money credit, experience or
+-- reputation) to every player a filter matches, e.g. refunding everyone a
+-- bug charged twice. Planning it is the dry run: the matched players are
+-- snapshotted into bulk_operation_rows as 'pending', and only those rows
+-- are ever touched when it runs. The worker applies them a chunk at a time,
+-- waiting throttle_ms between chunks, and records each player's value
+-- before and after. Those rows are the rollback artifact: rolling back
+-- applies the difference in reverse.
+--
+-- Operation status: planned -> running -> completed, failed or cancelled,
+-- then optionally rolling_back -> rolled_back.
+-- Row status: pending -> applied or skipped, then rolled_back or
+-- rollback_failed.
+
+CREATE TABLE IF NOT EXISTS bulk_operations (
+    id BIGSERIAL PRIMARY KEY,
+    title VARCHAR(200) NOT NULL,
+    reason TEXT NOT NULL,
+    filter JSONB NOT NULL,
+    mutation JSONB NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    throttle_ms INTEGER NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'planned',
+    matched INTEGER NOT NULL DEFAULT 0,
+    -- The first matched players with their current and resulting values
+    preview JSONB NOT NULL DEFAULT '[]',
+    applied INTEGER NOT NULL DEFAULT 0,
+    skipped INTEGER NOT NULL DEFAULT 0,
+    rolled_back INTEGER NOT NULL DEFAULT 0,
+    rollback_failed INTEGER NOT NULL DEFAULT 0,
+    created_by BIGINT NOT NULL,
+    planned_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    executed_by BIGINT,
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    rolled_back_by BIGINT,
+    rollback_reason TEXT,
+    rollback_started_at TIMESTAMPTZ,
+    rollback_finished_at TIMESTAMPTZ,
+    -- When the worker may take the next chunk
+    next_chunk_at TIMESTAMPTZ,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_bulk_operations_planned ON bulk_operations(planned_at DESC);
+CREATE INDEX IF NOT EXISTS idx_bulk_operations_active ON bulk_operations(next_chunk_at)
+    WHERE status IN ('running', 'rolling_back');
+
+CREATE TABLE IF NOT EXISTS bulk_operation_rows (
+    operation_id BIGINT NOT NULL REFERENCES bulk_operations(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    -- The bank account credited, for money operations
+    bank_account_id BIGINT,
+    before_value BIGINT,
+    after_value BIGINT,
+    note TEXT,
+    applied_at TIMESTAMPTZ,
+    rolled_back_at TIMESTAMPTZ,
+    PRIMARY KEY (operation_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_bulk_operation_rows_status ON bulk_operation_rows(operation_id, status, user_id);
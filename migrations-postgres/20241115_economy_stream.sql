@@ -0,0 +1,61 @@
+-- Real-time economy stream
+-- Date: 2024-11-15
+--
+-- The economy stream processor tails ledger_transactions past its cursor
+-- and folds every new transaction into per-minute time series: money
+-- created and destroyed per ledger reason in economy_minutes, and what each
+-- player's bank accounts took in and paid out in economy_earner_minutes.
+-- Players whose recent earnings jump far above their own baseline are
+-- flagged in economy_anomalies for a moderator to look at.
+--
+-- These series are for watching the economy as it happens. The daily
+-- figures in analytics.economy_daily stay the ones to report from.
+
+CREATE TABLE IF NOT EXISTS economy_stream_cursor (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    last_transaction_id BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Start at the end of the ledger; history is in analytics.economy_daily
+INSERT INTO economy_stream_cursor (last_transaction_id)
+SELECT COALESCE(MAX(id), 0) FROM ledger_transactions
+ON CONFLICT (id) DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS economy_minutes (
+    minute TIMESTAMPTZ NOT NULL,
+    reason VARCHAR(40) NOT NULL,
+    transactions BIGINT NOT NULL DEFAULT 0,
+    minted BIGINT NOT NULL DEFAULT 0,
+    sunk BIGINT NOT NULL DEFAULT 0,
+    volume BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (minute, reason)
+);
+
+CREATE TABLE IF NOT EXISTS economy_earner_minutes (
+    minute TIMESTAMPTZ NOT NULL,
+    user_id BIGINT NOT NULL,
+    earned BIGINT NOT NULL DEFAULT 0,
+    spent BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (minute, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_economy_earner_minutes_user ON economy_earner_minutes(user_id, minute);
+
+CREATE TABLE IF NOT EXISTS economy_anomalies (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Start of the window the spike was seen in
+    window_start TIMESTAMPTZ NOT NULL,
+    earned BIGINT NOT NULL,
+    -- The player's average earnings per window over the baseline period
+    baseline DOUBLE PRECISION NOT NULL,
+    ratio DOUBLE PRECISION NOT NULL,
+    flagged_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    acknowledged_by BIGINT,
+    acknowledged_at TIMESTAMPTZ,
+    UNIQUE (user_id, window_start)
+);
+
+CREATE INDEX IF NOT EXISTS idx_economy_anomalies_open ON economy_anomalies(flagged_at DESC)
+    WHERE acknowledged_at IS NULL;
@@ -0,0 +1,12 @@
+-- Co-op progress from completed processes
+-- Date: 2024-11-16
+--
+-- Co-op progress used to be reported by the client. It now comes from the
+-- members' completed processes: each completion of the mission's objective
+-- type, started after the mission did, adds a fixed amount.
+
+-- Process type whose completions count; NULL for any process acting on
+-- another server
+ALTER TABLE missions ADD COLUMN IF NOT EXISTS coop_objective VARCHAR(32);
+-- Taken from the template when accepted, like the reward
+ALTER TABLE coop_missions ADD COLUMN IF NOT EXISTS objective VARCHAR(32);
@@ -0,0 +1,45 @@
+-- OpenID Connect clients, codes and tokens
+-- Date: 2024-11-17
+--
+-- The provider kept these in process memory, so a code issued by one node
+-- could not be exchanged on another and a restart signed everyone out of the
+-- companion services. Codes and tokens are stored as their SHA-256.
+
+CREATE TABLE IF NOT EXISTS oidc_clients (
+    client_id VARCHAR(64) PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    redirect_uris TEXT[] NOT NULL,
+    confidential BOOLEAN NOT NULL DEFAULT FALSE,
+    secret_hash VARCHAR(64),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS oidc_codes (
+    code_hash VARCHAR(64) PRIMARY KEY,
+    client_id VARCHAR(64) NOT NULL REFERENCES oidc_clients(client_id) ON DELETE CASCADE,
+    redirect_uri TEXT NOT NULL,
+    scopes TEXT[] NOT NULL,
+    nonce TEXT,
+    code_challenge VARCHAR(64) NOT NULL,
+    user_id UUID NOT NULL,
+    email TEXT NOT NULL,
+    roles TEXT[] NOT NULL,
+    issued_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_oidc_codes_expires ON oidc_codes(expires_at);
+
+CREATE TABLE IF NOT EXISTS oidc_tokens (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    client_id VARCHAR(64) NOT NULL REFERENCES oidc_clients(client_id) ON DELETE CASCADE,
+    scopes TEXT[] NOT NULL,
+    user_id UUID NOT NULL,
+    email TEXT NOT NULL,
+    roles TEXT[] NOT NULL,
+    issued_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_oidc_tokens_user ON oidc_tokens(user_id);
+CREATE INDEX IF NOT EXISTS idx_oidc_tokens_expires ON oidc_tokens(expires_at);
@@ -0,0 +1,36 @@
+-- Process starts for the hourly quotas
+-- Date: 2024-11-18
+--
+-- Hourly quotas counted rows in `processes`, so cancelling a process (which
+-- deletes its row) handed the start back. Every insert into `processes` is
+-- now recorded here by a trigger, whatever path created it, and nothing
+-- deletes these rows before they age out.
+
+CREATE TABLE IF NOT EXISTS process_starts (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- lower case, as stored on the process
+    process_type VARCHAR(50) NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_process_starts_user ON process_starts(user_id, process_type, started_at);
+CREATE INDEX IF NOT EXISTS idx_process_starts_started ON process_starts(started_at);
+
+CREATE OR REPLACE FUNCTION record_process_start() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO process_starts (user_id, process_type, started_at)
+    VALUES (NEW.user_id, lower(NEW.process_type), COALESCE(NEW.start_time, NOW()));
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS processes_record_start ON processes;
+CREATE TRIGGER processes_record_start
+    AFTER INSERT ON processes
+    FOR EACH ROW EXECUTE FUNCTION record_process_start();
+
+-- Starts of the last hour, so quotas hold across the deploy
+INSERT INTO process_starts (user_id, process_type, started_at)
+SELECT user_id, lower(process_type), start_time FROM processes
+WHERE start_time >= NOW() - INTERVAL '1 hour';
@@ -0,0 +1,8 @@
+-- Held bounty refunds
+-- Date: 2024-11-19
+--
+-- A bounty whose placer has no bank account when it expires keeps its
+-- reward in escrow: it is marked expired with no settled_at, and the expiry
+-- sweep refunds it once the placer opens an account.
+
+CREATE INDEX IF NOT EXISTS idx_bounties_held_refunds ON bounties(placer_id) WHERE status = 'expired' AND settled_at IS NULL;
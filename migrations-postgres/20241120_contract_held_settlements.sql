@@ -0,0 +1,9 @@
+-- Held contract settlements
+-- Date: 2024-11-20
+--
+-- A contract closed while its payee has no bank account keeps the reward in
+-- escrow with no settled_at, and the contract sweep pays it once the payee
+-- opens an account.
+
+CREATE INDEX IF NOT EXISTS idx_player_contracts_unsettled ON player_contracts(id)
+    WHERE status IN ('paid', 'refunded', 'expired', 'cancelled') AND settled_at IS NULL;
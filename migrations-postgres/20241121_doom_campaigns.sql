@@ -0,0 +1,36 @@
+-- Doom virus campaigns
+-- Date: 2024-11-21
+--
+-- A campaign is the serialized he_game_mechanics::doom::DoomCampaign. Only
+-- one may be counting down at a time; every node ticks it, one at a time,
+-- and announces its start and end through the live-ops announcements.
+-- Rewards are held until the winner has a bank account to pay into.
+
+CREATE TABLE IF NOT EXISTS doom_campaigns (
+    id BIGSERIAL PRIMARY KEY,
+    instigator_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    campaign JSONB NOT NULL,
+    -- When the research step in progress started; NULL between steps
+    step_started_at TIMESTAMPTZ,
+    -- Set on install, while the countdown runs
+    detonates_at TIMESTAMPTZ,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One campaign per instigator, and one countdown world-wide
+CREATE UNIQUE INDEX IF NOT EXISTS idx_doom_campaigns_instigator ON doom_campaigns(instigator_id) WHERE active;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_doom_campaigns_countdown ON doom_campaigns((TRUE))
+    WHERE active AND detonates_at IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS doom_rewards (
+    id BIGSERIAL PRIMARY KEY,
+    campaign_id BIGINT NOT NULL REFERENCES doom_campaigns(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    money BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    paid_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_doom_rewards_unpaid ON doom_rewards(id) WHERE paid_at IS NULL;
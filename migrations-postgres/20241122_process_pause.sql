@@ -0,0 +1,8 @@
+-- Paused processes
+-- Date: 2024-11-22
+--
+-- A power spike used to delete the process it hit. It now stops it: the
+-- process keeps its slot but does not complete until its owner resumes it,
+-- which moves the end time on by however long it was stopped.
+
+ALTER TABLE processes ADD COLUMN IF NOT EXISTS paused_at TIMESTAMPTZ;
@@ -0,0 +1,16 @@
+-- Held mining payouts
+-- Date: 2024-11-23
+--
+-- A mining process whose owner has no bank account when it completes keeps
+-- its payout here instead of dropping it; the completion worker's sweep
+-- pays it once the owner opens an account.
+
+CREATE TABLE IF NOT EXISTS mining_held_payouts (
+    pid BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    money BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    paid_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_mining_held_payouts_unpaid ON mining_held_payouts(user_id) WHERE paid_at IS NULL;
@@ -0,0 +1,11 @@
+-- Content versions
+-- Date: 2024-11-24
+--
+-- Versions behind HTTP ETags, for deployments without Redis. Kept here so
+-- every node hands out the same ETag for the same data and sees every bump.
+
+CREATE TABLE IF NOT EXISTS content_versions (
+    key TEXT PRIMARY KEY,
+    version BIGINT NOT NULL DEFAULT 1,
+    modified_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
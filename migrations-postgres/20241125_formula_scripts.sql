@@ -0,0 +1,19 @@
+-- Formula override scripts
+-- Date: 2024-11-25
+--
+-- The Rhai scripts private servers override formulas with. Every change adds
+-- a revision; each node's live-ops reloader recompiles the set when the
+-- latest revision moves, so a script saved on one node reaches all of them.
+
+CREATE TABLE IF NOT EXISTS formula_scripts (
+    formula TEXT PRIMARY KEY,
+    source TEXT NOT NULL,
+    updated_by BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS formula_script_revisions (
+    id BIGSERIAL PRIMARY KEY,
+    created_by BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,13 @@
+-- What generated missions actually paid
+-- Date: 2024-11-26
+--
+-- Rewards are scaled down for repeating a kind and clamped to the daily mission
+-- caps (he_game_mechanics::missions::scale_mission_reward). The amounts paid
+-- are kept so the repeats and today's totals can be read back on the next
+-- completion; reward_money and reward_exp stay the offered amounts.
+
+ALTER TABLE generated_missions ADD COLUMN IF NOT EXISTS paid_money BIGINT;
+ALTER TABLE generated_missions ADD COLUMN IF NOT EXISTS paid_exp BIGINT;
+
+CREATE INDEX IF NOT EXISTS idx_generated_missions_completed ON generated_missions(user_id, ended_at)
+    WHERE status = 'completed';
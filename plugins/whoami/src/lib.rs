@@ -0,0 +1,87 @@
+//! Sample server-side plugin: `whoami` prints the player's login, gateway
+//! and bank balance, and `whoami --json` the raw snapshot.
+//!
+//! Build and install next to its manifest:
+//!
+//! ```text
+//! cargo build --release --target wasm32-unknown-unknown
+//! cp target/wasm32-unknown-unknown/release/he_plugin_whoami.wasm ../whoami.wasm
+//! ```
+//!
+//! then load it with `POST /api/admin/plugins/whoami/load`.
+
+#[link(wasm_import_module = "he")]
+extern "C" {
+    fn log(level: i32, ptr: *const u8, len: i32);
+    fn state(ptr: *mut u8, cap: i32) -> i32;
+    fn register_command(ptr: *const u8, len: i32) -> i32;
+}
+
+const COMMAND: &str = "whoami";
+
+fn info(message: &str) {
+    unsafe { log(2, message.as_ptr(), message.len() as i32) }
+}
+
+/// The player snapshot the host hands out, as JSON
+fn game_state() -> Option<serde_json::Value> {
+    let mut buf = vec![0u8; 1024];
+    let mut len = unsafe { state(buf.as_mut_ptr(), buf.len() as i32) };
+    if len > buf.len() as i32 {
+        buf.resize(len as usize, 0);
+        len = unsafe { state(buf.as_mut_ptr(), buf.len() as i32) };
+    }
+    if len < 0 {
+        return None;
+    }
+    serde_json::from_slice(&buf[..len as usize]).ok()
+}
+
+/// Hand the host memory to copy input into; never freed, as each call runs
+/// in a fresh instance
+#[no_mangle]
+pub extern "C" fn alloc(len: i32) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len.max(0) as usize);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+#[no_mangle]
+pub extern "C" fn init() {
+    if unsafe { register_command(COMMAND.as_ptr(), COMMAND.len() as i32) } != 0 {
+        info("whoami is already registered");
+    }
+}
+
+/// Output is returned as `ptr << 32 | len`
+#[no_mangle]
+pub extern "C" fn on_command(ptr: *const u8, len: i32) -> i64 {
+    let line = unsafe { std::slice::from_raw_parts(ptr, len.max(0) as usize) };
+    let line = std::str::from_utf8(line).unwrap_or_default();
+    let output = respond(line);
+
+    let output = output.into_bytes().into_boxed_slice();
+    let len = output.len() as i64;
+    let ptr = Box::into_raw(output) as *mut u8 as i64;
+    (ptr << 32) | len
+}
+
+fn respond(line: &str) -> String {
+    let Some(state) = game_state() else {
+        return "whoami: game state unavailable".to_string();
+    };
+    if line.split_whitespace().any(|arg| arg == "--json") {
+        return state.to_string();
+    }
+
+    let balance = state["bank_balance"].as_i64().unwrap_or(0);
+    format!(
+        "{}{}\ngateway: {}\nbalance: ${}.{:02}",
+        state["login"].as_str().unwrap_or("unknown"),
+        if state["premium"].as_bool().unwrap_or(false) { " [premium]" } else { "" },
+        state["gateway_ip"].as_str().unwrap_or("none"),
+        balance / 100,
+        balance % 100,
+    )
+}